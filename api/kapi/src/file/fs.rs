@@ -14,8 +14,9 @@ use core::{
 
 use fs_ng_vfs::{Location, Metadata, NodeFlags};
 use kerrno::{KError, KResult};
-use kfs::{FS_CONTEXT, FsContext};
+use kfs::{FS_CONTEXT, FileLocks, FsContext, LockOwner};
 use kpoll::{IoEvents, Pollable};
+use kprocess::Pid;
 use ksync::Mutex;
 use ktask::future::{block_on, poll_io};
 use linux_raw_sys::general::{AT_EMPTY_PATH, AT_FDCWD, AT_SYMLINK_NOFOLLOW};
@@ -145,6 +146,30 @@ impl File {
     fn is_blocking(&self) -> bool {
         self.inner.location().flags().contains(NodeFlags::BLOCKING)
     }
+
+    /// Returns the owner of the `flock` and OFD locks placed through this
+    /// open file description.
+    pub fn lock_owner(&self) -> LockOwner {
+        LockOwner::File(self as *const Self as usize)
+    }
+
+    /// Removes the record locks `pid` holds on this file.
+    ///
+    /// POSIX drops them whenever the process closes any descriptor of the
+    /// file, not only the one used to place them.
+    pub fn release_record_locks(&self, pid: Pid) {
+        if let Some(locks) = FileLocks::get(self.inner.location()) {
+            locks.release(LockOwner::Process(pid));
+        }
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        if let Some(locks) = FileLocks::get(self.inner.location()) {
+            locks.release(self.lock_owner());
+        }
+    }
 }
 
 /// Gets the absolute path of a location, or `<error>` if unavailable.
@@ -305,4 +330,205 @@ mod fs_tests {
         assert_eq!(AT_EMPTY_PATH, 0x1000);
         assert_eq!(AT_SYMLINK_NOFOLLOW, 0x100);
     }
+
+    /// Test `..` and absolute paths stay inside a chroot
+    #[def_test]
+    fn test_chroot_root_boundary() {
+        use fs_ng_vfs::{Mountpoint, NodePermission};
+
+        let fs = crate::vfs::MemoryFs::new();
+        let mp = Mountpoint::new_root(&fs);
+        let root = mp.root_location();
+        let mut ctx = FsContext::new(root.clone());
+        let jail = ctx.create_dir("/jail", NodePermission::default()).unwrap();
+        let inner = ctx
+            .create_dir("/jail/inner", NodePermission::default())
+            .unwrap();
+
+        // The cwd is kept, outside the new root.
+        ctx.set_root_dir(jail.clone()).unwrap();
+        assert!(ctx.current_dir().ptr_eq(&root));
+        assert!(ctx.path_of(ctx.current_dir()).is_none());
+
+        ctx.set_current_dir(inner.clone()).unwrap();
+        assert!(ctx.resolve("/").unwrap().ptr_eq(&jail));
+        assert!(ctx.resolve("../../..").unwrap().ptr_eq(&jail));
+        assert!(ctx.resolve("/../inner").unwrap().ptr_eq(&inner));
+        assert_eq!(ctx.canonicalize(".").unwrap().as_str(), "/inner");
+        assert_eq!(ctx.path_of(&jail).unwrap().as_str(), "/");
+
+        let new = ctx.create_dir("/new", NodePermission::default()).unwrap();
+        assert!(new.parent().unwrap().ptr_eq(&jail));
+    }
+
+    /// Test tmpfs reports the memory of the allocator as its capacity
+    #[def_test]
+    fn test_tmpfs_statfs() {
+        use fs_ng_vfs::Mountpoint;
+
+        let fs = crate::vfs::MemoryFs::new();
+        let mp = Mountpoint::new_root(&fs);
+        let ctx = FsContext::new(mp.root_location());
+        ctx.write("/file", b"data").unwrap();
+
+        let stat = ctx.statfs("/file").unwrap();
+        assert_eq!(stat, fs.stat().unwrap());
+        assert_eq!(stat.fs_type, 0x01021994);
+        assert_eq!(stat.block_size, 4096);
+        assert!(stat.blocks_free > 0 && stat.blocks_free <= stat.blocks);
+        assert_eq!(stat.blocks_available, stat.blocks_free);
+    }
+
+    /// Test the mount table: sources, paths and unmounting nested mounts
+    #[def_test]
+    fn test_mount_table() {
+        use alloc::{string::ToString, vec::Vec};
+
+        use fs_ng_vfs::{Mountpoint, NodePermission};
+
+        let fs = crate::vfs::MemoryFs::new();
+        let mp = Mountpoint::new_root_from(&fs, "/dev/vda");
+        let ctx = FsContext::new(mp.root_location());
+        let mnt = ctx.create_dir("/mnt", NodePermission::default()).unwrap();
+        let inner = mnt
+            .mount_from(&crate::vfs::MemoryFs::new(), "scratch")
+            .unwrap()
+            .root_location();
+        ctx.create_dir("/mnt/a b", NodePermission::default())
+            .unwrap()
+            .mount(&crate::vfs::MemoryFs::new())
+            .unwrap();
+
+        let table: Vec<_> = mp
+            .mount_table()
+            .iter()
+            .map(|it| {
+                (
+                    it.source().to_string(),
+                    it.mount_path().unwrap().to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            table,
+            [
+                ("/dev/vda".to_string(), "/".to_string()),
+                ("scratch".to_string(), "/mnt".to_string()),
+                ("tmpfs".to_string(), "/mnt/a b".to_string()),
+            ]
+        );
+        assert_eq!(mp.mount_table()[1].fs_type(), "tmpfs");
+
+        // Busy while something is mounted under it, not after
+        assert!(inner.unmount().is_err());
+        ctx.resolve("/mnt/a b").unwrap().unmount().unwrap();
+        inner.unmount().unwrap();
+        assert_eq!(mp.mount_table().len(), 1);
+    }
+
+    /// Test a file attached as a loop device
+    #[def_test]
+    fn test_loop_device() {
+        use fs_ng_vfs::Mountpoint;
+        use kfs::OpenOptions;
+        use ktask::future::block_on;
+
+        let fs = crate::vfs::MemoryFs::new();
+        let mp = Mountpoint::new_root(&fs);
+        let ctx = FsContext::new(mp.root_location());
+        let mut image = alloc::vec![0u8; 4096];
+        image[512..1024].fill(0xaa);
+        ctx.write("/image", &image).unwrap();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&ctx, "/image")
+            .unwrap()
+            .into_file()
+            .unwrap();
+
+        let backend = file.backend().unwrap().clone();
+        assert!(
+            kfs::attach_loop("loop7", backend.clone(), false)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(kfs::attach_loop("loop7", backend, false).is_err());
+        let info = kfs::block_devices()
+            .into_iter()
+            .find(|it| it.name == "loop7")
+            .unwrap();
+        assert_eq!(info.num_blocks, 8);
+        assert_eq!(
+            block_on(kfs::read_blocks("loop7", 1, 512)).unwrap(),
+            &image[512..1024]
+        );
+
+        // Queued writes reach the file once detached
+        block_on(kfs::write_blocks("loop7", 2, alloc::vec![0x55; 512])).unwrap();
+        kfs::detach_loop("loop7").unwrap();
+        assert!(
+            ctx.read("/image").unwrap()[1024..1536]
+                .iter()
+                .all(|&b| b == 0x55)
+        );
+        assert!(kfs::detach_loop("loop7").is_err());
+    }
+
+    /// Test symlink targets, loop limits and `O_NOFOLLOW`
+    #[def_test]
+    fn test_symlink_resolution() {
+        use fs_ng_vfs::{Mountpoint, NodePermission, NodeType};
+        use kfs::OpenOptions;
+
+        let fs = crate::vfs::MemoryFs::new();
+        let mp = Mountpoint::new_root(&fs);
+        let mut ctx = FsContext::new(mp.root_location());
+        let dir = ctx.create_dir("/dir", NodePermission::default()).unwrap();
+        ctx.write("/dir/file", b"data").unwrap();
+        let mnt = ctx.create_dir("/mnt", NodePermission::default()).unwrap();
+        mnt.mount(&crate::vfs::MemoryFs::new()).unwrap();
+
+        // Relative targets start from the link's directory, even across mounts.
+        ctx.symlink("file", "/dir/rel").unwrap();
+        ctx.symlink("/dir/file", "/dir/abs").unwrap();
+        ctx.symlink("../dir", "/mnt/up").unwrap();
+        assert_eq!(ctx.read("/dir/rel").unwrap(), b"data");
+        assert_eq!(ctx.read("/dir/abs").unwrap(), b"data");
+        assert!(ctx.resolve("/mnt/up").unwrap().ptr_eq(&dir));
+        assert_eq!(ctx.read("/mnt/up/rel").unwrap(), b"data");
+        let link = ctx.resolve_no_follow("/dir/rel").unwrap();
+        assert_eq!(link.node_type(), NodeType::Symlink);
+
+        ctx.symlink("loop", "/loop").unwrap();
+        assert!(matches!(ctx.resolve("/loop"), Err(KError::FilesystemLoop)));
+        assert!(matches!(
+            ctx.resolve("/loop/x"),
+            Err(KError::FilesystemLoop)
+        ));
+
+        // `/mnt/up/rel` follows two links; allow only one.
+        ctx.set_max_symlinks(1);
+        assert!(matches!(
+            ctx.resolve("/mnt/up/rel"),
+            Err(KError::FilesystemLoop)
+        ));
+        assert!(ctx.resolve("/dir/rel").is_ok());
+
+        let mut options = OpenOptions::new();
+        options.read(true).no_follow(true);
+        assert!(matches!(
+            options.open(&ctx, "/dir/rel"),
+            Err(KError::FilesystemLoop)
+        ));
+        options.path(true);
+        assert!(options.open(&ctx, "/dir/rel").is_ok());
+
+        // Creating through a dangling link creates its target.
+        ctx.symlink("new", "/dir/dangling").unwrap();
+        let mut options = OpenOptions::new();
+        options.write(true).create(true);
+        assert!(options.open(&ctx, "/dir/dangling").is_ok());
+        assert!(ctx.resolve("/dir/new").is_ok());
+    }
 }
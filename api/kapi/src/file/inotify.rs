@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+use alloc::{borrow::Cow, sync::Arc, vec::Vec};
+use core::{
+    mem,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Context,
+};
+
+use fs_ng_vfs::watch::{WatchEvent, WatchQueue};
+use kerrno::{KError, KResult};
+use kpoll::{IoEvents, Pollable};
+use ktask::future::{block_on, poll_io};
+use linux_raw_sys::ioctl::FIONREAD;
+use osvm::VirtMutPtr;
+use zerocopy::{Immutable, IntoBytes};
+
+use crate::file::{FileLike, IoDst, IoSrc};
+
+/// Header of an event as read from an inotify file, followed by `len` bytes
+/// of NUL-padded name.
+#[repr(C)]
+#[derive(Immutable, IntoBytes)]
+struct InotifyEvent {
+    wd: i32,
+    mask: u32,
+    cookie: u32,
+    len: u32,
+}
+
+const EVENT_SIZE: usize = mem::size_of::<InotifyEvent>();
+
+/// Length of the name field of `event`: the name with its NUL, padded to a
+/// multiple of the header size as Linux does.
+fn name_len(event: &WatchEvent) -> usize {
+    event
+        .name
+        .as_ref()
+        .map_or(0, |name| (name.len() + 1).next_multiple_of(EVENT_SIZE))
+}
+
+fn record_len(event: &WatchEvent) -> usize {
+    EVENT_SIZE + name_len(event)
+}
+
+fn encode(event: &WatchEvent, buf: &mut Vec<u8>) {
+    let len = name_len(event);
+    let header = InotifyEvent {
+        wd: event.wd,
+        mask: event.mask.bits(),
+        cookie: event.cookie,
+        len: len as u32,
+    };
+    buf.extend_from_slice(header.as_bytes());
+    if let Some(name) = &event.name {
+        let start = buf.len();
+        buf.extend_from_slice(name.as_bytes());
+        buf.resize(start + len, 0);
+    }
+}
+
+/// An inotify instance, reading the events of a [`WatchQueue`].
+pub struct Inotify {
+    queue: Arc<WatchQueue>,
+    non_blocking: AtomicBool,
+}
+
+impl Inotify {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            queue: WatchQueue::new(),
+            non_blocking: AtomicBool::new(false),
+        })
+    }
+
+    pub fn queue(&self) -> &Arc<WatchQueue> {
+        &self.queue
+    }
+}
+
+impl FileLike for Inotify {
+    fn read(&self, dst: &mut IoDst) -> KResult<usize> {
+        block_on(poll_io(self, IoEvents::IN, self.nonblocking(), || {
+            let mut buf = Vec::new();
+            self.queue.with_events(|events| {
+                let Some(first) = events.front() else {
+                    return Err(KError::WouldBlock);
+                };
+                if record_len(first) > dst.remaining_mut() {
+                    return Err(KError::InvalidInput);
+                }
+                while let Some(event) = events.front()
+                    && buf.len() + record_len(event) <= dst.remaining_mut()
+                {
+                    encode(event, &mut buf);
+                    events.pop_front();
+                }
+                Ok(())
+            })?;
+            dst.write(&buf)?;
+            Ok(buf.len())
+        }))
+    }
+
+    fn write(&self, _src: &mut IoSrc) -> KResult<usize> {
+        Err(KError::BadFileDescriptor)
+    }
+
+    fn nonblocking(&self) -> bool {
+        self.non_blocking.load(Ordering::Acquire)
+    }
+
+    fn set_nonblocking(&self, non_blocking: bool) -> KResult {
+        self.non_blocking.store(non_blocking, Ordering::Release);
+        Ok(())
+    }
+
+    fn path(&self) -> Cow<'_, str> {
+        "anon_inode:inotify".into()
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> KResult<usize> {
+        match cmd {
+            FIONREAD => {
+                let len = self
+                    .queue
+                    .with_events(|events| events.iter().map(record_len).sum::<usize>());
+                (arg as *mut u32).write_vm(len as u32)?;
+                Ok(0)
+            }
+            _ => Err(KError::NotATty),
+        }
+    }
+}
+
+impl Pollable for Inotify {
+    fn poll(&self) -> IoEvents {
+        self.queue.poll()
+    }
+
+    fn register(&self, context: &mut Context<'_>, events: IoEvents) {
+        self.queue.register(context, events);
+    }
+}
+
+#[cfg(unittest)]
+mod inotify_tests {
+    use alloc::string::ToString;
+
+    use fs_ng_vfs::watch::WatchMask;
+    use unittest::def_test;
+
+    use super::*;
+
+    /// Names are NUL-terminated and padded to a multiple of the header size
+    #[def_test]
+    fn test_inotify_event_encoding() {
+        let mut event = WatchEvent {
+            wd: 1,
+            mask: WatchMask::CREATE,
+            cookie: 0,
+            name: None,
+        };
+        assert_eq!(record_len(&event), 16);
+
+        event.name = Some("0123456789abcdef".to_string());
+        let mut buf = Vec::new();
+        encode(&event, &mut buf);
+        assert_eq!(buf.len(), 48);
+        assert_eq!(buf.len(), record_len(&event));
+        assert_eq!(&buf[12..16], &32u32.to_ne_bytes());
+        assert_eq!(&buf[16..32], b"0123456789abcdef");
+        assert!(buf[32..].iter().all(|&it| it == 0));
+    }
+}
@@ -7,6 +7,7 @@
 pub mod epoll;
 pub mod event;
 mod fs;
+pub mod inotify;
 mod net;
 mod pidfd;
 mod pipe;
@@ -249,6 +250,12 @@ pub fn get_file_like(fd: c_int) -> KResult<Arc<dyn FileLike>> {
         .ok_or(KError::BadFileDescriptor)
 }
 
+/// Returns the current process's `RLIMIT_NOFILE`: file descriptors must be
+/// below it.
+pub fn fd_limit() -> u64 {
+    current().as_thread().proc_data.rlim.read()[RLIMIT_NOFILE].current
+}
+
 /// Adds a file-like object to the current process's file descriptor table.
 ///
 /// # Arguments
@@ -258,7 +265,7 @@ pub fn get_file_like(fd: c_int) -> KResult<Arc<dyn FileLike>> {
 /// # Returns
 /// The new file descriptor number, or an error if the table is full.
 pub fn add_file_like(f: Arc<dyn FileLike>, cloexec: bool) -> KResult<c_int> {
-    let max_nofile = current().as_thread().proc_data.rlim.read()[RLIMIT_NOFILE].current;
+    let max_nofile = fd_limit();
     let mut table = FD_TABLE.write();
     if table.count() as u64 >= max_nofile {
         return Err(KError::TooManyOpenFiles);
@@ -277,9 +284,18 @@ pub fn close_file_like(fd: c_int) -> KResult {
         .remove(fd as usize)
         .ok_or(KError::BadFileDescriptor)?;
     debug!("close_file_like <= count: {}", Arc::strong_count(&f.inner));
+    release_record_locks(&f);
     Ok(())
 }
 
+/// Drops the record locks the current process holds on the file behind a
+/// descriptor that is being closed.
+pub fn release_record_locks(f: &FileDescriptor) {
+    if let Some(file) = f.inner.downcast_ref::<File>() {
+        file.release_record_locks(current().as_thread().proc_data.proc.pid());
+    }
+}
+
 pub fn add_stdio(fd_table: &mut FlattenObjects<FileDescriptor, { FILE_LIMIT }>) -> KResult<()> {
     assert_eq!(fd_table.count(), 0);
     let cx = FS_CONTEXT.lock();
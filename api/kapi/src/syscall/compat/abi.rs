@@ -0,0 +1,188 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Register conventions and structure layouts of the 32-bit syscall ABIs.
+//!
+//! `long`, `time_t` and pointers are 4 bytes wide here. i386 packs 64-bit
+//! members on 4-byte boundaries, while ARM EABI keeps them 8-byte aligned and
+//! passes 64-bit arguments in even/odd register pairs.
+
+use core::mem::size_of;
+
+use bytemuck::AnyBitPattern;
+use kerrno::{KError, KResult};
+use khal::{time::TimeValue, uspace::UserContext};
+use static_assertions::const_assert_eq;
+
+use crate::{file::Kstat, time::TimeValueLike};
+
+/// Returns the raw syscall number and the six zero-extended arguments.
+///
+/// i386 enters through `int 0x80` with the number in `eax` and arguments in
+/// `ebx`, `ecx`, `edx`, `esi`, `edi` and `ebp`.
+#[cfg(target_arch = "x86_64")]
+pub fn syscall_regs(uctx: &UserContext) -> (usize, [usize; 6]) {
+    let regs = [uctx.rbx, uctx.rcx, uctx.rdx, uctx.rsi, uctx.rdi, uctx.rbp];
+    (uctx.rax as u32 as usize, regs.map(|r| r as u32 as usize))
+}
+
+/// Returns the raw syscall number and the six zero-extended arguments.
+///
+/// ARM EABI passes the number in `r7` and arguments in `r0`-`r5`, which are
+/// banked into the low halves of `x7` and `x0`-`x5`.
+#[cfg(target_arch = "aarch64")]
+pub fn syscall_regs(uctx: &UserContext) -> (usize, [usize; 6]) {
+    let regs = [
+        uctx.x[0], uctx.x[1], uctx.x[2], uctx.x[3], uctx.x[4], uctx.x[5],
+    ];
+    (uctx.x[7] as u32 as usize, regs.map(|r| r as u32 as usize))
+}
+
+/// Index of the register holding the low half of a 64-bit argument that
+/// follows the first `idx` 32-bit ones.
+pub const fn pair(idx: usize) -> usize {
+    if cfg!(target_arch = "aarch64") {
+        (idx + 1) & !1
+    } else {
+        idx
+    }
+}
+
+/// Joins the 64-bit argument starting at register `idx` (see [`pair`]).
+pub fn arg64(args: &[usize; 6], idx: usize) -> i64 {
+    let idx = pair(idx);
+    ((args[idx + 1] as u64) << 32 | args[idx] as u64) as i64
+}
+
+/// Sign-extends a 32-bit `long` argument.
+pub const fn sext(arg: usize) -> isize {
+    arg as u32 as i32 as isize
+}
+
+/// 32-bit `struct iovec`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, AnyBitPattern)]
+pub struct CompatIoVec {
+    pub iov_base: u32,
+    pub iov_len: u32,
+}
+
+/// 32-bit `struct timespec`, with a 32-bit `time_t`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, AnyBitPattern)]
+pub struct CompatTimespec {
+    pub tv_sec: i32,
+    pub tv_nsec: i32,
+}
+
+impl TimeValueLike for CompatTimespec {
+    fn from_time_value(tv: TimeValue) -> Self {
+        Self {
+            tv_sec: tv.as_secs() as _,
+            tv_nsec: tv.subsec_nanos() as _,
+        }
+    }
+
+    fn try_into_time_value(self) -> KResult<TimeValue> {
+        if self.tv_nsec < 0 || self.tv_nsec > 999_999_999 || self.tv_sec < 0 {
+            return Err(KError::InvalidInput);
+        }
+        Ok(TimeValue::new(self.tv_sec as u64, self.tv_nsec as u32))
+    }
+}
+
+/// 32-bit `struct timeval`, with a 32-bit `time_t`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, AnyBitPattern)]
+pub struct CompatTimeval {
+    pub tv_sec: i32,
+    pub tv_usec: i32,
+}
+
+impl TimeValueLike for CompatTimeval {
+    fn from_time_value(tv: TimeValue) -> Self {
+        Self {
+            tv_sec: tv.as_secs() as _,
+            tv_usec: tv.subsec_micros() as _,
+        }
+    }
+
+    fn try_into_time_value(self) -> KResult<TimeValue> {
+        if self.tv_usec < 0 || self.tv_usec > 999_999 || self.tv_sec < 0 {
+            return Err(KError::InvalidInput);
+        }
+        Ok(TimeValue::new(
+            self.tv_sec as u64,
+            self.tv_usec as u32 * 1000,
+        ))
+    }
+}
+
+/// i386 `struct user_desc`, as taken by `set_thread_area`.
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, AnyBitPattern)]
+pub struct UserDesc {
+    pub entry_number: u32,
+    pub base_addr: u32,
+    pub limit: u32,
+    pub flags: u32,
+}
+
+/// `struct stat64` of the 32-bit ABIs.
+#[repr(C)]
+#[cfg_attr(target_arch = "x86_64", repr(packed(4)))]
+#[derive(Debug, Clone, Copy)]
+pub struct CompatStat64 {
+    st_dev: u64,
+    __pad0: [u8; 4],
+    __st_ino: u32,
+    st_mode: u32,
+    st_nlink: u32,
+    st_uid: u32,
+    st_gid: u32,
+    st_rdev: u64,
+    __pad3: [u8; 4],
+    st_size: i64,
+    st_blksize: u32,
+    st_blocks: u64,
+    st_atime: u32,
+    st_atime_nsec: u32,
+    st_mtime: u32,
+    st_mtime_nsec: u32,
+    st_ctime: u32,
+    st_ctime_nsec: u32,
+    st_ino: u64,
+}
+
+#[cfg(target_arch = "x86_64")]
+const_assert_eq!(size_of::<CompatStat64>(), 96);
+#[cfg(target_arch = "aarch64")]
+const_assert_eq!(size_of::<CompatStat64>(), 104);
+
+impl From<Kstat> for CompatStat64 {
+    fn from(value: Kstat) -> Self {
+        Self {
+            st_dev: value.dev,
+            __pad0: [0; 4],
+            __st_ino: value.ino as _,
+            st_mode: value.mode,
+            st_nlink: value.nlink,
+            st_uid: value.uid,
+            st_gid: value.gid,
+            st_rdev: value.rdev.0 as _,
+            __pad3: [0; 4],
+            st_size: value.size as _,
+            st_blksize: value.blksize,
+            st_blocks: value.blocks,
+            st_atime: value.atime.as_secs() as _,
+            st_atime_nsec: value.atime.subsec_nanos(),
+            st_mtime: value.mtime.as_secs() as _,
+            st_mtime_nsec: value.mtime.subsec_nanos(),
+            st_ctime: value.ctime.as_secs() as _,
+            st_ctime_nsec: value.ctime.subsec_nanos(),
+            st_ino: value.ino,
+        }
+    }
+}
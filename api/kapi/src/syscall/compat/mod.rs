@@ -0,0 +1,480 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! 32-bit compatibility syscall layer.
+//!
+//! Processes loaded from i386 (on x86_64) or armhf (on aarch64) ELF files
+//! trap into the kernel with the syscall numbers of [`CompatSysno`] and
+//! 32-bit argument registers. This module decodes them and:
+//! - translates calls whose arguments or structures differ in layout
+//!   (64-bit offsets split over two registers, `stat64`, 32-bit `timespec`,
+//!   `iovec` and pointer arrays) before calling the native handlers;
+//! - maps legacy path-based calls onto their `*at` forms;
+//! - forwards the remaining calls, whose arguments are plain integers and
+//!   file descriptors, to the native table by name, after renaming the
+//!   `*32`/`*64`/`*_time64` variants;
+//! - refuses calls whose 32-bit structures are not translated yet.
+//!
+//! Signal frames are still built with the native layout, so 32-bit
+//! programs that install signal handlers are not supported.
+
+mod abi;
+
+use alloc::{string::String, vec::Vec};
+use core::{ffi::c_char, str::FromStr};
+
+use kerrno::{KError, KResult, LinuxError};
+use khal::uspace::UserContext;
+#[cfg(target_arch = "x86_64")]
+use linux_raw_sys::general::CLONE_SETTLS;
+use linux_raw_sys::general::{
+    __kernel_clockid_t, AT_EMPTY_PATH, AT_FDCWD, AT_REMOVEDIR, AT_SYMLINK_NOFOLLOW, CLONE_VFORK,
+    CLONE_VM, O_CREAT, O_TRUNC, O_WRONLY, SIGCHLD,
+};
+#[cfg(target_arch = "aarch64")]
+use linux_sysno::arm::Sysno as CompatSysno;
+#[cfg(target_arch = "x86_64")]
+use linux_sysno::x86::Sysno as CompatSysno;
+use linux_sysno::{SyscallArgs, Sysno};
+use osvm::{VirtMutPtr, VirtPtr, load_vec, load_vec_until_null};
+
+use self::abi::*;
+use super::*;
+use crate::{file::resolve_at, mm::vm_load_string, time::TimeValueLike};
+
+/// ARM private syscall setting the user read-only thread pointer.
+#[cfg(target_arch = "aarch64")]
+const ARM_SET_TLS: usize = 0x0f_0005;
+
+/// Dispatches a syscall made by a 32-bit program.
+pub fn dispatch_compat_syscall(uctx: &mut UserContext) {
+    let (nr, args) = syscall_regs(uctx);
+
+    #[cfg(target_arch = "aarch64")]
+    if nr == ARM_SET_TLS {
+        uctx.set_tls(args[0]);
+        uctx.set_retval(0);
+        return;
+    }
+
+    let Some(sysno) = CompatSysno::new(nr) else {
+        warn!("Invalid compat syscall number: {nr}");
+        uctx.set_retval(-LinuxError::ENOSYS.into_raw() as _);
+        return;
+    };
+
+    trace!("Compat syscall {sysno:?}");
+
+    let result = dispatch(uctx, sysno, &args);
+    debug!("Compat syscall {sysno} return {result:?}");
+
+    let ret = result.unwrap_or_else(|err| -LinuxError::from(err).into_raw() as _);
+    uctx.set_retval(ret as u32 as _);
+}
+
+fn dispatch(uctx: &mut UserContext, sysno: CompatSysno, args: &[usize; 6]) -> KResult<isize> {
+    let at = AT_FDCWD;
+    let path = |idx: usize| args[idx] as *const c_char;
+
+    match sysno {
+        // legacy path-based calls
+        CompatSysno::open => sys_openat(at, path(0), args[1] as _, args[2] as _),
+        CompatSysno::creat => sys_openat(
+            at,
+            path(0),
+            (O_CREAT | O_WRONLY | O_TRUNC) as _,
+            args[1] as _,
+        ),
+        CompatSysno::mkdir => sys_mkdirat(at, path(0), args[1] as _),
+        CompatSysno::rmdir => sys_unlinkat(at, path(0), AT_REMOVEDIR as _),
+        CompatSysno::unlink => sys_unlinkat(at, path(0), 0),
+        CompatSysno::link => sys_linkat(at, path(0), at, path(1), 0),
+        CompatSysno::symlink => sys_symlinkat(path(0), at, path(1)),
+        CompatSysno::readlink => sys_readlinkat(at, path(0), args[1] as _, args[2]),
+        CompatSysno::rename => sys_renameat2(at, path(0), at, path(1), 0),
+        CompatSysno::chmod => sys_fchmodat(at, path(0), args[1] as _, 0),
+        CompatSysno::chown | CompatSysno::chown32 => {
+            sys_fchownat(at, path(0), args[1] as _, args[2] as _, 0)
+        }
+        CompatSysno::lchown | CompatSysno::lchown32 => {
+            sys_fchownat(at, path(0), args[1] as _, args[2] as _, AT_SYMLINK_NOFOLLOW)
+        }
+        CompatSysno::access => sys_faccessat2(at, path(0), args[1] as _, 0),
+
+        // legacy descriptor and process calls
+        CompatSysno::pipe => sys_pipe2(args[0] as _, 0),
+        CompatSysno::dup2 => sys_dup2(args[0] as _, args[1] as _),
+        CompatSysno::poll => sys_poll(args[0].into(), args[1] as _, args[2] as _),
+        CompatSysno::epoll_create => sys_epoll_create1(0),
+        CompatSysno::eventfd => sys_eventfd2(args[0] as _, 0),
+        CompatSysno::signalfd => sys_signalfd4(args[0] as _, args[1] as _, args[2], 0),
+        CompatSysno::inotify_init => sys_inotify_init1(0),
+        CompatSysno::getpgrp => sys_getpgid(0),
+        CompatSysno::fork => sys_clone(uctx, SIGCHLD, 0, 0, 0, 0),
+        CompatSysno::vfork => sys_clone(uctx, CLONE_VM | CLONE_VFORK | SIGCHLD, 0, 0, 0, 0),
+        #[cfg(target_arch = "x86_64")]
+        CompatSysno::waitpid => sys_waitpid(args[0] as _, args[1] as _, args[2] as _),
+        #[cfg(target_arch = "x86_64")]
+        CompatSysno::time => compat_time(args[0] as _),
+
+        // 64-bit offsets
+        CompatSysno::lseek => sys_lseek(args[0] as _, sext(args[1]) as _, args[2] as _),
+        CompatSysno::_llseek => compat_llseek(
+            args[0] as _,
+            (args[1] as u64) << 32 | args[2] as u64,
+            args[3] as _,
+            args[4] as _,
+        ),
+        CompatSysno::mmap2 => sys_mmap(
+            args[0],
+            args[1],
+            args[2] as _,
+            args[3] as _,
+            args[4] as i32,
+            (args[5] << 12) as _,
+        ),
+        CompatSysno::pread64 => sys_pread64(args[0] as _, args[1] as _, args[2], arg64(args, 3)),
+        CompatSysno::pwrite64 => sys_pwrite64(args[0] as _, args[1] as _, args[2], arg64(args, 3)),
+        CompatSysno::truncate => sys_truncate(args[0].into(), sext(args[1]) as _),
+        CompatSysno::ftruncate => sys_ftruncate(args[0] as _, sext(args[1]) as _),
+        CompatSysno::truncate64 => sys_truncate(args[0].into(), arg64(args, 1)),
+        CompatSysno::ftruncate64 => sys_ftruncate(args[0] as _, arg64(args, 1)),
+        CompatSysno::fallocate => compat_fallocate(args),
+
+        // structure layouts
+        CompatSysno::readv => compat_readv(args[0] as _, args[1] as _, args[2]),
+        CompatSysno::writev => compat_writev(args[0] as _, args[1] as _, args[2]),
+        CompatSysno::stat64 => compat_fstatat64(at, path(0), args[1] as _, 0),
+        CompatSysno::lstat64 => compat_fstatat64(at, path(0), args[1] as _, AT_SYMLINK_NOFOLLOW),
+        CompatSysno::fstat64 => {
+            compat_fstatat64(args[0] as _, core::ptr::null(), args[1] as _, AT_EMPTY_PATH)
+        }
+        CompatSysno::fstatat64 => {
+            compat_fstatat64(args[0] as _, path(1), args[2] as _, args[3] as _)
+        }
+        CompatSysno::clock_gettime => compat_clock_gettime(args[0] as _, args[1] as _),
+        CompatSysno::clock_getres => compat_clock_getres(args[1] as _),
+        CompatSysno::gettimeofday => compat_gettimeofday(args[0] as _),
+        CompatSysno::nanosleep => compat_nanosleep(args[0] as _, args[1] as _),
+        CompatSysno::execve => compat_execve(uctx, path(0), args[1] as _, args[2] as _),
+
+        // thread pointer
+        #[cfg(target_arch = "x86_64")]
+        CompatSysno::set_thread_area => compat_set_thread_area(uctx, args[0] as _),
+        #[cfg(target_arch = "x86_64")]
+        CompatSysno::clone => {
+            let mut tls = args[3];
+            if args[0] as u32 & CLONE_SETTLS != 0 {
+                tls = compat_tls_base(tls as _)?;
+            }
+            sys_clone(uctx, args[0] as _, args[1], args[2], args[4], tls)
+        }
+
+        _ => forward(uctx, sysno, args),
+    }
+}
+
+/// Runs a call whose arguments need no translation through the native table.
+fn forward(uctx: &mut UserContext, sysno: CompatSysno, args: &[usize; 6]) -> KResult<isize> {
+    let name = sysno.name();
+    if is_untranslated(name) {
+        warn!("Compat syscall {name} is not supported");
+        return Err(KError::Unsupported);
+    }
+    match Sysno::from_str(native_name(name)) {
+        Ok(native) => dispatch_syscall(uctx, native, SyscallArgs::from(args)),
+        Err(_) => {
+            warn!("Unimplemented compat syscall: {name}");
+            Err(KError::Unsupported)
+        }
+    }
+}
+
+/// Maps the name of a 32-bit variant onto the native call it aliases.
+fn native_name(name: &str) -> &str {
+    match name {
+        "getuid32" => "getuid",
+        "geteuid32" => "geteuid",
+        "getgid32" => "getgid",
+        "getegid32" => "getegid",
+        "setuid32" => "setuid",
+        "setgid32" => "setgid",
+        "setreuid32" => "setreuid",
+        "setregid32" => "setregid",
+        "setresuid32" => "setresuid",
+        "setresgid32" => "setresgid",
+        "getresuid32" => "getresuid",
+        "getresgid32" => "getresgid",
+        "getgroups32" => "getgroups",
+        "setgroups32" => "setgroups",
+        "setfsuid32" => "setfsuid",
+        "setfsgid32" => "setfsgid",
+        "fchown32" => "fchown",
+        "fcntl64" => "fcntl",
+        "sendfile64" => "sendfile",
+        "clock_gettime64" => "clock_gettime",
+        "clock_settime64" => "clock_settime",
+        "clock_getres_time64" => "clock_getres",
+        "clock_nanosleep_time64" => "clock_nanosleep",
+        "futex_time64" => "futex",
+        "utimensat_time64" => "utimensat",
+        "ppoll_time64" => "ppoll",
+        "timer_gettime64" => "timer_gettime",
+        "timer_settime64" => "timer_settime",
+        "timerfd_gettime64" => "timerfd_gettime",
+        "timerfd_settime64" => "timerfd_settime",
+        "rt_sigtimedwait_time64" => "rt_sigtimedwait",
+        "sched_rr_get_interval_time64" => "sched_rr_get_interval",
+        _ => name,
+    }
+}
+
+/// Returns whether the call takes structures whose 32-bit layout differs
+/// from the native one and is not translated above.
+fn is_untranslated(name: &str) -> bool {
+    matches!(
+        name,
+        "mmap"
+            | "select"
+            | "_newselect"
+            | "pselect6"
+            | "pselect6_time64"
+            | "ppoll"
+            | "stat"
+            | "lstat"
+            | "fstat"
+            | "oldstat"
+            | "oldlstat"
+            | "oldfstat"
+            | "statfs"
+            | "fstatfs"
+            | "statfs64"
+            | "fstatfs64"
+            | "getrlimit"
+            | "setrlimit"
+            | "ugetrlimit"
+            | "sysinfo"
+            | "times"
+            | "getrusage"
+            | "wait4"
+            | "waitid"
+            | "sigaction"
+            | "rt_sigaction"
+            | "sigaltstack"
+            | "sigreturn"
+            | "rt_sigreturn"
+            | "signal"
+            | "sigprocmask"
+            | "sigpending"
+            | "sigsuspend"
+            | "rt_sigtimedwait"
+            | "getdents"
+            | "readdir"
+            | "socketcall"
+            | "ipc"
+            | "sendmsg"
+            | "recvmsg"
+            | "sendmmsg"
+            | "recvmmsg"
+            | "recvmmsg_time64"
+            | "msgctl"
+            | "shmctl"
+            | "semctl"
+            | "msgsnd"
+            | "msgrcv"
+            | "semtimedop"
+            | "semtimedop_time64"
+            | "clock_settime"
+            | "clock_nanosleep"
+            | "settimeofday"
+            | "stime"
+            | "utime"
+            | "utimes"
+            | "futimesat"
+            | "utimensat"
+            | "futex"
+            | "io_getevents"
+            | "io_pgetevents"
+            | "io_pgetevents_time64"
+            | "mq_timedsend"
+            | "mq_timedreceive"
+            | "mq_timedsend_time64"
+            | "mq_timedreceive_time64"
+            | "sched_rr_get_interval"
+            | "timer_gettime"
+            | "timer_settime"
+            | "timerfd_gettime"
+            | "timerfd_settime"
+            | "getitimer"
+            | "setitimer"
+            | "adjtimex"
+            | "clock_adjtime"
+            | "clock_adjtime64"
+            | "preadv"
+            | "pwritev"
+            | "preadv2"
+            | "pwritev2"
+            | "process_vm_readv"
+            | "process_vm_writev"
+            | "vmsplice"
+            | "set_robust_list"
+            | "get_robust_list"
+            | "sendfile"
+            | "_sysctl"
+            | "fadvise64"
+            | "fadvise64_64"
+            | "arm_fadvise64_64"
+            | "sync_file_range"
+            | "sync_file_range2"
+            | "arm_sync_file_range"
+            | "readahead"
+            | "execveat"
+            | "get_thread_area"
+    )
+}
+
+#[cfg(target_arch = "x86_64")]
+fn compat_time(tloc: *mut i32) -> KResult<isize> {
+    let now = khal::time::wall_time().as_secs() as i32;
+    if let Some(tloc) = tloc.check_non_null() {
+        tloc.write_vm(now)?;
+    }
+    Ok(now as _)
+}
+
+fn compat_llseek(fd: i32, offset: u64, result: *mut i64, whence: i32) -> KResult<isize> {
+    let pos = sys_lseek(fd, offset as _, whence)?;
+    result.write_vm(pos as i64)?;
+    Ok(0)
+}
+
+fn compat_fallocate(args: &[usize; 6]) -> KResult<isize> {
+    // `len` follows the register pair of `offset`.
+    let len_idx = pair(2) + 2;
+    sys_fallocate(
+        args[0] as _,
+        args[1] as _,
+        arg64(args, 2),
+        arg64(args, len_idx),
+    )
+}
+
+fn compat_readv(fd: i32, iov: *const CompatIoVec, iovcnt: usize) -> KResult<isize> {
+    let mut total = 0;
+    for v in load_vec(iov, iovcnt)? {
+        let n = sys_read(fd, v.iov_base as _, v.iov_len as _)?;
+        total += n;
+        if n < v.iov_len as isize {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+fn compat_writev(fd: i32, iov: *const CompatIoVec, iovcnt: usize) -> KResult<isize> {
+    let mut total = 0;
+    for v in load_vec(iov, iovcnt)? {
+        let n = sys_write(fd, v.iov_base as _, v.iov_len as _)?;
+        total += n;
+        if n < v.iov_len as isize {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+fn compat_fstatat64(
+    dirfd: i32,
+    path: *const c_char,
+    statbuf: *mut CompatStat64,
+    flags: u32,
+) -> KResult<isize> {
+    let path = path.check_non_null().map(vm_load_string).transpose()?;
+    debug!("compat_fstatat64 <= dirfd: {dirfd}, path: {path:?}, flags: {flags}");
+
+    let loc = resolve_at(dirfd, path.as_deref(), flags)?;
+    statbuf.write_vm(loc.stat()?.into())?;
+    Ok(0)
+}
+
+fn compat_clock_gettime(clock_id: __kernel_clockid_t, ts: *mut CompatTimespec) -> KResult<isize> {
+    ts.write_vm(CompatTimespec::from_time_value(clock_time(clock_id)))?;
+    Ok(0)
+}
+
+fn compat_clock_getres(res: *mut CompatTimespec) -> KResult<isize> {
+    if let Some(res) = res.check_non_null() {
+        res.write_vm(CompatTimespec::from_time_value(
+            khal::time::TimeValue::from_micros(1),
+        ))?;
+    }
+    Ok(0)
+}
+
+fn compat_gettimeofday(tv: *mut CompatTimeval) -> KResult<isize> {
+    tv.write_vm(CompatTimeval::from_time_value(khal::time::wall_time()))?;
+    Ok(0)
+}
+
+fn compat_nanosleep(req: *const CompatTimespec, rem: *mut CompatTimespec) -> KResult<isize> {
+    let req = req.read_vm()?.try_into_time_value()?;
+    let actual = sleep_impl(khal::time::monotonic_time, req);
+
+    if let Some(diff) = req.checked_sub(actual) {
+        if let Some(rem) = rem.check_non_null() {
+            rem.write_vm(CompatTimespec::from_time_value(diff))?;
+        }
+        Err(KError::Interrupted)
+    } else {
+        Ok(0)
+    }
+}
+
+fn compat_execve(
+    uctx: &mut UserContext,
+    path: *const c_char,
+    argv: *const u32,
+    envp: *const u32,
+) -> KResult<isize> {
+    let load_strings = |ptr: *const u32| -> KResult<Vec<String>> {
+        if ptr.is_null() {
+            return Ok(Vec::new());
+        }
+        load_vec_until_null(ptr)?
+            .into_iter()
+            .map(|p| vm_load_string(p as usize as *const c_char))
+            .collect()
+    };
+
+    let path = vm_load_string(path)?;
+    let args = load_strings(argv)?;
+    let envs = load_strings(envp)?;
+    debug!("compat_execve <= path: {path:?}, args: {args:?}, envs: {envs:?}");
+
+    do_execve(uctx, path, args, envs)
+}
+
+/// Reads the TLS base out of a `struct user_desc`, checking that it targets
+/// the only TLS descriptor slot given to 32-bit programs.
+#[cfg(target_arch = "x86_64")]
+fn compat_tls_base(desc: *const UserDesc) -> KResult<usize> {
+    let desc = desc.read_vm()?;
+    if desc.entry_number as usize != UserContext::COMPAT_TLS_ENTRY {
+        return Err(KError::InvalidInput);
+    }
+    Ok(desc.base_addr as _)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn compat_set_thread_area(uctx: &mut UserContext, desc: *mut UserDesc) -> KResult<isize> {
+    let mut info = desc.read_vm()?;
+    if info.entry_number == u32::MAX {
+        info.entry_number = UserContext::COMPAT_TLS_ENTRY as _;
+        desc.write_vm(info)?;
+    } else if info.entry_number as usize != UserContext::COMPAT_TLS_ENTRY {
+        return Err(KError::InvalidInput);
+    }
+    uctx.set_tls(info.base_addr as _);
+    Ok(0)
+}
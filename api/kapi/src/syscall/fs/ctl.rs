@@ -12,7 +12,7 @@
 //! - Symbolic links (symlink, readlink, etc.)
 //! - File removal (unlink, unlinkat, etc.)
 
-use alloc::{ffi::CString, vec, vec::Vec};
+use alloc::{ffi::CString, format, string::String, vec, vec::Vec};
 use core::{
     ffi::{c_char, c_int},
     mem::offset_of,
@@ -22,7 +22,7 @@ use core::{
 use fs_ng_vfs::{MetadataUpdate, NodePermission, NodeType, path::Path};
 use kcore::task::AsThread;
 use kerrno::{KError, KResult};
-use kfs::{FS_CONTEXT, FsContext};
+use kfs::FS_CONTEXT;
 use khal::time::wall_time;
 use ktask::current;
 use linux_raw_sys::{
@@ -99,7 +99,7 @@ pub fn sys_chroot(path: *const c_char) -> KResult<isize> {
     if loc.node_type() != NodeType::Directory {
         return Err(KError::NotADirectory);
     }
-    *fs = FsContext::new(loc);
+    fs.set_root_dir(loc)?;
     Ok(0)
 }
 
@@ -276,7 +276,13 @@ pub fn sys_getcwd(buf: *mut u8, size: isize) -> KResult<isize> {
         return Ok(0);
     }
 
-    let cwd = FS_CONTEXT.lock().current_dir().absolute_path()?;
+    let fs = FS_CONTEXT.lock();
+    // Like Linux, mark a cwd left outside the root by chroot(2).
+    let cwd: String = match fs.path_of(fs.current_dir()) {
+        Some(path) => path.as_str().into(),
+        None => format!("(unreachable){}", fs.current_dir().absolute_path()?),
+    };
+    drop(fs);
     debug!("sys_getcwd => cwd: {cwd}");
 
     let cwd = CString::new(cwd.as_str()).map_err(|_| KError::InvalidInput)?;
@@ -541,7 +547,9 @@ pub fn sys_renameat2(
 }
 
 pub fn sys_sync() -> KResult<isize> {
-    warn!("dummy sys_sync");
+    // Block writes are held back in the request queues until flushed
+    let root = FS_CONTEXT.lock().root_dir().clone();
+    root.filesystem().flush()?;
     Ok(0)
 }
 
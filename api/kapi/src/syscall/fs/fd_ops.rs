@@ -14,21 +14,24 @@ use alloc::{format, string::ToString, sync::Arc};
 use core::{
     ffi::{c_char, c_int},
     mem,
-    ops::{Deref, DerefMut},
+    ops::{Deref, DerefMut, Range},
 };
 
 use bitflags::bitflags;
 use fs_ng_vfs::{DirEntry, FileNode, Location, NodePermission, NodeType, Reference};
 use kcore::{task::AsThread, vfs::Device};
 use kerrno::{KError, KResult};
-use kfs::{FS_CONTEXT, FileBackend, OpenOptions, OpenResult};
+use kfs::{
+    FS_CONTEXT, FileBackend, FileFlags, FileLock, FileLocks, LockKind, LockOwner, OpenOptions,
+    OpenResult,
+};
 use ktask::current;
 use linux_raw_sys::general::*;
 
 use crate::{
     file::{
-        Directory, FD_TABLE, File, FileLike, Pipe, add_file_like, close_file_like, get_file_like,
-        with_fs,
+        Directory, FD_TABLE, File, FileLike, Pipe, add_file_like, close_file_like, fd_limit,
+        get_file_like, release_record_locks, with_fs,
     },
     mm::{UserPtr, vm_load_string},
     syscall::sys::{sys_getegid, sys_geteuid},
@@ -222,7 +225,7 @@ pub fn sys_dup(old_fd: c_int) -> KResult<isize> {
     dup_fd(old_fd, false)
 }
 
-#[cfg(target_arch = "x86_64")]
+#[cfg(any(target_arch = "x86_64", feature = "compat"))]
 /// Duplicates a file descriptor to a specific target fd.
 pub fn sys_dup2(old_fd: c_int, new_fd: c_int) -> KResult<isize> {
     if old_fd == new_fd {
@@ -247,6 +250,9 @@ pub fn sys_dup3(old_fd: c_int, new_fd: c_int, flags: c_int) -> KResult<isize> {
     if old_fd == new_fd {
         return Err(KError::InvalidInput);
     }
+    if new_fd < 0 || new_fd as u64 >= fd_limit() {
+        return Err(KError::BadFileDescriptor);
+    }
 
     let mut fd_table = FD_TABLE.write();
     let mut f = fd_table
@@ -255,7 +261,9 @@ pub fn sys_dup3(old_fd: c_int, new_fd: c_int, flags: c_int) -> KResult<isize> {
         .ok_or(KError::BadFileDescriptor)?;
     f.cloexec = flags.contains(Dup3Flags::O_CLOEXEC);
 
-    fd_table.remove(new_fd as _);
+    if let Some(old) = fd_table.remove(new_fd as _) {
+        release_record_locks(&old);
+    }
     fd_table
         .add_at(new_fd as _, f)
         .map_err(|_| KError::BadFileDescriptor)?;
@@ -270,12 +278,8 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> KResult<isize> {
     match cmd as u32 {
         F_DUPFD => dup_fd(fd, false),
         F_DUPFD_CLOEXEC => dup_fd(fd, true),
-        F_SETLK | F_SETLKW => Ok(0),
-        F_OFD_SETLK | F_OFD_SETLKW => Ok(0),
-        F_GETLK | F_OFD_GETLK => {
-            let arg = UserPtr::<flock64>::from(arg);
-            arg.get_as_mut()?.l_type = F_UNLCK as _;
-            Ok(0)
+        F_SETLK | F_SETLKW | F_OFD_SETLK | F_OFD_SETLKW | F_GETLK | F_OFD_GETLK => {
+            fcntl_lock(fd, cmd as u32, UserPtr::<flock64>::from(arg))
         }
         F_SETFL => {
             get_file_like(fd)?.set_nonblocking(arg & (O_NONBLOCK as usize) > 0)?;
@@ -333,9 +337,117 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> KResult<isize> {
     }
 }
 
+/// Converts an `flock` structure into the byte range it describes.
+fn lock_range(f: &File, lock: &flock64) -> KResult<Range<u64>> {
+    let base = match lock.l_whence as u32 {
+        SEEK_SET => 0,
+        SEEK_CUR => f.inner().position().unwrap_or_default() as i64,
+        SEEK_END => f.inner().location().len()? as i64,
+        _ => return Err(KError::InvalidInput),
+    };
+    let start = base.checked_add(lock.l_start).ok_or(KError::InvalidInput)?;
+    let (start, end) = match lock.l_len {
+        0 => (start, u64::MAX),
+        len if len > 0 => (start, start.saturating_add(len) as u64),
+        len => (
+            start.checked_add(len).ok_or(KError::InvalidInput)?,
+            start as u64,
+        ),
+    };
+    if start < 0 {
+        return Err(KError::InvalidInput);
+    }
+    Ok(start as u64..end)
+}
+
+/// Handles the record locking commands of `fcntl`.
+fn fcntl_lock(fd: c_int, cmd: u32, arg: UserPtr<flock64>) -> KResult<isize> {
+    let f = File::from_fd(fd)?;
+    let lock = arg.get_as_mut()?;
+    let range = lock_range(&f, lock)?;
+    let pid = current().as_thread().proc_data.proc.pid();
+
+    let ofd = matches!(cmd, F_OFD_SETLK | F_OFD_SETLKW | F_OFD_GETLK);
+    if ofd && lock.l_pid != 0 {
+        return Err(KError::InvalidInput);
+    }
+    let owner = if ofd {
+        f.lock_owner()
+    } else {
+        LockOwner::Process(pid)
+    };
+    let kind = match lock.l_type as u32 {
+        F_RDLCK => Some(LockKind::Shared),
+        F_WRLCK => Some(LockKind::Exclusive),
+        F_UNLCK => None,
+        _ => return Err(KError::InvalidInput),
+    };
+    let locks = FileLocks::of(f.inner().location());
+
+    if matches!(cmd, F_GETLK | F_OFD_GETLK) {
+        let kind = kind.ok_or(KError::InvalidInput)?;
+        match locks.test_record(owner, kind, range) {
+            Some(conflict) => {
+                lock.l_type = match conflict.kind {
+                    LockKind::Shared => F_RDLCK,
+                    LockKind::Exclusive => F_WRLCK,
+                } as _;
+                lock.l_whence = SEEK_SET as _;
+                lock.l_start = conflict.range.start as _;
+                lock.l_len = if conflict.range.end == u64::MAX {
+                    0
+                } else {
+                    (conflict.range.end - conflict.range.start) as _
+                };
+                lock.l_pid = match conflict.owner {
+                    LockOwner::File(_) => -1,
+                    LockOwner::Process(pid) => pid as _,
+                };
+            }
+            None => lock.l_type = F_UNLCK as _,
+        }
+        return Ok(0);
+    }
+
+    match kind {
+        Some(kind) => {
+            let access = match kind {
+                LockKind::Shared => FileFlags::READ,
+                LockKind::Exclusive => FileFlags::WRITE,
+            };
+            f.inner().access(access)?;
+            locks.set_record(
+                FileLock {
+                    kind,
+                    range,
+                    owner,
+                    pid,
+                },
+                matches!(cmd, F_SETLKW | F_OFD_SETLKW),
+            )?;
+        }
+        None => locks.clear_record(owner, range),
+    }
+    Ok(0)
+}
+
 /// Applies or removes an advisory lock on a file descriptor.
 pub fn sys_flock(fd: c_int, operation: c_int) -> KResult<isize> {
     debug!("flock <= fd: {fd}, operation: {operation}");
-    // TODO: flock
+    let f = File::from_fd(fd)?;
+    let operation = operation as u32;
+    let kind = match operation & !LOCK_NB {
+        LOCK_SH => Some(LockKind::Shared),
+        LOCK_EX => Some(LockKind::Exclusive),
+        LOCK_UN => None,
+        _ => return Err(KError::InvalidInput),
+    };
+    let pid = current().as_thread().proc_data.proc.pid();
+    FileLocks::of(f.inner().location()).flock(
+        f.lock_owner(),
+        pid,
+        kind,
+        operation & LOCK_NB == 0,
+    )?;
     Ok(0)
 }
@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! inotify syscalls.
+//!
+//! This module implements file change notification including:
+//! - inotify instance creation (inotify_init, inotify_init1)
+//! - Adding and removing watches on files and directories
+
+use core::ffi::c_char;
+
+use bitflags::bitflags;
+use fs_ng_vfs::{path::Path, watch::WatchMask};
+use kerrno::{KError, KResult};
+use linux_raw_sys::general::{
+    AT_FDCWD, IN_ALL_EVENTS, IN_DONT_FOLLOW, IN_MASK_ADD, IN_ONLYDIR, O_CLOEXEC, O_NONBLOCK,
+};
+
+use crate::{
+    file::{FileLike, add_file_like, inotify::Inotify, with_fs},
+    mm::vm_load_string,
+};
+
+bitflags! {
+    /// Flags for the `inotify_init1` syscall.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct InotifyFlags: u32 {
+        /// Create a file descriptor that is closed on `exec`.
+        const CLOEXEC = O_CLOEXEC;
+        /// Create a non-blocking inotify instance.
+        const NONBLOCK = O_NONBLOCK;
+    }
+}
+
+/// Creates an inotify instance.
+#[cfg(target_arch = "x86_64")]
+pub fn sys_inotify_init() -> KResult<isize> {
+    sys_inotify_init1(0)
+}
+
+/// Creates an inotify instance with the given flags.
+pub fn sys_inotify_init1(flags: u32) -> KResult<isize> {
+    let flags = InotifyFlags::from_bits(flags).ok_or(KError::InvalidInput)?;
+
+    let inotify = Inotify::new();
+    inotify.set_nonblocking(flags.contains(InotifyFlags::NONBLOCK))?;
+
+    add_file_like(inotify as _, flags.contains(InotifyFlags::CLOEXEC)).map(|fd| fd as _)
+}
+
+/// Watches the file at `path` for the events in `mask`. Returns the watch
+/// descriptor, which is the same for every watch of the instance on the
+/// same file.
+pub fn sys_inotify_add_watch(fd: i32, path: *const c_char, mask: u32) -> KResult<isize> {
+    let inotify = Inotify::from_fd(fd)?;
+    if mask & IN_ALL_EVENTS == 0 {
+        return Err(KError::InvalidInput);
+    }
+    let path = vm_load_string(path)?;
+    let path = Path::new(&path);
+
+    let location = with_fs(AT_FDCWD, |fs| {
+        if mask & IN_DONT_FOLLOW != 0 {
+            fs.resolve_no_follow(path)
+        } else {
+            fs.resolve(path)
+        }
+    })?;
+    if mask & IN_ONLYDIR != 0 {
+        location.check_is_dir()?;
+    }
+
+    let wd = inotify.queue().add_watch(
+        &location,
+        WatchMask::from_bits_truncate(mask),
+        mask & IN_MASK_ADD != 0,
+    )?;
+    Ok(wd as _)
+}
+
+/// Removes a watch from an inotify instance.
+pub fn sys_inotify_rm_watch(fd: i32, wd: i32) -> KResult<isize> {
+    Inotify::from_fd(fd)?.queue().remove_watch(wd)?;
+    Ok(0)
+}
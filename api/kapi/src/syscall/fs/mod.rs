@@ -15,6 +15,7 @@
 mod ctl;
 mod event;
 mod fd_ops;
+mod inotify;
 mod io;
 mod memfd;
 mod mount;
@@ -24,5 +25,6 @@ mod signalfd;
 mod stat;
 
 pub use self::{
-    ctl::*, event::*, fd_ops::*, io::*, memfd::*, mount::*, pidfd::*, pipe::*, signalfd::*, stat::*,
+    ctl::*, event::*, fd_ops::*, inotify::*, io::*, memfd::*, mount::*, pidfd::*, pipe::*,
+    signalfd::*, stat::*,
 };
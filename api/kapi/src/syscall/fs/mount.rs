@@ -9,23 +9,55 @@
 //! - Unmount filesystem (umount, umount2, etc.)
 //! - Mount operations and flags
 
+use alloc::string::String;
 use core::ffi::{c_char, c_void};
 
+use fs_ng_vfs::{Filesystem, Mountpoint};
 use kerrno::{KError, KResult};
 use kfs::FS_CONTEXT;
 
-use crate::{mm::vm_load_string, vfs::MemoryFs};
+use crate::{
+    mm::vm_load_string,
+    vfs::{MemoryFs, OverlayFs},
+};
+
+/// Create an overlay from mount options like `lowerdir=/a,upperdir=/b`.
+///
+/// A single lower directory is supported. Without `upperdir`, changes go to
+/// a fresh tmpfs, and `workdir` is accepted but not needed.
+fn new_overlay(options: &str) -> KResult<Filesystem> {
+    let mut lower = None;
+    let mut upper = None;
+    for option in options.split(',').filter(|it| !it.is_empty()) {
+        match option.split_once('=') {
+            Some(("lowerdir", path)) if !path.contains(':') => lower = Some(path),
+            Some(("upperdir", path)) => upper = Some(path),
+            Some(("workdir", _)) => {}
+            _ => return Err(KError::InvalidInput),
+        }
+    }
+    let lower = lower.ok_or(KError::InvalidInput)?;
+
+    let fs = FS_CONTEXT.lock();
+    let lower = fs.resolve(lower)?;
+    let upper = match upper {
+        Some(upper) => fs.resolve(upper)?,
+        None => Mountpoint::new_root(&MemoryFs::new()).root_location(),
+    };
+    OverlayFs::new(lower, upper)
+}
 
 /// Mount a filesystem at the specified target path
 ///
-/// Currently only supports tmpfs (temporary memory-based filesystem).
-/// The source is loaded from user memory but not validated since tmpfs doesn't use source device names.
+/// Supports tmpfs (temporary memory-based filesystem), whose source is ignored,
+/// overlay, whose layers are given in `data`, and the disk filesystem built in
+/// on a block device such as `/dev/vda2`.
 pub fn sys_mount(
     source: *const c_char,
     target: *const c_char,
     fs_type: *const c_char,
     _flags: i32,
-    _data: *const c_void,
+    data: *const c_void,
 ) -> KResult<isize> {
     // Load filesystem type string from user memory
     let source = vm_load_string(source)?;
@@ -33,17 +65,25 @@ pub fn sys_mount(
     let fs_type = vm_load_string(fs_type)?;
     debug!("sys_mount <= source: {source:?}, target: {target:?}, fs_type: {fs_type:?}");
 
-    // Only tmpfs is supported - reject unsupported filesystem types
-    if fs_type != "tmpfs" {
-        return Err(KError::NoSuchDevice);
-    }
-
-    // Create a new in-memory filesystem instance
-    let fs = MemoryFs::new();
+    let fs = if fs_type == "tmpfs" {
+        // Create a new in-memory filesystem instance
+        MemoryFs::new()
+    } else if fs_type == "overlay" {
+        let options = if data.is_null() {
+            String::new()
+        } else {
+            vm_load_string(data.cast())?
+        };
+        new_overlay(&options)?
+    } else {
+        // Unsupported filesystem types are rejected with ENODEV
+        let name = source.strip_prefix("/dev/").unwrap_or(&source);
+        kfs::mount_block_device(name, &fs_type)?
+    };
 
     // Resolve the target mount point path and attach the filesystem
     let target = FS_CONTEXT.lock().resolve(target)?;
-    target.mount(&fs)?;
+    target.mount_from(&fs, &source)?;
 
     Ok(0)
 }
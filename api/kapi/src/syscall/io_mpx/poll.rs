@@ -98,7 +98,7 @@ fn do_poll(
 }
 
 /// Poll file descriptors with millisecond timeout
-#[cfg(target_arch = "x86_64")]
+#[cfg(any(target_arch = "x86_64", feature = "compat"))]
 pub fn sys_poll(fds: UserPtr<pollfd>, nfds: u32, timeout: i32) -> KResult<isize> {
     let fds = fds.get_as_mut_slice(nfds as usize)?;
     let timeout = if timeout < 0 {
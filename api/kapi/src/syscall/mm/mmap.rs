@@ -11,8 +11,6 @@
 //! - Memory synchronization (msync)
 //! - Memory advice (madvise)
 
-use alloc::sync::Arc;
-
 use kcore::{
     task::AsThread,
     vfs::{Device, DeviceMmap},
@@ -23,7 +21,7 @@ use khal::paging::{MappingFlags, PageSize};
 use ktask::current;
 use linux_raw_sys::general::*;
 use memaddr::{MemoryAddr, VirtAddr, VirtAddrRange, align_up_4k};
-use memspace::backend::{Backend, SharedPages};
+use memspace::backend::{Backend, ShmObject};
 use osvm::{load_vec, write_vm_mem};
 
 use crate::file::{File, FileLike};
@@ -169,17 +167,22 @@ pub fn sys_mmap(
         dst_addr
     } else {
         let align = page_size as usize;
+        let mut limit = aspace.end();
+        if curr.as_thread().proc_data.is_compat() {
+            // 32-bit programs can only address the low 4 GiB.
+            limit = limit.min(VirtAddr::from(1usize << 32));
+        }
         aspace
             .find_free_area(
                 VirtAddr::from(start),
                 length,
-                VirtAddrRange::new(aspace.base(), aspace.end()),
+                VirtAddrRange::new(aspace.base(), limit),
                 align,
             )
             .or(aspace.find_free_area(
                 aspace.base(),
                 length,
-                VirtAddrRange::new(aspace.base(), aspace.end()),
+                VirtAddrRange::new(aspace.base(), limit),
                 align,
             ))
             .ok_or(KError::NoMemory)?
@@ -241,7 +244,7 @@ pub fn sys_mmap(
                     }
                 }
             } else {
-                Backend::new_shared(start, Arc::new(SharedPages::new(length, PageSize::Size4K)?))
+                Backend::new_shm(start, ShmObject::new(length), 0)
             }
         }
         MmapFlags::PRIVATE => {
@@ -337,7 +340,18 @@ pub fn sys_madvise(addr: usize, length: usize, advice: i32) -> KResult<isize> {
 
 pub fn sys_msync(addr: usize, length: usize, flags: u32) -> KResult<isize> {
     debug!("sys_msync <= addr: {addr:#x}, length: {length:x}, flags: {flags:#x}");
+    if flags & !(MS_ASYNC | MS_INVALIDATE | MS_SYNC) != 0
+        || flags & (MS_ASYNC | MS_SYNC) == MS_ASYNC | MS_SYNC
+        || !PageSize::Size4K.is_aligned(addr)
+    {
+        return Err(KError::InvalidInput);
+    }
 
+    // Mappings share the page cache, so there is nothing to invalidate, and
+    // `MS_ASYNC` is written back right away as well
+    let curr = current();
+    let mut aspace = curr.as_thread().proc_data.aspace.lock();
+    aspace.sync(VirtAddr::from(addr), align_up_4k(length))?;
     Ok(0)
 }
 
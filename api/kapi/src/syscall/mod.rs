@@ -19,7 +19,13 @@
 //! - `sys`: System information and control
 //! - `task`: Process and thread management
 //! - `time`: Time-related operations
+//! - `compat`: 32-bit syscall ABI translation (with the `compat` feature)
 
+#[cfg(all(
+    feature = "compat",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod compat;
 mod fs;
 mod io_mpx;
 mod ipc;
@@ -32,9 +38,9 @@ mod sys;
 mod task;
 mod time;
 
-use kerrno::LinuxError;
+use kerrno::{KResult, LinuxError};
 use khal::uspace::UserContext;
-use linux_sysno::Sysno;
+use linux_sysno::{SyscallArgs, Sysno};
 // Re-export sys_getrandom for use in TEE modules
 pub use sys::sys_getrandom;
 
@@ -45,6 +51,14 @@ use self::{
 
 /// Dispatches a syscall from the given user context.
 pub fn dispatch_irq_syscall(uctx: &mut UserContext) {
+    #[cfg(all(
+        feature = "compat",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    if uctx.is_compat() {
+        return compat::dispatch_compat_syscall(uctx);
+    }
+
     let Some(sysno) = Sysno::new(uctx.sysno()) else {
         warn!("Invalid syscall number: {}", uctx.sysno());
         uctx.set_retval(-LinuxError::ENOSYS.into_raw() as _);
@@ -53,590 +67,610 @@ pub fn dispatch_irq_syscall(uctx: &mut UserContext) {
 
     trace!("Syscall {sysno:?}");
 
-    let result = match sysno {
+    let args = SyscallArgs::new(
+        uctx.arg0(),
+        uctx.arg1(),
+        uctx.arg2(),
+        uctx.arg3(),
+        uctx.arg4(),
+        uctx.arg5(),
+    );
+    let result = dispatch_syscall(uctx, sysno, args);
+    debug!("Syscall {sysno} return {result:?}");
+
+    uctx.set_retval(result.unwrap_or_else(|err| -LinuxError::from(err).into_raw() as _) as _);
+}
+
+/// Runs the handler of `sysno` with the already decoded `args`.
+fn dispatch_syscall(uctx: &mut UserContext, sysno: Sysno, args: SyscallArgs) -> KResult<isize> {
+    match sysno {
         // fs ctl
-        Sysno::ioctl => sys_ioctl(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::chdir => sys_chdir(uctx.arg0() as _),
-        Sysno::fchdir => sys_fchdir(uctx.arg0() as _),
-        Sysno::chroot => sys_chroot(uctx.arg0() as _),
+        Sysno::ioctl => sys_ioctl(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::chdir => sys_chdir(args.arg0 as _),
+        Sysno::fchdir => sys_fchdir(args.arg0 as _),
+        Sysno::chroot => sys_chroot(args.arg0 as _),
         #[cfg(target_arch = "x86_64")]
-        Sysno::mkdir => sys_mkdir(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::mkdirat => sys_mkdirat(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::getdents64 => sys_getdents64(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
+        Sysno::mkdir => sys_mkdir(args.arg0 as _, args.arg1 as _),
+        Sysno::mkdirat => sys_mkdirat(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::getdents64 => sys_getdents64(args.arg0 as _, args.arg1 as _, args.arg2 as _),
         #[cfg(target_arch = "x86_64")]
-        Sysno::link => sys_link(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::link => sys_link(args.arg0 as _, args.arg1 as _),
         Sysno::linkat => sys_linkat(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4 as _,
         ),
         #[cfg(target_arch = "x86_64")]
-        Sysno::rmdir => sys_rmdir(uctx.arg0() as _),
+        Sysno::rmdir => sys_rmdir(args.arg0 as _),
         #[cfg(target_arch = "x86_64")]
-        Sysno::unlink => sys_unlink(uctx.arg0() as _),
-        Sysno::unlinkat => sys_unlinkat(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::getcwd => sys_getcwd(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::unlink => sys_unlink(args.arg0 as _),
+        Sysno::unlinkat => sys_unlinkat(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::getcwd => sys_getcwd(args.arg0 as _, args.arg1 as _),
         #[cfg(target_arch = "x86_64")]
-        Sysno::symlink => sys_symlink(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::symlinkat => sys_symlinkat(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
+        Sysno::symlink => sys_symlink(args.arg0 as _, args.arg1 as _),
+        Sysno::symlinkat => sys_symlinkat(args.arg0 as _, args.arg1 as _, args.arg2 as _),
         #[cfg(target_arch = "x86_64")]
-        Sysno::rename => sys_rename(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::rename => sys_rename(args.arg0 as _, args.arg1 as _),
         Sysno::renameat => sys_renameat(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
         Sysno::renameat2 => sys_renameat2(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4 as _,
         ),
         Sysno::sync => sys_sync(),
-        Sysno::syncfs => sys_syncfs(uctx.arg0() as _),
+        Sysno::syncfs => sys_syncfs(args.arg0 as _),
 
         // file ops
         #[cfg(target_arch = "x86_64")]
-        Sysno::chown => sys_chown(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
+        Sysno::chown => sys_chown(args.arg0 as _, args.arg1 as _, args.arg2 as _),
         #[cfg(target_arch = "x86_64")]
-        Sysno::lchown => sys_lchown(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::fchown => sys_fchown(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
+        Sysno::lchown => sys_lchown(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::fchown => sys_fchown(args.arg0 as _, args.arg1 as _, args.arg2 as _),
         Sysno::fchownat => sys_fchownat(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4 as _,
         ),
         #[cfg(target_arch = "x86_64")]
-        Sysno::chmod => sys_chmod(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::fchmod => sys_fchmod(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::chmod => sys_chmod(args.arg0 as _, args.arg1 as _),
+        Sysno::fchmod => sys_fchmod(args.arg0 as _, args.arg1 as _),
         Sysno::fchmodat | Sysno::fchmodat2 => sys_fchmodat(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
         #[cfg(target_arch = "x86_64")]
-        Sysno::readlink => sys_readlink(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
+        Sysno::readlink => sys_readlink(args.arg0 as _, args.arg1 as _, args.arg2 as _),
         Sysno::readlinkat => sys_readlinkat(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
         #[cfg(target_arch = "x86_64")]
-        Sysno::utime => sys_utime(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::utime => sys_utime(args.arg0 as _, args.arg1 as _),
         #[cfg(target_arch = "x86_64")]
-        Sysno::utimes => sys_utimes(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::utimes => sys_utimes(args.arg0 as _, args.arg1 as _),
         Sysno::utimensat => sys_utimensat(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
 
         // fd ops
         #[cfg(target_arch = "x86_64")]
-        Sysno::open => sys_open(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
+        Sysno::open => sys_open(args.arg0 as _, args.arg1 as _, args.arg2 as _),
         Sysno::openat => sys_openat(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-        ),
-        Sysno::close => sys_close(uctx.arg0() as _),
-        Sysno::close_range => sys_close_range(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::dup => sys_dup(uctx.arg0() as _),
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+        ),
+        Sysno::close => sys_close(args.arg0 as _),
+        Sysno::close_range => sys_close_range(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::dup => sys_dup(args.arg0 as _),
         #[cfg(target_arch = "x86_64")]
-        Sysno::dup2 => sys_dup2(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::dup3 => sys_dup3(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::fcntl => sys_fcntl(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::flock => sys_flock(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::dup2 => sys_dup2(args.arg0 as _, args.arg1 as _),
+        Sysno::dup3 => sys_dup3(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::fcntl => sys_fcntl(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::flock => sys_flock(args.arg0 as _, args.arg1 as _),
 
         // io
-        Sysno::read => sys_read(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::readv => sys_readv(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::write => sys_write(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::writev => sys_writev(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::lseek => sys_lseek(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::truncate => sys_truncate(uctx.arg0().into(), uctx.arg1() as _),
-        Sysno::ftruncate => sys_ftruncate(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::read => sys_read(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::readv => sys_readv(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::write => sys_write(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::writev => sys_writev(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::lseek => sys_lseek(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::truncate => sys_truncate(args.arg0.into(), args.arg1 as _),
+        Sysno::ftruncate => sys_ftruncate(args.arg0 as _, args.arg1 as _),
         Sysno::fallocate => sys_fallocate(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
-        Sysno::fsync => sys_fsync(uctx.arg0() as _),
-        Sysno::fdatasync => sys_fdatasync(uctx.arg0() as _),
+        Sysno::fsync => sys_fsync(args.arg0 as _),
+        Sysno::fdatasync => sys_fdatasync(args.arg0 as _),
         Sysno::fadvise64 => sys_fadvise64(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
         Sysno::pread64 => sys_pread64(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
         Sysno::pwrite64 => sys_pwrite64(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
         Sysno::preadv => sys_preadv(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
         Sysno::pwritev => sys_pwritev(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
         Sysno::preadv2 => sys_preadv2(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4 as _,
         ),
         Sysno::pwritev2 => sys_pwritev2(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4 as _,
         ),
         Sysno::sendfile => sys_sendfile(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
         Sysno::copy_file_range => sys_copy_file_range(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4() as _,
-            uctx.arg5() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4 as _,
+            args.arg5 as _,
         ),
         Sysno::splice => sys_splice(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4() as _,
-            uctx.arg5() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4 as _,
+            args.arg5 as _,
         ),
 
         // io mpx
         #[cfg(target_arch = "x86_64")]
-        Sysno::poll => sys_poll(uctx.arg0().into(), uctx.arg1() as _, uctx.arg2() as _),
+        Sysno::poll => sys_poll(args.arg0.into(), args.arg1 as _, args.arg2 as _),
         Sysno::ppoll => sys_ppoll(
-            uctx.arg0().into(),
-            uctx.arg1() as _,
-            uctx.arg2().into(),
-            uctx.arg3().into(),
-            uctx.arg4() as _,
+            args.arg0.into(),
+            args.arg1 as _,
+            args.arg2.into(),
+            args.arg3.into(),
+            args.arg4 as _,
         ),
         #[cfg(target_arch = "x86_64")]
         Sysno::select => sys_select(
-            uctx.arg0() as _,
-            uctx.arg1().into(),
-            uctx.arg2().into(),
-            uctx.arg3().into(),
-            uctx.arg4().into(),
+            args.arg0 as _,
+            args.arg1.into(),
+            args.arg2.into(),
+            args.arg3.into(),
+            args.arg4.into(),
         ),
         Sysno::pselect6 => sys_pselect6(
-            uctx.arg0() as _,
-            uctx.arg1().into(),
-            uctx.arg2().into(),
-            uctx.arg3().into(),
-            uctx.arg4().into(),
-            uctx.arg5().into(),
-        ),
-        Sysno::epoll_create1 => sys_epoll_create1(uctx.arg0() as _),
+            args.arg0 as _,
+            args.arg1.into(),
+            args.arg2.into(),
+            args.arg3.into(),
+            args.arg4.into(),
+            args.arg5.into(),
+        ),
+        Sysno::epoll_create1 => sys_epoll_create1(args.arg0 as _),
         Sysno::epoll_ctl => sys_epoll_ctl(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3().into(),
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3.into(),
         ),
         Sysno::epoll_pwait => sys_epoll_pwait(
-            uctx.arg0() as _,
-            uctx.arg1().into(),
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4().into(),
-            uctx.arg5() as _,
+            args.arg0 as _,
+            args.arg1.into(),
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4.into(),
+            args.arg5 as _,
         ),
         Sysno::epoll_pwait2 => sys_epoll_pwait2(
-            uctx.arg0() as _,
-            uctx.arg1().into(),
-            uctx.arg2() as _,
-            uctx.arg3().into(),
-            uctx.arg4().into(),
-            uctx.arg5() as _,
+            args.arg0 as _,
+            args.arg1.into(),
+            args.arg2 as _,
+            args.arg3.into(),
+            args.arg4.into(),
+            args.arg5 as _,
         ),
 
         // fs mount
         Sysno::mount => sys_mount(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4 as _,
         ) as _,
-        Sysno::umount2 => sys_umount2(uctx.arg0() as _, uctx.arg1() as _) as _,
+        Sysno::umount2 => sys_umount2(args.arg0 as _, args.arg1 as _) as _,
 
         // pipe
-        Sysno::pipe2 => sys_pipe2(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::pipe2 => sys_pipe2(args.arg0 as _, args.arg1 as _),
         #[cfg(target_arch = "x86_64")]
-        Sysno::pipe => sys_pipe2(uctx.arg0() as _, 0),
+        Sysno::pipe => sys_pipe2(args.arg0 as _, 0),
 
         // event
-        Sysno::eventfd2 => sys_eventfd2(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::eventfd2 => sys_eventfd2(args.arg0 as _, args.arg1 as _),
 
         // pidfd
-        Sysno::pidfd_open => sys_pidfd_open(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::pidfd_getfd => sys_pidfd_getfd(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
+        Sysno::pidfd_open => sys_pidfd_open(args.arg0 as _, args.arg1 as _),
+        Sysno::pidfd_getfd => sys_pidfd_getfd(args.arg0 as _, args.arg1 as _, args.arg2 as _),
         Sysno::pidfd_send_signal => sys_pidfd_send_signal(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
 
         // memfd
-        Sysno::memfd_create => sys_memfd_create(uctx.arg0().into(), uctx.arg1() as _),
+        Sysno::memfd_create => sys_memfd_create(args.arg0.into(), args.arg1 as _),
 
         // fs stat
         #[cfg(target_arch = "x86_64")]
-        Sysno::stat => sys_stat(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::fstat => sys_fstat(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::stat => sys_stat(args.arg0 as _, args.arg1 as _),
+        Sysno::fstat => sys_fstat(args.arg0 as _, args.arg1 as _),
         #[cfg(target_arch = "x86_64")]
-        Sysno::lstat => sys_lstat(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::lstat => sys_lstat(args.arg0 as _, args.arg1 as _),
         #[cfg(target_arch = "x86_64")]
         Sysno::newfstatat => sys_fstatat(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
         #[cfg(not(target_arch = "x86_64"))]
         Sysno::fstatat => sys_fstatat(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
         Sysno::statx => sys_statx(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4 as _,
         ),
         #[cfg(target_arch = "x86_64")]
-        Sysno::access => sys_access(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::access => sys_access(args.arg0 as _, args.arg1 as _),
         Sysno::faccessat | Sysno::faccessat2 => sys_faccessat2(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
-        Sysno::statfs => sys_statfs(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::fstatfs => sys_fstatfs(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::statfs => sys_statfs(args.arg0 as _, args.arg1 as _),
+        Sysno::fstatfs => sys_fstatfs(args.arg0 as _, args.arg1 as _),
 
         // mm
-        Sysno::brk => sys_brk(uctx.arg0() as _),
+        Sysno::brk => sys_brk(args.arg0 as _),
         Sysno::mmap => sys_mmap(
-            uctx.arg0(),
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4() as _,
-            uctx.arg5() as _,
-        ),
-        Sysno::munmap => sys_munmap(uctx.arg0(), uctx.arg1() as _),
-        Sysno::mprotect => sys_mprotect(uctx.arg0(), uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::mincore => sys_mincore(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::mremap => sys_mremap(
-            uctx.arg0(),
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-        ),
-        Sysno::madvise => sys_madvise(uctx.arg0(), uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::msync => sys_msync(uctx.arg0(), uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::mlock => sys_mlock(uctx.arg0(), uctx.arg1() as _),
-        Sysno::mlock2 => sys_mlock2(uctx.arg0(), uctx.arg1() as _, uctx.arg2() as _),
+            args.arg0,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4 as _,
+            args.arg5 as _,
+        ),
+        Sysno::munmap => sys_munmap(args.arg0, args.arg1 as _),
+        Sysno::mprotect => sys_mprotect(args.arg0, args.arg1 as _, args.arg2 as _),
+        Sysno::mincore => sys_mincore(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::mremap => sys_mremap(args.arg0, args.arg1 as _, args.arg2 as _, args.arg3 as _),
+        Sysno::madvise => sys_madvise(args.arg0, args.arg1 as _, args.arg2 as _),
+        Sysno::msync => sys_msync(args.arg0, args.arg1 as _, args.arg2 as _),
+        Sysno::mlock => sys_mlock(args.arg0, args.arg1 as _),
+        Sysno::mlock2 => sys_mlock2(args.arg0, args.arg1 as _, args.arg2 as _),
 
         // task info
         Sysno::getpid => sys_getpid(),
         Sysno::getppid => sys_getppid(),
         Sysno::gettid => sys_gettid(),
-        Sysno::getrusage => sys_getrusage(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::getrusage => sys_getrusage(args.arg0 as _, args.arg1 as _),
 
         // task sched
         Sysno::sched_yield => sys_sched_yield(),
-        Sysno::nanosleep => sys_nanosleep(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::nanosleep => sys_nanosleep(args.arg0 as _, args.arg1 as _),
         Sysno::clock_nanosleep => sys_clock_nanosleep(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
         Sysno::sched_getaffinity => {
-            sys_sched_getaffinity(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _)
+            sys_sched_getaffinity(args.arg0 as _, args.arg1 as _, args.arg2 as _)
         }
         Sysno::sched_setaffinity => {
-            sys_sched_setaffinity(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _)
+            sys_sched_setaffinity(args.arg0 as _, args.arg1 as _, args.arg2 as _)
         }
-        Sysno::sched_getscheduler => sys_sched_getscheduler(uctx.arg0() as _),
+        Sysno::sched_getscheduler => sys_sched_getscheduler(args.arg0 as _),
         Sysno::sched_setscheduler => {
-            sys_sched_setscheduler(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _)
+            sys_sched_setscheduler(args.arg0 as _, args.arg1 as _, args.arg2 as _)
         }
-        Sysno::sched_getparam => sys_sched_getparam(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::getpriority => sys_getpriority(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::sched_getparam => sys_sched_getparam(args.arg0 as _, args.arg1 as _),
+        Sysno::getpriority => sys_getpriority(args.arg0 as _, args.arg1 as _),
+        Sysno::ioprio_get => sys_ioprio_get(args.arg0 as _, args.arg1 as _),
+        Sysno::ioprio_set => sys_ioprio_set(args.arg0 as _, args.arg1 as _, args.arg2 as _),
 
         // task ops
-        Sysno::execve => sys_execve(uctx, uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::set_tid_address => sys_set_tid_address(uctx.arg0()),
+        Sysno::execve => sys_execve(uctx, args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::set_tid_address => sys_set_tid_address(args.arg0),
         #[cfg(target_arch = "x86_64")]
-        Sysno::arch_prctl => sys_arch_prctl(uctx, uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::arch_prctl => sys_arch_prctl(uctx, args.arg0 as _, args.arg1 as _),
         Sysno::prctl => sys_prctl(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4 as _,
         ),
         Sysno::prlimit64 => sys_prlimit64(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-        ),
-        Sysno::capget => sys_capget(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::capset => sys_capset(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::umask => sys_umask(uctx.arg0() as _),
-        Sysno::setreuid => sys_setreuid(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::setresuid => sys_setresuid(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::setresgid => sys_setresgid(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+        ),
+        Sysno::capget => sys_capget(args.arg0 as _, args.arg1 as _),
+        Sysno::capset => sys_capset(args.arg0 as _, args.arg1 as _),
+        Sysno::umask => sys_umask(args.arg0 as _),
+        Sysno::setreuid => sys_setreuid(args.arg0 as _, args.arg1 as _),
+        Sysno::setresuid => sys_setresuid(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::setresgid => sys_setresgid(args.arg0 as _, args.arg1 as _, args.arg2 as _),
         Sysno::get_mempolicy => sys_get_mempolicy(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4 as _,
         ),
 
         // task management
         Sysno::clone => sys_clone(
             uctx,
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2(),
-            uctx.arg3(),
-            uctx.arg4(),
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2,
+            args.arg3,
+            args.arg4,
         ),
         #[cfg(target_arch = "x86_64")]
         Sysno::fork => sys_fork(uctx),
-        Sysno::exit => sys_exit(uctx.arg0() as _),
-        Sysno::exit_group => sys_exit_group(uctx.arg0() as _),
-        Sysno::wait4 => sys_waitpid(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::getsid => sys_getsid(uctx.arg0() as _),
+        Sysno::exit => sys_exit(args.arg0 as _),
+        Sysno::exit_group => sys_exit_group(args.arg0 as _),
+        Sysno::wait4 => sys_waitpid(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::getsid => sys_getsid(args.arg0 as _),
         Sysno::setsid => sys_setsid(),
-        Sysno::getpgid => sys_getpgid(uctx.arg0() as _),
-        Sysno::setpgid => sys_setpgid(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::getpgid => sys_getpgid(args.arg0 as _),
+        Sysno::setpgid => sys_setpgid(args.arg0 as _, args.arg1 as _),
 
         // signal
         Sysno::rt_sigprocmask => sys_rt_sigprocmask(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
         Sysno::rt_sigaction => sys_rt_sigaction(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
-        Sysno::rt_sigpending => sys_rt_sigpending(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::rt_sigpending => sys_rt_sigpending(args.arg0 as _, args.arg1 as _),
         Sysno::rt_sigreturn => sys_rt_sigreturn(uctx),
         Sysno::rt_sigtimedwait => sys_rt_sigtimedwait(
             uctx,
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-        ),
-        Sysno::rt_sigsuspend => sys_rt_sigsuspend(uctx, uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::kill => sys_kill(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::tkill => sys_tkill(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::tgkill => sys_tgkill(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+        ),
+        Sysno::rt_sigsuspend => sys_rt_sigsuspend(uctx, args.arg0 as _, args.arg1 as _),
+        Sysno::kill => sys_kill(args.arg0 as _, args.arg1 as _),
+        Sysno::tkill => sys_tkill(args.arg0 as _, args.arg1 as _),
+        Sysno::tgkill => sys_tgkill(args.arg0 as _, args.arg1 as _, args.arg2 as _),
         Sysno::rt_sigqueueinfo => sys_rt_sigqueueinfo(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
         Sysno::rt_tgsigqueueinfo => sys_rt_tgsigqueueinfo(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4 as _,
         ),
-        Sysno::sigaltstack => sys_sigaltstack(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::sigaltstack => sys_sigaltstack(args.arg0 as _, args.arg1 as _),
         Sysno::futex => sys_futex(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4() as _,
-            uctx.arg5() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4 as _,
+            args.arg5 as _,
         ),
         Sysno::get_robust_list => {
-            sys_get_robust_list(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _)
+            sys_get_robust_list(args.arg0 as _, args.arg1 as _, args.arg2 as _)
         }
-        Sysno::set_robust_list => sys_set_robust_list(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::set_robust_list => sys_set_robust_list(args.arg0 as _, args.arg1 as _),
 
         // sys
         Sysno::getuid => sys_getuid(),
         Sysno::geteuid => sys_geteuid(),
         Sysno::getgid => sys_getgid(),
         Sysno::getegid => sys_getegid(),
-        Sysno::setuid => sys_setuid(uctx.arg0() as _),
-        Sysno::setgid => sys_setgid(uctx.arg0() as _),
-        Sysno::getgroups => sys_getgroups(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::setgroups => sys_setgroups(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::uname => sys_uname(uctx.arg0() as _),
-        Sysno::sysinfo => sys_sysinfo(uctx.arg0() as _),
-        Sysno::syslog => sys_syslog(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::getrandom => sys_getrandom(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::seccomp => sys_seccomp(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
+        Sysno::setuid => sys_setuid(args.arg0 as _),
+        Sysno::setgid => sys_setgid(args.arg0 as _),
+        Sysno::getgroups => sys_getgroups(args.arg0 as _, args.arg1 as _),
+        Sysno::setgroups => sys_setgroups(args.arg0 as _, args.arg1 as _),
+        Sysno::uname => sys_uname(args.arg0 as _),
+        Sysno::sysinfo => sys_sysinfo(args.arg0 as _),
+        Sysno::syslog => sys_syslog(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::reboot => sys_reboot(
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+        ),
+        Sysno::getrandom => sys_getrandom(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::seccomp => sys_seccomp(args.arg0 as _, args.arg1 as _, args.arg2 as _),
         #[cfg(target_arch = "riscv64")]
         Sysno::riscv_flush_icache => sys_riscv_flush_icache(),
 
         // sync
-        Sysno::membarrier => sys_membarrier(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
+        Sysno::membarrier => sys_membarrier(args.arg0 as _, args.arg1 as _, args.arg2 as _),
 
         // time
-        Sysno::gettimeofday => sys_gettimeofday(uctx.arg0() as _),
-        Sysno::times => sys_times(uctx.arg0() as _),
-        Sysno::clock_gettime => sys_clock_gettime(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::clock_getres => sys_clock_getres(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::getitimer => sys_getitimer(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::setitimer => sys_setitimer(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
+        Sysno::gettimeofday => sys_gettimeofday(args.arg0 as _),
+        Sysno::times => sys_times(args.arg0 as _),
+        Sysno::clock_gettime => sys_clock_gettime(args.arg0 as _, args.arg1 as _),
+        Sysno::clock_getres => sys_clock_getres(args.arg0 as _, args.arg1 as _),
+        Sysno::getitimer => sys_getitimer(args.arg0 as _, args.arg1 as _),
+        Sysno::setitimer => sys_setitimer(args.arg0 as _, args.arg1 as _, args.arg2 as _),
 
         // msg
-        Sysno::msgget => sys_msgget(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::msgget => sys_msgget(args.arg0 as _, args.arg1 as _),
         Sysno::msgsnd => sys_msgsnd(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
         ),
         Sysno::msgrcv => sys_msgrcv(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4 as _,
         ),
-        Sysno::msgctl => sys_msgctl(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
+        Sysno::msgctl => sys_msgctl(args.arg0 as _, args.arg1 as _, args.arg2 as _),
 
         // shm
-        Sysno::shmget => sys_shmget(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::shmat => sys_shmat(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
-        Sysno::shmctl => sys_shmctl(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2().into()),
-        Sysno::shmdt => sys_shmdt(uctx.arg0() as _),
+        Sysno::shmget => sys_shmget(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::shmat => sys_shmat(args.arg0 as _, args.arg1 as _, args.arg2 as _),
+        Sysno::shmctl => sys_shmctl(args.arg0 as _, args.arg1 as _, args.arg2.into()),
+        Sysno::shmdt => sys_shmdt(args.arg0 as _),
 
         // net
-        Sysno::socket => sys_socket(uctx.arg0() as _, uctx.arg1() as _, uctx.arg2() as _),
+        Sysno::socket => sys_socket(args.arg0 as _, args.arg1 as _, args.arg2 as _),
         Sysno::socketpair => sys_socketpair(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3().into(),
-        ),
-        Sysno::bind => sys_bind(uctx.arg0() as _, uctx.arg1().into(), uctx.arg2() as _),
-        Sysno::connect => sys_connect(uctx.arg0() as _, uctx.arg1().into(), uctx.arg2() as _),
-        Sysno::getsockname => {
-            sys_getsockname(uctx.arg0() as _, uctx.arg1().into(), uctx.arg2().into())
-        }
-        Sysno::getpeername => {
-            sys_getpeername(uctx.arg0() as _, uctx.arg1().into(), uctx.arg2().into())
-        }
-        Sysno::listen => sys_listen(uctx.arg0() as _, uctx.arg1() as _),
-        Sysno::accept => sys_accept(uctx.arg0() as _, uctx.arg1().into(), uctx.arg2().into()),
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3.into(),
+        ),
+        Sysno::bind => sys_bind(args.arg0 as _, args.arg1.into(), args.arg2 as _),
+        Sysno::connect => sys_connect(args.arg0 as _, args.arg1.into(), args.arg2 as _),
+        Sysno::getsockname => sys_getsockname(args.arg0 as _, args.arg1.into(), args.arg2.into()),
+        Sysno::getpeername => sys_getpeername(args.arg0 as _, args.arg1.into(), args.arg2.into()),
+        Sysno::listen => sys_listen(args.arg0 as _, args.arg1 as _),
+        Sysno::accept => sys_accept(args.arg0 as _, args.arg1.into(), args.arg2.into()),
         Sysno::accept4 => sys_accept4(
-            uctx.arg0() as _,
-            uctx.arg1().into(),
-            uctx.arg2().into(),
-            uctx.arg3() as _,
+            args.arg0 as _,
+            args.arg1.into(),
+            args.arg2.into(),
+            args.arg3 as _,
         ),
-        Sysno::shutdown => sys_shutdown(uctx.arg0() as _, uctx.arg1() as _),
+        Sysno::shutdown => sys_shutdown(args.arg0 as _, args.arg1 as _),
         Sysno::sendto => sys_sendto(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4().into(),
-            uctx.arg5() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4.into(),
+            args.arg5 as _,
         ),
         Sysno::recvfrom => sys_recvfrom(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3() as _,
-            uctx.arg4().into(),
-            uctx.arg5().into(),
-        ),
-        Sysno::sendmsg => sys_sendmsg(uctx.arg0() as _, uctx.arg1().into(), uctx.arg2() as _),
-        Sysno::recvmsg => sys_recvmsg(uctx.arg0() as _, uctx.arg1().into(), uctx.arg2() as _),
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3 as _,
+            args.arg4.into(),
+            args.arg5.into(),
+        ),
+        Sysno::sendmsg => sys_sendmsg(args.arg0 as _, args.arg1.into(), args.arg2 as _),
+        Sysno::recvmsg => sys_recvmsg(args.arg0 as _, args.arg1.into(), args.arg2 as _),
         Sysno::getsockopt => sys_getsockopt(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3().into(),
-            uctx.arg4().into(),
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3.into(),
+            args.arg4.into(),
         ),
         Sysno::setsockopt => sys_setsockopt(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2() as _,
-            uctx.arg3().into(),
-            uctx.arg4() as _,
+            args.arg0 as _,
+            args.arg1 as _,
+            args.arg2 as _,
+            args.arg3.into(),
+            args.arg4 as _,
         ),
 
         // signal file descriptors
-        Sysno::signalfd4 => sys_signalfd4(
-            uctx.arg0() as _,
-            uctx.arg1() as _,
-            uctx.arg2(),
-            uctx.arg3() as _,
-        ),
+        Sysno::signalfd4 => {
+            sys_signalfd4(args.arg0 as _, args.arg1 as _, args.arg2, args.arg3 as _)
+        }
+
+        // inotify
+        #[cfg(target_arch = "x86_64")]
+        Sysno::inotify_init => sys_inotify_init(),
+        Sysno::inotify_init1 => sys_inotify_init1(args.arg0 as _),
+        Sysno::inotify_add_watch => {
+            sys_inotify_add_watch(args.arg0 as _, args.arg1 as _, args.arg2 as _)
+        }
+        Sysno::inotify_rm_watch => sys_inotify_rm_watch(args.arg0 as _, args.arg1 as _),
 
         // dummy fds
         Sysno::timerfd_create
         | Sysno::fanotify_init
-        | Sysno::inotify_init1
         | Sysno::userfaultfd
         | Sysno::perf_event_open
         | Sysno::io_uring_setup
@@ -666,8 +700,5 @@ pub fn dispatch_irq_syscall(uctx: &mut UserContext) {
                 Err(kerrno::KError::Unsupported)
             }
         }
-    };
-    debug!("Syscall {sysno} return {result:?}");
-
-    uctx.set_retval(result.unwrap_or_else(|err| -LinuxError::from(err).into_raw() as _) as _);
+    }
 }
@@ -10,7 +10,10 @@
 //! - Socket-level, IP-level, TCP-level, and other protocol options
 
 use kerrno::{KError, KResult, LinuxError};
-use knet::options::{Configurable, GetSocketOption, SetSocketOption};
+use knet::{
+    congestion::CongestionControl,
+    options::{Configurable, GetSocketOption, SetSocketOption},
+};
 use linux_raw_sys::net::socklen_t;
 
 use crate::{
@@ -22,6 +25,9 @@ const PROTO_TCP: u32 = linux_raw_sys::net::IPPROTO_TCP as u32;
 
 const PROTO_IP: u32 = linux_raw_sys::net::IPPROTO_IP as u32;
 
+/// Maximum length of a `TCP_CONGESTION` algorithm name, including the NUL.
+const TCP_CA_NAME_MAX: usize = 16;
+
 mod conv {
     use kerrno::{KError, KResult};
     use knet::options::UnixCredentials;
@@ -150,6 +156,22 @@ pub fn sys_getsockopt(
     }
 
     let socket = Socket::from_fd(fd)?;
+
+    // The algorithm name is a variable-length string, which the fixed-size
+    // conversions below cannot express
+    if (level, optname) == (PROTO_TCP, linux_raw_sys::net::TCP_CONGESTION) {
+        let mut cc = CongestionControl::default();
+        socket.get_option(GetSocketOption::Congestion(&mut cc))?;
+        let len = (*optlen as usize).min(TCP_CA_NAME_MAX);
+        let dst = optval.get_as_mut_slice(len)?;
+        dst.fill(0);
+        let name = cc.name().as_bytes();
+        let copied = name.len().min(len);
+        dst[..copied].copy_from_slice(&name[..copied]);
+        *optlen = len as socklen_t;
+        return Ok(0);
+    }
+
     macro_rules! dispatch {
         ($which:ident) => {
             socket.get_option(GetSocketOption::$which(get(optval, optlen)?))?;
@@ -190,6 +212,17 @@ pub fn sys_setsockopt(
     }
 
     let socket = Socket::from_fd(fd)?;
+
+    if (level, optname) == (PROTO_TCP, linux_raw_sys::net::TCP_CONGESTION) {
+        let len = (optlen as usize).min(TCP_CA_NAME_MAX - 1);
+        let name = optval.get_as_slice(len)?;
+        let name = name.split(|&b| b == 0).next().unwrap_or_default();
+        let name = str::from_utf8(name).map_err(|_| KError::NotFound)?;
+        let cc = CongestionControl::from_name(name)?;
+        socket.set_option(SetSocketOption::Congestion(&cc))?;
+        return Ok(0);
+    }
+
     macro_rules! dispatch {
         ($which:ident) => {
             socket.set_option(SetSocketOption::$which(get(optval, optlen)?))?;
@@ -15,7 +15,7 @@ use alloc::boxed::Box;
 use kcore::task::AsThread;
 use kerrno::{KError, KResult, LinuxError};
 #[cfg(feature = "vsock")]
-use knet::vsock::{VsockSocket, VsockStreamTransport};
+use knet::vsock::{self, VsockDgramTransport, VsockSocket, VsockSocketType, VsockStreamTransport};
 use knet::{
     Shutdown, SocketAddrEx, SocketOps,
     tcp::TcpSocket,
@@ -73,6 +73,16 @@ pub fn sys_socket(domain: u32, raw_ty: u32, proto: u32) -> KResult<isize> {
             // Virtio socket (hypervisor communication)
             knet::Socket::Vsock(Box::new(VsockSocket::new(VsockStreamTransport::new())))
         }
+        #[cfg(feature = "vsock")]
+        (AF_VSOCK, SOCK_SEQPACKET) if vsock::supports(VsockSocketType::SeqPacket) => {
+            knet::Socket::Vsock(Box::new(VsockSocket::new(
+                VsockStreamTransport::new_seqpacket(),
+            )))
+        }
+        #[cfg(feature = "vsock")]
+        (AF_VSOCK, SOCK_DGRAM) if vsock::supports(VsockSocketType::Dgram) => {
+            knet::Socket::Vsock(Box::new(VsockSocket::new(VsockDgramTransport::new())))
+        }
         (AF_INET, _) | (AF_UNIX, _) | (AF_VSOCK, _) => {
             // Socket type not supported for this domain
             warn!("Unsupported socket type: domain: {domain}, ty: {ty}");
@@ -127,7 +137,9 @@ pub fn sys_listen(fd: i32, backlog: i32) -> KResult<isize> {
         return Err(KError::InvalidInput);
     }
 
-    Socket::from_fd(fd)?.listen()?;
+    // `-1` and other oversized values are capped to `somaxconn`
+    let backlog = usize::try_from(backlog).unwrap_or(usize::MAX);
+    Socket::from_fd(fd)?.listen(backlog)?;
 
     Ok(0)
 }
@@ -11,7 +11,7 @@ use kerrno::{KError, KResult};
 use khal::time::TimeValue;
 use kprocess::Pid;
 use ktask::current;
-use linux_raw_sys::general::{__kernel_old_timeval, RLIM_NLIMITS, rlimit64, rusage};
+use linux_raw_sys::general::{__kernel_old_timeval, RLIM_NLIMITS, RLIMIT_AS, rlimit64, rusage};
 use osvm::{VirtMutPtr, VirtPtr};
 
 use crate::time::TimeValueLike;
@@ -43,7 +43,8 @@ pub fn sys_prlimit64(
             return Err(KError::InvalidInput);
         }
 
-        let limit = &mut proc_data.rlim.write()[resource];
+        let mut rlim = proc_data.rlim.write();
+        let limit = &mut rlim[resource];
         if new_limit.rlim_max <= limit.max {
             limit.max = new_limit.rlim_max;
         } else {
@@ -53,6 +54,9 @@ pub fn sys_prlimit64(
         }
 
         limit.current = new_limit.rlim_cur;
+        if resource == RLIMIT_AS {
+            proc_data.aspace.lock().set_size_limit(rlim.aspace_size());
+        }
     }
 
     Ok(0)
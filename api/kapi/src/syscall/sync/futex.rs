@@ -8,19 +8,23 @@
 //! - Futex wait and wake operations
 //! - Futex requeue operations
 //! - Robust futex lists
-//! - Priority-inheritance futexes
+//! - Priority-inheritance futexes, whose owners run with the priority of
+//!   their real-time waiters
 
 use core::sync::atomic::Ordering;
 
 use kcore::{
-    futex::FutexKey,
+    futex::{FutexKey, FutexTable},
+    mm::cmpxchg_user_u32,
     task::{AsThread, get_task},
 };
 use kerrno::{KError, KResult, LinuxError};
+use khal::time::{TimeValue, monotonic_time, wall_time};
 use ktask::current;
 use linux_raw_sys::general::{
-    FUTEX_CMD_MASK, FUTEX_CMP_REQUEUE, FUTEX_REQUEUE, FUTEX_WAIT, FUTEX_WAIT_BITSET, FUTEX_WAKE,
-    FUTEX_WAKE_BITSET, robust_list_head, timespec,
+    FUTEX_CLOCK_REALTIME, FUTEX_CMD_MASK, FUTEX_CMP_REQUEUE, FUTEX_LOCK_PI, FUTEX_LOCK_PI2,
+    FUTEX_OWNER_DIED, FUTEX_REQUEUE, FUTEX_TID_MASK, FUTEX_TRYLOCK_PI, FUTEX_UNLOCK_PI, FUTEX_WAIT,
+    FUTEX_WAIT_BITSET, FUTEX_WAITERS, FUTEX_WAKE, FUTEX_WAKE_BITSET, robust_list_head, timespec,
 };
 use osvm::{VirtMutPtr, VirtPtr};
 
@@ -35,6 +39,97 @@ fn assert_unsigned(value: u32) -> KResult<u32> {
     }
 }
 
+/// Locks the PI futex at `uaddr` for the current task, waiting until
+/// `deadline` in monotonic time, or only tries to with `try_only`.
+///
+/// The futex word holds the TID of its owner, which runs with the priority
+/// of the highest real-time waiter until it hands the futex over to it.
+fn futex_lock_pi(
+    uaddr: *const u32,
+    key: &FutexKey,
+    table: &FutexTable,
+    deadline: Option<TimeValue>,
+    try_only: bool,
+) -> KResult<isize> {
+    let tid = current().id().as_u64() as u32;
+    let futex = table.get_or_insert(key);
+    loop {
+        let mut waiters = futex.pi_waiters();
+        let word = uaddr.read_vm()?;
+        let owner = word & FUTEX_TID_MASK;
+        if owner == tid {
+            return Err(KError::from(LinuxError::EDEADLK));
+        }
+        if owner == 0 {
+            // Free, or left by an owner that died.
+            let waiters = if waiters.is_empty() { 0 } else { FUTEX_WAITERS };
+            let locked = tid | waiters | (word & FUTEX_OWNER_DIED);
+            if cmpxchg_user_u32(uaddr.addr(), word, locked)? != word {
+                continue;
+            }
+            return owner_died(word);
+        }
+        if try_only {
+            return Err(KError::WouldBlock);
+        }
+
+        let owner = get_task(owner)?;
+        let contended = word | FUTEX_WAITERS;
+        if contended != word && cmpxchg_user_u32(uaddr.addr(), word, contended)? != word {
+            continue;
+        }
+        let waiter = waiters.add(tid, &owner);
+        drop((owner, waiters));
+        let timeout = deadline.map(|deadline| deadline.saturating_sub(monotonic_time()));
+        let res = waiter.wait(timeout);
+        if futex.pi_waiters().remove(&waiter) {
+            res?;
+            continue;
+        }
+        // Handed over by the owner, which set this task as the owner.
+        return owner_died(uaddr.read_vm()?);
+    }
+}
+
+/// Returns the result of taking over a PI futex whose word was `word`.
+fn owner_died(word: u32) -> KResult<isize> {
+    if word & FUTEX_OWNER_DIED != 0 {
+        Err(KError::from(LinuxError::EOWNERDEAD))
+    } else {
+        Ok(0)
+    }
+}
+
+/// Unlocks the PI futex at `uaddr`, owned by the current task, handing it
+/// over to the waiter of the highest priority if there is one.
+fn futex_unlock_pi(uaddr: *const u32, key: &FutexKey, table: &FutexTable) -> KResult<isize> {
+    let tid = current().id().as_u64() as u32;
+    let futex = table.get(key);
+    let mut waiters = futex.as_ref().map(|futex| futex.pi_waiters());
+    let next = waiters.as_ref().and_then(|waiters| {
+        let next = waiters.next_owner()?;
+        Some(next | if waiters.len() > 1 { FUTEX_WAITERS } else { 0 })
+    });
+    let mut word = uaddr.read_vm()?;
+    loop {
+        if word & FUTEX_TID_MASK != tid {
+            return Err(KError::OperationNotPermitted);
+        }
+        match cmpxchg_user_u32(uaddr.addr(), word, next.unwrap_or(0))? {
+            old if old == word => break,
+            old => word = old,
+        }
+    }
+    if let Some(waiters) = &mut waiters {
+        if next.is_some() {
+            waiters.hand_over();
+        } else {
+            waiters.unboost();
+        }
+    }
+    Ok(0)
+}
+
 /// Fast userspace mutex (futex) system call.
 /// Implements Linux futex semantics for efficient synchronization primitives.
 pub fn sys_futex(
@@ -132,6 +227,31 @@ pub fn sys_futex(
             }
             Ok(count as _)
         }
+        FUTEX_LOCK_PI | FUTEX_LOCK_PI2 | FUTEX_TRYLOCK_PI => {
+            // An absolute timeout, on the realtime clock but for
+            // FUTEX_LOCK_PI2.
+            let deadline = if let Some(ts) = timeout.check_non_null()
+                && command != FUTEX_TRYLOCK_PI
+            {
+                let ts = unsafe { ts.read_uninit()?.assume_init() }.try_into_time_value()?;
+                let now = if command == FUTEX_LOCK_PI || futex_op & FUTEX_CLOCK_REALTIME != 0 {
+                    wall_time()
+                } else {
+                    monotonic_time()
+                };
+                Some(monotonic_time() + ts.saturating_sub(now))
+            } else {
+                None
+            };
+            futex_lock_pi(
+                uaddr,
+                &key,
+                &futex_table,
+                deadline,
+                command == FUTEX_TRYLOCK_PI,
+            )
+        }
+        FUTEX_UNLOCK_PI => futex_unlock_pi(uaddr, &key, &futex_table),
         _ => Err(KError::Unsupported),
     }
 }
@@ -9,18 +9,27 @@
 //! - System information (uname, sysinfo, etc.)
 //! - Process information queries
 //! - Hostname management
+//! - Power off and restart (reboot)
 
 use core::ffi::c_char;
 
+use kbuild_config::ARCH;
 use kcore::task::processes;
 use kerrno::{KError, KResult};
 use kfs::FS_CONTEXT;
+use kpower::{Action, Reason, RebootMode, Request};
 use linux_raw_sys::{
-    general::{GRND_INSECURE, GRND_NONBLOCK, GRND_RANDOM},
+    general::{
+        GRND_INSECURE, GRND_NONBLOCK, GRND_RANDOM, LINUX_REBOOT_CMD_CAD_OFF,
+        LINUX_REBOOT_CMD_CAD_ON, LINUX_REBOOT_CMD_HALT, LINUX_REBOOT_CMD_POWER_OFF,
+        LINUX_REBOOT_CMD_RESTART, LINUX_REBOOT_CMD_RESTART2, LINUX_REBOOT_MAGIC1,
+        LINUX_REBOOT_MAGIC2, LINUX_REBOOT_MAGIC2A, LINUX_REBOOT_MAGIC2B, LINUX_REBOOT_MAGIC2C,
+    },
     system::{new_utsname, sysinfo},
 };
 use osvm::{VirtMutPtr, write_vm_mem};
-use kbuild_config::ARCH;
+
+use crate::mm::vm_load_string;
 
 /// Get the real user ID of the current process
 pub fn sys_getuid() -> KResult<isize> {
@@ -82,7 +91,7 @@ const fn pad_str(info: &str) -> [c_char; 65] {
 // Compatible with Linux
 const UTSNAME: new_utsname = new_utsname {
     sysname: pad_str("Linux"),
-    nodename: pad_str("kylin-x"),
+    nodename: [0; 65],
     release: pad_str("10.0.0"),
     version: pad_str("10.0.0"),
     machine: pad_str(ARCH),
@@ -91,7 +100,10 @@ const UTSNAME: new_utsname = new_utsname {
 
 /// Get system information including OS name, version, and hardware platform
 pub fn sys_uname(name: *mut new_utsname) -> KResult<isize> {
-    name.write_vm(UTSNAME)?;
+    let mut utsname = UTSNAME;
+    // Also announced over mDNS, at most 63 bytes
+    utsname.nodename = pad_str(&knet::mdns::hostname());
+    name.write_vm(utsname)?;
     Ok(0)
 }
 
@@ -105,6 +117,40 @@ pub fn sys_sysinfo(info: *mut sysinfo) -> KResult<isize> {
     Ok(0)
 }
 
+/// Power off, halt or restart the machine, through the shutdown hooks
+///
+/// `LINUX_REBOOT_CMD_RESTART2` takes the reboot mode in `arg`: `cold`, `warm`
+/// or `bootloader`.
+pub fn sys_reboot(magic1: u32, magic2: u32, cmd: u32, arg: *const c_char) -> KResult<isize> {
+    debug!("sys_reboot <= cmd: {cmd:#x}");
+    if magic1 != LINUX_REBOOT_MAGIC1
+        || ![
+            LINUX_REBOOT_MAGIC2,
+            LINUX_REBOOT_MAGIC2A,
+            LINUX_REBOOT_MAGIC2B,
+            LINUX_REBOOT_MAGIC2C,
+        ]
+        .contains(&magic2)
+    {
+        return Err(KError::InvalidInput);
+    }
+    let action = match cmd {
+        LINUX_REBOOT_CMD_POWER_OFF => Action::PowerOff,
+        LINUX_REBOOT_CMD_HALT => Action::Halt,
+        LINUX_REBOOT_CMD_RESTART => Action::Reboot(RebootMode::Cold),
+        LINUX_REBOOT_CMD_RESTART2 => {
+            Action::Reboot(kpower::parse_reboot_mode(&vm_load_string(arg)?)?)
+        }
+        // There is no Ctrl-Alt-Del to trap
+        LINUX_REBOOT_CMD_CAD_ON | LINUX_REBOOT_CMD_CAD_OFF => return Ok(0),
+        _ => return Err(KError::InvalidInput),
+    };
+    kpower::shutdown(Request {
+        action,
+        reason: Reason::Syscall,
+    })
+}
+
 /// Access kernel log buffer (syslog)
 pub fn sys_syslog(_type: i32, _buf: *mut c_char, _len: usize) -> KResult<isize> {
     Ok(0)
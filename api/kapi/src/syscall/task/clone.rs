@@ -19,6 +19,7 @@ use kcore::{
 use kerrno::{KError, KResult};
 use kfs::FS_CONTEXT;
 use khal::uspace::UserContext;
+use knet::netns::{NET_NS, NetStack};
 use kprocess::Pid;
 use ksignal::Signo;
 use kspin::SpinNoIrq;
@@ -127,6 +128,10 @@ pub fn sys_clone(
     if flags.contains(CloneFlags::PIDFD | CloneFlags::PARENT_SETTID) {
         return Err(KError::InvalidInput);
     }
+    // Network namespaces belong to processes
+    if flags.contains(CloneFlags::THREAD | CloneFlags::NEWNET) {
+        return Err(KError::InvalidInput);
+    }
     let exit_signal = Signo::from_repr(exit_signal as u8);
 
     let mut new_uctx = *uctx;
@@ -148,6 +153,9 @@ pub fn sys_clone(
     let old_proc_data = &curr.as_thread().proc_data;
 
     let mut new_task = new_user_task(&curr.name(), new_uctx, set_child_tid);
+    new_task.set_io_priority(curr.io_priority());
+    #[cfg(target_arch = "aarch64")]
+    ktask::vector::inherit_vector_config(&mut new_task);
 
     let tid = new_task.id().as_u64() as Pid;
     if flags.contains(CloneFlags::PARENT_SETTID) {
@@ -155,9 +163,12 @@ pub fn sys_clone(
     }
 
     let new_proc_data = if flags.contains(CloneFlags::THREAD) {
+        let aspace = old_proc_data.aspace.lock();
         new_task
             .ctx_mut()
-            .set_page_table_root(old_proc_data.aspace.lock().page_table_root());
+            .set_page_table_root(aspace.page_table_root());
+        new_task.ctx_mut().set_asid(aspace.asid());
+        drop(aspace);
         old_proc_data.clone()
     } else {
         let proc = if flags.contains(CloneFlags::PARENT) {
@@ -175,9 +186,13 @@ pub fn sys_clone(
             copy_from_kernel(&mut aspace.lock())?;
             aspace
         };
-        new_task
-            .ctx_mut()
-            .set_page_table_root(aspace.lock().page_table_root());
+        {
+            let aspace = aspace.lock();
+            new_task
+                .ctx_mut()
+                .set_page_table_root(aspace.page_table_root());
+            new_task.ctx_mut().set_asid(aspace.asid());
+        }
 
         let signal_actions = if flags.contains(CloneFlags::SIGHAND) {
             old_proc_data.signal.actions.clone()
@@ -193,8 +208,10 @@ pub fn sys_clone(
             exit_signal,
         );
         proc_data.set_umask(old_proc_data.umask());
+        proc_data.set_compat(old_proc_data.is_compat());
         // Inherit heap pointers from parent to ensure child's heap state is consistent after fork
         proc_data.set_heap_top(old_proc_data.get_heap_top());
+        *proc_data.rlim.write() = old_proc_data.rlim.read().clone();
 
         {
             let mut scope = proc_data.scope.write();
@@ -215,6 +232,12 @@ pub fn sys_clone(
                     .lock()
                     .clone_from(&FS_CONTEXT.lock());
             }
+
+            if flags.contains(CloneFlags::NEWNET) {
+                *NET_NS.scope_mut(&mut scope) = Some(NetStack::new());
+            } else {
+                NET_NS.scope_mut(&mut scope).clone_from(&NET_NS);
+            }
         }
 
         proc_data
@@ -14,6 +14,8 @@ use core::ffi::c_char;
 use kcore::task::{AsThread, get_process_data};
 use kerrno::{KError, KResult};
 use ktask::current;
+#[cfg(target_arch = "aarch64")]
+use ktask::vector::{VectorKind, VectorLength};
 use linux_raw_sys::general::{__user_cap_data_struct, __user_cap_header_struct};
 use osvm::{VirtMutPtr, VirtPtr, write_vm_mem};
 
@@ -85,6 +87,35 @@ pub fn sys_get_mempolicy(
     Ok(0)
 }
 
+/// Encodes vector length settings as `PR_SVE_GET_VL` and `PR_SME_GET_VL`
+/// return them.
+#[cfg(target_arch = "aarch64")]
+fn encode_vl(vl: VectorLength) -> isize {
+    use linux_raw_sys::prctl::PR_SVE_VL_INHERIT;
+
+    let flags = if vl.inherit { PR_SVE_VL_INHERIT } else { 0 };
+    (vl.len as u32 | flags) as isize
+}
+
+/// Handles `PR_SVE_SET_VL` and `PR_SME_SET_VL`, whose arguments share their
+/// layout.
+#[cfg(target_arch = "aarch64")]
+fn set_vector_length(kind: VectorKind, arg: usize) -> KResult<isize> {
+    use linux_raw_sys::prctl::{PR_SVE_SET_VL_ONEXEC, PR_SVE_VL_INHERIT, PR_SVE_VL_LEN_MASK};
+
+    let arg = u32::try_from(arg).map_err(|_| KError::InvalidInput)?;
+    if arg & !(PR_SVE_VL_LEN_MASK | PR_SVE_VL_INHERIT | PR_SVE_SET_VL_ONEXEC) != 0 {
+        return Err(KError::InvalidInput);
+    }
+    ktask::vector::set_vector_length(
+        kind,
+        (arg & PR_SVE_VL_LEN_MASK) as usize,
+        arg & PR_SVE_VL_INHERIT != 0,
+        arg & PR_SVE_SET_VL_ONEXEC != 0,
+    )
+    .map(encode_vl)
+}
+
 /// prctl() is called with a first argument describing what to do, and further
 /// arguments with a significance depending on the first one.
 /// The first argument can be:
@@ -116,6 +147,14 @@ pub fn sys_prctl(
             buf[..len].copy_from_slice(&name.as_bytes()[..len]);
             write_vm_mem(arg2 as _, &buf)?;
         }
+        #[cfg(target_arch = "aarch64")]
+        PR_SVE_SET_VL => return set_vector_length(VectorKind::Sve, arg2),
+        #[cfg(target_arch = "aarch64")]
+        PR_SVE_GET_VL => return ktask::vector::vector_length(VectorKind::Sve).map(encode_vl),
+        #[cfg(target_arch = "aarch64")]
+        PR_SME_SET_VL => return set_vector_length(VectorKind::Sme, arg2),
+        #[cfg(target_arch = "aarch64")]
+        PR_SME_GET_VL => return ktask::vector::vector_length(VectorKind::Sme).map(encode_vl),
         PR_SET_SECCOMP => {}
         PR_MCE_KILL => {}
         PR_SET_MM => {
@@ -9,7 +9,11 @@
 //! - Program loading and initialization
 //! - Argument and environment passing
 
-use alloc::{string::ToString, sync::Arc, vec::Vec};
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 use core::ffi::c_char;
 
 use kcore::{config::USER_HEAP_BASE, mm::load_user_app, task::AsThread};
@@ -51,6 +55,16 @@ pub fn sys_execve(
 
     debug!("sys_execve <= path: {path:?}, args: {args:?}, envs: {envs:?}");
 
+    do_execve(uctx, path, args, envs)
+}
+
+/// Replaces the program of the current process with the one at `path`.
+pub(crate) fn do_execve(
+    uctx: &mut UserContext,
+    path: String,
+    args: Vec<String>,
+    envs: Vec<String>,
+) -> KResult<isize> {
     let curr = current();
     let proc_data = &curr.as_thread().proc_data;
 
@@ -60,9 +74,10 @@ pub fn sys_execve(
         return Err(KError::WouldBlock);
     }
 
+    let ustack_size = proc_data.rlim.read().stack_size();
     let mut aspace = proc_data.aspace.lock();
-    let (entry_point, user_stack_base) =
-        load_user_app(&mut aspace, Some(path.as_str()), &args, &envs)?;
+    let (entry_point, user_stack_base, compat) =
+        load_user_app(&mut aspace, Some(path.as_str()), &args, &envs, ustack_size)?;
     drop(aspace);
 
     let loc = FS_CONTEXT.lock().resolve(&path)?;
@@ -89,6 +104,24 @@ pub fn sys_execve(
     }
     drop(fd_table);
 
+    #[cfg(target_arch = "aarch64")]
+    ktask::vector::exec_vector_state();
+
+    proc_data.set_compat(compat);
+    #[cfg(all(
+        feature = "compat",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    if compat || uctx.is_compat() {
+        // Switching the execution state starts over from a fresh context.
+        *uctx = if compat {
+            UserContext::new_compat(entry_point.as_usize(), user_stack_base, 0)
+        } else {
+            UserContext::new(entry_point.as_usize(), user_stack_base, 0)
+        };
+        return Ok(0);
+    }
+
     uctx.set_ip(entry_point.as_usize());
     uctx.set_sp(user_stack_base.as_usize());
     Ok(0)
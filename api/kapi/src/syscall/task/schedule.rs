@@ -8,13 +8,17 @@
 //! - Yield control (sched_yield, etc.)
 //! - Sleep operations (sleep, nanosleep, etc.)
 //! - Scheduling priority (getpriority, setpriority, nice, etc.)
+//! - I/O priority (ioprio_get, ioprio_set)
 //! - CPU affinity (sched_setaffinity, sched_getaffinity, etc.)
 
-use kcore::task::{get_process_data, get_process_group};
+use alloc::{vec, vec::Vec};
+
+use kcore::task::{AsThread, get_process_data, get_process_group, get_task, tasks};
+use kdriver::prelude::IoPriority;
 use kerrno::{KError, KResult};
 use khal::time::TimeValue;
 use ktask::{
-    KCpuMask, current,
+    KCpuMask, KtaskRef, current,
     future::{block_on, interruptible, sleep},
 };
 use linux_raw_sys::general::{
@@ -30,7 +34,7 @@ pub fn sys_sched_yield() -> KResult<isize> {
     Ok(0)
 }
 
-fn sleep_impl(clock: impl Fn() -> TimeValue, dur: TimeValue) -> TimeValue {
+pub(crate) fn sleep_impl(clock: impl Fn() -> TimeValue, dur: TimeValue) -> TimeValue {
     debug!("sleep_impl <= {dur:?}");
 
     // Record the time before sleeping
@@ -109,14 +113,7 @@ pub fn sys_sched_getaffinity(pid: i32, cpusetsize: usize, user_mask: *mut u8) ->
         return Err(KError::InvalidInput);
     }
 
-    // TODO: support other threads
-    // Currently only supports getting the calling thread's affinity (pid=0)
-    if pid != 0 {
-        return Err(KError::OperationNotPermitted);
-    }
-
-    // Get current thread's CPU affinity mask
-    let mask = current().cpumask();
+    let mask = ktask::get_affinity(&get_task(pid as _)?);
     let mask_bytes = mask.as_bytes();
 
     // Write the mask to user space
@@ -125,7 +122,7 @@ pub fn sys_sched_getaffinity(pid: i32, cpusetsize: usize, user_mask: *mut u8) ->
     Ok(mask_bytes.len() as _)
 }
 
-pub fn sys_sched_setaffinity(_pid: i32, cpusetsize: usize, user_mask: *const u8) -> KResult<isize> {
+pub fn sys_sched_setaffinity(pid: i32, cpusetsize: usize, user_mask: *const u8) -> KResult<isize> {
     // Load the CPU mask from user space (limit to actual CPU count)
     let size = cpusetsize.min(kbuild_config::CPU_NUM.div_ceil(8) as _);
     let user_mask = load_vec(user_mask, size)?;
@@ -138,9 +135,10 @@ pub fn sys_sched_setaffinity(_pid: i32, cpusetsize: usize, user_mask: *const u8)
         }
     }
 
-    // TODO: support other threads
-    // Apply the CPU affinity to the current thread
-    ktask::set_current_affinity(cpu_mask);
+    let task = get_task(pid as _)?;
+    if !ktask::set_affinity(&task, cpu_mask) {
+        return Err(KError::InvalidInput);
+    }
 
     Ok(0)
 }
@@ -183,3 +181,57 @@ pub fn sys_getpriority(which: u32, who: u32) -> KResult<isize> {
         _ => Err(KError::InvalidInput),
     }
 }
+
+/// `which` of `ioprio_get(2)` and `ioprio_set(2)`.
+const IOPRIO_WHO_PROCESS: u32 = 1;
+const IOPRIO_WHO_PGRP: u32 = 2;
+const IOPRIO_WHO_USER: u32 = 3;
+
+/// Returns the tasks selected by `which` and `who` of `ioprio_set(2)`.
+fn ioprio_tasks(which: u32, who: u32) -> KResult<Vec<KtaskRef>> {
+    match which {
+        IOPRIO_WHO_PROCESS => Ok(vec![get_task(who)?]),
+        IOPRIO_WHO_PGRP => {
+            let pg = if who == 0 {
+                current().as_thread().proc_data.proc.group()
+            } else {
+                get_process_group(who)?
+            };
+            Ok(pg
+                .processes()
+                .iter()
+                .flat_map(|proc| proc.threads())
+                .filter_map(|tid| get_task(tid).ok())
+                .collect())
+        }
+        // Everything runs as root
+        IOPRIO_WHO_USER if who == 0 => Ok(tasks()),
+        IOPRIO_WHO_USER => Err(KError::NoSuchProcess),
+        _ => Err(KError::InvalidInput),
+    }
+}
+
+pub fn sys_ioprio_get(which: u32, who: u32) -> KResult<isize> {
+    debug!("sys_ioprio_get <= which: {which}, who: {who}");
+    // The highest priority of the tasks, like Linux
+    let priority = ioprio_tasks(which, who)?
+        .iter()
+        .map(|task| IoPriority::from_raw(task.io_priority()).unwrap_or_default())
+        .min()
+        .ok_or(KError::NoSuchProcess)?;
+    Ok(priority.to_raw() as _)
+}
+
+pub fn sys_ioprio_set(which: u32, who: u32, ioprio: u32) -> KResult<isize> {
+    debug!("sys_ioprio_set <= which: {which}, who: {who}, ioprio: {ioprio:#x}");
+    let ioprio = u16::try_from(ioprio).map_err(|_| KError::InvalidInput)?;
+    IoPriority::from_raw(ioprio).ok_or(KError::InvalidInput)?;
+    let tasks = ioprio_tasks(which, who)?;
+    if tasks.is_empty() {
+        return Err(KError::NoSuchProcess);
+    }
+    for task in tasks {
+        task.set_io_priority(ioprio);
+    }
+    Ok(0)
+}
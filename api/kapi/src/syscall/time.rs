@@ -24,7 +24,13 @@ use crate::time::TimeValueLike;
 
 /// Get the current time from the specified clock
 pub fn sys_clock_gettime(clock_id: __kernel_clockid_t, ts: *mut timespec) -> KResult<isize> {
-    let now = match clock_id as u32 {
+    ts.write_vm(timespec::from_time_value(clock_time(clock_id)))?;
+    Ok(0)
+}
+
+/// Reads the clock `clock_id`.
+pub(crate) fn clock_time(clock_id: __kernel_clockid_t) -> TimeValue {
+    match clock_id as u32 {
         CLOCK_REALTIME | CLOCK_REALTIME_COARSE => wall_time(),
         CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_MONOTONIC_COARSE | CLOCK_BOOTTIME => {
             monotonic_time()
@@ -38,9 +44,7 @@ pub fn sys_clock_gettime(clock_id: __kernel_clockid_t, ts: *mut timespec) -> KRe
             wall_time()
             // return Err(KError::EINVAL);
         }
-    };
-    ts.write_vm(timespec::from_time_value(now))?;
-    Ok(0)
+    }
 }
 
 /// Get the current time of day
@@ -9,6 +9,7 @@ use core::{ffi::c_long, sync::atomic::Ordering};
 use bytemuck::AnyBitPattern;
 use kcore::{
     futex::FutexKey,
+    mm::cmpxchg_user_u32,
     shm::SHM_MANAGER,
     task::{
         AsThread, get_process_data, get_task, send_signal_to_process, send_signal_to_thread,
@@ -21,10 +22,11 @@ use khal::uspace::{ExceptionKind, ReturnReason, UserContext};
 use kprocess::Pid;
 use ksignal::{SignalInfo, Signo};
 use ktask::{TaskInner, current};
-use linux_raw_sys::general::ROBUST_LIST_LIMIT;
+use linux_raw_sys::general::{FUTEX_OWNER_DIED, FUTEX_TID_MASK, FUTEX_WAITERS, ROBUST_LIST_LIMIT};
 use osvm::{VirtMutPtr, VirtPtr};
 
 use crate::{
+    file::{FD_TABLE, release_record_locks},
     signal::{check_signals, unblock_next_signal},
     syscall::dispatch_irq_syscall,
 };
@@ -43,7 +45,9 @@ pub fn new_user_task(name: &str, mut uctx: UserContext, set_child_tid: usize) ->
 
             let thr = curr.as_thread();
             while !thr.pending_exit() {
+                ktask::set_user_mode(true);
                 let reason = uctx.run();
+                ktask::set_user_mode(false);
 
                 set_timer_state(&curr, TimerState::Kernel);
 
@@ -133,8 +137,35 @@ fn dispatch_irq_futex_death(entry: *mut RobustList, offset: i64) -> KResult<()>
 
     let curr = current();
     let futex_table = curr.as_thread().proc_data.futex_table_for(&key);
+    let futex = futex_table.get(&key);
+    let mut waiters = futex.as_ref().map(|futex| futex.pi_waiters());
+    // The owner TID is taken out of the futex word, for the next locker of a
+    // PI futex to take it over, or replaced by the one of its first waiter.
+    let next = waiters.as_ref().and_then(|waiters| {
+        let next = waiters.next_owner()?;
+        Some(next | if waiters.len() > 1 { FUTEX_WAITERS } else { 0 })
+    });
+    let tid = curr.id().as_u64() as u32;
+    let mut word = (address as *const u32).read_vm()?;
+    let mut handed_over = false;
+    while word & FUTEX_TID_MASK == tid {
+        let dead = next.unwrap_or(word & FUTEX_WAITERS) | FUTEX_OWNER_DIED;
+        match cmpxchg_user_u32(address, word, dead)? {
+            old if old == word => {
+                handed_over = next.is_some();
+                break;
+            }
+            old => word = old,
+        }
+    }
+    if let Some(waiters) = &mut waiters
+        && handed_over
+    {
+        waiters.hand_over();
+    }
+    drop(waiters);
 
-    let Some(futex) = futex_table.get(&key) else {
+    let Some(futex) = futex else {
         return Ok(());
     };
     futex.owner_dead.store(true, Ordering::SeqCst);
@@ -197,6 +228,12 @@ pub fn do_exit(exit_code: i32, group_exit: bool) {
 
     let process = &thr.proc_data.proc;
     if process.exit_thread(curr.id().as_u64() as Pid, exit_code) {
+        let fd_table = FD_TABLE.read();
+        for fd in fd_table.ids() {
+            release_record_locks(fd_table.get(fd).unwrap());
+        }
+        drop(fd_table);
+
         process.exit();
         if let Some(parent) = process.parent() {
             if let Some(signo) = thr.proc_data.exit_signal {
@@ -13,7 +13,7 @@ use kdriver::prelude::{
     DriverError, DriverOps, Event, EventType, InputDevice, InputDeviceId, InputDriverOps,
 };
 use kerrno::{KError, KResult};
-use khal::time::wall_time;
+use khal::time::{monotonic_time, wall_time};
 use kpoll::{IoEvents, Pollable};
 use ksync::Mutex;
 use linux_raw_sys::{
@@ -35,6 +35,7 @@ impl Inner {
         if self.read_ahead.is_none() {
             match self.device.read_event() {
                 Ok(event) => {
+                    fbdevice::fb_activity(monotonic_time());
                     if event.event_type == EventType::Key as u16 {
                         if event.value == 0 {
                             self.key_state.set(event.code as usize, false);
@@ -9,7 +9,7 @@ use kcore::vfs::{DeviceMmap, DeviceOps};
 #[allow(unused_imports)]
 use kdriver::prelude::DisplayDriverOps;
 use kerrno::KError;
-use khal::mem::v2p;
+use khal::{mem::v2p, time::monotonic_time};
 use memaddr::{PhysAddrRange, VirtAddr};
 use osvm::VirtMutPtr;
 
@@ -84,6 +84,7 @@ struct FixScreenInfo {
 async fn refresh_task() {
     let delay = core::time::Duration::from_secs_f32(1. / 60.);
     loop {
+        fbdevice::fb_check_idle(monotonic_time());
         if !fbdevice::fb_flush() {
             warn!("Failed to refresh framebuffer");
         }
@@ -220,8 +221,13 @@ impl DeviceOps for FrameBuffer {
             0x4605 => Ok(0),
             // FBIOPAN_DISPLAY
             0x4606 => Err(KError::InvalidInput),
-            // FBIOBLANK
-            0x4611 => Err(KError::InvalidInput),
+            // FBIOBLANK, anything but FB_BLANK_UNBLANK blanks
+            0x4611 => {
+                if !fbdevice::fb_set_blanked(arg != 0, monotonic_time()) {
+                    return Err(KError::InvalidInput);
+                }
+                Ok(0)
+            }
             _ => Err(KError::NotATty),
         }
     }
@@ -2,6 +2,7 @@
 // Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
 // See LICENSES for license details.
 
+use alloc::{format, string::String};
 use core::{
     any::Any,
     sync::atomic::{AtomicBool, AtomicU32, Ordering},
@@ -22,6 +23,9 @@ use crate::file::get_file_like;
 
 /// /dev/loopX devices
 /// Loop device for attaching regular files as block devices
+///
+/// An attached file is also the block device `loopX` of [`kfs`], whose
+/// filesystem can be mounted from `/dev/loopX`.
 pub struct LoopDevice {
     number: u32,
     dev_id: DeviceId,
@@ -61,6 +65,11 @@ impl LoopDevice {
         Ok(())
     }
 
+    /// Returns the name of the block device in [`kfs`].
+    fn name(&self) -> String {
+        format!("loop{}", self.number)
+    }
+
     /// Clone the underlying file of the loop device.
     pub fn clone_file(&self) -> VfsResult<FileBackend> {
         let file = self.file.lock().clone();
@@ -100,13 +109,20 @@ impl DeviceOps for LoopDevice {
                     return Err(KError::ResourceBusy);
                 }
 
-                *guard = Some(file.inner().backend()?.clone());
+                let backend = file.inner().backend()?.clone();
+                kfs::attach_loop(
+                    &self.name(),
+                    backend.clone(),
+                    self.ro.load(Ordering::Relaxed),
+                )?;
+                *guard = Some(backend);
             }
             LOOP_CLR_FD => {
                 let mut guard = self.file.lock();
                 if guard.is_none() {
                     return Err(KError::from(LinuxError::ENXIO));
                 }
+                kfs::detach_loop(&self.name())?;
                 *guard = None;
             }
             LOOP_GET_STATUS => {
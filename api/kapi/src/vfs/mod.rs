@@ -5,16 +5,16 @@
 //! Virtual filesystems
 
 pub mod dev;
+mod overlay;
 mod proc;
+mod sys;
 mod tmp;
 
-use fs_ng_vfs::{
-    Filesystem, NodePermission,
-    path::{Path, PathBuf},
-};
+use fs_ng_vfs::{Filesystem, NodePermission};
 pub use kcore::vfs::{Device, DeviceOps, DirMapping, SimpleFs};
 use kerrno::LinuxResult;
 use kfs::{FS_CONTEXT, FsContext};
+pub use overlay::OverlayFs;
 pub use tmp::MemoryFs;
 
 const DIR_PERMISSION: NodePermission = NodePermission::from_bits_truncate(0o755);
@@ -37,17 +37,7 @@ pub fn mount_all() -> LinuxResult<()> {
     mount_at(&fs, "/dev/shm", tmp::MemoryFs::new())?;
     mount_at(&fs, "/tmp", tmp::MemoryFs::new())?;
     mount_at(&fs, "/proc", proc::new_procfs())?;
-
-    mount_at(&fs, "/sys", tmp::MemoryFs::new())?;
-    let mut path = PathBuf::new();
-    for comp in Path::new("/sys/class/graphics/fb0/device").components() {
-        path.push(comp.as_str());
-        if fs.resolve(&path).is_err() {
-            fs.create_dir(&path, DIR_PERMISSION)?;
-        }
-    }
-    path.push("subsystem");
-    fs.symlink("whatever", &path)?;
+    mount_at(&fs, "/sys", sys::new_sysfs())?;
     drop(fs);
 
     #[cfg(feature = "dev-log")]
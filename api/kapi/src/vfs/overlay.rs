@@ -0,0 +1,676 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Overlay filesystem.
+//!
+//! An overlay merges a read-only lower directory tree, e.g. the root of a
+//! mounted disk image, with a writable upper one, usually a tmpfs. Lookups
+//! prefer the upper layer, and directories present in both are merged.
+//!
+//! The lower layer is never modified:
+//! - Modifying a lower entry first copies it up, creating its parent
+//!   directories in the upper layer as needed.
+//! - Removing an entry that exists below leaves a whiteout in the upper
+//!   layer: a character device with device number 0/0, as Linux does.
+//! - A directory created over a whiteout is made opaque with a
+//!   `.wh..wh..opq` marker, hiding the lower directory of the same name.
+
+use alloc::{borrow::ToOwned, string::String, sync::Arc, vec, vec::Vec};
+use core::{any::Any, task::Context};
+
+use fs_ng_vfs::{
+    DeviceId, DirEntry, DirEntrySink, DirNode, DirNodeOps, FileNode, FileNodeOps, Filesystem,
+    FilesystemOps, Location, Metadata, MetadataUpdate, NodeOps, NodePermission, NodeType,
+    Reference, StatFs, VfsError, VfsResult, WeakDirEntry,
+};
+use hashbrown::{HashMap, HashSet};
+use kfs::CachedFile;
+use kpoll::{IoEvents, Pollable};
+use ksync::Mutex;
+
+const OVERLAYFS_SUPER_MAGIC: u32 = 0x794c7630;
+
+/// Name of the marker making an upper directory opaque.
+const OPAQUE_MARKER: &str = ".wh..wh..opq";
+
+/// Set in the inode numbers of entries only found in the upper layer, so
+/// that they don't collide with the ones of the lower layer.
+const UPPER_INO_BIT: u64 = 1 << 63;
+
+/// Size of the chunks files are copied up in.
+const COPY_UP_CHUNK: usize = 64 * 1024;
+
+/// A filesystem merging a writable upper directory over a read-only lower
+/// one.
+pub struct OverlayFs {
+    upper: Location,
+    root: Mutex<Option<DirEntry>>,
+}
+
+impl OverlayFs {
+    /// Creates an overlay of `upper` over `lower`, both of which must be
+    /// directories.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(lower: Location, upper: Location) -> VfsResult<Filesystem> {
+        lower.check_is_dir()?;
+        upper.check_is_dir()?;
+        let fs = Arc::new(Self {
+            upper: upper.clone(),
+            root: Mutex::default(),
+        });
+        let ino = lower.inode();
+        *fs.root.lock() = Some(DirEntry::new_dir(
+            |this| {
+                DirNode::new(OverlayNode::new(
+                    fs.clone(),
+                    None,
+                    String::new(),
+                    ino,
+                    Some(upper),
+                    Some(lower),
+                    Some(this),
+                ))
+            },
+            Reference::root(),
+        ));
+        Ok(Filesystem::new(fs))
+    }
+}
+
+impl FilesystemOps for OverlayFs {
+    fn name(&self) -> &str {
+        "overlay"
+    }
+
+    fn root_dir(&self) -> DirEntry {
+        self.root.lock().clone().unwrap()
+    }
+
+    /// Space is only ever allocated in the upper layer.
+    fn stat(&self) -> VfsResult<StatFs> {
+        let mut stat = self.upper.filesystem().stat()?;
+        stat.fs_type = OVERLAYFS_SUPER_MAGIC;
+        Ok(stat)
+    }
+
+    fn flush(&self) -> VfsResult<()> {
+        self.upper.filesystem().flush()
+    }
+}
+
+fn is_whiteout(loc: &Location) -> bool {
+    loc.node_type() == NodeType::CharacterDevice
+        && loc
+            .metadata()
+            .is_ok_and(|metadata| metadata.rdev == DeviceId::default())
+}
+
+fn is_opaque(dir: &Location) -> VfsResult<bool> {
+    Ok(lookup_layer(dir, OPAQUE_MARKER)?.is_some())
+}
+
+fn create_whiteout(dir: &Location, name: &str) -> VfsResult<()> {
+    dir.create(name, NodeType::CharacterDevice, NodePermission::empty())
+        .map(|_| ())
+}
+
+/// Looks up `name` in a layer, telling a missing entry apart from errors.
+fn lookup_layer(dir: &Location, name: &str) -> VfsResult<Option<Location>> {
+    match dir.lookup_no_follow(name) {
+        Ok(loc) => Ok(Some(loc)),
+        Err(VfsError::NotFound) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Lists the entries of a layer, `.` and `..` excluded.
+fn list_layer(dir: &Location) -> VfsResult<Vec<(String, u64, NodeType)>> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    loop {
+        let mut next = offset;
+        let read = dir.read_dir(offset, &mut |name: &str, ino, node_type, off| {
+            if name != "." && name != ".." {
+                entries.push((name.to_owned(), ino, node_type));
+            }
+            next = off;
+            true
+        })?;
+        if read == 0 {
+            return Ok(entries);
+        }
+        offset = next;
+    }
+}
+
+/// Removes the whiteouts and the opaque marker left in an upper directory
+/// that looks empty, so that it can be removed.
+fn clear_upper_dir(dir: &Location) -> VfsResult<()> {
+    for (name, ..) in list_layer(dir)? {
+        dir.unlink(&name, false)?;
+    }
+    Ok(())
+}
+
+struct OverlayNode {
+    fs: Arc<OverlayFs>,
+    parent: Option<Arc<OverlayNode>>,
+    name: String,
+    ino: u64,
+    /// The entry in the upper layer, created on copy-up.
+    upper: Mutex<Option<Location>>,
+    /// The entry in the lower layer, only kept as long as it shows through.
+    lower: Option<Location>,
+    /// The page cache of the file in the layer it currently lives in.
+    cache: Mutex<Option<CachedFile>>,
+    this: Option<WeakDirEntry>,
+}
+
+impl OverlayNode {
+    fn new(
+        fs: Arc<OverlayFs>,
+        parent: Option<Arc<OverlayNode>>,
+        name: String,
+        ino: u64,
+        upper: Option<Location>,
+        lower: Option<Location>,
+        this: Option<WeakDirEntry>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            fs,
+            parent,
+            name,
+            ino,
+            upper: Mutex::new(upper),
+            lower,
+            cache: Mutex::default(),
+            this,
+        })
+    }
+
+    fn upper(&self) -> Option<Location> {
+        self.upper.lock().clone()
+    }
+
+    /// Returns the entry of the topmost layer the node exists in.
+    fn top(&self) -> Location {
+        self.upper()
+            .or_else(|| self.lower.clone())
+            .expect("overlay node without layers")
+    }
+
+    fn cached(&self, loc: Location) -> CachedFile {
+        let mut cache = self.cache.lock();
+        match cache.as_ref() {
+            // A copy-up may have happened since `loc` was taken
+            Some(cached) if cached.location().ptr_eq(&loc) => cached.clone(),
+            _ => cache.insert(CachedFile::get_or_create(loc)).clone(),
+        }
+    }
+
+    /// Returns the upper entry, copying the node up from the lower layer
+    /// first if needed.
+    fn copy_up(&self) -> VfsResult<Location> {
+        let mut upper = self.upper.lock();
+        if let Some(upper) = upper.as_ref() {
+            return Ok(upper.clone());
+        }
+        let lower = self.lower.as_ref().ok_or(VfsError::NotFound)?;
+        let parent = self
+            .parent
+            .as_ref()
+            .ok_or(VfsError::InvalidInput)?
+            .copy_up()?;
+
+        let metadata = lower.metadata()?;
+        let new = parent.create(&self.name, metadata.node_type, metadata.mode)?;
+        let result = match metadata.node_type {
+            NodeType::Symlink => new.entry().as_file()?.set_symlink(&lower.read_link()?),
+            NodeType::RegularFile => copy_data(lower, &new),
+            _ => Ok(()),
+        }
+        .and_then(|_| {
+            new.update_metadata(MetadataUpdate {
+                owner: Some((metadata.uid, metadata.gid)),
+                atime: Some(metadata.atime),
+                mtime: Some(metadata.mtime),
+                ..Default::default()
+            })
+        });
+        if let Err(err) = result {
+            let _ = parent.unlink(&self.name, metadata.node_type == NodeType::Directory);
+            return Err(err);
+        }
+
+        *self.cache.lock() = None;
+        *upper = Some(new.clone());
+        Ok(new)
+    }
+
+    /// Inode number of a child: the lower one if the name exists below, so
+    /// that it stays the same across copy-up.
+    fn child_ino(&self, name: &str, upper: &Location) -> VfsResult<u64> {
+        if let Some(lower) = self.lower.as_ref()
+            && let Some(entry) = lookup_layer(lower, name)?
+        {
+            return Ok(entry.inode());
+        }
+        Ok(upper.inode() | UPPER_INO_BIT)
+    }
+
+    /// Returns whether the name exists in the lower layer under this
+    /// directory, shown or not.
+    fn in_lower(&self, name: &str) -> VfsResult<bool> {
+        match self.lower.as_ref() {
+            Some(lower) => Ok(lookup_layer(lower, name)?.is_some()),
+            None => Ok(false),
+        }
+    }
+
+    /// Makes room for a new upper entry, removing a whiteout in the way.
+    ///
+    /// Returns whether there was one.
+    fn remove_whiteout(upper: &Location, name: &str) -> VfsResult<bool> {
+        match lookup_layer(upper, name)? {
+            Some(entry) if is_whiteout(&entry) => {
+                upper.unlink(name, false)?;
+                Ok(true)
+            }
+            Some(_) => Err(VfsError::AlreadyExists),
+            None => Ok(false),
+        }
+    }
+
+    fn this_node(&self) -> VfsResult<Arc<Self>> {
+        self.this
+            .as_ref()
+            .and_then(WeakDirEntry::upgrade)
+            .ok_or(VfsError::NotFound)?
+            .downcast()
+    }
+
+    fn new_entry(
+        &self,
+        name: &str,
+        ino: u64,
+        upper: Option<Location>,
+        lower: Option<Location>,
+    ) -> VfsResult<DirEntry> {
+        let node_type = upper.as_ref().or(lower.as_ref()).unwrap().node_type();
+        let fs = self.fs.clone();
+        let parent = Some(self.this_node()?);
+        let reference = Reference::new(
+            self.this.as_ref().and_then(WeakDirEntry::upgrade),
+            name.to_owned(),
+        );
+        let name = name.to_owned();
+        Ok(if node_type == NodeType::Directory {
+            DirEntry::new_dir(
+                |this| {
+                    DirNode::new(OverlayNode::new(
+                        fs,
+                        parent,
+                        name,
+                        ino,
+                        upper,
+                        lower,
+                        Some(this),
+                    ))
+                },
+                reference,
+            )
+        } else {
+            DirEntry::new_file(
+                FileNode::new(OverlayNode::new(fs, parent, name, ino, upper, lower, None)),
+                node_type,
+                reference,
+            )
+        })
+    }
+
+    /// Lists the merged directory, `.` and `..` excluded.
+    fn merged_entries(&self) -> VfsResult<Vec<(String, u64, NodeType)>> {
+        let mut lower_entries = match self.lower.as_ref() {
+            Some(lower) => list_layer(lower)?
+                .into_iter()
+                .map(|(name, ino, node_type)| (name, (ino, node_type)))
+                .collect(),
+            None => HashMap::new(),
+        };
+        let mut entries = Vec::new();
+        let mut hidden = HashSet::new();
+        if let Some(upper) = self.upper() {
+            for (name, ino, node_type) in list_layer(&upper)? {
+                if name == OPAQUE_MARKER {
+                    continue;
+                }
+                let lower = lower_entries.remove(&name);
+                if node_type == NodeType::CharacterDevice
+                    && let Some(entry) = lookup_layer(&upper, &name)?
+                    && is_whiteout(&entry)
+                {
+                    hidden.insert(name);
+                    continue;
+                }
+                let ino = lower.map_or(ino | UPPER_INO_BIT, |(ino, _)| ino);
+                entries.push((name, ino, node_type));
+            }
+        }
+        entries.extend(
+            lower_entries
+                .into_iter()
+                .filter(|(name, _)| name != OPAQUE_MARKER && !hidden.contains(name))
+                .map(|(name, (ino, node_type))| (name, ino, node_type)),
+        );
+        Ok(entries)
+    }
+}
+
+fn copy_data(src: &Location, dst: &Location) -> VfsResult<()> {
+    let src = CachedFile::get_or_create(src.clone());
+    let dst = CachedFile::get_or_create(dst.clone());
+    let mut buf = vec![0; COPY_UP_CHUNK];
+    let mut offset = 0;
+    loop {
+        let read = src.read_at(&mut buf[..], offset)?;
+        if read == 0 {
+            return Ok(());
+        }
+        dst.write_at(&buf[..read], offset)?;
+        offset += read as u64;
+    }
+}
+
+impl NodeOps for OverlayNode {
+    fn inode(&self) -> u64 {
+        self.ino
+    }
+
+    fn metadata(&self) -> VfsResult<Metadata> {
+        let mut metadata = self.top().metadata()?;
+        metadata.inode = self.ino;
+        Ok(metadata)
+    }
+
+    fn update_metadata(&self, update: MetadataUpdate) -> VfsResult<()> {
+        self.copy_up()?.update_metadata(update)
+    }
+
+    fn filesystem(&self) -> &dyn FilesystemOps {
+        self.fs.as_ref()
+    }
+
+    fn sync(&self, data_only: bool) -> VfsResult<()> {
+        match self.upper() {
+            Some(upper) => upper.sync(data_only),
+            None => Ok(()),
+        }
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+impl FileNodeOps for OverlayNode {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> VfsResult<usize> {
+        let loc = self.top();
+        if loc.node_type() == NodeType::RegularFile {
+            self.cached(loc).read_at(buf, offset)
+        } else {
+            loc.entry().as_file()?.read_at(buf, offset)
+        }
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> VfsResult<usize> {
+        let loc = self.copy_up()?;
+        if loc.node_type() == NodeType::RegularFile {
+            self.cached(loc).write_at(buf, offset)
+        } else {
+            loc.entry().as_file()?.write_at(buf, offset)
+        }
+    }
+
+    fn append(&self, buf: &[u8]) -> VfsResult<(usize, u64)> {
+        let loc = self.copy_up()?;
+        if loc.node_type() == NodeType::RegularFile {
+            self.cached(loc).append(buf)
+        } else {
+            loc.entry().as_file()?.append(buf)
+        }
+    }
+
+    fn set_len(&self, len: u64) -> VfsResult<()> {
+        let loc = self.copy_up()?;
+        if loc.node_type() == NodeType::RegularFile {
+            self.cached(loc).set_len(len)
+        } else {
+            loc.entry().as_file()?.set_len(len)
+        }
+    }
+
+    fn set_symlink(&self, target: &str) -> VfsResult<()> {
+        self.copy_up()?.entry().as_file()?.set_symlink(target)
+    }
+}
+
+impl Pollable for OverlayNode {
+    fn poll(&self) -> IoEvents {
+        IoEvents::IN | IoEvents::OUT
+    }
+
+    fn register(&self, _context: &mut Context<'_>, _events: IoEvents) {}
+}
+
+impl DirNodeOps for OverlayNode {
+    fn read_dir(&self, offset: u64, sink: &mut dyn DirEntrySink) -> VfsResult<usize> {
+        let parent_ino = self.parent.as_ref().map_or(self.ino, |parent| parent.ino);
+        let entries = [
+            (".".to_owned(), self.ino, NodeType::Directory),
+            ("..".to_owned(), parent_ino, NodeType::Directory),
+        ]
+        .into_iter()
+        .chain(self.merged_entries()?);
+
+        let mut count = 0;
+        for (i, (name, ino, node_type)) in entries.enumerate().skip(offset as usize) {
+            if !sink.accept(&name, ino, node_type, i as u64 + 1) {
+                return Ok(count);
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn lookup(&self, name: &str) -> VfsResult<DirEntry> {
+        if name == OPAQUE_MARKER {
+            return Err(VfsError::NotFound);
+        }
+        let upper = match self.upper() {
+            Some(dir) => lookup_layer(&dir, name)?,
+            None => None,
+        };
+        if upper.as_ref().is_some_and(is_whiteout) {
+            return Err(VfsError::NotFound);
+        }
+        let lower = match (&upper, self.lower.as_ref()) {
+            (Some(upper), _) if !upper.is_dir() || is_opaque(upper)? => None,
+            (_, Some(dir)) => lookup_layer(dir, name)?,
+            (_, None) => None,
+        };
+        let ino = match (&upper, &lower) {
+            (_, Some(lower)) => lower.inode(),
+            (Some(upper), None) => self.child_ino(name, upper)?,
+            (None, None) => return Err(VfsError::NotFound),
+        };
+        // An upper directory only merges with a directory below
+        let lower = lower.filter(|lower| upper.is_none() || lower.is_dir());
+        self.new_entry(name, ino, upper, lower)
+    }
+
+    fn create(
+        &self,
+        name: &str,
+        node_type: NodeType,
+        permission: NodePermission,
+    ) -> VfsResult<DirEntry> {
+        if name == OPAQUE_MARKER {
+            return Err(VfsError::InvalidInput);
+        }
+        if self.lookup(name).is_ok() {
+            return Err(VfsError::AlreadyExists);
+        }
+        let upper = self.copy_up()?;
+        let whiteout = Self::remove_whiteout(&upper, name)?;
+        let new = upper.create(name, node_type, permission)?;
+        if node_type == NodeType::Directory && whiteout {
+            new.create(
+                OPAQUE_MARKER,
+                NodeType::RegularFile,
+                NodePermission::empty(),
+            )?;
+        }
+        let ino = self.child_ino(name, &new)?;
+        self.new_entry(name, ino, Some(new), None)
+    }
+
+    fn link(&self, name: &str, node: &DirEntry) -> VfsResult<DirEntry> {
+        let target = node.downcast::<Self>()?;
+        if self.lookup(name).is_ok() {
+            return Err(VfsError::AlreadyExists);
+        }
+        let src = target.copy_up()?;
+        let upper = self.copy_up()?;
+        Self::remove_whiteout(&upper, name)?;
+        let new = upper.link(name, &src)?;
+        self.new_entry(name, target.ino, Some(new), None)
+    }
+
+    fn unlink(&self, name: &str) -> VfsResult<()> {
+        let child = self.lookup(name)?.downcast::<Self>()?;
+        let is_dir = child.top().is_dir();
+        if is_dir && !child.merged_entries()?.is_empty() {
+            return Err(VfsError::DirectoryNotEmpty);
+        }
+        let upper = self.copy_up()?;
+        if let Some(child_upper) = child.upper() {
+            if is_dir {
+                clear_upper_dir(&child_upper)?;
+            }
+            upper.unlink(name, is_dir)?;
+        }
+        if self.in_lower(name)? {
+            create_whiteout(&upper, name)?;
+        }
+        Ok(())
+    }
+
+    fn rename(&self, src_name: &str, dst_dir: &DirNode, dst_name: &str) -> VfsResult<()> {
+        let dst = dst_dir.downcast::<Self>()?;
+        let src = self.lookup(src_name)?.downcast::<Self>()?;
+        // Like Linux without `redirect_dir`, merged directories can't be
+        // renamed, and callers fall back to copying.
+        if src.top().is_dir() && src.lower.is_some() {
+            return Err(VfsError::CrossesDevices);
+        }
+        if let Ok(entry) = dst.lookup(dst_name) {
+            if entry.inode() == src.ino {
+                return Ok(());
+            }
+            let old = entry.downcast::<Self>()?;
+            if let Some(old_upper) = old.upper()
+                && old_upper.is_dir()
+            {
+                clear_upper_dir(&old_upper)?;
+            }
+        }
+
+        src.copy_up()?;
+        let src_upper = self.copy_up()?;
+        let dst_upper = dst.copy_up()?;
+        Self::remove_whiteout(&dst_upper, dst_name).or_else(|err| match err {
+            VfsError::AlreadyExists => Ok(false),
+            err => Err(err),
+        })?;
+        src_upper.rename(src_name, &dst_upper, dst_name)?;
+        if self.in_lower(src_name)? {
+            create_whiteout(&src_upper, src_name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unittest)]
+mod overlay_tests {
+    use fs_ng_vfs::Mountpoint;
+    use kfs::FsContext;
+    use unittest::def_test;
+
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    fn layer() -> FsContext {
+        FsContext::new(Mountpoint::new_root(&MemoryFs::new()).root_location())
+    }
+
+    fn names(ctx: &FsContext, path: &str) -> Vec<String> {
+        let mut names = ctx
+            .read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().name)
+            .filter(|name| name != "." && name != "..")
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    /// Test copy-up, whiteouts and opaque directories
+    #[def_test]
+    fn test_overlay_layers() {
+        let perm = NodePermission::from_bits_truncate(0o755);
+        let lower = layer();
+        lower.create_dir("/etc", perm).unwrap();
+        lower.write("/etc/hostname", b"base").unwrap();
+        lower.write("/etc/passwd", b"root").unwrap();
+        lower.create_dir("/var", perm).unwrap();
+        lower.write("/var/old", b"old").unwrap();
+        let upper = layer();
+
+        let fs = OverlayFs::new(lower.root_dir().clone(), upper.root_dir().clone()).unwrap();
+        let ctx = FsContext::new(Mountpoint::new_root(&fs).root_location());
+        assert_eq!(ctx.read_to_string("/etc/hostname").unwrap(), "base");
+        let ino = ctx.metadata("/etc/hostname").unwrap().inode;
+
+        // Writing copies up, leaving the lower layer alone
+        ctx.write("/etc/hostname", b"node").unwrap();
+        assert_eq!(ctx.read_to_string("/etc/hostname").unwrap(), "node");
+        assert_eq!(upper.read_to_string("/etc/hostname").unwrap(), "node");
+        assert_eq!(lower.read_to_string("/etc/hostname").unwrap(), "base");
+        assert_eq!(ctx.metadata("/etc/hostname").unwrap().inode, ino);
+        assert_eq!(names(&ctx, "/etc"), ["hostname", "passwd"]);
+
+        // Removing a lower file leaves a whiteout
+        ctx.remove_file("/etc/passwd").unwrap();
+        assert!(matches!(
+            ctx.metadata("/etc/passwd"),
+            Err(VfsError::NotFound)
+        ));
+        assert!(is_whiteout(
+            &upper.resolve_no_follow("/etc/passwd").unwrap()
+        ));
+        assert_eq!(lower.read_to_string("/etc/passwd").unwrap(), "root");
+        assert_eq!(names(&ctx, "/etc"), ["hostname"]);
+        ctx.write("/etc/passwd", b"user").unwrap();
+        assert_eq!(ctx.read_to_string("/etc/passwd").unwrap(), "user");
+
+        // A directory recreated over a whiteout hides the lower one
+        ctx.remove_file("/var/old").unwrap();
+        ctx.remove_dir("/var").unwrap();
+        assert!(ctx.metadata("/var").is_err());
+        ctx.create_dir("/var", perm).unwrap();
+        assert!(names(&ctx, "/var").is_empty());
+        assert_eq!(names(&ctx, "/"), ["etc", "var"]);
+        assert_eq!(names(&lower, "/var"), ["old"]);
+    }
+}
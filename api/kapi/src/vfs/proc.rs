@@ -11,9 +11,14 @@ use alloc::{
     vec,
     vec::Vec,
 };
-use core::{ffi::CStr, iter};
+use core::{
+    ffi::CStr,
+    fmt::Write,
+    iter,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
 
-use fs_ng_vfs::{Filesystem, NodeType, VfsError, VfsResult};
+use fs_ng_vfs::{Filesystem, Mountpoint, NodeType, VfsError, VfsResult};
 use indoc::indoc;
 use kcore::{
     task::{AsThread, TaskStat, get_task, tasks},
@@ -22,70 +27,97 @@ use kcore::{
         SimpleFileOperation, SimpleFs,
     },
 };
+use khal::cpuinfo::CacheKind;
+use knet::{
+    congestion::{CongestionControl, default_congestion_control, set_default_congestion_control},
+    dns::{nameservers, set_nameservers},
+    mdns::{hostname, set_hostname},
+    netns::try_current_stack,
+    qdisc::QdiscConfig,
+    stats::{Protocol, TcpState},
+    tcp::{
+        max_syn_backlog, set_max_syn_backlog, set_somaxconn, set_syncookies_enabled,
+        set_timestamps_enabled, somaxconn, syncookies_enabled, timestamps_enabled,
+    },
+};
 use kprocess::Process;
 use ktask::{KtaskRef, WeakKtaskRef, current};
+use strum::VariantArray;
+
+use crate::file::{FD_TABLE, File};
+
+/// Formats `/proc/meminfo` from the allocator statistics.
+///
+/// There is no swap, and memory only accounted as heap shows as `Slab`, along
+/// with the object caches, whose empty slabs are reclaimable.
+fn meminfo() -> String {
+    use kalloc::UsageKind;
 
-use crate::file::FD_TABLE;
-
-const DUMMY_MEMINFO: &str = indoc! {"
-    MemTotal:       32536204 kB
-    MemFree:         5506524 kB
-    MemAvailable:   18768344 kB
-    Buffers:            3264 kB
-    Cached:         14454588 kB
-    SwapCached:            0 kB
-    Active:         18229700 kB
-    Inactive:        6540624 kB
-    Active(anon):   11380224 kB
-    Inactive(anon):        0 kB
-    Active(file):    6849476 kB
-    Inactive(file):  6540624 kB
-    Unevictable:      930088 kB
-    Mlocked:            1136 kB
-    SwapTotal:       4194300 kB
-    SwapFree:        4194300 kB
-    Zswap:                 0 kB
-    Zswapped:              0 kB
-    Dirty:             47952 kB
-    Writeback:             0 kB
-    AnonPages:      10992512 kB
-    Mapped:          1361184 kB
-    Shmem:           1068056 kB
-    KReclaimable:     341440 kB
-    Slab:             628996 kB
-    SReclaimable:     341440 kB
-    SUnreclaim:       287556 kB
-    KernelStack:       28704 kB
-    PageTables:        85308 kB
-    SecPageTables:      2084 kB
-    NFS_Unstable:          0 kB
-    Bounce:                0 kB
-    WritebackTmp:          0 kB
-    CommitLimit:    20462400 kB
-    Committed_AS:   45105316 kB
-    VmallocTotal:   34359738367 kB
-    VmallocUsed:      205924 kB
-    VmallocChunk:          0 kB
-    Percpu:            23840 kB
-    HardwareCorrupted:     0 kB
-    AnonHugePages:   1417216 kB
-    ShmemHugePages:        0 kB
-    ShmemPmdMapped:        0 kB
-    FileHugePages:    477184 kB
-    FilePmdMapped:    288768 kB
-    CmaTotal:              0 kB
-    CmaFree:               0 kB
-    Unaccepted:            0 kB
-    HugePages_Total:       0
-    HugePages_Free:        0
-    HugePages_Rsvd:        0
-    HugePages_Surp:        0
-    Hugepagesize:       2048 kB
-    Hugetlb:               0 kB
-    DirectMap4k:     1739900 kB
-    DirectMap2M:    31492096 kB
-    DirectMap1G:     1048576 kB
-"};
+    const PAGE_KB: usize = 4;
+    let allocator = kalloc::global_allocator();
+    let usages = allocator.usages();
+    let total = (allocator.used_pages() + allocator.available_pages()) * PAGE_KB;
+    let free = allocator.available_pages() * PAGE_KB;
+    let cached = usages.get(UsageKind::PageCache) / 1024;
+    let slab = (allocator.used_bytes() + usages.get(UsageKind::Slab)) / 1024;
+    let mut reclaimable = 0;
+    kalloc::for_each_cache(|cache| {
+        let stats = cache.stats();
+        reclaimable += stats.empty_slabs * stats.slab_size;
+    });
+    let reclaimable = reclaimable / 1024;
+
+    let mut out = String::new();
+    for (name, kb) in [
+        ("MemTotal", total),
+        ("MemFree", free),
+        ("MemAvailable", free + cached),
+        ("Buffers", 0),
+        ("Cached", cached),
+        ("SwapCached", 0),
+        ("SwapTotal", 0),
+        ("SwapFree", 0),
+        ("AnonPages", usages.get(UsageKind::VirtMem) / 1024),
+        ("Shmem", 0),
+        ("Slab", slab),
+        ("SReclaimable", reclaimable),
+        ("SUnreclaim", slab - reclaimable),
+        ("PageTables", usages.get(UsageKind::PageTable) / 1024),
+    ] {
+        let _ = writeln!(out, "{:<16}{kb:>8} kB", format!("{name}:"));
+    }
+    out
+}
+
+/// Formats `/proc/slabinfo` from the statistics of the object caches.
+fn slabinfo() -> String {
+    let mut out = String::from(
+        "slabinfo - version: 2.1\n# name            <active_objs> <num_objs> <objsize> \
+         <objperslab> <pagesperslab> : tunables <limit> <batchcount> <sharedfactor> : slabdata \
+         <active_slabs> <num_slabs> <sharedavail>\n",
+    );
+    kalloc::for_each_cache(|cache| {
+        let stats = cache.stats();
+        let _ = writeln!(
+            out,
+            "{:<17} {:>6} {:>6} {:>6} {:>4} {:>4} : tunables {:>4} {:>4} {:>4} : slabdata {:>6} \
+             {:>6} {:>6}",
+            cache.name(),
+            stats.active_objects,
+            stats.total_objects,
+            stats.stride,
+            stats.objects_per_slab,
+            stats.slab_size / 4096,
+            0,
+            0,
+            0,
+            stats.slabs - stats.empty_slabs,
+            stats.slabs,
+            0,
+        );
+    });
+    out
+}
 
 /// Create a new procfs filesystem for process information
 pub fn new_procfs() -> Filesystem {
@@ -134,20 +166,77 @@ impl SimpleDirOps for ProcessTaskDir {
 
 #[rustfmt::skip]
 fn task_status(task: &KtaskRef) -> String {
+    let stats = task.stats();
+    let (vm_size, vm_rss) = {
+        let aspace = task.as_thread().proc_data.aspace.lock();
+        (aspace.mapped_size(), aspace.resident_size())
+    };
     format!(
         "Tgid:\t{}\n\
         Pid:\t{}\n\
         Uid:\t0 0 0 0\n\
         Gid:\t0 0 0 0\n\
+        VmSize:\t{} kB\n\
+        VmRSS:\t{} kB\n\
         Cpus_allowed:\t1\n\
         Cpus_allowed_list:\t0\n\
         Mems_allowed:\t1\n\
-        Mems_allowed_list:\t0",
+        Mems_allowed_list:\t0\n\
+        voluntary_ctxt_switches:\t{}\n\
+        nonvoluntary_ctxt_switches:\t{}",
         task.as_thread().proc_data.proc.pid(),
-        task.id().as_u64()
+        task.id().as_u64(),
+        vm_size / 1024,
+        vm_rss / 1024,
+        stats.voluntary_switches,
+        stats.involuntary_switches
     )
 }
 
+/// /proc/[pid]/schedstat: the CPU time of the task in nanoseconds, the time
+/// it waited to run (not accounted), and the times it was switched out.
+fn task_schedstat(task: &KtaskRef) -> String {
+    let stats = task.stats();
+    format!(
+        "{} 0 {}\n",
+        (stats.utime + stats.stime).as_nanos(),
+        stats.voluntary_switches + stats.involuntary_switches
+    )
+}
+
+/// /proc/stat: the time of the CPUs in `USER_HZ` ticks, and their context
+/// switches.
+fn stat() -> String {
+    const NANOS_PER_USER_HZ: u128 = 10_000_000;
+    let cpus = ktask::stats();
+    let line = |out: &mut String, name: &str, cpu: &ktask::CpuStats| {
+        let _ = writeln!(
+            out,
+            "{name} {} 0 {} {} 0 {} 0 0 0 0",
+            cpu.user.as_nanos() / NANOS_PER_USER_HZ,
+            cpu.system.as_nanos() / NANOS_PER_USER_HZ,
+            cpu.idle.as_nanos() / NANOS_PER_USER_HZ,
+            cpu.irq.as_nanos() / NANOS_PER_USER_HZ
+        );
+    };
+    let total = cpus
+        .iter()
+        .fold(ktask::CpuStats::default(), |sum, cpu| ktask::CpuStats {
+            user: sum.user + cpu.user,
+            system: sum.system + cpu.system,
+            idle: sum.idle + cpu.idle,
+            irq: sum.irq + cpu.irq,
+            context_switches: sum.context_switches + cpu.context_switches,
+        });
+    let mut out = String::new();
+    line(&mut out, "cpu ", &total);
+    for (cpu_id, cpu) in cpus.iter().enumerate() {
+        line(&mut out, &format!("cpu{cpu_id}"), cpu);
+    }
+    let _ = writeln!(out, "ctxt {}", total.context_switches);
+    out
+}
+
 /// The /proc/[pid]/fd directory
 struct ThreadFdDir {
     fs: Arc<SimpleFs>,
@@ -188,6 +277,177 @@ impl SimpleDirOps for ThreadFdDir {
     }
 }
 
+/// The /proc/[pid]/fdinfo directory
+struct ThreadFdInfoDir {
+    fs: Arc<SimpleFs>,
+    task: WeakKtaskRef,
+}
+
+impl SimpleDirOps for ThreadFdInfoDir {
+    fn child_names<'a>(&'a self) -> Box<dyn Iterator<Item = Cow<'a, str>> + 'a> {
+        let Some(task) = self.task.upgrade() else {
+            return Box::new(iter::empty());
+        };
+        let ids = FD_TABLE
+            .scope(&task.as_thread().proc_data.scope.read())
+            .read()
+            .ids()
+            .map(|id| Cow::Owned(id.to_string()))
+            .collect::<Vec<_>>();
+        Box::new(ids.into_iter())
+    }
+
+    fn lookup_child(&self, name: &str) -> VfsResult<NodeOpsMux> {
+        let fs = self.fs.clone();
+        let task = self.task.upgrade().ok_or(VfsError::NotFound)?;
+        let fd = name.parse::<u32>().map_err(|_| VfsError::NotFound)?;
+        let file = FD_TABLE
+            .scope(&task.as_thread().proc_data.scope.read())
+            .read()
+            .get(fd as _)
+            .ok_or(VfsError::NotFound)?
+            .inner
+            .clone();
+        Ok(SimpleFile::new_regular(fs, move || {
+            let mut info = String::new();
+            let Some(file) = file.downcast_ref::<File>() else {
+                return Ok("pos:\t0\n".to_string());
+            };
+            let inner = file.inner();
+            let _ = writeln!(info, "pos:\t{}", inner.position().unwrap_or(0));
+            if let Some(ra) = inner.backend().ok().and_then(|it| it.readahead_info()) {
+                let _ = writeln!(info, "ra_window:\t{}", ra.window);
+                let _ = writeln!(info, "ra_prev_end:\t{}", ra.prev_end);
+                let _ = writeln!(info, "ra_ahead_until:\t{}", ra.ahead_until);
+                let _ = writeln!(info, "ra_pages:\t{}", ra.total_pages);
+            }
+            Ok(info)
+        })
+        .into())
+    }
+
+    fn supports_dentry_cache(&self) -> bool {
+        false
+    }
+}
+
+/// Returns the mount path of `mp`, with the characters separating the
+/// fields of the mount table escaped, like Linux.
+fn escaped_mount_path(mp: &Arc<Mountpoint>) -> String {
+    let path = mp.mount_path().map(|it| it.to_string()).unwrap_or_default();
+    let mut out = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            ' ' | '\t' | '\n' | '\\' => {
+                let _ = write!(out, "\\{:03o}", c as u32);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Formats /proc/cpuinfo, with a block for each CPU.
+fn cpuinfo() -> String {
+    let info = khal::cpuinfo::cpu_info();
+    let (family, model, stepping) = info.signature();
+    let flags = info
+        .features()
+        .iter()
+        .map(|f| f.name())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut out = String::new();
+    for cpu in 0..kbuild_config::CPU_NUM {
+        let _ = writeln!(out, "processor\t: {cpu}");
+        let _ = writeln!(out, "vendor_id\t: {}", info.vendor());
+        let _ = writeln!(out, "model name\t: {}", info.model_name());
+        let _ = writeln!(out, "cpu family\t: {family}");
+        let _ = writeln!(out, "model\t\t: {model:#x}");
+        let _ = writeln!(out, "stepping\t: {stepping}");
+        let _ = writeln!(out, "isa\t\t: {}", info.isa());
+        let _ = writeln!(out, "flags\t\t: {flags}");
+        for cache in info.caches() {
+            let kind = match cache.kind {
+                CacheKind::Data => "d",
+                CacheKind::Instruction => "i",
+                CacheKind::Unified => "",
+            };
+            let _ = writeln!(
+                out,
+                "cache L{}{kind}\t: {} KB, {}-way, {} byte lines",
+                cache.level,
+                cache.size / 1024,
+                cache.ways,
+                cache.line_size
+            );
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Formats the mount table for /proc/mounts.
+fn mounts() -> String {
+    let mut out = String::new();
+    let Some(ctx) = kfs::ROOT_FS_CONTEXT.get() else {
+        return out;
+    };
+    for mp in ctx.root_dir().mountpoint().mount_table() {
+        let _ = writeln!(
+            out,
+            "{} {} {} rw 0 0",
+            mp.source(),
+            escaped_mount_path(&mp),
+            mp.fs_type()
+        );
+    }
+    out
+}
+
+/// Formats the counters of `mp` and its nested mounts for /proc/mountstats.
+fn mount_stats(mp: &Arc<Mountpoint>, out: &mut String) {
+    for mp in mp.mount_table() {
+        mount_stats_of(&mp, out);
+    }
+}
+
+/// Formats the counters of `mp` alone.
+fn mount_stats_of(mp: &Arc<Mountpoint>, out: &mut String) {
+    let stats = mp.stats().snapshot();
+    let _ = writeln!(
+        out,
+        "device {} mounted on {} with fstype {}",
+        mp.source(),
+        escaped_mount_path(mp),
+        mp.fs_type()
+    );
+    let _ = writeln!(
+        out,
+        "\tlookups: {} dentry_hits: {}",
+        stats.lookups, stats.dentry_hits
+    );
+    let _ = writeln!(
+        out,
+        "\tpages: hits {} misses {} readahead {}",
+        stats.page_hits, stats.page_misses, stats.readahead_pages
+    );
+    let _ = writeln!(
+        out,
+        "\treads: {} bytes {} avg_latency_ns {}",
+        stats.reads,
+        stats.read_bytes,
+        stats.avg_read_latency().as_nanos()
+    );
+    let _ = writeln!(
+        out,
+        "\twrites: {} bytes {} avg_latency_ns {}",
+        stats.writes,
+        stats.write_bytes,
+        stats.avg_write_latency().as_nanos()
+    );
+}
+
 /// The /proc/[pid] directory
 struct ThreadDir {
     fs: Arc<SimpleFs>,
@@ -200,6 +460,7 @@ impl SimpleDirOps for ThreadDir {
             [
                 "stat",
                 "status",
+                "schedstat",
                 "oom_score_adj",
                 "task",
                 "maps",
@@ -208,6 +469,7 @@ impl SimpleDirOps for ThreadDir {
                 "comm",
                 "exe",
                 "fd",
+                "fdinfo",
             ]
             .into_iter()
             .map(Cow::Borrowed),
@@ -223,6 +485,7 @@ impl SimpleDirOps for ThreadDir {
             })
             .into(),
             "status" => SimpleFile::new_regular(fs, move || Ok(task_status(&task))).into(),
+            "schedstat" => SimpleFile::new_regular(fs, move || Ok(task_schedstat(&task))).into(),
             "oom_score_adj" => SimpleFile::new_regular(
                 fs,
                 RwFile::new(move |req| match req {
@@ -259,10 +522,7 @@ impl SimpleDirOps for ThreadDir {
                 "})
             })
             .into(),
-            "mounts" => SimpleFile::new_regular(fs, move || {
-                Ok("proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0\n")
-            })
-            .into(),
+            "mounts" => SimpleFile::new_regular(fs, move || Ok(mounts())).into(),
             "cmdline" => SimpleFile::new_regular(fs, move || {
                 let cmdline = task.as_thread().proc_data.cmdline.read();
                 let mut buf = Vec::new();
@@ -313,6 +573,14 @@ impl SimpleDirOps for ThreadDir {
                 }),
             )
             .into(),
+            "fdinfo" => SimpleDir::new_maker(
+                fs.clone(),
+                Arc::new(ThreadFdInfoDir {
+                    fs,
+                    task: Arc::downgrade(&task),
+                }),
+            )
+            .into(),
             _ => return Err(VfsError::NotFound),
         })
     }
@@ -357,17 +625,149 @@ impl SimpleDirOps for ProcFsHandler {
     }
 }
 
+/// A sysctl file holding a single unsigned integer.
+fn usize_sysctl(fs: Arc<SimpleFs>, get: fn() -> usize, set: fn(usize)) -> Arc<SimpleFile> {
+    SimpleFile::new_regular(
+        fs,
+        RwFile::new(move |req| match req {
+            SimpleFileOperation::Read => Ok(Some(format!("{}\n", get()).into_bytes())),
+            SimpleFileOperation::Write(data) => {
+                if !data.is_empty() {
+                    let value = str::from_utf8(data)
+                        .ok()
+                        .and_then(|it| it.trim().parse().ok())
+                        .ok_or(VfsError::InvalidInput)?;
+                    set(value);
+                }
+                Ok(None)
+            }
+        }),
+    )
+}
+
+/// Formats the counters of the current network stack like `/proc/net/snmp`.
+fn net_snmp() -> String {
+    let Some(stack) = try_current_stack() else {
+        return String::new();
+    };
+    let stats = stack.stats();
+    let established = stack
+        .sockets()
+        .iter()
+        .filter(|it| matches!(it.state, Some(TcpState::Established | TcpState::CloseWait)))
+        .count();
+    let (ip, tcp, udp) = (&stats.ip, &stats.tcp, &stats.udp);
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "Ip: Forwarding DefaultTTL InReceives InHdrErrors OutRequests OutDiscards \
+         OutNoRoutes\nIp: 2 64 {} {} {} {} {}",
+        ip.in_receives.get(),
+        ip.in_hdr_errors.get(),
+        ip.out_requests.get(),
+        ip.out_discards.get(),
+        ip.out_no_routes.get(),
+    );
+    let _ = writeln!(
+        out,
+        "Tcp: ActiveOpens PassiveOpens AttemptFails CurrEstab InSegs OutSegs RetransSegs InErrs \
+         OutRsts\nTcp: {} {} {} {established} {} {} {} {} {}",
+        tcp.active_opens.get(),
+        tcp.passive_opens.get(),
+        tcp.attempt_fails.get(),
+        tcp.in_segs.get(),
+        tcp.out_segs.get(),
+        tcp.retrans_segs.get(),
+        tcp.in_errs.get(),
+        tcp.out_rsts.get(),
+    );
+    let _ = writeln!(
+        out,
+        "Udp: InDatagrams NoPorts InErrors OutDatagrams\nUdp: {} {} {} {}",
+        udp.in_datagrams.get(),
+        udp.no_ports.get(),
+        udp.in_errors.get(),
+        udp.out_datagrams.get(),
+    );
+    out
+}
+
+/// Formats an address like `/proc/net/tcp`, as the words of the address in
+/// memory order.
+fn net_addr(addr: SocketAddr, out: &mut String) {
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            let _ = write!(out, "{:08X}", u32::from_le_bytes(ip.octets()));
+        }
+        IpAddr::V6(ip) => {
+            for word in ip.octets().chunks_exact(4) {
+                let _ = write!(out, "{:08X}", u32::from_le_bytes(word.try_into().unwrap()));
+            }
+        }
+    }
+    let _ = write!(out, ":{:04X}", addr.port());
+}
+
+/// Lists the sockets of the current network stack like `/proc/net/tcp` and
+/// friends.
+fn net_sockets(protocol: Protocol, ipv6: bool) -> String {
+    let mut out = String::from(
+        "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  \
+         timeout inode ref pointer drops\n",
+    );
+    let unspecified = match ipv6 {
+        true => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+        false => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+    };
+    let sockets = try_current_stack().map(|it| it.sockets());
+    let sockets = sockets
+        .iter()
+        .flatten()
+        .filter(|it| it.protocol == protocol && it.local.is_ipv6() == ipv6);
+    for (sl, socket) in sockets.enumerate() {
+        let _ = write!(out, "{sl:4}: ");
+        net_addr(socket.local, &mut out);
+        out.push(' ');
+        net_addr(socket.remote.unwrap_or(unspecified), &mut out);
+        // Bound UDP sockets show as closed, like on Linux
+        let state = socket.state.unwrap_or(TcpState::Close);
+        let _ = writeln!(
+            out,
+            " {:02X} {:08X}:{:08X} 00:00000000 {:08X}     0        0 0 1 0000000000000000 {}",
+            state as u8, socket.tx_queue, socket.rx_queue, socket.retransmits, socket.drops,
+        );
+    }
+    out
+}
+
 fn builder(fs: Arc<SimpleFs>) -> DirMaker {
     let mut root = DirMapping::new();
+    root.add(
+        "cpuinfo",
+        SimpleFile::new_regular(fs.clone(), || Ok(cpuinfo())),
+    );
     root.add(
         "mounts",
+        SimpleFile::new_regular(fs.clone(), || Ok(mounts())),
+    );
+    root.add(
+        "mountstats",
         SimpleFile::new_regular(fs.clone(), || {
-            Ok("proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0\n")
+            let mut out = String::new();
+            if let Some(ctx) = kfs::ROOT_FS_CONTEXT.get() {
+                mount_stats(ctx.root_dir().mountpoint(), &mut out);
+            }
+            Ok(out)
         }),
     );
+    root.add("stat", SimpleFile::new_regular(fs.clone(), || Ok(stat())));
     root.add(
         "meminfo",
-        SimpleFile::new_regular(fs.clone(), || Ok(DUMMY_MEMINFO)),
+        SimpleFile::new_regular(fs.clone(), || Ok(meminfo())),
+    );
+    root.add(
+        "slabinfo",
+        SimpleFile::new_regular(fs.clone(), || Ok(slabinfo())),
     );
     root.add(
         "meminfo2",
@@ -376,6 +776,45 @@ fn builder(fs: Arc<SimpleFs>) -> DirMaker {
             Ok(format!("{:?}\n", allocator.usages()))
         }),
     );
+    root.add(
+        "heaptags",
+        SimpleFile::new_regular(fs.clone(), || {
+            let mut out = String::from("tag current peak\n");
+            for &tag in kalloc::HeapTag::VARIANTS {
+                if let Some(usage) = kalloc::heap_tag_usage(tag) {
+                    let name: &'static str = tag.into();
+                    let _ = writeln!(out, "{name} {} {}", usage.current, usage.peak);
+                }
+            }
+            Ok(out)
+        }),
+    );
+    root.add("net", {
+        let mut net = DirMapping::new();
+        net.add(
+            "snmp",
+            SimpleFile::new_regular(fs.clone(), || Ok(net_snmp())),
+        );
+        net.add(
+            "netstat",
+            SimpleFile::new_regular(fs.clone(), || {
+                let drops = try_current_stack().map_or(0, |it| it.stats().tcp.listen_drops.get());
+                Ok(format!("TcpExt: ListenDrops\nTcpExt: {drops}\n"))
+            }),
+        );
+        for (name, protocol, ipv6) in [
+            ("tcp", Protocol::Tcp, false),
+            ("tcp6", Protocol::Tcp, true),
+            ("udp", Protocol::Udp, false),
+            ("udp6", Protocol::Udp, true),
+        ] {
+            net.add(
+                name,
+                SimpleFile::new_regular(fs.clone(), move || Ok(net_sockets(protocol, ipv6))),
+            );
+        }
+        SimpleDir::new_maker(fs.clone(), Arc::new(net))
+    });
     root.add(
         "instret",
         SimpleFile::new_regular(fs.clone(), || {
@@ -404,10 +843,213 @@ fn builder(fs: Arc<SimpleFs>) -> DirMaker {
                 "pid_max",
                 SimpleFile::new_regular(fs.clone(), || Ok("32768\n")),
             );
+            // The default log level and `pattern=level` directives, one per
+            // line; writes apply directives, see `klogger::levels`
+            kernel.add(
+                "log_levels",
+                SimpleFile::new_regular(
+                    fs.clone(),
+                    RwFile::new(|req| match req {
+                        SimpleFileOperation::Read => {
+                            let mut out = String::new();
+                            let _ = klogger::levels::write_directives(&mut out);
+                            Ok(Some(out.into_bytes()))
+                        }
+                        SimpleFileOperation::Write(data) => {
+                            let spec = str::from_utf8(data).map_err(|_| VfsError::InvalidInput)?;
+                            klogger::levels::apply_directives(spec)
+                                .map_err(|_| VfsError::InvalidInput)?;
+                            Ok(None)
+                        }
+                    }),
+                ),
+            );
+            kernel.add(
+                "hostname",
+                SimpleFile::new_regular(
+                    fs.clone(),
+                    RwFile::new(|req| match req {
+                        SimpleFileOperation::Read => {
+                            Ok(Some(format!("{}\n", hostname()).into_bytes()))
+                        }
+                        SimpleFileOperation::Write(data) => {
+                            if !data.is_empty() {
+                                let name = str::from_utf8(data)
+                                    .map_err(|_| VfsError::InvalidInput)?
+                                    .trim();
+                                set_hostname(name)?;
+                            }
+                            Ok(None)
+                        }
+                    }),
+                ),
+            );
 
             SimpleDir::new_maker(fs.clone(), Arc::new(kernel))
         });
 
+        sys.add("net", {
+            let mut net = DirMapping::new();
+
+            net.add("core", {
+                let mut core = DirMapping::new();
+
+                core.add(
+                    "somaxconn",
+                    usize_sysctl(fs.clone(), somaxconn, set_somaxconn),
+                );
+                // One line per device of the caller's namespace, written as
+                // `<dev> <config>` or `<dev> none`
+                core.add(
+                    "qdisc",
+                    SimpleFile::new_regular(
+                        fs.clone(),
+                        RwFile::new(|req| match req {
+                            SimpleFileOperation::Read => {
+                                let mut out = String::new();
+                                let qdiscs = try_current_stack().map(|it| it.qdiscs());
+                                for (dev, config) in qdiscs.into_iter().flatten() {
+                                    match config {
+                                        Some(config) => writeln!(out, "{dev} {config}"),
+                                        None => writeln!(out, "{dev} none"),
+                                    }
+                                    .unwrap();
+                                }
+                                Ok(Some(out.into_bytes()))
+                            }
+                            SimpleFileOperation::Write(data) => {
+                                let line = str::from_utf8(data)
+                                    .map_err(|_| VfsError::InvalidInput)?
+                                    .trim();
+                                if line.is_empty() {
+                                    return Ok(None);
+                                }
+                                let (dev, config) = line.split_once(' ').unwrap_or((line, "none"));
+                                let config = match config.trim() {
+                                    "none" => None,
+                                    config => Some(QdiscConfig::parse(config)?),
+                                };
+                                try_current_stack()
+                                    .ok_or(VfsError::NotFound)?
+                                    .set_qdisc(dev, config)?;
+                                Ok(None)
+                            }
+                        }),
+                    ),
+                );
+
+                SimpleDir::new_maker(fs.clone(), Arc::new(core))
+            });
+
+            // Name servers of the kernel's DNS client, separated by spaces
+            net.add("dns", {
+                let mut dns = DirMapping::new();
+
+                dns.add(
+                    "nameservers",
+                    SimpleFile::new_regular(
+                        fs.clone(),
+                        RwFile::new(|req| match req {
+                            SimpleFileOperation::Read => {
+                                let servers: Vec<_> =
+                                    nameservers().iter().map(|it| it.to_string()).collect();
+                                Ok(Some(format!("{}\n", servers.join(" ")).into_bytes()))
+                            }
+                            SimpleFileOperation::Write(data) => {
+                                let servers = str::from_utf8(data)
+                                    .map_err(|_| VfsError::InvalidInput)?
+                                    .split_whitespace()
+                                    .map(|it| {
+                                        it.parse::<SocketAddr>()
+                                            .or_else(|_| {
+                                                it.parse::<IpAddr>()
+                                                    .map(|ip| SocketAddr::new(ip, 53))
+                                            })
+                                            .map_err(|_| VfsError::InvalidInput)
+                                    })
+                                    .collect::<VfsResult<_>>()?;
+                                set_nameservers(servers);
+                                Ok(None)
+                            }
+                        }),
+                    ),
+                );
+
+                SimpleDir::new_maker(fs.clone(), Arc::new(dns))
+            });
+
+            net.add("ipv4", {
+                let mut ipv4 = DirMapping::new();
+
+                ipv4.add(
+                    "tcp_congestion_control",
+                    SimpleFile::new_regular(
+                        fs.clone(),
+                        RwFile::new(|req| match req {
+                            SimpleFileOperation::Read => Ok(Some(
+                                format!("{}\n", default_congestion_control()).into_bytes(),
+                            )),
+                            SimpleFileOperation::Write(data) => {
+                                if !data.is_empty() {
+                                    let name = str::from_utf8(data)
+                                        .map_err(|_| VfsError::InvalidInput)?
+                                        .trim();
+                                    set_default_congestion_control(CongestionControl::from_name(
+                                        name,
+                                    )?);
+                                }
+                                Ok(None)
+                            }
+                        }),
+                    ),
+                );
+                ipv4.add(
+                    "tcp_timestamps",
+                    SimpleFile::new_regular(
+                        fs.clone(),
+                        RwFile::new(|req| match req {
+                            SimpleFileOperation::Read => Ok(Some(
+                                format!("{}\n", timestamps_enabled() as u8).into_bytes(),
+                            )),
+                            SimpleFileOperation::Write(data) => {
+                                if !data.is_empty() {
+                                    let value = str::from_utf8(data)
+                                        .ok()
+                                        .and_then(|it| it.trim().parse::<u8>().ok())
+                                        .ok_or(VfsError::InvalidInput)?;
+                                    set_timestamps_enabled(value != 0);
+                                }
+                                Ok(None)
+                            }
+                        }),
+                    ),
+                );
+                ipv4.add(
+                    "tcp_max_syn_backlog",
+                    usize_sysctl(fs.clone(), max_syn_backlog, set_max_syn_backlog),
+                );
+                ipv4.add(
+                    "tcp_syncookies",
+                    usize_sysctl(
+                        fs.clone(),
+                        || syncookies_enabled() as usize,
+                        |value| set_syncookies_enabled(value != 0),
+                    ),
+                );
+                ipv4.add(
+                    "tcp_available_congestion_control",
+                    SimpleFile::new_regular(fs.clone(), || {
+                        let names = CongestionControl::ALL.map(CongestionControl::name);
+                        Ok(format!("{}\n", names.join(" ")))
+                    }),
+                );
+
+                SimpleDir::new_maker(fs.clone(), Arc::new(ipv4))
+            });
+
+            SimpleDir::new_maker(fs.clone(), Arc::new(net))
+        });
+
         SimpleDir::new_maker(fs.clone(), Arc::new(sys))
     });
 
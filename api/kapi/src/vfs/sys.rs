@@ -0,0 +1,176 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! sysfs, listing CPUs, block devices and the devices probed by drivers.
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    collections::btree_map::BTreeMap,
+    format,
+    string::ToString,
+    sync::Arc,
+};
+
+use fs_ng_vfs::{Filesystem, NodeType, VfsError, VfsResult};
+use kbuild_config::CPU_NUM;
+use kcore::vfs::{DirMaker, DirMapping, NodeOpsMux, SimpleDir, SimpleDirOps, SimpleFile, SimpleFs};
+use kdriver::prelude::DeviceKind;
+use kfs::BlockDeviceInfo;
+
+/// Create a new sysfs filesystem
+pub fn new_sysfs() -> Filesystem {
+    SimpleFs::new_with("sysfs".into(), 0x62656572, builder)
+}
+
+/// The class and name prefix of probed devices of `kind`, `None` for those
+/// listed elsewhere.
+fn device_class(kind: DeviceKind) -> Option<(&'static str, &'static str)> {
+    match kind {
+        // Listed with their partitions by `BlockClassDir`
+        DeviceKind::Block => None,
+        DeviceKind::Char => Some(("misc", "char")),
+        DeviceKind::Net => Some(("net", "eth")),
+        DeviceKind::Display => Some(("drm", "card")),
+        DeviceKind::Input => Some(("input", "input")),
+        DeviceKind::Vsock => Some(("vsock", "vsock")),
+        DeviceKind::Balloon => Some(("misc", "balloon")),
+    }
+}
+
+/// The `/sys/class/block` directory, listing the block devices as they are
+/// registered.
+struct BlockClassDir {
+    fs: Arc<SimpleFs>,
+}
+
+impl BlockClassDir {
+    fn device_dir(&self, info: BlockDeviceInfo) -> DirMaker {
+        let fs = &self.fs;
+        let sectors = info.num_blocks * info.block_size as u64 / 512;
+        let mut dir = DirMapping::new();
+        dir.add(
+            "size",
+            SimpleFile::new_regular(fs.clone(), move || Ok(format!("{sectors}\n"))),
+        );
+        dir.add("ro", SimpleFile::new_regular(fs.clone(), || Ok("0\n")));
+        if let Some(partition) = info.partition {
+            let start = partition.start * info.block_size as u64 / 512;
+            dir.add(
+                "partition",
+                SimpleFile::new_regular(fs.clone(), move || Ok(format!("{}\n", partition.number))),
+            );
+            dir.add(
+                "start",
+                SimpleFile::new_regular(fs.clone(), move || Ok(format!("{start}\n"))),
+            );
+        }
+        SimpleDir::new_maker(fs.clone(), Arc::new(dir))
+    }
+}
+
+impl SimpleDirOps for BlockClassDir {
+    fn child_names<'a>(&'a self) -> Box<dyn Iterator<Item = Cow<'a, str>> + 'a> {
+        Box::new(
+            kfs::block_devices()
+                .into_iter()
+                .map(|info| Cow::Owned(info.name)),
+        )
+    }
+
+    fn lookup_child(&self, name: &str) -> VfsResult<NodeOpsMux> {
+        let info = kfs::block_devices()
+            .into_iter()
+            .find(|info| info.name == name)
+            .ok_or(VfsError::NotFound)?;
+        Ok(NodeOpsMux::Dir(self.device_dir(info)))
+    }
+
+    fn supports_dentry_cache(&self) -> bool {
+        false
+    }
+}
+
+fn cpu_dir(fs: &Arc<SimpleFs>) -> DirMaker {
+    let cpus = match CPU_NUM {
+        1 => "0\n".to_string(),
+        n => format!("0-{}\n", n - 1),
+    };
+    let mut cpu = DirMapping::new();
+    for name in ["online", "possible", "present"] {
+        let cpus = cpus.clone();
+        cpu.add(
+            name,
+            SimpleFile::new_regular(fs.clone(), move || Ok(cpus.clone())),
+        );
+    }
+    SimpleDir::new_maker(fs.clone(), Arc::new(cpu))
+}
+
+fn class_dir(fs: &Arc<SimpleFs>) -> DirMaker {
+    let mut classes: BTreeMap<&str, DirMapping> = BTreeMap::new();
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for dev in kdriver::probed_devices() {
+        let Some((class, prefix)) = device_class(dev.kind) else {
+            continue;
+        };
+        let index = counts.entry(prefix).or_default();
+        let mut dir = DirMapping::new();
+        let name = format!("{}\n", dev.name);
+        dir.add(
+            "name",
+            SimpleFile::new_regular(fs.clone(), move || Ok(name.clone())),
+        );
+        classes.entry(class).or_default().add(
+            format!("{prefix}{index}"),
+            SimpleDir::new_maker(fs.clone(), Arc::new(dir)),
+        );
+        *index += 1;
+    }
+
+    let mut graphics = DirMapping::new();
+    if fbdevice::fb_available() {
+        let mut fb0 = DirMapping::new();
+        fb0.add("dev", SimpleFile::new_regular(fs.clone(), || Ok("29:0\n")));
+        // Looked up by programs probing for framebuffers, which only need the
+        // link to exist
+        let mut device = DirMapping::new();
+        device.add(
+            "subsystem",
+            SimpleFile::new(fs.clone(), NodeType::Symlink, || {
+                Ok("../../../../bus/platform")
+            }),
+        );
+        fb0.add("device", SimpleDir::new_maker(fs.clone(), Arc::new(device)));
+        graphics.add("fb0", SimpleDir::new_maker(fs.clone(), Arc::new(fb0)));
+    }
+
+    let mut class = DirMapping::new();
+    class.add(
+        "block",
+        SimpleDir::new_maker(fs.clone(), Arc::new(BlockClassDir { fs: fs.clone() })),
+    );
+    class.add(
+        "graphics",
+        SimpleDir::new_maker(fs.clone(), Arc::new(graphics)),
+    );
+    for (name, dir) in classes {
+        class.add(name, SimpleDir::new_maker(fs.clone(), Arc::new(dir)));
+    }
+    SimpleDir::new_maker(fs.clone(), Arc::new(class))
+}
+
+fn builder(fs: Arc<SimpleFs>) -> DirMaker {
+    let mut system = DirMapping::new();
+    system.add("cpu", cpu_dir(&fs));
+    let mut devices = DirMapping::new();
+    devices.add("system", SimpleDir::new_maker(fs.clone(), Arc::new(system)));
+
+    let mut root = DirMapping::new();
+    root.add("class", class_dir(&fs));
+    root.add(
+        "devices",
+        SimpleDir::new_maker(fs.clone(), Arc::new(devices)),
+    );
+    SimpleDir::new_maker(fs, Arc::new(root))
+}
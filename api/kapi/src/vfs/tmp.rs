@@ -8,14 +8,16 @@ use core::{any::Any, borrow::Borrow, cmp::Ordering, task::Context, time::Duratio
 use fs_ng_vfs::{
     DeviceId, DirEntry, DirEntrySink, DirNode, DirNodeOps, FileNode, FileNodeOps, Filesystem,
     FilesystemOps, Metadata, MetadataUpdate, NodeFlags, NodeOps, NodePermission, NodeType,
-    Reference, StatFs, VfsError, VfsResult, WeakDirEntry,
+    Reference, StatFs, VfsError, VfsResult, WeakDirEntry, path::MAX_NAME_LEN,
 };
 use hashbrown::HashMap;
-use kcore::vfs::dummy_stat_fs;
 use kpoll::{IoEvents, Pollable};
 use ksync::Mutex;
+use memaddr::PAGE_SIZE_4K;
 use slab::Slab;
 
+const TMPFS_MAGIC: u32 = 0x01021994;
+
 #[derive(PartialEq, Eq, Hash, Clone)]
 struct FileName(String);
 
@@ -95,8 +97,25 @@ impl FilesystemOps for MemoryFs {
         self.root.lock().clone().unwrap()
     }
 
+    /// Files live in memory: the blocks are the pages of the allocator, and
+    /// files are limited by nothing else.
     fn stat(&self) -> VfsResult<StatFs> {
-        Ok(dummy_stat_fs(0x01021994))
+        let allocator = kalloc::global_allocator();
+        let free = allocator.available_pages() as u64;
+        Ok(StatFs {
+            fs_type: TMPFS_MAGIC,
+            block_size: PAGE_SIZE_4K as _,
+            blocks: allocator.used_pages() as u64 + free,
+            blocks_free: free,
+            blocks_available: free,
+
+            file_count: 0,
+            free_file_count: 0,
+
+            name_length: MAX_NAME_LEN as _,
+            fragment_size: PAGE_SIZE_4K as _,
+            mount_flags: 0,
+        })
     }
 }
 
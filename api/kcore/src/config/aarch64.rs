@@ -16,6 +16,8 @@ pub const USER_SPACE_SIZE: usize = 0x7fff_ffff_f000;
 pub const USER_STACK_TOP: usize = 0x7fff_0000_0000;
 /// The size of the user stack.
 pub const USER_STACK_SIZE: usize = 0x8_0000;
+/// The highest address of the user stack for 32-bit (compat) programs.
+pub const COMPAT_USER_STACK_TOP: usize = 0xc000_0000;
 
 /// The lowest address of the user heap.
 pub const USER_HEAP_BASE: usize = 0x4000_0000;
@@ -12,7 +12,7 @@ use alloc::{
 use core::{
     future::poll_fn,
     ops::Deref,
-    sync::atomic::AtomicBool,
+    sync::atomic::{AtomicBool, Ordering},
     task::{Poll, Waker},
     time::Duration,
 };
@@ -20,9 +20,9 @@ use core::{
 use hashbrown::HashMap;
 use kerrno::KResult;
 use kspin::SpinNoIrq;
-use ksync::Mutex;
+use ksync::{Mutex, MutexGuard};
 use ktask::{
-    current,
+    KtaskRef, SchedPolicy, current,
     future::{self, block_on, interruptible},
 };
 use memaddr::VirtAddr;
@@ -31,7 +31,7 @@ use memspace::{
     backend::{Backend, SharedPages},
 };
 
-use crate::task::AsThread;
+use crate::task::{AsThread, get_task};
 
 /// Wait queue used by futex.
 #[derive(Default)]
@@ -137,6 +137,12 @@ impl FutexKey {
                         region: Ok(Arc::downgrade(backend.pages())),
                     };
                 }
+                Backend::Shm(shm) => {
+                    return Self::Shared {
+                        offset: shm.offset_of(VirtAddr::from_usize(address)),
+                        region: Err(shm.object().futex_key()),
+                    };
+                }
                 Backend::File(file) => {
                     return Self::Shared {
                         offset: address - area.start().as_usize(),
@@ -169,6 +175,9 @@ pub struct FutexEntry {
 
     /// Used by robust list, indicates if the owner of this futex is dead.
     pub owner_dead: AtomicBool,
+
+    /// The tasks waiting to take over this futex as a PI futex.
+    pi_waiters: Mutex<PiWaiters>,
 }
 
 impl FutexEntry {
@@ -176,7 +185,169 @@ impl FutexEntry {
         Self {
             wq: WaitQueue::new(),
             owner_dead: AtomicBool::new(false),
+            pi_waiters: Mutex::new(PiWaiters(Vec::new())),
+        }
+    }
+
+    /// Locks the waiters of the PI futex, which is to be held while its word
+    /// is updated.
+    pub fn pi_waiters(&self) -> MutexGuard<'_, PiWaiters> {
+        self.pi_waiters.lock()
+    }
+}
+
+/// A task waiting to take over a PI futex.
+pub struct PiWaiter {
+    tid: u32,
+    /// The real-time priority of the task, lent to the owner.
+    prio: u8,
+    /// Set once the futex is handed over to the task.
+    granted: AtomicBool,
+    waker: SpinNoIrq<Option<Waker>>,
+}
+
+impl PiWaiter {
+    /// Waits until the futex is handed over to the task, for at most
+    /// `timeout`.
+    pub fn wait(&self, timeout: Option<Duration>) -> KResult<()> {
+        block_on(interruptible(future::timeout(
+            timeout,
+            poll_fn(|cx| {
+                let mut waker = self.waker.lock();
+                if self.granted.load(Ordering::Acquire) {
+                    Poll::Ready(())
+                } else {
+                    *waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }),
+        )))??;
+        Ok(())
+    }
+
+    fn grant(&self) {
+        let mut waker = self.waker.lock();
+        self.granted.store(true, Ordering::Release);
+        if let Some(waker) = waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The tasks waiting for the owner of a PI futex, which runs with the
+/// highest real-time priority of theirs.
+pub struct PiWaiters(Vec<Arc<PiWaiter>>);
+
+impl PiWaiters {
+    /// Returns the number of waiters.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Checks if there is no waiter.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Queues the current task, `tid`, for `owner` to hand the futex over,
+    /// and lends `owner` its real-time priority.
+    pub fn add(&mut self, tid: u32, owner: &KtaskRef) -> Arc<PiWaiter> {
+        let waiter = Arc::new(PiWaiter {
+            tid,
+            prio: current().sched_policy().rt_priority(),
+            granted: AtomicBool::new(false),
+            waker: SpinNoIrq::new(None),
+        });
+        self.0.push(waiter.clone());
+        lend_priority(owner, self.id(), self.priority());
+        waiter
+    }
+
+    /// Takes `waiter` out, which gave up waiting, or returns `false` if the
+    /// futex has been handed over to it meanwhile.
+    ///
+    /// The owner keeps the priority lent until it unlocks the futex.
+    pub fn remove(&mut self, waiter: &Arc<PiWaiter>) -> bool {
+        let len = self.0.len();
+        self.0.retain(|w| !Arc::ptr_eq(w, waiter));
+        self.0.len() < len
+    }
+
+    /// Returns the TID of the waiter to hand the futex over to: the first
+    /// one of the highest priority.
+    pub fn next_owner(&self) -> Option<u32> {
+        self.next().map(|(_, waiter)| waiter.tid)
+    }
+
+    /// Hands the futex over from the current task to the waiter returned by
+    /// [`next_owner`](Self::next_owner), once set as the owner in the futex
+    /// word, and lends it the priority of the waiters left.
+    pub fn hand_over(&mut self) {
+        self.unboost();
+        let Some((index, _)) = self.next() else {
+            return;
+        };
+        let waiter = self.0.remove(index);
+        if let Ok(owner) = get_task(waiter.tid) {
+            lend_priority(&owner, self.id(), self.priority());
         }
+        waiter.grant();
+    }
+
+    /// Gives back the priority lent to the current task by the waiters, as
+    /// it no longer owns the futex.
+    pub fn unboost(&self) {
+        lend_priority(&current().clone(), self.id(), 0);
+    }
+
+    fn next(&self) -> Option<(usize, &Arc<PiWaiter>)> {
+        // The last maximum is taken, hence the reverse.
+        self.0
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, waiter)| waiter.prio)
+    }
+
+    /// Returns the highest priority of the waiters.
+    fn priority(&self) -> u8 {
+        self.0.iter().map(|waiter| waiter.prio).max().unwrap_or(0)
+    }
+
+    /// Identifies the futex among those lending priority to an owner.
+    fn id(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+/// The real-time priorities lent to a thread by the waiters of the PI
+/// futexes it owns.
+#[derive(Default)]
+pub(crate) struct PiBoost {
+    /// The scheduling policy of the thread while it is boosted.
+    own: Option<SchedPolicy>,
+    /// The highest priority of the waiters of each futex.
+    lent: Vec<(usize, u8)>,
+}
+
+/// Records that the waiters of the PI futex `id` lend `prio` to `owner`,
+/// and has it run with the highest priority lent, if above its own.
+fn lend_priority(owner: &KtaskRef, id: usize, prio: u8) {
+    let Some(thr) = owner.try_as_thread() else {
+        return;
+    };
+    let mut boost = thr.pi_boost.lock();
+    boost.lent.retain(|(futex, _)| *futex != id);
+    if prio > 0 {
+        boost.lent.push((id, prio));
+    }
+    let lent = boost.lent.iter().map(|(_, prio)| *prio).max().unwrap_or(0);
+    let own = boost.own.unwrap_or_else(|| owner.sched_policy());
+    if lent > own.rt_priority() {
+        boost.own = Some(own);
+        ktask::set_task_sched_policy(owner, SchedPolicy::Fifo(lent));
+    } else if boost.own.take().is_some() {
+        ktask::set_task_sched_policy(owner, own);
     }
 }
 
@@ -280,4 +451,22 @@ pub mod tests_futex {
         assert!(table.get(&key).is_none());
         assert!(table.is_empty());
     }
+
+    #[def_test]
+    fn test_futex_pi_next_owner() {
+        let entry = FutexEntry::new();
+        let mut waiters = entry.pi_waiters();
+        assert_eq!(waiters.next_owner(), None);
+        for (tid, prio) in [(1, 0), (2, 10), (3, 10), (4, 5)] {
+            waiters.0.push(Arc::new(PiWaiter {
+                tid,
+                prio,
+                granted: AtomicBool::new(false),
+                waker: SpinNoIrq::new(None),
+            }));
+        }
+        // The first of the highest priority
+        assert_eq!(waiters.next_owner(), Some(2));
+        assert_eq!(waiters.priority(), 10);
+    }
 }
@@ -5,11 +5,20 @@
 //! User address space management.
 
 use alloc::{borrow::ToOwned, string::String, vec, vec::Vec};
-use core::{ffi::CStr, hint::unlikely, iter, mem::MaybeUninit};
+use core::{
+    ffi::CStr,
+    hint::unlikely,
+    iter,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 use extern_trait::extern_trait;
 use fs_ng_vfs::Location;
-use kernel_elf_parser::{AuxEntry, ELFHeaders, ELFHeadersBuilder, ELFParser, app_stack_region};
+use kernel_elf_parser::{
+    AuxEntry, AuxType, ELFHeaders, ELFHeadersBuilder, ELFParser, app_stack_region,
+    app_stack_region_compat,
+};
 use kerrno::{KError, KResult};
 use kfs::{CachedFile, FS_CONTEXT, FileBackend};
 use khal::{
@@ -24,6 +33,7 @@ use memaddr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr};
 use memspace::{AddrSpace, backend::Backend};
 use osvm::{MemError, MemResult, VirtMemIo};
 use ouroboros::self_referencing;
+use xmas_elf::header::Class;
 
 use crate::{
     config::{USER_SPACE_BASE, USER_SPACE_SIZE},
@@ -137,6 +147,50 @@ fn map_elf<'a>(
     Ok(elf_parser)
 }
 
+/// The machine of 32-bit programs run in compat mode.
+#[cfg(all(feature = "compat", target_arch = "x86_64"))]
+const COMPAT_MACHINE: xmas_elf::header::Machine = xmas_elf::header::Machine::X86;
+#[cfg(all(feature = "compat", target_arch = "aarch64"))]
+const COMPAT_MACHINE: xmas_elf::header::Machine = xmas_elf::header::Machine::Arm;
+
+/// Tells whether `elf` is a 32-bit program to be run in compat mode.
+///
+/// 32-bit programs for any other machine are rejected.
+fn elf_compat(elf: &ELFHeaders) -> KResult<bool> {
+    match elf.header.pt1.class() {
+        Class::SixtyFour => Ok(false),
+        #[cfg(all(
+            feature = "compat",
+            any(target_arch = "x86_64", target_arch = "aarch64")
+        ))]
+        Class::ThirtyTwo if elf.header.pt2.machine().as_machine() == COMPAT_MACHINE => Ok(true),
+        _ => {
+            debug!(
+                "Unsupported ELF class {:?} for {:?}",
+                elf.header.pt1.class(),
+                elf.header.pt2.machine().as_machine()
+            );
+            Err(KError::InvalidExecutable)
+        }
+    }
+}
+
+/// The `AT_HWCAP` of 32-bit programs.
+#[cfg(feature = "compat")]
+fn compat_hwcap() -> usize {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "aarch64")] {
+            // HALF | THUMB | FAST_MULT | VFP | EDSP | NEON | VFPv3 | TLS |
+            // VFPv4 | IDIVA | IDIVT | VFPD32 | LPAE, which every AArch32 EL0
+            // with FP/SIMD provides.
+            0x001f_b0d6
+        } else {
+            // i386 programs see the same CPUID leaf 1 flags.
+            khal::cpuinfo::cpu_info().hwcap()
+        }
+    }
+}
+
 fn map_elf_error(err: &'static str) -> KError {
     debug!("Failed to parse ELF file: {err}");
     KError::InvalidExecutable
@@ -178,7 +232,7 @@ impl ElfCacheEntry {
 
 struct ElfLoader(LruCache<ElfCacheEntry, 32>);
 
-type LoadResult = Result<(VirtAddr, Vec<AuxEntry>), Vec<u8>>;
+type LoadResult = Result<(VirtAddr, Vec<AuxEntry>, bool), Vec<u8>>;
 
 impl ElfLoader {
     const fn new() -> Self {
@@ -203,6 +257,7 @@ impl ElfLoader {
         map_trampoline(uspace)?;
 
         let entry = self.0.peek_mru().unwrap();
+        let compat = elf_compat(entry.borrow_elf())?;
         let ldso = if let Some(header) = entry
             .borrow_elf()
             .ph
@@ -234,6 +289,9 @@ impl ElfLoader {
             let mut iter = self.0.items();
             let ldso = iter.next().unwrap();
             let elf = iter.next().unwrap();
+            if elf_compat(ldso.borrow_elf())? != compat {
+                return Err(KError::InvalidExecutable);
+            }
             (elf, Some(ldso))
         } else {
             (entry, None)
@@ -248,11 +306,22 @@ impl ElfLoader {
             ldso.as_ref()
                 .map_or_else(|| elf.entry(), |ldso| ldso.entry()),
         );
+        // User programs pick their crypto and string routines from AT_HWCAP
+        #[cfg(feature = "compat")]
+        let hwcap = if compat {
+            compat_hwcap()
+        } else {
+            khal::cpuinfo::cpu_info().hwcap()
+        };
+        #[cfg(not(feature = "compat"))]
+        let hwcap = khal::cpuinfo::cpu_info().hwcap();
+        let hwcap = AuxEntry::new(AuxType::HWCAP, hwcap);
         let auxv = elf
             .aux_vector(PAGE_SIZE_4K, ldso.map(|elf| elf.base()))
+            .chain([hwcap])
             .collect::<Vec<_>>();
 
-        Ok(Ok((entry, auxv)))
+        Ok(Ok((entry, auxv, compat)))
     }
 }
 
@@ -272,16 +341,20 @@ pub fn clear_elf_cache() {
 /// - `args`: The arguments of the user app. The first argument is the path of
 ///   the user app.
 /// - `envs`: The environment variables of the user app.
+/// - `ustack_size`: The size of the user stack, see
+///   [`Rlimits::stack_size`](crate::resources::Rlimits::stack_size).
 ///
 /// # Returns
 /// - The entry point of the user app.
 /// - The stack pointer of the user app.
+/// - Whether the user app is a 32-bit program, to be run in compat mode.
 pub fn load_user_app(
     uspace: &mut AddrSpace,
     path: Option<&str>,
     args: &[String],
     envs: &[String],
-) -> KResult<(VirtAddr, VirtAddr)> {
+    ustack_size: usize,
+) -> KResult<(VirtAddr, VirtAddr, bool)> {
     let path = path
         .or_else(|| args.first().map(String::as_str))
         .ok_or(KError::InvalidInput)?;
@@ -291,11 +364,11 @@ pub fn load_user_app(
         let new_args: Vec<String> = iter::once("/bin/sh".to_owned())
             .chain(args.iter().cloned())
             .collect();
-        return load_user_app(uspace, None, &new_args, envs);
+        return load_user_app(uspace, None, &new_args, envs, ustack_size);
     }
 
-    let (entry, auxv) = match { ELF_LOADER.lock().load(uspace, path)? } {
-        Ok((entry, auxv)) => (entry, auxv),
+    let (entry, auxv, compat) = match { ELF_LOADER.lock().load(uspace, path)? } {
+        Ok(loaded) => loaded,
         Err(data) => {
             if data.starts_with(b"#!") {
                 let head = &data[2..data.len().min(256)];
@@ -309,14 +382,32 @@ pub fn load_user_app(
                     .chain(iter::once(path.to_owned()))
                     .chain(args.iter().skip(1).cloned())
                     .collect();
-                return load_user_app(uspace, None, &new_args, envs);
+                return load_user_app(uspace, None, &new_args, envs, ustack_size);
             }
             return Err(KError::InvalidExecutable);
         }
     };
 
-    let ustack_top = VirtAddr::from_usize(crate::config::USER_STACK_TOP);
-    let ustack_size = crate::config::USER_STACK_SIZE;
+    #[cfg(feature = "compat")]
+    let ustack_top = if compat {
+        crate::config::COMPAT_USER_STACK_TOP
+    } else {
+        crate::config::USER_STACK_TOP
+    };
+    #[cfg(not(feature = "compat"))]
+    let ustack_top = crate::config::USER_STACK_TOP;
+    let ustack_top = VirtAddr::from_usize(ustack_top);
+    let stack_data = if compat {
+        app_stack_region_compat(args, envs, &auxv, ustack_top.into())
+    } else {
+        app_stack_region(args, envs, &auxv, ustack_top.into())
+    };
+    // The stack grows on demand within `RLIMIT_STACK`, which the arguments
+    // must leave room in
+    if stack_data.len() > ustack_size / 4 {
+        return Err(KError::ArgumentListTooLong);
+    }
+
     let ustack_start = ustack_top - ustack_size;
     debug!("Mapping user stack: {ustack_start:#x?} -> {ustack_top:#x?}");
 
@@ -327,8 +418,6 @@ pub fn load_user_app(
         false,
         Backend::new_alloc(ustack_start, PageSize::Size4K),
     )?;
-
-    let stack_data = app_stack_region(args, envs, &auxv, ustack_top.into());
     let user_sp = ustack_top - stack_data.len();
     let user_sp_aligned = user_sp.align_down_4k();
     uspace.populate_area(
@@ -344,11 +433,11 @@ pub fn load_user_app(
         heap_start,
         heap_size,
         MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
-        true,
+        false,
         Backend::new_alloc(heap_start, PageSize::Size4K),
     )?;
 
-    Ok((entry, user_sp))
+    Ok((entry, user_sp, compat))
 }
 
 /// Enables scoped access into user memory, allowing page faults to occur inside
@@ -379,6 +468,31 @@ pub fn check_access(start: usize, len: usize) -> MemResult {
     }
 }
 
+/// Atomically replaces the `u32` at `addr` in the current address space with
+/// `new` if it equals `old`, returning the value it had either way.
+///
+/// The page is populated for writing first, and stays mapped while the
+/// address space is locked.
+pub fn cmpxchg_user_u32(addr: usize, old: u32, new: u32) -> KResult<u32> {
+    if !addr.is_multiple_of(size_of::<u32>()) {
+        return Err(KError::InvalidInput);
+    }
+    check_access(addr, size_of::<u32>())?;
+    let curr = current();
+    let mut aspace = curr.as_thread().proc_data.aspace.lock();
+    aspace.populate_area(
+        VirtAddr::from_usize(addr).align_down_4k(),
+        PAGE_SIZE_4K,
+        MappingFlags::READ | MappingFlags::WRITE,
+    )?;
+    let word = unsafe { &*(addr as *const AtomicU32) };
+    Ok(access_user_memory(|| {
+        match word.compare_exchange(old, new, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(value) | Err(value) => value,
+        }
+    }))
+}
+
 #[extern_trait]
 unsafe impl VirtMemIo for Vm {
     fn new() -> Self {
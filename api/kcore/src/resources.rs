@@ -6,13 +6,20 @@
 
 use core::ops::{Index, IndexMut};
 
-use linux_raw_sys::general::{RLIM_NLIMITS, RLIMIT_NOFILE, RLIMIT_STACK};
+use linux_raw_sys::general::{RLIM_NLIMITS, RLIMIT_AS, RLIMIT_NOFILE, RLIMIT_STACK};
+use memaddr::PAGE_SIZE_4K;
 
 /// The maximum number of open files
 pub const FILE_LIMIT: usize = 1024;
 
+/// No limit on the resource
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// The largest user stack mapped at exec, whatever `RLIMIT_STACK` says
+pub const MAX_USER_STACK_SIZE: usize = 0x1000_0000;
+
 /// The limit for a specific resource
-#[derive(Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Rlimit {
     /// The current limit for the resource (soft)
     pub current: u64,
@@ -28,6 +35,11 @@ impl Rlimit {
             max: hard,
         }
     }
+
+    /// Whether the soft limit is [`RLIM_INFINITY`].
+    pub fn is_infinite(&self) -> bool {
+        self.current == RLIM_INFINITY
+    }
 }
 
 impl From<u64> for Rlimit {
@@ -40,11 +52,28 @@ impl From<u64> for Rlimit {
 }
 
 /// Process resource limits
+#[derive(Debug, Clone)]
 pub struct Rlimits([Rlimit; RLIM_NLIMITS as usize]);
 
+impl Rlimits {
+    /// The size of the user stack mapped at exec, from the soft
+    /// `RLIMIT_STACK`, up to [`MAX_USER_STACK_SIZE`].
+    pub fn stack_size(&self) -> usize {
+        let limit = self[RLIMIT_STACK].current.min(MAX_USER_STACK_SIZE as u64) as usize;
+        (limit & !(PAGE_SIZE_4K - 1)).max(PAGE_SIZE_4K)
+    }
+
+    /// The soft `RLIMIT_AS`, for [`AddrSpace::set_size_limit`].
+    ///
+    /// [`AddrSpace::set_size_limit`]: memspace::AddrSpace::set_size_limit
+    pub fn aspace_size(&self) -> usize {
+        self[RLIMIT_AS].current.try_into().unwrap_or(usize::MAX)
+    }
+}
+
 impl Default for Rlimits {
     fn default() -> Self {
-        let mut result = Self(Default::default());
+        let mut result = Self([RLIM_INFINITY.into(); RLIM_NLIMITS as usize]);
         result[RLIMIT_STACK] = (crate::config::USER_STACK_SIZE as u64).into();
         result[RLIMIT_NOFILE] = (FILE_LIMIT as u64).into();
         result
@@ -94,5 +123,19 @@ pub mod tests_resources {
             crate::config::USER_STACK_SIZE as u64
         );
         assert_eq!(limits[RLIMIT_NOFILE].current, FILE_LIMIT as u64);
+        assert!(limits[RLIMIT_AS].is_infinite());
+        assert_eq!(limits.aspace_size(), usize::MAX);
+    }
+
+    #[def_test]
+    fn test_rlimits_stack_size() {
+        let mut limits = Rlimits::default();
+        assert_eq!(limits.stack_size(), crate::config::USER_STACK_SIZE);
+        limits[RLIMIT_STACK] = Rlimit::new(PAGE_SIZE_4K as u64 * 3 + 1, RLIM_INFINITY);
+        assert_eq!(limits.stack_size(), PAGE_SIZE_4K * 3);
+        limits[RLIMIT_STACK] = RLIM_INFINITY.into();
+        assert_eq!(limits.stack_size(), MAX_USER_STACK_SIZE);
+        limits[RLIMIT_STACK] = 0.into();
+        assert_eq!(limits.stack_size(), PAGE_SIZE_4K);
     }
 }
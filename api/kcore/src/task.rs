@@ -17,12 +17,13 @@ use core::any::Any;
 use core::{
     cell::RefCell,
     ops::Deref,
-    sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicUsize, Ordering},
+    sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering},
 };
 
 use extern_trait::extern_trait;
 use hashbrown::HashMap;
 use kerrno::{KError, KResult};
+use khal::time::NANOS_PER_SEC;
 use kpoll::PollSet;
 use kprocess::{Pid, Process, ProcessGroup, Session};
 use ksignal::{
@@ -32,13 +33,14 @@ use ksignal::{
 use ksync::{Mutex, RwLock, spin::SpinNoIrq};
 use ktask::{KtaskRef, TaskExt, TaskInner, WeakKtaskRef, current};
 use lazy_static::lazy_static;
+use linux_raw_sys::general::RLIMIT_CPU;
 use memspace::AddrSpace;
 use scope_local::{ActiveScope, Scope};
 use weak_map::WeakMap;
 
 pub use self::stat::TaskStat;
 use crate::{
-    futex::{FutexKey, FutexTable},
+    futex::{FutexKey, FutexTable, PiBoost},
     resources::Rlimits,
     time::{TimeManager, TimerState},
 };
@@ -97,6 +99,9 @@ pub struct Thread {
     /// Ready to exit
     exit: AtomicBool,
 
+    /// The priorities lent by the waiters of the PI futexes it owns.
+    pub(crate) pi_boost: SpinNoIrq<PiBoost>,
+
     /// Indicates whether the thread is currently accessing user memory.
     accessing_user_memory: AtomicBool,
 
@@ -116,6 +121,7 @@ impl Thread {
             time: AssumeSync(RefCell::new(TimeManager::new())),
             oom_score_adj: AtomicI32::new(200),
             exit: AtomicBool::new(false),
+            pi_boost: SpinNoIrq::new(PiBoost::default()),
             accessing_user_memory: AtomicBool::new(false),
             #[cfg(feature = "tee")]
             tee_session_ctx: Mutex::new(None),
@@ -191,12 +197,26 @@ unsafe impl TaskExt for Box<Thread> {
         let scope = self.proc_data.scope.read();
         unsafe { ActiveScope::set(&scope) };
         core::mem::forget(scope);
+        // Busy when switched out in the middle of a poll, which then accounts
+        // for the time off the CPU as well
+        if let Ok(mut time) = self.time.try_borrow_mut() {
+            time.switch_in();
+        }
     }
 
     fn on_leave(&self) {
+        if let Ok(mut time) = self.time.try_borrow_mut() {
+            time.switch_out();
+        }
         ActiveScope::set_global();
         unsafe { self.proc_data.scope.force_unlock_read() };
     }
+
+    fn on_tick(&self) {
+        if let Ok(mut time) = self.time.try_borrow_mut() {
+            time.tick();
+        }
+    }
 }
 
 /// Helper trait to access the thread from a task.
@@ -235,6 +255,10 @@ pub struct ProcessData {
 
     /// The resource limits
     pub rlim: RwLock<Rlimits>,
+    /// The CPU time used by the threads, in nanoseconds
+    cpu_time_ns: AtomicU64,
+    /// The CPU time, in seconds, from which `SIGXCPU` is sent again
+    next_xcpu_secs: AtomicU64,
 
     /// The child exit wait event
     pub child_exit_event: Arc<PollSet>,
@@ -251,6 +275,9 @@ pub struct ProcessData {
 
     /// The default mask for file permissions.
     umask: AtomicU32,
+
+    /// Whether the process runs a 32-bit program in compat mode.
+    compat: AtomicBool,
 }
 
 impl ProcessData {
@@ -272,6 +299,8 @@ impl ProcessData {
             heap_top: AtomicUsize::new(crate::config::USER_HEAP_BASE),
 
             rlim: RwLock::default(),
+            cpu_time_ns: AtomicU64::new(0),
+            next_xcpu_secs: AtomicU64::new(0),
 
             child_exit_event: Arc::default(),
             exit_event: Arc::default(),
@@ -285,6 +314,8 @@ impl ProcessData {
             futex_table: Arc::new(FutexTable::new()),
 
             umask: AtomicU32::new(0o022),
+
+            compat: AtomicBool::new(false),
         })
     }
 
@@ -298,6 +329,33 @@ impl ProcessData {
         self.heap_top.store(top, Ordering::Release)
     }
 
+    /// Accounts `delta_ns` of CPU time to the process, and returns the signal
+    /// `RLIMIT_CPU` calls for, if any.
+    ///
+    /// Past the soft limit, `SIGXCPU` is due once per second of CPU time;
+    /// past the hard limit, `SIGKILL`.
+    pub fn account_cpu_time(&self, delta_ns: u64) -> Option<Signo> {
+        if delta_ns == 0 {
+            return None;
+        }
+        let total_ns = self.cpu_time_ns.fetch_add(delta_ns, Ordering::Relaxed) + delta_ns;
+        let secs = total_ns / NANOS_PER_SEC;
+        let limit = self.rlim.read()[RLIMIT_CPU];
+        if secs >= limit.max {
+            return Some(Signo::SIGKILL);
+        }
+        if secs < limit.current {
+            return None;
+        }
+        let next = self.next_xcpu_secs.load(Ordering::Relaxed);
+        (secs >= next
+            && self
+                .next_xcpu_secs
+                .compare_exchange(next, secs + 1, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok())
+        .then_some(Signo::SIGXCPU)
+    }
+
     /// Linux manual: A "clone" child is one which delivers no signal, or a
     /// signal other than SIGCHLD to its parent upon termination.
     pub fn is_clone_child(&self) -> bool {
@@ -318,6 +376,16 @@ impl ProcessData {
         }
     }
 
+    /// Whether the process runs a 32-bit program in compat mode.
+    pub fn is_compat(&self) -> bool {
+        self.compat.load(Ordering::Acquire)
+    }
+
+    /// Set whether the process runs a 32-bit program in compat mode.
+    pub fn set_compat(&self, compat: bool) {
+        self.compat.store(compat, Ordering::Release)
+    }
+
     /// Get the umask.
     pub fn umask(&self) -> u32 {
         self.umask.load(Ordering::SeqCst)
@@ -463,9 +531,7 @@ pub fn poll_timer(task: &TaskInner) {
         // reentrant borrow, likely IRQ
         return;
     };
-    time.poll(|signo| {
-        send_signal_thread_inner(task, thr, SignalInfo::new_kernel(signo));
-    });
+    poll_time(task, thr, &mut time);
 }
 
 /// Sets the timer state.
@@ -477,10 +543,19 @@ pub fn set_timer_state(task: &TaskInner, state: TimerState) {
         // reentrant borrow, likely IRQ
         return;
     };
-    time.poll(|signo| {
+    poll_time(task, thr, &mut time);
+    time.set_state(state);
+}
+
+/// Updates the timers and the CPU time of the process, enforcing
+/// `RLIMIT_CPU`.
+fn poll_time(task: &TaskInner, thr: &Thread, time: &mut TimeManager) {
+    let cpu_ns = time.poll(|signo| {
         send_signal_thread_inner(task, thr, SignalInfo::new_kernel(signo));
     });
-    time.set_state(state);
+    if let Some(signo) = thr.proc_data.account_cpu_time(cpu_ns as u64) {
+        send_signal_thread_inner(task, thr, SignalInfo::new_kernel(signo));
+    }
 }
 
 fn send_signal_thread_inner(task: &TaskInner, thr: &Thread, sig: SignalInfo) {
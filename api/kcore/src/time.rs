@@ -84,14 +84,13 @@ struct ITimer {
 
 impl ITimer {
     pub fn new(interval_ns: usize, remained_ns: usize) -> Self {
-        let result = Self {
+        Self {
             interval_ns,
             remained_ns,
-        };
-        result.renew_timer();
-        result
+        }
     }
 
+    /// Advances the timer by `delta` nanoseconds, returning whether it fired.
     pub fn update(&mut self, delta: usize) -> bool {
         if self.remained_ns == 0 {
             return false;
@@ -101,20 +100,17 @@ impl ITimer {
             false
         } else {
             self.remained_ns = self.interval_ns;
-            self.renew_timer();
             true
         }
     }
 
-    pub fn renew_timer(&self) {
+    /// Queues an alarm for `task` at the wall-time deadline, for real timers.
+    pub fn renew_timer(&self, task: WeakKtaskRef) {
         if self.remained_ns > 0 {
             let deadline = wall_time() + Duration::from_nanos(self.remained_ns as u64);
             let mut guard = ALARM_LIST.lock();
             let should_wake = guard.peek().is_none_or(|it| it.deadline > deadline);
-            guard.push(Entry {
-                deadline,
-                task: Arc::downgrade(&current()),
-            });
+            guard.push(Entry { deadline, task });
             drop(guard);
             if should_wake {
                 EVENT_NEW_TIMER.notify(1);
@@ -134,14 +130,26 @@ pub enum TimerState {
     Kernel,
 }
 
-// TODO(mivik): preempting does not change the timer state currently
 /// A manager for time-related operations.
+///
+/// The CPU time of the task is accounted on every scheduler tick and context
+/// switch, so that the virtual and profiling timers follow what it really
+/// ran. Those run with IRQs disabled and cannot send signals: the timers that
+/// fire there do so at the next [`poll`](Self::poll).
 pub struct TimeManager {
     utime_ns: usize,
     stime_ns: usize,
     last_wall_ns: usize,
     state: TimerState,
     itimers: [ITimer; 3],
+    /// The task is switched out, using no CPU time.
+    off_cpu: bool,
+    /// CPU time accounted since the last poll.
+    unpolled_cpu_ns: usize,
+    /// Timers fired since the last poll, one bit per [`ITimerType`].
+    fired: u8,
+    /// The task the alarms of the real timer are for.
+    owner: WeakKtaskRef,
 }
 
 impl Default for TimeManager {
@@ -158,6 +166,10 @@ impl TimeManager {
             last_wall_ns: 0,
             state: TimerState::None,
             itimers: Default::default(),
+            off_cpu: false,
+            unpolled_cpu_ns: 0,
+            fired: 0,
+            owner: WeakKtaskRef::new(),
         }
     }
 
@@ -168,27 +180,64 @@ impl TimeManager {
         (utime, stime)
     }
 
-    /// Polls the time manager to update the timers and emit signals if
-    /// necessary.
-    pub fn poll(&mut self, emitter: impl Fn(Signo)) {
+    /// Accounts the time since the last update and advances the timers.
+    fn update(&mut self) {
         let now_ns = monotonic_time_nanos() as usize;
         let delta = now_ns - self.last_wall_ns;
         match self.state {
+            _ if self.off_cpu => {}
             TimerState::User => {
                 self.utime_ns += delta;
-                self.update_itimer(ITimerType::Virtual, delta, &emitter);
-                self.update_itimer(ITimerType::Prof, delta, &emitter);
+                self.unpolled_cpu_ns += delta;
+                self.update_itimer(ITimerType::Virtual, delta);
+                self.update_itimer(ITimerType::Prof, delta);
             }
             TimerState::Kernel => {
                 self.stime_ns += delta;
-                self.update_itimer(ITimerType::Prof, delta, &emitter);
+                self.unpolled_cpu_ns += delta;
+                self.update_itimer(ITimerType::Prof, delta);
             }
             TimerState::None => {}
         }
-        self.update_itimer(ITimerType::Real, delta, &emitter);
+        self.update_itimer(ITimerType::Real, delta);
         self.last_wall_ns = now_ns;
     }
 
+    /// Polls the time manager to update the timers and emit signals if
+    /// necessary.
+    ///
+    /// Returns the CPU time accounted since the last poll, in nanoseconds.
+    pub fn poll(&mut self, emitter: impl Fn(Signo)) -> usize {
+        self.update();
+        for ty in [ITimerType::Real, ITimerType::Virtual, ITimerType::Prof] {
+            if self.fired & (1 << ty as u8) != 0 {
+                if ty == ITimerType::Real {
+                    self.itimers[ty as usize].renew_timer(self.owner.clone());
+                }
+                emitter(ty.signo());
+            }
+        }
+        self.fired = 0;
+        mem::take(&mut self.unpolled_cpu_ns)
+    }
+
+    /// Accounts the CPU time up to a scheduler tick.
+    pub fn tick(&mut self) {
+        self.update();
+    }
+
+    /// Stops accounting CPU time as the task is switched out.
+    pub fn switch_out(&mut self) {
+        self.update();
+        self.off_cpu = true;
+    }
+
+    /// Resumes accounting CPU time as the task is switched in.
+    pub fn switch_in(&mut self) {
+        self.update();
+        self.off_cpu = false;
+    }
+
     /// Updates the timer state.
     pub fn set_state(&mut self, state: TimerState) {
         self.state = state;
@@ -202,10 +251,16 @@ impl TimeManager {
         interval_ns: usize,
         remained_ns: usize,
     ) -> (TimeValue, TimeValue) {
-        let old = mem::replace(
-            &mut self.itimers[ty as usize],
-            ITimer::new(interval_ns, remained_ns),
-        );
+        // Up to date, for the old timer to read what is left of it
+        self.update();
+        self.fired &= !(1 << ty as u8);
+        let itimer = ITimer::new(interval_ns, remained_ns);
+        if ty == ITimerType::Real {
+            // Set by the task itself, unlike the polls from the alarm task
+            self.owner = Arc::downgrade(&current());
+            itimer.renew_timer(self.owner.clone());
+        }
+        let old = mem::replace(&mut self.itimers[ty as usize], itimer);
         (
             time_value_from_nanos(old.interval_ns),
             time_value_from_nanos(old.remained_ns),
@@ -221,9 +276,9 @@ impl TimeManager {
         )
     }
 
-    fn update_itimer(&mut self, ty: ITimerType, delta: usize, emitter: impl Fn(Signo)) {
+    fn update_itimer(&mut self, ty: ITimerType, delta: usize) {
         if self.itimers[ty as usize].update(delta) {
-            emitter(ty.signo());
+            self.fired |= 1 << ty as u8;
         }
     }
 }
@@ -287,7 +342,7 @@ pub mod tests_time {
     use ksignal::Signo;
     use unittest::def_test;
 
-    use super::{ITimerType, TimeManager};
+    use super::{ITimer, ITimerType, TimeManager};
 
     #[def_test]
     fn test_itimer_signo() {
@@ -304,6 +359,21 @@ pub mod tests_time {
         assert_eq!(ITimerType::from_repr(3), None);
     }
 
+    #[def_test]
+    fn test_itimer_update_reloads() {
+        let mut timer = ITimer::new(10, 25);
+        assert!(!timer.update(10));
+        assert!(!timer.update(10));
+        assert!(timer.update(10));
+        assert!(!timer.update(9));
+        assert!(timer.update(1));
+
+        // One-shot timers stay disarmed
+        let mut timer = ITimer::new(0, 5);
+        assert!(timer.update(5));
+        assert!(!timer.update(100));
+    }
+
     #[def_test]
     fn test_timemanager_default_output() {
         let tm = TimeManager::new();
@@ -15,6 +15,8 @@ pub mod arm;
 pub mod loongarch64;
 #[cfg(any(target_arch = "riscv64", feature = "riscv64"))]
 pub mod riscv64;
+#[cfg(any(target_arch = "x86", feature = "x86"))]
+pub mod x86;
 #[cfg(any(target_arch = "x86_64", feature = "x86_64"))]
 pub mod x86_64;
 #[cfg(all(any(target_arch = "x86_64", feature = "x86_64"), feature = "tee"))]
@@ -30,6 +32,8 @@ pub use arm::*;
 pub use loongarch64::*;
 #[cfg(target_arch = "riscv64")]
 pub use riscv64::*;
+#[cfg(target_arch = "x86")]
+pub use x86::*;
 #[cfg(all(target_arch = "x86_64", not(feature = "tee")))]
 pub use x86_64::*;
 #[cfg(all(target_arch = "x86_64", feature = "tee"))]
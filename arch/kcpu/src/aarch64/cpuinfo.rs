@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! CPU identification from `MIDR_EL1` and the ID registers.
+
+use core::{arch::asm, fmt::Write};
+
+pub use crate::cpuinfo_common::{CacheInfo, CacheKind, CpuInfo, Feature, Features};
+
+macro_rules! read_sysreg {
+    ($reg:literal) => {{
+        let value: u64;
+        unsafe { asm!(concat!("mrs {}, ", $reg), out(reg) value, options(nomem, nostack)) };
+        value
+    }};
+}
+
+/// Returns the 4-bit field of an ID register at `shift`.
+fn field(reg: u64, shift: u32) -> u64 {
+    (reg >> shift) & 0xf
+}
+
+fn implementer_name(implementer: u64) -> &'static str {
+    match implementer {
+        0x41 => "ARM",
+        0x42 => "Broadcom",
+        0x43 => "Cavium",
+        0x48 => "HiSilicon",
+        0x4e => "NVIDIA",
+        0x51 => "Qualcomm",
+        0x61 => "Apple",
+        0x70 => "Phytium",
+        0xc0 => "Ampere",
+        _ => "unknown",
+    }
+}
+
+fn arm_part_name(part: u64) -> Option<&'static str> {
+    Some(match part {
+        0xd03 => "Cortex-A53",
+        0xd04 => "Cortex-A35",
+        0xd05 => "Cortex-A55",
+        0xd07 => "Cortex-A57",
+        0xd08 => "Cortex-A72",
+        0xd09 => "Cortex-A73",
+        0xd0a => "Cortex-A75",
+        0xd0b => "Cortex-A76",
+        0xd0c => "Neoverse-N1",
+        0xd0d => "Cortex-A77",
+        0xd40 => "Neoverse-V1",
+        0xd41 => "Cortex-A78",
+        0xd46 => "Cortex-A510",
+        0xd47 => "Cortex-A710",
+        0xd49 => "Neoverse-N2",
+        0xd4f => "Neoverse-V2",
+        _ => return None,
+    })
+}
+
+/// Reads the caches from `CLIDR_EL1`, selecting each in `CSSELR_EL1` to
+/// read its geometry from `CCSIDR_EL1`.
+fn detect_caches(info: &mut CpuInfo) {
+    let clidr = read_sysreg!("clidr_el1");
+    let ccidx = field(read_sysreg!("id_aa64mmfr2_el1"), 20) != 0;
+    for level in 0..7u64 {
+        let kinds: &[(CacheKind, u64)] = match (clidr >> (level * 3)) & 0b111 {
+            0b001 => &[(CacheKind::Instruction, 1)],
+            0b010 => &[(CacheKind::Data, 0)],
+            0b011 => &[(CacheKind::Data, 0), (CacheKind::Instruction, 1)],
+            0b100 => &[(CacheKind::Unified, 0)],
+            _ => break,
+        };
+        for &(kind, ind) in kinds {
+            let ccsidr: u64;
+            unsafe {
+                asm!(
+                    "msr csselr_el1, {sel}",
+                    "isb",
+                    "mrs {ccsidr}, ccsidr_el1",
+                    sel = in(reg) (level << 1) | ind,
+                    ccsidr = out(reg) ccsidr,
+                    options(nostack),
+                )
+            };
+            let line_size = 1 << ((ccsidr & 0b111) + 4);
+            let (ways, sets) = if ccidx {
+                ((ccsidr >> 3) & 0x1f_ffff, (ccsidr >> 32) & 0xff_ffff)
+            } else {
+                ((ccsidr >> 3) & 0x3ff, (ccsidr >> 13) & 0x7fff)
+            };
+            info.add_cache(CacheInfo::new(
+                level as u8 + 1,
+                kind,
+                line_size,
+                ways as usize + 1,
+                sets as usize + 1,
+            ));
+        }
+    }
+}
+
+/// Identifies the current CPU.
+pub fn detect() -> CpuInfo {
+    let midr = read_sysreg!("midr_el1");
+    let implementer = (midr >> 24) & 0xff;
+    let part = (midr >> 4) & 0xfff;
+    let mut info = CpuInfo::new(implementer_name(implementer), "aarch64");
+    info.set_signature(midr, 8, part as u32, (midr & 0xf) as u32);
+    match arm_part_name(part).filter(|_| implementer == 0x41) {
+        Some(name) => info.write_str(name),
+        None => write!(info, "part {part:#05x}"),
+    }
+    .ok();
+
+    let pfr0 = read_sysreg!("id_aa64pfr0_el1");
+    let isar0 = read_sysreg!("id_aa64isar0_el1");
+    let aes = field(isar0, 4);
+    let sha2 = field(isar0, 12);
+    let features = [
+        // 0xf means not implemented
+        (Feature::Fp, field(pfr0, 16) != 0xf, 0),
+        (Feature::Simd, field(pfr0, 20) != 0xf, 1),
+        (Feature::Aes, aes >= 1, 3),
+        (Feature::Pmull, aes >= 2, 4),
+        (Feature::Sha1, field(isar0, 8) >= 1, 5),
+        (Feature::Sha2, sha2 >= 1, 6),
+        (Feature::Crc32, field(isar0, 16) >= 1, 7),
+        (Feature::Atomics, field(isar0, 20) >= 2, 8),
+        (Feature::Sha3, field(isar0, 32) >= 1, 17),
+        (Feature::Sm3, field(isar0, 36) >= 1, 18),
+        (Feature::Sm4, field(isar0, 40) >= 1, 19),
+        (Feature::Sha512, sha2 >= 2, 21),
+    ];
+    let mut hwcap = 0;
+    for (feature, present, bit) in features {
+        info.set_feature(feature, present);
+        if present {
+            hwcap |= 1 << bit;
+        }
+    }
+    // In AT_HWCAP2 rather than AT_HWCAP
+    info.set_feature(Feature::Rng, field(isar0, 60) >= 1);
+    info.set_hwcap(hwcap);
+
+    detect_caches(&mut info);
+    info
+}
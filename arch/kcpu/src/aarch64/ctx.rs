@@ -153,18 +153,26 @@ pub struct FpState {
     pub fpcr: u32,
     /// Floating-point Status Register (FPSR)
     pub fpsr: u32,
+    /// SVE & SME states, used instead of the above once the task has used
+    /// either extension.
+    #[cfg(feature = "fp-simd")]
+    pub sve: super::sve::SveState,
 }
 
 #[cfg(feature = "fp-simd")]
 impl FpState {
     /// Saves the current FP/SIMD states from CPU to this structure.
     pub fn save(&mut self) {
-        unsafe { fpstate_save(self) }
+        if !self.sve.save(&mut self.fpcr, &mut self.fpsr) {
+            unsafe { fpstate_save(self) }
+        }
     }
 
     /// Restores the FP/SIMD states from this structure to CPU.
     pub fn restore(&self) {
-        unsafe { fpstate_restore(self) }
+        if !self.sve.restore(self.fpcr, self.fpsr) {
+            unsafe { fpstate_restore(self) }
+        }
     }
 }
 
@@ -213,6 +221,9 @@ pub struct TaskContext {
     /// The `ttbr0_el1` register value, i.e., the page table root.
     #[cfg(feature = "uspace")]
     pub ttbr0_el1: memaddr::PhysAddr,
+    /// The ASID of the page table, or 0.
+    #[cfg(feature = "uspace")]
+    pub asid: u16,
     #[cfg(feature = "fp-simd")]
     pub fp_state: FpState,
 }
@@ -246,6 +257,13 @@ impl TaskContext {
         self.ttbr0_el1 = ttbr0_el1;
     }
 
+    /// Changes the address space identifier (ASID) in this context, the one
+    /// the TLB entries of its page table are tagged with, or 0 for none.
+    #[cfg(feature = "uspace")]
+    pub fn set_asid(&mut self, asid: u16) {
+        self.asid = asid;
+    }
+
     /// Switches to another task.
     ///
     /// It first saves the current task's context from CPU to this place, and then
@@ -262,9 +280,12 @@ impl TaskContext {
             next_ctx.fp_state.restore();
         }
         #[cfg(feature = "uspace")]
-        if self.ttbr0_el1 != next_ctx.ttbr0_el1 {
-            unsafe { crate::instrs::write_user_page_table(next_ctx.ttbr0_el1) };
-            crate::instrs::flush_tlb(None); // currently flush the entire TLB
+        if self.ttbr0_el1 != next_ctx.ttbr0_el1 || self.asid != next_ctx.asid {
+            unsafe { crate::instrs::write_user_page_table_asid(next_ctx.ttbr0_el1, next_ctx.asid) };
+            if next_ctx.asid == 0 {
+                // the entries of other ASIDs stay valid
+                crate::instrs::flush_tlb_asid(None, 0);
+            }
         }
         unsafe { context_switch(self, next_ctx) }
     }
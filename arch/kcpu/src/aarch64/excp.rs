@@ -113,6 +113,11 @@ fn dispatch_exception(tf: &mut ExceptionContext, kind: ArchTrap, source: ArchTra
                     debug!("BRK #{:#x} @ {:#x} ", iss, tf.elr);
                     tf.elr += 4;
                 }
+                #[cfg(feature = "fp-simd")]
+                Some(ESR_EL1::EC::Value::TrappedSve) if super::sve::handle_trap(false) => {}
+                #[cfg(feature = "fp-simd")]
+                _ if esr.read(ESR_EL1::EC) == super::sve::EC_TRAPPED_SME
+                    && super::sve::handle_trap(true) => {}
                 e => {
                     let vaddr = va!(FAR_EL1.get() as usize);
                     panic!(
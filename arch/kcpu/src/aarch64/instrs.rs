@@ -58,7 +58,8 @@ pub fn kernel_pt_root() -> PhysAddr {
 /// Returns the physical address of the page table root.
 #[inline]
 pub fn user_pt_root() -> PhysAddr {
-    let val = TTBR0_EL1.get();
+    // Leave out the ASID.
+    let val = TTBR0_EL1.get() & ((1 << 48) - 1);
     pa!(val as usize)
 }
 
@@ -103,6 +104,72 @@ pub unsafe fn write_user_page_table(root_paddr: PhysAddr) {
     TTBR0_EL1.set(root_paddr.as_usize() as _);
 }
 
+/// Returns the largest address space identifier (ASID) usable with
+/// [`write_user_page_table_asid`], or 0 if there are none.
+///
+/// The ASID of the user page table is in `TTBR0_EL1`, 8 or 16 bits wide
+/// depending on `TCR_EL1.AS`. Only user mappings are tagged with it, the
+/// kernel ones are global.
+pub fn enable_asid() -> u16 {
+    #[cfg(not(feature = "arm-el2"))]
+    {
+        if TCR_EL1.read(TCR_EL1::A1) != 0 {
+            // The ASID is taken from `TTBR1_EL1`.
+            return 0;
+        }
+        if TCR_EL1.read(TCR_EL1::AS) != 0 {
+            u16::MAX
+        } else {
+            u8::MAX as u16
+        }
+    }
+    #[cfg(feature = "arm-el2")]
+    {
+        0
+    }
+}
+
+/// Writes the register to update the current page table root for user space
+/// (`TTBR0_EL1`), tagging the TLB entries with `asid`.
+///
+/// Note that the TLB is **NOT** flushed after this operation.
+///
+/// # Safety
+///
+/// This function is unsafe as it changes the virtual memory address space.
+/// `asid` must not exceed the value returned by [`enable_asid`].
+#[inline]
+pub unsafe fn write_user_page_table_asid(root_paddr: PhysAddr, asid: u16) {
+    TTBR0_EL1.set(root_paddr.as_usize() as u64 | (asid as u64) << 48);
+    barrier::isb(barrier::SY);
+}
+
+/// Flushes the TLB entries of the current CPU tagged with `asid`.
+///
+/// If `vaddr` is [`None`], flushes all of them. Otherwise, flushes the one
+/// that maps the given virtual address.
+#[inline]
+pub fn flush_tlb_asid(vaddr: Option<VirtAddr>, asid: u16) {
+    #[cfg(not(feature = "arm-el2"))]
+    {
+        let asid = (asid as u64) << 48;
+        if let Some(vaddr) = vaddr {
+            const VA_MASK: u64 = (1 << 44) - 1; // VA[55:12] => bits[43:0]
+            let operand = asid | ((vaddr.as_usize() as u64 >> 12) & VA_MASK);
+            // TLB Invalidate by VA, EL1
+            unsafe { asm!("dsb ishst; tlbi vae1, {}; dsb nsh; isb", in(reg) operand) }
+        } else {
+            // TLB Invalidate by ASID, EL1
+            unsafe { asm!("dsb ishst; tlbi aside1, {}; dsb nsh; isb", in(reg) asid) }
+        }
+    }
+    #[cfg(feature = "arm-el2")]
+    {
+        let _ = asid;
+        flush_tlb(vaddr);
+    }
+}
+
 /// Flushes the TLB.
 ///
 /// If `vaddr` is [`None`], flushes the entire TLB. Otherwise, flushes the TLB
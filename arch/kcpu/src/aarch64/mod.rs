@@ -7,10 +7,14 @@
 mod ctx;
 
 pub mod boot;
+pub mod cpuinfo;
 pub mod instrs;
 
 mod excp;
 
+#[cfg(feature = "fp-simd")]
+pub mod sve;
+
 #[cfg(feature = "uspace")]
 pub mod userspace;
 
@@ -0,0 +1,492 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Scalable Vector Extension (SVE) and Scalable Matrix Extension (SME)
+//! register state.
+//!
+//! Both extensions start trapped (`CPACR_EL1.ZEN` and `SMEN`) for every
+//! task. The first use traps to [`handle_trap`], which asks the task layer
+//! through [`VECTOR_STATE`] for a save area and opens the extension on the
+//! CPU. From then on the state is live: [`FpState::save`] finds the extension
+//! open in `CPACR_EL1` and saves the scalable registers instead of the NEON
+//! ones, and [`FpState::restore`] reopens it for the task.
+//!
+//! The save area of a task is allocated by the task layer and sized by
+//! [`state_size`] for the largest vector lengths of the CPU, so changing the
+//! vector length never reallocates it.
+//!
+//! [`VECTOR_STATE`]: crate::excp::VECTOR_STATE
+//! [`FpState::save`]: super::FpState::save
+//! [`FpState::restore`]: super::FpState::restore
+
+use core::{
+    arch::{asm, naked_asm},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use aarch64_cpu::{
+    asm::barrier,
+    registers::{CPACR_EL1, Readable, Writeable},
+};
+
+/// Smallest vector length, in bytes.
+pub const VL_MIN: usize = 16;
+/// Largest vector length the architecture allows, in bytes.
+pub const VL_MAX: usize = 256;
+/// Vector length of tasks that did not choose one, in bytes, as on Linux.
+pub const DEFAULT_VL: usize = 64;
+/// Streaming vector length of tasks that did not choose one, in bytes.
+pub const DEFAULT_SVL: usize = 32;
+
+const CPACR_ZEN: u64 = 0b11 << 16;
+const CPACR_SMEN: u64 = 0b11 << 24;
+
+/// Exception class of SME access traps, absent from `ESR_EL1::EC`.
+pub(crate) const EC_TRAPPED_SME: u64 = 0b011101;
+
+/// `SVCR.SM`: the PE is in streaming SVE mode.
+const SVCR_SM: u64 = 1 << 0;
+/// `SVCR.ZA`: the ZA storage is live.
+const SVCR_ZA: u64 = 1 << 1;
+
+macro_rules! read_sysreg {
+    ($reg:literal) => {{
+        let value: u64;
+        unsafe { asm!(concat!("mrs {}, ", $reg), out(reg) value, options(nomem, nostack)) };
+        value
+    }};
+}
+
+macro_rules! write_sysreg {
+    ($reg:literal, $value:expr) => {{
+        let value: u64 = $value;
+        unsafe { asm!(concat!("msr ", $reg, ", {}"), in(reg) value, options(nomem, nostack)) };
+    }};
+}
+
+// `SVCR`, `ZCR_EL1` and `SMCR_EL1` go by their encodings, which the
+// assembler accepts without the extensions enabled.
+
+fn read_svcr() -> u64 {
+    read_sysreg!("S3_3_C4_C2_2")
+}
+
+fn write_svcr(svcr: u64) {
+    write_sysreg!("S3_3_C4_C2_2", svcr);
+    barrier::isb(barrier::SY);
+}
+
+/// Programs the vector length of the CPU, in bytes. SVE must be open.
+fn write_zcr(vl: usize) {
+    write_sysreg!("S3_0_C1_C2_0", (vl / VL_MIN - 1) as u64);
+}
+
+/// Programs the streaming vector length of the CPU, in bytes, leaving
+/// `SMCR_EL1.FA64` clear: streaming mode has no FFR. SME must be open.
+fn write_smcr(svl: usize) {
+    write_sysreg!("S3_0_C1_C2_6", (svl / VL_MIN - 1) as u64);
+}
+
+/// Opens or traps the extensions in `CPACR_EL1`, `enable` being a mask of
+/// [`CPACR_ZEN`] and [`CPACR_SMEN`].
+fn set_access(mask: u64, enable: u64) {
+    let cpacr = CPACR_EL1.get();
+    let new = (cpacr & !mask) | (enable & mask);
+    if new != cpacr {
+        CPACR_EL1.set(new);
+        barrier::isb(barrier::SY);
+    }
+}
+
+/// Whether the CPU implements SVE.
+pub fn sve_supported() -> bool {
+    (read_sysreg!("id_aa64pfr0_el1") >> 32) & 0xf != 0
+}
+
+/// Whether the CPU implements SME.
+pub fn sme_supported() -> bool {
+    (read_sysreg!("id_aa64pfr1_el1") >> 24) & 0xf != 0
+}
+
+static MAX_VL: AtomicUsize = AtomicUsize::new(usize::MAX);
+static MAX_SVL: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Reads the length the CPU settles on when asked for the longest one.
+fn probe(cached: &AtomicUsize, supported: fn() -> bool, sme: bool) -> usize {
+    let len = cached.load(Ordering::Relaxed);
+    if len != usize::MAX {
+        return len;
+    }
+    let len = if !supported() {
+        0
+    } else {
+        let mask = if sme { CPACR_SMEN } else { CPACR_ZEN };
+        let cpacr = CPACR_EL1.get();
+        set_access(mask, mask);
+        let len: usize;
+        if sme {
+            write_smcr(VL_MAX);
+            unsafe { asm!(".arch_extension sme", "rdsvl {}, #1", out(reg) len) };
+        } else {
+            write_zcr(VL_MAX);
+            unsafe { asm!(".arch_extension sve", "rdvl {}, #1", out(reg) len) };
+        }
+        CPACR_EL1.set(cpacr);
+        barrier::isb(barrier::SY);
+        len
+    };
+    cached.store(len, Ordering::Relaxed);
+    len
+}
+
+/// Longest vector length of the CPU in bytes, 0 without SVE.
+pub fn max_vl() -> usize {
+    probe(&MAX_VL, sve_supported, false)
+}
+
+/// Longest streaming vector length of the CPU in bytes, 0 without SME.
+pub fn max_svl() -> usize {
+    probe(&MAX_SVL, sme_supported, true)
+}
+
+/// Rounds `len` down to a power of two the CPU supports, up to `max`.
+fn round_len(len: usize, max: usize) -> usize {
+    let len = len.clamp(VL_MIN, max.max(VL_MIN));
+    1 << len.ilog2()
+}
+
+/// Layout of a save area, for vector lengths up to `vl` bytes.
+struct Layout {
+    p: usize,
+    ffr: usize,
+    za: usize,
+    size: usize,
+}
+
+impl Layout {
+    fn new() -> Self {
+        let vl = max_vl().max(max_svl());
+        let svl = max_svl();
+        let p = 32 * vl;
+        let ffr = p + 16 * (vl / 8);
+        let za = (ffr + vl / 8).next_multiple_of(16);
+        Self {
+            p,
+            ffr,
+            za,
+            size: za + svl * svl,
+        }
+    }
+}
+
+/// Size in bytes of the save area of a task, 16-byte aligned.
+pub fn state_size() -> usize {
+    Layout::new().size
+}
+
+/// Vector length settings of a task for one extension.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VectorConfig {
+    /// Vector length in bytes, 0 for the default.
+    pub len: u16,
+    /// Vector length to switch to at the next `execve`, 0 for none.
+    pub onexec: u16,
+    /// Whether `execve` keeps the vector length rather than resetting it to
+    /// the default.
+    pub inherit: bool,
+}
+
+/// SVE and SME state of a task, part of its [`FpState`].
+///
+/// [`FpState`]: super::FpState
+#[derive(Debug, Default)]
+pub struct SveState {
+    /// SVE vector length settings.
+    pub sve: VectorConfig,
+    /// SME streaming vector length settings.
+    pub sme: VectorConfig,
+    /// Address of the save area, 0 until the task first uses an extension.
+    buf: usize,
+    /// Extensions the task had open in `CPACR_EL1` when switched out.
+    access: u64,
+    /// `SVCR` of the task when switched out.
+    svcr: u64,
+}
+
+impl SveState {
+    /// Vector length the task runs with, in bytes.
+    pub fn vl(&self) -> usize {
+        let len = match self.sve.len {
+            0 => DEFAULT_VL,
+            len => len as usize,
+        };
+        round_len(len, max_vl())
+    }
+
+    /// Streaming vector length the task runs with, in bytes.
+    pub fn svl(&self) -> usize {
+        let len = match self.sme.len {
+            0 => DEFAULT_SVL,
+            len => len as usize,
+        };
+        round_len(len, max_svl())
+    }
+
+    /// Returns the address of the save area, 0 if there is none yet.
+    pub fn buffer(&self) -> usize {
+        self.buf
+    }
+
+    /// Gives the task a save area of [`state_size`] bytes, 16-byte aligned.
+    ///
+    /// # Safety
+    ///
+    /// `buf` must stay valid and unused by anything else until taken back
+    /// with [`take_buffer`](Self::take_buffer).
+    pub unsafe fn set_buffer(&mut self, buf: usize) {
+        self.buf = buf;
+    }
+
+    /// Takes the save area back, for freeing it when the task is gone.
+    pub fn take_buffer(&mut self) -> Option<usize> {
+        self.access = 0;
+        self.svcr = 0;
+        match core::mem::take(&mut self.buf) {
+            0 => None,
+            buf => Some(buf),
+        }
+    }
+
+    /// Programs the vector lengths of the task into the CPU, leaving the
+    /// extensions open.
+    fn write_lengths(&self) {
+        let (max_vl, max_svl) = (max_vl(), max_svl());
+        let mut open = 0;
+        if max_vl != 0 {
+            open |= CPACR_ZEN;
+        }
+        if max_svl != 0 {
+            open |= CPACR_SMEN;
+        }
+        set_access(open, open);
+        if max_vl != 0 {
+            write_zcr(self.vl());
+        }
+        if max_svl != 0 {
+            write_smcr(self.svl());
+        }
+    }
+
+    /// Applies new vector lengths to the task running on this CPU.
+    ///
+    /// Like Linux, the scalable state beyond the NEON registers is dropped:
+    /// both extensions trap again, streaming mode is left and ZA is
+    /// discarded.
+    pub fn reload_current(&mut self) {
+        if CPACR_EL1.get() & CPACR_SMEN == CPACR_SMEN && read_svcr() != 0 {
+            write_svcr(0);
+        }
+        self.write_lengths();
+        set_access(CPACR_ZEN | CPACR_SMEN, 0);
+    }
+
+    /// Applies the settings of the task to `execve`, and drops the state of
+    /// the task running on this CPU.
+    pub fn exec_current(&mut self) {
+        for config in [&mut self.sve, &mut self.sme] {
+            if config.onexec != 0 {
+                config.len = core::mem::take(&mut config.onexec);
+            } else if !config.inherit {
+                config.len = 0;
+            }
+        }
+        self.reload_current();
+    }
+
+    fn layout(&self) -> Option<(*mut u8, Layout)> {
+        (self.buf != 0).then(|| (self.buf as *mut u8, Layout::new()))
+    }
+
+    /// Saves the scalable state from the CPU. Returns false if the task
+    /// only has NEON state.
+    pub(super) fn save(&mut self, fpcr: &mut u32, fpsr: &mut u32) -> bool {
+        let Some((buf, layout)) = self.layout() else {
+            return false;
+        };
+        self.access = CPACR_EL1.get() & (CPACR_ZEN | CPACR_SMEN);
+        self.svcr = if self.access & CPACR_SMEN == CPACR_SMEN {
+            read_svcr()
+        } else {
+            0
+        };
+        let streaming = self.svcr & SVCR_SM != 0;
+        let sve = streaming || self.access & CPACR_ZEN == CPACR_ZEN;
+        unsafe {
+            if self.svcr & SVCR_ZA != 0 {
+                za_save(buf.add(layout.za), self.svl());
+            }
+            if sve {
+                let ffr = if streaming {
+                    core::ptr::null_mut()
+                } else {
+                    buf.add(layout.ffr)
+                };
+                sve_save(buf, buf.add(layout.p), ffr);
+                // Before leaving streaming mode, which resets FPSR
+                let (cr, sr): (u64, u64);
+                asm!("mrs {}, fpcr", "mrs {}, fpsr", out(reg) cr, out(reg) sr);
+                (*fpcr, *fpsr) = (cr as u32, sr as u32);
+            }
+        }
+        if self.svcr != 0 {
+            write_svcr(0);
+        }
+        sve
+    }
+
+    /// Restores the scalable state to the CPU. Returns false if the task
+    /// only has NEON state, for the caller to restore it.
+    pub(super) fn restore(&self, fpcr: u32, fpsr: u32) -> bool {
+        if max_vl() == 0 && max_svl() == 0 {
+            return false;
+        }
+        self.write_lengths();
+        set_access(CPACR_ZEN | CPACR_SMEN, self.access);
+        let Some((buf, layout)) = self.layout() else {
+            return false;
+        };
+        let streaming = self.svcr & SVCR_SM != 0;
+        let sve = streaming || self.access & CPACR_ZEN == CPACR_ZEN;
+        if self.svcr != 0 {
+            write_svcr(self.svcr);
+        }
+        unsafe {
+            if self.svcr & SVCR_ZA != 0 {
+                za_load(buf.add(layout.za), self.svl());
+            }
+            if sve {
+                let ffr = if streaming {
+                    core::ptr::null()
+                } else {
+                    buf.add(layout.ffr).cast_const()
+                };
+                sve_load(buf, buf.add(layout.p), ffr);
+                asm!("msr fpcr, {}", "msr fpsr, {}", in(reg) fpcr as u64, in(reg) fpsr as u64);
+            }
+        }
+        sve
+    }
+}
+
+/// Handles an SVE (`sme` false) or SME access trap from the current task.
+///
+/// Returns false if the CPU lacks the extension or the task layer could not
+/// provide a save area, the access then being an undefined instruction.
+pub(crate) fn handle_trap(sme: bool) -> bool {
+    let supported = if sme {
+        sme_supported()
+    } else {
+        sve_supported()
+    };
+    if !supported || !dispatch_irq_trap!(VECTOR_STATE,) {
+        return false;
+    }
+    if sme {
+        set_access(CPACR_SMEN, CPACR_SMEN);
+    } else {
+        set_access(CPACR_ZEN, CPACR_ZEN);
+        // The bits beyond the NEON registers still hold the state of the
+        // last task that used SVE on this CPU.
+        unsafe { sve_flush() };
+    }
+    true
+}
+
+#[unsafe(naked)]
+unsafe extern "C" fn sve_save(_z: *mut u8, _p: *mut u8, _ffr: *mut u8) {
+    naked_asm!(
+        ".arch_extension sve
+        .irp n, \
+         0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31
+        str     z\\n, [x0, #\\n, mul vl]
+        .endr
+        .irp n, 0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15
+        str     p\\n, [x1, #\\n, mul vl]
+        .endr
+        cbz     x2, 1f
+        rdffr   p0.b
+        str     p0, [x2]
+        ldr     p0, [x1]
+    1:
+        ret"
+    )
+}
+
+#[unsafe(naked)]
+unsafe extern "C" fn sve_load(_z: *const u8, _p: *const u8, _ffr: *const u8) {
+    naked_asm!(
+        ".arch_extension sve
+        cbz     x2, 1f
+        ldr     p0, [x2]
+        wrffr   p0.b
+    1:
+        .irp n, 0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15
+        ldr     p\\n, [x1, #\\n, mul vl]
+        .endr
+        .irp n, \
+         0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31
+        ldr     z\\n, [x0, #\\n, mul vl]
+        .endr
+        ret"
+    )
+}
+
+/// Zeroes the SVE state beyond the NEON registers, which are kept.
+#[unsafe(naked)]
+unsafe extern "C" fn sve_flush() {
+    naked_asm!(
+        ".arch_extension sve
+        .irp n, \
+         0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31
+        mov     v\\n.16b, v\\n.16b
+        .endr
+        .irp n, 0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15
+        pfalse  p\\n.b
+        .endr
+        setffr
+        ret"
+    )
+}
+
+/// Saves the `svl` rows of `svl` bytes of ZA.
+#[unsafe(naked)]
+unsafe extern "C" fn za_save(_za: *mut u8, _svl: usize) {
+    naked_asm!(
+        ".arch_extension sme
+        mov     w12, #0
+    1:
+        str     za[w12, 0], [x0]
+        add     x0, x0, x1
+        add     w12, w12, #1
+        cmp     x12, x1
+        b.lo    1b
+        ret"
+    )
+}
+
+/// Loads the `svl` rows of `svl` bytes of ZA.
+#[unsafe(naked)]
+unsafe extern "C" fn za_load(_za: *const u8, _svl: usize) {
+    naked_asm!(
+        ".arch_extension sme
+        mov     w12, #0
+    1:
+        ldr     za[w12, 0], [x0]
+        add     x0, x0, x1
+        add     w12, w12, #1
+        cmp     x12, x1
+        b.lo    1b
+        ret"
+    )
+}
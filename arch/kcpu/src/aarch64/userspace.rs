@@ -6,7 +6,7 @@
 
 use core::ops::{Deref, DerefMut};
 
-use aarch64_cpu::registers::{ESR_EL1, FAR_EL1, Readable};
+use aarch64_cpu::registers::{ESR_EL1, FAR_EL1, Readable, TPIDRRO_EL0, Writeable};
 use memaddr::VirtAddr;
 use tock_registers::LocalRegisterCopy;
 
@@ -30,6 +30,11 @@ pub struct UserContext {
 
 impl UserContext {
     const PAD_MAGIC: u64 = 0x1234_5678_9abc_def0;
+    /// SPSR for AArch32 User mode with asynchronous exceptions masked except
+    /// for IRQs (A, F and M = 0b10000).
+    const SPSR_AARCH32_USR: u64 = (1 << 8) | (1 << 6) | Self::SPSR_M_AARCH32;
+    /// SPSR.M[4]: the exception was taken from AArch32 state.
+    const SPSR_M_AARCH32: u64 = 1 << 4;
 
     /// Creates a new context with the given entry point, user stack pointer,
     /// and the argument.
@@ -54,14 +59,39 @@ impl UserContext {
         }
     }
 
+    /// Creates a new context that enters user space in AArch32 state, for
+    /// running armhf binaries.
+    ///
+    /// Bit 0 of `entry` selects the Thumb instruction set, as for `BX`.
+    pub fn new_compat(entry: usize, ustack_top: VirtAddr, arg0: usize) -> Self {
+        let mut uctx = Self::new(entry & !1, ustack_top, arg0);
+        uctx.tf.spsr = Self::SPSR_AARCH32_USR | ((entry as u64 & 1) << 5);
+        uctx.tf.x[13] = ustack_top.as_usize() as _;
+        uctx
+    }
+
+    /// Whether this context runs in AArch32 state.
+    pub const fn is_compat(&self) -> bool {
+        self.tf.spsr & Self::SPSR_M_AARCH32 != 0
+    }
+
     /// Gets the stack pointer.
     pub const fn sp(&self) -> usize {
-        self.sp as _
+        if self.is_compat() {
+            // R13_usr is banked into X13 while EL0 runs in AArch32 state.
+            self.tf.x[13] as _
+        } else {
+            self.sp as _
+        }
     }
 
     /// Sets the stack pointer.
     pub const fn set_sp(&mut self, sp: usize) {
-        self.sp = sp as _;
+        if self.is_compat() {
+            self.tf.x[13] = sp as _;
+        } else {
+            self.sp = sp as _;
+        }
     }
 
     /// Gets the TLS area.
@@ -86,6 +116,11 @@ impl UserContext {
         }
 
         crate::instrs::disable_local(); // updated module reference from asm -> instrs
+        if self.is_compat() {
+            // AArch32 reads its thread pointer from TPIDRURO, which is
+            // read-only to EL0 and therefore never needs saving back.
+            TPIDRRO_EL0.set(self.tpidr);
+        }
         let trap_kind = unsafe { enter_user(self) };
 
         let ret = match trap_kind {
@@ -101,7 +136,9 @@ impl UserContext {
                 let iss = esr.read(ESR_EL1::ISS);
 
                 match esr.read_as_enum(ESR_EL1::EC) {
-                    Some(ESR_EL1::EC::Value::SVC64) => ReturnReason::Syscall,
+                    Some(ESR_EL1::EC::Value::SVC64 | ESR_EL1::EC::Value::SVC32) => {
+                        ReturnReason::Syscall
+                    }
                     Some(ESR_EL1::EC::Value::InstrAbortLowerEL) if check_page_fault(iss) => {
                         ReturnReason::PageFault(
                             va!(far),
@@ -120,6 +157,16 @@ impl UserContext {
                             } | PageFaultFlags::USER,
                         )
                     }
+                    #[cfg(feature = "fp-simd")]
+                    Some(ESR_EL1::EC::Value::TrappedSve) if super::sve::handle_trap(false) => {
+                        ReturnReason::Interrupt
+                    }
+                    #[cfg(feature = "fp-simd")]
+                    _ if esr.read(ESR_EL1::EC) == super::sve::EC_TRAPPED_SME
+                        && super::sve::handle_trap(true) =>
+                    {
+                        ReturnReason::Interrupt
+                    }
                     _ => ReturnReason::Exception(ExceptionInfo { esr, far }),
                 }
             }
@@ -158,9 +205,13 @@ impl ExceptionInfo {
     pub fn kind(&self) -> ExceptionKind {
         match self.esr.read_as_enum(ESR_EL1::EC) {
             Some(ESR_EL1::EC::Value::BreakpointLowerEL) => ExceptionKind::Breakpoint,
-            Some(ESR_EL1::EC::Value::IllegalExecutionState) => ExceptionKind::IllegalInstruction,
+            Some(ESR_EL1::EC::Value::IllegalExecutionState | ESR_EL1::EC::Value::TrappedSve) => {
+                ExceptionKind::IllegalInstruction
+            }
             Some(ESR_EL1::EC::Value::PCAlignmentFault)
             | Some(ESR_EL1::EC::Value::SPAlignmentFault) => ExceptionKind::Misaligned,
+            // SME access trap, absent from `ESR_EL1::EC`
+            _ if self.esr.read(ESR_EL1::EC) == 0b011101 => ExceptionKind::IllegalInstruction,
             _ => ExceptionKind::Other,
         }
     }
@@ -0,0 +1,420 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! CPU identification: vendor, model, ISA extensions and caches.
+//!
+//! Each architecture's `cpuinfo::detect` reads them from the identification
+//! registers of the current CPU: CPUID on x86_64, `MIDR_EL1` and the ID
+//! registers on AArch64, and CPUCFG on LoongArch. They are not readable
+//! from S-mode on RISC-V, where they are parsed from the `riscv,isa` string
+//! of the device tree instead.
+
+use core::fmt;
+
+/// An ISA extension that kernel or user code may dispatch on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Feature {
+    /// Scalar floating point.
+    Fp,
+    /// 128-bit SIMD: SSE2, Advanced SIMD, LSX or the V extension.
+    Simd,
+    /// 256-bit SIMD: AVX2 or LASX.
+    Simd256,
+    /// 512-bit SIMD: AVX-512F.
+    Simd512,
+    /// AES rounds.
+    Aes,
+    /// Carry-less multiplication, as used by GCM and CRC folding.
+    Pmull,
+    /// SHA-1.
+    Sha1,
+    /// SHA-256.
+    Sha2,
+    /// SHA-3.
+    Sha3,
+    /// SHA-512.
+    Sha512,
+    /// SM3 hash.
+    Sm3,
+    /// SM4 block cipher.
+    Sm4,
+    /// CRC-32 instructions.
+    Crc32,
+    /// Atomic read-modify-write instructions.
+    Atomics,
+    /// Hardware random numbers.
+    Rng,
+}
+
+impl Feature {
+    /// All the features, in the order they are listed.
+    pub const ALL: [Feature; 15] = [
+        Feature::Fp,
+        Feature::Simd,
+        Feature::Simd256,
+        Feature::Simd512,
+        Feature::Aes,
+        Feature::Pmull,
+        Feature::Sha1,
+        Feature::Sha2,
+        Feature::Sha3,
+        Feature::Sha512,
+        Feature::Sm3,
+        Feature::Sm4,
+        Feature::Crc32,
+        Feature::Atomics,
+        Feature::Rng,
+    ];
+
+    /// Returns the name of the feature in `/proc/cpuinfo`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Feature::Fp => "fp",
+            Feature::Simd => "simd",
+            Feature::Simd256 => "simd256",
+            Feature::Simd512 => "simd512",
+            Feature::Aes => "aes",
+            Feature::Pmull => "pmull",
+            Feature::Sha1 => "sha1",
+            Feature::Sha2 => "sha2",
+            Feature::Sha3 => "sha3",
+            Feature::Sha512 => "sha512",
+            Feature::Sm3 => "sm3",
+            Feature::Sm4 => "sm4",
+            Feature::Crc32 => "crc32",
+            Feature::Atomics => "atomics",
+            Feature::Rng => "rng",
+        }
+    }
+}
+
+/// A set of [`Feature`]s.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Features(u32);
+
+impl Features {
+    /// Returns the empty set.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Adds `feature` to the set if `present`.
+    pub fn set(&mut self, feature: Feature, present: bool) {
+        if present {
+            self.0 |= 1 << feature as u8;
+        }
+    }
+
+    /// Returns whether `feature` is in the set.
+    pub const fn contains(self, feature: Feature) -> bool {
+        self.0 & (1 << feature as u8) != 0
+    }
+
+    /// Iterates over the features in the set.
+    pub fn iter(self) -> impl Iterator<Item = Feature> {
+        Feature::ALL.into_iter().filter(move |f| self.contains(*f))
+    }
+}
+
+impl fmt::Debug for Features {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// What a cache holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    /// Data only.
+    Data,
+    /// Instructions only.
+    Instruction,
+    /// Both.
+    Unified,
+}
+
+/// Geometry of a cache of the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheInfo {
+    /// Level, 1 being closest to the core.
+    pub level: u8,
+    /// What the cache holds.
+    pub kind: CacheKind,
+    /// Size in bytes.
+    pub size: usize,
+    /// Line size in bytes.
+    pub line_size: usize,
+    /// Associativity.
+    pub ways: usize,
+}
+
+impl CacheInfo {
+    #[cfg_attr(
+        any(target_arch = "riscv32", target_arch = "riscv64"),
+        allow(dead_code)
+    )]
+    pub(crate) fn new(
+        level: u8,
+        kind: CacheKind,
+        line_size: usize,
+        ways: usize,
+        sets: usize,
+    ) -> Self {
+        Self {
+            level,
+            kind,
+            size: line_size * ways * sets,
+            line_size,
+            ways,
+        }
+    }
+}
+
+/// Maximum number of caches recorded.
+const MAX_CACHES: usize = 8;
+
+/// Maximum length of a model name, that of the x86 brand string.
+const MAX_MODEL_NAME: usize = 48;
+
+/// Identification of a CPU.
+#[derive(Clone)]
+pub struct CpuInfo {
+    vendor: &'static str,
+    model_name: [u8; MAX_MODEL_NAME],
+    model_name_len: usize,
+    isa: &'static str,
+    id: u64,
+    family: u32,
+    model: u32,
+    stepping: u32,
+    features: Features,
+    hwcap: usize,
+    caches: [Option<CacheInfo>; MAX_CACHES],
+}
+
+impl CpuInfo {
+    pub(crate) const fn new(vendor: &'static str, isa: &'static str) -> Self {
+        Self {
+            vendor,
+            model_name: [0; MAX_MODEL_NAME],
+            model_name_len: 0,
+            isa,
+            id: 0,
+            family: 0,
+            model: 0,
+            stepping: 0,
+            features: Features::empty(),
+            hwcap: 0,
+            caches: [None; MAX_CACHES],
+        }
+    }
+
+    /// Returns the vendor, or `"unknown"`.
+    pub fn vendor(&self) -> &'static str {
+        self.vendor
+    }
+
+    /// Returns the model name, such as the x86 brand string.
+    pub fn model_name(&self) -> &str {
+        core::str::from_utf8(&self.model_name[..self.model_name_len]).unwrap_or("")
+    }
+
+    /// Returns the ISA: the `riscv,isa` string on RISC-V, the architecture
+    /// name elsewhere.
+    pub fn isa(&self) -> &'static str {
+        self.isa
+    }
+
+    /// Returns the raw identification register: CPUID leaf 1 `EAX` on
+    /// x86_64, `MIDR_EL1` on AArch64, the PRID on LoongArch.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the family, model and stepping.
+    ///
+    /// On AArch64 they are the architecture, part number and revision fields
+    /// of `MIDR_EL1`.
+    pub fn signature(&self) -> (u32, u32, u32) {
+        (self.family, self.model, self.stepping)
+    }
+
+    /// Returns the ISA extensions implemented.
+    pub fn features(&self) -> Features {
+        self.features
+    }
+
+    /// Returns whether `feature` is implemented.
+    pub fn has(&self, feature: Feature) -> bool {
+        self.features.contains(feature)
+    }
+
+    /// Returns the `AT_HWCAP` value that Linux gives user programs, in the
+    /// encoding of the architecture.
+    pub fn hwcap(&self) -> usize {
+        self.hwcap
+    }
+
+    /// Iterates over the caches, by level.
+    pub fn caches(&self) -> impl Iterator<Item = &CacheInfo> {
+        self.caches.iter().flatten()
+    }
+
+    #[cfg_attr(
+        any(target_arch = "riscv32", target_arch = "riscv64"),
+        allow(dead_code)
+    )]
+    pub(crate) fn set_signature(&mut self, id: u64, family: u32, model: u32, stepping: u32) {
+        self.id = id;
+        self.family = family;
+        self.model = model;
+        self.stepping = stepping;
+    }
+
+    pub(crate) fn set_feature(&mut self, feature: Feature, present: bool) {
+        self.features.set(feature, present);
+    }
+
+    pub(crate) fn set_hwcap(&mut self, hwcap: usize) {
+        self.hwcap = hwcap;
+    }
+
+    #[cfg_attr(
+        any(target_arch = "riscv32", target_arch = "riscv64"),
+        allow(dead_code)
+    )]
+    pub(crate) fn add_cache(&mut self, cache: CacheInfo) {
+        if let Some(slot) = self.caches.iter_mut().find(|c| c.is_none()) {
+            *slot = Some(cache);
+        }
+    }
+
+    /// Sets the model name from raw bytes, trimming padding.
+    #[cfg_attr(
+        not(any(target_arch = "x86_64", target_arch = "loongarch64")),
+        allow(dead_code)
+    )]
+    pub(crate) fn set_model_name(&mut self, name: &[u8]) {
+        let name = name.split(|&b| b == 0).next().unwrap_or_default();
+        let name = name.trim_ascii();
+        let len = name.len().min(MAX_MODEL_NAME);
+        self.model_name[..len].copy_from_slice(&name[..len]);
+        self.model_name_len = len;
+    }
+}
+
+/// Formats into the model name, truncating.
+impl fmt::Write for CpuInfo {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = s.len().min(MAX_MODEL_NAME - self.model_name_len);
+        let end = self.model_name_len + len;
+        self.model_name[self.model_name_len..end].copy_from_slice(&s.as_bytes()[..len]);
+        self.model_name_len = end;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for CpuInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CpuInfo")
+            .field("vendor", &self.vendor)
+            .field("model_name", &self.model_name())
+            .field("isa", &self.isa)
+            .field("id", &format_args!("{:#x}", self.id))
+            .field("features", &self.features)
+            .finish()
+    }
+}
+
+/// Parses a RISC-V ISA string such as `rv64imafdcv_zicsr_zkne_zknd`.
+///
+/// Returns the features and the `AT_HWCAP` value, that Linux sets from the
+/// single-letter extensions.
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64", unittest))]
+pub(crate) fn parse_riscv_isa(isa: &str) -> (Features, usize) {
+    let mut parts = isa.trim().split('_');
+    let base = parts.next().unwrap_or_default();
+    let letters = base.get(4..).unwrap_or_default();
+
+    let mut has_letter = [false; 26];
+    for c in letters.bytes().map(|c| c.to_ascii_lowercase()) {
+        if !c.is_ascii_lowercase() {
+            continue;
+        }
+        if c == b'g' {
+            for c in *b"imafd" {
+                has_letter[(c - b'a') as usize] = true;
+            }
+        } else {
+            has_letter[(c - b'a') as usize] = true;
+        }
+    }
+    let letter = |c: u8| has_letter[(c - b'a') as usize];
+    let ext = |name: &str| parts.clone().any(|e| e.eq_ignore_ascii_case(name));
+
+    let mut features = Features::empty();
+    features.set(Feature::Fp, letter(b'f') || letter(b'd'));
+    features.set(Feature::Simd, letter(b'v'));
+    features.set(Feature::Atomics, letter(b'a'));
+    let zkn = ext("zk") || ext("zkn");
+    features.set(Feature::Aes, zkn || (ext("zkne") && ext("zknd")));
+    features.set(Feature::Sha2, zkn || ext("zknh"));
+    features.set(Feature::Sha512, zkn || ext("zknh"));
+    features.set(Feature::Pmull, zkn || ext("zbc") || ext("zbkc"));
+    let zks = ext("zks");
+    features.set(Feature::Sm3, zks || ext("zksh"));
+    features.set(Feature::Sm4, zks || ext("zksed"));
+    features.set(Feature::Rng, ext("zk") || ext("zkr"));
+
+    let hwcap = b"imafdcv"
+        .iter()
+        .filter(|&&c| letter(c))
+        .fold(0, |hwcap, &c| hwcap | 1 << (c - b'a'));
+    (features, hwcap)
+}
+
+#[cfg(unittest)]
+pub mod tests_cpuinfo_common {
+    use unittest::def_test;
+
+    use super::{CpuInfo, Feature, Features, parse_riscv_isa};
+
+    #[def_test]
+    fn test_features_set() {
+        let mut features = Features::empty();
+        features.set(Feature::Aes, true);
+        features.set(Feature::Sha2, false);
+        assert!(features.contains(Feature::Aes));
+        assert!(!features.contains(Feature::Sha2));
+        assert_eq!(features.iter().count(), 1);
+    }
+
+    #[def_test]
+    fn test_riscv_isa_letters() {
+        let (features, hwcap) = parse_riscv_isa("rv64gc");
+        assert!(features.contains(Feature::Fp));
+        assert!(features.contains(Feature::Atomics));
+        assert!(!features.contains(Feature::Simd));
+        // i, m, a, f, d and c
+        assert_eq!(hwcap, 0x112d);
+    }
+
+    #[def_test]
+    fn test_riscv_isa_extensions() {
+        let (features, _) = parse_riscv_isa("rv64imafdcv_zicsr_zkne_zknd_zknh_zksed");
+        assert!(features.contains(Feature::Simd));
+        assert!(features.contains(Feature::Aes));
+        assert!(features.contains(Feature::Sha2));
+        assert!(features.contains(Feature::Sm4));
+        assert!(!features.contains(Feature::Sm3));
+    }
+
+    #[def_test]
+    fn test_model_name_trimmed() {
+        let mut info = CpuInfo::new("unknown", "");
+        info.set_model_name(b"  Example CPU @ 2.00GHz\0\0\0");
+        assert_eq!(info.model_name(), "Example CPU @ 2.00GHz");
+    }
+}
@@ -20,6 +20,12 @@ pub static IRQ: [fn(usize) -> bool];
 #[def_trap_handler]
 pub static PAGE_FAULT: [fn(VirtAddr, PageFaultFlags) -> bool];
 
+/// A slice of handlers giving the current task a save area for its SVE and
+/// SME states, on its first use of either extension.
+#[cfg(all(target_arch = "aarch64", feature = "fp-simd"))]
+#[def_trap_handler]
+pub static VECTOR_STATE: [fn() -> bool];
+
 #[allow(unused_macros)]
 macro_rules! dispatch_irq_trap {
     ($trap:ident, $($args:tt)*) => {{
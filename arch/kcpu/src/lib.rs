@@ -18,6 +18,10 @@ extern crate memaddr;
 pub mod excp;
 
 mod active_exception_context;
+mod cpuinfo_common;
+
+#[cfg(feature = "replay")]
+pub mod replay;
 
 pub use active_exception_context::{
     ExceptionContextGuard, active_exception_context, with_active_exception_context,
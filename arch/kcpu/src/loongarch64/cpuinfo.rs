@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! CPU identification from CPUCFG and the IOCSR identification registers.
+
+use core::arch::asm;
+
+pub use crate::cpuinfo_common::{CacheInfo, CacheKind, CpuInfo, Feature, Features};
+
+/// IOCSR holding the model name, such as `3A5000`.
+const IOCSR_CPUNAME: usize = 0x20;
+
+fn cpucfg(word: usize) -> u32 {
+    let value: usize;
+    unsafe { asm!("cpucfg {}, {}", out(reg) value, in(reg) word, options(nomem, nostack)) };
+    value as u32
+}
+
+fn bit(reg: u32, bit: u32) -> bool {
+    reg & (1 << bit) != 0
+}
+
+/// Reads the caches from CPUCFG words 0x10 (presence) and 0x11 to 0x14
+/// (geometry of the L1 instruction or unified, L1 data, L2 and L3 caches).
+fn detect_caches(info: &mut CpuInfo) {
+    let present = cpucfg(0x10);
+    let caches = [
+        (1, bit(present, 0), bit(present, 1), 0x11),
+        (1, bit(present, 2), false, 0x12),
+        (2, bit(present, 3), bit(present, 4), 0x13),
+        (3, bit(present, 10), bit(present, 11), 0x14),
+    ];
+    for (level, present, unified, word) in caches {
+        if !present {
+            continue;
+        }
+        let kind = match word {
+            0x12 => CacheKind::Data,
+            _ if unified => CacheKind::Unified,
+            _ => CacheKind::Instruction,
+        };
+        let config = cpucfg(word);
+        info.add_cache(CacheInfo::new(
+            level,
+            kind,
+            1 << ((config >> 24) & 0x7f),
+            (config & 0xffff) as usize + 1,
+            1 << ((config >> 16) & 0xff),
+        ));
+    }
+}
+
+/// Identifies the current CPU.
+pub fn detect() -> CpuInfo {
+    let prid = cpucfg(0);
+    let vendor = match (prid >> 16) & 0xff {
+        0x14 => "Loongson",
+        _ => "unknown",
+    };
+    let mut info = CpuInfo::new(vendor, "loongarch64");
+    info.set_signature(prid as u64, (prid >> 8) & 0xff, prid & 0xff, 0);
+    let name: u64;
+    unsafe {
+        asm!("iocsrrd.d {}, {}", out(reg) name, in(reg) IOCSR_CPUNAME, options(nomem, nostack))
+    };
+    info.set_model_name(&name.to_le_bytes());
+
+    let cfg1 = cpucfg(1);
+    let cfg2 = cpucfg(2);
+    // Bits of Linux's HWCAP_LOONGARCH_*, after CPUCFG (bit 0) that user
+    // space can always use
+    let features = [
+        (Some(Feature::Atomics), bit(cfg2, 22), 1),
+        // UAL: unaligned accesses
+        (None, bit(cfg1, 20), 2),
+        (Some(Feature::Fp), bit(cfg2, 0), 3),
+        (Some(Feature::Simd), bit(cfg2, 6), 4),
+        (Some(Feature::Simd256), bit(cfg2, 7), 5),
+        (Some(Feature::Crc32), bit(cfg1, 25), 6),
+    ];
+    let mut hwcap = 1;
+    for (feature, present, bit) in features {
+        if let Some(feature) = feature {
+            info.set_feature(feature, present);
+        }
+        if present {
+            hwcap |= 1 << bit;
+        }
+    }
+    info.set_hwcap(hwcap);
+
+    detect_caches(&mut info);
+    info
+}
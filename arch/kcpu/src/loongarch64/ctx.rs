@@ -266,6 +266,9 @@ pub struct TaskContext {
     #[cfg(feature = "uspace")]
     /// user page table root
     pub pgdl: usize,
+    #[cfg(feature = "uspace")]
+    /// ASID of the user page table, unused
+    pub asid: u16,
     #[cfg(feature = "fp-simd")]
     /// Floating Point Unit states
     pub fpu: FpuState,
@@ -294,6 +297,13 @@ impl TaskContext {
         self.pgdl = pgdl.as_usize();
     }
 
+    /// Changes the address space identifier (ASID) in this context, the one
+    /// the TLB entries of its page table are tagged with, or 0 for none.
+    #[cfg(feature = "uspace")]
+    pub fn set_asid(&mut self, asid: u16) {
+        self.asid = asid;
+    }
+
     /// Switches to another task.
     ///
     /// It first saves the current task's context from CPU to this place, and then
@@ -82,6 +82,32 @@ pub unsafe fn write_kernel_page_table(root_paddr: PhysAddr) {
     pgdh::set_base(root_paddr.as_usize());
 }
 
+/// Returns the largest address space identifier (ASID) usable with
+/// [`write_user_page_table_asid`], or 0 if there are none.
+///
+/// The ASIDs of LoongArch are not used yet.
+pub fn enable_asid() -> u16 {
+    0
+}
+
+/// Writes the register to update the current page table root for user space
+/// (`PGDL`). The same as [`write_user_page_table`], as there are no ASIDs.
+///
+/// # Safety
+///
+/// This function is unsafe as it changes the virtual memory address space.
+#[inline]
+pub unsafe fn write_user_page_table_asid(root_paddr: PhysAddr, _asid: u16) {
+    unsafe { write_user_page_table(root_paddr) }
+}
+
+/// Flushes the TLB entries tagged with `asid`. The same as [`flush_tlb`], as
+/// there are no ASIDs.
+#[inline]
+pub fn flush_tlb_asid(vaddr: Option<VirtAddr>, _asid: u16) {
+    flush_tlb(vaddr)
+}
+
 /// Flushes the TLB.
 ///
 /// If `vaddr` is [`None`], flushes the entire TLB. Otherwise, flushes the TLB
@@ -14,6 +14,7 @@ mod unaligned;
 pub mod instrs;
 pub use instrs as asm;
 pub mod boot;
+pub mod cpuinfo;
 
 #[cfg(feature = "uspace")]
 pub mod userspace;
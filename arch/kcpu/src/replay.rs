@@ -0,0 +1,470 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Deterministic record and replay of nondeterministic events.
+//!
+//! In record mode the kernel logs every interrupt it takes, every
+//! scheduling decision and every timer read into a caller-provided buffer.
+//! In replay mode timer reads return the logged values, scheduling decisions
+//! are checked against the log and interrupts are re-injected at the point
+//! they arrived, relative to the other events, rather than by the hardware.
+//! The first mismatch ends the replay and is kept as a [`Divergence`].
+//!
+//! This module only keeps the log; the hooks live in `khal` and `ktask`.
+//! Both modes are meant for uniprocessor runs: the log is a single stream
+//! and is protected by disabling local interrupts only.
+//!
+//! # Encoding
+//!
+//! Each event is a tag byte followed by LEB128 fields:
+//!
+//! - interrupt: IRQ number, interrupted instruction pointer;
+//! - schedule: ID of the next task;
+//! - time: zigzag-encoded difference from the previous time read.
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+const TAG_IRQ: u8 = 1;
+const TAG_SCHEDULE: u8 = 2;
+const TAG_TIME: u8 = 3;
+
+/// Longest encoding of an event.
+pub const MAX_EVENT_LEN: usize = 1 + 2 * 10;
+
+/// Events an interrupt handler may log, see [`begin_irq`].
+const MAX_IRQ_EVENTS: usize = 32;
+
+/// What is done with events.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Off = 0,
+    Record = 1,
+    Replay = 2,
+}
+
+/// A nondeterministic event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// An interrupt was taken at `ip`.
+    Irq { irq: usize, ip: usize },
+    /// The scheduler switched to task `task`.
+    Schedule { task: u64 },
+    /// A timer read returned `ns`.
+    Time { ns: u64 },
+}
+
+/// Where a replay stopped following its log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    /// Offset of the unmatched event in the log.
+    pub offset: usize,
+    /// The logged event, `None` past the end of the log.
+    pub expected: Option<Event>,
+    /// What happened instead.
+    pub found: Event,
+}
+
+/// An event log over a byte buffer.
+pub struct Trace<'a> {
+    buf: &'a mut [u8],
+    /// End of the log.
+    len: usize,
+    /// Next event to replay.
+    pos: usize,
+    /// Last time read, the base of the next time delta.
+    last_time: u64,
+    overflowed: bool,
+}
+
+fn put_varint(buf: &mut [u8], mut value: u64) -> usize {
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf[len] = byte;
+            return len + 1;
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+}
+
+fn get_varint(buf: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *buf.get(*pos)?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+impl<'a> Trace<'a> {
+    /// Starts an empty log in `buf`.
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            len: 0,
+            pos: 0,
+            last_time: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Opens the log in the first `len` bytes of `buf` for replay.
+    pub fn open(buf: &'a mut [u8], len: usize) -> Self {
+        let len = len.min(buf.len());
+        Self {
+            buf,
+            len,
+            pos: 0,
+            last_time: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Length of the log in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether events were dropped because the buffer was full. The log
+    /// then holds the events before the first one dropped.
+    pub fn is_overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    /// Offset of the next event to replay.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Appends `event`, returning `false` if it does not fit.
+    pub fn push(&mut self, event: Event) -> bool {
+        let mut encoded = [0u8; MAX_EVENT_LEN];
+        let len = match event {
+            Event::Irq { irq, ip } => {
+                encoded[0] = TAG_IRQ;
+                let len = 1 + put_varint(&mut encoded[1..], irq as u64);
+                len + put_varint(&mut encoded[len..], ip as u64)
+            }
+            Event::Schedule { task } => {
+                encoded[0] = TAG_SCHEDULE;
+                1 + put_varint(&mut encoded[1..], task)
+            }
+            Event::Time { ns } => {
+                let delta = ns.wrapping_sub(self.last_time) as i64;
+                encoded[0] = TAG_TIME;
+                1 + put_varint(&mut encoded[1..], ((delta << 1) ^ (delta >> 63)) as u64)
+            }
+        };
+        if self.overflowed || self.buf.len() - self.len < len {
+            self.overflowed = true;
+            return false;
+        }
+        if let Event::Time { ns } = event {
+            self.last_time = ns;
+        }
+        self.buf[self.len..self.len + len].copy_from_slice(&encoded[..len]);
+        self.len += len;
+        true
+    }
+
+    fn decode(&self) -> Option<(Event, usize)> {
+        let log = &self.buf[..self.len];
+        let mut pos = self.pos;
+        let tag = *log.get(pos)?;
+        pos += 1;
+        let event = match tag {
+            TAG_IRQ => Event::Irq {
+                irq: get_varint(log, &mut pos)? as usize,
+                ip: get_varint(log, &mut pos)? as usize,
+            },
+            TAG_SCHEDULE => Event::Schedule {
+                task: get_varint(log, &mut pos)?,
+            },
+            TAG_TIME => {
+                let zigzag = get_varint(log, &mut pos)?;
+                let delta = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+                Event::Time {
+                    ns: self.last_time.wrapping_add(delta as u64),
+                }
+            }
+            _ => return None,
+        };
+        Some((event, pos))
+    }
+
+    /// Returns the next event to replay, or `None` at the end of the log or
+    /// at a malformed event.
+    pub fn peek(&self) -> Option<Event> {
+        self.decode().map(|(event, _)| event)
+    }
+
+    /// Consumes the next event to replay.
+    pub fn next_event(&mut self) -> Option<Event> {
+        let (event, pos) = self.decode()?;
+        if let Event::Time { ns } = event {
+            self.last_time = ns;
+        }
+        self.pos = pos;
+        Some(event)
+    }
+
+    /// Gives the buffer back.
+    pub fn into_buf(self) -> &'a mut [u8] {
+        self.buf
+    }
+}
+
+/// The log of a session, handed back by [`stop`].
+pub struct Stopped {
+    pub buf: &'static mut [u8],
+    /// Length of the log in `buf`.
+    pub len: usize,
+    pub overflowed: bool,
+    /// Why a replay ended early.
+    pub divergence: Option<Divergence>,
+}
+
+struct Session {
+    trace: Trace<'static>,
+    divergence: Option<Divergence>,
+    in_irq: bool,
+    /// Events logged by the running interrupt handler.
+    irq_events: [Event; MAX_IRQ_EVENTS],
+    irq_events_len: usize,
+}
+
+struct SessionCell(UnsafeCell<Option<Session>>);
+
+// Safety: only accessed with local IRQs disabled on a uniprocessor
+unsafe impl Sync for SessionCell {}
+
+static MODE: AtomicU8 = AtomicU8::new(Mode::Off as u8);
+static SESSION: SessionCell = SessionCell(UnsafeCell::new(None));
+
+fn with_session<R>(f: impl FnOnce(&mut Option<Session>) -> R) -> R {
+    let flags = kplat::interrupts::save_disable();
+    // Safety: see `SessionCell`
+    let result = f(unsafe { &mut *SESSION.0.get() });
+    kplat::interrupts::restore(flags);
+    result
+}
+
+/// Returns the current mode.
+#[inline]
+pub fn mode() -> Mode {
+    match MODE.load(Ordering::Acquire) {
+        1 => Mode::Record,
+        2 => Mode::Replay,
+        _ => Mode::Off,
+    }
+}
+
+fn start(trace: Trace<'static>, mode: Mode) -> Result<(), &'static mut [u8]> {
+    with_session(|session| {
+        if session.is_some() {
+            return Err(trace.into_buf());
+        }
+        *session = Some(Session {
+            trace,
+            divergence: None,
+            in_irq: false,
+            irq_events: [Event::Time { ns: 0 }; MAX_IRQ_EVENTS],
+            irq_events_len: 0,
+        });
+        MODE.store(mode as u8, Ordering::Release);
+        Ok(())
+    })
+}
+
+/// Starts logging events into `buf`.
+///
+/// Fails, giving `buf` back, while the log of an earlier session has not
+/// been collected with [`stop`].
+pub fn start_record(buf: &'static mut [u8]) -> Result<(), &'static mut [u8]> {
+    start(Trace::new(buf), Mode::Record)
+}
+
+/// Starts replaying the log in the first `len` bytes of `buf`.
+///
+/// Fails like [`start_record`].
+pub fn start_replay(buf: &'static mut [u8], len: usize) -> Result<(), &'static mut [u8]> {
+    start(Trace::open(buf, len), Mode::Replay)
+}
+
+/// Ends the session, if any, and hands back its log.
+pub fn stop() -> Option<Stopped> {
+    with_session(|session| {
+        MODE.store(Mode::Off as u8, Ordering::Release);
+        let Session {
+            trace, divergence, ..
+        } = session.take()?;
+        let (len, overflowed) = (trace.len(), trace.is_overflowed());
+        Some(Stopped {
+            buf: trace.into_buf(),
+            len,
+            overflowed,
+            divergence,
+        })
+    })
+}
+
+/// Logs `event` in record mode.
+pub fn record(event: Event) {
+    if mode() != Mode::Record {
+        return;
+    }
+    with_session(|session| {
+        let Some(session) = session else {
+            return;
+        };
+        if !session.in_irq {
+            session.trace.push(event);
+        } else if session.irq_events_len < MAX_IRQ_EVENTS {
+            session.irq_events[session.irq_events_len] = event;
+            session.irq_events_len += 1;
+        } else {
+            session.trace.overflowed = true;
+        }
+    });
+}
+
+/// Starts holding back the events logged by an interrupt handler.
+///
+/// The interrupt number is only known once the handler ran, while a replay
+/// must inject the interrupt before the handler logs anything: [`end_irq`]
+/// logs the interrupt first, then the events held back.
+pub fn begin_irq() {
+    if mode() != Mode::Record {
+        return;
+    }
+    with_session(|session| {
+        if let Some(session) = session {
+            session.in_irq = true;
+            session.irq_events_len = 0;
+        }
+    });
+}
+
+/// Logs interrupt `irq` taken at `ip`, if any, and the events of its
+/// handler.
+pub fn end_irq(irq: Option<usize>, ip: usize) {
+    if mode() != Mode::Record {
+        return;
+    }
+    with_session(|session| {
+        let Some(session) = session else {
+            return;
+        };
+        session.in_irq = false;
+        if let Some(irq) = irq {
+            session.trace.push(Event::Irq { irq, ip });
+        }
+        for event in &session.irq_events[..session.irq_events_len] {
+            session.trace.push(*event);
+        }
+        session.irq_events_len = 0;
+    });
+}
+
+/// Returns the next event to replay without consuming it.
+pub fn peek() -> Option<Event> {
+    if mode() != Mode::Replay {
+        return None;
+    }
+    with_session(|session| session.as_ref()?.trace.peek())
+}
+
+/// Consumes the next event to replay if it matches `found`, as decided by
+/// `matches`, and returns it.
+///
+/// Otherwise the replay ends: the mismatch is kept for [`stop`] and `None`
+/// is returned. The replay also ends quietly at the end of the log.
+pub fn replay(found: Event, matches: impl FnOnce(&Event) -> bool) -> Option<Event> {
+    if mode() != Mode::Replay {
+        return None;
+    }
+    with_session(|session| {
+        let session = session.as_mut()?;
+        let expected = session.trace.peek();
+        match expected {
+            Some(event) if matches(&event) => session.trace.next_event(),
+            None if session.trace.position() == session.trace.len() => {
+                MODE.store(Mode::Off as u8, Ordering::Release);
+                None
+            }
+            _ => {
+                session.divergence = Some(Divergence {
+                    offset: session.trace.position(),
+                    expected,
+                    found,
+                });
+                MODE.store(Mode::Off as u8, Ordering::Release);
+                None
+            }
+        }
+    })
+}
+
+#[cfg(unittest)]
+pub mod tests_replay {
+    use unittest::def_test;
+
+    use super::*;
+
+    #[def_test]
+    fn test_trace_roundtrip() {
+        let events = [
+            Event::Time { ns: 1_000_000 },
+            Event::Irq {
+                irq: 30,
+                ip: 0xffff_0000_4008_1234,
+            },
+            Event::Time { ns: 1_000_500 },
+            // Time reads may go backwards across clocks
+            Event::Time { ns: 999_000 },
+            Event::Schedule { task: 7 },
+        ];
+        let mut buf = [0u8; 64];
+        let mut trace = Trace::new(&mut buf);
+        for event in events {
+            assert!(trace.push(event));
+        }
+        let len = trace.len();
+        // Small time deltas take a few bytes each
+        assert!(len < 32);
+
+        let mut replay = Trace::open(trace.into_buf(), len);
+        for event in events {
+            assert_eq!(replay.peek(), Some(event));
+            assert_eq!(replay.next_event(), Some(event));
+        }
+        assert_eq!(replay.next_event(), None);
+    }
+
+    #[def_test]
+    fn test_trace_overflow_keeps_prefix() {
+        let mut buf = [0u8; 4];
+        let mut trace = Trace::new(&mut buf);
+        assert!(trace.push(Event::Schedule { task: 1 }));
+        assert!(!trace.push(Event::Time { ns: 1 << 40 }));
+        // Nothing after a dropped event is logged
+        assert!(!trace.push(Event::Schedule { task: 2 }));
+        assert!(trace.is_overflowed());
+        assert_eq!(trace.len(), 2);
+    }
+}
@@ -0,0 +1,21 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! CPU identification from the `riscv,isa` string of the device tree.
+//!
+//! `misa` and the vendor CSRs are only readable from M-mode, so the vendor,
+//! model and caches are left unknown.
+
+pub use crate::cpuinfo_common::{CacheInfo, CacheKind, CpuInfo, Feature, Features};
+
+/// Identifies the CPU whose device tree node has `isa` as `riscv,isa`.
+pub fn detect(isa: &'static str) -> CpuInfo {
+    let mut info = CpuInfo::new("unknown", isa);
+    let (features, hwcap) = crate::cpuinfo_common::parse_riscv_isa(isa);
+    for feature in features.iter() {
+        info.set_feature(feature, true);
+    }
+    info.set_hwcap(hwcap);
+    info
+}
@@ -338,6 +338,9 @@ pub struct TaskContext {
     /// The `satp` register value, i.e., the page table root.
     #[cfg(feature = "uspace")]
     pub satp: memaddr::PhysAddr,
+    /// The ASID of the page table, or 0.
+    #[cfg(feature = "uspace")]
+    pub asid: u16,
     #[cfg(feature = "fp-simd")]
     pub fp_state: FpState,
 }
@@ -375,6 +378,13 @@ impl TaskContext {
         self.satp = satp;
     }
 
+    /// Changes the address space identifier (ASID) in this context, the one
+    /// the TLB entries of its page table are tagged with, or 0 for none.
+    #[cfg(feature = "uspace")]
+    pub fn set_asid(&mut self, asid: u16) {
+        self.asid = asid;
+    }
+
     /// Switches to another task.
     ///
     /// It first saves the current task's context from CPU to this place, and then
@@ -386,9 +396,12 @@ impl TaskContext {
             unsafe { crate::instrs::write_thread_pointer(next_ctx.tp) };
         }
         #[cfg(feature = "uspace")]
-        if self.satp != next_ctx.satp {
-            unsafe { crate::instrs::write_user_page_table(next_ctx.satp) };
-            crate::instrs::flush_tlb(None); // currently flush the entire TLB
+        if self.satp != next_ctx.satp || self.asid != next_ctx.asid {
+            unsafe { crate::instrs::write_user_page_table_asid(next_ctx.satp, next_ctx.asid) };
+            if next_ctx.asid == 0 {
+                // the entries of other ASIDs stay valid
+                crate::instrs::flush_tlb_asid(None, 0);
+            }
         }
         #[cfg(feature = "fp-simd")]
         {
@@ -4,11 +4,10 @@
 
 //! Wrapper functions for assembly instructions.
 
+use core::arch::asm;
+
 use memaddr::{PhysAddr, VirtAddr};
-use riscv::{
-    asm,
-    register::{satp, sstatus, stvec},
-};
+use riscv::register::{satp, sstatus, stvec};
 
 /// Allows the current CPU to respond to interrupts.
 #[inline]
@@ -97,16 +96,65 @@ pub unsafe fn write_kernel_page_table(root_paddr: PhysAddr) {
     unsafe { write_user_page_table(root_paddr) };
 }
 
+/// Returns the largest address space identifier (ASID) the current CPU
+/// implements in `satp`, or 0 if there are none.
+///
+/// The ASIDs need no enabling on RISC-V, the width of the field is probed
+/// by writing ones to it.
+pub fn enable_asid() -> u16 {
+    const ASID_MASK: usize = 0xffff << 44;
+    let satp: usize;
+    let probe: usize;
+    unsafe {
+        asm!("csrr {}, satp", out(reg) satp);
+        asm!("csrw satp, {}", in(reg) satp | ASID_MASK);
+        asm!("csrr {}, satp", out(reg) probe);
+        asm!("csrw satp, {}", in(reg) satp);
+    }
+    ((probe & ASID_MASK) >> 44) as u16
+}
+
+/// Writes the register to update the current page table root for user space
+/// (`satp`), tagging the TLB entries with `asid`.
+///
+/// Note that the TLB is **NOT** flushed after this operation.
+///
+/// # Safety
+///
+/// This function is unsafe as it changes the virtual memory address space.
+/// `asid` must not exceed the value returned by [`enable_asid`].
+#[inline]
+pub unsafe fn write_user_page_table_asid(root_paddr: PhysAddr, asid: u16) {
+    unsafe { satp::set(satp::Mode::Sv39, asid as usize, root_paddr.as_usize() >> 12) };
+}
+
 /// Flushes the TLB.
 ///
 /// If `vaddr` is [`None`], flushes the entire TLB. Otherwise, flushes the TLB
-/// entry that maps the given virtual address.
+/// entries that map the given virtual address, in all address spaces.
 #[inline]
 pub fn flush_tlb(vaddr: Option<VirtAddr>) {
-    if let Some(vaddr) = vaddr {
-        asm::sfence_vma(0, vaddr.as_usize())
-    } else {
-        asm::sfence_vma_all();
+    unsafe {
+        if let Some(vaddr) = vaddr {
+            asm!("sfence.vma {}, zero", in(reg) vaddr.as_usize());
+        } else {
+            asm!("sfence.vma");
+        }
+    }
+}
+
+/// Flushes the TLB entries tagged with `asid`.
+///
+/// If `vaddr` is [`None`], flushes all of them. Otherwise, flushes the one
+/// that maps the given virtual address.
+#[inline]
+pub fn flush_tlb_asid(vaddr: Option<VirtAddr>, asid: u16) {
+    unsafe {
+        if let Some(vaddr) = vaddr {
+            asm!("sfence.vma {}, {}", in(reg) vaddr.as_usize(), in(reg) asid as usize);
+        } else {
+            asm!("sfence.vma zero, {}", in(reg) asid as usize);
+        }
     }
 }
 
@@ -13,6 +13,7 @@ mod excp;
 pub mod instrs;
 pub use instrs as asm;
 pub mod boot;
+pub mod cpuinfo;
 
 #[cfg(feature = "uspace")]
 pub mod userspace;
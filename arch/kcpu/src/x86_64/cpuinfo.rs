@@ -0,0 +1,118 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! CPU identification from CPUID.
+
+use x86::cpuid::{CpuIdResult, native_cpuid::cpuid_count};
+
+pub use crate::cpuinfo_common::{CacheInfo, CacheKind, CpuInfo, Feature, Features};
+
+fn cpuid(leaf: u32, sub_leaf: u32) -> CpuIdResult {
+    cpuid_count(leaf, sub_leaf)
+}
+
+fn bit(reg: u32, bit: u32) -> bool {
+    reg & (1 << bit) != 0
+}
+
+fn vendor_name(vendor: &[u8; 12]) -> &'static str {
+    match vendor {
+        b"GenuineIntel" => "GenuineIntel",
+        b"AuthenticAMD" => "AuthenticAMD",
+        b"HygonGenuine" => "HygonGenuine",
+        b"CentaurHauls" => "CentaurHauls",
+        b"  Shanghai  " => "Shanghai",
+        _ => "unknown",
+    }
+}
+
+/// Reads the caches from the deterministic cache parameters, leaf 4 on
+/// Intel and 0x8000_001d on AMD.
+fn detect_caches(info: &mut CpuInfo, leaf: u32) {
+    for sub_leaf in 0.. {
+        let CpuIdResult { eax, ebx, ecx, .. } = cpuid(leaf, sub_leaf);
+        let kind = match eax & 0x1f {
+            1 => CacheKind::Data,
+            2 => CacheKind::Instruction,
+            3 => CacheKind::Unified,
+            _ => break,
+        };
+        let ways = (ebx >> 22) as usize + 1;
+        let partitions = ((ebx >> 12) & 0x3ff) as usize + 1;
+        let line_size = (ebx & 0xfff) as usize + 1;
+        info.add_cache(CacheInfo::new(
+            ((eax >> 5) & 0b111) as u8,
+            kind,
+            line_size,
+            ways,
+            partitions * (ecx as usize + 1),
+        ));
+    }
+}
+
+/// Identifies the current CPU.
+pub fn detect() -> CpuInfo {
+    let leaf0 = cpuid(0, 0);
+    let mut vendor = [0; 12];
+    vendor[..4].copy_from_slice(&leaf0.ebx.to_le_bytes());
+    vendor[4..8].copy_from_slice(&leaf0.edx.to_le_bytes());
+    vendor[8..].copy_from_slice(&leaf0.ecx.to_le_bytes());
+    let mut info = CpuInfo::new(vendor_name(&vendor), "x86_64");
+    let max_leaf = leaf0.eax;
+    let max_ext_leaf = cpuid(0x8000_0000, 0).eax;
+
+    let leaf1 = cpuid(1, 0);
+    let signature = leaf1.eax;
+    let base_family = (signature >> 8) & 0xf;
+    let mut family = base_family;
+    let mut model = (signature >> 4) & 0xf;
+    if base_family == 0xf {
+        family += (signature >> 20) & 0xff;
+    }
+    if base_family == 0x6 || base_family == 0xf {
+        model |= ((signature >> 16) & 0xf) << 4;
+    }
+    info.set_signature(signature as u64, family, model, signature & 0xf);
+
+    if max_ext_leaf >= 0x8000_0004 {
+        let mut brand = [0; 48];
+        for (i, leaf) in (0x8000_0002..=0x8000_0004).enumerate() {
+            let CpuIdResult { eax, ebx, ecx, edx } = cpuid(leaf, 0);
+            for (j, reg) in [eax, ebx, ecx, edx].into_iter().enumerate() {
+                let at = i * 16 + j * 4;
+                brand[at..at + 4].copy_from_slice(&reg.to_le_bytes());
+            }
+        }
+        info.set_model_name(&brand);
+    }
+
+    let (ecx, edx) = (leaf1.ecx, leaf1.edx);
+    let leaf7 = if max_leaf >= 7 { cpuid(7, 0).ebx } else { 0 };
+    info.set_feature(Feature::Fp, bit(edx, 0));
+    info.set_feature(Feature::Simd, bit(edx, 26));
+    info.set_feature(Feature::Simd256, bit(leaf7, 5));
+    info.set_feature(Feature::Simd512, bit(leaf7, 16));
+    info.set_feature(Feature::Aes, bit(ecx, 25));
+    info.set_feature(Feature::Pmull, bit(ecx, 1));
+    info.set_feature(Feature::Sha1, bit(leaf7, 29));
+    info.set_feature(Feature::Sha2, bit(leaf7, 29));
+    // SSE4.2
+    info.set_feature(Feature::Crc32, bit(ecx, 20));
+    info.set_feature(Feature::Atomics, true);
+    info.set_feature(Feature::Rng, bit(ecx, 30));
+    // Linux gives the feature flags of leaf 1 as they are
+    info.set_hwcap(edx as usize);
+
+    match info.vendor() {
+        "AuthenticAMD" | "HygonGenuine" => {
+            let topology_ext = bit(cpuid(0x8000_0001, 0).ecx, 22);
+            if max_ext_leaf >= 0x8000_001d && topology_ext {
+                detect_caches(&mut info, 0x8000_001d);
+            }
+        }
+        _ if max_leaf >= 4 => detect_caches(&mut info, 4),
+        _ => {}
+    }
+    info
+}
@@ -283,6 +283,9 @@ pub struct TaskContext {
     /// The `CR3` register value, i.e., the page table root.
     #[cfg(feature = "uspace")]
     pub cr3: memaddr::PhysAddr,
+    /// The ASID of the page table, or 0.
+    #[cfg(feature = "uspace")]
+    pub asid: u16,
 }
 
 impl TaskContext {
@@ -300,6 +303,8 @@ impl TaskContext {
             fs_base: 0,
             #[cfg(feature = "uspace")]
             cr3: crate::instrs::read_kernel_page_table(),
+            #[cfg(feature = "uspace")]
+            asid: 0,
             #[cfg(feature = "fp-simd")]
             ext_state: ExtendedState::default(),
         }
@@ -336,6 +341,13 @@ impl TaskContext {
         self.cr3 = cr3;
     }
 
+    /// Changes the address space identifier (ASID) in this context, the one
+    /// the TLB entries of its page table are tagged with, or 0 for none.
+    #[cfg(feature = "uspace")]
+    pub fn set_asid(&mut self, asid: u16) {
+        self.asid = asid;
+    }
+
     /// Switches to another task.
     ///
     /// It first saves the current task's context from CPU to this place, and then
@@ -353,9 +365,9 @@ impl TaskContext {
         }
         #[cfg(feature = "uspace")]
         unsafe {
-            if next_ctx.cr3 != self.cr3 {
-                crate::instrs::write_user_page_table(next_ctx.cr3);
-                // writing to CR3 has flushed the TLB
+            if next_ctx.cr3 != self.cr3 || next_ctx.asid != self.asid {
+                // without a PCID, writing to CR3 flushes the TLB
+                crate::instrs::write_user_page_table_asid(next_ctx.cr3, next_ctx.asid);
             }
         }
         unsafe { context_switch(&mut self.rsp, &next_ctx.rsp) }
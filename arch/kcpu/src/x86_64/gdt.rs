@@ -9,7 +9,7 @@ use x86_64::{
     instructions::tables::load_tss,
     registers::segmentation::{CS, Segment, SegmentSelector},
     structures::{
-        gdt::{Descriptor, GlobalDescriptorTable},
+        gdt::{Descriptor, DescriptorFlags, GlobalDescriptorTable},
         tss::TaskStateSegment,
     },
 };
@@ -19,7 +19,7 @@ use x86_64::{
 static TSS: TaskStateSegment = TaskStateSegment::new();
 
 #[percpu::def_percpu]
-static GDT: GlobalDescriptorTable = GlobalDescriptorTable::new();
+static GDT: GlobalDescriptorTable<9> = GlobalDescriptorTable::empty();
 
 /// Kernel code segment for 64-bit mode.
 pub const KCODE64: SegmentSelector = SegmentSelector::new(1, PrivilegeLevel::Ring0);
@@ -29,6 +29,10 @@ pub const KDATA: SegmentSelector = SegmentSelector::new(2, PrivilegeLevel::Ring0
 pub const UDATA: SegmentSelector = SegmentSelector::new(3, PrivilegeLevel::Ring3);
 /// User code segment for 64-bit mode.
 pub const UCODE64: SegmentSelector = SegmentSelector::new(4, PrivilegeLevel::Ring3);
+/// User code segment for 32-bit compatibility mode.
+pub const UCODE32: SegmentSelector = SegmentSelector::new(5, PrivilegeLevel::Ring3);
+/// User data segment whose base is the TLS area of 32-bit programs.
+pub const UTLS32: SegmentSelector = SegmentSelector::new(6, PrivilegeLevel::Ring3);
 
 /// Initializes the per-CPU TSS and GDT structures and loads them into the
 /// current CPU.
@@ -38,6 +42,14 @@ pub(super) fn init() {
     assert_eq!(gdt.append(Descriptor::kernel_data_segment()), KDATA);
     assert_eq!(gdt.append(Descriptor::user_data_segment()), UDATA);
     assert_eq!(gdt.append(Descriptor::user_code_segment()), UCODE64);
+    assert_eq!(
+        gdt.append(Descriptor::UserSegment(DescriptorFlags::USER_CODE32.bits())),
+        UCODE32
+    );
+    assert_eq!(
+        gdt.append(Descriptor::UserSegment(DescriptorFlags::USER_DATA.bits())),
+        UTLS32
+    );
     let tss = gdt.append(Descriptor::tss_segment(unsafe { TSS.current_ref_raw() }));
     gdt.load();
     unsafe {
@@ -45,3 +57,22 @@ pub(super) fn init() {
         load_tss(tss);
     }
 }
+
+/// Points the [`UTLS32`] segment of the current CPU at `base`.
+///
+/// 32-bit programs reach their TLS by loading this segment into `%gs`, so it
+/// is rewritten every time one of them is entered.
+#[cfg(feature = "uspace")]
+pub(super) fn set_compat_tls(base: u32) {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use x86_64::structures::gdt::Entry;
+
+    let base = base as u64;
+    let raw = DescriptorFlags::USER_DATA.bits() | (base & 0xff_ffff) << 16 | (base >> 24) << 56;
+    let gdt = unsafe { GDT.current_ref_raw() };
+    let entry: *const Entry = &gdt.entries()[UTLS32.index() as usize];
+    // SAFETY: `Entry` is a transparent wrapper of `AtomicU64` on x86_64.
+    let entry = unsafe { &*(entry as *const AtomicU64) };
+    entry.store(raw, Ordering::Relaxed);
+}
@@ -4,11 +4,23 @@
 
 //! Wrapper functions for assembly instructions.
 
-use core::arch::asm;
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use memaddr::{MemoryAddr, PhysAddr, VirtAddr};
-use x86::{controlregs, msr, tlb};
-use x86_64::instructions::interrupts;
+use x86::{controlregs, cpuid::CpuId, msr, tlb};
+use x86_64::{
+    instructions::{
+        interrupts,
+        tlb::{InvPcidCommand, Pcid, flush_pcid},
+    },
+    registers::control::{Cr4, Cr4Flags},
+};
+
+/// Whether the TLB entries are tagged with PCIDs.
+static PCID_ENABLED: AtomicBool = AtomicBool::new(false);
 
 /// Allows the current CPU to respond to interrupts.
 #[inline]
@@ -111,17 +123,78 @@ pub unsafe fn write_kernel_page_table(root_paddr: PhysAddr) {
     unsafe { write_user_page_table(root_paddr) }
 }
 
+/// Enables process-context identifiers (PCIDs) on the current CPU, if it
+/// supports them and the `INVPCID` instruction.
+///
+/// Returns the largest PCID usable with [`write_user_page_table_asid`], or 0
+/// if there are none. PCID 0 is the one of [`write_user_page_table`].
+pub fn enable_asid() -> u16 {
+    let cpuid = CpuId::new();
+    let supported = cpuid.get_feature_info().is_some_and(|f| f.has_pcid())
+        && cpuid
+            .get_extended_feature_info()
+            .is_some_and(|f| f.has_invpcid());
+    if !supported {
+        return 0;
+    }
+    unsafe { Cr4::update(|flags| flags.insert(Cr4Flags::PCID)) };
+    PCID_ENABLED.store(true, Ordering::Relaxed);
+    4095
+}
+
+/// Writes the register to update the current page table root for user space
+/// (`CR3`), tagging the TLB entries with the PCID `asid`.
+///
+/// The TLB is **NOT** flushed after this operation, unless `asid` is 0, in
+/// which case this is the same as [`write_user_page_table`].
+///
+/// # Safety
+///
+/// This function is unsafe as it changes the virtual memory address space.
+/// `asid` must not exceed the value returned by [`enable_asid`].
+#[inline]
+pub unsafe fn write_user_page_table_asid(root_paddr: PhysAddr, asid: u16) {
+    if asid == 0 {
+        unsafe { write_user_page_table(root_paddr) }
+    } else {
+        // Bit 63 keeps the entries of the PCID.
+        let cr3 = root_paddr.as_usize() as u64 | asid as u64 | 1 << 63;
+        unsafe { controlregs::cr3_write(cr3) }
+    }
+}
+
 /// Flushes the TLB.
 ///
 /// If `vaddr` is [`None`], flushes the entire TLB. Otherwise, flushes the TLB
 /// entry that maps the given virtual address.
 #[inline]
 pub fn flush_tlb(vaddr: Option<VirtAddr>) {
-    if let Some(vaddr) = vaddr {
-        unsafe { tlb::flush(vaddr.into()) }
-    } else {
-        unsafe { tlb::flush_all() }
+    let pcid = PCID_ENABLED.load(Ordering::Relaxed);
+    match vaddr {
+        // `invlpg` only flushes the entries of the current PCID, while the
+        // kernel mappings are cached under all of them.
+        Some(vaddr) if !pcid || vaddr.as_usize() < 1 << 47 => unsafe { tlb::flush(vaddr.into()) },
+        _ if pcid => unsafe { flush_pcid(InvPcidCommand::All) },
+        _ => unsafe { tlb::flush_all() },
+    }
+}
+
+/// Flushes the TLB entries tagged with the PCID `asid`.
+///
+/// If `vaddr` is [`None`], flushes all of them. Otherwise, flushes the one
+/// that maps the given virtual address. Without PCIDs, this is the same as
+/// [`flush_tlb`].
+#[inline]
+pub fn flush_tlb_asid(vaddr: Option<VirtAddr>, asid: u16) {
+    if !PCID_ENABLED.load(Ordering::Relaxed) {
+        return flush_tlb(vaddr);
     }
+    let pcid = Pcid::new(asid).expect("PCID out of range");
+    let command = match vaddr {
+        Some(vaddr) => InvPcidCommand::Address(x86_64::VirtAddr::new(vaddr.as_usize() as _), pcid),
+        None => InvPcidCommand::Single(pcid),
+    };
+    unsafe { flush_pcid(command) }
 }
 
 /// Reads the thread pointer of the current CPU (`FS_BASE`).
@@ -12,6 +12,7 @@ pub mod instrs;
 pub use instrs as asm;
 pub use instrs::hypercall;
 pub mod boot;
+pub mod cpuinfo;
 
 mod excp;
 
@@ -55,14 +55,41 @@ impl UserContext {
         }
     }
 
+    /// Creates a new context that enters user space in 32-bit compatibility
+    /// mode, for running i386 binaries.
+    pub fn new_compat(entry: usize, ustack_top: VirtAddr, arg0: usize) -> Self {
+        let mut uctx = Self::new(entry, ustack_top, arg0);
+        uctx.tf.cs = gdt::UCODE32.0 as _;
+        uctx
+    }
+
+    /// The GDT entry that 32-bit programs load into `%gs` for their TLS, as
+    /// reported by `set_thread_area`.
+    pub const COMPAT_TLS_ENTRY: usize = (gdt::UTLS32.0 >> 3) as usize;
+
+    /// Whether this context runs in 32-bit compatibility mode.
+    pub const fn is_compat(&self) -> bool {
+        self.tf.cs == gdt::UCODE32.0 as u64
+    }
+
     /// Gets the TLS area.
+    ///
+    /// 32-bit programs address their TLS through `%gs` instead of `%fs`.
     pub const fn tls(&self) -> usize {
-        self.fs_base as _
+        if self.is_compat() {
+            self.gs_base as _
+        } else {
+            self.fs_base as _
+        }
     }
 
     /// Sets the TLS area.
     pub const fn set_tls(&mut self, tls_area: usize) {
-        self.fs_base = tls_area as _;
+        if self.is_compat() {
+            self.gs_base = tls_area as _;
+        } else {
+            self.fs_base = tls_area as _;
+        }
     }
 
     /// Enters user space.
@@ -76,11 +103,15 @@ impl UserContext {
             unsafe fn enter_user(uctx: &mut UserContext);
         }
 
-        assert_eq!(self.cs, gdt::UCODE64.0 as _);
+        assert!(self.cs == gdt::UCODE64.0 as _ || self.is_compat());
         assert_eq!(self.ss, gdt::UDATA.0 as _);
 
         crate::instrs::disable_local();
 
+        if self.is_compat() {
+            gdt::set_compat_tls(self.gs_base as _);
+        }
+
         let kernel_fs_base = read_thread_pointer();
         unsafe { write_thread_pointer(self.fs_base as _) };
         KernelGsBase::write(x86_64::VirtAddr::new_truncate(self.gs_base));
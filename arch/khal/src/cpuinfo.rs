@@ -0,0 +1,56 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Identification of the CPUs: vendor, model, ISA extensions and caches.
+//!
+//! The CPUs are assumed to be identical, so the boot CPU is identified once
+//! on behalf of all of them.
+
+use lazyinit::LazyInit;
+
+pub use kcpu::cpuinfo::{CacheInfo, CacheKind, CpuInfo, Feature, Features};
+
+static CPU_INFO: LazyInit<CpuInfo> = LazyInit::new();
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+fn detect() -> CpuInfo {
+    let isa = crate::dtb::get_fdt()
+        .and_then(|fdt| {
+            fdt.all_nodes()
+                .find_map(|node| node.find_property("riscv,isa"))
+        })
+        .map_or("", |prop| prop.str());
+    kcpu::cpuinfo::detect(isa)
+}
+
+#[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+fn detect() -> CpuInfo {
+    kcpu::cpuinfo::detect()
+}
+
+/// Identifies the boot CPU, and checks that it implements what the kernel
+/// was built to use.
+///
+/// # Panics
+///
+/// Panics if the `fp-simd` feature is enabled but the CPU has no FPU.
+pub fn init() {
+    let info = cpu_info();
+    info!(
+        "CPU: {} {} ({})",
+        info.vendor(),
+        info.model_name(),
+        info.isa()
+    );
+    info!("  features: {:?}", info.features());
+    if cfg!(feature = "fp-simd") && !info.has(Feature::Fp) {
+        panic!("built with FP/SIMD support, but the CPU has no FPU");
+    }
+}
+
+/// Returns the identification of the CPUs.
+pub fn cpu_info() -> &'static CpuInfo {
+    CPU_INFO.call_once(detect);
+    CPU_INFO.get().unwrap()
+}
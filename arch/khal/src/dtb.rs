@@ -5,7 +5,8 @@
 //! DTB (Device Tree Blob) related functionality.
 use core::ptr::NonNull;
 
-use fdt_parser::Fdt;
+use fdt_parser::{Fdt, Node};
+use heapless::Vec;
 use lazyinit::LazyInit;
 
 static BOOTARG: LazyInit<usize> = LazyInit::new();
@@ -56,6 +57,110 @@ pub fn get_chosen_bootargs() -> Option<&'static str> {
     *CACHED_BOOTARGS.init_once(init_bootargs())
 }
 
+/// Compatible strings and node names telling a serial port apart.
+const UART_HINTS: &[&str] = &["uart", "serial", "pl011", "16550", "8250"];
+
+fn reg_bases<'a>(node: &Node<'a>) -> impl Iterator<Item = usize> + 'a {
+    node.reg()
+        .into_iter()
+        .flatten()
+        .map(|reg| reg.address as usize)
+}
+
+/// Collects the register addresses of the matching nodes, for the warnings.
+fn find_bases(fdt: &Fdt<'static>, filter: impl Fn(&Node) -> bool) -> Vec<usize, 8> {
+    let mut bases = Vec::new();
+    for node in fdt.all_nodes().filter(|node| filter(node)) {
+        for base in reg_bases(&node) {
+            let _ = bases.push(base);
+        }
+    }
+    bases
+}
+
+/// Cross-checks the platform configuration the kernel was built with against
+/// the device tree the firmware passed, warning about each mismatch.
+///
+/// The CPU count, the RAM and the addresses of the console UART and of the
+/// interrupt controller are checked, to catch kernels built for the wrong
+/// variant of a board before their devices misbehave. Nothing is checked
+/// without a device tree.
+///
+/// Returns the number of mismatches.
+pub fn check_platconfig() -> usize {
+    let Some(fdt) = get_fdt() else {
+        return 0;
+    };
+    let mut mismatches = 0;
+
+    let cpus = fdt
+        .all_nodes()
+        .filter(|node| {
+            node.find_property("device_type")
+                .is_some_and(|prop| prop.str() == "cpu")
+        })
+        .count();
+    if cpus != 0 && cpus != kbuild_config::CPU_NUM {
+        warn!(
+            "platconfig: built for {} CPUs, but the device tree has {}",
+            kbuild_config::CPU_NUM,
+            cpus
+        );
+        mismatches += 1;
+    }
+
+    let ram = kplat::memory::total_ram();
+    let dt_ram: usize = fdt
+        .memory()
+        .flat_map(|memory| memory.regions())
+        .map(|region| region.size)
+        .sum();
+    if dt_ram != 0 && dt_ram != ram {
+        warn!(
+            "platconfig: built for {} MiB of RAM, but the device tree has {} MiB",
+            ram >> 20,
+            dt_ram >> 20
+        );
+        mismatches += 1;
+    }
+
+    if let Some(uart) = kplat::board::uart_paddr() {
+        let bases = find_bases(fdt, |node| {
+            let name = node.name();
+            UART_HINTS.iter().any(|hint| {
+                name.starts_with(hint) || node.compatibles().any(|it| it.contains(hint))
+            })
+        });
+        if !bases.contains(&uart) {
+            warn!(
+                "platconfig: built for a UART at {:#x}, but the device tree has serial ports at \
+                 {:#x?}",
+                uart, bases
+            );
+            mismatches += 1;
+        }
+    }
+
+    if let Some(intc) = kplat::board::intc_paddr() {
+        let bases = find_bases(fdt, |node| {
+            node.find_property("interrupt-controller").is_some()
+        });
+        if !bases.contains(&intc) {
+            warn!(
+                "platconfig: built for an interrupt controller at {:#x}, but the device tree has \
+                 them at {:#x?}",
+                intc, bases
+            );
+            mismatches += 1;
+        }
+    }
+
+    if mismatches == 0 {
+        info!("Device tree matches the platform configuration.");
+    }
+    mismatches
+}
+
 #[cfg(unittest)]
 #[allow(missing_docs)]
 pub mod tests_dtb {
@@ -5,12 +5,13 @@
 //! Dummy implementation of platform-related interfaces defined in [`kplat`].
 
 use kplat::{
+    board::BoardInfo,
     boot::BootHandler,
     impl_dev_interface,
     interrupts::{Handler, IntrManager, TargetCpu},
     io::ConsoleIf,
     memory::{HwMemory, MemRange},
-    sys::SysCtrl,
+    sys::{RebootMode, SysCtrl},
     timer::GlobalTimer,
 };
 
@@ -20,6 +21,7 @@ struct DummyMem;
 struct DummyTime;
 struct DummyPower;
 struct DummyIrq;
+struct DummyBoard;
 
 #[impl_dev_interface]
 impl BootHandler for DummyInit {
@@ -71,6 +73,10 @@ impl HwMemory for DummyMem {
         &[]
     }
 
+    fn cma_size() -> usize {
+        0
+    }
+
     fn p2v(_paddr: memaddr::PhysAddr) -> memaddr::VirtAddr {
         va!(0)
     }
@@ -102,6 +108,10 @@ impl GlobalTimer for DummyTime {
         0
     }
 
+    fn rtc_ns() -> Option<u64> {
+        None
+    }
+
     fn freq() -> u64 {
         0
     }
@@ -121,6 +131,10 @@ impl SysCtrl for DummyPower {
     fn shutdown() -> ! {
         unimplemented!()
     }
+
+    fn reboot(_mode: RebootMode) -> ! {
+        unimplemented!()
+    }
 }
 
 #[impl_dev_interface]
@@ -157,3 +171,14 @@ impl IntrManager for DummyIrq {
         false
     }
 }
+
+#[impl_dev_interface]
+impl BoardInfo for DummyBoard {
+    fn uart_paddr() -> Option<usize> {
+        None
+    }
+
+    fn intc_paddr() -> Option<usize> {
+        None
+    }
+}
@@ -0,0 +1,18 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! CPUs coming online and going offline.
+use knotifier::AtomicNotifierChain;
+
+/// A CPU changing state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuEvent {
+    /// The CPU is done with its bring-up, and about to run tasks.
+    Online(usize),
+    /// The CPU stops for good.
+    Offline(usize),
+}
+
+/// Notified of CPU state changes, on the CPU concerned.
+pub static CPU_NOTIFIER: AtomicNotifierChain<CpuEvent> = AtomicNotifierChain::new();
@@ -4,22 +4,36 @@
 
 //! Interrupt management.
 
-use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "replay")]
+use core::sync::atomic::AtomicBool;
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
 
+use kbuild_config::CPU_NUM;
 use kcpu::excp::{IRQ, register_trap_handler};
+use kmetrics::{Counter, Kind, MetricsWriter};
 #[cfg(feature = "ipi")]
 pub use kplat::interrupts::{TargetCpu, notify_cpu};
-pub use kplat::interrupts::{
-    dispatch_irq, enable, reg_handler as register, restore, save_disable, set_prio,
-    unreg_handler as unregister,
-};
-#[cfg(feature = "ipi")]
-pub use platconfig::devices::IPI_IRQ;
+pub use kplat::interrupts::{dispatch_irq, restore, save_disable, set_prio};
+#[cfg(not(feature = "replay"))]
+pub use kplat::interrupts::{enable, reg_handler as register, unreg_handler as unregister};
 #[cfg(feature = "ipi")]
-pub use platconfig::devices::IPI_IRQ;
+pub use kbuild_config::IPI_IRQ;
 
 static IRQ_HOOK: AtomicUsize = AtomicUsize::new(0);
 
+/// Number of IRQs with their own count.
+const MAX_COUNTED_IRQS: usize = 1024;
+
+static IRQS: Counter = Counter::new("xkernel_irq_vectors_total", "Interrupt vectors taken.");
+static IRQ_COUNTS: [AtomicU64; MAX_COUNTED_IRQS] = [const { AtomicU64::new(0) }; MAX_COUNTED_IRQS];
+/// Time spent in the IRQ handlers on each CPU, in nanoseconds.
+static IRQ_TIME_NS: [AtomicU64; CPU_NUM] = [const { AtomicU64::new(0) }; CPU_NUM];
+/// IRQ handlers, softirqs included, running on each CPU.
+static IRQ_NESTING: [AtomicUsize; CPU_NUM] = [const { AtomicUsize::new(0) }; CPU_NUM];
+
 /// Register a hook function called after an IRQ is dispatched.
 ///
 /// This function can be called only once; subsequent calls will return false.
@@ -44,25 +58,165 @@ pub fn register_irq_hook(hook: fn(usize)) -> bool {
 #[register_trap_handler(IRQ)]
 pub fn irq_handler(vector: usize) -> bool {
     let guard = kspin::NoPreempt::new();
+    let start_ns = crate::time::monotonic_time_nanos();
+    let nesting = &IRQ_NESTING[crate::percpu::this_cpu_id()];
+    nesting.fetch_add(1, Ordering::Relaxed);
 
-    if let Some(irq) = dispatch_irq(vector) {
-        let hook = IRQ_HOOK.load(Ordering::SeqCst);
-        if hook != 0 {
-            let hook = unsafe { core::mem::transmute::<usize, fn(usize)>(hook) };
-            hook(irq);
-        }
+    IRQS.inc();
+    #[cfg(feature = "replay")]
+    kcpu::replay::begin_irq();
+    let irq = dispatch_irq(vector);
+    if let Some(irq) = irq {
+        irq_dispatched(irq);
     }
+    #[cfg(feature = "replay")]
+    kcpu::replay::end_irq(
+        irq,
+        kcpu::active_exception_context().map_or(0, |tf| tf.ip()),
+    );
+    IRQ_TIME_NS[crate::percpu::this_cpu_id()].fetch_add(
+        crate::time::monotonic_time_nanos() - start_ns,
+        Ordering::Relaxed,
+    );
+    crate::softirq::run_pending();
+    nesting.fetch_sub(1, Ordering::Relaxed);
 
     let _ = guard; // rescheduling may occur when preemption is re-enabled.
     true
 }
 
+/// Returns whether the current CPU is running an IRQ handler or the
+/// softirqs.
+pub fn in_irq() -> bool {
+    IRQ_NESTING[crate::percpu::this_cpu_id()].load(Ordering::Relaxed) != 0
+}
+
+/// Returns the time spent in the IRQ handlers on CPU `cpu_id`, softirqs
+/// aside, in nanoseconds.
+pub fn irq_time_nanos(cpu_id: usize) -> u64 {
+    IRQ_TIME_NS
+        .get(cpu_id)
+        .map_or(0, |time| time.load(Ordering::Relaxed))
+}
+
+/// Accounts for IRQ `irq` once its handler ran and calls the hook.
+fn irq_dispatched(irq: usize) {
+    if let Some(count) = IRQ_COUNTS.get(irq) {
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+    let hook = IRQ_HOOK.load(Ordering::SeqCst);
+    if hook != 0 {
+        let hook = unsafe { core::mem::transmute::<usize, fn(usize)>(hook) };
+        hook(irq);
+    }
+}
+
+/// Handlers of the IRQs registered through [`register`], so that a replay
+/// can run them without the hardware.
+#[cfg(feature = "replay")]
+static HANDLERS: [AtomicUsize; MAX_COUNTED_IRQS] =
+    [const { AtomicUsize::new(0) }; MAX_COUNTED_IRQS];
+/// IRQs enabled through [`enable`].
+#[cfg(feature = "replay")]
+static ENABLED: [AtomicBool; MAX_COUNTED_IRQS] =
+    [const { AtomicBool::new(false) }; MAX_COUNTED_IRQS];
+/// Whether the enabled IRQs are masked for a replay.
+#[cfg(feature = "replay")]
+static MASKED: AtomicBool = AtomicBool::new(false);
+
+/// Registers `handler` for IRQ `irq`.
+#[cfg(feature = "replay")]
+pub fn register(irq: usize, handler: kplat::interrupts::Handler) -> bool {
+    if !kplat::interrupts::reg_handler(irq, handler) {
+        return false;
+    }
+    if let Some(slot) = HANDLERS.get(irq) {
+        slot.store(handler as usize, Ordering::Release);
+    }
+    true
+}
+
+/// Unregisters the handler of IRQ `irq` and returns it.
+#[cfg(feature = "replay")]
+pub fn unregister(irq: usize) -> Option<kplat::interrupts::Handler> {
+    if let Some(slot) = HANDLERS.get(irq) {
+        slot.store(0, Ordering::Release);
+    }
+    kplat::interrupts::unreg_handler(irq)
+}
+
+/// Enables or disables IRQ `irq`. While a replay masks IRQs, the change
+/// takes effect when it ends.
+#[cfg(feature = "replay")]
+pub fn enable(irq: usize, on: bool) {
+    if let Some(enabled) = ENABLED.get(irq) {
+        enabled.store(on, Ordering::Release);
+    }
+    if !MASKED.load(Ordering::Acquire) {
+        kplat::interrupts::enable(irq, on);
+    }
+}
+
+/// Masks or unmasks the enabled IRQs for a replay.
+#[cfg(feature = "replay")]
+pub(crate) fn set_masked(masked: bool) {
+    if MASKED.swap(masked, Ordering::AcqRel) == masked {
+        return;
+    }
+    for (irq, enabled) in ENABLED.iter().enumerate() {
+        if enabled.load(Ordering::Acquire) {
+            kplat::interrupts::enable(irq, !masked);
+        }
+    }
+}
+
+/// Runs the handler of IRQ `irq` as if the hardware raised it.
+#[cfg(feature = "replay")]
+pub(crate) fn inject(irq: usize) {
+    let flags = save_disable();
+    let guard = kspin::NoPreempt::new();
+    let nesting = &IRQ_NESTING[crate::percpu::this_cpu_id()];
+    nesting.fetch_add(1, Ordering::Relaxed);
+    IRQS.inc();
+    let handler = HANDLERS
+        .get(irq)
+        .map_or(0, |slot| slot.load(Ordering::Acquire));
+    if handler != 0 {
+        let handler = unsafe { core::mem::transmute::<usize, kplat::interrupts::Handler>(handler) };
+        handler();
+    }
+    irq_dispatched(irq);
+    crate::softirq::run_pending();
+    nesting.fetch_sub(1, Ordering::Relaxed);
+    drop(guard);
+    restore(flags);
+}
+
+/// Adds the interrupt counts to the metrics export.
+pub fn register_metrics() {
+    kmetrics::register(&IRQS);
+    kmetrics::register_collector(collect_irqs);
+}
+
+fn collect_irqs(writer: &mut MetricsWriter) {
+    const NAME: &str = "xkernel_irqs_total";
+    writer.family(NAME, Kind::Counter, "Interrupts dispatched by IRQ number.");
+    for (irq, count) in IRQ_COUNTS.iter().enumerate() {
+        let count = count.load(Ordering::Relaxed);
+        if count != 0 {
+            let mut label = heapless::String::<8>::new();
+            let _ = write!(label, "{irq}");
+            writer.sample(NAME, &[("irq", &label)], count);
+        }
+    }
+}
+
 #[cfg(unittest)]
 #[allow(missing_docs)]
 pub mod tests_irq {
     use unittest::def_test;
 
-    use super::{irq_handler, register_irq_hook};
+    use super::{in_irq, irq_handler, register_irq_hook};
 
     fn dummy_hook(_irq: usize) {}
 
@@ -78,4 +232,10 @@ pub mod tests_irq {
     fn test_irq_handler_returns_true() {
         assert!(irq_handler(0));
     }
+
+    #[def_test]
+    fn test_not_in_irq_after_handler() {
+        irq_handler(0);
+        assert!(!in_irq());
+    }
 }
@@ -26,6 +26,7 @@
 //! - `tls`: Enable kernel space thread-local storage support.
 //! - `rtc`: Enable real-time clock support.
 //! - `uspace`: Enable user space support.
+//! - `replay`: Enable recording and replaying interrupts and timer reads.
 
 #![no_std]
 #![feature(doc_cfg)]
@@ -41,7 +42,9 @@ extern crate memaddr;
 
 // mod dummy;
 
+pub mod cpuinfo;
 pub mod dtb;
+pub mod hotplug;
 pub mod mem;
 pub mod percpu;
 pub mod time;
@@ -50,6 +53,10 @@ pub mod time;
 pub mod tls;
 
 pub mod irq;
+pub mod softirq;
+
+#[cfg(feature = "replay")]
+pub mod replay;
 
 #[cfg(feature = "paging")]
 pub mod paging;
@@ -63,7 +70,7 @@ pub mod console {
 pub mod power {
     #[cfg(feature = "smp")]
     pub use kplat::sys::boot_ap;
-    pub use kplat::sys::shutdown;
+    pub use kplat::sys::{RebootMode, reboot, shutdown};
 }
 
 #[cfg(feature = "crosvm")]
@@ -73,6 +80,8 @@ pub mod psci {
 
 /// Trap handling.
 pub mod trap {
+    #[cfg(all(target_arch = "aarch64", feature = "fp-simd"))]
+    pub use kcpu::excp::VECTOR_STATE;
     pub use kcpu::excp::{IRQ, PAGE_FAULT, PageFaultFlags, register_trap_handler};
 }
 
@@ -101,6 +110,8 @@ pub mod context {
 }
 
 pub use kcpu::instrs as asm;
+#[cfg(all(target_arch = "aarch64", feature = "fp-simd"))]
+pub use kcpu::sve;
 #[cfg(feature = "uspace")]
 pub use kcpu::userspace as uspace;
 pub use kplat::boot::final_init;
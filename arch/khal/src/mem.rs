@@ -6,7 +6,7 @@
 
 use heapless::Vec;
 pub use kplat::memory::{
-    MemFlags, MemoryRegion, dma_regions, kernel_layout, mmio_regions, p2v, ram_regions,
+    MemFlags, MemoryRegion, cma_size, dma_regions, kernel_layout, mmio_regions, p2v, ram_regions,
     rsvd_regions, total_ram, v2p,
 };
 use kplat::memory::{check_overlap, sub_ranges};
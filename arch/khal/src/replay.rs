@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Deterministic record and replay of interrupts and timer reads.
+//!
+//! The log is kept by [`kcpu::replay`]; this module feeds it. Every clock
+//! read of [`crate::time`] and every interrupt taken is logged while
+//! recording. While replaying, clock reads return the logged values and the
+//! IRQs enabled through [`crate::irq::enable`] are masked: the logged
+//! interrupts are injected instead, as soon as local interrupts are enabled
+//! at an event following the one they arrived after. The IRQs are unmasked
+//! when the replay ends, at the end of the log or at the first divergence.
+//!
+//! An interrupt that arrived in a window without any logged event, between
+//! enabling local interrupts and disabling them again, cannot be injected
+//! at the same point and ends the replay with a divergence.
+use kcpu::replay::{self as log, Event};
+pub use kcpu::replay::{Divergence, Mode, Stopped, mode};
+
+/// Starts logging events into `buf`.
+///
+/// Fails, giving `buf` back, while the log of an earlier session has not
+/// been collected with [`stop`].
+pub fn start_record(buf: &'static mut [u8]) -> Result<(), &'static mut [u8]> {
+    log::start_record(buf)
+}
+
+/// Starts replaying the log in the first `len` bytes of `buf`.
+///
+/// Fails like [`start_record`].
+pub fn start_replay(buf: &'static mut [u8], len: usize) -> Result<(), &'static mut [u8]> {
+    log::start_replay(buf, len)?;
+    crate::irq::set_masked(true);
+    Ok(())
+}
+
+/// Ends the session, if any, and hands back the buffer it was given.
+pub fn stop() -> Option<Stopped> {
+    let stopped = log::stop();
+    crate::irq::set_masked(false);
+    stopped
+}
+
+/// Unmasks the IRQs once a replay ended by itself.
+fn check_ended() {
+    if mode() != Mode::Replay {
+        crate::irq::set_masked(false);
+    }
+}
+
+/// Injects the interrupts logged next, if local interrupts are enabled.
+///
+/// Returns whether any was injected.
+fn inject_pending() -> bool {
+    let mut injected = false;
+    while kplat::interrupts::is_enabled() {
+        let Some(Event::Irq { irq, ip }) = log::peek() else {
+            break;
+        };
+        log::replay(Event::Irq { irq, ip }, |_| true);
+        crate::irq::inject(irq);
+        injected = true;
+    }
+    injected
+}
+
+/// Logs or replays a clock read that returned `live` nanoseconds.
+pub(crate) fn time(live: u64) -> u64 {
+    match mode() {
+        Mode::Off => live,
+        Mode::Record => {
+            log::record(Event::Time { ns: live });
+            live
+        }
+        Mode::Replay => {
+            inject_pending();
+            let replayed = log::replay(Event::Time { ns: live }, |event| {
+                matches!(event, Event::Time { .. })
+            });
+            let Some(Event::Time { ns }) = replayed else {
+                check_ended();
+                return live;
+            };
+            inject_pending();
+            ns
+        }
+    }
+}
+
+/// Logs or checks that the scheduler switches to task `task`.
+pub fn on_schedule(task: u64) {
+    match mode() {
+        Mode::Off => {}
+        Mode::Record => log::record(Event::Schedule { task }),
+        Mode::Replay => {
+            inject_pending();
+            let found = Event::Schedule { task };
+            if log::replay(found, |event| *event == found).is_none() {
+                check_ended();
+            }
+        }
+    }
+}
+
+/// Called by an idle CPU before it waits for interrupts.
+///
+/// Returns whether a logged interrupt was injected, in which case the CPU
+/// should not wait.
+pub fn on_idle() -> bool {
+    if mode() != Mode::Replay {
+        return false;
+    }
+    if inject_pending() {
+        return true;
+    }
+    // Recording waited for an interrupt here, there is nothing to replay
+    // but the end of the log
+    log::replay(Event::Irq { irq: 0, ip: 0 }, |_| false);
+    check_ended();
+    false
+}
@@ -0,0 +1,210 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Softirqs, the deferred halves of interrupt handlers.
+//!
+//! An IRQ handler does the least it can with the interrupts disabled, and
+//! raises a softirq for the rest of the work. The softirqs raised on a CPU
+//! run on its way out of the interrupt, with the interrupts enabled and
+//! preemption still disabled. A softirq raised outside of an interrupt runs
+//! on the next one.
+//!
+//! Tasklets are one-shot callbacks run by the [`SoftIrq::Tasklet`] softirq,
+//! for the drivers that do not need a softirq of their own.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use heapless::Deque;
+use kbuild_config::CPU_NUM;
+use kspin::SpinNoIrq;
+
+use crate::percpu::this_cpu_id;
+
+/// Times the softirqs raised while running are run again before being left
+/// for the next interrupt, so that they cannot starve the tasks.
+const MAX_RESTARTS: usize = 10;
+
+/// Tasklets that can be scheduled on a CPU at once.
+const MAX_TASKLETS: usize = 64;
+
+/// Softirq vectors, run in this order.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoftIrq {
+    /// Expired timers.
+    Timer   = 0,
+    /// Packets sent by the network devices.
+    NetTx   = 1,
+    /// Packets received by the network devices.
+    NetRx   = 2,
+    /// Scheduled [`Tasklet`]s.
+    Tasklet = 3,
+}
+
+/// Number of softirq vectors.
+const NR_SOFTIRQS: usize = 4;
+
+static HANDLERS: [AtomicUsize; NR_SOFTIRQS] = [const { AtomicUsize::new(0) }; NR_SOFTIRQS];
+
+/// Softirqs raised on each CPU, one bit per vector.
+static PENDING: [AtomicU32; CPU_NUM] = [const { AtomicU32::new(0) }; CPU_NUM];
+/// Whether each CPU is running its softirqs.
+static RUNNING: [AtomicBool; CPU_NUM] = [const { AtomicBool::new(false) }; CPU_NUM];
+
+static TASKLETS: [SpinNoIrq<Deque<&'static Tasklet, MAX_TASKLETS>>; CPU_NUM] =
+    [const { SpinNoIrq::new(Deque::new()) }; CPU_NUM];
+
+/// Registers `handler` for the softirq `softirq`.
+///
+/// Each vector takes a single handler; subsequent calls return false. The
+/// handler of [`SoftIrq::Tasklet`] is built in.
+pub fn register(softirq: SoftIrq, handler: fn()) -> bool {
+    if softirq == SoftIrq::Tasklet {
+        return false;
+    }
+    HANDLERS[softirq as usize]
+        .compare_exchange(
+            0,
+            handler as *const () as usize,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        )
+        .is_ok()
+}
+
+/// Raises the softirq `softirq` on the current CPU.
+pub fn raise(softirq: SoftIrq) {
+    PENDING[this_cpu_id()].fetch_or(1 << softirq as u32, Ordering::AcqRel);
+}
+
+/// Returns whether the softirq `softirq` is raised on the current CPU.
+pub fn is_pending(softirq: SoftIrq) -> bool {
+    PENDING[this_cpu_id()].load(Ordering::Acquire) & (1 << softirq as u32) != 0
+}
+
+fn handler(vector: usize) -> Option<fn()> {
+    if vector == SoftIrq::Tasklet as usize {
+        return Some(run_tasklets);
+    }
+    match HANDLERS[vector].load(Ordering::Acquire) {
+        0 => None,
+        handler => Some(unsafe { core::mem::transmute::<usize, fn()>(handler) }),
+    }
+}
+
+/// Runs the softirqs raised on the current CPU, unless it is running them
+/// already, in the interrupt they were taken from.
+///
+/// Called on exit of an IRQ, with the interrupts and preemption disabled.
+/// The interrupts are enabled while the handlers run.
+pub(crate) fn run_pending() {
+    let cpu = this_cpu_id();
+    if PENDING[cpu].load(Ordering::Acquire) == 0 || RUNNING[cpu].swap(true, Ordering::Acquire) {
+        return;
+    }
+    for _ in 0..MAX_RESTARTS {
+        let mut pending = PENDING[cpu].swap(0, Ordering::AcqRel);
+        if pending == 0 {
+            break;
+        }
+        crate::asm::enable_local();
+        while pending != 0 {
+            let vector = pending.trailing_zeros() as usize;
+            pending &= pending - 1;
+            if let Some(handler) = handler(vector) {
+                handler();
+            }
+        }
+        crate::asm::disable_local();
+    }
+    RUNNING[cpu].store(false, Ordering::Release);
+}
+
+/// A callback run once by the [`SoftIrq::Tasklet`] softirq each time it is
+/// scheduled.
+///
+/// A tasklet is never run on two CPUs at once, nor scheduled twice before
+/// it runs.
+pub struct Tasklet {
+    func: fn(usize),
+    data: usize,
+    scheduled: AtomicBool,
+}
+
+impl Tasklet {
+    /// Creates a tasklet calling `func` with `data`.
+    pub const fn new(func: fn(usize), data: usize) -> Self {
+        Self {
+            func,
+            data,
+            scheduled: AtomicBool::new(false),
+        }
+    }
+
+    /// Schedules the tasklet on the current CPU.
+    ///
+    /// Returns false if it is scheduled already, or if the CPU has too many
+    /// tasklets scheduled.
+    pub fn schedule(&'static self) -> bool {
+        if self.scheduled.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        if TASKLETS[this_cpu_id()].lock().push_back(self).is_err() {
+            self.scheduled.store(false, Ordering::Release);
+            return false;
+        }
+        raise(SoftIrq::Tasklet);
+        true
+    }
+
+    /// Returns whether the tasklet is scheduled and has not run yet.
+    pub fn is_scheduled(&self) -> bool {
+        self.scheduled.load(Ordering::Acquire)
+    }
+}
+
+/// Runs the tasklets scheduled on the current CPU.
+fn run_tasklets() {
+    let tasklets = &TASKLETS[this_cpu_id()];
+    while let Some(tasklet) = tasklets.lock().pop_front() {
+        // Cleared first, so that it can be scheduled again while it runs.
+        tasklet.scheduled.store(false, Ordering::Release);
+        (tasklet.func)(tasklet.data);
+    }
+}
+
+#[cfg(unittest)]
+#[allow(missing_docs)]
+pub mod tests_softirq {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use unittest::def_test;
+
+    use super::{SoftIrq, Tasklet, is_pending, register};
+
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    fn count(data: usize) {
+        RUNS.fetch_add(data, Ordering::SeqCst);
+    }
+
+    #[def_test]
+    fn test_softirq_tasklet_vector_is_builtin() {
+        assert!(!register(SoftIrq::Tasklet, || {}));
+    }
+
+    #[def_test]
+    fn test_tasklet_scheduled_once() {
+        static TASKLET: Tasklet = Tasklet::new(count, 1);
+        let flags = crate::irq::save_disable();
+        assert!(TASKLET.schedule());
+        assert!(!TASKLET.schedule());
+        assert!(TASKLET.is_scheduled());
+        assert!(is_pending(SoftIrq::Tasklet));
+        super::run_tasklets();
+        assert!(!TASKLET.is_scheduled());
+        crate::irq::restore(flags);
+        assert_eq!(RUNS.load(Ordering::SeqCst), 1);
+    }
+}
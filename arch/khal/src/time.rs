@@ -7,14 +7,124 @@
 pub use core::time::Duration;
 pub type TimeValue = Duration;
 
+use core::sync::atomic::{AtomicI64, Ordering};
+
 // Aliases for kplat names if needed locally or exposed
 pub use kplat::timer::{
-    MS_SEC, NS_MS, NS_SEC, NS_SEC as NANOS_PER_SEC, NS_US, NS_US as NANOS_PER_MICROS, US_SEC,
-    arm_timer, freq, interrupt_id, now, now as monotonic_time, now_ns as monotonic_time_nanos,
-    now_ns, now_ticks, ns2t, offset_ns, spin_until, spin_wait, t2ns, wall as wall_time, wall,
+    MS_SEC, NS_MS, NS_SEC, NS_SEC as NANOS_PER_SEC, NS_US, NS_US as NANOS_PER_MICROS, US_SEC, freq,
+    interrupt_id, ns2t, offset_ns, rtc_ns, spin_until, spin_wait, step_wall, t2ns, wall_offset_ns,
+    wall_step_ns,
+};
+
+#[cfg(not(feature = "replay"))]
+pub use self::synced::{
+    now, now as monotonic_time, now_ns as monotonic_time_nanos, now_ns, wall as wall_time, wall,
+    wall_ns as wall_time_nanos, wall_ns,
+};
+
+#[cfg(feature = "replay")]
+pub use self::replayed::{
+    now, now as monotonic_time, now_ns as monotonic_time_nanos, now_ns, wall as wall_time, wall,
     wall_ns as wall_time_nanos, wall_ns,
 };
 
+/// Ticks added to the counter of this CPU so that it agrees with those of
+/// the others, see [`adjust_clock_offset`].
+#[percpu::def_percpu]
+static CLOCK_OFFSET_TICKS: AtomicI64 = AtomicI64::new(0);
+
+fn current_clock_offset() -> i64 {
+    // A task migrating between the two reads gets an offset from another
+    // CPU, off by no more than the skew being compensated.
+    unsafe { CLOCK_OFFSET_TICKS.current_ref_raw() }.load(Ordering::Relaxed)
+}
+
+/// Converts signed ticks to nanoseconds.
+pub fn signed_t2ns(ticks: i64) -> i64 {
+    let ns = t2ns(ticks.unsigned_abs()) as i64;
+    if ticks < 0 { -ns } else { ns }
+}
+
+/// Returns the timer ticks of the current CPU, compensated so that they
+/// agree with those of the other CPUs.
+pub fn now_ticks() -> u64 {
+    kplat::timer::now_ticks().wrapping_add_signed(current_clock_offset())
+}
+
+/// Arms the timer of the current CPU to fire at `deadline_ns`, in the
+/// compensated time of [`now_ns`].
+pub fn arm_timer(deadline_ns: u64) {
+    let offset_ns = signed_t2ns(current_clock_offset());
+    kplat::timer::arm_timer(deadline_ns.saturating_add_signed(-offset_ns));
+}
+
+/// Returns the ticks added to the counter of `cpu_id`.
+pub fn clock_offset(cpu_id: usize) -> i64 {
+    unsafe { CLOCK_OFFSET_TICKS.remote_ref_raw(cpu_id) }.load(Ordering::Relaxed)
+}
+
+/// Adds `delta_ticks` to the counter of `cpu_id`, whose clock was measured
+/// `-delta_ticks` ahead of the others.
+///
+/// Time on `cpu_id` jumps by `delta_ticks`: backwards if negative, so this
+/// is for bringing CPUs in line before the skew matters, at boot, or by
+/// amounts below the clock resolution afterwards.
+pub fn adjust_clock_offset(cpu_id: usize, delta_ticks: i64) {
+    unsafe { CLOCK_OFFSET_TICKS.remote_ref_raw(cpu_id) }.fetch_add(delta_ticks, Ordering::Relaxed);
+}
+
+/// Clock reads of the current CPU, compensated by [`now_ticks`].
+#[cfg_attr(feature = "replay", allow(dead_code))]
+mod synced {
+    use super::{TimeValue, now_ticks, t2ns, wall_offset_ns};
+
+    /// Returns the monotonic time in nanoseconds.
+    pub fn now_ns() -> u64 {
+        t2ns(now_ticks())
+    }
+
+    /// Returns the monotonic time.
+    pub fn now() -> TimeValue {
+        TimeValue::from_nanos(now_ns())
+    }
+
+    /// Returns the wall-clock time in nanoseconds.
+    pub fn wall_ns() -> u64 {
+        now_ns() + wall_offset_ns()
+    }
+
+    /// Returns the wall-clock time.
+    pub fn wall() -> TimeValue {
+        TimeValue::from_nanos(wall_ns())
+    }
+}
+
+/// Clock reads that are logged and replayed, see [`crate::replay`].
+#[cfg(feature = "replay")]
+mod replayed {
+    use super::{TimeValue, wall_offset_ns};
+
+    /// Returns the monotonic time in nanoseconds.
+    pub fn now_ns() -> u64 {
+        crate::replay::time(super::synced::now_ns())
+    }
+
+    /// Returns the monotonic time.
+    pub fn now() -> TimeValue {
+        TimeValue::from_nanos(now_ns())
+    }
+
+    /// Returns the wall-clock time in nanoseconds.
+    pub fn wall_ns() -> u64 {
+        now_ns() + wall_offset_ns()
+    }
+
+    /// Returns the wall-clock time.
+    pub fn wall() -> TimeValue {
+        TimeValue::from_nanos(wall_ns())
+    }
+}
+
 /// Busy-wait for the given duration.
 pub fn busy_wait(dur: Duration) {
     spin_wait(dur);
@@ -30,7 +140,7 @@ pub fn busy_wait_until(deadline: TimeValue) {
 pub mod tests_time {
     use unittest::def_test;
 
-    use super::{Duration, NANOS_PER_SEC};
+    use super::{Duration, NANOS_PER_SEC, freq, signed_t2ns};
 
     #[def_test]
     fn test_duration_from_nanos() {
@@ -46,4 +156,11 @@ pub mod tests_time {
         let long = Duration::from_millis(2);
         assert!(long > short);
     }
+
+    #[def_test]
+    fn test_signed_t2ns() {
+        let ticks = freq() as i64;
+        assert_eq!(signed_t2ns(ticks), NANOS_PER_SEC as i64);
+        assert_eq!(signed_t2ns(-ticks), -(NANOS_PER_SEC as i64));
+    }
 }
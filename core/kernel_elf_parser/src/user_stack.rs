@@ -131,3 +131,82 @@ pub fn app_stack_region(args: &[String], envs: &[String], auxv: &[AuxEntry], sp:
     result.extend_from_slice(second);
     result
 }
+
+/// Generate initial stack frame for a 32-bit (compat) user stack
+///
+/// The layout is the same as [`app_stack_region`], except that `argc`, the
+/// `argv`/`envp` pointers and both halves of each auxiliary vector entry are
+/// 4 bytes wide, as expected by i386 and armhf programs.
+///
+/// # Arguments
+///
+/// * `args` - Arguments of the application
+/// * `envs` - Environment variables of the application
+/// * `auxv` - Auxiliary vectors of the application
+/// * `sp`   - Highest address of the stack, which must lie below 4 GiB
+///
+/// # Return
+///
+/// * [`Vec<u8>`] - Initial stack frame of the application
+pub fn app_stack_region_compat(
+    args: &[String],
+    envs: &[String],
+    auxv: &[AuxEntry],
+    sp: usize,
+) -> Vec<u8> {
+    assert!(sp <= u32::MAX as usize);
+
+    let mut data = VecDeque::new();
+    let mut push = |src: &[u8]| -> usize {
+        data.extend(src.iter().cloned());
+        data.rotate_right(src.len());
+        sp - data.len()
+    };
+
+    let random_str_pos = push("0123456789abcdef".as_bytes());
+    let envs_slice: Vec<u32> = envs
+        .iter()
+        .map(|env| {
+            push(b"\0");
+            push(env.as_bytes()) as u32
+        })
+        .collect();
+    let argv_slice: Vec<u32> = args
+        .iter()
+        .map(|arg| {
+            push(b"\0");
+            push(arg.as_bytes()) as u32
+        })
+        .collect();
+    let sp = push(&[0; 4]);
+    push(&b"\0".repeat(sp % 16));
+
+    // Words in memory order, starting from the final stack pointer.
+    let mut words = Vec::with_capacity(args.len() + envs.len() + auxv.len() * 2 + 7);
+    words.push(args.len() as u32);
+    words.extend_from_slice(&argv_slice);
+    words.push(0);
+    words.extend_from_slice(&envs_slice);
+    words.push(0);
+    if !auxv.iter().any(|entry| entry.get_type() == AuxType::EXECFN) {
+        words.extend([AuxType::EXECFN as u32, argv_slice[0]]);
+    }
+    if !auxv.iter().any(|entry| entry.get_type() == AuxType::RANDOM) {
+        words.extend([AuxType::RANDOM as u32, random_str_pos as u32]);
+    }
+    for entry in auxv {
+        words.extend([entry.get_type() as u32, entry.value() as u32]);
+    }
+
+    // Pad so that the stack pointer ends up 16-byte aligned.
+    push(&b"\0".repeat((4 - words.len() % 4) % 4 * 4));
+    let sp = push(words.as_bytes());
+
+    assert!(sp % 16 == 0);
+
+    let mut result = Vec::with_capacity(data.len());
+    let (first, second) = data.as_slices();
+    result.extend_from_slice(first);
+    result.extend_from_slice(second);
+    result
+}
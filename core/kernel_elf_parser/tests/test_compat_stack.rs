@@ -0,0 +1,38 @@
+use kernel_elf_parser::{AuxEntry, AuxType, app_stack_region_compat};
+
+fn word(data: &[u8], idx: usize) -> u32 {
+    u32::from_ne_bytes(data[idx * 4..idx * 4 + 4].try_into().unwrap())
+}
+
+#[test]
+fn test_compat_ustack() {
+    let args: Vec<String> = vec!["/bin/true".to_string(), "arg1".to_string()];
+    let envs: Vec<String> = vec!["LOG=file".to_string()];
+    let auxv = [
+        AuxEntry::new(AuxType::PAGESZ, 0x1000),
+        AuxEntry::new(AuxType::NULL, 0),
+    ];
+
+    let ustack_end = 0xc000_0000;
+    let stack_data = app_stack_region_compat(&args, &envs, &auxv, ustack_end);
+    let sp = ustack_end - stack_data.len();
+    assert_eq!(sp % 16, 0);
+
+    // argc, argv[0..2], NULL, envp[0], NULL
+    assert_eq!(word(&stack_data, 0), 2);
+    assert_eq!(word(&stack_data, 3), 0);
+    assert_eq!(word(&stack_data, 5), 0);
+
+    let argv0 = word(&stack_data, 1) as usize - sp;
+    assert_eq!(&stack_data[argv0..argv0 + 10], b"/bin/true\0");
+    let envp0 = word(&stack_data, 4) as usize - sp;
+    assert_eq!(&stack_data[envp0..envp0 + 9], b"LOG=file\0");
+
+    // Missing EXECFN and RANDOM entries come first, then the given vector.
+    assert_eq!(word(&stack_data, 6), AuxType::EXECFN as u32);
+    assert_eq!(word(&stack_data, 7), word(&stack_data, 1));
+    assert_eq!(word(&stack_data, 8), AuxType::RANDOM as u32);
+    assert_eq!(word(&stack_data, 10), AuxType::PAGESZ as u32);
+    assert_eq!(word(&stack_data, 11), 0x1000);
+    assert_eq!(word(&stack_data, 12), AuxType::NULL as u32);
+}
@@ -0,0 +1,241 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Notifier chains.
+//!
+//! A chain is a list of callbacks subsystems register for some kind of
+//! event, run in order of decreasing priority each time one happens. The
+//! subsystem raising the event only knows about the chain, not about who
+//! listens on it.
+//!
+//! - [`AtomicNotifierChain`] runs the callbacks with the chain locked and the
+//!   interrupts disabled. It may be called from any context, but its
+//!   callbacks must neither sleep nor touch the chain.
+//! - [`BlockingNotifierChain`] runs the callbacks on a copy of the chain, so
+//!   they may sleep. It must be called from a task.
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kspin::SpinNoIrq;
+
+/// What a callback made of an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyResult {
+    /// The event is of no interest to the callback.
+    Done,
+    /// The callback acted on the event.
+    Ok,
+    /// The callback acted on the event, which the callbacks after it are not
+    /// to see.
+    Stop,
+}
+
+/// A registered callback, to unregister it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotifierId(usize);
+
+type Callback<E> = dyn Fn(&E) -> NotifyResult + Send + Sync;
+
+struct Entry<E> {
+    id: usize,
+    priority: i32,
+    callback: Arc<Callback<E>>,
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+struct Chain<E> {
+    entries: SpinNoIrq<Vec<Entry<E>>>,
+}
+
+impl<E> Chain<E> {
+    const fn new() -> Self {
+        Self {
+            entries: SpinNoIrq::new(Vec::new()),
+        }
+    }
+
+    fn register(&self, priority: i32, callback: Arc<Callback<E>>) -> NotifierId {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let mut entries = self.entries.lock();
+        // After the callbacks of the same priority registered earlier
+        let pos = entries
+            .iter()
+            .position(|it| it.priority < priority)
+            .unwrap_or(entries.len());
+        entries.insert(
+            pos,
+            Entry {
+                id,
+                priority,
+                callback,
+            },
+        );
+        NotifierId(id)
+    }
+
+    fn unregister(&self, id: NotifierId) -> bool {
+        let mut entries = self.entries.lock();
+        let len = entries.len();
+        entries.retain(|it| it.id != id.0);
+        entries.len() != len
+    }
+}
+
+fn run<'a, E: 'a>(
+    callbacks: impl Iterator<Item = &'a Arc<Callback<E>>>,
+    event: &E,
+) -> NotifyResult {
+    let mut result = NotifyResult::Done;
+    for callback in callbacks {
+        match callback(event) {
+            NotifyResult::Done => {}
+            NotifyResult::Ok => result = NotifyResult::Ok,
+            NotifyResult::Stop => return NotifyResult::Stop,
+        }
+    }
+    result
+}
+
+/// A notifier chain whose callbacks run in atomic context.
+pub struct AtomicNotifierChain<E>(Chain<E>);
+
+impl<E> AtomicNotifierChain<E> {
+    /// Creates an empty chain.
+    pub const fn new() -> Self {
+        Self(Chain::new())
+    }
+
+    /// Adds `callback` to the chain, to run before the callbacks of lower
+    /// `priority` and after those registered earlier with the same one.
+    pub fn register<F>(&self, priority: i32, callback: F) -> NotifierId
+    where
+        F: Fn(&E) -> NotifyResult + Send + Sync + 'static,
+    {
+        self.0.register(priority, Arc::new(callback))
+    }
+
+    /// Removes a callback from the chain. Returns whether it was there.
+    pub fn unregister(&self, id: NotifierId) -> bool {
+        self.0.unregister(id)
+    }
+
+    /// Runs the callbacks on `event`, until one returns
+    /// [`NotifyResult::Stop`].
+    ///
+    /// Returns [`NotifyResult::Stop`] if a callback stopped the chain,
+    /// [`NotifyResult::Ok`] if one acted on the event, and
+    /// [`NotifyResult::Done`] otherwise.
+    pub fn call(&self, event: &E) -> NotifyResult {
+        let entries = self.0.entries.lock();
+        run(entries.iter().map(|it| &it.callback), event)
+    }
+}
+
+impl<E> Default for AtomicNotifierChain<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A notifier chain whose callbacks may sleep.
+pub struct BlockingNotifierChain<E>(Chain<E>);
+
+impl<E> BlockingNotifierChain<E> {
+    /// Creates an empty chain.
+    pub const fn new() -> Self {
+        Self(Chain::new())
+    }
+
+    /// Adds `callback` to the chain, to run before the callbacks of lower
+    /// `priority` and after those registered earlier with the same one.
+    pub fn register<F>(&self, priority: i32, callback: F) -> NotifierId
+    where
+        F: Fn(&E) -> NotifyResult + Send + Sync + 'static,
+    {
+        self.0.register(priority, Arc::new(callback))
+    }
+
+    /// Removes a callback from the chain. Returns whether it was there.
+    ///
+    /// A call of the chain under way may still run it.
+    pub fn unregister(&self, id: NotifierId) -> bool {
+        self.0.unregister(id)
+    }
+
+    /// Runs the callbacks on `event`, like [`AtomicNotifierChain::call`].
+    ///
+    /// The callbacks run on the chain as it was when the call started, and
+    /// may register or unregister callbacks themselves.
+    pub fn call(&self, event: &E) -> NotifyResult {
+        let callbacks = self
+            .0
+            .entries
+            .lock()
+            .iter()
+            .map(|it| it.callback.clone())
+            .collect::<Vec<_>>();
+        run(callbacks.iter(), event)
+    }
+}
+
+impl<E> Default for BlockingNotifierChain<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(unittest)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use kspin::SpinNoIrq;
+    use unittest::{assert, assert_eq, def_test};
+
+    use super::*;
+
+    #[def_test]
+    fn test_chain_priority_order() {
+        static ORDER: SpinNoIrq<Vec<u32>> = SpinNoIrq::new(Vec::new());
+        let chain = BlockingNotifierChain::<u32>::new();
+        chain.register(0, |_| {
+            ORDER.lock().push(1);
+            NotifyResult::Done
+        });
+        chain.register(10, |_| {
+            ORDER.lock().push(2);
+            NotifyResult::Ok
+        });
+        chain.register(0, |_| {
+            ORDER.lock().push(3);
+            NotifyResult::Done
+        });
+
+        assert_eq!(chain.call(&0), NotifyResult::Ok);
+        assert_eq!(*ORDER.lock(), [2, 1, 3]);
+    }
+
+    #[def_test]
+    fn test_chain_stop_and_unregister() {
+        let chain = AtomicNotifierChain::<u32>::new();
+        let stop = chain.register(1, |&event| {
+            if event == 0 {
+                NotifyResult::Stop
+            } else {
+                NotifyResult::Done
+            }
+        });
+        chain.register(0, |_| NotifyResult::Ok);
+
+        assert_eq!(chain.call(&0), NotifyResult::Stop);
+        assert_eq!(chain.call(&1), NotifyResult::Ok);
+        assert!(chain.unregister(stop));
+        assert!(!chain.unregister(stop));
+        assert_eq!(chain.call(&0), NotifyResult::Ok);
+    }
+}
@@ -0,0 +1,213 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Orderly shutdown and reboot.
+//!
+//! Powering off, halting or restarting goes through [`shutdown`], which runs
+//! the hooks subsystems added with [`register_hook`] stage by stage, see
+//! [`Stage`], before handing over to the platform. A failing hook is logged
+//! and the teardown goes on: the machine goes down anyway.
+//!
+//! Panics skip the hooks, and power off straight away.
+//!
+//! Subsystems wanting to know about power transitions in general, shutdowns
+//! as well as VM snapshots, listen on [`POWER_NOTIFIER`] instead.
+#![no_std]
+
+#[macro_use]
+extern crate log;
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use kerrno::{KError, KResult};
+pub use khal::power::RebootMode;
+use knotifier::BlockingNotifierChain;
+use kspin::SpinNoIrq;
+
+/// How many hooks may be registered.
+pub const MAX_HOOKS: usize = 32;
+
+/// What the machine does at the end of a shutdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Turns the power off.
+    PowerOff,
+    /// Stops the CPUs, leaving the power on.
+    Halt,
+    /// Restarts the machine.
+    Reboot(RebootMode),
+}
+
+/// Parses a reboot mode: `cold`, `warm` or `bootloader`.
+pub fn parse_reboot_mode(mode: &str) -> KResult<RebootMode> {
+    match mode {
+        "cold" => Ok(RebootMode::Cold),
+        "warm" => Ok(RebootMode::Warm),
+        "bootloader" => Ok(RebootMode::Bootloader),
+        _ => Err(KError::InvalidInput),
+    }
+}
+
+/// Why the machine goes down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// The kernel `main` returned, e.g. after the init process exited.
+    InitExited,
+    /// A program called `reboot(2)`.
+    Syscall,
+    /// The host asked through the guest agent.
+    Host,
+    /// A command run from the debug shell.
+    Shell,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Reason::InitExited => "init exited",
+            Reason::Syscall => "reboot syscall",
+            Reason::Host => "host request",
+            Reason::Shell => "debug shell",
+        })
+    }
+}
+
+/// A shutdown, as passed to the hooks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Request {
+    pub action: Action,
+    pub reason: Reason,
+}
+
+/// A power transition, as raised on [`POWER_NOTIFIER`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerEvent {
+    /// A shutdown starts, before any of its hooks run.
+    Shutdown(Request),
+    /// The VM is about to be saved.
+    Suspend,
+    /// The VM was resumed, possibly restored or migrated.
+    Resume,
+}
+
+/// Notified of power transitions, in the task making them.
+pub static POWER_NOTIFIER: BlockingNotifierChain<PowerEvent> = BlockingNotifierChain::new();
+
+/// The stages of a shutdown, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// Services the host or other machines rely on learn about the shutdown.
+    Services,
+    /// Filesystems are flushed.
+    Filesystems,
+    /// Network stacks stop, once the last writes went out.
+    Network,
+    /// Devices are flushed and quiesced.
+    Devices,
+    /// The other CPUs are parked.
+    Cpus,
+    /// Last words, from the one CPU left.
+    Final,
+}
+
+impl Stage {
+    const ALL: [Stage; 6] = [
+        Stage::Services,
+        Stage::Filesystems,
+        Stage::Network,
+        Stage::Devices,
+        Stage::Cpus,
+        Stage::Final,
+    ];
+}
+
+/// Part of the teardown of a subsystem, run in the task requesting the
+/// shutdown.
+pub type Hook = fn(&Request) -> KResult;
+
+#[derive(Clone, Copy)]
+struct Entry {
+    stage: Stage,
+    name: &'static str,
+    hook: Hook,
+}
+
+static HOOKS: SpinNoIrq<[Option<Entry>; MAX_HOOKS]> = SpinNoIrq::new([None; MAX_HOOKS]);
+
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Registers `hook` to run at `stage` of a shutdown, after the hooks
+/// registered earlier for the same stage.
+///
+/// Fails with [`KError::NoMemory`] once [`MAX_HOOKS`] are registered.
+pub fn register_hook(stage: Stage, name: &'static str, hook: Hook) -> KResult {
+    let mut hooks = HOOKS.lock();
+    let slot = hooks
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .ok_or(KError::NoMemory)?;
+    *slot = Some(Entry { stage, name, hook });
+    Ok(())
+}
+
+/// Whether a shutdown is under way.
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::Acquire)
+}
+
+/// Runs the hooks, and powers off, halts or restarts as `request` says.
+///
+/// Only the first request is carried out: later ones wait for the machine
+/// to go down.
+pub fn shutdown(request: Request) -> ! {
+    if SHUTTING_DOWN.swap(true, Ordering::AcqRel) {
+        debug!("shutdown: already under way, {:?} ignored", request);
+        loop {
+            khal::asm::await_interrupts();
+        }
+    }
+    info!("shutdown: {:?} ({})", request.action, request.reason);
+    POWER_NOTIFIER.call(&PowerEvent::Shutdown(request));
+
+    // Copied, for the hooks to sleep
+    let hooks = *HOOKS.lock();
+    for stage in Stage::ALL {
+        for entry in hooks.iter().flatten().filter(|it| it.stage == stage) {
+            debug!("shutdown: {:?}: {}", stage, entry.name);
+            if let Err(err) = (entry.hook)(&request) {
+                warn!("shutdown: {} failed: {:?}", entry.name, err);
+            }
+        }
+    }
+
+    match request.action {
+        Action::PowerOff => khal::power::shutdown(),
+        Action::Reboot(mode) => khal::power::reboot(mode),
+        Action::Halt => {
+            info!("System halted");
+            loop {
+                khal::asm::stop_cpu();
+            }
+        }
+    }
+}
+
+/// Shuts down and turns the power off.
+pub fn power_off(reason: Reason) -> ! {
+    shutdown(Request {
+        action: Action::PowerOff,
+        reason,
+    })
+}
+
+/// Shuts down and restarts in `mode`.
+pub fn reboot(mode: RebootMode, reason: Reason) -> ! {
+    shutdown(Request {
+        action: Action::Reboot(mode),
+        reason,
+    })
+}
@@ -0,0 +1,496 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Multi-producer, multi-consumer channels.
+//!
+//! A channel is [`bounded`], blocking its senders while it is full, or
+//! [`unbounded`]. Both [`Sender`] and [`Receiver`] can be cloned; each
+//! message is received once. The channel is disconnected once all the
+//! senders, or all the receivers, are dropped.
+//!
+//! Both ends are [`Pollable`], and [`Select`] waits on several of them at
+//! once.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use ksync::channel::{self, Select};
+//!
+//! let (tx, rx) = channel::bounded(16);
+//! let (ctl_tx, ctl_rx) = channel::unbounded::<()>();
+//! tx.send(42).unwrap();
+//!
+//! let mut select = Select::new();
+//! let data = select.recv(&rx);
+//! let ctl = select.recv(&ctl_rx);
+//! match select.ready() {
+//!     i if i == data => assert_eq!(rx.try_recv(), Ok(42)),
+//!     i if i == ctl => return,
+//!     _ => unreachable!(),
+//! }
+//! # drop((tx, ctl_tx));
+//! ```
+
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::{
+    fmt,
+    future::poll_fn,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use kpoll::{IoEvents, PollSet, Pollable};
+use kspin::SpinNoIrq;
+use ktask::future::{block_on, timeout};
+
+/// Error returned by [`Sender::send`]: all the receivers are dropped. It
+/// holds the message back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// Error returned by [`Sender::try_send`], holding the message back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// The channel is full.
+    Full(T),
+    /// All the receivers are dropped.
+    Disconnected(T),
+}
+
+/// Error returned by [`Sender::send_timeout`], holding the message back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendTimeoutError<T> {
+    /// The channel stayed full.
+    Timeout(T),
+    /// All the receivers are dropped.
+    Disconnected(T),
+}
+
+/// Error returned by [`Receiver::recv`]: the channel is empty and all the
+/// senders are dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+/// Error returned by [`Receiver::try_recv`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// The channel is empty.
+    Empty,
+    /// The channel is empty and all the senders are dropped.
+    Disconnected,
+}
+
+/// Error returned by [`Receiver::recv_timeout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    /// The channel stayed empty.
+    Timeout,
+    /// The channel is empty and all the senders are dropped.
+    Disconnected,
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sending on a disconnected channel")
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => write!(f, "sending on a full channel"),
+            Self::Disconnected(_) => write!(f, "sending on a disconnected channel"),
+        }
+    }
+}
+
+impl<T> fmt::Display for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout(_) => write!(f, "timed out sending on a full channel"),
+            Self::Disconnected(_) => write!(f, "sending on a disconnected channel"),
+        }
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "receiving on a disconnected channel")
+    }
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "receiving on an empty channel"),
+            Self::Disconnected => write!(f, "receiving on a disconnected channel"),
+        }
+    }
+}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "timed out receiving on an empty channel"),
+            Self::Disconnected => write!(f, "receiving on a disconnected channel"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> core::error::Error for SendError<T> {}
+impl<T: fmt::Debug> core::error::Error for TrySendError<T> {}
+impl<T: fmt::Debug> core::error::Error for SendTimeoutError<T> {}
+impl core::error::Error for RecvError {}
+impl core::error::Error for TryRecvError {}
+impl core::error::Error for RecvTimeoutError {}
+
+struct Chan<T> {
+    queue: SpinNoIrq<VecDeque<T>>,
+    /// Messages the channel holds at most, `usize::MAX` if unbounded.
+    cap: usize,
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+    /// Woken when a message is sent, or the last sender is dropped.
+    recv_wakers: PollSet,
+    /// Woken when a message is received, or the last receiver is dropped.
+    send_wakers: PollSet,
+}
+
+impl<T> Chan<T> {
+    fn new(cap: usize) -> Arc<Self> {
+        Arc::new(Self {
+            queue: SpinNoIrq::new(VecDeque::new()),
+            cap,
+            senders: AtomicUsize::new(1),
+            receivers: AtomicUsize::new(1),
+            recv_wakers: PollSet::new(),
+            send_wakers: PollSet::new(),
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.queue.lock().len()
+    }
+}
+
+/// Creates a channel holding at most `cap` messages.
+///
+/// # Panics
+///
+/// Panics if `cap` is 0.
+pub fn bounded<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(cap > 0, "bounded channel of capacity 0");
+    let chan = Chan::new(cap);
+    (Sender { chan: chan.clone() }, Receiver { chan })
+}
+
+/// Creates a channel holding any number of messages.
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    let chan = Chan::new(usize::MAX);
+    (Sender { chan: chan.clone() }, Receiver { chan })
+}
+
+/// The sending end of a channel.
+pub struct Sender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends `msg` without blocking.
+    pub fn try_send(&self, msg: T) -> Result<(), TrySendError<T>> {
+        if self.chan.receivers.load(Ordering::Acquire) == 0 {
+            return Err(TrySendError::Disconnected(msg));
+        }
+        let mut queue = self.chan.queue.lock();
+        if queue.len() >= self.chan.cap {
+            return Err(TrySendError::Full(msg));
+        }
+        queue.push_back(msg);
+        drop(queue);
+        self.chan.recv_wakers.wake();
+        Ok(())
+    }
+
+    fn poll_send(
+        &self,
+        cx: &mut Context<'_>,
+        slot: &mut Option<T>,
+    ) -> Poll<Result<(), SendError<T>>> {
+        let msg = slot.take().expect("message already sent");
+        let res = match self.try_send(msg) {
+            Err(TrySendError::Full(msg)) => {
+                self.chan.send_wakers.register(cx.waker());
+                // Tried again, in case a message was received before
+                // registering.
+                match self.try_send(msg) {
+                    Err(TrySendError::Full(msg)) => {
+                        *slot = Some(msg);
+                        return Poll::Pending;
+                    }
+                    res => res,
+                }
+            }
+            res => res,
+        };
+        Poll::Ready(res.map_err(|e| match e {
+            TrySendError::Full(msg) | TrySendError::Disconnected(msg) => SendError(msg),
+        }))
+    }
+
+    /// Sends `msg`, blocking while the channel is full.
+    pub fn send(&self, msg: T) -> Result<(), SendError<T>> {
+        let mut slot = Some(msg);
+        block_on(poll_fn(|cx| self.poll_send(cx, &mut slot)))
+    }
+
+    /// Sends `msg`, blocking while the channel is full, for at most `dur`.
+    pub fn send_timeout(&self, msg: T, dur: Duration) -> Result<(), SendTimeoutError<T>> {
+        let mut slot = Some(msg);
+        match block_on(timeout(
+            Some(dur),
+            poll_fn(|cx| self.poll_send(cx, &mut slot)),
+        )) {
+            Ok(res) => res.map_err(|SendError(msg)| SendTimeoutError::Disconnected(msg)),
+            Err(_) => Err(SendTimeoutError::Timeout(
+                slot.take().expect("message already sent"),
+            )),
+        }
+    }
+
+    /// Returns the number of messages in the channel.
+    pub fn len(&self) -> usize {
+        self.chan.len()
+    }
+
+    /// Returns whether the channel is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns whether all the receivers are dropped.
+    pub fn is_disconnected(&self) -> bool {
+        self.chan.receivers.load(Ordering::Acquire) == 0
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.chan.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            chan: self.chan.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.chan.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.chan.recv_wakers.wake();
+        }
+    }
+}
+
+impl<T> Pollable for Sender<T> {
+    fn poll(&self) -> IoEvents {
+        let mut events = IoEvents::empty();
+        events.set(IoEvents::OUT, self.chan.len() < self.chan.cap);
+        events.set(IoEvents::ERR, self.is_disconnected());
+        events
+    }
+
+    fn register(&self, context: &mut Context<'_>, _events: IoEvents) {
+        // Errors are always polled.
+        self.chan.send_wakers.register(context.waker());
+    }
+}
+
+/// The receiving end of a channel.
+pub struct Receiver<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Receives a message without blocking.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        // Checked first, so that the messages sent before the last sender
+        // was dropped are received.
+        let disconnected = self.chan.senders.load(Ordering::Acquire) == 0;
+        match self.chan.queue.lock().pop_front() {
+            Some(msg) => {
+                self.chan.send_wakers.wake();
+                Ok(msg)
+            }
+            None if disconnected => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Result<T, RecvError>> {
+        let res = match self.try_recv() {
+            Err(TryRecvError::Empty) => {
+                self.chan.recv_wakers.register(cx.waker());
+                // Tried again, in case a message was sent before registering.
+                match self.try_recv() {
+                    Err(TryRecvError::Empty) => return Poll::Pending,
+                    res => res,
+                }
+            }
+            res => res,
+        };
+        Poll::Ready(res.map_err(|_| RecvError))
+    }
+
+    /// Receives a message, blocking while the channel is empty.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        block_on(poll_fn(|cx| self.poll_recv(cx)))
+    }
+
+    /// Receives a message, blocking while the channel is empty, for at most
+    /// `dur`.
+    pub fn recv_timeout(&self, dur: Duration) -> Result<T, RecvTimeoutError> {
+        match block_on(timeout(Some(dur), poll_fn(|cx| self.poll_recv(cx)))) {
+            Ok(res) => res.map_err(|_| RecvTimeoutError::Disconnected),
+            Err(_) => Err(RecvTimeoutError::Timeout),
+        }
+    }
+
+    /// Returns an iterator over the messages received, blocking for each,
+    /// until the channel is disconnected.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        core::iter::from_fn(|| self.recv().ok())
+    }
+
+    /// Returns an iterator over the messages in the channel, without
+    /// blocking.
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        core::iter::from_fn(|| self.try_recv().ok())
+    }
+
+    /// Returns the number of messages in the channel.
+    pub fn len(&self) -> usize {
+        self.chan.len()
+    }
+
+    /// Returns whether the channel is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns whether all the senders are dropped.
+    pub fn is_disconnected(&self) -> bool {
+        self.chan.senders.load(Ordering::Acquire) == 0
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.chan.receivers.fetch_add(1, Ordering::Relaxed);
+        Self {
+            chan: self.chan.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        if self.chan.receivers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.chan.send_wakers.wake();
+        }
+    }
+}
+
+impl<T> Pollable for Receiver<T> {
+    fn poll(&self) -> IoEvents {
+        let mut events = IoEvents::empty();
+        events.set(IoEvents::IN, !self.is_empty());
+        events.set(IoEvents::HUP, self.is_disconnected());
+        events
+    }
+
+    fn register(&self, context: &mut Context<'_>, _events: IoEvents) {
+        // Hang-ups are always polled.
+        self.chan.recv_wakers.register(context.waker());
+    }
+}
+
+/// Waits on several [`Pollable`]s at once, channel ends or others, until one
+/// of them is ready.
+///
+/// Another receiver or sender may take the message or the room that made a
+/// channel ready, so the operation it is ready for may still fail with
+/// [`TryRecvError::Empty`] or [`TrySendError::Full`].
+#[derive(Default)]
+pub struct Select<'a> {
+    handles: Vec<(&'a dyn Pollable, IoEvents)>,
+}
+
+impl<'a> Select<'a> {
+    /// Creates an empty selection.
+    pub fn new() -> Self {
+        Self {
+            handles: Vec::new(),
+        }
+    }
+
+    /// Adds `pollable`, ready on `events`, or the events always polled.
+    ///
+    /// Returns its index.
+    pub fn add(&mut self, pollable: &'a dyn Pollable, events: IoEvents) -> usize {
+        self.handles
+            .push((pollable, events | IoEvents::ALWAYS_POLL));
+        self.handles.len() - 1
+    }
+
+    /// Adds `rx`, ready if it has a message or is disconnected.
+    ///
+    /// Returns its index.
+    pub fn recv<T>(&mut self, rx: &'a Receiver<T>) -> usize {
+        self.add(rx, IoEvents::IN)
+    }
+
+    /// Adds `tx`, ready if it has room for a message or is disconnected.
+    ///
+    /// Returns its index.
+    pub fn send<T>(&mut self, tx: &'a Sender<T>) -> usize {
+        self.add(tx, IoEvents::OUT)
+    }
+
+    /// Returns the index of the first ready pollable, in the order they
+    /// were added, without blocking.
+    pub fn try_ready(&self) -> Option<usize> {
+        self.handles
+            .iter()
+            .position(|(pollable, events)| pollable.poll().intersects(*events))
+    }
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<usize> {
+        if let Some(index) = self.try_ready() {
+            return Poll::Ready(index);
+        }
+        for (pollable, events) in &self.handles {
+            pollable.register(cx, *events);
+        }
+        match self.try_ready() {
+            Some(index) => Poll::Ready(index),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Blocks until a pollable is ready, and returns its index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the selection is empty.
+    pub fn ready(&self) -> usize {
+        assert!(!self.handles.is_empty(), "selecting on nothing");
+        block_on(poll_fn(|cx| self.poll_ready(cx)))
+    }
+
+    /// Blocks until a pollable is ready, for at most `dur`, and returns its
+    /// index.
+    pub fn ready_timeout(&self, dur: Duration) -> Option<usize> {
+        block_on(timeout(Some(dur), poll_fn(|cx| self.poll_ready(cx)))).ok()
+    }
+}
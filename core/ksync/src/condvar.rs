@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! A condition variable implementation.
+
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use ktask::WaitQueue;
+
+use crate::MutexGuard;
+
+/// A condition variable, to block tasks until a [`Mutex`](crate::Mutex)-protected
+/// condition holds.
+///
+/// Waiting may return spuriously, so the condition must be checked again
+/// in a loop, or with [`Condvar::wait_while`].
+pub struct Condvar {
+    /// Bumped by each notification, so that a notification sent between
+    /// unlocking the mutex and blocking is not lost.
+    seq: AtomicU64,
+    wq: WaitQueue,
+}
+
+impl Condvar {
+    /// Creates a new condition variable.
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicU64::new(0),
+            wq: WaitQueue::new(),
+        }
+    }
+
+    /// Unlocks the mutex of `guard` and blocks until notified, then locks
+    /// it again.
+    pub fn wait<T: ?Sized>(&self, guard: &mut MutexGuard<'_, T>) {
+        let seq = self.seq.load(Ordering::Acquire);
+        MutexGuard::unlocked(guard, || {
+            self.wq
+                .wait_until(|| self.seq.load(Ordering::Acquire) != seq)
+        });
+    }
+
+    /// Blocks as [`Condvar::wait`] while `condition` holds on the protected
+    /// data.
+    pub fn wait_while<T: ?Sized, F>(&self, guard: &mut MutexGuard<'_, T>, mut condition: F)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut *guard) {
+            self.wait(guard);
+        }
+    }
+
+    /// Blocks as [`Condvar::wait`], for at most `dur`.
+    ///
+    /// Returns true if `dur` passed before a notification.
+    pub fn wait_timeout<T: ?Sized>(&self, guard: &mut MutexGuard<'_, T>, dur: Duration) -> bool {
+        let seq = self.seq.load(Ordering::Acquire);
+        MutexGuard::unlocked(guard, || {
+            self.wq
+                .wait_timeout_until(dur, || self.seq.load(Ordering::Acquire) != seq)
+        })
+    }
+
+    /// Wakes up one task blocked on the condition variable.
+    ///
+    /// Returns whether a task was woken up.
+    pub fn notify_one(&self) -> bool {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.wq.notify_one(false)
+    }
+
+    /// Wakes up all tasks blocked on the condition variable.
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.wq.notify_all(false);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -7,10 +7,17 @@
 //! This crate provides blocking synchronization primitives for kernel tasks:
 //!
 //! - [`Mutex`]: Mutual exclusion lock with configurable spinning
-//! - [`RwLock`]: Reader-writer lock (allows multiple readers or one writer)
+//! - [`RwLock`]: Reader-writer lock (allows multiple readers or one writer),
+//!   with upgradable reads and a fairness policy
 //! - [`Semaphore`]: Counting semaphore for resource management
+//! - [`Condvar`]: Condition variable waited on with a [`MutexGuard`]
+//! - [`Notify`]: Lightweight event to wake up a task
+//! - [`channel`]: Bounded and unbounded multi-producer, multi-consumer channels
 //! - [`spin`]: Re-export of `kspin` for spinlocks
 //!
+//! The locks and the semaphore can also be taken with a timeout, as
+//! [`lock_api::Mutex::try_lock_for`] or [`Semaphore::try_acquire_for`].
+//!
 //! # Examples
 //!
 //! ## Mutex
@@ -55,6 +62,43 @@
 //! }
 //! ```
 //!
+//! ## RwLock with Upgradable Reads
+//! ```no_run
+//! use ksync::{RawRwLock, RwLock, RwLockPolicy, RwLockUpgradableReadGuard};
+//!
+//! // Let the writers in first, so that lookups do not starve them
+//! static ROUTES: RwLock<Vec<u32>> = RwLock::const_new(
+//!     RawRwLock::with_policy(RwLockPolicy::WriterPreferred),
+//!     Vec::new(),
+//! );
+//!
+//! fn add_route(route: u32) {
+//!     let routes = ROUTES.upgradable_read();
+//!     if !routes.contains(&route) {
+//!         // readers may still share the lock until here
+//!         RwLockUpgradableReadGuard::upgrade(routes).push(route);
+//!     }
+//! }
+//! ```
+//!
+//! ## Timed Locking
+//! ```no_run
+//! use core::time::Duration;
+//!
+//! use ksync::Mutex;
+//!
+//! static DEVICE: Mutex<u32> = Mutex::new(0);
+//!
+//! fn reset() -> Result<(), &'static str> {
+//!     // Give up instead of blocking the watchdog-sensitive path forever
+//!     let mut device = DEVICE
+//!         .try_lock_for(Duration::from_millis(10))
+//!         .ok_or("device busy")?;
+//!     *device = 0;
+//!     Ok(())
+//! }
+//! ```
+//!
 //! ## Semaphore
 //! ```no_run
 //! use ksync::Semaphore;
@@ -68,17 +112,74 @@
 //! }
 //! ```
 //!
+//! ## Condvar
+//! ```no_run
+//! use ksync::{Condvar, Mutex};
+//!
+//! static READY: Mutex<bool> = Mutex::new(false);
+//! static COND: Condvar = Condvar::new();
+//!
+//! fn consumer() {
+//!     let mut ready = READY.lock();
+//!     COND.wait_while(&mut ready, |ready| !*ready);
+//! }
+//!
+//! fn producer() {
+//!     *READY.lock() = true;
+//!     COND.notify_all();
+//! }
+//! ```
+//!
+//! ## Notify
+//! ```no_run
+//! use ksync::Notify;
+//!
+//! static RX_READY: Notify = Notify::new();
+//!
+//! fn irq_handler() {
+//!     RX_READY.notify_one();
+//! }
+//!
+//! fn rx_task() {
+//!     loop {
+//!         RX_READY.wait();
+//!         // drain the receive ring
+//!     }
+//! }
+//! ```
+//!
+//! ## Channel
+//! ```no_run
+//! use ksync::channel;
+//!
+//! let (tx, rx) = channel::bounded(8);
+//!
+//! fn producer(tx: channel::Sender<u32>) {
+//!     tx.send(42).unwrap();
+//! }
+//!
+//! producer(tx);
+//! assert_eq!(rx.recv(), Ok(42));
+//! ```
+//!
 //! # Features
 //!
 //! - `stats`: Enable mutex statistics tracking (total locks, spins, blocks)
 //! - `watchdog`: Enable watchdog support for deadlock detection
+//! - `lockdep`: Validate the order the locks are taken in, each [`Mutex`] and
+//!   [`RwLock`] being a lock class of its own
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
 
+extern crate alloc;
+
 pub use kspin as spin;
 
+pub mod channel;
+mod condvar;
 mod mutex;
+mod notify;
 mod rwlock;
 mod semaphore;
 mod tests;
@@ -87,8 +188,13 @@ mod util;
 #[cfg(feature = "stats")]
 pub use self::mutex::MutexStats;
 pub use self::{
+    condvar::Condvar,
     mutex::{Mutex, MutexGuard, RawMutex},
-    rwlock::{RawRwLock, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    notify::Notify,
+    rwlock::{
+        RawRwLock, RwLock, RwLockPolicy, RwLockReadGuard, RwLockUpgradableReadGuard,
+        RwLockWriteGuard,
+    },
     semaphore::{Semaphore, SemaphoreGuard},
     util::SpinConfig,
 };
@@ -6,12 +6,16 @@
 
 #[cfg(feature = "stats")]
 use core::sync::atomic::AtomicU64 as StatsAtomicU64;
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
 use event_listener::{Event, listener};
-use ktask::{current, future::block_on};
+use khal::time::TimeValue;
+use ktask::current;
 
-use crate::util::{Spin, SpinConfig};
+use crate::util::{Spin, SpinConfig, deadline_after, wait_until};
 
 /// Statistics for mutex operations (available with `stats` feature).
 #[cfg(feature = "stats")]
@@ -87,27 +91,24 @@ impl RawMutex {
         self.stats.total_spins.store(0, Ordering::Relaxed);
         self.stats.total_blocks.store(0, Ordering::Relaxed);
     }
-}
 
-impl Default for RawMutex {
-    fn default() -> Self {
-        Self::new()
+    /// Takes the lock as [`lock_api::RawMutexTimed`], blocking until
+    /// `deadline`, if any.
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn lock_timed(&self, deadline: Option<TimeValue>) -> bool {
+        let acquired = self.lock_until(deadline);
+        // Validated as a try lock, since it gives up instead of deadlocking.
+        #[cfg(feature = "lockdep")]
+        if acquired {
+            crate::util::lockdep_acquire(self, kspin::lockdep::LockMode::Try);
+        }
+        acquired
     }
-}
-
-unsafe impl lock_api::RawMutex for RawMutex {
-    type GuardMarker = lock_api::GuardSend;
 
-    /// Initial value for an unlocked mutex.
+    /// Takes the lock, blocking until `deadline`, if any.
     ///
-    /// A “non-constant” const item is a legacy way to supply an initialized
-    /// value to downstream static items. Can hopefully be replaced with
-    /// `const fn new() -> Self` at some point.
-    #[allow(clippy::declare_interior_mutable_const)]
-    const INIT: Self = RawMutex::new();
-
-    #[inline(always)]
-    fn lock(&self) {
+    /// Returns `false` if the deadline passed first.
+    fn lock_until(&self, deadline: Option<TimeValue>) -> bool {
         #[cfg(feature = "stats")]
         self.stats.total_locks.fetch_add(1, Ordering::Relaxed);
         let current_id = current().id().as_u64();
@@ -144,7 +145,7 @@ unsafe impl lock_api::RawMutex for RawMutex {
                             current().inner().clear_waiting_lock();
                             current().inner().push_held_lock(self as *const _ as usize);
                         }
-                        break;
+                        return true;
                     }
                     Err(x) => owner_id = x,
                 }
@@ -178,12 +179,50 @@ unsafe impl lock_api::RawMutex for RawMutex {
             current()
                 .inner()
                 .set_waiting_lock(self as *const _ as usize, khal::time::now_ticks() as usize);
-            block_on(listener);
+            if !wait_until(listener, deadline) {
+                #[cfg(feature = "watchdog")]
+                current().inner().clear_waiting_lock();
+                return false;
+            }
             owner_id = self.owner_id.load(Ordering::Acquire);
         }
     }
+}
+
+impl Default for RawMutex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "lockdep")]
+impl Drop for RawMutex {
+    fn drop(&mut self) {
+        crate::util::lockdep_forget(self);
+    }
+}
+
+unsafe impl lock_api::RawMutex for RawMutex {
+    type GuardMarker = lock_api::GuardSend;
+
+    /// Initial value for an unlocked mutex.
+    ///
+    /// A “non-constant” const item is a legacy way to supply an initialized
+    /// value to downstream static items. Can hopefully be replaced with
+    /// `const fn new() -> Self` at some point.
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = RawMutex::new();
+
+    #[inline(always)]
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn lock(&self) {
+        #[cfg(feature = "lockdep")]
+        crate::util::lockdep_acquire(self, kspin::lockdep::LockMode::Exclusive);
+        self.lock_until(None);
+    }
 
     #[inline(always)]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     fn try_lock(&self) -> bool {
         let current_id = current().id().as_u64();
         // The reason for using a strong compare_exchange is explained here:
@@ -193,6 +232,8 @@ unsafe impl lock_api::RawMutex for RawMutex {
             .compare_exchange(0, current_id, Ordering::Acquire, Ordering::Relaxed)
             .is_ok();
         if acquired {
+            #[cfg(feature = "lockdep")]
+            crate::util::lockdep_acquire(self, kspin::lockdep::LockMode::Try);
             #[cfg(feature = "watchdog")]
             current().inner().push_held_lock(self as *const _ as usize);
         }
@@ -210,6 +251,8 @@ unsafe impl lock_api::RawMutex for RawMutex {
         );
         #[cfg(feature = "watchdog")]
         current().inner().pop_held_lock(self as *const _ as usize);
+        #[cfg(feature = "lockdep")]
+        crate::util::lockdep_release(self);
         self.event.notify(1);
     }
 
@@ -219,6 +262,22 @@ unsafe impl lock_api::RawMutex for RawMutex {
     }
 }
 
+unsafe impl lock_api::RawMutexTimed for RawMutex {
+    type Duration = Duration;
+    /// A wall-clock deadline, as for [`ktask::future::timeout_at`].
+    type Instant = TimeValue;
+
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn try_lock_for(&self, timeout: Duration) -> bool {
+        self.lock_timed(deadline_after(timeout))
+    }
+
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn try_lock_until(&self, timeout: TimeValue) -> bool {
+        self.lock_timed(Some(timeout))
+    }
+}
+
 /// An alias of [`lock_api::Mutex`].
 pub type Mutex<T> = lock_api::Mutex<RawMutex, T>;
 /// An alias of [`lock_api::MutexGuard`].
@@ -0,0 +1,79 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! A lightweight event to notify tasks.
+
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use ktask::WaitQueue;
+
+/// An event for a task to wait on until another task, or an interrupt
+/// handler, notifies it.
+///
+/// [`Notify::notify_one`] stores a permit if no task is waiting, which the
+/// next wait consumes at once, so that a notification sent before the wait
+/// is not lost. At most one permit is stored.
+pub struct Notify {
+    permit: AtomicBool,
+    /// Bumped by [`Notify::notify_waiters`], to wake the tasks waiting
+    /// then only.
+    generation: AtomicU64,
+    wq: WaitQueue,
+}
+
+impl Notify {
+    /// Creates a new event, without a permit.
+    pub const fn new() -> Self {
+        Self {
+            permit: AtomicBool::new(false),
+            generation: AtomicU64::new(0),
+            wq: WaitQueue::new(),
+        }
+    }
+
+    fn notified(&self, generation: u64) -> bool {
+        self.permit.swap(false, Ordering::AcqRel)
+            || self.generation.load(Ordering::Acquire) != generation
+    }
+
+    /// Blocks until notified, or consumes the stored permit.
+    pub fn wait(&self) {
+        let generation = self.generation.load(Ordering::Acquire);
+        self.wq.wait_until(|| self.notified(generation));
+    }
+
+    /// Blocks as [`Notify::wait`], for at most `dur`.
+    ///
+    /// Returns true if `dur` passed before a notification.
+    pub fn wait_timeout(&self, dur: Duration) -> bool {
+        let generation = self.generation.load(Ordering::Acquire);
+        self.wq
+            .wait_timeout_until(dur, || self.notified(generation))
+    }
+
+    /// Wakes up one waiting task, or stores a permit for the next wait.
+    ///
+    /// Does not yield the current task.
+    pub fn notify_one(&self) {
+        self.permit.store(true, Ordering::Release);
+        self.wq.notify_one(false);
+    }
+
+    /// Wakes up all waiting tasks, without storing a permit.
+    ///
+    /// Does not yield the current task.
+    pub fn notify_waiters(&self) {
+        self.generation.fetch_add(1, Ordering::Release);
+        self.wq.notify_all(false);
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
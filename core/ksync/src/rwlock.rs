@@ -4,133 +4,282 @@
 
 //! A reader-writer lock implementation.
 
-use core::sync::atomic::{AtomicU32, Ordering};
+use core::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
 
 use event_listener::{Event, listener};
-use ktask::future::block_on;
+use khal::time::TimeValue;
+
+use crate::util::{deadline_after, wait_until};
 
 const WRITE_LOCKED: u32 = 1 << 31;
-const MAX_READERS: u32 = WRITE_LOCKED - 1;
+const UPGRADABLE: u32 = 1 << 30;
+const MAX_READERS: u32 = UPGRADABLE - 1;
+
+/// Which of the readers and the writers a [`RawRwLock`] lets in first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RwLockPolicy {
+    /// New readers share the lock even if writers are waiting, which may
+    /// starve the writers under a steady stream of readers. Read locks may
+    /// be taken recursively.
+    #[default]
+    ReaderPreferred,
+    /// New readers wait while a writer is waiting, so that the writers are
+    /// not starved. A task must not take the read lock again while holding
+    /// it, or it may deadlock with a waiting writer.
+    WriterPreferred,
+}
 
 /// A [`lock_api::RawRwLock`] implementation.
 ///
 /// Allows multiple readers or a single writer.
-/// The high bit of the state represents the write lock,
-/// and the low 31 bits represent the reader count.
+/// The high bit of the state represents the write lock, the next one the
+/// upgradable read lock, and the low 30 bits represent the reader count,
+/// including the upgradable reader.
 pub struct RawRwLock {
-    state: AtomicU32, // High bit: write lock, low 31 bits: reader count
+    state: AtomicU32, // High bit: write lock, then upgradable, low 30 bits: reader count
+    /// Number of the tasks waiting for the write lock or to upgrade.
+    writers_waiting: AtomicU32,
+    policy: RwLockPolicy,
     writer_event: Event,
     reader_event: Event,
+    upgrade_event: Event,
 }
 
 impl RawRwLock {
-    /// Creates a new [`RawRwLock`].
+    /// Creates a new [`RawRwLock`], preferring the readers.
     pub const fn new() -> Self {
+        Self::with_policy(RwLockPolicy::ReaderPreferred)
+    }
+
+    /// Creates a new [`RawRwLock`] with the given fairness policy.
+    pub const fn with_policy(policy: RwLockPolicy) -> Self {
         Self {
             state: AtomicU32::new(0),
+            writers_waiting: AtomicU32::new(0),
+            policy,
             writer_event: Event::new(),
             reader_event: Event::new(),
+            upgrade_event: Event::new(),
         }
     }
-}
 
-impl Default for RawRwLock {
-    fn default() -> Self {
-        Self::new()
+    /// Returns the fairness policy of the lock.
+    pub fn policy(&self) -> RwLockPolicy {
+        self.policy
     }
-}
 
-unsafe impl lock_api::RawRwLock for RawRwLock {
-    type GuardMarker = lock_api::GuardSend;
+    /// Returns the number of tasks waiting for the write lock or to upgrade.
+    #[cfg(unittest)]
+    pub(crate) fn writers_waiting(&self) -> u32 {
+        self.writers_waiting.load(Ordering::Acquire)
+    }
 
-    #[allow(clippy::declare_interior_mutable_const)]
-    const INIT: Self = RawRwLock::new();
+    /// Whether a new reader, or upgradable reader if `upgradable`, must wait
+    /// in `state`.
+    fn must_wait_read(&self, state: u32, upgradable: bool) -> bool {
+        let busy = if upgradable {
+            WRITE_LOCKED | UPGRADABLE
+        } else {
+            WRITE_LOCKED
+        };
+        state & busy != 0
+            || (self.policy == RwLockPolicy::WriterPreferred
+                && self.writers_waiting.load(Ordering::Acquire) != 0)
+    }
 
-    #[inline]
-    fn lock_shared(&self) {
+    /// Takes a read lock, upgradable if `upgradable`, waiting for it until
+    /// `deadline`, if any.
+    ///
+    /// Returns `false` if the deadline passed first.
+    fn lock_read(&self, upgradable: bool, deadline: Option<TimeValue>) -> bool {
+        let new_state = if upgradable { UPGRADABLE + 1 } else { 1 };
         loop {
             let state = self.state.load(Ordering::Relaxed);
 
-            // Check if write locked
-            if state & WRITE_LOCKED != 0 {
+            // Check if write locked, or a writer is let in first
+            if self.must_wait_read(state, upgradable) {
                 listener!(self.reader_event => listener);
-                if self.state.load(Ordering::Acquire) & WRITE_LOCKED != 0 {
-                    block_on(listener);
+                if self.must_wait_read(self.state.load(Ordering::Acquire), upgradable)
+                    && !wait_until(listener, deadline)
+                {
+                    return false;
                 }
                 continue;
             }
 
             // Check reader count
-            if state >= MAX_READERS {
+            if state & MAX_READERS == MAX_READERS {
                 panic!("too many readers");
             }
 
             // Try to increment reader count
             match self.state.compare_exchange_weak(
                 state,
-                state + 1,
+                state + new_state,
                 Ordering::Acquire,
                 Ordering::Relaxed,
             ) {
-                Ok(_) => return,
+                Ok(_) => return true,
                 Err(_) => continue,
             }
         }
     }
 
-    #[inline]
-    fn try_lock_shared(&self) -> bool {
+    /// Takes a read lock, upgradable if `upgradable`, without waiting.
+    fn try_lock_read(&self, upgradable: bool) -> bool {
+        let new_state = if upgradable { UPGRADABLE + 1 } else { 1 };
         let state = self.state.load(Ordering::Relaxed);
 
-        if state & WRITE_LOCKED != 0 || state >= MAX_READERS {
+        if self.must_wait_read(state, upgradable) || state & MAX_READERS == MAX_READERS {
             return false;
         }
 
         // Using strong compare_exchange here since this is a single-shot attempt
-        // without retry loop, unlike lock_shared which uses _weak in a loop
+        // without retry loop, unlike lock_read which uses _weak in a loop
         self.state
-            .compare_exchange(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+            .compare_exchange(
+                state,
+                state + new_state,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
             .is_ok()
     }
 
+    /// Swaps the state from `from`, no other reader or writer holding the
+    /// lock, to write locked.
     #[inline]
-    unsafe fn unlock_shared(&self) {
-        let state = self.state.fetch_sub(1, Ordering::Release);
+    fn try_lock_write(&self, from: u32) -> bool {
+        self.state
+            .compare_exchange(from, WRITE_LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Takes the write lock from the state `from`, woken up by `event`,
+    /// waiting for it until `deadline`, if any.
+    ///
+    /// Returns `false` if the deadline passed first.
+    fn lock_write(&self, from: u32, event: &Event, deadline: Option<TimeValue>) -> bool {
+        if self.try_lock_write(from) {
+            return true;
+        }
+
+        self.writers_waiting.fetch_add(1, Ordering::AcqRel);
+        let acquired = loop {
+            // Try to acquire write lock
+            if self.try_lock_write(from) {
+                break true;
+            }
+            listener!(event => listener);
+            if self.state.load(Ordering::Acquire) != from && !wait_until(listener, deadline) {
+                break false;
+            }
+        };
+        // Wake up the readers let in after the writers, if none is left
+        if self.writers_waiting.fetch_sub(1, Ordering::AcqRel) == 1 && !acquired {
+            self.reader_event.notify(usize::MAX);
+        }
+        acquired
+    }
+
+    /// Takes a lock as [`lock_api::RawRwLockTimed`] with `lock`, which waits
+    /// for it until a deadline.
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn lock_timed(&self, lock: impl FnOnce(&Self) -> bool) -> bool {
+        let acquired = lock(self);
+        // Validated as a try lock, since it gives up instead of deadlocking.
+        #[cfg(feature = "lockdep")]
+        if acquired {
+            crate::util::lockdep_acquire(self, kspin::lockdep::LockMode::Try);
+        }
+        acquired
+    }
 
-        // Wake up a waiting writer if this was the last reader
-        if state == 1 {
+    /// Releases a read lock, `released` being its share of the state.
+    fn unlock_read(&self, released: u32) {
+        let state = self.state.fetch_sub(released, Ordering::Release) - released;
+
+        // Wake up a waiting writer if this was the last reader, or the
+        // upgradable reader waiting for the others to leave
+        if state == 0 {
             self.writer_event.notify(1);
+        } else if state == UPGRADABLE + 1 {
+            self.upgrade_event.notify(1);
         }
     }
+}
+
+impl Default for RawRwLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "lockdep")]
+impl Drop for RawRwLock {
+    fn drop(&mut self) {
+        crate::util::lockdep_forget(self);
+    }
+}
+
+unsafe impl lock_api::RawRwLock for RawRwLock {
+    type GuardMarker = lock_api::GuardSend;
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = RawRwLock::new();
 
     #[inline]
-    fn lock_exclusive(&self) {
-        loop {
-            // Try to acquire write lock
-            match self
-                .state
-                .compare_exchange(0, WRITE_LOCKED, Ordering::Acquire, Ordering::Relaxed)
-            {
-                Ok(_) => return,
-                Err(_) => {
-                    listener!(self.writer_event => listener);
-                    if self.state.load(Ordering::Acquire) != 0 {
-                        block_on(listener);
-                    }
-                }
-            }
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn lock_shared(&self) {
+        #[cfg(feature = "lockdep")]
+        crate::util::lockdep_acquire(self, kspin::lockdep::LockMode::Shared);
+        self.lock_read(false, None);
+    }
+
+    #[inline]
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn try_lock_shared(&self) -> bool {
+        let acquired = self.try_lock_read(false);
+        #[cfg(feature = "lockdep")]
+        if acquired {
+            crate::util::lockdep_acquire(self, kspin::lockdep::LockMode::Try);
         }
+        acquired
+    }
+
+    #[inline]
+    unsafe fn unlock_shared(&self) {
+        #[cfg(feature = "lockdep")]
+        crate::util::lockdep_release(self);
+        self.unlock_read(1);
     }
 
     #[inline]
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn lock_exclusive(&self) {
+        #[cfg(feature = "lockdep")]
+        crate::util::lockdep_acquire(self, kspin::lockdep::LockMode::Exclusive);
+        self.lock_write(0, &self.writer_event, None);
+    }
+
+    #[inline]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     fn try_lock_exclusive(&self) -> bool {
-        self.state
-            .compare_exchange(0, WRITE_LOCKED, Ordering::Acquire, Ordering::Relaxed)
-            .is_ok()
+        let acquired = self.try_lock_write(0);
+        #[cfg(feature = "lockdep")]
+        if acquired {
+            crate::util::lockdep_acquire(self, kspin::lockdep::LockMode::Try);
+        }
+        acquired
     }
 
     #[inline]
     unsafe fn unlock_exclusive(&self) {
+        #[cfg(feature = "lockdep")]
+        crate::util::lockdep_release(self);
         self.state.store(0, Ordering::Release);
 
         // Wake up all waiting readers and one writer
@@ -139,9 +288,132 @@ unsafe impl lock_api::RawRwLock for RawRwLock {
     }
 }
 
+unsafe impl lock_api::RawRwLockUpgrade for RawRwLock {
+    #[inline]
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn lock_upgradable(&self) {
+        // Exclusive among the upgradable readers, which are validated as
+        // writers.
+        #[cfg(feature = "lockdep")]
+        crate::util::lockdep_acquire(self, kspin::lockdep::LockMode::Exclusive);
+        self.lock_read(true, None);
+    }
+
+    #[inline]
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn try_lock_upgradable(&self) -> bool {
+        let acquired = self.try_lock_read(true);
+        #[cfg(feature = "lockdep")]
+        if acquired {
+            crate::util::lockdep_acquire(self, kspin::lockdep::LockMode::Try);
+        }
+        acquired
+    }
+
+    #[inline]
+    unsafe fn unlock_upgradable(&self) {
+        #[cfg(feature = "lockdep")]
+        crate::util::lockdep_release(self);
+        self.unlock_read(UPGRADABLE + 1);
+
+        // Wake up the waiting upgradable readers
+        self.reader_event.notify(usize::MAX);
+    }
+
+    #[inline]
+    unsafe fn upgrade(&self) {
+        // Wait for the other readers to leave
+        self.lock_write(UPGRADABLE + 1, &self.upgrade_event, None);
+    }
+
+    #[inline]
+    unsafe fn try_upgrade(&self) -> bool {
+        self.try_lock_write(UPGRADABLE + 1)
+    }
+}
+
+unsafe impl lock_api::RawRwLockTimed for RawRwLock {
+    type Duration = Duration;
+    /// A wall-clock deadline, as for [`ktask::future::timeout_at`].
+    type Instant = TimeValue;
+
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn try_lock_shared_for(&self, timeout: Duration) -> bool {
+        self.lock_timed(|lock| lock.lock_read(false, deadline_after(timeout)))
+    }
+
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn try_lock_shared_until(&self, timeout: TimeValue) -> bool {
+        self.lock_timed(|lock| lock.lock_read(false, Some(timeout)))
+    }
+
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn try_lock_exclusive_for(&self, timeout: Duration) -> bool {
+        self.lock_timed(|lock| lock.lock_write(0, &lock.writer_event, deadline_after(timeout)))
+    }
+
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn try_lock_exclusive_until(&self, timeout: TimeValue) -> bool {
+        self.lock_timed(|lock| lock.lock_write(0, &lock.writer_event, Some(timeout)))
+    }
+}
+
+unsafe impl lock_api::RawRwLockUpgradeTimed for RawRwLock {
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn try_lock_upgradable_for(&self, timeout: Duration) -> bool {
+        self.lock_timed(|lock| lock.lock_read(true, deadline_after(timeout)))
+    }
+
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    fn try_lock_upgradable_until(&self, timeout: TimeValue) -> bool {
+        self.lock_timed(|lock| lock.lock_read(true, Some(timeout)))
+    }
+
+    unsafe fn try_upgrade_for(&self, timeout: Duration) -> bool {
+        self.lock_write(UPGRADABLE + 1, &self.upgrade_event, deadline_after(timeout))
+    }
+
+    unsafe fn try_upgrade_until(&self, timeout: TimeValue) -> bool {
+        self.lock_write(UPGRADABLE + 1, &self.upgrade_event, Some(timeout))
+    }
+}
+
+unsafe impl lock_api::RawRwLockDowngrade for RawRwLock {
+    #[inline]
+    unsafe fn downgrade(&self) {
+        self.state.store(1, Ordering::Release);
+
+        // Wake up all waiting readers
+        self.reader_event.notify(usize::MAX);
+    }
+}
+
+unsafe impl lock_api::RawRwLockUpgradeDowngrade for RawRwLock {
+    #[inline]
+    unsafe fn downgrade_upgradable(&self) {
+        self.state.fetch_sub(UPGRADABLE, Ordering::Release);
+
+        // Wake up the waiting upgradable readers
+        self.reader_event.notify(usize::MAX);
+    }
+
+    #[inline]
+    unsafe fn downgrade_to_upgradable(&self) {
+        self.state.store(UPGRADABLE + 1, Ordering::Release);
+
+        // Wake up all waiting readers
+        self.reader_event.notify(usize::MAX);
+    }
+}
+
 /// A reader-writer lock.
 pub type RwLock<T> = lock_api::RwLock<RawRwLock, T>;
 /// A read guard for a [`RwLock`].
 pub type RwLockReadGuard<'a, T> = lock_api::RwLockReadGuard<'a, RawRwLock, T>;
+/// An upgradable read guard for a [`RwLock`].
+///
+/// Shares the lock with the readers, but not with the other upgradable
+/// readers, so that it can be upgraded to a write guard.
+pub type RwLockUpgradableReadGuard<'a, T> = lock_api::RwLockUpgradableReadGuard<'a, RawRwLock, T>;
 /// A write guard for a [`RwLock`].
 pub type RwLockWriteGuard<'a, T> = lock_api::RwLockWriteGuard<'a, RawRwLock, T>;
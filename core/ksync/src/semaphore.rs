@@ -4,10 +4,15 @@
 
 //! A counting semaphore implementation.
 
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
 
 use event_listener::{Event, listener};
-use ktask::future::block_on;
+use khal::time::TimeValue;
+
+use crate::util::{deadline_after, wait_until};
 
 /// A counting semaphore.
 ///
@@ -28,14 +33,22 @@ impl Semaphore {
 
     /// Acquires a permit, blocking until one is available.
     pub fn acquire(&self) {
+        self.acquire_until(None);
+    }
+
+    /// Acquires a permit, blocking until one is available or `deadline`, if
+    /// any, passes.
+    ///
+    /// Returns `false` if the deadline passed first.
+    fn acquire_until(&self, deadline: Option<TimeValue>) -> bool {
         loop {
             // Use Acquire ordering for consistency
             let count = self.count.load(Ordering::Acquire);
 
             if count == 0 {
                 listener!(self.event => listener);
-                if self.count.load(Ordering::Acquire) == 0 {
-                    block_on(listener);
+                if self.count.load(Ordering::Acquire) == 0 && !wait_until(listener, deadline) {
+                    return false;
                 }
                 continue;
             }
@@ -46,7 +59,7 @@ impl Semaphore {
                 Ordering::Acquire,
                 Ordering::Relaxed,
             ) {
-                Ok(_) => return,
+                Ok(_) => return true,
                 Err(_) => continue,
             }
         }
@@ -81,6 +94,22 @@ impl Semaphore {
         false
     }
 
+    /// Acquires a permit, blocking for at most `timeout`.
+    ///
+    /// Returns `true` if a permit was acquired, `false` if `timeout` passed
+    /// first.
+    pub fn try_acquire_for(&self, timeout: Duration) -> bool {
+        self.acquire_until(deadline_after(timeout))
+    }
+
+    /// Acquires a permit, blocking until the wall-clock `deadline` at most.
+    ///
+    /// Returns `true` if a permit was acquired, `false` if `deadline` passed
+    /// first.
+    pub fn try_acquire_until(&self, deadline: TimeValue) -> bool {
+        self.acquire_until(Some(deadline))
+    }
+
     /// Releases a permit.
     ///
     /// Note: This method allows releasing more permits than the semaphore was
@@ -102,6 +131,13 @@ impl Semaphore {
         self.acquire();
         SemaphoreGuard { sem: self }
     }
+
+    /// Acquires a permit as [`Semaphore::try_acquire_for`], and returns a
+    /// guard if it was acquired.
+    pub fn try_acquire_guard_for(&self, timeout: Duration) -> Option<SemaphoreGuard<'_>> {
+        self.try_acquire_for(timeout)
+            .then_some(SemaphoreGuard { sem: self })
+    }
 }
 
 /// RAII guard for a semaphore permit.
@@ -5,10 +5,19 @@
 extern crate alloc;
 
 use alloc::{sync::Arc, vec, vec::Vec};
+use core::time::Duration;
 
+use kpoll::{IoEvents, Pollable};
 use unittest::{assert, assert_eq, def_test};
 
-use super::{Mutex, RwLock, Semaphore, SpinConfig};
+use super::{
+    Condvar, Mutex, Notify, RawRwLock, RwLock, RwLockPolicy, RwLockUpgradableReadGuard,
+    RwLockWriteGuard, Semaphore, SpinConfig,
+    channel::{
+        self, RecvError, RecvTimeoutError, Select, SendError, SendTimeoutError, TryRecvError,
+        TrySendError,
+    },
+};
 
 // ============================================================================
 // Mutex Tests
@@ -122,6 +131,17 @@ fn test_mutex_boundary_zero_and_overflow() {
     }
 }
 
+#[def_test]
+fn test_mutex_try_lock_for() {
+    let mutex = Mutex::new(0);
+
+    let mut guard = mutex.try_lock_for(Duration::from_millis(10)).unwrap();
+    *guard = 1;
+    drop(guard);
+
+    assert_eq!(*mutex.try_lock_for(Duration::ZERO).unwrap(), 1);
+}
+
 // ============================================================================
 // RwLock Tests
 // ============================================================================
@@ -259,6 +279,141 @@ fn test_rwlock_writer_blocks_readers() {
     }
 }
 
+#[def_test]
+fn test_rwlock_upgradable_read_shared_with_readers() {
+    let lock = RwLock::new(1);
+
+    let u = lock.upgradable_read();
+    // Readers share the lock with the upgradable reader, but writers and
+    // other upgradable readers do not
+    let r = lock.try_read();
+    assert!(r.is_some());
+    assert!(lock.try_upgradable_read().is_none());
+    assert!(lock.try_write().is_none());
+
+    // Cannot upgrade while another reader holds the lock
+    let u = match RwLockUpgradableReadGuard::try_upgrade(u) {
+        Ok(_) => panic!("upgraded with a reader holding the lock"),
+        Err(u) => u,
+    };
+    drop(r);
+
+    let mut w = RwLockUpgradableReadGuard::upgrade(u);
+    *w = 2;
+    assert!(lock.try_read().is_none());
+    drop(w);
+
+    assert_eq!(*lock.read(), 2);
+    assert!(lock.try_upgradable_read().is_some());
+}
+
+#[def_test]
+fn test_rwlock_downgrade() {
+    let lock = RwLock::new(0);
+
+    let mut w = lock.write();
+    *w = 1;
+    let u = RwLockWriteGuard::downgrade_to_upgradable(w);
+    assert_eq!(*u, 1);
+    assert!(lock.try_read().is_some());
+    assert!(lock.try_upgradable_read().is_none());
+
+    let r = RwLockUpgradableReadGuard::downgrade(u);
+    assert_eq!(*r, 1);
+    assert!(lock.try_upgradable_read().is_some());
+    assert!(lock.try_write().is_none());
+    drop(r);
+
+    let r = RwLockWriteGuard::downgrade(lock.write());
+    assert!(lock.try_read().is_some());
+    assert!(lock.try_write().is_none());
+    drop(r);
+    assert!(lock.try_write().is_some());
+}
+
+#[def_test]
+fn test_rwlock_writer_preferred() {
+    let raw = RawRwLock::with_policy(RwLockPolicy::WriterPreferred);
+    assert_eq!(raw.policy(), RwLockPolicy::WriterPreferred);
+    assert_eq!(RawRwLock::new().policy(), RwLockPolicy::ReaderPreferred);
+    let lock = RwLock::const_new(raw, 0);
+
+    // Without waiting writers, readers still share the lock
+    let r1 = lock.read();
+    let r2 = lock.try_read();
+    assert!(r2.is_some());
+    drop(r2);
+    drop(r1);
+
+    *RwLockUpgradableReadGuard::upgrade(lock.upgradable_read()) = 1;
+    assert_eq!(*lock.read(), 1);
+
+    let lock = Arc::new(lock);
+    let r = lock.read();
+    let writer = ktask::spawn({
+        let lock = lock.clone();
+        move || *lock.write() = 2
+    });
+    while unsafe { lock.raw() }.writers_waiting() == 0 {
+        ktask::yield_now();
+    }
+    // New readers wait behind the queued writer
+    assert!(lock.try_read().is_none());
+
+    let seen = Arc::new(Mutex::new(0));
+    let reader = ktask::spawn({
+        let (lock, seen) = (lock.clone(), seen.clone());
+        move || *seen.lock() = *lock.read()
+    });
+    ktask::yield_now();
+    drop(r);
+    writer.join();
+    reader.join();
+    // The later reader only got in after the writer
+    assert_eq!(*seen.lock(), 2);
+}
+
+#[def_test]
+fn test_rwlock_timed() {
+    let lock = RwLock::new(0);
+
+    let w = lock.write();
+    assert!(lock.try_read_for(Duration::from_millis(10)).is_none());
+    assert!(
+        lock.try_upgradable_read_for(Duration::from_millis(10))
+            .is_none()
+    );
+    drop(w);
+
+    let r = lock.try_read_for(Duration::from_millis(10)).unwrap();
+    assert!(lock.try_write_for(Duration::from_millis(10)).is_none());
+    let u = lock
+        .try_upgradable_read_for(Duration::from_millis(10))
+        .unwrap();
+    // Still shared with a reader
+    let u = match RwLockUpgradableReadGuard::try_upgrade_for(u, Duration::from_millis(10)) {
+        Ok(_) => panic!("upgraded with a reader holding the lock"),
+        Err(u) => u,
+    };
+    drop(r);
+
+    let mut w = RwLockUpgradableReadGuard::try_upgrade_for(u, Duration::from_millis(10)).unwrap();
+    *w = 1;
+    drop(w);
+    assert_eq!(*lock.try_write_for(Duration::from_millis(10)).unwrap(), 1);
+}
+
+#[def_test]
+fn test_rwlock_writer_preferred_timed_out_writer() {
+    let lock = RwLock::const_new(RawRwLock::with_policy(RwLockPolicy::WriterPreferred), 0);
+
+    // A writer giving up lets the readers in again
+    let r = lock.read();
+    assert!(lock.try_write_for(Duration::from_millis(10)).is_none());
+    assert!(lock.try_read().is_some());
+    drop(r);
+}
+
 // ============================================================================
 // Semaphore Tests
 // ============================================================================
@@ -370,3 +525,160 @@ fn test_semaphore_multiple_release_overflow() {
     assert_eq!(sem.available_permits(), 0);
     assert!(!sem.try_acquire());
 }
+
+#[def_test]
+fn test_semaphore_try_acquire_for() {
+    let sem = Semaphore::new(1);
+
+    assert!(sem.try_acquire_for(Duration::from_millis(10)));
+    assert!(!sem.try_acquire_for(Duration::from_millis(10)));
+    assert!(
+        sem.try_acquire_guard_for(Duration::from_millis(10))
+            .is_none()
+    );
+    sem.release();
+
+    {
+        let _guard = sem
+            .try_acquire_guard_for(Duration::from_millis(10))
+            .unwrap();
+        assert_eq!(sem.available_permits(), 0);
+    }
+    assert_eq!(sem.available_permits(), 1);
+}
+
+// ============================================================================
+// Condvar Tests
+// ============================================================================
+
+#[def_test]
+fn test_condvar_wait_timeout_relocks() {
+    // Test that a timed-out wait holds the mutex again
+    let mutex = Mutex::new(0);
+    let cond = Condvar::new();
+
+    let mut guard = mutex.lock();
+    assert!(cond.wait_timeout(&mut guard, Duration::from_millis(10)));
+    *guard += 1;
+    assert!(mutex.is_locked());
+    drop(guard);
+
+    assert_eq!(*mutex.lock(), 1);
+}
+
+#[def_test]
+fn test_condvar_wait_while_condition_false() {
+    // Test that wait_while returns at once if the condition does not hold
+    let mutex = Mutex::new(true);
+    let cond = Condvar::new();
+
+    let mut guard = mutex.lock();
+    cond.wait_while(&mut guard, |ready| !*ready);
+    assert!(*guard);
+
+    // Nobody is waiting to be notified
+    assert!(!cond.notify_one());
+}
+
+// ============================================================================
+// Notify Tests
+// ============================================================================
+
+#[def_test]
+fn test_notify_stored_permit() {
+    // Test that a notification sent before waiting is not lost
+    let notify = Notify::new();
+
+    notify.notify_one();
+    notify.notify_one();
+    notify.wait();
+
+    // Only one permit is stored
+    assert!(notify.wait_timeout(Duration::from_millis(10)));
+}
+
+#[def_test]
+fn test_notify_waiters_stores_no_permit() {
+    // Test that notify_waiters only wakes the tasks waiting already
+    let notify = Notify::new();
+
+    notify.notify_waiters();
+    assert!(notify.wait_timeout(Duration::from_millis(10)));
+}
+
+// ============================================================================
+// Channel Tests
+// ============================================================================
+
+#[def_test]
+fn test_channel_bounded_full() {
+    // Test that a bounded channel refuses messages once full
+    let (tx, rx) = channel::bounded(2);
+
+    assert_eq!(tx.try_send(1), Ok(()));
+    assert_eq!(tx.try_send(2), Ok(()));
+    assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+    assert_eq!(
+        tx.send_timeout(3, Duration::from_millis(10)),
+        Err(SendTimeoutError::Timeout(3))
+    );
+    assert_eq!(rx.len(), 2);
+
+    // Messages are received in order
+    assert_eq!(rx.recv(), Ok(1));
+    assert_eq!(tx.send(3), Ok(()));
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![2, 3]);
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    assert_eq!(
+        rx.recv_timeout(Duration::from_millis(10)),
+        Err(RecvTimeoutError::Timeout)
+    );
+}
+
+#[def_test]
+fn test_channel_disconnect() {
+    // Test that dropping all of one end disconnects the channel
+    let (tx, rx) = channel::unbounded();
+    let tx2 = tx.clone();
+
+    tx.send(1).unwrap();
+    drop(tx);
+    assert!(!rx.is_disconnected());
+    drop(tx2);
+    assert!(rx.is_disconnected());
+
+    // Messages sent before are still received
+    assert!(rx.poll().contains(IoEvents::IN | IoEvents::HUP));
+    assert_eq!(rx.try_recv(), Ok(1));
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    assert_eq!(rx.recv(), Err(RecvError));
+
+    let (tx, rx) = channel::bounded(1);
+    drop(rx);
+    assert!(tx.poll().contains(IoEvents::ERR));
+    assert_eq!(tx.send(1), Err(SendError(1)));
+}
+
+#[def_test]
+fn test_channel_select() {
+    // Test that select returns the first ready channel
+    let (tx1, rx1) = channel::unbounded::<u32>();
+    let (tx2, rx2) = channel::bounded::<u32>(1);
+
+    let mut select = Select::new();
+    let r1 = select.recv(&rx1);
+    let r2 = select.recv(&rx2);
+    assert_eq!(select.try_ready(), None);
+    assert_eq!(select.ready_timeout(Duration::from_millis(10)), None);
+
+    tx2.send(2).unwrap();
+    assert_eq!(select.ready(), r2);
+    tx1.send(1).unwrap();
+    assert_eq!(select.ready(), r1);
+
+    let mut select = Select::new();
+    let s2 = select.send(&tx2);
+    assert_eq!(select.try_ready(), None);
+    assert_eq!(rx2.try_recv(), Ok(2));
+    assert_eq!(select.try_ready(), Some(s2));
+}
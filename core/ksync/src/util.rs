@@ -4,7 +4,13 @@
 
 //! Utility types and functions for synchronization primitives.
 
-use ktask::yield_now;
+use core::time::Duration;
+
+use khal::time::{TimeValue, wall_time};
+use ktask::{
+    future::{block_on, timeout_at},
+    yield_now,
+};
 
 /// Spin configuration for blocking synchronization primitives.
 ///
@@ -66,3 +72,44 @@ impl Spin {
         true
     }
 }
+
+/// Returns the wall-clock deadline `dur` from now, `None` if it is too far
+/// to be reached.
+pub(crate) fn deadline_after(dur: Duration) -> Option<TimeValue> {
+    dur.checked_add(wall_time())
+}
+
+/// Blocks on `listener` until it is notified, or until `deadline`, if any,
+/// passes.
+///
+/// Returns `false` if the deadline passed first.
+pub(crate) fn wait_until<F: Future>(listener: F, deadline: Option<TimeValue>) -> bool {
+    block_on(timeout_at(deadline, listener)).is_ok()
+}
+
+/// Records a sleeping lock at `lock`, a lockdep class of its own, being
+/// taken.
+#[cfg(feature = "lockdep")]
+#[track_caller]
+pub(crate) fn lockdep_acquire<L>(lock: &L, mode: kspin::lockdep::LockMode) {
+    let addr = lock as *const L as usize;
+    kspin::lockdep::acquire(
+        addr,
+        kspin::lockdep::ClassKey::Addr(addr),
+        true,
+        mode,
+        core::panic::Location::caller(),
+    );
+}
+
+/// Records a sleeping lock at `lock` being released.
+#[cfg(feature = "lockdep")]
+pub(crate) fn lockdep_release<L>(lock: &L) {
+    kspin::lockdep::release(lock as *const L as usize, true);
+}
+
+/// Forgets the lockdep class of the sleeping lock at `lock`, being freed.
+#[cfg(feature = "lockdep")]
+pub(crate) fn lockdep_forget<L>(lock: &L) {
+    kspin::lockdep::forget(kspin::lockdep::ClassKey::Addr(lock as *const L as usize));
+}
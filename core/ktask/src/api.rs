@@ -19,8 +19,9 @@ pub(crate) use crate::run_queue::{current_run_queue, select_run_queue};
 #[cfg(feature = "task-ext")]
 pub use crate::task::{KTaskExt, TaskExt};
 pub use crate::{
-    task::{CurrentTask, TaskId, TaskInner, TaskState},
-    timers::register_timer_callback,
+    stats::{CpuStats, TaskStats, set_user_mode, stats},
+    task::{CurrentTask, MAX_RT_PRIO, SchedPolicy, TaskId, TaskInner, TaskState},
+    timers::{HrTimer, register_timer_callback, start_hrtimer},
     wait_queue::WaitQueue,
 };
 
@@ -103,8 +104,13 @@ pub fn init_scheduler_with_cpu_num(cpu_num: usize) {
     CPU_NUM.store(cpu_num, core::sync::atomic::Ordering::Relaxed);
 
     crate::run_queue::init();
+    khal::softirq::register(khal::softirq::SoftIrq::Timer, crate::timers::check_events);
+    kmetrics::register(&crate::run_queue::CONTEXT_SWITCHES);
+    kmetrics::register(&crate::run_queue::TASKS_SPAWNED);
+    kmetrics::register(&crate::run_queue::TASKS_STOLEN);
 
     info!("  use {} scheduler.", Scheduler::scheduler_name());
+    crate::workqueue::init_percpu();
 }
 
 pub(crate) fn active_cpu_num() -> usize {
@@ -114,22 +120,28 @@ pub(crate) fn active_cpu_num() -> usize {
 /// Initializes the task scheduler for secondary CPUs.
 pub fn init_scheduler_secondary() {
     crate::run_queue::init_secondary();
+    crate::workqueue::init_percpu();
 }
 
-/// Handles periodic timer ticks for the task manager.
+/// Handles the timer interrupts for the task manager.
 ///
-/// For example, advance scheduler states, checks timed events, etc.
+/// For example, advance scheduler states on ticks, checks timed events,
+/// etc. The timed events are checked in the timer softirq, once the IRQ
+/// handlers are done. The timer is armed again for the next of them.
 pub fn on_timer_tick() {
     use kspin::NoOp;
-    crate::timers::check_events();
-    // Since irq and preemption are both disabled here,
-    // we can get current run queue with the default `kspin::NoOp`.
-    current_run_queue::<NoOp>().scheduler_timer_tick();
+    if crate::timers::on_timer_irq() {
+        // Since irq and preemption are both disabled here,
+        // we can get current run queue with the default `kspin::NoOp`.
+        current_run_queue::<NoOp>().scheduler_timer_tick();
+    }
+    khal::softirq::raise(khal::softirq::SoftIrq::Timer);
 }
 
 /// Adds the given task to the run queue, returns the task reference.
 pub fn spawn_task(task: TaskInner) -> KtaskRef {
     let task_ref = task.into_arc();
+    crate::run_queue::TASKS_SPAWNED.inc();
     select_run_queue::<NoPreemptIrqSave>(&task_ref).add_task(task_ref.clone());
     task_ref
 }
@@ -180,11 +192,34 @@ pub fn set_prio(prio: isize) -> bool {
     current_run_queue::<NoPreemptIrqSave>().set_current_priority(prio)
 }
 
+/// Set the scheduling class for current task, see [`SchedPolicy`].
+///
+/// A real-time task keeps the CPU until it blocks or yields, or a real-time
+/// task of a higher priority is ready; the tasks of the priority of a
+/// [`SchedPolicy::RoundRobin`] one also take turns with it.
+///
+/// Returns `false` if the real-time priority is out of range.
+pub fn set_sched_policy(policy: SchedPolicy) -> bool {
+    current_run_queue::<NoPreemptIrqSave>().set_current_sched_policy(policy)
+}
+
+/// Set the scheduling class for the given task, see [`set_sched_policy`].
+///
+/// A ready task is moved at once to the queue of its new class, to run
+/// before the normal tasks for example.
+///
+/// Returns `false` if the real-time priority is out of range.
+pub fn set_task_sched_policy(task: &KtaskRef, policy: SchedPolicy) -> bool {
+    if current().ptr_eq(task) {
+        set_sched_policy(policy)
+    } else {
+        crate::run_queue::set_task_sched_policy(task, policy)
+    }
+}
+
 /// Set the affinity for the current task.
 /// [`KCpuMask`] is used to specify the CPU affinity.
 /// Returns `true` if the affinity is set successfully.
-///
-/// TODO: support set the affinity for other tasks.
 pub fn set_current_affinity(cpumask: KCpuMask) -> bool {
     if cpumask.is_empty() {
         false
@@ -196,14 +231,8 @@ pub fn set_current_affinity(cpumask: KCpuMask) -> bool {
         // the affinity. If not, we need to migrate the task to the correct CPU.
         #[cfg(feature = "smp")]
         if !cpumask.get(khal::percpu::this_cpu_id()) {
-            const MIGRATION_TASK_STACK_SIZE: usize = 4096;
             // Spawn a new migration task for migrating.
-            let migration_task = TaskInner::new(
-                move || crate::run_queue::migrate_entry(curr),
-                "migration-task".into(),
-                MIGRATION_TASK_STACK_SIZE,
-            )
-            .into_arc();
+            let migration_task = crate::run_queue::migration_task(curr);
 
             // Migrate the current task to the correct CPU using the migration task.
             current_run_queue::<NoPreemptIrqSave>().migrate_current(migration_task);
@@ -214,6 +243,36 @@ pub fn set_current_affinity(cpumask: KCpuMask) -> bool {
     }
 }
 
+/// Set the affinity for the given task, restricted to the active CPUs.
+/// Returns `false` if none of them is in `cpumask`.
+///
+/// The current task is migrated at once. Another one keeps its place until
+/// it is next scheduled: a ready task is moved to an allowed CPU once picked,
+/// and a running one once preempted, at the latest on the next timer tick
+/// of its CPU with the `preempt` feature.
+pub fn set_affinity(task: &KtaskRef, cpumask: KCpuMask) -> bool {
+    let mut allowed = KCpuMask::new();
+    for cpu_id in (0..active_cpu_num()).filter(|&cpu_id| cpumask.get(cpu_id)) {
+        allowed.set(cpu_id, true);
+    }
+    if allowed.is_empty() || task.is_idle() {
+        return false;
+    }
+    if current().ptr_eq(task) {
+        return set_current_affinity(allowed);
+    }
+    #[cfg(feature = "smp")]
+    task.set_pending_cpumask(allowed);
+    #[cfg(not(feature = "smp"))]
+    task.set_cpumask(allowed);
+    true
+}
+
+/// Get the affinity of the given task, as last set by [`set_affinity`].
+pub fn get_affinity(task: &KtaskRef) -> KCpuMask {
+    task.affinity()
+}
+
 /// Current task gives up the CPU time voluntarily, and switches to another
 /// ready task.
 pub fn yield_now() {
@@ -237,12 +296,23 @@ pub fn exit(exit_code: i32) -> ! {
 
 /// The idle task routine.
 ///
-/// It runs an infinite loop that keeps calling [`yield_now()`].
+/// It runs an infinite loop that keeps calling [`yield_now()`], and waits
+/// for IRQs with the scheduler tick stopped in between.
 pub fn run_idle() -> ! {
     loop {
         yield_now();
+        #[cfg(feature = "replay")]
+        if khal::replay::on_idle() {
+            continue;
+        }
         trace!("idle task: waiting for IRQs...");
+        crate::timers::stop_tick();
         khal::asm::await_interrupts();
+        crate::timers::restart_tick();
+        {
+            let _g = NoPreemptIrqSave::new();
+            crate::stats::account_current();
+        }
     }
 }
 
@@ -16,6 +16,22 @@ use futures_util::{FutureExt, select_biased};
 use kerrno::KError;
 use khal::time::{TimeValue, wall_time};
 
+/// Converts wall time to the time the timers run on.
+///
+/// Steps of the wall clock, e.g. after a VM snapshot was restored, are taken
+/// out so that pending timers keep their remaining durations.
+fn unstepped(wall: TimeValue) -> TimeValue {
+    let nanos = wall.as_nanos() as u64;
+    TimeValue::from_nanos(nanos.wrapping_add_signed(khal::time::wall_step_ns().wrapping_neg()))
+}
+
+/// Converts `deadline`, in the time the timers run on, to nanoseconds of
+/// monotonic time.
+fn monotonic_nanos(deadline: TimeValue) -> u64 {
+    let left = deadline.saturating_sub(unstepped(wall_time()));
+    khal::time::monotonic_time_nanos().saturating_add(left.as_nanos() as u64)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 struct TimerKey {
     deadline: TimeValue,
@@ -39,6 +55,7 @@ impl TimerRuntime {
         if deadline <= wall_time() {
             return None;
         }
+        let deadline = unstepped(deadline);
 
         let key = TimerKey {
             deadline,
@@ -46,6 +63,7 @@ impl TimerRuntime {
         };
         self.wheel.insert(key, Waker::noop().clone());
         self.key += 1;
+        crate::timers::arm_for(monotonic_nanos(deadline));
 
         Some(key)
     }
@@ -68,7 +86,7 @@ impl TimerRuntime {
             return;
         }
 
-        let now = wall_time();
+        let now = unstepped(wall_time());
 
         let pending = self.wheel.split_off(&TimerKey {
             deadline: now,
@@ -98,6 +116,59 @@ fn with_current<R>(f: impl FnOnce(&mut TimerRuntime) -> R) -> R {
     f(unsafe { TIMER_RUNTIME.current_ref_mut_raw() })
 }
 
+/// Has `waker` woken by the timers of this CPU at `deadline`, unless it has
+/// passed already, returning `false` then.
+pub(crate) fn wake_at(deadline: TimeValue, waker: Waker) -> bool {
+    with_current(|r| match r.add(deadline) {
+        Some(key) => {
+            r.wheel.insert(key, waker);
+            true
+        }
+        None => false,
+    })
+}
+
+/// Returns the deadline of the nearest timer of this CPU, in nanoseconds of
+/// monotonic time.
+pub(crate) fn next_timer_deadline() -> Option<u64> {
+    with_current(|r| r.wheel.first_key_value().map(|(key, _)| key.deadline)).map(monotonic_nanos)
+}
+
+/// Returns the number of timers pending on the current CPU.
+#[cfg(feature = "snapshot")]
+pub(crate) fn pending_timers() -> usize {
+    with_current(|r| r.wheel.len())
+}
+
+/// The timers pending on this CPU, as far as they could be walked.
+#[cfg(feature = "integrity")]
+pub(crate) struct TimerSnapshot {
+    /// The number of timers the wheel counts.
+    pub len: usize,
+    /// The key the next timer gets.
+    pub next_key: u64,
+    /// `(deadline, key)` of the timers in order, up to one more than counted.
+    pub timers: alloc::vec::Vec<(TimeValue, u64)>,
+    /// Deadlines up to this had passed when the timers were last woken.
+    pub expired_by: TimeValue,
+}
+
+/// Walks the timers of this CPU, last woken at `woken_at`.
+#[cfg(feature = "integrity")]
+pub(crate) fn timer_snapshot(woken_at: TimeValue) -> TimerSnapshot {
+    with_current(|r| TimerSnapshot {
+        len: r.wheel.len(),
+        next_key: r.key,
+        timers: r
+            .wheel
+            .keys()
+            .take(r.wheel.len() + 1)
+            .map(|key| (key.deadline, key.key))
+            .collect(),
+        expired_by: unstepped(woken_at),
+    })
+}
+
 /// Future returned by `sleep` and `sleep_until`.
 #[must_use = "futures do nothing unless you `.await` or poll them"]
 pub struct TimerFuture(TimerKey);
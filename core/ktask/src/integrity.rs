@@ -0,0 +1,247 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Periodic integrity checks of the run queues and timers.
+//!
+//! Every [`CHECK_INTERVAL`], each CPU validates its structures on a timer
+//! tick:
+//!
+//! - the run queue ends after as many tasks as it counts, so that it has no
+//!   cycle, and holds each task once, ready, on a CPU the task may run on;
+//! - the timers are as many as counted, sorted by deadline, all issued by the
+//!   wheel, and none is left after its deadline passed.
+//!
+//! A violation dumps both structures and the latest events of the trace
+//! buffer to the console, then panics: corruption is caught when it happens
+//! rather than when something trips over it much later.
+use alloc::{collections::BTreeSet, string::String};
+use core::{fmt, time::Duration};
+
+use khal::time::TimeValue;
+
+use crate::{
+    future::{TimerSnapshot, timer_snapshot},
+    run_queue::{ReadySnapshot, ready_snapshot},
+    task::TaskState,
+};
+
+/// How often each CPU checks its structures.
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+/// Trace events dumped on a violation.
+const RECENT_EVENTS: usize = 64;
+
+percpu_static! {
+    NEXT_CHECK_NS: u64 = 0,
+}
+
+/// A broken invariant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Violation {
+    /// The run queue goes on past its count, possibly in a cycle.
+    RunQueueOverrun { cpu: usize, len: usize },
+    /// The run queue ends before its count.
+    RunQueueShort {
+        cpu: usize,
+        len: usize,
+        found: usize,
+    },
+    /// A task is queued twice.
+    DuplicateTask { cpu: usize, task: u64 },
+    /// A queued task is not ready to run.
+    TaskNotReady {
+        cpu: usize,
+        task: u64,
+        state: TaskState,
+    },
+    /// The idle task is queued, while it runs only when the queue is empty.
+    IdleTaskQueued { cpu: usize, task: u64 },
+    /// A task is queued on a CPU outside of its affinity.
+    #[cfg(feature = "smp")]
+    TaskNotAllowed { cpu: usize, task: u64 },
+    /// The timers are not as many as counted.
+    TimerCountMismatch {
+        cpu: usize,
+        len: usize,
+        found: usize,
+    },
+    /// The timer at `index` does not come after the one before.
+    TimersUnsorted { cpu: usize, index: usize },
+    /// A timer has a key the wheel has not handed out yet.
+    TimerKeyUnissued { cpu: usize, key: u64, next_key: u64 },
+    /// A timer was not woken although its deadline passed.
+    TimerExpired {
+        cpu: usize,
+        key: u64,
+        deadline_ns: u128,
+        expired_by_ns: u128,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RunQueueOverrun { cpu, len } => {
+                write!(f, "run queue of CPU {cpu} holds more than its {len} tasks")
+            }
+            Self::RunQueueShort { cpu, len, found } => write!(
+                f,
+                "run queue of CPU {cpu} holds {found} tasks, {len} counted"
+            ),
+            Self::DuplicateTask { cpu, task } => {
+                write!(f, "task {task} queued twice on CPU {cpu}")
+            }
+            Self::TaskNotReady { cpu, task, state } => {
+                write!(f, "task {task} queued on CPU {cpu} is {state:?}")
+            }
+            Self::IdleTaskQueued { cpu, task } => {
+                write!(f, "idle task {task} queued on CPU {cpu}")
+            }
+            #[cfg(feature = "smp")]
+            Self::TaskNotAllowed { cpu, task } => {
+                write!(f, "task {task} queued on CPU {cpu} outside of its affinity")
+            }
+            Self::TimerCountMismatch { cpu, len, found } => {
+                write!(f, "CPU {cpu} has {found} timers, {len} counted")
+            }
+            Self::TimersUnsorted { cpu, index } => {
+                write!(f, "timer {index} of CPU {cpu} is out of order")
+            }
+            Self::TimerKeyUnissued { cpu, key, next_key } => write!(
+                f,
+                "timer key {key} on CPU {cpu} not issued yet, next is {next_key}"
+            ),
+            Self::TimerExpired {
+                cpu,
+                key,
+                deadline_ns,
+                expired_by_ns,
+            } => write!(
+                f,
+                "timer {key} on CPU {cpu} due at {deadline_ns} ns still pending at \
+                 {expired_by_ns} ns"
+            ),
+        }
+    }
+}
+
+fn check_run_queue(ready: &ReadySnapshot) -> Result<(), Violation> {
+    let cpu = ready.cpu_id;
+    let len = ready.len;
+    let found = ready.tasks.len();
+    if found > len {
+        return Err(Violation::RunQueueOverrun { cpu, len });
+    }
+    if found < len {
+        return Err(Violation::RunQueueShort { cpu, len, found });
+    }
+    let mut seen = BTreeSet::new();
+    for task in &ready.tasks {
+        let id = task.id().as_u64();
+        if !seen.insert(id) {
+            return Err(Violation::DuplicateTask { cpu, task: id });
+        }
+        if task.is_idle() {
+            return Err(Violation::IdleTaskQueued { cpu, task: id });
+        }
+        let state = task.state();
+        if state != TaskState::Ready {
+            return Err(Violation::TaskNotReady {
+                cpu,
+                task: id,
+                state,
+            });
+        }
+        // Without SMP, every task shares the one run queue
+        #[cfg(feature = "smp")]
+        if !task.cpumask().get(cpu) {
+            return Err(Violation::TaskNotAllowed { cpu, task: id });
+        }
+    }
+    Ok(())
+}
+
+fn check_timers(cpu: usize, timers: &TimerSnapshot) -> Result<(), Violation> {
+    let found = timers.timers.len();
+    if found != timers.len {
+        return Err(Violation::TimerCountMismatch {
+            cpu,
+            len: timers.len,
+            found,
+        });
+    }
+    for (index, pair) in timers.timers.windows(2).enumerate() {
+        if pair[0] >= pair[1] {
+            return Err(Violation::TimersUnsorted {
+                cpu,
+                index: index + 1,
+            });
+        }
+    }
+    for &(_, key) in &timers.timers {
+        if key >= timers.next_key {
+            return Err(Violation::TimerKeyUnissued {
+                cpu,
+                key,
+                next_key: timers.next_key,
+            });
+        }
+    }
+    match timers.timers.first() {
+        Some(&(deadline, key)) if deadline <= timers.expired_by => Err(Violation::TimerExpired {
+            cpu,
+            key,
+            deadline_ns: deadline.as_nanos(),
+            expired_by_ns: timers.expired_by.as_nanos(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn dump(ready: &ReadySnapshot, timers: &TimerSnapshot) {
+    kplat::kprintln!(
+        "integrity: run queue of CPU {}, {} tasks counted:",
+        ready.cpu_id,
+        ready.len
+    );
+    for task in &ready.tasks {
+        kplat::kprintln!("  {} {:?}", task.id_name(), task.state());
+    }
+    kplat::kprintln!(
+        "integrity: timers of CPU {}, {} counted, next key {}:",
+        ready.cpu_id,
+        timers.len,
+        timers.next_key
+    );
+    for (deadline, key) in &timers.timers {
+        kplat::kprintln!("  key={} deadline_ns={}", key, deadline.as_nanos());
+    }
+
+    let (records, written) = ktrace::snapshot();
+    let recent = &records[records.len().saturating_sub(RECENT_EVENTS)..];
+    let mut out = String::new();
+    ktrace::write_trace(&mut out, recent, written);
+    kplat::kprintln!("integrity: latest trace events:\n{}", out);
+}
+
+/// Checks the structures of this CPU if [`CHECK_INTERVAL`] passed since the
+/// last check, the timers having been woken at `woken_at`.
+///
+/// Called in the timer softirq, with IRQs disabled.
+pub(crate) fn on_timer_tick(woken_at: TimeValue) {
+    let now_ns = khal::time::monotonic_time_nanos();
+    // Safety: IRQs are disabled
+    let next_check_ns = unsafe { NEXT_CHECK_NS.current_ref_mut_raw() };
+    if now_ns < *next_check_ns {
+        return;
+    }
+    *next_check_ns = now_ns + CHECK_INTERVAL.as_nanos() as u64;
+
+    let ready = ready_snapshot();
+    let timers = timer_snapshot(woken_at);
+    let result = check_run_queue(&ready).and_then(|()| check_timers(ready.cpu_id, &timers));
+    if let Err(violation) = result {
+        dump(&ready, &timers);
+        panic!("integrity: {violation}");
+    }
+}
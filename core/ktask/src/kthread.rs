@@ -0,0 +1,163 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Kernel threads, for the long-running tasks of the subsystems.
+//!
+//! A kernel thread is given a [`KThreadContext`] to park itself between two
+//! batches of work, and to check whether it is asked to stop. The
+//! [`JoinHandle`] it is spawned with unparks it, asks it to stop, and joins
+//! it for its result.
+//!
+//! # Examples
+//!
+//! ```
+//! use ktask::kthread;
+//!
+//! ktask::init_scheduler();
+//! let handle = kthread::spawn("writeback", |ctx| {
+//!     let mut rounds = 0;
+//!     while !ctx.should_stop() {
+//!         rounds += 1;
+//!         ctx.park();
+//!     }
+//!     rounds
+//! });
+//! handle.unpark();
+//! assert!(handle.stop() >= 1);
+//! ```
+
+use alloc::{string::String, sync::Arc};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use kspin::SpinNoIrq;
+
+use crate::{KtaskRef, WaitQueue};
+
+struct Shared {
+    /// The thread is asked to stop.
+    stop: AtomicBool,
+    /// The thread was unparked, and its next park returns at once.
+    token: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl Shared {
+    fn take_token(&self) -> bool {
+        self.token.swap(false, Ordering::AcqRel) || self.stop.load(Ordering::Acquire)
+    }
+
+    fn unpark(&self) {
+        self.token.store(true, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+}
+
+/// Given to a kernel thread to park itself and check for stop requests.
+pub struct KThreadContext {
+    shared: Arc<Shared>,
+}
+
+impl KThreadContext {
+    /// Returns whether the thread is asked to stop; it should return then.
+    pub fn should_stop(&self) -> bool {
+        self.shared.stop.load(Ordering::Acquire)
+    }
+
+    /// Blocks until the thread is unparked or asked to stop.
+    ///
+    /// Returns at once if it was unparked since it last parked.
+    pub fn park(&self) {
+        self.shared.wq.wait_until(|| self.shared.take_token());
+    }
+
+    /// Blocks until the thread is unparked or asked to stop, or `dur` has
+    /// passed.
+    ///
+    /// Returns false if `dur` passed.
+    pub fn park_timeout(&self, dur: Duration) -> bool {
+        !self
+            .shared
+            .wq
+            .wait_timeout_until(dur, || self.shared.take_token())
+    }
+}
+
+/// The handle of a kernel thread, to unpark, stop and join it.
+pub struct JoinHandle<T> {
+    task: KtaskRef,
+    shared: Arc<Shared>,
+    result: Arc<SpinNoIrq<Option<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Returns the task of the thread.
+    pub fn task(&self) -> &KtaskRef {
+        &self.task
+    }
+
+    /// Wakes the thread up if parked, or has its next park return at once.
+    pub fn unpark(&self) {
+        self.shared.unpark();
+    }
+
+    /// Asks the thread to stop, unparking it.
+    pub fn request_stop(&self) {
+        self.shared.stop.store(true, Ordering::Release);
+        self.shared.wq.notify_one(true);
+    }
+
+    /// Returns whether the thread has returned.
+    pub fn is_finished(&self) -> bool {
+        self.result.lock().is_some()
+    }
+
+    /// Waits for the thread to return, and returns its result.
+    pub fn join(self) -> T {
+        self.task.join();
+        self.result
+            .lock()
+            .take()
+            .expect("kernel thread exited without a result")
+    }
+
+    /// Asks the thread to stop, and joins it.
+    pub fn stop(self) -> T {
+        self.request_stop();
+        self.join()
+    }
+}
+
+/// Spawns a kernel thread named `name` running `f`, with the default stack
+/// size.
+pub fn spawn<F, T>(name: impl Into<String>, f: F) -> JoinHandle<T>
+where
+    F: FnOnce(&KThreadContext) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let shared = Arc::new(Shared {
+        stop: AtomicBool::new(false),
+        token: AtomicBool::new(false),
+        wq: WaitQueue::new(),
+    });
+    let result = Arc::new(SpinNoIrq::new(None));
+    let ctx = KThreadContext {
+        shared: shared.clone(),
+    };
+    let slot = result.clone();
+    let task = crate::spawn_with_name(
+        move || {
+            let value = f(&ctx);
+            *slot.lock() = Some(value);
+        },
+        name.into(),
+    );
+    JoinHandle {
+        task,
+        shared,
+        result,
+    }
+}
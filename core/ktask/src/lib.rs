@@ -14,6 +14,15 @@
 //!   `preempt` features if it is enabled.
 //! - `sched-cfs`: Use the [Completely Fair Scheduler][3]. It also enables the
 //!   `preempt` features if it is enabled.
+//! - `replay`: Record and replay interrupts, timer reads and scheduling
+//!   decisions, see [`replay`].
+//! - `snapshot`: Cooperate with VM snapshots and resume after restores, see
+//!   [`snapshot`].
+//! - `trace`: Record context switches, wakeups and migrations into the
+//!   trace buffer of [`ktrace`].
+//! - `integrity`: Validate the run queues and timers periodically, panicking
+//!   with a dump of them and of the latest trace events on corruption. It
+//!   also enables the `trace` feature.
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
@@ -27,7 +36,6 @@ extern crate log;
 
 extern crate alloc;
 
-
 const CPU_NUM: usize = kbuild_config::CPU_NUM as usize;
 const TASK_STACK_SIZE: usize = kbuild_config::TASK_STACK_SIZE as usize;
 
@@ -36,10 +44,27 @@ mod run_queue;
 mod api;
 #[cfg(feature = "watchdog")]
 mod global_task_queue;
+#[cfg(feature = "integrity")]
+mod integrity;
+#[cfg(feature = "lockdep")]
+mod lockdep;
+mod rt_queue;
+mod stats;
 mod task;
 mod timers;
+#[cfg(feature = "trace")]
+mod trace;
 mod wait_queue;
 
+#[cfg(target_arch = "aarch64")]
+pub mod vector;
+
 pub mod future;
+pub mod kthread;
+#[cfg(feature = "replay")]
+pub mod replay;
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+pub mod workqueue;
 
 pub use self::api::{sleep, sleep_until, yield_now, *};
@@ -0,0 +1,37 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Hooks of the lock dependency validator: the spinlocks held per CPU, the
+//! sleeping locks held per task, and the reports.
+
+use kspin::lockdep::{HeldLocks, LockdepIf, Report};
+
+static CPU_HELD_LOCKS: [HeldLocks; crate::CPU_NUM] = [const { HeldLocks::new() }; crate::CPU_NUM];
+
+struct LockdepIfImpl;
+
+#[crate_interface::impl_interface]
+impl LockdepIf for LockdepIfImpl {
+    fn held_locks(sleeping: bool) -> *const HeldLocks {
+        // Both need the per-CPU data, set up along with the current task.
+        match crate::current_may_uninit() {
+            Some(curr) if sleeping => &curr.held_locks as *const HeldLocks,
+            Some(_) => &CPU_HELD_LOCKS[khal::percpu::this_cpu_id()],
+            None => core::ptr::null(),
+        }
+    }
+
+    fn in_irq() -> bool {
+        khal::irq::in_irq()
+    }
+
+    fn report(report: &Report) {
+        // Printed without the logger, whose lock may be the one reported.
+        kplat::kprint_atomic!(
+            "lockdep: {report}\ncurrent task: {}\n{}\n",
+            crate::current().id_name(),
+            backtrace::Backtrace::capture(),
+        );
+    }
+}
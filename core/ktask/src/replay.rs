@@ -0,0 +1,85 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Deterministic record and replay of a uniprocessor run.
+//!
+//! A recording logs the interrupts taken, the timer reads and the tasks the
+//! scheduler switches to. Replaying the log from the same starting point
+//! feeds back the same timer values and injects the same interrupts at the
+//! same points, so that the same scheduling decisions follow; the first one
+//! that does not is reported as a [`Divergence`]. See [`khal::replay`] for
+//! how interrupts are replayed.
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use kerrno::{KError, KResult};
+pub use khal::replay::{Divergence, Mode, mode};
+
+/// Whether the session was started by this module, which owns its buffer.
+static OWNED: AtomicBool = AtomicBool::new(false);
+
+/// The log of a finished session.
+#[derive(Debug)]
+pub struct Recording {
+    pub log: Vec<u8>,
+    /// Whether the log filled up; it then ends at the first event dropped.
+    pub overflowed: bool,
+    /// Why a replay ended early.
+    pub divergence: Option<Divergence>,
+}
+
+fn start(
+    buf: Box<[u8]>,
+    start: impl FnOnce(&'static mut [u8]) -> Result<(), &'static mut [u8]>,
+) -> KResult {
+    if crate::CPU_NUM != 1 {
+        return Err(KError::Unsupported);
+    }
+    if OWNED.swap(true, Ordering::AcqRel) {
+        return Err(KError::ResourceBusy);
+    }
+    start(Box::leak(buf)).map_err(|buf| {
+        // Safety: leaked above
+        drop(unsafe { Box::from_raw(buf) });
+        OWNED.store(false, Ordering::Release);
+        KError::ResourceBusy
+    })
+}
+
+/// Starts recording into a log of up to `capacity` bytes.
+pub fn record(capacity: usize) -> KResult {
+    if capacity == 0 {
+        return Err(KError::InvalidInput);
+    }
+    start(vec![0; capacity].into_boxed_slice(), |buf| {
+        khal::replay::start_record(buf)
+    })
+}
+
+/// Starts replaying `log`, recorded by [`record`].
+pub fn replay(log: Vec<u8>) -> KResult {
+    let len = log.len();
+    start(log.into_boxed_slice(), |buf| {
+        khal::replay::start_replay(buf, len)
+    })
+}
+
+/// Ends the session started by [`record`] or [`replay`] and returns its log.
+///
+/// A replay that ended by itself is still to be stopped for its result.
+pub fn stop() -> Option<Recording> {
+    if !OWNED.load(Ordering::Acquire) {
+        return None;
+    }
+    let stopped = khal::replay::stop()?;
+    OWNED.store(false, Ordering::Release);
+    // Safety: the buffer was leaked by `start`
+    let mut log = Vec::from(unsafe { Box::from_raw(stopped.buf) });
+    log.truncate(stopped.len);
+    Some(Recording {
+        log,
+        overflowed: stopped.overflowed,
+        divergence: stopped.divergence,
+    })
+}
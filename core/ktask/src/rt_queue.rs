@@ -0,0 +1,110 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! The real-time scheduling class.
+//!
+//! The ready tasks with a real-time [`SchedPolicy`](crate::SchedPolicy) are kept apart from the
+//! scheduler chosen by the cargo features, in a FIFO queue per priority, and
+//! run before all of its tasks.
+
+use alloc::{collections::VecDeque, sync::Arc};
+
+#[cfg(feature = "preempt")]
+use crate::task::SchedPolicy;
+use crate::{KtaskRef, task::MAX_RT_PRIO};
+
+/// Ticks of the time slice of a [`SchedPolicy::RoundRobin`](crate::SchedPolicy::RoundRobin) task.
+pub(crate) const RR_TIME_SLICE: u32 = 10;
+
+/// The ready real-time tasks of a run queue.
+pub(crate) struct RtQueue {
+    /// The tasks of priority `prio`, at `prio - 1`.
+    queues: [VecDeque<KtaskRef>; MAX_RT_PRIO as usize],
+    /// Bit `prio - 1` is set when there are tasks of priority `prio`.
+    ready: u128,
+}
+
+impl RtQueue {
+    pub fn new() -> Self {
+        Self {
+            queues: core::array::from_fn(|_| VecDeque::new()),
+            ready: 0,
+        }
+    }
+
+    /// Queues `task`, a real-time task, at the tail of its priority, or at
+    /// the head for one preempted in the middle of its turn.
+    pub fn push(&mut self, task: KtaskRef, front: bool) {
+        let policy = task.sched_policy();
+        debug_assert!(policy.is_rt() && policy.is_valid());
+        let index = policy.rt_priority() as usize - 1;
+        if front {
+            self.queues[index].push_front(task);
+        } else {
+            self.queues[index].push_back(task);
+        }
+        self.ready |= 1 << index;
+    }
+
+    /// Takes the first task of the highest priority.
+    pub fn pop(&mut self) -> Option<KtaskRef> {
+        let index = self.highest_priority().checked_sub(1)? as usize;
+        self.take(index, 0)
+    }
+
+    /// Takes the last task of the highest priority for which `movable`
+    /// returns `true`, to run on another CPU.
+    #[cfg(feature = "smp")]
+    pub fn steal(&mut self, movable: impl Fn(&KtaskRef) -> bool) -> Option<KtaskRef> {
+        (0..self.queues.len())
+            .rev()
+            .filter(|&index| self.ready & (1 << index) != 0)
+            .find_map(|index| Some((index, self.queues[index].iter().rposition(&movable)?)))
+            .and_then(|(index, position)| self.take(index, position))
+    }
+
+    /// Takes `task` out, if it is queued here.
+    pub fn remove(&mut self, task: &KtaskRef) -> Option<KtaskRef> {
+        (0..self.queues.len())
+            .filter(|&index| self.ready & (1 << index) != 0)
+            .find_map(|index| {
+                let position = self.queues[index]
+                    .iter()
+                    .position(|t| Arc::ptr_eq(t, task))?;
+                Some((index, position))
+            })
+            .and_then(|(index, position)| self.take(index, position))
+    }
+
+    fn take(&mut self, index: usize, position: usize) -> Option<KtaskRef> {
+        let task = self.queues[index].remove(position)?;
+        if self.queues[index].is_empty() {
+            self.ready &= !(1 << index);
+        }
+        Some(task)
+    }
+
+    /// Returns the highest priority of the tasks queued, or 0 if there is
+    /// none.
+    pub fn highest_priority(&self) -> u8 {
+        (u128::BITS - self.ready.leading_zeros()) as u8
+    }
+
+    /// Returns the tasks in the order they run.
+    #[cfg(feature = "integrity")]
+    pub fn iter(&self) -> impl Iterator<Item = &KtaskRef> {
+        self.queues.iter().rev().flatten()
+    }
+}
+
+/// Returns whether a task of `policy` made ready should preempt `current`,
+/// with `resched` the wish of the waker for the normal tasks.
+#[cfg(feature = "preempt")]
+pub(crate) fn should_preempt(policy: SchedPolicy, current: SchedPolicy, resched: bool) -> bool {
+    if policy.is_rt() || current.is_rt() {
+        policy.rt_priority() > current.rt_priority()
+    } else {
+        resched
+    }
+}
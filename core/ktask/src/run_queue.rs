@@ -7,22 +7,27 @@
 #[cfg(feature = "smp")]
 use alloc::sync::Weak;
 use alloc::{collections::VecDeque, sync::Arc};
+#[cfg(feature = "smp")]
+use core::sync::atomic::AtomicBool;
 use core::{
     future::poll_fn,
     mem::MaybeUninit,
+    sync::atomic::{Ordering, fence},
     task::{Context, Poll},
 };
 
 use axsched::BaseScheduler;
 use futures_util::task::AtomicWaker;
 use khal::percpu::this_cpu_id;
+use kmetrics::Counter;
 use kspin::{BaseGuard, SpinNoIrqGuard, SpinRaw};
 use lazyinit::LazyInit;
 
 use crate::{
     KCpuMask, KtaskRef, Scheduler, TaskInner,
     future::block_on,
-    task::{CurrentTask, TaskState},
+    rt_queue::RtQueue,
+    task::{CurrentTask, SchedPolicy, TaskState},
 };
 
 macro_rules! percpu_static {
@@ -48,6 +53,23 @@ percpu_static! {
     PREV_TASK: Weak<crate::KTask> = Weak::new(),
 }
 
+pub(crate) static CONTEXT_SWITCHES: Counter = Counter::new(
+    "xkernel_sched_context_switches_total",
+    "Switches from one task to another.",
+);
+pub(crate) static TASKS_SPAWNED: Counter = Counter::new(
+    "xkernel_sched_tasks_spawned_total",
+    "Tasks added to a run queue.",
+);
+pub(crate) static TASKS_STOLEN: Counter = Counter::new(
+    "xkernel_sched_tasks_stolen_total",
+    "Tasks pulled from the run queue of another CPU by load balancing.",
+);
+
+/// Timer ticks between two periodic load balancings of a run queue.
+#[cfg(feature = "smp")]
+const BALANCE_INTERVAL: usize = 4;
+
 /// An array of references to run queues, one for each CPU, indexed by cpu_id.
 ///
 /// This static variable holds references to the run queues for each CPU in the system.
@@ -62,6 +84,12 @@ static mut RUN_QUEUES: [MaybeUninit<&'static mut RunQueue>; crate::CPU_NUM] =
 #[allow(clippy::declare_interior_mutable_const)] // It's ok because it's used only for initialization `RUN_QUEUES`.
 const ARRAY_REPEAT_VALUE: MaybeUninit<&'static mut RunQueue> = MaybeUninit::uninit();
 
+/// Whether each entry of `RUN_QUEUES` is initialized, and can be balanced
+/// with.
+#[cfg(feature = "smp")]
+static RUN_QUEUE_READY: [AtomicBool; crate::CPU_NUM] =
+    [const { AtomicBool::new(false) }; crate::CPU_NUM];
+
 /// Returns a reference to the current run queue in [`CurrentRunQueueRef`].
 ///
 /// ## Safety
@@ -155,9 +183,12 @@ fn get_run_queue(index: usize) -> &'static mut RunQueue {
 ///
 /// * [`KRunQueueRef`] - a static reference to the selected [`RunQueue`] (current or remote).
 ///
+/// The run queues are balanced afterwards, by the idle CPUs taking over the
+/// tasks of the others, and periodically on the timer ticks.
+///
 /// ## TODO
 ///
-/// 1. Implement better load balancing across CPUs for more efficient task distribution.
+/// 1. Weigh the tasks by their priorities when balancing, not just count them.
 /// 2. Use a more generic load balancing algorithm that can be customized or replaced.
 #[inline]
 pub(crate) fn select_run_queue<G: BaseGuard>(task: &KtaskRef) -> KRunQueueRef<'static, G> {
@@ -175,7 +206,7 @@ pub(crate) fn select_run_queue<G: BaseGuard>(task: &KtaskRef) -> KRunQueueRef<'s
     #[cfg(feature = "smp")]
     {
         // When SMP is enabled, select the run queue based on the task's CPU affinity and load balance.
-        let index = select_run_queue_index(task.cpumask());
+        let index = select_run_queue_index(task.affinity());
         KRunQueueRef {
             inner: get_run_queue(index),
             state: irq_state,
@@ -184,6 +215,36 @@ pub(crate) fn select_run_queue<G: BaseGuard>(task: &KtaskRef) -> KRunQueueRef<'s
     }
 }
 
+/// Sets the scheduling class of `task`, which is not the current task, and
+/// moves it to the queue of its new class if it is ready.
+///
+/// Returns `false` if the real-time priority is out of range.
+pub(crate) fn set_task_sched_policy(task: &KtaskRef, policy: SchedPolicy) -> bool {
+    if !task.set_sched_policy(policy) {
+        return false;
+    }
+    // Pairs with `ReadyQueue::enqueue`.
+    fence(Ordering::SeqCst);
+    while let Some(cpu_id) = task.queued_on() {
+        #[cfg(feature = "smp")]
+        let inner = get_run_queue(cpu_id);
+        #[cfg(not(feature = "smp"))]
+        let inner = {
+            let _ = cpu_id;
+            unsafe { RUN_QUEUE.current_ref_mut_raw() }
+        };
+        let mut rq = KRunQueueRef::<kspin::NoPreemptIrqSave> {
+            inner,
+            state: kspin::NoPreemptIrqSave::acquire(),
+            _phantom: core::marker::PhantomData,
+        };
+        if rq.requeue_task(task) {
+            break;
+        }
+    }
+    true
+}
+
 /// [`RunQueue`] represents a run queue for global system or a specific CPU.
 pub(crate) struct RunQueue {
     /// The ID of the CPU this run queue is associated with.
@@ -191,7 +252,169 @@ pub(crate) struct RunQueue {
     /// The core scheduler of this run queue.
     /// Since irq and preempt are preserved by the kernel guard hold by `KRunQueueRef`,
     /// we just use a simple raw spin lock here.
-    scheduler: SpinRaw<Scheduler>,
+    scheduler: SpinRaw<ReadyQueue>,
+    /// Timer ticks since the last periodic load balancing.
+    #[cfg(feature = "smp")]
+    balance_ticks: usize,
+}
+
+/// The core scheduler of a run queue, counting the tasks it holds.
+///
+/// The real-time tasks are queued apart, and picked first.
+struct ReadyQueue {
+    /// The ID of the CPU of the run queue.
+    cpu_id: usize,
+    scheduler: Scheduler,
+    rt: RtQueue,
+    /// Tasks put into the scheduler and not picked yet.
+    len: usize,
+    /// The last tasks put into the scheduler, the latest at the back, which
+    /// may have been picked since.
+    #[cfg(feature = "smp")]
+    recent: VecDeque<Weak<crate::KTask>>,
+}
+
+/// How many of the tasks put last into a scheduler may be stolen from it.
+#[cfg(feature = "smp")]
+const STEAL_SCAN: usize = 8;
+
+impl ReadyQueue {
+    fn new(cpu_id: usize, scheduler: Scheduler) -> Self {
+        Self {
+            cpu_id,
+            scheduler,
+            rt: RtQueue::new(),
+            len: 0,
+            #[cfg(feature = "smp")]
+            recent: VecDeque::with_capacity(STEAL_SCAN),
+        }
+    }
+
+    /// Records `task` as queued here, before its class is looked at.
+    ///
+    /// Pairs with the fence of [`set_task_sched_policy`]: either the class set
+    /// there is seen, or the task is seen queued, and moved.
+    fn enqueue(&mut self, task: &KtaskRef) {
+        task.set_queued_on(Some(self.cpu_id));
+        fence(Ordering::SeqCst);
+        self.len += 1;
+    }
+
+    fn dequeue(&mut self, task: KtaskRef) -> KtaskRef {
+        task.set_queued_on(None);
+        self.len -= 1;
+        task
+    }
+
+    /// Remembers `task` as put into the scheduler, to be found by
+    /// [`steal`](Self::steal).
+    fn push_recent(&mut self, task: &KtaskRef) {
+        #[cfg(feature = "smp")]
+        {
+            if self.recent.len() == STEAL_SCAN {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(Arc::downgrade(task));
+        }
+        #[cfg(not(feature = "smp"))]
+        let _ = task;
+    }
+
+    fn add_task(&mut self, task: KtaskRef) {
+        self.enqueue(&task);
+        if task.sched_policy().is_rt() {
+            task.reset_rt_time_slice();
+            self.rt.push(task, false);
+        } else {
+            self.push_recent(&task);
+            self.scheduler.add_task(task);
+        }
+    }
+
+    fn put_prev_task(&mut self, prev: KtaskRef, preempt: bool) {
+        self.enqueue(&prev);
+        match prev.sched_policy() {
+            SchedPolicy::Normal => {
+                self.push_recent(&prev);
+                self.scheduler.put_prev_task(prev, preempt);
+            }
+            policy => {
+                // A preempted task keeps its turn, unless its time slice is over.
+                let front = preempt
+                    && (matches!(policy, SchedPolicy::Fifo(_)) || prev.rt_time_slice_left());
+                if !front {
+                    prev.reset_rt_time_slice();
+                }
+                self.rt.push(prev, front);
+            }
+        }
+    }
+
+    /// Puts a task woken up, which waits behind the ready tasks of its
+    /// priority if it is a real-time one.
+    fn wake_task(&mut self, task: KtaskRef, resched: bool) {
+        if task.sched_policy().is_rt() {
+            self.add_task(task);
+        } else {
+            self.put_prev_task(task, resched);
+        }
+    }
+
+    fn pick_next_task(&mut self) -> Option<KtaskRef> {
+        let next = self.rt.pop().or_else(|| self.scheduler.pick_next_task())?;
+        Some(self.dequeue(next))
+    }
+
+    /// Takes `task` out, which must be queued here.
+    fn remove_task(&mut self, task: &KtaskRef) -> KtaskRef {
+        debug_assert_eq!(task.queued_on(), Some(self.cpu_id));
+        // Its class may have changed since it was queued.
+        let task = self
+            .rt
+            .remove(task)
+            .or_else(|| self.scheduler.remove_task(task))
+            .expect("task not in its run queue");
+        self.dequeue(task)
+    }
+
+    fn task_tick(&mut self, current: &KtaskRef) -> bool {
+        let waiting = self.rt.highest_priority();
+        match current.sched_policy() {
+            // A real-time task woken up on another CPU takes over here.
+            SchedPolicy::Normal => self.scheduler.task_tick(current) || waiting > 0,
+            SchedPolicy::Fifo(prio) => waiting > prio,
+            SchedPolicy::RoundRobin(prio) => {
+                let expired = current.rt_time_slice_tick();
+                if expired && waiting < prio {
+                    // Nobody to take turns with.
+                    current.reset_rt_time_slice();
+                }
+                waiting > prio || (expired && waiting == prio)
+            }
+        }
+    }
+
+    fn set_priority(&mut self, task: &KtaskRef, prio: isize) -> bool {
+        self.scheduler.set_priority(task, prio)
+    }
+
+    /// Takes a task that may run on `cpu_id`, and is not still switching out
+    /// of its CPU: a real-time one of the highest priority, or else one of the
+    /// last [`STEAL_SCAN`] tasks put into the scheduler, the latest first.
+    #[cfg(feature = "smp")]
+    fn steal(&mut self, cpu_id: usize) -> Option<KtaskRef> {
+        let movable = |task: &KtaskRef| !task.on_cpu() && task.affinity().get(cpu_id);
+        if let Some(task) = self.rt.steal(movable) {
+            return Some(self.dequeue(task));
+        }
+        // Those picked or moved since are not queued here any more.
+        let index = self.recent.iter().rposition(|task| {
+            task.upgrade()
+                .is_some_and(|task| task.queued_on() == Some(self.cpu_id) && movable(&task))
+        })?;
+        let task = self.recent.remove(index)?.upgrade()?;
+        Some(self.remove_task(&task))
+    }
 }
 
 /// A reference to the run queue with specific guard.
@@ -248,7 +471,11 @@ impl<G: BaseGuard> KRunQueueRef<'_, G> {
             let _g = kspin::NoPreempt::new();
             crate::global_task_queue::record_task_for_watchdog(&task);
         }
+        let policy = task.sched_policy();
         self.inner.scheduler.lock().add_task(task);
+        if self.inner.cpu_id == this_cpu_id() {
+            self.preempt_for(policy, false);
+        }
     }
 
     /// Unblock one task by inserting it into the run queue.
@@ -257,6 +484,7 @@ impl<G: BaseGuard> KRunQueueRef<'_, G> {
     /// which means the task is already unblocked by other cores.
     pub fn unblock_task(&mut self, task: KtaskRef, resched: bool) {
         let task_id_name = task.id_name();
+        let policy = task.sched_policy();
         // Try to change the state of the task from `Blocked` to `Ready`,
         // if successful, the task will be put into this run queue,
         // otherwise, the task is already unblocked by other cores.
@@ -270,10 +498,40 @@ impl<G: BaseGuard> KRunQueueRef<'_, G> {
             let cpu_id = self.inner.cpu_id;
             debug!("task unblock: {task_id_name} on run_queue {cpu_id}");
             // Note: when the task is unblocked on another CPU's run queue,
-            // we just ingiore the `resched` flag.
-            if resched && cpu_id == this_cpu_id() {
-                #[cfg(feature = "preempt")]
-                crate::current().set_preempt_pending(true);
+            // we just ingiore the `resched` flag. A real-time task takes
+            // over there on the next timer tick.
+            if cpu_id == this_cpu_id() {
+                self.preempt_for(policy, resched);
+            }
+        }
+    }
+
+    /// Queues `task` again by its class, if it is still queued here.
+    fn requeue_task(&mut self, task: &KtaskRef) -> bool {
+        let mut ready = self.inner.scheduler.lock();
+        // It may have been picked, and queued on another CPU since.
+        if task.queued_on() != Some(self.inner.cpu_id) {
+            return false;
+        }
+        let task = ready.remove_task(task);
+        let policy = task.sched_policy();
+        ready.add_task(task);
+        drop(ready);
+        if self.inner.cpu_id == this_cpu_id() {
+            self.preempt_for(policy, false);
+        }
+        true
+    }
+
+    /// Has the current task preempted if a task of `policy`, made ready on
+    /// this CPU, should run before it.
+    #[cfg_attr(not(feature = "preempt"), allow(unused_variables))]
+    fn preempt_for(&self, policy: SchedPolicy, resched: bool) {
+        #[cfg(feature = "preempt")]
+        {
+            let curr = crate::current();
+            if crate::rt_queue::should_preempt(policy, curr.sched_policy(), resched) {
+                curr.set_preempt_pending(true);
             }
         }
     }
@@ -282,11 +540,30 @@ impl<G: BaseGuard> KRunQueueRef<'_, G> {
 /// Core functions of run queue.
 impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
     pub fn scheduler_timer_tick(&mut self) {
+        #[cfg(feature = "smp")]
+        {
+            self.inner.balance_ticks += 1;
+            if self.inner.balance_ticks >= BALANCE_INTERVAL {
+                self.inner.balance_ticks = 0;
+                self.inner.balance();
+            }
+        }
         let curr = &self.current_task;
+        #[cfg(feature = "task-ext")]
+        if let Some(ext) = curr.task_ext() {
+            use crate::TaskExt;
+
+            ext.on_tick()
+        }
         if !curr.is_idle() && self.inner.scheduler.lock().task_tick(curr) {
             #[cfg(feature = "preempt")]
             curr.set_preempt_pending(true);
         }
+        // Its CPU affinity was set from another CPU, check it out.
+        #[cfg(all(feature = "smp", feature = "preempt"))]
+        if curr.has_pending_cpumask() {
+            curr.set_preempt_pending(true);
+        }
     }
 
     /// Yield the current task and reschedule.
@@ -297,6 +574,12 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
         trace!("task yield: {}", curr.id_name());
         assert!(curr.is_running());
 
+        #[cfg(feature = "smp")]
+        if !curr.apply_pending_cpumask(self.inner.cpu_id) {
+            self.migrate_current(migration_task(curr.clone()));
+            return;
+        }
+
         self.inner
             .put_task_with_state(curr.clone(), TaskState::Running, false);
 
@@ -352,6 +635,11 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
             can_preempt
         );
         if can_preempt {
+            #[cfg(feature = "smp")]
+            if !curr.apply_pending_cpumask(self.inner.cpu_id) {
+                self.migrate_current(migration_task(curr.clone()));
+                return;
+            }
             self.inner
                 .put_task_with_state(curr.clone(), TaskState::Running, true);
             self.inner.resched();
@@ -425,10 +713,29 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
     }
 
     pub fn set_current_priority(&mut self, prio: isize) -> bool {
-        self.inner
+        let ok = self
+            .inner
             .scheduler
             .lock()
-            .set_priority(&self.current_task, prio)
+            .set_priority(&self.current_task, prio);
+        if ok {
+            self.current_task.set_priority(prio as _);
+        }
+        ok
+    }
+
+    /// Sets the scheduling class of the current task, which yields to the
+    /// real-time tasks of a higher priority than its new one.
+    pub fn set_current_sched_policy(&mut self, policy: SchedPolicy) -> bool {
+        if !self.current_task.set_sched_policy(policy) {
+            return false;
+        }
+        let waiting = self.inner.scheduler.lock().rt.highest_priority();
+        if waiting > policy.rt_priority() {
+            #[cfg(feature = "preempt")]
+            self.current_task.set_preempt_pending(true);
+        }
+        true
     }
 }
 
@@ -445,11 +752,13 @@ impl RunQueue {
         // gc task should be pinned to the current CPU.
         gc_task.set_cpumask(KCpuMask::one_shot(cpu_id));
 
-        let mut scheduler = Scheduler::new();
+        let mut scheduler = ReadyQueue::new(cpu_id, Scheduler::new());
         scheduler.add_task(gc_task);
         Self {
             cpu_id,
             scheduler: SpinRaw::new(scheduler),
+            #[cfg(feature = "smp")]
+            balance_ticks: 0,
         }
     }
 
@@ -488,33 +797,117 @@ impl RunQueue {
                     core::hint::spin_loop();
                 }
             }
-            // TODO: priority
+            #[cfg(feature = "trace")]
+            {
+                if current_state == TaskState::Blocked {
+                    crate::trace::sched_wakeup(&task, self.cpu_id);
+                }
+                #[cfg(feature = "smp")]
+                if task.cpu_id() as usize != self.cpu_id {
+                    crate::trace::sched_migrate(&task, task.cpu_id() as _, self.cpu_id);
+                }
+            }
             #[cfg(feature = "smp")]
             task.set_cpu_id(self.cpu_id as _);
-            self.scheduler.lock().put_prev_task(task, preempt);
+            let mut ready = self.scheduler.lock();
+            if current_state == TaskState::Blocked {
+                // Its CPU affinity may have been set since this run queue was
+                // selected for it.
+                #[cfg(feature = "smp")]
+                if !task.apply_pending_cpumask(self.cpu_id) {
+                    drop(ready);
+                    put_ready_task(task);
+                    return true;
+                }
+                ready.wake_task(task, preempt);
+            } else {
+                ready.put_prev_task(task, preempt);
+            }
             true
         } else {
             false
         }
     }
 
+    /// Picks the next task to run on this run queue.
+    ///
+    /// The CPU affinity of a task set while it was queued is applied when it
+    /// is picked, moving it away if it may not run here. The current task is
+    /// left to do so itself, as it is not switched out yet.
+    fn pick_next_task(&mut self) -> Option<KtaskRef> {
+        #[cfg(feature = "smp")]
+        loop {
+            let Some(next) = self.scheduler.lock().pick_next_task() else {
+                // Rather than idle, take over a task from another CPU.
+                return self.steal_task(1);
+            };
+            if crate::current().ptr_eq(&next) || next.apply_pending_cpumask(self.cpu_id) {
+                return Some(next);
+            }
+            put_ready_task(next);
+        }
+        #[cfg(not(feature = "smp"))]
+        self.scheduler.lock().pick_next_task()
+    }
+
+    /// Pulls a task that may run on this CPU from the run queue of the other
+    /// CPUs with the most ready tasks, if it has at least `min_len`.
+    #[cfg(feature = "smp")]
+    fn steal_task(&mut self, min_len: usize) -> Option<KtaskRef> {
+        let mut busiest: Option<(usize, usize)> = None;
+        for (cpu_id, _) in RUN_QUEUE_READY
+            .iter()
+            .enumerate()
+            .take(crate::api::active_cpu_num())
+            .filter(|(cpu_id, ready)| *cpu_id != self.cpu_id && ready.load(Ordering::Acquire))
+        {
+            // A busy lock is not worth waiting for.
+            let Some(ready) = get_run_queue(cpu_id).scheduler.try_lock() else {
+                continue;
+            };
+            if ready.len >= min_len && busiest.is_none_or(|(_, len)| ready.len > len) {
+                busiest = Some((cpu_id, ready.len));
+            }
+        }
+        let (busiest, _) = busiest?;
+        let task = get_run_queue(busiest).scheduler.lock().steal(self.cpu_id)?;
+        if !task.apply_pending_cpumask(self.cpu_id) {
+            // Its CPU affinity has just been set.
+            put_ready_task(task);
+            return None;
+        }
+        #[cfg(feature = "trace")]
+        crate::trace::sched_migrate(&task, busiest, self.cpu_id);
+        task.set_cpu_id(self.cpu_id as _);
+        TASKS_STOLEN.inc();
+        Some(task)
+    }
+
+    /// Pulls a task from the busiest run queue of the other CPUs, if it has
+    /// two ready tasks more than this one.
+    #[cfg(feature = "smp")]
+    fn balance(&mut self) {
+        let len = self.scheduler.lock().len;
+        if let Some(task) = self.steal_task(len + 2) {
+            self.scheduler.lock().add_task(task);
+        }
+    }
+
     /// Core reschedule subroutine.
     /// Pick the next task to run and switch to it.
     fn resched(&mut self) {
-        let next = self
-            .scheduler
-            .lock()
-            .pick_next_task()
-            .unwrap_or_else(|| unsafe {
-                // Safety: IRQs must be disabled at this time.
-                IDLE_TASK.current_ref_raw().get_unchecked().clone()
-            });
+        let next = self.pick_next_task().unwrap_or_else(|| unsafe {
+            // Safety: IRQs must be disabled at this time.
+            IDLE_TASK.current_ref_raw().get_unchecked().clone()
+        });
         assert!(
             next.is_ready(),
             "next {} is not ready: {:?}",
             next.id_name(),
             next.state()
         );
+        #[cfg(feature = "replay")]
+        khal::replay::on_schedule(next.id().as_u64());
         self.switch_to(crate::current(), next);
     }
 
@@ -535,6 +928,14 @@ impl RunQueue {
         if prev_task.ptr_eq(&next_task) {
             return;
         }
+        CONTEXT_SWITCHES.inc();
+        crate::stats::on_switch(
+            &prev_task,
+            &next_task,
+            matches!(prev_task.state(), TaskState::Blocked | TaskState::Exited),
+        );
+        #[cfg(feature = "trace")]
+        crate::trace::sched_switch(&prev_task, &next_task);
 
         // Claim the task as running, we do this before switching to it
         // such that any running task will have this set.
@@ -627,19 +1028,87 @@ fn poll_gc(cx: &mut Context<'_>) -> Poll<()> {
     Poll::Pending
 }
 
+/// Puts `task`, a ready task switched out, into a run queue its CPU
+/// affinity allows.
+#[cfg(feature = "smp")]
+fn put_ready_task(task: KtaskRef) {
+    // IRQs and preemption are disabled by the caller.
+    let rq = select_run_queue::<kspin::NoOp>(&task);
+    #[cfg(feature = "trace")]
+    crate::trace::sched_migrate(&task, task.cpu_id() as _, rq.inner.cpu_id);
+    task.set_cpu_id(rq.inner.cpu_id as _);
+    rq.inner.scheduler.lock().put_prev_task(task, false);
+}
+
+/// Creates a task to run [`migrate_entry`] for `task`, the current task,
+/// once it is switched out; see [`CurrentRunQueueRef::migrate_current`].
+#[cfg(feature = "smp")]
+pub(crate) fn migration_task(task: KtaskRef) -> KtaskRef {
+    const MIGRATION_TASK_STACK_SIZE: usize = 4096;
+    TaskInner::new(
+        move || migrate_entry(task),
+        "migration-task".into(),
+        MIGRATION_TASK_STACK_SIZE,
+    )
+    .into_arc()
+}
+
 /// The task routine for migrating the current task to the correct CPU.
 ///
 /// It calls `select_run_queue` to get the correct run queue for the task, and
 /// then puts the task to the scheduler of target run queue.
 #[cfg(feature = "smp")]
 pub(crate) fn migrate_entry(migrated_task: KtaskRef) {
-    select_run_queue::<kspin::NoPreemptIrqSave>(&migrated_task)
-        .inner
+    let rq = select_run_queue::<kspin::NoPreemptIrqSave>(&migrated_task);
+    #[cfg(feature = "trace")]
+    crate::trace::sched_migrate(&migrated_task, this_cpu_id(), rq.inner.cpu_id);
+    migrated_task.set_cpu_id(rq.inner.cpu_id as _);
+    rq.inner
         .scheduler
         .lock()
         .put_prev_task(migrated_task, false)
 }
 
+/// The tasks in the run queue of this CPU, as far as they could be walked.
+#[cfg(feature = "integrity")]
+pub(crate) struct ReadySnapshot {
+    pub cpu_id: usize,
+    /// The number of tasks the run queue counts.
+    pub len: usize,
+    /// The tasks in scheduling order, up to one more than counted.
+    pub tasks: alloc::vec::Vec<KtaskRef>,
+}
+
+/// Walks the run queue of this CPU, taking the tasks out one by one and
+/// putting them back in order, which restarts their round-robin time slices.
+/// The real-time tasks, queued apart, are only looked at.
+///
+/// The walk stops one task after the count, so that it ends on a queue
+/// running in a cycle too.
+#[cfg(feature = "integrity")]
+pub(crate) fn ready_snapshot() -> ReadySnapshot {
+    let rq = current_run_queue::<kspin::NoPreemptIrqSave>();
+    let mut ready = rq.inner.scheduler.lock();
+    let len = ready.len;
+    let mut tasks = alloc::vec::Vec::with_capacity(len + 1);
+    tasks.extend(ready.rt.iter().take(len + 1).cloned());
+    let rt_len = tasks.len();
+    while tasks.len() <= len {
+        match ready.scheduler.pick_next_task() {
+            Some(task) => tasks.push(task),
+            None => break,
+        }
+    }
+    for task in &tasks[rt_len..] {
+        ready.scheduler.put_prev_task(task.clone(), false);
+    }
+    ReadySnapshot {
+        cpu_id: rq.inner.cpu_id,
+        len,
+        tasks,
+    }
+}
+
 /// Clear the `on_cpu` field of previous task running on this CPU.
 #[cfg(feature = "smp")]
 pub(crate) unsafe fn clear_prev_task_on_cpu() {
@@ -677,6 +1146,8 @@ pub(crate) fn init() {
     unsafe {
         RUN_QUEUES[cpu_id].write(RUN_QUEUE.current_ref_mut_raw());
     }
+    #[cfg(feature = "smp")]
+    RUN_QUEUE_READY[cpu_id].store(true, Ordering::Release);
 }
 
 pub(crate) fn init_secondary() {
@@ -696,4 +1167,6 @@ pub(crate) fn init_secondary() {
     unsafe {
         RUN_QUEUES[cpu_id].write(RUN_QUEUE.current_ref_mut_raw());
     }
+    #[cfg(feature = "smp")]
+    RUN_QUEUE_READY[cpu_id].store(true, Ordering::Release);
 }
@@ -0,0 +1,199 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Cooperative snapshots of the VM under QEMU `savevm`/`loadvm`.
+//!
+//! [`checkpoint`] brings the kernel into a state worth saving: the
+//! subsystems that added [`Hooks`] quiesce, e.g. flush their caches, and a
+//! marker line with the scheduler and timer state goes to the console:
+//!
+//! ```text
+//! snapshot: checkpoint 1 monotonic_ns=… wall_ns=… context_switches=… tasks_spawned=… timers=… ready
+//! ```
+//!
+//! A harness waiting for the marker takes the snapshot with `savevm`, and
+//! restores it with `loadvm` as often as it likes, skipping the boot.
+//!
+//! The kernel cannot tell when the VM is stopped: the monotonic clock,
+//! driven by QEMU's virtual clock, goes on from where it stopped, but the
+//! RTC follows the host. [`watch`] compares the two; a jump means the VM was
+//! paused, restored or migrated, and the kernel resumes:
+//!
+//! - the wall clock is stepped to the RTC, pending timers keep their
+//!   remaining durations;
+//! - the timer interrupt of every CPU is re-armed;
+//! - the hooks pick up, e.g. poll devices whose interrupts were lost;
+//! - and a `snapshot: resumed checkpoint 1 step_ns=…` marker is printed.
+use alloc::vec::Vec;
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use kspin::SpinNoIrq;
+
+use crate::run_queue::{CONTEXT_SWITCHES, TASKS_SPAWNED};
+
+/// Smallest difference between the RTC and the wall clock taken for a jump.
+///
+/// The RTC counts whole seconds, so the clocks differ by up to one anyway.
+pub const JUMP_THRESHOLD: Duration = Duration::from_secs(2);
+/// How often [`watch`] compares the clocks.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Callbacks of a subsystem taking part in snapshots.
+#[derive(Clone, Copy)]
+pub struct Hooks {
+    pub name: &'static str,
+    /// Brings the subsystem to a state worth saving.
+    pub quiesce: fn(),
+    /// Picks up after the VM was resumed.
+    pub resume: fn(),
+}
+
+/// The state recorded by a [`checkpoint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Number of the checkpoint since boot, from 1.
+    pub generation: u64,
+    pub monotonic_ns: u64,
+    pub wall_ns: u64,
+    pub context_switches: u64,
+    pub tasks_spawned: u64,
+    /// Timers pending on the CPU taking the checkpoint.
+    pub timers: usize,
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "checkpoint {} monotonic_ns={} wall_ns={} context_switches={} tasks_spawned={} \
+             timers={}",
+            self.generation,
+            self.monotonic_ns,
+            self.wall_ns,
+            self.context_switches,
+            self.tasks_spawned,
+            self.timers
+        )
+    }
+}
+
+static HOOKS: SpinNoIrq<Vec<Hooks>> = SpinNoIrq::new(Vec::new());
+static LAST_CHECKPOINT: SpinNoIrq<Option<Checkpoint>> = SpinNoIrq::new(None);
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+static RESUMES: AtomicU64 = AtomicU64::new(0);
+/// Whether a resume is under way.
+static RESUMING: AtomicBool = AtomicBool::new(false);
+
+/// Makes `hooks` run on checkpoints and resumes, in the order added.
+pub fn register(hooks: Hooks) {
+    HOOKS.lock().push(hooks);
+}
+
+fn hooks() -> Vec<Hooks> {
+    HOOKS.lock().clone()
+}
+
+/// Quiesces the subsystems, records the state and prints the marker a
+/// harness takes the snapshot on.
+pub fn checkpoint() -> Checkpoint {
+    for hooks in hooks() {
+        debug!("snapshot: quiescing {}", hooks.name);
+        (hooks.quiesce)();
+    }
+    let checkpoint = Checkpoint {
+        generation: GENERATION.fetch_add(1, Ordering::Relaxed) + 1,
+        monotonic_ns: khal::time::monotonic_time_nanos(),
+        wall_ns: khal::time::wall_time_nanos(),
+        context_switches: CONTEXT_SWITCHES.get(),
+        tasks_spawned: TASKS_SPAWNED.get(),
+        timers: crate::future::pending_timers(),
+    };
+    *LAST_CHECKPOINT.lock() = Some(checkpoint);
+    kplat::kprintln!("snapshot: {} ready", checkpoint);
+    checkpoint
+}
+
+/// Returns the last checkpoint taken.
+pub fn last_checkpoint() -> Option<Checkpoint> {
+    *LAST_CHECKPOINT.lock()
+}
+
+/// Returns how many times the kernel resumed.
+pub fn resumes() -> u64 {
+    RESUMES.load(Ordering::Relaxed)
+}
+
+/// Resumes the kernel if the VM was paused, restored or migrated since the
+/// last call, returning by how many nanoseconds the wall clock was stepped.
+///
+/// Returns `None` on platforms without an RTC.
+pub fn poll_resume() -> Option<i64> {
+    let rtc_ns = khal::time::rtc_ns()?;
+    let step_ns = rtc_ns as i64 - khal::time::wall_time_nanos() as i64;
+    if step_ns.unsigned_abs() < JUMP_THRESHOLD.as_nanos() as u64 {
+        return None;
+    }
+    if RESUMING.swap(true, Ordering::Acquire) {
+        return None;
+    }
+    resume(step_ns);
+    RESUMING.store(false, Ordering::Release);
+    Some(step_ns)
+}
+
+fn resume(step_ns: i64) {
+    khal::time::step_wall(step_ns);
+    rearm_timers();
+    for hooks in hooks() {
+        debug!("snapshot: resuming {}", hooks.name);
+        (hooks.resume)();
+    }
+    RESUMES.fetch_add(1, Ordering::Relaxed);
+    match last_checkpoint() {
+        Some(checkpoint) => kplat::kprintln!(
+            "snapshot: resumed checkpoint {} step_ns={}",
+            checkpoint.generation,
+            step_ns
+        ),
+        None => kplat::kprintln!("snapshot: resumed step_ns={}", step_ns),
+    }
+}
+
+/// Fires the timer interrupt of every CPU, which re-arms the periodic tick.
+fn rearm_timers() {
+    #[cfg(feature = "smp")]
+    {
+        let cpumask = crate::current().cpumask();
+        for cpu in 0..crate::CPU_NUM {
+            crate::set_current_affinity(crate::KCpuMask::one_shot(cpu));
+            khal::time::arm_timer(khal::time::monotonic_time_nanos());
+        }
+        crate::set_current_affinity(cpumask);
+    }
+    #[cfg(not(feature = "smp"))]
+    khal::time::arm_timer(khal::time::monotonic_time_nanos());
+}
+
+/// Starts a task resuming the kernel whenever the VM was stopped.
+///
+/// Does nothing on platforms without an RTC, where stops go unnoticed.
+pub fn watch() {
+    if khal::time::rtc_ns().is_none() {
+        warn!("snapshot: no RTC, resumes go unnoticed");
+        return;
+    }
+    crate::spawn_with_name(
+        || {
+            loop {
+                crate::sleep(WATCH_INTERVAL);
+                poll_resume();
+            }
+        },
+        "snapshot-watch".into(),
+    );
+}
@@ -0,0 +1,183 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Runtime statistics of the tasks and the CPUs.
+//!
+//! The CPU time of the current task is accounted on context switches, timer
+//! interrupts, and switches between user and kernel mode, to the task and
+//! to its CPU. The time of the idle task is the idle time of the CPU.
+
+use alloc::vec::Vec;
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use khal::time::monotonic_time_nanos;
+
+use crate::TaskInner;
+
+/// Runtime statistics of a task.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TaskStats {
+    /// CPU time spent in user mode.
+    pub utime: Duration,
+    /// CPU time spent in the kernel.
+    pub stime: Duration,
+    /// Switches out of the task as it blocked or exited.
+    pub voluntary_switches: u64,
+    /// Switches out of the task while it could still run.
+    pub involuntary_switches: u64,
+}
+
+/// Runtime statistics of a CPU.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuStats {
+    /// Time the tasks spent in user mode.
+    pub user: Duration,
+    /// Time the tasks spent in the kernel, but for the idle task.
+    pub system: Duration,
+    /// Time the idle task ran.
+    pub idle: Duration,
+    /// Time spent handling the interrupts, also accounted to the task they
+    /// interrupted.
+    pub irq: Duration,
+    /// Context switches.
+    pub context_switches: u64,
+}
+
+/// The statistics of a task, as accounted.
+pub(crate) struct TaskStatsInner {
+    user_ns: AtomicU64,
+    system_ns: AtomicU64,
+    /// When the time of the task was last accounted, 0 while it is not
+    /// running.
+    since_ns: AtomicU64,
+    in_user: AtomicBool,
+    voluntary: AtomicU64,
+    involuntary: AtomicU64,
+}
+
+impl TaskStatsInner {
+    pub const fn new() -> Self {
+        Self {
+            user_ns: AtomicU64::new(0),
+            system_ns: AtomicU64::new(0),
+            since_ns: AtomicU64::new(0),
+            in_user: AtomicBool::new(false),
+            voluntary: AtomicU64::new(0),
+            involuntary: AtomicU64::new(0),
+        }
+    }
+
+    /// Accounts the time since the last accounting at `now_ns`, to `cpu` as
+    /// well, unless the task is not running.
+    fn account(&self, now_ns: u64, cpu: &CpuStatsInner, idle: bool) {
+        let since = self.since_ns.load(Ordering::Relaxed);
+        if since == 0 {
+            return;
+        }
+        self.since_ns.store(now_ns, Ordering::Relaxed);
+        let delta = now_ns.saturating_sub(since);
+        let (task, cpu) = if self.in_user.load(Ordering::Relaxed) {
+            (&self.user_ns, &cpu.user_ns)
+        } else if idle {
+            (&self.system_ns, &cpu.idle_ns)
+        } else {
+            (&self.system_ns, &cpu.system_ns)
+        };
+        task.fetch_add(delta, Ordering::Relaxed);
+        cpu.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    /// Starts accounting the time of the task, which runs from `now_ns`.
+    pub fn start(&self, now_ns: u64) {
+        self.since_ns.store(now_ns.max(1), Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> TaskStats {
+        TaskStats {
+            utime: Duration::from_nanos(self.user_ns.load(Ordering::Relaxed)),
+            stime: Duration::from_nanos(self.system_ns.load(Ordering::Relaxed)),
+            voluntary_switches: self.voluntary.load(Ordering::Relaxed),
+            involuntary_switches: self.involuntary.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct CpuStatsInner {
+    user_ns: AtomicU64,
+    system_ns: AtomicU64,
+    idle_ns: AtomicU64,
+    context_switches: AtomicU64,
+}
+
+static CPU_STATS: [CpuStatsInner; crate::CPU_NUM] = [const {
+    CpuStatsInner {
+        user_ns: AtomicU64::new(0),
+        system_ns: AtomicU64::new(0),
+        idle_ns: AtomicU64::new(0),
+        context_switches: AtomicU64::new(0),
+    }
+}; crate::CPU_NUM];
+
+fn this_cpu_stats() -> &'static CpuStatsInner {
+    &CPU_STATS[khal::percpu::this_cpu_id()]
+}
+
+/// Accounts the time of the current task up to now.
+///
+/// Called with IRQs disabled.
+pub(crate) fn account_current() {
+    let curr = crate::current();
+    curr.run_stats
+        .account(monotonic_time_nanos(), this_cpu_stats(), curr.is_idle());
+}
+
+/// Accounts the switch from `prev` to `next` on this CPU; `prev` switches
+/// out voluntarily if it blocked or exited.
+///
+/// Called with IRQs disabled.
+pub(crate) fn on_switch(prev: &TaskInner, next: &TaskInner, voluntary: bool) {
+    let now_ns = monotonic_time_nanos();
+    let cpu = this_cpu_stats();
+    prev.run_stats.account(now_ns, cpu, prev.is_idle());
+    prev.run_stats.since_ns.store(0, Ordering::Relaxed);
+    match voluntary {
+        true => &prev.run_stats.voluntary,
+        false => &prev.run_stats.involuntary,
+    }
+    .fetch_add(1, Ordering::Relaxed);
+    next.run_stats.start(now_ns);
+    cpu.context_switches.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Accounts the time of the current task to user mode from now on, or back
+/// to the kernel.
+pub fn set_user_mode(user: bool) {
+    let _g = kspin::NoPreemptIrqSave::new();
+    account_current();
+    crate::current()
+        .run_stats
+        .in_user
+        .store(user, Ordering::Relaxed);
+}
+
+/// Returns the statistics of the active CPUs, as accounted at the last
+/// context switch, timer interrupt or mode switch on each.
+pub fn stats() -> Vec<CpuStats> {
+    let _g = kspin::NoPreemptIrqSave::new();
+    account_current();
+    CPU_STATS[..crate::api::active_cpu_num()]
+        .iter()
+        .enumerate()
+        .map(|(cpu_id, cpu)| CpuStats {
+            user: Duration::from_nanos(cpu.user_ns.load(Ordering::Relaxed)),
+            system: Duration::from_nanos(cpu.system_ns.load(Ordering::Relaxed)),
+            idle: Duration::from_nanos(cpu.idle_ns.load(Ordering::Relaxed)),
+            irq: Duration::from_nanos(khal::irq::irq_time_nanos(cpu_id)),
+            context_switches: cpu.context_switches.load(Ordering::Relaxed),
+        })
+        .collect()
+}
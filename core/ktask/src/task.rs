@@ -15,18 +15,23 @@ use core::{
     mem::ManuallyDrop,
     ops::Deref,
     ptr::NonNull,
-    sync::atomic::{AtomicBool, AtomicI32, AtomicU8, AtomicU32, AtomicU64, Ordering},
+    sync::atomic::{AtomicBool, AtomicI32, AtomicU8, AtomicU16, AtomicU32, AtomicU64, Ordering},
     task::{Context, Poll},
 };
 
 use futures_util::task::AtomicWaker;
+use kalloc::KmemCache;
 use khal::context::TaskContext;
 #[cfg(feature = "tls")]
 use khal::tls::TlsArea;
 use kspin::SpinNoIrq;
 use memaddr::{VirtAddr, align_up_4k};
 
-use crate::{KCpuMask, KTask, KtaskRef, future::block_on};
+use crate::{
+    KCpuMask, KTask, KtaskRef,
+    future::block_on,
+    stats::{TaskStats, TaskStatsInner},
+};
 
 /// A unique identifier for a thread.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -47,6 +52,68 @@ pub enum TaskState {
     Exited  = 4,
 }
 
+/// The highest real-time priority, the lowest being 1, as with
+/// `sched_setscheduler(2)`.
+pub const MAX_RT_PRIO: u8 = 99;
+
+/// The scheduling class of a task.
+///
+/// The real-time tasks run before all the others, in the order of their
+/// priorities, and preempt the tasks of a lower priority they wake up
+/// (with the `preempt` feature).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SchedPolicy {
+    /// Scheduled by the scheduler chosen by the cargo features, when no
+    /// real-time task is ready.
+    Normal,
+    /// Real-time with the given priority, running until it blocks, yields or
+    /// a task of a higher priority is ready, as `SCHED_FIFO`.
+    Fifo(u8),
+    /// Real-time as [`Fifo`](Self::Fifo), but taking turns in time slices
+    /// with the tasks of the same priority, as `SCHED_RR`.
+    RoundRobin(u8),
+}
+
+impl SchedPolicy {
+    /// Returns the real-time priority, or 0 for [`Normal`](Self::Normal).
+    pub const fn rt_priority(self) -> u8 {
+        match self {
+            Self::Normal => 0,
+            Self::Fifo(prio) | Self::RoundRobin(prio) => prio,
+        }
+    }
+
+    /// Returns whether it is a real-time policy.
+    pub const fn is_rt(self) -> bool {
+        !matches!(self, Self::Normal)
+    }
+
+    /// Returns whether the real-time priority is in `1..=MAX_RT_PRIO`.
+    pub const fn is_valid(self) -> bool {
+        match self {
+            Self::Normal => true,
+            Self::Fifo(prio) | Self::RoundRobin(prio) => prio >= 1 && prio <= MAX_RT_PRIO,
+        }
+    }
+
+    const fn encode(self) -> u16 {
+        match self {
+            Self::Normal => 0,
+            Self::Fifo(prio) => 1 << 8 | prio as u16,
+            Self::RoundRobin(prio) => 2 << 8 | prio as u16,
+        }
+    }
+
+    const fn decode(value: u16) -> Self {
+        let prio = value as u8;
+        match value >> 8 {
+            1 => Self::Fifo(prio),
+            2 => Self::RoundRobin(prio),
+            _ => Self::Normal,
+        }
+    }
+}
+
 /// User-defined task extended data.
 /// # Safety
 /// See [`extern_trait`].
@@ -60,8 +127,14 @@ pub unsafe trait TaskExt {
     fn on_enter(&self) {}
     /// Called when the task is switched out.
     fn on_leave(&self) {}
+    /// Called on every timer tick the task is running on, with IRQs
+    /// disabled, e.g. to account its CPU time.
+    fn on_tick(&self) {}
 }
 
+/// The `queued_on` of a task in no run queue.
+const NOT_QUEUED: u32 = u32::MAX;
+
 // How many held locks we track per task (debug only).
 #[cfg(feature = "watchdog")]
 const HELD_LOCK_SLOTS: usize = 4;
@@ -100,9 +173,23 @@ pub struct TaskInner {
 
     /// CPU affinity mask.
     cpumask: SpinNoIrq<KCpuMask>,
+    /// CPU affinity mask set for the task while it was queued or running on
+    /// another CPU, applied the next time it is scheduled.
+    pending_cpumask: SpinNoIrq<Option<KCpuMask>>,
+    /// I/O priority, encoded as by `ioprio_set(2)`.
+    io_priority: AtomicU16,
+    /// Scheduling priority, as last accepted by [`set_prio`](crate::set_prio).
+    priority: AtomicI32,
+    /// Scheduling class, a [`SchedPolicy`] encoded.
+    sched_policy: AtomicU16,
+    /// Ticks left of the time slice of a [`SchedPolicy::RoundRobin`] task.
+    rt_time_slice: AtomicU32,
 
     /// Used to indicate the CPU ID where the task is running or will run.
     cpu_id: AtomicU32,
+    /// The CPU ID of the run queue holding the task, or [`NOT_QUEUED`].
+    /// Only changed under the lock of that run queue.
+    queued_on: AtomicU32,
     /// Used to indicate whether the task is running on a CPU.
     #[cfg(feature = "smp")]
     on_cpu: AtomicBool,
@@ -130,6 +217,13 @@ pub struct TaskInner {
     /// Per-task watchdog recording (lock-free/NMI-safe).
     #[cfg(feature = "watchdog")]
     record_lock: PerTaskRecording,
+
+    /// CPU time and context switches.
+    pub(crate) run_stats: TaskStatsInner,
+
+    /// Sleeping locks held, for the lock dependency validator.
+    #[cfg(feature = "lockdep")]
+    pub(crate) held_locks: kspin::lockdep::HeldLocks,
 }
 
 impl TaskId {
@@ -182,6 +276,7 @@ impl TaskInner {
         if t.name() == "idle" {
             t.is_idle = true;
         }
+        t.run_stats.start(khal::time::monotonic_time_nanos());
         t
     }
 
@@ -273,7 +368,124 @@ impl TaskInner {
     /// `cpumask` - The cpu affinity mask to be set in type [`KCpuMask`].
     #[inline]
     pub fn set_cpumask(&self, cpumask: KCpuMask) {
-        *self.cpumask.lock() = cpumask
+        *self.cpumask.lock() = cpumask;
+        *self.pending_cpumask.lock() = None;
+    }
+
+    /// Gets the cpu affinity mask last set for the task, which may not have
+    /// been applied yet; see [`set_affinity`](crate::set_affinity).
+    #[inline]
+    pub fn affinity(&self) -> KCpuMask {
+        self.pending_cpumask
+            .lock()
+            .unwrap_or_else(|| self.cpumask())
+    }
+
+    /// Sets the cpu affinity mask of the task to apply the next time it is
+    /// scheduled.
+    #[cfg(feature = "smp")]
+    #[inline]
+    pub(crate) fn set_pending_cpumask(&self, cpumask: KCpuMask) {
+        *self.pending_cpumask.lock() = Some(cpumask);
+    }
+
+    /// Returns whether a cpu affinity mask waits to be applied.
+    #[cfg(all(feature = "smp", feature = "preempt"))]
+    #[inline]
+    pub(crate) fn has_pending_cpumask(&self) -> bool {
+        self.pending_cpumask.lock().is_some()
+    }
+
+    /// Applies the cpu affinity mask set for the task, if any. Returns
+    /// `false` if it may not run on `cpu_id` from now on.
+    #[cfg(feature = "smp")]
+    pub(crate) fn apply_pending_cpumask(&self, cpu_id: usize) -> bool {
+        match self.pending_cpumask.lock().take() {
+            Some(cpumask) => {
+                *self.cpumask.lock() = cpumask;
+                cpumask.get(cpu_id)
+            }
+            None => true,
+        }
+    }
+
+    /// Returns the I/O priority of the task, encoded as by `ioprio_set(2)`.
+    ///
+    /// It is 0 for a task that didn't set one, whose requests get the default
+    /// priority of the block layer.
+    #[inline]
+    pub fn io_priority(&self) -> u16 {
+        self.io_priority.load(Ordering::Relaxed)
+    }
+
+    /// Sets the I/O priority of the task, encoded as by `ioprio_set(2)`.
+    #[inline]
+    pub fn set_io_priority(&self, io_priority: u16) {
+        self.io_priority.store(io_priority, Ordering::Relaxed);
+    }
+
+    /// Returns the scheduling priority of the task, whose range depends on
+    /// the scheduler; see [`set_prio`](crate::set_prio).
+    ///
+    /// It is 0 for a task that didn't set one.
+    #[inline]
+    pub fn priority(&self) -> i32 {
+        self.priority.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn set_priority(&self, priority: i32) {
+        self.priority.store(priority, Ordering::Relaxed);
+    }
+
+    /// Returns the CPU time and context switches of the task, as accounted
+    /// at its last context switch, timer interrupt or mode switch.
+    pub fn stats(&self) -> TaskStats {
+        self.run_stats.snapshot()
+    }
+
+    /// Returns the scheduling class of the task.
+    #[inline]
+    pub fn sched_policy(&self) -> SchedPolicy {
+        SchedPolicy::decode(self.sched_policy.load(Ordering::Relaxed))
+    }
+
+    /// Sets the scheduling class of the task, before it is spawned; see
+    /// [`set_sched_policy`](crate::set_sched_policy) for the current task.
+    ///
+    /// A task already in a run queue keeps its place until it is picked.
+    /// Returns `false` if the real-time priority is out of range.
+    pub fn set_sched_policy(&self, policy: SchedPolicy) -> bool {
+        if !policy.is_valid() {
+            return false;
+        }
+        self.reset_rt_time_slice();
+        self.sched_policy.store(policy.encode(), Ordering::Relaxed);
+        true
+    }
+
+    /// Restarts the time slice of a [`SchedPolicy::RoundRobin`] task.
+    #[inline]
+    pub(crate) fn reset_rt_time_slice(&self) {
+        self.rt_time_slice
+            .store(crate::rt_queue::RR_TIME_SLICE, Ordering::Relaxed);
+    }
+
+    /// Accounts a tick to the time slice of a [`SchedPolicy::RoundRobin`]
+    /// task, returning `true` if it is used up.
+    #[inline]
+    pub(crate) fn rt_time_slice_tick(&self) -> bool {
+        let left = self
+            .rt_time_slice
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                Some(left.saturating_sub(1))
+            });
+        left.unwrap() <= 1
+    }
+
+    #[inline]
+    pub(crate) fn rt_time_slice_left(&self) -> bool {
+        self.rt_time_slice.load(Ordering::Relaxed) > 0
     }
 
     /// Polls whether the task has been interrupted.
@@ -405,7 +617,13 @@ impl TaskInner {
             state: AtomicU8::new(TaskState::Ready as u8),
             // By default, the task is allowed to run on all CPUs.
             cpumask: SpinNoIrq::new(cpumask),
+            pending_cpumask: SpinNoIrq::new(None),
+            io_priority: AtomicU16::new(0),
+            priority: AtomicI32::new(0),
+            sched_policy: AtomicU16::new(SchedPolicy::Normal.encode()),
+            rt_time_slice: AtomicU32::new(0),
             cpu_id: AtomicU32::new(0),
+            queued_on: AtomicU32::new(NOT_QUEUED),
             #[cfg(feature = "smp")]
             on_cpu: AtomicBool::new(false),
             #[cfg(feature = "preempt")]
@@ -424,6 +642,9 @@ impl TaskInner {
             tls: TlsArea::alloc(),
             #[cfg(feature = "watchdog")]
             record_lock: PerTaskRecording::new(),
+            run_stats: TaskStatsInner::new(),
+            #[cfg(feature = "lockdep")]
+            held_locks: kspin::lockdep::HeldLocks::new(),
         }
     }
 
@@ -443,6 +664,7 @@ impl TaskInner {
         if t.name() == "idle" {
             t.is_idle = true;
         }
+        t.run_stats.start(khal::time::monotonic_time_nanos());
         t
     }
 
@@ -556,6 +778,21 @@ impl TaskInner {
         self.cpu_id.store(cpu_id, Ordering::Release);
     }
 
+    /// Returns the CPU ID of the run queue holding the task, if it is queued
+    /// and not picked yet.
+    #[inline]
+    pub(crate) fn queued_on(&self) -> Option<usize> {
+        let cpu_id = self.queued_on.load(Ordering::SeqCst);
+        (cpu_id != NOT_QUEUED).then_some(cpu_id as usize)
+    }
+
+    /// Records the run queue holding the task, whose lock is held.
+    #[inline]
+    pub(crate) fn set_queued_on(&self, cpu_id: Option<usize>) {
+        let cpu_id = cpu_id.map_or(NOT_QUEUED, |cpu_id| cpu_id as u32);
+        self.queued_on.store(cpu_id, Ordering::SeqCst);
+    }
+
     /// Returns whether the task is running on a CPU.
     ///
     /// It is used to protect the task from being moved to a different run queue
@@ -598,9 +835,20 @@ impl fmt::Debug for TaskInner {
 impl Drop for TaskInner {
     fn drop(&mut self) {
         debug!("task drop: {}", self.id_name());
+        #[cfg(all(target_arch = "aarch64", feature = "fp-simd"))]
+        crate::vector::free_vector_state(&mut self.ctx.get_mut().fp_state.sve);
     }
 }
 
+/// Layout of the stacks of the default size, which most tasks have.
+const DEFAULT_STACK_LAYOUT: Layout = match Layout::from_size_align(crate::TASK_STACK_SIZE, 16) {
+    Ok(layout) => layout,
+    Err(_) => panic!("invalid task stack size"),
+};
+
+/// Cache of the stacks of the default size.
+static STACK_CACHE: KmemCache = KmemCache::new("task_stack", DEFAULT_STACK_LAYOUT, None);
+
 struct TaskStack {
     ptr: NonNull<u8>,
     layout: Layout,
@@ -609,6 +857,14 @@ struct TaskStack {
 impl TaskStack {
     pub fn alloc(size: usize) -> Self {
         let layout = Layout::from_size_align(size, 16).unwrap();
+        if layout == DEFAULT_STACK_LAYOUT {
+            return Self {
+                ptr: STACK_CACHE.alloc().expect("failed to allocate task stack"),
+                layout,
+            };
+        }
+        #[cfg(feature = "heap-tags")]
+        let _tag = kalloc::HeapTagGuard::new(kalloc::HeapTag::TaskStack);
         Self {
             ptr: NonNull::new(unsafe { alloc::alloc::alloc(layout) }).unwrap(),
             layout,
@@ -622,7 +878,11 @@ impl TaskStack {
 
 impl Drop for TaskStack {
     fn drop(&mut self) {
-        unsafe { alloc::alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+        if self.layout == DEFAULT_STACK_LAYOUT {
+            unsafe { STACK_CACHE.dealloc(self.ptr) }
+        } else {
+            unsafe { alloc::alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+        }
     }
 }
 
@@ -2,10 +2,16 @@
 // Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
 // See LICENSES for license details.
 
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
 use std::sync::{Mutex, Once};
 
-use crate::{WaitQueue, api as ktask, current};
+use crate::{
+    SchedPolicy, WaitQueue, api as ktask, current, kthread,
+    workqueue::{self, Work},
+};
 
 static INIT: Once = Once::new();
 static SERIAL: Mutex<()> = Mutex::new(());
@@ -129,3 +135,197 @@ fn test_task_join() {
         assert_eq!(task.join(), i as _);
     }
 }
+
+#[test]
+fn test_sched_rt_priority() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(ktask::init_scheduler);
+
+    const PRIOS: [u8; 4] = [10, 50, 10, 90];
+    static ORDER: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+    assert!(
+        !ktask::TaskInner::new(|| {}, String::new(), 0x1000).set_sched_policy(SchedPolicy::Fifo(0))
+    );
+
+    let mut tasks = Vec::with_capacity(PRIOS.len() + 1);
+    // A normal task ready first still runs after the real-time ones.
+    tasks.push(ktask::spawn(|| ORDER.lock().unwrap().push(usize::MAX)));
+    for (i, prio) in PRIOS.into_iter().enumerate() {
+        let task = ktask::TaskInner::new(
+            move || {
+                // Tasks of the same priority take turns when they yield.
+                ktask::yield_now();
+                ORDER.lock().unwrap().push(i);
+            },
+            format!("RT{i}"),
+            0x1000,
+        );
+        assert!(task.set_sched_policy(SchedPolicy::Fifo(prio)));
+        tasks.push(ktask::spawn_task(task));
+    }
+
+    for task in tasks {
+        task.join();
+    }
+    assert_eq!(*ORDER.lock().unwrap(), [3, 1, 0, 2, usize::MAX]);
+}
+
+#[test]
+fn test_sched_policy_boost_ready_task() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(ktask::init_scheduler);
+
+    static ORDER: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+    static WQ: WaitQueue = WaitQueue::new();
+    static RELEASED: AtomicUsize = AtomicUsize::new(0);
+
+    // A normal task holding what a real-time one waits for.
+    let owner = ktask::spawn(|| {
+        ORDER.lock().unwrap().push("owner");
+        assert!(ktask::set_sched_policy(SchedPolicy::Normal));
+        RELEASED.store(1, Ordering::Release);
+        WQ.notify_one(false);
+    });
+    let hog = ktask::TaskInner::new(
+        || {
+            for _ in 0..100 {
+                if RELEASED.load(Ordering::Acquire) != 0 {
+                    break;
+                }
+                ktask::yield_now();
+            }
+            ORDER.lock().unwrap().push("hog");
+        },
+        "hog".into(),
+        0x1000,
+    );
+    assert!(hog.set_sched_policy(SchedPolicy::Fifo(10)));
+    let hog = ktask::spawn_task(hog);
+    let waiter = {
+        let owner = owner.clone();
+        ktask::TaskInner::new(
+            move || {
+                // The ready owner runs before the tasks of a lower priority.
+                assert!(ktask::set_task_sched_policy(&owner, SchedPolicy::Fifo(50)));
+                WQ.wait_until(|| RELEASED.load(Ordering::Acquire) != 0);
+                ORDER.lock().unwrap().push("waiter");
+            },
+            "waiter".into(),
+            0x1000,
+        )
+    };
+    assert!(waiter.set_sched_policy(SchedPolicy::Fifo(50)));
+    let waiter = ktask::spawn_task(waiter);
+
+    for task in [waiter, owner, hog] {
+        task.join();
+    }
+    assert_eq!(*ORDER.lock().unwrap(), ["owner", "waiter", "hog"]);
+}
+
+#[test]
+fn test_task_affinity() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(ktask::init_scheduler);
+
+    let task = ktask::spawn(ktask::yield_now);
+    let cpu0 = ktask::KCpuMask::one_shot(0);
+    assert!(ktask::set_affinity(&task, cpu0));
+    assert_eq!(ktask::get_affinity(&task), cpu0);
+    // Only the active CPUs are allowed.
+    assert!(!ktask::set_affinity(&task, ktask::KCpuMask::new()));
+    assert_eq!(ktask::get_affinity(&task), cpu0);
+    task.join();
+}
+
+#[test]
+fn test_workqueue() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(ktask::init_scheduler);
+
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    let work = Work::new(|| {
+        RUNS.fetch_add(1, Ordering::Relaxed);
+    });
+    assert!(workqueue::queue_work(&work));
+    // Queued at most once at a time.
+    assert!(!workqueue::queue_work(&work));
+    work.flush();
+    assert_eq!(RUNS.load(Ordering::Relaxed), 1);
+
+    // Cancelled before its timer fires.
+    assert!(workqueue::queue_delayed_work(
+        &work,
+        Duration::from_secs(60)
+    ));
+    assert!(work.is_pending());
+    assert!(work.cancel_sync());
+    assert!(!work.is_pending());
+
+    // Flushing a delayed work runs it at once.
+    assert!(workqueue::queue_delayed_work(
+        &work,
+        Duration::from_secs(60)
+    ));
+    work.flush();
+    assert_eq!(RUNS.load(Ordering::Relaxed), 2);
+    workqueue::flush_all();
+}
+
+#[test]
+fn test_hrtimer_cancel() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(ktask::init_scheduler);
+
+    let deadline = khal::time::monotonic_time() + Duration::from_secs(60);
+    let timer = ktask::start_hrtimer(deadline, || panic!("cancelled timer fired"));
+    assert_eq!(timer.deadline(), deadline);
+    assert!(timer.cancel());
+    // Cancelled only once.
+    assert!(!timer.cancel());
+}
+
+#[test]
+fn test_task_stats() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(ktask::init_scheduler);
+
+    let task = ktask::spawn(|| {
+        ktask::yield_now();
+        ktask::set_user_mode(true);
+        ktask::set_user_mode(false);
+    });
+    task.join();
+    let stats = task.stats();
+    // Yielding leaves the task ready to run.
+    assert!(stats.involuntary_switches >= 1);
+    assert!(stats.voluntary_switches >= 1);
+    assert!(!ktask::stats().is_empty());
+}
+
+#[test]
+fn test_kthread_park_stop() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(ktask::init_scheduler);
+
+    let handle = kthread::spawn("test-kthread", |ctx| {
+        let mut rounds = 0;
+        while !ctx.should_stop() {
+            rounds += 1;
+            ctx.park();
+        }
+        // Parking returns at once once stopped.
+        assert!(ctx.park_timeout(Duration::from_secs(60)));
+        rounds
+    });
+    handle.unpark();
+    ktask::yield_now();
+    assert!(!handle.is_finished());
+    assert!(handle.stop() >= 1);
+
+    // Joined for the result of a thread that returns on its own.
+    let handle = kthread::spawn("test-kthread", |_| 42);
+    assert_eq!(handle.join(), 42);
+}
@@ -2,12 +2,21 @@
 // Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
 // See LICENSES for license details.
 
-//! Timer tick callbacks and time-based event dispatch.
+//! Timer tick callbacks, high-resolution timers and time-based event
+//! dispatch.
+//!
+//! The timer of each CPU is one-shot, armed for the nearest of its
+//! deadlines: its high-resolution timers, the timers of the futures, and the
+//! scheduler tick. The tick is stopped while the CPU is idle, so that it
+//! sleeps until the next timer is due.
 
-use alloc::{boxed::Box, vec::Vec};
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
 
-use khal::time::{TimeValue, wall_time};
-use kspin::NoPreemptIrqSave;
+use khal::time::{TimeValue, monotonic_time_nanos, wall_time};
+use kspin::{NoPreemptIrqSave, SpinNoIrq};
+
+/// Nanoseconds between two scheduler ticks.
+const TICK_NANOS: u64 = khal::time::NANOS_PER_SEC / kbuild_config::TICKS_PER_SECOND as u64;
 
 percpu_static! {
     TIMER_CALLBACKS: Vec<Box<dyn Fn(TimeValue) + Send + Sync>> = Vec::new(),
@@ -26,9 +35,191 @@ where
     };
 }
 
+type HrTimerKey = (u64, u64);
+
+/// The high-resolution timers and the tick of a CPU.
+struct TimerBase {
+    next_key: u64,
+    /// Callbacks by deadline, in nanoseconds of monotonic time.
+    timers: BTreeMap<HrTimerKey, Box<dyn FnOnce() + Send>>,
+    /// Deadline of the next scheduler tick, `u64::MAX` while it is stopped.
+    next_tick: u64,
+    /// Whether the tick is due in the timer softirq.
+    ticked: bool,
+    /// Deadline the timer is armed for, `u64::MAX` if it is not.
+    armed: u64,
+}
+
+impl TimerBase {
+    const fn new() -> Self {
+        Self {
+            next_key: 0,
+            timers: BTreeMap::new(),
+            next_tick: 0,
+            ticked: false,
+            armed: u64::MAX,
+        }
+    }
+
+    /// Arms the timer of this CPU for `deadline`, unless it fires earlier
+    /// already. Firing early only has the timer armed again.
+    fn arm(&mut self, deadline: u64) {
+        if deadline < self.armed {
+            self.armed = deadline;
+            khal::time::arm_timer(deadline);
+        }
+    }
+
+    /// Arms the timer of this CPU for its nearest deadline.
+    fn program(&mut self) {
+        let timer = self.timers.first_key_value().map_or(u64::MAX, |(k, _)| k.0);
+        let future = crate::future::next_timer_deadline().unwrap_or(u64::MAX);
+        self.arm(self.next_tick.min(timer).min(future));
+    }
+}
+
+static TIMER_BASES: [SpinNoIrq<TimerBase>; crate::CPU_NUM] =
+    [const { SpinNoIrq::new(TimerBase::new()) }; crate::CPU_NUM];
+
+fn current_base() -> &'static SpinNoIrq<TimerBase> {
+    &TIMER_BASES[khal::percpu::this_cpu_id()]
+}
+
+/// A high-resolution timer started by [`start_hrtimer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HrTimer {
+    cpu: usize,
+    key: HrTimerKey,
+}
+
+impl HrTimer {
+    /// Returns the deadline of the timer, in monotonic time.
+    pub fn deadline(&self) -> TimeValue {
+        TimeValue::from_nanos(self.key.0)
+    }
+
+    /// Cancels the timer.
+    ///
+    /// Returns false if its callback ran or is running already.
+    pub fn cancel(&self) -> bool {
+        TIMER_BASES[self.cpu]
+            .lock()
+            .timers
+            .remove(&self.key)
+            .is_some()
+    }
+}
+
+/// Starts a timer on the current CPU calling `callback` at `deadline`, in
+/// monotonic time, with nanosecond resolution.
+///
+/// The callback runs in the timer softirq of the CPU, with IRQs enabled and
+/// preemption disabled: it must not block. It runs as soon as possible if
+/// the deadline has passed already.
+pub fn start_hrtimer<F>(deadline: TimeValue, callback: F) -> HrTimer
+where
+    F: FnOnce() + Send + 'static,
+{
+    let _g = NoPreemptIrqSave::new();
+    let cpu = khal::percpu::this_cpu_id();
+    let mut base = TIMER_BASES[cpu].lock();
+    let key = (deadline.as_nanos() as u64, base.next_key);
+    base.next_key += 1;
+    base.timers.insert(key, Box::new(callback));
+    base.arm(key.0);
+    HrTimer { cpu, key }
+}
+
+/// Arms the timer of this CPU for `deadline`, in nanoseconds of monotonic
+/// time, if it is earlier than the nearest one.
+pub(crate) fn arm_for(deadline: u64) {
+    current_base().lock().arm(deadline);
+}
+
+/// Handles the timer interrupt of this CPU: advances the scheduler tick if
+/// it is due, and arms the timer for the next deadline.
+///
+/// Returns whether the tick is due. Called with IRQs disabled.
+pub(crate) fn on_timer_irq() -> bool {
+    crate::stats::account_current();
+    let now = monotonic_time_nanos();
+    let mut base = current_base().lock();
+    base.armed = u64::MAX;
+    let tick = now >= base.next_tick;
+    if tick {
+        // Ticks missed are skipped.
+        base.next_tick = match base.next_tick + TICK_NANOS {
+            next if next > now => next,
+            _ => now + TICK_NANOS,
+        };
+        base.ticked = true;
+    }
+    base.program();
+    tick
+}
+
+/// Stops the scheduler tick of this CPU, which is idle.
+///
+/// The tasks woken on an idle CPU from another one would wait for its next
+/// timer, so the tick is only stopped on a single CPU.
+pub(crate) fn stop_tick() {
+    #[cfg(not(feature = "smp"))]
+    {
+        let mut base = current_base().lock();
+        base.next_tick = u64::MAX;
+        base.program();
+    }
+}
+
+/// Restarts the scheduler tick of this CPU once it leaves idle.
+pub(crate) fn restart_tick() {
+    #[cfg(not(feature = "smp"))]
+    {
+        let mut base = current_base().lock();
+        if base.next_tick == u64::MAX {
+            base.next_tick = monotonic_time_nanos() + TICK_NANOS;
+            base.program();
+        }
+    }
+}
+
+/// Runs the expired high-resolution timers of this CPU.
+fn run_hrtimers() {
+    let base = current_base();
+    loop {
+        let now = monotonic_time_nanos();
+        let callback = {
+            let mut base = base.lock();
+            match base.timers.first_entry() {
+                Some(entry) if entry.key().0 <= now => entry.remove(),
+                _ => break,
+            }
+        };
+        callback();
+    }
+}
+
+/// Runs the expired high-resolution timers and, on ticks, the timer
+/// callbacks, then wakes the expired timers of the futures of this CPU.
+///
+/// Called in the timer softirq, with IRQs enabled and preemption disabled.
 pub(crate) fn check_events() {
-    for callback in unsafe { TIMER_CALLBACKS.current_ref_raw().iter() } {
-        callback(wall_time());
+    #[cfg(feature = "integrity")]
+    let now = wall_time();
+    run_hrtimers();
+    let ticked = core::mem::take(&mut current_base().lock().ticked);
+    if ticked {
+        for callback in unsafe { TIMER_CALLBACKS.current_ref_raw().iter() } {
+            callback(wall_time());
+        }
     }
+    // The timers may be added by the IRQ handlers.
+    let _g = NoPreemptIrqSave::new();
     crate::future::check_timer_events();
+    // Armed for the timers added by the callbacks.
+    current_base().lock().program();
+    #[cfg(feature = "integrity")]
+    if ticked {
+        crate::integrity::on_timer_tick(now);
+    }
 }
@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Scheduler events for the trace buffer, see [`ktrace`].
+//!
+//! Tasks appear with their task IDs as pids and their priorities mapped onto
+//! Linux's scale, where 120 is nice 0 and the real-time priorities are below
+//! 100. Idle tasks appear as pid 0, which trace viewers show as idle time.
+use ktrace::{Comm, Event, PrevState, TaskInfo};
+
+use crate::{
+    TaskInner,
+    task::{MAX_RT_PRIO, SchedPolicy, TaskState},
+};
+
+fn info(task: &TaskInner) -> TaskInfo {
+    if task.is_idle() {
+        return TaskInfo {
+            pid: 0,
+            prio: 120,
+            comm: Comm::new("swapper"),
+        };
+    }
+    TaskInfo {
+        pid: task.id().as_u64(),
+        prio: match task.sched_policy() {
+            SchedPolicy::Normal => 120 + task.priority(),
+            policy => MAX_RT_PRIO as i32 - policy.rt_priority() as i32,
+        },
+        comm: Comm::new(&task.name()),
+    }
+}
+
+fn current_info() -> TaskInfo {
+    info(&crate::current())
+}
+
+/// Records the switch from `prev`, the current task, to `next`.
+pub(crate) fn sched_switch(prev: &TaskInner, next: &TaskInner) {
+    if !ktrace::is_enabled() {
+        return;
+    }
+    let prev_state = match prev.state() {
+        TaskState::Running | TaskState::Ready => PrevState::Running,
+        TaskState::Blocked => PrevState::Sleeping,
+        TaskState::Exited => PrevState::Dead,
+    };
+    let prev = info(prev);
+    ktrace::record(
+        prev,
+        Event::SchedSwitch {
+            prev,
+            prev_state,
+            next: info(next),
+        },
+    );
+}
+
+/// Records `task` being woken up onto the run queue of `target_cpu`.
+pub(crate) fn sched_wakeup(task: &TaskInner, target_cpu: usize) {
+    if !ktrace::is_enabled() {
+        return;
+    }
+    ktrace::record(
+        current_info(),
+        Event::SchedWakeup {
+            task: info(task),
+            target_cpu,
+        },
+    );
+}
+
+/// Records `task` moving from the run queue of `orig_cpu` to `dest_cpu`.
+#[cfg(feature = "smp")]
+pub(crate) fn sched_migrate(task: &TaskInner, orig_cpu: usize, dest_cpu: usize) {
+    if !ktrace::is_enabled() {
+        return;
+    }
+    ktrace::record(
+        current_info(),
+        Event::SchedMigrate {
+            task: info(task),
+            orig_cpu,
+            dest_cpu,
+        },
+    );
+}
@@ -0,0 +1,192 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Per-task vector lengths of the AArch64 scalable vector extensions.
+//!
+//! Each task chooses its SVE vector length and SME streaming vector length,
+//! which [`khal::sve`] programs into the CPU on every switch. The save area
+//! of the scalable registers is only allocated when the task first uses an
+//! extension, on the access trap.
+//!
+//! Without the `fp-simd` feature the extensions stay trapped, and the
+//! functions here report them as unsupported.
+
+#[cfg(feature = "fp-simd")]
+use alloc::alloc::{alloc_zeroed, dealloc};
+#[cfg(feature = "fp-simd")]
+use core::alloc::Layout;
+
+use kerrno::{KError, KResult};
+#[cfg(feature = "fp-simd")]
+use khal::{
+    sve::{self, SveState, VectorConfig},
+    trap::{VECTOR_STATE, register_trap_handler},
+};
+
+use crate::TaskInner;
+
+/// A scalable vector extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorKind {
+    /// The Scalable Vector Extension.
+    Sve,
+    /// The streaming mode of the Scalable Matrix Extension.
+    Sme,
+}
+
+/// Vector length settings of a task for one extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VectorLength {
+    /// Vector length in bytes the task runs with.
+    pub len: usize,
+    /// Whether `execve` keeps the vector length.
+    pub inherit: bool,
+}
+
+#[cfg(feature = "fp-simd")]
+impl VectorKind {
+    fn config(self, state: &mut SveState) -> &mut VectorConfig {
+        match self {
+            Self::Sve => &mut state.sve,
+            Self::Sme => &mut state.sme,
+        }
+    }
+
+    fn max_len(self) -> usize {
+        match self {
+            Self::Sve => sve::max_vl(),
+            Self::Sme => sve::max_svl(),
+        }
+    }
+
+    fn current_len(self, state: &SveState) -> usize {
+        match self {
+            Self::Sve => state.vl(),
+            Self::Sme => state.svl(),
+        }
+    }
+}
+
+/// Runs `f` on the vector state of the current task.
+#[cfg(feature = "fp-simd")]
+fn with_current_state<R>(f: impl FnOnce(&mut SveState) -> R) -> R {
+    // Not to race with the context switch saving the state
+    let _guard = kspin::NoPreemptIrqSave::new();
+    let curr = crate::current();
+    // SAFETY: the context of the running task is only touched by the task
+    // itself and by context switches, which are disabled meanwhile.
+    f(unsafe { &mut (*curr.ctx_mut_ptr()).fp_state.sve })
+}
+
+/// Returns the vector length settings of the current task.
+///
+/// Fails with [`KError::InvalidInput`] if the CPU lacks the extension.
+pub fn vector_length(kind: VectorKind) -> KResult<VectorLength> {
+    #[cfg(feature = "fp-simd")]
+    if kind.max_len() != 0 {
+        return Ok(with_current_state(|state| VectorLength {
+            len: kind.current_len(state),
+            inherit: kind.config(state).inherit,
+        }));
+    }
+    let _ = kind;
+    Err(KError::InvalidInput)
+}
+
+/// Sets the vector length of the current task, in bytes, returning the new
+/// settings.
+///
+/// The length, a multiple of 16 up to 256, is rounded down to one the CPU
+/// supports when applied. With `onexec` it only takes effect at the next
+/// `execve`; otherwise the scalable state of the task is discarded right
+/// away, keeping the NEON registers.
+pub fn set_vector_length(
+    kind: VectorKind,
+    len: usize,
+    inherit: bool,
+    onexec: bool,
+) -> KResult<VectorLength> {
+    #[cfg(feature = "fp-simd")]
+    if kind.max_len() != 0 {
+        if !len.is_multiple_of(sve::VL_MIN) || !(sve::VL_MIN..=sve::VL_MAX).contains(&len) {
+            return Err(KError::InvalidInput);
+        }
+        return Ok(with_current_state(|state| {
+            let config = kind.config(state);
+            config.inherit = inherit;
+            if onexec {
+                config.onexec = len as u16;
+            } else {
+                config.onexec = 0;
+                config.len = len as u16;
+                state.reload_current();
+            }
+            VectorLength {
+                len: kind.current_len(state),
+                inherit,
+            }
+        }));
+    }
+    let _ = (kind, len, inherit, onexec);
+    Err(KError::InvalidInput)
+}
+
+/// Applies the vector length settings of the current task to `execve`.
+///
+/// Lengths set for the next `execve` take effect, others go back to the
+/// default unless inherited, and the scalable state is discarded.
+pub fn exec_vector_state() {
+    #[cfg(feature = "fp-simd")]
+    with_current_state(SveState::exec_current);
+}
+
+/// Gives `task`, about to be spawned, the vector length settings of the
+/// current task.
+pub fn inherit_vector_config(task: &mut TaskInner) {
+    #[cfg(feature = "fp-simd")]
+    {
+        let (sve, sme) = with_current_state(|state| (state.sve, state.sme));
+        let state = &mut task.ctx_mut().fp_state.sve;
+        state.sve = sve;
+        state.sme = sme;
+    }
+    let _ = task;
+}
+
+/// Frees the save area of a task that is gone.
+#[cfg(feature = "fp-simd")]
+pub(crate) fn free_vector_state(state: &mut SveState) {
+    if let Some(buf) = state.take_buffer() {
+        // SAFETY: allocated by `alloc_vector_state` with the same layout.
+        unsafe { dealloc(buf as *mut u8, state_layout()) };
+    }
+}
+
+#[cfg(feature = "fp-simd")]
+fn state_layout() -> Layout {
+    Layout::from_size_align(sve::state_size(), 16).unwrap()
+}
+
+#[cfg(feature = "fp-simd")]
+#[register_trap_handler(VECTOR_STATE)]
+fn alloc_vector_state() -> bool {
+    let Some(curr) = crate::current_may_uninit() else {
+        return false;
+    };
+    // SAFETY: interrupts are disabled in the trap, so nothing else touches
+    // the context of the running task.
+    let state = unsafe { &mut (*curr.ctx_mut_ptr()).fp_state.sve };
+    if state.buffer() != 0 {
+        return true;
+    }
+    let buf = unsafe { alloc_zeroed(state_layout()) };
+    if buf.is_null() {
+        warn!("no memory for the vector state of {}", curr.id_name());
+        return false;
+    }
+    // SAFETY: the buffer has the size and alignment asked for, and belongs to
+    // the task until `free_vector_state`.
+    unsafe { state.set_buffer(buf as usize) };
+    true
+}
@@ -0,0 +1,301 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Work queues, to defer work out of interrupt context.
+//!
+//! Each CPU has a worker task, pinned to it, running the [`Work`] queued on
+//! the CPU in order. A work is queued at most once at a time: queuing it
+//! again while pending does nothing, but it can be queued again while it
+//! runs, to run once more afterwards.
+//!
+//! # Examples
+//!
+//! ```
+//! use ktask::workqueue::{self, Work};
+//!
+//! ktask::init_scheduler();
+//! let work = Work::new(|| println!("deferred"));
+//! assert!(workqueue::queue_work(&work));
+//! work.flush();
+//! ```
+
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    sync::{Arc, Weak},
+    task::Wake,
+};
+use core::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    task::Waker,
+    time::Duration,
+};
+
+use khal::{percpu::this_cpu_id, time::wall_time};
+use kspin::SpinNoIrq;
+
+use crate::{KCpuMask, TaskInner, WaitQueue};
+
+/// Queued on a CPU, or its timer armed.
+const PENDING: u64 = 1 << 0;
+/// Its timer armed, along with [`PENDING`].
+const DELAYED: u64 = 1 << 1;
+/// The timers of a delayed work are counted above the flags, so that an old
+/// timer cannot queue it once cancelled and armed again.
+const TIMER_SHIFT: u32 = 2;
+
+const WORKER_STACK_SIZE: usize = 0x4000;
+
+struct WorkInner {
+    func: Box<dyn Fn() + Send + Sync>,
+    /// Flags, and the count of timers armed.
+    state: AtomicU64,
+    /// Workers running it, more than one if it was queued on another CPU
+    /// while running.
+    running: AtomicUsize,
+    /// CPU it is queued on, or to be queued on by its timer.
+    cpu: AtomicUsize,
+    /// Notified when it stops running or is cancelled.
+    done: WaitQueue,
+}
+
+/// A function to run later in a worker task, see the [module-level
+/// documentation](self).
+#[derive(Clone)]
+pub struct Work(Arc<WorkInner>);
+
+struct WorkerPool {
+    queue: SpinNoIrq<VecDeque<Arc<WorkInner>>>,
+    /// Notified when a work is queued.
+    queued: WaitQueue,
+}
+
+impl WorkerPool {
+    const fn new() -> Self {
+        Self {
+            queue: SpinNoIrq::new(VecDeque::new()),
+            queued: WaitQueue::new(),
+        }
+    }
+}
+
+static POOLS: [WorkerPool; crate::CPU_NUM] = [const { WorkerPool::new() }; crate::CPU_NUM];
+
+impl Work {
+    /// Creates a work running `func` each time it is queued.
+    pub fn new<F>(func: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        Self(Arc::new(WorkInner {
+            func: Box::new(func),
+            state: AtomicU64::new(0),
+            running: AtomicUsize::new(0),
+            cpu: AtomicUsize::new(0),
+            done: WaitQueue::new(),
+        }))
+    }
+
+    /// Returns whether it is queued, or its timer armed.
+    pub fn is_pending(&self) -> bool {
+        self.0.state.load(Ordering::Acquire) & PENDING != 0
+    }
+
+    /// Returns whether a worker runs it.
+    pub fn is_running(&self) -> bool {
+        self.0.running.load(Ordering::Acquire) != 0
+    }
+
+    /// Waits until it is neither pending nor running. A delayed work is
+    /// queued at once.
+    ///
+    /// It must not be called from the work itself.
+    pub fn flush(&self) {
+        let work = &self.0;
+        let fired = work
+            .state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                (state & DELAYED != 0).then_some(state & !DELAYED)
+            });
+        if fired.is_ok() {
+            enqueue(work.cpu.load(Ordering::Relaxed), work.clone());
+        }
+        work.done.wait_until(|| {
+            // Its workers count before it stops pending.
+            work.state.load(Ordering::Acquire) & PENDING == 0
+                && work.running.load(Ordering::Acquire) == 0
+        });
+    }
+
+    /// Takes it out of its queue, or disarms its timer, if it is pending.
+    /// Returns whether it was pending.
+    ///
+    /// It may still be running once this returns.
+    pub fn cancel(&self) -> bool {
+        let work = &self.0;
+        let disarmed = work
+            .state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                (state & DELAYED != 0).then_some(state & !(PENDING | DELAYED))
+            });
+        let cancelled = disarmed.is_ok() || {
+            let cpu = work.cpu.load(Ordering::Relaxed);
+            let mut queue = POOLS[cpu].queue.lock();
+            match queue.iter().position(|it| Arc::ptr_eq(it, work)) {
+                Some(index) => {
+                    queue.remove(index);
+                    work.state.fetch_and(!PENDING, Ordering::AcqRel);
+                    true
+                }
+                None => false,
+            }
+        };
+        if cancelled {
+            work.done.notify_all(false);
+        }
+        cancelled
+    }
+
+    /// Cancels it as [`cancel`](Self::cancel) does, and waits until it no
+    /// longer runs.
+    ///
+    /// It must not be called from the work itself.
+    pub fn cancel_sync(&self) -> bool {
+        let cancelled = self.cancel();
+        let work = &self.0;
+        work.done
+            .wait_until(|| work.running.load(Ordering::Acquire) == 0);
+        cancelled
+    }
+}
+
+/// Queues `work`, marked as pending, on `cpu`.
+fn enqueue(cpu: usize, work: Arc<WorkInner>) {
+    let pool = &POOLS[cpu];
+    work.cpu.store(cpu, Ordering::Relaxed);
+    pool.queue.lock().push_back(work);
+    pool.queued.notify_one(false);
+}
+
+/// Queues `work` on the current CPU. Returns `false` if it was pending
+/// already.
+///
+/// It can be called in interrupt context.
+pub fn queue_work(work: &Work) -> bool {
+    queue_work_on(this_cpu_id(), work)
+}
+
+/// Queues `work` on `cpu`. Returns `false` if it was pending already.
+///
+/// It can be called in interrupt context.
+pub fn queue_work_on(cpu: usize, work: &Work) -> bool {
+    assert!(cpu < crate::CPU_NUM, "invalid CPU {cpu}");
+    if work.0.state.fetch_or(PENDING, Ordering::AcqRel) & PENDING != 0 {
+        return false;
+    }
+    enqueue(cpu, work.0.clone());
+    true
+}
+
+/// Queues `work` on the current CPU once `delay` has elapsed. Returns
+/// `false` if it was pending already.
+pub fn queue_delayed_work(work: &Work, delay: Duration) -> bool {
+    queue_delayed_work_on(this_cpu_id(), work, delay)
+}
+
+/// Queues `work` on `cpu` once `delay` has elapsed, on a timer of the
+/// current CPU. Returns `false` if it was pending already.
+pub fn queue_delayed_work_on(cpu: usize, work: &Work, delay: Duration) -> bool {
+    assert!(cpu < crate::CPU_NUM, "invalid CPU {cpu}");
+    let Ok(state) = work
+        .0
+        .state
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+            (state & PENDING == 0).then(|| (state + (1 << TIMER_SHIFT)) | PENDING | DELAYED)
+        })
+    else {
+        return false;
+    };
+    work.0.cpu.store(cpu, Ordering::Relaxed);
+    let timer = Arc::new(WorkTimer {
+        work: Arc::downgrade(&work.0),
+        timer: (state >> TIMER_SHIFT) + 1,
+    });
+    if !crate::future::wake_at(wall_time() + delay, Waker::from(timer.clone())) {
+        timer.fire();
+    }
+    true
+}
+
+/// The timer of a delayed work.
+struct WorkTimer {
+    work: Weak<WorkInner>,
+    /// Which of the timers armed for the work it is.
+    timer: u64,
+}
+
+impl WorkTimer {
+    fn fire(&self) {
+        let Some(work) = self.work.upgrade() else {
+            return;
+        };
+        let fired = work
+            .state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                (state & DELAYED != 0 && state >> TIMER_SHIFT == self.timer)
+                    .then_some(state & !DELAYED)
+            });
+        if fired.is_ok() {
+            enqueue(work.cpu.load(Ordering::Relaxed), work);
+        }
+    }
+}
+
+impl Wake for WorkTimer {
+    fn wake(self: Arc<Self>) {
+        self.fire();
+    }
+}
+
+/// Waits until the works queued on every CPU so far have run.
+pub fn flush_all() {
+    for cpu in 0..crate::api::active_cpu_num() {
+        let barrier = Work::new(|| {});
+        queue_work_on(cpu, &barrier);
+        barrier.flush();
+    }
+}
+
+fn worker_entry(cpu: usize) {
+    let pool = &POOLS[cpu];
+    loop {
+        let mut work = None;
+        pool.queued.wait_until(|| {
+            let mut queue = pool.queue.lock();
+            work = queue.pop_front();
+            if let Some(work) = &work {
+                // Under the lock, for `cancel` not to find it gone but pending.
+                work.running.fetch_add(1, Ordering::AcqRel);
+                work.state.fetch_and(!PENDING, Ordering::AcqRel);
+            }
+            work.is_some()
+        });
+        let work = work.unwrap();
+        (work.func)();
+        work.running.fetch_sub(1, Ordering::AcqRel);
+        work.done.notify_all(false);
+    }
+}
+
+/// Spawns the worker task of the current CPU.
+pub(crate) fn init_percpu() {
+    let cpu = this_cpu_id();
+    let worker = TaskInner::new(
+        move || worker_entry(cpu),
+        alloc::format!("kworker/{cpu}"),
+        WORKER_STACK_SIZE,
+    );
+    worker.set_cpumask(KCpuMask::one_shot(cpu));
+    crate::spawn_task(worker);
+}
@@ -0,0 +1,327 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! An in-memory block device for tests, with configurable latency and
+//! injected I/O errors.
+//!
+//! The disk keeps an [`Arc<DiskFaults>`] that the test holds on to after
+//! handing the disk to the code under test, so errors can be injected
+//! mid-run.
+
+extern crate alloc;
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use driver_base::{DeviceKind, DriverError, DriverOps, DriverResult};
+
+use crate::BlockDriverOps;
+
+/// Blocks no fault is tied to.
+const NO_BLOCK: u64 = u64::MAX;
+
+/// Latency, injected errors and operation counts of a [`FakeDisk`].
+#[derive(Debug)]
+pub struct DiskFaults {
+    latency_ns: AtomicU64,
+    /// Number of upcoming operations to fail.
+    fail_next: AtomicU64,
+    /// Block whose reads and writes fail, [`NO_BLOCK`] if none.
+    bad_block: AtomicU64,
+    reads: AtomicU64,
+    writes: AtomicU64,
+    flushes: AtomicU64,
+    discards: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl Default for DiskFaults {
+    fn default() -> Self {
+        Self {
+            latency_ns: AtomicU64::new(0),
+            fail_next: AtomicU64::new(0),
+            bad_block: AtomicU64::new(NO_BLOCK),
+            reads: AtomicU64::new(0),
+            writes: AtomicU64::new(0),
+            flushes: AtomicU64::new(0),
+            discards: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        }
+    }
+}
+
+impl DiskFaults {
+    /// Delays every operation by `latency`.
+    pub fn set_latency(&self, latency: Duration) {
+        self.latency_ns
+            .store(latency.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Fails the next `count` operations with [`DriverError::Io`].
+    pub fn fail_next(&self, count: u64) {
+        self.fail_next.store(count, Ordering::Relaxed);
+    }
+
+    /// Fails reads and writes covering `block` with [`DriverError::Io`], or
+    /// none with `None`.
+    pub fn set_bad_block(&self, block: Option<u64>) {
+        self.bad_block
+            .store(block.unwrap_or(NO_BLOCK), Ordering::Relaxed);
+    }
+
+    /// Number of reads, successful or not.
+    pub fn reads(&self) -> u64 {
+        self.reads.load(Ordering::Relaxed)
+    }
+
+    /// Number of writes, successful or not.
+    pub fn writes(&self) -> u64 {
+        self.writes.load(Ordering::Relaxed)
+    }
+
+    /// Number of flushes, successful or not.
+    pub fn flushes(&self) -> u64 {
+        self.flushes.load(Ordering::Relaxed)
+    }
+
+    /// Number of discards, successful or not.
+    pub fn discards(&self) -> u64 {
+        self.discards.load(Ordering::Relaxed)
+    }
+
+    /// Number of operations failed by injection.
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Decides whether an operation on `blocks` fails.
+    fn inject(&self, blocks: Option<(u64, u64)>) -> DriverResult {
+        let scheduled = self
+            .fail_next
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok();
+        let bad_block = self.bad_block.load(Ordering::Relaxed);
+        let bad = blocks.is_some_and(|(start, count)| (start..start + count).contains(&bad_block));
+        if scheduled || bad {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+            Err(DriverError::Io)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A RAM disk for tests.
+pub struct FakeDisk {
+    data: Vec<u8>,
+    block_size: usize,
+    faults: Arc<DiskFaults>,
+    delay: Option<fn(Duration)>,
+}
+
+impl FakeDisk {
+    /// Creates a zeroed disk of `num_blocks` blocks of `block_size` bytes.
+    pub fn new(num_blocks: u64, block_size: usize) -> Self {
+        Self {
+            data: vec![0; num_blocks as usize * block_size],
+            block_size,
+            faults: Arc::default(),
+            delay: None,
+        }
+    }
+
+    /// Creates a disk holding `image`, padded to whole blocks.
+    pub fn from_image(image: &[u8], block_size: usize) -> Self {
+        let mut disk = Self::new(image.len().div_ceil(block_size) as u64, block_size);
+        disk.data[..image.len()].copy_from_slice(image);
+        disk
+    }
+
+    /// Sets the function waiting out the latency set by
+    /// [`DiskFaults::set_latency`]; without one, latency is ignored.
+    pub fn with_delay(mut self, delay: fn(Duration)) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Returns the fault controls of the disk.
+    pub fn faults(&self) -> Arc<DiskFaults> {
+        self.faults.clone()
+    }
+
+    fn wait(&self) {
+        let latency = self.faults.latency_ns.load(Ordering::Relaxed);
+        if let Some(delay) = self.delay
+            && latency > 0
+        {
+            delay(Duration::from_nanos(latency));
+        }
+    }
+
+    /// Returns the byte range of `len` bytes from `block_id`.
+    fn range(&self, block_id: u64, len: usize) -> DriverResult<Range<usize>> {
+        if !len.is_multiple_of(self.block_size) {
+            return Err(DriverError::InvalidInput);
+        }
+        let start = (block_id as usize)
+            .checked_mul(self.block_size)
+            .ok_or(DriverError::Io)?;
+        let end = start.checked_add(len).ok_or(DriverError::Io)?;
+        if end > self.data.len() {
+            return Err(DriverError::Io);
+        }
+        Ok(start..end)
+    }
+}
+
+impl DriverOps for FakeDisk {
+    fn device_kind(&self) -> DeviceKind {
+        DeviceKind::Block
+    }
+
+    fn name(&self) -> &str {
+        "fake-disk"
+    }
+}
+
+impl BlockDriverOps for FakeDisk {
+    fn num_blocks(&self) -> u64 {
+        (self.data.len() / self.block_size) as u64
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DriverResult {
+        self.faults.reads.fetch_add(1, Ordering::Relaxed);
+        self.wait();
+        let range = self.range(block_id, buf.len())?;
+        let count = (buf.len() / self.block_size) as u64;
+        self.faults.inject(Some((block_id, count)))?;
+        buf.copy_from_slice(&self.data[range]);
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DriverResult {
+        self.faults.writes.fetch_add(1, Ordering::Relaxed);
+        self.wait();
+        let range = self.range(block_id, buf.len())?;
+        let count = (buf.len() / self.block_size) as u64;
+        self.faults.inject(Some((block_id, count)))?;
+        self.data[range].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> DriverResult {
+        self.faults.flushes.fetch_add(1, Ordering::Relaxed);
+        self.wait();
+        self.faults.inject(None)
+    }
+
+    /// Zeroes the blocks, as thin-provisioned storage reads them back.
+    fn discard(&mut self, block_id: u64, num_blocks: u64) -> DriverResult {
+        self.faults.discards.fetch_add(1, Ordering::Relaxed);
+        self.wait();
+        let len = (num_blocks as usize)
+            .checked_mul(self.block_size)
+            .ok_or(DriverError::Io)?;
+        let range = self.range(block_id, len)?;
+        self.faults.inject(Some((block_id, num_blocks)))?;
+        self.data[range].fill(0);
+        Ok(())
+    }
+}
+
+#[cfg(unittest)]
+mod tests_fake {
+    use unittest::{assert, assert_eq, def_test};
+
+    use super::*;
+
+    #[def_test]
+    fn test_fake_disk_io() {
+        let mut disk = FakeDisk::from_image(&[0xAB; 1000], 512);
+        assert_eq!(disk.num_blocks(), 2);
+        assert_eq!(disk.block_size(), 512);
+
+        let mut buf = vec![0; 1024];
+        assert!(disk.read_block(0, &mut buf).is_ok());
+        assert!(buf[..1000].iter().all(|&b| b == 0xAB));
+        assert!(buf[1000..].iter().all(|&b| b == 0));
+
+        assert!(disk.write_block(1, &[0x55; 512]).is_ok());
+        assert!(disk.read_block(1, &mut buf[..512]).is_ok());
+        assert_eq!(&buf[..512], &[0x55; 512]);
+
+        assert!(matches!(
+            disk.read_block(2, &mut buf[..512]),
+            Err(DriverError::Io)
+        ));
+        assert!(matches!(
+            disk.read_block(0, &mut buf[..100]),
+            Err(DriverError::InvalidInput)
+        ));
+        let faults = disk.faults();
+        assert_eq!(faults.reads(), 4);
+        assert_eq!(faults.writes(), 1);
+        assert_eq!(faults.errors(), 0);
+    }
+
+    #[def_test]
+    fn test_fake_disk_faults() {
+        let mut disk = FakeDisk::new(8, 512);
+        let faults = disk.faults();
+        let mut buf = vec![0; 512];
+
+        faults.fail_next(2);
+        assert!(matches!(disk.read_block(0, &mut buf), Err(DriverError::Io)));
+        assert!(matches!(disk.flush(), Err(DriverError::Io)));
+        assert!(disk.read_block(0, &mut buf).is_ok());
+
+        // Failed writes leave the disk unchanged
+        faults.set_bad_block(Some(5));
+        assert!(matches!(
+            disk.write_block(4, &[1; 1024]),
+            Err(DriverError::Io)
+        ));
+        assert!(disk.read_block(4, &mut buf).is_ok());
+        assert_eq!(buf, vec![0; 512]);
+        assert!(disk.write_block(6, &[1; 512]).is_ok());
+        faults.set_bad_block(None);
+        assert!(disk.read_block(5, &mut buf).is_ok());
+        assert_eq!(faults.errors(), 3);
+    }
+
+    #[def_test]
+    fn test_fake_disk_discard() {
+        let mut disk = FakeDisk::from_image(&[0xAB; 2048], 512);
+        assert!(disk.discard(1, 2).is_ok());
+        let mut buf = vec![0; 2048];
+        assert!(disk.read_block(0, &mut buf).is_ok());
+        assert!(buf[..512].iter().all(|&b| b == 0xAB));
+        assert!(buf[512..1536].iter().all(|&b| b == 0));
+        assert!(buf[1536..].iter().all(|&b| b == 0xAB));
+        assert!(matches!(disk.discard(3, 2), Err(DriverError::Io)));
+        assert_eq!(disk.faults().discards(), 2);
+    }
+
+    #[def_test]
+    fn test_fake_disk_latency() {
+        static WAITED_NS: AtomicU64 = AtomicU64::new(0);
+        fn delay(dur: Duration) {
+            WAITED_NS.fetch_add(dur.as_nanos() as u64, Ordering::Relaxed);
+        }
+
+        let mut disk = FakeDisk::new(1, 512).with_delay(delay);
+        disk.faults().set_latency(Duration::from_micros(3));
+        assert!(disk.flush().is_ok());
+        assert!(disk.write_block(0, &[0; 512]).is_ok());
+        assert_eq!(WAITED_NS.load(Ordering::Relaxed), 6000);
+    }
+}
@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Asynchronous block requests.
+//!
+//! A [`BlockIo`] is [submitted](crate::BlockDriverOps::submit) to a device,
+//! which owns its buffer until the request completes, and taken back with
+//! [`poll_complete`](crate::BlockDriverOps::poll_complete) once the device
+//! interrupts.
+//!
+//! Requests carry the [`IoPriority`] of the task issuing them, which devices
+//! ordering their requests honor.
+
+extern crate alloc;
+
+use alloc::{vec, vec::Vec};
+
+/// The direction of a [`BlockIo`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockOp {
+    Read,
+    Write,
+}
+
+/// The scheduling class of an [`IoPriority`], as of `ioprio_set(2)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IoClass {
+    /// Served before any other class.
+    RealTime,
+    /// Served in turn with the others of the class.
+    BestEffort,
+    /// Served only when no other class is waiting, or once it waited long.
+    Idle,
+}
+
+/// The priority of a block request: its class, then its level within the
+/// class, from 0 highest to 7 lowest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct IoPriority {
+    pub class: IoClass,
+    pub level: u8,
+}
+
+impl IoPriority {
+    /// The number of levels in a class.
+    pub const LEVELS: u8 = 8;
+    /// The priority of tasks that didn't set one, that of Linux for nice 0.
+    pub const DEFAULT: Self = Self::new(IoClass::BestEffort, 4);
+
+    /// Creates a priority of `class` at `level`, clamped to the lowest.
+    pub const fn new(class: IoClass, level: u8) -> Self {
+        let level = if level < Self::LEVELS {
+            level
+        } else {
+            Self::LEVELS - 1
+        };
+        Self { class, level }
+    }
+
+    /// Decodes a priority as passed to `ioprio_set(2)`: the class in the top
+    /// 3 bits, the level in the low ones.
+    ///
+    /// No class stands for [`DEFAULT`](Self::DEFAULT). Returns `None` for an
+    /// unknown class or a level out of range.
+    pub const fn from_raw(raw: u16) -> Option<Self> {
+        let level = (raw & 0x1fff) as u8;
+        let class = match raw >> 13 {
+            0 => return Some(Self::DEFAULT),
+            1 => IoClass::RealTime,
+            2 => IoClass::BestEffort,
+            3 => IoClass::Idle,
+            _ => return None,
+        };
+        if raw & 0x1fff >= Self::LEVELS as u16 {
+            return None;
+        }
+        Some(Self::new(class, level))
+    }
+
+    /// Encodes the priority as returned by `ioprio_get(2)`.
+    pub const fn to_raw(self) -> u16 {
+        let class = match self.class {
+            IoClass::RealTime => 1,
+            IoClass::BestEffort => 2,
+            IoClass::Idle => 3,
+        };
+        (class << 13) | self.level as u16
+    }
+}
+
+impl Default for IoPriority {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A request to a block device, owning the data read or written.
+#[derive(Debug)]
+pub struct BlockIo {
+    pub op: BlockOp,
+    /// The first block read or written.
+    pub block_id: u64,
+    /// A whole number of blocks.
+    pub buf: Vec<u8>,
+    pub priority: IoPriority,
+}
+
+impl BlockIo {
+    /// Creates a request reading `len` bytes from `block_id` on.
+    pub fn read(block_id: u64, len: usize) -> Self {
+        Self {
+            op: BlockOp::Read,
+            block_id,
+            buf: vec![0; len],
+            priority: IoPriority::DEFAULT,
+        }
+    }
+
+    /// Creates a request writing `buf` from `block_id` on.
+    pub fn write(block_id: u64, buf: Vec<u8>) -> Self {
+        Self {
+            op: BlockOp::Write,
+            block_id,
+            buf,
+            priority: IoPriority::DEFAULT,
+        }
+    }
+
+    /// Sets the priority of the request.
+    pub fn with_priority(mut self, priority: IoPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+/// Identifies a request submitted to a device, unique for the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IoToken(pub u64);
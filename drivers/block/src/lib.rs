@@ -10,8 +10,15 @@
 // #[cfg(feature = "bcm2835-sdhci")]
 // pub mod bcm2835sdhci;
 
-// #[cfg(feature = "ramdisk")]
-// pub mod ramdisk;
+#[cfg(feature = "fake")]
+pub mod fake;
+
+mod io;
+mod partition;
+mod queue;
+
+#[cfg(feature = "ramdisk")]
+pub mod ramdisk;
 
 // #[cfg(feature = "ramdisk-static")]
 // pub mod ramdisk_static;
@@ -21,8 +28,16 @@
 // #[cfg(feature = "sdmmc")]
 // pub mod sdmmc;
 
+use core::time::Duration;
+
 #[doc(no_inline)]
-pub use driver_base::{DeviceKind, DriverError, DriverOps, DriverResult};
+pub use driver_base::{DeviceKind, DriverError, DriverOps, DriverResult, IoPolicy};
+
+pub use self::{
+    io::{BlockIo, BlockOp, IoClass, IoPriority, IoToken},
+    partition::{Partition, PartitionInfo, PartitionType, scan_partitions},
+    queue::{Deadline, Elevator, Noop, QueueStats, Request, RequestQueue},
+};
 
 /// Operations that require a block storage device driver to implement.
 pub trait BlockDriverOps: DriverOps {
@@ -45,6 +60,59 @@ pub trait BlockDriverOps: DriverOps {
     /// contiguous blocks will be written.
     fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DriverResult;
 
+    /// Writes like [`write_block`](Self::write_block), for a task of the
+    /// given I/O priority.
+    ///
+    /// Devices ordering their requests, like a [`RequestQueue`], honor the
+    /// priority; the others write right away.
+    fn write_block_at(&mut self, block_id: u64, buf: &[u8], priority: IoPriority) -> DriverResult {
+        let _ = priority;
+        self.write_block(block_id, buf)
+    }
+
     /// Flushes the device to write all pending data to the storage.
     fn flush(&mut self) -> DriverResult;
+
+    /// Tells the device that the `num_blocks` blocks from `block_id` on hold
+    /// no data worth keeping, so it may release their storage (TRIM).
+    ///
+    /// Reading them afterwards returns unspecified data. Fails with
+    /// [`DriverError::Unsupported`] if the device can't discard, which
+    /// callers may ignore: discarding is only a hint.
+    fn discard(&mut self, block_id: u64, num_blocks: u64) -> DriverResult {
+        let _ = (block_id, num_blocks);
+        Err(DriverError::Unsupported)
+    }
+
+    /// Sets how long a request may take before failing with
+    /// [`DriverError::TimedOut`], `None` to wait for good.
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> DriverResult {
+        let _ = timeout;
+        Err(DriverError::Unsupported)
+    }
+
+    /// Submits `io` without waiting for it to complete, returning the token
+    /// to [`poll_complete`](Self::poll_complete) it with.
+    ///
+    /// Fails with [`DriverError::WouldBlock`] while the device has no room
+    /// for more requests, and [`DriverError::Unsupported`] if the driver only
+    /// does synchronous I/O.
+    fn submit(&mut self, io: BlockIo) -> DriverResult<IoToken> {
+        let _ = io;
+        Err(DriverError::Unsupported)
+    }
+
+    /// Takes back the request `token` once it completed, failing with
+    /// [`DriverError::WouldBlock`] while it is in flight.
+    ///
+    /// The interrupt of the device is acknowledged.
+    fn poll_complete(&mut self, token: IoToken) -> DriverResult<BlockIo> {
+        let _ = token;
+        Err(DriverError::InvalidInput)
+    }
+
+    /// Enables or disables the interrupt raised by completed requests.
+    fn enable_interrupt(&mut self, enable: bool) {
+        let _ = enable;
+    }
 }
@@ -0,0 +1,656 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! GPT and MBR partition tables.
+//!
+//! [`scan_partitions`] reads the partition table of a disk, and a
+//! [`Partition`] is a block device covering one partition, translating block
+//! numbers to the disk's. A disk is wrapped as a whole with
+//! [`Partition::whole_disk`] first; its partitions share it behind a lock.
+//!
+//! A disk with a protective MBR is read as GPT, from the primary header or,
+//! if that is damaged, from the backup at the end of the disk. Otherwise the
+//! MBR's primary partitions are numbered 1 to 4 and the logical partitions
+//! in an extended one from 5, like Linux does.
+
+extern crate alloc;
+
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use core::time::Duration;
+
+use driver_base::{DeviceKind, DriverError, DriverOps, DriverResult};
+use spin::{Mutex, MutexGuard};
+
+use crate::{BlockDriverOps, BlockIo, IoPriority, IoToken};
+
+/// Size of an MBR or EBR.
+const MBR_SIZE: usize = 512;
+/// MBR type of the partition spanning a GPT disk.
+const MBR_TYPE_GPT: u8 = 0xee;
+/// MBR types of extended partitions.
+const MBR_TYPES_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+/// Maximum number of logical partitions followed in an extended partition.
+const MAX_LOGICAL: usize = 128;
+/// Maximum number of GPT entries read.
+const MAX_GPT_ENTRIES: u32 = 1024;
+
+/// The type of a partition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionType {
+    /// An MBR partition type, e.g. `0x83` for Linux.
+    Mbr(u8),
+    /// A GPT partition type GUID, in its on-disk (mixed-endian) layout.
+    Gpt([u8; 16]),
+}
+
+impl PartitionType {
+    /// The GPT type of Linux filesystems.
+    pub const GPT_LINUX: Self = Self::Gpt(guid(0x0fc63daf, 0x8483, 0x4772, 0x8e79_3d69d8477de4));
+    /// The GPT type of Windows basic data, i.e. FAT or NTFS, partitions.
+    pub const GPT_BASIC_DATA: Self =
+        Self::Gpt(guid(0xebd0a0a2, 0xb9e5, 0x4433, 0x87c0_68b6b72699c7));
+    /// The GPT type of EFI system partitions, formatted with FAT.
+    pub const GPT_EFI_SYSTEM: Self =
+        Self::Gpt(guid(0xc12a7328, 0xf81f, 0x11d2, 0xba4b_00a0c93ec93b));
+
+    /// Whether partitions of this type usually hold a Linux filesystem,
+    /// e.g. ext4.
+    pub fn is_linux(&self) -> bool {
+        matches!(self, Self::Mbr(0x83)) || *self == Self::GPT_LINUX
+    }
+
+    /// Whether partitions of this type usually hold a FAT filesystem.
+    pub fn is_fat(&self) -> bool {
+        matches!(
+            self,
+            Self::Mbr(0x01 | 0x04 | 0x06 | 0x0b | 0x0c | 0x0e | 0xef)
+        ) || *self == Self::GPT_BASIC_DATA
+            || *self == Self::GPT_EFI_SYSTEM
+    }
+}
+
+/// Lays out a GUID given in its textual groups the way GPT stores it.
+const fn guid(a: u32, b: u16, c: u16, d: u64) -> [u8; 16] {
+    let a = a.to_le_bytes();
+    let b = b.to_le_bytes();
+    let c = c.to_le_bytes();
+    // The last two groups are stored as they read
+    let d = d.to_be_bytes();
+    [
+        a[0], a[1], a[2], a[3], b[0], b[1], c[0], c[1], d[0], d[1], d[2], d[3], d[4], d[5], d[6],
+        d[7],
+    ]
+}
+
+/// A partition found by [`scan_partitions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionInfo {
+    /// Number of the partition, from 1, as in `vda1`.
+    pub number: usize,
+    /// First block of the partition on the disk.
+    pub start: u64,
+    /// Size of the partition in blocks.
+    pub num_blocks: u64,
+    pub partition_type: PartitionType,
+    /// The GPT partition name, empty for MBR partitions.
+    pub name: String,
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+/// CRC-32 (IEEE) as used by GPT.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn read_blocks<D: BlockDriverOps>(
+    dev: &mut D,
+    block_id: u64,
+    count: usize,
+) -> DriverResult<Vec<u8>> {
+    let mut buf = vec![0; count * dev.block_size()];
+    dev.read_block(block_id, &mut buf)?;
+    Ok(buf)
+}
+
+/// Reads the partition table of `dev`.
+///
+/// Returns no partitions if the disk has no partition table, or if the
+/// table is damaged.
+pub fn scan_partitions<D: BlockDriverOps>(dev: &mut D) -> DriverResult<Vec<PartitionInfo>> {
+    if dev.block_size() < MBR_SIZE || dev.num_blocks() == 0 {
+        return Ok(Vec::new());
+    }
+    let mbr = read_blocks(dev, 0, 1)?;
+    if mbr[510..512] != [0x55, 0xaa] {
+        return Ok(Vec::new());
+    }
+    let entries = mbr_entries(&mbr);
+    if entries.iter().any(|entry| entry.ty == MBR_TYPE_GPT) {
+        let num_blocks = dev.num_blocks();
+        for header_lba in [1, num_blocks - 1] {
+            if let Some(partitions) = scan_gpt(dev, header_lba)? {
+                return Ok(partitions);
+            }
+            log::warn!("GPT header at block {} is damaged", header_lba);
+        }
+        return Ok(Vec::new());
+    }
+    scan_mbr(dev, &entries)
+}
+
+/// A used entry of an MBR or EBR.
+struct MbrEntry {
+    index: usize,
+    ty: u8,
+    /// First sector, relative to a base that depends on the table.
+    start: u64,
+    len: u64,
+}
+
+fn mbr_entries(mbr: &[u8]) -> Vec<MbrEntry> {
+    (0..4)
+        .map(|index| {
+            let entry = &mbr[446 + index * 16..446 + (index + 1) * 16];
+            MbrEntry {
+                index,
+                ty: entry[4],
+                start: read_u32(entry, 8) as u64,
+                len: read_u32(entry, 12) as u64,
+            }
+        })
+        .filter(|entry| entry.ty != 0 && entry.len != 0)
+        .collect()
+}
+
+fn scan_mbr<D: BlockDriverOps>(
+    dev: &mut D,
+    entries: &[MbrEntry],
+) -> DriverResult<Vec<PartitionInfo>> {
+    let num_blocks = dev.num_blocks();
+    let in_disk =
+        |start: u64, len: u64| start.checked_add(len).is_some_and(|end| end <= num_blocks);
+    let mut partitions = Vec::new();
+    let mut logical = 5;
+    for entry in entries {
+        if !in_disk(entry.start, entry.len) {
+            log::warn!("MBR partition {} lies beyond the disk", entry.index + 1);
+            continue;
+        }
+        if !MBR_TYPES_EXTENDED.contains(&entry.ty) {
+            partitions.push(PartitionInfo {
+                number: entry.index + 1,
+                start: entry.start,
+                num_blocks: entry.len,
+                partition_type: PartitionType::Mbr(entry.ty),
+                name: String::new(),
+            });
+            continue;
+        }
+        // A chain of EBRs, each with a logical partition relative to itself
+        // and a link to the next relative to the extended partition
+        let mut ebr_lba = entry.start;
+        for _ in 0..MAX_LOGICAL {
+            let ebr = read_blocks(dev, ebr_lba, 1)?;
+            if ebr[510..512] != [0x55, 0xaa] {
+                break;
+            }
+            let ebr_entries = mbr_entries(&ebr);
+            if let Some(logical_entry) = ebr_entries.iter().find(|it| it.index == 0)
+                && in_disk(ebr_lba + logical_entry.start, logical_entry.len)
+            {
+                partitions.push(PartitionInfo {
+                    number: logical,
+                    start: ebr_lba + logical_entry.start,
+                    num_blocks: logical_entry.len,
+                    partition_type: PartitionType::Mbr(logical_entry.ty),
+                    name: String::new(),
+                });
+                logical += 1;
+            }
+            // Links only point forward, which also rules out loops
+            match ebr_entries.iter().find(|it| it.index == 1) {
+                Some(link) if entry.start + link.start > ebr_lba => {
+                    ebr_lba = entry.start + link.start;
+                }
+                _ => break,
+            }
+        }
+    }
+    Ok(partitions)
+}
+
+/// Reads the GPT whose header is at `header_lba`, returning `None` if the
+/// header or the entries are damaged.
+fn scan_gpt<D: BlockDriverOps>(
+    dev: &mut D,
+    header_lba: u64,
+) -> DriverResult<Option<Vec<PartitionInfo>>> {
+    let block_size = dev.block_size();
+    let num_blocks = dev.num_blocks();
+    let mut header = read_blocks(dev, header_lba, 1)?;
+    let header_size = read_u32(&header, 12) as usize;
+    if &header[0..8] != b"EFI PART" || !(92..=block_size).contains(&header_size) {
+        return Ok(None);
+    }
+    let header_crc = read_u32(&header, 16);
+    header[16..20].fill(0);
+    if crc32(&header[..header_size]) != header_crc {
+        return Ok(None);
+    }
+
+    let first_usable = read_u64(&header, 40);
+    let last_usable = read_u64(&header, 48);
+    let entries_lba = read_u64(&header, 72);
+    let num_entries = read_u32(&header, 80);
+    let entry_size = read_u32(&header, 84) as usize;
+    let entries_crc = read_u32(&header, 88);
+    if num_entries > MAX_GPT_ENTRIES || entry_size < 128 || !entry_size.is_power_of_two() {
+        return Ok(None);
+    }
+    let entries_len = num_entries as usize * entry_size;
+    let entries_blocks = entries_len.div_ceil(block_size);
+    if entries_lba
+        .checked_add(entries_blocks as u64)
+        .is_none_or(|end| end > num_blocks)
+    {
+        return Ok(None);
+    }
+    let entries = read_blocks(dev, entries_lba, entries_blocks)?;
+    if crc32(&entries[..entries_len]) != entries_crc {
+        return Ok(None);
+    }
+
+    let mut partitions = Vec::new();
+    for (i, entry) in entries[..entries_len].chunks_exact(entry_size).enumerate() {
+        let type_guid: [u8; 16] = entry[0..16].try_into().unwrap();
+        if type_guid == [0; 16] {
+            continue;
+        }
+        let first = read_u64(entry, 32);
+        let last = read_u64(entry, 40);
+        if first < first_usable || last > last_usable || first > last {
+            log::warn!("GPT partition {} lies beyond the usable blocks", i + 1);
+            continue;
+        }
+        let name = char::decode_utf16((0..36).map(|j| read_u16(entry, 56 + j * 2)))
+            .map_while(|c| c.ok().filter(|&c| c != '\0'))
+            .collect();
+        partitions.push(PartitionInfo {
+            number: i + 1,
+            start: first,
+            num_blocks: last - first + 1,
+            partition_type: PartitionType::Gpt(type_guid),
+            name,
+        });
+    }
+    Ok(Some(partitions))
+}
+
+/// A block device covering a range of blocks of a disk.
+pub struct Partition<D> {
+    disk: Arc<Mutex<D>>,
+    name: String,
+    start: u64,
+    num_blocks: u64,
+    block_size: usize,
+}
+
+impl<D: BlockDriverOps> Partition<D> {
+    /// Creates the device `name` covering all of `disk`.
+    pub fn whole_disk(disk: D, name: String) -> Self {
+        Self {
+            num_blocks: disk.num_blocks(),
+            block_size: disk.block_size(),
+            disk: Arc::new(Mutex::new(disk)),
+            name,
+            start: 0,
+        }
+    }
+
+    /// Creates the device `name` for the partition `info` found on this
+    /// device, sharing the disk.
+    pub fn partition(&self, name: String, info: &PartitionInfo) -> Self {
+        Self {
+            disk: self.disk.clone(),
+            name,
+            start: self.start + info.start,
+            num_blocks: info.num_blocks,
+            block_size: self.block_size,
+        }
+    }
+
+    /// The first block of the partition on the disk.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Locks the disk shared by the partitions.
+    pub fn disk(&self) -> MutexGuard<'_, D> {
+        self.disk.lock()
+    }
+
+    /// Translates `block_id` to the disk, checking that the `len` bytes
+    /// from there lie within the partition.
+    fn translate(&self, block_id: u64, len: usize) -> DriverResult<u64> {
+        let blocks = len.div_ceil(self.block_size) as u64;
+        match block_id.checked_add(blocks) {
+            Some(end) if end <= self.num_blocks => Ok(self.start + block_id),
+            _ => Err(DriverError::Io),
+        }
+    }
+}
+
+/// Another handle to the same range of the disk.
+impl<D> Clone for Partition<D> {
+    fn clone(&self) -> Self {
+        Self {
+            disk: self.disk.clone(),
+            name: self.name.clone(),
+            start: self.start,
+            num_blocks: self.num_blocks,
+            block_size: self.block_size,
+        }
+    }
+}
+
+impl<D: BlockDriverOps> DriverOps for Partition<D> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn device_kind(&self) -> DeviceKind {
+        DeviceKind::Block
+    }
+
+    fn irq(&self) -> Option<usize> {
+        self.disk.lock().irq()
+    }
+
+    /// Resets the whole disk.
+    fn reset(&mut self) -> DriverResult {
+        self.disk.lock().reset()
+    }
+}
+
+impl<D: BlockDriverOps> BlockDriverOps for Partition<D> {
+    fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DriverResult {
+        let block_id = self.translate(block_id, buf.len())?;
+        self.disk.lock().read_block(block_id, buf)
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DriverResult {
+        let block_id = self.translate(block_id, buf.len())?;
+        self.disk.lock().write_block(block_id, buf)
+    }
+
+    fn write_block_at(&mut self, block_id: u64, buf: &[u8], priority: IoPriority) -> DriverResult {
+        let block_id = self.translate(block_id, buf.len())?;
+        self.disk.lock().write_block_at(block_id, buf, priority)
+    }
+
+    fn flush(&mut self) -> DriverResult {
+        self.disk.lock().flush()
+    }
+
+    fn discard(&mut self, block_id: u64, num_blocks: u64) -> DriverResult {
+        let len = (num_blocks as usize)
+            .checked_mul(self.block_size)
+            .ok_or(DriverError::Io)?;
+        let block_id = self.translate(block_id, len)?;
+        self.disk.lock().discard(block_id, num_blocks)
+    }
+
+    /// Sets the timeout of the whole disk.
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> DriverResult {
+        self.disk.lock().set_timeout(timeout)
+    }
+
+    fn submit(&mut self, mut io: BlockIo) -> DriverResult<IoToken> {
+        io.block_id = self.translate(io.block_id, io.buf.len())?;
+        self.disk.lock().submit(io)
+    }
+
+    /// Takes back the request `token`, which must have been submitted through
+    /// this partition.
+    fn poll_complete(&mut self, token: IoToken) -> DriverResult<BlockIo> {
+        let mut io = self.disk.lock().poll_complete(token)?;
+        io.block_id -= self.start;
+        Ok(io)
+    }
+
+    fn enable_interrupt(&mut self, enable: bool) {
+        self.disk.lock().enable_interrupt(enable);
+    }
+}
+
+#[cfg(unittest)]
+mod tests_partition {
+    use unittest::{assert, assert_eq, def_test};
+
+    use super::*;
+
+    const BLOCK_SIZE: usize = 512;
+
+    struct MemDisk(Vec<u8>);
+
+    impl MemDisk {
+        fn new(num_blocks: usize) -> Self {
+            Self(vec![0; num_blocks * BLOCK_SIZE])
+        }
+
+        fn block(&mut self, block_id: u64) -> &mut [u8] {
+            let start = block_id as usize * BLOCK_SIZE;
+            &mut self.0[start..start + BLOCK_SIZE]
+        }
+
+        /// Writes the MBR or EBR entry `index` at `block_id`.
+        fn mbr_entry(&mut self, block_id: u64, index: usize, ty: u8, start: u32, len: u32) {
+            let block = self.block(block_id);
+            let entry = &mut block[446 + index * 16..446 + (index + 1) * 16];
+            entry[4] = ty;
+            entry[8..12].copy_from_slice(&start.to_le_bytes());
+            entry[12..16].copy_from_slice(&len.to_le_bytes());
+            block[510..512].copy_from_slice(&[0x55, 0xaa]);
+        }
+
+        /// Writes a GPT header at `header_lba` with its entries at
+        /// `entries_lba`, listing `partitions` as (type, first, last, name).
+        fn gpt(
+            &mut self,
+            header_lba: u64,
+            entries_lba: u64,
+            partitions: &[(PartitionType, u64, u64, &str)],
+        ) {
+            const NUM_ENTRIES: usize = 4;
+            let mut entries = vec![0u8; NUM_ENTRIES * 128];
+            for (entry, (ty, first, last, name)) in entries.chunks_exact_mut(128).zip(partitions) {
+                let PartitionType::Gpt(guid) = ty else {
+                    panic!("not a GPT type");
+                };
+                entry[0..16].copy_from_slice(guid);
+                entry[32..40].copy_from_slice(&first.to_le_bytes());
+                entry[40..48].copy_from_slice(&last.to_le_bytes());
+                for (j, unit) in name.encode_utf16().enumerate() {
+                    entry[56 + j * 2..58 + j * 2].copy_from_slice(&unit.to_le_bytes());
+                }
+            }
+            let num_blocks = (self.0.len() / BLOCK_SIZE) as u64;
+            let mut header = vec![0u8; 92];
+            header[0..8].copy_from_slice(b"EFI PART");
+            header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+            header[12..16].copy_from_slice(&92u32.to_le_bytes());
+            header[40..48].copy_from_slice(&34u64.to_le_bytes());
+            header[48..56].copy_from_slice(&(num_blocks - 34).to_le_bytes());
+            header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+            header[80..84].copy_from_slice(&(NUM_ENTRIES as u32).to_le_bytes());
+            header[84..88].copy_from_slice(&128u32.to_le_bytes());
+            header[88..92].copy_from_slice(&crc32(&entries).to_le_bytes());
+            let crc = crc32(&header);
+            header[16..20].copy_from_slice(&crc.to_le_bytes());
+            self.block(header_lba)[..92].copy_from_slice(&header);
+            self.block(entries_lba)[..entries.len()].copy_from_slice(&entries);
+        }
+    }
+
+    impl DriverOps for MemDisk {
+        fn name(&self) -> &str {
+            "mem"
+        }
+
+        fn device_kind(&self) -> DeviceKind {
+            DeviceKind::Block
+        }
+    }
+
+    impl BlockDriverOps for MemDisk {
+        fn num_blocks(&self) -> u64 {
+            (self.0.len() / BLOCK_SIZE) as u64
+        }
+
+        fn block_size(&self) -> usize {
+            BLOCK_SIZE
+        }
+
+        fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DriverResult {
+            let start = block_id as usize * BLOCK_SIZE;
+            let data = self
+                .0
+                .get(start..start + buf.len())
+                .ok_or(DriverError::Io)?;
+            buf.copy_from_slice(data);
+            Ok(())
+        }
+
+        fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DriverResult {
+            let start = block_id as usize * BLOCK_SIZE;
+            let data = self
+                .0
+                .get_mut(start..start + buf.len())
+                .ok_or(DriverError::Io)?;
+            data.copy_from_slice(buf);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> DriverResult {
+            Ok(())
+        }
+    }
+
+    #[def_test]
+    fn test_scan_mbr_with_logical_partitions() {
+        let mut disk = MemDisk::new(256);
+        disk.mbr_entry(0, 0, 0x83, 8, 64);
+        disk.mbr_entry(0, 1, 0x05, 128, 128);
+        // Two logical partitions, the EBRs right before them
+        disk.mbr_entry(128, 0, 0x0c, 1, 31);
+        disk.mbr_entry(128, 1, 0x05, 32, 32);
+        disk.mbr_entry(160, 0, 0x83, 1, 63);
+
+        let partitions = scan_partitions(&mut disk).unwrap();
+        let layout: Vec<_> = partitions
+            .iter()
+            .map(|it| (it.number, it.start, it.num_blocks, it.partition_type))
+            .collect();
+        assert_eq!(
+            layout,
+            [
+                (1, 8, 64, PartitionType::Mbr(0x83)),
+                (5, 129, 31, PartitionType::Mbr(0x0c)),
+                (6, 161, 63, PartitionType::Mbr(0x83)),
+            ]
+        );
+        assert!(partitions[0].partition_type.is_linux());
+        assert!(partitions[1].partition_type.is_fat());
+    }
+
+    #[def_test]
+    fn test_scan_gpt_falls_back_to_backup() {
+        let mut disk = MemDisk::new(256);
+        disk.mbr_entry(0, 0, MBR_TYPE_GPT, 1, 255);
+        let layout = [
+            (PartitionType::GPT_EFI_SYSTEM, 34, 99, "esp"),
+            (PartitionType::GPT_LINUX, 100, 221, "root"),
+        ];
+        disk.gpt(1, 2, &layout);
+        disk.gpt(255, 223, &layout);
+
+        let summary = |disk: &mut MemDisk| {
+            scan_partitions(disk)
+                .unwrap()
+                .into_iter()
+                .map(|it| {
+                    let linux = it.partition_type.is_linux();
+                    (it.number, it.start, it.num_blocks, it.name, linux)
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected = [
+            (1, 34, 66, String::from("esp"), false),
+            (2, 100, 122, String::from("root"), true),
+        ];
+        assert_eq!(summary(&mut disk), expected);
+
+        // A damaged primary header
+        disk.block(1)[20] ^= 0xff;
+        assert_eq!(summary(&mut disk), expected);
+
+        // And no table at all
+        disk.block(0)[510] = 0;
+        assert!(scan_partitions(&mut disk).unwrap().is_empty());
+    }
+
+    #[def_test]
+    fn test_partition_translates_blocks() {
+        let mut disk = MemDisk::new(64);
+        disk.mbr_entry(0, 0, 0x83, 16, 8);
+        let mut whole = Partition::whole_disk(disk, "mem".into());
+        assert_eq!(whole.num_blocks(), 64);
+        let partitions = scan_partitions(&mut whole).unwrap();
+        let mut part = whole.partition("mem1".into(), &partitions[0]);
+        assert_eq!(part.num_blocks(), 8);
+        assert_eq!(part.name(), "mem1");
+
+        part.write_block(1, &[0xaa; BLOCK_SIZE * 2]).unwrap();
+        let mut buf = [0; BLOCK_SIZE];
+        whole.read_block(17, &mut buf).unwrap();
+        assert_eq!(buf, [0xaa; BLOCK_SIZE]);
+        whole.read_block(18, &mut buf).unwrap();
+        assert_eq!(buf, [0xaa; BLOCK_SIZE]);
+        part.read_block(2, &mut buf).unwrap();
+        assert_eq!(buf, [0xaa; BLOCK_SIZE]);
+
+        // Nothing beyond the end of the partition
+        assert!(matches!(part.read_block(8, &mut buf), Err(DriverError::Io)));
+        assert!(matches!(
+            part.write_block(7, &[0; BLOCK_SIZE * 2]),
+            Err(DriverError::Io)
+        ));
+    }
+}
@@ -0,0 +1,979 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Request queue between filesystems and a block driver.
+//!
+//! A [`RequestQueue`] holds writes back, merging the ones to overlapping or
+//! adjacent blocks, and hands them to the driver once it fills up or is
+//! flushed, in the order its [`Elevator`] picks. Reads go to the driver right
+//! away, with the queued writes applied over what it returns.
+//!
+//! Writes are queued at the [`IoPriority`] of the task issuing them, see
+//! [`write_block_at`](BlockDriverOps::write_block_at). The [`Deadline`]
+//! elevator serves the higher classes first, and lets the requests of lower
+//! ones wait longer before they expire.
+//!
+//! Requests to the driver follow the [`IoPolicy`] of the queue: failed ones
+//! are retried, and a device out of retries may go offline.
+//!
+//! [Submitted](BlockDriverOps::submit) writes are queued like the others and
+//! complete right away. Submitted reads go to the driver asynchronously if
+//! it can, and see the writes queued when they were submitted.
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::time::Duration;
+
+use driver_base::{DeviceKind, DriverError, DriverOps, DriverResult, IoPolicy};
+
+use crate::{BlockDriverOps, BlockIo, BlockOp, IoClass, IoPriority, IoToken};
+
+/// Queued requests beyond which some are dispatched.
+const MAX_REQUESTS: usize = 64;
+/// Requests dispatched at once when the queue is full.
+const DISPATCH_BATCH: usize = 16;
+/// Largest request adjacent writes are merged into, in blocks.
+///
+/// Overlapping writes are merged regardless.
+const MAX_MERGE_BLOCKS: u64 = 256;
+
+/// A queued write, as seen by an [`Elevator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Request {
+    /// The first block written.
+    pub start: u64,
+    pub num_blocks: u64,
+    /// When the request was queued, counted in requests queued before.
+    pub queued_at: u64,
+    /// The highest priority of the writes merged into the request.
+    pub priority: IoPriority,
+}
+
+impl Request {
+    /// The block after the last one written.
+    pub fn end(&self) -> u64 {
+        self.start + self.num_blocks
+    }
+}
+
+/// Decides the order queued writes are dispatched in.
+pub trait Elevator: Send + Sync {
+    /// The name of the scheduler.
+    fn name(&self) -> &'static str;
+
+    /// Returns the index of the request in `requests`, sorted by block, to
+    /// dispatch next.
+    ///
+    /// `head` is the block after the last one dispatched, and `now` the
+    /// number of requests queued so far.
+    fn pick(&mut self, requests: &[Request], head: u64, now: u64) -> usize;
+}
+
+/// Dispatches the requests in the order they were queued, whatever their
+/// priority.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Noop;
+
+impl Elevator for Noop {
+    fn name(&self) -> &'static str {
+        "noop"
+    }
+
+    fn pick(&mut self, requests: &[Request], _head: u64, _now: u64) -> usize {
+        let oldest = requests
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, it)| it.queued_at);
+        oldest.map_or(0, |(i, _)| i)
+    }
+}
+
+/// Dispatches the requests of the highest class queued in one sweep across
+/// the disk, except those that waited too long, which go first.
+///
+/// How long a request may wait depends on its priority: a quarter of the
+/// expiry for the real-time class, from half of it to nearly one and a half
+/// across the best-effort levels, and four times it for the idle class.
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    /// Requests queued after a request of the default priority for it to
+    /// expire.
+    expire: u64,
+}
+
+impl Deadline {
+    /// Requests queued after a request for it to expire by default.
+    pub const DEFAULT_EXPIRE: u64 = 128;
+
+    /// Creates a scheduler under which requests of the default priority
+    /// expire once `expire` others were queued after them.
+    pub const fn new(expire: u64) -> Self {
+        Self { expire }
+    }
+
+    /// Returns when `request` expires, counted like its queuing.
+    fn deadline(&self, request: &Request) -> u64 {
+        // In eighths of the expiry, the default priority getting all of it
+        let weight = match request.priority.class {
+            IoClass::RealTime => 2,
+            IoClass::BestEffort => 4 + request.priority.level as u64,
+            IoClass::Idle => 32,
+        };
+        request.queued_at + self.expire * weight / 8
+    }
+}
+
+impl Default for Deadline {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_EXPIRE)
+    }
+}
+
+impl Elevator for Deadline {
+    fn name(&self) -> &'static str {
+        "deadline"
+    }
+
+    fn pick(&mut self, requests: &[Request], head: u64, now: u64) -> usize {
+        let first_due = requests
+            .iter()
+            .map(|it| self.deadline(it))
+            .enumerate()
+            .min_by_key(|&(_, deadline)| deadline);
+        if let Some((i, deadline)) = first_due
+            && now >= deadline
+        {
+            return i;
+        }
+        let Some(class) = requests.iter().map(|it| it.priority.class).min() else {
+            return 0;
+        };
+        // Onwards from the head, then around from the start of the disk
+        let mut candidates = requests
+            .iter()
+            .enumerate()
+            .filter(|(_, it)| it.priority.class == class);
+        let first = candidates.clone().next().map_or(0, |(i, _)| i);
+        candidates
+            .find(|(_, it)| it.start >= head)
+            .map_or(first, |(i, _)| i)
+    }
+}
+
+/// Counters of a [`RequestQueue`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Writes queued.
+    pub queued: u64,
+    /// Writes merged into another.
+    pub merged: u64,
+    /// Writes handed to the driver.
+    pub dispatched: u64,
+}
+
+struct Pending {
+    request: Request,
+    data: Vec<u8>,
+}
+
+/// A request submitted to the queue.
+enum Submitted {
+    /// Completed on submission.
+    Done(BlockIo),
+    /// A read in flight on the driver.
+    Read {
+        token: IoToken,
+        block_id: u64,
+        len: usize,
+        /// The queued writes it overlaps, as (offset in the buffer, data).
+        overlay: Vec<(usize, Vec<u8>)>,
+    },
+}
+
+/// A block device queueing writes in front of the driver `D`, dispatching
+/// them in the order of the elevator `E`.
+///
+/// Queued writes are dispatched on [`flush`](BlockDriverOps::flush) and when
+/// the queue is dropped.
+///
+/// Once offline, requests fail with [`DriverError::Io`] until the device is
+/// [reset](DriverOps::reset).
+pub struct RequestQueue<D: BlockDriverOps, E: Elevator = Deadline> {
+    dev: D,
+    elevator: E,
+    /// Keyed by the first block.
+    pending: BTreeMap<u64, Pending>,
+    /// The block after the last one dispatched.
+    head: u64,
+    stats: QueueStats,
+    policy: IoPolicy,
+    offline: bool,
+    next_token: u64,
+    submitted: BTreeMap<IoToken, Submitted>,
+}
+
+impl<D: BlockDriverOps, E: Elevator> RequestQueue<D, E> {
+    /// Creates a queue in front of `dev`, passing errors on as they are.
+    pub fn new(dev: D, elevator: E) -> Self {
+        Self {
+            dev,
+            elevator,
+            pending: BTreeMap::new(),
+            head: 0,
+            stats: QueueStats::default(),
+            policy: IoPolicy::PATIENT,
+            offline: false,
+            next_token: 0,
+            submitted: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the device behind the queue.
+    pub fn device(&self) -> &D {
+        &self.dev
+    }
+
+    /// Returns the policy for failing requests.
+    pub fn policy(&self) -> IoPolicy {
+        self.policy
+    }
+
+    /// Sets the policy for failing requests.
+    ///
+    /// The timeout only holds for drivers able to time requests.
+    pub fn set_policy(&mut self, policy: IoPolicy) -> DriverResult {
+        match self.dev.set_timeout(policy.timeout) {
+            Ok(()) | Err(DriverError::Unsupported) => {}
+            Err(err) => return Err(err),
+        }
+        self.policy = policy;
+        Ok(())
+    }
+
+    /// Whether the device went offline after failing requests.
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Returns the name of the scheduler.
+    pub fn elevator(&self) -> &'static str {
+        self.elevator.name()
+    }
+
+    /// Returns the counters of the queue.
+    pub fn stats(&self) -> QueueStats {
+        self.stats
+    }
+
+    /// Returns the number of queued writes.
+    pub fn queued(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Checks that `len` bytes from `block_id` on are whole blocks on the
+    /// device, and that it is online.
+    fn check(&self, block_id: u64, len: usize) -> DriverResult {
+        let block_size = self.dev.block_size();
+        if len == 0 || !len.is_multiple_of(block_size) {
+            return Err(DriverError::InvalidInput);
+        }
+        if self.offline || block_id + (len / block_size) as u64 > self.dev.num_blocks() {
+            return Err(DriverError::Io);
+        }
+        Ok(())
+    }
+
+    /// Returns the parts of the queued writes overlapping the `len` bytes
+    /// from `block_id` on, as (offset from `block_id`, data).
+    fn overlapping(&self, block_id: u64, len: usize) -> impl Iterator<Item = (usize, &[u8])> {
+        let block_size = self.dev.block_size();
+        let end = block_id + (len / block_size) as u64;
+        self.pending
+            .range(..end)
+            .rev()
+            .map(|(_, it)| it)
+            .filter(move |it| it.request.end() > block_id)
+            .map(move |pending| {
+                let from = pending.request.start.max(block_id);
+                let to = pending.request.end().min(end);
+                let src = (from - pending.request.start) as usize * block_size;
+                let dst = (from - block_id) as usize * block_size;
+                let len = (to - from) as usize * block_size;
+                (dst, &pending.data[src..src + len])
+            })
+    }
+
+    fn queue(&mut self, block_id: u64, buf: &[u8], priority: IoPriority) {
+        let block_size = self.dev.block_size();
+        let mut request = Request {
+            start: block_id,
+            num_blocks: (buf.len() / block_size) as u64,
+            queued_at: self.stats.queued,
+            priority,
+        };
+        self.stats.queued += 1;
+
+        // Queued writes never overlap, so they end in the order they start
+        let touching: Vec<_> = self
+            .pending
+            .range(..=request.end())
+            .rev()
+            .map(|(_, it)| it.request)
+            .take_while(|it| it.end() >= request.start)
+            .collect();
+        // Overlapping writes, and adjacent ones up to the size limit
+        let span = touching.iter().map(|it| it.num_blocks).sum::<u64>() + request.num_blocks;
+        let merged: Vec<_> = touching
+            .iter()
+            .filter(|it| {
+                span <= MAX_MERGE_BLOCKS || (it.end() > request.start && it.start < request.end())
+            })
+            .filter_map(|it| self.pending.remove(&it.start))
+            .collect();
+        if merged.is_empty() {
+            self.pending.insert(
+                request.start,
+                Pending {
+                    request,
+                    data: buf.to_vec(),
+                },
+            );
+            return;
+        }
+
+        self.stats.merged += merged.len() as u64;
+        let start = merged
+            .iter()
+            .map(|it| it.request.start)
+            .fold(request.start, u64::min);
+        let end = merged
+            .iter()
+            .map(|it| it.request.end())
+            .fold(request.end(), u64::max);
+        let mut data = vec![0; ((end - start) as usize) * block_size];
+        // Queued writes don't overlap each other, the new one goes over them
+        for old in &merged {
+            let offset = (old.request.start - start) as usize * block_size;
+            data[offset..offset + old.data.len()].copy_from_slice(&old.data);
+            request.queued_at = request.queued_at.min(old.request.queued_at);
+            request.priority = request.priority.min(old.request.priority);
+        }
+        let offset = (block_id - start) as usize * block_size;
+        data[offset..offset + buf.len()].copy_from_slice(buf);
+        request.start = start;
+        request.num_blocks = end - start;
+        self.pending.insert(start, Pending { request, data });
+    }
+
+    /// Drops the queued writes to the blocks from `start` to `end`, keeping
+    /// the parts of them outside.
+    fn unqueue(&mut self, start: u64, end: u64) {
+        let block_size = self.dev.block_size();
+        let overlapping: Vec<_> = self
+            .pending
+            .range(..end)
+            .rev()
+            .take_while(|(_, it)| it.request.end() > start)
+            .map(|(&key, _)| key)
+            .collect();
+        for key in overlapping {
+            let Pending { request, data } = self.pending.remove(&key).unwrap();
+            if request.start < start {
+                let num_blocks = start - request.start;
+                let len = num_blocks as usize * block_size;
+                self.pending.insert(
+                    request.start,
+                    Pending {
+                        request: Request {
+                            num_blocks,
+                            ..request
+                        },
+                        data: data[..len].to_vec(),
+                    },
+                );
+            }
+            if request.end() > end {
+                let offset = (end - request.start) as usize * block_size;
+                self.pending.insert(
+                    end,
+                    Pending {
+                        request: Request {
+                            start: end,
+                            num_blocks: request.end() - end,
+                            ..request
+                        },
+                        data: data[offset..].to_vec(),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Runs `op` on the driver, retrying it and taking the device offline as
+    /// the policy asks.
+    fn run<T>(&mut self, mut op: impl FnMut(&mut D) -> DriverResult<T>) -> DriverResult<T> {
+        if self.offline {
+            return Err(DriverError::Io);
+        }
+        let mut retries = 0;
+        let err = loop {
+            let err = match op(&mut self.dev) {
+                Ok(it) => return Ok(it),
+                Err(err) => err,
+            };
+            if !IoPolicy::retries_on(&err) || retries == self.policy.retries {
+                break err;
+            }
+            retries += 1;
+            if self.policy.reset {
+                match self.dev.reset() {
+                    Ok(()) | Err(DriverError::Unsupported) => {}
+                    Err(err) => log::warn!("{}: failed to reset: {:?}", self.dev.name(), err),
+                }
+            }
+        };
+        if !self.policy.offline || !IoPolicy::retries_on(&err) {
+            return Err(err);
+        }
+        log::warn!(
+            "{}: taken offline after {:?}, dropping {} queued writes",
+            self.dev.name(),
+            err,
+            self.pending.len()
+        );
+        self.offline = true;
+        self.pending.clear();
+        Err(DriverError::Io)
+    }
+
+    /// Dispatches up to `count` queued writes.
+    fn dispatch(&mut self, count: usize) -> DriverResult {
+        for _ in 0..count {
+            let requests: Vec<_> = self.pending.values().map(|it| it.request).collect();
+            if requests.is_empty() {
+                break;
+            }
+            let index = self.elevator.pick(&requests, self.head, self.stats.queued);
+            let request = requests[index.min(requests.len() - 1)];
+            let pending = self.pending.remove(&request.start).unwrap();
+            if let Err(err) = self.run(|dev| dev.write_block(request.start, &pending.data)) {
+                if !self.offline {
+                    self.pending.insert(request.start, pending);
+                }
+                return Err(err);
+            }
+            self.head = request.end();
+            self.stats.dispatched += 1;
+        }
+        Ok(())
+    }
+}
+
+impl<D: BlockDriverOps, E: Elevator> DriverOps for RequestQueue<D, E> {
+    fn name(&self) -> &str {
+        self.dev.name()
+    }
+
+    fn device_kind(&self) -> DeviceKind {
+        DeviceKind::Block
+    }
+
+    fn irq(&self) -> Option<usize> {
+        self.dev.irq()
+    }
+
+    /// Resets the device, the queued writes are dispatched afterwards.
+    ///
+    /// An offline device comes back online.
+    fn reset(&mut self) -> DriverResult {
+        self.dev.reset()?;
+        self.offline = false;
+        Ok(())
+    }
+}
+
+impl<D: BlockDriverOps, E: Elevator> BlockDriverOps for RequestQueue<D, E> {
+    fn num_blocks(&self) -> u64 {
+        self.dev.num_blocks()
+    }
+
+    fn block_size(&self) -> usize {
+        self.dev.block_size()
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DriverResult {
+        self.run(|dev| dev.read_block(block_id, buf))?;
+        for (offset, data) in self.overlapping(block_id, buf.len()) {
+            buf[offset..offset + data.len()].copy_from_slice(data);
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DriverResult {
+        self.write_block_at(block_id, buf, IoPriority::DEFAULT)
+    }
+
+    fn write_block_at(&mut self, block_id: u64, buf: &[u8], priority: IoPriority) -> DriverResult {
+        self.check(block_id, buf.len())?;
+        self.queue(block_id, buf, priority);
+        if self.pending.len() > MAX_REQUESTS {
+            self.dispatch(DISPATCH_BATCH)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> DriverResult {
+        self.dispatch(usize::MAX)?;
+        self.run(|dev| dev.flush())
+    }
+
+    /// Discards the blocks on the driver, dropping the queued writes to them
+    /// first.
+    fn discard(&mut self, block_id: u64, num_blocks: u64) -> DriverResult {
+        let len = (num_blocks as usize)
+            .checked_mul(self.dev.block_size())
+            .ok_or(DriverError::Io)?;
+        self.check(block_id, len)?;
+        self.unqueue(block_id, block_id + num_blocks);
+        self.run(|dev| dev.discard(block_id, num_blocks))
+    }
+
+    /// Sets the timeout of the device, see [`set_policy`](Self::set_policy)
+    /// for the whole policy.
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> DriverResult {
+        self.dev.set_timeout(timeout)?;
+        self.policy.timeout = timeout;
+        Ok(())
+    }
+
+    fn submit(&mut self, io: BlockIo) -> DriverResult<IoToken> {
+        let submitted = match io.op {
+            BlockOp::Write => {
+                self.write_block_at(io.block_id, &io.buf, io.priority)?;
+                Submitted::Done(io)
+            }
+            BlockOp::Read => {
+                let (block_id, len, priority) = (io.block_id, io.buf.len(), io.priority);
+                self.check(block_id, len)?;
+                let overlay = self
+                    .overlapping(block_id, len)
+                    .map(|(offset, data)| (offset, data.to_vec()))
+                    .collect();
+                match self.dev.submit(io) {
+                    Ok(token) => Submitted::Read {
+                        token,
+                        block_id,
+                        len,
+                        overlay,
+                    },
+                    Err(DriverError::Unsupported) => {
+                        let mut io = BlockIo::read(block_id, len).with_priority(priority);
+                        self.read_block(block_id, &mut io.buf)?;
+                        Submitted::Done(io)
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+        };
+        let token = IoToken(self.next_token);
+        self.next_token += 1;
+        self.submitted.insert(token, submitted);
+        Ok(token)
+    }
+
+    /// Takes back the request `token`; a read the driver failed is retried
+    /// synchronously, under the policy.
+    fn poll_complete(&mut self, token: IoToken) -> DriverResult<BlockIo> {
+        let submitted = self
+            .submitted
+            .remove(&token)
+            .ok_or(DriverError::InvalidInput)?;
+        let (dev_token, block_id, len, overlay) = match submitted {
+            Submitted::Done(io) => return Ok(io),
+            Submitted::Read {
+                token,
+                block_id,
+                len,
+                overlay,
+            } => (token, block_id, len, overlay),
+        };
+        let mut io = match self.dev.poll_complete(dev_token) {
+            Ok(io) => io,
+            Err(DriverError::WouldBlock) => {
+                self.submitted.insert(
+                    token,
+                    Submitted::Read {
+                        token: dev_token,
+                        block_id,
+                        len,
+                        overlay,
+                    },
+                );
+                return Err(DriverError::WouldBlock);
+            }
+            Err(err) if IoPolicy::retries_on(&err) && self.policy.retries > 0 => {
+                let mut io = BlockIo::read(block_id, len);
+                self.run(|dev| dev.read_block(block_id, &mut io.buf))?;
+                io
+            }
+            Err(err) => return Err(err),
+        };
+        for (offset, data) in overlay {
+            io.buf[offset..offset + data.len()].copy_from_slice(&data);
+        }
+        Ok(io)
+    }
+
+    fn enable_interrupt(&mut self, enable: bool) {
+        self.dev.enable_interrupt(enable);
+    }
+}
+
+impl<D: BlockDriverOps, E: Elevator> Drop for RequestQueue<D, E> {
+    fn drop(&mut self) {
+        if self.offline {
+            return;
+        }
+        if let Err(err) = self.flush() {
+            log::warn!(
+                "{}: lost {} queued writes: {:?}",
+                self.dev.name(),
+                self.pending.len(),
+                err
+            );
+        }
+    }
+}
+
+#[cfg(unittest)]
+mod tests_queue {
+    use unittest::{assert, assert_eq, def_test};
+
+    use super::*;
+
+    const BLOCK_SIZE: usize = 512;
+
+    /// A disk recording the writes it gets as (first block, blocks).
+    struct LogDisk {
+        data: Vec<u8>,
+        writes: Vec<(u64, u64)>,
+        discards: Vec<(u64, u64)>,
+        /// Requests left to time out.
+        failures: usize,
+        resets: usize,
+        /// Whether requests can be submitted, they complete when polled.
+        async_io: bool,
+        in_flight: BTreeMap<IoToken, BlockIo>,
+    }
+
+    impl LogDisk {
+        fn new(num_blocks: usize) -> Self {
+            Self {
+                data: vec![0; num_blocks * BLOCK_SIZE],
+                writes: Vec::new(),
+                discards: Vec::new(),
+                failures: 0,
+                resets: 0,
+                async_io: false,
+                in_flight: BTreeMap::new(),
+            }
+        }
+
+        fn fail(&mut self) -> DriverResult {
+            if self.failures == 0 {
+                return Ok(());
+            }
+            self.failures -= 1;
+            Err(DriverError::TimedOut)
+        }
+    }
+
+    impl DriverOps for LogDisk {
+        fn name(&self) -> &str {
+            "log"
+        }
+
+        fn device_kind(&self) -> DeviceKind {
+            DeviceKind::Block
+        }
+
+        fn reset(&mut self) -> DriverResult {
+            self.resets += 1;
+            Ok(())
+        }
+    }
+
+    impl BlockDriverOps for LogDisk {
+        fn num_blocks(&self) -> u64 {
+            (self.data.len() / BLOCK_SIZE) as u64
+        }
+
+        fn block_size(&self) -> usize {
+            BLOCK_SIZE
+        }
+
+        fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DriverResult {
+            self.fail()?;
+            let start = block_id as usize * BLOCK_SIZE;
+            buf.copy_from_slice(&self.data[start..start + buf.len()]);
+            Ok(())
+        }
+
+        fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DriverResult {
+            self.fail()?;
+            let start = block_id as usize * BLOCK_SIZE;
+            self.data[start..start + buf.len()].copy_from_slice(buf);
+            self.writes
+                .push((block_id, (buf.len() / BLOCK_SIZE) as u64));
+            Ok(())
+        }
+
+        fn flush(&mut self) -> DriverResult {
+            Ok(())
+        }
+
+        fn discard(&mut self, block_id: u64, num_blocks: u64) -> DriverResult {
+            self.fail()?;
+            self.discards.push((block_id, num_blocks));
+            Ok(())
+        }
+
+        fn submit(&mut self, io: BlockIo) -> DriverResult<IoToken> {
+            if !self.async_io {
+                return Err(DriverError::Unsupported);
+            }
+            let token = IoToken(self.in_flight.len() as u64);
+            self.in_flight.insert(token, io);
+            Ok(token)
+        }
+
+        fn poll_complete(&mut self, token: IoToken) -> DriverResult<BlockIo> {
+            let mut io = self
+                .in_flight
+                .remove(&token)
+                .ok_or(DriverError::InvalidInput)?;
+            match io.op {
+                BlockOp::Read => self.read_block(io.block_id, &mut io.buf)?,
+                BlockOp::Write => self.write_block(io.block_id, &io.buf)?,
+            }
+            Ok(io)
+        }
+    }
+
+    fn fill(blocks: usize, byte: u8) -> Vec<u8> {
+        vec![byte; blocks * BLOCK_SIZE]
+    }
+
+    #[def_test]
+    fn test_queue_merges_adjacent_and_overlapping_writes() {
+        let mut queue = RequestQueue::new(LogDisk::new(64), Deadline::default());
+        queue.write_block(4, &fill(2, 1)).unwrap();
+        queue.write_block(6, &fill(1, 2)).unwrap();
+        queue.write_block(2, &fill(2, 3)).unwrap();
+        queue.write_block(5, &fill(2, 4)).unwrap();
+        queue.write_block(20, &fill(1, 5)).unwrap();
+        assert_eq!(queue.queued(), 2);
+        assert!(queue.dev.writes.is_empty());
+
+        // Reads see the queued data
+        let mut buf = fill(6, 0);
+        queue.read_block(2, &mut buf).unwrap();
+        let blocks: Vec<_> = buf.chunks(BLOCK_SIZE).map(|it| it[0]).collect();
+        assert_eq!(blocks, [3, 3, 1, 4, 4, 0]);
+
+        queue.flush().unwrap();
+        assert_eq!(queue.dev.writes, [(2, 5), (20, 1)]);
+        assert_eq!(
+            queue.stats(),
+            QueueStats {
+                queued: 5,
+                merged: 3,
+                dispatched: 2,
+            }
+        );
+        let mut disk = fill(6, 0);
+        queue.dev.read_block(2, &mut disk).unwrap();
+        assert_eq!(disk, buf);
+    }
+
+    #[def_test]
+    fn test_queue_deadline_sweeps_from_head() {
+        let mut queue = RequestQueue::new(LogDisk::new(64), Deadline::default());
+        for block in [30, 10, 50, 20] {
+            queue.write_block(block, &fill(1, 1)).unwrap();
+        }
+        queue.head = 25;
+        queue.flush().unwrap();
+        assert_eq!(queue.dev.writes, [(30, 1), (50, 1), (10, 1), (20, 1)]);
+
+        // An expired request goes first
+        let mut queue = RequestQueue::new(LogDisk::new(64), Deadline::new(2));
+        for block in [30, 10, 50] {
+            queue.write_block(block, &fill(1, 1)).unwrap();
+        }
+        queue.flush().unwrap();
+        assert_eq!(queue.dev.writes[0], (30, 1));
+    }
+
+    #[def_test]
+    fn test_queue_deadline_serves_higher_classes_first() {
+        let idle = IoPriority::new(IoClass::Idle, 0);
+        let rt = IoPriority::new(IoClass::RealTime, 0);
+        let mut queue = RequestQueue::new(LogDisk::new(64), Deadline::default());
+        for block in [10, 20, 30] {
+            queue.write_block_at(block, &fill(1, 1), idle).unwrap();
+        }
+        queue.write_block(40, &fill(1, 1)).unwrap();
+        queue.write_block_at(50, &fill(1, 1), rt).unwrap();
+        queue.flush().unwrap();
+        assert_eq!(
+            queue.dev.writes,
+            [(50, 1), (40, 1), (10, 1), (20, 1), (30, 1)]
+        );
+
+        // Merging into a request raises its priority
+        let mut queue = RequestQueue::new(LogDisk::new(64), Deadline::default());
+        queue.write_block_at(10, &fill(1, 1), idle).unwrap();
+        queue.write_block(20, &fill(1, 1)).unwrap();
+        queue.write_block_at(11, &fill(1, 1), rt).unwrap();
+        queue.flush().unwrap();
+        assert_eq!(queue.dev.writes, [(10, 2), (20, 1)]);
+    }
+
+    #[def_test]
+    fn test_queue_deadline_idle_requests_expire_late() {
+        let idle = IoPriority::new(IoClass::Idle, 0);
+        let mut queue = RequestQueue::new(LogDisk::new(256), Deadline::new(4));
+        queue.write_block_at(0, &fill(1, 1), idle).unwrap();
+        // Best-effort writes keep coming, the idle one waits until 16
+        for i in 1..15 {
+            queue.write_block(i * 8, &fill(1, 1)).unwrap();
+            queue.dispatch(1).unwrap();
+        }
+        assert_eq!(queue.dev.writes.len(), 14);
+        assert_eq!(queue.queued(), 1);
+
+        queue.write_block(15 * 8, &fill(1, 1)).unwrap();
+        queue.dispatch(1).unwrap();
+        assert_eq!(queue.dev.writes.last(), Some(&(0, 1)));
+    }
+
+    #[def_test]
+    fn test_io_priority_raw() {
+        assert_eq!(IoPriority::from_raw(0), Some(IoPriority::DEFAULT));
+        let idle = IoPriority::new(IoClass::Idle, 7);
+        assert_eq!(idle.to_raw(), (3 << 13) | 7);
+        assert_eq!(IoPriority::from_raw(idle.to_raw()), Some(idle));
+        assert_eq!(IoPriority::from_raw((1 << 13) | 8), None);
+        assert_eq!(IoPriority::from_raw(4 << 13), None);
+        assert!(IoPriority::new(IoClass::RealTime, 7) < IoPriority::DEFAULT);
+        assert_eq!(IoPriority::new(IoClass::BestEffort, 9).level, 7);
+    }
+
+    #[def_test]
+    fn test_queue_noop_keeps_order() {
+        let mut queue = RequestQueue::new(LogDisk::new(64), Noop);
+        for block in [30, 10, 50, 20] {
+            queue.write_block(block, &fill(1, 1)).unwrap();
+        }
+        queue.flush().unwrap();
+        assert_eq!(queue.dev.writes, [(30, 1), (10, 1), (50, 1), (20, 1)]);
+    }
+
+    #[def_test]
+    fn test_queue_dispatches_when_full() {
+        let mut queue = RequestQueue::new(LogDisk::new(1024), Noop);
+        for i in 0..=MAX_REQUESTS as u64 {
+            queue.write_block(i * 2, &fill(1, 1)).unwrap();
+        }
+        assert_eq!(queue.dev.writes.len(), DISPATCH_BATCH);
+        assert_eq!(queue.queued(), MAX_REQUESTS + 1 - DISPATCH_BATCH);
+        assert!(matches!(
+            queue.write_block(1024, &fill(1, 1)),
+            Err(DriverError::Io)
+        ));
+    }
+
+    #[def_test]
+    fn test_queue_discard_drops_queued_writes() {
+        let mut queue = RequestQueue::new(LogDisk::new(64), Noop);
+        queue.write_block(2, &fill(4, 1)).unwrap();
+        queue.write_block(8, &fill(1, 2)).unwrap();
+        queue.write_block(10, &fill(1, 3)).unwrap();
+        queue.discard(4, 6).unwrap();
+        assert_eq!(queue.dev.discards, [(4, 6)]);
+        assert_eq!(queue.queued(), 2);
+
+        // The write straddling the range keeps its blocks before it
+        queue.flush().unwrap();
+        assert_eq!(queue.dev.writes, [(2, 2), (10, 1)]);
+        assert!(matches!(queue.discard(60, 8), Err(DriverError::Io)));
+    }
+
+    #[def_test]
+    fn test_queue_retries_after_reset() {
+        let mut queue = RequestQueue::new(LogDisk::new(64), Noop);
+        queue.dev.failures = 1;
+        let mut buf = fill(1, 0);
+        assert!(matches!(
+            queue.read_block(0, &mut buf),
+            Err(DriverError::TimedOut)
+        ));
+
+        queue.set_policy(IoPolicy::default()).unwrap();
+        queue.dev.failures = 2;
+        queue.read_block(0, &mut buf).unwrap();
+        assert_eq!(queue.dev.resets, 2);
+        assert!(!queue.is_offline());
+    }
+
+    #[def_test]
+    fn test_queue_goes_offline() {
+        let mut queue = RequestQueue::new(LogDisk::new(64), Noop);
+        queue.set_policy(IoPolicy::default()).unwrap();
+        queue.write_block(4, &fill(1, 1)).unwrap();
+        queue.dev.failures = usize::MAX;
+        assert!(matches!(queue.flush(), Err(DriverError::Io)));
+        assert!(queue.is_offline());
+        assert_eq!(queue.queued(), 0);
+        assert_eq!(queue.dev.resets, 2);
+
+        // Requests fail without reaching the device
+        queue.dev.failures = 0;
+        let mut buf = fill(1, 0);
+        assert!(matches!(
+            queue.read_block(0, &mut buf),
+            Err(DriverError::Io)
+        ));
+        assert!(matches!(queue.write_block(0, &buf), Err(DriverError::Io)));
+        assert_eq!(queue.dev.resets, 2);
+
+        queue.reset().unwrap();
+        assert!(!queue.is_offline());
+        queue.read_block(0, &mut buf).unwrap();
+    }
+
+    #[def_test]
+    fn test_queue_submitted_reads_see_earlier_writes() {
+        for async_io in [false, true] {
+            let mut disk = LogDisk::new(64);
+            disk.async_io = async_io;
+            let mut queue = RequestQueue::new(disk, Noop);
+            let write = queue.submit(BlockIo::write(4, fill(1, 1))).unwrap();
+            let read = queue.submit(BlockIo::read(3, 3 * BLOCK_SIZE)).unwrap();
+            assert_eq!(queue.dev.in_flight.len(), async_io as usize);
+            // Queued after the read was submitted
+            queue.write_block(5, &fill(1, 2)).unwrap();
+
+            assert_eq!(queue.poll_complete(write).unwrap().buf, fill(1, 1));
+            let io = queue.poll_complete(read).unwrap();
+            let blocks: Vec<_> = io.buf.chunks(BLOCK_SIZE).map(|it| it[0]).collect();
+            assert_eq!(blocks, [0, 1, 0]);
+            assert!(matches!(
+                queue.poll_complete(read),
+                Err(DriverError::InvalidInput)
+            ));
+        }
+    }
+}
@@ -3,13 +3,16 @@
 // See LICENSES for license details.
 
 //! A RAM disk driver backed by heap-allocated memory.
+//!
+//! Without any storage device, the kernel can still mount its root filesystem
+//! from a RAM disk, blank or holding an image built into the kernel.
 
 extern crate alloc;
 
-use alloc::alloc::{alloc_zeroed, dealloc};
+use alloc::alloc::{alloc_zeroed, dealloc, handle_alloc_error};
 use core::{
     alloc::Layout,
-    ops::{Deref, DerefMut},
+    ops::{Deref, DerefMut, Range},
     ptr::NonNull,
 };
 
@@ -38,13 +41,43 @@ impl RamDisk {
     /// The size is rounded up to be aligned to the block size (512 bytes).
     pub fn new(size_hint: usize) -> Self {
         let size = align_up(size_hint);
-        let layout = unsafe { Layout::from_size_align_unchecked(size, BLOCK_SIZE) };
+        if size == 0 {
+            return Self::default();
+        }
+        let layout = Layout::from_size_align(size, BLOCK_SIZE).expect("RAM disk too large");
 
         // Allocate the memory and create a NonNull pointer to the RAM disk buffer.
-        let ptr = unsafe { NonNull::new_unchecked(alloc_zeroed(layout)) };
+        let Some(ptr) = NonNull::new(unsafe { alloc_zeroed(layout) }) else {
+            handle_alloc_error(layout);
+        };
 
         Self(NonNull::slice_from_raw_parts(ptr, size))
     }
+
+    /// Creates a RAM disk of at least `size_hint` bytes starting out with
+    /// `image`, e.g. a filesystem image built into the kernel.
+    ///
+    /// The disk is large enough for the whole image, the rest is zeroed.
+    pub fn from_image(image: &[u8], size_hint: usize) -> Self {
+        let mut ramdisk = RamDisk::new(size_hint.max(image.len()));
+        ramdisk[..image.len()].copy_from_slice(image);
+        ramdisk
+    }
+
+    /// Returns the byte range of `len` bytes from `block_id`.
+    fn range(&self, block_id: u64, len: usize) -> DriverResult<Range<usize>> {
+        if !len.is_multiple_of(BLOCK_SIZE) {
+            return Err(DriverError::InvalidInput);
+        }
+        let start = (block_id as usize)
+            .checked_mul(BLOCK_SIZE)
+            .ok_or(DriverError::Io)?;
+        let end = start.checked_add(len).ok_or(DriverError::Io)?;
+        if end > self.len() {
+            return Err(DriverError::Io);
+        }
+        Ok(start..end)
+    }
 }
 
 impl Drop for RamDisk {
@@ -81,9 +114,7 @@ impl DerefMut for RamDisk {
 
 impl From<&[u8]> for RamDisk {
     fn from(data: &[u8]) -> Self {
-        let mut ramdisk = RamDisk::new(data.len());
-        ramdisk[..data.len()].copy_from_slice(data);
-        ramdisk
+        RamDisk::from_image(data, data.len())
     }
 }
 
@@ -110,32 +141,30 @@ impl BlockDriverOps for RamDisk {
     }
 
     fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DriverResult {
-        if buf.len() % BLOCK_SIZE != 0 {
-            return Err(DriverError::InvalidInput);
-        }
-        let offset = block_id as usize * BLOCK_SIZE;
-        if offset + buf.len() > self.len() {
-            return Err(DriverError::Io);
-        }
-        buf.copy_from_slice(&self[offset..offset + buf.len()]);
+        let range = self.range(block_id, buf.len())?;
+        buf.copy_from_slice(&self[range]);
         Ok(())
     }
 
     fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DriverResult {
-        if buf.len() % BLOCK_SIZE != 0 {
-            return Err(DriverError::InvalidInput);
-        }
-        let offset = block_id as usize * BLOCK_SIZE;
-        if offset + buf.len() > self.len() {
-            return Err(DriverError::Io);
-        }
-        self[offset..offset + buf.len()].copy_from_slice(buf);
+        let range = self.range(block_id, buf.len())?;
+        self[range].copy_from_slice(buf);
         Ok(())
     }
 
     fn flush(&mut self) -> DriverResult {
         Ok(())
     }
+
+    /// Zeroes the blocks; the memory stays allocated.
+    fn discard(&mut self, block_id: u64, num_blocks: u64) -> DriverResult {
+        let len = (num_blocks as usize)
+            .checked_mul(BLOCK_SIZE)
+            .ok_or(DriverError::Io)?;
+        let range = self.range(block_id, len)?;
+        self[range].fill(0);
+        Ok(())
+    }
 }
 
 /// Aligns a given size upwards to the nearest multiple of `BLOCK_SIZE`.
@@ -191,26 +220,29 @@ mod tests_ramdisk {
 
         // Test reading beyond disk boundary
         let mut buf = vec![0; 512];
-        assert_eq!(disk.read_block(3, &mut buf), Err(DriverError::Io));
-        assert_eq!(disk.read_block(100, &mut buf), Err(DriverError::Io));
+        assert!(matches!(disk.read_block(3, &mut buf), Err(DriverError::Io)));
+        assert!(matches!(
+            disk.read_block(100, &mut buf),
+            Err(DriverError::Io)
+        ));
 
         // Test writing beyond disk boundary
         let data = vec![0xFF; 512];
-        assert_eq!(disk.write_block(3, &data), Err(DriverError::Io));
-        assert_eq!(disk.write_block(100, &data), Err(DriverError::Io));
+        assert!(matches!(disk.write_block(3, &data), Err(DriverError::Io)));
+        assert!(matches!(disk.write_block(100, &data), Err(DriverError::Io)));
 
         // Test invalid buffer sizes (not multiple of block size)
         let mut invalid_buf = vec![0; 510]; // Not aligned to block size
-        assert_eq!(
+        assert!(matches!(
             disk.read_block(0, &mut invalid_buf),
             Err(DriverError::InvalidInput)
-        );
+        ));
 
         let invalid_data = vec![0xFF; 510];
-        assert_eq!(
+        assert!(matches!(
             disk.write_block(0, &invalid_data),
             Err(DriverError::InvalidInput)
-        );
+        ));
 
         // Test multi-block operations at boundaries
         let mut multi_buf = vec![0; 1024]; // 2 blocks
@@ -224,8 +256,14 @@ mod tests_ramdisk {
         assert!(disk.write_block(1, &multi_buf).is_ok());
 
         // This should fail (blocks 2-3, but only block 2 exists)
-        assert_eq!(disk.read_block(2, &mut multi_buf), Err(DriverError::Io));
-        assert_eq!(disk.write_block(2, &multi_buf), Err(DriverError::Io));
+        assert!(matches!(
+            disk.read_block(2, &mut multi_buf),
+            Err(DriverError::Io)
+        ));
+        assert!(matches!(
+            disk.write_block(2, &multi_buf),
+            Err(DriverError::Io)
+        ));
 
         // Test edge case: exactly at boundary
         let edge_data = vec![0xCC; 512];
@@ -245,4 +283,36 @@ mod tests_ramdisk {
         assert!(disk_from_slice_mut.read_block(0, &mut verify_buf).is_ok());
         assert_eq!(verify_buf, vec![0x12; 512]);
     }
+
+    #[def_test]
+    fn test_ramdisk_from_image() {
+        let image = vec![0x34; 700];
+        let mut disk = RamDisk::from_image(&image, 4096);
+        assert_eq!(disk.num_blocks(), 8);
+
+        let mut buf = vec![0; 1024];
+        assert!(disk.read_block(0, &mut buf).is_ok());
+        assert_eq!(&buf[..700], &image[..]);
+        assert!(buf[700..].iter().all(|&b| b == 0));
+
+        // An image larger than the hint sizes the disk
+        let disk = RamDisk::from_image(&vec![0x56; 2048], 512);
+        assert_eq!(disk.num_blocks(), 4);
+
+        assert_eq!(RamDisk::new(0).num_blocks(), 0);
+    }
+
+    #[def_test]
+    fn test_ramdisk_discard() {
+        let mut disk = RamDisk::from_image(&vec![0x78; 2048], 0);
+        assert!(disk.discard(1, 2).is_ok());
+
+        let mut buf = vec![0; 2048];
+        assert!(disk.read_block(0, &mut buf).is_ok());
+        assert!(buf[..512].iter().all(|&b| b == 0x78));
+        assert!(buf[512..1536].iter().all(|&b| b == 0));
+        assert!(buf[1536..].iter().all(|&b| b == 0x78));
+
+        assert!(matches!(disk.discard(3, 2), Err(DriverError::Io)));
+    }
 }
@@ -0,0 +1,83 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Blanking of an idle display.
+
+use core::time::Duration;
+
+/// Decides when a display is blanked for inactivity, and unblanked again.
+///
+/// The owner of the display reports user activity, e.g. input events, with
+/// [`activity`](Self::activity), and polls [`expired`](Self::expired)
+/// periodically. Times are measured from any fixed point, e.g. boot.
+#[derive(Debug, Clone, Copy)]
+pub struct BlankTimer {
+    timeout: Option<Duration>,
+    last_activity: Duration,
+    blanked: bool,
+}
+
+impl BlankTimer {
+    /// Blank timeout unless changed, that of the Linux console.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(600);
+
+    /// Creates a timer blanking after `timeout` without activity, or never
+    /// with `None`.
+    pub const fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            last_activity: Duration::ZERO,
+            blanked: false,
+        }
+    }
+
+    /// Returns the inactivity after which the display is blanked.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Sets the inactivity after which the display is blanked, counted from
+    /// the last activity.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Whether the display is blanked.
+    pub fn is_blanked(&self) -> bool {
+        self.blanked
+    }
+
+    /// Records that the display was blanked or unblanked by other means, e.g.
+    /// on request of the user.
+    pub fn set_blanked(&mut self, blanked: bool, now: Duration) {
+        self.blanked = blanked;
+        self.last_activity = now;
+    }
+
+    /// Records activity at `now`, returning `true` if the display is to be
+    /// unblanked.
+    pub fn activity(&mut self, now: Duration) -> bool {
+        self.last_activity = self.last_activity.max(now);
+        core::mem::replace(&mut self.blanked, false)
+    }
+
+    /// Returns `true` if the display is to be blanked at `now`, once the
+    /// timeout passed since the last activity.
+    pub fn expired(&mut self, now: Duration) -> bool {
+        let Some(timeout) = self.timeout else {
+            return false;
+        };
+        if self.blanked || now.saturating_sub(self.last_activity) < timeout {
+            return false;
+        }
+        self.blanked = true;
+        true
+    }
+}
+
+impl Default for BlankTimer {
+    fn default() -> Self {
+        Self::new(Some(Self::DEFAULT_TIMEOUT))
+    }
+}
@@ -6,9 +6,13 @@
 
 #![no_std]
 
+mod blank;
+
 #[doc(no_inline)]
 pub use driver_base::{DeviceKind, DriverError, DriverOps, DriverResult};
 
+pub use self::blank::BlankTimer;
+
 /// The information of the graphics device.
 #[derive(Debug, Clone, Copy)]
 pub struct DisplayInfo {
@@ -60,6 +64,15 @@ pub trait DisplayDriverOps: DriverOps {
 
     /// Flush framebuffer to the screen.
     fn flush(&mut self) -> DriverResult;
+
+    /// Blanks the screen, or shows the framebuffer again.
+    ///
+    /// The framebuffer stays writable while the screen is blank, and
+    /// [flushing](Self::flush) it has no effect until it is unblanked.
+    fn set_blanked(&mut self, blanked: bool) -> DriverResult {
+        let _ = blanked;
+        Err(DriverError::Unsupported)
+    }
 }
 
 mod tests;
@@ -8,7 +8,9 @@ use alloc::vec;
 
 use unittest::{assert, assert_eq, def_test};
 
-use super::{DisplayInfo, FrameBuffer};
+use core::time::Duration;
+
+use super::{BlankTimer, DisplayInfo, FrameBuffer};
 
 // ============================================================================
 // DisplayInfo Tests
@@ -287,3 +289,40 @@ fn test_display_framebuffer_integration() {
         }
     }
 }
+
+// ============================================================================
+// BlankTimer Tests
+// ============================================================================
+
+#[def_test]
+fn test_blank_timer_blanks_after_inactivity() {
+    let secs = Duration::from_secs;
+    let mut timer = BlankTimer::new(Some(secs(10)));
+    assert!(!timer.expired(secs(9)));
+    assert!(timer.expired(secs(10)));
+    assert!(timer.is_blanked());
+    // Blanked once only
+    assert!(!timer.expired(secs(20)));
+
+    // Activity unblanks and restarts the countdown
+    assert!(timer.activity(secs(25)));
+    assert!(!timer.activity(secs(26)));
+    assert!(!timer.expired(secs(35)));
+    assert!(timer.expired(secs(36)));
+
+    timer.set_timeout(None);
+    timer.activity(secs(40));
+    assert!(!timer.expired(secs(10_000)));
+    assert_eq!(BlankTimer::default().timeout(), Some(secs(600)));
+}
+
+#[def_test]
+fn test_blank_timer_explicit_blanking() {
+    let secs = Duration::from_secs;
+    let mut timer = BlankTimer::new(Some(secs(10)));
+    timer.set_blanked(true, secs(5));
+    assert!(!timer.expired(secs(100)));
+    timer.set_blanked(false, secs(100));
+    assert!(!timer.expired(secs(109)));
+    assert!(timer.expired(secs(110)));
+}
@@ -20,6 +20,8 @@
 #![no_std]
 #![allow(rustdoc::broken_intra_doc_links)]
 
+use core::time::Duration;
+
 /// All supported device kinds.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum DeviceKind {
@@ -35,6 +37,8 @@ pub enum DeviceKind {
     Input,
     /// Vsock device (e.g., virtio-vsock).
     Vsock,
+    /// Memory balloon device (e.g., virtio-balloon).
+    Balloon,
 }
 
 /// The error type for driver operation failures.
@@ -56,6 +60,8 @@ pub enum DriverError {
     ResourceBusy,
     /// This operation is unsupported or unimplemented.
     Unsupported,
+    /// The device did not complete the request in time.
+    TimedOut,
 }
 
 impl DriverError {
@@ -70,6 +76,7 @@ impl DriverError {
             DriverError::NoMemory => "Not enough memory",
             DriverError::ResourceBusy => "Resource is busy",
             DriverError::Unsupported => "Unsupported operation",
+            DriverError::TimedOut => "Timed out",
         }
     }
 }
@@ -80,6 +87,69 @@ impl core::fmt::Display for DriverError {
     }
 }
 
+impl From<DriverError> for kerrno::KError {
+    fn from(err: DriverError) -> Self {
+        match err {
+            DriverError::AlreadyExists => kerrno::KError::AlreadyExists,
+            DriverError::WouldBlock => kerrno::KError::WouldBlock,
+            DriverError::BadState => kerrno::KError::BadState,
+            DriverError::InvalidInput => kerrno::KError::InvalidInput,
+            DriverError::Io => kerrno::KError::Io,
+            DriverError::NoMemory => kerrno::KError::NoMemory,
+            DriverError::ResourceBusy => kerrno::KError::ResourceBusy,
+            DriverError::Unsupported => kerrno::KError::OperationNotSupported,
+            DriverError::TimedOut => kerrno::KError::TimedOut,
+        }
+    }
+}
+
+/// What the request layer above a device does about it failing requests.
+///
+/// Requests failing with a timeout or an I/O error are retried, after
+/// resetting the device if asked to. Once out of retries, the device may go
+/// offline: the requests to it fail right away from then on, instead of
+/// hanging every task touching it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoPolicy {
+    /// How long the device may take for a request, `None` to wait for good.
+    pub timeout: Option<Duration>,
+    /// How often a failed request is retried.
+    pub retries: u32,
+    /// Whether the device is reset before retrying.
+    pub reset: bool,
+    /// Whether the device goes offline once out of retries.
+    pub offline: bool,
+}
+
+impl IoPolicy {
+    /// Waits for the device for good, and passes errors on as they are.
+    pub const PATIENT: Self = Self {
+        timeout: None,
+        retries: 0,
+        reset: false,
+        offline: false,
+    };
+
+    /// Whether a request failing with `err` is worth retrying.
+    pub const fn retries_on(err: &DriverError) -> bool {
+        matches!(
+            err,
+            DriverError::Io | DriverError::TimedOut | DriverError::BadState
+        )
+    }
+}
+
+impl Default for IoPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Some(Duration::from_secs(30)),
+            retries: 2,
+            reset: true,
+            offline: true,
+        }
+    }
+}
+
 /// A specialized `Result` type for device operations.
 pub type DriverResult<T = ()> = Result<T, DriverError>;
 
@@ -95,4 +165,10 @@ pub trait DriverOps: Send + Sync {
     fn irq(&self) -> Option<usize> {
         None
     }
+
+    /// Resets the device and sets it up again, e.g. after its backend on the
+    /// host restarted. Requests in flight are lost.
+    fn reset(&mut self) -> DriverResult {
+        Err(DriverError::Unsupported)
+    }
 }
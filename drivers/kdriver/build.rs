@@ -2,11 +2,26 @@
 // Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
 // See LICENSES for license details.
 
-const NET_DEV_FEATURES: &[&str] = &["fxmac", "ixgbe", "virtio-net"];
-const BLOCK_DEV_FEATURES: &[&str] = &["ahci", "ramdisk", "sdmmc", "bcm2835-sdhci", "virtio-blk"];
+// Test devices come first so that they replace the real ones when enabled.
+const NET_DEV_FEATURES: &[&str] = &["test-net", "fxmac", "ixgbe", "virtio-net"];
+const BLOCK_DEV_FEATURES: &[&str] = &[
+    "test-block",
+    "ahci",
+    "ramdisk",
+    "sdmmc",
+    "bcm2835-sdhci",
+    "virtio-blk",
+];
 const DISPLAY_DEV_FEATURES: &[&str] = &["virtio-gpu"];
 const INPUT_DEV_FEATURES: &[&str] = &["virtio-input"];
 const VSOCK_DEV_FEATURES: &[&str] = &["virtio-socket"];
+const ALL_DEV_FEATURES: &[&[&str]] = &[
+    NET_DEV_FEATURES,
+    BLOCK_DEV_FEATURES,
+    DISPLAY_DEV_FEATURES,
+    INPUT_DEV_FEATURES,
+    VSOCK_DEV_FEATURES,
+];
 
 fn make_cfg_values(str_list: &[&str]) -> String {
     str_list
@@ -28,6 +43,69 @@ fn enable_cfg(key: &str, value: &str) {
     println!("cargo:rustc-cfg={key}=\"{value}\"");
 }
 
+/// The `allow` and `deny` lists of the `[drivers]` table of the platform config.
+struct DriverLists {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl DriverLists {
+    /// Loads the lists from `PLAT_CONFIG_PATH`, else from the config of the platform `PLAT`
+    /// exported by the Makefile, else from the dummy config, which has none.
+    fn load() -> Self {
+        println!("cargo:rerun-if-env-changed=PLAT_CONFIG_PATH");
+        println!("cargo:rerun-if-env-changed=PLAT");
+        let root = format!("{}/../..", std::env::var("CARGO_MANIFEST_DIR").unwrap());
+        let path = std::env::var("PLAT_CONFIG_PATH")
+            .ok()
+            .or_else(|| {
+                let plat = std::env::var("PLAT").ok()?;
+                let path = format!("{root}/platforms/{plat}/platconfig.toml");
+                std::path::Path::new(&path).exists().then_some(path)
+            })
+            .unwrap_or_else(|| format!("{root}/configs/dummy.toml"));
+        println!("cargo:rerun-if-changed={path}");
+        let config: toml::Table = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("failed to read platform config {path}: {e}"))
+            .parse()
+            .unwrap_or_else(|e| panic!("failed to parse platform config {path}: {e}"));
+
+        let list = |key: &str| -> Vec<String> {
+            let Some(value) = config.get("drivers").and_then(|drivers| drivers.get(key)) else {
+                return Vec::new();
+            };
+            let names = value
+                .as_array()
+                .and_then(|names| {
+                    names
+                        .iter()
+                        .map(|name| name.as_str())
+                        .collect::<Option<Vec<_>>>()
+                })
+                .unwrap_or_else(|| panic!("`drivers.{key}` in {path} must be a list of strings"));
+            for name in &names {
+                if !ALL_DEV_FEATURES
+                    .iter()
+                    .flat_map(|list| list.iter())
+                    .any(|feat| feat == name)
+                {
+                    panic!("`drivers.{key}` in {path} names an unknown driver `{name}`");
+                }
+            }
+            names.into_iter().map(String::from).collect()
+        };
+        Self {
+            allow: list("allow"),
+            deny: list("deny"),
+        }
+    }
+
+    fn allows(&self, feature: &str) -> bool {
+        !self.deny.iter().any(|name| name == feature)
+            && (self.allow.is_empty() || self.allow.iter().any(|name| name == feature))
+    }
+}
+
 fn main() {
     if has_feature("bus-mmio") {
         enable_cfg("bus", "mmio");
@@ -37,6 +115,8 @@ fn main() {
 
     // Generate cfgs like `net_dev="virtio-net"`. if `dyn` is not enabled, only one device is
     // selected for each device category. If no device is selected, `dummy` is selected.
+    // Drivers left out by the `[drivers]` lists of the platform config are never selected.
+    let lists = DriverLists::load();
     let is_dyn = has_feature("dyn");
     for (dev_kind, feat_list) in [
         ("net", NET_DEV_FEATURES),
@@ -51,12 +131,17 @@ fn main() {
 
         let mut selected = false;
         for feat in feat_list {
-            if has_feature(feat) {
-                enable_cfg(&format!("{dev_kind}_dev"), feat);
-                selected = true;
-                if !is_dyn {
-                    break;
-                }
+            if !has_feature(feat) {
+                continue;
+            }
+            if !lists.allows(feat) {
+                println!("cargo:warning=driver `{feat}` is excluded by the platform config");
+                continue;
+            }
+            enable_cfg(&format!("{dev_kind}_dev"), feat);
+            selected = true;
+            if !is_dyn {
+                break;
             }
         }
         if !is_dyn && !selected {
@@ -64,6 +149,32 @@ fn main() {
         }
     }
 
+    // The image the test disk starts out with
+    println!("cargo:rerun-if-env-changed=TEST_DISK_IMAGE");
+    if has_feature("test-block")
+        && let Ok(image) = std::env::var("TEST_DISK_IMAGE")
+    {
+        let image = std::fs::canonicalize(&image)
+            .unwrap_or_else(|e| panic!("failed to find TEST_DISK_IMAGE {image}: {e}"));
+        println!("cargo:rerun-if-changed={}", image.display());
+        println!("cargo:rustc-env=TEST_DISK_IMAGE={}", image.display());
+        println!("cargo:rustc-cfg=test_disk_image");
+    }
+    println!("cargo::rustc-check-cfg=cfg(test_disk_image)");
+
+    // The image the RAM disk starts out with, e.g. the root filesystem
+    println!("cargo:rerun-if-env-changed=RAMDISK_IMAGE");
+    if has_feature("ramdisk")
+        && let Ok(image) = std::env::var("RAMDISK_IMAGE")
+    {
+        let image = std::fs::canonicalize(&image)
+            .unwrap_or_else(|e| panic!("failed to find RAMDISK_IMAGE {image}: {e}"));
+        println!("cargo:rerun-if-changed={}", image.display());
+        println!("cargo:rustc-env=RAMDISK_IMAGE={}", image.display());
+        println!("cargo:rustc-cfg=ramdisk_image");
+    }
+    println!("cargo::rustc-check-cfg=cfg(ramdisk_image)");
+
     println!(
         "cargo::rustc-check-cfg=cfg(bus, values({}))",
         make_cfg_values(&["pci", "mmio"])
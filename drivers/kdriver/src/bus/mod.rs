@@ -7,3 +7,14 @@
 mod mmio;
 #[cfg(bus = "pci")]
 mod pci;
+
+#[cfg(bus = "pci")]
+pub use self::pci::pci_memory_bar;
+
+/// Without a PCI bus, there is no function to find.
+#[cfg(not(bus = "pci"))]
+pub fn pci_memory_bar(_vendor_id: u16, _device_id: u16, _bar: u8) -> Option<(usize, usize)> {
+    None
+}
+#[cfg(bus = "pci")]
+pub(crate) use self::pci::pci_root;
@@ -103,22 +103,45 @@ fn config_pci_device<C: ConfigurationAccess>(
     Ok(())
 }
 
+/// Returns the root complex, to access the configuration space.
+pub(crate) fn pci_root() -> PciRoot<MmioCam<'static>> {
+    let base_vaddr = p2v((kbuild_config::PCI_ECAM_BASE as usize).into());
+    #[cfg(feature = "pci-mmio")]
+    {
+        let cam = unsafe { MmioCam::new(base_vaddr.as_mut_ptr(), Cam::MmioCam) };
+        PciRoot::new(cam)
+    }
+    #[cfg(not(feature = "pci-mmio"))]
+    {
+        let cam = unsafe { MmioCam::new(base_vaddr.as_mut_ptr(), Cam::Ecam) };
+        PciRoot::new(cam)
+    }
+}
+
+/// Returns the physical address and size of the memory BAR `bar` of the
+/// first PCI function with the given IDs.
+///
+/// For functions no driver claims, e.g. those mapped by other subsystems;
+/// the BAR is assigned once [`init_drivers`](crate::init_drivers) probed the
+/// bus.
+pub fn pci_memory_bar(vendor_id: u16, device_id: u16, bar: u8) -> Option<(usize, usize)> {
+    let mut root = pci_root();
+    for bus in 0..=kbuild_config::PCI_BUS_END as u8 {
+        let found = root
+            .enumerate_bus(bus)
+            .find(|(_, info)| info.vendor_id == vendor_id && info.device_id == device_id);
+        if let Some((bdf, _)) = found {
+            let (address, size) = root.bar_info(bdf, bar).ok()??.memory_address_size()?;
+            return (address > 0 && size > 0).then_some((address as usize, size as usize));
+        }
+    }
+    None
+}
+
 impl AllDevices {
     /// Enumerate PCI devices and register matching drivers.
     pub(crate) fn probe_bus_devices(&mut self) {
-        let base_vaddr = p2v((kbuild_config::PCI_ECAM_BASE as usize).into() );
-        let mut root = {
-            #[cfg(feature = "pci-mmio")]
-            {
-                let cam = unsafe { MmioCam::new(base_vaddr.as_mut_ptr(), Cam::MmioCam) };
-                PciRoot::new(cam)
-            }
-            #[cfg(not(feature = "pci-mmio"))]
-            {
-                let cam = unsafe { MmioCam::new(base_vaddr.as_mut_ptr(), Cam::Ecam) };
-                PciRoot::new(cam)
-            }
-        };
+        let mut root = pci_root();
 
         // PCI 32-bit MMIO space
         let mut allocator = kbuild_config::PCI_RANGES
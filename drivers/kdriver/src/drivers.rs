@@ -74,12 +74,47 @@ cfg_if::cfg_if! {
         pub struct RamDiskDriver;
         register_block_driver!(RamDiskDriver, block::ramdisk::RamDisk);
 
+        /// Size of the RAM disk, unless its image is larger.
+        const RAMDISK_SIZE: usize = 0x100_0000; // 16 MiB
+
         impl DriverProbe for RamDiskDriver {
+            /// Creates the RAM disk, holding the image named by `RAMDISK_IMAGE`
+            /// at build time, or blank without one.
+            fn probe_global() -> Option<DeviceEnum> {
+                #[cfg(ramdisk_image)]
+                let disk = block::ramdisk::RamDisk::from_image(
+                    include_bytes!(env!("RAMDISK_IMAGE")),
+                    RAMDISK_SIZE,
+                );
+                #[cfg(not(ramdisk_image))]
+                let disk = block::ramdisk::RamDisk::new(RAMDISK_SIZE);
+                Some(DeviceEnum::from_block(disk))
+            }
+        }
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(block_dev = "test-block")] {
+        pub struct TestBlockDriver;
+        register_block_driver!(TestBlockDriver, block::fake::FakeDisk);
+
+        impl DriverProbe for TestBlockDriver {
+            fn probe_global() -> Option<DeviceEnum> {
+                Some(DeviceEnum::from_block(crate::test_devices::new_disk()))
+            }
+        }
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(net_dev = "test-net")] {
+        pub struct TestNetDriver;
+        register_net_driver!(TestNetDriver, net::VirtualNic);
+
+        impl DriverProbe for TestNetDriver {
             fn probe_global() -> Option<DeviceEnum> {
-                // TODO: format RAM disk
-                Some(DeviceEnum::from_block(
-                    block::ramdisk::RamDisk::new(0x100_0000), // 16 MiB
-                ))
+                Some(DeviceEnum::from_net(crate::test_devices::new_nic()))
             }
         }
     }
@@ -167,8 +202,14 @@ cfg_if::cfg_if! {
 cfg_if::cfg_if! {
     if #[cfg(net_dev = "ixgbe")] {
         use crate::ixgbe::IxgbeHalImpl;
+
+        /// Descriptors per ring.
+        const IXGBE_QS: usize = 512;
+        /// Number of RX rings.
+        const IXGBE_QN: u16 = 4;
+
         pub struct IxgbeDriver;
-        register_net_driver!(IxgbeDriver, net::ixgbe::IxgbeNic<IxgbeHalImpl, 1024, 1>);
+        register_net_driver!(IxgbeDriver, net::ixgbe::IxgbeNic<IxgbeHalImpl, IXGBE_QS, IXGBE_QN>);
         impl DriverProbe for IxgbeDriver {
             #[cfg(bus = "pci")]
             fn probe_pci<C: pci::ConfigurationAccess>(
@@ -177,32 +218,30 @@ cfg_if::cfg_if! {
                 dev_info: &pci::DeviceFunctionInfo,
             ) -> Option<crate::DeviceEnum> {
                 use net::ixgbe::{INTEL_82599, INTEL_VEND, IxgbeNic};
-                if dev_info.vendor_id == INTEL_VEND && dev_info.device_id == INTEL_82599 {
-                    // Intel 10Gb Network
-                    info!("ixgbe PCI device found at {:?}", bdf);
-
-                    // Initialize the device
-                    // These can be changed according to the requirments specified in the ixgbe init
-                    // function.
-                    const QN: u16 = 1;
-                    const QS: usize = 1024;
-                    let bar_info = root.bar_info(bdf, 0).unwrap();
-                    match bar_info {
-                        pci::BarInfo::Memory { address, size, .. } => {
-                            let ixgbe_nic = IxgbeNic::<IxgbeHalImpl, QS, QN>::init(
-                                khal::mem::p2v((address as usize).into()).into(),
-                                size as usize,
-                            )
-                            .expect("failed to initialize ixgbe device");
-                            return Some(DeviceEnum::from_net(ixgbe_nic));
-                        }
-                        pci::BarInfo::IO { .. } => {
-                            error!("ixgbe: BAR0 is of I/O type");
-                            return None;
+                if dev_info.vendor_id != INTEL_VEND || dev_info.device_id != INTEL_82599 {
+                    return None;
+                }
+                info!("ixgbe PCI device found at {:?}", bdf);
+
+                match root.bar_info(bdf, 0).ok().flatten() {
+                    Some(pci::BarInfo::Memory { address, size, .. }) if address != 0 => {
+                        let base = khal::mem::p2v((address as usize).into());
+                        match IxgbeNic::<IxgbeHalImpl, IXGBE_QS, IXGBE_QN>::init(
+                            base.as_usize(),
+                            size as usize,
+                        ) {
+                            Ok(nic) => Some(DeviceEnum::from_net(nic)),
+                            Err(e) => {
+                                warn!("failed to initialize ixgbe device at {bdf}: {e:?}");
+                                None
+                            }
                         }
                     }
+                    _ => {
+                        error!("ixgbe: BAR0 is not an assigned memory BAR");
+                        None
+                    }
                 }
-                None
             }
         }
     }
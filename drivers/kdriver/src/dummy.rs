@@ -170,10 +170,11 @@ cfg_if! {
             fn guest_cid(&self) -> u64 {
                 unimplemented!()
             }
-            fn listen(&mut self, _src_port: u32) {
-                unimplemented!()
+            fn listen(&mut self, _src_port: u32, _ty: VsockSocketType) -> DriverResult<()> {
+                Err(DriverError::Unsupported)
             }
-            fn connect(&mut self, _cid: VsockConnId) -> DriverResult<()> {
+            fn unlisten(&mut self, _src_port: u32) {}
+            fn connect(&mut self, _cid: VsockConnId, _ty: VsockSocketType) -> DriverResult<()> {
                 Err(DriverError::Unsupported)
             }
             fn send(&mut self, _cid: VsockConnId, _buf: &[u8]) -> DriverResult<usize> {
@@ -2,17 +2,23 @@
 // Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
 // See LICENSES for license details.
 
-use kdma::{DmaBusAddress, DMAInfo, allocate_dma_memory, deallocate_dma_memory};
-use net::ixgbe::{IxgbeHal, PhysAddr as IxgbePhysAddr};
+//! ixgbe driver glue.
+use core::{alloc::Layout, ptr::NonNull, time::Duration};
+
+use kdma::{DMAInfo, DmaBusAddress, allocate_dma_memory, deallocate_dma_memory};
 use khal::mem::{p2v, v2p};
-use core::{alloc::Layout, ptr::NonNull};
+use net::ixgbe::{IxgbeHal, PhysAddr as IxgbePhysAddr};
+
+/// Descriptor rings must be 128-byte aligned; pages keep large regions
+/// contiguous.
+const DMA_ALIGN: usize = 0x1000;
 
 /// HAL implementation for the ixgbe driver.
 pub struct IxgbeHalImpl;
 
 unsafe impl IxgbeHal for IxgbeHalImpl {
     fn dma_alloc(size: usize) -> (IxgbePhysAddr, NonNull<u8>) {
-        let layout = Layout::from_size_align(size, 8).unwrap();
+        let layout = Layout::from_size_align(size, DMA_ALIGN).unwrap();
         match unsafe { allocate_dma_memory(layout) } {
             Ok(dma_info) => (dma_info.bus_addr.as_u64() as usize, dma_info.cpu_addr),
             Err(_) => (0, NonNull::dangling()),
@@ -20,7 +26,7 @@ unsafe impl IxgbeHal for IxgbeHalImpl {
     }
 
     unsafe fn dma_dealloc(paddr: IxgbePhysAddr, vaddr: NonNull<u8>, size: usize) -> i32 {
-        let layout = Layout::from_size_align(size, 8).unwrap();
+        let layout = Layout::from_size_align(size, DMA_ALIGN).unwrap();
         let dma_info = DMAInfo {
             cpu_addr: vaddr,
             bus_addr: DmaBusAddress::from(paddr as u64),
@@ -37,8 +43,7 @@ unsafe impl IxgbeHal for IxgbeHalImpl {
         v2p((vaddr.as_ptr() as usize).into()).into()
     }
 
-    fn wait_until(duration: core::time::Duration) -> Result<(), &'static str> {
-        khal::time::busy_wait_until(duration);
-        Ok(())
+    fn wait(duration: Duration) {
+        khal::time::busy_wait(duration);
     }
 }
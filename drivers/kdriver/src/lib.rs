@@ -8,6 +8,9 @@
 //!
 //! Device categories: [`NetDevice`], [`BlockDevice`], [`DisplayDevice`].
 //!
+//! Every device probed is also listed by [`probed_devices`], for
+//! introspection once the devices have been handed over to their subsystems.
+//!
 //! Supports static and dynamic device models via the `dyn` feature.
 
 #![no_std]
@@ -16,6 +19,7 @@
 
 #[macro_use]
 extern crate log;
+extern crate alloc;
 
 #[macro_use]
 mod macros;
@@ -28,10 +32,16 @@ mod structs;
 #[cfg(feature = "virtio")]
 mod virtio;
 
-// #[cfg(feature = "ixgbe")]
-// mod ixgbe;
+#[cfg(feature = "ixgbe")]
+mod ixgbe;
 
 pub mod prelude;
+#[cfg(any(block_dev = "test-block", net_dev = "test-net"))]
+pub mod test_devices;
+
+use alloc::{string::String, vec::Vec};
+
+use kspin::SpinNoIrq;
 
 #[allow(unused_imports)]
 use self::prelude::*;
@@ -41,7 +51,25 @@ pub use self::structs::BlockDevice;
 pub use self::structs::DisplayDevice;
 #[cfg(feature = "net")]
 pub use self::structs::NetDevice;
-pub use self::structs::{DeviceContainer, DeviceEnum};
+pub use self::{
+    bus::pci_memory_bar,
+    structs::{DeviceContainer, DeviceEnum},
+};
+
+/// A device probed by [`init_drivers`].
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub kind: DeviceKind,
+    /// Name of the driver.
+    pub name: String,
+}
+
+static PROBED_DEVICES: SpinNoIrq<Vec<DeviceInfo>> = SpinNoIrq::new(Vec::new());
+
+/// Returns the devices probed so far, in probing order.
+pub fn probed_devices() -> Vec<DeviceInfo> {
+    PROBED_DEVICES.lock().clone()
+}
 
 /// A structure that contains all device drivers, organized by their category.
 #[derive(Default)]
@@ -87,6 +115,10 @@ impl AllDevices {
     /// Adds device to corresponding container.
     #[allow(dead_code)]
     fn add_device(&mut self, dev: DeviceEnum) {
+        PROBED_DEVICES.lock().push(DeviceInfo {
+            kind: dev.device_kind(),
+            name: dev.name().into(),
+        });
         match dev {
             #[cfg(feature = "net")]
             DeviceEnum::Net(dev) => self.net.push(dev),
@@ -82,6 +82,16 @@ macro_rules! for_each_drivers {
             type $drv_type = <virtio::VirtIoSocket as VirtIoDevMeta>::Driver;
             $code
         }
+        #[cfg(block_dev = "test-block")]
+        {
+            type $drv_type = crate::drivers::TestBlockDriver;
+            $code
+        }
+        #[cfg(net_dev = "test-net")]
+        {
+            type $drv_type = crate::drivers::TestNetDriver;
+            $code
+        }
         #[cfg(block_dev = "ramdisk")]
         {
             type $drv_type = crate::drivers::RamDiskDriver;
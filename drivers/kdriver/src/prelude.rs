@@ -4,13 +4,19 @@
 
 //! Device driver prelude that includes some traits and types.
 
-pub use driver_base::{DeviceKind, DriverError, DriverOps, DriverResult};
+pub use driver_base::{DeviceKind, DriverError, DriverOps, DriverResult, IoPolicy};
 #[cfg(feature = "block")]
-pub use {crate::structs::BlockDevice, block::BlockDriverOps};
+pub use {
+    crate::structs::BlockDevice,
+    block::{
+        BlockDriverOps, BlockIo, BlockOp, Deadline, IoClass, IoPriority, IoToken, Noop, Partition,
+        PartitionInfo, PartitionType, RequestQueue, scan_partitions,
+    },
+};
 #[cfg(feature = "display")]
 pub use {
     crate::structs::DisplayDevice,
-    display::{DisplayDriverOps, DisplayInfo},
+    display::{BlankTimer, DisplayDriverOps, DisplayInfo},
 };
 #[cfg(feature = "input")]
 pub use {
@@ -20,10 +26,13 @@ pub use {
 #[cfg(feature = "net")]
 pub use {
     crate::structs::NetDevice,
-    net::{NetBufHandle, NetDriverOps},
+    net::{
+        Bridge, LinkChangeHook, MacAddress, NetBufHandle, NetCapabilities, NetDriverOps,
+        RxNotifyHook, VirtualNic,
+    },
 };
 #[cfg(feature = "vsock")]
 pub use {
     crate::structs::VsockDevice,
-    vsock::{VsockAddr, VsockConnId, VsockDriverEventType, VsockDriverOps},
+    vsock::{VsockAddr, VsockConnId, VsockDriverEventType, VsockDriverOps, VsockSocketType},
 };
@@ -0,0 +1,74 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! In-memory devices replacing the real ones for tests.
+//!
+//! With the `test-block` feature the block device is a [`FakeDisk`] holding
+//! the image named by `TEST_DISK_IMAGE` at build time, or a blank 16 MiB disk
+//! without one. Tests inject latency and errors through [`disk_faults`].
+//!
+//! With the `test-net` feature the NIC is one end of a [`VirtualNic`] pair.
+//! Tests take the other end with [`take_nic_peer`] and play the rest of the
+//! network on it.
+
+#[cfg(block_dev = "test-block")]
+use alloc::sync::Arc;
+
+#[cfg(block_dev = "test-block")]
+use block::fake::{DiskFaults, FakeDisk};
+use kspin::SpinNoIrq;
+#[cfg(net_dev = "test-net")]
+use net::{MacAddress, VirtualNic};
+
+/// Sector size of the test disk.
+#[cfg(block_dev = "test-block")]
+const DISK_BLOCK_SIZE: usize = 512;
+/// Size of the test disk without an image.
+#[cfg(block_dev = "test-block")]
+const BLANK_DISK_SIZE: u64 = 0x100_0000;
+
+/// MAC addresses of the NIC and of its peer.
+#[cfg(net_dev = "test-net")]
+const NIC_MACS: (MacAddress, MacAddress) = (
+    MacAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]),
+    MacAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x57]),
+);
+
+#[cfg(block_dev = "test-block")]
+static DISK_FAULTS: SpinNoIrq<Option<Arc<DiskFaults>>> = SpinNoIrq::new(None);
+
+#[cfg(net_dev = "test-net")]
+static NIC_PEER: SpinNoIrq<Option<VirtualNic>> = SpinNoIrq::new(None);
+
+/// Creates the test disk.
+#[cfg(block_dev = "test-block")]
+pub(crate) fn new_disk() -> FakeDisk {
+    #[cfg(test_disk_image)]
+    let disk = FakeDisk::from_image(include_bytes!(env!("TEST_DISK_IMAGE")), DISK_BLOCK_SIZE);
+    #[cfg(not(test_disk_image))]
+    let disk = FakeDisk::new(BLANK_DISK_SIZE / DISK_BLOCK_SIZE as u64, DISK_BLOCK_SIZE);
+    let disk = disk.with_delay(khal::time::busy_wait);
+    *DISK_FAULTS.lock() = Some(disk.faults());
+    disk
+}
+
+/// Returns the fault controls of the test disk, once probed.
+#[cfg(block_dev = "test-block")]
+pub fn disk_faults() -> Option<Arc<DiskFaults>> {
+    DISK_FAULTS.lock().clone()
+}
+
+/// Creates the test NIC, keeping its peer for [`take_nic_peer`].
+#[cfg(net_dev = "test-net")]
+pub(crate) fn new_nic() -> VirtualNic {
+    let (nic, peer) = VirtualNic::new_pair(NIC_MACS.0, NIC_MACS.1);
+    *NIC_PEER.lock() = Some(peer);
+    nic
+}
+
+/// Takes the NIC connected to the test NIC, once probed.
+#[cfg(net_dev = "test-net")]
+pub fn take_nic_peer() -> Option<VirtualNic> {
+    NIC_PEER.lock().take()
+}
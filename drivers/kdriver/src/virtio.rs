@@ -10,7 +10,7 @@ use driver_base::{DeviceKind, DriverOps, DriverResult};
 use khal::mem::p2v;
 #[cfg(feature = "crosvm")]
 use khal::psci::{dma_share, dma_unshare};
-use virtio::{BufferDirection, PhysAddr, VirtIoHal};
+use virtio::{BufferDirection, PhysAddr, Reconnect, VirtIoHal};
 
 use crate::{DeviceEnum, drivers::DriverProbe};
 
@@ -34,7 +34,14 @@ pub trait VirtIoDevMeta {
     type Driver = VirtIoDriver<Self>;
 
     /// Try to construct a driver instance from a transport and optional IRQ.
-    fn try_new(transport: VirtIoTransport, irq: Option<usize>) -> DriverResult<DeviceEnum>;
+    ///
+    /// `reconnect` gives new transports to the device, for drivers able to
+    /// reset it.
+    fn try_new(
+        transport: VirtIoTransport,
+        irq: Option<usize>,
+        reconnect: Reconnect<VirtIoTransport>,
+    ) -> DriverResult<DeviceEnum>;
 }
 
 cfg_if! {
@@ -45,8 +52,13 @@ cfg_if! {
             const DEVICE_TYPE: DeviceKind = DeviceKind::Net;
             type Device = virtio::VirtIoNetDev<VirtIoHalImpl, VirtIoTransport, 64>;
 
-            fn try_new(transport: VirtIoTransport, irq: Option<usize>) -> DriverResult<DeviceEnum> {
-                Ok(DeviceEnum::from_net(Self::Device::try_new(transport, irq)?))
+            fn try_new(
+                transport: VirtIoTransport,
+                irq: Option<usize>,
+                reconnect: Reconnect<VirtIoTransport>,
+            ) -> DriverResult<DeviceEnum> {
+                let dev = Self::Device::try_new(transport, irq)?.with_reconnect(reconnect);
+                Ok(DeviceEnum::from_net(dev))
             }
         }
     }
@@ -60,8 +72,16 @@ cfg_if! {
             const DEVICE_TYPE: DeviceKind = DeviceKind::Block;
             type Device = virtio::VirtIoBlkDev<VirtIoHalImpl, VirtIoTransport>;
 
-            fn try_new(transport: VirtIoTransport, _irq: Option<usize>) -> DriverResult<DeviceEnum> {
-                Ok(DeviceEnum::from_block(Self::Device::try_new(transport)?))
+            fn try_new(
+                transport: VirtIoTransport,
+                irq: Option<usize>,
+                reconnect: Reconnect<VirtIoTransport>,
+            ) -> DriverResult<DeviceEnum> {
+                let dev = Self::Device::try_new(transport)?
+                    .with_irq(irq)
+                    .with_reconnect(reconnect)
+                    .with_clock(khal::time::monotonic_time);
+                Ok(DeviceEnum::from_block(dev))
             }
         }
     }
@@ -75,7 +95,11 @@ cfg_if! {
             const DEVICE_TYPE: DeviceKind = DeviceKind::Display;
             type Device = virtio::VirtIoGpuDev<VirtIoHalImpl, VirtIoTransport>;
 
-            fn try_new(transport: VirtIoTransport, _irq: Option<usize>) -> DriverResult<DeviceEnum> {
+            fn try_new(
+                transport: VirtIoTransport,
+                _irq: Option<usize>,
+                _reconnect: Reconnect<VirtIoTransport>,
+            ) -> DriverResult<DeviceEnum> {
                 Ok(DeviceEnum::from_display(Self::Device::try_new(transport)?))
             }
         }
@@ -90,7 +114,11 @@ cfg_if! {
             const DEVICE_TYPE: DeviceKind = DeviceKind::Input;
             type Device = virtio::VirtIoInputDev<VirtIoHalImpl, VirtIoTransport>;
 
-            fn try_new(transport: VirtIoTransport, _irq: Option<usize>) -> DriverResult<DeviceEnum> {
+            fn try_new(
+                transport: VirtIoTransport,
+                _irq: Option<usize>,
+                _reconnect: Reconnect<VirtIoTransport>,
+            ) -> DriverResult<DeviceEnum> {
                 Ok(DeviceEnum::from_input(Self::Device::try_new(transport)?))
             }
         }
@@ -105,7 +133,11 @@ cfg_if! {
             const DEVICE_TYPE: DeviceKind = DeviceKind::Vsock;
             type Device = virtio::VirtIoSocketDev<VirtIoHalImpl, VirtIoTransport>;
 
-            fn try_new(transport: VirtIoTransport, _irq:  Option<usize>) -> DriverResult<DeviceEnum> {
+            fn try_new(
+                transport: VirtIoTransport,
+                _irq: Option<usize>,
+                _reconnect: Reconnect<VirtIoTransport>,
+            ) -> DriverResult<DeviceEnum> {
                 Ok(DeviceEnum::from_vsock(Self::Device::try_new(transport)?))
             }
         }
@@ -122,7 +154,10 @@ impl<D: VirtIoDevMeta> DriverProbe for VirtIoDriver<D> {
         if let Some((ty, transport)) = virtio::probe_mmio_device(base_vaddr.as_mut_ptr(), mmio_size)
             && ty == D::DEVICE_TYPE
         {
-            match D::try_new(transport, None) {
+            let reconnect = move || {
+                virtio::probe_mmio_device(base_vaddr.as_mut_ptr(), mmio_size).map(|(_, it)| it)
+            };
+            match D::try_new(transport, None, alloc::boxed::Box::new(reconnect)) {
                 Ok(dev) => return Some(dev),
                 Err(e) => {
                     warn!(
@@ -160,7 +195,16 @@ impl<D: VirtIoDevMeta> DriverProbe for VirtIoDriver<D> {
             virtio::probe_pci_device::<VirtIoHalImpl, C>(root, bdf, dev_info)
             && ty == D::DEVICE_TYPE
         {
-            match D::try_new(transport, Some(irq)) {
+            let info = dev_info.clone();
+            let reconnect = move || {
+                virtio::probe_pci_device::<VirtIoHalImpl, _>(
+                    &mut crate::bus::pci_root(),
+                    bdf,
+                    &info,
+                )
+                .map(|(_, it, _)| it)
+            };
+            match D::try_new(transport, Some(irq), alloc::boxed::Box::new(reconnect)) {
                 Ok(dev) => return Some(dev),
                 Err(e) => {
                     warn!("failed to initialize PCI device at {bdf}({dev_info}): {e:?}");
@@ -231,6 +275,11 @@ unsafe impl VirtIoHal for VirtIoHalImpl {
     unsafe fn share(buffer: NonNull<[u8]>, direction: BufferDirection) -> PhysAddr {
         #[cfg(any(feature = "sev", feature = "crosvm"))]
         {
+            // Coherent memory, e.g. network buffers, is shared already
+            if let Some(bus_addr) = kdma::coherent_bus_addr(buffer.cast(), buffer.len()) {
+                return bus_addr.as_u64() as PhysAddr;
+            }
+
             use core::{
                 alloc::Layout,
                 sync::atomic::{Ordering, fence},
@@ -283,6 +332,11 @@ unsafe impl VirtIoHal for VirtIoHalImpl {
     unsafe fn unshare(paddr: PhysAddr, buffer: NonNull<[u8]>, direction: BufferDirection) {
         #[cfg(any(feature = "sev", feature = "crosvm"))]
         {
+            // No bounce buffer was set up by `share`
+            if kdma::coherent_bus_addr(buffer.cast(), buffer.len()).is_some() {
+                return;
+            }
+
             use core::{
                 alloc::Layout,
                 sync::atomic::{Ordering, fence},
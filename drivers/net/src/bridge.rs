@@ -0,0 +1,398 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Software L2 bridge.
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec::Vec};
+use core::time::Duration;
+
+use spin::Mutex;
+
+use crate::{
+    DriverError, MacAddress, NetDriverOps,
+    vnic::{Endpoint, VirtualNic},
+};
+
+/// Maximum number of learned addresses.
+const FDB_LEN: usize = 4096;
+
+/// A learning Ethernet bridge.
+///
+/// A port is either a [`VirtualNic`] created by [`Bridge::add_port`], or a
+/// NIC enslaved with [`Bridge::add_nic`]. The bridge learns the port behind
+/// each source address, forwards unicast frames to the learned port, and
+/// floods broadcast, multicast and unknown unicast frames to all other ports.
+///
+/// Frames sent by a virtual NIC are forwarded at once, while those received
+/// by an enslaved NIC wait for [`Bridge::poll`]. Enslaved NICs are not
+/// switched to promiscuous mode; those that filter by destination address
+/// only pass frames for themselves.
+///
+/// Learned addresses move when they show up on another port and are
+/// forgotten with their port. A bridge created by [`Bridge::with_ageing`]
+/// also forgets those without traffic for the ageing time.
+#[derive(Clone, Default)]
+pub struct Bridge {
+    pub(crate) inner: Arc<BridgeInner>,
+}
+
+impl Bridge {
+    /// Creates a bridge without ports.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a bridge without ports, forgetting the addresses without
+    /// traffic for `time` as measured by `clock`.
+    pub fn with_ageing(time: Duration, clock: fn() -> Duration) -> Self {
+        Self {
+            inner: Arc::new(BridgeInner {
+                state: Mutex::default(),
+                ageing: Some(Ageing { time, clock }),
+            }),
+        }
+    }
+
+    /// Adds a port and returns the NIC connected to it.
+    pub fn add_port(&self, mac: MacAddress) -> VirtualNic {
+        let rx = Arc::new(Endpoint::default());
+        let port = self.inner.state.lock().add(Port::Virtual(rx.clone()));
+        VirtualNic::new_bridge_port(mac, self, rx, port)
+    }
+
+    /// Enslaves `dev` as a port, returning the port.
+    pub fn add_nic(&self, dev: impl NetDriverOps + 'static) -> usize {
+        self.inner.state.lock().add(Port::Nic(Box::new(dev)))
+    }
+
+    /// Releases the NIC enslaved as `port`, forgetting the addresses learned
+    /// on it.
+    ///
+    /// Returns `None` if `port` is not an enslaved NIC.
+    pub fn remove_nic(&self, port: usize) -> Option<Box<dyn NetDriverOps>> {
+        let mut state = self.inner.state.lock();
+        if !matches!(state.ports.get(port), Some(Some(Port::Nic(_)))) {
+            return None;
+        }
+        match state.remove(port) {
+            Some(Port::Nic(dev)) => Some(dev),
+            _ => None,
+        }
+    }
+
+    /// Forwards the frames received by the enslaved NICs, returning whether
+    /// there were any.
+    pub fn poll(&self) -> bool {
+        let now = self.inner.now();
+        let mut state = self.inner.state.lock();
+        let mut forwarded = false;
+        for port in 0..state.ports.len() {
+            let Some(Some(Port::Nic(dev))) = state.ports.get_mut(port) else {
+                continue;
+            };
+            dev.ack_interrupt();
+            let budget = dev.rx_queue_len();
+            // Bounded so that a busy port cannot starve the others
+            for _ in 0..budget {
+                let Some(Some(Port::Nic(dev))) = state.ports.get_mut(port) else {
+                    break;
+                };
+                let rx_buf = match dev.recv() {
+                    Ok(buf) => buf,
+                    Err(DriverError::WouldBlock) => break,
+                    Err(err) => {
+                        log::warn!("bridge: port {port} recv failed: {err:?}");
+                        break;
+                    }
+                };
+                let frame = rx_buf.data().to_vec();
+                if let Err(err) = dev.recycle_rx(rx_buf) {
+                    log::warn!("bridge: port {port} recycle_rx failed: {err:?}");
+                }
+                state.forward(port, &frame, now, self.inner.ageing.as_ref());
+                forwarded = true;
+            }
+        }
+        forwarded
+    }
+
+    /// Calls `f` with each enslaved NIC and its port.
+    pub fn for_each_nic(&self, mut f: impl FnMut(usize, &mut dyn NetDriverOps)) {
+        for (port, slot) in self.inner.state.lock().ports.iter_mut().enumerate() {
+            if let Some(Port::Nic(dev)) = slot {
+                f(port, dev.as_mut());
+            }
+        }
+    }
+
+    /// Returns the port that `mac` was learned on.
+    pub fn lookup(&self, mac: MacAddress) -> Option<usize> {
+        let now = self.inner.now();
+        let state = self.inner.state.lock();
+        let entry = state.fdb.get(&mac.0)?;
+        entry
+            .is_live(now, self.inner.ageing.as_ref())
+            .then_some(entry.port)
+    }
+
+    /// Returns the number of ports.
+    pub fn port_count(&self) -> usize {
+        let state = self.inner.state.lock();
+        state.ports.iter().filter(|port| port.is_some()).count()
+    }
+}
+
+/// A port of a bridge.
+enum Port {
+    /// The receiving side of a [`VirtualNic`].
+    Virtual(Arc<Endpoint>),
+    /// An enslaved NIC.
+    Nic(Box<dyn NetDriverOps>),
+}
+
+impl Port {
+    /// Sends a copy of `frame` out of the port `port`.
+    fn transmit(&mut self, port: usize, frame: &[u8]) {
+        match self {
+            Port::Virtual(endpoint) => {
+                if !endpoint.deliver(frame) {
+                    log::debug!("bridge: port {port} queue full, dropping frame");
+                }
+            }
+            Port::Nic(dev) => {
+                if !dev.link_up() {
+                    return;
+                }
+                let result = dev.recycle_tx().and_then(|()| {
+                    let mut tx_buf = dev.alloc_tx_buf(frame.len())?;
+                    tx_buf.data_mut().copy_from_slice(frame);
+                    dev.send(tx_buf)
+                });
+                if let Err(err) = result {
+                    log::debug!("bridge: port {port} dropping frame: {err:?}");
+                }
+            }
+        }
+    }
+}
+
+struct Ageing {
+    time: Duration,
+    clock: fn() -> Duration,
+}
+
+struct FdbEntry {
+    port: usize,
+    /// When traffic from the address was last seen.
+    seen: Duration,
+}
+
+impl FdbEntry {
+    fn is_live(&self, now: Duration, ageing: Option<&Ageing>) -> bool {
+        ageing.is_none_or(|ageing| now.saturating_sub(self.seen) < ageing.time)
+    }
+}
+
+#[derive(Default)]
+struct BridgeState {
+    /// Ports by number, `None` for removed ones.
+    ports: Vec<Option<Port>>,
+    /// Forwarding database, mapping addresses to ports.
+    fdb: BTreeMap<[u8; 6], FdbEntry>,
+}
+
+impl BridgeState {
+    fn add(&mut self, port: Port) -> usize {
+        match self.ports.iter().position(Option::is_none) {
+            Some(index) => {
+                self.ports[index] = Some(port);
+                index
+            }
+            None => {
+                self.ports.push(Some(port));
+                self.ports.len() - 1
+            }
+        }
+    }
+
+    fn remove(&mut self, port: usize) -> Option<Port> {
+        self.fdb.retain(|_, entry| entry.port != port);
+        self.ports[port].take()
+    }
+
+    /// Forwards `frame` received on `in_port`.
+    fn forward(&mut self, in_port: usize, frame: &[u8], now: Duration, ageing: Option<&Ageing>) {
+        if frame.len() < 14 {
+            return;
+        }
+        let dst: [u8; 6] = frame[0..6].try_into().unwrap();
+        let src: [u8; 6] = frame[6..12].try_into().unwrap();
+
+        // Group addresses are never valid sources
+        if src[0] & 1 == 0 {
+            if self.fdb.len() >= FDB_LEN && !self.fdb.contains_key(&src) {
+                self.fdb.retain(|_, entry| entry.is_live(now, ageing));
+            }
+            if self.fdb.len() < FDB_LEN || self.fdb.contains_key(&src) {
+                let entry = FdbEntry {
+                    port: in_port,
+                    seen: now,
+                };
+                self.fdb.insert(src, entry);
+            }
+        }
+
+        match self.fdb.get(&dst) {
+            Some(entry) if dst[0] & 1 == 0 && entry.is_live(now, ageing) => {
+                // A frame for the port it came from is already there
+                let port = entry.port;
+                if port != in_port
+                    && let Some(target) = &mut self.ports[port]
+                {
+                    target.transmit(port, frame);
+                }
+            }
+            _ => {
+                for (port, target) in self.ports.iter_mut().enumerate() {
+                    if port != in_port
+                        && let Some(target) = target
+                    {
+                        target.transmit(port, frame);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct BridgeInner {
+    state: Mutex<BridgeState>,
+    ageing: Option<Ageing>,
+}
+
+impl BridgeInner {
+    fn now(&self) -> Duration {
+        self.ageing
+            .as_ref()
+            .map_or(Duration::ZERO, |ageing| (ageing.clock)())
+    }
+
+    /// Forwards `frame` sent by the virtual NIC of `in_port`.
+    pub(crate) fn forward(&self, in_port: usize, frame: &[u8]) {
+        let now = self.now();
+        let ageing = self.ageing.as_ref();
+        self.state.lock().forward(in_port, frame, now, ageing);
+    }
+
+    pub(crate) fn remove_port(&self, port: usize) {
+        self.state.lock().remove(port);
+    }
+}
+
+#[cfg(unittest)]
+mod tests_bridge {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use unittest::def_test;
+
+    use super::*;
+    use crate::NetBuf;
+
+    const MAC_A: MacAddress = MacAddress([0x02, 0, 0, 0, 0, 0xa]);
+    const MAC_B: MacAddress = MacAddress([0x02, 0, 0, 0, 0, 0xb]);
+    const BROADCAST: MacAddress = MacAddress([0xff; 6]);
+
+    fn send(nic: &mut VirtualNic, dst: MacAddress) {
+        let mut tx_buf = nic.alloc_tx_buf(60).unwrap();
+        let frame = tx_buf.data_mut();
+        frame[0..6].copy_from_slice(&dst.0);
+        frame[6..12].copy_from_slice(&nic.mac().0);
+        nic.send(tx_buf).unwrap();
+    }
+
+    /// Returns the source addresses of all received frames.
+    fn drain(nic: &mut VirtualNic) -> Vec<[u8; 6]> {
+        let mut sources = Vec::new();
+        while let Ok(rx_buf) = nic.recv() {
+            sources.push(rx_buf.data()[6..12].try_into().unwrap());
+            drop(unsafe { NetBuf::from_handle(rx_buf) });
+        }
+        sources
+    }
+
+    #[def_test]
+    fn test_bridge_learning() {
+        let bridge = Bridge::new();
+        let mut a = bridge.add_port(MAC_A);
+        let mut b = bridge.add_port(MAC_B);
+        let mut c = bridge.add_port(MacAddress([0x02, 0, 0, 0, 0, 0xc]));
+        assert_eq!(bridge.port_count(), 3);
+
+        // Unknown destinations are flooded, never back to the sender
+        send(&mut a, MAC_B);
+        assert!(drain(&mut a).is_empty());
+        assert_eq!(drain(&mut b), [MAC_A.0]);
+        assert_eq!(drain(&mut c), [MAC_A.0]);
+        assert_eq!(bridge.lookup(MAC_A), Some(0));
+
+        // Learned destinations are not
+        send(&mut b, MAC_A);
+        assert_eq!(drain(&mut a), [MAC_B.0]);
+        assert!(drain(&mut c).is_empty());
+
+        send(&mut c, BROADCAST);
+        assert_eq!(drain(&mut a).len(), 1);
+        assert_eq!(drain(&mut b).len(), 1);
+
+        // Removing a port forgets its addresses
+        drop(a);
+        assert_eq!(bridge.port_count(), 2);
+        assert_eq!(bridge.lookup(MAC_A), None);
+        send(&mut b, MAC_A);
+        assert_eq!(drain(&mut c), [MAC_B.0]);
+    }
+
+    #[def_test]
+    fn test_bridge_nic_ageing() {
+        static NOW: AtomicU64 = AtomicU64::new(0);
+        let clock = || Duration::from_secs(NOW.load(Ordering::Relaxed));
+
+        let bridge = Bridge::with_ageing(Duration::from_secs(300), clock);
+        let mut a = bridge.add_port(MAC_A);
+        let (mut b, slave) = VirtualNic::new_pair(MAC_B, MAC_B);
+        let port = bridge.add_nic(slave);
+        assert_eq!(a.bridge_port(), Some(0));
+        assert_eq!(b.bridge_port(), None);
+
+        // Frames from an enslaved NIC wait for the bridge to be polled
+        send(&mut b, MAC_A);
+        assert!(drain(&mut a).is_empty());
+        assert!(bridge.poll());
+        assert_eq!(drain(&mut a), [MAC_B.0]);
+        assert_eq!(bridge.lookup(MAC_B), Some(port));
+        send(&mut a, MAC_B);
+        assert_eq!(drain(&mut b), [MAC_A.0]);
+
+        NOW.store(300, Ordering::Relaxed);
+        assert_eq!(bridge.lookup(MAC_B), None);
+        assert!(!bridge.poll());
+
+        assert!(bridge.remove_nic(a.bridge_port().unwrap()).is_none());
+        assert!(bridge.remove_nic(port).is_some());
+        assert_eq!(bridge.port_count(), 1);
+    }
+
+    #[def_test]
+    fn test_vnic_pair() {
+        let (mut a, mut b) = VirtualNic::new_pair(MAC_A, MAC_B);
+        assert!(a.link_up() && b.link_up());
+        send(&mut a, MAC_B);
+        assert!(b.can_rx());
+        assert_eq!(drain(&mut b), [MAC_A.0]);
+        assert!(!a.can_rx());
+
+        drop(b);
+        assert!(!a.link_up());
+    }
+}
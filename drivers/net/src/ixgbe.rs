@@ -2,57 +2,613 @@
 // Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
 // See LICENSES for license details.
 
-//! Intel ixgbe NIC driver implementation.
-use alloc::{collections::VecDeque, sync::Arc};
-use core::{convert::From, mem::ManuallyDrop, ptr::NonNull};
+//! Intel 82599 (ixgbe) 10GbE NIC driver.
+//!
+//! The driver polls; it masks all device interrupts. Received packets are
+//! spread over `QN` RX rings by RSS, while all packets are sent on a single
+//! TX ring. Packet buffers come from a [`NetBufPool`] in DMA memory and are
+//! handed to the rings as they are.
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{
+    marker::PhantomData,
+    ptr::NonNull,
+    sync::atomic::{Ordering, fence},
+    time::Duration,
+};
 
 use driver_base::{DeviceKind, DriverError, DriverOps, DriverResult};
-pub use ixgbe_driver::{INTEL_82599, INTEL_VEND, IxgbeHal, PhysAddr};
-use ixgbe_driver::{IxgbeDevice, IxgbeError, IxgbeNetBuf, MemPool, NicDevice};
 use log::*;
 
-use crate::{MacAddress, NetBufHandle, NetDriverOps};
+use crate::{MacAddress, NetBuf, NetBufBox, NetBufHandle, NetBufMemory, NetBufPool, NetDriverOps};
 
-const RECV_BATCH_SIZE: usize = 64;
-const RX_BUFFER_SIZE: usize = 1024;
-const MEM_POOL: usize = 4096;
+/// PCI vendor ID of Intel.
+pub const INTEL_VEND: u16 = 0x8086;
+/// PCI device ID of the 82599 with SFP+ ports.
+pub const INTEL_82599: u16 = 0x10FB;
+
+/// Bus address of DMA memory.
+pub type PhysAddr = usize;
+
+/// Platform services needed by the ixgbe driver.
+///
+/// # Safety
+///
+/// [`IxgbeHal::dma_alloc`] must return memory that stays valid until it is
+/// given back, and that the device accesses at the returned bus address
+/// coherently with the CPU.
+pub unsafe trait IxgbeHal {
+    /// Allocates `size` bytes of coherent DMA memory, aligned to at least
+    /// 128 bytes. Returns its bus and virtual address, with a zero bus
+    /// address on failure.
+    fn dma_alloc(size: usize) -> (PhysAddr, NonNull<u8>);
+
+    /// Frees memory allocated by [`IxgbeHal::dma_alloc`].
+    ///
+    /// # Safety
+    ///
+    /// The memory must not be used afterwards, by the CPU or the device.
+    unsafe fn dma_dealloc(paddr: PhysAddr, vaddr: NonNull<u8>, size: usize) -> i32;
+
+    /// Maps the MMIO region at `paddr`.
+    ///
+    /// # Safety
+    ///
+    /// `paddr` must be the start of an MMIO region of at least `size` bytes.
+    unsafe fn mmio_p2v(paddr: PhysAddr, size: usize) -> NonNull<u8>;
+
+    /// Translates a mapped MMIO address back.
+    ///
+    /// # Safety
+    ///
+    /// `vaddr` must have been returned by [`IxgbeHal::mmio_p2v`].
+    unsafe fn mmio_v2p(vaddr: NonNull<u8>, size: usize) -> PhysAddr;
+
+    /// Busy-waits for `duration`.
+    fn wait(duration: Duration);
+}
+
+/// Length of each packet buffer; also the RX buffer size of the rings.
 const MEM_POOL_ENTRY_SIZE: usize = 2048;
+/// Size of a descriptor, both advanced RX and TX.
+const DESC_LEN: usize = 16;
+/// Polls of a register before giving up, 1ms apart.
+const WAIT_POLLS: usize = 1000;
+/// Maximum number of RX rings RSS spreads packets over.
+const MAX_RX_RINGS: usize = 16;
+
+/// Microsoft's default RSS hash key.
+const RSS_KEY: [u8; 40] = [
+    0x6d, 0x5a, 0x56, 0xda, 0x25, 0x5b, 0x0e, 0xc2, 0x41, 0x67, 0x25, 0x3d, 0x43, 0xa3, 0x8f, 0xb0,
+    0xd0, 0xca, 0x2b, 0xcb, 0xae, 0x7b, 0x30, 0xb4, 0x77, 0xcb, 0x2d, 0xa3, 0x80, 0x30, 0xf2, 0x0c,
+    0x6a, 0x42, 0xb7, 0x3b, 0xbe, 0xac, 0x01, 0xfa,
+];
+
+/// Register offsets and bits, named as in the 82599 datasheet.
+mod regs {
+    pub const CTRL: usize = 0x00000;
+    pub const CTRL_LNK_RST: u32 = 1 << 3;
+    pub const CTRL_RST: u32 = 1 << 26;
+    pub const CTRL_RST_MASK: u32 = CTRL_LNK_RST | CTRL_RST;
+
+    pub const CTRL_EXT: usize = 0x00018;
+    pub const CTRL_EXT_NS_DIS: u32 = 1 << 16;
+
+    pub const EIMC: usize = 0x00888;
+    pub const EIMC_ALL: u32 = 0x7FFF_FFFF;
+
+    pub const EEC: usize = 0x10010;
+    pub const EEC_ARD: u32 = 1 << 9;
+
+    pub const RDRXCTL: usize = 0x02F00;
+    pub const RDRXCTL_CRCSTRIP: u32 = 1 << 1;
+    pub const RDRXCTL_DMAIDONE: u32 = 1 << 3;
+
+    pub const AUTOC: usize = 0x042A0;
+    pub const AUTOC_LMS_MASK: u32 = 0x7 << 13;
+    pub const AUTOC_LMS_10G_SERIAL: u32 = 0x3 << 13;
+    pub const AUTOC_10G_PMA_PMD_MASK: u32 = 0x3 << 7;
+    pub const AUTOC_10G_XAUI: u32 = 0;
+    pub const AUTOC_AN_RESTART: u32 = 1 << 12;
+
+    pub const LINKS: usize = 0x042A4;
+    pub const LINKS_UP: u32 = 1 << 30;
+    pub const LINKS_SPEED_MASK: u32 = 0x3 << 28;
+    pub const LINKS_SPEED_100M: u32 = 0x1 << 28;
+    pub const LINKS_SPEED_1G: u32 = 0x2 << 28;
+    pub const LINKS_SPEED_10G: u32 = 0x3 << 28;
+
+    pub const HLREG0: usize = 0x04240;
+    pub const HLREG0_TXCRCEN: u32 = 1 << 0;
+    pub const HLREG0_RXCRCSTRP: u32 = 1 << 1;
+    pub const HLREG0_TXPADEN: u32 = 1 << 10;
+
+    pub const RXCTRL: usize = 0x03000;
+    pub const RXCTRL_RXEN: u32 = 1 << 0;
+    pub const FCTRL: usize = 0x05080;
+    pub const FCTRL_BAM: u32 = 1 << 10;
+    pub const RXCSUM: usize = 0x05000;
+    pub const RXCSUM_PCSD: u32 = 1 << 13;
+
+    pub const MRQC: usize = 0x05818;
+    pub const MRQC_RSSEN: u32 = 0x1;
+    pub const MRQC_RSS_FIELDS: u32 = (1 << 16) // TCP/IPv4
+        | (1 << 17) // IPv4
+        | (1 << 20) // IPv6
+        | (1 << 21) // TCP/IPv6
+        | (1 << 22) // UDP/IPv4
+        | (1 << 23); // UDP/IPv6
+
+    pub const fn rssrk(i: usize) -> usize {
+        0x05C80 + 4 * i
+    }
+    pub const fn reta(i: usize) -> usize {
+        0x0EB00 + 4 * i
+    }
+    pub const fn rxpbsize(i: usize) -> usize {
+        0x03C00 + 4 * i
+    }
+    pub const RXPBSIZE_128KB: u32 = 0x0002_0000;
+    pub const fn txpbsize(i: usize) -> usize {
+        0x0CC00 + 4 * i
+    }
+    pub const TXPBSIZE_40KB: u32 = 0x0000_A000;
+
+    pub const fn rdbal(i: usize) -> usize {
+        0x01000 + 0x40 * i
+    }
+    pub const fn rdbah(i: usize) -> usize {
+        0x01004 + 0x40 * i
+    }
+    pub const fn rdlen(i: usize) -> usize {
+        0x01008 + 0x40 * i
+    }
+    pub const fn dca_rxctrl(i: usize) -> usize {
+        0x0100C + 0x40 * i
+    }
+    pub const DCA_RXCTRL_RELAX_WR: u32 = 1 << 12;
+    pub const fn rdh(i: usize) -> usize {
+        0x01010 + 0x40 * i
+    }
+    pub const fn srrctl(i: usize) -> usize {
+        0x01014 + 0x40 * i
+    }
+    pub const SRRCTL_BSIZEPKT_MASK: u32 = 0x1F;
+    pub const SRRCTL_DESCTYPE_MASK: u32 = 0x7 << 25;
+    pub const SRRCTL_DESCTYPE_ADV_ONEBUF: u32 = 0x1 << 25;
+    pub const SRRCTL_DROP_EN: u32 = 1 << 28;
+    pub const fn rdt(i: usize) -> usize {
+        0x01018 + 0x40 * i
+    }
+    pub const fn rxdctl(i: usize) -> usize {
+        0x01028 + 0x40 * i
+    }
+
+    pub const DTXMXSZRQ: usize = 0x08100;
+    pub const RTTDCS: usize = 0x04900;
+    pub const RTTDCS_ARBDIS: u32 = 1 << 6;
+    pub const DMATXCTL: usize = 0x04A80;
+    pub const DMATXCTL_TE: u32 = 1 << 0;
+
+    pub const TDBAL: usize = 0x06000;
+    pub const TDBAH: usize = 0x06004;
+    pub const TDLEN: usize = 0x06008;
+    pub const TDH: usize = 0x06010;
+    pub const TDT: usize = 0x06018;
+    pub const TXDCTL: usize = 0x06028;
+    pub const TXDCTL_THRESH_MASK: u32 = 0x3F | (0x3F << 8) | (0x3F << 16);
+    /// Prefetch 36, host 8 and write-back 4 descriptors.
+    pub const TXDCTL_THRESH: u32 = 36 | (8 << 8) | (4 << 16);
+
+    /// Enables an RX or TX queue, in RXDCTL or TXDCTL.
+    pub const DCTL_ENABLE: u32 = 1 << 25;
+
+    pub const RAL0: usize = 0x0A200;
+    pub const RAH0: usize = 0x0A204;
+
+    /// Statistic registers, cleared on read.
+    pub const STATS: [usize; 6] = [0x04074, 0x04080, 0x04088, 0x0408C, 0x04090, 0x04094];
+}
+
+/// Descriptor bits.
+mod desc {
+    /// Descriptor done, in the RX write-back status and the TX write-back.
+    pub const DD: u32 = 1 << 0;
+    /// End of packet, in the RX write-back status.
+    pub const RX_EOP: u32 = 1 << 1;
+    /// MAC error (bad CRC, symbol or length), in the RX write-back status.
+    pub const RX_ERR: u32 = 1 << 29;
+
+    pub const TX_DTYP_DATA: u32 = 0x3 << 20;
+    pub const TX_EOP: u32 = 1 << 24;
+    pub const TX_IFCS: u32 = 1 << 25;
+    pub const TX_RS: u32 = 1 << 27;
+    pub const TX_DEXT: u32 = 1 << 29;
+    pub const TX_PAYLEN_SHIFT: u32 = 14;
+}
+
+/// A contiguous region of DMA memory from the HAL.
+struct DmaRegion<H: IxgbeHal> {
+    paddr: PhysAddr,
+    vaddr: NonNull<u8>,
+    size: usize,
+    _hal: PhantomData<H>,
+}
+
+unsafe impl<H: IxgbeHal> Send for DmaRegion<H> {}
+unsafe impl<H: IxgbeHal> Sync for DmaRegion<H> {}
+
+impl<H: IxgbeHal> DmaRegion<H> {
+    fn new(size: usize) -> DriverResult<Self> {
+        let (paddr, vaddr) = H::dma_alloc(size);
+        if paddr == 0 {
+            return Err(DriverError::NoMemory);
+        }
+        unsafe { vaddr.write_bytes(0, size) };
+        Ok(Self {
+            paddr,
+            vaddr,
+            size,
+            _hal: PhantomData,
+        })
+    }
+
+    /// Reads the two words of descriptor `i`.
+    fn read_desc(&self, i: usize) -> (u64, u64) {
+        let desc = unsafe { self.vaddr.add(i * DESC_LEN).cast::<u64>() };
+        unsafe { (desc.read_volatile(), desc.add(1).read_volatile()) }
+    }
+
+    /// Writes the two words of descriptor `i`.
+    fn write_desc(&self, i: usize, lo: u64, hi: u64) {
+        let desc = unsafe { self.vaddr.add(i * DESC_LEN).cast::<u64>() };
+        unsafe {
+            desc.write_volatile(lo);
+            desc.add(1).write_volatile(hi);
+        }
+    }
+}
+
+impl<H: IxgbeHal> NetBufMemory for DmaRegion<H> {
+    fn as_ptr(&self) -> NonNull<u8> {
+        self.vaddr
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn bus_addr(&self) -> Option<u64> {
+        Some(self.paddr as u64)
+    }
+}
+
+impl<H: IxgbeHal> Drop for DmaRegion<H> {
+    fn drop(&mut self) {
+        unsafe { H::dma_dealloc(self.paddr, self.vaddr, self.size) };
+    }
+}
+
+/// The register space of the NIC.
+struct Regs<H: IxgbeHal> {
+    base: NonNull<u8>,
+    _hal: PhantomData<H>,
+}
+
+impl<H: IxgbeHal> Regs<H> {
+    fn read(&self, reg: usize) -> u32 {
+        unsafe { self.base.add(reg).cast::<u32>().read_volatile() }
+    }
+
+    fn write(&self, reg: usize, value: u32) {
+        unsafe { self.base.add(reg).cast::<u32>().write_volatile(value) }
+    }
+
+    fn set_flags(&self, reg: usize, flags: u32) {
+        self.write(reg, self.read(reg) | flags);
+    }
+
+    fn clear_flags(&self, reg: usize, flags: u32) {
+        self.write(reg, self.read(reg) & !flags);
+    }
+
+    /// Waits until `mask` bits of `reg` all equal `set`.
+    fn wait(&self, reg: usize, mask: u32, set: bool) -> DriverResult {
+        let want = if set { mask } else { 0 };
+        for _ in 0..WAIT_POLLS {
+            if self.read(reg) & mask == want {
+                return Ok(());
+            }
+            H::wait(Duration::from_millis(1));
+        }
+        error!("ixgbe: timeout waiting for register {reg:#x} & {mask:#x} == {want:#x}");
+        Err(DriverError::BadState)
+    }
+}
+
+/// Returns redirection table register `i`, mapping hash buckets round-robin
+/// to `rings` RX rings.
+const fn reta_entry(i: usize, rings: usize) -> u32 {
+    let mut value = 0;
+    let mut j = 0;
+    while j < 4 {
+        value |= (((4 * i + j) % rings) as u32) << (8 * j);
+        j += 1;
+    }
+    value
+}
+
+/// Returns the words of an advanced TX data descriptor for a `len` bytes
+/// packet at `addr`.
+const fn tx_desc(addr: u64, len: usize) -> (u64, u64) {
+    let cmd = desc::TX_EOP | desc::TX_RS | desc::TX_IFCS | desc::TX_DEXT | desc::TX_DTYP_DATA;
+    let olinfo = (len as u32) << desc::TX_PAYLEN_SHIFT;
+    (addr, (cmd | len as u32) as u64 | ((olinfo as u64) << 32))
+}
+
+struct RxRing<H: IxgbeHal> {
+    descs: DmaRegion<H>,
+    bufs: Vec<Option<NetBufBox>>,
+    /// Next descriptor to check.
+    index: usize,
+}
+
+struct TxRing<H: IxgbeHal> {
+    descs: DmaRegion<H>,
+    /// Buffers of packets in flight.
+    bufs: Vec<Option<NetBufBox>>,
+    /// Next descriptor to fill.
+    tail: usize,
+    /// Oldest descriptor in flight.
+    clean: usize,
+}
 
 /// The ixgbe NIC device driver.
 ///
-/// `QS` is the ixgbe queue size, `QN` is the ixgbe queue num.
+/// `QS` is the number of descriptors of each ring, `QN` the number of RX
+/// rings.
 pub struct IxgbeNic<H: IxgbeHal, const QS: usize, const QN: u16> {
-    inner: IxgbeDevice<H, QS>,
-    mem_pool: Arc<MemPool>,
-    rx_buffer_queue: VecDeque<NetBufHandle>,
+    regs: Regs<H>,
+    mac: MacAddress,
+    rx_rings: Vec<RxRing<H>>,
+    tx_ring: TxRing<H>,
+    pool: Arc<NetBufPool>,
+    /// RX ring to look at first, so that no ring starves the others.
+    next_rx: usize,
 }
 
 unsafe impl<H: IxgbeHal, const QS: usize, const QN: u16> Sync for IxgbeNic<H, QS, QN> {}
 unsafe impl<H: IxgbeHal, const QS: usize, const QN: u16> Send for IxgbeNic<H, QS, QN> {}
 
-impl<H: IxgbeHal, const QS: usize, const QN: u16> IxgbeNic<H, QS, QN> {
-    /// Creates a net ixgbe NIC instance and initialize, or returns a error if
-    /// any step fails.
+impl<H: IxgbeHal + 'static, const QS: usize, const QN: u16> IxgbeNic<H, QS, QN> {
+    /// Resets and initializes the NIC whose BAR0 is mapped at `base`, or
+    /// returns an error if any step fails.
     pub fn init(base: usize, len: usize) -> DriverResult<Self> {
-        let mem_pool = MemPool::allocate::<H>(MEM_POOL, MEM_POOL_ENTRY_SIZE)
-            .map_err(|_| DriverError::NoMemory)?;
-        let inner = IxgbeDevice::<H, QS>::init(base, len, QN, QN, &mem_pool).map_err(|err| {
-            error!("Failed to initialize ixgbe device: {err:?}");
-            DriverError::BadState
-        })?;
-
-        let rx_buffer_queue = VecDeque::with_capacity(RX_BUFFER_SIZE);
-        Ok(Self {
-            inner,
-            mem_pool,
-            rx_buffer_queue,
-        })
+        // RDLEN and TDLEN must be multiples of 128 bytes
+        if QN == 0 || QN as usize > MAX_RX_RINGS || QS == 0 || !QS.is_multiple_of(8) {
+            return Err(DriverError::InvalidInput);
+        }
+        let regs = Regs {
+            base: NonNull::new(base as *mut u8).ok_or(DriverError::InvalidInput)?,
+            _hal: PhantomData,
+        };
+        info!("ixgbe: init @ {base:#x}, size {len:#x}, {QN} RX rings of {QS}");
+
+        let rings = QN as usize;
+        // Every descriptor holds a buffer, plus a ring's worth for packets
+        // handed to the network stack
+        let slots = (rings + 2) * QS;
+        let memory = DmaRegion::<H>::new(slots * MEM_POOL_ENTRY_SIZE)?;
+        let pool = NetBufPool::with_memory(slots, MEM_POOL_ENTRY_SIZE, Box::new(memory))?;
+
+        let new_ring = || -> DriverResult<_> {
+            let bufs = (0..QS).map(|_| None).collect();
+            Ok((DmaRegion::<H>::new(QS * DESC_LEN)?, bufs))
+        };
+        let mut rx_rings = Vec::with_capacity(rings);
+        for _ in 0..rings {
+            let (descs, bufs) = new_ring()?;
+            rx_rings.push(RxRing {
+                descs,
+                bufs,
+                index: 0,
+            });
+        }
+        let (descs, bufs) = new_ring()?;
+        let tx_ring = TxRing {
+            descs,
+            bufs,
+            tail: 0,
+            clean: 0,
+        };
+
+        let mut nic = Self {
+            regs,
+            mac: MacAddress([0; 6]),
+            rx_rings,
+            tx_ring,
+            pool,
+            next_rx: 0,
+        };
+        nic.reset_and_init()?;
+        Ok(nic)
+    }
+
+    fn reset_and_init(&mut self) -> DriverResult {
+        let regs = &self.regs;
+        // 4.6.3: disable interrupts, reset, and disable interrupts again
+        regs.write(regs::EIMC, regs::EIMC_ALL);
+        regs.write(regs::CTRL, regs::CTRL_RST_MASK);
+        H::wait(Duration::from_millis(10));
+        regs.wait(regs::CTRL, regs::CTRL_RST_MASK, false)?;
+        regs.write(regs::EIMC, regs::EIMC_ALL);
+
+        // 4.6.3: wait for the EEPROM auto-read and the DMA init
+        regs.wait(regs::EEC, regs::EEC_ARD, true)?;
+        regs.wait(regs::RDRXCTL, regs::RDRXCTL_DMAIDONE, true)?;
+
+        // The EEPROM auto-read loads the permanent address into RAL0/RAH0
+        let ral = regs.read(regs::RAL0).to_le_bytes();
+        let rah = regs.read(regs::RAH0).to_le_bytes();
+        self.mac = MacAddress([ral[0], ral[1], ral[2], ral[3], rah[0], rah[1]]);
+        info!("ixgbe: MAC address {:02x?}", self.mac.0);
+
+        // 4.6.4: link setup, 10G serial with auto-negotiation
+        let autoc = regs.read(regs::AUTOC);
+        let autoc = (autoc & !regs::AUTOC_LMS_MASK) | regs::AUTOC_LMS_10G_SERIAL;
+        let autoc = (autoc & !regs::AUTOC_10G_PMA_PMD_MASK) | regs::AUTOC_10G_XAUI;
+        regs.write(regs::AUTOC, autoc | regs::AUTOC_AN_RESTART);
+
+        for reg in regs::STATS {
+            regs.read(reg);
+        }
+
+        self.init_rx();
+        self.init_tx();
+        for ring in 0..self.rx_rings.len() {
+            self.start_rx_ring(ring)?;
+        }
+        self.start_tx_ring()?;
+        Ok(())
+    }
+
+    /// 4.6.7: RX initialization.
+    fn init_rx(&self) {
+        let regs = &self.regs;
+        regs.clear_flags(regs::RXCTRL, regs::RXCTRL_RXEN);
+
+        // A single packet buffer, no DCB
+        regs.write(regs::rxpbsize(0), regs::RXPBSIZE_128KB);
+        for i in 1..8 {
+            regs.write(regs::rxpbsize(i), 0);
+        }
+        regs.set_flags(regs::HLREG0, regs::HLREG0_RXCRCSTRP);
+        regs.set_flags(regs::RDRXCTL, regs::RDRXCTL_CRCSTRIP);
+        regs.set_flags(regs::FCTRL, regs::FCTRL_BAM);
+
+        for (i, ring) in self.rx_rings.iter().enumerate() {
+            let srrctl = regs.read(regs::srrctl(i))
+                & !(regs::SRRCTL_DESCTYPE_MASK | regs::SRRCTL_BSIZEPKT_MASK);
+            // Buffer size in KiB; drop packets when no descriptor is free
+            // instead of stalling the other rings
+            let bsize = (MEM_POOL_ENTRY_SIZE / 1024) as u32;
+            regs.write(
+                regs::srrctl(i),
+                srrctl | regs::SRRCTL_DESCTYPE_ADV_ONEBUF | regs::SRRCTL_DROP_EN | bsize,
+            );
+            regs.write(regs::rdbal(i), ring.descs.paddr as u32);
+            regs.write(regs::rdbah(i), (ring.descs.paddr as u64 >> 32) as u32);
+            regs.write(regs::rdlen(i), (QS * DESC_LEN) as u32);
+            regs.write(regs::rdh(i), 0);
+            regs.write(regs::rdt(i), 0);
+            regs.clear_flags(regs::dca_rxctrl(i), regs::DCA_RXCTRL_RELAX_WR);
+        }
+
+        if self.rx_rings.len() > 1 {
+            for (i, word) in RSS_KEY.chunks_exact(4).enumerate() {
+                regs.write(regs::rssrk(i), u32::from_le_bytes(word.try_into().unwrap()));
+            }
+            for i in 0..32 {
+                regs.write(regs::reta(i), reta_entry(i, self.rx_rings.len()));
+            }
+            // The RSS hash takes the place of the fragment checksum
+            regs.set_flags(regs::RXCSUM, regs::RXCSUM_PCSD);
+            regs.write(regs::MRQC, regs::MRQC_RSSEN | regs::MRQC_RSS_FIELDS);
+        }
+
+        regs.set_flags(regs::CTRL_EXT, regs::CTRL_EXT_NS_DIS);
+        regs.set_flags(regs::RXCTRL, regs::RXCTRL_RXEN);
+    }
+
+    /// 4.6.8: TX initialization.
+    fn init_tx(&self) {
+        let regs = &self.regs;
+        regs.set_flags(regs::HLREG0, regs::HLREG0_TXCRCEN | regs::HLREG0_TXPADEN);
+
+        regs.write(regs::txpbsize(0), regs::TXPBSIZE_40KB);
+        for i in 1..8 {
+            regs.write(regs::txpbsize(i), 0);
+        }
+        regs.write(regs::DTXMXSZRQ, 0xFFFF);
+        regs.clear_flags(regs::RTTDCS, regs::RTTDCS_ARBDIS);
+
+        let paddr = self.tx_ring.descs.paddr as u64;
+        regs.write(regs::TDBAL, paddr as u32);
+        regs.write(regs::TDBAH, (paddr >> 32) as u32);
+        regs.write(regs::TDLEN, (QS * DESC_LEN) as u32);
+        let txdctl = regs.read(regs::TXDCTL) & !regs::TXDCTL_THRESH_MASK;
+        regs.write(regs::TXDCTL, txdctl | regs::TXDCTL_THRESH);
+
+        regs.write(regs::DMATXCTL, regs::DMATXCTL_TE);
+    }
+
+    fn start_rx_ring(&mut self, i: usize) -> DriverResult {
+        let ring = &mut self.rx_rings[i];
+        for (slot, buf) in ring.bufs.iter_mut().enumerate() {
+            let new_buf = self.pool.alloc_boxed().ok_or(DriverError::NoMemory)?;
+            ring.descs.write_desc(slot, new_buf.bus_addr().unwrap(), 0);
+            *buf = Some(new_buf);
+        }
+        let regs = &self.regs;
+        regs.set_flags(regs::rxdctl(i), regs::DCTL_ENABLE);
+        regs.wait(regs::rxdctl(i), regs::DCTL_ENABLE, true)?;
+        regs.write(regs::rdh(i), 0);
+        // One descriptor stays with the driver, so that a full ring is
+        // distinguishable from an empty one
+        regs.write(regs::rdt(i), (QS - 1) as u32);
+        Ok(())
+    }
+
+    fn start_tx_ring(&mut self) -> DriverResult {
+        let regs = &self.regs;
+        regs.write(regs::TDH, 0);
+        regs.write(regs::TDT, 0);
+        regs.set_flags(regs::TXDCTL, regs::DCTL_ENABLE);
+        regs.wait(regs::TXDCTL, regs::DCTL_ENABLE, true)
+    }
+
+    /// Takes the next packet received on RX ring `i`.
+    fn recv_ring(&mut self, i: usize) -> DriverResult<Option<NetBufBox>> {
+        let ring = &mut self.rx_rings[i];
+        loop {
+            let slot = ring.index;
+            let (_, hi) = ring.descs.read_desc(slot);
+            let status = hi as u32;
+            if status & desc::DD == 0 {
+                return Ok(None);
+            }
+            fence(Ordering::Acquire);
+
+            // Leave the packet in the ring until there is a buffer to
+            // replace it with
+            let new_buf = self.pool.alloc_boxed().ok_or(DriverError::NoMemory)?;
+            ring.descs.write_desc(slot, new_buf.bus_addr().unwrap(), 0);
+            let mut buf = ring.bufs[slot]
+                .replace(new_buf)
+                .ok_or(DriverError::BadState)?;
+            ring.index = (slot + 1) % QS;
+            fence(Ordering::Release);
+            self.regs.write(regs::rdt(i), slot as u32);
+
+            // Frames always fit in one buffer with the standard MTU
+            if status & desc::RX_EOP == 0 || status & desc::RX_ERR != 0 {
+                debug!("ixgbe: dropping bad frame, status {status:#x}");
+                continue;
+            }
+            buf.set_payload_len((hi >> 32) as u16 as usize);
+            return Ok(Some(buf));
+        }
+    }
+
+    /// Number of descriptors the TX ring can still take.
+    fn tx_free(&self) -> usize {
+        let in_flight = (self.tx_ring.tail + QS - self.tx_ring.clean) % QS;
+        QS - 1 - in_flight
     }
 }
 
 impl<H: IxgbeHal, const QS: usize, const QN: u16> DriverOps for IxgbeNic<H, QS, QN> {
     fn name(&self) -> &str {
-        self.inner.get_driver_name()
+        "ixgbe"
     }
 
     fn device_kind(&self) -> DeviceKind {
@@ -60,9 +616,26 @@ impl<H: IxgbeHal, const QS: usize, const QN: u16> DriverOps for IxgbeNic<H, QS,
     }
 }
 
-impl<H: IxgbeHal, const QS: usize, const QN: u16> NetDriverOps for IxgbeNic<H, QS, QN> {
+impl<H: IxgbeHal + 'static, const QS: usize, const QN: u16> NetDriverOps for IxgbeNic<H, QS, QN> {
     fn mac(&self) -> MacAddress {
-        MacAddress(self.inner.get_mac_addr())
+        self.mac
+    }
+
+    fn link_up(&self) -> bool {
+        self.regs.read(regs::LINKS) & regs::LINKS_UP != 0
+    }
+
+    fn speed(&self) -> Option<u32> {
+        let links = self.regs.read(regs::LINKS);
+        if links & regs::LINKS_UP == 0 {
+            return None;
+        }
+        match links & regs::LINKS_SPEED_MASK {
+            regs::LINKS_SPEED_100M => Some(100),
+            regs::LINKS_SPEED_1G => Some(1000),
+            regs::LINKS_SPEED_10G => Some(10000),
+            _ => None,
+        }
     }
 
     fn rx_queue_len(&self) -> usize {
@@ -74,129 +647,148 @@ impl<H: IxgbeHal, const QS: usize, const QN: u16> NetDriverOps for IxgbeNic<H, Q
     }
 
     fn can_rx(&self) -> bool {
-        !self.rx_buffer_queue.is_empty() || self.inner.can_receive(0).unwrap()
+        self.rx_rings
+            .iter()
+            .any(|ring| ring.descs.read_desc(ring.index).1 as u32 & desc::DD != 0)
     }
 
     fn can_tx(&self) -> bool {
-        // Default implementation is return true forever.
-        self.inner.can_send(0).unwrap()
+        self.tx_free() > 0
     }
 
     fn recycle_rx(&mut self, rx_buf: NetBufHandle) -> DriverResult {
-        let rx_buf = ixgbe_ptr_to_buf(rx_buf, &self.mem_pool)?;
-        drop(rx_buf);
+        // The rings were refilled by `recv`
+        drop(unsafe { NetBuf::from_handle(rx_buf) });
         Ok(())
     }
 
     fn recycle_tx(&mut self) -> DriverResult {
-        self.inner
-            .recycle_tx_buffers(0)
-            .map_err(|_| DriverError::BadState)?;
+        let ring = &mut self.tx_ring;
+        while ring.clean != ring.tail {
+            let (_, hi) = ring.descs.read_desc(ring.clean);
+            if (hi >> 32) as u32 & desc::DD == 0 {
+                break;
+            }
+            ring.bufs[ring.clean] = None;
+            ring.clean = (ring.clean + 1) % QS;
+        }
         Ok(())
     }
 
     fn recv(&mut self) -> DriverResult<NetBufHandle> {
-        if !self.can_rx() {
-            return Err(DriverError::WouldBlock);
-        }
-        if !self.rx_buffer_queue.is_empty() {
-            // RX buffer have received packets.
-            Ok(self.rx_buffer_queue.pop_front().unwrap())
-        } else {
-            let f = |rx_buf| {
-                let rx_buf = NetBufHandle::from(rx_buf);
-                self.rx_buffer_queue.push_back(rx_buf);
-            };
-
-            // RX queue is empty, recv from ixgbe NIC.
-            match self.inner.receive_packets(0, RECV_BATCH_SIZE, f) {
-                Ok(recv_nums) => {
-                    if recv_nums == 0 {
-                        // No payload is received, it is impossible things.
-                        panic!("Error: No recv packets.")
-                    } else {
-                        Ok(self.rx_buffer_queue.pop_front().unwrap())
-                    }
-                }
-                Err(e) => match e {
-                    IxgbeError::NotReady => Err(DriverError::WouldBlock),
-                    _ => Err(DriverError::BadState),
-                },
+        for i in 0..self.rx_rings.len() {
+            let ring = (self.next_rx + i) % self.rx_rings.len();
+            if let Some(buf) = self.recv_ring(ring)? {
+                self.next_rx = (ring + 1) % self.rx_rings.len();
+                return Ok(buf.into_handle());
             }
         }
+        Err(DriverError::WouldBlock)
     }
 
     fn send(&mut self, tx_buf: NetBufHandle) -> DriverResult {
-        let tx_buf = ixgbe_ptr_to_buf(tx_buf, &self.mem_pool)?;
-        match self.inner.send(0, tx_buf) {
-            Ok(_) => Ok(()),
-            Err(err) => match err {
-                IxgbeError::QueueFull => Err(DriverError::WouldBlock),
-                _ => panic!("Unexpected err: {:?}", err),
-            },
+        let tx_buf = unsafe { NetBuf::from_handle(tx_buf) };
+        if self.tx_free() == 0 {
+            return Err(DriverError::WouldBlock);
         }
+        let ring = &mut self.tx_ring;
+        let slot = ring.tail;
+        let addr = tx_buf.bus_addr().unwrap() + tx_buf.hdr_len() as u64;
+        let (lo, hi) = tx_desc(addr, tx_buf.payload_len());
+        ring.descs.write_desc(slot, lo, hi);
+        ring.bufs[slot] = Some(tx_buf);
+        ring.tail = (slot + 1) % QS;
+        fence(Ordering::Release);
+        self.regs.write(regs::TDT, ring.tail as u32);
+        Ok(())
     }
 
     fn alloc_tx_buf(&mut self, size: usize) -> DriverResult<NetBufHandle> {
-        let tx_buf = IxgbeNetBuf::alloc(&self.mem_pool, size).map_err(|_| DriverError::NoMemory)?;
-        Ok(NetBufHandle::from(tx_buf))
-    }
-}
-
-impl From<IxgbeNetBuf> for NetBufHandle {
-    fn from(buf: IxgbeNetBuf) -> Self {
-        // Use `ManuallyDrop` to avoid drop `tx_buf`.
-        let mut buf = ManuallyDrop::new(buf);
-        let buf_ref =
-            unsafe { &mut *(&mut buf as *mut ManuallyDrop<IxgbeNetBuf> as *mut IxgbeNetBuf) };
-        // In ixgbe, `raw_ptr` is the pool entry, `buf_ptr` is the payload ptr, `len` is payload len
-        // to avoid too many dynamic memory allocation.
-        let buf_ptr = buf_ref.packet_mut().as_mut_ptr();
-        Self::new(
-            NonNull::new(buf_ref.pool_entry() as *mut u8).unwrap(),
-            NonNull::new(buf_ptr).unwrap(),
-            buf_ref.packet_len(),
-        )
+        let mut tx_buf = self.pool.alloc_boxed().ok_or(DriverError::NoMemory)?;
+        if size > tx_buf.capacity() {
+            return Err(DriverError::InvalidInput);
+        }
+        tx_buf.set_payload_len(size);
+        Ok(tx_buf.into_handle())
     }
 }
 
-// Converts a `NetBufHandle` to `IxgbeNetBuf`.
-fn ixgbe_ptr_to_buf(ptr: NetBufHandle, pool: &Arc<MemPool>) -> DriverResult<IxgbeNetBuf> {
-    IxgbeNetBuf::construct(ptr.owner_ptr::<()>().addr(), pool, ptr.len())
-        .map_err(|_| DriverError::BadState)
-}
-
 #[cfg(unittest)]
 pub mod tests_ixgbe {
     use unittest::def_test;
     extern crate alloc;
-    use alloc::{collections::VecDeque, sync::Arc, vec, vec::Vec};
+    use alloc::{
+        alloc::{Layout, alloc, dealloc},
+        collections::VecDeque,
+        vec,
+        vec::Vec,
+    };
     use core::ptr::NonNull;
 
     use super::*;
     use crate::{MacAddress, NetBufHandle};
 
-    // Mock IxgbeHal for testing
+    const QS: usize = 1024;
+    const RECV_BATCH_SIZE: usize = 64;
+    const RX_BUFFER_SIZE: usize = 1024;
+
+    // Mock IxgbeHal for testing, with heap memory at identical bus addresses
     struct MockIxgbeHal;
 
-    impl IxgbeHal for MockIxgbeHal {
-        fn dma_alloc(&self, _size: usize) -> (PhysAddr, *mut u8) {
-            // Mock allocation - return safe test addresses
-            let vaddr = 0x1000_0000;
-            (PhysAddr::new(0x2000_0000), vaddr as *mut u8)
+    unsafe impl IxgbeHal for MockIxgbeHal {
+        fn dma_alloc(size: usize) -> (PhysAddr, NonNull<u8>) {
+            let ptr = unsafe { alloc(Layout::from_size_align(size, 128).unwrap()) };
+            (ptr as PhysAddr, NonNull::new(ptr).unwrap())
         }
 
-        fn dma_dealloc(&self, _vaddr: *mut u8, _size: usize) {
-            // Mock deallocation - no-op for tests
+        unsafe fn dma_dealloc(_paddr: PhysAddr, vaddr: NonNull<u8>, size: usize) -> i32 {
+            unsafe { dealloc(vaddr.as_ptr(), Layout::from_size_align(size, 128).unwrap()) };
+            0
         }
 
-        fn phys_to_virt(&self, paddr: PhysAddr) -> *mut u8 {
-            paddr.0 as *mut u8
+        unsafe fn mmio_p2v(paddr: PhysAddr, _size: usize) -> NonNull<u8> {
+            NonNull::new(paddr as *mut u8).unwrap()
         }
 
-        fn virt_to_phys(&self, vaddr: *mut u8) -> PhysAddr {
-            PhysAddr::new(vaddr as usize)
+        unsafe fn mmio_v2p(vaddr: NonNull<u8>, _size: usize) -> PhysAddr {
+            vaddr.as_ptr() as PhysAddr
         }
+
+        fn wait(_duration: Duration) {}
+    }
+
+    #[def_test]
+    fn test_ixgbe_descriptors() {
+        // Buckets go to the rings in turn
+        assert_eq!(reta_entry(0, 1), 0);
+        assert_eq!(reta_entry(0, 4), 0x0302_0100);
+        assert_eq!(reta_entry(1, 3), 0x0100_0201);
+
+        let (lo, hi) = tx_desc(0x1234_5000, 60);
+        assert_eq!(lo, 0x1234_5000);
+        let cmd_type_len = hi as u32;
+        assert_eq!(cmd_type_len & 0xFFFF, 60);
+        assert_ne!(cmd_type_len & desc::TX_EOP, 0);
+        assert_ne!(cmd_type_len & desc::TX_RS, 0);
+        assert_eq!((hi >> 32) as u32 >> desc::TX_PAYLEN_SHIFT, 60);
+        // Not yet written back
+        assert_eq!((hi >> 32) as u32 & desc::DD, 0);
+
+        let ring = DmaRegion::<MockIxgbeHal>::new(4 * DESC_LEN).unwrap();
+        assert_eq!(ring.read_desc(3), (0, 0));
+        ring.write_desc(3, lo, hi);
+        assert_eq!(ring.read_desc(3), (lo, hi));
+        assert_eq!(ring.read_desc(2), (0, 0));
+
+        // Buffers carved out of DMA memory know their bus address
+        let memory = DmaRegion::<MockIxgbeHal>::new(2 * MEM_POOL_ENTRY_SIZE).unwrap();
+        let base = memory.paddr as u64;
+        let pool = NetBufPool::with_memory(2, MEM_POOL_ENTRY_SIZE, Box::new(memory)).unwrap();
+        let a = pool.alloc_buf().unwrap();
+        let b = pool.alloc_buf().unwrap();
+        let mut addrs = [a.bus_addr().unwrap(), b.bus_addr().unwrap()];
+        addrs.sort();
+        assert_eq!(addrs, [base, base + MEM_POOL_ENTRY_SIZE as u64]);
     }
 
     #[def_test]
@@ -211,7 +803,6 @@ pub mod tests_ixgbe {
         );
 
         // Test memory pool configuration validation
-        assert!(MEM_POOL > 0, "Memory pool size must be positive");
         assert!(
             MEM_POOL_ENTRY_SIZE >= 64,
             "Pool entry size should be reasonable"
@@ -239,7 +830,6 @@ pub mod tests_ixgbe {
         assert!(rx_queue.is_empty());
 
         // Fill queue with test data
-        let mut test_handles = Vec::new();
         for i in 0..core::cmp::min(RX_BUFFER_SIZE, 100) {
             let data = vec![(i % 256) as u8; 1514];
             let box_data = Box::new(data.clone());
@@ -309,7 +899,7 @@ pub mod tests_ixgbe {
 
             // Test locally administered detection
             let is_locally_administered = (mac_bytes[0] & 0x02) != 0;
-            let is_globally_unique = !is_locally_administered;
+            let _is_globally_unique = !is_locally_administered;
 
             // Validate address types are mutually exclusive (except broadcast is also multicast)
             if is_broadcast {
@@ -331,7 +921,7 @@ pub mod tests_ixgbe {
         }
 
         // Test MAC address conversion and manipulation
-        for i in 0..256u8 {
+        for i in 0..=255u8 {
             let test_mac = [
                 i,
                 i.wrapping_add(1),
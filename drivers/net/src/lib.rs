@@ -9,28 +9,123 @@
 
 extern crate alloc;
 
+use alloc::boxed::Box;
+
 // #[cfg(feature = "fxmac")]
 // /// fxmac driver for PhytiumPi
 // pub mod fxmac;
-// #[cfg(feature = "ixgbe")]
-// /// ixgbe NIC device driver.
-// pub mod ixgbe;
+#[cfg(feature = "ixgbe")]
+/// ixgbe NIC device driver.
+pub mod ixgbe;
 
 #[doc(no_inline)]
 pub use driver_base::{DeviceKind, DriverError, DriverOps, DriverResult};
 
+mod bridge;
 mod net_buf;
-pub use self::net_buf::{NetBuf, NetBufBox, NetBufHandle, NetBufPool};
+mod vnic;
+pub use self::{
+    bridge::Bridge,
+    net_buf::{NetBuf, NetBufBox, NetBufHandle, NetBufMemory, NetBufPool},
+    vnic::VirtualNic,
+};
 
 /// The hardware (MAC) address of a NIC.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MacAddress(pub [u8; 6]);
 
+bitflags::bitflags! {
+    /// Offloads a NIC performs in hardware.
+    ///
+    /// The network stack skips the corresponding software work for devices
+    /// that advertise these capabilities.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct NetCapabilities: u32 {
+        /// The NIC verifies IPv4, TCP and UDP checksums of received packets
+        /// and drops packets with bad checksums.
+        const RX_CSUM = 1 << 0;
+        /// The NIC fills in IPv4, TCP and UDP checksums of transmitted packets.
+        const TX_CSUM = 1 << 1;
+        /// The NIC splits oversized TCP segments (TCP segmentation offload).
+        const TSO = 1 << 2;
+        /// The NIC fragments oversized UDP datagrams (UDP fragmentation offload).
+        const UFO = 1 << 3;
+    }
+}
+
+/// Callback invoked by a driver when the link goes up (`true`) or down
+/// (`false`).
+///
+/// It may be called from interrupt context, so it must not block.
+pub type LinkChangeHook = Box<dyn Fn(bool) + Send + Sync>;
+
+/// Callback invoked by a driver without receive interrupts when packets
+/// arrive.
+///
+/// It may be called from any context, so it must not block.
+pub type RxNotifyHook = Box<dyn Fn() + Send + Sync>;
+
 /// Operations that require a network device (NIC) driver to implement.
 pub trait NetDriverOps: DriverOps {
     /// The hardware address of the NIC.
     fn mac(&self) -> MacAddress;
 
+    /// The hardware offloads enabled on the NIC.
+    ///
+    /// Defaults to none, in which case the network stack computes and
+    /// verifies all checksums in software.
+    fn capabilities(&self) -> NetCapabilities {
+        NetCapabilities::empty()
+    }
+
+    /// Whether the NIC has carrier, i.e. the link is up.
+    ///
+    /// Drivers that cannot tell report the link as always up.
+    fn link_up(&self) -> bool {
+        true
+    }
+
+    /// The negotiated link speed in Mbit/s, or `None` if unknown.
+    fn speed(&self) -> Option<u32> {
+        None
+    }
+
+    /// Installs the hook to call on link state changes, replacing any
+    /// previous one.
+    ///
+    /// Drivers without link change interrupts drop the hook; the link state
+    /// is then only observable through [`NetDriverOps::link_up`].
+    fn set_link_change_hook(&mut self, hook: LinkChangeHook) {
+        drop(hook);
+    }
+
+    /// Installs the hook to call when packets arrive, replacing any previous
+    /// one.
+    ///
+    /// Drivers that raise an interrupt on reception, see
+    /// [`DriverOps::irq`], drop the hook.
+    fn set_rx_notify_hook(&mut self, hook: RxNotifyHook) {
+        drop(hook);
+    }
+
+    /// Acknowledges the interrupt of the NIC, see [`DriverOps::irq`].
+    ///
+    /// Returns whether an interrupt was pending. Drivers without interrupts
+    /// return `false`.
+    fn ack_interrupt(&mut self) -> bool {
+        false
+    }
+
+    /// Unmasks (`true`) or masks (`false`) the interrupt raised when packets
+    /// arrive.
+    ///
+    /// The network stack masks it while draining the receive queue, so that
+    /// a burst of packets raises a single interrupt. Drivers without receive
+    /// interrupts ignore it.
+    fn enable_rx_interrupt(&mut self, enable: bool) {
+        let _ = enable;
+    }
+
     /// Whether the device can transmit packets.
     fn can_tx(&self) -> bool;
 
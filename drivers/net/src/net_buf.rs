@@ -144,6 +144,12 @@ impl NetBuf {
         unsafe { self.get_slice_mut(0, self.buf_len) }
     }
 
+    /// Returns the bus address of the start of the buffer, or `None` if the
+    /// pool is not backed by DMA memory.
+    pub fn bus_addr(&self) -> Option<u64> {
+        Some(self.pool.memory.bus_addr()? + self.pool_offset as u64)
+    }
+
     /// Set the length of the header part.
     pub fn set_hdr_len(&mut self, hdr_len: usize) {
         debug_assert!(hdr_len + self.payload_len <= self.buf_len);
@@ -185,28 +191,73 @@ impl Drop for NetBuf {
     }
 }
 
+/// Memory that a [`NetBufPool`] divides into buffers.
+pub trait NetBufMemory: Send + Sync {
+    /// Returns the start of the memory.
+    fn as_ptr(&self) -> NonNull<u8>;
+
+    /// Returns the size of the memory in bytes.
+    fn size(&self) -> usize;
+
+    /// Returns the bus address of the start of the memory, or `None` if
+    /// devices cannot access it directly.
+    fn bus_addr(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl NetBufMemory for Vec<u8> {
+    fn as_ptr(&self) -> NonNull<u8> {
+        NonNull::new(self.as_slice().as_ptr() as *mut u8).unwrap()
+    }
+
+    fn size(&self) -> usize {
+        self.len()
+    }
+}
+
+fn check_layout(slot_count: usize, buf_len: usize) -> DriverResult {
+    if slot_count == 0 || !(MIN_BUFFER_LEN..=MAX_BUFFER_LEN).contains(&buf_len) {
+        return Err(DriverError::InvalidInput);
+    }
+    Ok(())
+}
+
 /// A pool of [`NetBuf`]s to speed up buffer allocation.
 ///
-/// It divides a large memory into several equal parts for each buffer.
+/// It divides a large memory into several equal parts for each buffer. With
+/// DMA memory, see [`NetBufPool::with_memory`], the buffers can be handed to
+/// the device as they are.
 pub struct NetBufPool {
     slot_count: usize,
     buf_len: usize,
-    storage: Vec<u8>,
+    memory: Box<dyn NetBufMemory>,
     free_offsets: Mutex<Vec<usize>>,
 }
 
 impl NetBufPool {
     /// Creates a new pool with the given `slot_count`, and all buffer lengths are
     /// set to `buf_len`.
+    ///
+    /// The buffers are allocated from the heap.
     pub fn new(slot_count: usize, buf_len: usize) -> DriverResult<Arc<Self>> {
-        if slot_count == 0 {
-            return Err(DriverError::InvalidInput);
-        }
-        if !(MIN_BUFFER_LEN..=MAX_BUFFER_LEN).contains(&buf_len) {
+        check_layout(slot_count, buf_len)?;
+        let memory = vec![0u8; slot_count * buf_len];
+        Self::with_memory(slot_count, buf_len, Box::new(memory))
+    }
+
+    /// Creates a new pool like [`NetBufPool::new`], carving the buffers out
+    /// of `memory`.
+    pub fn with_memory(
+        slot_count: usize,
+        buf_len: usize,
+        memory: Box<dyn NetBufMemory>,
+    ) -> DriverResult<Arc<Self>> {
+        check_layout(slot_count, buf_len)?;
+        if memory.size() < slot_count * buf_len {
             return Err(DriverError::InvalidInput);
         }
 
-        let storage = vec![0; slot_count * buf_len];
         let mut free_offsets = Vec::with_capacity(slot_count);
         for i in 0..slot_count {
             free_offsets.push(i * buf_len);
@@ -214,7 +265,7 @@ impl NetBufPool {
         Ok(Arc::new(Self {
             slot_count,
             buf_len,
-            storage,
+            memory,
             free_offsets: Mutex::new(free_offsets),
         }))
     }
@@ -229,13 +280,17 @@ impl NetBufPool {
         self.buf_len
     }
 
+    /// Whether the buffers are DMA memory.
+    pub fn is_dma(&self) -> bool {
+        self.memory.bus_addr().is_some()
+    }
+
     /// Allocates a buffer from the pool.
     ///
     /// Returns `None` if no buffer is available.
     pub fn alloc_buf(self: &Arc<Self>) -> Option<NetBuf> {
         let pool_offset = self.free_offsets.lock().pop()?;
-        let buf_ptr =
-            unsafe { NonNull::new(self.storage.as_ptr().add(pool_offset) as *mut u8).unwrap() };
+        let buf_ptr = unsafe { self.memory.as_ptr().add(pool_offset) };
         Some(NetBuf {
             hdr_len: 0,
             payload_len: 0,
@@ -313,6 +368,43 @@ pub mod tests_netbuf {
         }
     }
 
+    /// Heap memory posing as DMA memory.
+    struct FakeDma(Vec<u8>);
+
+    impl NetBufMemory for FakeDma {
+        fn as_ptr(&self) -> NonNull<u8> {
+            NetBufMemory::as_ptr(&self.0)
+        }
+
+        fn size(&self) -> usize {
+            self.0.len()
+        }
+
+        fn bus_addr(&self) -> Option<u64> {
+            Some(0x8000_0000)
+        }
+    }
+
+    #[def_test]
+    fn test_netbuf_dma_pool() {
+        let memory = FakeDma(vec![0; 2 * MIN_BUFFER_LEN]);
+        assert!(NetBufPool::with_memory(3, MIN_BUFFER_LEN, Box::new(memory)).is_err());
+
+        let memory = FakeDma(vec![0; 2 * MIN_BUFFER_LEN]);
+        let pool = NetBufPool::with_memory(2, MIN_BUFFER_LEN, Box::new(memory)).unwrap();
+        assert!(pool.is_dma());
+
+        let buf = pool.alloc_buf().unwrap();
+        let other = pool.alloc_buf().unwrap();
+        let mut addrs = [buf.bus_addr().unwrap(), other.bus_addr().unwrap()];
+        addrs.sort();
+        assert_eq!(addrs, [0x8000_0000, 0x8000_0000 + MIN_BUFFER_LEN as u64]);
+        assert!(pool.alloc_buf().is_none());
+        drop(other);
+        assert!(pool.alloc_buf().is_some());
+        assert!(!NetBufPool::new(1, MIN_BUFFER_LEN).unwrap().is_dma());
+    }
+
     #[def_test]
     fn test_netbuf_pool_edge_cases() {
         // Test pool allocation with boundary conditions
@@ -0,0 +1,233 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Virtual NICs, connected in pairs or to a [`Bridge`].
+use alloc::{
+    collections::VecDeque,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use spin::Mutex;
+
+use crate::{
+    DeviceKind, DriverError, DriverOps, DriverResult, LinkChangeHook, MacAddress, NetBuf,
+    NetBufHandle, NetBufPool, NetDriverOps, RxNotifyHook,
+    bridge::{Bridge, BridgeInner},
+};
+
+/// Number of frames a virtual NIC queues before dropping.
+pub(crate) const QUEUE_LEN: usize = 64;
+/// Buffer size fitting an untagged Ethernet frame of standard MTU.
+const FRAME_BUF_LEN: usize = 1526;
+
+#[derive(Default)]
+struct Hooks {
+    link_change: Option<LinkChangeHook>,
+    rx_notify: Option<RxNotifyHook>,
+}
+
+/// The receiving side of a virtual NIC.
+#[derive(Default)]
+pub(crate) struct Endpoint {
+    queue: Mutex<VecDeque<Vec<u8>>>,
+    hooks: Mutex<Hooks>,
+}
+
+impl Endpoint {
+    /// Queues `frame` for reception, returning `false` if the queue is full.
+    pub(crate) fn deliver(&self, frame: &[u8]) -> bool {
+        {
+            let mut queue = self.queue.lock();
+            if queue.len() >= QUEUE_LEN {
+                return false;
+            }
+            queue.push_back(frame.to_vec());
+        }
+        if let Some(hook) = &self.hooks.lock().rx_notify {
+            hook();
+        }
+        true
+    }
+
+    fn set_link(&self, up: bool) {
+        if let Some(hook) = &self.hooks.lock().link_change {
+            hook(up);
+        }
+    }
+}
+
+/// Where the frames sent by a virtual NIC go.
+enum Wire {
+    Peer(Weak<Endpoint>),
+    Bridge {
+        bridge: Arc<BridgeInner>,
+        port: usize,
+    },
+}
+
+/// A software NIC.
+///
+/// Frames sent on one NIC of a pair created by [`VirtualNic::new_pair`] are
+/// received by the other, and a NIC created by [`Bridge::add_port`] is
+/// connected to the bridge. Either way the NIC has no interrupt; instead it
+/// invokes the hook set by [`NetDriverOps::set_rx_notify_hook`] when a frame
+/// arrives.
+pub struct VirtualNic {
+    mac: MacAddress,
+    rx: Arc<Endpoint>,
+    wire: Wire,
+    pool: Arc<NetBufPool>,
+}
+
+impl VirtualNic {
+    fn new(mac: MacAddress, rx: Arc<Endpoint>, wire: Wire) -> Self {
+        Self {
+            mac,
+            rx,
+            wire,
+            pool: NetBufPool::new(QUEUE_LEN, FRAME_BUF_LEN).unwrap(),
+        }
+    }
+
+    /// Creates two NICs connected to each other, like a veth pair.
+    pub fn new_pair(mac_a: MacAddress, mac_b: MacAddress) -> (Self, Self) {
+        let rx_a = Arc::new(Endpoint::default());
+        let rx_b = Arc::new(Endpoint::default());
+        let wire_a = Wire::Peer(Arc::downgrade(&rx_b));
+        let wire_b = Wire::Peer(Arc::downgrade(&rx_a));
+        (
+            Self::new(mac_a, rx_a, wire_a),
+            Self::new(mac_b, rx_b, wire_b),
+        )
+    }
+
+    pub(crate) fn new_bridge_port(
+        mac: MacAddress,
+        bridge: &Bridge,
+        rx: Arc<Endpoint>,
+        port: usize,
+    ) -> Self {
+        let wire = Wire::Bridge {
+            bridge: bridge.inner.clone(),
+            port,
+        };
+        Self::new(mac, rx, wire)
+    }
+
+    /// Returns the bridge port the NIC is connected to, if it is.
+    pub fn bridge_port(&self) -> Option<usize> {
+        match &self.wire {
+            Wire::Peer(_) => None,
+            Wire::Bridge { port, .. } => Some(*port),
+        }
+    }
+}
+
+impl DriverOps for VirtualNic {
+    fn name(&self) -> &str {
+        "vnic"
+    }
+
+    fn device_kind(&self) -> DeviceKind {
+        DeviceKind::Net
+    }
+}
+
+impl NetDriverOps for VirtualNic {
+    fn mac(&self) -> MacAddress {
+        self.mac
+    }
+
+    fn link_up(&self) -> bool {
+        match &self.wire {
+            Wire::Peer(peer) => peer.strong_count() > 0,
+            Wire::Bridge { .. } => true,
+        }
+    }
+
+    fn set_link_change_hook(&mut self, hook: LinkChangeHook) {
+        self.rx.hooks.lock().link_change = Some(hook);
+    }
+
+    fn set_rx_notify_hook(&mut self, hook: RxNotifyHook) {
+        self.rx.hooks.lock().rx_notify = Some(hook);
+    }
+
+    fn can_tx(&self) -> bool {
+        true
+    }
+
+    fn can_rx(&self) -> bool {
+        !self.rx.queue.lock().is_empty()
+    }
+
+    fn rx_queue_len(&self) -> usize {
+        QUEUE_LEN
+    }
+
+    fn tx_queue_len(&self) -> usize {
+        QUEUE_LEN
+    }
+
+    fn recycle_rx(&mut self, rx_buf: NetBufHandle) -> DriverResult {
+        drop(unsafe { NetBuf::from_handle(rx_buf) });
+        Ok(())
+    }
+
+    fn recycle_tx(&mut self) -> DriverResult {
+        // Frames are copied out by `send`
+        Ok(())
+    }
+
+    fn send(&mut self, tx_buf: NetBufHandle) -> DriverResult {
+        let tx_buf = unsafe { NetBuf::from_handle(tx_buf) };
+        match &self.wire {
+            // Like a cable without a peer, frames are lost silently
+            Wire::Peer(peer) => {
+                if let Some(peer) = peer.upgrade() {
+                    peer.deliver(tx_buf.payload());
+                }
+            }
+            Wire::Bridge { bridge, port } => bridge.forward(*port, tx_buf.payload()),
+        }
+        Ok(())
+    }
+
+    fn recv(&mut self) -> DriverResult<NetBufHandle> {
+        // Allocate first so that a frame is never dequeued and then lost
+        let mut rx_buf = self.pool.alloc_boxed().ok_or(DriverError::NoMemory)?;
+        let frame = self
+            .rx
+            .queue
+            .lock()
+            .pop_front()
+            .ok_or(DriverError::WouldBlock)?;
+        rx_buf.set_payload_len(frame.len());
+        rx_buf.payload_mut().copy_from_slice(&frame);
+        Ok(rx_buf.into_handle())
+    }
+
+    fn alloc_tx_buf(&mut self, size: usize) -> DriverResult<NetBufHandle> {
+        let mut tx_buf = self.pool.alloc_boxed().ok_or(DriverError::NoMemory)?;
+        if size > tx_buf.capacity() {
+            return Err(DriverError::InvalidInput);
+        }
+        tx_buf.set_payload_len(size);
+        Ok(tx_buf.into_handle())
+    }
+}
+
+impl Drop for VirtualNic {
+    fn drop(&mut self) {
+        match &self.wire {
+            Wire::Peer(peer) => {
+                if let Some(peer) = peer.upgrade() {
+                    peer.set_link(false);
+                }
+            }
+            Wire::Bridge { bridge, port } => bridge.remove_port(*port),
+        }
+    }
+}
@@ -0,0 +1,391 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! VirtIO memory balloon driver.
+//!
+//! `virtio-drivers` has no balloon device, so this module drives the inflate
+//! and deflate virtqueues itself. Balloon pages are taken from the kalloc page
+//! allocator and accounted as [`UsageKind::Balloon`] while the hypervisor
+//! holds them.
+use alloc::vec::Vec;
+use core::{
+    hint::spin_loop,
+    marker::PhantomData,
+    ptr::NonNull,
+    sync::atomic::{Ordering, fence},
+};
+
+use bitflags::bitflags;
+use driver_base::{DeviceKind, DriverError, DriverOps, DriverResult};
+use kalloc::{UsageKind, global_allocator};
+use memaddr::VirtAddr;
+use virtio_drivers::{
+    BufferDirection, Hal, PhysAddr,
+    transport::{InterruptStatus, Transport},
+};
+
+use crate::as_driver_error;
+
+const PAGE_SIZE: usize = 0x1000;
+/// The balloon protocol always counts in 4 KiB frames.
+const BALLOON_PFN_SHIFT: usize = 12;
+
+const INFLATE_QUEUE: u16 = 0;
+const DEFLATE_QUEUE: u16 = 1;
+const QUEUE_SIZE: u16 = 8;
+/// Maximum number of frames reported in one request, same as Linux.
+const PFNS_PER_REQUEST: usize = 256;
+
+/// Config space: pages the host wants in the balloon.
+const CONFIG_NUM_PAGES: usize = 0;
+/// Config space: pages the driver has put in the balloon.
+const CONFIG_ACTUAL: usize = 4;
+
+bitflags! {
+    /// Feature bits of the balloon device (VirtIO 1.2, section 5.5.3).
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct BalloonFeatures: u64 {
+        /// Host must be told before balloon pages are reused.
+        const MUST_TELL_HOST = 1 << 0;
+        /// A statistics virtqueue is present.
+        const STATS_VQ = 1 << 1;
+        /// Guest may deflate the balloon under memory pressure.
+        const DEFLATE_ON_OOM = 1 << 2;
+        /// Device complies with VirtIO 1.0 or later.
+        const VERSION_1 = 1 << 32;
+    }
+}
+
+/// Deflation always waits for the host, so `MUST_TELL_HOST` costs nothing.
+const SUPPORTED_FEATURES: BalloonFeatures = BalloonFeatures::MUST_TELL_HOST
+    .union(BalloonFeatures::DEFLATE_ON_OOM)
+    .union(BalloonFeatures::VERSION_1);
+
+/// A split virtqueue carrying one device-readable buffer at a time.
+///
+/// It always uses the legacy contiguous layout, which every transport accepts.
+struct BalloonQueue<H: Hal> {
+    idx: u16,
+    paddr: PhysAddr,
+    vaddr: NonNull<u8>,
+    pages: usize,
+    avail_idx: u16,
+    last_used_idx: u16,
+    _hal: PhantomData<H>,
+}
+
+impl<H: Hal> BalloonQueue<H> {
+    const AVAIL_OFFSET: usize = 16 * QUEUE_SIZE as usize;
+    const USED_OFFSET: usize =
+        (Self::AVAIL_OFFSET + 2 * (3 + QUEUE_SIZE as usize)).next_multiple_of(PAGE_SIZE);
+    const USED_SIZE: usize = 2 * 3 + 8 * QUEUE_SIZE as usize;
+
+    fn new<T: Transport>(transport: &mut T, idx: u16) -> DriverResult<Self> {
+        if transport.queue_used(idx) {
+            return Err(DriverError::AlreadyExists);
+        }
+        if transport.max_queue_size(idx) < QUEUE_SIZE as u32 {
+            return Err(DriverError::Unsupported);
+        }
+        let pages = (Self::USED_OFFSET + Self::USED_SIZE).div_ceil(PAGE_SIZE);
+        let (paddr, vaddr) = H::dma_alloc(pages, BufferDirection::Both);
+        if paddr == 0 {
+            return Err(DriverError::NoMemory);
+        }
+        // Requests are polled, the device need not interrupt on completion.
+        unsafe {
+            (vaddr.as_ptr().add(Self::AVAIL_OFFSET) as *mut u16).write_volatile(1u16.to_le());
+        }
+        transport.queue_set(
+            idx,
+            QUEUE_SIZE as u32,
+            paddr,
+            paddr + Self::AVAIL_OFFSET as PhysAddr,
+            paddr + Self::USED_OFFSET as PhysAddr,
+        );
+        Ok(Self {
+            idx,
+            paddr,
+            vaddr,
+            pages,
+            avail_idx: 0,
+            last_used_idx: 0,
+            _hal: PhantomData,
+        })
+    }
+
+    /// Hands `len` bytes at `buf` to the device and waits until it is used.
+    fn submit<T: Transport>(&mut self, transport: &mut T, buf: PhysAddr, len: u32) {
+        let base = self.vaddr.as_ptr();
+        unsafe {
+            // Descriptor 0: addr, len, flags = 0 (device-readable), next.
+            (base as *mut u64).write_volatile(buf.to_le());
+            (base.add(8) as *mut u32).write_volatile(len.to_le());
+            (base.add(12) as *mut u32).write_volatile(0);
+
+            let avail = base.add(Self::AVAIL_OFFSET) as *mut u16;
+            let slot = (self.avail_idx % QUEUE_SIZE) as usize;
+            avail.add(2 + slot).write_volatile(0);
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            avail.add(1).write_volatile(self.avail_idx.to_le());
+            fence(Ordering::SeqCst);
+        }
+        transport.notify(self.idx);
+
+        let used_idx = unsafe { (base.add(Self::USED_OFFSET) as *const u16).add(1) };
+        while u16::from_le(unsafe { used_idx.read_volatile() }) == self.last_used_idx {
+            spin_loop();
+        }
+        fence(Ordering::SeqCst);
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+    }
+}
+
+impl<H: Hal> Drop for BalloonQueue<H> {
+    fn drop(&mut self) {
+        unsafe { H::dma_dealloc(self.paddr, self.vaddr, self.pages) };
+    }
+}
+
+/// The VirtIO memory balloon device driver.
+///
+/// Inflating takes pages from kalloc and reports them to the hypervisor, which
+/// may then reclaim the backing memory. Deflating asks for them back and
+/// returns them to kalloc.
+pub struct VirtIoBalloonDev<H: Hal, T: Transport> {
+    transport: T,
+    inflate_queue: BalloonQueue<H>,
+    deflate_queue: BalloonQueue<H>,
+    /// One DMA page holding the frame numbers of the request in flight.
+    pfn_paddr: PhysAddr,
+    pfn_vaddr: NonNull<u8>,
+    /// Pages currently in the balloon.
+    pages: Vec<VirtAddr>,
+    v2p: fn(VirtAddr) -> memaddr::PhysAddr,
+}
+
+unsafe impl<H: Hal, T: Transport> Send for VirtIoBalloonDev<H, T> {}
+unsafe impl<H: Hal, T: Transport> Sync for VirtIoBalloonDev<H, T> {}
+
+impl<H: Hal, T: Transport> VirtIoBalloonDev<H, T> {
+    /// Creates a new driver instance and initializes the device, or returns
+    /// an error if any step fails.
+    ///
+    /// `v2p` translates balloon page addresses to the physical addresses
+    /// reported to the hypervisor.
+    pub fn try_new(mut transport: T, v2p: fn(VirtAddr) -> memaddr::PhysAddr) -> DriverResult<Self> {
+        let features = transport.begin_init(SUPPORTED_FEATURES);
+        log::debug!("virtio-balloon: negotiated features {features:?}");
+
+        let inflate_queue = BalloonQueue::new(&mut transport, INFLATE_QUEUE)?;
+        let deflate_queue = BalloonQueue::new(&mut transport, DEFLATE_QUEUE)?;
+        let (pfn_paddr, pfn_vaddr) = H::dma_alloc(1, BufferDirection::DriverToDevice);
+        if pfn_paddr == 0 {
+            return Err(DriverError::NoMemory);
+        }
+        transport.finish_init();
+
+        let mut dev = Self {
+            transport,
+            inflate_queue,
+            deflate_queue,
+            pfn_paddr,
+            pfn_vaddr,
+            pages: Vec::new(),
+            v2p,
+        };
+        dev.update_actual()?;
+        Ok(dev)
+    }
+
+    /// Number of pages the hypervisor asks the balloon to hold.
+    pub fn target_pages(&self) -> usize {
+        self.transport
+            .read_config_space::<u32>(CONFIG_NUM_PAGES)
+            .map_or(self.pages.len(), |n| n as usize)
+    }
+
+    /// Number of pages currently in the balloon.
+    pub fn actual_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Moves up to `count` pages from kalloc into the balloon.
+    ///
+    /// Stops early when kalloc runs out of pages. Returns the number of pages
+    /// inflated, or [`DriverError::NoMemory`] if none could be allocated.
+    pub fn inflate(&mut self, count: usize) -> DriverResult<usize> {
+        let mut done = 0;
+        while done < count {
+            let chunk = (count - done).min(PFNS_PER_REQUEST);
+            let mut batch = Vec::with_capacity(chunk);
+            for _ in 0..chunk {
+                match global_allocator().alloc_pages(1, PAGE_SIZE, UsageKind::Balloon) {
+                    Ok(va) => batch.push(VirtAddr::from(va)),
+                    Err(_) => break,
+                }
+            }
+            if batch.is_empty() {
+                break;
+            }
+
+            let len = self.fill_pfns(&batch);
+            self.inflate_queue
+                .submit(&mut self.transport, self.pfn_paddr, len);
+            done += batch.len();
+            let short = batch.len() < chunk;
+            self.pages.extend(batch);
+            if short {
+                break;
+            }
+        }
+        self.update_actual()?;
+
+        if done == 0 && count > 0 {
+            return Err(DriverError::NoMemory);
+        }
+        Ok(done)
+    }
+
+    /// Takes up to `count` pages back from the balloon and frees them to
+    /// kalloc. Returns the number of pages deflated.
+    pub fn deflate(&mut self, count: usize) -> DriverResult<usize> {
+        let count = count.min(self.pages.len());
+        let mut done = 0;
+        while done < count {
+            let chunk = (count - done).min(PFNS_PER_REQUEST);
+            let start = self.pages.len() - chunk;
+            let batch: Vec<VirtAddr> = self.pages.drain(start..).collect();
+
+            // The host must release the pages before they are reused.
+            let len = self.fill_pfns(&batch);
+            self.deflate_queue
+                .submit(&mut self.transport, self.pfn_paddr, len);
+            for va in batch {
+                global_allocator().dealloc_pages(va.as_usize(), 1, UsageKind::Balloon);
+            }
+            done += chunk;
+        }
+        self.update_actual()?;
+        Ok(done)
+    }
+
+    /// Inflates or deflates towards [`target_pages`](Self::target_pages).
+    ///
+    /// Returns the change in balloon size, positive when inflated.
+    pub fn adjust(&mut self) -> DriverResult<isize> {
+        let target = self.target_pages();
+        let actual = self.pages.len();
+        if target > actual {
+            Ok(self.inflate(target - actual)? as isize)
+        } else {
+            Ok(-(self.deflate(actual - target)? as isize))
+        }
+    }
+
+    /// Acknowledges an interrupt, returning `true` if the target changed.
+    pub fn ack_interrupt(&mut self) -> bool {
+        self.transport
+            .ack_interrupt()
+            .contains(InterruptStatus::DEVICE_CONFIGURATION_INTERRUPT)
+    }
+
+    /// Writes the frame numbers of `pages` into the request buffer and
+    /// returns its length in bytes.
+    fn fill_pfns(&mut self, pages: &[VirtAddr]) -> u32 {
+        let pfns = self.pfn_vaddr.as_ptr() as *mut u32;
+        for (i, &va) in pages.iter().enumerate() {
+            let pfn = ((self.v2p)(va).as_usize() >> BALLOON_PFN_SHIFT) as u32;
+            unsafe { pfns.add(i).write_volatile(pfn.to_le()) };
+        }
+        (pages.len() * size_of::<u32>()) as u32
+    }
+
+    fn update_actual(&mut self) -> DriverResult {
+        self.transport
+            .write_config_space::<u32>(CONFIG_ACTUAL, self.pages.len() as u32)
+            .map_err(as_driver_error)
+    }
+}
+
+impl<H: Hal, T: Transport> DriverOps for VirtIoBalloonDev<H, T> {
+    fn name(&self) -> &str {
+        "virtio-balloon"
+    }
+
+    fn device_kind(&self) -> DeviceKind {
+        DeviceKind::Balloon
+    }
+}
+
+impl<H: Hal, T: Transport> Drop for VirtIoBalloonDev<H, T> {
+    fn drop(&mut self) {
+        // Pages still in the balloon are asked back before the device goes away.
+        if let Err(e) = self.deflate(self.pages.len()) {
+            log::warn!("virtio-balloon: failed to deflate on drop: {e:?}");
+        }
+        self.transport.queue_unset(INFLATE_QUEUE);
+        self.transport.queue_unset(DEFLATE_QUEUE);
+        unsafe { H::dma_dealloc(self.pfn_paddr, self.pfn_vaddr, 1) };
+    }
+}
+
+#[cfg(unittest)]
+mod tests {
+    use unittest::{assert, assert_eq, def_test};
+    use virtio_drivers::transport::DeviceType;
+
+    use super::*;
+    use crate::mock_virtio::{MockHal, MockTransport};
+
+    fn identity_v2p(va: VirtAddr) -> memaddr::PhysAddr {
+        memaddr::PhysAddr::from(va.as_usize())
+    }
+
+    fn balloon_transport(num_pages: u32) -> MockTransport {
+        let mut transport = MockTransport::new();
+        transport.device_type = DeviceType::MemoryBalloon;
+        transport.features = (BalloonFeatures::all()).bits();
+        transport.config_space.borrow_mut()[..4].copy_from_slice(&num_pages.to_le_bytes());
+        transport
+    }
+
+    #[def_test]
+    fn test_virtio_balloon_init() {
+        let dev = VirtIoBalloonDev::<MockHal, MockTransport>::try_new(
+            balloon_transport(16),
+            identity_v2p,
+        );
+        assert!(dev.is_ok());
+        let dev = dev.unwrap();
+        assert_eq!(dev.name(), "virtio-balloon");
+        assert_eq!(dev.device_kind(), DeviceKind::Balloon);
+        assert_eq!(dev.target_pages(), 16);
+        assert_eq!(dev.actual_pages(), 0);
+        let actual = dev.transport.config_space.borrow()[4..8].to_vec();
+        assert_eq!(actual, [0u8; 4]);
+    }
+
+    #[def_test]
+    fn test_virtio_balloon_deflate_empty() {
+        let mut dev =
+            VirtIoBalloonDev::<MockHal, MockTransport>::try_new(balloon_transport(0), identity_v2p)
+                .unwrap();
+        // Nothing to hand back, the deflate queue must not be touched.
+        assert_eq!(dev.deflate(8).unwrap(), 0);
+        assert_eq!(dev.adjust().unwrap(), 0);
+    }
+
+    #[def_test]
+    fn test_virtio_balloon_pfn_encoding() {
+        let mut dev =
+            VirtIoBalloonDev::<MockHal, MockTransport>::try_new(balloon_transport(0), identity_v2p)
+                .unwrap();
+        let pages = [VirtAddr::from(0x8000_0000), VirtAddr::from(0x8000_3000)];
+        assert_eq!(dev.fill_pfns(&pages), 8);
+        let pfns = unsafe { core::slice::from_raw_parts(dev.pfn_vaddr.as_ptr() as *const u32, 2) };
+        assert_eq!(pfns, [0x80000, 0x80003]);
+    }
+}
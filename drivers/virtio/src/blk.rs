@@ -3,15 +3,61 @@
 // See LICENSES for license details.
 
 //! VirtIO block driver adapter.
-use block::BlockDriverOps;
-use driver_base::{DeviceKind, DriverOps, DriverResult};
-use virtio_drivers::{Hal, device::blk::VirtIOBlk as InnerDev, transport::Transport};
+use alloc::{boxed::Box, collections::BTreeMap};
+use core::{hint::spin_loop, time::Duration};
 
-use crate::as_driver_error;
+use block::{BlockDriverOps, BlockIo, BlockOp, IoToken};
+use driver_base::{DeviceKind, DriverError, DriverOps, DriverResult};
+use virtio_drivers::{
+    Hal,
+    device::blk::{BlkReq, BlkResp, VirtIOBlk as InnerDev},
+    transport::Transport,
+};
+
+use crate::{Reconnect, as_driver_error};
+
+/// Polls of the used ring after which a request is taken for lost, and the
+/// device for stuck, when there is no clock to time it.
+const STALL_POLLS: usize = 1 << 26;
+
+/// Polls of the used ring between two reads of the clock.
+const CLOCK_POLLS: usize = 1 << 10;
+
+/// A request submitted with [`BlockDriverOps::submit`], holding the buffers
+/// the device accesses until it completes.
+struct InFlight {
+    token: IoToken,
+    io: BlockIo,
+    req: Box<BlkReq>,
+    resp: Box<BlkResp>,
+}
 
 /// The VirtIO block device driver.
+///
+/// A request not answered within the [timeout](BlockDriverOps::set_timeout)
+/// fails with [`DriverError::TimedOut`]. Given a way to
+/// [reconnect](Self::with_reconnect), the device is then reset, e.g. when
+/// the backend on the host restarted; otherwise it is left down.
+///
+/// [Submitted](BlockDriverOps::submit) requests are not timed, they fail
+/// when the device is reset.
+///
+/// Blocks can't be [discarded](BlockDriverOps::discard): the inner driver
+/// doesn't negotiate `VIRTIO_BLK_F_DISCARD`.
 pub struct VirtIoBlkDev<H: Hal, T: Transport> {
-    inner: InnerDev<H, T>,
+    /// `None` if a reset failed or a request got stuck.
+    inner: Option<InnerDev<H, T>>,
+    /// Keyed by the token of the device. Dropped after `inner`, which stops
+    /// the device from accessing the buffers.
+    in_flight: BTreeMap<u16, InFlight>,
+    done: BTreeMap<IoToken, DriverResult<BlockIo>>,
+    next_token: u64,
+    irq: Option<usize>,
+    capacity: u64,
+    reconnect: Option<Reconnect<T>>,
+    clock: Option<fn() -> Duration>,
+    timeout: Option<Duration>,
+    stall_polls: usize,
 }
 
 unsafe impl<H: Hal, T: Transport> Send for VirtIoBlkDev<H, T> {}
@@ -21,10 +67,134 @@ impl<H: Hal, T: Transport> VirtIoBlkDev<H, T> {
     /// Creates a new driver instance and initializes the device, or returns
     /// an error if any step fails.
     pub fn try_new(transport: T) -> DriverResult<Self> {
+        let mut inner = InnerDev::new(transport).map_err(as_driver_error)?;
+        inner.disable_interrupts();
         Ok(Self {
-            inner: InnerDev::new(transport).map_err(as_driver_error)?,
+            capacity: inner.capacity(),
+            inner: Some(inner),
+            in_flight: BTreeMap::new(),
+            done: BTreeMap::new(),
+            next_token: 0,
+            irq: None,
+            reconnect: None,
+            clock: None,
+            timeout: None,
+            stall_polls: STALL_POLLS,
         })
     }
+
+    /// Sets the interrupt the device raises for completed requests, enabled
+    /// while waiting for [submitted](BlockDriverOps::submit) ones.
+    pub fn with_irq(mut self, irq: Option<usize>) -> Self {
+        self.irq = irq;
+        self
+    }
+
+    /// Lets the driver reset the device, with transports from `reconnect`.
+    pub fn with_reconnect(mut self, reconnect: Reconnect<T>) -> Self {
+        self.reconnect = Some(reconnect);
+        self
+    }
+
+    /// Lets the driver time requests with `clock`, the time since boot.
+    pub fn with_clock(mut self, clock: fn() -> Duration) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    fn inner(&mut self) -> DriverResult<&mut InnerDev<H, T>> {
+        self.inner.as_mut().ok_or(DriverError::BadState)
+    }
+
+    /// Waits for the request `token` to complete, returning `false` if the
+    /// device looks stuck.
+    ///
+    /// Without a timeout, waits for good unless the device can be reset.
+    fn wait(&mut self, token: u16) -> DriverResult<bool> {
+        let clock = self.clock;
+        let deadline = self
+            .timeout
+            .zip(clock)
+            .map(|(timeout, now)| now() + timeout);
+        let limit = match (deadline, &self.reconnect) {
+            (None, Some(_)) => self.stall_polls,
+            _ => usize::MAX,
+        };
+        for polls in 0..limit {
+            match self.inner()?.peek_used() {
+                Some(used) if used == token => {
+                    self.inner()?.ack_interrupt();
+                    return Ok(true);
+                }
+                // A submitted request completed first
+                Some(used) => self.complete(used)?,
+                None => spin_loop(),
+            }
+            if let (Some(deadline), Some(now)) = (deadline, clock)
+                && polls % CLOCK_POLLS == 0
+                && now() >= deadline
+            {
+                return Ok(false);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Completes the submitted request `used`, the next in the used ring.
+    fn complete(&mut self, used: u16) -> DriverResult {
+        let inner = self.inner.as_mut().ok_or(DriverError::BadState)?;
+        let mut request = self.in_flight.remove(&used).ok_or(DriverError::BadState)?;
+        let io = &mut request.io;
+        // Safe because these are the buffers the request was submitted with
+        let result = unsafe {
+            match io.op {
+                BlockOp::Read => {
+                    inner.complete_read_blocks(used, &request.req, &mut io.buf, &mut request.resp)
+                }
+                BlockOp::Write => {
+                    inner.complete_write_blocks(used, &request.req, &io.buf, &mut request.resp)
+                }
+            }
+        };
+        let result = result.map(|()| request.io).map_err(as_driver_error);
+        self.done.insert(request.token, result);
+        Ok(())
+    }
+
+    /// Waits for the submitted requests to complete, for a request whose
+    /// completion the inner driver waits for itself.
+    fn drain(&mut self) -> DriverResult {
+        while let Some((&used, request)) = self.in_flight.first_key_value() {
+            let block_id = request.io.block_id;
+            if !self.wait(used)? {
+                return Err(self.abort(block_id));
+            }
+            self.complete(used)?;
+        }
+        Ok(())
+    }
+
+    /// Drops the device, failing the submitted requests.
+    fn stop(&mut self) {
+        // The transport resets the device when dropped, the buffers are no
+        // longer accessed afterwards
+        self.inner = None;
+        for request in core::mem::take(&mut self.in_flight).into_values() {
+            self.done.insert(request.token, Err(DriverError::Io));
+        }
+    }
+
+    /// Takes the buffers of a stuck request back from the device, resetting
+    /// it or else leaving it down.
+    fn abort(&mut self, block_id: u64) -> DriverError {
+        log::warn!("virtio-blk: request for block {block_id} timed out");
+        match self.reset() {
+            Ok(()) => {}
+            Err(DriverError::Unsupported) => self.stop(),
+            Err(err) => log::warn!("virtio-blk: failed to reset the device: {err:?}"),
+        }
+        DriverError::TimedOut
+    }
 }
 
 impl<H: Hal, T: Transport> DriverOps for VirtIoBlkDev<H, T> {
@@ -35,12 +205,32 @@ impl<H: Hal, T: Transport> DriverOps for VirtIoBlkDev<H, T> {
     fn device_kind(&self) -> DeviceKind {
         DeviceKind::Block
     }
+
+    fn irq(&self) -> Option<usize> {
+        self.irq
+    }
+
+    fn reset(&mut self) -> DriverResult {
+        if self.reconnect.is_none() {
+            return Err(DriverError::Unsupported);
+        }
+        // The old transport must be gone before the new one sets up the
+        // queue
+        self.stop();
+        let reconnect = self.reconnect.as_mut().unwrap();
+        let transport = reconnect().ok_or(DriverError::Io)?;
+        let mut inner = InnerDev::new(transport).map_err(as_driver_error)?;
+        inner.disable_interrupts();
+        self.capacity = inner.capacity();
+        self.inner = Some(inner);
+        Ok(())
+    }
 }
 
 impl<H: Hal, T: Transport> BlockDriverOps for VirtIoBlkDev<H, T> {
     #[inline]
     fn num_blocks(&self) -> u64 {
-        self.inner.capacity()
+        self.capacity
     }
 
     #[inline]
@@ -49,20 +239,124 @@ impl<H: Hal, T: Transport> BlockDriverOps for VirtIoBlkDev<H, T> {
     }
 
     fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DriverResult {
-        self.inner
-            .read_blocks(block_id as _, buf)
-            .map_err(as_driver_error)
+        let mut req = BlkReq::default();
+        let mut resp = BlkResp::default();
+        // Safe because the buffers outlive the request: it either
+        // completes, or the device is stopped
+        let token = unsafe {
+            self.inner()?
+                .read_blocks_nb(block_id as _, &mut req, buf, &mut resp)
+                .map_err(as_driver_error)?
+        };
+        if !self.wait(token)? {
+            return Err(self.abort(block_id));
+        }
+        unsafe {
+            self.inner()?
+                .complete_read_blocks(token, &req, buf, &mut resp)
+                .map_err(as_driver_error)
+        }
     }
 
     fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DriverResult {
-        self.inner
-            .write_blocks(block_id as _, buf)
-            .map_err(as_driver_error)
+        let mut req = BlkReq::default();
+        let mut resp = BlkResp::default();
+        let token = unsafe {
+            self.inner()?
+                .write_blocks_nb(block_id as _, &mut req, buf, &mut resp)
+                .map_err(as_driver_error)?
+        };
+        if !self.wait(token)? {
+            return Err(self.abort(block_id));
+        }
+        unsafe {
+            self.inner()?
+                .complete_write_blocks(token, &req, buf, &mut resp)
+                .map_err(as_driver_error)
+        }
     }
 
+    /// Flushes the write cache of the device, if it has one
+    /// (`VIRTIO_BLK_F_FLUSH`), once the submitted requests completed.
     fn flush(&mut self) -> DriverResult {
+        self.drain()?;
+        self.inner()?.flush().map_err(as_driver_error)
+    }
+
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> DriverResult {
+        if self.clock.is_none() {
+            return Err(DriverError::Unsupported);
+        }
+        self.timeout = timeout;
         Ok(())
     }
+
+    fn submit(&mut self, mut io: BlockIo) -> DriverResult<IoToken> {
+        if io.buf.is_empty() || !io.buf.len().is_multiple_of(self.block_size()) {
+            return Err(DriverError::InvalidInput);
+        }
+        let mut req = Box::<BlkReq>::default();
+        let mut resp = Box::<BlkResp>::default();
+        let inner = self.inner()?;
+        // Safe because the buffers are kept in `in_flight` until the request
+        // completes or the device is stopped; moving the `Vec` and the boxes
+        // leaves their contents in place
+        let used = unsafe {
+            match io.op {
+                BlockOp::Read => {
+                    inner.read_blocks_nb(io.block_id as _, &mut req, &mut io.buf, &mut resp)
+                }
+                BlockOp::Write => {
+                    inner.write_blocks_nb(io.block_id as _, &mut req, &io.buf, &mut resp)
+                }
+            }
+        }
+        .map_err(|err| match err {
+            virtio_drivers::Error::QueueFull => DriverError::WouldBlock,
+            err => as_driver_error(err),
+        })?;
+        let token = IoToken(self.next_token);
+        self.next_token += 1;
+        self.in_flight.insert(
+            used,
+            InFlight {
+                token,
+                io,
+                req,
+                resp,
+            },
+        );
+        Ok(token)
+    }
+
+    fn poll_complete(&mut self, token: IoToken) -> DriverResult<BlockIo> {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.ack_interrupt();
+        }
+        while let Some(used) = self.inner.as_mut().and_then(|inner| inner.peek_used()) {
+            self.complete(used)?;
+        }
+        if self.in_flight.is_empty() {
+            self.enable_interrupt(false);
+        }
+        match self.done.remove(&token) {
+            Some(result) => result,
+            None if self.in_flight.values().any(|it| it.token == token) => {
+                Err(DriverError::WouldBlock)
+            }
+            None => Err(DriverError::InvalidInput),
+        }
+    }
+
+    fn enable_interrupt(&mut self, enable: bool) {
+        if let Some(inner) = self.inner.as_mut() {
+            if enable {
+                inner.enable_interrupts();
+            } else {
+                inner.disable_interrupts();
+            }
+        }
+    }
 }
 
 #[cfg(unittest)]
@@ -86,6 +380,89 @@ mod tests {
         }
     }
 
+    #[def_test]
+    fn test_virtio_blk_reset_on_stuck_request() {
+        use alloc::{boxed::Box, sync::Arc};
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        let with_capacity = |blocks: u32| {
+            let transport = MockTransport::new();
+            transport.config_space.borrow_mut()[..4].copy_from_slice(&blocks.to_le_bytes());
+            transport
+        };
+        let mut dev = VirtIoBlkDev::<MockHal, MockTransport>::try_new(with_capacity(8)).unwrap();
+        assert_eq!(dev.num_blocks(), 8);
+        assert!(matches!(dev.reset(), Err(DriverError::Unsupported)));
+
+        // The mock device never completes a request
+        let reconnects = Arc::new(AtomicUsize::new(0));
+        let counter = reconnects.clone();
+        let mut dev = dev.with_reconnect(Box::new(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+            Some(with_capacity(16))
+        }));
+        dev.stall_polls = 16;
+        let mut buf = [0; 512];
+        assert!(matches!(
+            dev.read_block(0, &mut buf),
+            Err(DriverError::TimedOut)
+        ));
+        assert_eq!(reconnects.load(Ordering::Relaxed), 1);
+        assert_eq!(dev.num_blocks(), 16);
+    }
+
+    #[def_test]
+    fn test_virtio_blk_timeout_leaves_device_down() {
+        use core::sync::atomic::{AtomicU64, Ordering};
+
+        static TICKS: AtomicU64 = AtomicU64::new(0);
+        fn clock() -> Duration {
+            Duration::from_millis(TICKS.fetch_add(1, Ordering::Relaxed))
+        }
+
+        let mut dev =
+            VirtIoBlkDev::<MockHal, MockTransport>::try_new(MockTransport::new()).unwrap();
+        assert!(matches!(
+            dev.set_timeout(Some(Duration::from_millis(4))),
+            Err(DriverError::Unsupported)
+        ));
+        let mut dev = dev.with_clock(clock);
+        dev.set_timeout(Some(Duration::from_millis(4))).unwrap();
+        let mut buf = [0; 512];
+        assert!(matches!(
+            dev.read_block(0, &mut buf),
+            Err(DriverError::TimedOut)
+        ));
+        assert!(matches!(
+            dev.write_block(0, &buf),
+            Err(DriverError::BadState)
+        ));
+    }
+
+    #[def_test]
+    fn test_virtio_blk_submitted_request_fails_on_reset() {
+        use alloc::boxed::Box;
+
+        let dev = VirtIoBlkDev::<MockHal, MockTransport>::try_new(MockTransport::new()).unwrap();
+        let mut dev = dev.with_reconnect(Box::new(|| Some(MockTransport::new())));
+        assert!(matches!(
+            dev.submit(BlockIo::read(0, 100)),
+            Err(DriverError::InvalidInput)
+        ));
+        // The mock device never completes a request
+        let token = dev.submit(BlockIo::read(0, 512)).unwrap();
+        assert!(matches!(
+            dev.poll_complete(token),
+            Err(DriverError::WouldBlock)
+        ));
+        dev.reset().unwrap();
+        assert!(matches!(dev.poll_complete(token), Err(DriverError::Io)));
+        assert!(matches!(
+            dev.poll_complete(token),
+            Err(DriverError::InvalidInput)
+        ));
+    }
+
     #[def_test]
     fn test_virtio_blk_concurrency_traits() {
         fn assert_send_sync<T: Send + Sync>() {}
@@ -3,6 +3,9 @@
 // See LICENSES for license details.
 
 //! VirtIO GPU driver adapter.
+use alloc::vec::Vec;
+use core::slice;
+
 use display::{DisplayDriverOps, DisplayInfo, FrameBuffer};
 use driver_base::{DeviceKind, DriverOps, DriverResult};
 use virtio_drivers::{Hal, device::gpu::VirtIOGpu as InnerDev, transport::Transport};
@@ -10,9 +13,14 @@ use virtio_drivers::{Hal, device::gpu::VirtIOGpu as InnerDev, transport::Transpo
 use crate::as_driver_error;
 
 /// The VirtIO GPU device driver.
+///
+/// While [blanked](DisplayDriverOps::set_blanked), the host shows a black
+/// frame and is sent no updates. The inner driver doesn't expose
+/// `SET_SCANOUT`, so the scanout stays attached rather than disabled.
 pub struct VirtIoGpuDev<H: Hal, T: Transport> {
     inner: InnerDev<H, T>,
     info: DisplayInfo,
+    blanked: bool,
 }
 
 unsafe impl<H: Hal, T: Transport> Send for VirtIoGpuDev<H, T> {}
@@ -39,6 +47,7 @@ impl<H: Hal, T: Transport> VirtIoGpuDev<H, T> {
         Ok(Self {
             inner: virtio,
             info,
+            blanked: false,
         })
     }
 }
@@ -65,10 +74,34 @@ impl<H: Hal, T: Transport> DisplayDriverOps for VirtIoGpuDev<H, T> {
     }
 
     fn need_flush(&self) -> bool {
-        true
+        !self.blanked
     }
 
     fn flush(&mut self) -> DriverResult {
+        if self.blanked {
+            return Ok(());
+        }
         self.inner.flush().map_err(as_driver_error)
     }
+
+    fn set_blanked(&mut self, blanked: bool) -> DriverResult {
+        if blanked == self.blanked {
+            return Ok(());
+        }
+        if !blanked {
+            self.blanked = false;
+            return self.flush();
+        }
+        // Send a black frame, keeping what was drawn for when it unblanks
+        let fb = unsafe {
+            slice::from_raw_parts_mut(self.info.fb_base_vaddr as *mut u8, self.info.fb_size)
+        };
+        let drawn: Vec<u8> = fb.to_vec();
+        fb.fill(0);
+        let result = self.inner.flush();
+        fb.copy_from_slice(&drawn);
+        result.map_err(as_driver_error)?;
+        self.blanked = true;
+        Ok(())
+    }
 }
@@ -21,6 +21,11 @@ extern crate alloc;
 #[cfg(feature = "net")]
 extern crate net as driver_net;
 
+#[cfg(feature = "balloon")]
+mod balloon;
+#[cfg(feature = "balloon")]
+pub use self::balloon::VirtIoBalloonDev;
+
 #[cfg(feature = "block")]
 mod blk;
 #[cfg(feature = "block")]
@@ -43,6 +48,7 @@ pub use self::net::VirtIoNetDev;
 
 #[cfg(unittest)]
 pub mod mock_virtio;
+mod ring;
 #[cfg(feature = "socket")]
 mod socket;
 use driver_base::{DeviceKind, DriverError};
@@ -56,9 +62,22 @@ pub use virtio_drivers::{
     },
 };
 
-use self::pci::{ConfigurationAccess, DeviceFunction, DeviceFunctionInfo, PciRoot};
+pub use self::ring::RingFeatures;
 #[cfg(feature = "socket")]
 pub use self::socket::VirtIoSocketDev;
+use self::{
+    pci::{ConfigurationAccess, DeviceFunction, DeviceFunctionInfo, PciRoot},
+    ring::log_ring_features,
+};
+
+/// Gives a new transport to the device a driver was created on, for the
+/// driver to reset it and set it up again, see [`DriverOps::reset`].
+///
+/// The old transport is dropped before, resetting the device.
+///
+/// [`DriverOps::reset`]: driver_base::DriverOps::reset
+#[cfg(feature = "alloc")]
+pub type Reconnect<T> = alloc::boxed::Box<dyn FnMut() -> Option<T> + Send + Sync>;
 
 /// Try to probe a VirtIO MMIO device from the given memory region.
 ///
@@ -73,8 +92,9 @@ pub fn probe_mmio_device(
     use virtio_drivers::transport::mmio::VirtIOHeader;
 
     let header = NonNull::new(reg_base as *mut VirtIOHeader).unwrap();
-    let transport = unsafe { MmioTransport::new(header, reg_size) }.ok()?;
+    let mut transport = unsafe { MmioTransport::new(header, reg_size) }.ok()?;
     let dev_kind = as_device_kind(transport.device_type())?;
+    log_ring_features(&mut transport);
     Some((dev_kind, transport))
 }
 
@@ -99,7 +119,8 @@ pub fn probe_pci_device<H: VirtIoHal, C: ConfigurationAccess>(
     const PCI_IRQ_BASE: usize = 0x23;
 
     let dev_kind = virtio_device_type(dev_info).and_then(as_device_kind)?;
-    let transport = PciTransport::new::<H, C>(root, bdf).ok()?;
+    let mut transport = PciTransport::new::<H, C>(root, bdf).ok()?;
+    log_ring_features(&mut transport);
     let irq = PCI_IRQ_BASE + (bdf.device & 3) as usize;
     Some((dev_kind, transport, irq))
 }
@@ -112,6 +133,7 @@ const fn as_device_kind(t: VirtIoDevType) -> Option<DeviceKind> {
         GPU => Some(DeviceKind::Display),
         Input => Some(DeviceKind::Input),
         Socket => Some(DeviceKind::Vsock),
+        MemoryBalloon => Some(DeviceKind::Balloon),
         _ => None,
     }
 }
@@ -3,16 +3,73 @@
 // See LICENSES for license details.
 
 //! VirtIO network driver adapter.
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{marker::PhantomData, ptr::NonNull};
 
 use driver_base::{DeviceKind, DriverError, DriverOps, DriverResult};
-use driver_net::{MacAddress, NetBuf, NetBufBox, NetBufHandle, NetBufPool, NetDriverOps};
-use virtio_drivers::{Hal, device::net::VirtIONetRaw as InnerDev, transport::Transport};
+use driver_net::{
+    MacAddress, NetBuf, NetBufBox, NetBufHandle, NetBufMemory, NetBufPool, NetCapabilities,
+    NetDriverOps,
+};
+use virtio_drivers::{
+    BufferDirection, Hal, PAGE_SIZE, PhysAddr, device::net::VirtIONetRaw as InnerDev,
+    transport::Transport,
+};
 
-use crate::as_driver_error;
+use crate::{Reconnect, as_driver_error};
 
 const NET_BUF_LEN: usize = 1526;
 
+/// Coherent DMA memory from the HAL, backing the buffer pool.
+///
+/// Buffers in it are shared with the device for good, so handing them to the
+/// virtqueues needs no bounce buffer.
+struct HalMemory<H: Hal> {
+    paddr: PhysAddr,
+    vaddr: NonNull<u8>,
+    pages: usize,
+    _hal: PhantomData<H>,
+}
+
+unsafe impl<H: Hal> Send for HalMemory<H> {}
+unsafe impl<H: Hal> Sync for HalMemory<H> {}
+
+impl<H: Hal> HalMemory<H> {
+    fn new(size: usize) -> DriverResult<Self> {
+        let pages = size.div_ceil(PAGE_SIZE);
+        let (paddr, vaddr) = H::dma_alloc(pages, BufferDirection::Both);
+        if paddr == 0 {
+            return Err(DriverError::NoMemory);
+        }
+        Ok(Self {
+            paddr,
+            vaddr,
+            pages,
+            _hal: PhantomData,
+        })
+    }
+}
+
+impl<H: Hal> NetBufMemory for HalMemory<H> {
+    fn as_ptr(&self) -> NonNull<u8> {
+        self.vaddr
+    }
+
+    fn size(&self) -> usize {
+        self.pages * PAGE_SIZE
+    }
+
+    fn bus_addr(&self) -> Option<u64> {
+        Some(self.paddr)
+    }
+}
+
+impl<H: Hal> Drop for HalMemory<H> {
+    fn drop(&mut self) {
+        unsafe { H::dma_dealloc(self.paddr, self.vaddr, self.pages) };
+    }
+}
+
 /// The VirtIO network device driver.
 ///
 /// `QS` is the VirtIO queue size.
@@ -21,14 +78,17 @@ pub struct VirtIoNetDev<H: Hal, T: Transport, const QS: usize> {
     tx_buffers: [Option<NetBufBox>; QS],
     free_tx_bufs: Vec<NetBufBox>,
     buf_pool: Arc<NetBufPool>,
-    inner: InnerDev<H, T, QS>,
+    /// `None` if a reset failed.
+    inner: Option<InnerDev<H, T, QS>>,
+    mac: MacAddress,
     irq: Option<usize>,
+    reconnect: Option<Reconnect<T>>,
 }
 
 unsafe impl<H: Hal, T: Transport, const QS: usize> Send for VirtIoNetDev<H, T, QS> {}
 unsafe impl<H: Hal, T: Transport, const QS: usize> Sync for VirtIoNetDev<H, T, QS> {}
 
-impl<H: Hal, T: Transport, const QS: usize> VirtIoNetDev<H, T, QS> {
+impl<H: Hal + 'static, T: Transport, const QS: usize> VirtIoNetDev<H, T, QS> {
     /// Creates a new driver instance and initializes the device, or returns
     /// an error if any step fails.
     pub fn try_new(transport: T, irq: Option<usize>) -> DriverResult<Self> {
@@ -37,46 +97,78 @@ impl<H: Hal, T: Transport, const QS: usize> VirtIoNetDev<H, T, QS> {
         let inner = InnerDev::new(transport).map_err(as_driver_error)?;
         let rx_buffers = [NONE_BUF; QS];
         let tx_buffers = [NONE_BUF; QS];
-        let buf_pool = NetBufPool::new(2 * QS, NET_BUF_LEN)?;
+        let memory = HalMemory::<H>::new(2 * QS * NET_BUF_LEN)?;
+        let buf_pool = NetBufPool::with_memory(2 * QS, NET_BUF_LEN, Box::new(memory))?;
         let free_tx_bufs = Vec::with_capacity(QS);
 
         let mut dev = Self {
             rx_buffers,
-            inner,
+            mac: MacAddress(inner.mac_address()),
+            inner: Some(inner),
             tx_buffers,
             free_tx_bufs,
             buf_pool,
             irq,
+            reconnect: None,
         };
 
         // 1. Fill all rx buffers.
-        for (i, rx_buf_place) in dev.rx_buffers.iter_mut().enumerate() {
-            let mut rx_buf = dev.buf_pool.alloc_boxed().ok_or(DriverError::NoMemory)?;
-            // Safe because the buffer lives as long as the queue.
-            let token = unsafe {
-                dev.inner
-                    .receive_begin(rx_buf.buffer_mut())
-                    .map_err(as_driver_error)?
-            };
-            assert_eq!(token, i as u16);
-            *rx_buf_place = Some(rx_buf);
+        for _ in 0..QS {
+            let rx_buf = dev.buf_pool.alloc_boxed().ok_or(DriverError::NoMemory)?;
+            dev.post_rx(rx_buf)?;
         }
 
         // 2. Allocate all tx buffers.
         for _ in 0..QS {
             let mut tx_buf = dev.buf_pool.alloc_boxed().ok_or(DriverError::NoMemory)?;
-            // Fill header
-            let hdr_len = dev
-                .inner
-                .fill_buffer_header(tx_buf.buffer_mut())
-                .or(Err(DriverError::InvalidInput))?;
-            tx_buf.set_hdr_len(hdr_len);
+            dev.fill_header(&mut tx_buf)?;
             dev.free_tx_bufs.push(tx_buf);
         }
 
         // 3. Return the driver instance.
         Ok(dev)
     }
+
+    /// Lets the driver reset the device, with transports from `reconnect`.
+    pub fn with_reconnect(mut self, reconnect: Reconnect<T>) -> Self {
+        self.reconnect = Some(reconnect);
+        self
+    }
+}
+
+impl<H: Hal, T: Transport, const QS: usize> VirtIoNetDev<H, T, QS> {
+    fn inner(&mut self) -> DriverResult<&mut InnerDev<H, T, QS>> {
+        self.inner.as_mut().ok_or(DriverError::BadState)
+    }
+
+    /// Hands `rx_buf` to the receive queue.
+    fn post_rx(&mut self, mut rx_buf: NetBufBox) -> DriverResult {
+        // Safe because we take the ownership of `rx_buf` to `rx_buffers`, it
+        // lives as long as the queue.
+        let token = unsafe {
+            self.inner()?
+                .receive_begin(rx_buf.buffer_mut())
+                .map_err(as_driver_error)?
+        };
+        // `rx_buffers[token]` is expected to be `None` since it was taken
+        // away at `Self::recv()` and has not been added back.
+        if self.rx_buffers[token as usize].is_some() {
+            return Err(DriverError::BadState);
+        }
+        self.rx_buffers[token as usize] = Some(rx_buf);
+        Ok(())
+    }
+
+    /// Fills the VirtIO header of `tx_buf`, whose layout depends on the
+    /// negotiated features.
+    fn fill_header(&mut self, tx_buf: &mut NetBufBox) -> DriverResult {
+        let hdr_len = self
+            .inner()?
+            .fill_buffer_header(tx_buf.buffer_mut())
+            .or(Err(DriverError::InvalidInput))?;
+        tx_buf.set_hdr_len(hdr_len);
+        Ok(())
+    }
 }
 
 impl<H: Hal, T: Transport, const QS: usize> DriverOps for VirtIoNetDev<H, T, QS> {
@@ -91,22 +183,90 @@ impl<H: Hal, T: Transport, const QS: usize> DriverOps for VirtIoNetDev<H, T, QS>
     fn irq(&self) -> Option<usize> {
         self.irq
     }
+
+    fn reset(&mut self) -> DriverResult {
+        let reconnect = self.reconnect.as_mut().ok_or(DriverError::Unsupported)?;
+        // The old transport resets the device when dropped, it must be gone
+        // before the new one sets up the queues
+        self.inner = None;
+        let transport = reconnect().ok_or(DriverError::Io)?;
+        let inner = InnerDev::new(transport).map_err(as_driver_error)?;
+        self.mac = MacAddress(inner.mac_address());
+        self.inner = Some(inner);
+
+        // Frames in flight are lost, their buffers go to the new queues
+        let rx_bufs: Vec<_> = self
+            .rx_buffers
+            .iter_mut()
+            .filter_map(Option::take)
+            .collect();
+        for rx_buf in rx_bufs {
+            self.post_rx(rx_buf)?;
+        }
+        let tx_bufs: Vec<_> = self
+            .tx_buffers
+            .iter_mut()
+            .filter_map(Option::take)
+            .collect();
+        self.free_tx_bufs.extend(tx_bufs);
+        let mut free_tx_bufs = core::mem::take(&mut self.free_tx_bufs);
+        let filled = free_tx_bufs
+            .iter_mut()
+            .try_for_each(|tx_buf| self.fill_header(tx_buf));
+        self.free_tx_bufs = free_tx_bufs;
+        filled
+    }
 }
 
 impl<H: Hal, T: Transport, const QS: usize> NetDriverOps for VirtIoNetDev<H, T, QS> {
     #[inline]
     fn mac(&self) -> MacAddress {
-        MacAddress(self.inner.mac_address())
+        self.mac
+    }
+
+    #[inline]
+    fn capabilities(&self) -> NetCapabilities {
+        // `VirtIONetRaw` negotiates neither VIRTIO_NET_F_CSUM nor
+        // VIRTIO_NET_F_GUEST_CSUM, so checksums stay in software.
+        NetCapabilities::empty()
+    }
+
+    #[inline]
+    fn link_up(&self) -> bool {
+        // `VirtIONetRaw` keeps the config space, and thus VIRTIO_NET_S_LINK_UP,
+        // to itself, so the link is reported as always up.
+        true
+    }
+
+    #[inline]
+    fn ack_interrupt(&mut self) -> bool {
+        self.inner
+            .as_mut()
+            .is_some_and(|inner| !inner.ack_interrupt().is_empty())
+    }
+
+    fn enable_rx_interrupt(&mut self, enable: bool) {
+        let Some(inner) = self.inner.as_mut() else {
+            return;
+        };
+        // The transmit queue is only ever polled, its notifications go along
+        if enable {
+            inner.enable_interrupts();
+        } else {
+            inner.disable_interrupts();
+        }
     }
 
     #[inline]
     fn can_tx(&self) -> bool {
-        !self.free_tx_bufs.is_empty() && self.inner.can_send()
+        !self.free_tx_bufs.is_empty() && self.inner.as_ref().is_some_and(|inner| inner.can_send())
     }
 
     #[inline]
     fn can_rx(&self) -> bool {
-        self.inner.poll_receive().is_some()
+        self.inner
+            .as_ref()
+            .is_some_and(|inner| inner.poll_receive().is_some())
     }
 
     #[inline]
@@ -120,30 +280,17 @@ impl<H: Hal, T: Transport, const QS: usize> NetDriverOps for VirtIoNetDev<H, T,
     }
 
     fn recycle_rx(&mut self, rx_buf: NetBufHandle) -> DriverResult {
-        let mut rx_buf = unsafe { NetBuf::from_handle(rx_buf) };
-        // Safe because we take the ownership of `rx_buf` back to `rx_buffers`,
-        // it lives as long as the queue.
-        let new_token = unsafe {
-            self.inner
-                .receive_begin(rx_buf.buffer_mut())
-                .map_err(as_driver_error)?
-        };
-        // `rx_buffers[new_token]` is expected to be `None` since it was taken
-        // away at `Self::recv()` and has not been added back.
-        if self.rx_buffers[new_token as usize].is_some() {
-            return Err(DriverError::BadState);
-        }
-        self.rx_buffers[new_token as usize] = Some(rx_buf);
-        Ok(())
+        let rx_buf = unsafe { NetBuf::from_handle(rx_buf) };
+        self.post_rx(rx_buf)
     }
 
     fn recycle_tx(&mut self) -> DriverResult {
-        while let Some(token) = self.inner.poll_transmit() {
+        while let Some(token) = self.inner()?.poll_transmit() {
             let tx_buf = self.tx_buffers[token as usize]
                 .take()
                 .ok_or(DriverError::BadState)?;
             unsafe {
-                self.inner
+                self.inner()?
                     .transmit_complete(token, tx_buf.frame())
                     .map_err(as_driver_error)?;
             }
@@ -158,7 +305,7 @@ impl<H: Hal, T: Transport, const QS: usize> NetDriverOps for VirtIoNetDev<H, T,
         let tx_buf = unsafe { NetBuf::from_handle(tx_buf) };
         // 1. send payload.
         let token = unsafe {
-            self.inner
+            self.inner()?
                 .transmit_begin(tx_buf.frame())
                 .map_err(as_driver_error)?
         };
@@ -167,14 +314,13 @@ impl<H: Hal, T: Transport, const QS: usize> NetDriverOps for VirtIoNetDev<H, T,
     }
 
     fn recv(&mut self) -> DriverResult<NetBufHandle> {
-        self.inner.ack_interrupt();
-        if let Some(token) = self.inner.poll_receive() {
+        if let Some(token) = self.inner()?.poll_receive() {
             let mut rx_buf = self.rx_buffers[token as usize]
                 .take()
                 .ok_or(DriverError::BadState)?;
             // Safe because the buffer lives as long as the queue.
             let (hdr_len, pkt_len) = unsafe {
-                self.inner
+                self.inner()?
                     .receive_complete(token, rx_buf.buffer_mut())
                     .map_err(as_driver_error)?
             };
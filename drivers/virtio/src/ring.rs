@@ -0,0 +1,90 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Virtqueue layout features.
+//!
+//! The device drivers in `virtio-drivers` negotiate their own feature sets
+//! during initialization, and do not tell which ones the device accepted.
+//! This module reports which virtqueue layout features a device offers and
+//! which of them the backend is able to use, an upper bound of what each
+//! driver negotiates.
+//!
+//! `virtio-drivers` only implements the split virtqueue, so the packed layout
+//! (`VIRTIO_F_RING_PACKED`) is not negotiated and not reported here.
+use bitflags::bitflags;
+use virtio_drivers::transport::Transport;
+
+bitflags! {
+    /// Transport-level virtqueue layout features (VirtIO 1.2, section 6).
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct RingFeatures: u64 {
+        /// `VIRTIO_F_INDIRECT_DESC`: descriptors may point to a descriptor table.
+        const INDIRECT_DESC = 1 << 28;
+        /// `VIRTIO_F_EVENT_IDX`: `used_event`/`avail_event` notification suppression.
+        const EVENT_IDX = 1 << 29;
+    }
+}
+
+impl RingFeatures {
+    /// Layout features the backend is able to drive.
+    ///
+    /// Indirect descriptor tables are heap allocated, so they need the `alloc`
+    /// feature.
+    pub const fn supported() -> Self {
+        if cfg!(feature = "alloc") {
+            Self::INDIRECT_DESC.union(Self::EVENT_IDX)
+        } else {
+            Self::EVENT_IDX
+        }
+    }
+
+    /// Reads the layout features offered by the device behind `transport`.
+    pub fn offered<T: Transport>(transport: &mut T) -> Self {
+        Self::from_bits_truncate(transport.read_device_features())
+    }
+
+    /// Offered layout features that the backend is able to use.
+    pub fn usable(self) -> Self {
+        self & Self::supported()
+    }
+}
+
+/// Logs the virtqueue layout features the device behind `transport` offers.
+pub(crate) fn log_ring_features<T: Transport>(transport: &mut T) -> RingFeatures {
+    let offered = RingFeatures::offered(transport);
+    let usable = offered.usable();
+    log::debug!(
+        "virtio {:?}: ring features offered {:?}, usable {:?}",
+        transport.device_type(),
+        offered,
+        usable
+    );
+    usable
+}
+
+#[cfg(unittest)]
+mod tests {
+    use unittest::{assert, assert_eq, def_test};
+
+    use super::*;
+    use crate::mock_virtio::MockTransport;
+
+    #[def_test]
+    fn test_ring_features_offered() {
+        let mut transport = MockTransport::new();
+        transport.features = (1 << 28) | (1 << 29) | (1 << 34) | (1 << 32);
+        let offered = RingFeatures::offered(&mut transport);
+        assert_eq!(offered, RingFeatures::all());
+    }
+
+    #[def_test]
+    fn test_ring_features_usable() {
+        let usable = RingFeatures::all().usable();
+        assert!(usable.contains(RingFeatures::EVENT_IDX));
+        assert_eq!(
+            usable.contains(RingFeatures::INDIRECT_DESC),
+            cfg!(feature = "alloc")
+        );
+    }
+}
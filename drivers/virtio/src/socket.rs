@@ -2,25 +2,529 @@
 // Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
 // See LICENSES for license details.
 
-//! VirtIO vsock driver adapter.
-use driver_base::{DeviceKind, DriverOps, DriverResult};
-use virtio_drivers::{
-    Hal,
-    device::socket::{
-        VirtIOSocket, VsockAddr, VsockConnectionManager as InnerDev, VsockEvent, VsockEventType,
-    },
-    transport::Transport,
+//! VirtIO vsock driver.
+//!
+//! `virtio-drivers` only handles stream connections, so this module drives
+//! the virtqueues itself. Besides streams it supports seqpacket connections
+//! and, on devices offering them, datagrams.
+mod packet;
+mod queue;
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    vec::Vec,
 };
-use vsock::{VsockConnId, VsockDriverEventType, VsockDriverOps};
 
-use crate::as_driver_error;
+use bitflags::bitflags;
+use driver_base::{DeviceKind, DriverError, DriverOps, DriverResult};
+use virtio_drivers::{Hal, transport::Transport};
+use vsock::{VsockAddr, VsockConnId, VsockDriverEventType, VsockDriverOps, VsockSocketType};
+
+use self::{packet::*, queue::PacketQueue};
+
+const RX_QUEUE: u16 = 0;
+const TX_QUEUE: u16 = 1;
+const EVENT_QUEUE: u16 = 2;
+const QUEUE_SIZE: u16 = 16;
+/// Size of a packet buffer, header included.
+const PACKET_LEN: usize = 4096;
+const MAX_PAYLOAD: usize = PACKET_LEN - HDR_LEN;
+/// Size of an event buffer.
+const EVENT_LEN: usize = 8;
+/// The only event defined: every connection was reset.
+const EVENT_TRANSPORT_RESET: u32 = 0;
+
+/// Receive buffer space of each connection, granted to peers as credit.
+const DEFAULT_BUFFER_SIZE: u32 = 32 * 1024;
+/// Datagrams held until received; more are dropped.
+const MAX_DATAGRAMS: usize = 64;
+
+/// Config space: the guest CID.
+const CONFIG_GUEST_CID: usize = 0;
+
+bitflags! {
+    /// Feature bits of the socket device (VirtIO 1.2, section 5.10.3).
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct VsockFeatures: u64 {
+        /// Stream sockets are supported.
+        const STREAM = 1 << 0;
+        /// Seqpacket sockets are supported.
+        const SEQPACKET = 1 << 1;
+        /// Streams are only supported if `STREAM` is negotiated.
+        const NO_IMPLIED_STREAM = 1 << 2;
+        /// Datagram sockets are supported, as in the datagram proposal.
+        const DGRAM = 1 << 3;
+        /// Device complies with VirtIO 1.0 or later.
+        const VERSION_1 = 1 << 32;
+    }
+}
+
+impl VsockFeatures {
+    fn supports(self, ty: VsockSocketType) -> bool {
+        match ty {
+            VsockSocketType::Stream => {
+                self.contains(Self::STREAM) || !self.contains(Self::NO_IMPLIED_STREAM)
+            }
+            VsockSocketType::SeqPacket => self.contains(Self::SEQPACKET),
+            VsockSocketType::Dgram => self.contains(Self::DGRAM),
+        }
+    }
+}
+
+fn type_code(ty: VsockSocketType) -> u16 {
+    match ty {
+        VsockSocketType::Stream => TYPE_STREAM,
+        VsockSocketType::SeqPacket => TYPE_SEQPACKET,
+        VsockSocketType::Dgram => TYPE_DGRAM,
+    }
+}
+
+/// A stream or seqpacket connection.
+struct Connection {
+    ty: VsockSocketType,
+    /// Whether the peer accepted the connection.
+    connected: bool,
+    /// Whether either side closed the connection. It is dropped once its
+    /// data has been received.
+    closed: bool,
+    rx: VecDeque<u8>,
+    /// Lengths of the complete messages at the front of `rx`.
+    msg_lens: VecDeque<usize>,
+    /// Bytes of `rx` after the complete messages.
+    partial_len: usize,
+    /// Bytes received by the socket, as reported to the peer.
+    fwd_cnt: u32,
+    /// Bytes sent.
+    tx_cnt: u32,
+    peer_buf_alloc: u32,
+    peer_fwd_cnt: u32,
+    /// Credit a blocked send waits for, or 0.
+    credit_wanted: u32,
+}
+
+impl Connection {
+    fn new(ty: VsockSocketType, connected: bool) -> Self {
+        Self {
+            ty,
+            connected,
+            closed: false,
+            rx: VecDeque::new(),
+            msg_lens: VecDeque::new(),
+            partial_len: 0,
+            fwd_cnt: 0,
+            tx_cnt: 0,
+            peer_buf_alloc: 0,
+            peer_fwd_cnt: 0,
+            credit_wanted: 0,
+        }
+    }
+
+    /// Bytes the peer can still take.
+    fn peer_credit(&self) -> u32 {
+        self.peer_buf_alloc
+            .saturating_sub(self.tx_cnt.wrapping_sub(self.peer_fwd_cnt))
+    }
+
+    /// Takes received data, stopping at the end of a message. Returns the
+    /// length read and whether it ended a message.
+    fn read(&mut self, buf: &mut [u8]) -> (usize, bool) {
+        let limit = self.msg_lens.front().copied().unwrap_or(self.rx.len());
+        let len = buf.len().min(limit);
+        for (dst, src) in buf.iter_mut().zip(self.rx.drain(..len)) {
+            *dst = src;
+        }
+        self.fwd_cnt = self.fwd_cnt.wrapping_add(len as u32);
+        match self.msg_lens.front_mut() {
+            Some(remaining) => {
+                *remaining -= len;
+                let eom = *remaining == 0;
+                if eom {
+                    self.msg_lens.pop_front();
+                }
+                (len, eom)
+            }
+            None => {
+                self.partial_len -= len;
+                (len, false)
+            }
+        }
+    }
+}
+
+/// Protocol state, kept apart from the virtqueues.
+///
+/// Packets to send are queued in `outbox` and events for the stack in
+/// `events`.
+struct Protocol {
+    guest_cid: u64,
+    features: VsockFeatures,
+    listening: BTreeMap<u32, VsockSocketType>,
+    connections: BTreeMap<VsockConnId, Connection>,
+    datagrams: VecDeque<(VsockConnId, Vec<u8>)>,
+    outbox: VecDeque<(Header, Vec<u8>)>,
+    events: VecDeque<VsockDriverEventType>,
+}
+
+impl Protocol {
+    fn new(guest_cid: u64, features: VsockFeatures) -> Self {
+        Self {
+            guest_cid,
+            features,
+            listening: BTreeMap::new(),
+            connections: BTreeMap::new(),
+            datagrams: VecDeque::new(),
+            outbox: VecDeque::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    fn header(&self, cid: VsockConnId, ty: VsockSocketType, op: u16) -> Header {
+        let fwd_cnt = self.connections.get(&cid).map_or(0, |conn| conn.fwd_cnt);
+        Header {
+            src_cid: self.guest_cid,
+            dst_cid: cid.peer_addr.cid,
+            src_port: cid.local_port,
+            dst_port: cid.peer_addr.port,
+            ty: type_code(ty),
+            op,
+            buf_alloc: DEFAULT_BUFFER_SIZE,
+            fwd_cnt,
+            ..Default::default()
+        }
+    }
+
+    fn send_control(&mut self, cid: VsockConnId, ty: VsockSocketType, op: u16, flags: u32) {
+        let header = Header {
+            flags,
+            ..self.header(cid, ty, op)
+        };
+        self.outbox.push_back((header, Vec::new()));
+    }
+
+    /// Answers a packet that belongs to no connection.
+    fn reset(&mut self, header: &Header) {
+        if header.op == OP_RST {
+            return;
+        }
+        let reply = Header {
+            src_cid: self.guest_cid,
+            dst_cid: header.src_cid,
+            src_port: header.dst_port,
+            dst_port: header.src_port,
+            ty: header.ty,
+            op: OP_RST,
+            ..Default::default()
+        };
+        self.outbox.push_back((reply, Vec::new()));
+    }
+
+    /// Drops a closed connection once its data has been received.
+    fn reap(&mut self, cid: VsockConnId) {
+        if self
+            .connections
+            .get(&cid)
+            .is_some_and(|conn| conn.closed && conn.rx.is_empty())
+        {
+            self.connections.remove(&cid);
+        }
+    }
+
+    /// Handles a packet from the device.
+    fn handle(&mut self, header: &Header, payload: &[u8]) {
+        if header.dst_cid != self.guest_cid {
+            log::debug!("virtio-vsock: dropping packet for CID {}", header.dst_cid);
+            return;
+        }
+        let ty = match header.ty {
+            TYPE_STREAM => VsockSocketType::Stream,
+            TYPE_SEQPACKET => VsockSocketType::SeqPacket,
+            TYPE_DGRAM => VsockSocketType::Dgram,
+            _ => return self.reset(header),
+        };
+        let cid = VsockConnId {
+            peer_addr: VsockAddr {
+                cid: header.src_cid,
+                port: header.src_port,
+            },
+            local_port: header.dst_port,
+        };
+        if ty == VsockSocketType::Dgram {
+            return self.handle_dgram(cid, header, payload);
+        }
+
+        if header.op == OP_REQUEST {
+            if self.connections.contains_key(&cid) {
+                log::debug!("virtio-vsock: duplicate connection request {cid:?}");
+            } else if self.listening.get(&cid.local_port) == Some(&ty) {
+                let mut conn = Connection::new(ty, true);
+                conn.peer_buf_alloc = header.buf_alloc;
+                conn.peer_fwd_cnt = header.fwd_cnt;
+                self.connections.insert(cid, conn);
+                self.send_control(cid, ty, OP_RESPONSE, 0);
+                self.events
+                    .push_back(VsockDriverEventType::ConnectionRequest(cid));
+            } else {
+                self.reset(header);
+            }
+            return;
+        }
+
+        let Some(conn) = self.connections.get_mut(&cid).filter(|conn| conn.ty == ty) else {
+            return self.reset(header);
+        };
+        conn.peer_buf_alloc = header.buf_alloc;
+        conn.peer_fwd_cnt = header.fwd_cnt;
+        if conn.credit_wanted != 0 && conn.peer_credit() >= conn.credit_wanted {
+            conn.credit_wanted = 0;
+            self.events
+                .push_back(VsockDriverEventType::CreditUpdate(cid));
+        }
+
+        match header.op {
+            OP_RESPONSE if !conn.connected => {
+                conn.connected = true;
+                self.events.push_back(VsockDriverEventType::Connected(cid));
+            }
+            OP_RW if conn.connected && !conn.closed => {
+                if conn.rx.len() + payload.len() > DEFAULT_BUFFER_SIZE as usize {
+                    // The peer ignored its credit
+                    log::warn!("virtio-vsock: {cid:?} overran its credit, dropping data");
+                    return;
+                }
+                conn.rx.extend(payload);
+                conn.partial_len += payload.len();
+                if ty == VsockSocketType::SeqPacket && header.flags & SEQ_EOM != 0 {
+                    conn.msg_lens.push_back(conn.partial_len);
+                    conn.partial_len = 0;
+                }
+                self.events
+                    .push_back(VsockDriverEventType::Received(cid, payload.len()));
+            }
+            OP_CREDIT_UPDATE => {}
+            OP_CREDIT_REQUEST => self.send_control(cid, ty, OP_CREDIT_UPDATE, 0),
+            OP_SHUTDOWN | OP_RST => {
+                let was_closed = conn.closed;
+                conn.closed = true;
+                let both = SHUTDOWN_RCV | SHUTDOWN_SEND;
+                if header.op == OP_SHUTDOWN && header.flags & both == both {
+                    self.send_control(cid, ty, OP_RST, 0);
+                }
+                if !was_closed {
+                    self.events
+                        .push_back(VsockDriverEventType::Disconnected(cid));
+                }
+                self.reap(cid);
+            }
+            _ => {
+                log::warn!(
+                    "virtio-vsock: unexpected op {} on {cid:?}, resetting",
+                    header.op
+                );
+                self.connections.remove(&cid);
+                self.send_control(cid, ty, OP_RST, 0);
+                self.events
+                    .push_back(VsockDriverEventType::Disconnected(cid));
+            }
+        }
+    }
+
+    fn handle_dgram(&mut self, cid: VsockConnId, header: &Header, payload: &[u8]) {
+        if header.op != OP_RW || !self.features.supports(VsockSocketType::Dgram) {
+            return;
+        }
+        if self.datagrams.len() >= MAX_DATAGRAMS {
+            log::debug!("virtio-vsock: datagram queue full, dropping one from {cid:?}");
+            return;
+        }
+        self.datagrams.push_back((cid, payload.to_vec()));
+        self.events
+            .push_back(VsockDriverEventType::DatagramReceived(cid, payload.len()));
+    }
+
+    /// Drops every connection, as the device asked.
+    fn transport_reset(&mut self, guest_cid: u64) {
+        self.guest_cid = guest_cid;
+        for (cid, conn) in core::mem::take(&mut self.connections) {
+            if !conn.closed {
+                self.events
+                    .push_back(VsockDriverEventType::Disconnected(cid));
+            }
+        }
+        self.datagrams.clear();
+    }
+
+    fn listen(&mut self, port: u32, ty: VsockSocketType) -> DriverResult {
+        if ty == VsockSocketType::Dgram || !self.features.supports(ty) {
+            return Err(DriverError::Unsupported);
+        }
+        self.listening.insert(port, ty);
+        Ok(())
+    }
+
+    fn connect(&mut self, cid: VsockConnId, ty: VsockSocketType) -> DriverResult {
+        if ty == VsockSocketType::Dgram || !self.features.supports(ty) {
+            return Err(DriverError::Unsupported);
+        }
+        if self.connections.contains_key(&cid) {
+            return Err(DriverError::AlreadyExists);
+        }
+        self.connections.insert(cid, Connection::new(ty, false));
+        self.send_control(cid, ty, OP_REQUEST, 0);
+        Ok(())
+    }
+
+    fn send(&mut self, cid: VsockConnId, buf: &[u8]) -> DriverResult<usize> {
+        let conn = self.connections.get(&cid).ok_or(DriverError::BadState)?;
+        if !conn.connected || conn.closed {
+            return Err(DriverError::BadState);
+        }
+        let ty = conn.ty;
+        let credit = conn.peer_credit() as usize;
+        let len = match ty {
+            // Messages go whole or not at all
+            VsockSocketType::SeqPacket if buf.len() > conn.peer_buf_alloc as usize => {
+                return Err(DriverError::InvalidInput);
+            }
+            VsockSocketType::SeqPacket if buf.len() > credit => 0,
+            _ => buf.len().min(credit),
+        };
+        if len < buf.len() {
+            // Wake the sender once the rest, or the whole message, fits
+            let wanted = if ty == VsockSocketType::SeqPacket {
+                buf.len() as u32
+            } else {
+                1
+            };
+            self.connections.get_mut(&cid).unwrap().credit_wanted = wanted;
+            if len == 0 {
+                self.send_control(cid, ty, OP_CREDIT_REQUEST, 0);
+                return Err(DriverError::WouldBlock);
+            }
+        }
 
-/// Default buffer size for VirtIO socket device (32KB).
-const DEFAULT_BUFFER_SIZE: usize = 32 * 1024;
+        let header = self.header(cid, ty, OP_RW);
+        let mut chunks = buf[..len].chunks(MAX_PAYLOAD).peekable();
+        if chunks.peek().is_none() && ty == VsockSocketType::SeqPacket {
+            // An empty message still needs a packet
+            let flags = SEQ_EOM;
+            self.outbox
+                .push_back((Header { flags, ..header }, Vec::new()));
+        }
+        while let Some(chunk) = chunks.next() {
+            let last = chunks.peek().is_none();
+            let flags = if ty == VsockSocketType::SeqPacket && last {
+                SEQ_EOM
+            } else {
+                0
+            };
+            let packet = Header {
+                len: chunk.len() as u32,
+                flags,
+                ..header
+            };
+            self.outbox.push_back((packet, chunk.to_vec()));
+        }
+        let conn = self.connections.get_mut(&cid).unwrap();
+        conn.tx_cnt = conn.tx_cnt.wrapping_add(len as u32);
+        Ok(len)
+    }
+
+    fn recv(&mut self, cid: VsockConnId, buf: &mut [u8]) -> DriverResult<(usize, bool)> {
+        let conn = self
+            .connections
+            .get_mut(&cid)
+            .ok_or(DriverError::BadState)?;
+        let ty = conn.ty;
+        let closed = conn.closed;
+        let (len, eom) = conn.read(buf);
+        if len > 0 && !closed {
+            self.send_control(cid, ty, OP_CREDIT_UPDATE, 0);
+        }
+        self.reap(cid);
+        Ok((len, eom))
+    }
+
+    fn recv_avail(&self, cid: VsockConnId) -> DriverResult<usize> {
+        let conn = self.connections.get(&cid).ok_or(DriverError::BadState)?;
+        Ok(conn.rx.len())
+    }
+
+    fn send_credit(&self, cid: VsockConnId) -> DriverResult<usize> {
+        let conn = self.connections.get(&cid).ok_or(DriverError::BadState)?;
+        if !conn.connected || conn.closed {
+            return Err(DriverError::BadState);
+        }
+        Ok(conn.peer_credit() as usize)
+    }
+
+    fn disconnect(&mut self, cid: VsockConnId) -> DriverResult {
+        let conn = self
+            .connections
+            .get_mut(&cid)
+            .ok_or(DriverError::BadState)?;
+        let ty = conn.ty;
+        conn.closed = true;
+        // Nobody receives the rest, and the peer answers with a reset, which
+        // drops the connection
+        conn.rx.clear();
+        conn.msg_lens.clear();
+        conn.partial_len = 0;
+        self.send_control(cid, ty, OP_SHUTDOWN, SHUTDOWN_RCV | SHUTDOWN_SEND);
+        Ok(())
+    }
+
+    fn abort(&mut self, cid: VsockConnId) -> DriverResult {
+        let conn = self.connections.remove(&cid).ok_or(DriverError::BadState)?;
+        self.send_control(cid, conn.ty, OP_RST, 0);
+        Ok(())
+    }
+
+    fn send_dgram(
+        &mut self,
+        src_port: u32,
+        peer_addr: VsockAddr,
+        buf: &[u8],
+    ) -> DriverResult<usize> {
+        if !self.features.supports(VsockSocketType::Dgram) {
+            return Err(DriverError::Unsupported);
+        }
+        if buf.len() > MAX_PAYLOAD {
+            return Err(DriverError::InvalidInput);
+        }
+        let header = Header {
+            src_cid: self.guest_cid,
+            dst_cid: peer_addr.cid,
+            src_port,
+            dst_port: peer_addr.port,
+            len: buf.len() as u32,
+            ty: TYPE_DGRAM,
+            op: OP_RW,
+            ..Default::default()
+        };
+        self.outbox.push_back((header, buf.to_vec()));
+        Ok(buf.len())
+    }
+
+    fn recv_dgram(&mut self, cid: VsockConnId, buf: &mut [u8]) -> DriverResult<usize> {
+        let pos = self
+            .datagrams
+            .iter()
+            .position(|(it, _)| *it == cid)
+            .ok_or(DriverError::WouldBlock)?;
+        let (_, data) = self.datagrams.remove(pos).unwrap();
+        let len = buf.len().min(data.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Ok(data.len())
+    }
+}
 
 /// The VirtIO socket device driver.
 pub struct VirtIoSocketDev<H: Hal, T: Transport> {
-    inner: InnerDev<H, T>,
+    transport: T,
+    rx: PacketQueue<H>,
+    tx: PacketQueue<H>,
+    event: PacketQueue<H>,
+    proto: Protocol,
 }
 
 unsafe impl<H: Hal, T: Transport> Send for VirtIoSocketDev<H, T> {}
@@ -29,12 +533,76 @@ unsafe impl<H: Hal, T: Transport> Sync for VirtIoSocketDev<H, T> {}
 impl<H: Hal, T: Transport> VirtIoSocketDev<H, T> {
     /// Creates a new driver instance and initializes the device, or returns
     /// an error if any step fails.
-    pub fn try_new(transport: T) -> DriverResult<Self> {
-        let virtio_socket = VirtIOSocket::<H, _>::new(transport).map_err(as_driver_error)?;
+    pub fn try_new(mut transport: T) -> DriverResult<Self> {
+        let features = transport.begin_init(VsockFeatures::all());
+        log::debug!("virtio-vsock: negotiated features {features:?}");
+        let guest_cid = read_guest_cid(&transport)?;
+
+        let mut rx = PacketQueue::new(&mut transport, RX_QUEUE, QUEUE_SIZE, PACKET_LEN, true)?;
+        let tx = PacketQueue::new(&mut transport, TX_QUEUE, QUEUE_SIZE, PACKET_LEN, false)?;
+        let mut event = PacketQueue::new(&mut transport, EVENT_QUEUE, QUEUE_SIZE, EVENT_LEN, true)?;
+        rx.fill();
+        event.fill();
+        transport.finish_init();
+        transport.notify(RX_QUEUE);
+        transport.notify(EVENT_QUEUE);
+
         Ok(Self {
-            inner: InnerDev::new_with_capacity(virtio_socket, DEFAULT_BUFFER_SIZE as u32),
+            transport,
+            rx,
+            tx,
+            event,
+            proto: Protocol::new(guest_cid, features),
         })
     }
+
+    /// Handles the packets and events the device has written.
+    fn process(&mut self) -> DriverResult {
+        let mut recycled = false;
+        while let Some((id, len)) = self.rx.pop_used() {
+            match Header::decode(&self.rx.buffer(id)[..len]) {
+                Some((header, payload)) => self.proto.handle(&header, payload),
+                None => log::warn!("virtio-vsock: dropping truncated packet"),
+            }
+            self.rx.push(id, PACKET_LEN);
+            recycled = true;
+        }
+        if recycled {
+            self.transport.notify(RX_QUEUE);
+        }
+
+        while let Some((id, _)) = self.event.pop_used() {
+            let event_id = u32::from_le_bytes(self.event.buffer(id)[..4].try_into().unwrap());
+            if event_id == EVENT_TRANSPORT_RESET {
+                log::info!("virtio-vsock: transport reset");
+                let guest_cid = read_guest_cid(&self.transport)?;
+                self.proto.transport_reset(guest_cid);
+            }
+            self.event.push(id, EVENT_LEN);
+            self.transport.notify(EVENT_QUEUE);
+        }
+        self.flush();
+        Ok(())
+    }
+
+    /// Sends the queued packets.
+    fn flush(&mut self) {
+        while let Some((header, payload)) = self.proto.outbox.pop_front() {
+            let buf = self.tx.buffer(0);
+            header.encode(buf);
+            buf[HDR_LEN..HDR_LEN + payload.len()].copy_from_slice(&payload);
+            self.tx.send(&mut self.transport, HDR_LEN + payload.len());
+        }
+    }
+}
+
+fn read_guest_cid<T: Transport>(transport: &T) -> DriverResult<u64> {
+    let low = transport.read_config_space::<u32>(CONFIG_GUEST_CID);
+    let high = transport.read_config_space::<u32>(CONFIG_GUEST_CID + 4);
+    match (low, high) {
+        (Ok(low), Ok(high)) => Ok(low as u64 | (high as u64) << 32),
+        _ => Err(DriverError::BadState),
+    }
 }
 
 impl<H: Hal, T: Transport> DriverOps for VirtIoSocketDev<H, T> {
@@ -47,107 +615,308 @@ impl<H: Hal, T: Transport> DriverOps for VirtIoSocketDev<H, T> {
     }
 }
 
-fn extract_addr_and_port(cid: VsockConnId) -> (VsockAddr, u32) {
-    (
-        VsockAddr {
-            cid: cid.peer_addr.cid as _,
-            port: cid.peer_addr.port as _,
-        },
-        cid.local_port,
-    )
-}
-
 impl<H: Hal, T: Transport> VsockDriverOps for VirtIoSocketDev<H, T> {
     fn guest_cid(&self) -> u64 {
-        self.inner.guest_cid()
+        self.proto.guest_cid
+    }
+
+    fn supports(&self, ty: VsockSocketType) -> bool {
+        self.proto.features.supports(ty)
     }
 
-    fn listen(&mut self, src_port: u32) {
-        self.inner.listen(src_port)
+    fn listen(&mut self, src_port: u32, ty: VsockSocketType) -> DriverResult<()> {
+        self.proto.listen(src_port, ty)
     }
 
-    fn connect(&mut self, cid: VsockConnId) -> DriverResult<()> {
-        let (peer_addr, src_port) = extract_addr_and_port(cid);
-        self.inner
-            .connect(peer_addr, src_port)
-            .map_err(as_driver_error)
+    fn unlisten(&mut self, src_port: u32) {
+        self.proto.listening.remove(&src_port);
+    }
+
+    fn connect(&mut self, cid: VsockConnId, ty: VsockSocketType) -> DriverResult<()> {
+        self.proto.connect(cid, ty)?;
+        self.flush();
+        Ok(())
     }
 
     fn send(&mut self, cid: VsockConnId, buf: &[u8]) -> DriverResult<usize> {
-        let (peer_addr, src_port) = extract_addr_and_port(cid);
-        match self.inner.send(peer_addr, src_port, buf) {
-            Ok(()) => Ok(buf.len()),
-            Err(e) => Err(as_driver_error(e)),
-        }
+        let result = self.proto.send(cid, buf);
+        self.flush();
+        result
     }
 
     fn recv(&mut self, cid: VsockConnId, buf: &mut [u8]) -> DriverResult<usize> {
-        let (peer_addr, src_port) = extract_addr_and_port(cid);
-        let res = self
-            .inner
-            .recv(peer_addr, src_port, buf)
-            .map_err(as_driver_error);
-        let _ = self.inner.update_credit(peer_addr, src_port);
-        res
+        self.recv_msg(cid, buf).map(|(len, _)| len)
+    }
+
+    fn recv_msg(&mut self, cid: VsockConnId, buf: &mut [u8]) -> DriverResult<(usize, bool)> {
+        let result = self.proto.recv(cid, buf);
+        self.flush();
+        result
     }
 
     fn recv_avail(&mut self, cid: VsockConnId) -> DriverResult<usize> {
-        let (peer_addr, src_port) = extract_addr_and_port(cid);
-        self.inner
-            .recv_buffer_available_bytes(peer_addr, src_port)
-            .map_err(as_driver_error)
+        self.proto.recv_avail(cid)
+    }
+
+    fn send_credit(&mut self, cid: VsockConnId) -> DriverResult<usize> {
+        self.proto.send_credit(cid)
+    }
+
+    fn send_dgram(
+        &mut self,
+        src_port: u32,
+        peer_addr: VsockAddr,
+        buf: &[u8],
+    ) -> DriverResult<usize> {
+        let len = self.proto.send_dgram(src_port, peer_addr, buf)?;
+        self.flush();
+        Ok(len)
+    }
+
+    fn recv_dgram(&mut self, cid: VsockConnId, buf: &mut [u8]) -> DriverResult<usize> {
+        self.proto.recv_dgram(cid, buf)
     }
 
     fn disconnect(&mut self, cid: VsockConnId) -> DriverResult<()> {
-        let (peer_addr, src_port) = extract_addr_and_port(cid);
-        self.inner
-            .shutdown(peer_addr, src_port)
-            .map_err(as_driver_error)
+        self.proto.disconnect(cid)?;
+        self.flush();
+        Ok(())
     }
 
     fn abort(&mut self, cid: VsockConnId) -> DriverResult<()> {
-        let (peer_addr, src_port) = extract_addr_and_port(cid);
-        self.inner
-            .force_close(peer_addr, src_port)
-            .map_err(as_driver_error)
+        self.proto.abort(cid)?;
+        self.flush();
+        Ok(())
     }
 
     fn poll_event(&mut self) -> DriverResult<Option<VsockDriverEventType>> {
-        match self.inner.poll() {
-            Ok(None) => {
-                // no event
-                Ok(None)
-            }
-            Ok(Some(event)) => {
-                // translate event
-                let result = translate_virtio_event(event, &mut self.inner)?;
-                Ok(Some(result))
-            }
-            Err(e) => {
-                // error
-                Err(as_driver_error(e))
-            }
+        if self.proto.events.is_empty() {
+            self.process()?;
         }
+        Ok(self.proto.events.pop_front())
     }
 }
 
-fn translate_virtio_event<H: Hal, T: Transport>(
-    event: VsockEvent,
-    _inner: &mut InnerDev<H, T>,
-) -> DriverResult<VsockDriverEventType> {
-    let cid = VsockConnId {
-        peer_addr: vsock::VsockAddr {
-            cid: event.source.cid as _,
-            port: event.source.port as _,
-        },
-        local_port: event.destination.port,
-    };
-
-    match event.event_type {
-        VsockEventType::ConnectionRequest => Ok(VsockDriverEventType::ConnectionRequest(cid)),
-        VsockEventType::Connected => Ok(VsockDriverEventType::Connected(cid)),
-        VsockEventType::Received { length } => Ok(VsockDriverEventType::Received(cid, length)),
-        VsockEventType::Disconnected { reason: _ } => Ok(VsockDriverEventType::Disconnected(cid)),
-        _ => Ok(VsockDriverEventType::Unknown),
+impl<H: Hal, T: Transport> Drop for VirtIoSocketDev<H, T> {
+    fn drop(&mut self) {
+        // The device must stop using the buffers before they are freed.
+        self.transport.queue_unset(RX_QUEUE);
+        self.transport.queue_unset(TX_QUEUE);
+        self.transport.queue_unset(EVENT_QUEUE);
+    }
+}
+
+#[cfg(unittest)]
+mod tests {
+    use unittest::{assert, assert_eq, def_test};
+    use virtio_drivers::transport::DeviceType;
+
+    use super::*;
+    use crate::mock_virtio::{MockHal, MockTransport};
+
+    const GUEST: u64 = 3;
+    const HOST: u64 = 2;
+
+    fn peer(port: u32) -> VsockConnId {
+        VsockConnId {
+            peer_addr: VsockAddr { cid: HOST, port },
+            local_port: 1024,
+        }
+    }
+
+    fn packet(cid: VsockConnId, ty: u16, op: u16, flags: u32, len: usize) -> Header {
+        Header {
+            src_cid: HOST,
+            dst_cid: GUEST,
+            src_port: cid.peer_addr.port,
+            dst_port: cid.local_port,
+            len: len as u32,
+            ty,
+            op,
+            flags,
+            buf_alloc: 8,
+            fwd_cnt: 0,
+        }
+    }
+
+    fn protocol() -> Protocol {
+        Protocol::new(GUEST, VsockFeatures::all())
+    }
+
+    fn sent_ops(proto: &mut Protocol) -> Vec<u16> {
+        proto
+            .outbox
+            .drain(..)
+            .map(|(header, _)| header.op)
+            .collect()
+    }
+
+    #[def_test]
+    fn test_virtio_vsock_init() {
+        let mut transport = MockTransport::new();
+        transport.device_type = DeviceType::Socket;
+        transport.features = (VsockFeatures::STREAM | VsockFeatures::SEQPACKET).bits();
+        transport.config_space.borrow_mut()[..8].copy_from_slice(&42u64.to_le_bytes());
+        let dev = VirtIoSocketDev::<MockHal, MockTransport>::try_new(transport).unwrap();
+        assert_eq!(dev.guest_cid(), 42);
+        assert!(dev.supports(VsockSocketType::SeqPacket));
+        assert!(!dev.supports(VsockSocketType::Dgram));
+    }
+
+    #[def_test]
+    fn test_virtio_vsock_header() {
+        let header = packet(peer(5), TYPE_SEQPACKET, OP_RW, SEQ_EOM, 3);
+        let mut buf = [0u8; HDR_LEN + 3];
+        header.encode(&mut buf);
+        buf[HDR_LEN..].copy_from_slice(b"abc");
+        assert_eq!(Header::decode(&buf), Some((header, &b"abc"[..])));
+        assert_eq!(Header::decode(&buf[..HDR_LEN + 2]), None);
+    }
+
+    #[def_test]
+    fn test_virtio_vsock_accept_and_credit() {
+        let mut proto = protocol();
+        let cid = peer(5);
+        proto
+            .listen(cid.local_port, VsockSocketType::Stream)
+            .unwrap();
+
+        // Requests of another type are refused
+        proto.handle(&packet(cid, TYPE_SEQPACKET, OP_REQUEST, 0, 0), &[]);
+        assert_eq!(sent_ops(&mut proto), [OP_RST]);
+
+        proto.handle(&packet(cid, TYPE_STREAM, OP_REQUEST, 0, 0), &[]);
+        assert_eq!(sent_ops(&mut proto), [OP_RESPONSE]);
+        assert!(matches!(
+            proto.events.pop_front(),
+            Some(VsockDriverEventType::ConnectionRequest(it)) if it == cid
+        ));
+
+        // The peer granted 8 bytes of credit
+        assert_eq!(proto.send(cid, b"0123456789").ok(), Some(8));
+        assert_eq!(sent_ops(&mut proto), [OP_RW]);
+        assert!(matches!(
+            proto.send(cid, b"89"),
+            Err(DriverError::WouldBlock)
+        ));
+        assert_eq!(sent_ops(&mut proto), [OP_CREDIT_REQUEST]);
+
+        proto.handle(&packet(cid, TYPE_STREAM, OP_RW, 0, 4), b"ping");
+        let mut buf = [0u8; 16];
+        assert_eq!(proto.recv(cid, &mut buf).ok(), Some((4, false)));
+        assert_eq!(&buf[..4], b"ping");
+        let (update, _) = proto.outbox.pop_front().unwrap();
+        assert_eq!((update.op, update.fwd_cnt), (OP_CREDIT_UPDATE, 4));
+
+        // The blocked sender is told once the peer consumed the data
+        proto.events.clear();
+        assert_eq!(proto.send_credit(cid).ok(), Some(0));
+        let update = Header {
+            fwd_cnt: 8,
+            ..packet(cid, TYPE_STREAM, OP_CREDIT_UPDATE, 0, 0)
+        };
+        proto.handle(&update, &[]);
+        assert_eq!(proto.send_credit(cid).ok(), Some(8));
+        assert!(matches!(
+            proto.events.pop_front(),
+            Some(VsockDriverEventType::CreditUpdate(it)) if it == cid
+        ));
+        proto.handle(&update, &[]);
+        assert!(proto.events.is_empty());
+    }
+
+    #[def_test]
+    fn test_virtio_vsock_seqpacket_messages() {
+        let mut proto = protocol();
+        let cid = peer(6);
+        proto.connect(cid, VsockSocketType::SeqPacket).unwrap();
+        assert_eq!(sent_ops(&mut proto), [OP_REQUEST]);
+        proto.handle(&packet(cid, TYPE_SEQPACKET, OP_RESPONSE, 0, 0), &[]);
+        assert!(matches!(
+            proto.events.pop_front(),
+            Some(VsockDriverEventType::Connected(_))
+        ));
+
+        proto.handle(&packet(cid, TYPE_SEQPACKET, OP_RW, 0, 3), b"abc");
+        proto.handle(&packet(cid, TYPE_SEQPACKET, OP_RW, SEQ_EOM, 2), b"de");
+        proto.handle(&packet(cid, TYPE_SEQPACKET, OP_RW, SEQ_EOM, 1), b"f");
+        let mut buf = [0u8; 4];
+        assert_eq!(proto.recv(cid, &mut buf).ok(), Some((4, false)));
+        assert_eq!(proto.recv(cid, &mut buf).ok(), Some((1, true)));
+        assert_eq!(proto.recv(cid, &mut buf).ok(), Some((1, true)));
+        assert_eq!(&buf[..1], b"f");
+
+        // Messages are not split by credit
+        proto.outbox.clear();
+        assert!(matches!(
+            proto.send(cid, &[0; 9]),
+            Err(DriverError::InvalidInput)
+        ));
+        assert_eq!(proto.send(cid, &[0; 8]).ok(), Some(8));
+        let (header, _) = proto.outbox.pop_front().unwrap();
+        assert_eq!((header.op, header.flags), (OP_RW, SEQ_EOM));
+    }
+
+    #[def_test]
+    fn test_virtio_vsock_close() {
+        let mut proto = protocol();
+        let cid = peer(7);
+        proto
+            .listen(cid.local_port, VsockSocketType::Stream)
+            .unwrap();
+        proto.handle(&packet(cid, TYPE_STREAM, OP_REQUEST, 0, 0), &[]);
+        proto.handle(&packet(cid, TYPE_STREAM, OP_RW, 0, 2), b"hi");
+        proto.outbox.clear();
+        proto.events.clear();
+
+        // Data sent before the shutdown can still be received
+        let both = SHUTDOWN_RCV | SHUTDOWN_SEND;
+        proto.handle(&packet(cid, TYPE_STREAM, OP_SHUTDOWN, both, 0), &[]);
+        assert_eq!(sent_ops(&mut proto), [OP_RST]);
+        assert!(matches!(
+            proto.events.pop_front(),
+            Some(VsockDriverEventType::Disconnected(_))
+        ));
+        let mut buf = [0u8; 4];
+        assert_eq!(proto.recv(cid, &mut buf).ok(), Some((2, false)));
+        assert!(proto.connections.is_empty());
+
+        // Packets for unknown connections are reset
+        proto.handle(&packet(cid, TYPE_STREAM, OP_RW, 0, 1), b"x");
+        assert_eq!(sent_ops(&mut proto), [OP_RST]);
+    }
+
+    #[def_test]
+    fn test_virtio_vsock_dgram() {
+        let mut proto = protocol();
+        let cid = peer(8);
+        proto.handle(&packet(cid, TYPE_DGRAM, OP_RW, 0, 5), b"hello");
+        assert!(matches!(
+            proto.events.pop_front(),
+            Some(VsockDriverEventType::DatagramReceived(it, 5)) if it == cid
+        ));
+        let mut buf = [0u8; 2];
+        assert_eq!(proto.recv_dgram(cid, &mut buf).ok(), Some(5));
+        assert_eq!(&buf, b"he");
+        assert!(matches!(
+            proto.recv_dgram(cid, &mut buf),
+            Err(DriverError::WouldBlock)
+        ));
+
+        assert_eq!(proto.send_dgram(9, cid.peer_addr, b"hey").ok(), Some(3));
+        let (header, payload) = proto.outbox.pop_front().unwrap();
+        assert_eq!(
+            (header.ty, header.src_port, header.dst_port),
+            (TYPE_DGRAM, 9, 8)
+        );
+        assert_eq!(payload, b"hey");
+
+        let mut proto = Protocol::new(GUEST, VsockFeatures::STREAM);
+        assert!(matches!(
+            proto.send_dgram(9, cid.peer_addr, b"hey"),
+            Err(DriverError::Unsupported)
+        ));
+        proto.handle(&packet(cid, TYPE_DGRAM, OP_RW, 0, 1), b"x");
+        assert!(proto.events.is_empty());
     }
 }
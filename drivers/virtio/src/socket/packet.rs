@@ -0,0 +1,84 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Packet header of the virtio-vsock protocol (VirtIO 1.2, section 5.10.6).
+
+/// Length of an encoded header.
+pub(super) const HDR_LEN: usize = 44;
+
+pub(super) const TYPE_STREAM: u16 = 1;
+pub(super) const TYPE_SEQPACKET: u16 = 2;
+/// Not in VirtIO 1.2 yet, numbered as in the datagram proposal.
+pub(super) const TYPE_DGRAM: u16 = 3;
+
+pub(super) const OP_REQUEST: u16 = 1;
+pub(super) const OP_RESPONSE: u16 = 2;
+pub(super) const OP_RST: u16 = 3;
+pub(super) const OP_SHUTDOWN: u16 = 4;
+pub(super) const OP_RW: u16 = 5;
+pub(super) const OP_CREDIT_UPDATE: u16 = 6;
+pub(super) const OP_CREDIT_REQUEST: u16 = 7;
+
+/// `OP_SHUTDOWN` flag: the sender will receive no more data.
+pub(super) const SHUTDOWN_RCV: u32 = 1;
+/// `OP_SHUTDOWN` flag: the sender will send no more data.
+pub(super) const SHUTDOWN_SEND: u32 = 2;
+/// `OP_RW` flag on seqpacket connections: last packet of a message.
+pub(super) const SEQ_EOM: u32 = 1;
+
+/// Header preceding every packet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct Header {
+    pub src_cid: u64,
+    pub dst_cid: u64,
+    pub src_port: u32,
+    pub dst_port: u32,
+    pub len: u32,
+    pub ty: u16,
+    pub op: u16,
+    pub flags: u32,
+    pub buf_alloc: u32,
+    pub fwd_cnt: u32,
+}
+
+impl Header {
+    /// Encodes the header into the start of `out`.
+    pub fn encode(&self, out: &mut [u8]) {
+        out[0..8].copy_from_slice(&self.src_cid.to_le_bytes());
+        out[8..16].copy_from_slice(&self.dst_cid.to_le_bytes());
+        out[16..20].copy_from_slice(&self.src_port.to_le_bytes());
+        out[20..24].copy_from_slice(&self.dst_port.to_le_bytes());
+        out[24..28].copy_from_slice(&self.len.to_le_bytes());
+        out[28..30].copy_from_slice(&self.ty.to_le_bytes());
+        out[30..32].copy_from_slice(&self.op.to_le_bytes());
+        out[32..36].copy_from_slice(&self.flags.to_le_bytes());
+        out[36..40].copy_from_slice(&self.buf_alloc.to_le_bytes());
+        out[40..44].copy_from_slice(&self.fwd_cnt.to_le_bytes());
+    }
+
+    /// Decodes a packet, returning its header and payload, or `None` if it
+    /// is truncated.
+    pub fn decode(buf: &[u8]) -> Option<(Self, &[u8])> {
+        if buf.len() < HDR_LEN {
+            return None;
+        }
+        let u16_at = |at: usize| u16::from_le_bytes([buf[at], buf[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(buf[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(buf[at..at + 8].try_into().unwrap());
+        let header = Self {
+            src_cid: u64_at(0),
+            dst_cid: u64_at(8),
+            src_port: u32_at(16),
+            dst_port: u32_at(20),
+            len: u32_at(24),
+            ty: u16_at(28),
+            op: u16_at(30),
+            flags: u32_at(32),
+            buf_alloc: u32_at(36),
+            fwd_cnt: u32_at(40),
+        };
+        let payload = buf[HDR_LEN..].get(..header.len as usize)?;
+        Some((header, payload))
+    }
+}
@@ -0,0 +1,188 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Split virtqueues with driver-owned packet buffers.
+//!
+//! Every descriptor always points at the same buffer slot, so packets are
+//! copied in and out and nothing has to outlive a request. Like the balloon
+//! queues, they use the legacy contiguous layout and are polled.
+use core::{
+    hint::spin_loop,
+    marker::PhantomData,
+    ptr::NonNull,
+    slice,
+    sync::atomic::{Ordering, fence},
+};
+
+use driver_base::{DriverError, DriverResult};
+use virtio_drivers::{BufferDirection, Hal, PhysAddr, transport::Transport};
+
+const PAGE_SIZE: usize = 0x1000;
+/// Descriptor flag: the device writes the buffer.
+const DESC_F_WRITE: u16 = 2;
+/// Available ring flag: do not interrupt on used buffers.
+const AVAIL_F_NO_INTERRUPT: u16 = 1;
+
+/// A virtqueue of `size` buffers of `buf_len` bytes each.
+pub(super) struct PacketQueue<H: Hal> {
+    idx: u16,
+    size: u16,
+    writable: bool,
+    buf_len: usize,
+    ring_paddr: PhysAddr,
+    ring_vaddr: NonNull<u8>,
+    ring_pages: usize,
+    buf_paddr: PhysAddr,
+    buf_vaddr: NonNull<u8>,
+    buf_pages: usize,
+    avail_idx: u16,
+    last_used_idx: u16,
+    _hal: PhantomData<H>,
+}
+
+impl<H: Hal> PacketQueue<H> {
+    /// Sets up queue `idx`. The device writes the buffers if `writable` is
+    /// set, and reads them otherwise.
+    pub fn new<T: Transport>(
+        transport: &mut T,
+        idx: u16,
+        size: u16,
+        buf_len: usize,
+        writable: bool,
+    ) -> DriverResult<Self> {
+        if transport.queue_used(idx) {
+            return Err(DriverError::AlreadyExists);
+        }
+        if transport.max_queue_size(idx) < size as u32 {
+            return Err(DriverError::Unsupported);
+        }
+        let avail_offset = 16 * size as usize;
+        let used_offset = (avail_offset + 2 * (3 + size as usize)).next_multiple_of(PAGE_SIZE);
+        let ring_pages = (used_offset + 2 * 3 + 8 * size as usize).div_ceil(PAGE_SIZE);
+        let (ring_paddr, ring_vaddr) = H::dma_alloc(ring_pages, BufferDirection::Both);
+        if ring_paddr == 0 {
+            return Err(DriverError::NoMemory);
+        }
+        let buf_pages = (size as usize * buf_len).div_ceil(PAGE_SIZE);
+        let direction = if writable {
+            BufferDirection::DeviceToDriver
+        } else {
+            BufferDirection::DriverToDevice
+        };
+        let (buf_paddr, buf_vaddr) = H::dma_alloc(buf_pages, direction);
+        if buf_paddr == 0 {
+            unsafe { H::dma_dealloc(ring_paddr, ring_vaddr, ring_pages) };
+            return Err(DriverError::NoMemory);
+        }
+
+        let queue = Self {
+            idx,
+            size,
+            writable,
+            buf_len,
+            ring_paddr,
+            ring_vaddr,
+            ring_pages,
+            buf_paddr,
+            buf_vaddr,
+            buf_pages,
+            avail_idx: 0,
+            last_used_idx: 0,
+            _hal: PhantomData,
+        };
+        unsafe { queue.avail().write_volatile(AVAIL_F_NO_INTERRUPT.to_le()) };
+        transport.queue_set(
+            idx,
+            size as u32,
+            ring_paddr,
+            ring_paddr + avail_offset as PhysAddr,
+            ring_paddr + used_offset as PhysAddr,
+        );
+        Ok(queue)
+    }
+
+    fn avail(&self) -> *mut u16 {
+        unsafe { self.ring_vaddr.as_ptr().add(16 * self.size as usize) as *mut u16 }
+    }
+
+    fn used(&self) -> *mut u16 {
+        let offset =
+            (16 * self.size as usize + 2 * (3 + self.size as usize)).next_multiple_of(PAGE_SIZE);
+        unsafe { self.ring_vaddr.as_ptr().add(offset) as *mut u16 }
+    }
+
+    /// Returns the buffer of descriptor `id`.
+    pub fn buffer(&mut self, id: u16) -> &mut [u8] {
+        let start = id as usize * self.buf_len;
+        unsafe { slice::from_raw_parts_mut(self.buf_vaddr.as_ptr().add(start), self.buf_len) }
+    }
+
+    /// Makes the first `len` bytes of the buffer of descriptor `id`
+    /// available to the device.
+    pub fn push(&mut self, id: u16, len: usize) {
+        let desc = unsafe { self.ring_vaddr.as_ptr().add(16 * id as usize) };
+        let addr = self.buf_paddr + (id as usize * self.buf_len) as PhysAddr;
+        let flags = if self.writable { DESC_F_WRITE } else { 0 };
+        unsafe {
+            (desc as *mut u64).write_volatile(addr.to_le());
+            (desc.add(8) as *mut u32).write_volatile((len as u32).to_le());
+            (desc.add(12) as *mut u16).write_volatile(flags.to_le());
+
+            let avail = self.avail();
+            let slot = (self.avail_idx % self.size) as usize;
+            avail.add(2 + slot).write_volatile(id.to_le());
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            avail.add(1).write_volatile(self.avail_idx.to_le());
+        }
+        fence(Ordering::SeqCst);
+    }
+
+    /// Makes every buffer available, for queues the device writes to.
+    pub fn fill(&mut self) {
+        for id in 0..self.size {
+            self.push(id, self.buf_len);
+        }
+    }
+
+    /// Takes the next buffer the device is done with, returning its
+    /// descriptor and the length written.
+    pub fn pop_used(&mut self) -> Option<(u16, usize)> {
+        let used = self.used();
+        let used_idx = u16::from_le(unsafe { used.add(1).read_volatile() });
+        if used_idx == self.last_used_idx {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let slot = (self.last_used_idx % self.size) as usize;
+        let elem = unsafe { (used.add(2) as *const u32).add(2 * slot) };
+        let (id, len) = unsafe {
+            (
+                u32::from_le(elem.read_volatile()),
+                u32::from_le(elem.add(1).read_volatile()),
+            )
+        };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+        Some((id as u16, (len as usize).min(self.buf_len)))
+    }
+
+    /// Sends the first `len` bytes of buffer 0 and waits until the device
+    /// has read them.
+    pub fn send<T: Transport>(&mut self, transport: &mut T, len: usize) {
+        self.push(0, len);
+        transport.notify(self.idx);
+        while self.pop_used().is_none() {
+            spin_loop();
+        }
+    }
+}
+
+impl<H: Hal> Drop for PacketQueue<H> {
+    fn drop(&mut self) {
+        unsafe {
+            H::dma_dealloc(self.buf_paddr, self.buf_vaddr, self.buf_pages);
+            H::dma_dealloc(self.ring_paddr, self.ring_vaddr, self.ring_pages);
+        }
+    }
+}
@@ -19,6 +19,18 @@ pub struct VsockAddr {
     pub port: u32,
 }
 
+/// Vsock socket type.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VsockSocketType {
+    /// Connection-oriented byte stream.
+    #[default]
+    Stream,
+    /// Connection-oriented, preserving message boundaries.
+    SeqPacket,
+    /// Connectionless messages with explicit peer addresses.
+    Dgram,
+}
+
 /// Vsock connection id.
 #[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct VsockConnId {
@@ -47,6 +59,11 @@ pub enum VsockDriverEventType {
     Connected(VsockConnId),
     /// Data was received on a connection.
     Received(VsockConnId, usize),
+    /// A datagram was received from `peer_addr` on `local_port`.
+    DatagramReceived(VsockConnId, usize),
+    /// The peer granted credit to a connection whose send would have
+    /// blocked.
+    CreditUpdate(VsockConnId),
     /// A connection was disconnected.
     Disconnected(VsockConnId),
     /// Unknown or unsupported event.
@@ -58,21 +75,64 @@ pub trait VsockDriverOps: DriverOps {
     /// Returns the guest CID.
     fn guest_cid(&self) -> u64;
 
-    /// Listen on a specific port.
-    fn listen(&mut self, src_port: u32);
+    /// Returns whether sockets of type `ty` are supported.
+    fn supports(&self, ty: VsockSocketType) -> bool {
+        ty == VsockSocketType::Stream
+    }
 
-    /// Connect to a peer socket.
-    fn connect(&mut self, cid: VsockConnId) -> DriverResult<()>;
+    /// Listen on a specific port for connections of type `ty`.
+    fn listen(&mut self, src_port: u32, ty: VsockSocketType) -> DriverResult<()>;
+
+    /// Stop listening on a port.
+    fn unlisten(&mut self, src_port: u32);
+
+    /// Connect to a peer socket with a connection of type `ty`.
+    fn connect(&mut self, cid: VsockConnId, ty: VsockSocketType) -> DriverResult<()>;
 
     /// Send data to the connected peer socket.
+    ///
+    /// On seqpacket connections `buf` is sent as one message, or not at all.
     fn send(&mut self, cid: VsockConnId, buf: &[u8]) -> DriverResult<usize>;
 
     /// Receive data from the connected peer socket.
+    ///
+    /// On seqpacket connections this stops at the end of a message.
     fn recv(&mut self, cid: VsockConnId, buf: &mut [u8]) -> DriverResult<usize>;
 
+    /// Receive data like [`recv`](Self::recv), also returning whether it
+    /// ended a message. Stream data never does.
+    fn recv_msg(&mut self, cid: VsockConnId, buf: &mut [u8]) -> DriverResult<(usize, bool)> {
+        self.recv(cid, buf).map(|len| (len, false))
+    }
+
+    /// Send a datagram from `src_port` to `peer_addr`.
+    fn send_dgram(
+        &mut self,
+        _src_port: u32,
+        _peer_addr: VsockAddr,
+        _buf: &[u8],
+    ) -> DriverResult<usize> {
+        Err(DriverError::Unsupported)
+    }
+
+    /// Receive the oldest datagram from `cid.peer_addr` to `cid.local_port`,
+    /// discarding what does not fit in `buf`. Returns the length of the
+    /// datagram.
+    fn recv_dgram(&mut self, _cid: VsockConnId, _buf: &mut [u8]) -> DriverResult<usize> {
+        Err(DriverError::Unsupported)
+    }
+
     /// Returns the number of bytes in the receive buffer available to be read by recv.
     fn recv_avail(&mut self, cid: VsockConnId) -> DriverResult<usize>;
 
+    /// Returns the number of bytes the peer can take right now.
+    ///
+    /// Drivers that do not track the peer's credit return `usize::MAX` and
+    /// let [`send`](Self::send) fail with [`DriverError::WouldBlock`].
+    fn send_credit(&mut self, _cid: VsockConnId) -> DriverResult<usize> {
+        Ok(usize::MAX)
+    }
+
     /// Disconnect from the connected peer socket.
     fn disconnect(&mut self, cid: VsockConnId) -> DriverResult<()>;
 
@@ -11,6 +11,7 @@ use alloc::{
 use kapi::{file::FD_TABLE, task::new_user_task, vfs::dev::tty::N_TTY};
 use kcore::{
     mm::{copy_from_kernel, load_user_app, new_user_aspace_empty},
+    resources::Rlimits,
     task::{ProcessData, Thread, add_task_to_table},
 };
 use kfs::FS_CONTEXT;
@@ -38,13 +39,33 @@ pub fn run_initproc(args: &[String], envs: &[String]) -> i32 {
         .expect("Failed to get executable absolute path");
     let name = loc.name();
 
-    let (entry_vaddr, ustack_top) = load_user_app(&mut uspace, None, args, envs)
-        .unwrap_or_else(|e| panic!("Failed to load user app: {}", e));
+    let (entry_vaddr, ustack_top, compat) = load_user_app(
+        &mut uspace,
+        None,
+        args,
+        envs,
+        Rlimits::default().stack_size(),
+    )
+    .unwrap_or_else(|e| panic!("Failed to load user app: {}", e));
 
+    #[cfg(all(
+        feature = "compat",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    let uctx = if compat {
+        UserContext::new_compat(entry_vaddr.into(), ustack_top, 0)
+    } else {
+        UserContext::new(entry_vaddr.into(), ustack_top, 0)
+    };
+    #[cfg(not(all(
+        feature = "compat",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    )))]
     let uctx = UserContext::new(entry_vaddr.into(), ustack_top, 0);
 
     let mut task = new_user_task(name, uctx, 0);
     task.ctx_mut().set_page_table_root(uspace.page_table_root());
+    task.ctx_mut().set_asid(uspace.asid());
 
     let pid = task.id().as_u64() as Pid;
     let proc = Process::new_init(pid);
@@ -60,6 +81,7 @@ pub fn run_initproc(args: &[String], envs: &[String]) -> i32 {
         Arc::default(),
         None,
     );
+    proc_data.set_compat(compat);
     {
         let mut scope = proc_data.scope.write();
         kapi::file::add_stdio(&mut FD_TABLE.scope_mut(&mut scope).write())
@@ -0,0 +1,385 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Write-back block buffer cache shared by filesystems.
+//!
+//! Each mounted filesystem keeps the blocks of its device in a
+//! [`BufferCache`], indexed by block number in a [`RadixTree`]. Written
+//! buffers stay dirty until the owner writes them back, in block order and
+//! coalesced into runs, with [`BufferCache::writeback`].
+//!
+//! All caches charge one global budget (see [`set_capacity`]). Once it is
+//! exceeded, a cache makes room by evicting its own least recently used
+//! buffers, down to its [fair share](BufferCache::fair_share) of the
+//! budget, so one busy filesystem cannot starve the others.
+#![no_std]
+
+extern crate alloc;
+
+mod radix;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+pub use radix::{Iter, RadixTree};
+
+/// Budget of all buffer caches together, unless [changed](set_capacity).
+pub const DEFAULT_CAPACITY: usize = 8 * 1024 * 1024;
+
+static CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_CAPACITY);
+static USED: AtomicUsize = AtomicUsize::new(0);
+static CACHES: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets how many bytes all buffer caches may hold together.
+///
+/// Caches over the new budget shrink as they next load a block, or when
+/// their owner calls [`BufferCache::shrink`].
+pub fn set_capacity(bytes: usize) {
+    CAPACITY.store(bytes, Ordering::Relaxed);
+}
+
+/// Returns `true` if the buffer caches hold more than their budget.
+pub fn over_capacity() -> bool {
+    USED.load(Ordering::Relaxed) > CAPACITY.load(Ordering::Relaxed)
+}
+
+/// Memory held by all buffer caches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheUsage {
+    /// Bytes of cached blocks.
+    pub used: usize,
+    /// The budget of [`used`](Self::used).
+    pub capacity: usize,
+    /// Number of live caches sharing the budget.
+    pub caches: usize,
+}
+
+/// Returns the memory held by all buffer caches.
+pub fn usage() -> CacheUsage {
+    CacheUsage {
+        used: USED.load(Ordering::Relaxed),
+        capacity: CAPACITY.load(Ordering::Relaxed),
+        caches: CACHES.load(Ordering::Relaxed),
+    }
+}
+
+/// A cached block.
+#[derive(Debug, Clone)]
+pub struct Buffer {
+    /// The contents of the block.
+    pub data: Vec<u8>,
+    /// Whether `data` differs from the device.
+    pub dirty: bool,
+    /// The block number on the device.
+    pub block_num: u64,
+    /// When the buffer was last used, in ticks of its cache.
+    pub last_access: u64,
+}
+
+impl Buffer {
+    /// Creates a clean buffer holding `data`.
+    pub fn new(data: Vec<u8>, block_num: u64) -> Self {
+        Self {
+            data,
+            dirty: false,
+            block_num,
+            last_access: 0,
+        }
+    }
+
+    /// Marks the buffer to be written back.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+}
+
+/// The cached blocks of one device.
+pub struct BufferCache {
+    buffers: RadixTree<Buffer>,
+    block_size: usize,
+    clock: u64,
+}
+
+impl BufferCache {
+    /// Creates an empty cache of `block_size`-byte blocks.
+    pub fn new(block_size: usize) -> Self {
+        CACHES.fetch_add(1, Ordering::Relaxed);
+        Self {
+            buffers: RadixTree::new(),
+            block_size,
+            clock: 0,
+        }
+    }
+
+    /// Returns the size of the cached blocks.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Returns the number of cached blocks.
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Returns `true` if no block is cached.
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    /// Returns the number of dirty blocks.
+    pub fn dirty_len(&self) -> usize {
+        self.buffers.iter().filter(|(_, buf)| buf.dirty).count()
+    }
+
+    /// Returns `true` if `block_num` is cached.
+    pub fn contains(&self, block_num: u64) -> bool {
+        self.buffers.contains_key(block_num)
+    }
+
+    /// Returns the cached `block_num`, without counting it as used.
+    pub fn get(&self, block_num: u64) -> Option<&Buffer> {
+        self.buffers.get(block_num)
+    }
+
+    /// Returns the cached `block_num` mutably and counts it as used.
+    pub fn get_mut(&mut self, block_num: u64) -> Option<&mut Buffer> {
+        let buf = self.buffers.get_mut(block_num)?;
+        self.clock += 1;
+        buf.last_access = self.clock;
+        Some(buf)
+    }
+
+    /// Caches `data` as `block_num`, replacing any cached copy.
+    pub fn insert(&mut self, block_num: u64, data: Vec<u8>) -> &mut Buffer {
+        if self
+            .buffers
+            .insert(block_num, Buffer::new(data, block_num))
+            .is_none()
+        {
+            USED.fetch_add(self.block_size, Ordering::Relaxed);
+        }
+        self.get_mut(block_num).unwrap()
+    }
+
+    /// Marks the cached `block_num` to be written back.
+    pub fn mark_dirty(&mut self, block_num: u64) {
+        if let Some(buf) = self.buffers.get_mut(block_num) {
+            buf.mark_dirty();
+        }
+    }
+
+    /// Drops `block_num` from the cache, dirty or not.
+    pub fn remove(&mut self, block_num: u64) -> Option<Buffer> {
+        let buf = self.buffers.remove(block_num)?;
+        USED.fetch_sub(self.block_size, Ordering::Relaxed);
+        Some(buf)
+    }
+
+    /// Drops every block, dirty or not.
+    pub fn clear(&mut self) {
+        USED.fetch_sub(self.len() * self.block_size, Ordering::Relaxed);
+        self.buffers.clear();
+    }
+
+    /// Iterates over the cached blocks in block order.
+    pub fn iter(&self) -> impl Iterator<Item = &Buffer> {
+        self.buffers.iter().map(|(_, buf)| buf)
+    }
+
+    /// Returns the least recently used block, if any.
+    pub fn lru(&self) -> Option<u64> {
+        self.buffers
+            .iter()
+            .min_by_key(|(_, buf)| buf.last_access)
+            .map(|(block_num, _)| block_num)
+    }
+
+    /// Returns how many blocks this cache may keep while the budget is
+    /// exceeded: an equal part of it for every cache.
+    pub fn fair_share(&self) -> usize {
+        let usage = usage();
+        usage.capacity / usage.caches.max(1) / self.block_size.max(1)
+    }
+
+    /// Returns `true` if a block should be evicted before another is loaded,
+    /// because this cache holds `max_blocks`, or more than its fair share of
+    /// an exceeded budget.
+    pub fn needs_eviction(&self, max_blocks: usize) -> bool {
+        self.len() >= max_blocks || (over_capacity() && self.len() >= self.fair_share())
+    }
+
+    /// Drops clean blocks, least recently used first, until at most
+    /// `target` are cached. Returns how many were dropped.
+    ///
+    /// Dirty blocks are kept; write them back first to drop them too.
+    pub fn shrink(&mut self, target: usize) -> usize {
+        let excess = self.len().saturating_sub(target);
+        if excess == 0 {
+            return 0;
+        }
+        let mut clean: Vec<(u64, u64)> = self
+            .buffers
+            .iter()
+            .filter(|(_, buf)| !buf.dirty)
+            .map(|(block_num, buf)| (buf.last_access, block_num))
+            .collect();
+        clean.sort_unstable();
+        clean.truncate(excess);
+        for &(_, block_num) in &clean {
+            self.remove(block_num);
+        }
+        clean.len()
+    }
+
+    /// Drops up to `max` clean blocks, for memory running out. Returns how
+    /// many were dropped.
+    ///
+    /// Unlike [`shrink`](Self::shrink), it does not allocate, and ignores how
+    /// recently the blocks were used.
+    pub fn reclaim(&mut self, max: usize) -> usize {
+        const BATCH: usize = 32;
+        let mut dropped = 0;
+        while dropped < max {
+            let mut batch = [0; BATCH];
+            let mut len = 0;
+            let clean = self.buffers.iter().filter(|(_, buf)| !buf.dirty);
+            for (block_num, _) in clean.take(BATCH.min(max - dropped)) {
+                batch[len] = block_num;
+                len += 1;
+            }
+            if len == 0 {
+                break;
+            }
+            for &block_num in &batch[..len] {
+                self.remove(block_num);
+            }
+            dropped += len;
+        }
+        dropped
+    }
+
+    /// Writes back every dirty block with `write(start, data)`, merging
+    /// consecutive blocks into runs of at most `max_run`. Returns how many
+    /// blocks were written.
+    ///
+    /// On error, the blocks of the failed run and after stay dirty.
+    pub fn writeback<E>(
+        &mut self,
+        max_run: usize,
+        mut write: impl FnMut(u64, &[u8]) -> Result<(), E>,
+    ) -> Result<usize, E> {
+        let dirty: Vec<u64> = self
+            .buffers
+            .iter()
+            .filter(|(_, buf)| buf.dirty)
+            .map(|(block_num, _)| block_num)
+            .collect();
+
+        let mut written = 0;
+        let mut run = Vec::new();
+        let mut idx = 0;
+        while idx < dirty.len() {
+            let start = dirty[idx];
+            let mut len = 1;
+            while idx + len < dirty.len() && len < max_run && dirty[idx + len] == start + len as u64
+            {
+                len += 1;
+            }
+
+            run.clear();
+            for &block_num in &dirty[idx..idx + len] {
+                run.extend_from_slice(&self.buffers.get(block_num).unwrap().data);
+            }
+            write(start, &run)?;
+            for &block_num in &dirty[idx..idx + len] {
+                self.buffers.get_mut(block_num).unwrap().dirty = false;
+            }
+
+            written += len;
+            idx += len;
+        }
+        Ok(written)
+    }
+}
+
+impl Drop for BufferCache {
+    fn drop(&mut self) {
+        self.clear();
+        CACHES.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use super::*;
+
+    #[test]
+    fn test_writeback_merges_runs_in_block_order() {
+        let mut cache = BufferCache::new(4);
+        for block_num in [7, 3, 4, 5, 9] {
+            cache
+                .insert(block_num, vec![block_num as u8; 4])
+                .mark_dirty();
+        }
+        cache.insert(6, vec![0; 4]);
+
+        let mut writes = Vec::new();
+        let written = cache
+            .writeback(2, |start, data| {
+                writes.push((start, data.len() / 4));
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        assert_eq!(written, 5);
+        assert_eq!(writes, [(3, 2), (5, 1), (7, 1), (9, 1)]);
+        assert_eq!(cache.dirty_len(), 0);
+        assert_eq!(cache.writeback(2, |_, _| Err(())), Ok(0));
+    }
+
+    #[test]
+    fn test_failed_writeback_keeps_blocks_dirty() {
+        let mut cache = BufferCache::new(4);
+        cache.insert(1, vec![0; 4]).mark_dirty();
+        cache.insert(5, vec![0; 4]).mark_dirty();
+        let result = cache.writeback(8, |start, _| if start == 5 { Err(()) } else { Ok(()) });
+        assert_eq!(result, Err(()));
+        assert!(!cache.get(1).unwrap().dirty);
+        assert!(cache.get(5).unwrap().dirty);
+    }
+
+    #[test]
+    fn test_shrink_drops_clean_lru_blocks() {
+        let mut cache = BufferCache::new(4);
+        for block_num in 0..4 {
+            cache.insert(block_num, vec![0; 4]);
+        }
+        cache.mark_dirty(0);
+        cache.get_mut(1);
+        assert_eq!(cache.lru(), Some(0));
+
+        // Block 0 is dirty, block 1 recently used: 2 and 3 go first.
+        assert_eq!(cache.shrink(2), 2);
+        assert!(cache.contains(0) && cache.contains(1));
+        assert_eq!(cache.shrink(0), 1);
+        assert!(cache.contains(0));
+        cache.remove(0);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_reclaim_keeps_dirty_blocks() {
+        let mut cache = BufferCache::new(4);
+        for block_num in 0..100 {
+            cache.insert(block_num, vec![0; 4]);
+        }
+        cache.mark_dirty(42);
+        assert_eq!(cache.reclaim(40), 40);
+        assert_eq!(cache.len(), 60);
+        assert_eq!(cache.reclaim(usize::MAX), 59);
+        assert!(cache.contains(42));
+        assert_eq!(cache.reclaim(1), 0);
+    }
+}
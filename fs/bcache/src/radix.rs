@@ -0,0 +1,327 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! A sparse radix tree keyed by block number.
+
+use alloc::{boxed::Box, vec::Vec};
+
+const BITS: u32 = 6;
+const FANOUT: usize = 1 << BITS;
+const MASK: u64 = FANOUT as u64 - 1;
+/// Enough levels to cover every `u64` key.
+const MAX_HEIGHT: u32 = u64::BITS.div_ceil(BITS);
+
+enum Node<T> {
+    Inner {
+        children: Box<[Option<Node<T>>; FANOUT]>,
+        count: usize,
+    },
+    Leaf {
+        items: Box<[Option<T>; FANOUT]>,
+        count: usize,
+    },
+}
+
+impl<T> Node<T> {
+    fn new(level: u32) -> Self {
+        if level == 0 {
+            Node::Leaf {
+                items: Box::new(core::array::from_fn(|_| None)),
+                count: 0,
+            }
+        } else {
+            Node::Inner {
+                children: Box::new(core::array::from_fn(|_| None)),
+                count: 0,
+            }
+        }
+    }
+
+    fn count(&self) -> usize {
+        match self {
+            Node::Inner { count, .. } | Node::Leaf { count, .. } => *count,
+        }
+    }
+}
+
+fn index(key: u64, level: u32) -> usize {
+    ((key >> (BITS * level)) & MASK) as usize
+}
+
+/// Largest key a tree of `height` levels holds.
+fn max_key(height: u32) -> u64 {
+    if height >= MAX_HEIGHT {
+        u64::MAX
+    } else {
+        (1 << (BITS * height)) - 1
+    }
+}
+
+/// A map from `u64` keys to values, iterated in key order.
+///
+/// Nodes have 64 slots; the tree only grows as tall as its largest key needs,
+/// so the blocks of a small device are found in two or three steps.
+pub struct RadixTree<T> {
+    root: Option<Node<T>>,
+    height: u32,
+    len: usize,
+}
+
+impl<T> RadixTree<T> {
+    /// Creates an empty tree.
+    pub const fn new() -> Self {
+        Self {
+            root: None,
+            height: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of values in the tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree holds no value.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the value of `key`, if any.
+    pub fn get(&self, key: u64) -> Option<&T> {
+        if key > max_key(self.height) {
+            return None;
+        }
+        let mut node = self.root.as_ref()?;
+        let mut level = self.height - 1;
+        loop {
+            match node {
+                Node::Inner { children, .. } => {
+                    node = children[index(key, level)].as_ref()?;
+                    level -= 1;
+                }
+                Node::Leaf { items, .. } => return items[index(key, 0)].as_ref(),
+            }
+        }
+    }
+
+    /// Returns the value of `key` mutably, if any.
+    pub fn get_mut(&mut self, key: u64) -> Option<&mut T> {
+        if key > max_key(self.height) {
+            return None;
+        }
+        let mut node = self.root.as_mut()?;
+        let mut level = self.height - 1;
+        loop {
+            match node {
+                Node::Inner { children, .. } => {
+                    node = children[index(key, level)].as_mut()?;
+                    level -= 1;
+                }
+                Node::Leaf { items, .. } => return items[index(key, 0)].as_mut(),
+            }
+        }
+    }
+
+    /// Returns `true` if the tree has a value for `key`.
+    pub fn contains_key(&self, key: u64) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts `value` at `key`, returning the value it replaces.
+    pub fn insert(&mut self, key: u64, value: T) -> Option<T> {
+        if self.root.is_none() {
+            self.height = 1;
+        }
+        while key > max_key(self.height) {
+            // Grow at the top: the old root becomes the first child.
+            let mut root = Node::new(self.height);
+            if let (Some(old), Node::Inner { children, count }) = (self.root.take(), &mut root) {
+                children[0] = Some(old);
+                *count = 1;
+            }
+            self.root = Some(root);
+            self.height += 1;
+        }
+
+        let mut level = self.height - 1;
+        let mut node = self.root.get_or_insert_with(|| Node::new(level));
+        let old = loop {
+            match node {
+                Node::Inner { children, count } => {
+                    let slot = &mut children[index(key, level)];
+                    if slot.is_none() {
+                        *count += 1;
+                    }
+                    level -= 1;
+                    node = slot.get_or_insert_with(|| Node::new(level));
+                }
+                Node::Leaf { items, count } => {
+                    let old = items[index(key, 0)].replace(value);
+                    if old.is_none() {
+                        *count += 1;
+                    }
+                    break old;
+                }
+            }
+        };
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// Removes the value of `key`, freeing nodes left empty.
+    pub fn remove(&mut self, key: u64) -> Option<T> {
+        fn remove<T>(node: &mut Node<T>, key: u64, level: u32) -> Option<T> {
+            match node {
+                Node::Inner { children, count } => {
+                    let slot = &mut children[index(key, level)];
+                    let child = slot.as_mut()?;
+                    let value = remove(child, key, level - 1);
+                    if child.count() == 0 {
+                        *slot = None;
+                        *count -= 1;
+                    }
+                    value
+                }
+                Node::Leaf { items, count } => {
+                    let value = items[index(key, 0)].take();
+                    if value.is_some() {
+                        *count -= 1;
+                    }
+                    value
+                }
+            }
+        }
+
+        if key > max_key(self.height) {
+            return None;
+        }
+        let root = self.root.as_mut()?;
+        let value = remove(root, key, self.height - 1)?;
+        let empty = root.count() == 0;
+        self.len -= 1;
+        if empty {
+            self.clear();
+        }
+        Some(value)
+    }
+
+    /// Removes every value.
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.height = 0;
+        self.len = 0;
+    }
+
+    /// Iterates over the values in ascending key order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut stack = Vec::new();
+        if let Some(root) = &self.root {
+            stack.push(Frame {
+                node: root,
+                base: 0,
+                level: self.height - 1,
+                next: 0,
+            });
+        }
+        Iter { stack }
+    }
+}
+
+impl<T> Default for RadixTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Frame<'a, T> {
+    node: &'a Node<T>,
+    base: u64,
+    level: u32,
+    next: usize,
+}
+
+/// Iterator returned by [`RadixTree::iter`].
+pub struct Iter<'a, T> {
+    stack: Vec<Frame<'a, T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (u64, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let frame = self.stack.last_mut()?;
+            if frame.next == FANOUT {
+                self.stack.pop();
+                continue;
+            }
+            let i = frame.next;
+            frame.next += 1;
+            match frame.node {
+                Node::Leaf { items, .. } => {
+                    if let Some(value) = &items[i] {
+                        return Some((frame.base | i as u64, value));
+                    }
+                }
+                Node::Inner { children, .. } => {
+                    if let Some(child) = &children[i] {
+                        let frame = Frame {
+                            node: child,
+                            base: frame.base | ((i as u64) << (BITS * frame.level)),
+                            level: frame.level - 1,
+                            next: 0,
+                        };
+                        self.stack.push(frame);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    #[test]
+    fn test_radix_insert_get_remove() {
+        let mut tree = RadixTree::new();
+        assert!(tree.get(0).is_none());
+        assert_eq!(tree.insert(5, 'a'), None);
+        assert_eq!(tree.insert(5, 'b'), Some('a'));
+        assert_eq!(tree.insert(1 << 20, 'c'), None);
+        assert_eq!(tree.insert(u64::MAX, 'd'), None);
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.get(5), Some(&'b'));
+        assert_eq!(tree.get(1 << 20), Some(&'c'));
+        assert_eq!(tree.get(u64::MAX), Some(&'d'));
+        assert!(tree.get(6).is_none());
+
+        assert_eq!(tree.remove(1 << 20), Some('c'));
+        assert_eq!(tree.remove(1 << 20), None);
+        assert_eq!(tree.remove(5), Some('b'));
+        assert_eq!(tree.remove(u64::MAX), Some('d'));
+        assert!(tree.is_empty());
+        assert!(tree.root.is_none());
+    }
+
+    #[test]
+    fn test_radix_iter_in_key_order() {
+        let mut tree = RadixTree::new();
+        let keys = [4097u64, 3, 64, 63, 1 << 40, 0];
+        for key in keys {
+            tree.insert(key, key * 2);
+        }
+        let mut sorted = keys;
+        sorted.sort();
+        let iterated: Vec<_> = tree.iter().map(|(key, value)| (key, *value)).collect();
+        let expected: Vec<_> = sorted.iter().map(|&key| (key, key * 2)).collect();
+        assert_eq!(iterated, expected);
+    }
+}
@@ -10,6 +10,10 @@ use inherit_methods_macro::inherit_methods;
 use crate::{DirEntry, VfsResult};
 
 /// Filesystem statistics returned by [`FilesystemOps::stat`].
+///
+/// Counts a filesystem doesn't limit, e.g. the files of one without an inode
+/// table, are zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct StatFs {
     /// Filesystem type identifier.
     pub fs_type: u32,
@@ -19,7 +23,8 @@ pub struct StatFs {
     pub blocks: u64,
     /// Free blocks in the filesystem.
     pub blocks_free: u64,
-    /// Blocks available to unprivileged users.
+    /// Blocks available to unprivileged users: the free ones, less those
+    /// reserved for the superuser.
     pub blocks_available: u64,
 
     /// Total file count.
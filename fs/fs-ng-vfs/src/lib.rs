@@ -4,6 +4,7 @@
 
 //! Next-generation VFS interfaces and data structures.
 #![no_std]
+#![feature(allocator_api)]
 #![allow(rustdoc::broken_intra_doc_links)]
 
 extern crate alloc;
@@ -12,14 +13,18 @@ mod fs;
 mod mount;
 mod node;
 pub mod path;
+mod stats;
 mod types;
+pub mod watch;
 
 mod test_path;
+mod test_stats;
 mod test_types;
 
 pub use fs::*;
 pub use mount::*;
 pub use node::*;
+pub use stats::*;
 pub use types::*;
 
 pub type VfsError = kerrno::KError;
@@ -7,6 +7,7 @@ use alloc::{
     string::String,
     sync::{Arc, Weak},
     vec,
+    vec::Vec,
 };
 use core::{
     iter, mem,
@@ -19,9 +20,11 @@ use inherit_methods_macro::inherit_methods;
 use kpoll::{IoEvents, Pollable};
 
 use crate::{
-    DirEntry, DirEntrySink, Filesystem, FilesystemOps, Metadata, MetadataUpdate, Mutex, MutexGuard,
-    NodeFlags, NodePermission, NodeType, OpenOptions, ReferenceKey, TypeMap, VfsError, VfsResult,
+    DirEntry, DirEntrySink, Filesystem, FilesystemOps, Metadata, MetadataUpdate, MountStats, Mutex,
+    MutexGuard, NodeFlags, NodePermission, NodeType, OpenOptions, ReferenceKey, TypeMap, VfsError,
+    VfsResult,
     path::{DOT, DOTDOT, PathBuf},
+    watch,
 };
 
 /// A mounted filesystem instance and its relationships.
@@ -35,12 +38,30 @@ pub struct Mountpoint {
     /// Maps from directory entry keys to weak references to child mountpoints.
     child_mounts: Mutex<HashMap<ReferenceKey, Weak<Self>>>,
     /// Device ID
+    ///
+    /// Allocated in mount order.
     device: u64,
+    /// Where the filesystem comes from, e.g. `/dev/vda2`.
+    source: String,
+    /// Performance counters for this mount.
+    stats: MountStats,
 }
 
 impl Mountpoint {
     /// Create a new mountpoint for a filesystem at an optional parent location.
+    ///
+    /// The source of the mount is the name of the filesystem.
     pub fn new(fs: &Filesystem, location_in_parent: Option<Location>) -> Arc<Self> {
+        Self::new_from(fs, location_in_parent, fs.name())
+    }
+
+    /// Create a new mountpoint for a filesystem from `source`, e.g. a block
+    /// device, at an optional parent location.
+    pub fn new_from(
+        fs: &Filesystem,
+        location_in_parent: Option<Location>,
+        source: &str,
+    ) -> Arc<Self> {
         static DEVICE_COUNTER: AtomicU64 = AtomicU64::new(1);
 
         let root = fs.root_dir();
@@ -49,6 +70,8 @@ impl Mountpoint {
             location: location_in_parent,
             child_mounts: Mutex::default(),
             device: DEVICE_COUNTER.fetch_add(1, Ordering::Relaxed),
+            source: source.into(),
+            stats: MountStats::default(),
         })
     }
 
@@ -57,6 +80,11 @@ impl Mountpoint {
         Self::new(fs, None)
     }
 
+    /// Create a root mountpoint for a filesystem from `source`.
+    pub fn new_root_from(fs: &Filesystem, source: &str) -> Arc<Self> {
+        Self::new_from(fs, None, source)
+    }
+
     /// Return a `Location` representing the mountpoint root.
     pub fn root_location(self: &Arc<Self>) -> Location {
         Location::new(self.clone(), self.root.clone())
@@ -102,6 +130,49 @@ impl Mountpoint {
     pub fn device(self: &Arc<Self>) -> u64 {
         self.device
     }
+
+    /// Returns where the filesystem comes from, e.g. `/dev/vda2`.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns the type of the filesystem mounted, e.g. `ext4`.
+    pub fn fs_type(&self) -> &str {
+        self.root.filesystem().name()
+    }
+
+    /// Returns the absolute path the filesystem is mounted at.
+    pub fn mount_path(self: &Arc<Self>) -> VfsResult<PathBuf> {
+        self.root_location().absolute_path()
+    }
+
+    /// Returns this mountpoint and all those nested under it, in the order
+    /// they were mounted.
+    pub fn mount_table(self: &Arc<Self>) -> Vec<Arc<Mountpoint>> {
+        let mut table = vec![self.clone()];
+        let mut next = 0;
+        while next < table.len() {
+            let children = table[next].child_mounts();
+            table.extend(children);
+            next += 1;
+        }
+        table.sort_by_key(|it| it.device);
+        table
+    }
+
+    /// Returns the performance counters of this mountpoint.
+    pub fn stats(&self) -> &MountStats {
+        &self.stats
+    }
+
+    /// Returns the live mountpoints nested directly under this one.
+    pub fn child_mounts(&self) -> Vec<Arc<Mountpoint>> {
+        self.child_mounts
+            .lock()
+            .values()
+            .filter_map(Weak::upgrade)
+            .collect()
+    }
 }
 
 /// A resolved location within a mountpoint.
@@ -242,7 +313,14 @@ impl Location {
             DOT => self.clone(),
             DOTDOT => self.parent().unwrap_or_else(|| self.clone()),
             _ => {
-                let loc = Self::new(self.mountpoint.clone(), self.entry.as_dir()?.lookup(name)?);
+                let dir = self.entry.as_dir()?;
+                let cached = dir.lookup_cache(name);
+                self.mountpoint.stats.record_lookup(cached.is_some());
+                let entry = match cached {
+                    Some(entry) => entry,
+                    None => dir.lookup(name)?,
+                };
+                let loc = Self::new(self.mountpoint.clone(), entry);
                 loc.resolve_final_mount()
             }
         })
@@ -280,9 +358,17 @@ impl Location {
         if !self.ptr_eq(dst_dir) && self.entry.is_ancestor_of(&dst_dir.entry)? {
             return Err(VfsError::InvalidInput);
         }
-        self.entry
-            .as_dir()?
-            .rename(src_name, dst_dir.entry.as_dir()?, dst_name)
+        let src_dir = self.entry.as_dir()?;
+        let moved = if watch::watching() {
+            src_dir.lookup(src_name).ok()
+        } else {
+            None
+        };
+        src_dir.rename(src_name, dst_dir.entry.as_dir()?, dst_name)?;
+        if let Some(moved) = moved {
+            watch::notify_move(&moved, &self.entry, src_name, &dst_dir.entry, dst_name);
+        }
+        Ok(())
     }
 
     /// Remove a file or directory entry.
@@ -304,12 +390,20 @@ impl Location {
     }
 
     /// Mount a filesystem at this location.
+    ///
+    /// The source of the mount is the name of the filesystem.
     pub fn mount(&self, fs: &Filesystem) -> VfsResult<Arc<Mountpoint>> {
+        self.mount_from(fs, fs.name())
+    }
+
+    /// Mount a filesystem from `source`, e.g. a block device, at this
+    /// location.
+    pub fn mount_from(&self, fs: &Filesystem, source: &str) -> VfsResult<Arc<Mountpoint>> {
         let mut mountpoint = self.entry.as_dir()?.mount_at_this_dir.lock();
         if mountpoint.is_some() {
             return Err(VfsError::ResourceBusy);
         }
-        let result = Mountpoint::new(fs, Some(self.clone()));
+        let result = Mountpoint::new_from(fs, Some(self.clone()), source);
         *mountpoint = Some(result.clone());
         self.mountpoint
             .child_mounts
@@ -330,6 +424,11 @@ impl Location {
         self.entry.as_dir()?.forget();
         if let Some(parent_loc) = &self.mountpoint.location {
             *parent_loc.entry.as_dir()?.mount_at_this_dir.lock() = None;
+            parent_loc
+                .mountpoint
+                .child_mounts
+                .lock()
+                .remove(&parent_loc.entry.key());
         }
         Ok(())
     }
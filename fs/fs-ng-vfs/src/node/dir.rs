@@ -16,6 +16,7 @@ use crate::{
     MetadataUpdate, Mountpoint, Mutex, MutexGuard, NodeOps, NodePermission, NodeType, VfsError,
     VfsResult,
     path::{DOT, DOTDOT, MAX_NAME_LEN, verify_entry_name},
+    watch,
 };
 
 /// A trait for a sink that can receive directory entries.
@@ -251,6 +252,7 @@ impl DirNode {
             self.dentry_cache
                 .lock()
                 .insert(name.to_owned(), entry.clone());
+            watch::notify_create(entry);
         })
     }
 
@@ -266,9 +268,11 @@ impl DirNode {
             _ => {}
         }
 
-        self.ops.unlink(name).inspect(|_| {
-            Self::forget_entry(&mut children, name);
-        })
+        self.ops.unlink(name)?;
+        Self::forget_entry(&mut children, name);
+        drop(children);
+        watch::notify_delete(&entry);
+        Ok(())
     }
 
     /// Returns whether the directory contains children.
@@ -294,6 +298,7 @@ impl DirNode {
     ) -> VfsResult<DirEntry> {
         let entry = self.ops.create(name, node_type, permission)?;
         children.insert(name.to_owned(), entry.clone());
+        watch::notify_create(&entry);
         Ok(entry)
     }
 
@@ -399,6 +404,27 @@ impl DirNode {
         self.mount_at_this_dir.lock().is_some()
     }
 
+    /// Drops the cached entries of this directory and the ones below that
+    /// nothing else refers to, for memory running out. Entries with user
+    /// data, such as watches, and mountpoints are kept.
+    ///
+    /// Returns the number of entries dropped.
+    pub fn prune(&self) -> usize {
+        let mut pruned = 0;
+        self.dentry_cache.lock().retain(|_, child| {
+            if let Ok(dir) = child.as_dir() {
+                pruned += dir.prune();
+                if dir.is_mountpoint() {
+                    return true;
+                }
+            }
+            let unused = child.is_unused();
+            pruned += unused as usize;
+            !unused
+        });
+        pruned
+    }
+
     /// Clears the cache of directory entries & user data, allowing them to be
     /// released.
     pub(crate) fn forget(&self) {
@@ -24,6 +24,7 @@ use bitflags::bitflags;
 pub use dir::*;
 pub use file::*;
 use inherit_methods_macro::inherit_methods;
+use kalloc::{CacheStats, KmemCache, arc_layout};
 use kpoll::{IoEvents, Pollable};
 use smallvec::SmallVec;
 
@@ -203,6 +204,9 @@ impl TypeMap {
     }
 }
 
+/// Cache of the directory entries, the inodes of the VFS.
+static DENTRY_CACHE: KmemCache = KmemCache::new("dentry", arc_layout::<Inner>(), None);
+
 struct Inner {
     node: Node,
     node_type: NodeType,
@@ -222,11 +226,11 @@ impl fmt::Debug for Inner {
 
 /// Strong reference to a directory entry.
 #[derive(Debug, Clone)]
-pub struct DirEntry(Arc<Inner>);
+pub struct DirEntry(Arc<Inner, &'static KmemCache>);
 
 /// Weak reference to a directory entry.
 #[derive(Debug, Clone)]
-pub struct WeakDirEntry(Weak<Inner>);
+pub struct WeakDirEntry(Weak<Inner, &'static KmemCache>);
 
 impl WeakDirEntry {
     /// Upgrade to a strong reference if the entry still exists.
@@ -263,22 +267,28 @@ impl DirEntry {
 impl DirEntry {
     /// Construct a file entry with the given node and reference.
     pub fn new_file(node: FileNode, node_type: NodeType, reference: Reference) -> Self {
-        Self(Arc::new(Inner {
-            node: Node::File(node),
-            node_type,
-            reference,
-            user_data: Mutex::default(),
-        }))
+        Self(Arc::new_in(
+            Inner {
+                node: Node::File(node),
+                node_type,
+                reference,
+                user_data: Mutex::default(),
+            },
+            &DENTRY_CACHE,
+        ))
     }
 
     /// Construct a directory entry with a node builder.
     pub fn new_dir(node_fn: impl FnOnce(WeakDirEntry) -> DirNode, reference: Reference) -> Self {
-        Self(Arc::new_cyclic(|this| Inner {
-            node: Node::Dir(node_fn(WeakDirEntry(this.clone()))),
-            node_type: NodeType::Directory,
-            reference,
-            user_data: Mutex::default(),
-        }))
+        Self(Arc::new_cyclic_in(
+            |this| Inner {
+                node: Node::Dir(node_fn(WeakDirEntry(this.clone()))),
+                node_type: NodeType::Directory,
+                reference,
+                user_data: Mutex::default(),
+            },
+            &DENTRY_CACHE,
+        ))
     }
 
     /// Returns metadata for this entry, filling in its node type.
@@ -425,6 +435,18 @@ impl DirEntry {
     pub fn user_data(&self) -> MutexGuard<'_, TypeMap> {
         self.0.user_data.lock()
     }
+
+    /// Returns `true` if only the dentry cache holds the entry, which has no
+    /// user data.
+    pub(crate) fn is_unused(&self) -> bool {
+        Arc::strong_count(&self.0) == 1 && self.0.user_data.lock().0.is_empty()
+    }
+}
+
+/// Returns the statistics of the cache the directory entries are allocated
+/// from.
+pub fn dentry_cache_stats() -> CacheStats {
+    DENTRY_CACHE.stats()
 }
 
 impl Pollable for DirEntry {
@@ -0,0 +1,126 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Per-mount performance counters.
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Lock-free counters kept by every [`Mountpoint`].
+///
+/// The VFS records lookups; the file layer on top records page cache,
+/// readahead and I/O activity.
+///
+/// [`Mountpoint`]: crate::Mountpoint
+#[derive(Debug, Default)]
+pub struct MountStats {
+    lookups: AtomicU64,
+    dentry_hits: AtomicU64,
+    page_hits: AtomicU64,
+    page_misses: AtomicU64,
+    readahead_pages: AtomicU64,
+    reads: AtomicU64,
+    read_bytes: AtomicU64,
+    read_nanos: AtomicU64,
+    writes: AtomicU64,
+    write_bytes: AtomicU64,
+    write_nanos: AtomicU64,
+}
+
+impl MountStats {
+    /// Records a name lookup and whether the dentry cache answered it.
+    pub fn record_lookup(&self, cache_hit: bool) {
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        if cache_hit {
+            self.dentry_hits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records an access to a page of file data.
+    pub fn record_page_access(&self, cache_hit: bool) {
+        if cache_hit {
+            self.page_hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.page_misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records pages read into the cache ahead of a sequential reader.
+    pub fn record_readahead(&self, pages: u64) {
+        self.readahead_pages.fetch_add(pages, Ordering::Relaxed);
+    }
+
+    /// Records a completed read request.
+    pub fn record_read(&self, bytes: usize, elapsed: Duration) {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.read_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.read_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Records a completed write request.
+    pub fn record_write(&self, bytes: usize, elapsed: Duration) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        self.write_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.write_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Takes a point-in-time copy of all counters.
+    pub fn snapshot(&self) -> MountStatsSnapshot {
+        MountStatsSnapshot {
+            lookups: self.lookups.load(Ordering::Relaxed),
+            dentry_hits: self.dentry_hits.load(Ordering::Relaxed),
+            page_hits: self.page_hits.load(Ordering::Relaxed),
+            page_misses: self.page_misses.load(Ordering::Relaxed),
+            readahead_pages: self.readahead_pages.load(Ordering::Relaxed),
+            reads: self.reads.load(Ordering::Relaxed),
+            read_bytes: self.read_bytes.load(Ordering::Relaxed),
+            read_nanos: self.read_nanos.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+            write_bytes: self.write_bytes.load(Ordering::Relaxed),
+            write_nanos: self.write_nanos.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A copy of [`MountStats`] taken by [`MountStats::snapshot`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MountStatsSnapshot {
+    /// Name lookups.
+    pub lookups: u64,
+    /// Lookups answered by the dentry cache.
+    pub dentry_hits: u64,
+    /// Page accesses answered by the page cache.
+    pub page_hits: u64,
+    /// Page accesses that had to read from the filesystem.
+    pub page_misses: u64,
+    /// Pages read ahead of sequential readers.
+    pub readahead_pages: u64,
+    /// Read requests.
+    pub reads: u64,
+    /// Bytes returned by read requests.
+    pub read_bytes: u64,
+    /// Total time spent in read requests, in nanoseconds.
+    pub read_nanos: u64,
+    /// Write requests.
+    pub writes: u64,
+    /// Bytes accepted by write requests.
+    pub write_bytes: u64,
+    /// Total time spent in write requests, in nanoseconds.
+    pub write_nanos: u64,
+}
+
+impl MountStatsSnapshot {
+    /// Average latency of a read request.
+    pub fn avg_read_latency(&self) -> Duration {
+        Duration::from_nanos(self.read_nanos.checked_div(self.reads).unwrap_or(0))
+    }
+
+    /// Average latency of a write request.
+    pub fn avg_write_latency(&self) -> Duration {
+        Duration::from_nanos(self.write_nanos.checked_div(self.writes).unwrap_or(0))
+    }
+}
@@ -0,0 +1,44 @@
+#![cfg(unittest)]
+
+use core::time::Duration;
+
+use unittest::{assert_eq, def_test};
+
+use crate::stats::MountStats;
+
+#[def_test]
+fn test_mount_stats_counters() {
+    let stats = MountStats::default();
+    stats.record_lookup(true);
+    stats.record_lookup(false);
+    stats.record_page_access(true);
+    stats.record_page_access(false);
+    stats.record_page_access(false);
+    stats.record_readahead(4);
+
+    let snap = stats.snapshot();
+    assert_eq!(snap.lookups, 2);
+    assert_eq!(snap.dentry_hits, 1);
+    assert_eq!(snap.page_hits, 1);
+    assert_eq!(snap.page_misses, 2);
+    assert_eq!(snap.readahead_pages, 4);
+}
+
+#[def_test]
+fn test_mount_stats_latency() {
+    let stats = MountStats::default();
+    // No requests yet: averages must not divide by zero
+    assert_eq!(stats.snapshot().avg_read_latency(), Duration::ZERO);
+
+    stats.record_read(100, Duration::from_micros(10));
+    stats.record_read(300, Duration::from_micros(30));
+    stats.record_write(512, Duration::from_micros(5));
+
+    let snap = stats.snapshot();
+    assert_eq!(snap.reads, 2);
+    assert_eq!(snap.read_bytes, 400);
+    assert_eq!(snap.avg_read_latency(), Duration::from_micros(20));
+    assert_eq!(snap.writes, 1);
+    assert_eq!(snap.write_bytes, 512);
+    assert_eq!(snap.avg_write_latency(), Duration::from_micros(5));
+}
@@ -0,0 +1,369 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! File change notification, the machinery behind `inotify(7)`.
+//!
+//! A [`WatchQueue`] collects the events of the entries it watches. The
+//! watches hang off the user data of the entries: directories report the
+//! names created, removed and moved in them, and the file layer reports
+//! writes with [`notify_modify`].
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    mem,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+    task::Context,
+};
+
+use bitflags::bitflags;
+use kpoll::{IoEvents, PollSet, Pollable};
+
+use crate::{DirEntry, Location, Mutex, VfsError, VfsResult};
+
+bitflags! {
+    /// Kinds of file changes, with the values of the `IN_*` constants.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct WatchMask: u32 {
+        /// The file was written to or truncated.
+        const MODIFY = 0x2;
+        /// A name was moved out of the directory.
+        const MOVED_FROM = 0x40;
+        /// A name was moved into the directory.
+        const MOVED_TO = 0x80;
+        /// A name was created in the directory.
+        const CREATE = 0x100;
+        /// A name was removed from the directory.
+        const DELETE = 0x200;
+        /// The watched entry itself was removed.
+        const DELETE_SELF = 0x400;
+        /// The watched entry itself was moved.
+        const MOVE_SELF = 0x800;
+        /// Events were dropped, the queue being full.
+        const Q_OVERFLOW = 0x4000;
+        /// The watch was removed.
+        const IGNORED = 0x8000;
+        /// The name the event is about is a directory.
+        const ISDIR = 0x4000_0000;
+    }
+}
+
+impl WatchMask {
+    /// The events a watch can subscribe to.
+    pub const ALL_EVENTS: Self = Self::MODIFY
+        .union(Self::MOVED_FROM)
+        .union(Self::MOVED_TO)
+        .union(Self::CREATE)
+        .union(Self::DELETE)
+        .union(Self::DELETE_SELF)
+        .union(Self::MOVE_SELF);
+}
+
+/// A change reported to a [`WatchQueue`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchEvent {
+    /// Watch descriptor of the watch reporting the event, or -1 for
+    /// [`WatchMask::Q_OVERFLOW`].
+    pub wd: i32,
+    /// What happened.
+    pub mask: WatchMask,
+    /// Ties the two halves of a rename together.
+    pub cookie: u32,
+    /// Name in the watched directory the event is about.
+    pub name: Option<String>,
+}
+
+/// Events queued before further ones are dropped.
+const MAX_QUEUED_EVENTS: usize = 16384;
+
+/// Watches anywhere, to skip looking for watchers while there are none.
+static WATCHES: AtomicUsize = AtomicUsize::new(0);
+
+static NEXT_COOKIE: AtomicU32 = AtomicU32::new(1);
+
+struct Watch {
+    queue: Weak<WatchQueue>,
+    wd: i32,
+    mask: WatchMask,
+}
+
+/// The watches on an entry, kept in its user data.
+#[derive(Default)]
+struct Watchers(Mutex<Vec<Watch>>);
+
+fn watchers(entry: &DirEntry) -> Arc<Watchers> {
+    entry.user_data().get_or_insert_with(Watchers::default)
+}
+
+#[derive(Default)]
+struct QueueState {
+    events: VecDeque<WatchEvent>,
+    watches: BTreeMap<i32, DirEntry>,
+    next_wd: i32,
+}
+
+/// A queue of file change events, from the watches added to it.
+///
+/// The queue keeps the watched entries alive; dropping it removes its
+/// watches.
+#[derive(Default)]
+pub struct WatchQueue {
+    state: Mutex<QueueState>,
+    poll_rx: PollSet,
+}
+
+impl WatchQueue {
+    /// Creates a queue without watches.
+    pub fn new() -> Arc<Self> {
+        Arc::default()
+    }
+
+    /// Watches `location` for the events in `mask`. Returns the watch
+    /// descriptor.
+    ///
+    /// If the queue already watches the entry, the mask of that watch is
+    /// replaced, or extended when `merge` is set.
+    pub fn add_watch(
+        self: &Arc<Self>,
+        location: &Location,
+        mask: WatchMask,
+        merge: bool,
+    ) -> VfsResult<i32> {
+        let mask = mask & WatchMask::ALL_EVENTS;
+        let entry = location.entry();
+        let mut state = self.state.lock();
+        let watchers = watchers(entry);
+        let mut watches = watchers.0.lock();
+
+        let existing = state
+            .watches
+            .iter()
+            .find(|(_, it)| it.ptr_eq(entry))
+            .map(|(wd, _)| *wd);
+        if let Some(wd) = existing {
+            if let Some(watch) = watches.iter_mut().find(|it| it.wd == wd && self.owns(it)) {
+                watch.mask = if merge { watch.mask | mask } else { mask };
+            }
+            return Ok(wd);
+        }
+
+        state.next_wd += 1;
+        let wd = state.next_wd;
+        state.watches.insert(wd, entry.clone());
+        watches.push(Watch {
+            queue: Arc::downgrade(self),
+            wd,
+            mask,
+        });
+        WATCHES.fetch_add(1, Ordering::Relaxed);
+        Ok(wd)
+    }
+
+    /// Removes a watch, queueing a [`WatchMask::IGNORED`] event for it.
+    pub fn remove_watch(&self, wd: i32) -> VfsResult<()> {
+        let mut state = self.state.lock();
+        let entry = state.watches.remove(&wd).ok_or(VfsError::InvalidInput)?;
+        watchers(&entry)
+            .0
+            .lock()
+            .retain(|it| !(it.wd == wd && self.owns(it)));
+        WATCHES.fetch_sub(1, Ordering::Relaxed);
+        self.push_locked(&mut state, wd, WatchMask::IGNORED, 0, None);
+        drop(state);
+        self.poll_rx.wake();
+        Ok(())
+    }
+
+    /// Gives access to the events queued, oldest first, for the reader to
+    /// take some.
+    pub fn with_events<R>(&self, f: impl FnOnce(&mut VecDeque<WatchEvent>) -> R) -> R {
+        f(&mut self.state.lock().events)
+    }
+
+    fn owns(&self, watch: &Watch) -> bool {
+        core::ptr::eq(watch.queue.as_ptr(), self)
+    }
+
+    fn push_locked(
+        &self,
+        state: &mut QueueState,
+        wd: i32,
+        mask: WatchMask,
+        cookie: u32,
+        name: Option<&str>,
+    ) {
+        let event = WatchEvent {
+            wd,
+            mask,
+            cookie,
+            name: name.map(ToString::to_string),
+        };
+        // Like Linux, identical events in a row are coalesced
+        if state.events.back() == Some(&event) {
+            return;
+        }
+        if state.events.len() >= MAX_QUEUED_EVENTS {
+            let overflow = WatchEvent {
+                wd: -1,
+                mask: WatchMask::Q_OVERFLOW,
+                cookie: 0,
+                name: None,
+            };
+            if state.events.back() != Some(&overflow) {
+                state.events.push_back(overflow);
+            }
+            return;
+        }
+        state.events.push_back(event);
+    }
+
+    fn push(&self, wd: i32, mask: WatchMask, cookie: u32, name: Option<&str>) {
+        self.push_locked(&mut self.state.lock(), wd, mask, cookie, name);
+        self.poll_rx.wake();
+    }
+
+    /// Drops a watch whose entry went away, after its last events.
+    fn forget(&self, wd: i32) {
+        let mut state = self.state.lock();
+        if state.watches.remove(&wd).is_some() {
+            WATCHES.fetch_sub(1, Ordering::Relaxed);
+            self.push_locked(&mut state, wd, WatchMask::IGNORED, 0, None);
+        }
+        drop(state);
+        self.poll_rx.wake();
+    }
+}
+
+impl Drop for WatchQueue {
+    fn drop(&mut self) {
+        let state = mem::take(self.state.get_mut());
+        for (wd, entry) in state.watches {
+            if let Some(watchers) = entry.user_data().get::<Watchers>() {
+                watchers
+                    .0
+                    .lock()
+                    .retain(|it| !(it.wd == wd && self.owns(it)));
+            }
+            WATCHES.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Pollable for WatchQueue {
+    fn poll(&self) -> IoEvents {
+        let mut events = IoEvents::empty();
+        events.set(IoEvents::IN, !self.state.lock().events.is_empty());
+        events
+    }
+
+    fn register(&self, context: &mut Context<'_>, events: IoEvents) {
+        if events.contains(IoEvents::IN) {
+            self.poll_rx.register(context.waker());
+        }
+    }
+}
+
+/// Returns whether any watch exists, for callers to skip gathering what
+/// they would report.
+pub(crate) fn watching() -> bool {
+    WATCHES.load(Ordering::Relaxed) != 0
+}
+
+/// Reports `mask` to the watches on `entry` subscribed to it.
+fn report(entry: &DirEntry, mask: WatchMask, cookie: u32, name: Option<&str>) {
+    if !watching() {
+        return;
+    }
+    let Some(watchers) = entry.user_data().get::<Watchers>() else {
+        return;
+    };
+    // Collected first: the queues are locked before the watchers elsewhere
+    let targets = watchers
+        .0
+        .lock()
+        .iter()
+        .filter(|it| it.mask.intersects(mask))
+        .filter_map(|it| Some((it.queue.upgrade()?, it.wd)))
+        .collect::<Vec<_>>();
+    for (queue, wd) in targets {
+        queue.push(wd, mask, cookie, name);
+    }
+}
+
+/// Reports `mask` about `entry` to the watches of its directory.
+fn report_in_parent(entry: &DirEntry, mask: WatchMask, cookie: u32) {
+    if let Some(parent) = entry.parent() {
+        let mask = if entry.is_dir() {
+            mask | WatchMask::ISDIR
+        } else {
+            mask
+        };
+        report(&parent, mask, cookie, Some(entry.name()));
+    }
+}
+
+/// Reports that `entry` was created.
+pub(crate) fn notify_create(entry: &DirEntry) {
+    report_in_parent(entry, WatchMask::CREATE, 0);
+}
+
+/// Reports that `entry` was removed from its directory, and drops its
+/// watches if it is gone for good.
+pub(crate) fn notify_delete(entry: &DirEntry) {
+    report_in_parent(entry, WatchMask::DELETE, 0);
+    if !watching() {
+        return;
+    }
+    // Other hard links keep the file alive
+    if !entry.is_dir() && entry.metadata().is_ok_and(|it| it.nlink > 0) {
+        return;
+    }
+    let Some(watchers) = entry.user_data().get::<Watchers>() else {
+        return;
+    };
+    let watches = mem::take(&mut *watchers.0.lock());
+    for watch in watches {
+        if let Some(queue) = watch.queue.upgrade() {
+            if watch.mask.contains(WatchMask::DELETE_SELF) {
+                queue.push(watch.wd, WatchMask::DELETE_SELF, 0, None);
+            }
+            queue.forget(watch.wd);
+        }
+    }
+}
+
+/// Reports that `entry` was moved from `src` to `dst`, both directories.
+pub(crate) fn notify_move(
+    entry: &DirEntry,
+    src: &DirEntry,
+    src_name: &str,
+    dst: &DirEntry,
+    dst_name: &str,
+) {
+    if !watching() {
+        return;
+    }
+    let cookie = NEXT_COOKIE.fetch_add(1, Ordering::Relaxed);
+    let isdir = if entry.is_dir() {
+        WatchMask::ISDIR
+    } else {
+        WatchMask::empty()
+    };
+    report(src, WatchMask::MOVED_FROM | isdir, cookie, Some(src_name));
+    report(dst, WatchMask::MOVED_TO | isdir, cookie, Some(dst_name));
+    report(entry, WatchMask::MOVE_SELF, 0, None);
+}
+
+/// Reports that the file at `location` was written to or truncated.
+pub fn notify_modify(location: &Location) {
+    if !watching() {
+        return;
+    }
+    let entry = location.entry();
+    report(entry, WatchMask::MODIFY, 0, None);
+    report_in_parent(entry, WatchMask::MODIFY, 0);
+}
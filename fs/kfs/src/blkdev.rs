@@ -0,0 +1,448 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Block devices and their partitions.
+//!
+//! [`init_filesystems`] registers the disks as `vda`, `vdb`, … and the
+//! partitions found on them as `vda1`, `vda2`, … A filesystem is created on
+//! a device with [`mount_block_device`].
+//!
+//! Files are attached as loop devices `loop0`, `loop1`, … with
+//! [`attach_loop`], their partitions named `loop0p1`, `loop0p2`, …
+//!
+//! Failing requests to a disk follow its [`IoPolicy`], set with
+//! [`set_io_policy`]. By default a disk not answering goes offline after a
+//! few resets, failing the requests to it and its partitions with
+//! [`VfsError::Io`].
+//!
+//! [`read_blocks`] and [`write_blocks`] do I/O asynchronously: the task
+//! sleeps until the disk interrupts instead of busy-waiting, and several
+//! requests can be in flight at once.
+//!
+//! Requests are issued at the I/O priority of the current task, so that the
+//! request queue serves e.g. an interactive task before a background scrub.
+//!
+//! [`init_filesystems`]: crate::init_filesystems
+use alloc::{format, string::String, vec::Vec};
+use core::{future::poll_fn, task::Poll, time::Duration};
+
+use fs_ng_vfs::{Filesystem, VfsError, VfsResult};
+use kdriver::{BlockDevice as KBlockDevice, prelude::*};
+use kerrno::ResultExt;
+use kspin::SpinNoPreempt as Mutex;
+use ktask::future::register_irq_waker;
+
+use crate::{highlevel::FileBackend, loopdev::LoopDisk};
+
+/// The scheduler of the request queues in front of the disks.
+#[cfg(feature = "elevator-noop")]
+type Elevator = Noop;
+#[cfg(not(feature = "elevator-noop"))]
+type Elevator = Deadline;
+
+/// A device a filesystem is created on: a whole disk or a partition of one.
+///
+/// Partitions of a disk share its request queue.
+pub(crate) type Disk = Partition<RequestQueue<Storage, Elevator>>;
+
+/// What a disk stores its blocks on.
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Storage {
+    /// A device probed by the drivers.
+    Device(KBlockDevice),
+    /// A file, see [`attach_loop`].
+    Loop(LoopDisk),
+}
+
+macro_rules! storage_call {
+    ($self:expr, $dev:ident => $call:expr) => {
+        match $self {
+            Storage::Device($dev) => $call,
+            Storage::Loop($dev) => $call,
+        }
+    };
+}
+
+impl DriverOps for Storage {
+    fn name(&self) -> &str {
+        storage_call!(self, dev => dev.name())
+    }
+
+    fn device_kind(&self) -> DeviceKind {
+        DeviceKind::Block
+    }
+
+    fn irq(&self) -> Option<usize> {
+        storage_call!(self, dev => dev.irq())
+    }
+
+    fn reset(&mut self) -> DriverResult {
+        storage_call!(self, dev => dev.reset())
+    }
+}
+
+impl BlockDriverOps for Storage {
+    fn num_blocks(&self) -> u64 {
+        storage_call!(self, dev => dev.num_blocks())
+    }
+
+    fn block_size(&self) -> usize {
+        storage_call!(self, dev => dev.block_size())
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DriverResult {
+        storage_call!(self, dev => dev.read_block(block_id, buf))
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DriverResult {
+        storage_call!(self, dev => dev.write_block(block_id, buf))
+    }
+
+    fn write_block_at(&mut self, block_id: u64, buf: &[u8], priority: IoPriority) -> DriverResult {
+        storage_call!(self, dev => dev.write_block_at(block_id, buf, priority))
+    }
+
+    fn flush(&mut self) -> DriverResult {
+        storage_call!(self, dev => dev.flush())
+    }
+
+    fn discard(&mut self, block_id: u64, num_blocks: u64) -> DriverResult {
+        storage_call!(self, dev => dev.discard(block_id, num_blocks))
+    }
+
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> DriverResult {
+        storage_call!(self, dev => dev.set_timeout(timeout))
+    }
+
+    fn submit(&mut self, io: BlockIo) -> DriverResult<IoToken> {
+        storage_call!(self, dev => dev.submit(io))
+    }
+
+    fn poll_complete(&mut self, token: IoToken) -> DriverResult<BlockIo> {
+        storage_call!(self, dev => dev.poll_complete(token))
+    }
+
+    fn enable_interrupt(&mut self, enable: bool) {
+        storage_call!(self, dev => dev.enable_interrupt(enable))
+    }
+}
+
+/// A registered block device.
+#[derive(Debug, Clone)]
+pub struct BlockDeviceInfo {
+    pub name: String,
+    pub num_blocks: u64,
+    pub block_size: usize,
+    /// The partition covered, `None` for a whole disk.
+    pub partition: Option<PartitionInfo>,
+    /// Whether the disk went offline.
+    pub offline: bool,
+}
+
+struct Entry {
+    info: BlockDeviceInfo,
+    /// The name of the whole disk.
+    disk: String,
+    dev: Disk,
+    /// Whether a filesystem was created on the device.
+    mounted: bool,
+}
+
+static BLOCK_DEVICES: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+
+/// Returns the name of the `index`th disk.
+pub(crate) fn disk_name(index: usize) -> String {
+    let mut name = String::from("vd");
+    let mut suffix = Vec::new();
+    let mut n = index + 1;
+    // Like Linux: vdz is followed by vdaa
+    while n > 0 {
+        suffix.push(b'a' + ((n - 1) % 26) as u8);
+        n = (n - 1) / 26;
+    }
+    name.extend(suffix.iter().rev().map(|&c| c as char));
+    name
+}
+
+fn partition_name(disk: &str, number: usize) -> String {
+    // Like Linux: loop0p1 rather than loop01
+    if disk.ends_with(|c: char| c.is_ascii_digit()) {
+        format!("{}p{}", disk, number)
+    } else {
+        format!("{}{}", disk, number)
+    }
+}
+
+/// Registers `dev` as the disk `name`, and the partitions found on it.
+///
+/// Returns the partitions.
+pub(crate) fn register_disk(name: String, dev: Storage) -> Vec<PartitionInfo> {
+    let mut queue = RequestQueue::new(dev, Elevator::default());
+    debug!("{}: {} elevator", name, queue.elevator());
+    if let Err(err) = queue.set_policy(IoPolicy::default()) {
+        warn!("{}: failed to set the I/O policy: {:?}", name, err);
+    }
+    let mut disk = Disk::whole_disk(queue, name.clone());
+    let partitions = scan_partitions(&mut disk).unwrap_or_else(|err| {
+        warn!("{}: failed to read the partition table: {:?}", name, err);
+        Vec::new()
+    });
+
+    let block_size = disk.block_size();
+    let mut entries = Vec::from([Entry {
+        info: BlockDeviceInfo {
+            name: name.clone(),
+            num_blocks: disk.num_blocks(),
+            block_size,
+            partition: None,
+            offline: false,
+        },
+        disk: name.clone(),
+        dev: disk.clone(),
+        mounted: false,
+    }]);
+    for partition in &partitions {
+        let part_name = partition_name(&name, partition.number);
+        info!(
+            "  {}: {} blocks from block {}, type {:x?}",
+            part_name, partition.num_blocks, partition.start, partition.partition_type
+        );
+        entries.push(Entry {
+            disk: name.clone(),
+            dev: disk.partition(part_name.clone(), partition),
+            info: BlockDeviceInfo {
+                name: part_name,
+                num_blocks: partition.num_blocks,
+                block_size,
+                partition: Some(partition.clone()),
+                offline: false,
+            },
+            mounted: false,
+        });
+    }
+    BLOCK_DEVICES.lock().extend(entries);
+    partitions
+}
+
+/// Attaches `file` as the loop device `name`, e.g. `loop0`, registering it
+/// like a disk with the partitions found on it.
+///
+/// Returns the partitions. Writes to a `read_only` device fail when they are
+/// dispatched to the file.
+pub fn attach_loop(
+    name: &str,
+    file: FileBackend,
+    read_only: bool,
+) -> VfsResult<Vec<PartitionInfo>> {
+    if device(name).is_ok() {
+        return Err(VfsError::ResourceBusy);
+    }
+    let dev = LoopDisk::new(file, read_only)?;
+    info!("{}: {} blocks", name, dev.num_blocks());
+    Ok(register_disk(name.into(), Storage::Loop(dev)))
+}
+
+/// Detaches the loop device `name`, writing back what is queued for it.
+///
+/// Fails with [`VfsError::ResourceBusy`] if a filesystem was created on it
+/// or on one of its partitions.
+pub fn detach_loop(name: &str) -> VfsResult<()> {
+    let mut devices = BLOCK_DEVICES.lock();
+    let entries = || devices.iter().filter(|entry| entry.disk == name);
+    let Some(disk) = entries().find(|entry| entry.info.partition.is_none()) else {
+        return Err(VfsError::NotFound);
+    };
+    if !matches!(disk.dev.disk().device(), Storage::Loop(_)) {
+        return Err(VfsError::InvalidInput);
+    }
+    if entries().any(|entry| entry.mounted) {
+        return Err(VfsError::ResourceBusy);
+    }
+    disk.dev.clone().flush().map_err(as_vfs_error)?;
+    devices.retain(|entry| entry.disk != name);
+    Ok(())
+}
+
+/// Picks the device on the disk `disk` to mount as root: the first partition
+/// meant for the filesystem built in, else the first partition, else the
+/// whole disk.
+pub(crate) fn root_device(disk: &str, partitions: &[PartitionInfo]) -> String {
+    let fits = |partition: &&PartitionInfo| {
+        if cfg!(feature = "ext4") {
+            partition.partition_type.is_linux()
+        } else {
+            partition.partition_type.is_fat()
+        }
+    };
+    partitions
+        .iter()
+        .find(fits)
+        .or(partitions.first())
+        .map_or_else(|| disk.into(), |it| partition_name(disk, it.number))
+}
+
+/// Lists the registered block devices.
+pub fn block_devices() -> Vec<BlockDeviceInfo> {
+    BLOCK_DEVICES
+        .lock()
+        .iter()
+        .map(|entry| BlockDeviceInfo {
+            offline: entry.dev.disk().is_offline(),
+            ..entry.info.clone()
+        })
+        .collect()
+}
+
+fn device(name: &str) -> VfsResult<Disk> {
+    BLOCK_DEVICES
+        .lock()
+        .iter()
+        .find(|entry| entry.info.name == name)
+        .map(|entry| entry.dev.clone())
+        .ok_or(VfsError::NotFound)
+}
+
+fn as_vfs_error(err: DriverError) -> VfsError {
+    match err {
+        DriverError::InvalidInput => VfsError::InvalidInput,
+        _ => VfsError::Io,
+    }
+}
+
+/// Waits for the request `token` submitted to `dev` to complete, sleeping
+/// until the disk interrupts if it has an interrupt.
+pub(crate) async fn complete(dev: &mut Disk, token: IoToken) -> DriverResult<BlockIo> {
+    poll_fn(|cx| {
+        match dev.poll_complete(token) {
+            Err(DriverError::WouldBlock) => {}
+            result => return Poll::Ready(result),
+        }
+        match dev.irq() {
+            Some(irq) => {
+                register_irq_waker(irq, cx.waker());
+                dev.enable_interrupt(true);
+            }
+            // Polled again on the next round
+            None => cx.waker().wake_by_ref(),
+        }
+        // It may have completed before the interrupt was enabled
+        match dev.poll_complete(token) {
+            Err(DriverError::WouldBlock) => Poll::Pending,
+            result => Poll::Ready(result),
+        }
+    })
+    .await
+}
+
+/// Returns the I/O priority of the current task, see [`ktask::TaskInner::io_priority`].
+pub(crate) fn current_io_priority() -> IoPriority {
+    IoPriority::from_raw(ktask::current().io_priority()).unwrap_or_default()
+}
+
+/// Reads `len` bytes, whole blocks, from the block device `name` from
+/// `block_id` on.
+pub async fn read_blocks(name: &str, block_id: u64, len: usize) -> VfsResult<Vec<u8>> {
+    let mut dev = device(name)?;
+    let token = dev
+        .submit(BlockIo::read(block_id, len).with_priority(current_io_priority()))
+        .map_err(as_vfs_error)?;
+    let io = complete(&mut dev, token).await.map_err(as_vfs_error)?;
+    Ok(io.buf)
+}
+
+/// Writes `buf`, whole blocks, to the block device `name` from `block_id`
+/// on.
+///
+/// Like other writes, it is queued until the disk is flushed.
+pub async fn write_blocks(name: &str, block_id: u64, buf: Vec<u8>) -> VfsResult<()> {
+    let mut dev = device(name)?;
+    let token = dev
+        .submit(BlockIo::write(block_id, buf).with_priority(current_io_priority()))
+        .map_err(as_vfs_error)?;
+    complete(&mut dev, token).await.map_err(as_vfs_error)?;
+    Ok(())
+}
+
+/// Sets the policy for failing requests to the disk holding the block
+/// device `name`.
+pub fn set_io_policy(name: &str, policy: IoPolicy) -> VfsResult<()> {
+    let devices = BLOCK_DEVICES.lock();
+    let entry = devices
+        .iter()
+        .find(|entry| entry.info.name == name)
+        .ok_or(VfsError::NotFound)?;
+    entry
+        .dev
+        .disk()
+        .set_policy(policy)
+        .context(format_args!("{name}: failed to set the I/O policy"))
+}
+
+/// Flushes the write caches of the disks, e.g. before a shutdown.
+///
+/// All the disks are flushed even if some fail; the first failure is
+/// returned.
+pub fn flush_block_devices() -> VfsResult<()> {
+    let disks: Vec<_> = BLOCK_DEVICES
+        .lock()
+        .iter()
+        .filter(|entry| entry.info.partition.is_none())
+        .map(|entry| (entry.info.name.clone(), entry.dev.clone()))
+        .collect();
+    let mut result = Ok(());
+    for (name, mut dev) in disks {
+        if let Err(err) = dev.flush() {
+            warn!("{}: failed to flush: {:?}", name, err);
+            result = result.and(Err(as_vfs_error(err)));
+        }
+    }
+    result
+}
+
+/// Resets the disks, e.g. after their backends on the host restarted.
+///
+/// Offline disks come back online. Disks unable to reset are left alone.
+pub fn reset_block_devices() {
+    let mut devices = BLOCK_DEVICES.lock();
+    let disks = devices
+        .iter_mut()
+        .filter(|entry| entry.info.partition.is_none());
+    for entry in disks {
+        match entry.dev.reset() {
+            Ok(()) => info!("{}: reset", entry.info.name),
+            Err(DriverError::Unsupported) => {}
+            Err(err) => warn!("{}: failed to reset: {:?}", entry.info.name, err),
+        }
+    }
+}
+
+/// Creates a filesystem of type `fs_type` on the block device `name`, e.g.
+/// `vda2`.
+///
+/// A device backs one filesystem only, and stays busy after it is
+/// unmounted. Devices on an offline disk fail with [`VfsError::Io`].
+pub fn mount_block_device(name: &str, fs_type: &str) -> VfsResult<Filesystem> {
+    if fs_type != crate::fs::FS_TYPE {
+        return Err(VfsError::NoSuchDevice);
+    }
+    let set_mounted = |mounted: bool| {
+        let mut devices = BLOCK_DEVICES.lock();
+        let entry = devices
+            .iter_mut()
+            .find(|entry| entry.info.name == name)
+            .ok_or(VfsError::NotFound)?;
+        if mounted && entry.mounted {
+            return Err(VfsError::ResourceBusy);
+        }
+        if mounted && entry.dev.disk().is_offline() {
+            return Err(VfsError::Io);
+        }
+        entry.mounted = mounted;
+        Ok(entry.dev.clone())
+    };
+    let dev = set_mounted(true)?;
+    crate::fs::new_default(dev).inspect_err(|_| {
+        let _ = set_mounted(false);
+    })
+}
@@ -0,0 +1,47 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Reclaim of the cached directory entries of the mounted filesystems.
+use alloc::sync::Arc;
+
+use fs_ng_vfs::dentry_cache_stats;
+use kalloc::{ShrinkControl, Shrinker};
+
+use crate::ROOT_FS_CONTEXT;
+
+/// Prunes the unused entries of the dentry caches when memory runs out.
+struct DentryShrinker;
+
+impl Shrinker for DentryShrinker {
+    fn name(&self) -> &'static str {
+        "dentry"
+    }
+
+    fn count(&self) -> usize {
+        let stats = dentry_cache_stats();
+        stats.active_objects * stats.stride
+    }
+
+    fn scan(&self, control: &ShrinkControl) -> usize {
+        // The caches are behind sleeping locks
+        if !control.may_block {
+            return 0;
+        }
+        let Some(ctx) = ROOT_FS_CONTEXT.get() else {
+            return 0;
+        };
+        let mut pruned = 0;
+        for mp in ctx.root_dir().mountpoint().mount_table() {
+            if let Ok(dir) = mp.root_location().entry().as_dir() {
+                pruned += dir.prune();
+            }
+        }
+        pruned * dentry_cache_stats().stride
+    }
+}
+
+/// Has the dentry caches pruned when memory runs out.
+pub(crate) fn register() {
+    kalloc::register_shrinker(Arc::new(DentryShrinker));
+}
@@ -7,7 +7,9 @@
 use alloc::{boxed::Box, vec};
 use core::mem;
 
-use kdriver::{BlockDevice as KBlockDevice, prelude::*};
+use kdriver::prelude::*;
+
+use crate::blkdev::Disk;
 
 /// Consume `cnt` bytes from the front of a slice.
 fn take<'a>(buf: &mut &'a [u8], cnt: usize) -> &'a [u8] {
@@ -26,7 +28,7 @@ fn take_mut<'a>(buf: &mut &'a mut [u8], cnt: usize) -> &'a mut [u8] {
 
 /// A disk device with a cursor.
 pub struct SeekableDisk {
-    dev: KBlockDevice,
+    dev: Disk,
 
     block_id: u64,
     offset: usize,
@@ -42,7 +44,7 @@ pub struct SeekableDisk {
 
 impl SeekableDisk {
     /// Create a new disk.
-    pub fn new(dev: KBlockDevice) -> Self {
+    pub fn new(dev: Disk) -> Self {
         assert!(dev.block_size().is_power_of_two());
         let block_size_log2 = dev.block_size().trailing_zeros() as u8;
         let read_buffer = vec![0u8; dev.block_size()].into_boxed_slice();
@@ -84,15 +86,21 @@ impl SeekableDisk {
     /// Write all pending changes to the disk.
     pub fn flush(&mut self) -> DriverResult<()> {
         if self.write_buffer_dirty {
-            self.dev.write_block(self.block_id, &self.write_buffer)?;
+            crate::metrics::write_block(&mut self.dev, self.block_id, &self.write_buffer)?;
             self.write_buffer_dirty = false;
         }
         Ok(())
     }
 
+    /// Write all pending changes through to the storage.
+    pub fn sync(&mut self) -> DriverResult<()> {
+        self.flush()?;
+        self.dev.flush()
+    }
+
     fn read_partial(&mut self, buf: &mut &mut [u8]) -> DriverResult<usize> {
         self.flush()?;
-        self.dev.read_block(self.block_id, &mut self.read_buffer)?;
+        crate::metrics::read_block(&mut self.dev, self.block_id, &mut self.read_buffer)?;
 
         let data = &self.read_buffer[self.offset..];
         let length = buf.len().min(data.len());
@@ -116,8 +124,7 @@ impl SeekableDisk {
         if buf.len() >= self.block_size() {
             let blocks = buf.len() >> self.block_size_log2;
             let length = blocks << self.block_size_log2;
-            self.dev
-                .read_block(self.block_id, take_mut(&mut buf, length))?;
+            crate::metrics::read_block(&mut self.dev, self.block_id, take_mut(&mut buf, length))?;
             read += length;
 
             self.block_id += blocks as u64;
@@ -131,7 +138,7 @@ impl SeekableDisk {
 
     fn write_partial(&mut self, buf: &mut &[u8]) -> DriverResult<usize> {
         if !self.write_buffer_dirty {
-            self.dev.read_block(self.block_id, &mut self.write_buffer)?;
+            crate::metrics::read_block(&mut self.dev, self.block_id, &mut self.write_buffer)?;
             self.write_buffer_dirty = true;
         }
 
@@ -158,8 +165,7 @@ impl SeekableDisk {
         if buf.len() >= self.block_size() {
             let blocks = buf.len() >> self.block_size_log2;
             let length = blocks << self.block_size_log2;
-            self.dev
-                .write_block(self.block_id, take(&mut buf, length))?;
+            crate::metrics::write_block(&mut self.dev, self.block_id, take(&mut buf, length))?;
             written += length;
 
             self.block_id += blocks as u64;
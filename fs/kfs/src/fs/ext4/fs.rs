@@ -4,16 +4,20 @@
 
 //! Ext4 filesystem adapter (rsext4 backend).
 use alloc::sync::Arc;
-use core::cell::OnceCell;
+use core::{
+    cell::OnceCell,
+    ops::{Deref, DerefMut},
+};
 
 use fs_ng_vfs::{
     DirEntry, DirNode, Filesystem, FilesystemOps, Reference, StatFs, VfsResult, path::MAX_NAME_LEN,
 };
-use kdriver::BlockDevice as KBlockDevice;
+use kalloc::{HeapTag, HeapTagGuard};
 use kspin::{SpinNoPreempt as Mutex, SpinNoPreemptGuard as MutexGuard};
 use rsext4::Jbd2Dev;
 
 use super::{Ext4Disk, Inode, util::into_vfs_err};
+use crate::{blkdev::Disk, fs::writeback::Writeback};
 
 const EXT4_ROOT_INO: u32 = 2;
 
@@ -39,9 +43,9 @@ pub struct Ext4Filesystem {
 
 impl Ext4Filesystem {
     /// Create a new ext4 filesystem instance backed by a block device.
-    pub fn new(dev: KBlockDevice) -> VfsResult<Filesystem> {
+    pub fn new(dev: Disk) -> VfsResult<Filesystem> {
         let mut dev = Jbd2Dev::initial_jbd2dev(0, Ext4Disk(dev), false);
-        let fs = rsext4::mount(&mut dev).map_err(into_vfs_err)?;
+        let fs = kalloc::heap_tagged!(FsCache, rsext4::mount(&mut dev)).map_err(into_vfs_err)?;
 
         let fs = Arc::new(Self {
             inner: Mutex::new(Ext4State { fs, dev }),
@@ -58,12 +62,63 @@ impl Ext4Filesystem {
             },
             Reference::root(),
         ));
+        crate::fs::writeback::register(Arc::downgrade(&fs) as _);
         Ok(Filesystem::new(fs))
     }
 
     /// Lock the inner ext4 filesystem state.
-    pub(crate) fn lock(&self) -> MutexGuard<'_, Ext4State> {
-        self.inner.lock()
+    ///
+    /// Heap allocations made while it is held, mostly by the rsext4 caches,
+    /// are charged to [`HeapTag::FsCache`].
+    pub(crate) fn lock(&self) -> Ext4Guard<'_> {
+        let state = self.inner.lock();
+        Ext4Guard {
+            state,
+            _tag: HeapTagGuard::new(HeapTag::FsCache),
+        }
+    }
+}
+
+/// Guard of the locked [`Ext4State`].
+pub(crate) struct Ext4Guard<'a> {
+    state: MutexGuard<'a, Ext4State>,
+    _tag: HeapTagGuard,
+}
+
+impl Deref for Ext4Guard<'_> {
+    type Target = Ext4State;
+
+    fn deref(&self) -> &Ext4State {
+        &self.state
+    }
+}
+
+impl DerefMut for Ext4Guard<'_> {
+    fn deref_mut(&mut self) -> &mut Ext4State {
+        &mut self.state
+    }
+}
+
+impl Writeback for Ext4Filesystem {
+    fn writeback(&self) {
+        let mut state = self.lock();
+        let (fs, dev) = state.split();
+        if let Err(err) = fs.writeback(dev) {
+            warn!("ext4: background writeback failed: {err:?}");
+        }
+    }
+
+    fn shrink(&self) {
+        self.lock().fs.shrink_caches();
+    }
+
+    fn reclaim(&self, bytes: usize, may_block: bool) -> usize {
+        if may_block {
+            return self.lock().fs.reclaim_caches(bytes);
+        }
+        self.inner
+            .try_lock()
+            .map_or(0, |mut state| state.fs.reclaim_caches(bytes))
     }
 }
 
@@ -81,20 +136,16 @@ impl FilesystemOps for Ext4Filesystem {
     }
 
     fn stat(&self) -> VfsResult<StatFs> {
-        let fs = self.lock();
-        let superblock = &fs.fs.superblock;
-        let block_size = superblock.block_size();
-        let blocks = superblock.blocks_count();
-        let blocks_free = superblock.free_blocks_count();
+        let stats = self.lock().fs.statfs();
         Ok(StatFs {
             fs_type: 0xef53,
-            block_size: block_size as _,
-            blocks,
-            blocks_free,
-            blocks_available: blocks_free,
+            block_size: stats.block_size as _,
+            blocks: stats.total_blocks,
+            blocks_free: stats.free_blocks,
+            blocks_available: stats.available_blocks,
 
-            file_count: superblock.s_inodes_count as _,
-            free_file_count: superblock.s_free_inodes_count as _,
+            file_count: stats.total_inodes as _,
+            free_file_count: stats.free_inodes as _,
 
             name_length: MAX_NAME_LEN as _,
             fragment_size: 0,
@@ -103,8 +154,9 @@ impl FilesystemOps for Ext4Filesystem {
     }
 
     fn flush(&self) -> VfsResult<()> {
-        let mut state = self.inner.lock();
+        let mut state = self.lock();
         let (fs, dev) = state.split();
+        rsext4::delalloc::flush_all(fs, dev).map_err(into_vfs_err)?;
         fs.inodetable_cahce.flush_all(dev).map_err(into_vfs_err)?;
         fs.datablock_cache.flush_all(dev).map_err(into_vfs_err)?;
         dev.cantflush().map_err(into_vfs_err)
@@ -178,7 +178,9 @@ impl NodeOps for Inode {
     }
 
     fn sync(&self, _data_only: bool) -> VfsResult<()> {
-        Ok(())
+        let mut state = self.fs.lock();
+        let (fs, dev) = state.split();
+        rsext4::delalloc::flush_inode(fs, dev, self.ino).map_err(into_vfs_err)
     }
 
     fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
@@ -251,7 +253,11 @@ impl FileNodeOps for Inode {
                 continue;
             }
 
-            if let Some(&phys) = extent_map.get(&(lbn as u32)) {
+            if let Some(data) = fs.delalloc.read_block(self.ino, lbn as u32) {
+                // 尚未分配物理块的延迟写入数据
+                buf[written..written + copy_len as usize]
+                    .copy_from_slice(&data[copy_start as usize..(copy_start + copy_len) as usize]);
+            } else if let Some(&phys) = extent_map.get(&(lbn as u32)) {
                 let cached = fs
                     .datablock_cache
                     .get_or_load(dev, phys)
@@ -364,7 +370,7 @@ impl FileNodeOps for Inode {
             let iblocks_used = used_datablocks.saturating_mul(BLOCK_SIZE as u64 / 512) as u32;
             inode.i_blocks_lo = iblocks_used;
             inode.l_i_blocks_high = 0;
-            rsext4::file::build_file_block_mapping(fs, &mut inode, &data_blocks, dev);
+            rsext4::file::build_file_block_mapping(fs, self.ino, &mut inode, &data_blocks, dev);
         }
 
         fs.modify_inode(dev, self.ino, |on_disk| {
@@ -10,16 +10,18 @@ mod util;
 pub use fs::*;
 pub use inode::*;
 #[allow(unused_imports)]
-use kdriver::{BlockDevice as KBlockDevice, prelude::BlockDriverOps};
+use kdriver::prelude::{BlockDriverOps, DriverError};
 use rsext4::{
     BlockDevice,
     error::{BlockDevError, BlockDevResult},
 };
 
+use crate::blkdev::Disk;
+
 const FS_BLOCK_SIZE: usize = rsext4::BLOCK_SIZE;
 
 /// Block device wrapper implementing the ext4 driver traits.
-pub(crate) struct Ext4Disk(KBlockDevice);
+pub(crate) struct Ext4Disk(Disk);
 
 impl BlockDevice for Ext4Disk {
     fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()> {
@@ -39,8 +41,7 @@ impl BlockDevice for Ext4Disk {
             });
         }
         let start_block = block_id as u64 * factor;
-        self.0
-            .write_block(start_block, &buffer[..required_size])
+        crate::metrics::write_block(&mut self.0, start_block, &buffer[..required_size])
             .map_err(|_| BlockDevError::WriteError)
     }
 
@@ -61,8 +62,7 @@ impl BlockDevice for Ext4Disk {
             });
         }
         let start_block = block_id as u64 * factor;
-        self.0
-            .read_block(start_block, &mut buffer[..required_size])
+        crate::metrics::read_block(&mut self.0, start_block, &mut buffer[..required_size])
             .map_err(|_| BlockDevError::ReadError)
     }
 
@@ -88,6 +88,18 @@ impl BlockDevice for Ext4Disk {
         self.0.flush().map_err(|_| BlockDevError::IoError)
     }
 
+    /// Discards the blocks on the disk, if it can.
+    fn discard(&mut self, block_id: u32, count: u32) -> BlockDevResult<()> {
+        let factor = (FS_BLOCK_SIZE / self.0.block_size()) as u64;
+        match self
+            .0
+            .discard(block_id as u64 * factor, count as u64 * factor)
+        {
+            Ok(()) | Err(DriverError::Unsupported) => Ok(()),
+            Err(_) => Err(BlockDevError::IoError),
+        }
+    }
+
     fn is_open(&self) -> bool {
         true
     }
@@ -37,7 +37,7 @@ pub fn into_vfs_err_mount(err: RSEXT4Error) -> VfsError {
     let linux_error = match err {
         RSEXT4Error::IoError => LinuxError::EIO,
         RSEXT4Error::InvalidMagic | RSEXT4Error::InvalidSuperblock => LinuxError::EINVAL,
-        RSEXT4Error::FilesystemHasErrors => LinuxError::EIO,
+        RSEXT4Error::FilesystemHasErrors | RSEXT4Error::ChecksumMismatch => LinuxError::EIO,
         RSEXT4Error::UnsupportedFeature => LinuxError::EOPNOTSUPP,
         RSEXT4Error::AlreadyMounted => LinuxError::EBUSY,
     };
@@ -7,14 +7,14 @@ use alloc::sync::Arc;
 use core::marker::PhantomPinned;
 
 use fs_ng_vfs::{
-    DirEntry, Filesystem, FilesystemOps, Reference, StatFs, VfsResult, path::MAX_NAME_LEN,
+    DirEntry, Filesystem, FilesystemOps, Reference, StatFs, VfsError, VfsResult, path::MAX_NAME_LEN,
 };
-use kdriver::BlockDevice as KBlockDevice;
+use kdriver::prelude::BlockDriverOps;
 use kspin::{SpinNoPreempt as Mutex, SpinNoPreemptGuard as MutexGuard};
 use slab::Slab;
 
 use super::{dir::FatDirNode, ff, util::into_vfs_err};
-use crate::disk::SeekableDisk;
+use crate::{blkdev::Disk, disk::SeekableDisk};
 
 /// Inner FAT filesystem state.
 pub struct FatFilesystemInner {
@@ -39,14 +39,16 @@ impl FatFilesystemInner {
 pub struct FatFilesystem {
     inner: Mutex<FatFilesystemInner>,
     root_dir: Mutex<Option<DirEntry>>,
+    /// The device, to write the queued requests through.
+    dev: Mutex<Disk>,
 }
 
 impl FatFilesystem {
     /// Create a new FAT filesystem instance backed by a block device.
-    pub fn new(dev: KBlockDevice) -> Filesystem {
+    pub fn new(dev: Disk) -> VfsResult<Filesystem> {
         let mut inner = FatFilesystemInner {
-            inner: ff::FileSystem::new(SeekableDisk::new(dev), fatfs::FsOptions::new())
-                .expect("failed to initialize FAT filesystem"),
+            inner: ff::FileSystem::new(SeekableDisk::new(dev.clone()), fatfs::FsOptions::new())
+                .map_err(into_vfs_err)?,
             inode_allocator: Slab::new(),
             _pinned: PhantomPinned,
         };
@@ -54,6 +56,7 @@ impl FatFilesystem {
         let result = Arc::new(Self {
             inner: Mutex::new(inner),
             root_dir: Mutex::default(),
+            dev: Mutex::new(dev),
         });
 
         let root_dir = DirEntry::new_dir(
@@ -68,7 +71,7 @@ impl FatFilesystem {
             Reference::root(),
         );
         *result.root_dir.lock() = Some(root_dir);
-        Filesystem::new(result)
+        Ok(Filesystem::new(result))
     }
 }
 
@@ -105,4 +108,8 @@ impl FilesystemOps for FatFilesystem {
             mount_flags: 0,
         })
     }
+
+    fn flush(&self) -> VfsResult<()> {
+        self.dev.lock().flush().map_err(|_| VfsError::Io)
+    }
 }
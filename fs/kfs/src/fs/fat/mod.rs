@@ -33,7 +33,7 @@ impl fatfs::Write for SeekableDisk {
     }
 
     fn flush(&mut self) -> Result<(), Self::Error> {
-        SeekableDisk::flush(self).map_err(|_| ())
+        SeekableDisk::sync(self).map_err(|_| ())
     }
 }
 
@@ -8,18 +8,28 @@ mod fat;
 
 #[cfg(feature = "ext4")]
 mod ext4;
+#[cfg(feature = "ext4")]
+mod writeback;
 
 use cfg_if::cfg_if;
 use fs_ng_vfs::{Filesystem, VfsResult};
-use kdriver::BlockDevice as KBlockDevice;
+
+use crate::blkdev::Disk;
+
+/// The type of the filesystems [`new_default`] creates.
+pub const FS_TYPE: &str = if cfg!(feature = "ext4") {
+    "ext4"
+} else {
+    "vfat"
+};
 
 /// Create the default filesystem instance for the given block device.
-pub fn new_default(_dev: KBlockDevice) -> VfsResult<Filesystem> {
+pub fn new_default(_dev: Disk) -> VfsResult<Filesystem> {
     cfg_if! {
         if #[cfg(feature = "ext4")] {
             ext4::Ext4Filesystem::new(_dev)
         } else if #[cfg(feature = "fat")] {
-            Ok(fat::FatFilesystem::new(_dev))
+            fat::FatFilesystem::new(_dev)
         } else {
             panic!("No filesystem feature enabled");
         }
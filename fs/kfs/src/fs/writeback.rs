@@ -0,0 +1,107 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Background writeback of the shared block buffer cache.
+//!
+//! Filesystems keeping blocks in a [`bcache::BufferCache`] register here.
+//! The `bcache-writeback` task then periodically writes their dirty buffers
+//! back, and makes them drop clean ones while the caches together are over
+//! their memory budget. A [`Shrinker`] also has them drop clean buffers
+//! when memory runs out.
+
+use alloc::{
+    string::ToString,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use kalloc::{ShrinkControl, Shrinker};
+use kspin::SpinNoPreempt as Mutex;
+
+/// How often dirty buffers are written back.
+const WRITEBACK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A filesystem with buffers for the `bcache-writeback` task.
+pub(crate) trait Writeback: Send + Sync {
+    /// Writes back the dirty buffers.
+    fn writeback(&self);
+
+    /// Drops clean buffers while the caches are over their budget.
+    fn shrink(&self);
+
+    /// Drops clean buffers regardless of the budget, about `bytes` of them,
+    /// without allocating. Returns the number of bytes dropped.
+    ///
+    /// Unless `may_block`, nothing is dropped if the filesystem is busy.
+    fn reclaim(&self, bytes: usize, may_block: bool) -> usize;
+}
+
+static FILESYSTEMS: Mutex<Vec<Weak<dyn Writeback>>> = Mutex::new(Vec::new());
+
+/// Has `fs` written back in the background for as long as it lives.
+pub(crate) fn register(fs: Weak<dyn Writeback>) {
+    FILESYSTEMS.lock().push(fs);
+
+    static STARTED: AtomicBool = AtomicBool::new(false);
+    if !STARTED.swap(true, Ordering::AcqRel) {
+        ktask::spawn_with_name(writeback_task, "bcache-writeback".to_string());
+        kalloc::register_shrinker(Arc::new(BufferShrinker));
+    }
+}
+
+fn alive() -> Vec<Arc<dyn Writeback>> {
+    let mut filesystems = FILESYSTEMS.lock();
+    filesystems.retain(|fs| fs.strong_count() > 0);
+    filesystems.iter().filter_map(Weak::upgrade).collect()
+}
+
+fn writeback_task() {
+    loop {
+        ktask::sleep(WRITEBACK_INTERVAL);
+        let filesystems = alive();
+        for fs in &filesystems {
+            fs.writeback();
+        }
+        if bcache::over_capacity() {
+            for fs in &filesystems {
+                fs.shrink();
+            }
+        }
+    }
+}
+
+/// Has the filesystems drop clean buffers when memory runs out.
+struct BufferShrinker;
+
+impl Shrinker for BufferShrinker {
+    fn name(&self) -> &'static str {
+        "bcache"
+    }
+
+    fn count(&self) -> usize {
+        bcache::usage().used
+    }
+
+    fn scan(&self, control: &ShrinkControl) -> usize {
+        let mut dropped = 0;
+        let mut reclaim = |fs: &dyn Writeback| {
+            if dropped < control.bytes {
+                dropped += fs.reclaim(control.bytes - dropped, control.may_block);
+            }
+        };
+        if control.may_block {
+            alive().iter().for_each(|fs| reclaim(fs.as_ref()));
+        } else if let Some(filesystems) = FILESYSTEMS.try_lock() {
+            // Without collecting them, which allocates
+            for fs in filesystems.iter().filter_map(Weak::upgrade) {
+                reclaim(fs.as_ref());
+            }
+        }
+        dropped
+    }
+}
@@ -9,7 +9,7 @@
 use alloc::{collections::vec_deque::VecDeque, string::String, vec::Vec};
 
 use fs_ng_vfs::{
-    Location, Metadata, NodePermission, NodeType, VfsResult,
+    Location, Metadata, NodePermission, NodeType, StatFs, VfsResult,
     path::{Path, PathBuf},
 };
 use kio::{Read, Write};
@@ -64,6 +64,38 @@ impl FsOperations {
         self.context.chdir(dir)
     }
 
+    /// Changes the root directory, keeping the current working directory
+    #[inline]
+    pub fn set_root_dir(&mut self, dir: Location) -> VfsResult<()> {
+        self.context.chroot(dir)
+    }
+
+    /// Returns the path of `loc` relative to the root directory, if reachable
+    #[inline]
+    pub fn path_of(&self, loc: &Location) -> Option<PathBuf> {
+        self.context.path_of(loc)
+    }
+
+    /// Returns the working context paths are resolved in
+    #[inline]
+    pub fn context(&self) -> &WorkingContext {
+        &self.context
+    }
+
+    /// Returns the resolver used for paths in this context
+    #[inline]
+    pub fn resolver(&self) -> &PathResolver {
+        &self.resolver
+    }
+
+    /// Limits how many symlinks a single path resolution may follow
+    ///
+    /// Resolving past the limit fails with `FilesystemLoop`.
+    #[inline]
+    pub fn set_max_symlinks(&mut self, max: usize) {
+        self.resolver = PathResolver::with_max_symlinks(max);
+    }
+
     /// Creates a new context with a different current working directory
     #[inline]
     pub fn with_current_dir(&self, current_dir: Location) -> VfsResult<Self> {
@@ -78,15 +110,13 @@ impl FsOperations {
     /// Resolves a path starting from current_dir
     #[inline]
     pub fn resolve(&self, path: impl AsRef<Path>) -> VfsResult<Location> {
-        self.resolver
-            .resolve(self.context.cwd(), path.as_ref(), true)
+        self.resolver.resolve(&self.context, path.as_ref(), true)
     }
 
     /// Resolves a path without following symlinks
     #[inline]
     pub fn resolve_no_follow(&self, path: impl AsRef<Path>) -> VfsResult<Location> {
-        self.resolver
-            .resolve(self.context.cwd(), path.as_ref(), false)
+        self.resolver.resolve(&self.context, path.as_ref(), false)
     }
 
     // ========== File Operations ==========
@@ -96,6 +126,11 @@ impl FsOperations {
         self.resolve(path)?.metadata()
     }
 
+    /// Retrieves the statistics of the filesystem holding the file
+    pub fn statfs(&self, path: impl AsRef<Path>) -> VfsResult<StatFs> {
+        self.resolve(path)?.filesystem().stat()
+    }
+
     /// Reads the entire contents of a file into a bytes vector
     pub fn read(&self, path: impl AsRef<Path>) -> VfsResult<Vec<u8>> {
         let mut buf = Vec::new();
@@ -157,12 +192,8 @@ impl FsOperations {
 
     /// Renames a file or directory to a new name
     pub fn rename(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> VfsResult<()> {
-        let (src_dir, src_name) = self
-            .resolver
-            .resolve_parent(self.context.cwd(), from.as_ref())?;
-        let (dst_dir, dst_name) = self
-            .resolver
-            .resolve_parent(self.context.cwd(), to.as_ref())?;
+        let (src_dir, src_name) = self.resolver.resolve_parent(&self.context, from.as_ref())?;
+        let (dst_dir, dst_name) = self.resolver.resolve_parent(&self.context, to.as_ref())?;
         src_dir.rename(&src_name, &dst_dir, &dst_name)
     }
 
@@ -170,7 +201,7 @@ impl FsOperations {
     pub fn create_dir(&self, path: impl AsRef<Path>, mode: NodePermission) -> VfsResult<Location> {
         let (dir, name) = self
             .resolver
-            .resolve_nonexistent(self.context.cwd(), path.as_ref())?;
+            .resolve_nonexistent(&self.context, path.as_ref())?;
         dir.create(name, NodeType::Directory, mode)
     }
 
@@ -183,7 +214,7 @@ impl FsOperations {
         let old = self.resolve(old_path.as_ref())?;
         let (new_dir, new_name) = self
             .resolver
-            .resolve_nonexistent(self.context.cwd(), new_path.as_ref())?;
+            .resolve_nonexistent(&self.context, new_path.as_ref())?;
         new_dir.link(new_name, &old)
     }
 
@@ -195,7 +226,7 @@ impl FsOperations {
     ) -> VfsResult<Location> {
         let (dir, name) = self
             .resolver
-            .resolve_nonexistent(self.context.cwd(), link_path.as_ref())?;
+            .resolve_nonexistent(&self.context, link_path.as_ref())?;
         if dir.lookup_no_follow(name).is_ok() {
             return Err(fs_ng_vfs::VfsError::AlreadyExists);
         }
@@ -204,9 +235,13 @@ impl FsOperations {
         Ok(symlink)
     }
 
-    /// Returns the canonical, absolute form of a path
+    /// Returns the canonical, absolute form of a path, as seen from the root
     pub fn canonicalize(&self, path: impl AsRef<Path>) -> VfsResult<PathBuf> {
-        self.resolve(path.as_ref())?.absolute_path()
+        let loc = self.resolve(path.as_ref())?;
+        match self.context.path_of(&loc) {
+            Some(path) => Ok(path),
+            None => loc.absolute_path(),
+        }
     }
 }
 
@@ -5,6 +5,7 @@
 //! File abstraction and caching layer.
 use alloc::{
     boxed::Box,
+    collections::BTreeMap,
     sync::{Arc, Weak},
     vec::Vec,
 };
@@ -13,13 +14,17 @@ use core::sync::atomic::{AtomicU8, Ordering};
 use core::{num::NonZeroUsize, ops::Range, task::Context};
 
 use fs_ng_vfs::{
-    FileNode, Location, NodeFlags, NodePermission, NodeType, VfsError, VfsResult, path::Path,
+    FileNode, Location, NodeFlags, NodePermission, NodeType, VfsError, VfsResult, path::Path, watch,
 };
 use intrusive_collections::{LinkedList, LinkedListAtomicLink, intrusive_adapter};
-use kalloc::{UsageKind, global_allocator};
-use khal::mem::{PhysAddr, VirtAddr, v2p};
+use kalloc::{Migrator, UsageKind, global_allocator};
+use khal::{
+    mem::{PhysAddr, VirtAddr, v2p},
+    time::monotonic_time,
+};
 use kio::{SeekFrom, prelude::*};
 use kpoll::{IoEvents, Pollable};
+use kspin::SpinNoIrq;
 use ksync::{Mutex, RwLock};
 use lru::LruCache;
 
@@ -250,10 +255,24 @@ impl OpenOptions {
                         user: self.user,
                     },
                 )?;
-                if !self.no_follow {
-                    context.resolve(path)?
-                } else {
+                if loc.node_type() != NodeType::Symlink {
+                    loc
+                } else if self.no_follow {
+                    // Like O_NOFOLLOW, refuse a trailing symlink unless
+                    // only the path itself is wanted.
+                    if !self.path {
+                        return Err(VfsError::FilesystemLoop);
+                    }
                     loc
+                } else {
+                    match context.resolve(path.as_ref()) {
+                        Err(VfsError::NotFound) if self.create => {
+                            // A dangling symlink: create what it points to.
+                            let context = context.with_current_dir(parent)?;
+                            return self.open(&context, loc.read_link()?);
+                        }
+                        result => result?,
+                    }
                 }
             }
             Err(VfsError::InvalidInput) => {
@@ -309,6 +328,12 @@ impl Default for OpenOptions {
 
 const PAGE_SIZE: usize = 4096;
 
+/// Initial readahead window once sequential access is detected, in pages.
+const READAHEAD_MIN_PAGES: u32 = 4;
+/// Upper bound of the readahead window; must stay well below the page cache
+/// capacity so prefetching does not evict the pages being read.
+const READAHEAD_MAX_PAGES: u32 = 32;
+
 #[derive(Debug)]
 pub struct PageCache {
     addr: VirtAddr,
@@ -350,10 +375,66 @@ impl Drop for PageCache {
         if self.dirty {
             warn!("dirty page dropped without flushing");
         }
+        if global_allocator().is_cma(self.addr.as_usize()) {
+            CMA_PAGES.lock().remove(&self.addr.as_usize());
+        }
         global_allocator().dealloc_pages(self.addr.as_usize(), 1, UsageKind::PageCache);
     }
 }
 
+/// The cached pages lent from the CMA pool, by address, with the file and
+/// page number they cache.
+static CMA_PAGES: SpinNoIrq<BTreeMap<usize, (Weak<CachedFileShared>, u32)>> =
+    SpinNoIrq::new(BTreeMap::new());
+
+/// Moves cached pages out of the CMA pool.
+///
+/// Only the pages of the files not mapped can move, as a mapping could
+/// still use the old page after failing to be unmapped.
+struct PageCacheMigrator;
+
+impl Migrator for PageCacheMigrator {
+    unsafe fn migrate(&self, from: usize, to: usize) -> bool {
+        let Some((shared, pn)) = CMA_PAGES
+            .lock()
+            .get(&from)
+            .and_then(|(shared, pn)| Some((shared.upgrade()?, *pn)))
+        else {
+            return false;
+        };
+        // Held until the page moved, for no mapping to be made meanwhile
+        let Some(listeners) = shared.evict_listeners.try_lock() else {
+            return false;
+        };
+        if !listeners.is_empty() {
+            return false;
+        }
+        let Some(mut cache) = shared.page_cache.try_lock() else {
+            return false;
+        };
+        let Some(page) = cache
+            .peek_mut(&pn)
+            .filter(|page| page.addr.as_usize() == from)
+        else {
+            return false;
+        };
+        unsafe { core::ptr::copy_nonoverlapping(from as *const u8, to as *mut u8, PAGE_SIZE) };
+        page.addr = to.into();
+
+        let mut cma_pages = CMA_PAGES.lock();
+        cma_pages.remove(&from);
+        if global_allocator().is_cma(to) {
+            cma_pages.insert(to, (Arc::downgrade(&shared), pn));
+        }
+        true
+    }
+}
+
+/// Lets the page cache borrow pages from the CMA pool.
+pub(crate) fn register_page_cache_migrator() {
+    kalloc::register_migrator(UsageKind::PageCache, Arc::new(PageCacheMigrator));
+}
+
 type EvictListenerFn = dyn Fn(u32, &PageCache) + Send + Sync;
 
 struct EvictListener {
@@ -384,6 +465,19 @@ impl CachedFileShared {
     }
 }
 
+/// Readahead state of an opened file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadaheadInfo {
+    /// Byte offset right after the previous read.
+    pub prev_end: u64,
+    /// Current readahead window in pages, zero for non-sequential access.
+    pub window: u32,
+    /// Pages below this one have already been read ahead.
+    pub ahead_until: u32,
+    /// Total number of pages read ahead.
+    pub total_pages: u64,
+}
+
 pub struct CachedFile {
     inner: Location,
     shared: Arc<CachedFileShared>,
@@ -391,6 +485,8 @@ pub struct CachedFile {
     /// Only one thread can append to the file at a time, while multiple writers
     /// are permitted.
     append_lock: RwLock<()>,
+    /// Readahead is tracked per open file, like the file position.
+    readahead: Mutex<ReadaheadInfo>,
 }
 
 impl Clone for CachedFile {
@@ -400,6 +496,7 @@ impl Clone for CachedFile {
             shared: self.shared.clone(),
             in_memory: self.in_memory,
             append_lock: RwLock::new(()),
+            readahead: Mutex::default(),
         }
     }
 }
@@ -444,6 +541,7 @@ impl CachedFile {
             shared,
             in_memory,
             append_lock: RwLock::new(()),
+            readahead: Mutex::default(),
         }
     }
 
@@ -485,6 +583,11 @@ impl CachedFile {
         for listener in self.shared.evict_listeners.lock().iter() {
             (listener.listener)(pn, page);
         }
+        self.write_back(file, pn, page)
+    }
+
+    /// Writes the page back to the file if it is dirty, up to the file end.
+    fn write_back(&self, file: &FileNode, pn: u32, page: &mut PageCache) -> VfsResult<()> {
         if page.dirty {
             let page_start = pn as u64 * PAGE_SIZE as u64;
             let len = (file.len()?.saturating_sub(page_start)).min(PAGE_SIZE as u64) as usize;
@@ -504,9 +607,20 @@ impl CachedFile {
     ) -> VfsResult<(&'a mut PageCache, Option<(u32, PageCache)>)> {
         // TODO: Matching the result of `get_mut` confuses compiler. See
         // https://users.rust-lang.org/t/return-do-not-release-mutable-borrow/55757.
-        if cache.contains(&pn) {
+        let hit = cache.contains(&pn);
+        self.inner.mountpoint().stats().record_page_access(hit);
+        if hit {
             return Ok((cache.get_mut(&pn).unwrap(), None));
         }
+        self.load_page(file, cache, pn)
+    }
+
+    fn load_page<'a>(
+        &self,
+        file: &FileNode,
+        cache: &'a mut LruCache<u32, PageCache>,
+        pn: u32,
+    ) -> VfsResult<(&'a mut PageCache, Option<(u32, PageCache)>)> {
         let mut evicted = None;
         if cache.len() == cache.cap().get() {
             // Cache is full, remove the least recently used page
@@ -523,7 +637,13 @@ impl CachedFile {
         } else {
             file.read_at(page.data(), pn as u64 * PAGE_SIZE as u64)?;
         }
+        let addr = page.addr.as_usize();
         cache.put(pn, page);
+        if global_allocator().is_cma(addr) {
+            CMA_PAGES
+                .lock()
+                .insert(addr, (Arc::downgrade(&self.shared), pn));
+        }
         Ok((cache.get_mut(&pn).unwrap(), evicted))
     }
 
@@ -569,13 +689,62 @@ impl CachedFile {
         Ok(initial)
     }
 
+    /// Reads pages following a sequential read into the cache.
+    ///
+    /// The window starts at [`READAHEAD_MIN_PAGES`] once a read continues
+    /// where the previous one ended and doubles on every further sequential
+    /// read; any other access pattern resets it.
+    fn readahead(&self, range: Range<u64>, file_len: u64) -> VfsResult<()> {
+        let mut ra = self.readahead.lock();
+        if range.start == ra.prev_end && range.start != 0 {
+            ra.window = (ra.window * 2).clamp(READAHEAD_MIN_PAGES, READAHEAD_MAX_PAGES);
+        } else {
+            ra.window = 0;
+            ra.ahead_until = 0;
+        }
+        ra.prev_end = range.end;
+        if ra.window == 0 {
+            return Ok(());
+        }
+
+        // Keep `window` pages ahead of the reader, only loading what is not
+        // covered by earlier readahead
+        let last_page = file_len.div_ceil(PAGE_SIZE as u64) as u32;
+        let next_page = range.end.div_ceil(PAGE_SIZE as u64) as u32;
+        let start = next_page.max(ra.ahead_until);
+        let end = (next_page + ra.window).min(last_page);
+        if start >= end {
+            return Ok(());
+        }
+
+        let file = self.inner.entry().as_file()?;
+        let mut loaded = 0;
+        let mut guard = self.shared.page_cache.lock();
+        for pn in start..end {
+            if !guard.contains(&pn) {
+                self.load_page(file, &mut guard, pn)?;
+                loaded += 1;
+            }
+        }
+        drop(guard);
+        ra.ahead_until = end;
+        ra.total_pages += loaded;
+        self.inner.mountpoint().stats().record_readahead(loaded);
+        Ok(())
+    }
+
+    /// Returns the readahead state of this opened file.
+    pub fn readahead_info(&self) -> ReadaheadInfo {
+        *self.readahead.lock()
+    }
+
     pub fn read_at(&self, mut dst: impl Write + IoBufMut, offset: u64) -> VfsResult<usize> {
         let len = self.inner.len()?;
         let end = (offset + dst.remaining_mut() as u64).min(len);
         if end <= offset {
             return Ok(0);
         }
-        self.with_pages(
+        let read = self.with_pages(
             offset..end,
             |_| Ok(0),
             |read, page, range| {
@@ -583,7 +752,11 @@ impl CachedFile {
                 dst.write(&page.data()[range.start..range.end])?;
                 Ok(read + len)
             },
-        )
+        )?;
+        if !self.in_memory {
+            self.readahead(offset..end, len)?;
+        }
+        Ok(read)
     }
 
     fn write_at_locked(&self, mut buf: impl Read + IoBuf, offset: u64) -> VfsResult<usize> {
@@ -671,6 +844,26 @@ impl CachedFile {
         Ok(())
     }
 
+    /// Writes the dirty cached pages in `pages` back to the file, keeping
+    /// them cached.
+    ///
+    /// Used by `msync(2)` and when unmapping shared file mappings, whose
+    /// pages may stay mapped elsewhere.
+    pub fn write_back_pages(&self, pages: Range<u32>) -> VfsResult<()> {
+        if self.in_memory {
+            return Ok(());
+        }
+        let file = self.inner.entry().as_file()?;
+        let mut guard = self.shared.page_cache.lock();
+        for pn in pages {
+            // `peek_mut` leaves the LRU order alone
+            if let Some(page) = guard.peek_mut(&pn) {
+                self.write_back(file, pn, page)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn location(&self) -> &Location {
         &self.inner
     }
@@ -706,18 +899,27 @@ impl FileBackend {
     }
 
     pub fn read_at(&self, mut dst: impl Write + IoBufMut, mut offset: u64) -> VfsResult<usize> {
-        match self {
+        let start = monotonic_time();
+        let result = match self {
             Self::Cached(cached) => cached.read_at(dst, offset),
             Self::Direct(loc) => dst.read_from(&mut kio::read_fn(|buf| {
                 loc.entry().as_file()?.read_at(buf, offset).inspect(|read| {
                     offset += *read as u64;
                 })
             })),
+        };
+        if let Ok(read) = result {
+            self.location()
+                .mountpoint()
+                .stats()
+                .record_read(read, monotonic_time().saturating_sub(start));
         }
+        result
     }
 
     pub fn write_at(&self, mut src: impl Read + IoBuf, mut offset: u64) -> VfsResult<usize> {
-        match self {
+        let start = monotonic_time();
+        let result = match self {
             Self::Cached(cached) => cached.write_at(src, offset),
             Self::Direct(loc) => src.write_to(&mut kio::write_fn(|buf| {
                 loc.entry()
@@ -727,11 +929,20 @@ impl FileBackend {
                         offset += *written as u64;
                     })
             })),
+        };
+        if let Ok(written) = result {
+            self.location()
+                .mountpoint()
+                .stats()
+                .record_write(written, monotonic_time().saturating_sub(start));
+            watch::notify_modify(self.location());
         }
+        result
     }
 
     pub fn append(&self, mut src: impl Read + IoBuf) -> VfsResult<(usize, u64)> {
-        match self {
+        let start = monotonic_time();
+        let result = match self {
             Self::Cached(cached) => cached.append(src),
             Self::Direct(loc) => {
                 let mut end = 0;
@@ -743,6 +954,22 @@ impl FileBackend {
                 }))
                 .map(|n| (n, end))
             }
+        };
+        if let Ok((written, _)) = result {
+            self.location()
+                .mountpoint()
+                .stats()
+                .record_write(written, monotonic_time().saturating_sub(start));
+            watch::notify_modify(self.location());
+        }
+        result
+    }
+
+    /// Returns the readahead state if the file goes through the page cache.
+    pub fn readahead_info(&self) -> Option<ReadaheadInfo> {
+        match self {
+            Self::Cached(cached) => Some(cached.readahead_info()),
+            Self::Direct(_) => None,
         }
     }
 
@@ -765,6 +992,7 @@ impl FileBackend {
             Self::Cached(cached) => cached.set_len(len),
             Self::Direct(loc) => loc.entry().as_file()?.set_len(len),
         }
+        .inspect(|_| watch::notify_modify(self.location()))
     }
 }
 
@@ -838,6 +1066,11 @@ impl File {
         self.inner.location()
     }
 
+    /// Returns the current file position, or `None` for stream files.
+    pub fn position(&self) -> Option<u64> {
+        self.position.as_ref().map(|pos| *pos.lock())
+    }
+
     /// Reads a number of bytes starting from a given offset.
     pub fn read_at(&self, dst: impl Write + IoBufMut, offset: u64) -> VfsResult<usize> {
         self.access(FileFlags::READ)?.read_at(dst, offset)
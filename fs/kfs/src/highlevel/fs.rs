@@ -12,14 +12,12 @@ use alloc::{
 };
 
 use fs_ng_vfs::{
-    Location, Metadata, NodePermission, NodeType, VfsError, VfsResult,
+    Location, Metadata, NodePermission, NodeType, StatFs, VfsError, VfsResult,
     path::{Path, PathBuf},
 };
 use ksync::Mutex;
 use ktypes::Once;
 
-use crate::PathResolver;
-
 #[allow(dead_code)]
 /// Maximum symlink follow depth for legacy APIs.
 pub const SYMLINKS_MAX: usize = 40;
@@ -90,6 +88,27 @@ impl FsContext {
         self.inner.set_current_dir(current_dir)
     }
 
+    /// Change the root directory, as `chroot(2)` does.
+    ///
+    /// The current directory is kept; `..` never leaves the new root.
+    pub fn set_root_dir(&mut self, root_dir: Location) -> VfsResult<()> {
+        self.inner.set_root_dir(root_dir)
+    }
+
+    /// Returns the path of `loc` as seen from the root directory.
+    ///
+    /// Returns `None` if `loc` lies outside the root directory.
+    pub fn path_of(&self, loc: &Location) -> Option<PathBuf> {
+        self.inner.path_of(loc)
+    }
+
+    /// Limit how many symlinks a single path resolution may follow.
+    ///
+    /// Resolving past the limit fails with `FilesystemLoop` (`ELOOP`).
+    pub fn set_max_symlinks(&mut self, max: usize) {
+        self.inner.set_max_symlinks(max)
+    }
+
     /// Create a new context with a different current directory.
     pub fn with_current_dir(&self, current_dir: Location) -> VfsResult<Self> {
         Ok(Self {
@@ -111,8 +130,10 @@ impl FsContext {
     /// Resolve a path to its parent directory and entry name.
     pub fn resolve_parent<'a>(&self, path: &'a Path) -> VfsResult<(Location, Cow<'a, str>)> {
         // Use inner resolver but convert String to Cow
-        let resolver = PathResolver::new();
-        let (dir, name) = resolver.resolve_parent(self.inner.current_dir(), path)?;
+        let (dir, name) = self
+            .inner
+            .resolver()
+            .resolve_parent(self.inner.context(), path)?;
         Ok((dir, Cow::Owned(name)))
     }
 
@@ -125,9 +146,11 @@ impl FsContext {
         let mut components = path.components();
         components.next_back();
 
-        let resolver = PathResolver::new();
-        let dir =
-            resolver.resolve_components_internal(self.inner.current_dir(), components, &mut 0)?;
+        let dir = self.inner.resolver().resolve_components_internal(
+            self.inner.context(),
+            components,
+            &mut 0,
+        )?;
         dir.check_is_dir()?;
         Ok((dir, entry_name))
     }
@@ -137,6 +160,11 @@ impl FsContext {
         self.inner.metadata(path)
     }
 
+    /// Retrieves the capacity and usage of the filesystem holding the file.
+    pub fn statfs(&self, path: impl AsRef<Path>) -> VfsResult<StatFs> {
+        self.inner.statfs(path)
+    }
+
     /// Reads the entire contents of a file into a bytes vector.
     pub fn read(&self, path: impl AsRef<Path>) -> VfsResult<Vec<u8>> {
         self.inner.read(path)
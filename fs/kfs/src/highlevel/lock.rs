@@ -0,0 +1,278 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Advisory file locks for `flock(2)` and `fcntl(2)` record locking.
+//!
+//! Locks are kept per inode in the user data of its directory entry, so every
+//! open file description of the same file sees the same records. `flock` and
+//! `fcntl` locks live in separate tables and never conflict with each other,
+//! as on Linux.
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{future::poll_fn, ops::Range, task::Poll};
+
+use fs_ng_vfs::{Location, VfsError, VfsResult};
+use kerrno::LinuxError;
+use kpoll::PollSet;
+use ksync::Mutex;
+use ktask::future::{block_on, interruptible};
+
+/// Kind of an advisory lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+    /// A read lock, which may be held by several owners at once.
+    Shared,
+    /// A write lock, which excludes every other owner.
+    Exclusive,
+}
+
+/// Holder of an advisory lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LockOwner {
+    /// An open file description, for `flock` and OFD locks.
+    File(usize),
+    /// A process, for traditional POSIX record locks.
+    Process(u32),
+}
+
+/// A lock held on a byte range of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLock {
+    /// Kind of the lock.
+    pub kind: LockKind,
+    /// Locked byte range; an end of `u64::MAX` extends to the end of file.
+    pub range: Range<u64>,
+    /// Holder of the lock.
+    pub owner: LockOwner,
+    /// Process that placed the lock, as reported by `F_GETLK`.
+    pub pid: u32,
+}
+
+impl FileLock {
+    fn overlaps(&self, range: &Range<u64>) -> bool {
+        self.range.start < range.end && range.start < self.range.end
+    }
+
+    fn conflicts(&self, owner: LockOwner, kind: LockKind, range: &Range<u64>) -> bool {
+        self.owner != owner
+            && self.overlaps(range)
+            && (kind == LockKind::Exclusive || self.kind == LockKind::Exclusive)
+    }
+}
+
+/// Processes blocked on a POSIX lock, keyed by the waiting process and
+/// pointing at the process holding the conflicting lock.
+static BLOCKED_ON: Mutex<BTreeMap<u32, u32>> = Mutex::new(BTreeMap::new());
+
+/// Records that `waiter` waits for `holder`, unless that closes a cycle.
+fn wait_for(waiter: u32, holder: u32) -> VfsResult<()> {
+    let mut blocked = BLOCKED_ON.lock();
+    let mut next = holder;
+    // A cycle longer than the number of blocked processes is impossible, the
+    // bound only guards against a stale entry pointing back into the chain.
+    for _ in 0..=blocked.len() {
+        if next == waiter {
+            blocked.remove(&waiter);
+            return Err(VfsError::from(LinuxError::EDEADLK));
+        }
+        match blocked.get(&next) {
+            Some(&it) => next = it,
+            None => break,
+        }
+    }
+    blocked.insert(waiter, holder);
+    Ok(())
+}
+
+fn stop_waiting(owner: LockOwner) {
+    if let LockOwner::Process(pid) = owner {
+        BLOCKED_ON.lock().remove(&pid);
+    }
+}
+
+#[derive(Default)]
+struct LockTable {
+    locks: Mutex<Vec<FileLock>>,
+    waiters: PollSet,
+}
+
+impl LockTable {
+    fn conflict(&self, owner: LockOwner, kind: LockKind, range: &Range<u64>) -> Option<FileLock> {
+        self.locks
+            .lock()
+            .iter()
+            .find(|it| it.conflicts(owner, kind, range))
+            .cloned()
+    }
+
+    /// Places `lock` unless another owner holds a conflicting one. Returns
+    /// whether some of the owner's previous locks were dropped or downgraded.
+    fn try_set(locks: &mut Vec<FileLock>, lock: FileLock) -> Result<bool, FileLock> {
+        if let Some(conflict) = locks
+            .iter()
+            .find(|it| it.conflicts(lock.owner, lock.kind, &lock.range))
+        {
+            return Err(conflict.clone());
+        }
+        let changed = Self::remove_range(locks, lock.owner, &lock.range);
+
+        // Coalesce with adjacent locks of the same kind, as POSIX does.
+        let mut lock = lock;
+        locks.retain(|it| {
+            let adjacent = it.owner == lock.owner
+                && it.kind == lock.kind
+                && (it.range.end == lock.range.start || lock.range.end == it.range.start);
+            if adjacent {
+                lock.range = lock.range.start.min(it.range.start)..lock.range.end.max(it.range.end);
+            }
+            !adjacent
+        });
+        locks.push(lock);
+        Ok(changed)
+    }
+
+    /// Drops the part of `owner`'s locks inside `range`, splitting the locks
+    /// straddling its boundaries. Returns whether anything changed.
+    fn remove_range(locks: &mut Vec<FileLock>, owner: LockOwner, range: &Range<u64>) -> bool {
+        let mut changed = false;
+        let mut rest = Vec::new();
+        locks.retain(|it| {
+            if it.owner != owner || !it.overlaps(range) {
+                return true;
+            }
+            changed = true;
+            if it.range.start < range.start {
+                rest.push(FileLock {
+                    range: it.range.start..range.start,
+                    ..it.clone()
+                });
+            }
+            if range.end < it.range.end {
+                rest.push(FileLock {
+                    range: range.end..it.range.end,
+                    ..it.clone()
+                });
+            }
+            false
+        });
+        locks.extend(rest);
+        changed
+    }
+
+    fn lock(&self, lock: FileLock, wait: bool) -> VfsResult<()> {
+        let changed = if wait {
+            self.wait_set(lock)?
+        } else {
+            Self::try_set(&mut self.locks.lock(), lock).map_err(|_| VfsError::WouldBlock)?
+        };
+        if changed {
+            self.waiters.wake();
+        }
+        Ok(())
+    }
+
+    fn wait_set(&self, lock: FileLock) -> VfsResult<bool> {
+        let owner = lock.owner;
+        let result = block_on(interruptible(poll_fn(|cx| {
+            let mut locks = self.locks.lock();
+            match Self::try_set(&mut locks, lock.clone()) {
+                Ok(changed) => Poll::Ready(Ok(changed)),
+                Err(conflict) => {
+                    if let (LockOwner::Process(waiter), LockOwner::Process(holder)) =
+                        (lock.owner, conflict.owner)
+                        && let Err(err) = wait_for(waiter, holder)
+                    {
+                        return Poll::Ready(Err(err));
+                    }
+                    self.waiters.register(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })));
+        stop_waiting(owner);
+        result?
+    }
+
+    fn unlock(&self, owner: LockOwner, range: &Range<u64>) {
+        if Self::remove_range(&mut self.locks.lock(), owner, range) {
+            self.waiters.wake();
+        }
+    }
+}
+
+/// The advisory locks placed on one file.
+#[derive(Default)]
+pub struct FileLocks {
+    flock: LockTable,
+    posix: LockTable,
+}
+
+impl FileLocks {
+    /// Returns the lock records of the file at `location`.
+    pub fn of(location: &Location) -> Arc<Self> {
+        location.user_data().get_or_insert_with(Self::default)
+    }
+
+    /// Returns the lock records of the file at `location`, if any lock was
+    /// ever placed on it.
+    pub fn get(location: &Location) -> Option<Arc<Self>> {
+        location.user_data().get()
+    }
+
+    /// Places a whole-file `flock` lock for `owner`, or removes it when `kind`
+    /// is `None`.
+    ///
+    /// Like Linux, converting an existing lock is not atomic: the old lock is
+    /// dropped before waiting for the new one.
+    pub fn flock(
+        &self,
+        owner: LockOwner,
+        pid: u32,
+        kind: Option<LockKind>,
+        wait: bool,
+    ) -> VfsResult<()> {
+        self.flock.unlock(owner, &(0..u64::MAX));
+        match kind {
+            Some(kind) => self.flock.lock(
+                FileLock {
+                    kind,
+                    range: 0..u64::MAX,
+                    owner,
+                    pid,
+                },
+                wait,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Places a record lock, waiting for conflicting locks to go away if
+    /// `wait` is set.
+    ///
+    /// Fails with `EDEADLK` if waiting would deadlock two processes.
+    pub fn set_record(&self, lock: FileLock, wait: bool) -> VfsResult<()> {
+        self.posix.lock(lock, wait)
+    }
+
+    /// Removes `owner`'s record locks inside `range`.
+    pub fn clear_record(&self, owner: LockOwner, range: Range<u64>) {
+        self.posix.unlock(owner, &range);
+    }
+
+    /// Returns a record lock that would block `owner` from placing a lock of
+    /// `kind` on `range`, for `F_GETLK`.
+    pub fn test_record(
+        &self,
+        owner: LockOwner,
+        kind: LockKind,
+        range: Range<u64>,
+    ) -> Option<FileLock> {
+        self.posix.conflict(owner, kind, &range)
+    }
+
+    /// Removes every lock held by `owner`.
+    pub fn release(&self, owner: LockOwner) {
+        self.flock.unlock(owner, &(0..u64::MAX));
+        self.posix.unlock(owner, &(0..u64::MAX));
+    }
+}
@@ -13,11 +13,15 @@ extern crate alloc;
 #[macro_use]
 extern crate log;
 
+mod test_file_lock;
 mod test_path_resolver;
 mod test_working_context;
 
+use fs_ng_vfs::VfsResult;
 use kdriver::{BlockDevice as KBlockDevice, DeviceContainer, prelude::*};
 
+mod blkdev;
+mod dcache;
 #[cfg(feature = "fat")]
 mod disk;
 #[cfg_attr(test, allow(dead_code))]
@@ -25,10 +29,16 @@ pub(crate) mod fs;
 
 // New refactored components
 mod fs_operations;
+mod loopdev;
+mod metrics;
 mod path_resolver;
 mod working_context;
 
 mod highlevel;
+pub use blkdev::{
+    BlockDeviceInfo, attach_loop, block_devices, detach_loop, flush_block_devices,
+    mount_block_device, read_blocks, reset_block_devices, set_io_policy, write_blocks,
+};
 // Export new components (FsOperations for advanced use)
 pub use fs_operations::FsOperations;
 pub use highlevel::*;
@@ -36,28 +46,62 @@ pub use path_resolver::PathResolver;
 pub use working_context::WorkingContext;
 
 /// Initialize the filesystem subsystem and mount the root filesystem.
+///
+/// The disks and the partitions on them are registered as block devices,
+/// see [`block_devices`]. The root filesystem is on the last disk, on the
+/// first partition meant for it if there is a partition table.
 pub fn init_filesystems(mut block_devs: DeviceContainer<KBlockDevice>) {
     info!("Initialize filesystem subsystem...");
+    metrics::register();
+    dcache::register();
+    highlevel::register_page_cache_migrator();
 
-    let dev = {
+    let disks = {
         #[cfg(feature = "crosvm")]
         {
             // must have two block devices: secure and non-secure
             // we only use the second blk
-            block_devs
-                .take_nth(1)
-                .expect("Less than two block devices found!")
+            alloc::vec![
+                block_devs
+                    .take_nth(1)
+                    .expect("Less than two block devices found!"),
+            ]
         }
         #[cfg(not(feature = "crosvm"))]
         {
-            block_devs.take_one().expect("No block device found!")
+            block_devs.drain(..).collect::<alloc::vec::Vec<_>>()
         }
     };
-    info!("  use block device 0: {:?}", dev.name());
+    let mut root = None;
+    for (index, dev) in disks.into_iter().enumerate() {
+        let name = blkdev::disk_name(index);
+        info!("  use block device {}: {:?}", name, dev.name());
+        let partitions = blkdev::register_disk(name.clone(), blkdev::Storage::Device(dev));
+        root = Some(blkdev::root_device(&name, &partitions));
+    }
+    let root = root.expect("No block device found!");
 
-    let fs = fs::new_default(dev).expect("Failed to initialize filesystem");
-    info!("  filesystem type: {:?}", fs.name());
+    let fs = mount_block_device(&root, fs::FS_TYPE).expect("Failed to initialize filesystem");
+    info!("  root device: {}, filesystem type: {:?}", root, fs.name());
 
-    let mp = fs_ng_vfs::Mountpoint::new_root(&fs);
+    let mp = fs_ng_vfs::Mountpoint::new_root_from(&fs, &alloc::format!("/dev/{root}"));
     ROOT_FS_CONTEXT.call_once(|| FsContext::new(mp.root_location()));
 }
+
+/// Flushes the filesystems mounted under the root, e.g. before a shutdown.
+///
+/// All of them are flushed even if some fail; the first failure is
+/// returned.
+pub fn sync_filesystems() -> VfsResult<()> {
+    let Some(ctx) = ROOT_FS_CONTEXT.get() else {
+        return Ok(());
+    };
+    let mut result = Ok(());
+    for mp in ctx.root_dir().mountpoint().mount_table() {
+        if let Err(err) = mp.root_location().filesystem().flush() {
+            warn!("{}: failed to flush: {:?}", mp.source(), err);
+            result = result.and(Err(err));
+        }
+    }
+    result
+}
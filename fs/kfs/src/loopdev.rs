@@ -0,0 +1,114 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Loop devices: files on a mounted filesystem as block devices.
+//!
+//! [`attach_loop`] registers a file as a disk like the others, so that the
+//! filesystem image it holds, or those on its partitions, can be mounted
+//! with [`mount_block_device`].
+//!
+//! [`attach_loop`]: crate::attach_loop
+//! [`mount_block_device`]: crate::mount_block_device
+
+use fs_ng_vfs::VfsResult;
+use kdriver::prelude::*;
+
+use crate::highlevel::FileBackend;
+
+/// Block size of loop devices, that of Linux.
+const BLOCK_SIZE: usize = 512;
+
+/// A block device reading and writing a file.
+///
+/// Its size is that of the file when attached, rounded down to whole
+/// blocks.
+pub(crate) struct LoopDisk {
+    file: FileBackend,
+    num_blocks: u64,
+    read_only: bool,
+}
+
+impl LoopDisk {
+    /// Creates a device over `file`, failing writes with
+    /// [`DriverError::Unsupported`] if `read_only`.
+    pub(crate) fn new(file: FileBackend, read_only: bool) -> VfsResult<Self> {
+        let num_blocks = file.location().len()? / BLOCK_SIZE as u64;
+        Ok(Self {
+            file,
+            num_blocks,
+            read_only,
+        })
+    }
+
+    /// Returns the offset in the file of `len` bytes from `block_id`.
+    fn offset(&self, block_id: u64, len: usize) -> DriverResult<u64> {
+        if !len.is_multiple_of(BLOCK_SIZE) {
+            return Err(DriverError::InvalidInput);
+        }
+        let end = block_id
+            .checked_add((len / BLOCK_SIZE) as u64)
+            .ok_or(DriverError::Io)?;
+        if end > self.num_blocks {
+            return Err(DriverError::Io);
+        }
+        Ok(block_id * BLOCK_SIZE as u64)
+    }
+}
+
+impl DriverOps for LoopDisk {
+    fn name(&self) -> &str {
+        "loop"
+    }
+
+    fn device_kind(&self) -> DeviceKind {
+        DeviceKind::Block
+    }
+}
+
+impl BlockDriverOps for LoopDisk {
+    fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DriverResult {
+        let offset = self.offset(block_id, buf.len())?;
+        let read = self.file.read_at(&mut *buf, offset).map_err(|err| {
+            warn!("loop: failed to read at {}: {:?}", offset, err);
+            DriverError::Io
+        })?;
+        // The file shrank since attached
+        buf[read..].fill(0);
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DriverResult {
+        if self.read_only {
+            return Err(DriverError::Unsupported);
+        }
+        let offset = self.offset(block_id, buf.len())?;
+        let written = self.file.write_at(buf, offset).map_err(|err| {
+            warn!("loop: failed to write at {}: {:?}", offset, err);
+            DriverError::Io
+        })?;
+        if written < buf.len() {
+            return Err(DriverError::Io);
+        }
+        Ok(())
+    }
+
+    /// Writes the file back to the filesystem holding it.
+    fn flush(&mut self) -> DriverResult {
+        if self.read_only {
+            return Ok(());
+        }
+        self.file.sync(true).map_err(|err| {
+            warn!("loop: failed to sync: {:?}", err);
+            DriverError::Io
+        })
+    }
+}
@@ -0,0 +1,54 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Block I/O metrics of the file systems.
+use kdriver::prelude::*;
+use kmetrics::{Counter, Histogram};
+
+use crate::blkdev::Disk;
+
+static READ_BYTES: Counter = Counter::new(
+    "xkernel_block_read_bytes_total",
+    "Bytes read from block devices.",
+);
+static WRITE_BYTES: Counter = Counter::new(
+    "xkernel_block_write_bytes_total",
+    "Bytes written to block devices.",
+);
+static LATENCY: Histogram<6> = Histogram::new(
+    "xkernel_block_request_microseconds",
+    "Duration of block device requests.",
+    [10, 100, 1_000, 10_000, 100_000, 1_000_000],
+);
+
+/// Adds the block I/O metrics to the export.
+pub(crate) fn register() {
+    kmetrics::register(&READ_BYTES);
+    kmetrics::register(&WRITE_BYTES);
+    kmetrics::register(&LATENCY);
+}
+
+fn timed<T>(f: impl FnOnce() -> T) -> T {
+    let start = khal::time::monotonic_time_nanos();
+    let result = f();
+    LATENCY.observe((khal::time::monotonic_time_nanos() - start) / 1_000);
+    result
+}
+
+/// Reads `buf.len()` bytes starting at `block_id`.
+pub(crate) fn read_block(dev: &mut Disk, block_id: u64, buf: &mut [u8]) -> DriverResult {
+    let len = buf.len();
+    timed(|| dev.read_block(block_id, buf))?;
+    READ_BYTES.add(len as u64);
+    Ok(())
+}
+
+/// Writes `buf` starting at `block_id`, at the I/O priority of the current
+/// task.
+pub(crate) fn write_block(dev: &mut Disk, block_id: u64, buf: &[u8]) -> DriverResult {
+    let priority = crate::blkdev::current_io_priority();
+    timed(|| dev.write_block_at(block_id, buf, priority))?;
+    WRITE_BYTES.add(buf.len() as u64);
+    Ok(())
+}
@@ -13,6 +13,8 @@ use fs_ng_vfs::{
     path::{Component, Components, Path, PathBuf},
 };
 
+use crate::WorkingContext;
+
 /// Default maximum symlink follow depth
 pub const DEFAULT_MAX_SYMLINKS: usize = 40;
 
@@ -22,6 +24,9 @@ pub const DEFAULT_MAX_SYMLINKS: usize = 40;
 /// - Absolute and relative path resolution
 /// - Symlink following with loop detection
 /// - Path component normalization (`.` and `..`)
+///
+/// Paths are resolved within the root directory of a [`WorkingContext`]:
+/// absolute paths and symlinks start from it, and `..` stops at it.
 #[derive(Debug, Clone)]
 pub struct PathResolver {
     max_symlinks: usize,
@@ -42,10 +47,16 @@ impl PathResolver {
         Self { max_symlinks: max }
     }
 
-    /// Resolves a path starting from the given base location
+    /// Returns how many symlinks one resolution may follow
+    #[inline]
+    pub fn max_symlinks(&self) -> usize {
+        self.max_symlinks
+    }
+
+    /// Resolves a path starting from the current directory of `ctx`
     ///
     /// # Arguments
-    /// * `base` - The base directory to resolve from (typically root or cwd)
+    /// * `ctx` - The root directory and the directory to resolve from
     /// * `path` - The path to resolve
     /// * `follow_symlinks` - Whether to follow symlinks
     ///
@@ -53,37 +64,27 @@ impl PathResolver {
     /// The resolved `Location`, or an error if the path doesn't exist or a loop is detected
     pub fn resolve(
         &self,
-        base: &Location,
+        ctx: &WorkingContext,
         path: &Path,
         follow_symlinks: bool,
     ) -> VfsResult<Location> {
         let mut follow_count = 0;
-        self.resolve_with_count(base, path, follow_symlinks, &mut follow_count)
+        self.resolve_with_count(ctx, path, follow_symlinks, &mut follow_count)
     }
 
     /// Internal resolution with symlink counter
     fn resolve_with_count(
         &self,
-        base: &Location,
+        ctx: &WorkingContext,
         path: &Path,
         follow_symlinks: bool,
         follow_count: &mut usize,
     ) -> VfsResult<Location> {
-        let entry_name = path.file_name();
-        let mut components = path.components();
-
-        // If path has a file name, we need to resolve parent first
-        if entry_name.is_some() {
-            components.next_back();
-        }
-
-        let dir = self.resolve_components(base, components, follow_count)?;
-        dir.check_is_dir()?;
-
+        let (dir, entry_name) = self.resolve_inner(ctx, path, follow_count)?;
         match entry_name {
             Some(name) => {
                 if follow_symlinks {
-                    self.lookup(&dir, name, follow_count)
+                    self.lookup(ctx.root(), &dir, name, follow_count)
                 } else {
                     dir.lookup_no_follow(name)
                 }
@@ -96,8 +97,12 @@ impl PathResolver {
     ///
     /// # Returns
     /// `(parent_directory, entry_name)` tuple
-    pub fn resolve_parent(&self, base: &Location, path: &Path) -> VfsResult<(Location, String)> {
-        let (dir, name) = self.resolve_inner(base, path, &mut 0)?;
+    pub fn resolve_parent(
+        &self,
+        ctx: &WorkingContext,
+        path: &Path,
+    ) -> VfsResult<(Location, String)> {
+        let (dir, name) = self.resolve_inner(ctx, path, &mut 0)?;
         if let Some(name) = name {
             Ok((dir, name.to_owned()))
         } else if let Some(parent) = dir.parent() {
@@ -112,10 +117,10 @@ impl PathResolver {
     /// Verifies that the parent directory exists but the entry doesn't
     pub fn resolve_nonexistent<'a>(
         &self,
-        base: &Location,
+        ctx: &WorkingContext,
         path: &'a Path,
     ) -> VfsResult<(Location, &'a str)> {
-        let (dir, name) = self.resolve_inner(base, path, &mut 0)?;
+        let (dir, name) = self.resolve_inner(ctx, path, &mut 0)?;
         if let Some(name) = name {
             Ok((dir, name))
         } else {
@@ -126,16 +131,17 @@ impl PathResolver {
     /// Internal helper for resolve_parent and resolve_nonexistent
     fn resolve_inner<'a>(
         &self,
-        base: &Location,
+        ctx: &WorkingContext,
         path: &'a Path,
         follow_count: &mut usize,
     ) -> VfsResult<(Location, Option<&'a str>)> {
         let entry_name = path.file_name();
         let mut components = path.components();
+        // If path has a file name, we need to resolve parent first
         if entry_name.is_some() {
             components.next_back();
         }
-        let dir = self.resolve_components(base, components, follow_count)?;
+        let dir = self.resolve_components(ctx.root(), ctx.cwd(), components, follow_count)?;
         dir.check_is_dir()?;
         Ok((dir, entry_name))
     }
@@ -144,16 +150,17 @@ impl PathResolver {
     #[doc(hidden)]
     pub fn resolve_components_internal(
         &self,
-        base: &Location,
+        ctx: &WorkingContext,
         components: Components,
         follow_count: &mut usize,
     ) -> VfsResult<Location> {
-        self.resolve_components(base, components, follow_count)
+        self.resolve_components(ctx.root(), ctx.cwd(), components, follow_count)
     }
 
-    /// Resolves path components iteratively
+    /// Resolves path components iteratively from `base`, within `root`
     fn resolve_components(
         &self,
+        root: &Location,
         base: &Location,
         components: Components,
         follow_count: &mut usize,
@@ -166,16 +173,20 @@ impl PathResolver {
                     // `.` - stay in current directory
                 }
                 Component::ParentDir => {
-                    // `..` - go to parent
-                    current = current.parent().unwrap_or_else(|| base.clone());
+                    // `..` - go to parent, the root is its own parent
+                    if !current.ptr_eq(root)
+                        && let Some(parent) = current.parent()
+                    {
+                        current = parent;
+                    }
                 }
                 Component::RootDir => {
                     // `/` - go to root
-                    current = self.find_root(&current);
+                    current = root.clone();
                 }
                 Component::Normal(name) => {
                     // Regular component - lookup and potentially follow symlink
-                    current = self.lookup(&current, name, follow_count)?;
+                    current = self.lookup(root, &current, name, follow_count)?;
                 }
             }
         }
@@ -184,14 +195,21 @@ impl PathResolver {
     }
 
     /// Looks up a name in a directory and follows symlinks if needed
-    fn lookup(&self, dir: &Location, name: &str, follow_count: &mut usize) -> VfsResult<Location> {
+    fn lookup(
+        &self,
+        root: &Location,
+        dir: &Location,
+        name: &str,
+        follow_count: &mut usize,
+    ) -> VfsResult<Location> {
         let loc = dir.lookup_no_follow(name)?;
-        self.try_resolve_symlink(dir, loc, follow_count)
+        self.try_resolve_symlink(root, dir, loc, follow_count)
     }
 
     /// Attempts to resolve a symlink
     fn try_resolve_symlink(
         &self,
+        root: &Location,
         base: &Location,
         loc: Location,
         follow_count: &mut usize,
@@ -211,16 +229,7 @@ impl PathResolver {
         }
 
         // Resolve the symlink target
-        self.resolve_components(base, PathBuf::from(target).components(), follow_count)
-    }
-
-    /// Finds the root of the filesystem
-    fn find_root(&self, loc: &Location) -> Location {
-        let mut current = loc.clone();
-        while let Some(parent) = current.parent() {
-            current = parent;
-        }
-        current
+        self.resolve_components(root, base, PathBuf::from(target).components(), follow_count)
     }
 }
 
@@ -0,0 +1,79 @@
+//! Unit tests for advisory file locks.
+
+#![cfg(unittest)]
+
+use unittest::{TestResult, assert, assert_eq, def_test};
+
+use crate::{FileLock, FileLocks, LockKind, LockOwner};
+
+fn record(kind: LockKind, start: u64, end: u64, pid: u32) -> FileLock {
+    FileLock {
+        kind,
+        range: start..end,
+        owner: LockOwner::Process(pid),
+        pid,
+    }
+}
+
+#[def_test]
+fn test_flock_shared_and_exclusive() -> TestResult {
+    let locks = FileLocks::default();
+    let a = LockOwner::File(1);
+    let b = LockOwner::File(2);
+
+    assert!(locks.flock(a, 1, Some(LockKind::Shared), false).is_ok());
+    assert!(locks.flock(b, 1, Some(LockKind::Shared), false).is_ok());
+    assert!(locks.flock(b, 1, Some(LockKind::Exclusive), false).is_err());
+
+    // Record locks are independent of flock locks.
+    assert!(
+        locks
+            .set_record(record(LockKind::Exclusive, 0, u64::MAX, 3), false)
+            .is_ok()
+    );
+
+    assert!(locks.flock(a, 1, None, false).is_ok());
+    assert!(locks.flock(b, 1, Some(LockKind::Exclusive), false).is_ok());
+    assert!(locks.flock(a, 1, Some(LockKind::Shared), false).is_err());
+    TestResult::Ok
+}
+
+#[def_test]
+fn test_record_lock_ranges() -> TestResult {
+    let locks = FileLocks::default();
+
+    assert!(
+        locks
+            .set_record(record(LockKind::Exclusive, 0, 100, 1), false)
+            .is_ok()
+    );
+    // Unlocking the middle splits the lock in two.
+    locks.clear_record(LockOwner::Process(1), 40..60);
+
+    assert!(
+        locks
+            .set_record(record(LockKind::Exclusive, 40, 60, 2), false)
+            .is_ok()
+    );
+    assert!(
+        locks
+            .set_record(record(LockKind::Shared, 30, 50, 2), false)
+            .is_err()
+    );
+
+    let conflict = locks.test_record(LockOwner::Process(2), LockKind::Shared, 90..200);
+    assert_eq!(conflict.map(|it| (it.range, it.pid)), Some((60..100, 1)));
+    assert!(
+        locks
+            .test_record(LockOwner::Process(1), LockKind::Shared, 0..40)
+            .is_none()
+    );
+
+    locks.release(LockOwner::Process(1));
+    assert!(
+        locks
+            .test_record(LockOwner::Process(2), LockKind::Exclusive, 0..u64::MAX)
+            .is_none()
+    );
+    TestResult::Ok
+}
@@ -6,7 +6,9 @@
 //!
 //! Lightweight state management for root and current working directory.
 
-use fs_ng_vfs::{Location, VfsResult};
+use alloc::{string::String, vec::Vec};
+
+use fs_ng_vfs::{Location, VfsResult, path::PathBuf};
 
 /// Working context - manages root and current working directory
 ///
@@ -52,6 +54,37 @@ impl WorkingContext {
         Ok(())
     }
 
+    /// Changes the root directory, as `chroot(2)` does
+    ///
+    /// The current working directory is left alone, even if it now lies
+    /// outside the root.
+    ///
+    /// # Errors
+    /// Returns `NotADirectory` if the target is not a directory
+    pub fn chroot(&mut self, dir: Location) -> VfsResult<()> {
+        dir.check_is_dir()?;
+        self.root_dir = dir;
+        Ok(())
+    }
+
+    /// Returns the path of `loc` as seen from the root directory
+    ///
+    /// Returns `None` if `loc` cannot be reached from the root, e.g. a
+    /// working directory left outside it by [`chroot`](Self::chroot).
+    pub fn path_of(&self, loc: &Location) -> Option<PathBuf> {
+        let mut names = Vec::new();
+        let mut cur = loc.clone();
+        while !cur.ptr_eq(&self.root_dir) {
+            names.push(String::from(cur.name()));
+            cur = cur.parent()?;
+        }
+        Some(
+            core::iter::once("/")
+                .chain(names.iter().map(String::as_str).rev())
+                .collect(),
+        )
+    }
+
     /// Creates a new context with a different current working directory
     ///
     /// This is an immutable version of `chdir` that returns a new context
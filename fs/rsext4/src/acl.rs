@@ -0,0 +1,323 @@
+//! # POSIX ACL 模块
+//!
+//! POSIX ACL 存放在扩展属性 `system.posix_acl_access`（访问 ACL）与
+//! `system.posix_acl_default`（目录的默认 ACL）中，磁盘上使用 ext4 的紧凑格式：
+//! 不带 id 的项只占 4 字节。`getxattr` / `setxattr` 使用的用户态格式每项固定
+//! 8 字节，本模块负责两者之间的转换与校验。
+//!
+//! 这里只负责 ACL 的存储，权限检查以及与 `i_mode` 的同步由上层完成。
+
+use alloc::vec::Vec;
+
+use crate::{
+    blockdev::*,
+    endian::*,
+    ext4::Ext4FileSystem,
+    xattr::{self, XattrError, XattrResult, XattrSetMode},
+};
+
+/// 访问 ACL 的扩展属性名
+pub const ACL_ACCESS_NAME: &str = "system.posix_acl_access";
+/// 默认 ACL 的扩展属性名
+pub const ACL_DEFAULT_NAME: &str = "system.posix_acl_default";
+
+/// 用户态格式版本（POSIX_ACL_XATTR_VERSION）
+const XATTR_VERSION: u32 = 2;
+/// 磁盘格式版本（EXT4_ACL_VERSION）
+const DISK_VERSION: u32 = 1;
+/// 不带 id 的项在用户态格式中的 id
+const UNDEFINED_ID: u32 = u32::MAX;
+/// 权限位 rwx
+const PERM_MASK: u16 = 0o7;
+
+/// ACL 项类型
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AclTag {
+    /// 文件所有者
+    UserObj  = 0x01,
+    /// 指定用户
+    User     = 0x02,
+    /// 文件所属组
+    GroupObj = 0x04,
+    /// 指定组
+    Group    = 0x08,
+    /// 指定用户与组权限的上限
+    Mask     = 0x10,
+    /// 其他用户
+    Other    = 0x20,
+}
+
+impl AclTag {
+    fn from_u16(tag: u16) -> Option<Self> {
+        match tag {
+            0x01 => Some(AclTag::UserObj),
+            0x02 => Some(AclTag::User),
+            0x04 => Some(AclTag::GroupObj),
+            0x08 => Some(AclTag::Group),
+            0x10 => Some(AclTag::Mask),
+            0x20 => Some(AclTag::Other),
+            _ => None,
+        }
+    }
+
+    /// 是否带用户或组 id
+    fn has_id(self) -> bool {
+        matches!(self, AclTag::User | AclTag::Group)
+    }
+}
+
+/// ACL 项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AclEntry {
+    pub tag: AclTag,
+    /// 权限位（rwx，0..=7）
+    pub perm: u16,
+    /// 用户或组 id，仅 `User` / `Group` 项有意义
+    pub id: u32,
+}
+
+/// ACL 类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclType {
+    /// 访问 ACL
+    Access,
+    /// 默认 ACL，仅用于目录，由其中新建的文件继承
+    Default,
+}
+
+impl AclType {
+    /// 存放该类 ACL 的扩展属性名
+    pub fn xattr_name(self) -> &'static str {
+        match self {
+            AclType::Access => ACL_ACCESS_NAME,
+            AclType::Default => ACL_DEFAULT_NAME,
+        }
+    }
+}
+
+/// 校验 ACL：必须各有一个所有者、所属组与其他用户项，带 id 的项不重复且需要
+/// mask 项，顺序按类型与 id 递增（与内核 `posix_acl_valid` 一致）
+pub fn is_valid(entries: &[AclEntry]) -> bool {
+    let mut prev: Option<(AclTag, u32)> = None;
+    let (mut user_obj, mut group_obj, mut mask, mut other) = (0, 0, 0, 0);
+    let mut has_named = false;
+    for entry in entries {
+        if entry.perm & !PERM_MASK != 0 {
+            return false;
+        }
+        let key = (entry.tag, if entry.tag.has_id() { entry.id } else { 0 });
+        if prev.is_some_and(|prev| prev >= key) {
+            return false;
+        }
+        prev = Some(key);
+        match entry.tag {
+            AclTag::UserObj => user_obj += 1,
+            AclTag::GroupObj => group_obj += 1,
+            AclTag::Mask => mask += 1,
+            AclTag::Other => other += 1,
+            AclTag::User | AclTag::Group => has_named = true,
+        }
+    }
+    user_obj == 1 && group_obj == 1 && other == 1 && (mask == 1 || (mask == 0 && !has_named))
+}
+
+/// 解析用户态格式，格式错误或 ACL 无效时返回 None
+pub fn from_xattr(value: &[u8]) -> Option<Vec<AclEntry>> {
+    if value.len() < 4 || !(value.len() - 4).is_multiple_of(8) {
+        return None;
+    }
+    if read_u32_le(&value[0..4]) != XATTR_VERSION {
+        return None;
+    }
+    let entries = value[4..]
+        .chunks_exact(8)
+        .map(|raw| {
+            let tag = AclTag::from_u16(read_u16_le(&raw[0..2]))?;
+            let id = if tag.has_id() {
+                read_u32_le(&raw[4..8])
+            } else {
+                UNDEFINED_ID
+            };
+            Some(AclEntry {
+                tag,
+                perm: read_u16_le(&raw[2..4]),
+                id,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    is_valid(&entries).then_some(entries)
+}
+
+/// 编码为用户态格式
+pub fn to_xattr(entries: &[AclEntry]) -> Vec<u8> {
+    let mut value = alloc::vec![0u8; 4 + entries.len() * 8];
+    write_u32_le(XATTR_VERSION, &mut value[0..4]);
+    for (entry, raw) in entries.iter().zip(value[4..].chunks_exact_mut(8)) {
+        let id = if entry.tag.has_id() {
+            entry.id
+        } else {
+            UNDEFINED_ID
+        };
+        write_u16_le(entry.tag as u16, &mut raw[0..2]);
+        write_u16_le(entry.perm, &mut raw[2..4]);
+        write_u32_le(id, &mut raw[4..8]);
+    }
+    value
+}
+
+/// 解析磁盘格式，格式错误时返回 None
+pub(crate) fn from_disk(value: &[u8]) -> Option<Vec<AclEntry>> {
+    if value.len() < 4 || read_u32_le(&value[0..4]) != DISK_VERSION {
+        return None;
+    }
+    let mut entries = Vec::new();
+    let mut pos = 4;
+    while pos < value.len() {
+        let raw = value.get(pos..pos + 4)?;
+        let tag = AclTag::from_u16(read_u16_le(&raw[0..2]))?;
+        let perm = read_u16_le(&raw[2..4]);
+        let id = if tag.has_id() {
+            let id = read_u32_le(value.get(pos + 4..pos + 8)?);
+            pos += 8;
+            id
+        } else {
+            pos += 4;
+            UNDEFINED_ID
+        };
+        entries.push(AclEntry { tag, perm, id });
+    }
+    Some(entries)
+}
+
+/// 编码为磁盘格式
+pub(crate) fn to_disk(entries: &[AclEntry]) -> Vec<u8> {
+    let mut value = Vec::with_capacity(4 + entries.len() * 8);
+    value.extend_from_slice(&DISK_VERSION.to_le_bytes());
+    for entry in entries {
+        value.extend_from_slice(&(entry.tag as u16).to_le_bytes());
+        value.extend_from_slice(&entry.perm.to_le_bytes());
+        if entry.tag.has_id() {
+            value.extend_from_slice(&entry.id.to_le_bytes());
+        }
+    }
+    value
+}
+
+/// 读取 inode 的 ACL，没有设置时返回 None
+pub fn get_acl<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    inode_num: u32,
+    acl_type: AclType,
+) -> XattrResult<Option<Vec<AclEntry>>> {
+    match xattr::get_xattr(fs, block_dev, inode_num, acl_type.xattr_name()) {
+        Ok(value) => from_xattr(&value).map(Some).ok_or(XattrError::InvalidValue),
+        Err(XattrError::NotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// 设置 inode 的 ACL，`None` 表示删除
+///
+/// 默认 ACL 只能设置在目录上
+pub fn set_acl<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    inode_num: u32,
+    acl_type: AclType,
+    entries: Option<&[AclEntry]>,
+) -> XattrResult<()> {
+    let name = acl_type.xattr_name();
+    let Some(entries) = entries else {
+        return match xattr::remove_xattr(fs, block_dev, inode_num, name) {
+            Err(XattrError::NotFound) => Ok(()),
+            result => result,
+        };
+    };
+    if acl_type == AclType::Default && !fs.get_inode_by_num(block_dev, inode_num)?.is_dir() {
+        return Err(XattrError::InvalidValue);
+    }
+    if !is_valid(entries) {
+        return Err(XattrError::InvalidValue);
+    }
+    xattr::set_xattr(
+        fs,
+        block_dev,
+        inode_num,
+        name,
+        &to_xattr(entries),
+        XattrSetMode::Any,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(tag: AclTag, perm: u16, id: u32) -> AclEntry {
+        AclEntry { tag, perm, id }
+    }
+
+    #[test]
+    fn test_acl_format_roundtrip() {
+        let acl = [
+            entry(AclTag::UserObj, 7, UNDEFINED_ID),
+            entry(AclTag::User, 5, 1000),
+            entry(AclTag::User, 4, 1001),
+            entry(AclTag::GroupObj, 5, UNDEFINED_ID),
+            entry(AclTag::Group, 1, 100),
+            entry(AclTag::Mask, 7, UNDEFINED_ID),
+            entry(AclTag::Other, 0, UNDEFINED_ID),
+        ];
+        let value = to_xattr(&acl);
+        assert_eq!(value.len(), 4 + acl.len() * 8);
+        assert_eq!(from_xattr(&value).unwrap(), acl);
+
+        let disk = to_disk(&acl);
+        assert_eq!(disk.len(), 4 + 4 * 4 + 3 * 8);
+        assert_eq!(from_disk(&disk).unwrap(), acl);
+        assert!(from_disk(&disk[..disk.len() - 2]).is_none());
+    }
+
+    #[test]
+    fn test_acl_validation() {
+        let minimal = [
+            entry(AclTag::UserObj, 6, UNDEFINED_ID),
+            entry(AclTag::GroupObj, 4, UNDEFINED_ID),
+            entry(AclTag::Other, 4, UNDEFINED_ID),
+        ];
+        assert!(is_valid(&minimal));
+        // 缺少其他用户项
+        assert!(!is_valid(&minimal[..2]));
+        // 顺序错误
+        assert!(!is_valid(&[minimal[1], minimal[0], minimal[2]]));
+        // 权限位越界
+        assert!(!is_valid(&[
+            minimal[0],
+            minimal[1],
+            entry(AclTag::Other, 8, UNDEFINED_ID)
+        ]));
+        // 带 id 的项需要 mask
+        let named = [
+            minimal[0],
+            entry(AclTag::User, 4, 1000),
+            minimal[1],
+            minimal[2],
+        ];
+        assert!(!is_valid(&named));
+        // 重复的 id
+        assert!(!is_valid(&[
+            minimal[0],
+            entry(AclTag::User, 4, 1000),
+            entry(AclTag::User, 4, 1000),
+            minimal[1],
+            entry(AclTag::Mask, 4, UNDEFINED_ID),
+            minimal[2],
+        ]));
+
+        let mut value = to_xattr(&minimal);
+        value[0] = 1;
+        assert!(from_xattr(&value).is_none());
+    }
+}
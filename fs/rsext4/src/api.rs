@@ -207,6 +207,8 @@ pub fn read_at<B: BlockDevice>(
         return Ok(Vec::new());
     }
 
+    // 延迟分配的数据先落盘，保证 extent 映射完整
+    delalloc::flush_inode(fs, dev, file.inode_num)?;
     refresh_open_file_inode(dev, fs, file)?;
 
     let file_size = file.inode.size();
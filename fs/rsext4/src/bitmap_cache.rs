@@ -2,9 +2,10 @@
 
 use alloc::{collections::BTreeMap, vec::Vec};
 
+use bcache::BufferCache;
 use log::debug;
 
-use crate::{BITMAP_CACHE_MAX, blockdev::*, error::*};
+use crate::{BITMAP_CACHE_MAX, BLOCK_SIZE, blockdev::*, error::*};
 
 /// 位图类型
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -39,51 +40,28 @@ impl CacheKey {
 }
 
 /// 缓存的位图数据
-#[derive(Debug, Clone)]
-pub struct CachedBitmap {
-    /// 位图数据
-    pub data: Vec<u8>,
-    /// 是否被修改（脏）
-    pub dirty: bool,
-    /// 磁盘块号
-    pub block_num: u64,
-    /// 最后访问时间戳（用于LRU）
-    pub last_access: u64,
-}
-
-impl CachedBitmap {
-    pub fn new(data: Vec<u8>, block_num: u64) -> Self {
-        Self {
-            data,
-            dirty: false,
-            block_num,
-            last_access: 0,
-        }
-    }
-
-    /// 标记为脏
-    pub fn mark_dirty(&mut self) {
-        self.dirty = true;
-    }
-}
+pub type CachedBitmap = bcache::Buffer;
 
 /// 位图缓存管理器
+///
+/// 位图块存放在共享的 [`BufferCache`] 中，按块号索引；`index` 记录每个
+/// 位图所在的块。
 pub struct BitmapCache {
-    /// 缓存的位图
-    cache: BTreeMap<CacheKey, CachedBitmap>,
+    /// 缓存的位图块
+    cache: BufferCache,
+    /// 缓存键到位图块号的映射
+    index: BTreeMap<CacheKey, u64>,
     /// 最大缓存条目数（LRU淘汰）
     max_entries: usize,
-    /// 访问计数器（用于LRU）
-    access_counter: u64,
 }
 
 impl BitmapCache {
     /// 创建位图缓存
     pub fn new(max_entries: usize) -> Self {
         Self {
-            cache: BTreeMap::new(),
+            cache: BufferCache::new(BLOCK_SIZE),
+            index: BTreeMap::new(),
             max_entries,
-            access_counter: 0,
         }
     }
 
@@ -102,25 +80,8 @@ impl BitmapCache {
         key: CacheKey,
         block_num: u64,
     ) -> BlockDevResult<&CachedBitmap> {
-        if !self.cache.contains_key(&key) {
-            if self.cache.len() >= self.max_entries {
-                self.evict_lru(block_dev)?;
-            }
-
-            block_dev.read_block(block_num as u32)?;
-            let buffer = block_dev.buffer();
-            let data = buffer.to_vec();
-
-            let bitmap = CachedBitmap::new(data, block_num);
-            self.cache.insert(key, bitmap);
-        }
-
-        self.access_counter += 1;
-        if let Some(bitmap) = self.cache.get_mut(&key) {
-            bitmap.last_access = self.access_counter;
-        }
-
-        self.cache.get(&key).ok_or(BlockDevError::Corrupted)
+        self.get_or_load_mut(block_dev, key, block_num)
+            .map(|bitmap| &*bitmap)
     }
 
     /// 内部使用：获取可变引用（如果不存在则从磁盘加载）
@@ -130,8 +91,8 @@ impl BitmapCache {
         key: CacheKey,
         block_num: u64,
     ) -> BlockDevResult<&mut CachedBitmap> {
-        if !self.cache.contains_key(&key) {
-            if self.cache.len() >= self.max_entries {
+        if !self.index.contains_key(&key) {
+            if self.cache.needs_eviction(self.max_entries) {
                 self.evict_lru(block_dev)?;
             }
 
@@ -139,32 +100,48 @@ impl BitmapCache {
             let buffer = block_dev.buffer();
             let data = buffer.to_vec();
 
-            let bitmap = CachedBitmap::new(data, block_num);
-            self.cache.insert(key, bitmap);
+            self.cache.insert(block_num, data);
+            self.index.insert(key, block_num);
         }
 
-        self.access_counter += 1;
-        if let Some(bitmap) = self.cache.get_mut(&key) {
-            bitmap.last_access = self.access_counter;
-            Ok(bitmap)
-        } else {
-            Err(BlockDevError::Corrupted)
+        let block_num = self.index[&key];
+        self.cache
+            .get_mut(block_num)
+            .ok_or(BlockDevError::Corrupted)
+    }
+
+    /// 直接放入一个内存中构造的位图（不读盘），并标记为脏
+    ///
+    /// 用于 BLOCK_UNINIT / INODE_UNINIT 块组：磁盘上的位图块尚未初始化
+    pub fn insert_new<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        key: CacheKey,
+        block_num: u64,
+        data: Vec<u8>,
+    ) -> BlockDevResult<()> {
+        if !self.index.contains_key(&key) && self.cache.needs_eviction(self.max_entries) {
+            self.evict_lru(block_dev)?;
         }
+
+        self.cache.insert(block_num, data).mark_dirty();
+        self.index.insert(key, block_num);
+        Ok(())
     }
 
     /// 获取已缓存的位图（不加载）
     pub fn get(&self, key: &CacheKey) -> Option<&CachedBitmap> {
-        self.cache.get(key)
+        self.cache.get(*self.index.get(key)?)
     }
 
     /// 获取可变引用
     pub fn get_mut(&mut self, key: &CacheKey) -> Option<&mut CachedBitmap> {
-        self.cache.get_mut(key)
+        self.cache.get_mut(*self.index.get(key)?)
     }
 
     /// 标记位图为脏
     pub fn mark_dirty(&mut self, key: &CacheKey) {
-        if let Some(bitmap) = self.cache.get_mut(key) {
+        if let Some(bitmap) = self.get_mut(key) {
             bitmap.mark_dirty();
         }
     }
@@ -201,11 +178,12 @@ impl BitmapCache {
 
     /// LRU淘汰：找到最久未访问的并写回（如果脏）
     fn evict_lru<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
-        let lru_key = self
-            .cache
-            .iter()
-            .min_by_key(|(_, bitmap)| bitmap.last_access)
-            .map(|(key, _)| *key);
+        let lru_key = self.cache.lru().and_then(|lru| {
+            self.index
+                .iter()
+                .find(|&(_, &block_num)| block_num == lru)
+                .map(|(key, _)| *key)
+        });
 
         if let Some(key) = lru_key {
             self.evict(block_dev, &key)?;
@@ -220,7 +198,8 @@ impl BitmapCache {
         block_dev: &mut Jbd2Dev<B>,
         key: &CacheKey,
     ) -> BlockDevResult<()> {
-        if let Some(bitmap) = self.cache.remove(key)
+        if let Some(block_num) = self.index.remove(key)
+            && let Some(bitmap) = self.cache.remove(block_num)
             && bitmap.dirty
         {
             Self::write_bitmap_static(block_dev, bitmap.block_num, &bitmap.data)?;
@@ -230,39 +209,44 @@ impl BitmapCache {
 
     /// 刷新所有脏位图到磁盘
     pub fn flush_all<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
-        let mut dirty_bitmaps: Vec<(CacheKey, u64, Vec<u8>)> = self
-            .cache
-            .iter()
-            .filter(|(_, bitmap)| bitmap.dirty)
-            .map(|(key, bitmap)| (*key, bitmap.block_num, bitmap.data.clone()))
-            .collect();
-
-        if dirty_bitmaps.is_empty() {
-            return Ok(());
-        }
-
-        // 按物理块号排序，尽量让写入顺序更顺滑
-        dirty_bitmaps.sort_by_key(|(_, block_num, _)| *block_num);
-
-        debug!(
-            "BitmapCache::flush_all: dirty_entries={} (will write all dirty bitmaps to disk)",
-            dirty_bitmaps.len()
-        );
-
-        for (key, block_num, data) in dirty_bitmaps {
-            debug!(
-                "BitmapCache::flush_all: writing bitmap key=({}:{:?}) block_num={} to disk",
-                key.group_id, key.bitmap_type, block_num
-            );
+        self.writeback(block_dev).map(|_| ())
+    }
 
-            Self::write_bitmap_static(block_dev, block_num, &data)?;
+    /// 写回所有脏位图，返回写回的块数
+    ///
+    /// 按物理块号顺序逐块写回，位图属于元数据，经由日志写入
+    pub fn writeback<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+    ) -> BlockDevResult<usize> {
+        let written = self.cache.writeback(1, |block_num, data| {
+            debug!("BitmapCache::writeback: writing bitmap block_num={block_num} to disk");
+            Self::write_bitmap_static(block_dev, block_num, data)
+        })?;
+        if written > 0 {
+            debug!("BitmapCache::writeback: wrote {written} dirty bitmaps");
         }
+        Ok(written)
+    }
 
-        for bitmap in self.cache.values_mut() {
-            bitmap.dirty = false;
+    /// 共享预算超出时，丢弃最久未访问的干净位图，直到不超过本缓存的份额，
+    /// 返回丢弃的个数
+    pub fn shrink(&mut self) -> usize {
+        if !bcache::over_capacity() {
+            return 0;
         }
+        let dropped = self.cache.shrink(self.cache.fair_share());
+        let cache = &self.cache;
+        self.index.retain(|_, block_num| cache.contains(*block_num));
+        dropped
+    }
 
-        Ok(())
+    /// 内存不足时丢弃至多 `max` 个干净位图（不分配内存），返回丢弃的个数
+    pub fn reclaim(&mut self, max: usize) -> usize {
+        let dropped = self.cache.reclaim(max);
+        let cache = &self.cache;
+        self.index.retain(|_, block_num| cache.contains(*block_num));
+        dropped
     }
 
     /// 刷新指定位图到磁盘
@@ -271,7 +255,7 @@ impl BitmapCache {
         block_dev: &mut Jbd2Dev<B>,
         key: &CacheKey,
     ) -> BlockDevResult<()> {
-        if let Some(bitmap) = self.cache.get(key)
+        if let Some(bitmap) = self.get(key)
             && bitmap.dirty
         {
             let block_num = bitmap.block_num;
@@ -281,7 +265,7 @@ impl BitmapCache {
             Self::write_bitmap_static(block_dev, block_num, &data)?;
 
             // 清除脏标记
-            if let Some(bitmap) = self.cache.get_mut(key) {
+            if let Some(bitmap) = self.get_mut(key) {
                 bitmap.dirty = false;
             }
         }
@@ -304,15 +288,14 @@ impl BitmapCache {
     /// 清空缓存（不写回）
     pub fn clear(&mut self) {
         self.cache.clear();
+        self.index.clear();
     }
 
     /// 获取缓存统计
     pub fn stats(&self) -> CacheStats {
-        let dirty_count = self.cache.values().filter(|b| b.dirty).count();
-
         CacheStats {
             total_entries: self.cache.len(),
-            dirty_entries: dirty_count,
+            dirty_entries: self.cache.dirty_len(),
             max_entries: self.max_entries,
         }
     }
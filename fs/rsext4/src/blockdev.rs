@@ -2,14 +2,14 @@
 //!
 //! 定义了块设备的抽象接口和实现，为文件系统提供底层存储支持
 
-use alloc::{boxed::Box, vec::Vec};
+use alloc::{boxed::Box, collections::BTreeSet, vec::Vec};
 
 use log::{error, trace, warn};
 
 use crate::{
     config::{JBD2_BUFFER_MAX, *},
     error::*,
-    jbd2::jbdstruct::*,
+    jbd2::{jbd2::Jbd2ReplayStats, jbdstruct::*},
 };
 
 /// 需要调用块设备写操作的函数标记
@@ -86,6 +86,21 @@ pub trait BlockDevice {
         Ok(()) // 默认实现为空操作
     }
 
+    /// 通知设备这些块已不再使用，可以释放其存储（discard/TRIM）
+    ///
+    /// # 参数
+    ///
+    /// * `block_id` - 起始块号
+    /// * `count` - 块数量
+    ///
+    /// # 返回值
+    ///
+    /// 默认实现为空操作；discard 只是提示，设备不支持时也应返回 `Ok(())`
+    fn discard(&mut self, block_id: u32, count: u32) -> BlockDevResult<()> {
+        let _ = (block_id, count);
+        Ok(())
+    }
+
     /// 检查设备是否已打开
     ///
     /// # 返回值
@@ -187,16 +202,27 @@ impl<B: BlockDevice> Jbd2Dev<B> {
     }
 
     /// 外部重放journal日志入口 注意性能影响
-    pub fn journal_replay(&mut self) {
+    pub fn journal_replay(&mut self) -> BlockDevResult<Jbd2ReplayStats> {
         if self.journal_use {
             let dev = &mut self.inner.dev;
             let jbd_sys = &mut self
                 .systeam
                 .as_mut()
                 .expect("jbd2dev are not initial,please initial the jbd2dev first!");
-            jbd_sys.replay(&mut *dev);
+            jbd_sys.replay(&mut *dev)
         } else {
             warn!("Jouranl function not turn ,please turn on this function and retry!");
+            Ok(Jbd2ReplayStats::default())
+        }
+    }
+
+    /// 块被释放：撤销它在日志中的旧副本，避免重放时覆盖重新分配后的内容
+    pub fn journal_revoke(&mut self, block_id: u64) {
+        if !self.journal_use {
+            return;
+        }
+        if let Some(systeam) = self.systeam.as_mut() {
+            systeam.revoke(block_id);
         }
     }
 
@@ -219,20 +245,23 @@ impl<B: BlockDevice> Jbd2Dev<B> {
             sequence: super_block.s_sequence,
             jbd2_super_block: super_block,
             commit_queue: Vec::new(),
+            revoke_queue: Vec::new(),
+            journaled: BTreeSet::new(),
         };
         self.systeam = Some(system);
     }
 
-    /// 防止滥用，仅仅umount调用，确保事务缓存全部提交完毕
-    pub fn umount_commit(&mut self) {
+    /// 防止滥用，仅仅umount调用，确保事务缓存全部提交完毕，并把日志标记为空
+    pub fn umount_commit(&mut self) -> BlockDevResult<()> {
         if self.journal_use {
-            self.systeam
-                .as_mut()
-                .unwrap()
+            let systeam = self.systeam.as_mut().unwrap();
+            systeam
                 .commit_transaction(&mut self.inner.dev)
                 .expect("Translation commit failed!!!");
+            systeam.mark_clean(&mut self.inner.dev)
         } else {
-            warn!("Jouranl not use , no thing to commit")
+            warn!("Jouranl not use , no thing to commit");
+            Ok(())
         }
     }
 
@@ -267,6 +296,7 @@ impl<B: BlockDevice> Jbd2Dev<B> {
         }
 
         let systeam = self.systeam.as_mut().unwrap();
+        systeam.cancel_revoke(block_id as u64);
 
         // 使用原始底层块设备提交事务
         let raw_dev = self.inner.device_mut();
@@ -343,6 +373,7 @@ impl<B: BlockDevice> Jbd2Dev<B> {
             let mut boxbuf = Box::new([0; BLOCK_SIZE]);
             boxbuf[..].copy_from_slice(&buf[off..off + BLOCK_SIZE]);
             let updates = Jbd2Update((block_id + i) as u64, boxbuf);
+            systeam.cancel_revoke(updates.0);
 
             // 先写入缓存
             if systeam.commit_queue.len() > JBD2_BUFFER_MAX {
@@ -364,6 +395,11 @@ impl<B: BlockDevice> Jbd2Dev<B> {
         self.inner.flush()
     }
 
+    /// 对一段不再使用的块下发 discard
+    pub fn discard(&mut self, block_id: u32, count: u32) -> BlockDevResult<()> {
+        self.inner.discard(block_id, count)
+    }
+
     pub fn total_blocks(&self) -> u64 {
         self.inner.total_blocks()
     }
@@ -484,6 +520,22 @@ impl<B: BlockDevice> BlockDev<B> {
         self.dev.write(buffer, block_id, count)
     }
 
+    /// 对一段块下发 discard，内部缓冲区中的该段块直接丢弃（不写回）
+    pub fn discard(&mut self, block_id: u32, count: u32) -> BlockDevResult<()> {
+        if self.dev.is_readonly() {
+            return Err(BlockDevError::ReadOnly);
+        }
+
+        if self
+            .cached_block
+            .is_some_and(|cached| (block_id..block_id + count).contains(&cached))
+        {
+            self.cached_block = None;
+            self.is_dirty = false;
+        }
+        self.dev.discard(block_id, count)
+    }
+
     /// 获取缓冲区引用
     pub fn buffer(&self) -> &[u8] {
         self.buffer.as_slice()
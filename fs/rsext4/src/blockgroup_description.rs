@@ -2,7 +2,7 @@
 //!
 //! 定义了 ext4 文件系统的块组描述符结构和相关操作。
 
-use crate::endian::*;
+use crate::{checksum::group_desc_csum, endian::*, superblock::Ext4Superblock};
 
 /// Ext4 块组描述符结构
 ///
@@ -99,6 +99,37 @@ impl Ext4GroupDesc {
         (self.bg_inode_bitmap_csum_hi as u32) << 16 | self.bg_inode_bitmap_csum_lo as u32
     }
 
+    /// 序列化块组描述符并按特性填写 bg_checksum
+    pub fn to_disk_bytes_with_csum(&self, sb: &Ext4Superblock, group: u32, bytes: &mut [u8]) {
+        self.to_disk_bytes(bytes);
+        if sb.has_group_desc_csum() {
+            let csum = group_desc_csum(sb, group, bytes);
+            write_u16_le(csum, &mut bytes[30..32]);
+        }
+    }
+
+    /// 校验磁盘上的块组描述符校验和
+    pub fn verify_csum(sb: &Ext4Superblock, group: u32, bytes: &[u8]) -> bool {
+        !sb.has_group_desc_csum()
+            || group_desc_csum(sb, group, bytes) == read_u16_le(&bytes[30..32])
+    }
+
+    /// 设置块位图校验和，`desc_size` 决定是否写入高 16 位
+    pub fn set_block_bitmap_csum(&mut self, csum: u32, desc_size: u16) {
+        self.bg_block_bitmap_csum_lo = (csum & 0xFFFF) as u16;
+        if desc_size as usize >= Self::EXT4_DESC_SIZE_64BIT {
+            self.bg_block_bitmap_csum_hi = (csum >> 16) as u16;
+        }
+    }
+
+    /// 设置inode位图校验和，`desc_size` 决定是否写入高 16 位
+    pub fn set_inode_bitmap_csum(&mut self, csum: u32, desc_size: u16) {
+        self.bg_inode_bitmap_csum_lo = (csum & 0xFFFF) as u16;
+        if desc_size as usize >= Self::EXT4_DESC_SIZE_64BIT {
+            self.bg_inode_bitmap_csum_hi = (csum >> 16) as u16;
+        }
+    }
+
     /// 检查块组是否未初始化（inode表和位图未初始化）
     pub fn is_uninit_bg(&self) -> bool {
         self.bg_flags & Self::EXT4_BG_INODE_UNINIT != 0
@@ -142,6 +142,44 @@ impl BlockAllocator {
         })
     }
 
+    /// 以 goal 为起点在指定块组中分配一段空闲块（多块分配）
+    /// * `bitmap_data` - 块位图数据
+    /// * `group_idx` - 块组索引
+    /// * `goal` - 组内目标块号，从这里开始向后查找，到组尾后回绕
+    /// * `max_len` - 期望分配的最大块数
+    ///
+    /// 返回分配结果和实际分配的块数（1..=max_len）
+    pub fn alloc_extent_in_group(
+        &self,
+        bitmap_data: &mut [u8],
+        group_idx: u32,
+        goal: u32,
+        max_len: u32,
+    ) -> Result<(BlockAlloc, u32), AllocError> {
+        if max_len == 0 {
+            return Err(AllocError::InvalidParameter);
+        }
+
+        let mut bitmap = BlockBitmapMut::new(bitmap_data, self.blocks_per_group);
+
+        let (block_in_group, len) = self
+            .find_free_extent(&bitmap, goal, max_len)
+            .ok_or(AllocError::NoSpace)?;
+
+        bitmap.allocate_range(block_in_group, len)?;
+
+        let global_block = self.block_to_global(group_idx, block_in_group);
+
+        Ok((
+            BlockAlloc {
+                group_idx,
+                block_in_group,
+                global_block,
+            },
+            len,
+        ))
+    }
+
     /// 释放一个块
     /// * `bitmap_data` - 块位图数据
     /// * `block_in_group` - 块组内的块索引
@@ -203,6 +241,41 @@ impl BlockAllocator {
         Ok(None)
     }
 
+    /// 从 goal 开始查找空闲区间
+    /// 返回第一段长度达到 max_len 的区间，找不到则返回扫描到的最长区间
+    fn find_free_extent(
+        &self,
+        bitmap: &BlockBitmapMut,
+        goal: u32,
+        max_len: u32,
+    ) -> Option<(u32, u32)> {
+        let goal = goal.min(self.blocks_per_group);
+        let mut best: Option<(u32, u32)> = None;
+
+        for (from, to) in [(goal, self.blocks_per_group), (0, goal)] {
+            let mut idx = from;
+            while idx < to {
+                if bitmap.is_allocated(idx) != Some(false) {
+                    idx += 1;
+                    continue;
+                }
+                let start = idx;
+                while idx < to && idx - start < max_len && bitmap.is_allocated(idx) == Some(false) {
+                    idx += 1;
+                }
+                let len = idx - start;
+                if len == max_len {
+                    return Some((start, len));
+                }
+                if best.is_none_or(|(_, best_len)| len > best_len) {
+                    best = Some((start, len));
+                }
+            }
+        }
+
+        best
+    }
+
     /// 将块组内块号转换为全局块号
     fn block_to_global(&self, group_idx: u32, block_in_group: u32) -> u64 {
         (group_idx as u64 * self.blocks_per_group as u64)
@@ -377,6 +450,43 @@ mod tests {
         assert_eq!(alloc.block_in_group, 0);
     }
 
+    #[test]
+    fn test_block_allocator_extent_goal() {
+        let sb = Ext4Superblock {
+            s_blocks_per_group: 1024,
+            s_first_data_block: 0,
+            ..Default::default()
+        };
+
+        let allocator = BlockAllocator::new(&sb);
+
+        let mut bitmap_data = vec![0u8; 128];
+        // 占用 [100, 110) 和 [200, 1024)
+        {
+            let mut bitmap = BlockBitmapMut::new(&mut bitmap_data, 1024);
+            bitmap.allocate_range(100, 10).unwrap();
+            bitmap.allocate_range(200, 824).unwrap();
+        }
+
+        // 从 goal 开始分配完整长度
+        let (alloc, len) = allocator
+            .alloc_extent_in_group(&mut bitmap_data, 0, 50, 20)
+            .unwrap();
+        assert_eq!((alloc.block_in_group, len), (50, 20));
+
+        // goal 被占用时跳到其后的第一段满足长度的空闲区间
+        let (alloc, len) = allocator
+            .alloc_extent_in_group(&mut bitmap_data, 0, 105, 40)
+            .unwrap();
+        assert_eq!((alloc.block_in_group, len), (110, 40));
+
+        // 空间不足时退化为最长的空闲区间，并回绕到组头查找
+        let (alloc, len) = allocator
+            .alloc_extent_in_group(&mut bitmap_data, 0, 190, 500)
+            .unwrap();
+        assert_eq!((alloc.block_in_group, len), (0, 50));
+    }
+
     #[test]
     fn test_inode_allocator() {
         let mut sb = Ext4Superblock::default();
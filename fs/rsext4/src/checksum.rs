@@ -0,0 +1,405 @@
+//! # 元数据校验和模块
+//!
+//! 实现 metadata_csum（crc32c）与 gdt_csum（crc16）两种校验方式，
+//! 覆盖超级块、块组描述符、位图、inode、目录块尾部和 extent 块尾部。
+//!
+//! 与 Linux 内核保持一致：crc32c 不做最终取反，初始值由调用方传入。
+
+use crate::{endian::*, entries::Ext4DirEntryTail, superblock::Ext4Superblock};
+
+/// crc32c（Castagnoli）反射多项式
+const CRC32C_POLY: u32 = 0x82F6_3B78;
+/// crc16（ANSI）反射多项式
+const CRC16_POLY: u16 = 0xA001;
+
+const fn build_crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32C_POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const fn build_crc16_table() -> [u16; 256] {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u16;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC16_POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32C_TABLE: [u32; 256] = build_crc32c_table();
+static CRC16_TABLE: [u16; 256] = build_crc16_table();
+
+/// 计算 crc32c（等价于内核 `crc32c_le`，不做最终取反）
+pub fn crc32c(mut crc: u32, data: &[u8]) -> u32 {
+    for &b in data {
+        crc = CRC32C_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// 计算 crc16（等价于内核 `crc16`）
+pub fn crc16(mut crc: u16, data: &[u8]) -> u16 {
+    for &b in data {
+        crc = CRC16_TABLE[((crc ^ b as u16) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// 超级块中 s_checksum 字段的偏移
+pub const SUPERBLOCK_CSUM_OFFSET: usize = 0x3FC;
+/// 块组描述符中 bg_checksum 字段的偏移
+pub const GROUP_DESC_CSUM_OFFSET: usize = 0x1E;
+/// inode 中 l_i_checksum_lo 字段的偏移
+pub const INODE_CSUM_LO_OFFSET: usize = 0x7C;
+/// inode 中 i_checksum_hi 字段的偏移
+pub const INODE_CSUM_HI_OFFSET: usize = 0x82;
+/// i_extra_isize 至少覆盖到 i_checksum_hi 末尾时才使用高 16 位
+pub const INODE_CSUM_HI_EXTRA_END: u16 = 4;
+/// extent 块尾部（ext4_extent_tail）大小
+pub const EXTENT_TAIL_SIZE: usize = 4;
+/// 扩展属性块头中 h_checksum 字段的偏移
+pub const XATTR_BLOCK_CSUM_OFFSET: usize = 0x10;
+
+/// 计算超级块校验和（原始 1024 字节）
+pub fn superblock_csum(raw: &[u8]) -> u32 {
+    crc32c(!0, &raw[..SUPERBLOCK_CSUM_OFFSET])
+}
+
+/// 校验超级块校验和
+pub fn verify_superblock_csum(sb: &Ext4Superblock, raw: &[u8]) -> bool {
+    !sb.has_metadata_csum() || superblock_csum(raw) == sb.s_checksum
+}
+
+/// 计算块组描述符校验和
+///
+/// metadata_csum 使用 crc32c 的低 16 位；gdt_csum 使用 crc16；都未启用时返回 0
+pub fn group_desc_csum(sb: &Ext4Superblock, group: u32, raw: &[u8]) -> u16 {
+    let desc_size = (sb.get_desc_size() as usize).min(raw.len());
+    let le_group = group.to_le_bytes();
+    let tail_start = GROUP_DESC_CSUM_OFFSET + 2;
+
+    if sb.has_metadata_csum() {
+        let mut crc = crc32c(sb.csum_seed(), &le_group);
+        crc = crc32c(crc, &raw[..GROUP_DESC_CSUM_OFFSET]);
+        crc = crc32c(crc, &[0, 0]);
+        if tail_start < desc_size {
+            crc = crc32c(crc, &raw[tail_start..desc_size]);
+        }
+        return (crc & 0xFFFF) as u16;
+    }
+
+    if sb.has_feature_ro_compat(Ext4Superblock::EXT4_FEATURE_RO_COMPAT_GDT_CSUM) {
+        let mut crc = crc16(!0, &sb.s_uuid);
+        crc = crc16(crc, &le_group);
+        crc = crc16(crc, &raw[..GROUP_DESC_CSUM_OFFSET]);
+        if sb.has_feature_incompat(Ext4Superblock::EXT4_FEATURE_INCOMPAT_64BIT)
+            && tail_start < desc_size
+        {
+            crc = crc16(crc, &raw[tail_start..desc_size]);
+        }
+        return crc;
+    }
+
+    0
+}
+
+/// 计算位图校验和，`len` 为参与计算的有效字节数
+pub fn bitmap_csum(sb: &Ext4Superblock, bitmap: &[u8], len: usize) -> u32 {
+    crc32c(sb.csum_seed(), &bitmap[..len.min(bitmap.len())])
+}
+
+/// 计算单个 inode 的校验种子，目录块和 extent 块也复用该种子
+pub fn inode_csum_seed(fs_seed: u32, inode_num: u32, generation: u32) -> u32 {
+    let crc = crc32c(fs_seed, &inode_num.to_le_bytes());
+    crc32c(crc, &generation.to_le_bytes())
+}
+
+/// 计算 inode 原始字节（长度为 s_inode_size）的校验和
+fn inode_csum(fs_seed: u32, inode_num: u32, raw: &[u8]) -> u32 {
+    let generation = read_u32_le(&raw[100..104]);
+    let mut crc = inode_csum_seed(fs_seed, inode_num, generation);
+    crc = crc32c(crc, &raw[..INODE_CSUM_LO_OFFSET]);
+    crc = crc32c(crc, &[0, 0]);
+    crc = crc32c(crc, &raw[INODE_CSUM_LO_OFFSET + 2..128]);
+    if raw.len() > 128 {
+        let has_hi = read_u16_le(&raw[128..130]) >= INODE_CSUM_HI_EXTRA_END;
+        if has_hi {
+            crc = crc32c(crc, &raw[128..INODE_CSUM_HI_OFFSET]);
+            crc = crc32c(crc, &[0, 0]);
+            crc = crc32c(crc, &raw[INODE_CSUM_HI_OFFSET + 2..]);
+        } else {
+            crc = crc32c(crc, &raw[128..]);
+        }
+    }
+    crc
+}
+
+fn inode_has_csum_hi(raw: &[u8]) -> bool {
+    raw.len() > 128 && read_u16_le(&raw[128..130]) >= INODE_CSUM_HI_EXTRA_END
+}
+
+/// 就地写入 inode 校验和
+pub fn set_inode_csum(fs_seed: u32, inode_num: u32, raw: &mut [u8]) {
+    let crc = inode_csum(fs_seed, inode_num, raw);
+    write_u16_le((crc & 0xFFFF) as u16, &mut raw[INODE_CSUM_LO_OFFSET..]);
+    if inode_has_csum_hi(raw) {
+        write_u16_le((crc >> 16) as u16, &mut raw[INODE_CSUM_HI_OFFSET..]);
+    }
+}
+
+/// 校验 inode 校验和；全零的未使用 inode 视为合法
+pub fn verify_inode_csum(fs_seed: u32, inode_num: u32, raw: &[u8]) -> bool {
+    if raw.iter().all(|&b| b == 0) {
+        return true;
+    }
+    let crc = inode_csum(fs_seed, inode_num, raw);
+    let lo = read_u16_le(&raw[INODE_CSUM_LO_OFFSET..]) as u32;
+    if inode_has_csum_hi(raw) {
+        let hi = read_u16_le(&raw[INODE_CSUM_HI_OFFSET..]) as u32;
+        crc == (hi << 16 | lo)
+    } else {
+        crc & 0xFFFF == lo
+    }
+}
+
+/// 判断目录块末尾是否已有校验尾部
+pub fn has_dir_tail(block: &[u8]) -> bool {
+    let off = block.len() - Ext4DirEntryTail::TAIL_LEN as usize;
+    read_u32_le(&block[off..]) == 0
+        && read_u16_le(&block[off + 4..]) == Ext4DirEntryTail::TAIL_LEN
+        && block[off + 6] == 0
+        && block[off + 7] == Ext4DirEntryTail::RESERVED_FT
+}
+
+/// 就地写入目录块尾部（ext4_dir_entry_tail）及其校验和
+pub fn set_dir_block_csum(inode_seed: u32, block: &mut [u8]) {
+    let off = block.len() - Ext4DirEntryTail::TAIL_LEN as usize;
+    write_u32_le(0, &mut block[off..]);
+    write_u16_le(Ext4DirEntryTail::TAIL_LEN, &mut block[off + 4..]);
+    block[off + 6] = 0;
+    block[off + 7] = Ext4DirEntryTail::RESERVED_FT;
+    let crc = crc32c(inode_seed, &block[..off]);
+    write_u32_le(crc, &mut block[off + 8..]);
+}
+
+/// 校验目录块尾部校验和；没有尾部的块（如 htree 内部节点）不做校验
+pub fn verify_dir_block_csum(inode_seed: u32, block: &[u8]) -> bool {
+    if !has_dir_tail(block) {
+        return true;
+    }
+    let off = block.len() - Ext4DirEntryTail::TAIL_LEN as usize;
+    crc32c(inode_seed, &block[..off]) == read_u32_le(&block[off + 8..])
+}
+
+/// htree 索引块中 dx_countlimit 的偏移；不是索引块时返回 None
+///
+/// 内部节点以 rec_len 覆盖整块的伪目录项开头；根节点以 "." / ".." 开头，其后是 dx_root_info
+pub fn dx_countlimit_offset(block: &[u8]) -> Option<usize> {
+    let first_rec_len = read_u16_le(&block[4..6]) as usize;
+    if first_rec_len == block.len() {
+        return Some(8);
+    }
+    if first_rec_len != 12 {
+        return None;
+    }
+    let dotdot_rec_len = read_u16_le(&block[16..18]) as usize;
+    let info_length = block[0x1d] as usize;
+    (dotdot_rec_len == block.len() - 12 && info_length == 8).then_some(0x18 + info_length)
+}
+
+/// htree 索引块的 dx_tail 校验和；limit 没有为尾部留出空间时返回 None
+fn dx_node_csum(inode_seed: u32, block: &[u8]) -> Option<(usize, u32)> {
+    let off = dx_countlimit_offset(block)?;
+    let limit = read_u16_le(&block[off..]) as usize;
+    let count = read_u16_le(&block[off + 2..]) as usize;
+    let tail = off + limit * 8;
+    if count > limit || tail + 8 > block.len() {
+        return None;
+    }
+    let crc = crc32c(inode_seed, &block[..off + count * 8]);
+    let crc = crc32c(crc, &block[tail..tail + 4]);
+    Some((tail, crc32c(crc, &[0u8; 4])))
+}
+
+/// 就地写入 htree 索引块尾部（dx_tail）校验和
+pub fn set_dx_node_csum(inode_seed: u32, block: &mut [u8]) {
+    if let Some((tail, crc)) = dx_node_csum(inode_seed, block) {
+        write_u32_le(crc, &mut block[tail + 4..]);
+    }
+}
+
+/// 校验 htree 索引块尾部校验和
+pub fn verify_dx_node_csum(inode_seed: u32, block: &[u8]) -> bool {
+    match dx_node_csum(inode_seed, block) {
+        Some((tail, crc)) => crc == read_u32_le(&block[tail + 4..]),
+        None => false,
+    }
+}
+
+/// extent 块尾部在块内的偏移：header(12) + eh_max * 12
+fn extent_tail_offset(block: &[u8]) -> Option<usize> {
+    let eh_max = read_u16_le(&block[4..6]) as usize;
+    let off = 12 + eh_max * 12;
+    (off + EXTENT_TAIL_SIZE <= block.len()).then_some(off)
+}
+
+/// 就地写入 extent 块尾部校验和
+pub fn set_extent_block_csum(inode_seed: u32, block: &mut [u8]) {
+    if let Some(off) = extent_tail_offset(block) {
+        let crc = crc32c(inode_seed, &block[..off]);
+        write_u32_le(crc, &mut block[off..]);
+    }
+}
+
+/// 校验 extent 块尾部校验和
+pub fn verify_extent_block_csum(inode_seed: u32, block: &[u8]) -> bool {
+    match extent_tail_offset(block) {
+        Some(off) => crc32c(inode_seed, &block[..off]) == read_u32_le(&block[off..]),
+        None => false,
+    }
+}
+
+/// 计算扩展属性块的校验和：以块号为种子，块内 h_checksum 字段按 0 计算
+fn xattr_block_csum(fs_seed: u32, block_num: u64, block: &[u8]) -> u32 {
+    let mut crc = crc32c(fs_seed, &block_num.to_le_bytes());
+    crc = crc32c(crc, &block[..XATTR_BLOCK_CSUM_OFFSET]);
+    crc = crc32c(crc, &[0; 4]);
+    crc32c(crc, &block[XATTR_BLOCK_CSUM_OFFSET + 4..])
+}
+
+/// 就地写入扩展属性块校验和
+pub fn set_xattr_block_csum(fs_seed: u32, block_num: u64, block: &mut [u8]) {
+    let crc = xattr_block_csum(fs_seed, block_num, block);
+    write_u32_le(crc, &mut block[XATTR_BLOCK_CSUM_OFFSET..]);
+}
+
+/// 校验扩展属性块校验和
+pub fn verify_xattr_block_csum(fs_seed: u32, block_num: u64, block: &[u8]) -> bool {
+    xattr_block_csum(fs_seed, block_num, block) == read_u32_le(&block[XATTR_BLOCK_CSUM_OFFSET..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32c_check_value() {
+        // 标准 crc32c 校验值：初值 ~0，结果取反
+        assert_eq!(crc32c(!0, b"123456789") ^ !0, 0xE306_9283);
+    }
+
+    #[test]
+    fn test_crc16_check_value() {
+        // CRC-16/ARC 校验值
+        assert_eq!(crc16(0, b"123456789"), 0xBB3D);
+    }
+
+    #[test]
+    fn test_group_desc_csum_metadata() {
+        let mut sb = Ext4Superblock {
+            s_feature_ro_compat: Ext4Superblock::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM,
+            s_feature_incompat: Ext4Superblock::EXT4_FEATURE_INCOMPAT_64BIT,
+            s_desc_size: 64,
+            ..Default::default()
+        };
+        sb.s_uuid = [7; 16];
+        let mut raw = [0u8; 64];
+        raw[0] = 0x10;
+        let csum = group_desc_csum(&sb, 3, &raw);
+
+        // 校验和字段本身不参与计算
+        raw[GROUP_DESC_CSUM_OFFSET] = 0xAA;
+        assert_eq!(group_desc_csum(&sb, 3, &raw), csum);
+        // 不同组号得到不同的校验和
+        assert_ne!(group_desc_csum(&sb, 4, &raw), csum);
+    }
+
+    #[test]
+    fn test_inode_csum_roundtrip() {
+        let mut raw = [0u8; 256];
+        raw[0] = 0xED;
+        raw[1] = 0x41;
+        write_u16_le(32, &mut raw[128..130]);
+        set_inode_csum(0x1234_5678, 12, &mut raw);
+        assert!(verify_inode_csum(0x1234_5678, 12, &raw));
+        assert!(!verify_inode_csum(0x1234_5678, 13, &raw));
+
+        raw[4] ^= 1;
+        assert!(!verify_inode_csum(0x1234_5678, 12, &raw));
+    }
+
+    #[test]
+    fn test_dir_and_extent_tail_roundtrip() {
+        let mut block = [0u8; 4096];
+        block[0] = 2;
+        set_dir_block_csum(0xCAFE, &mut block);
+        assert!(has_dir_tail(&block));
+        assert!(verify_dir_block_csum(0xCAFE, &block));
+
+        let mut ext = [0u8; 4096];
+        write_u16_le(0xF30A, &mut ext[0..2]);
+        write_u16_le(340, &mut ext[4..6]);
+        set_extent_block_csum(0xBEEF, &mut ext);
+        assert!(verify_extent_block_csum(0xBEEF, &ext));
+        ext[12] = 1;
+        assert!(!verify_extent_block_csum(0xBEEF, &ext));
+    }
+
+    #[test]
+    fn test_xattr_block_csum_roundtrip() {
+        let mut block = [0u8; 4096];
+        write_u32_le(0xEA02_0000, &mut block[0..4]);
+        set_xattr_block_csum(0xCAFE, 100, &mut block);
+        assert!(verify_xattr_block_csum(0xCAFE, 100, &block));
+        // 校验和与块号绑定，搬到别处的块无法通过校验
+        assert!(!verify_xattr_block_csum(0xCAFE, 101, &block));
+        block[40] = 1;
+        assert!(!verify_xattr_block_csum(0xCAFE, 100, &block));
+    }
+
+    #[test]
+    fn test_dx_node_tail_roundtrip() {
+        // 内部节点：伪目录项覆盖整块，limit 为 dx_tail 留出 8 字节
+        let mut node = [0u8; 4096];
+        write_u16_le(4096, &mut node[4..6]);
+        write_u16_le(510, &mut node[8..10]);
+        write_u16_le(2, &mut node[10..12]);
+        write_u32_le(7, &mut node[12..16]);
+        assert_eq!(dx_countlimit_offset(&node), Some(8));
+        set_dx_node_csum(0x1234, &mut node);
+        assert!(verify_dx_node_csum(0x1234, &node));
+        node[20] = 1;
+        assert!(!verify_dx_node_csum(0x1234, &node));
+
+        // 普通目录块不是索引块
+        let mut leaf = [0u8; 4096];
+        write_u16_le(24, &mut leaf[4..6]);
+        assert_eq!(dx_countlimit_offset(&leaf), None);
+    }
+}
@@ -50,6 +50,12 @@ pub const INODE_CACHE_MAX: usize = 128;
 pub const DATABLOCK_CACHE_MAX: usize = 128;
 /// BITMAP cache数量
 pub const BITMAP_CACHE_MAX: usize = 128;
+/// 延迟分配缓存的全局块数上限，超过后刷写全部 inode
+pub const DELALLOC_MAX_BLOCKS: usize = 1024;
+/// 单个 inode 延迟分配的块数上限，超过后刷写该 inode
+pub const DELALLOC_INODE_MAX_BLOCKS: usize = 256;
+/// 单个 inode 预留窗口的最大块数
+pub const RESERVE_WINDOW_MAX_BLOCKS: u32 = 4096;
 
 //============================================================================
 // 目录项DirEntry配置
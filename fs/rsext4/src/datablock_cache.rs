@@ -1,50 +1,28 @@
 //! 数据块缓存模块
 //!
-//! 提供文件和目录数据块的缓存管理，支持延迟写回和LRU淘汰
+//! 提供文件和目录数据块的缓存管理，支持延迟写回和LRU淘汰。
+//! 缓存的块存放在与其他文件系统共享内存预算的 [`bcache::BufferCache`] 中。
 
-use alloc::{collections::BTreeMap, vec::Vec};
+use alloc::vec::Vec;
+
+use bcache::BufferCache;
 
 use crate::{blockdev::*, config::*, error::*};
 /// 数据块缓存键（全局块号）
 pub type BlockCacheKey = u64;
 
 /// 缓存的数据块
-#[derive(Debug, Clone)]
-pub struct CachedBlock {
-    /// 数据块内容 最后变成[u8]
-    pub data: Vec<u8>,
-    /// 是否被修改（脏）
-    pub dirty: bool,
-    /// 块号
-    pub block_num: u64,
-    /// 最后访问时间戳（用于LRU）
-    pub last_access: u64,
-}
-
-impl CachedBlock {
-    pub fn new(data: Vec<u8>, block_num: u64) -> Self {
-        Self {
-            data,
-            dirty: false,
-            block_num,
-            last_access: 0,
-        }
-    }
+pub type CachedBlock = bcache::Buffer;
 
-    /// 标记为脏
-    pub fn mark_dirty(&mut self) {
-        self.dirty = true;
-    }
-}
+/// 回写时最多聚合的连续块数
+const MAX_WRITEBACK_RUN: usize = 100;
 
 /// 数据块缓存管理器
 pub struct DataBlockCache {
     /// 缓存的数据块
-    cache: BTreeMap<BlockCacheKey, CachedBlock>,
+    cache: BufferCache,
     /// 最大缓存条目数
     max_entries: usize,
-    /// 访问计数器（用于LRU）
-    access_counter: u64,
     /// 块大小
     block_size: usize,
 }
@@ -57,9 +35,8 @@ impl DataBlockCache {
     /// * `block_size` - 块大小（通常是4096字节）
     pub fn new(max_entries: usize, block_size: usize) -> Self {
         Self {
-            cache: BTreeMap::new(),
+            cache: BufferCache::new(block_size),
             max_entries,
-            access_counter: 0,
             block_size,
         }
     }
@@ -90,24 +67,8 @@ impl DataBlockCache {
         block_dev: &mut Jbd2Dev<B>,
         block_num: u64,
     ) -> BlockDevResult<&CachedBlock> {
-        // 如果缓存中不存在，则加载
-        if !self.cache.contains_key(&block_num) {
-            if self.cache.len() >= self.max_entries {
-                self.evict_lru(block_dev)?;
-            }
-
-            let data = self.load_block(block_dev, block_num)?;
-            let cached = CachedBlock::new(data, block_num);
-            self.cache.insert(block_num, cached);
-        }
-
-        // 更新访问时间
-        self.access_counter += 1;
-        if let Some(cached) = self.cache.get_mut(&block_num) {
-            cached.last_access = self.access_counter;
-        }
-
-        self.cache.get(&block_num).ok_or(BlockDevError::Corrupted)
+        self.get_or_load_mut(block_dev, block_num)
+            .map(|cached| &*cached)
     }
 
     /// 内部使用：获取可变引用（如果不存在则从磁盘加载）
@@ -116,39 +77,30 @@ impl DataBlockCache {
         block_dev: &mut Jbd2Dev<B>,
         block_num: u64,
     ) -> BlockDevResult<&mut CachedBlock> {
-        if !self.cache.contains_key(&block_num) {
-            if self.cache.len() >= self.max_entries {
+        // 如果缓存中不存在，则加载；已满或超出共享预算时先淘汰
+        if !self.cache.contains(block_num) {
+            if self.cache.needs_eviction(self.max_entries) {
                 self.evict_lru(block_dev)?;
             }
 
             let data = self.load_block(block_dev, block_num)?;
-            let cached = CachedBlock::new(data, block_num);
-            self.cache.insert(block_num, cached);
+            self.cache.insert(block_num, data);
         }
 
-        self.access_counter += 1;
-        if let Some(cached) = self.cache.get_mut(&block_num) {
-            cached.last_access = self.access_counter;
-            Ok(cached)
-        } else {
-            Err(BlockDevError::Corrupted)
-        }
+        // 更新访问时间
+        self.cache
+            .get_mut(block_num)
+            .ok_or(BlockDevError::Corrupted)
     }
 
     /// 获取已缓存的数据块（不加载）
     pub fn get(&self, block_num: u64) -> Option<&CachedBlock> {
-        self.cache.get(&block_num)
+        self.cache.get(block_num)
     }
 
     /// 获取可变引用
     pub fn get_mut(&mut self, block_num: u64) -> Option<&mut CachedBlock> {
-        if let Some(cached) = self.cache.get_mut(&block_num) {
-            self.access_counter += 1;
-            cached.last_access = self.access_counter;
-            Some(cached)
-        } else {
-            None
-        }
+        self.cache.get_mut(block_num)
     }
 
     /// 创建新的数据块缓存（不立即写入磁盘），并返回可变引用 自动标记为脏
@@ -158,21 +110,14 @@ impl DataBlockCache {
         }
 
         let data = alloc::vec![0u8; self.block_size];
-        let mut cached = CachedBlock::new(data, block_num);
-        cached.dirty = true;
-
-        self.access_counter += 1;
-        cached.last_access = self.access_counter;
-
-        self.cache.insert(block_num, cached);
-        self.cache.get_mut(&block_num).unwrap()
+        let cached = self.cache.insert(block_num, data);
+        cached.mark_dirty();
+        cached
     }
 
     /// 标记数据块为脏
     pub fn mark_dirty(&mut self, block_num: u64) {
-        if let Some(cached) = self.cache.get_mut(&block_num) {
-            cached.mark_dirty();
-        }
+        self.cache.mark_dirty(block_num);
     }
 
     /// 使用闭包修改指定数据块，并自动标记为脏
@@ -205,13 +150,7 @@ impl DataBlockCache {
     /// LRU淘汰：找到最久未访问的并写回（如果脏）
     fn evict_lru<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
         // 找到最小的last_access
-        let lru_key = self
-            .cache
-            .iter()
-            .min_by_key(|(_, cached)| cached.last_access)
-            .map(|(key, _)| *key);
-
-        if let Some(key) = lru_key {
+        if let Some(key) = self.cache.lru() {
             self.evict(block_dev, key)?;
         }
 
@@ -224,7 +163,7 @@ impl DataBlockCache {
         block_dev: &mut Jbd2Dev<B>,
         block_num: u64,
     ) -> BlockDevResult<()> {
-        if let Some(cached) = self.cache.remove(&block_num)
+        if let Some(cached) = self.cache.remove(block_num)
             && cached.dirty
         {
             // 写回磁盘
@@ -234,57 +173,36 @@ impl DataBlockCache {
     }
 
     /// 刷新所有脏数据块到磁盘
+    ///
+    /// 按块号顺序写回，连续块聚合后使用 write_blocks 一次性写回
     pub fn flush_all<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
-        // 收集需要写回的数据块信息（block_num, data），并按块号排序
-        let mut dirty_blocks: Vec<(u64, Vec<u8>)> = self
-            .cache
-            .values()
-            .filter(|cached| cached.dirty)
-            .map(|cached| (cached.block_num, cached.data.clone()))
-            .collect();
-
-        if dirty_blocks.is_empty() {
-            return Ok(());
-        }
-
-        dirty_blocks.sort_by_key(|(block_num, _)| *block_num);
+        self.writeback(block_dev).map(|_| ())
+    }
 
-        // 将连续块聚合后，使用 write_blocks 一次性写回
-        let max_part_size = BLOCK_SIZE * 100; //最大聚合块数;
+    /// 写回所有脏数据块，返回写回的块数
+    pub fn writeback<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+    ) -> BlockDevResult<usize> {
         let block_size = self.block_size;
-        let mut idx = 0usize;
-        while idx < dirty_blocks.len() {
-            let (start_block, _) = dirty_blocks[idx];
-            let mut run_len = 1usize;
-
-            // 统计从 start_block 开始的连续块数量
-            while idx + run_len < dirty_blocks.len() && run_len <= max_part_size {
-                let expected = start_block + run_len as u64;
-                if dirty_blocks[idx + run_len].0 == expected {
-                    run_len += 1;
-                } else {
-                    break;
-                }
-            }
-
-            // 聚合这一段连续块的数据到一个大的 buffer 中
-            let mut buf: Vec<u8> = Vec::with_capacity(block_size * run_len);
-            for off in 0..run_len {
-                buf.extend_from_slice(&dirty_blocks[idx + off].1);
-            }
-
-            // 通过底层的 write_blocks 一次性写入连续块
-            block_dev.write_blocks(&buf, start_block as u32, run_len as u32, false)?;
-
-            idx += run_len;
-        }
+        self.cache.writeback(MAX_WRITEBACK_RUN, |start_block, buf| {
+            let count = (buf.len() / block_size) as u32;
+            block_dev.write_blocks(buf, start_block as u32, count, false)
+        })
+    }
 
-        // 清除脏标记
-        for cached in self.cache.values_mut() {
-            cached.dirty = false;
+    /// 共享预算超出时，丢弃最久未访问的干净数据块，直到不超过本缓存的份额，
+    /// 返回丢弃的块数
+    pub fn shrink(&mut self) -> usize {
+        if !bcache::over_capacity() {
+            return 0;
         }
+        self.cache.shrink(self.cache.fair_share())
+    }
 
-        Ok(())
+    /// 内存不足时丢弃至多 `max` 个干净数据块（不分配内存），返回丢弃的块数
+    pub fn reclaim(&mut self, max: usize) -> usize {
+        self.cache.reclaim(max)
     }
 
     /// 刷新指定数据块到磁盘
@@ -293,13 +211,13 @@ impl DataBlockCache {
         block_dev: &mut Jbd2Dev<B>,
         block_num: u64,
     ) -> BlockDevResult<()> {
-        if let Some(cached) = self.cache.get(&block_num)
+        if let Some(cached) = self.cache.get(block_num)
             && cached.dirty
         {
             let data = cached.data.clone();
             Self::write_block_static(block_dev, block_num, &data)?;
 
-            if let Some(cached) = self.cache.get_mut(&block_num) {
+            if let Some(cached) = self.cache.get_mut(block_num) {
                 cached.dirty = false;
             }
         }
@@ -323,7 +241,7 @@ impl DataBlockCache {
     ///
     /// 用于删除文件或目录时，避免写回已删除的数据
     pub fn invalidate(&mut self, block_num: u64) {
-        self.cache.remove(&block_num);
+        self.cache.remove(block_num);
     }
 
     /// 清空缓存（不写回）
@@ -333,7 +251,7 @@ impl DataBlockCache {
 
     /// 获取缓存统计
     pub fn stats(&self) -> DataBlockCacheStats {
-        let dirty_count = self.cache.values().filter(|c| c.dirty).count();
+        let dirty_count = self.cache.dirty_len();
 
         let total_size = self.cache.len() * self.block_size;
 
@@ -0,0 +1,388 @@
+//! 延迟分配模块
+//!
+//! 写入落在空洞上的数据先缓存在内存中，不立即分配物理块；等到刷写时
+//! （读取、截断、sync、卸载或缓存达到阈值）再把逻辑连续的块合并，
+//! 通过多块分配器一次分配出尽量长的物理连续区间并插入一个 extent。
+//!
+//! 每个 inode 还维护一个内存中的预留窗口：该 inode 的后续分配优先落在
+//! 窗口内，其他 inode 选择分配目标时会避开它，使并发写入的多个文件各自
+//! 保持连续。窗口只是软预留，不占用位图，卸载后即失效。
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+use log::debug;
+
+use crate::{
+    blockdev::*, config::*, disknode::*, error::*, ext4::Ext4FileSystem, extents_tree::ExtentTree,
+};
+
+/// 单个 extent 可以表示的最大块数（ee_len 最高位为未初始化标志）
+const MAX_EXTENT_BLOCKS: u32 = 32767;
+
+/// 预留窗口：[start, end) 为预留区间，next 为下一次分配的目标块
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReserveWindow {
+    pub start: u64,
+    pub end: u64,
+    pub next: u64,
+}
+
+impl ReserveWindow {
+    fn size(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+/// 延迟分配状态
+#[derive(Debug, Default)]
+pub struct DelayedAlloc {
+    /// inode -> (逻辑块号 -> 块数据)
+    pending: BTreeMap<u32, BTreeMap<u32, Vec<u8>>>,
+    /// 所有 inode 待分配的块总数
+    pending_blocks: usize,
+    /// inode -> 预留窗口
+    windows: BTreeMap<u32, ReserveWindow>,
+    /// 下一个新窗口的起始块号
+    cursor: u64,
+}
+
+impl DelayedAlloc {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 所有 inode 待分配的块总数
+    pub fn pending_blocks(&self) -> usize {
+        self.pending_blocks
+    }
+
+    /// 指定 inode 待分配的块数
+    pub fn inode_pending_blocks(&self, inode_num: u32) -> usize {
+        self.pending.get(&inode_num).map_or(0, |m| m.len())
+    }
+
+    /// 指定 inode 是否有待分配的数据
+    pub fn has_pending(&self, inode_num: u32) -> bool {
+        self.pending.contains_key(&inode_num)
+    }
+
+    /// 逻辑块是否在延迟分配缓存中
+    pub fn contains(&self, inode_num: u32, lbn: u32) -> bool {
+        self.pending
+            .get(&inode_num)
+            .is_some_and(|m| m.contains_key(&lbn))
+    }
+
+    /// 读取缓存中的逻辑块
+    pub fn read_block(&self, inode_num: u32, lbn: u32) -> Option<&[u8]> {
+        self.pending
+            .get(&inode_num)
+            .and_then(|m| m.get(&lbn))
+            .map(|b| b.as_slice())
+    }
+
+    /// 写入一个尚未分配物理块的逻辑块，块首次出现时以全 0 初始化
+    pub fn write_block(&mut self, inode_num: u32, lbn: u32, offset: usize, data: &[u8]) {
+        let blocks = self.pending.entry(inode_num).or_default();
+        let block = blocks.entry(lbn).or_insert_with(|| {
+            self.pending_blocks += 1;
+            vec![0u8; BLOCK_SIZE]
+        });
+        block[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    /// 截断到 new_size：丢弃其后的块，并清零最后一个块中超出的部分
+    pub fn truncate(&mut self, inode_num: u32, new_size: u64) {
+        let Some(blocks) = self.pending.get_mut(&inode_num) else {
+            return;
+        };
+
+        let block_bytes = BLOCK_SIZE as u64;
+        let keep = new_size.div_ceil(block_bytes).min(u32::MAX as u64) as u32;
+        let dropped = blocks.split_off(&keep);
+        self.pending_blocks -= dropped.len();
+
+        let tail = (new_size % block_bytes) as usize;
+        if tail != 0
+            && let Some(block) = blocks.get_mut(&(keep - 1))
+        {
+            block[tail..].fill(0);
+        }
+
+        if blocks.is_empty() {
+            self.pending.remove(&inode_num);
+        }
+    }
+
+    /// 丢弃 inode 的全部缓存数据和预留窗口（inode 被释放时调用）
+    pub fn forget_inode(&mut self, inode_num: u32) {
+        if let Some(blocks) = self.pending.remove(&inode_num) {
+            self.pending_blocks -= blocks.len();
+        }
+        self.windows.remove(&inode_num);
+    }
+
+    /// 已缓存数据的 inode 列表
+    pub fn pending_inodes(&self) -> Vec<u32> {
+        self.pending.keys().copied().collect()
+    }
+
+    fn take_inode(&mut self, inode_num: u32) -> BTreeMap<u32, Vec<u8>> {
+        let blocks = self.pending.remove(&inode_num).unwrap_or_default();
+        self.pending_blocks -= blocks.len();
+        blocks
+    }
+
+    fn restore_inode(&mut self, inode_num: u32, blocks: BTreeMap<u32, Vec<u8>>) {
+        if blocks.is_empty() {
+            return;
+        }
+        self.pending_blocks += blocks.len();
+        self.pending.entry(inode_num).or_default().extend(blocks);
+    }
+
+    /// 是否落在其他 inode 的预留窗口中
+    fn in_foreign_window(&self, inode_num: u32, block: u64) -> bool {
+        self.windows
+            .iter()
+            .any(|(&ino, w)| ino != inode_num && block >= w.start && block < w.end)
+    }
+
+    /// 为 inode 选择分配目标，返回 (goal, 本次最多分配的块数)
+    ///
+    /// 优先紧接前一个逻辑块的物理位置，其次是自己窗口中的下一个块，
+    /// 都不可用时在 cursor 处开一个新窗口（窗口大小逐次翻倍）。
+    /// 分配长度限制在下一个其他 inode 窗口之前。
+    fn pick_goal(
+        &mut self,
+        inode_num: u32,
+        prev_end: Option<u64>,
+        want: u32,
+        total_blocks: u64,
+    ) -> (u64, u32) {
+        let goal = match prev_end {
+            Some(p) if !self.in_foreign_window(inode_num, p) => p,
+            _ => match self.windows.get(&inode_num) {
+                Some(w) if w.next < w.end => w.next,
+                _ => self.open_window(inode_num, want, total_blocks),
+            },
+        };
+
+        let limit = self
+            .windows
+            .iter()
+            .filter(|&(&ino, w)| ino != inode_num && w.start > goal)
+            .map(|(_, w)| w.start - goal)
+            .min()
+            .unwrap_or(u64::MAX);
+
+        (goal, limit.clamp(1, want as u64) as u32)
+    }
+
+    /// 在 cursor 处为 inode 开一个新窗口
+    ///
+    /// 连续写满阈值的 inode 直接拿到最大窗口，其余按上一个窗口翻倍。
+    /// 已经没有待分配数据的 inode 视为空闲，顺便回收它们的窗口。
+    fn open_window(&mut self, inode_num: u32, want: u32, total_blocks: u64) -> u64 {
+        let pending = &self.pending;
+        self.windows
+            .retain(|ino, _| *ino == inode_num || pending.contains_key(ino));
+
+        let size = if want as usize >= DELALLOC_INODE_MAX_BLOCKS {
+            RESERVE_WINDOW_MAX_BLOCKS as u64
+        } else {
+            self.windows
+                .get(&inode_num)
+                .map_or(0, |w| w.size().saturating_mul(2))
+                .min(RESERVE_WINDOW_MAX_BLOCKS as u64)
+        }
+        .max(want as u64);
+
+        if self.cursor.saturating_add(size) > total_blocks {
+            self.cursor = 0;
+        }
+        let start = self.cursor;
+        self.cursor = start + size;
+
+        self.windows.insert(
+            inode_num,
+            ReserveWindow {
+                start,
+                end: start + size,
+                next: start,
+            },
+        );
+        start
+    }
+
+    /// 记录一次实际分配，推进（或迁移）inode 的窗口
+    fn commit_window(&mut self, inode_num: u32, phys: u64, len: u32) {
+        let alloc_end = phys + len as u64;
+        let w = self.windows.entry(inode_num).or_insert(ReserveWindow {
+            start: phys,
+            end: alloc_end,
+            next: phys,
+        });
+
+        if phys >= w.start && phys < w.end {
+            w.next = w.next.max(alloc_end);
+        } else {
+            // 分配器没有落在窗口内，把窗口迁移到实际分配的位置
+            let size = w.size().max(len as u64);
+            *w = ReserveWindow {
+                start: phys,
+                end: phys + size,
+                next: alloc_end,
+            };
+        }
+        w.end = w.end.max(w.next);
+        self.cursor = self.cursor.max(w.end);
+    }
+}
+
+/// 为 inode 所有延迟分配的数据分配物理块并写入块缓存
+pub fn flush_inode<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    inode_num: u32,
+) -> BlockDevResult<()> {
+    let mut blocks = fs.delalloc.take_inode(inode_num);
+    if blocks.is_empty() {
+        return Ok(());
+    }
+
+    let mut inode = fs.get_inode_by_num(block_dev, inode_num)?;
+    let result = flush_blocks(fs, block_dev, inode_num, &mut inode, &mut blocks);
+
+    // 已经插入的 extent 无论成功与否都要写回，剩余的块放回缓存
+    fs.modify_inode(block_dev, inode_num, |td| *td = inode)?;
+    fs.delalloc.restore_inode(inode_num, blocks);
+    result
+}
+
+/// 刷写所有 inode 的延迟分配数据
+pub fn flush_all<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+) -> BlockDevResult<()> {
+    for inode_num in fs.delalloc.pending_inodes() {
+        flush_inode(fs, block_dev, inode_num)?;
+    }
+    Ok(())
+}
+
+fn flush_blocks<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    inode_num: u32,
+    inode: &mut Ext4Inode,
+    blocks: &mut BTreeMap<u32, Vec<u8>>,
+) -> BlockDevResult<()> {
+    let csum_seed = fs.inode_csum_seed(inode_num, inode);
+    let total_blocks = fs.superblock.blocks_count();
+
+    while let Some(&first) = blocks.keys().next() {
+        // 以 first 开始的逻辑连续块数
+        let mut run = 1u32;
+        while run < MAX_EXTENT_BLOCKS && blocks.contains_key(&(first + run)) {
+            run += 1;
+        }
+
+        let prev_end = match first.checked_sub(1) {
+            Some(prev) => ExtentTree::new(inode)
+                .find_extent(block_dev, prev)?
+                .map(|ext| ext.start_block() + (prev - ext.ee_block) as u64 + 1),
+            None => None,
+        };
+        let (goal, limit) = fs
+            .delalloc
+            .pick_goal(inode_num, prev_end, run, total_blocks);
+        let (phys, len) = fs.alloc_extent(block_dev, Some(goal), limit)?;
+
+        for i in 0..len {
+            let data = blocks
+                .remove(&(first + i))
+                .ok_or(BlockDevError::Corrupted)?;
+            fs.datablock_cache
+                .modify_new(phys + i as u64, |blk| blk.copy_from_slice(&data));
+        }
+
+        let ext = Ext4Extent::new(first, phys, len as u16);
+        ExtentTree::new(inode)
+            .with_csum_seed(csum_seed)
+            .insert_extent(fs, ext, block_dev)?;
+
+        let sectors = inode
+            .blocks_count()
+            .saturating_add(len as u64 * (BLOCK_SIZE as u64 / 512));
+        inode.i_blocks_lo = (sectors & 0xFFFF_FFFF) as u32;
+        inode.l_i_blocks_high = ((sectors >> 32) & 0xFFFF) as u16;
+
+        fs.delalloc.commit_window(inode_num, phys, len);
+
+        debug!(
+            "delalloc: inode={inode_num} lbn={first} run={run} goal={goal} -> phys={phys} \
+             len={len}"
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_write_and_truncate() {
+        let mut da = DelayedAlloc::new();
+        da.write_block(12, 0, 10, &[1, 2, 3]);
+        da.write_block(12, 1, 0, &[4; BLOCK_SIZE]);
+        da.write_block(12, 5, 0, &[5; 8]);
+        da.write_block(12, 0, 0, &[9]);
+        assert_eq!(da.pending_blocks(), 3);
+        assert_eq!(da.inode_pending_blocks(12), 3);
+
+        let blk = da.read_block(12, 0).unwrap();
+        assert_eq!(&blk[..13], &[9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3]);
+
+        // 截断到第 2 个块中间：丢弃 lbn 5，清零 lbn 1 的尾部
+        da.truncate(12, BLOCK_SIZE as u64 + 100);
+        assert_eq!(da.pending_blocks(), 2);
+        assert!(!da.contains(12, 5));
+        let blk = da.read_block(12, 1).unwrap();
+        assert!(blk[..100].iter().all(|&b| b == 4));
+        assert!(blk[100..].iter().all(|&b| b == 0));
+
+        da.forget_inode(12);
+        assert_eq!(da.pending_blocks(), 0);
+        assert!(!da.has_pending(12));
+    }
+
+    #[test]
+    fn test_reserve_window_goal() {
+        let mut da = DelayedAlloc::new();
+
+        // 两个 inode 各开一个窗口，互不重叠
+        let (g1, l1) = da.pick_goal(12, None, 8, 1 << 20);
+        da.commit_window(12, g1, l1);
+        let (g2, l2) = da.pick_goal(13, None, 8, 1 << 20);
+        da.commit_window(13, g2, l2);
+        assert_eq!((g1, l1), (0, 8));
+        assert_eq!((g2, l2), (8, 8));
+
+        // inode 12 的窗口用尽，前一块之后又是 inode 13 的窗口：开一个翻倍的新窗口
+        let (g, l) = da.pick_goal(12, Some(8), 32, 1 << 20);
+        assert_eq!((g, l), (16, 32));
+        da.commit_window(12, g, 10);
+
+        // inode 13 不会占用 inode 12 窗口剩余的部分
+        let (g, l) = da.pick_goal(13, Some(16), 8, 1 << 20);
+        assert_eq!((g, l), (48, 8));
+        da.commit_window(13, g, l);
+
+        // inode 12 接着前一块继续分配，长度截止到 inode 13 窗口的起点
+        let (g, l) = da.pick_goal(12, Some(26), 100, 1 << 20);
+        assert_eq!((g, l), (26, 22));
+    }
+}
@@ -7,8 +7,9 @@ use alloc::{string::String, vec::Vec};
 use log::{debug, error};
 
 use crate::{
-    alloc::string::ToString, blockdev::*, config::*, disknode::*, endian::*, entries::*, error::*,
-    ext4::*, extents_tree::*, file::*, loopfile::*,
+    alloc::string::ToString, blockdev::*, checksum::has_dir_tail, config::*, disknode::*,
+    endian::*, entries::*, error::*, ext4::*, extents_tree::*, file::*, hashtree::*, loopfile::*,
+    superblock::Ext4Superblock,
 };
 
 /// 文件操作错误类型
@@ -90,30 +91,15 @@ pub fn get_inode_with_num<B: BlockDevice>(
 
         let target = name.as_bytes();
 
-        let total_size = current_inode.size() as usize;
-        let block_bytes = BLOCK_SIZE;
-        let total_blocks = if total_size == 0 {
-            0
-        } else {
-            total_size.div_ceil(block_bytes)
-        };
-
-        let mut found_inode_num: Option<u64> = None;
-
-        for lbn in 0..total_blocks {
-            let phys = match resolve_inode_block(device, &mut current_inode, lbn as u32)? {
-                Some(b) => b,
-                None => continue,
-            };
-
-            let cached_block = fs.datablock_cache.get_or_load(device, phys as u64)?;
-            let block_data = &cached_block.data[..block_bytes];
-
-            if let Some(entry) = classic_dir::find_entry(block_data, target) {
-                found_inode_num = Some(entry.inode as u64);
-                break;
+        // 带索引的目录按哈希定位叶子块，否则线性扫描
+        let found_inode_num = match lookup_directory_entry(fs, device, &current_inode, target) {
+            Ok(result) => Some(result.inode as u64),
+            Err(HashTreeError::EntryNotFound) => None,
+            Err(e) => {
+                error!("Directory lookup of {name:?} failed: {e}");
+                return Err(BlockDevError::Corrupted);
             }
-        }
+        };
 
         let inode_num = match found_inode_num {
             Some(n) => n,
@@ -144,8 +130,149 @@ pub fn get_inode_with_num<B: BlockDevice>(
     Ok(Some((current_ino, current_inode)))
 }
 
+/// 在目录块的前 `block_bytes` 字节内为新目录项寻找空间并写入
+///
+/// 优先复用空闲目录项，否则拆分已有目录项 rec_len 的剩余空间；成功返回 true
+pub(crate) fn insert_entry_into_block(
+    data: &mut [u8],
+    block_bytes: usize,
+    new_entry: &Ext4DirEntry2,
+) -> bool {
+    let new_rec_len = Ext4DirEntry2::entry_len(new_entry.name_len) as usize;
+    let mut offset = 0usize;
+    while offset + 8 <= block_bytes {
+        let inode = u32::from_le_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ]);
+        let rec_len = u16::from_le_bytes([data[offset + 4], data[offset + 5]]) as usize;
+        if rec_len < 8 {
+            return false;
+        }
+        let entry_end = offset + rec_len;
+        if entry_end > block_bytes {
+            return false;
+        }
+
+        // Free entry: directly use it if it can hold the new entry.
+        if inode == 0 {
+            if rec_len >= new_rec_len {
+                let mut full_entry = *new_entry;
+                full_entry.rec_len = rec_len as u16;
+                full_entry.to_disk_bytes(&mut data[offset..offset + 8]);
+                let nlen = full_entry.name_len as usize;
+                data[offset + 8..offset + 8 + nlen].copy_from_slice(&full_entry.name[..nlen]);
+                return true;
+            }
+            return false;
+        }
+
+        // Occupied entry: try to split tail space.
+        let cur_name_len = data[offset + 6] as usize;
+        let mut ideal = 8 + cur_name_len;
+        ideal = (ideal + 3) & !3;
+        if ideal <= rec_len {
+            let tail = rec_len - ideal;
+            if tail >= new_rec_len {
+                let ideal_bytes = (ideal as u16).to_le_bytes();
+                data[offset + 4] = ideal_bytes[0];
+                data[offset + 5] = ideal_bytes[1];
+
+                let new_off = offset + ideal;
+                let mut full_entry = *new_entry;
+                full_entry.rec_len = tail as u16;
+                full_entry.to_disk_bytes(&mut data[new_off..new_off + 8]);
+                let nlen = full_entry.name_len as usize;
+                data[new_off + 8..new_off + 8 + nlen].copy_from_slice(&full_entry.name[..nlen]);
+                return true;
+            }
+        }
+
+        if entry_end == block_bytes {
+            return false;
+        }
+        offset = entry_end;
+    }
+    false
+}
+
+/// 将目录 inode 的大小、块数、标志和块映射写回 inode 表
+pub(crate) fn write_back_dir_inode<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    dir_ino_num: u32,
+    dir_inode: &Ext4Inode,
+) -> BlockDevResult<()> {
+    fs.modify_inode(device, dir_ino_num, |inode| {
+        inode.i_size_lo = dir_inode.i_size_lo;
+        inode.i_size_high = dir_inode.i_size_high;
+        inode.i_blocks_lo = dir_inode.i_blocks_lo;
+        inode.l_i_blocks_high = dir_inode.l_i_blocks_high;
+        inode.i_flags = dir_inode.i_flags;
+        inode.i_block = dir_inode.i_block;
+    })
+}
+
+/// 为目录追加一个清零的数据块，扩展 inode 的块映射和大小并写回 inode 表
+///
+/// 返回 (逻辑块号, 物理块号)
+pub(crate) fn append_dir_block<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    parent_ino_num: u32,
+    parent_inode: &mut Ext4Inode,
+) -> BlockDevResult<(u32, u64)> {
+    let new_block = fs.alloc_block(device)?;
+
+    // 更新 parent_inode 的块映射（extent 或直接块）和大小统计
+    let total_size = parent_inode.size() as usize;
+    let block_bytes = BLOCK_SIZE;
+    let old_blocks = if total_size == 0 {
+        0
+    } else {
+        total_size.div_ceil(block_bytes)
+    };
+    let new_lbn = old_blocks as u32; // 新块对应的逻辑块号
+
+    if fs.superblock.has_extents() && parent_inode.have_extend_header_and_use_extend() {
+        // extent 目录：通过 ExtentTree 追加一个长度为 1 的 extent
+        let new_ext = Ext4Extent::new(new_lbn, new_block, 1);
+        let csum_seed = fs.inode_csum_seed(parent_ino_num, parent_inode);
+        let mut tree = ExtentTree::new(parent_inode).with_csum_seed(csum_seed);
+        tree.insert_extent(fs, new_ext, device)?;
+    } else {
+        // 传统直接块模式：仅支持追加到前 12 个直接块
+        if old_blocks >= 12 {
+            return Err(BlockDevError::Unsupported);
+        }
+        parent_inode.i_block[old_blocks] = new_block as u32;
+    }
+
+    // 更新 parent_inode 的 i_size / i_blocks，并写回 inode 表
+    let new_size = total_size + block_bytes;
+    parent_inode.i_size_lo = new_size as u32;
+    parent_inode.i_size_high = ((new_size as u64) >> 32) as u32;
+    // fix:extend元数据也会占block，不能仅仅靠现有blocks_count计算，需要考虑extent树的开销
+    let cur = parent_inode.blocks_count();
+    let add_sectors = BLOCK_SIZE as u64 / 512;
+    let newv = cur.saturating_add(add_sectors);
+    parent_inode.i_blocks_lo = (newv & 0xffff_ffff) as u32;
+    parent_inode.l_i_blocks_high = ((newv >> 32) & 0xffff) as u16;
+
+    write_back_dir_inode(fs, device, parent_ino_num, parent_inode)?;
+
+    fs.datablock_cache
+        .modify_new(new_block, |data| data.fill(0));
+    Ok((new_lbn, new_block))
+}
+
 /// 在父目录的所有逻辑块中查找空闲空间并插入一个目录项；
 /// 若所有现有块都无法容纳，则自动为目录分配一个新数据块并扩展 inode 映射和大小。
+///
+/// 带 htree 索引的目录按哈希定位叶子块插入并维护索引；
+/// 单块线性目录写满时转换为索引目录（需要 dir_index 特性）。
 pub fn insert_dir_entry<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     device: &mut Jbd2Dev<B>,
@@ -157,7 +284,6 @@ pub fn insert_dir_entry<B: BlockDevice>(
 ) -> BlockDevResult<()> {
     let name_bytes = child_name.as_bytes();
     let name_len = core::cmp::min(name_bytes.len(), Ext4DirEntry2::MAX_NAME_LEN as usize);
-    let new_rec_len = Ext4DirEntry2::entry_len(name_len as u8) as usize;
     let new_entry = Ext4DirEntry2::new(
         child_ino,
         Ext4DirEntry2::entry_len(name_len as u8),
@@ -165,6 +291,15 @@ pub fn insert_dir_entry<B: BlockDevice>(
         &name_bytes[..name_len],
     );
 
+    if is_dx_dir(fs, parent_inode) {
+        if dx_insert_entry(fs, device, parent_ino_num, parent_inode, &new_entry)? {
+            return Ok(());
+        }
+        // 索引损坏：清除索引标志，按线性目录处理
+        parent_inode.i_flags &= !Ext4Inode::EXT4_INDEX_FL;
+        write_back_dir_inode(fs, device, parent_ino_num, parent_inode)?;
+    }
+
     let total_size = parent_inode.size() as usize;
     let block_bytes = BLOCK_SIZE;
     let total_blocks = if total_size == 0 {
@@ -173,15 +308,11 @@ pub fn insert_dir_entry<B: BlockDevice>(
         total_size.div_ceil(block_bytes)
     };
 
-    let mut inserted = false;
+    let has_csum = fs.superblock.has_metadata_csum();
 
     let blocks = resolve_inode_block_allextend(fs, device, parent_inode)?;
 
     for lbn in 0..total_blocks {
-        if inserted {
-            break;
-        }
-
         let phys = match blocks.get(&(lbn as u32)) {
             Some(&b) => b,
             None => {
@@ -193,153 +324,45 @@ pub fn insert_dir_entry<B: BlockDevice>(
             }
         };
 
-        let _ = fs.datablock_cache.modify(device, phys as u64, |data| {
-            if inserted {
-                return;
-            }
-
-            let block_bytes = BLOCK_SIZE;
-
-            let mut offset = 0usize;
-            while offset + 8 <= block_bytes {
-                let inode = u32::from_le_bytes([
-                    data[offset],
-                    data[offset + 1],
-                    data[offset + 2],
-                    data[offset + 3],
-                ]);
-                let rec_len = u16::from_le_bytes([data[offset + 4], data[offset + 5]]) as usize;
-                if rec_len < 8 {
-                    return;
-                }
-                let entry_end = offset + rec_len;
-                if entry_end > block_bytes {
-                    return;
-                }
-
-                // Free entry: directly use it if it can hold the new entry.
-                if inode == 0 {
-                    if rec_len >= new_rec_len {
-                        let mut full_entry = new_entry;
-                        full_entry.rec_len = rec_len as u16;
-                        full_entry.to_disk_bytes(&mut data[offset..offset + 8]);
-                        let nlen = full_entry.name_len as usize;
-                        data[offset + 8..offset + 8 + nlen]
-                            .copy_from_slice(&full_entry.name[..nlen]);
-                        inserted = true;
-                    }
-                    return;
-                }
-
-                // Occupied entry: try to split tail space.
-                let cur_name_len = data[offset + 6] as usize;
-                let mut ideal = 8 + cur_name_len;
-                ideal = (ideal + 3) & !3;
-                if ideal <= rec_len {
-                    let tail = rec_len - ideal;
-                    if tail >= new_rec_len {
-                        let ideal_bytes = (ideal as u16).to_le_bytes();
-                        data[offset + 4] = ideal_bytes[0];
-                        data[offset + 5] = ideal_bytes[1];
-
-                        let new_off = offset + ideal;
-                        let mut full_entry = new_entry;
-                        full_entry.rec_len = tail as u16;
-                        full_entry.to_disk_bytes(&mut data[new_off..new_off + 8]);
-                        let nlen = full_entry.name_len as usize;
-                        data[new_off + 8..new_off + 8 + nlen]
-                            .copy_from_slice(&full_entry.name[..nlen]);
-                        inserted = true;
-                        return;
-                    }
-                }
-
-                if entry_end == block_bytes {
-                    return;
-                }
-                offset = entry_end;
-            }
-        });
+        let mut inserted = false;
+        fs.datablock_cache.modify(device, phys, |data| {
+            // 带校验尾部的目录块，尾部 12 字节不参与目录项分配
+            let block_bytes = if has_csum && has_dir_tail(data) {
+                BLOCK_SIZE - Ext4DirEntryTail::TAIL_LEN as usize
+            } else {
+                BLOCK_SIZE
+            };
+            inserted = insert_entry_into_block(data, block_bytes, &new_entry);
+        })?;
+        if inserted {
+            fs.update_dir_block_csum(device, parent_ino_num, phys, false)?;
+            return Ok(());
+        }
     }
 
-    if inserted {
+    // 单块目录已写满：转换为 htree 索引目录，避免后续线性增长
+    if total_blocks == 1
+        && fs
+            .superblock
+            .has_feature_compat(Ext4Superblock::EXT4_FEATURE_COMPAT_DIR_INDEX)
+        && make_indexed_dir(fs, device, parent_ino_num, parent_inode, &new_entry)?
+    {
         return Ok(());
     }
 
     // 所有现有逻辑块都无法容纳新目录项：为目录分配一个新数据块，并扩展 inode 映射
-    let new_block = fs.alloc_block(device)?;
+    let (_new_lbn, new_block) = append_dir_block(fs, device, parent_ino_num, parent_inode)?;
 
-    // 更新 parent_inode 的块映射（extent 或直接块）和大小统计
-    let block_bytes = BLOCK_SIZE;
-    let old_blocks = if total_size == 0 {
-        0
-    } else {
-        total_size.div_ceil(block_bytes)
-    };
-    let new_lbn = old_blocks as u32; // 新块对应的逻辑块号
-
-    if fs.superblock.has_extents() && parent_inode.have_extend_header_and_use_extend() {
-        // extent 目录：通过 ExtentTree 追加一个长度为 1 的 extent
-        let new_ext = Ext4Extent::new(new_lbn, new_block, 1);
-        let mut tree = ExtentTree::new(parent_inode);
-        tree.insert_extent(fs, new_ext, device)?;
-    } else {
-        // 传统直接块模式：仅支持追加到前 12 个直接块
-        if old_blocks >= 12 {
-            return Err(BlockDevError::Unsupported);
-        }
-        parent_inode.i_block[old_blocks] = new_block as u32;
-    }
-
-    // 更新 parent_inode 的 i_size / i_blocks，并写回 inode 表
-    let new_size = total_size + block_bytes;
-    parent_inode.i_size_lo = new_size as u32;
-    parent_inode.i_size_high = ((new_size as u64) >> 32) as u32;
-    // fix:extend元数据也会占block，不能仅仅靠现有blocks_count计算，需要考虑extent树的开销
-    let cur = parent_inode.blocks_count();
-    let add_sectors = BLOCK_SIZE as u64 / 512;
-    let newv = cur.saturating_add(add_sectors);
-    parent_inode.i_blocks_lo = (newv & 0xffff_ffff) as u32;
-    parent_inode.l_i_blocks_high = ((newv >> 32) & 0xffff) as u16;
-
-    let (p_group, _pidx) = fs.inode_allocator.global_to_group(parent_ino_num);
-    let inode_table_start = match fs.group_descs.get(p_group as usize) {
-        Some(desc) => desc.inode_table(),
-        None => return Err(BlockDevError::Corrupted),
-    };
-    let (p_block_num, p_offset, _pg) = fs.inodetable_cahce.calc_inode_location(
-        parent_ino_num,
-        fs.superblock.s_inodes_per_group,
-        inode_table_start,
-        BLOCK_SIZE,
-    );
-
-    fs.inodetable_cahce.modify(
-        device,
-        parent_ino_num as u64,
-        p_block_num,
-        p_offset,
-        |inode| {
-            inode.i_size_lo = parent_inode.i_size_lo;
-            inode.i_size_high = parent_inode.i_size_high;
-            inode.i_blocks_lo = parent_inode.i_blocks_lo;
-            inode.l_i_blocks_high = parent_inode.l_i_blocks_high;
-            inode.i_flags = parent_inode.i_flags;
-            inode.i_block = parent_inode.i_block;
-        },
-    )?;
-
-    // 在新分配的数据块中写入唯一的目录项，占满整个块
+    // 在新分配的数据块中写入唯一的目录项，占满整个块（metadata_csum 下留出尾部）
+    let payload_len = fs.dir_block_payload_len();
     fs.datablock_cache.modify(device, new_block, |data| {
-        for b in data.iter_mut() {
-            *b = 0;
-        }
         let mut full_entry = new_entry;
-        full_entry.rec_len = BLOCK_SIZE as u16;
+        full_entry.rec_len = payload_len as u16;
         full_entry.to_disk_bytes(&mut data[0..8]);
         let nlen = full_entry.name_len as usize;
         data[8..8 + nlen].copy_from_slice(&full_entry.name[..nlen]);
     })?;
+    fs.update_dir_block_csum(device, parent_ino_num, new_block, true)?;
 
     Ok(())
 }
@@ -488,6 +511,7 @@ pub fn mkdir_with_ino<B: BlockDevice>(
     };
 
     // 初始化新目录的数据块：写 '.' 和 '..'
+    let payload_len = fs.dir_block_payload_len() as u16;
     {
         let cached = fs.datablock_cache.create_new(data_block);
         let data = &mut cached.data;
@@ -502,7 +526,7 @@ pub fn mkdir_with_ino<B: BlockDevice>(
         );
 
         let dotdot_name = b"..";
-        let dotdot_rec_len = (BLOCK_SIZE as u16).saturating_sub(dot_rec_len);
+        let dotdot_rec_len = payload_len.saturating_sub(dot_rec_len);
         let dotdot = Ext4DirEntry2::new(
             parent_ino_num,
             dotdot_rec_len,
@@ -531,7 +555,7 @@ pub fn mkdir_with_ino<B: BlockDevice>(
     let mut inode_pre = fs
         .get_inode_by_num(device, new_dir_ino)
         .expect("Can't getinode");
-    build_file_block_mapping(fs, &mut inode_pre, &[data_block], device);
+    build_file_block_mapping(fs, new_dir_ino, &mut inode_pre, &[data_block], device);
     if fs
         .modify_inode(device, new_dir_ino, |inode| {
             inode.i_block = inode_pre.i_block;
@@ -551,6 +575,10 @@ pub fn mkdir_with_ino<B: BlockDevice>(
         error!("mkdir modify_inode failed path={path} ino={new_dir_ino}");
         return None;
     }
+    if let Err(e) = fs.update_dir_block_csum(device, new_dir_ino, data_block, true) {
+        error!("mkdir update_dir_block_csum failed path={path} ino={new_dir_ino} err={e:?}");
+        return None;
+    }
 
     // 更新父目录的i_links_count+1
     {
@@ -631,6 +659,7 @@ pub fn create_root_directory_entry<B: BlockDevice>(
     let data_block = fs.alloc_block(block_dev)?;
 
     //  写入目录项 . 和 ..
+    let payload_len = fs.dir_block_payload_len() as u16;
     {
         let cached = fs.datablock_cache.create_new(data_block);
         let data = &mut cached.data;
@@ -647,7 +676,7 @@ pub fn create_root_directory_entry<B: BlockDevice>(
 
         // ..目录项（根的父目录仍为自己）
         let dotdot_name = b"..";
-        let dotdot_rec_len = (BLOCK_SIZE as u16).saturating_sub(dot_rec_len);
+        let dotdot_rec_len = payload_len.saturating_sub(dot_rec_len);
         let dotdot = Ext4DirEntry2::new(
             root_inode_num,
             dotdot_rec_len,
@@ -674,7 +703,7 @@ pub fn create_root_directory_entry<B: BlockDevice>(
     let mut inode_pre = fs
         .get_inode_by_num(block_dev, root_inode_num)
         .expect("Can't getinode");
-    build_file_block_mapping(fs, &mut inode_pre, &[data_block], block_dev);
+    build_file_block_mapping(fs, root_inode_num, &mut inode_pre, &[data_block], block_dev);
 
     fs.modify_inode(block_dev, fs.root_inode, |inode| {
         inode.i_flags = inode_pre.i_flags;
@@ -687,6 +716,7 @@ pub fn create_root_directory_entry<B: BlockDevice>(
         inode.i_blocks_lo = (BLOCK_SIZE / 512) as u32;
         inode.l_i_blocks_high = 0;
     })?;
+    fs.update_dir_block_csum(block_dev, root_inode_num, data_block, true)?;
 
     // 块组描述符更新 目录数
     if let Some(desc) = fs.get_group_desc_mut(0) {
@@ -722,6 +752,7 @@ pub fn create_lost_found_directory<B: BlockDevice>(
     let data_block = fs.alloc_block(block_dev)?;
 
     //  初始化 lost+found 目录块（".", ".."）
+    let payload_len = fs.dir_block_payload_len() as u16;
     {
         let cached = fs.datablock_cache.create_new(data_block);
         let data = &mut cached.data;
@@ -731,7 +762,7 @@ pub fn create_lost_found_directory<B: BlockDevice>(
         let dot = Ext4DirEntry2::new(lost_ino, dot_rec_len, Ext4DirEntry2::EXT4_FT_DIR, dot_name);
 
         let dotdot_name = b"..";
-        let dotdot_rec_len = (BLOCK_SIZE as u16).saturating_sub(dot_rec_len);
+        let dotdot_rec_len = payload_len.saturating_sub(dot_rec_len);
         let dotdot = Ext4DirEntry2::new(
             root_inode_num,
             dotdot_rec_len,
@@ -760,7 +791,7 @@ pub fn create_lost_found_directory<B: BlockDevice>(
     let mut inode_pre = fs
         .get_inode_by_num(block_dev, lost_ino)
         .expect("Can't getinode");
-    build_file_block_mapping(fs, &mut inode_pre, &[data_block], block_dev);
+    build_file_block_mapping(fs, lost_ino, &mut inode_pre, &[data_block], block_dev);
     debug!(
         "When create lost+found inode iblock,:{:?} ,data_block:{:?}",
         inode_pre.i_block, data_block
@@ -775,6 +806,7 @@ pub fn create_lost_found_directory<B: BlockDevice>(
         inode.i_size_lo = BLOCK_SIZE as u32;
         inode.i_blocks_lo = (BLOCK_SIZE / 512) as u32;
     })?;
+    fs.update_dir_block_csum(block_dev, lost_ino, data_block, true)?;
 
     if let Some(desc) = fs.get_group_desc_mut(lf_group) {
         let newc = desc.used_dirs_count().saturating_add(1);
@@ -814,7 +846,7 @@ pub fn create_lost_found_directory<B: BlockDevice>(
             );
 
             let lf_name = b"lost+found";
-            let lf_rec_len = (BLOCK_SIZE as u16).saturating_sub(dot_rec_len + dotdot_rec_len);
+            let lf_rec_len = payload_len.saturating_sub(dot_rec_len + dotdot_rec_len);
             let lost =
                 Ext4DirEntry2::new(lost_ino, lf_rec_len, Ext4DirEntry2::EXT4_FT_DIR, lf_name);
 
@@ -840,6 +872,7 @@ pub fn create_lost_found_directory<B: BlockDevice>(
             let lf_len = lost.name_len as usize;
             data[offset + 8..offset + 8 + lf_len].copy_from_slice(&lost.name[..lf_len]);
         })?;
+    fs.update_dir_block_csum(block_dev, root_inode_num, root_block as u64, true)?;
 
     //  更新根 inode 的链接计数（多了一个子目录）
     let inode_table_start = match fs.group_descs.first() {
@@ -300,58 +300,188 @@ pub mod classic_dir {
 pub mod htree_dir {
     use super::*;
 
-    /// 计算文件名的哈希值
+    /// 超级块 s_flags：目录哈希使用无符号字符
+    pub const EXT2_FLAGS_UNSIGNED_HASH: u32 = 0x0002;
+    /// 32位哈希的 EOF 标记
+    const EXT4_HTREE_EOF_32BIT: u32 = 0x7fff_ffff;
+    /// 种子全零时使用的默认哈希种子
+    const DEFAULT_SEED: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+
+    /// 根据根节点哈希版本和超级块标志得到实际使用的哈希版本
+    pub fn effective_hash_version(root_version: u8, s_flags: u32) -> u8 {
+        if root_version <= Ext4DxRootInfo::DX_HASH_TEA && s_flags & EXT2_FLAGS_UNSIGNED_HASH != 0 {
+            root_version + 3
+        } else {
+            root_version
+        }
+    }
+
+    /// 计算文件名的哈希值（与内核 ext4fs_dirhash 一致，最低位恒为0）
     pub fn calculate_hash(name: &[u8], hash_version: u8, hash_seed: &[u32; 4]) -> u32 {
-        match hash_version {
-            Ext4DxRootInfo::DX_HASH_LEGACY => legacy_hash(name),
-            Ext4DxRootInfo::DX_HASH_HALF_MD4 => half_md4_hash(name, hash_seed),
-            Ext4DxRootInfo::DX_HASH_TEA => tea_hash(name, hash_seed),
+        let mut buf = if hash_seed.iter().any(|&w| w != 0) {
+            *hash_seed
+        } else {
+            DEFAULT_SEED
+        };
+
+        let hash = match hash_version {
+            Ext4DxRootInfo::DX_HASH_LEGACY => dx_hack_hash(name, true),
+            Ext4DxRootInfo::DX_HASH_LEGACY_UNSIGNED => dx_hack_hash(name, false),
+            Ext4DxRootInfo::DX_HASH_HALF_MD4 | Ext4DxRootInfo::DX_HASH_HALF_MD4_UNSIGNED => {
+                let signed = hash_version == Ext4DxRootInfo::DX_HASH_HALF_MD4;
+                let mut input = [0u32; 8];
+                for chunk_start in (0..name.len()).step_by(32) {
+                    str2hashbuf(&name[chunk_start..], signed, &mut input);
+                    half_md4_transform(&mut buf, &input);
+                }
+                buf[1]
+            }
+            Ext4DxRootInfo::DX_HASH_TEA | Ext4DxRootInfo::DX_HASH_TEA_UNSIGNED => {
+                let signed = hash_version == Ext4DxRootInfo::DX_HASH_TEA;
+                let mut input = [0u32; 4];
+                for chunk_start in (0..name.len()).step_by(16) {
+                    str2hashbuf(&name[chunk_start..], signed, &mut input);
+                    tea_transform(&mut buf, &input);
+                }
+                buf[0]
+            }
             _ => 0,
+        };
+
+        let hash = hash & !1;
+        if hash == EXT4_HTREE_EOF_32BIT << 1 {
+            (EXT4_HTREE_EOF_32BIT - 1) << 1
+        } else {
+            hash
         }
     }
 
-    /// 传统哈希算法（简化实现）
-    fn legacy_hash(name: &[u8]) -> u32 {
-        let mut hash = 0u32;
-        for &byte in name {
-            hash = hash.wrapping_mul(33).wrapping_add(byte as u32);
+    /// 按有符号/无符号字符取字节值
+    fn char_value(byte: u8, signed: bool) -> u32 {
+        if signed {
+            byte as i8 as i32 as u32
+        } else {
+            byte as u32
         }
-        hash
     }
 
-    /// Half MD4哈希算法（简化实现）
-    fn half_md4_hash(name: &[u8], seed: &[u32; 4]) -> u32 {
-        // 这是一个简化版本，实际实现需要完整的MD4算法
-        let mut hash = seed[0];
+    /// 传统哈希算法（dx_hack_hash）
+    fn dx_hack_hash(name: &[u8], signed: bool) -> u32 {
+        let mut hash0: u32 = 0x12a3_fe2d;
+        let mut hash1: u32 = 0x37ab_e8f9;
         for &byte in name {
-            hash = hash.wrapping_mul(1103515245).wrapping_add(byte as u32);
+            let mut hash =
+                hash1.wrapping_add(hash0 ^ char_value(byte, signed).wrapping_mul(7_152_373));
+            if hash & 0x8000_0000 != 0 {
+                hash = hash.wrapping_sub(0x7fff_ffff);
+            }
+            hash1 = hash0;
+            hash0 = hash;
         }
-        hash
+        hash0 << 1
     }
 
-    /// TEA哈希算法（Tiny Encryption Algorithm）
-    fn tea_hash(name: &[u8], seed: &[u32; 4]) -> u32 {
-        let mut hash = seed[0];
-        let mut buf = [0u32; 4];
-
-        for chunk in name.chunks(16) {
-            for (i, bytes) in chunk.chunks(4).enumerate() {
-                if i >= 4 {
-                    break;
-                }
-                let mut val = 0u32;
-                for &b in bytes {
-                    val = (val << 8) | b as u32;
-                }
-                buf[i] = val;
+    /// 将文件名打包为哈希输入字（str2hashbuf）
+    fn str2hashbuf(msg: &[u8], signed: bool, out: &mut [u32]) {
+        let num = out.len();
+        let len = msg.len();
+        let mut pad = (len as u32) | ((len as u32) << 8);
+        pad |= pad << 16;
+
+        let mut val = pad;
+        let mut idx = 0;
+        for (i, &byte) in msg.iter().take(num * 4).enumerate() {
+            val = char_value(byte, signed).wrapping_add(val << 8);
+            if i % 4 == 3 {
+                out[idx] = val;
+                idx += 1;
+                val = pad;
             }
+        }
+        if idx < num {
+            out[idx] = val;
+            idx += 1;
+        }
+        for word in &mut out[idx..] {
+            *word = pad;
+        }
+    }
 
-            // TEA算法的简化版本
-            for _ in 0..4 {
-                hash = hash.wrapping_add(buf[0] ^ buf[1]);
-            }
+    /// Half MD4 变换
+    fn half_md4_transform(buf: &mut [u32; 4], input: &[u32; 8]) {
+        const K2: u32 = 0o13240474631;
+        const K3: u32 = 0o15666365641;
+
+        fn f(x: u32, y: u32, z: u32) -> u32 {
+            z ^ (x & (y ^ z))
+        }
+        fn g(x: u32, y: u32, z: u32) -> u32 {
+            (x & y).wrapping_add((x ^ y) & z)
+        }
+        fn h(x: u32, y: u32, z: u32) -> u32 {
+            x ^ y ^ z
+        }
+
+        let [mut a, mut b, mut c, mut d] = *buf;
+        macro_rules! round {
+            ($f:ident, $a:ident, $b:ident, $c:ident, $d:ident, $x:expr, $s:expr) => {
+                $a = $a
+                    .wrapping_add($f($b, $c, $d))
+                    .wrapping_add($x)
+                    .rotate_left($s);
+            };
+        }
+
+        round!(f, a, b, c, d, input[0], 3);
+        round!(f, d, a, b, c, input[1], 7);
+        round!(f, c, d, a, b, input[2], 11);
+        round!(f, b, c, d, a, input[3], 19);
+        round!(f, a, b, c, d, input[4], 3);
+        round!(f, d, a, b, c, input[5], 7);
+        round!(f, c, d, a, b, input[6], 11);
+        round!(f, b, c, d, a, input[7], 19);
+
+        round!(g, a, b, c, d, input[1].wrapping_add(K2), 3);
+        round!(g, d, a, b, c, input[3].wrapping_add(K2), 5);
+        round!(g, c, d, a, b, input[5].wrapping_add(K2), 9);
+        round!(g, b, c, d, a, input[7].wrapping_add(K2), 13);
+        round!(g, a, b, c, d, input[0].wrapping_add(K2), 3);
+        round!(g, d, a, b, c, input[2].wrapping_add(K2), 5);
+        round!(g, c, d, a, b, input[4].wrapping_add(K2), 9);
+        round!(g, b, c, d, a, input[6].wrapping_add(K2), 13);
+
+        round!(h, a, b, c, d, input[3].wrapping_add(K3), 3);
+        round!(h, d, a, b, c, input[7].wrapping_add(K3), 9);
+        round!(h, c, d, a, b, input[2].wrapping_add(K3), 11);
+        round!(h, b, c, d, a, input[6].wrapping_add(K3), 15);
+        round!(h, a, b, c, d, input[1].wrapping_add(K3), 3);
+        round!(h, d, a, b, c, input[5].wrapping_add(K3), 9);
+        round!(h, c, d, a, b, input[0].wrapping_add(K3), 11);
+        round!(h, b, c, d, a, input[4].wrapping_add(K3), 15);
+
+        buf[0] = buf[0].wrapping_add(a);
+        buf[1] = buf[1].wrapping_add(b);
+        buf[2] = buf[2].wrapping_add(c);
+        buf[3] = buf[3].wrapping_add(d);
+    }
+
+    /// TEA 变换（Tiny Encryption Algorithm）
+    fn tea_transform(buf: &mut [u32; 4], input: &[u32; 4]) {
+        const DELTA: u32 = 0x9E37_79B9;
+        let mut sum: u32 = 0;
+        let (mut b0, mut b1) = (buf[0], buf[1]);
+        let [a, b, c, d] = *input;
+        for _ in 0..16 {
+            sum = sum.wrapping_add(DELTA);
+            b0 = b0.wrapping_add(
+                ((b1 << 4).wrapping_add(a)) ^ b1.wrapping_add(sum) ^ ((b1 >> 5).wrapping_add(b)),
+            );
+            b1 = b1.wrapping_add(
+                ((b0 << 4).wrapping_add(c)) ^ b0.wrapping_add(sum) ^ ((b0 >> 5).wrapping_add(d)),
+            );
         }
-        hash
+        buf[0] = buf[0].wrapping_add(b0);
+        buf[1] = buf[1].wrapping_add(b1);
     }
 }
 
@@ -113,6 +113,8 @@ pub enum RSEXT4Error {
     UnsupportedFeature,
     /// 已经挂载
     AlreadyMounted,
+    /// 元数据校验和不匹配
+    ChecksumMismatch,
 }
 
 impl core::fmt::Display for RSEXT4Error {
@@ -124,6 +126,7 @@ impl core::fmt::Display for RSEXT4Error {
             RSEXT4Error::FilesystemHasErrors => write!(f, "文件系统有错误"),
             RSEXT4Error::UnsupportedFeature => write!(f, "不支持的特性"),
             RSEXT4Error::AlreadyMounted => write!(f, "文件系统已挂载"),
+            RSEXT4Error::ChecksumMismatch => write!(f, "元数据校验和不匹配"),
         }
     }
 }
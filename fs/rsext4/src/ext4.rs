@@ -2,7 +2,10 @@
 //!
 //! 提供 ext4 文件系统的核心实现，包括文件系统挂载、卸载、文件操作等高层接口。
 
-use alloc::{collections::vec_deque::VecDeque, vec::Vec};
+use alloc::{
+    collections::{BTreeSet, vec_deque::VecDeque},
+    vec::Vec,
+};
 
 use log::{debug, error, info, trace, warn};
 
@@ -12,15 +15,19 @@ use crate::{
     blockdev::*,
     blockgroup_description::*,
     bmalloc::*,
+    checksum::*,
     config::*,
     datablock_cache::*,
+    delalloc::{self, DelayedAlloc},
     dir::*,
     disknode::*,
     endian::*,
+    entries::Ext4DirEntryTail,
     error::*,
     inodetable_cache::*,
     jbd2::{jbd2::*, jbdstruct::*},
     loopfile::*,
+    orphan,
     superblock::*,
     tool::*,
 };
@@ -42,6 +49,7 @@ use crate::{
 /// * `group_count` - 块组数量
 /// * `mounted` - 是否已挂载标志
 /// * `journal_sb_block_start` - Journal 超级块起始块号
+/// * `delalloc` - 延迟分配缓存和预留窗口
 pub struct Ext4FileSystem {
     /// 超级块
     pub superblock: Ext4Superblock,
@@ -65,6 +73,10 @@ pub struct Ext4FileSystem {
     pub mounted: bool,
     /// Journal 超级块 开始块号
     pub journal_sb_block_start: Option<u32>,
+    /// 延迟分配状态
+    pub delalloc: DelayedAlloc,
+    /// 已释放、尚未下发 discard 的数据块
+    pub freed_blocks: BTreeSet<u64>,
 }
 
 impl Ext4FileSystem {
@@ -197,6 +209,43 @@ impl Ext4FileSystem {
         create_root_directory_entry(self, block_dev)
     }
 
+    /// 按超级块中的 inode 大小与校验和种子创建 inode 缓存
+    fn new_inode_cache(superblock: &Ext4Superblock) -> InodeCache {
+        let inode_size = match superblock.s_inode_size {
+            0 => DEFAULT_INODE_SIZE as usize,
+            n => n as usize,
+        };
+        let mut inode_cache = InodeCache::new(INODE_CACHE_MAX, inode_size);
+        if superblock.has_metadata_csum() {
+            inode_cache.set_csum_seed(superblock.csum_seed());
+        }
+        inode_cache
+    }
+
+    /// 日志重放改写了磁盘上的元数据：重新读取超级块与块组描述符，丢弃重放前加载的缓存
+    fn reload_metadata<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+    ) -> Result<(), RSEXT4Error> {
+        let superblock = read_superblock(block_dev).map_err(|_| RSEXT4Error::IoError)?;
+        if superblock.s_magic != EXT4_SUPER_MAGIC {
+            error!(
+                "Invalid magic after journal replay: {:#x}",
+                superblock.s_magic
+            );
+            return Err(RSEXT4Error::InvalidMagic);
+        }
+        self.group_count = superblock.block_groups_count();
+        self.group_descs = Self::load_group_descriptors(block_dev, self.group_count)?;
+        self.block_allocator = BlockAllocator::new(&superblock);
+        self.inode_allocator = InodeAllocator::new(&superblock);
+        self.bitmap_cache = BitmapCache::create_default();
+        self.inodetable_cahce = Self::new_inode_cache(&superblock);
+        self.datablock_cache = DataBlockCache::new(DATABLOCK_CACHE_MAX, BLOCK_SIZE);
+        self.superblock = superblock;
+        Ok(())
+    }
+
     /// 打开Ext4文件系统
     pub fn mount<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> Result<Self, RSEXT4Error> {
         debug!("Start mounting Ext4 filesystem...");
@@ -217,6 +266,29 @@ impl Ext4FileSystem {
         }
         debug!("Superblock magic verified");
 
+        // 2.5 校验块大小、特性与超级块校验和
+        if superblock.block_size() != BLOCK_SIZE as u64 {
+            error!(
+                "Unsupported block size: {}, expected: {}",
+                superblock.block_size(),
+                BLOCK_SIZE
+            );
+            return Err(RSEXT4Error::UnsupportedFeature);
+        }
+        let unsupported = superblock.unsupported_incompat();
+        if unsupported != 0 {
+            error!("Unsupported incompat features: {unsupported:#x}");
+            return Err(RSEXT4Error::UnsupportedFeature);
+        }
+        let unsupported = superblock.unsupported_ro_compat();
+        if unsupported != 0 {
+            warn!("Unknown ro_compat features: {unsupported:#x}, metadata may be updated blindly");
+        }
+        if !Self::check_superblock_csum(block_dev, &superblock)? {
+            error!("Superblock checksum mismatch");
+            return Err(RSEXT4Error::ChecksumMismatch);
+        }
+
         // 3. 检查文件系统状态
         if superblock.s_state == Ext4Superblock::EXT4_ERROR_FS {
             warn!("Filesystem is in error state");
@@ -244,11 +316,7 @@ impl Ext4FileSystem {
         // NOTE: inode size is a filesystem property (superblock.s_inode_size), not a fixed constant.
         // Using a wrong inode size will make inode table offsets incorrect and may read zeroed inodes
         // (e.g. /dev becomes mode=0, then VFS mount fails with ENOTDIR).
-        let inode_size = match superblock.s_inode_size {
-            0 => DEFAULT_INODE_SIZE as usize,
-            n => n as usize,
-        };
-        let inode_cache = InodeCache::new(INODE_CACHE_MAX, inode_size);
+        let inode_cache = Self::new_inode_cache(&superblock);
         debug!("Inode cache initialized");
 
         // 初始化数据块缓存
@@ -268,49 +336,12 @@ impl Ext4FileSystem {
             group_count,
             mounted: true,
             journal_sb_block_start: None,
+            delalloc: DelayedAlloc::new(),
+            freed_blocks: BTreeSet::new(),
         };
         // 详细debug输出
         debug_super_and_desc(&fs.superblock, &fs);
 
-        // rootinode check !
-        debug!("Checking root directory...");
-        {
-            let root_inode = fs.get_root(block_dev).map_err(|_| RSEXT4Error::IoError)?;
-            if root_inode.i_mode == 0 || !root_inode.is_dir() {
-                warn!(
-                    "Root inode is uninitialized or not a directory, creating root and \
-                     lost+found... i_mode: {}, is_dir: {}",
-                    root_inode.i_mode,
-                    root_inode.is_dir()
-                );
-                fs.create_root_dir(block_dev)
-                    .map_err(|_| RSEXT4Error::IoError)?;
-            }
-        }
-
-        // lost+found check!
-        debug!("Checking lost+found directory...");
-        {
-            // 1. 优先信任超级块中的 s_lpf_ino（如果非 0）
-            if fs.superblock.s_lpf_ino != 0 {
-                let ino = fs.superblock.s_lpf_ino;
-                debug!("Lost+found inode recorded in superblock: {ino}");
-            } else {
-                warn!("s_lpf_ino is 0, lost+found not recorded in superblock");
-            }
-
-            // 2. 通过路径做一次校验（不会在失败时创建新目录）
-            match find_file(&mut fs, block_dev, "/lost+found") {
-                Some(_inode) => {
-                    info!("/lost+found exists (path resolution)");
-                }
-                None => {
-                    info!("/lost+found not found by path scan;will create!");
-                    create_lost_found_directory(&mut fs, block_dev).ok();
-                }
-            }
-        }
-
         // journal check
         {
             if fs.superblock.has_journal() {
@@ -358,11 +389,71 @@ impl Ext4FileSystem {
                 // 把 journal superblock 交给 Jbd2Dev，由它内部 lazy-init JBD2DEVSYSTEM
                 block_dev.set_journal_superblock(j_sb, fs.journal_sb_block_start.unwrap());
 
-                // Mount-time journal replay for crash recovery.
-                block_dev.journal_replay(); //这里是在读取超级块之后再进行回放的，目前为了快速开启日志时数据不一致问题已经在写入超级块，块组描述符时直接落盘
+                // 挂载时重放日志：必须在使用任何元数据之前完成，重放过事务时重新加载元数据
+                let stats = block_dev
+                    .journal_replay()
+                    .map_err(|_| RSEXT4Error::IoError)?;
+                if stats.transactions > 0 {
+                    info!(
+                        "Journal replayed: {} transactions, {} blocks, {} revoked",
+                        stats.transactions, stats.replayed, stats.revoked
+                    );
+                    fs.reload_metadata(block_dev)?;
+                } else {
+                    fs.datablock_cache.invalidate(journal_first_block as u64);
+                }
+                fs.superblock.s_feature_incompat &= !Ext4Superblock::EXT4_FEATURE_INCOMPAT_RECOVER;
             }
         }
 
+        // rootinode check !
+        debug!("Checking root directory...");
+        {
+            let root_inode = fs.get_root(block_dev).map_err(|_| RSEXT4Error::IoError)?;
+            if root_inode.i_mode == 0 || !root_inode.is_dir() {
+                warn!(
+                    "Root inode is uninitialized or not a directory, creating root and \
+                     lost+found... i_mode: {}, is_dir: {}",
+                    root_inode.i_mode,
+                    root_inode.is_dir()
+                );
+                fs.create_root_dir(block_dev)
+                    .map_err(|_| RSEXT4Error::IoError)?;
+            }
+        }
+
+        // lost+found check!
+        debug!("Checking lost+found directory...");
+        {
+            // 1. 优先信任超级块中的 s_lpf_ino（如果非 0）
+            if fs.superblock.s_lpf_ino != 0 {
+                let ino = fs.superblock.s_lpf_ino;
+                debug!("Lost+found inode recorded in superblock: {ino}");
+            } else {
+                warn!("s_lpf_ino is 0, lost+found not recorded in superblock");
+            }
+
+            // 2. 通过路径做一次校验（不会在失败时创建新目录）
+            match find_file(&mut fs, block_dev, "/lost+found") {
+                Some(_inode) => {
+                    info!("/lost+found exists (path resolution)");
+                }
+                None => {
+                    info!("/lost+found not found by path scan;will create!");
+                    create_lost_found_directory(&mut fs, block_dev).ok();
+                }
+            }
+        }
+
+        // 处理上次未完成的删除 / 截断留下的孤儿 inode
+        if fs.superblock.s_last_orphan != 0 {
+            orphan::process_orphan_list(&mut fs, block_dev).map_err(|_| RSEXT4Error::IoError)?;
+            fs.sync_group_descriptors(block_dev)
+                .map_err(|_| RSEXT4Error::IoError)?;
+            fs.sync_superblock(block_dev)
+                .map_err(|_| RSEXT4Error::IoError)?;
+        }
+
         // 详细的Inode/DataBlock占用情况
         {
             let g0 = match fs.group_descs.first() {
@@ -440,6 +531,27 @@ impl Ext4FileSystem {
         Ok(fs)
     }
 
+    /// 重新读取磁盘上的原始超级块并校验 metadata_csum 校验和
+    pub(crate) fn check_superblock_csum<B: BlockDevice>(
+        block_dev: &mut Jbd2Dev<B>,
+        superblock: &Ext4Superblock,
+    ) -> Result<bool, RSEXT4Error> {
+        if !superblock.has_metadata_csum() {
+            return Ok(true);
+        }
+        let (block_num, offset) = if BLOCK_SIZE == 1024 {
+            (1, 0)
+        } else {
+            (0, Ext4Superblock::SUPERBLOCK_OFFSET as usize)
+        };
+        block_dev
+            .read_block(block_num)
+            .map_err(|_| RSEXT4Error::IoError)?;
+        let buffer = block_dev.buffer();
+        let raw = &buffer[offset..offset + Ext4Superblock::SUPERBLOCK_SIZE];
+        Ok(verify_superblock_csum(superblock, raw))
+    }
+
     /// 加载所有块组描述符 顺序性
     fn load_group_descriptors<B: BlockDevice>(
         block_dev: &mut Jbd2Dev<B>,
@@ -482,7 +594,12 @@ impl Ext4FileSystem {
                 return Err(RSEXT4Error::InvalidSuperblock);
             }
 
-            let desc = Ext4GroupDesc::from_disk_bytes(&buffer[in_block..end]);
+            let raw = &buffer[in_block..end];
+            if !Ext4GroupDesc::verify_csum(&superblock, group_id, raw) {
+                error!("Group descriptor {group_id} checksum mismatch");
+                return Err(RSEXT4Error::ChecksumMismatch);
+            }
+            let desc = Ext4GroupDesc::from_disk_bytes(raw);
             group_descs.push(desc);
         }
 
@@ -501,6 +618,10 @@ impl Ext4FileSystem {
 
         debug!("Unmounting Ext4 filesystem...");
 
+        // 0. 为延迟分配的数据分配物理块
+        delalloc::flush_all(self, block_dev)?;
+        debug!("Delayed allocation flushed");
+
         // 1. Flush dirty caches
         info!("Flushing bitmap cache...");
         self.bitmap_cache.flush_all(block_dev)?;
@@ -520,7 +641,10 @@ impl Ext4FileSystem {
         self.sync_group_descriptors(block_dev)?;
 
         // 确保缓存已经提交完毕
-        block_dev.umount_commit();
+        block_dev.umount_commit()?;
+
+        // 事务已提交，释放的块可以安全丢弃
+        self.discard_freed(block_dev);
 
         self.mounted = false;
         info!("Filesystem unmounted cleanly");
@@ -528,6 +652,70 @@ impl Ext4FileSystem {
         Ok(())
     }
 
+    /// 后台回写：将位图和数据块缓存中的脏块写回磁盘，返回写回的块数
+    ///
+    /// 与 [`umount`](Self::umount) 不同，不分配延迟分配的数据，也不写超级块
+    pub fn writeback<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+    ) -> BlockDevResult<usize> {
+        let bitmaps = self.bitmap_cache.writeback(block_dev)?;
+        let blocks = self.datablock_cache.writeback(block_dev)?;
+        // 启用日志时，位图的修改要等事务提交后才算落盘，留到 umount 再丢弃
+        if !block_dev.is_use_journal() {
+            self.discard_freed(block_dev);
+        }
+        Ok(bitmaps + blocks)
+    }
+
+    /// 对释放后未再分配的数据块下发 discard，连续的块合并为一次请求，
+    /// 返回丢弃的块数
+    ///
+    /// 须在释放这些块的位图落盘后调用：否则崩溃后仍被引用的块可能已被丢弃。
+    /// discard 只是提示，失败时仅记录日志，不再重试
+    pub fn discard_freed<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> usize {
+        let freed = core::mem::take(&mut self.freed_blocks);
+        let mut discarded = 0;
+        let mut blocks = freed.into_iter().peekable();
+        while let Some(start) = blocks.next() {
+            let mut count = 1u32;
+            while blocks.next_if_eq(&(start + count as u64)).is_some() {
+                count += 1;
+            }
+            for block in start..start + count as u64 {
+                self.datablock_cache.invalidate(block);
+            }
+            match block_dev.discard(start as u32, count) {
+                Ok(()) => discarded += count as usize,
+                Err(e) => warn!("discard of {count} blocks at {start} failed: {e:?}"),
+            }
+        }
+        discarded
+    }
+
+    /// 重新分配的块不再需要丢弃
+    fn reuse_freed(&mut self, start: u64, count: u64) {
+        let mut reused = self.freed_blocks.split_off(&start);
+        let mut after = reused.split_off(&(start + count));
+        self.freed_blocks.append(&mut after);
+    }
+
+    /// 共享的缓冲区缓存超出预算时，丢弃各缓存中的干净块，返回丢弃的块数
+    pub fn shrink_caches(&mut self) -> usize {
+        self.bitmap_cache.shrink() + self.datablock_cache.shrink()
+    }
+
+    /// 内存不足时丢弃各缓存中的干净块，不论预算，直到释放约 `bytes`
+    /// 字节（不分配内存），返回释放的字节数。优先丢弃数据块
+    pub fn reclaim_caches(&mut self, bytes: usize) -> usize {
+        let blocks = bytes.div_ceil(BLOCK_SIZE);
+        let mut dropped = self.datablock_cache.reclaim(blocks);
+        if dropped < blocks {
+            dropped += self.bitmap_cache.reclaim(blocks - dropped);
+        }
+        dropped * BLOCK_SIZE
+    }
+
     /// 同步块组描述符到磁盘
     /// 按 ext4 标准布局，将所有块组描述符写回：
     /// GDT 字节流紧跟在超级块之后
@@ -584,7 +772,7 @@ impl Ext4FileSystem {
                 return Err(BlockDevError::Corrupted);
             }
 
-            desc.to_disk_bytes(&mut buffer[in_block..end]);
+            desc.to_disk_bytes_with_csum(&self.superblock, idx as u32, &mut buffer[in_block..end]);
         }
 
         // 写回最后一个块
@@ -628,6 +816,204 @@ impl Ext4FileSystem {
         self.group_descs.get_mut(group_idx as usize)
     }
 
+    /// 计算指定 inode 的 metadata_csum 校验种子，未启用 metadata_csum 时返回 None
+    ///
+    /// 目录块与 extent 块的校验和都以该种子为初值
+    pub fn inode_csum_seed(&self, inode_num: u32, inode: &Ext4Inode) -> Option<u32> {
+        self.superblock
+            .has_metadata_csum()
+            .then(|| inode_csum_seed(self.superblock.csum_seed(), inode_num, inode.i_generation))
+    }
+
+    /// 目录块中可用于存放目录项的字节数（metadata_csum 下块尾预留 12 字节校验项）
+    pub fn dir_block_payload_len(&self) -> usize {
+        if self.superblock.has_metadata_csum() {
+            BLOCK_SIZE - Ext4DirEntryTail::TAIL_LEN as usize
+        } else {
+            BLOCK_SIZE
+        }
+    }
+
+    /// 刷新目录块尾部校验和（仅 metadata_csum）
+    ///
+    /// `init_tail` 为 true 时无条件写入尾部项（新建的目录块）；否则只处理已带尾部的块，
+    /// 避免覆盖 htree 索引块等没有尾部的目录块
+    pub fn update_dir_block_csum<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        dir_ino: u32,
+        block_num: u64,
+        init_tail: bool,
+    ) -> BlockDevResult<()> {
+        if !self.superblock.has_metadata_csum() {
+            return Ok(());
+        }
+        let dir_inode = self.get_inode_by_num(block_dev, dir_ino)?;
+        let Some(seed) = self.inode_csum_seed(dir_ino, &dir_inode) else {
+            return Ok(());
+        };
+        self.datablock_cache.modify(block_dev, block_num, |data| {
+            if init_tail || has_dir_tail(data) {
+                set_dir_block_csum(seed, data);
+            }
+        })
+    }
+
+    /// 刷新 htree 索引块尾部（dx_tail）校验和（仅 metadata_csum）
+    pub fn update_dx_node_csum<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        dir_ino: u32,
+        block_num: u64,
+    ) -> BlockDevResult<()> {
+        if !self.superblock.has_metadata_csum() {
+            return Ok(());
+        }
+        let dir_inode = self.get_inode_by_num(block_dev, dir_ino)?;
+        let Some(seed) = self.inode_csum_seed(dir_ino, &dir_inode) else {
+            return Ok(());
+        };
+        self.datablock_cache
+            .modify(block_dev, block_num, |data| set_dx_node_csum(seed, data))
+    }
+
+    /// 为带 BLOCK_UNINIT / INODE_UNINIT 标志的块组在缓存中构造位图，并清除对应标志
+    ///
+    /// 这类块组的位图块在磁盘上未初始化，直接读盘会得到垃圾数据
+    fn init_uninit_bitmap<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        key: CacheKey,
+    ) -> BlockDevResult<()> {
+        let group_idx = key.group_id;
+        let desc = *self
+            .get_group_desc(group_idx)
+            .ok_or(BlockDevError::Corrupted)?;
+
+        let (flag, block_num, data) = match key.bitmap_type {
+            BitmapType::Block => {
+                if !desc.is_block_bitmap_uninit() {
+                    return Ok(());
+                }
+                let data = self.build_uninit_block_bitmap(group_idx, &desc);
+                (
+                    Ext4GroupDesc::EXT4_BG_BLOCK_UNINIT,
+                    desc.block_bitmap(),
+                    data,
+                )
+            }
+            BitmapType::Inode => {
+                if !desc.is_inode_bitmap_uninit() {
+                    return Ok(());
+                }
+                let mut data = alloc::vec![0u8; BLOCK_SIZE];
+                // 超出 inodes_per_group 的位填 1
+                for bit in self.superblock.s_inodes_per_group as usize..BLOCK_SIZE * 8 {
+                    data[bit / 8] |= 1 << (bit % 8);
+                }
+                (
+                    Ext4GroupDesc::EXT4_BG_INODE_UNINIT,
+                    desc.inode_bitmap(),
+                    data,
+                )
+            }
+        };
+
+        debug!(
+            "init_uninit_bitmap: group={group_idx} type={:?}",
+            key.bitmap_type
+        );
+        self.bitmap_cache
+            .insert_new(block_dev, key, block_num, data)?;
+        if let Some(desc_mut) = self.get_group_desc_mut(group_idx) {
+            desc_mut.bg_flags &= !flag;
+        }
+        Ok(())
+    }
+
+    /// 按块组布局构造未初始化块组的块位图：超级块/GDT 备份、位图与 inode 表所在块标记为已用
+    fn build_uninit_block_bitmap(&self, group_idx: u32, desc: &Ext4GroupDesc) -> Vec<u8> {
+        let sb = &self.superblock;
+        let mut data = alloc::vec![0u8; BLOCK_SIZE];
+        let blocks_per_group = sb.blocks_per_group() as u64;
+        let group_start = sb.s_first_data_block as u64 + group_idx as u64 * blocks_per_group;
+        let group_end = sb.blocks_count().min(group_start + blocks_per_group);
+
+        let mut mark = |block: u64| {
+            if (group_start..group_end).contains(&block) {
+                let bit = (block - group_start) as usize;
+                data[bit / 8] |= 1 << (bit % 8);
+            }
+        };
+
+        let sparse = sb.has_feature_ro_compat(Ext4Superblock::EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER);
+        if !sparse || need_redundant_backup(group_idx) {
+            let descs_per_block = (BLOCK_SIZE / sb.get_desc_size() as usize) as u64;
+            let gdt_blocks = (self.group_count as u64).div_ceil(descs_per_block);
+            let backup_blocks = 1 + gdt_blocks + sb.s_reserved_gdt_blocks as u64;
+            for off in 0..backup_blocks {
+                mark(group_start + off);
+            }
+        }
+        mark(desc.block_bitmap());
+        mark(desc.inode_bitmap());
+        for off in 0..sb.inode_table_blocks() as u64 {
+            mark(desc.inode_table() + off);
+        }
+
+        // 最后一个块组可能不满，超出部分填 1
+        for bit in (group_end - group_start) as usize..BLOCK_SIZE * 8 {
+            data[bit / 8] |= 1 << (bit % 8);
+        }
+        data
+    }
+
+    /// 位图修改后刷新块组描述符中的位图校验和（仅 metadata_csum）
+    pub(crate) fn refresh_bitmap_csum(&mut self, key: CacheKey) {
+        if !self.superblock.has_metadata_csum() {
+            return;
+        }
+        let Some(bitmap) = self.bitmap_cache.get(&key) else {
+            return;
+        };
+        let desc_size = self.superblock.get_desc_size();
+        let csum = match key.bitmap_type {
+            BitmapType::Block => {
+                let len = self.superblock.s_clusters_per_group as usize / 8;
+                bitmap_csum(&self.superblock, &bitmap.data, len)
+            }
+            BitmapType::Inode => {
+                let len = self.superblock.s_inodes_per_group as usize / 8;
+                bitmap_csum(&self.superblock, &bitmap.data, len)
+            }
+        };
+        if let Some(desc) = self.group_descs.get_mut(key.group_id as usize) {
+            match key.bitmap_type {
+                BitmapType::Block => desc.set_block_bitmap_csum(csum, desc_size),
+                BitmapType::Inode => desc.set_inode_bitmap_csum(csum, desc_size),
+            }
+        }
+    }
+
+    /// 计算 inode 在 inode 表中的位置：(块号, 块内偏移)
+    pub fn inode_location(&self, inode_num: u32) -> BlockDevResult<(u64, usize)> {
+        let (group_idx, _idx_in_group) = self.inode_allocator.global_to_group(inode_num);
+
+        let inode_table_start = self
+            .group_descs
+            .get(group_idx as usize)
+            .ok_or(BlockDevError::Corrupted)?
+            .inode_table();
+
+        let (block_num, offset, _g) = self.inodetable_cahce.calc_inode_location(
+            inode_num,
+            self.superblock.s_inodes_per_group,
+            inode_table_start,
+            BLOCK_SIZE,
+        );
+        Ok((block_num, offset))
+    }
+
     /// 使用闭包修改指定 inode，内部自动计算 inode 在磁盘上的位置
     pub fn modify_inode<B, F>(
         &mut self,
@@ -699,8 +1085,9 @@ impl Ext4FileSystem {
         trace!("alloc_blocks: request count={count} (will scan groups for free space)");
 
         // 选择一个有足够空闲块的块组，并在该组内做连续分配
-        for (idx, desc) in self.group_descs.iter().enumerate() {
+        for idx in 0..self.group_descs.len() {
             let group_idx = idx as u32;
+            let desc = self.group_descs[idx];
             let free = desc.free_blocks_count();
 
             trace!("alloc_blocks: inspect group={group_idx} free_blocks={free} need={count}");
@@ -712,6 +1099,7 @@ impl Ext4FileSystem {
             let bitmap_block = desc.block_bitmap();
             let cache_key = CacheKey::new_block(group_idx);
             let mut alloc_res: Result<BlockAlloc, BlockDevError> = Err(BlockDevError::NoSpace);
+            self.init_uninit_bitmap(block_dev, cache_key)?;
 
             debug!(
                 "alloc_blocks: candidate group={group_idx} bitmap_block={bitmap_block} starting \
@@ -726,8 +1114,12 @@ impl Ext4FileSystem {
                         .alloc_contiguous_blocks(data, group_idx, count);
                     alloc_res = r.map_err(|_| BlockDevError::NoSpace);
                 })?;
+            self.refresh_bitmap_csum(cache_key);
 
-            let alloc = alloc_res?;
+            // 空闲块数足够但没有足够长的连续区间时，继续尝试下一个块组
+            let Ok(alloc) = alloc_res else {
+                continue;
+            };
 
             // 更新块组描述符
             if let Some(desc_mut) = self.get_group_desc_mut(group_idx) {
@@ -754,6 +1146,8 @@ impl Ext4FileSystem {
                  (delta=-{count})"
             );
 
+            self.reuse_freed(alloc.global_block, count as u64);
+
             let mut blocks = Vec::with_capacity(count as usize);
             for off in 0..count {
                 blocks.push(alloc.global_block + off as u64);
@@ -773,6 +1167,80 @@ impl Ext4FileSystem {
         Err(BlockDevError::NoSpace)
     }
 
+    /// 以 goal 为目标分配一段物理连续的数据块（多块分配）
+    ///
+    /// 从 goal 所在块组开始依次尝试各块组，返回 (起始块号, 实际分配块数)，
+    /// 实际分配的块数可能小于 `max_len`。goal 为 None 时从第一个块组开始。
+    pub fn alloc_extent<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        goal: Option<u64>,
+        max_len: u32,
+    ) -> BlockDevResult<(u64, u32)> {
+        if max_len == 0 {
+            return Err(BlockDevError::InvalidInput);
+        }
+
+        let group_count = self.group_descs.len() as u32;
+        let (goal_group, goal_in_group) = match goal {
+            Some(g) if g < self.superblock.blocks_count() => {
+                self.block_allocator.global_to_group(g)
+            }
+            _ => (0, 0),
+        };
+
+        for i in 0..group_count {
+            let group_idx = (goal_group + i) % group_count;
+            let desc = self.group_descs[group_idx as usize];
+            if desc.free_blocks_count() == 0 {
+                continue;
+            }
+
+            let goal = if i == 0 { goal_in_group } else { 0 };
+            let cache_key = CacheKey::new_block(group_idx);
+            self.init_uninit_bitmap(block_dev, cache_key)?;
+
+            let mut alloc_res = None;
+            self.bitmap_cache
+                .modify(block_dev, cache_key, desc.block_bitmap(), |data| {
+                    alloc_res = self
+                        .block_allocator
+                        .alloc_extent_in_group(data, group_idx, goal, max_len)
+                        .ok();
+                })?;
+            let Some((alloc, len)) = alloc_res else {
+                continue;
+            };
+            self.refresh_bitmap_csum(cache_key);
+
+            // 更新块组描述符
+            if let Some(desc_mut) = self.get_group_desc_mut(group_idx) {
+                let new_count = desc_mut.free_blocks_count().saturating_sub(len);
+                desc_mut.bg_free_blocks_count_lo = (new_count & 0xFFFF) as u16;
+                desc_mut.bg_free_blocks_count_hi = (new_count >> 16) as u16;
+            }
+
+            // 更新超级块
+            let sb_free = self
+                .superblock
+                .free_blocks_count()
+                .saturating_sub(len as u64);
+            self.superblock.s_free_blocks_count_lo = (sb_free & 0xFFFF_FFFF) as u32;
+            self.superblock.s_free_blocks_count_hi = (sb_free >> 32) as u32;
+
+            self.reuse_freed(alloc.global_block, len as u64);
+
+            debug!(
+                "alloc_extent: group={} goal={:?} start={} len={}/{}",
+                group_idx, goal, alloc.global_block, len, max_len
+            );
+
+            return Ok((alloc.global_block, len));
+        }
+
+        Err(BlockDevError::NoSpace)
+    }
+
     /// 在整个文件系统中分配一个数据块（兼容旧接口）
     pub fn alloc_block<B: BlockDevice>(
         &mut self,
@@ -793,8 +1261,9 @@ impl Ext4FileSystem {
         }
 
         // 目前按“同一块组内尽量连续”策略，从第一个有足够空闲 inode 的组开始分配
-        for (idx, desc) in self.group_descs.iter().enumerate() {
+        for idx in 0..self.group_descs.len() {
             let group_idx = idx as u32;
+            let desc = self.group_descs[idx];
             let free = desc.free_inodes_count();
             if free < count {
                 continue;
@@ -804,6 +1273,7 @@ impl Ext4FileSystem {
             let cache_key = CacheKey::new_inode(group_idx);
 
             let mut inodes: Vec<u32> = Vec::with_capacity(count as usize);
+            self.init_uninit_bitmap(block_dev, cache_key)?;
 
             self.bitmap_cache
                 .modify(block_dev, cache_key, bitmap_block, |data| {
@@ -811,7 +1281,7 @@ impl Ext4FileSystem {
                     for _ in 0..count {
                         let r = self
                             .inode_allocator
-                            .alloc_inode_in_group(data, group_idx, desc);
+                            .alloc_inode_in_group(data, group_idx, &desc);
                         match r {
                             Ok(InodeAlloc { global_inode, .. }) => {
                                 inodes.push(global_inode);
@@ -822,16 +1292,28 @@ impl Ext4FileSystem {
                         }
                     }
                 })?;
+            self.refresh_bitmap_csum(cache_key);
 
             if inodes.len() as u32 != count {
                 return Err(BlockDevError::NoSpace);
             }
 
             // 更新块组描述符
+            let inodes_per_group = self.superblock.s_inodes_per_group;
             if let Some(desc_mut) = self.get_group_desc_mut(group_idx) {
                 let new_count = desc_mut.free_inodes_count().saturating_sub(count);
                 desc_mut.bg_free_inodes_count_lo = (new_count & 0xFFFF) as u16;
                 desc_mut.bg_free_inodes_count_hi = (new_count >> 16) as u16;
+
+                // 维护 itable_unused：inode 表尾部从未使用过的 inode 数
+                let highest = inodes.iter().map(|ino| (ino - 1) % inodes_per_group).max();
+                if let Some(highest) = highest {
+                    let unused = inodes_per_group - highest - 1;
+                    if unused < desc_mut.itable_unused() {
+                        desc_mut.bg_itable_unused_lo = (unused & 0xFFFF) as u16;
+                        desc_mut.bg_itable_unused_hi = (unused >> 16) as u16;
+                    }
+                }
             }
 
             // 更新超级块
@@ -896,10 +1378,13 @@ impl Ext4FileSystem {
                 };
             })?;
         free_ok?;
+        self.refresh_bitmap_csum(cache_key);
 
         if !did_free {
             return Ok(());
         }
+        block_dev.journal_revoke(global_block);
+        self.freed_blocks.insert(global_block);
         let desc = self
             .get_group_desc_mut(group_idx)
             .ok_or(BlockDevError::Corrupted)?;
@@ -922,6 +1407,13 @@ impl Ext4FileSystem {
         block_dev: &mut Jbd2Dev<B>,
         inode_num: u32,
     ) -> BlockDevResult<()> {
+        // 尚未分配物理块的数据随 inode 一起丢弃
+        self.delalloc.forget_inode(inode_num);
+        // 扩展属性块可能与其他 inode 共享，只放弃本 inode 的引用
+        if let Err(e) = crate::xattr::release_inode_xattrs(self, block_dev, inode_num) {
+            warn!("inode {inode_num}: failed to release xattrs: {e}");
+        }
+
         // 通过 InodeAllocator 反推 (group_idx, inode_in_group)
         let (group_idx, inode_in_group) = self.inode_allocator.global_to_group(inode_num);
         let bitmap_block;
@@ -951,6 +1443,7 @@ impl Ext4FileSystem {
                 };
             })?;
         free_ok?;
+        self.refresh_bitmap_csum(cache_key);
 
         if !did_free {
             return Ok(());
@@ -993,10 +1486,17 @@ impl Ext4FileSystem {
     }
 
     /// 获取文件系统统计信息
+    ///
+    /// 延迟分配中尚未落盘的块已经不算空闲，保留块不计入可用块
     pub fn statfs(&self) -> FileSystemStats {
+        let free_blocks = self
+            .superblock
+            .free_blocks_count()
+            .saturating_sub(self.delalloc.pending_blocks() as u64);
         FileSystemStats {
             total_blocks: self.superblock.blocks_count(),
-            free_blocks: self.superblock.free_blocks_count(),
+            free_blocks,
+            available_blocks: free_blocks.saturating_sub(self.superblock.reserved_blocks_count()),
             total_inodes: self.superblock.s_inodes_count,
             free_inodes: self.superblock.s_free_inodes_count,
             block_size: self.superblock.block_size(),
@@ -1017,6 +1517,8 @@ pub struct FileSystemStats {
     pub total_blocks: u64,
     /// 空闲块数
     pub free_blocks: u64,
+    /// 普通用户可用的块数（空闲块数减去保留块数）
+    pub available_blocks: u64,
     /// 总inode数
     pub total_inodes: u32,
     /// 空闲inode数
@@ -1101,6 +1603,25 @@ pub struct FsLayoutInfo {
     group0_metadata_blocks: u32,
     /// 预留块总数（按比例预留给 root）
     reserved_blocks: u64,
+    /// 设备总块数
+    total_blocks: u64,
+}
+
+impl FsLayoutInfo {
+    /// 块组实际包含的块数（最后一个块组可能不满）
+    fn group_blocks(&self, group_id: u32) -> u32 {
+        let start = self.first_data_block as u64 + group_id as u64 * self.blocks_per_group as u64;
+        self.total_blocks
+            .saturating_sub(start)
+            .min(self.blocks_per_group as u64) as u32
+    }
+}
+
+/// 位图中块组末尾之后的位标记为已用，防止分配到设备之外
+fn pad_block_bitmap(bitmap: &mut [u8], group_blocks: u32, blocks_per_group: u32) {
+    for i in group_blocks..blocks_per_group {
+        bitmap[(i / 8) as usize] |= 1 << (i % 8);
+    }
 }
 
 /// block_group 布局信息，仅在 mkfs 阶段使用
@@ -1194,6 +1715,7 @@ pub fn compute_fs_layout(inode_size: u16, total_blocks: u64) -> FsLayoutInfo {
         group0_inode_table,
         group0_metadata_blocks,
         reserved_blocks,
+        total_blocks,
     }
 }
 
@@ -1360,7 +1882,7 @@ fn build_uninit_group_desc(
 
     // 理论空闲块数：整组减去元数据块
     let used_meta = gl.metadata_blocks_in_group as u32;
-    let free_blocks = layout.blocks_per_group.saturating_sub(used_meta);
+    let free_blocks = layout.group_blocks(group_id).saturating_sub(used_meta);
 
     if group_id == 0 {
         // 组0 还需要扣掉保留 inode
@@ -1412,7 +1934,7 @@ fn write_superblock_redundant_backup<B: BlockDevice>(
                     .read_block(super_blocks as u32)
                     .expect("Superblock read failed!");
                 let buffer = block_dev.buffer_mut();
-                sb.to_disk_bytes(&mut buffer[0..SUPERBLOCK_SIZE]);
+                sb.to_disk_bytes_with_csum(&mut buffer[0..SUPERBLOCK_SIZE]);
                 block_dev.write_block(super_blocks as u32, true)?;
             }
         }
@@ -1429,14 +1951,14 @@ fn write_superblock<B: BlockDevice>(
     if BLOCK_SIZE == 1024 {
         block_dev.read_block(1)?;
         let buffer = block_dev.buffer_mut();
-        sb.to_disk_bytes(&mut buffer[0..SUPERBLOCK_SIZE]);
+        sb.to_disk_bytes_with_csum(&mut buffer[0..SUPERBLOCK_SIZE]);
         block_dev.write_block(1, true)?;
     } else {
         block_dev.read_block(0)?;
         let buffer = block_dev.buffer_mut();
         let offset = Ext4Superblock::SUPERBLOCK_OFFSET as usize; // 1024
         let end = offset + Ext4Superblock::SUPERBLOCK_SIZE;
-        sb.to_disk_bytes(&mut buffer[offset..end]);
+        sb.to_disk_bytes_with_csum(&mut buffer[offset..end]);
         block_dev.write_block(0, false)?; //由于目前日志回放在超级块读取后，目前为了快速修复防止读取到旧的超级块。直接让超级块落盘写回
     }
 
@@ -1444,7 +1966,9 @@ fn write_superblock<B: BlockDevice>(
 }
 
 /// 读取超级块 管字节序
-fn read_superblock<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<Ext4Superblock> {
+pub(crate) fn read_superblock<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+) -> BlockDevResult<Ext4Superblock> {
     // 超级块总是从分区偏移 1024 字节开始，占用 1024 字节
     // 这里通过按 BLOCK_SIZE 读块，再在块内做 1024 字节切片来解析
     if BLOCK_SIZE == 1024 {
@@ -1499,15 +2023,17 @@ fn write_gdt_redundant_backup<B: BlockDevice>(
                 );
                 let gdt_start = group_layout.group_start_block + 1; //跳过超级块
 
-                let mut desc_iter = descs.iter();
+                let mut desc_iter = descs.iter().enumerate();
                 // 循环写入desc
                 for gdt_block_id in gdt_start..group_layout.group_blcok_bitmap_startblocks {
                     block_dev.read_block(gdt_block_id as u32)?;
                     let buffer = block_dev.buffer_mut();
                     let mut current_offset = 0_usize; //descoffset循环记录
                     for _ in 0..fs_layout.descs_per_block {
-                        if let Some(desc) = desc_iter.next() {
-                            desc.to_disk_bytes(
+                        if let Some((desc_gid, desc)) = desc_iter.next() {
+                            desc.to_disk_bytes_with_csum(
+                                sb,
+                                desc_gid as u32,
                                 &mut buffer[current_offset..current_offset + desc_size as usize],
                             );
                             current_offset += desc_size as usize;
@@ -1547,7 +2073,7 @@ fn write_group_desc<B: BlockDevice>(
     if end > buffer.len() {
         return Err(BlockDevError::Corrupted);
     }
-    desc.to_disk_bytes(&mut buffer[in_block..end]);
+    desc.to_disk_bytes_with_csum(&superblock, group_id, &mut buffer[in_block..end]);
     block_dev.write_block(block_num as u32, true)?;
 
     Ok(())
@@ -1573,6 +2099,7 @@ fn initialize_group_0<B: BlockDevice>(
             let bit_idx = i % 8;
             buffer[byte_idx] |= 1 << bit_idx;
         }
+        pad_block_bitmap(buffer, layout.group_blocks(0), layout.blocks_per_group);
     }
     block_dev.write_block(block_bitmap_blk, true)?;
 
@@ -1609,7 +2136,7 @@ fn initialize_group_0<B: BlockDevice>(
     let desc = Ext4GroupDesc {
         bg_flags: Ext4GroupDesc::EXT4_BG_INODE_ZEROED,
         bg_free_blocks_count_lo: layout
-            .blocks_per_group
+            .group_blocks(0)
             .saturating_sub(layout.group0_metadata_blocks) as u16,
         bg_free_inodes_count_lo: layout.inodes_per_group.saturating_sub(RESERVED_INODES) as u16,
         bg_block_bitmap_lo: block_bitmap_blk,
@@ -1658,6 +2185,11 @@ fn initialize_other_groups_bitmaps<B: BlockDevice>(
                 let bit_idx = i % 8;
                 buffer[byte_idx] |= 1 << bit_idx;
             }
+            pad_block_bitmap(
+                buffer,
+                layout.group_blocks(group_id),
+                layout.blocks_per_group,
+            );
         }
         block_dev.write_block(block_bitmap_blk, true)?;
 
@@ -1679,3 +2211,149 @@ fn initialize_other_groups_bitmaps<B: BlockDevice>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::{rc::Rc, vec, vec::Vec};
+    use core::cell::RefCell;
+
+    use super::*;
+
+    /// 记录 discard 请求 (起始块, 块数) 的内存块设备
+    struct MemBlockDev {
+        data: Vec<u8>,
+        total_blocks: u64,
+        discards: Rc<RefCell<Vec<(u32, u32)>>>,
+    }
+
+    impl BlockDevice for MemBlockDev {
+        fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+            let start = block_id as usize * BLOCK_SIZE;
+            let len = count as usize * BLOCK_SIZE;
+            self.data[start..start + len].copy_from_slice(&buffer[..len]);
+            Ok(())
+        }
+
+        fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+            let start = block_id as usize * BLOCK_SIZE;
+            let len = count as usize * BLOCK_SIZE;
+            buffer[..len].copy_from_slice(&self.data[start..start + len]);
+            Ok(())
+        }
+
+        fn open(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn total_blocks(&self) -> u64 {
+            self.total_blocks
+        }
+
+        fn block_size(&self) -> u32 {
+            BLOCK_SIZE as u32
+        }
+
+        fn discard(&mut self, block_id: u32, count: u32) -> BlockDevResult<()> {
+            self.discards.borrow_mut().push((block_id, count));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_freed_blocks_discarded_on_writeback() {
+        let total_blocks = 16 * 1024;
+        let dev = MemBlockDev {
+            data: vec![0u8; total_blocks as usize * BLOCK_SIZE],
+            total_blocks,
+            discards: Rc::default(),
+        };
+        let discards = dev.discards.clone();
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, dev, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+
+        let blocks = fs.alloc_blocks(&mut dev, 6).unwrap();
+        for &block in &blocks {
+            fs.free_block(&mut dev, block).unwrap();
+        }
+        // 重新分配到的块不能再被丢弃
+        let (reused, len) = fs.alloc_extent(&mut dev, Some(blocks[2]), 2).unwrap();
+        assert_eq!((reused, len), (blocks[2], 2));
+
+        fs.writeback(&mut dev).unwrap();
+        let first = blocks[0] as u32;
+        assert_eq!(*discards.borrow(), [(first, 2), (first + 4, 2)]);
+        assert!(fs.freed_blocks.is_empty());
+        fs.writeback(&mut dev).unwrap();
+        assert_eq!(discards.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_statfs_counts_delayed_blocks_as_used() {
+        let total_blocks = 16 * 1024;
+        let dev = MemBlockDev {
+            data: vec![0u8; total_blocks as usize * BLOCK_SIZE],
+            total_blocks,
+            discards: Rc::default(),
+        };
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, dev, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        fs.superblock.s_r_blocks_count_lo = 100;
+
+        let before = fs.statfs();
+        assert_eq!(before.total_blocks, total_blocks);
+        assert_eq!(before.available_blocks, before.free_blocks - 100);
+
+        let (ino, _) = crate::file::mkfile_with_ino(&mut dev, &mut fs, "/f", None, None).unwrap();
+        let data = vec![0x5au8; 3 * BLOCK_SIZE];
+        crate::file::write_file_with_ino(&mut dev, &mut fs, ino, 0, &data).unwrap();
+        let pending = fs.delalloc.pending_blocks() as u64;
+        assert_eq!(pending, 3);
+        let after = fs.statfs();
+        assert_eq!(
+            after.free_blocks,
+            fs.superblock.free_blocks_count() - pending
+        );
+        assert!(after.free_blocks <= before.free_blocks - 3);
+        assert_eq!(after.free_inodes, before.free_inodes - 1);
+    }
+
+    #[test]
+    fn test_journal_clean_after_umount() {
+        let total_blocks = 16 * 1024;
+        let dev = MemBlockDev {
+            data: vec![0u8; total_blocks as usize * BLOCK_SIZE],
+            total_blocks,
+            discards: Rc::default(),
+        };
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, dev, false);
+        mkfs(&mut dev).unwrap();
+        dev.set_journal_use(true);
+
+        let mut fs = mount(&mut dev).unwrap();
+        let (ino, _) = crate::file::mkfile_with_ino(&mut dev, &mut fs, "/f", None, None).unwrap();
+        let data = vec![0x5au8; 3 * BLOCK_SIZE];
+        crate::file::write_file_with_ino(&mut dev, &mut fs, ino, 0, &data).unwrap();
+        fs.umount(&mut dev).unwrap();
+
+        // 干净卸载后日志为空，下次挂载不需要重放
+        let journal_sb = fs.journal_sb_block_start.unwrap();
+        let mut raw = vec![0u8; BLOCK_SIZE];
+        dev.read_blocks(&mut raw, journal_sb, 1).unwrap();
+        let jsb = JournalSuperBllockS::from_disk_bytes(&raw);
+        assert_eq!(jsb.s_start, 0);
+        assert!(jsb.s_sequence > 1);
+
+        let mut fs = mount(&mut dev).unwrap();
+        let read = crate::file::read_file(&mut dev, &mut fs, "/f").unwrap();
+        assert_eq!(read.as_deref(), Some(&data[..]));
+        let report = fs.verify(&mut dev).unwrap();
+        assert!(report.is_clean(), "{:?}", report.issues);
+        fs.umount(&mut dev).unwrap();
+    }
+}
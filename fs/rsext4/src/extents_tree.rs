@@ -6,7 +6,10 @@ use alloc::{vec, vec::*};
 
 use log::{debug, error};
 
-use crate::{blockdev::*, config::*, disknode::*, endian::*, error::*, ext4::*};
+use crate::{
+    blockdev::*, checksum::set_extent_block_csum, config::*, disknode::*, endian::*, error::*,
+    ext4::*,
+};
 
 /// 内存中的 extent 树节点表示
 #[derive(Clone)]
@@ -46,6 +49,8 @@ impl ExtentNode {
 /// 绑定到单个 inode 的 extent 树视图（不持有 BlockDev，按需传入）
 pub struct ExtentTree<'a> {
     pub inode: &'a mut Ext4Inode,
+    /// metadata_csum 下该 inode 的校验种子，写 extent 块时用于生成尾部校验和
+    pub csum_seed: Option<u32>,
 }
 
 /// 用于在递归插入时向上冒泡分裂信息
@@ -59,7 +64,16 @@ struct SplitInfo {
 impl<'a> ExtentTree<'a> {
     /// 构造：从给定 inode 开始操作其 extent 树
     pub fn new(inode: &'a mut Ext4Inode) -> Self {
-        Self { inode }
+        Self {
+            inode,
+            csum_seed: None,
+        }
+    }
+
+    /// 设置 extent 块校验种子（见 `Ext4FileSystem::inode_csum_seed`）
+    pub fn with_csum_seed(mut self, csum_seed: Option<u32>) -> Self {
+        self.csum_seed = csum_seed;
+        self
     }
 
     fn add_inode_sectors_for_block(&mut self) {
@@ -668,7 +682,13 @@ impl<'a> ExtentTree<'a> {
                     header: *header,
                     entries: entries.clone(),
                 };
-                ExtentTree::write_node_to_block(dev, block_id, &disk_node, header.eh_max)?;
+                ExtentTree::write_node_to_block(
+                    dev,
+                    block_id,
+                    &disk_node,
+                    header.eh_max,
+                    tree.csum_seed,
+                )?;
             }
 
             Ok(StepRes {
@@ -752,6 +772,7 @@ impl<'a> ExtentTree<'a> {
                                         block_id,
                                         &disk_node,
                                         header.eh_max,
+                                        tree.csum_seed,
                                     )?;
                                 }
 
@@ -967,7 +988,13 @@ impl<'a> ExtentTree<'a> {
 
                 // 将当前的 root (左半部分) 写入新分配的物理块
                 // 注意：写入磁盘时要更新 eh_max，因为从 inode (max~4) 移到了 block (max~340)
-                Self::write_node_to_block(block_dev, new_left_block as u32, &root, block_eh_max)?;
+                Self::write_node_to_block(
+                    block_dev,
+                    new_left_block as u32,
+                    &root,
+                    block_eh_max,
+                    self.csum_seed,
+                )?;
 
                 // 在 Inode 中构建新的 Root Index
                 let inline_bytes = self.inode.i_block.len() * 4;
@@ -1039,7 +1066,8 @@ impl<'a> ExtentTree<'a> {
                     .binary_search_by_key(&new_ext.ee_block, |e| e.ee_block)
                     .unwrap_or_else(|i| i);
 
-                const MAX_LEN: u32 = 32768;
+                // ee_len 最高位被当作未初始化标志处理，合并后的长度不能写成 0x8000
+                const MAX_LEN: u32 = 32767;
 
                 if pos > 0 {
                     let prev = &mut entries[pos - 1];
@@ -1081,6 +1109,7 @@ impl<'a> ExtentTree<'a> {
                                                 block_id,
                                                 &disk_node,
                                                 header.eh_max,
+                                                self.csum_seed,
                                             )?;
                                         }
                                         return Ok(None);
@@ -1125,6 +1154,7 @@ impl<'a> ExtentTree<'a> {
                                                     block_id,
                                                     &disk_node,
                                                     header.eh_max,
+                                                    self.csum_seed,
                                                 )?;
                                             }
                                             return Ok(None);
@@ -1158,7 +1188,13 @@ impl<'a> ExtentTree<'a> {
                             header: *header,
                             entries: entries.clone(),
                         };
-                        Self::write_node_to_block(block_dev, block_id, &disk_node, header.eh_max)?;
+                        Self::write_node_to_block(
+                            block_dev,
+                            block_id,
+                            &disk_node,
+                            header.eh_max,
+                            self.csum_seed,
+                        )?;
                     }
                     // Root 节点由调用方负责写回 Inode，这里返回 None
                     return Ok(None);
@@ -1203,6 +1239,7 @@ impl<'a> ExtentTree<'a> {
                     new_phy_block as u32,
                     &right_node,
                     right_header.eh_max,
+                    self.csum_seed,
                 )?;
                 // 写左节点（当前节点）
                 // 如果当前节点是普通块，写回磁盘；如果是 Root，调用方会处理，但这里我们要在内存中保持正确状态
@@ -1211,7 +1248,13 @@ impl<'a> ExtentTree<'a> {
                         header: *header,
                         entries: entries.clone(),
                     };
-                    Self::write_node_to_block(block_dev, block_id, &disk_node, header.eh_max)?;
+                    Self::write_node_to_block(
+                        block_dev,
+                        block_id,
+                        &disk_node,
+                        header.eh_max,
+                        self.csum_seed,
+                    )?;
                 }
 
                 // 返回分裂信息
@@ -1299,6 +1342,7 @@ impl<'a> ExtentTree<'a> {
                                 block_id,
                                 &disk_node,
                                 header.eh_max,
+                                self.csum_seed,
                             )?;
                         }
                         return Ok(None);
@@ -1345,13 +1389,20 @@ impl<'a> ExtentTree<'a> {
                         new_phy_block as u32,
                         &right_node,
                         right_header.eh_max,
+                        self.csum_seed,
                     )?;
                     if let Some(block_id) = phy_block {
                         let disk_node = ExtentNode::Index {
                             header: *header,
                             entries: entries.clone(),
                         };
-                        Self::write_node_to_block(block_dev, block_id, &disk_node, header.eh_max)?;
+                        Self::write_node_to_block(
+                            block_dev,
+                            block_id,
+                            &disk_node,
+                            header.eh_max,
+                            self.csum_seed,
+                        )?;
                     }
 
                     // 返回分裂信息
@@ -1379,6 +1430,7 @@ impl<'a> ExtentTree<'a> {
         block_id: u32,
         node: &ExtentNode,
         eh_max: u16,
+        csum_seed: Option<u32>,
     ) -> BlockDevResult<()> {
         let hdr_size = Ext4ExtentHeader::disk_size();
         // 读取块
@@ -1419,6 +1471,9 @@ impl<'a> ExtentTree<'a> {
                 }
             }
         }
+        if let Some(seed) = csum_seed {
+            set_extent_block_csum(seed, buf);
+        }
         // 标记脏并写回
         dev.write_block(block_id, true)?;
         Ok(())
@@ -10,8 +10,8 @@ use alloc::{
 use log::{debug, error, info, warn};
 
 use crate::{
-    blockdev::*, config::*, dir::*, disknode::*, entries::*, error::*, ext4::*, extents_tree::*,
-    loopfile::*,
+    blockdev::*, config::*, delalloc, dir::*, disknode::*, entries::*, error::*, ext4::*,
+    extents_tree::*, loopfile::*, xattr::xattr_block_sectors,
 };
 
 /// 重命名文件或目录
@@ -96,6 +96,10 @@ pub fn truncate_with_ino<B: BlockDevice>(
     inode_num: u32,
     truncate_size: u64,
 ) -> BlockDevResult<()> {
+    // 先丢弃截断范围外的延迟分配数据，剩余部分落盘后再按 extent 处理
+    fs.delalloc.truncate(inode_num, truncate_size);
+    delalloc::flush_inode(fs, device, inode_num)?;
+
     let mut inode = fs.get_inode_by_num(device, inode_num)?;
 
     if !inode.is_file() {
@@ -127,36 +131,7 @@ pub fn truncate_with_ino<B: BlockDevice>(
         if truncate_size < old_size {
             // shrink：删除逻辑范围尾部，但 hole 不应导致 double free。
             // 通过 ExtentTree::remove_extend 让 extent tree 内部负责释放物理块。
-            let del_start_lbn = new_blocks as u32;
-
-            loop {
-                let blocks_map = resolve_inode_block_allextend(fs, device, &mut inode)?;
-                let del_len = if truncate_size == 0 {
-                    blocks_map.len() as u32
-                } else {
-                    blocks_map.range(del_start_lbn..).count() as u32
-                };
-
-                if del_len == 0 {
-                    break;
-                }
-
-                let start_lbn = if truncate_size == 0 {
-                    // Plan B: start from the first mapped LBN to avoid rescanning from 0 repeatedly.
-                    let Some((&first_lbn, _)) = blocks_map.iter().next() else {
-                        break;
-                    };
-                    first_lbn
-                } else {
-                    del_start_lbn
-                };
-
-                let chunk = core::cmp::min(del_len, 0x7FFF);
-                {
-                    let mut tree = ExtentTree::new(&mut inode);
-                    tree.remove_extend(fs, Ext4Extent::new(start_lbn, 0, chunk as u16), device)?;
-                }
-            }
+            trim_extent_blocks(device, fs, inode_num, &mut inode, new_blocks as u32)?;
         }
 
         if new_blocks > old_blocks {
@@ -171,7 +146,8 @@ pub fn truncate_with_ino<B: BlockDevice>(
                 new_blocks_map.push((lbn, phys));
             }
 
-            let mut tree = ExtentTree::new(&mut inode);
+            let csum_seed = fs.inode_csum_seed(inode_num, &inode);
+            let mut tree = ExtentTree::new(&mut inode).with_csum_seed(csum_seed);
             if !new_blocks_map.is_empty() {
                 let mut idx = 0usize;
                 while idx < new_blocks_map.len() {
@@ -201,7 +177,8 @@ pub fn truncate_with_ino<B: BlockDevice>(
         inode.i_size_high = (truncate_size >> 32) as u32;
         // i_blocks reflects number of allocated blocks, not logical length. Recompute after edits.
         let alloc_blocks = resolve_inode_block_allextend(fs, device, &mut inode)?.len() as u64;
-        let iblocks_used = alloc_blocks.saturating_mul(BLOCK_SIZE as u64 / 512);
+        let iblocks_used =
+            alloc_blocks.saturating_mul(BLOCK_SIZE as u64 / 512) + xattr_block_sectors(&inode);
         inode.i_blocks_lo = (iblocks_used & 0xffff_ffff) as u32;
         inode.l_i_blocks_high = ((iblocks_used >> 32) & 0xffff) as u16;
 
@@ -243,7 +220,8 @@ pub fn truncate_with_ino<B: BlockDevice>(
 
     inode.i_size_lo = (truncate_size & 0xffff_ffff) as u32;
     inode.i_size_high = (truncate_size >> 32) as u32;
-    let iblocks_used = new_blocks.saturating_mul(BLOCK_SIZE as u64 / 512);
+    let iblocks_used =
+        new_blocks.saturating_mul(BLOCK_SIZE as u64 / 512) + xattr_block_sectors(&inode);
     inode.i_blocks_lo = (iblocks_used & 0xffff_ffff) as u32;
     inode.l_i_blocks_high = ((iblocks_used >> 32) & 0xffff) as u16;
 
@@ -253,6 +231,34 @@ pub fn truncate_with_ino<B: BlockDevice>(
 
     Ok(())
 }
+
+/// 释放 extent inode 中逻辑块号 >= `from_lbn` 的所有数据块（空洞不计入）
+///
+/// 只修改传入的 inode 副本（extent 树与 i_blocks），由调用者负责写回
+pub(crate) fn trim_extent_blocks<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    inode_num: u32,
+    inode: &mut Ext4Inode,
+    from_lbn: u32,
+) -> BlockDevResult<()> {
+    loop {
+        let blocks_map = resolve_inode_block_allextend(fs, device, inode)?;
+        let mut tail = blocks_map.range(from_lbn..);
+        // 从第一个已映射的逻辑块开始删，避免每轮都从 from_lbn 重新扫描空洞
+        let Some((&start_lbn, _)) = tail.next() else {
+            break;
+        };
+        let del_len = tail.count() as u32 + 1;
+
+        let chunk = core::cmp::min(del_len, 0x7FFF);
+        let csum_seed = fs.inode_csum_seed(inode_num, inode);
+        let mut tree = ExtentTree::new(inode).with_csum_seed(csum_seed);
+        tree.remove_extend(fs, Ext4Extent::new(start_lbn, 0, chunk as u16), device)?;
+    }
+    Ok(())
+}
+
 pub fn create_symbol_link<B: BlockDevice>(
     device: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
@@ -353,7 +359,7 @@ pub fn create_symbol_link<B: BlockDevice>(
         new_inode.i_blocks_lo = iblocks_used;
         new_inode.l_i_blocks_high = 0; // iblocks_used is u32, so high part is 0
 
-        build_file_block_mapping(fs, &mut new_inode, &data_blocks, device);
+        build_file_block_mapping(fs, new_ino, &mut new_inode, &data_blocks, device);
     }
 
     fs.modify_inode(device, new_ino, |on_disk| {
@@ -454,12 +460,17 @@ fn read_file_follow<B: BlockDevice>(
         return Err(BlockDevError::InvalidInput);
     }
 
-    let mut inode = match get_file_inode(fs, device, path) {
-        Ok(Some((_ino_num, ino))) => ino,
+    let (inode_num, mut inode) = match get_file_inode(fs, device, path) {
+        Ok(Some(v)) => v,
         Ok(None) => return Ok(None),
         Err(e) => return Err(e),
     };
 
+    if fs.delalloc.has_pending(inode_num) {
+        delalloc::flush_inode(fs, device, inode_num)?;
+        inode = fs.get_inode_by_num(device, inode_num)?;
+    }
+
     if inode.is_symlink() {
         let target_bytes = read_symlink_target(device, fs, &mut inode)?;
         let target = match core::str::from_utf8(&target_bytes) {
@@ -766,6 +777,7 @@ pub fn mv<B: BlockDevice>(
                     data[off1 + 2] = bytes[2];
                     data[off1 + 3] = bytes[3];
                 });
+            let _ = fs.update_dir_block_csum(block_dev, src_ino, first_blk as u64, false);
         }
     }
 
@@ -1041,7 +1053,7 @@ pub fn remove_inodeentry_from_parentdir<B: BlockDevice>(
             return false;
         }
     };
-    let (_parent_ino_num, mut parent_inode) = parent_info;
+    let (parent_ino_num, mut parent_inode) = parent_info;
 
     let total_size = parent_inode.size() as usize;
     let block_bytes = BLOCK_SIZE;
@@ -1123,6 +1135,9 @@ pub fn remove_inodeentry_from_parentdir<B: BlockDevice>(
                 offset = entry_end;
             }
         });
+        if removed {
+            let _ = fs.update_dir_block_csum(block_dev, parent_ino_num, phys as u64, false);
+        }
     }
 
     removed
@@ -1468,6 +1483,7 @@ pub fn delete_file<B: BlockDevice>(
 /// - 否则使用传统直接块指针（i_block[0..]）。
 pub fn build_file_block_mapping<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
+    inode_num: u32,
     inode: &mut Ext4Inode,
     data_blocks: &[u64],
     block_dev: &mut Jbd2Dev<B>,
@@ -1520,7 +1536,8 @@ pub fn build_file_block_mapping<B: BlockDevice>(
         exts_vec.push(ext);
 
         // 构造一个叶子根节点，并通过 ExtentTree 将其写入 inode.i_block
-        let mut tree = ExtentTree::new(inode);
+        let csum_seed = fs.inode_csum_seed(inode_num, inode);
+        let mut tree = ExtentTree::new(inode).with_csum_seed(csum_seed);
         for extend in exts_vec {
             tree.insert_extent(fs, extend, block_dev)
                 .expect("Extend insert Failed!");
@@ -1680,7 +1697,7 @@ pub fn mkfile_with_ino<B: BlockDevice>(
         new_inode.i_blocks_lo = used_blocks_lo;
         new_inode.l_i_blocks_high = (iblocks_used >> 32) as u16;
 
-        build_file_block_mapping(fs, &mut new_inode, &data_blocks, device);
+        build_file_block_mapping(fs, new_file_ino, &mut new_inode, &data_blocks, device);
     } else {
         // 无初始数据：空文件
         new_inode.i_size_lo = 0;
@@ -1812,64 +1829,38 @@ pub fn write_file_with_ino<B: BlockDevice>(
         return Err(BlockDevError::Unsupported);
     }
 
-    let mut blocks_map = if inode.have_extend_header_and_use_extend() {
-        Some(resolve_inode_block_allextend(fs, device, &mut inode)?)
-    } else {
-        None
-    };
+    let extent_mapped = inode.have_extend_header_and_use_extend();
 
     for lbn in start_lbn..=end_lbn {
-        let phys = if inode.have_extend_header_and_use_extend() {
-            let map = blocks_map.as_mut().ok_or(BlockDevError::Corrupted)?;
-            if let Some(&b) = map.get(&(lbn as u32)) {
-                b
+        let block_start = lbn * block_bytes;
+        let write_start = core::cmp::max(offset, block_start);
+        let write_end = core::cmp::min(end, block_start + block_bytes);
+        let src = &data[(write_start - offset) as usize..(write_end - offset) as usize];
+        let dst_off = (write_start - block_start) as usize;
+
+        let phys = if extent_mapped {
+            let lbn = lbn as u32;
+            if fs.delalloc.contains(inode_num, lbn) {
+                None
             } else {
-                // Hole: allocate a new block and insert an extent for this single LBN.
-                let new_phys = fs.alloc_block(device)?;
-                fs.datablock_cache.modify_new(new_phys, |blk| {
-                    for b in blk.iter_mut() {
-                        *b = 0;
-                    }
-                });
-                {
-                    let mut tree = ExtentTree::new(&mut inode);
-                    let ext = Ext4Extent::new(lbn as u32, new_phys, 1);
-                    tree.insert_extent(fs, ext, device)?;
-                }
-                map.insert(lbn as u32, new_phys);
-
-                let add_iblocks = (BLOCK_SIZE / 512) as u32;
-                inode.i_blocks_lo = inode.i_blocks_lo.saturating_add(add_iblocks);
-                inode.l_i_blocks_high = inode
-                    .l_i_blocks_high
-                    .saturating_add(((add_iblocks as u64) >> 32) as u16);
-
-                new_phys
+                ExtentTree::new(&mut inode)
+                    .find_extent(device, lbn)?
+                    .map(|ext| ext.start_block() + (lbn - ext.ee_block) as u64)
             }
         } else {
             match resolve_inode_block(device, &mut inode, lbn as u32)? {
-                Some(b) => b as u64,
+                Some(b) => Some(b as u64),
                 None => return Err(BlockDevError::Unsupported),
             }
         };
 
-        fs.datablock_cache.modify(device, phys as u64, |blk| {
-            let block_start = lbn * block_bytes;
-            let block_end = block_start + block_bytes;
-
-            let write_start = core::cmp::max(offset, block_start);
-            let write_end = core::cmp::min(end, block_end);
-            if write_start >= write_end {
-                return;
-            }
-
-            let src_off = write_start - offset;
-            let dst_off = (write_start - block_start) as usize;
-            let len = write_end - write_start;
-
-            blk[dst_off..dst_off + len as usize]
-                .copy_from_slice(&data[src_off as usize..(src_off + len) as usize]);
-        })?;
+        match phys {
+            Some(phys) => fs.datablock_cache.modify(device, phys, |blk| {
+                blk[dst_off..dst_off + src.len()].copy_from_slice(src);
+            })?,
+            // 空洞：数据先进入延迟分配缓存，物理块在刷写时成批分配
+            None => fs.delalloc.write_block(inode_num, lbn as u32, dst_off, src),
+        }
     }
 
     if end > old_size {
@@ -1881,5 +1872,11 @@ pub fn write_file_with_ino<B: BlockDevice>(
         *td = inode;
     })?;
 
+    if fs.delalloc.inode_pending_blocks(inode_num) >= DELALLOC_INODE_MAX_BLOCKS {
+        delalloc::flush_inode(fs, device, inode_num)?;
+    } else if fs.delalloc.pending_blocks() >= DELALLOC_MAX_BLOCKS {
+        delalloc::flush_all(fs, device)?;
+    }
+
     Ok(())
 }
@@ -7,7 +7,19 @@ use alloc::vec::Vec;
 
 use log::{debug, error, warn};
 
-use crate::{blockdev::*, config::*, disknode::*, endian::*, entries::*, ext4::*, loopfile::*};
+use crate::{
+    blockdev::*,
+    checksum::has_dir_tail,
+    config::*,
+    dir::{append_dir_block, insert_entry_into_block},
+    disknode::*,
+    endian::*,
+    entries::*,
+    error::*,
+    ext4::*,
+    loopfile::*,
+    superblock::Ext4Superblock,
+};
 
 /// Hash tree error type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -40,16 +52,46 @@ impl core::fmt::Display for HashTreeError {
 }
 
 /// Hash tree search result
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct HashTreeSearchResult {
-    /// Found directory entry
-    pub entry: Ext4DirEntryInfo<'static>,
-    /// Block number where entry is located
-    pub block_num: u32,
-    /// Offset within the block
+    /// Inode number of the found entry
+    pub inode: u32,
+    /// File type of the found entry
+    pub file_type: u8,
+    /// Physical block where the entry is located
+    pub block_num: u64,
+    /// Offset of the entry within the block
     pub offset: usize,
 }
 
+/// Offset of `dx_countlimit` in the root block (after "." / ".." and `dx_root_info`)
+const DX_ROOT_COUNT_OFFSET: usize = 0x20;
+/// Offset of `dx_countlimit` in an internal node (after the fake dirent)
+const DX_NODE_COUNT_OFFSET: usize = 8;
+/// Size of `dx_tail` reserved at the end of index blocks with metadata_csum
+const DX_TAIL_SIZE: usize = 8;
+
+/// One level of the path from the root to a leaf
+#[derive(Debug)]
+struct DxFrame {
+    /// Physical block of the index node
+    phys: u64,
+    /// Offset of `dx_countlimit` within the block
+    count_offset: usize,
+    /// Maximum number of entries in the node
+    limit: u16,
+    /// Index entries; `entries[0].hash` is implicitly 0
+    entries: Vec<Ext4DxEntry>,
+    /// Position of the entry that was followed
+    at: usize,
+}
+
+impl DxFrame {
+    fn is_full(&self) -> bool {
+        self.entries.len() >= self.limit as usize
+    }
+}
+
 /// Hash tree manager
 pub struct HashTreeManager {
     /// Hash seed (from superblock)
@@ -84,26 +126,14 @@ impl HashTreeManager {
         );
 
         // 1. Check if directory has hash tree index enabled
-        if !dir_inode.is_htree_indexed() {
-            // warn!("Directory does not have hash tree index enabled, falling back to linear search");
+        if !is_dx_dir(fs, dir_inode) {
             return self.fallback_to_linear_search(fs, block_dev, dir_inode, target_name);
         }
 
-        // 2. Calculate hash value of target filename
-        let target_hash =
-            htree_dir::calculate_hash(target_name, self.hash_version, &self.hash_seed);
-        debug!("Target hash value: 0x{target_hash:08x}");
-
-        // 3. Read root node
-        let root_block = self.get_root_block(block_dev, dir_inode)?;
-        let root_data = self.read_block_data(fs, block_dev, root_block)?;
-
-        // 4. Parse root node
-        let root_info = self.parse_root_node(&root_data)?;
-
-        // 5. Search in hash tree
-        match self.search_in_hash_tree(fs, block_dev, &root_info, target_hash, target_name) {
+        // 2. Walk the index down to the leaf covering the target hash
+        match self.search_in_hash_tree(fs, block_dev, dir_inode, target_name) {
             Ok(result) => Ok(result),
+            Err(HashTreeError::EntryNotFound) => Err(HashTreeError::EntryNotFound),
             Err(e) => {
                 warn!("Hash tree lookup failed: {e}, falling back to linear search");
                 self.fallback_to_linear_search(fs, block_dev, dir_inode, target_name)
@@ -111,18 +141,53 @@ impl HashTreeManager {
         }
     }
 
-    /// Get hash tree root block number
-    fn get_root_block<B: BlockDevice>(
+    /// Build the manager for one directory from its root info
+    fn for_directory(
+        &self,
+        fs: &Ext4FileSystem,
+        root: &HashTreeNode,
+    ) -> Result<Self, HashTreeError> {
+        let HashTreeNode::Root {
+            hash_version,
+            indirect_levels,
+            ..
+        } = *root
+        else {
+            return Err(HashTreeError::InvalidHashTree);
+        };
+        if hash_version > Ext4DxRootInfo::DX_HASH_TEA {
+            return Err(HashTreeError::UnsupportedHashVersion);
+        }
+        if indirect_levels as usize >= max_htree_levels(fs) {
+            return Err(HashTreeError::CorruptedHashTree);
+        }
+        Ok(Self {
+            hash_seed: self.hash_seed,
+            hash_version: htree_dir::effective_hash_version(hash_version, fs.superblock.s_flags),
+            indirect_levels,
+        })
+    }
+
+    /// Hash of a filename for this directory
+    fn hash(&self, name: &[u8]) -> u32 {
+        htree_dir::calculate_hash(name, self.hash_version, &self.hash_seed)
+    }
+
+    /// Read a directory block by logical block number
+    fn read_dir_block<B: BlockDevice>(
         &self,
+        fs: &mut Ext4FileSystem,
         block_dev: &mut Jbd2Dev<B>,
         dir_inode: &Ext4Inode,
-    ) -> Result<u32, HashTreeError> {
-        // Root block is usually the first data block of the directory
-        match resolve_inode_block(block_dev, &mut dir_inode.clone(), 0) {
-            Ok(Some(block)) => Ok(block),
-            Ok(None) => Err(HashTreeError::InvalidHashTree),
-            Err(_) => Err(HashTreeError::BlockOutOfRange),
-        }
+        lbn: u32,
+    ) -> Result<(u64, Vec<u8>), HashTreeError> {
+        let phys = match resolve_inode_block(block_dev, &mut dir_inode.clone(), lbn) {
+            Ok(Some(block)) => block as u64,
+            Ok(None) => return Err(HashTreeError::InvalidHashTree),
+            Err(_) => return Err(HashTreeError::BlockOutOfRange),
+        };
+        let data = self.read_block_data(fs, block_dev, phys)?;
+        Ok((phys, data))
     }
 
     /// Read block data
@@ -130,9 +195,9 @@ impl HashTreeManager {
         &self,
         fs: &mut Ext4FileSystem,
         block_dev: &mut Jbd2Dev<B>,
-        block_num: u32,
+        block_num: u64,
     ) -> Result<Vec<u8>, HashTreeError> {
-        match fs.datablock_cache.get_or_load(block_dev, block_num as u64) {
+        match fs.datablock_cache.get_or_load(block_dev, block_num) {
             Ok(cached_block) => Ok(cached_block.data.clone()),
             Err(_) => Err(HashTreeError::BlockOutOfRange),
         }
@@ -140,30 +205,38 @@ impl HashTreeManager {
 
     /// Parse root node
     fn parse_root_node(&self, data: &[u8]) -> Result<HashTreeNode, HashTreeError> {
-        if data.len() < core::mem::size_of::<Ext4DxRoot>() {
+        if data.len() < DX_ROOT_COUNT_OFFSET + 8 {
             return Err(HashTreeError::BufferTooSmall);
         }
 
-        // Parse root node info
-        let dot = Ext4DirEntryInfo::parse_from_bytes(&data[0..8])
+        // "." must be a 12-byte entry followed by ".." covering the rest of the block
+        let dot = Ext4DirEntryInfo::parse_from_bytes(&data[0..12])
             .ok_or(HashTreeError::CorruptedHashTree)?;
-
-        let dotdot = Ext4DirEntryInfo::parse_from_bytes(&data[dot.inode as usize..])
+        let dot_rec_len = read_u16_le(&data[4..6]) as usize;
+        let dotdot = Ext4DirEntryInfo::parse_from_bytes(&data[12..24])
             .ok_or(HashTreeError::CorruptedHashTree)?;
-
-        // Extract root info
-        let info_offset = dot.inode as usize + dotdot.inode as usize;
-        if info_offset + core::mem::size_of::<Ext4DxRootInfo>() > data.len() {
+        let dotdot_rec_len = read_u16_le(&data[16..18]) as usize;
+        if !dot.is_dot()
+            || !dotdot.is_dotdot()
+            || dot_rec_len != 12
+            || dotdot_rec_len != data.len() - 12
+        {
             return Err(HashTreeError::CorruptedHashTree);
         }
 
-        let info_bytes = &data[info_offset..info_offset + core::mem::size_of::<Ext4DxRootInfo>()];
-        let hash_version = info_bytes[5]; // hash_version field is at offset 5
-        let indirect_levels = info_bytes[6]; // indirect_levels field is at offset 6
+        // dx_root_info follows ".."
+        let info = &data[0x18..DX_ROOT_COUNT_OFFSET];
+        if read_u32_le(&info[0..4]) != 0 || info[5] != Ext4DxRootInfo::INFO_LENGTH {
+            return Err(HashTreeError::CorruptedHashTree);
+        }
+        let hash_version = info[4];
+        let indirect_levels = info[6];
 
-        // Parse hash entries
-        let entries_offset = info_offset + core::mem::size_of::<Ext4DxRootInfo>();
-        let entries = self.parse_dx_entries(&data[entries_offset..])?;
+        let (_limit, entries) = self.parse_countlimit(
+            data,
+            DX_ROOT_COUNT_OFFSET,
+            dx_limit(data.len(), DX_ROOT_COUNT_OFFSET, false),
+        )?;
 
         Ok(HashTreeNode::Root {
             hash_version,
@@ -172,6 +245,29 @@ impl HashTreeManager {
         })
     }
 
+    /// Parse the `dx_countlimit` header and the entries that follow it
+    fn parse_countlimit(
+        &self,
+        data: &[u8],
+        count_offset: usize,
+        max_limit: u16,
+    ) -> Result<(u16, Vec<Ext4DxEntry>), HashTreeError> {
+        let limit = read_u16_le(&data[count_offset..count_offset + 2]);
+        let count = read_u16_le(&data[count_offset + 2..count_offset + 4]) as usize;
+        if limit == 0 || limit > max_limit || count == 0 || count > limit as usize {
+            return Err(HashTreeError::CorruptedHashTree);
+        }
+
+        let end = count_offset + count * core::mem::size_of::<Ext4DxEntry>();
+        let mut entries = self.parse_dx_entries(&data[count_offset..end])?;
+        if entries.len() != count {
+            return Err(HashTreeError::CorruptedHashTree);
+        }
+        // The first entry's hash slot holds the countlimit itself
+        entries[0].hash = 0;
+        Ok((limit, entries))
+    }
+
     /// Parse DX entry array
     fn parse_dx_entries(&self, data: &[u8]) -> Result<Vec<Ext4DxEntry>, HashTreeError> {
         let mut entries = Vec::new();
@@ -192,61 +288,137 @@ impl HashTreeManager {
         Ok(entries)
     }
 
-    /// Search in hash tree
-    fn search_in_hash_tree<B: BlockDevice>(
+    /// Parse internal node
+    fn parse_internal_node(&self, data: &[u8]) -> Result<HashTreeNode, HashTreeError> {
+        if data.len() < DX_NODE_COUNT_OFFSET + 8 {
+            return Err(HashTreeError::BufferTooSmall);
+        }
+
+        // The fake directory entry is unused and spans the whole block
+        let fake_inode = read_u32_le(&data[0..4]);
+        let fake_rec_len = read_u16_le(&data[4..6]) as usize;
+        if fake_inode != 0 || fake_rec_len != data.len() {
+            return Err(HashTreeError::CorruptedHashTree);
+        }
+
+        let (_limit, entries) = self.parse_countlimit(
+            data,
+            DX_NODE_COUNT_OFFSET,
+            dx_limit(data.len(), DX_NODE_COUNT_OFFSET, false),
+        )?;
+
+        Ok(HashTreeNode::Internal { entries, level: 0 })
+    }
+
+    /// Walk from the root to the leaf covering `hash`, recording every index level
+    fn probe<B: BlockDevice>(
         &self,
         fs: &mut Ext4FileSystem,
         block_dev: &mut Jbd2Dev<B>,
-        node: &HashTreeNode,
-        target_hash: u32,
-        target_name: &[u8],
-    ) -> Result<HashTreeSearchResult, HashTreeError> {
-        match node {
-            HashTreeNode::Root { entries, .. } => {
-                self.search_in_entries(fs, block_dev, entries, target_hash, target_name, 0)
+        dir_inode: &Ext4Inode,
+        root_phys: u64,
+        root_data: &[u8],
+        hash: u32,
+    ) -> Result<Vec<DxFrame>, HashTreeError> {
+        let mut frames = Vec::with_capacity(self.indirect_levels as usize + 1);
+        let mut phys = root_phys;
+        let mut data = root_data.to_vec();
+        let mut count_offset = DX_ROOT_COUNT_OFFSET;
+
+        for level in 0..=self.indirect_levels {
+            let max_limit = dx_limit(data.len(), count_offset, false);
+            let (limit, entries) = self.parse_countlimit(&data, count_offset, max_limit)?;
+
+            // Largest entry whose hash is <= target (entry 0 covers everything below entry 1)
+            let at = entries[1..].partition_point(|e| e.hash <= hash);
+            let next = entries[at].block;
+            frames.push(DxFrame {
+                phys,
+                count_offset,
+                limit,
+                entries,
+                at,
+            });
+
+            if level < self.indirect_levels {
+                let (node_phys, node_data) = self.read_dir_block(fs, block_dev, dir_inode, next)?;
+                self.parse_internal_node(&node_data)?;
+                phys = node_phys;
+                data = node_data;
+                count_offset = DX_NODE_COUNT_OFFSET;
             }
-            HashTreeNode::Internal { entries, .. } => {
-                self.search_in_entries(fs, block_dev, entries, target_hash, target_name, 0)
+        }
+
+        Ok(frames)
+    }
+
+    /// Advance the path to the next leaf; returns the hash that starts it
+    fn next_leaf<B: BlockDevice>(
+        &self,
+        fs: &mut Ext4FileSystem,
+        block_dev: &mut Jbd2Dev<B>,
+        dir_inode: &Ext4Inode,
+        frames: &mut [DxFrame],
+    ) -> Result<Option<u32>, HashTreeError> {
+        // Find the lowest level that still has entries to the right
+        let mut level = frames.len();
+        loop {
+            if level == 0 {
+                return Ok(None);
             }
-            HashTreeNode::Leaf { block_num, .. } => {
-                self.search_in_leaf_block(fs, block_dev, *block_num, target_name)
+            level -= 1;
+            if frames[level].at + 1 < frames[level].entries.len() {
+                break;
             }
         }
+        frames[level].at += 1;
+        let start_hash = frames[level].entries[frames[level].at].hash;
+
+        // Descend along the leftmost path below it
+        for lower in level + 1..frames.len() {
+            let upper = &frames[lower - 1];
+            let block = upper.entries[upper.at].block;
+            let (phys, data) = self.read_dir_block(fs, block_dev, dir_inode, block)?;
+            let HashTreeNode::Internal { entries, .. } = self.parse_internal_node(&data)? else {
+                return Err(HashTreeError::CorruptedHashTree);
+            };
+            frames[lower].phys = phys;
+            frames[lower].entries = entries;
+            frames[lower].at = 0;
+        }
+        Ok(Some(start_hash))
     }
 
-    /// Search in entry list
-    fn search_in_entries<B: BlockDevice>(
+    /// Search in hash tree
+    fn search_in_hash_tree<B: BlockDevice>(
         &self,
         fs: &mut Ext4FileSystem,
         block_dev: &mut Jbd2Dev<B>,
-        entries: &[Ext4DxEntry],
-        target_hash: u32,
+        dir_inode: &Ext4Inode,
         target_name: &[u8],
-        level: u32,
     ) -> Result<HashTreeSearchResult, HashTreeError> {
-        // Find appropriate entry (largest entry with hash <= target hash)
-        let mut selected_entry = None;
-        for entry in entries {
-            if entry.hash <= target_hash {
-                selected_entry = Some(entry);
-            } else {
-                break;
-            }
-        }
+        let (root_phys, root_data) = self.read_dir_block(fs, block_dev, dir_inode, 0)?;
+        let root = self.parse_root_node(&root_data)?;
+        let dir = self.for_directory(fs, &root)?;
 
-        let entry = selected_entry.ok_or(HashTreeError::EntryNotFound)?;
+        let target_hash = dir.hash(target_name);
+        debug!("Target hash value: 0x{target_hash:08x}");
 
-        // Read target block
-        let block_data = self.read_block_data(fs, block_dev, entry.block)?;
+        let mut frames = dir.probe(fs, block_dev, dir_inode, root_phys, &root_data, target_hash)?;
+        loop {
+            let leaf = frames.last().ok_or(HashTreeError::CorruptedHashTree)?;
+            let leaf_block = leaf.entries[leaf.at].block;
+            if let Ok(result) =
+                dir.search_in_leaf_block(fs, block_dev, dir_inode, leaf_block, target_name)
+            {
+                return Ok(result);
+            }
 
-        // Check if this is a leaf node
-        if level >= self.indirect_levels as u32 {
-            // Leaf node, search for specific directory entries within it
-            self.search_in_leaf_data(&block_data, target_name, entry.block)
-        } else {
-            // Internal node, recursive search
-            let internal_node = self.parse_internal_node(&block_data)?;
-            self.search_in_hash_tree(fs, block_dev, &internal_node, target_hash, target_name)
+            // Names with colliding hashes may continue in the following leaf
+            match dir.next_leaf(fs, block_dev, dir_inode, &mut frames)? {
+                Some(next_hash) if next_hash & !1 == target_hash => continue,
+                _ => return Err(HashTreeError::EntryNotFound),
+            }
         }
     }
 
@@ -255,11 +427,12 @@ impl HashTreeManager {
         &self,
         fs: &mut Ext4FileSystem,
         block_dev: &mut Jbd2Dev<B>,
-        block_num: u32,
+        dir_inode: &Ext4Inode,
+        lbn: u32,
         target_name: &[u8],
     ) -> Result<HashTreeSearchResult, HashTreeError> {
-        let block_data = self.read_block_data(fs, block_dev, block_num)?;
-        self.search_in_leaf_data(&block_data, target_name, block_num)
+        let (phys, block_data) = self.read_dir_block(fs, block_dev, dir_inode, lbn)?;
+        self.search_in_leaf_data(&block_data, target_name, phys)
     }
 
     /// Search in leaf data
@@ -267,51 +440,9 @@ impl HashTreeManager {
         &self,
         data: &[u8],
         target_name: &[u8],
-        block_num: u32,
+        block_num: u64,
     ) -> Result<HashTreeSearchResult, HashTreeError> {
-        let iter = DirEntryIterator::new(data);
-
-        for (entry, offset) in iter {
-            if entry.name == target_name {
-                return Ok(HashTreeSearchResult {
-                    entry: unsafe {
-                        core::mem::transmute::<Ext4DirEntryInfo<'_>, Ext4DirEntryInfo<'_>>(entry)
-                    },
-                    block_num,
-                    offset: offset as usize,
-                });
-            }
-        }
-
-        Err(HashTreeError::EntryNotFound)
-    }
-
-    /// Parse internal node
-    fn parse_internal_node(&self, data: &[u8]) -> Result<HashTreeNode, HashTreeError> {
-        if data.len() < core::mem::size_of::<Ext4DxNode>() {
-            return Err(HashTreeError::BufferTooSmall);
-        }
-
-        // Skip fake directory entries
-        let fake_entry_size = core::mem::size_of::<Ext4DirEntry2>();
-        let countlimit_offset = fake_entry_size;
-
-        if countlimit_offset + core::mem::size_of::<Ext4DxCountlimit>() > data.len() {
-            return Err(HashTreeError::CorruptedHashTree);
-        }
-
-        let countlimit_bytes =
-            &data[countlimit_offset..countlimit_offset + core::mem::size_of::<Ext4DxCountlimit>()];
-        let _count = read_u16_le(&countlimit_bytes[2..4]) as usize; // count field is at offset 2
-
-        // Parse entries
-        let entries_offset = countlimit_offset + core::mem::size_of::<Ext4DxCountlimit>();
-        let entries = self.parse_dx_entries(&data[entries_offset..])?;
-
-        Ok(HashTreeNode::Internal {
-            entries,
-            level: 0, // Should actually read from node
-        })
+        find_entry_with_offset(data, target_name, block_num).ok_or(HashTreeError::EntryNotFound)
     }
 
     /// Fall back to linear search
@@ -349,22 +480,14 @@ impl HashTreeManager {
                     None => continue,
                 };
 
-                let cached_block = match fs.datablock_cache.get_or_load(block_dev, phys as u64) {
+                let cached_block = match fs.datablock_cache.get_or_load(block_dev, phys) {
                     Ok(block) => block,
                     Err(_) => return Err(HashTreeError::BlockOutOfRange),
                 };
 
                 let block_data = &cached_block.data[..block_bytes];
-                if let Some(entry) = classic_dir::find_entry(block_data, target_name) {
-                    return Ok(HashTreeSearchResult {
-                        entry: unsafe {
-                            core::mem::transmute::<Ext4DirEntryInfo<'_>, Ext4DirEntryInfo<'_>>(
-                                entry,
-                            )
-                        },
-                        block_num: phys as u32,
-                        offset: 0,
-                    });
+                if let Some(result) = find_entry_with_offset(block_data, target_name, phys) {
+                    return Ok(result);
                 }
             }
 
@@ -376,6 +499,33 @@ impl HashTreeManager {
     }
 }
 
+/// Find a live entry by name in a directory block, recording its offset
+fn find_entry_with_offset(
+    data: &[u8],
+    target_name: &[u8],
+    block_num: u64,
+) -> Option<HashTreeSearchResult> {
+    let mut offset = 0usize;
+    while offset + 8 <= data.len() {
+        let rec_len = read_u16_le(&data[offset + 4..offset + 6]) as usize;
+        if rec_len < 8 || offset + rec_len > data.len() {
+            return None;
+        }
+        if let Some(entry) = Ext4DirEntryInfo::parse_from_bytes(&data[offset..offset + rec_len])
+            && entry.name == target_name
+        {
+            return Some(HashTreeSearchResult {
+                inode: entry.inode,
+                file_type: entry.file_type,
+                block_num,
+                offset,
+            });
+        }
+        offset += rec_len;
+    }
+    None
+}
+
 /// Hash tree node type
 #[derive(Debug)]
 pub enum HashTreeNode {
@@ -426,7 +576,7 @@ pub fn create_hash_tree_manager(fs: &Ext4FileSystem) -> HashTreeManager {
     HashTreeManager::new(
         fs.superblock.s_hash_seed,
         fs.superblock.s_def_hash_version,
-        0, // indirect_levels, needs to be read from directory inode
+        0, // indirect_levels, read from the directory root on lookup
     )
 }
 
@@ -441,6 +591,405 @@ pub fn lookup_directory_entry<B: BlockDevice>(
     manager.lookup(fs, block_dev, dir_inode, target_name)
 }
 
+/// Check if a directory is htree indexed (needs both the inode flag and `dir_index`)
+pub fn is_dx_dir(fs: &Ext4FileSystem, inode: &Ext4Inode) -> bool {
+    inode.is_dir()
+        && inode.is_htree_indexed()
+        && fs
+            .superblock
+            .has_feature_compat(Ext4Superblock::EXT4_FEATURE_COMPAT_DIR_INDEX)
+}
+
+/// Maximum depth of the index (root included)
+fn max_htree_levels(fs: &Ext4FileSystem) -> usize {
+    if fs
+        .superblock
+        .has_feature_incompat(Ext4Superblock::EXT4_FEATURE_INCOMPAT_LARGEDIR)
+    {
+        3
+    } else {
+        2
+    }
+}
+
+/// Number of index entries that fit in a node, leaving room for `dx_tail` with metadata_csum
+fn dx_limit(block_size: usize, count_offset: usize, has_csum: bool) -> u16 {
+    let room = block_size - count_offset - if has_csum { DX_TAIL_SIZE } else { 0 };
+    (room / core::mem::size_of::<Ext4DxEntry>()) as u16
+}
+
+/// Write the index entries of a node followed by its `dx_countlimit`
+fn write_dx_entries(data: &mut [u8], count_offset: usize, limit: u16, entries: &[Ext4DxEntry]) {
+    for (i, entry) in entries.iter().enumerate() {
+        let off = count_offset + i * core::mem::size_of::<Ext4DxEntry>();
+        write_u32_le(entry.hash, &mut data[off..off + 4]);
+        write_u32_le(entry.block, &mut data[off + 4..off + 8]);
+    }
+    write_u16_le(limit, &mut data[count_offset..count_offset + 2]);
+    write_u16_le(
+        entries.len() as u16,
+        &mut data[count_offset + 2..count_offset + 4],
+    );
+}
+
+/// Initialize an empty internal index node holding `entries`
+fn init_dx_node(data: &mut [u8], limit: u16, entries: &[Ext4DxEntry]) {
+    data.fill(0);
+    write_u16_le(data.len() as u16, &mut data[4..6]);
+    write_dx_entries(data, DX_NODE_COUNT_OFFSET, limit, entries);
+}
+
+/// Minimal-length copies of the live entries of a leaf block, with their hashes
+fn collect_leaf_entries(manager: &HashTreeManager, data: &[u8]) -> Vec<(u32, Vec<u8>)> {
+    let mut map = Vec::new();
+    let mut offset = 0usize;
+    while offset + 8 <= data.len() {
+        let rec_len = read_u16_le(&data[offset + 4..offset + 6]) as usize;
+        if rec_len < 8 || offset + rec_len > data.len() {
+            break;
+        }
+        if let Some(entry) = Ext4DirEntryInfo::parse_from_bytes(&data[offset..offset + rec_len]) {
+            let len = Ext4DirEntry2::entry_len(entry.name.len() as u8) as usize;
+            let mut raw = data[offset..offset + 8 + entry.name.len()].to_vec();
+            raw.resize(len, 0);
+            write_u16_le(len as u16, &mut raw[4..6]);
+            map.push((manager.hash(entry.name), raw));
+        }
+        offset += rec_len;
+    }
+    map
+}
+
+/// Rewrite a leaf block with packed entries; the last one spans to `payload_len`
+fn write_leaf_entries(data: &mut [u8], entries: &[(u32, Vec<u8>)], payload_len: usize) {
+    data.fill(0);
+    let mut offset = 0usize;
+    for (i, (_, raw)) in entries.iter().enumerate() {
+        data[offset..offset + raw.len()].copy_from_slice(raw);
+        if i + 1 == entries.len() {
+            write_u16_le(
+                (payload_len - offset) as u16,
+                &mut data[offset + 4..offset + 6],
+            );
+        }
+        offset += raw.len();
+    }
+    if entries.is_empty() {
+        write_u16_le(payload_len as u16, &mut data[4..6]);
+    }
+}
+
+/// Insert a directory entry into an htree indexed directory, maintaining the index
+///
+/// Returns `Ok(false)` if the index is unusable and the directory should be treated
+/// as linear; fails with `NoSpace` once the index reaches its maximum depth.
+pub fn dx_insert_entry<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+    new_entry: &Ext4DirEntry2,
+) -> BlockDevResult<bool> {
+    let manager = create_hash_tree_manager(fs);
+    let name = &new_entry.name[..new_entry.name_len as usize];
+    let has_csum = fs.superblock.has_metadata_csum();
+
+    loop {
+        let Ok((root_phys, root_data)) = manager.read_dir_block(fs, block_dev, dir_inode, 0) else {
+            return Ok(false);
+        };
+        let Ok(dir) = manager
+            .parse_root_node(&root_data)
+            .and_then(|root| manager.for_directory(fs, &root))
+        else {
+            warn!("Directory {dir_ino} has a corrupted htree root");
+            return Ok(false);
+        };
+        let hash = dir.hash(name);
+        let Ok(frames) = dir.probe(fs, block_dev, dir_inode, root_phys, &root_data, hash) else {
+            warn!("Directory {dir_ino} has a corrupted htree index");
+            return Ok(false);
+        };
+
+        let bottom = &frames[frames.len() - 1];
+        let leaf_lbn = bottom.entries[bottom.at].block;
+        let Ok((leaf_phys, _)) = dir.read_dir_block(fs, block_dev, dir_inode, leaf_lbn) else {
+            return Ok(false);
+        };
+
+        let mut inserted = false;
+        fs.datablock_cache.modify(block_dev, leaf_phys, |data| {
+            let block_bytes = if has_csum && has_dir_tail(data) {
+                BLOCK_SIZE - Ext4DirEntryTail::TAIL_LEN as usize
+            } else {
+                BLOCK_SIZE
+            };
+            inserted = insert_entry_into_block(data, block_bytes, new_entry);
+        })?;
+        if inserted {
+            fs.update_dir_block_csum(block_dev, dir_ino, leaf_phys, false)?;
+            return Ok(true);
+        }
+
+        // The leaf must be split; make room in the index first, then retry from the root
+        if bottom.is_full() {
+            grow_index(fs, block_dev, dir_ino, dir_inode, &frames)?;
+            continue;
+        }
+
+        split_leaf(
+            fs, block_dev, dir_ino, dir_inode, &dir, &frames, leaf_phys, hash, new_entry,
+        )?;
+        return Ok(true);
+    }
+}
+
+/// Split the lowest non-full index level, or add a level below the root when every
+/// node on the path is full
+fn grow_index<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+    frames: &[DxFrame],
+) -> BlockDevResult<()> {
+    let mut level = frames.len() - 1;
+    while level > 0 && frames[level - 1].is_full() {
+        level -= 1;
+    }
+    let add_level = level == 0;
+    if add_level && frames.len() >= max_htree_levels(fs) {
+        warn!("Directory {dir_ino} index full at {} levels", frames.len());
+        return Err(BlockDevError::NoSpace);
+    }
+
+    let node_limit = dx_limit(
+        BLOCK_SIZE,
+        DX_NODE_COUNT_OFFSET,
+        fs.superblock.has_metadata_csum(),
+    );
+    let (new_lbn, new_phys) = append_dir_block(fs, block_dev, dir_ino, dir_inode)?;
+
+    if add_level {
+        // Move all root entries into a new node that becomes the root's only child
+        let root = &frames[0];
+        fs.datablock_cache.modify(block_dev, new_phys, |data| {
+            init_dx_node(data, node_limit, &root.entries);
+        })?;
+        fs.datablock_cache.modify(block_dev, root.phys, |data| {
+            let child = Ext4DxEntry {
+                hash: 0,
+                block: new_lbn,
+            };
+            write_dx_entries(data, root.count_offset, root.limit, &[child]);
+            data[0x1e] += 1;
+        })?;
+        fs.update_dx_node_csum(block_dev, dir_ino, new_phys)?;
+        fs.update_dx_node_csum(block_dev, dir_ino, root.phys)?;
+        return Ok(());
+    }
+
+    // Move the upper half of the node into a new sibling and link it from the parent
+    let node = &frames[level];
+    let split = node.entries.len() / 2;
+    let hash2 = node.entries[split].hash;
+    fs.datablock_cache.modify(block_dev, new_phys, |data| {
+        init_dx_node(data, node_limit, &node.entries[split..]);
+    })?;
+    fs.datablock_cache.modify(block_dev, node.phys, |data| {
+        write_dx_entries(data, node.count_offset, node.limit, &node.entries[..split]);
+    })?;
+    dx_insert_block(fs, block_dev, &frames[level - 1], hash2, new_lbn)?;
+    fs.update_dx_node_csum(block_dev, dir_ino, new_phys)?;
+    fs.update_dx_node_csum(block_dev, dir_ino, node.phys)?;
+    fs.update_dx_node_csum(block_dev, dir_ino, frames[level - 1].phys)?;
+    Ok(())
+}
+
+/// Insert an index entry right after the one the frame points at
+fn dx_insert_block<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    frame: &DxFrame,
+    hash: u32,
+    block: u32,
+) -> BlockDevResult<()> {
+    let mut entries = frame.entries.clone();
+    entries.insert(frame.at + 1, Ext4DxEntry { hash, block });
+    fs.datablock_cache.modify(block_dev, frame.phys, |data| {
+        write_dx_entries(data, frame.count_offset, frame.limit, &entries);
+    })
+}
+
+/// Split a full leaf by hash into itself and a new block, then insert the entry
+#[allow(clippy::too_many_arguments)]
+fn split_leaf<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+    dir: &HashTreeManager,
+    frames: &[DxFrame],
+    leaf_phys: u64,
+    hash: u32,
+    new_entry: &Ext4DirEntry2,
+) -> BlockDevResult<()> {
+    let payload_len = fs.dir_block_payload_len();
+    let leaf_data = fs
+        .datablock_cache
+        .get_or_load(block_dev, leaf_phys)?
+        .data
+        .clone();
+    let mut map = collect_leaf_entries(dir, &leaf_data[..payload_len]);
+    map.sort_by_key(|(h, _)| *h);
+
+    // Split the block in the middle, size-wise
+    let count = map.len();
+    let mut size = 0usize;
+    let mut moved = 0usize;
+    let mut stopped = None;
+    for i in (0..count).rev() {
+        if size + map[i].1.len() / 2 > BLOCK_SIZE / 2 {
+            stopped = Some(i);
+            break;
+        }
+        size += map[i].1.len();
+        moved += 1;
+    }
+    let split = match stopped {
+        Some(i) if i > 0 => count - moved,
+        _ => count / 2,
+    };
+    if split == 0 {
+        // Only fragmented free space: compact the leaf in place
+        let mut inserted = false;
+        fs.datablock_cache.modify(block_dev, leaf_phys, |data| {
+            write_leaf_entries(data, &map, payload_len);
+            inserted = insert_entry_into_block(data, payload_len, new_entry);
+        })?;
+        fs.update_dir_block_csum(block_dev, dir_ino, leaf_phys, true)?;
+        return if inserted {
+            Ok(())
+        } else {
+            Err(BlockDevError::NoSpace)
+        };
+    }
+
+    let hash2 = map[split].0;
+    let continued = (hash2 == map[split - 1].0) as u32;
+
+    let (new_lbn, new_phys) = append_dir_block(fs, block_dev, dir_ino, dir_inode)?;
+    fs.datablock_cache.modify(block_dev, leaf_phys, |data| {
+        write_leaf_entries(data, &map[..split], payload_len);
+    })?;
+    fs.datablock_cache.modify(block_dev, new_phys, |data| {
+        write_leaf_entries(data, &map[split..], payload_len);
+    })?;
+
+    // Which block gets the new entry?
+    let target = if hash >= hash2 { new_phys } else { leaf_phys };
+    let mut inserted = false;
+    fs.datablock_cache.modify(block_dev, target, |data| {
+        inserted = insert_entry_into_block(data, payload_len, new_entry);
+    })?;
+
+    dx_insert_block(
+        fs,
+        block_dev,
+        &frames[frames.len() - 1],
+        hash2 + continued,
+        new_lbn,
+    )?;
+    fs.update_dir_block_csum(block_dev, dir_ino, leaf_phys, true)?;
+    fs.update_dir_block_csum(block_dev, dir_ino, new_phys, true)?;
+    fs.update_dx_node_csum(block_dev, dir_ino, frames[frames.len() - 1].phys)?;
+
+    if inserted {
+        Ok(())
+    } else {
+        Err(BlockDevError::NoSpace)
+    }
+}
+
+/// Convert a full single-block linear directory into an htree indexed one and insert
+/// the entry
+///
+/// Block 0 becomes the index root and its entries move to a new leaf. Returns
+/// `Ok(false)` if the directory layout does not allow the conversion.
+pub fn make_indexed_dir<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+    new_entry: &Ext4DirEntry2,
+) -> BlockDevResult<bool> {
+    let hash_version = fs.superblock.s_def_hash_version;
+    if hash_version > Ext4DxRootInfo::DX_HASH_TEA {
+        return Ok(false);
+    }
+
+    let manager = create_hash_tree_manager(fs);
+    let Ok((root_phys, root_data)) = manager.read_dir_block(fs, block_dev, dir_inode, 0) else {
+        return Ok(false);
+    };
+    let has_csum = fs.superblock.has_metadata_csum();
+    let block_bytes = if has_csum && has_dir_tail(&root_data) {
+        BLOCK_SIZE - Ext4DirEntryTail::TAIL_LEN as usize
+    } else {
+        BLOCK_SIZE
+    };
+
+    // "." must be 12 bytes long and followed by ".."
+    let dot_rec_len = read_u16_le(&root_data[4..6]) as usize;
+    let dotdot_rec_len = read_u16_le(&root_data[16..18]) as usize;
+    let dots_ok = Ext4DirEntryInfo::parse_from_bytes(&root_data[0..12]).is_some_and(|e| e.is_dot())
+        && Ext4DirEntryInfo::parse_from_bytes(&root_data[12..24]).is_some_and(|e| e.is_dotdot());
+    let start = 12 + dotdot_rec_len;
+    if !dots_ok || dot_rec_len != 12 || start + 8 > block_bytes {
+        return Ok(false);
+    }
+
+    dir_inode.i_flags |= Ext4Inode::EXT4_INDEX_FL;
+    let (leaf_lbn, leaf_phys) = append_dir_block(fs, block_dev, dir_ino, dir_inode)?;
+
+    // Move the entries after ".." into the new leaf, stretching the last one to its end
+    let payload_len = fs.dir_block_payload_len();
+    let moved = &root_data[start..block_bytes];
+    fs.datablock_cache.modify(block_dev, leaf_phys, |data| {
+        data[..moved.len()].copy_from_slice(moved);
+        let mut offset = 0usize;
+        loop {
+            let rec_len = read_u16_le(&data[offset + 4..offset + 6]) as usize;
+            if rec_len < 8 || offset + rec_len >= moved.len() {
+                break;
+            }
+            offset += rec_len;
+        }
+        write_u16_le(
+            (payload_len - offset) as u16,
+            &mut data[offset + 4..offset + 6],
+        );
+    })?;
+
+    // Block 0 keeps "." and ".." and becomes the index root
+    let root_limit = dx_limit(BLOCK_SIZE, DX_ROOT_COUNT_OFFSET, has_csum);
+    fs.datablock_cache.modify(block_dev, root_phys, |data| {
+        data[24..].fill(0);
+        write_u16_le((BLOCK_SIZE - 12) as u16, &mut data[16..18]);
+        data[0x1c] = hash_version;
+        data[0x1d] = Ext4DxRootInfo::INFO_LENGTH;
+        let leaf = Ext4DxEntry {
+            hash: 0,
+            block: leaf_lbn,
+        };
+        write_dx_entries(data, DX_ROOT_COUNT_OFFSET, root_limit, &[leaf]);
+    })?;
+    fs.update_dir_block_csum(block_dev, dir_ino, leaf_phys, true)?;
+    fs.update_dx_node_csum(block_dev, dir_ino, root_phys)?;
+
+    dx_insert_entry(fs, block_dev, dir_ino, dir_inode, new_entry)
+}
+
 #[cfg(test)]
 mod tests {
     use alloc::vec::Vec;
@@ -550,6 +1099,8 @@ mod tests {
             group_count: 1,
             mounted: true,
             journal_sb_block_start: None,
+            delalloc: crate::delalloc::DelayedAlloc::new(),
+            freed_blocks: Default::default(),
         }
     }
 
@@ -726,4 +1277,85 @@ mod tests {
 
         assert!(matches!(result, Err(HashTreeError::EntryNotFound)));
     }
+
+    #[test]
+    fn test_htree_hash_known_values() {
+        // Reference values from `debugfs -R "dx_hash -h <alg> <name>"` with a zero seed
+        let zero = [0u32; 4];
+        let long = b"a_much_longer_file_name_exceeding_thirty_two_bytes.txt";
+        let cases: [(&[u8], u8, u32); 9] = [
+            (b"hello", Ext4DxRootInfo::DX_HASH_LEGACY, 0x3225_2546),
+            (b"hello", Ext4DxRootInfo::DX_HASH_HALF_MD4, 0x1746_da32),
+            (b"hello", Ext4DxRootInfo::DX_HASH_TEA, 0x6f5b_b1a8),
+            (long, Ext4DxRootInfo::DX_HASH_LEGACY, 0x9bee_d270),
+            (long, Ext4DxRootInfo::DX_HASH_HALF_MD4, 0x140f_45bc),
+            (long, Ext4DxRootInfo::DX_HASH_TEA, 0x974c_dc30),
+            (
+                "café".as_bytes(),
+                Ext4DxRootInfo::DX_HASH_LEGACY_UNSIGNED,
+                0x6dde_4230,
+            ),
+            (
+                "café".as_bytes(),
+                Ext4DxRootInfo::DX_HASH_HALF_MD4_UNSIGNED,
+                0x9d72_aed6,
+            ),
+            (
+                "café".as_bytes(),
+                Ext4DxRootInfo::DX_HASH_TEA_UNSIGNED,
+                0x6621_f032,
+            ),
+        ];
+
+        for (name, version, expected) in cases {
+            assert_eq!(htree_dir::calculate_hash(name, version, &zero), expected);
+        }
+        assert_eq!(
+            htree_dir::calculate_hash("café".as_bytes(), Ext4DxRootInfo::DX_HASH_HALF_MD4, &zero),
+            0xfb9c_5e5c
+        );
+    }
+
+    #[test]
+    fn test_root_node_parsing() {
+        let fs = create_test_fs();
+        let manager = create_hash_tree_manager(&fs);
+
+        let mut root = alloc::vec![0u8; 4096];
+        Ext4DirEntry2::new(2, 12, Ext4DirEntry2::EXT4_FT_DIR, b".").to_disk_bytes(&mut root[0..8]);
+        root[8] = b'.';
+        Ext4DirEntry2::new(2, 4084, Ext4DirEntry2::EXT4_FT_DIR, b"..")
+            .to_disk_bytes(&mut root[12..20]);
+        root[20..22].copy_from_slice(b"..");
+        root[0x1c] = Ext4DxRootInfo::DX_HASH_HALF_MD4;
+        root[0x1d] = Ext4DxRootInfo::INFO_LENGTH;
+        root[0x1e] = 1;
+        let entries = [
+            Ext4DxEntry { hash: 0, block: 1 },
+            Ext4DxEntry {
+                hash: 0x8000_0000,
+                block: 2,
+            },
+        ];
+        write_dx_entries(&mut root, DX_ROOT_COUNT_OFFSET, 507, &entries);
+
+        match manager.parse_root_node(&root).unwrap() {
+            HashTreeNode::Root {
+                hash_version,
+                indirect_levels,
+                entries,
+            } => {
+                assert_eq!(hash_version, Ext4DxRootInfo::DX_HASH_HALF_MD4);
+                assert_eq!(indirect_levels, 1);
+                assert_eq!(entries.len(), 2);
+                assert_eq!((entries[0].hash, entries[0].block), (0, 1));
+                assert_eq!((entries[1].hash, entries[1].block), (0x8000_0000, 2));
+            }
+            _ => panic!("Expected root node"),
+        }
+
+        // ".." must cover the rest of the block
+        root[16..18].copy_from_slice(&12u16.to_le_bytes());
+        assert!(manager.parse_root_node(&root).is_err());
+    }
 }
@@ -4,7 +4,9 @@
 
 use alloc::{collections::BTreeMap, vec::Vec};
 
-use crate::{blockdev::*, config::*, disknode::*, endian::*, error::*};
+use log::warn;
+
+use crate::{blockdev::*, checksum::*, config::*, disknode::*, endian::*, error::*};
 /// Inode缓存键（全局inode号）
 pub type InodeCacheKey = u64;
 
@@ -66,6 +68,8 @@ pub struct InodeCache {
     access_counter: u64,
     /// 每个inode的大小=
     inode_size: usize,
+    /// metadata_csum 校验种子，未启用时为 None
+    csum_seed: Option<u32>,
 }
 
 impl InodeCache {
@@ -78,9 +82,15 @@ impl InodeCache {
             max_entries,
             access_counter: 0,
             inode_size,
+            csum_seed: None,
         }
     }
 
+    /// 启用 metadata_csum：写回时计算 inode 校验和
+    pub fn set_csum_seed(&mut self, seed: u32) {
+        self.csum_seed = Some(seed);
+    }
+
     /// 创建默认配置的缓存
     pub fn default(inode_size: u16) -> Self {
         Self::new(INODE_CACHE_MAX, inode_size as usize)
@@ -120,6 +130,7 @@ impl InodeCache {
     fn load_inode<B: BlockDevice>(
         &self,
         block_dev: &mut Jbd2Dev<B>,
+        inode_num: u64,
         block_num: u64,
         offset: usize,
     ) -> BlockDevResult<Ext4Inode> {
@@ -130,7 +141,13 @@ impl InodeCache {
             return Err(BlockDevError::Corrupted);
         }
 
-        let inode = Ext4Inode::from_disk_bytes(&buffer[offset..offset + self.inode_size]);
+        let raw = &buffer[offset..offset + self.inode_size];
+        if let Some(seed) = self.csum_seed
+            && !verify_inode_csum(seed, inode_num as u32, raw)
+        {
+            warn!("Inode {inode_num} checksum mismatch");
+        }
+        let inode = Ext4Inode::from_disk_bytes(raw);
 
         Ok(inode)
     }
@@ -155,7 +172,7 @@ impl InodeCache {
             }
 
             // 从磁盘加载
-            let inode = self.load_inode(block_dev, block_num, offset)?;
+            let inode = self.load_inode(block_dev, inode_num, block_num, offset)?;
             let cached = CachedInode::new(inode, inode_num, block_num, offset);
             self.cache.insert(inode_num, cached);
         }
@@ -183,7 +200,7 @@ impl InodeCache {
                 self.evict_lru(block_dev)?;
             }
 
-            let inode = self.load_inode(block_dev, block_num, offset)?;
+            let inode = self.load_inode(block_dev, inode_num, block_num, offset)?;
             let cached = CachedInode::new(inode, inode_num, block_num, offset);
             self.cache.insert(inode_num, cached);
         }
@@ -280,55 +297,45 @@ impl InodeCache {
         if let Some(cached) = self.cache.remove(&inode_num)
             && cached.dirty
         {
-            Self::write_inode_static(
-                block_dev,
-                &cached.inode,
-                cached.block_num,
-                cached.offset_in_block,
-                self.inode_size,
-            )?;
+            self.write_inode(block_dev, &cached)?;
         }
         Ok(())
     }
 
     /// 刷新所有脏inode到磁盘
     pub fn flush_all<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
-        // 收集所有脏 inode，对应 (block_num, offset_in_block, encoded_bytes)
-        let mut dirty_inodes: Vec<(u64, usize, Vec<u8>)> = self
-            .cache
-            .values()
-            .filter(|cached| cached.dirty)
-            .map(|cached| {
-                let mut buffer = alloc::vec![0u8; self.inode_size];
-                cached.inode.to_disk_bytes(&mut buffer);
-                (cached.block_num, cached.offset_in_block, buffer)
-            })
-            .collect();
+        // 收集所有脏 inode
+        let mut dirty_inodes: Vec<&CachedInode> =
+            self.cache.values().filter(|cached| cached.dirty).collect();
 
         if dirty_inodes.is_empty() {
             return Ok(());
         }
 
         // 先按 (block_num, offset) 排序，方便按块聚合写回
-        dirty_inodes.sort_by_key(|(block_num, offset, _)| (*block_num, *offset));
+        dirty_inodes.sort_by_key(|cached| (cached.block_num, cached.offset_in_block));
 
         let mut idx = 0usize;
         while idx < dirty_inodes.len() {
-            let (block_num, ..) = dirty_inodes[idx];
+            let block_num = dirty_inodes[idx].block_num;
 
             // 读出当前 inode 表块到 Jbd2Dev 的 buffer
             block_dev.read_block(block_num as u32)?;
             {
                 let buffer = block_dev.buffer_mut();
 
-                // 将该块上所有脏 inode 的字节写入同一个 buffer 中
-                while idx < dirty_inodes.len() && dirty_inodes[idx].0 == block_num {
-                    let (_b, offset, ref data) = dirty_inodes[idx];
-                    let end = offset + data.len();
+                // 将该块上所有脏 inode 原地编码到同一个 buffer 中
+                while idx < dirty_inodes.len() && dirty_inodes[idx].block_num == block_num {
+                    let cached = dirty_inodes[idx];
+                    let end = cached.offset_in_block + self.inode_size;
                     if end > buffer.len() {
                         return Err(BlockDevError::Corrupted);
                     }
-                    buffer[offset..end].copy_from_slice(data);
+                    Self::encode_inode(
+                        self.csum_seed,
+                        cached,
+                        &mut buffer[cached.offset_in_block..end],
+                    );
                     idx += 1;
                 }
             }
@@ -354,12 +361,7 @@ impl InodeCache {
         if let Some(cached) = self.cache.get(&inode_num)
             && cached.dirty
         {
-            let block_num = cached.block_num;
-            let offset = cached.offset_in_block;
-            let mut buffer = alloc::vec![0u8; self.inode_size];
-            cached.inode.to_disk_bytes(&mut buffer);
-
-            Self::write_inode_bytes_static(block_dev, block_num, offset, &buffer)?;
+            self.write_inode(block_dev, cached)?;
 
             if let Some(cached) = self.cache.get_mut(&inode_num) {
                 cached.dirty = false;
@@ -368,33 +370,84 @@ impl InodeCache {
         Ok(())
     }
 
+    /// 将inode编码到磁盘字节中
+    ///
+    /// 原地覆盖，保留 inode 尾部的内联扩展属性；启用 metadata_csum 时同时更新校验和
+    fn encode_inode(csum_seed: Option<u32>, cached: &CachedInode, raw: &mut [u8]) {
+        cached.inode.to_disk_bytes(raw);
+        if let Some(seed) = csum_seed {
+            set_inode_csum(seed, cached.inode_num as u32, raw);
+        }
+    }
+
     /// 写inode到磁盘
-    fn write_inode_static<B: BlockDevice>(
+    fn write_inode<B: BlockDevice>(
+        &self,
+        block_dev: &mut Jbd2Dev<B>,
+        cached: &CachedInode,
+    ) -> BlockDevResult<()> {
+        let offset = cached.offset_in_block;
+        block_dev.read_block(cached.block_num as u32)?;
+        let buffer = block_dev.buffer_mut();
+        if offset + self.inode_size > buffer.len() {
+            return Err(BlockDevError::Corrupted);
+        }
+
+        Self::encode_inode(
+            self.csum_seed,
+            cached,
+            &mut buffer[offset..offset + self.inode_size],
+        );
+
+        block_dev.write_block(cached.block_num as u32, true)?; //只供崩溃恢复用
+        Ok(())
+    }
+
+    /// 读取 inode 的原始字节（长度为 inode_size），包括尾部的内联扩展属性
+    ///
+    /// 缓存中尚未写回的修改不会反映在结果中
+    pub fn read_raw<B: BlockDevice>(
+        &self,
         block_dev: &mut Jbd2Dev<B>,
-        inode: &Ext4Inode,
         block_num: u64,
         offset: usize,
-        inode_size: usize,
-    ) -> BlockDevResult<()> {
-        let mut buffer = alloc::vec![0u8; inode_size];
-        inode.to_disk_bytes(&mut buffer);
-        Self::write_inode_bytes_static(block_dev, block_num, offset, &buffer)
+    ) -> BlockDevResult<Vec<u8>> {
+        block_dev.read_block(block_num as u32)?;
+        let buffer = block_dev.buffer();
+        if offset + self.inode_size > buffer.len() {
+            return Err(BlockDevError::Corrupted);
+        }
+        Ok(buffer[offset..offset + self.inode_size].to_vec())
     }
 
-    /// 写inode字节到磁盘
-    fn write_inode_bytes_static<B: BlockDevice>(
+    /// 改写 inode 原始字节中从 `from` 开始的尾部（内联扩展属性区）
+    ///
+    /// 先写回缓存中该 inode 的修改，再在磁盘上原地改写尾部并重新计算校验和
+    pub fn write_raw_tail<B: BlockDevice>(
+        &mut self,
         block_dev: &mut Jbd2Dev<B>,
+        inode_num: u64,
         block_num: u64,
         offset: usize,
+        from: usize,
         data: &[u8],
     ) -> BlockDevResult<()> {
+        if from + data.len() > self.inode_size {
+            return Err(BlockDevError::InvalidInput);
+        }
+        self.flush(block_dev, inode_num)?;
+
         block_dev.read_block(block_num as u32)?;
         let buffer = block_dev.buffer_mut();
-
-        buffer[offset..offset + data.len()].copy_from_slice(data);
-
-        block_dev.write_block(block_num as u32, true)?; //只供崩溃恢复用
-        Ok(())
+        if offset + self.inode_size > buffer.len() {
+            return Err(BlockDevError::Corrupted);
+        }
+        let raw = &mut buffer[offset..offset + self.inode_size];
+        raw[from..from + data.len()].copy_from_slice(data);
+        if let Some(seed) = self.csum_seed {
+            set_inode_csum(seed, inode_num as u32, raw);
+        }
+        block_dev.write_block(block_num as u32, true)
     }
 
     /// 清空缓存（不写回）
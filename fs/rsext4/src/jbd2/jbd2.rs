@@ -2,7 +2,7 @@
 //!
 //! 实现了 ext4 文件系统的日志功能，确保事务的原子性和一致性。
 
-use alloc::{vec, vec::Vec};
+use alloc::{collections::BTreeMap, vec, vec::Vec};
 
 use log::{debug, info, warn};
 
@@ -11,54 +11,234 @@ use crate::{
     loopfile::*,
 };
 
+/// revoke 块头大小（通用头 + r_count）
+const REVOKE_HEADER_SIZE: usize = 16;
+/// 不带 SAME_UUID 标志的 tag 之后的 UUID 大小
+const TAG_UUID_SIZE: usize = 16;
+
+/// 日志重放结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Jbd2ReplayStats {
+    /// 重放的完整事务数
+    pub transactions: u32,
+    /// 写回主盘的块数
+    pub replayed: u32,
+    /// 因撤销记录跳过的块数
+    pub revoked: u32,
+}
+
+/// 扫描日志得到的一个完整事务
+#[derive(Default)]
+struct ScannedTransaction {
+    /// (目标块号, 日志内相对块号, tag flags)
+    blocks: Vec<(u64, u32, u16)>,
+    /// 本事务撤销的块
+    revoked: Vec<u64>,
+}
+
 impl JBD2DEVSYSTEM {
-    /// 计算下一个日志块的位置(处理回绕),返回当前的（可以直接用，直接写，已经处理过偏移）!
+    fn has_incompat(&self, feature: u32) -> bool {
+        self.jbd2_super_block.s_feature_incompat & feature != 0
+    }
+
+    /// 日志区最后一个相对块号：s_maxlen 是包含日志超级块在内的日志总块数
+    fn last_rel(&self) -> u32 {
+        self.jbd2_super_block.s_maxlen.saturating_sub(1)
+    }
+
+    /// 一个 tag 在 descriptor 中占用的字节数（不含 UUID）
+    fn tag_bytes(&self) -> usize {
+        if self.has_incompat(JBD2_FEATURE_INCOMPAT_CSUM_V3) {
+            16
+        } else if self.has_incompat(JBD2_FEATURE_INCOMPAT_64BIT) {
+            12
+        } else {
+            8
+        }
+    }
+
+    /// 一条撤销记录的字节数
+    fn revoke_record_bytes(&self) -> usize {
+        if self.has_incompat(JBD2_FEATURE_INCOMPAT_64BIT) {
+            8
+        } else {
+            4
+        }
+    }
+
+    /// descriptor / revoke 块末尾校验和的大小
+    fn block_tail_bytes(&self) -> usize {
+        if self.has_incompat(JBD2_FEATURE_INCOMPAT_CSUM_V2 | JBD2_FEATURE_INCOMPAT_CSUM_V3) {
+            4
+        } else {
+            0
+        }
+    }
+
+    /// 按日志特性编码一个 tag
+    fn encode_tag(&self, bytes: &mut [u8], block_nr: u64, flags: u16) {
+        if self.tag_bytes() == 16 {
+            JouranlBlockTag3S {
+                t_blocknr: block_nr as u32,
+                t_flags: flags as u32,
+                t_blocknr_high: (block_nr >> 32) as u32,
+                t_checksum: 0,
+            }
+            .to_disk_bytes(bytes);
+            return;
+        }
+        JournalBlockTagS {
+            t_blocknr: block_nr as u32,
+            t_checksum: 0,
+            t_flags: flags,
+        }
+        .to_disk_bytes(bytes);
+        if self.tag_bytes() == 12 {
+            bytes[8..12].copy_from_slice(&((block_nr >> 32) as u32).to_be_bytes());
+        }
+    }
+
+    /// 解析 descriptor 块中的 tag：(目标块号, flags)
+    fn parse_descriptor(&self, block: &[u8]) -> Vec<(u64, u16)> {
+        let tag_bytes = self.tag_bytes();
+        let end = BLOCK_SIZE - self.block_tail_bytes();
+        let is_64bit = self.has_incompat(JBD2_FEATURE_INCOMPAT_64BIT);
+        let uuid = &self.jbd2_super_block.s_uuid[..];
+        let mut tags = Vec::new();
+        let mut off = JournalHeaderS::disk_size();
+        while off + tag_bytes <= end {
+            let raw = &block[off..off + tag_bytes];
+            let (low, high, flags) = if tag_bytes == 16 {
+                let tag = JouranlBlockTag3S::from_disk_bytes(raw);
+                (tag.t_blocknr, tag.t_blocknr_high, tag.t_flags as u16)
+            } else {
+                let tag = JournalBlockTagS::from_disk_bytes(raw);
+                let high = if tag_bytes == 12 {
+                    u32::from_be_bytes(raw[8..12].try_into().unwrap())
+                } else {
+                    0
+                };
+                (tag.t_blocknr, high, tag.t_flags)
+            };
+            let high = if is_64bit { high } else { 0 };
+            tags.push(((high as u64) << 32 | low as u64, flags));
+            off += tag_bytes;
+
+            // 不带 SAME_UUID 的 tag 后跟日志超级块的 UUID；早期版本写入的 tag 既没有该标志
+            // 也没有 UUID，按内容区分
+            if flags & JBD2_FLAG_SAME_UUID == 0 && block.get(off..off + TAG_UUID_SIZE) == Some(uuid)
+            {
+                off += TAG_UUID_SIZE;
+            }
+            if flags & JBD2_FLAG_LAST_TAG != 0 {
+                break;
+            }
+        }
+        tags
+    }
+
+    /// 解析 revoke 块中被撤销的块号
+    fn parse_revoke(&self, block: &[u8]) -> Vec<u64> {
+        let head = Jbd2JournalRevokeHeadS::from_disk_bytes(block);
+        let record = self.revoke_record_bytes();
+        let end =
+            (head.r_count as usize).clamp(REVOKE_HEADER_SIZE, BLOCK_SIZE - self.block_tail_bytes());
+        block[REVOKE_HEADER_SIZE..end]
+            .chunks_exact(record)
+            .map(|raw| {
+                if record == 8 {
+                    u64::from_be_bytes(raw.try_into().unwrap())
+                } else {
+                    u32::from_be_bytes(raw.try_into().unwrap()) as u64
+                }
+            })
+            .collect()
+    }
+
+    /// 写回日志超级块（读-改-写，避免破坏其它字节）
+    fn write_journal_superblock<B: BlockDevice>(&self, block_dev: &mut B) -> BlockDevResult<()> {
+        let mut blk = [0u8; BLOCK_SIZE];
+        block_dev.read(&mut blk, self.start_block, 1)?;
+        self.jbd2_super_block.to_disk_bytes(&mut blk[0..1024]);
+        debug!(
+            "[JBD2] write journal superblock to block={} (sequence={} s_start={})",
+            self.start_block, self.jbd2_super_block.s_sequence, self.jbd2_super_block.s_start
+        );
+        block_dev.write(&blk, self.start_block, 1)?;
+        block_dev.flush()
+    }
+
+    /// 计算下一个日志块的位置,返回当前的（可以直接用，直接写，已经处理过偏移）!
+    ///
+    /// 回绕由 [`reserve_log_space`](Self::reserve_log_space) 在事务开始前处理
     pub fn set_next_log_block<B: BlockDevice>(&mut self, block_dev: &mut B) -> u32 {
-        // 处理第一次使用journal提交
+        // 处理第一次使用journal提交：日志从 s_first 开始，期待的第一个事务就是当前事务
         if self.jbd2_super_block.s_start == 0 {
-            // 更新内存的s_start
             self.jbd2_super_block.s_start = self.jbd2_super_block.s_first;
-            // 写入超级块
-            let mut sb_data = [0u8; BLOCK_SIZE];
-            block_dev
-                .read(&mut sb_data, self.start_block, 1)
-                .expect("Read superblock failed");
-            self.jbd2_super_block.to_disk_bytes(&mut sb_data);
-            block_dev
-                .write(&sb_data, self.start_block, 1)
+            self.jbd2_super_block.s_sequence = self.sequence;
+            self.head = 0;
+            self.write_journal_superblock(block_dev)
                 .expect("Write superblock failed");
-            self.head += 1;
-            let mut target_use = self.start_block + self.jbd2_super_block.s_start + self.head - 1;
-            // 处理环绕
-            if target_use - self.start_block > self.max_len {
-                self.head = 0;
-                target_use = self.start_block + self.jbd2_super_block.s_start;
-            }
-            target_use
-        } else {
-            // 不是第一次提交
-            self.head += 1;
-            // 处理环绕
-            let mut target_use = self.start_block + self.jbd2_super_block.s_start + self.head - 1;
-            if target_use - self.start_block > self.max_len {
-                self.head = 0;
-                target_use = self.start_block + self.jbd2_super_block.s_start;
-            }
-            target_use
         }
+        self.head += 1;
+        self.start_block + self.jbd2_super_block.s_start + self.head - 1
+    }
+
+    /// 为即将写入的 `blocks` 个日志块预留连续空间
+    ///
+    /// 事务提交后立即检查点（写回主盘），日志中已有的事务都不再需要：剩余空间不够时
+    /// 把日志起点移回 s_first，保证一个事务不会跨越日志末尾
+    fn reserve_log_space<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut B,
+        blocks: u32,
+    ) -> BlockDevResult<()> {
+        let first = self.jbd2_super_block.s_first;
+        let last = self.last_rel();
+        if first == 0 || last < first || blocks > last - first + 1 {
+            return Err(BlockDevError::NoSpace);
+        }
+        let start = self.jbd2_super_block.s_start;
+        if start == 0 || start + self.head + blocks - 1 <= last {
+            return Ok(());
+        }
+        debug!("[JBD2 commit] journal full, restart log at rel_block={first}");
+        self.jbd2_super_block.s_start = first;
+        self.jbd2_super_block.s_sequence = self.sequence;
+        self.head = 0;
+        self.journaled.clear();
+        self.write_journal_superblock(block_dev)
+    }
+
+    /// 撤销块 `block` 在日志中的旧副本
+    ///
+    /// 块释放后可能作为普通数据重新使用，崩溃后重放旧的元数据副本会覆盖新数据
+    pub fn revoke(&mut self, block: u64) {
+        self.commit_queue.retain(|update| update.0 != block);
+        if self.journaled.remove(&block) && !self.revoke_queue.contains(&block) {
+            self.revoke_queue.push(block);
+        }
+    }
+
+    /// 块重新作为元数据写入日志，取消当前事务对它的撤销
+    pub fn cancel_revoke(&mut self, block: u64) {
+        self.revoke_queue.retain(|&b| b != block);
     }
 
     /// 提交事务
     /// 允许使用原始块设备!
-    /// update:Vec<JBD2_UPDATE>
+    ///
+    /// 依次写 descriptor 与元数据块、revoke 块和 commit 块，commit 块落盘后把元数据
+    /// 写回主盘（检查点）
     #[allow(clippy::result_unit_err)]
     pub fn commit_transaction<B: BlockDevice>(&mut self, block_dev: &mut B) -> Result<bool, ()> {
         let tid = self.sequence; //事务id
         debug!(
-            "[JBD2 commit] begin: tid={} updates_len={} head={} start_block={} max_len={} \
-             seq_in_superblock={} s_start={}",
+            "[JBD2 commit] begin: tid={} updates_len={} revokes_len={} head={} start_block={} \
+             max_len={} seq_in_superblock={} s_start={}",
             tid,
             self.commit_queue.len(),
+            self.revoke_queue.len(),
             self.head,
             self.start_block,
             self.max_len,
@@ -66,88 +246,123 @@ impl JBD2DEVSYSTEM {
             self.jbd2_super_block.s_start,
         );
 
-        if self.commit_queue.is_empty() {
+        if self.commit_queue.is_empty() && self.revoke_queue.is_empty() {
             warn!("No thing need to commit");
             return Ok(false);
         }
 
-        let mut desc_buffer = vec![0; BLOCK_SIZE];
+        let header_size = JournalHeaderS::disk_size();
+        let tags_per_desc = (BLOCK_SIZE - header_size - self.block_tail_bytes()) / self.tag_bytes();
+        let records_per_revoke = (BLOCK_SIZE - REVOKE_HEADER_SIZE - self.block_tail_bytes())
+            / self.revoke_record_bytes();
+        let log_blocks = self.commit_queue.len().div_ceil(tags_per_desc)
+            + self.commit_queue.len()
+            + self.revoke_queue.len().div_ceil(records_per_revoke)
+            + 1;
+        if let Err(e) = self.reserve_log_space(block_dev, log_blocks as u32) {
+            warn!("[JBD2 commit] tid={tid} needs {log_blocks} journal blocks: {e:?}");
+            return Err(());
+        }
+        if !self.revoke_queue.is_empty() && !self.has_incompat(JBD2_FEATURE_INCOMPAT_REVOKE) {
+            self.jbd2_super_block.s_feature_incompat |= JBD2_FEATURE_INCOMPAT_REVOKE;
+            self.write_journal_superblock(block_dev)
+                .expect("Write superblock failed");
+        }
 
-        // 写header->内存缓存
-        let new_jbd_header = JournalHeaderS {
-            h_blocktype: 1,
-            h_sequence: tid,
-            ..Default::default()
-        };
-        new_jbd_header.to_disk_bytes(&mut desc_buffer[0..JournalHeaderS::disk_size()]);
-
-        let mut current_offset = 12; //跳过头
-        // 写many tag，目前开发测试简化为一个descriptor块能塞下:)
-        for (idx, update) in self.commit_queue.iter().enumerate() {
-            // 检查逃逸escape 如果数据块开头也是jbd2_magic 要标志逃逸
-            let mut tag = JournalBlockTagS {
-                t_blocknr: update.0 as u32,
-                t_checksum: 0,
-                t_flags: 0, // 后面记得处理逃逸
-            };
-            let magic: u32 = u32::from_le_bytes(update.1[0..4].try_into().unwrap());
-            if magic == JBD2_MAGIC {
-                tag.t_flags |= JOURANL_ESCAPE;
-                debug!("JOURNAL ERROR ,Updates data escape!!!");
-            }
+        let updates = core::mem::take(&mut self.commit_queue);
+        let revoked = core::mem::take(&mut self.revoke_queue);
 
-            // 最后一个
-            if idx == self.commit_queue.len() - 1 {
-                tag.t_flags |= JBD2_FLAG_LAST_TAG;
+        // descriptor + 元数据块，元数据块开头与 jbd2 魔数相同时需要逃逸
+        for chunk in updates.chunks(tags_per_desc) {
+            let mut desc_buffer = vec![0; BLOCK_SIZE];
+            JournalHeaderS {
+                h_blocktype: JBD2_DESCRIPTOR_BLOCK,
+                h_sequence: tid,
+                ..Default::default()
+            }
+            .to_disk_bytes(&mut desc_buffer[0..header_size]);
+
+            let mut current_offset = header_size; //跳过头
+            for (idx, update) in chunk.iter().enumerate() {
+                // 所有 tag 都不带 UUID
+                let mut flags = JBD2_FLAG_SAME_UUID;
+                if u32::from_be_bytes(update.1[0..4].try_into().unwrap()) == JBD2_MAGIC {
+                    flags |= JOURANL_ESCAPE;
+                }
+                if idx == chunk.len() - 1 {
+                    flags |= JBD2_FLAG_LAST_TAG;
+                }
+                debug!(
+                    "[JBD2 commit] tid={} tag_idx={} t_blocknr={} t_flags=0x{:x}",
+                    tid, idx, update.0, flags,
+                );
+                let tag_end = current_offset + self.tag_bytes();
+                self.encode_tag(&mut desc_buffer[current_offset..tag_end], update.0, flags);
+                current_offset = tag_end;
             }
-            debug!(
-                "[JBD2 commit] tid={} tag_idx={} t_blocknr={} t_flags=0x{:x}",
-                tid, idx, tag.t_blocknr, tag.t_flags,
-            );
-            tag.to_disk_bytes(&mut desc_buffer[current_offset..current_offset + 8]);
-            current_offset += 8;
-        }
 
-        // 实际写入盘 这里可以直接写
-        let block_id = self.set_next_log_block(block_dev);
-        debug!("[JBD2 commit] tid={tid} descriptor_block_id={block_id} (absolute)");
-        block_dev
-            .write(&desc_buffer, block_id, 1)
-            .expect("Jouranl block write failed!");
+            let block_id = self.set_next_log_block(block_dev);
+            debug!("[JBD2 commit] tid={tid} descriptor_block_id={block_id} (absolute)");
+            block_dev
+                .write(&desc_buffer, block_id, 1)
+                .expect("Jouranl block write failed!");
 
-        let mut no_escape: Vec<(u64, [u8; BLOCK_SIZE])> = Vec::new();
-        // 逃逸处理
-        for update in self.commit_queue.iter() {
-            // 逃逸处理
-            let mut check_data: [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
-            check_data.copy_from_slice(&*update.1);
-            let magic = u32::from_le_bytes(check_data[0..4].try_into().unwrap());
-            if magic == JBD2_MAGIC {
-                debug!("Find excape data,will fill 0");
-                check_data[0..4].fill(0);
+            // 写实际的metadata CORE!!!!!
+            for (idx, update) in chunk.iter().enumerate() {
+                let mut data = *update.1;
+                if u32::from_be_bytes(data[0..4].try_into().unwrap()) == JBD2_MAGIC {
+                    debug!("Find excape data,will fill 0");
+                    data[0..4].fill(0);
+                }
+                let metadata_journal_block_id = self.set_next_log_block(block_dev);
+                debug!(
+                    "[JBD2 commit] tid={} meta_idx={} journal_block_id={} (absolute) \
+                     target_phys_block={}",
+                    tid, idx, metadata_journal_block_id, update.0
+                );
+                block_dev
+                    .write(&data, metadata_journal_block_id, 1)
+                    .expect("Jouranl block write failed!");
             }
-            no_escape.push((update.0, check_data));
         }
 
-        // 写实际的metadata CORE!!!!!
-        for (idx, up) in no_escape.iter().enumerate() {
-            let metadata_journal_block_id = self.set_next_log_block(block_dev);
+        // revoke 块
+        let record = self.revoke_record_bytes();
+        for chunk in revoked.chunks(records_per_revoke) {
+            let mut revoke_buffer = [0_u8; BLOCK_SIZE];
+            Jbd2JournalRevokeHeadS {
+                r_header: JournalHeaderS {
+                    h_blocktype: JBD2_REVOKE_BLOCK,
+                    h_sequence: tid,
+                    ..Default::default()
+                },
+                r_count: (REVOKE_HEADER_SIZE + chunk.len() * record) as u32,
+            }
+            .to_disk_bytes(&mut revoke_buffer);
+            for (&block, raw) in chunk
+                .iter()
+                .zip(revoke_buffer[REVOKE_HEADER_SIZE..].chunks_exact_mut(record))
+            {
+                if record == 8 {
+                    raw.copy_from_slice(&block.to_be_bytes());
+                } else {
+                    raw.copy_from_slice(&(block as u32).to_be_bytes());
+                }
+            }
+            let revoke_block_id = self.set_next_log_block(block_dev);
             debug!(
-                "[JBD2 commit] tid={} meta_idx={} journal_block_id={} (absolute) \
-                 target_phys_block={}",
-                tid, idx, metadata_journal_block_id, up.0
+                "[JBD2 commit] tid={} revoke_block_id={} (absolute) records={}",
+                tid,
+                revoke_block_id,
+                chunk.len()
             );
             block_dev
-                .write(&up.1, metadata_journal_block_id, 1)
+                .write(&revoke_buffer, revoke_block_id, 1)
                 .expect("Jouranl block write failed!");
         }
 
         block_dev.flush().expect("Jouranl block write failed!");
 
-        // 清空update缓存
-        self.commit_queue.clear();
-        debug!("[JBD2 BUFFER] BUFFER ALREADY CLEA");
-
         // 写入Commit Block
 
         let mut commit_buffer = [0_u8; BLOCK_SIZE];
@@ -156,7 +371,7 @@ impl JBD2DEVSYSTEM {
             // commit block type 2
             h_header: JournalHeaderS {
                 h_magic: JBD2_MAGIC,
-                h_blocktype: 2,
+                h_blocktype: JBD2_COMMIT_BLOCK,
                 h_sequence: tid,
             }, // 注意完成的tid
             h_chksum_type: 0,
@@ -175,6 +390,16 @@ impl JBD2DEVSYSTEM {
             .expect("Jouranl block write failed!");
         // 至此，commit已经完成，metadata数据已经安全:）
         block_dev.flush().expect("Jouranl block write failed!");
+
+        // 检查点：元数据写回主盘。之后崩溃，重放会重新写一遍相同的内容
+        for update in updates.iter() {
+            block_dev
+                .write(&update.1[..], update.0 as u32, 1)
+                .expect("Checkpoint block write failed!");
+            self.journaled.insert(update.0);
+        }
+        block_dev.flush().expect("Checkpoint block write failed!");
+
         self.sequence += 1;
         debug!(
             "[JBD2 commit] end: tid={} new_sequence={}",
@@ -185,225 +410,145 @@ impl JBD2DEVSYSTEM {
         Ok(true)
     }
 
-    /// 事务重放：从当前 superblock 状态开始，尽可能重放连续的完整事务 replay前确保全部commit
-    pub fn replay<B: BlockDevice>(&mut self, block_dev: &mut B) {
-        // 注意：journal_superblock_s 里的 s_first / s_start 是“日志区内部的相对块号”，
-        // 真实物理块号 = self.start_block + rel。
-
-        // 扫描起点（相对块号）：只使用 s_start。s_start==0 表示没有需要重放的事务。
-        let mut journal_rel = self.jbd2_super_block.s_start;
-        if journal_rel == 0 {
-            return;
-        }
-
-        let first_rel = self.jbd2_super_block.s_first; // 第一个日志块（相对 superblock）
-        let maxlen = self.jbd2_super_block.s_maxlen; // 可用日志块数量（不含 superblock）
-        let last_rel = first_rel.saturating_add(maxlen.saturating_sub(1));
-        let mut expect_seq = self.jbd2_super_block.s_sequence;
-
-        // 简单防护：maxlen 为 0 直接返回
-        if maxlen == 0 {
-            return;
-        }
-
-        debug!(
-            "[JBD2 replay] begin: journal_sb_phys={} first_rel={} last_rel={} s_start(rel)={} \
-             maxlen={} expect_seq={}",
-            self.start_block, first_rel, last_rel, journal_rel, maxlen, expect_seq,
-        );
-
-        // 相对块号前进（含回绕）
-        let advance_rel = |rel: &mut u32| {
-            if *rel >= last_rel {
-                *rel = first_rel;
-            } else {
-                *rel = rel.saturating_add(1);
-            }
-        };
-
-        loop {
-            // 1) 读取 descriptor 块并做基本校验
-            let mut desc_buf = [0u8; BLOCK_SIZE];
-            let desc_phys = self.start_block + journal_rel; // descriptor 物理块号
-            if let Err(e) = block_dev.read(&mut desc_buf, desc_phys, 1) {
-                debug!(
-                    "[JBD2 replay] read descriptor failed at rel_block={journal_rel} \
-                     phys_block={desc_phys} err={e:?}"
-                );
-                break;
-            }
+    /// 所有事务都已提交并检查点后把日志标记为空，下次挂载无需重放
+    pub fn mark_clean<B: BlockDevice>(&mut self, block_dev: &mut B) -> BlockDevResult<()> {
+        self.jbd2_super_block.s_start = 0;
+        self.jbd2_super_block.s_sequence = self.sequence;
+        self.head = 0;
+        self.journaled.clear();
+        self.write_journal_superblock(block_dev)
+    }
 
-            let hdr = JournalHeaderS::from_disk_bytes(&desc_buf[0..12]);
+    /// 扫描日志，返回从 s_start 开始连续完整提交的事务
+    ///
+    /// 遇到魔数或序列号不符的块即认为日志结束，最后一个没有 commit 块的事务被丢弃
+    fn scan_log<B: BlockDevice>(
+        &self,
+        block_dev: &mut B,
+    ) -> BlockDevResult<Vec<ScannedTransaction>> {
+        let first = self.jbd2_super_block.s_first;
+        let last = self.last_rel();
+        let advance = |rel: u32| if rel >= last { first } else { rel + 1 };
+
+        let mut rel = self.jbd2_super_block.s_start;
+        let mut seq = self.jbd2_super_block.s_sequence;
+        // 日志是环形的，最多扫描一圈
+        let mut remaining = last - first + 1;
+        let mut pending = ScannedTransaction::default();
+        let mut transactions = Vec::new();
+        let mut buf = [0u8; BLOCK_SIZE];
+        while remaining > 0 {
+            block_dev.read(&mut buf, self.start_block + rel, 1)?;
+            let hdr = JournalHeaderS::from_disk_bytes(&buf[0..12]);
             debug!(
-                "[JBD2 replay] descriptor: phys_block={} h_magic=0x{:x} h_blocktype={} \
-                 h_sequence={} expect_seq={}",
-                desc_phys, hdr.h_magic, hdr.h_blocktype, hdr.h_sequence, expect_seq
+                "[JBD2 replay] scan: rel_block={} h_magic=0x{:x} h_blocktype={} h_sequence={} \
+                 expect_seq={}",
+                rel, hdr.h_magic, hdr.h_blocktype, hdr.h_sequence, seq
             );
-            if hdr.h_magic != JBD2_MAGIC || hdr.h_blocktype != 1 {
-                // 不是合法的 descriptor，认为后面没有可重放事务
+            if hdr.h_magic != JBD2_MAGIC || hdr.h_sequence != seq {
                 break;
             }
-            if hdr.h_sequence != expect_seq {
-                // 序列号不匹配，认为没有更多可重放事务
-                break;
-            }
-
-            // 2) 解析 descriptor 里的 tags
-            let mut tags: Vec<JournalBlockTagS> = Vec::new();
-            let mut off = 12usize; // 跳过 header
-            let mut tag_idx = 0usize;
-            while off + 8 <= BLOCK_SIZE {
-                let tag = JournalBlockTagS::from_disk_bytes(&desc_buf[off..off + 8]);
-
-                // 注意：t_blocknr==0 在 ext4 上是合法的（例如 superblock/group desc 等元数据），
-                // 不能直接用 "t_blocknr==0" 当作 tag 结束条件。
-                // 我们只在"当前 8 字节全 0 且后续全部为 0 padding"时，才认为 descriptor 结束。
-                if tag.t_blocknr == 0
-                    && tag.t_checksum == 0
-                    && tag.t_flags == 0
-                    && desc_buf[off + 8..].iter().all(|b| *b == 0)
-                {
-                    break;
+            remaining -= 1;
+            match hdr.h_blocktype {
+                JBD2_DESCRIPTOR_BLOCK => {
+                    let tags = self.parse_descriptor(&buf);
+                    if tags.len() as u32 > remaining {
+                        break;
+                    }
+                    remaining -= tags.len() as u32;
+                    for (target, flags) in tags {
+                        rel = advance(rel);
+                        pending.blocks.push((target, rel, flags));
+                    }
                 }
-
-                debug!(
-                    "[JBD2 replay] tid={} tag_idx={} t_blocknr={} t_flags=0x{:x}",
-                    expect_seq, tag_idx, tag.t_blocknr, tag.t_flags
-                );
-
-                let last = (tag.t_flags & JBD2_FLAG_LAST_TAG) != 0;
-                tags.push(tag);
-                off += 8;
-                tag_idx += 1;
-
-                if last {
-                    break;
+                JBD2_REVOKE_BLOCK => pending.revoked.extend(self.parse_revoke(&buf)),
+                JBD2_COMMIT_BLOCK => {
+                    transactions.push(core::mem::take(&mut pending));
+                    seq = seq.wrapping_add(1);
                 }
-            }
-
-            if tags.is_empty() {
-                // 没有任何 tag，无事务可重放
-                break;
-            }
-
-            // 3) 读取对应数量的 metadata 日志块
-            let mut meta_blocks: Vec<[u8; BLOCK_SIZE]> = Vec::new();
-            for (idx, _) in tags.iter().enumerate() {
-                // 下一个 journal 块（相对块号），注意处理回绕
-                advance_rel(&mut journal_rel);
-                let meta_phys = self.start_block + journal_rel;
-                let mut mbuf = [0u8; BLOCK_SIZE];
-                if let Err(e) = block_dev.read(&mut mbuf, meta_phys, 1) {
-                    debug!(
-                        "[JBD2 replay] read meta block failed: idx={idx} rel_block={journal_rel} \
-                         phys_block={meta_phys} err={e:?}"
-                    );
-                    return;
+                other => {
+                    warn!("[JBD2 replay] unknown block type {other} at rel_block={rel}");
+                    break;
                 }
-                debug!(
-                    "[JBD2 replay] tid={expect_seq} loaded meta_idx={idx} from \
-                     rel_block={journal_rel} phys_block={meta_phys}"
-                );
-                meta_blocks.push(mbuf);
             }
+            rel = advance(rel);
+        }
+        if !pending.blocks.is_empty() || !pending.revoked.is_empty() {
+            warn!("[JBD2 replay] tid={seq} has no commit block, discarded");
+        }
+        Ok(transactions)
+    }
 
-            // 4) 读取 commit 块并验证
-            advance_rel(&mut journal_rel);
-            let commit_rel = journal_rel;
-            let commit_phys = self.start_block + commit_rel;
-            let mut cbuf = [0u8; BLOCK_SIZE];
-            if let Err(e) = block_dev.read(&mut cbuf, commit_phys, 1) {
-                debug!(
-                    "[JBD2 replay] read commit failed at rel_block={commit_rel} \
-                     phys_block={commit_phys} err={e:?}"
-                );
-                return;
-            }
-            let chdr = JournalHeaderS::from_disk_bytes(&cbuf[0..12]);
-            debug!(
-                "[JBD2 replay] commit: rel_block={} phys_block={} h_magic=0x{:x} h_blocktype={} \
-                 h_sequence={} expect_seq={}",
-                commit_rel,
-                commit_phys,
-                chdr.h_magic,
-                chdr.h_blocktype,
-                chdr.h_sequence,
-                expect_seq
+    /// 事务重放：扫描出完整提交的事务，收集撤销记录，再把未被撤销的元数据块写回主盘
+    ///
+    /// 一个块被某个事务撤销后，该事务及之前事务中的副本都不再重放。重放后日志被标记为空
+    pub fn replay<B: BlockDevice>(&mut self, block_dev: &mut B) -> BlockDevResult<Jbd2ReplayStats> {
+        // 注意：journal_superblock_s 里的 s_first / s_start 是“日志区内部的相对块号”，
+        // 真实物理块号 = self.start_block + rel。s_start==0 表示没有需要重放的事务。
+        let mut stats = Jbd2ReplayStats::default();
+        let sb = self.jbd2_super_block;
+        if sb.s_start == 0 {
+            return Ok(stats);
+        }
+        if sb.s_first == 0 || sb.s_start < sb.s_first || sb.s_start > self.last_rel() {
+            warn!(
+                "[JBD2 replay] bad journal superblock: s_first={} s_maxlen={} s_start={}",
+                sb.s_first, sb.s_maxlen, sb.s_start
             );
-            if chdr.h_magic != JBD2_MAGIC || chdr.h_blocktype != 2 || chdr.h_sequence != expect_seq
-            {
-                // 没有匹配的 commit，事务不完整，不再继续
-                break;
-            }
+            return Err(BlockDevError::Corrupted);
+        }
+        debug!(
+            "[JBD2 replay] begin: journal_sb_phys={} first_rel={} last_rel={} s_start(rel)={} \
+             expect_seq={}",
+            self.start_block,
+            sb.s_first,
+            self.last_rel(),
+            sb.s_start,
+            sb.s_sequence,
+        );
 
-            // 5) 真正重放：把每个 metadata 块写回主盘对应的 t_blocknr
-            for (i, tag) in tags.iter().enumerate() {
-                let phys = tag.t_blocknr;
-                let data = &mut meta_blocks[i];
-
-                // 检查是否逃逸
-                if (tag.t_flags & 1) != 0 {
-                    // JBD2_FLAG_ESCAPE = 1
-                    let magic_bytes = JBD2_MAGIC.to_be_bytes();
-                    data[0] = magic_bytes[0];
-                    data[1] = magic_bytes[1];
-                    data[2] = magic_bytes[2];
-                    data[3] = magic_bytes[3];
-                    debug!("Restored JBD2 Magic for block {phys}");
-                }
-                debug!(
-                    "[JBD2 replay] tid={expect_seq} apply meta_idx={i} to phys_block={phys} \
-                     (journal data from idx={i})"
-                );
+        // 1) 扫描
+        let transactions = self.scan_log(block_dev)?;
 
-                let _ = block_dev.write(data, phys, 1);
+        // 2) 撤销记录：块号 -> 最后一个撤销它的事务
+        let mut revoked = BTreeMap::new();
+        for (idx, txn) in transactions.iter().enumerate() {
+            for &block in &txn.revoked {
+                revoked.insert(block, idx);
             }
-            let _ = block_dev.flush();
-
-            // 6) 更新内存中的 journal superblock 状态
-            expect_seq = expect_seq.wrapping_add(1);
-            self.jbd2_super_block.s_sequence = expect_seq;
-            // 更新内存
-            self.sequence = expect_seq;
-
-            // s_start 指向下一个事务起点（commit 后一块），保持为相对块号
-            let mut next_desc_rel = commit_rel;
-            advance_rel(&mut next_desc_rel);
-            self.jbd2_super_block.s_start = next_desc_rel;
-
-            debug!(
-                "[JBD2 replay] transaction applied: new_sequence={} new_s_start(rel)={}",
-                self.jbd2_super_block.s_sequence, self.jbd2_super_block.s_start
-            );
-
-            // 下一轮从新的 descriptor 起点开始
-            journal_rel = next_desc_rel;
         }
 
-        // 已经没有更多可重放事务：将 s_start 置 0 表示 journal clean
-        self.jbd2_super_block.s_start = 0;
-
-        self.head = 0; //重放完成后，head归0，从s_start开始写入
-
-        // replay 完成后写回 journal superblock（read-modify-write，避免破坏其它字节）
-        let sb_block = self.start_block;
-        if sb_block != 0 {
-            let mut blk = [0u8; BLOCK_SIZE];
-            if block_dev.read(&mut blk, sb_block, 1).is_ok() {
-                self.jbd2_super_block.to_disk_bytes(&mut blk[0..1024]);
-                debug!(
-                    "[JBD2 replay] write journal superblock to block={} (sequence={} s_start={})",
-                    sb_block, self.jbd2_super_block.s_sequence, self.jbd2_super_block.s_start
-                );
-                // 直接写，避免鬼打墙
-                let _ = block_dev.write(&blk, sb_block, 1);
-                let _ = block_dev.flush();
+        // 3) 按事务顺序写回，后面事务的副本覆盖前面的
+        let mut buf = [0u8; BLOCK_SIZE];
+        for (idx, txn) in transactions.iter().enumerate() {
+            for &(target, rel, flags) in &txn.blocks {
+                if revoked.get(&target).is_some_and(|&by| by >= idx) {
+                    debug!("[JBD2 replay] block {target} revoked, skipped");
+                    stats.revoked += 1;
+                    continue;
+                }
+                let Ok(phys) = u32::try_from(target) else {
+                    warn!("[JBD2 replay] block {target} out of range");
+                    return Err(BlockDevError::Corrupted);
+                };
+                block_dev.read(&mut buf, self.start_block + rel, 1)?;
+                if flags & JOURANL_ESCAPE != 0 {
+                    buf[0..4].copy_from_slice(&JBD2_MAGIC.to_be_bytes());
+                }
+                block_dev.write(&buf, phys, 1)?;
+                stats.replayed += 1;
             }
         }
-        debug!(
-            "[JBD2 replay] end: final_sequence={} final_s_start={} ",
-            self.jbd2_super_block.s_sequence, self.jbd2_super_block.s_start
+        block_dev.flush()?;
+
+        stats.transactions = transactions.len() as u32;
+        self.sequence = sb.s_sequence.wrapping_add(stats.transactions);
+        self.mark_clean(block_dev)?;
+        info!(
+            "[JBD2 replay] transactions={} blocks={} revoked={} next_sequence={}",
+            stats.transactions, stats.replayed, stats.revoked, self.sequence
         );
+        Ok(stats)
     }
 }
 
@@ -446,7 +591,13 @@ pub fn create_journal_entry<B: BlockDevice>(
         .get_inode_by_num(block_dev, journal_inode_num as u32)
         .unwrap();
     jour_inode.write_extend_header();
-    build_file_block_mapping(fs, &mut jour_inode, &free_block, block_dev);
+    build_file_block_mapping(
+        fs,
+        journal_inode_num as u32,
+        &mut jour_inode,
+        &free_block,
+        block_dev,
+    );
     debug!("When create jouranl inode: iblock:{:?}", jour_inode.i_block);
     let inode_size: usize = BLOCK_SIZE * free_block.len();
     // 初始化 然后写入 journal inode
@@ -461,7 +612,8 @@ pub fn create_journal_entry<B: BlockDevice>(
     .expect("Jouranl inode create faild!");
 
     let jbd2_sb = JournalSuperBllockS {
-        s_maxlen: (free_block.len() - 1) as u32,
+        // 日志总块数，包含日志超级块本身
+        s_maxlen: free_block.len() as u32,
         s_start: 0,
         s_blocksize: BLOCK_SIZE_U32,
         s_sequence: 1,
@@ -475,3 +627,217 @@ pub fn create_journal_entry<B: BlockDevice>(
     info!("Journal inode created!");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, collections::BTreeSet};
+
+    use super::*;
+
+    const TOTAL_BLOCKS: u32 = 64;
+    const JOURNAL_START: u32 = 32;
+    const JOURNAL_LEN: u32 = 16;
+
+    struct MemBlockDev {
+        data: Vec<u8>,
+    }
+
+    impl BlockDevice for MemBlockDev {
+        fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+            let start = block_id as usize * BLOCK_SIZE;
+            let len = count as usize * BLOCK_SIZE;
+            self.data[start..start + len].copy_from_slice(&buffer[..len]);
+            Ok(())
+        }
+
+        fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+            let start = block_id as usize * BLOCK_SIZE;
+            let len = count as usize * BLOCK_SIZE;
+            buffer[..len].copy_from_slice(&self.data[start..start + len]);
+            Ok(())
+        }
+
+        fn open(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn total_blocks(&self) -> u64 {
+            TOTAL_BLOCKS as u64
+        }
+
+        fn block_size(&self) -> u32 {
+            BLOCK_SIZE as u32
+        }
+    }
+
+    impl MemBlockDev {
+        fn new() -> Self {
+            let mut dev = Self {
+                data: vec![0u8; TOTAL_BLOCKS as usize * BLOCK_SIZE],
+            };
+            let sb = JournalSuperBllockS {
+                s_maxlen: JOURNAL_LEN,
+                s_uuid: [0x42; 16],
+                ..Default::default()
+            };
+            let mut raw = [0u8; BLOCK_SIZE];
+            sb.to_disk_bytes(&mut raw[..1024]);
+            dev.write(&raw, JOURNAL_START, 1).unwrap();
+            dev
+        }
+
+        fn block(&self, block_id: u32) -> &[u8] {
+            let start = block_id as usize * BLOCK_SIZE;
+            &self.data[start..start + BLOCK_SIZE]
+        }
+
+        fn zero(&mut self, block_id: u32) {
+            self.write(&[0u8; BLOCK_SIZE], block_id, 1).unwrap();
+        }
+
+        /// 按磁盘上的日志超级块构造日志系统，相当于重新挂载
+        fn journal(&mut self) -> JBD2DEVSYSTEM {
+            let mut raw = [0u8; BLOCK_SIZE];
+            self.read(&mut raw, JOURNAL_START, 1).unwrap();
+            let sb = JournalSuperBllockS::from_disk_bytes(&raw);
+            JBD2DEVSYSTEM {
+                start_block: JOURNAL_START,
+                max_len: sb.s_maxlen,
+                head: 0,
+                sequence: sb.s_sequence,
+                jbd2_super_block: sb,
+                commit_queue: Vec::new(),
+                revoke_queue: Vec::new(),
+                journaled: BTreeSet::new(),
+            }
+        }
+    }
+
+    fn update(block: u64, fill: u8) -> Jbd2Update {
+        Jbd2Update(block, Box::new([fill; BLOCK_SIZE]))
+    }
+
+    #[test]
+    fn test_replay_committed_transactions() {
+        let mut dev = MemBlockDev::new();
+        let mut journal = dev.journal();
+
+        journal.commit_queue.push(update(2, 0xa1));
+        journal.commit_queue.push(update(3, 0xb1));
+        journal.commit_transaction(&mut dev).unwrap();
+
+        // 块 2 释放后重新用作数据，旧副本必须撤销
+        journal.revoke(2);
+        journal.commit_queue.push(update(4, 0xc1));
+        journal.commit_transaction(&mut dev).unwrap();
+
+        // 开头与 jbd2 魔数相同的块需要逃逸
+        let mut escaped = update(5, 0xe1);
+        escaped.1[0..4].copy_from_slice(&JBD2_MAGIC.to_be_bytes());
+        let escaped_data = *escaped.1;
+        journal.commit_queue.push(escaped);
+        journal.commit_transaction(&mut dev).unwrap();
+
+        // 最后一个事务的 commit 块没有写完
+        journal.commit_queue.push(update(6, 0xd1));
+        journal.commit_transaction(&mut dev).unwrap();
+        let commit_block =
+            journal.start_block + journal.jbd2_super_block.s_start + journal.head - 1;
+        dev.zero(commit_block);
+
+        // 模拟检查点没有完成：主盘上的块都还是旧内容
+        for block in 2..=6 {
+            dev.zero(block);
+        }
+
+        let mut journal = dev.journal();
+        let stats = journal.replay(&mut dev).unwrap();
+        assert_eq!(
+            stats,
+            Jbd2ReplayStats {
+                transactions: 3,
+                replayed: 3,
+                revoked: 1,
+            }
+        );
+        assert!(dev.block(2).iter().all(|&b| b == 0));
+        assert!(dev.block(3).iter().all(|&b| b == 0xb1));
+        assert!(dev.block(4).iter().all(|&b| b == 0xc1));
+        assert_eq!(dev.block(5), &escaped_data[..]);
+        assert!(dev.block(6).iter().all(|&b| b == 0));
+
+        // 重放后日志为空，序列号接在最后一个完整事务之后
+        let mut journal = dev.journal();
+        assert_eq!(journal.jbd2_super_block.s_start, 0);
+        assert_eq!(journal.sequence, 4);
+        assert_eq!(
+            journal.replay(&mut dev).unwrap(),
+            Jbd2ReplayStats::default()
+        );
+    }
+
+    #[test]
+    fn test_log_restarts_when_full() {
+        let mut dev = MemBlockDev::new();
+        let mut journal = dev.journal();
+        // 每个事务占 3 个日志块，日志区只有 15 块，第 6 个事务从头开始写
+        for i in 0..6u8 {
+            journal.commit_queue.push(update(10 + i as u64, i + 1));
+            journal.commit_transaction(&mut dev).unwrap();
+        }
+        assert_eq!(journal.jbd2_super_block.s_start, 1);
+        assert_eq!(journal.jbd2_super_block.s_sequence, 6);
+        for block in 10..16 {
+            dev.zero(block);
+        }
+
+        let mut journal = dev.journal();
+        let stats = journal.replay(&mut dev).unwrap();
+        assert_eq!(stats.transactions, 1);
+        assert!(dev.block(10).iter().all(|&b| b == 0));
+        assert!(dev.block(15).iter().all(|&b| b == 6));
+        assert_eq!(journal.sequence, 7);
+    }
+
+    #[test]
+    fn test_parse_descriptor_formats() {
+        let mut dev = MemBlockDev::new();
+        let journal = dev.journal();
+        let tag = |block: &mut [u8], blocknr: u32, flags: u16| {
+            JournalBlockTagS {
+                t_blocknr: blocknr,
+                t_checksum: 0,
+                t_flags: flags,
+            }
+            .to_disk_bytes(block)
+        };
+
+        // 标准格式：第一个 tag 后跟 UUID
+        let mut desc = [0u8; BLOCK_SIZE];
+        tag(&mut desc[12..20], 100, 0);
+        desc[20..36].copy_from_slice(&[0x42; 16]);
+        tag(
+            &mut desc[36..44],
+            101,
+            JBD2_FLAG_SAME_UUID | JBD2_FLAG_LAST_TAG,
+        );
+        assert_eq!(
+            journal.parse_descriptor(&desc),
+            [(100, 0), (101, JBD2_FLAG_SAME_UUID | JBD2_FLAG_LAST_TAG)]
+        );
+
+        // 早期格式：没有 UUID 也没有 SAME_UUID 标志
+        let mut desc = [0u8; BLOCK_SIZE];
+        tag(&mut desc[12..20], 100, 0);
+        tag(&mut desc[20..28], 0, 0);
+        tag(&mut desc[28..36], 102, JBD2_FLAG_LAST_TAG);
+        assert_eq!(
+            journal.parse_descriptor(&desc),
+            [(100, 0), (0, 0), (102, JBD2_FLAG_LAST_TAG)]
+        );
+    }
+}
@@ -2,7 +2,7 @@
 //!
 //! 定义了 JBD2 日志系统使用的各种数据结构。
 
-use alloc::{boxed::Box, vec::Vec};
+use alloc::{boxed::Box, collections::BTreeSet, vec::Vec};
 use core::convert::TryInto;
 
 use crate::{config::*, endian::*};
@@ -11,7 +11,20 @@ pub const JOURNAL_FILE_INODE: u64 = 8;
 pub const JBD2_MAGIC: u32 = 0xC03B_3998u32; // jbd2 magic number (on-disk big-endian)
 pub const JOURNAL_BLOCK_COUNT: u32 = 32 * 1024 * 1024 / BLOCK_SIZE_U32;
 pub const JOURANL_ESCAPE: u16 = 0x1;
+/// tag 后面没有 16 字节 UUID
+pub const JBD2_FLAG_SAME_UUID: u16 = 0x2;
 pub const JBD2_FLAG_LAST_TAG: u16 = 0x8;
+
+/// 日志块类型
+pub const JBD2_DESCRIPTOR_BLOCK: u32 = 1;
+pub const JBD2_COMMIT_BLOCK: u32 = 2;
+pub const JBD2_REVOKE_BLOCK: u32 = 5;
+
+/// 日志超级块 incompat 特性
+pub const JBD2_FEATURE_INCOMPAT_REVOKE: u32 = 0x1;
+pub const JBD2_FEATURE_INCOMPAT_64BIT: u32 = 0x2;
+pub const JBD2_FEATURE_INCOMPAT_CSUM_V2: u32 = 0x8;
+pub const JBD2_FEATURE_INCOMPAT_CSUM_V3: u32 = 0x10;
 #[repr(C)]
 /// （主物理块号，元数据内容）
 pub struct Jbd2Update(pub u64, pub Box<[u8; BLOCK_SIZE]>);
@@ -23,6 +36,8 @@ pub struct JBD2DEVSYSTEM {
     pub head: u32,                     // commit游标(相对块号)
     pub sequence: u32,                 // 当前期待事务ID(验证和写commit用)
     pub commit_queue: Vec<Jbd2Update>, // 事务缓存
+    pub revoke_queue: Vec<u64>,        // 当前事务撤销的块
+    pub journaled: BTreeSet<u64>,      // 日志中仍可能被重放的块
 }
 
 #[repr(C)]
@@ -10,6 +10,7 @@
 //! - 数据结构管理（superblock, inodetable_cache, datablock_cache）
 //! - 辅助工具和配置（tool, config, endian）
 //! - 日志系统（jbd2）
+//! - 扩展属性与 POSIX ACL（xattr, acl）
 
 #![no_std]
 
@@ -21,10 +22,11 @@ pub use api::{lseek, open, read_at, write_at};
 pub use blockdev::{BlockDevice, Jbd2Dev};
 pub use config::{
     BITMAP_CACHE_MAX, BLOCK_SIZE, BLOCK_SIZE_U32, DATABLOCK_CACHE_MAX, DEFAULT_FEATURE_COMPAT,
-    DEFAULT_FEATURE_INCOMPAT, DEFAULT_FEATURE_RO_COMPAT, DEFAULT_INODE_SIZE, DIRNAME_LEN,
-    EXT4_MAJOR_VERSION, EXT4_MINOR_VERSION, EXT4_SUPER_MAGIC, GROUP_DESC_SIZE, GROUP_DESC_SIZE_OLD,
-    INODE_CACHE_MAX, JBD2_BUFFER_MAX, LOG_BLOCK_SIZE, RESERVED_GDT_BLOCKS, RESERVED_INODES,
-    SUPERBLOCK_OFFSET, SUPERBLOCK_SIZE,
+    DEFAULT_FEATURE_INCOMPAT, DEFAULT_FEATURE_RO_COMPAT, DEFAULT_INODE_SIZE,
+    DELALLOC_INODE_MAX_BLOCKS, DELALLOC_MAX_BLOCKS, DIRNAME_LEN, EXT4_MAJOR_VERSION,
+    EXT4_MINOR_VERSION, EXT4_SUPER_MAGIC, GROUP_DESC_SIZE, GROUP_DESC_SIZE_OLD, INODE_CACHE_MAX,
+    JBD2_BUFFER_MAX, LOG_BLOCK_SIZE, RESERVE_WINDOW_MAX_BLOCKS, RESERVED_GDT_BLOCKS,
+    RESERVED_INODES, SUPERBLOCK_OFFSET, SUPERBLOCK_SIZE,
 };
 pub use dir::mkdir;
 // 重新导出核心模块
@@ -34,15 +36,19 @@ pub use file::{
     create_symbol_link, delete_dir, delete_file, link, mkfile, mv, read_file, rename, truncate,
     unlink, write_file,
 };
+pub use xattr::{XattrError, XattrSetMode, get_xattr, list_xattr, remove_xattr, set_xattr};
 
+pub mod acl;
 pub mod api;
 pub mod bitmap;
 pub mod bitmap_cache;
 pub mod blockdev;
 pub mod blockgroup_description;
 pub mod bmalloc;
+pub mod checksum;
 pub mod config;
 pub mod datablock_cache;
+pub mod delalloc;
 pub mod dir;
 pub mod disknode;
 pub mod endian;
@@ -55,5 +61,8 @@ pub mod hashtree;
 pub mod inodetable_cache;
 pub mod jbd2;
 pub mod loopfile;
+pub mod orphan;
 pub mod superblock;
 pub mod tool;
+pub mod verify;
+pub mod xattr;
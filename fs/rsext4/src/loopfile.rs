@@ -172,8 +172,9 @@ pub fn get_file_inode<B: BlockDevice>(
         // 尝试使用哈希树查找
         match lookup_directory_entry(fs, block_dev, &current_inode, target) {
             Ok(result) => {
-                found_inode_num = Some(result.entry.inode as u64);
+                found_inode_num = Some(result.inode as u64);
             }
+            Err(HashTreeError::EntryNotFound) => {}
             Err(_) => {
                 // 哈希树查找失败，回退到线性查找
                 debug!("Hash tree lookup failed, falling back to linear search");
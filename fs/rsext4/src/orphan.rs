@@ -0,0 +1,238 @@
+//! 孤儿 inode 链表处理
+//!
+//! 删除或截断文件的过程中如果发生掉电，超级块的 `s_last_orphan` 会指向一条
+//! 未处理完的孤儿 inode 链表，链表中每个 inode 的 `i_dtime` 复用为下一个
+//! 孤儿的 inode 号。挂载时遍历该链表：链接数为 0 的 inode 释放全部数据块
+//! 和 inode 本身，仍有链接的 inode 把 `i_size` 之后的块截掉。
+
+use alloc::collections::BTreeSet;
+
+use log::{info, warn};
+
+use crate::{
+    blockdev::*, config::*, disknode::*, error::*, ext4::Ext4FileSystem, file::trim_extent_blocks,
+    xattr::xattr_block_sectors,
+};
+
+/// 孤儿链表处理结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OrphanStats {
+    /// 被释放的 inode 数（链接数为 0）
+    pub released: u32,
+    /// 被截断到 i_size 的 inode 数
+    pub truncated: u32,
+}
+
+/// 遍历并清空超级块上的孤儿 inode 链表
+///
+/// 遇到非法 inode 号或成环时停止遍历，已经处理过的 inode 不受影响
+pub fn process_orphan_list<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+) -> BlockDevResult<OrphanStats> {
+    let mut stats = OrphanStats::default();
+    let mut visited = BTreeSet::new();
+    let mut next = fs.superblock.s_last_orphan;
+
+    while next != 0 {
+        let inode_num = next;
+        if inode_num < fs.superblock.s_first_ino || inode_num > fs.superblock.s_inodes_count {
+            warn!("orphan list: invalid inode {inode_num}, stop");
+            break;
+        }
+        if !visited.insert(inode_num) {
+            warn!("orphan list: loop at inode {inode_num}, stop");
+            break;
+        }
+
+        let inode = fs.get_inode_by_num(block_dev, inode_num)?;
+        next = inode.i_dtime;
+
+        if inode.i_links_count == 0 {
+            release_orphan(fs, block_dev, inode_num, inode)?;
+            stats.released += 1;
+        } else {
+            truncate_orphan(fs, block_dev, inode_num, inode)?;
+            stats.truncated += 1;
+        }
+    }
+
+    fs.superblock.s_last_orphan = 0;
+    if stats.released + stats.truncated > 0 {
+        info!(
+            "Orphan list processed: released={} truncated={}",
+            stats.released, stats.truncated
+        );
+    }
+    Ok(stats)
+}
+
+/// 释放 inode 在 `from_lbn` 之后的数据块
+fn trim_blocks<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    inode_num: u32,
+    inode: &mut Ext4Inode,
+    from_lbn: u32,
+) -> BlockDevResult<()> {
+    if inode.have_extend_header_and_use_extend() {
+        return trim_extent_blocks(block_dev, fs, inode_num, inode, from_lbn);
+    }
+
+    // 快速符号链接的目标直接存放在 i_block 中，没有数据块
+    if inode.is_symlink() && inode.blocks_count() == 0 {
+        return Ok(());
+    }
+
+    // 传统块映射：只处理 12 个直接块
+    for lbn in from_lbn as usize..12 {
+        let phys = inode.i_block[lbn] as u64;
+        if phys != 0 {
+            fs.free_block(block_dev, phys)?;
+            inode.i_block[lbn] = 0;
+        }
+    }
+    if inode.i_block[12..].iter().any(|&b| b != 0) {
+        warn!("orphan inode {inode_num}: indirect blocks are not released");
+    }
+    let sectors = (from_lbn.min(12) as u64).saturating_mul(BLOCK_SIZE as u64 / 512)
+        + xattr_block_sectors(inode);
+    if sectors < inode.blocks_count() {
+        inode.i_blocks_lo = (sectors & 0xFFFF_FFFF) as u32;
+        inode.l_i_blocks_high = ((sectors >> 32) & 0xFFFF) as u16;
+    }
+    Ok(())
+}
+
+fn release_orphan<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    inode_num: u32,
+    mut inode: Ext4Inode,
+) -> BlockDevResult<()> {
+    trim_blocks(fs, block_dev, inode_num, &mut inode, 0)?;
+
+    if inode.is_dir() {
+        let group_idx = (inode_num - 1) / fs.superblock.s_inodes_per_group;
+        if let Some(desc) = fs.get_group_desc_mut(group_idx) {
+            let count = desc.used_dirs_count().saturating_sub(1);
+            desc.bg_used_dirs_count_lo = (count & 0xFFFF) as u16;
+            desc.bg_used_dirs_count_hi = (count >> 16) as u16;
+        }
+    }
+
+    fs.free_inode(block_dev, inode_num)
+}
+
+fn truncate_orphan<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    inode_num: u32,
+    mut inode: Ext4Inode,
+) -> BlockDevResult<()> {
+    let keep = inode
+        .size()
+        .div_ceil(BLOCK_SIZE as u64)
+        .min(u32::MAX as u64) as u32;
+    trim_blocks(fs, block_dev, inode_num, &mut inode, keep)?;
+
+    inode.i_dtime = 0;
+    fs.modify_inode(block_dev, inode_num, |td| *td = inode)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use super::*;
+    use crate::{
+        ext4::{mkfs, mount},
+        file::{mkfile_with_ino, remove_inodeentry_from_parentdir, write_file_with_ino},
+    };
+
+    struct MemBlockDev {
+        data: Vec<u8>,
+        total_blocks: u64,
+    }
+
+    impl BlockDevice for MemBlockDev {
+        fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+            let start = block_id as usize * BLOCK_SIZE;
+            let len = count as usize * BLOCK_SIZE;
+            self.data[start..start + len].copy_from_slice(&buffer[..len]);
+            Ok(())
+        }
+
+        fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+            let start = block_id as usize * BLOCK_SIZE;
+            let len = count as usize * BLOCK_SIZE;
+            buffer[..len].copy_from_slice(&self.data[start..start + len]);
+            Ok(())
+        }
+
+        fn open(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn total_blocks(&self) -> u64 {
+            self.total_blocks
+        }
+
+        fn block_size(&self) -> u32 {
+            BLOCK_SIZE as u32
+        }
+    }
+
+    #[test]
+    fn test_orphan_list_processed_at_mount() {
+        let total_blocks = 16 * 1024;
+        let dev = MemBlockDev {
+            data: vec![0u8; total_blocks as usize * BLOCK_SIZE],
+            total_blocks,
+        };
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, dev, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+
+        let data = vec![0x5au8; 5 * BLOCK_SIZE];
+        let (ino_a, _) = mkfile_with_ino(&mut dev, &mut fs, "/a", None, None).unwrap();
+        let (ino_b, _) = mkfile_with_ino(&mut dev, &mut fs, "/b", None, None).unwrap();
+        write_file_with_ino(&mut dev, &mut fs, ino_a, 0, &data[..3 * BLOCK_SIZE]).unwrap();
+        write_file_with_ino(&mut dev, &mut fs, ino_b, 0, &data).unwrap();
+        fs.umount(&mut dev).unwrap();
+
+        // 模拟掉电：/a 已经从目录摘除但还没释放，/b 的 i_size 已经缩小但块还没截掉
+        let mut fs = mount(&mut dev).unwrap();
+        let free_before = fs.superblock.free_blocks_count();
+        assert!(remove_inodeentry_from_parentdir(
+            &mut fs, &mut dev, "/", "a"
+        ));
+        fs.modify_inode(&mut dev, ino_a, |td| {
+            td.i_links_count = 0;
+            td.i_dtime = ino_b;
+        })
+        .unwrap();
+        fs.modify_inode(&mut dev, ino_b, |td| {
+            td.i_size_lo = BLOCK_SIZE as u32 + 1;
+            td.i_dtime = 0;
+        })
+        .unwrap();
+        fs.superblock.s_last_orphan = ino_a;
+        fs.umount(&mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(fs.superblock.s_last_orphan, 0);
+        // /a 的 3 个块和 /b 的后 3 个块被释放
+        assert_eq!(fs.superblock.free_blocks_count(), free_before + 6);
+        assert_eq!(fs.get_inode_by_num(&mut dev, ino_a).unwrap().i_mode, 0);
+        let inode_b = fs.get_inode_by_num(&mut dev, ino_b).unwrap();
+        assert_eq!(inode_b.i_dtime, 0);
+        assert_eq!(inode_b.blocks_count(), 2 * (BLOCK_SIZE as u64 / 512));
+        let report = fs.verify(&mut dev).unwrap();
+        assert!(report.is_clean(), "{:?}", report.issues);
+    }
+}
@@ -318,7 +318,7 @@ impl Ext4Superblock {
     /// 每个块组的组描述符大小（字节）
     pub fn get_desc_size(&self) -> u16 {
         if self.s_desc_size == 0 {
-            if self.has_feature_compat(Ext4Superblock::EXT4_FEATURE_INCOMPAT_64BIT) {
+            if self.has_feature_incompat(Ext4Superblock::EXT4_FEATURE_INCOMPAT_64BIT) {
                 return GROUP_DESC_SIZE;
             } else {
                 return GROUP_DESC_SIZE_OLD;
@@ -363,6 +363,69 @@ impl Ext4Superblock {
     pub fn has_journal(&self) -> bool {
         self.has_feature_compat(Self::EXT4_FEATURE_COMPAT_HAS_JOURNAL)
     }
+
+    /// 序列化超级块，启用 metadata_csum 时同时填写 s_checksum
+    pub fn to_disk_bytes_with_csum(&self, bytes: &mut [u8]) {
+        self.to_disk_bytes(bytes);
+        if self.has_metadata_csum() {
+            let csum = crate::checksum::superblock_csum(bytes);
+            write_u32_le(csum, &mut bytes[crate::checksum::SUPERBLOCK_CSUM_OFFSET..]);
+        }
+    }
+
+    /// 是否启用了 64 位块号特性
+    pub fn is_64bit(&self) -> bool {
+        self.has_feature_incompat(Self::EXT4_FEATURE_INCOMPAT_64BIT)
+    }
+
+    /// 是否启用了 metadata_csum 特性
+    pub fn has_metadata_csum(&self) -> bool {
+        self.has_feature_ro_compat(Self::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM)
+    }
+
+    /// 块组描述符是否带校验和（metadata_csum 或 gdt_csum）
+    pub fn has_group_desc_csum(&self) -> bool {
+        self.has_metadata_csum()
+            || self.has_feature_ro_compat(Self::EXT4_FEATURE_RO_COMPAT_GDT_CSUM)
+    }
+
+    /// 元数据校验和种子
+    ///
+    /// 启用 metadata_csum_seed 时直接使用 s_checksum_seed，否则由 UUID 计算
+    pub fn csum_seed(&self) -> u32 {
+        if self.has_feature_incompat(Self::EXT4_FEATURE_INCOMPAT_CSUM_SEED) {
+            self.s_checksum_seed
+        } else {
+            crate::checksum::crc32c(!0, &self.s_uuid)
+        }
+    }
+
+    /// 不被支持的不兼容特性位（非 0 时不能挂载）
+    pub fn unsupported_incompat(&self) -> u32 {
+        self.s_feature_incompat & !Self::SUPPORTED_FEATURE_INCOMPAT
+    }
+
+    /// 不被支持的只读兼容特性位
+    pub fn unsupported_ro_compat(&self) -> u32 {
+        self.s_feature_ro_compat & !Self::SUPPORTED_FEATURE_RO_COMPAT
+    }
+}
+
+// 本实现支持的特性集合（覆盖 mke2fs 默认特性）
+impl Ext4Superblock {
+    pub const SUPPORTED_FEATURE_INCOMPAT: u32 = Self::EXT4_FEATURE_INCOMPAT_FILETYPE
+        | Self::EXT4_FEATURE_INCOMPAT_RECOVER
+        | Self::EXT4_FEATURE_INCOMPAT_EXTENTS
+        | Self::EXT4_FEATURE_INCOMPAT_64BIT
+        | Self::EXT4_FEATURE_INCOMPAT_FLEX_BG
+        | Self::EXT4_FEATURE_INCOMPAT_CSUM_SEED;
+    pub const SUPPORTED_FEATURE_RO_COMPAT: u32 = Self::EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER
+        | Self::EXT4_FEATURE_RO_COMPAT_LARGE_FILE
+        | Self::EXT4_FEATURE_RO_COMPAT_HUGE_FILE
+        | Self::EXT4_FEATURE_RO_COMPAT_GDT_CSUM
+        | Self::EXT4_FEATURE_RO_COMPAT_DIR_NLINK
+        | Self::EXT4_FEATURE_RO_COMPAT_EXTRA_ISIZE
+        | Self::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM;
 }
 
 // 文件系统状态常量
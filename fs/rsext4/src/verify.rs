@@ -0,0 +1,548 @@
+//! 一致性校验与修复（fsck-lite）
+//!
+//! [`Ext4FileSystem::verify`] 检查超级块、块组描述符和位图之间的一致性，
+//! 不修改任何元数据，可以在挂载后随时调用。发现的问题以列表形式返回，
+//! 由调用者决定如何处理。
+//!
+//! [`Ext4FileSystem::check`] 在校验之后按需修复：处理孤儿链表、补标元数据块、
+//! 以位图为准重算空闲计数并刷新校验和。位图本身被视为可信，
+//! 元数据越界、块组数 / inode 数不符这类布局错误无法在线修复。
+
+use alloc::vec::Vec;
+
+use log::{info, warn};
+
+use crate::{
+    bitmap_cache::CacheKey,
+    blockdev::*,
+    checksum::*,
+    config::*,
+    error::*,
+    ext4::{Ext4FileSystem, read_superblock},
+    orphan::{OrphanStats, process_orphan_list},
+};
+
+/// 校验发现的问题
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyIssue {
+    /// 磁盘上的超级块校验和不匹配
+    SuperblockChecksum,
+    /// 超级块中的块组数量与块数不符
+    GroupCount { recorded: u32, expected: u32 },
+    /// 超级块 inode 总数与 inodes_per_group * 块组数不符
+    InodeCount { recorded: u32, expected: u64 },
+    /// 超级块空闲块数与各块组之和不符
+    SuperblockFreeBlocks { recorded: u64, counted: u64 },
+    /// 超级块空闲 inode 数与各块组之和不符
+    SuperblockFreeInodes { recorded: u32, counted: u64 },
+    /// 块组元数据（位图 / inode 表）超出文件系统范围
+    MetadataOutOfRange { group: u32, block: u64 },
+    /// 块组元数据所在块在块位图中未标记为已用
+    MetadataNotInUse { group: u32, block: u64 },
+    /// 块位图校验和不匹配
+    BlockBitmapChecksum { group: u32 },
+    /// inode 位图校验和不匹配
+    InodeBitmapChecksum { group: u32 },
+    /// 块组描述符空闲块数与块位图不符
+    GroupFreeBlocks {
+        group: u32,
+        recorded: u32,
+        counted: u32,
+    },
+    /// 块组描述符空闲 inode 数与 inode 位图不符
+    GroupFreeInodes {
+        group: u32,
+        recorded: u32,
+        counted: u32,
+    },
+    /// 孤儿链表非空
+    OrphanListPending { head: u32 },
+}
+
+impl core::fmt::Display for VerifyIssue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            VerifyIssue::SuperblockChecksum => write!(f, "superblock checksum mismatch"),
+            VerifyIssue::GroupCount { recorded, expected } => {
+                write!(f, "group count {recorded}, expected {expected}")
+            }
+            VerifyIssue::InodeCount { recorded, expected } => {
+                write!(f, "inode count {recorded}, expected {expected}")
+            }
+            VerifyIssue::SuperblockFreeBlocks { recorded, counted } => {
+                write!(f, "superblock free blocks {recorded}, groups sum {counted}")
+            }
+            VerifyIssue::SuperblockFreeInodes { recorded, counted } => {
+                write!(f, "superblock free inodes {recorded}, groups sum {counted}")
+            }
+            VerifyIssue::MetadataOutOfRange { group, block } => {
+                write!(f, "group {group}: metadata block {block} out of range")
+            }
+            VerifyIssue::MetadataNotInUse { group, block } => {
+                write!(f, "group {group}: metadata block {block} not marked in use")
+            }
+            VerifyIssue::BlockBitmapChecksum { group } => {
+                write!(f, "group {group}: block bitmap checksum mismatch")
+            }
+            VerifyIssue::InodeBitmapChecksum { group } => {
+                write!(f, "group {group}: inode bitmap checksum mismatch")
+            }
+            VerifyIssue::GroupFreeBlocks {
+                group,
+                recorded,
+                counted,
+            } => write!(f, "group {group}: free blocks {recorded}, bitmap {counted}"),
+            VerifyIssue::GroupFreeInodes {
+                group,
+                recorded,
+                counted,
+            } => write!(f, "group {group}: free inodes {recorded}, bitmap {counted}"),
+            VerifyIssue::OrphanListPending { head } => {
+                write!(f, "orphan list not empty, head inode {head}")
+            }
+        }
+    }
+}
+
+/// 校验结果
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    /// 已检查的块组数
+    pub groups_checked: u32,
+    /// 发现的问题
+    pub issues: Vec<VerifyIssue>,
+}
+
+impl VerifyReport {
+    /// 是否没有发现任何问题
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl VerifyIssue {
+    /// [`Ext4FileSystem::check`] 能否修复该问题
+    pub fn is_repairable(&self) -> bool {
+        !matches!(
+            self,
+            VerifyIssue::GroupCount { .. }
+                | VerifyIssue::InodeCount { .. }
+                | VerifyIssue::MetadataOutOfRange { .. }
+        )
+    }
+}
+
+/// 检查与修复结果
+#[derive(Debug, Clone, Default)]
+pub struct CheckReport {
+    /// 修复前的校验结果
+    pub before: VerifyReport,
+    /// 修复后重新校验仍然存在的问题，未修复时与 `before` 相同
+    pub remaining: Vec<VerifyIssue>,
+    /// 已修复的问题
+    pub repaired: Vec<VerifyIssue>,
+    /// 修复时处理的孤儿 inode
+    pub orphans: OrphanStats,
+}
+
+impl CheckReport {
+    /// 检查（及修复）之后文件系统是否一致
+    pub fn is_clean(&self) -> bool {
+        self.remaining.is_empty()
+    }
+}
+
+/// 统计位图前 `bits` 位中为 0 的位数
+fn count_free_bits(bitmap: &[u8], bits: u32) -> u32 {
+    let full = (bits / 8) as usize;
+    let mut used: u32 = bitmap[..full].iter().map(|b| b.count_ones()).sum();
+    for bit in full as u32 * 8..bits {
+        if bitmap[(bit / 8) as usize] & (1 << (bit % 8)) != 0 {
+            used += 1;
+        }
+    }
+    bits - used
+}
+
+impl Ext4FileSystem {
+    /// 只读一致性校验：超级块 / 块组描述符 / 位图
+    ///
+    /// 带 BLOCK_UNINIT / INODE_UNINIT 标志的位图在磁盘上没有初始化，跳过对应检查
+    pub fn verify<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+    ) -> BlockDevResult<VerifyReport> {
+        let mut report = VerifyReport::default();
+        let sb = self.superblock;
+
+        // 内存中的 s_checksum 不随写盘更新，用磁盘上的副本自校验
+        let disk_sb = read_superblock(block_dev)?;
+        if !Self::check_superblock_csum(block_dev, &disk_sb)
+            .map_err(|_| BlockDevError::ReadError)?
+        {
+            report.issues.push(VerifyIssue::SuperblockChecksum);
+        }
+
+        let expected_groups = sb.block_groups_count();
+        if self.group_count != expected_groups || self.group_descs.len() as u32 != expected_groups {
+            report.issues.push(VerifyIssue::GroupCount {
+                recorded: self.group_descs.len() as u32,
+                expected: expected_groups,
+            });
+        }
+        let expected_inodes = sb.s_inodes_per_group as u64 * self.group_descs.len() as u64;
+        if sb.s_inodes_count as u64 != expected_inodes {
+            report.issues.push(VerifyIssue::InodeCount {
+                recorded: sb.s_inodes_count,
+                expected: expected_inodes,
+            });
+        }
+        if sb.s_last_orphan != 0 {
+            report.issues.push(VerifyIssue::OrphanListPending {
+                head: sb.s_last_orphan,
+            });
+        }
+
+        let total_blocks = sb.blocks_count();
+        let blocks_per_group = sb.blocks_per_group() as u64;
+        let inodes_per_group = sb.s_inodes_per_group;
+        let desc_size = sb.get_desc_size() as usize;
+        let csum_mask = if desc_size >= GROUP_DESC_SIZE as usize {
+            u32::MAX
+        } else {
+            0xFFFF
+        };
+
+        let mut free_blocks_sum = 0u64;
+        let mut free_inodes_sum = 0u64;
+
+        for group in 0..self.group_descs.len() as u32 {
+            let desc = self.group_descs[group as usize];
+            free_blocks_sum += desc.free_blocks_count() as u64;
+            free_inodes_sum += desc.free_inodes_count() as u64;
+
+            // 元数据块位置
+            let mut meta: Vec<u64> = Vec::new();
+            meta.push(desc.block_bitmap());
+            meta.push(desc.inode_bitmap());
+            meta.extend((0..sb.inode_table_blocks() as u64).map(|off| desc.inode_table() + off));
+            let mut meta_ok = true;
+            for &block in &meta {
+                if block < sb.s_first_data_block as u64 || block >= total_blocks {
+                    report
+                        .issues
+                        .push(VerifyIssue::MetadataOutOfRange { group, block });
+                    meta_ok = false;
+                    break;
+                }
+            }
+
+            // 块位图
+            if !desc.is_block_bitmap_uninit() && meta_ok {
+                let group_start = sb.s_first_data_block as u64 + group as u64 * blocks_per_group;
+                let group_len =
+                    (total_blocks.saturating_sub(group_start)).min(blocks_per_group) as u32;
+                let bitmap = self
+                    .bitmap_cache
+                    .get_or_load(block_dev, CacheKey::new_block(group), desc.block_bitmap())?
+                    .data
+                    .clone();
+
+                let counted = count_free_bits(&bitmap, group_len);
+                if counted != desc.free_blocks_count() {
+                    report.issues.push(VerifyIssue::GroupFreeBlocks {
+                        group,
+                        recorded: desc.free_blocks_count(),
+                        counted,
+                    });
+                }
+                if sb.has_metadata_csum() {
+                    let len = sb.s_clusters_per_group as usize / 8;
+                    if bitmap_csum(&sb, &bitmap, len) & csum_mask
+                        != desc.block_bitmap_csum() & csum_mask
+                    {
+                        report
+                            .issues
+                            .push(VerifyIssue::BlockBitmapChecksum { group });
+                    }
+                }
+
+                // 本组块位图覆盖到的元数据块必须已标记
+                for &block in &meta {
+                    if block < group_start || block >= group_start + group_len as u64 {
+                        continue;
+                    }
+                    let bit = (block - group_start) as usize;
+                    if bitmap[bit / 8] & (1 << (bit % 8)) == 0 {
+                        report
+                            .issues
+                            .push(VerifyIssue::MetadataNotInUse { group, block });
+                    }
+                }
+            }
+
+            // inode 位图
+            let counted = if desc.is_inode_bitmap_uninit() {
+                inodes_per_group
+            } else if meta_ok {
+                let bitmap = self
+                    .bitmap_cache
+                    .get_or_load(block_dev, CacheKey::new_inode(group), desc.inode_bitmap())?
+                    .data
+                    .clone();
+                if sb.has_metadata_csum() {
+                    let len = inodes_per_group as usize / 8;
+                    if bitmap_csum(&sb, &bitmap, len) & csum_mask
+                        != desc.inode_bitmap_csum() & csum_mask
+                    {
+                        report
+                            .issues
+                            .push(VerifyIssue::InodeBitmapChecksum { group });
+                    }
+                }
+                count_free_bits(&bitmap, inodes_per_group)
+            } else {
+                desc.free_inodes_count()
+            };
+            if counted != desc.free_inodes_count() {
+                report.issues.push(VerifyIssue::GroupFreeInodes {
+                    group,
+                    recorded: desc.free_inodes_count(),
+                    counted,
+                });
+            }
+
+            report.groups_checked += 1;
+        }
+
+        if sb.free_blocks_count() != free_blocks_sum {
+            report.issues.push(VerifyIssue::SuperblockFreeBlocks {
+                recorded: sb.free_blocks_count(),
+                counted: free_blocks_sum,
+            });
+        }
+        if sb.s_free_inodes_count as u64 != free_inodes_sum {
+            report.issues.push(VerifyIssue::SuperblockFreeInodes {
+                recorded: sb.s_free_inodes_count,
+                counted: free_inodes_sum,
+            });
+        }
+
+        for issue in &report.issues {
+            warn!("verify: {issue}");
+        }
+        Ok(report)
+    }
+
+    /// 一致性检查，`repair` 为 true 时修复能修复的问题并写回磁盘
+    ///
+    /// 修复以位图为准：孤儿链表先被处理，未标记的元数据块补标为已用，
+    /// 然后按位图重算各块组和超级块的空闲计数、刷新位图校验和。
+    /// 修复完成后重新校验，[`CheckReport::remaining`] 给出仍然存在的问题
+    pub fn check<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        repair: bool,
+    ) -> BlockDevResult<CheckReport> {
+        let before = self.verify(block_dev)?;
+        if !repair || before.is_clean() {
+            return Ok(CheckReport {
+                remaining: before.issues.clone(),
+                before,
+                ..Default::default()
+            });
+        }
+
+        let mut orphans = OrphanStats::default();
+        if self.superblock.s_last_orphan != 0 {
+            orphans = process_orphan_list(self, block_dev)?;
+        }
+
+        // 元数据越界的块组位图不可信，不碰
+        let broken = |group: u32| {
+            before.issues.iter().any(|issue| {
+                matches!(issue, VerifyIssue::MetadataOutOfRange { group: g, .. } if *g == group)
+            })
+        };
+
+        for issue in &before.issues {
+            if let VerifyIssue::MetadataNotInUse { group, block } = *issue {
+                let bitmap_block = self.group_descs[group as usize].block_bitmap();
+                let group_start = self.superblock.s_first_data_block as u64
+                    + group as u64 * self.superblock.blocks_per_group() as u64;
+                let bit = (block - group_start) as usize;
+                self.bitmap_cache.modify(
+                    block_dev,
+                    CacheKey::new_block(group),
+                    bitmap_block,
+                    |data| data[bit / 8] |= 1 << (bit % 8),
+                )?;
+            }
+        }
+
+        let total_blocks = self.superblock.blocks_count();
+        let blocks_per_group = self.superblock.blocks_per_group() as u64;
+        let inodes_per_group = self.superblock.s_inodes_per_group;
+        for group in 0..self.group_descs.len() as u32 {
+            if broken(group) {
+                continue;
+            }
+            let desc = self.group_descs[group as usize];
+
+            if !desc.is_block_bitmap_uninit() {
+                let key = CacheKey::new_block(group);
+                let group_start =
+                    self.superblock.s_first_data_block as u64 + group as u64 * blocks_per_group;
+                let group_len =
+                    (total_blocks.saturating_sub(group_start)).min(blocks_per_group) as u32;
+                let bitmap = self
+                    .bitmap_cache
+                    .get_or_load(block_dev, key, desc.block_bitmap())?;
+                let free = count_free_bits(&bitmap.data, group_len);
+                let desc = &mut self.group_descs[group as usize];
+                desc.bg_free_blocks_count_lo = (free & 0xFFFF) as u16;
+                desc.bg_free_blocks_count_hi = (free >> 16) as u16;
+                self.refresh_bitmap_csum(key);
+            }
+
+            let free = if desc.is_inode_bitmap_uninit() {
+                inodes_per_group
+            } else {
+                let key = CacheKey::new_inode(group);
+                let bitmap = self
+                    .bitmap_cache
+                    .get_or_load(block_dev, key, desc.inode_bitmap())?;
+                let free = count_free_bits(&bitmap.data, inodes_per_group);
+                self.refresh_bitmap_csum(key);
+                free
+            };
+            let desc = &mut self.group_descs[group as usize];
+            desc.bg_free_inodes_count_lo = (free & 0xFFFF) as u16;
+            desc.bg_free_inodes_count_hi = (free >> 16) as u16;
+        }
+
+        // 超级块空闲计数由块组描述符汇总，写回时同时更新校验和
+        self.bitmap_cache.flush_all(block_dev)?;
+        self.sync_group_descriptors(block_dev)?;
+        self.sync_superblock(block_dev)?;
+
+        let after = self.verify(block_dev)?;
+        let repaired = before
+            .issues
+            .iter()
+            .filter(|issue| !after.issues.contains(issue))
+            .copied()
+            .collect::<Vec<_>>();
+        for issue in &repaired {
+            info!("check: repaired {issue}");
+        }
+        Ok(CheckReport {
+            before,
+            remaining: after.issues,
+            repaired,
+            orphans,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use super::*;
+    use crate::ext4::{mkfs, mount};
+
+    struct MemBlockDev {
+        data: Vec<u8>,
+        total_blocks: u64,
+    }
+
+    impl BlockDevice for MemBlockDev {
+        fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+            let start = block_id as usize * BLOCK_SIZE;
+            let len = count as usize * BLOCK_SIZE;
+            self.data[start..start + len].copy_from_slice(&buffer[..len]);
+            Ok(())
+        }
+
+        fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+            let start = block_id as usize * BLOCK_SIZE;
+            let len = count as usize * BLOCK_SIZE;
+            buffer[..len].copy_from_slice(&self.data[start..start + len]);
+            Ok(())
+        }
+
+        fn open(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn total_blocks(&self) -> u64 {
+            self.total_blocks
+        }
+
+        fn block_size(&self) -> u32 {
+            BLOCK_SIZE as u32
+        }
+    }
+
+    #[test]
+    fn test_count_free_bits() {
+        let bitmap = [0xFFu8, 0x0F, 0x00, 0x80];
+        assert_eq!(count_free_bits(&bitmap, 32), 32 - 8 - 4 - 1);
+        // 只统计前 12 位
+        assert_eq!(count_free_bits(&bitmap, 12), 0);
+        assert_eq!(count_free_bits(&bitmap, 13), 1);
+    }
+
+    #[test]
+    fn test_check_repairs_counts_and_bitmap() {
+        let total_blocks = 16 * 1024;
+        let dev = MemBlockDev {
+            data: vec![0u8; total_blocks as usize * BLOCK_SIZE],
+            total_blocks,
+        };
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, dev, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+
+        // 模拟写了一半：块位图丢了自己所在块的标记，描述符计数也没更新
+        let desc = *fs.get_group_desc(0).unwrap();
+        let bitmap_block = desc.block_bitmap();
+        let bit = (bitmap_block - fs.superblock.s_first_data_block as u64) as usize;
+        fs.bitmap_cache
+            .modify(&mut dev, CacheKey::new_block(0), bitmap_block, |data| {
+                data[bit / 8] &= !(1 << (bit % 8))
+            })
+            .unwrap();
+        let desc = fs.get_group_desc_mut(0).unwrap();
+        desc.bg_free_inodes_count_lo -= 3;
+
+        let report = fs.check(&mut dev, false).unwrap();
+        assert!(!report.is_clean());
+        assert!(report.repaired.is_empty());
+        assert!(report.remaining.contains(&VerifyIssue::MetadataNotInUse {
+            group: 0,
+            block: bitmap_block,
+        }));
+        assert!(
+            report
+                .remaining
+                .iter()
+                .any(|issue| matches!(issue, VerifyIssue::GroupFreeInodes { group: 0, .. }))
+        );
+        assert!(report.remaining.iter().all(VerifyIssue::is_repairable));
+
+        let report = fs.check(&mut dev, true).unwrap();
+        assert!(report.is_clean(), "{:?}", report.remaining);
+        assert_eq!(report.repaired, report.before.issues);
+        fs.umount(&mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        let report = fs.verify(&mut dev).unwrap();
+        assert!(report.is_clean(), "{:?}", report.issues);
+    }
+}
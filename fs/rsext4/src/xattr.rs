@@ -0,0 +1,906 @@
+//! # 扩展属性模块
+//!
+//! 读写 inode 的扩展属性（xattr），磁盘格式与 Linux ext4 一致，属性存放在两处：
+//! - inode 内联区：大 inode 中 `128 + i_extra_isize` 之后的尾部空间，以魔数开头；
+//! - 扩展属性块：`i_file_acl` 指向的数据块，可由多个 inode 按引用计数共享。
+//!
+//! 写入时按名字排序后优先放入内联区，放不下的放入扩展属性块。共享的扩展属性块
+//! 不会被原地修改，而是复制出一个新块。值存放在独立 inode 中（ea_inode 特性）的
+//! 属性只能列出，不能读取，其所在 inode 的扩展属性也不能修改。
+//!
+//! POSIX ACL 以 `system.posix_acl_access` / `system.posix_acl_default` 存储，
+//! 读写时在用户态格式与磁盘格式之间转换，见 [`crate::acl`]。
+
+use alloc::{string::String, vec, vec::Vec};
+
+use log::warn;
+
+use crate::{
+    acl, blockdev::*, checksum::*, config::*, disknode::*, endian::*, error::*,
+    ext4::Ext4FileSystem, superblock::Ext4Superblock,
+};
+
+/// 扩展属性头魔数（内联区与扩展属性块相同）
+pub const XATTR_MAGIC: u32 = 0xEA02_0000;
+/// 属性名（去掉名字空间前缀）最大长度
+pub const XATTR_NAME_MAX: usize = 255;
+
+/// 扩展属性块头大小
+const BLOCK_HEADER_SIZE: usize = 32;
+/// 属性项固定部分大小
+const ENTRY_HEADER_SIZE: usize = 16;
+/// 属性项列表结尾的 4 字节 0
+const ENTRY_END_SIZE: usize = 4;
+/// 内联区要求的最小 i_extra_isize：`Ext4Inode::to_disk_bytes` 会写入 128..160 的扩展字段，
+/// 内联区必须在其之后
+const IBODY_MIN_EXTRA_ISIZE: u16 = 32;
+
+/// 名字空间索引
+const INDEX_USER: u8 = 1;
+const INDEX_POSIX_ACL_ACCESS: u8 = 2;
+const INDEX_POSIX_ACL_DEFAULT: u8 = 3;
+const INDEX_TRUSTED: u8 = 4;
+const INDEX_SECURITY: u8 = 6;
+const INDEX_SYSTEM: u8 = 7;
+
+/// 带后缀的名字空间前缀
+const NAME_PREFIXES: [(u8, &str); 4] = [
+    (INDEX_USER, "user."),
+    (INDEX_TRUSTED, "trusted."),
+    (INDEX_SECURITY, "security."),
+    (INDEX_SYSTEM, "system."),
+];
+
+/// 扩展属性操作错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XattrError {
+    /// 属性不存在
+    NotFound,
+    /// 属性已存在
+    Exists,
+    /// 属性名无效（如名字空间后为空）
+    InvalidName,
+    /// 属性名过长
+    NameTooLong,
+    /// 不支持的名字空间或存储方式
+    Unsupported,
+    /// 属性值无效（如格式错误的 ACL）
+    InvalidValue,
+    /// 内联区与扩展属性块都放不下
+    NoSpace,
+    /// 块设备或元数据错误
+    Device(BlockDevError),
+}
+
+impl From<BlockDevError> for XattrError {
+    fn from(e: BlockDevError) -> Self {
+        XattrError::Device(e)
+    }
+}
+
+impl core::fmt::Display for XattrError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            XattrError::NotFound => write!(f, "no such attribute"),
+            XattrError::Exists => write!(f, "attribute already exists"),
+            XattrError::InvalidName => write!(f, "invalid attribute name"),
+            XattrError::NameTooLong => write!(f, "attribute name too long"),
+            XattrError::Unsupported => write!(f, "unsupported attribute"),
+            XattrError::InvalidValue => write!(f, "invalid attribute value"),
+            XattrError::NoSpace => write!(f, "no space left for attributes"),
+            XattrError::Device(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// 扩展属性操作结果类型
+pub type XattrResult<T> = Result<T, XattrError>;
+
+/// 设置属性时对已有属性的要求，对应 `setxattr` 的 flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XattrSetMode {
+    /// 不存在则创建，存在则替换
+    Any,
+    /// 仅创建（XATTR_CREATE），已存在时报错
+    Create,
+    /// 仅替换（XATTR_REPLACE），不存在时报错
+    Replace,
+}
+
+/// 按名字空间拆分属性名：(索引, 后缀)
+fn split_name(name: &str) -> XattrResult<(u8, &str)> {
+    if name == acl::ACL_ACCESS_NAME {
+        return Ok((INDEX_POSIX_ACL_ACCESS, ""));
+    }
+    if name == acl::ACL_DEFAULT_NAME {
+        return Ok((INDEX_POSIX_ACL_DEFAULT, ""));
+    }
+    for (index, prefix) in NAME_PREFIXES {
+        if let Some(suffix) = name.strip_prefix(prefix) {
+            if suffix.is_empty() {
+                return Err(XattrError::InvalidName);
+            }
+            if suffix.len() > XATTR_NAME_MAX {
+                return Err(XattrError::NameTooLong);
+            }
+            return Ok((index, suffix));
+        }
+    }
+    Err(XattrError::Unsupported)
+}
+
+/// 由索引和后缀拼出完整属性名，未知名字空间返回 None
+fn full_name(index: u8, suffix: &[u8]) -> Option<String> {
+    let suffix = core::str::from_utf8(suffix).ok()?;
+    match index {
+        INDEX_POSIX_ACL_ACCESS => Some(acl::ACL_ACCESS_NAME.into()),
+        INDEX_POSIX_ACL_DEFAULT => Some(acl::ACL_DEFAULT_NAME.into()),
+        _ => NAME_PREFIXES
+            .iter()
+            .find(|(i, _)| *i == index)
+            .map(|(_, prefix)| [prefix, suffix].concat()),
+    }
+}
+
+fn is_acl_index(index: u8) -> bool {
+    index == INDEX_POSIX_ACL_ACCESS || index == INDEX_POSIX_ACL_DEFAULT
+}
+
+/// 按 4 字节对齐
+const fn pad4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// 一个扩展属性
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct XattrEntry {
+    index: u8,
+    name: Vec<u8>,
+    value: Vec<u8>,
+    /// 存放值的 inode 号（ea_inode 特性），0 表示值存放在属性项旁
+    value_inum: u32,
+}
+
+impl XattrEntry {
+    /// 属性项（含名字）占用的字节数
+    fn entry_size(&self) -> usize {
+        pad4(ENTRY_HEADER_SIZE + self.name.len())
+    }
+
+    /// 属性项与值共占用的字节数
+    fn storage_size(&self) -> usize {
+        self.entry_size() + pad4(self.value.len())
+    }
+
+    /// 扩展属性块内的排序键
+    fn sort_key(&self) -> (u8, usize, &[u8]) {
+        (self.index, self.name.len(), &self.name)
+    }
+
+    /// 属性项哈希（等价于内核 `ext4_xattr_hash_entry`）
+    fn hash(&self) -> u32 {
+        let mut hash = 0u32;
+        for &c in &self.name {
+            hash = (hash << 5) ^ (hash >> 27) ^ c as u32;
+        }
+        for chunk in self.value.chunks(4) {
+            let mut word = [0u8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            hash = (hash << 16) ^ (hash >> 16) ^ u32::from_le_bytes(word);
+        }
+        hash
+    }
+}
+
+/// 扩展属性块哈希（等价于内核 `ext4_xattr_rehash`），任一属性项哈希为 0 时为 0
+fn block_hash(entries: &[&XattrEntry]) -> u32 {
+    let mut hash = 0u32;
+    for entry in entries {
+        let entry_hash = entry.hash();
+        if entry_hash == 0 {
+            return 0;
+        }
+        hash = (hash << 16) ^ (hash >> 16) ^ entry_hash;
+    }
+    hash
+}
+
+/// 从 `region[start..]` 解析属性项，值偏移相对于 `region` 起始
+fn parse_entries(region: &[u8], start: usize) -> Option<Vec<XattrEntry>> {
+    let mut entries = Vec::new();
+    let mut pos = start;
+    loop {
+        if read_u32_le(region.get(pos..pos + ENTRY_END_SIZE)?) == 0 {
+            return Some(entries);
+        }
+        let header = region.get(pos..pos + ENTRY_HEADER_SIZE)?;
+        let name_len = header[0] as usize;
+        let value_offs = read_u16_le(&header[2..4]) as usize;
+        let value_inum = read_u32_le(&header[4..8]);
+        let value_size = read_u32_le(&header[8..12]) as usize;
+        let name_start = pos + ENTRY_HEADER_SIZE;
+        let name = region.get(name_start..name_start + name_len)?.to_vec();
+        let value = if value_inum != 0 || value_size == 0 {
+            Vec::new()
+        } else {
+            region.get(value_offs..value_offs + value_size)?.to_vec()
+        };
+        entries.push(XattrEntry {
+            index: header[1],
+            name,
+            value,
+            value_inum,
+        });
+        pos += pad4(ENTRY_HEADER_SIZE + name_len);
+    }
+}
+
+/// 把属性项写入 `region[start..]`：属性项从前往后，值从区域末尾往前
+///
+/// 调用者保证放得下
+fn write_entries(region: &mut [u8], start: usize, entries: &[&XattrEntry]) {
+    region[start..].fill(0);
+    let mut pos = start;
+    let mut value_end = region.len();
+    for entry in entries {
+        let value_offs = if entry.value.is_empty() {
+            0
+        } else {
+            value_end -= pad4(entry.value.len());
+            region[value_end..value_end + entry.value.len()].copy_from_slice(&entry.value);
+            value_end
+        };
+        region[pos] = entry.name.len() as u8;
+        region[pos + 1] = entry.index;
+        write_u16_le(value_offs as u16, &mut region[pos + 2..pos + 4]);
+        write_u32_le(0, &mut region[pos + 4..pos + 8]);
+        write_u32_le(entry.value.len() as u32, &mut region[pos + 8..pos + 12]);
+        write_u32_le(entry.hash(), &mut region[pos + 12..pos + 16]);
+        let name_start = pos + ENTRY_HEADER_SIZE;
+        region[name_start..name_start + entry.name.len()].copy_from_slice(&entry.name);
+        pos += entry.entry_size();
+    }
+}
+
+/// 扩展属性块中属性项与值可用的字节数
+const fn block_capacity() -> usize {
+    BLOCK_SIZE - BLOCK_HEADER_SIZE - ENTRY_END_SIZE
+}
+
+/// inode 占用的扩展属性块的扇区数（计入 i_blocks）
+pub fn xattr_block_sectors(inode: &Ext4Inode) -> u64 {
+    if inode.file_acl() != 0 {
+        (BLOCK_SIZE / 512) as u64
+    } else {
+        0
+    }
+}
+
+/// 一个 inode 的全部扩展属性及其存放位置
+struct InodeXattrs {
+    inode_num: u32,
+    inode: Ext4Inode,
+    /// inode 在 inode 表中的位置
+    location: (u64, usize),
+    /// 内联区在 inode 原始字节中的起始偏移与长度（含魔数），不可用时为 None
+    ibody: Option<(usize, usize)>,
+    /// 加载时内联区是否有属性
+    had_ibody: bool,
+    /// 扩展属性块的引用计数，没有扩展属性块时为 0
+    block_refcount: u32,
+    entries: Vec<XattrEntry>,
+}
+
+impl InodeXattrs {
+    fn load<B: BlockDevice>(
+        fs: &mut Ext4FileSystem,
+        block_dev: &mut Jbd2Dev<B>,
+        inode_num: u32,
+    ) -> XattrResult<Self> {
+        let inode = fs.get_inode_by_num(block_dev, inode_num)?;
+        let location = fs.inode_location(inode_num)?;
+        let inode_size = fs.superblock.inode_size() as usize;
+        let ibody_start = Ext4Inode::GOOD_OLD_INODE_SIZE as usize + inode.i_extra_isize as usize;
+        let ibody = (inode.i_extra_isize >= IBODY_MIN_EXTRA_ISIZE
+            && ibody_start + 4 + ENTRY_END_SIZE <= inode_size)
+            .then_some((ibody_start, inode_size - ibody_start));
+
+        let mut entries = Vec::new();
+        let mut had_ibody = false;
+        if let Some((start, len)) = ibody {
+            let raw = fs
+                .inodetable_cahce
+                .read_raw(block_dev, location.0, location.1)?;
+            let region = &raw[start..start + len];
+            if read_u32_le(&region[..4]) == XATTR_MAGIC {
+                let parsed = parse_entries(&region[4..], 0).ok_or(BlockDevError::Corrupted)?;
+                had_ibody = !parsed.is_empty();
+                entries.extend(parsed);
+            }
+        }
+
+        let mut block_refcount = 0;
+        let block_num = inode.file_acl();
+        if block_num != 0 {
+            let block = &fs.datablock_cache.get_or_load(block_dev, block_num)?.data;
+            if read_u32_le(&block[0..4]) != XATTR_MAGIC || read_u32_le(&block[8..12]) != 1 {
+                warn!("inode {inode_num}: bad xattr block {block_num}");
+                return Err(BlockDevError::Corrupted.into());
+            }
+            if fs.superblock.has_metadata_csum()
+                && !verify_xattr_block_csum(fs.superblock.csum_seed(), block_num, block)
+            {
+                warn!("inode {inode_num}: xattr block {block_num} checksum mismatch");
+            }
+            block_refcount = read_u32_le(&block[4..8]);
+            entries
+                .extend(parse_entries(block, BLOCK_HEADER_SIZE).ok_or(BlockDevError::Corrupted)?);
+        }
+
+        Ok(Self {
+            inode_num,
+            inode,
+            location,
+            ibody,
+            had_ibody,
+            block_refcount,
+            entries,
+        })
+    }
+
+    fn find(&self, index: u8, name: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|e| e.index == index && e.name == name.as_bytes())
+    }
+
+    /// 把属性写回内联区与扩展属性块
+    fn store<B: BlockDevice>(
+        mut self,
+        fs: &mut Ext4FileSystem,
+        block_dev: &mut Jbd2Dev<B>,
+    ) -> XattrResult<()> {
+        if self.entries.iter().any(|e| e.value_inum != 0) {
+            return Err(XattrError::Unsupported);
+        }
+        self.entries.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+
+        // 先尽量放入内联区，其余放入扩展属性块
+        let ibody_capacity = self.ibody.map_or(0, |(_, len)| len - 4 - ENTRY_END_SIZE);
+        let (mut ibody_used, mut block_used) = (0, 0);
+        let mut in_ibody = Vec::new();
+        let mut in_block = Vec::new();
+        for entry in &self.entries {
+            let size = entry.storage_size();
+            if ibody_used + size <= ibody_capacity {
+                ibody_used += size;
+                in_ibody.push(entry);
+            } else if block_used + size <= block_capacity() {
+                block_used += size;
+                in_block.push(entry);
+            } else {
+                return Err(XattrError::NoSpace);
+            }
+        }
+
+        self.store_block(fs, block_dev, &in_block)?;
+        self.store_ibody(fs, block_dev, &in_ibody)?;
+
+        if !self.entries.is_empty()
+            && !fs
+                .superblock
+                .has_feature_compat(Ext4Superblock::EXT4_FEATURE_COMPAT_EXT_ATTR)
+        {
+            fs.superblock.s_feature_compat |= Ext4Superblock::EXT4_FEATURE_COMPAT_EXT_ATTR;
+        }
+        Ok(())
+    }
+
+    fn store_ibody<B: BlockDevice>(
+        &self,
+        fs: &mut Ext4FileSystem,
+        block_dev: &mut Jbd2Dev<B>,
+        entries: &[&XattrEntry],
+    ) -> XattrResult<()> {
+        let Some((start, len)) = self.ibody else {
+            return Ok(());
+        };
+        if entries.is_empty() && !self.had_ibody {
+            return Ok(());
+        }
+        let mut region = vec![0u8; len];
+        if !entries.is_empty() {
+            write_u32_le(XATTR_MAGIC, &mut region[..4]);
+            write_entries(&mut region[4..], 0, entries);
+        }
+        fs.inodetable_cahce.write_raw_tail(
+            block_dev,
+            self.inode_num as u64,
+            self.location.0,
+            self.location.1,
+            start,
+            &region,
+        )?;
+        Ok(())
+    }
+
+    fn store_block<B: BlockDevice>(
+        &self,
+        fs: &mut Ext4FileSystem,
+        block_dev: &mut Jbd2Dev<B>,
+        entries: &[&XattrEntry],
+    ) -> XattrResult<()> {
+        let old_block = self.inode.file_acl();
+        if entries.is_empty() {
+            if old_block != 0 {
+                release_xattr_block(fs, block_dev, old_block)?;
+                set_inode_xattr_block(fs, block_dev, self.inode_num, 0)?;
+            }
+            return Ok(());
+        }
+
+        // 独占的块原地改写，共享的块复制出新块
+        let block_num = if old_block != 0 && self.block_refcount == 1 {
+            old_block
+        } else {
+            let new_block = fs.alloc_block(block_dev)?;
+            if old_block != 0 {
+                release_xattr_block(fs, block_dev, old_block)?;
+            }
+            set_inode_xattr_block(fs, block_dev, self.inode_num, new_block)?;
+            new_block
+        };
+
+        let mut block = vec![0u8; BLOCK_SIZE];
+        write_u32_le(XATTR_MAGIC, &mut block[0..4]);
+        write_u32_le(1, &mut block[4..8]);
+        write_u32_le(1, &mut block[8..12]);
+        write_u32_le(block_hash(entries), &mut block[12..16]);
+        write_entries(&mut block, BLOCK_HEADER_SIZE, entries);
+        if fs.superblock.has_metadata_csum() {
+            set_xattr_block_csum(fs.superblock.csum_seed(), block_num, &mut block);
+        }
+        fs.datablock_cache
+            .modify_new(block_num, |data| data.copy_from_slice(&block));
+        Ok(())
+    }
+}
+
+/// 更新 inode 的扩展属性块号，并随之增减 i_blocks
+fn set_inode_xattr_block<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    inode_num: u32,
+    block_num: u64,
+) -> BlockDevResult<()> {
+    fs.modify_inode(block_dev, inode_num, |td| {
+        let sectors = td.blocks_count() - xattr_block_sectors(td);
+        td.i_file_acl_lo = (block_num & 0xFFFF_FFFF) as u32;
+        td.l_i_file_acl_high = ((block_num >> 32) & 0xFFFF) as u16;
+        let sectors = sectors + xattr_block_sectors(td);
+        td.i_blocks_lo = (sectors & 0xFFFF_FFFF) as u32;
+        td.l_i_blocks_high = ((sectors >> 32) & 0xFFFF) as u16;
+    })
+}
+
+/// 放弃对扩展属性块的一次引用：引用计数归零时释放该块
+pub(crate) fn release_xattr_block<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    block_num: u64,
+) -> BlockDevResult<()> {
+    let mut block = fs
+        .datablock_cache
+        .get_or_load(block_dev, block_num)?
+        .data
+        .clone();
+    if read_u32_le(&block[0..4]) != XATTR_MAGIC {
+        warn!("xattr block {block_num}: bad magic, not released");
+        return Err(BlockDevError::Corrupted);
+    }
+    let refcount = read_u32_le(&block[4..8]);
+    if refcount <= 1 {
+        fs.datablock_cache.invalidate(block_num);
+        return fs.free_block(block_dev, block_num);
+    }
+    write_u32_le(refcount - 1, &mut block[4..8]);
+    if fs.superblock.has_metadata_csum() {
+        set_xattr_block_csum(fs.superblock.csum_seed(), block_num, &mut block);
+    }
+    fs.datablock_cache
+        .modify_new(block_num, |data| data.copy_from_slice(&block));
+    Ok(())
+}
+
+/// 释放 inode 的全部扩展属性，在释放 inode 前调用
+///
+/// 扩展属性块引用计数减一（归零时释放），内联区清空
+pub(crate) fn release_inode_xattrs<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    inode_num: u32,
+) -> XattrResult<()> {
+    let mut xattrs = InodeXattrs::load(fs, block_dev, inode_num)?;
+    // ea_inode 中的值无法释放，只丢弃引用
+    xattrs.entries.clear();
+    xattrs.store(fs, block_dev)
+}
+
+/// 读取扩展属性的值
+///
+/// ACL 属性以用户态格式（`posix_acl_xattr`）返回
+pub fn get_xattr<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    inode_num: u32,
+    name: &str,
+) -> XattrResult<Vec<u8>> {
+    let (index, suffix) = split_name(name)?;
+    let xattrs = InodeXattrs::load(fs, block_dev, inode_num)?;
+    let entry = xattrs
+        .find(index, suffix)
+        .map(|pos| &xattrs.entries[pos])
+        .ok_or(XattrError::NotFound)?;
+    if entry.value_inum != 0 {
+        return Err(XattrError::Unsupported);
+    }
+    if is_acl_index(index) {
+        let acl = acl::from_disk(&entry.value).ok_or(BlockDevError::Corrupted)?;
+        return Ok(acl::to_xattr(&acl));
+    }
+    Ok(entry.value.clone())
+}
+
+/// 列出 inode 的全部扩展属性名，未知名字空间的属性不列出
+pub fn list_xattr<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    inode_num: u32,
+) -> XattrResult<Vec<String>> {
+    let xattrs = InodeXattrs::load(fs, block_dev, inode_num)?;
+    Ok(xattrs
+        .entries
+        .iter()
+        .filter_map(|e| full_name(e.index, &e.name))
+        .collect())
+}
+
+/// 设置扩展属性
+///
+/// ACL 属性的值为用户态格式（`posix_acl_xattr`），校验后转换为磁盘格式存储
+pub fn set_xattr<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    inode_num: u32,
+    name: &str,
+    value: &[u8],
+    mode: XattrSetMode,
+) -> XattrResult<()> {
+    let (index, suffix) = split_name(name)?;
+    let value = if is_acl_index(index) {
+        let acl = acl::from_xattr(value).ok_or(XattrError::InvalidValue)?;
+        acl::to_disk(&acl)
+    } else {
+        value.to_vec()
+    };
+    let entry = XattrEntry {
+        index,
+        name: suffix.as_bytes().to_vec(),
+        value,
+        value_inum: 0,
+    };
+    if entry.storage_size() > block_capacity() {
+        return Err(XattrError::NoSpace);
+    }
+
+    let mut xattrs = InodeXattrs::load(fs, block_dev, inode_num)?;
+    match (xattrs.find(index, suffix), mode) {
+        (Some(_), XattrSetMode::Create) => return Err(XattrError::Exists),
+        (None, XattrSetMode::Replace) => return Err(XattrError::NotFound),
+        (Some(pos), _) => xattrs.entries[pos] = entry,
+        (None, _) => xattrs.entries.push(entry),
+    }
+    xattrs.store(fs, block_dev)
+}
+
+/// 删除扩展属性
+pub fn remove_xattr<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    inode_num: u32,
+    name: &str,
+) -> XattrResult<()> {
+    let (index, suffix) = split_name(name)?;
+    let mut xattrs = InodeXattrs::load(fs, block_dev, inode_num)?;
+    let pos = xattrs.find(index, suffix).ok_or(XattrError::NotFound)?;
+    xattrs.entries.remove(pos);
+    xattrs.store(fs, block_dev)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use super::*;
+    use crate::{
+        acl::{AclEntry, AclTag, AclType, get_acl, set_acl},
+        dir::mkdir_with_ino,
+        ext4::{mkfs, mount},
+        file::{delete_file, mkfile_with_ino},
+    };
+
+    struct MemBlockDev {
+        data: Vec<u8>,
+        total_blocks: u64,
+    }
+
+    impl BlockDevice for MemBlockDev {
+        fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+            let start = block_id as usize * BLOCK_SIZE;
+            let len = count as usize * BLOCK_SIZE;
+            self.data[start..start + len].copy_from_slice(&buffer[..len]);
+            Ok(())
+        }
+
+        fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+            let start = block_id as usize * BLOCK_SIZE;
+            let len = count as usize * BLOCK_SIZE;
+            buffer[..len].copy_from_slice(&self.data[start..start + len]);
+            Ok(())
+        }
+
+        fn open(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> BlockDevResult<()> {
+            Ok(())
+        }
+
+        fn total_blocks(&self) -> u64 {
+            self.total_blocks
+        }
+
+        fn block_size(&self) -> u32 {
+            BLOCK_SIZE as u32
+        }
+    }
+
+    fn new_dev() -> Jbd2Dev<MemBlockDev> {
+        let total_blocks = 16 * 1024;
+        let dev = MemBlockDev {
+            data: vec![0u8; total_blocks as usize * BLOCK_SIZE],
+            total_blocks,
+        };
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, dev, false);
+        mkfs(&mut dev).unwrap();
+        dev
+    }
+
+    #[test]
+    fn test_split_name() {
+        assert_eq!(split_name("user.foo"), Ok((INDEX_USER, "foo")));
+        assert_eq!(
+            split_name("security.selinux"),
+            Ok((INDEX_SECURITY, "selinux"))
+        );
+        assert_eq!(
+            split_name("system.posix_acl_access"),
+            Ok((INDEX_POSIX_ACL_ACCESS, ""))
+        );
+        assert_eq!(split_name("user."), Err(XattrError::InvalidName));
+        assert_eq!(split_name("foo.bar"), Err(XattrError::Unsupported));
+        assert_eq!(
+            full_name(INDEX_POSIX_ACL_DEFAULT, b"").as_deref(),
+            Some("system.posix_acl_default")
+        );
+    }
+
+    #[test]
+    fn test_xattr_block_roundtrip() {
+        let mut dev = new_dev();
+        let mut fs = mount(&mut dev).unwrap();
+        let (ino, _) = mkfile_with_ino(&mut dev, &mut fs, "/a", None, None).unwrap();
+        let free_before = fs.superblock.free_blocks_count();
+
+        set_xattr(
+            &mut fs,
+            &mut dev,
+            ino,
+            "user.a",
+            b"hello",
+            XattrSetMode::Any,
+        )
+        .unwrap();
+        set_xattr(
+            &mut fs,
+            &mut dev,
+            ino,
+            "security.capability",
+            &[1, 0, 0, 2],
+            XattrSetMode::Create,
+        )
+        .unwrap();
+        assert_eq!(
+            set_xattr(&mut fs, &mut dev, ino, "user.a", b"x", XattrSetMode::Create),
+            Err(XattrError::Exists)
+        );
+        assert_eq!(
+            set_xattr(
+                &mut fs,
+                &mut dev,
+                ino,
+                "user.b",
+                b"x",
+                XattrSetMode::Replace
+            ),
+            Err(XattrError::NotFound)
+        );
+        set_xattr(
+            &mut fs,
+            &mut dev,
+            ino,
+            "user.a",
+            b"world!",
+            XattrSetMode::Replace,
+        )
+        .unwrap();
+        assert_eq!(fs.superblock.free_blocks_count(), free_before - 1);
+        let inode = fs.get_inode_by_num(&mut dev, ino).unwrap();
+        assert_ne!(inode.file_acl(), 0);
+        assert_eq!(inode.blocks_count(), BLOCK_SIZE as u64 / 512);
+        fs.umount(&mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        assert!(
+            fs.superblock
+                .has_feature_compat(Ext4Superblock::EXT4_FEATURE_COMPAT_EXT_ATTR)
+        );
+        assert_eq!(
+            get_xattr(&mut fs, &mut dev, ino, "user.a").unwrap(),
+            b"world!"
+        );
+        let mut names = list_xattr(&mut fs, &mut dev, ino).unwrap();
+        names.sort();
+        assert_eq!(names, ["security.capability", "user.a"]);
+
+        remove_xattr(&mut fs, &mut dev, ino, "user.a").unwrap();
+        assert_eq!(
+            get_xattr(&mut fs, &mut dev, ino, "user.a"),
+            Err(XattrError::NotFound)
+        );
+        remove_xattr(&mut fs, &mut dev, ino, "security.capability").unwrap();
+        let inode = fs.get_inode_by_num(&mut dev, ino).unwrap();
+        assert_eq!(inode.file_acl(), 0);
+        assert_eq!(inode.blocks_count(), 0);
+        assert_eq!(fs.superblock.free_blocks_count(), free_before);
+        fs.umount(&mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        let report = fs.verify(&mut dev).unwrap();
+        assert!(report.is_clean(), "{:?}", report.issues);
+    }
+
+    #[test]
+    fn test_xattr_in_inode_body() {
+        let mut dev = new_dev();
+        let mut fs = mount(&mut dev).unwrap();
+        let (ino, _) = mkfile_with_ino(&mut dev, &mut fs, "/a", None, None).unwrap();
+        fs.modify_inode(&mut dev, ino, |td| td.i_extra_isize = IBODY_MIN_EXTRA_ISIZE)
+            .unwrap();
+
+        // 小属性放入内联区，不分配扩展属性块
+        set_xattr(
+            &mut fs,
+            &mut dev,
+            ino,
+            "user.small",
+            b"abc",
+            XattrSetMode::Any,
+        )
+        .unwrap();
+        assert_eq!(fs.get_inode_by_num(&mut dev, ino).unwrap().file_acl(), 0);
+
+        // 内联区放不下的属性溢出到扩展属性块
+        let big = vec![0x42u8; 512];
+        set_xattr(&mut fs, &mut dev, ino, "user.big", &big, XattrSetMode::Any).unwrap();
+        assert_ne!(fs.get_inode_by_num(&mut dev, ino).unwrap().file_acl(), 0);
+        fs.umount(&mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(
+            get_xattr(&mut fs, &mut dev, ino, "user.small").unwrap(),
+            b"abc"
+        );
+        assert_eq!(get_xattr(&mut fs, &mut dev, ino, "user.big").unwrap(), big);
+        let inode = fs.get_inode_by_num(&mut dev, ino).unwrap();
+        assert_eq!(inode.i_extra_isize, IBODY_MIN_EXTRA_ISIZE);
+
+        remove_xattr(&mut fs, &mut dev, ino, "user.big").unwrap();
+        assert_eq!(fs.get_inode_by_num(&mut dev, ino).unwrap().file_acl(), 0);
+        assert_eq!(list_xattr(&mut fs, &mut dev, ino).unwrap(), ["user.small"]);
+        fs.umount(&mut dev).unwrap();
+    }
+
+    #[test]
+    fn test_xattr_block_released_with_inode() {
+        let mut dev = new_dev();
+        let mut fs = mount(&mut dev).unwrap();
+        let free_before = fs.superblock.free_blocks_count();
+        let (ino, _) = mkfile_with_ino(&mut dev, &mut fs, "/a", None, None).unwrap();
+        set_xattr(&mut fs, &mut dev, ino, "user.a", b"1", XattrSetMode::Any).unwrap();
+        assert_eq!(fs.superblock.free_blocks_count(), free_before - 1);
+
+        delete_file(&mut fs, &mut dev, "/a");
+        assert_eq!(fs.superblock.free_blocks_count(), free_before);
+        fs.umount(&mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        let report = fs.verify(&mut dev).unwrap();
+        assert!(report.is_clean(), "{:?}", report.issues);
+    }
+
+    #[test]
+    fn test_acl_stored_in_disk_format() {
+        let mut dev = new_dev();
+        let mut fs = mount(&mut dev).unwrap();
+        let (file, _) = mkfile_with_ino(&mut dev, &mut fs, "/a", None, None).unwrap();
+        let (dir, _) = mkdir_with_ino(&mut dev, &mut fs, "/d").unwrap();
+        let acl = [
+            AclEntry {
+                tag: AclTag::UserObj,
+                perm: 6,
+                id: u32::MAX,
+            },
+            AclEntry {
+                tag: AclTag::User,
+                perm: 4,
+                id: 1000,
+            },
+            AclEntry {
+                tag: AclTag::GroupObj,
+                perm: 4,
+                id: u32::MAX,
+            },
+            AclEntry {
+                tag: AclTag::Mask,
+                perm: 4,
+                id: u32::MAX,
+            },
+            AclEntry {
+                tag: AclTag::Other,
+                perm: 0,
+                id: u32::MAX,
+            },
+        ];
+
+        assert_eq!(
+            set_acl(&mut fs, &mut dev, file, AclType::Default, Some(&acl)),
+            Err(XattrError::InvalidValue)
+        );
+        set_acl(&mut fs, &mut dev, file, AclType::Access, Some(&acl)).unwrap();
+        set_acl(&mut fs, &mut dev, dir, AclType::Default, Some(&acl)).unwrap();
+
+        // 磁盘上是紧凑格式：版本 + 4 个短项 + 1 个带 id 的项
+        let xattrs = InodeXattrs::load(&mut fs, &mut dev, file).unwrap();
+        assert_eq!(xattrs.entries[0].index, INDEX_POSIX_ACL_ACCESS);
+        assert_eq!(xattrs.entries[0].value.len(), 4 + 4 * 4 + 8);
+        fs.umount(&mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(
+            get_acl(&mut fs, &mut dev, file, AclType::Access).unwrap(),
+            Some(acl.to_vec())
+        );
+        assert_eq!(
+            get_acl(&mut fs, &mut dev, dir, AclType::Default).unwrap(),
+            Some(acl.to_vec())
+        );
+        set_acl(&mut fs, &mut dev, file, AclType::Access, None).unwrap();
+        assert_eq!(
+            get_acl(&mut fs, &mut dev, file, AclType::Access).unwrap(),
+            None
+        );
+        fs.umount(&mut dev).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Cross-CPU clock synchronization check.
+//!
+//! The counters of different CPUs may have been started at different times,
+//! like the TSCs of some x86 machines, or drift apart. One CPU measures each
+//! of the others against its own counter by ping-pong: it reads its time and
+//! bumps a sequence number, the other answers with its own time, and so on.
+//! Each side only spins on the other's store, without a lock or barrier
+//! that would add to the uncertainty.
+//!
+//! A round trip brackets the other CPU's read: if its clock agrees, its time
+//! falls between the two reads of the measuring CPU. Falling outside is a
+//! warp, time going backwards for a task moving between the two. The round
+//! trip with the least latency gives the offset, which is compensated in
+//! [`khal::time::adjust_clock_offset`].
+
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use khal::time::{now_ticks, ns2t, signed_t2ns, t2ns};
+
+/// Round trips made with each CPU.
+const ROUNDS: u64 = 32;
+
+/// How long a side waits for the other, covering the IPI latency.
+const TIMEOUT: Duration = Duration::from_millis(50);
+
+/// How often the clocks are checked again after boot.
+const INTERVAL: Duration = Duration::from_secs(60);
+
+/// Sequence number of the last store: odd ones from the measuring CPU, even
+/// ones from the measured CPU.
+static SEQ: AtomicU64 = AtomicU64::new(0);
+/// Time of the measured CPU, written before even sequence numbers.
+static CPU_TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Set while a check runs, the above being shared.
+static CHECKING: AtomicBool = AtomicBool::new(false);
+
+/// Measured offset of a CPU's clock.
+struct Sample {
+    /// Round trip of the best round, in ticks.
+    rtt: u64,
+    /// Ticks the CPU is ahead, at the best round.
+    offset: i64,
+    /// Most ticks the CPU was seen outside a round trip.
+    warp: u64,
+}
+
+/// Spins until the sequence number reaches `seq`, returning `false` if it
+/// is abandoned or the other side times out.
+fn wait_for(seq: u64) -> bool {
+    let deadline = kplat::timer::now_ticks() + ns2t(TIMEOUT.as_nanos() as u64);
+    loop {
+        match SEQ.load(Ordering::Acquire) {
+            s if s == seq => return true,
+            s if s > seq => return false,
+            _ if kplat::timer::now_ticks() > deadline => return false,
+            _ => core::hint::spin_loop(),
+        }
+    }
+}
+
+/// The measured CPU's side, run from an IPI.
+fn respond(base: u64) {
+    for round in 0..ROUNDS {
+        if !wait_for(base + 2 * round + 1) {
+            return;
+        }
+        CPU_TICKS.store(now_ticks(), Ordering::Relaxed);
+        SEQ.store(base + 2 * round + 2, Ordering::Release);
+    }
+}
+
+/// The measuring CPU's side.
+fn measure(base: u64) -> Option<Sample> {
+    let mut best: Option<(u64, i64)> = None;
+    let mut warp = 0;
+    for round in 0..ROUNDS {
+        let t0 = now_ticks();
+        SEQ.store(base + 2 * round + 1, Ordering::Release);
+        if !wait_for(base + 2 * round + 2) {
+            // Abandon the remaining rounds
+            SEQ.store(base + 2 * ROUNDS, Ordering::Release);
+            return None;
+        }
+        let t2 = now_ticks();
+        let t1 = CPU_TICKS.load(Ordering::Relaxed);
+
+        warp = warp.max(t0.saturating_sub(t1)).max(t1.saturating_sub(t2));
+        let rtt = t2 - t0;
+        let offset = t1.wrapping_sub(t0 + rtt / 2) as i64;
+        if best.is_none_or(|(best_rtt, _)| rtt < best_rtt) {
+            best = Some((rtt, offset));
+        }
+    }
+    best.map(|(rtt, offset)| Sample { rtt, offset, warp })
+}
+
+/// Checks the clocks of the other CPUs against that of the current one,
+/// logging warps and compensating offsets beyond the measurement error.
+///
+/// With `backwards`, CPUs whose clock is ahead are set back, so this is
+/// only allowed before their time is relied on; otherwise they are only
+/// reported.
+pub fn check(backwards: bool) {
+    if CHECKING.swap(true, Ordering::Acquire) {
+        return;
+    }
+    // Stay on this CPU and keep interrupts from stretching round trips
+    let irqs_enabled = khal::asm::is_enabled();
+    khal::asm::disable_local();
+    let this_cpu = khal::percpu::this_cpu_id();
+    for cpu in (0..kbuild_config::CPU_NUM).filter(|&cpu| cpu != this_cpu) {
+        let base = SEQ.load(Ordering::Relaxed);
+        if kipi::run_on_cpu(cpu, move || respond(base)).is_err() {
+            continue;
+        }
+        let Some(sample) = measure(base) else {
+            warn!("clock sync: CPU {cpu} did not respond");
+            continue;
+        };
+        let offset_ns = signed_t2ns(sample.offset);
+        let error_ns = t2ns(sample.rtt / 2);
+        if sample.warp > 0 {
+            warn!(
+                "clock sync: CPU {cpu} warps by {} ns (offset {offset_ns} ns)",
+                t2ns(sample.warp)
+            );
+        } else {
+            debug!("clock sync: CPU {cpu} offset {offset_ns} +/- {error_ns} ns");
+        }
+        if sample.offset.unsigned_abs() > sample.rtt / 2 && (backwards || sample.offset < 0) {
+            info!("clock sync: compensating CPU {cpu} by {} ns", -offset_ns);
+            khal::time::adjust_clock_offset(cpu, -sample.offset);
+        }
+    }
+    if irqs_enabled {
+        khal::asm::enable_local();
+    }
+    CHECKING.store(false, Ordering::Release);
+}
+
+/// Checks the clocks once all CPUs are up, then every [`INTERVAL`].
+pub fn init() {
+    check(true);
+    ktask::spawn(|| {
+        loop {
+            ktask::sleep(INTERVAL);
+            check(false);
+        }
+    });
+}
@@ -7,7 +7,12 @@ use core::panic::PanicInfo;
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    let backtrace = backtrace::Backtrace::capture();
     kprintln!("{}", info);
-    kprintln!("{}", backtrace::Backtrace::capture());
+    kprintln!("{}", backtrace);
+    #[cfg(feature = "vsock-telemetry")]
+    crate::telemetry::report_panic(info, &backtrace);
+    #[cfg(feature = "liveness")]
+    liveness::notify_panic();
     khal::power::shutdown()
 }
@@ -10,6 +10,18 @@
 //! - `fs`: Enable filesystem support.
 //! - `net`: Enable networking support.
 //! - `display`: Enable graphics support.
+//! - `vsock-telemetry`: Report panics and lockups to a collector on the host.
+//! - `liveness`: Publish a heartbeat and panics to the host through
+//!   pvpanic and ivshmem devices, see [`liveness`].
+//! - `agent`: Serve host orchestration requests over vsock.
+//! - `snapshot`: Quiesce for VM snapshots and resume after restores, see
+//!   [`ktask::snapshot`].
+//! - `trace`: Record scheduler events from boot, served at `/trace` with
+//!   `httpd`, see [`ktrace`].
+//!
+//! Whatever the features, the subsystems are torn down in order on shutdown,
+//! see [`kpower`]; with `agent`, the host can also ask for one with the
+//! `poweroff`, `halt` and `reboot` commands.
 //!
 //! All the features are optional and disabled by default.
 
@@ -18,12 +30,27 @@
 
 #[macro_use]
 extern crate klogger;
+#[cfg(any(feature = "alloc", feature = "agent"))]
+extern crate alloc;
 
 #[cfg(all(target_os = "none", not(test)))]
 mod lang_items;
 
+#[cfg(all(feature = "smp", feature = "ipi"))]
+mod clocksync;
+#[cfg(feature = "alloc")]
+mod metrics;
 #[cfg(feature = "smp")]
 mod mp;
+#[cfg(feature = "alloc")]
+mod reclaim;
+mod shutdown;
+#[cfg(feature = "snapshot")]
+mod snapshot;
+#[cfg(feature = "vsock-telemetry")]
+mod telemetry;
+#[cfg(feature = "trace")]
+mod trace;
 
 #[cfg(feature = "smp")]
 pub use self::mp::rust_main_secondary;
@@ -169,8 +196,14 @@ pub fn rust_main(cpu_id: usize, arg: usize) -> ! {
         );
     }
 
+    khal::cpuinfo::init();
+    khal::dtb::check_platconfig();
+
     #[cfg(feature = "alloc")]
-    init_allocator();
+    {
+        init_allocator();
+        metrics::init();
+    }
 
     {
         use core::ops::Range;
@@ -201,19 +234,43 @@ pub fn rust_main(cpu_id: usize, arg: usize) -> ! {
     khal::final_init(cpu_id, arg);
 
     ktask::init_scheduler();
-
-    #[cfg(any(feature = "fs", feature = "net", feature = "display"))]
+    #[cfg(feature = "alloc")]
+    self::reclaim::init();
+    #[cfg(feature = "trace")]
+    self::trace::init();
+
+    #[cfg(any(
+        feature = "fs",
+        feature = "net",
+        feature = "display",
+        feature = "liveness"
+    ))]
     {
         #[allow(unused_variables)]
         let all_devices = kdriver::init_drivers();
 
+        #[cfg(feature = "liveness")]
+        liveness::init();
+
         #[cfg(feature = "fs")]
         kfs::init_filesystems(all_devices.block);
 
         #[cfg(feature = "net")]
         knet::init_network(all_devices.net);
+        #[cfg(all(feature = "httpd", feature = "trace"))]
+        self::trace::register_endpoint();
+        #[cfg(feature = "httpd")]
+        if let Err(err) = khttpd::start(khttpd::DEFAULT_PORT) {
+            warn!("Failed to start the diagnostics server: {:?}", err);
+        }
         #[cfg(feature = "vsock")]
         knet::init_vsock(all_devices.vsock);
+        #[cfg(feature = "vsock-telemetry")]
+        self::telemetry::init();
+        #[cfg(feature = "agent")]
+        if let Err(err) = kagent::start(kagent::DEFAULT_PORT) {
+            warn!("Failed to start the guest agent: {:?}", err);
+        }
 
         #[cfg(feature = "display")]
         fbdevice::fb_init(all_devices.display);
@@ -222,15 +279,29 @@ pub fn rust_main(cpu_id: usize, arg: usize) -> ! {
         inputdev::init_input(all_devices.input);
     }
 
+    #[cfg(all(
+        feature = "watchdog",
+        any(feature = "vsock-telemetry", feature = "liveness")
+    ))]
+    watchdog::set_lockup_handler(report_lockup);
+
     #[cfg(feature = "smp")]
     self::mp::start_secondary_cpus(cpu_id);
 
     info!("Initialize interrupt handlers...");
     init_interrupt();
 
+    #[cfg(feature = "ipi")]
+    kipi::init();
+
     #[cfg(feature = "watchdog")]
     watchdog::init_primary();
 
+    #[cfg(feature = "snapshot")]
+    self::snapshot::init();
+
+    self::shutdown::init();
+
     kinit_setup::init_cb();
 
     info!("Primary CPU {cpu_id} init OK.");
@@ -239,15 +310,37 @@ pub fn rust_main(cpu_id: usize, arg: usize) -> ! {
     while !is_init_ok() {
         core::hint::spin_loop();
     }
+    khal::hotplug::CPU_NOTIFIER.call(&khal::hotplug::CpuEvent::Online(cpu_id));
+
+    #[cfg(all(feature = "smp", feature = "ipi"))]
+    clocksync::init();
+
+    #[cfg(feature = "liveness")]
+    liveness::notify_running();
 
     unsafe { main() };
 
-    ktask::exit(0);
+    kpower::power_off(kpower::Reason::InitExited);
+}
+
+/// Passes lockups detected by the watchdog to the host.
+#[cfg(all(
+    feature = "watchdog",
+    any(feature = "vsock-telemetry", feature = "liveness")
+))]
+fn report_lockup(lockup: &watchdog::Lockup) {
+    #[cfg(feature = "vsock-telemetry")]
+    self::telemetry::report_lockup(lockup);
+    #[cfg(feature = "liveness")]
+    {
+        let _ = lockup;
+        liveness::notify_lockup();
+    }
 }
 
 #[cfg(feature = "alloc")]
 fn init_allocator() {
-    use khal::mem::{MemFlags, memory_regions, p2v, v2p};
+    use khal::mem::{MemFlags, MemoryRegion, memory_regions, p2v, v2p};
 
     info!("Initialize global memory allocator...");
     info!("  use {} allocator.", kalloc::global_allocator().name());
@@ -259,6 +352,29 @@ fn init_allocator() {
     }
     let kernel_end_paddr = v2p(_ekernel.as_ptr().addr().into());
 
+    // The CMA pool is carved out of the end of the largest free memory
+    // region, aligned for large buffers.
+    const CMA_ALIGN: usize = 0x20_0000;
+    let cma_size = memaddr::align_up_4k(khal::mem::cma_size());
+    let cma = free_regions()
+        .max_by_key(|r| r.size)
+        .filter(|_| cma_size > 0)
+        .and_then(|r| {
+            let end = r.paddr.as_usize() + r.size;
+            let start = memaddr::align_down(end.checked_sub(cma_size)?, CMA_ALIGN);
+            // Leave most of the region to the allocator
+            let cut = end - start;
+            (cut <= r.size / 2).then_some((r.paddr, start, cut))
+        });
+    if cma_size > 0 && cma.is_none() {
+        warn!("no room for a CMA pool of {cma_size:#x} bytes");
+    }
+    // Size of a free memory region left to the allocator
+    let usable = |r: &MemoryRegion| match cma {
+        Some((paddr, _, cut)) if paddr == r.paddr => r.size - cut,
+        _ => r.size,
+    };
+
     let init_region = free_regions()
         // First try to find a free memory region after the kernel image
         .find(|r| r.paddr >= kernel_end_paddr)
@@ -266,15 +382,20 @@ fn init_allocator() {
         .or_else(|| free_regions().max_by_key(|r| r.size))
         .expect("no free memory region found!!");
 
-    kalloc::global_init(p2v(init_region.paddr).as_usize(), init_region.size);
+    kalloc::global_init(p2v(init_region.paddr).as_usize(), usable(&init_region));
 
     for r in free_regions() {
         if r.paddr != init_region.paddr {
-            kalloc::global_add_memory(p2v(r.paddr).as_usize(), r.size)
+            kalloc::global_add_memory(p2v(r.paddr).as_usize(), usable(&r))
                 .expect("add heap memory region failed");
         }
     }
 
+    if let Some((_, start, size)) = cma {
+        info!("  CMA pool: [PA:{start:#x}, PA:{:#x})", start + size);
+        kalloc::global_init_cma(p2v(start.into()).as_usize(), size);
+    }
+
     let dma_regions = || memory_regions().filter(|r| r.flags.contains(MemFlags::UNCACHED));
     for r in dma_regions() {
         kalloc::global_init_dma_page_allocator(p2v(r.paddr).as_usize(), r.size);
@@ -282,26 +403,9 @@ fn init_allocator() {
 }
 
 fn init_interrupt() {
-    // Setup timer interrupt handler
-    const PERIODIC_INTERVAL_NANOS: u64 =
-        khal::time::NANOS_PER_SEC / kbuild_config::TICKS_PER_SECOND as u64;
-
-    #[percpu::def_percpu]
-    static NEXT_DEADLINE: u64 = 0;
-
-    fn update_timer() {
-        let now_ns = khal::time::monotonic_time_nanos();
-        // Safety: we have disabled preemption in IRQ handler.
-        let mut deadline = unsafe { NEXT_DEADLINE.read_current_raw() };
-        if now_ns >= deadline {
-            deadline = now_ns + PERIODIC_INTERVAL_NANOS;
-        }
-        unsafe { NEXT_DEADLINE.write_current_raw(deadline + PERIODIC_INTERVAL_NANOS) };
-        khal::time::arm_timer(deadline);
-    }
-
+    // Setup timer interrupt handler, which arms the timer for the next
+    // deadline.
     khal::irq::register(khal::time::interrupt_id(), || {
-        update_timer();
         ktask::on_timer_tick();
     });
 
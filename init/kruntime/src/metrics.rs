@@ -0,0 +1,82 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Uptime and allocator metrics.
+use kalloc::{HeapTag, UsageKind, global_allocator, heap_tag_usage};
+use kmetrics::{Kind, MetricsWriter};
+use strum::VariantArray;
+
+pub(crate) fn init() {
+    kmetrics::register_collector(collect);
+    khal::irq::register_metrics();
+}
+
+fn collect(writer: &mut MetricsWriter) {
+    writer.single(
+        "xkernel_uptime_seconds",
+        Kind::Gauge,
+        "Time since boot.",
+        khal::time::monotonic_time().as_secs_f64(),
+    );
+
+    let allocator = global_allocator();
+    let usages = allocator.usages();
+    writer.family(
+        "xkernel_memory_usage_bytes",
+        Kind::Gauge,
+        "Memory in use by kind.",
+    );
+    for &kind in UsageKind::VARIANTS {
+        let name: &'static str = kind.into();
+        writer.sample(
+            "xkernel_memory_usage_bytes",
+            &[("kind", name)],
+            usages.get(kind),
+        );
+    }
+    if heap_tag_usage(HeapTag::Other).is_some() {
+        collect_heap_tags(writer);
+    }
+    writer.single(
+        "xkernel_memory_used_bytes",
+        Kind::Gauge,
+        "Bytes allocated from the heap.",
+        allocator.used_bytes(),
+    );
+    writer.single(
+        "xkernel_memory_available_bytes",
+        Kind::Gauge,
+        "Bytes the heap can still allocate without more pages.",
+        allocator.available_bytes(),
+    );
+    writer.single(
+        "xkernel_memory_free_pages",
+        Kind::Gauge,
+        "Pages left in the page allocator.",
+        allocator.available_pages(),
+    );
+}
+
+fn collect_heap_tags(writer: &mut MetricsWriter) {
+    for (name, help, peak) in [
+        (
+            "xkernel_heap_tag_bytes",
+            "Heap bytes in use by subsystem.",
+            false,
+        ),
+        (
+            "xkernel_heap_tag_peak_bytes",
+            "Highest heap bytes in use by subsystem.",
+            true,
+        ),
+    ] {
+        writer.family(name, Kind::Gauge, help);
+        for &tag in HeapTag::VARIANTS {
+            let usage = heap_tag_usage(tag).unwrap_or_default();
+            let label: &'static str = tag.into();
+            let value = if peak { usage.peak } else { usage.current };
+            writer.sample(name, &[("tag", label)], value);
+        }
+    }
+}
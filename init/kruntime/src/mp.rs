@@ -64,6 +64,7 @@ pub fn rust_main_secondary(cpu_id: usize) -> ! {
     while !super::is_init_ok() {
         core::hint::spin_loop();
     }
+    khal::hotplug::CPU_NOTIFIER.call(&khal::hotplug::CpuEvent::Online(cpu_id));
 
     #[cfg(feature = "pmu")]
     khal::irq::enable(kbuild_config::PMU_IRQ, true);
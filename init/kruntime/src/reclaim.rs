@@ -0,0 +1,56 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Background memory reclaim.
+//!
+//! The `kreclaimd` task sleeps until the free pages drop below the low
+//! watermark, then asks the registered shrinkers for memory until they are
+//! back over twice the watermark, so that allocations seldom have to reclaim
+//! by themselves.
+
+use alloc::string::ToString;
+use core::time::Duration;
+
+use kalloc::{LOW_MEMORY, global_allocator};
+use knotifier::NotifyResult;
+use ktask::WaitQueue;
+
+/// Share of the memory kept free, as a fraction.
+const LOW_WATERMARK_RATIO: usize = 64;
+/// Pause after a round of reclaim freeing nothing.
+const BACKOFF: Duration = Duration::from_secs(1);
+
+const PAGE_SIZE: usize = 0x1000;
+
+static RECLAIM_WQ: WaitQueue = WaitQueue::new();
+
+/// Sets the low watermark and starts `kreclaimd`.
+pub(crate) fn init() {
+    let allocator = global_allocator();
+    let total = allocator.used_pages() + allocator.available_pages();
+    kalloc::set_low_watermark(total / LOW_WATERMARK_RATIO);
+    LOW_MEMORY.register(0, |_| {
+        RECLAIM_WQ.notify_one(false);
+        NotifyResult::Ok
+    });
+    ktask::spawn_with_name(reclaim_task, "kreclaimd".to_string());
+}
+
+fn reclaim_task() {
+    let high = kalloc::low_watermark() * 2;
+    loop {
+        RECLAIM_WQ.wait_until(kalloc::is_low_memory);
+        let free = global_allocator().available_pages();
+        let freed = if free < high {
+            kalloc::shrink_memory((high - free) * PAGE_SIZE)
+        } else {
+            0
+        };
+        debug!("kreclaimd: {free} pages free, freed {freed} bytes");
+        if freed == 0 {
+            ktask::sleep(BACKOFF);
+        }
+        kalloc::clear_low_memory();
+    }
+}
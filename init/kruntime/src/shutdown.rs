@@ -0,0 +1,106 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Shutdown hooks of the runtime's subsystems, see [`kpower`].
+use kpower::{Hook, Stage};
+
+pub(crate) fn init() {
+    // Deliberate, which the host should not take for a crash
+    #[cfg(feature = "liveness")]
+    register(Stage::Services, "liveness", |_| {
+        liveness::notify_shutdown();
+        Ok(())
+    });
+    #[cfg(feature = "fs")]
+    register(Stage::Filesystems, "fs", |_| kfs::sync_filesystems());
+    #[cfg(feature = "net")]
+    register(Stage::Network, "net", |_| {
+        knet::stop_interfaces();
+        Ok(())
+    });
+    #[cfg(feature = "fs")]
+    register(Stage::Devices, "disks", |_| kfs::flush_block_devices());
+    // Before the CPUs stop taking timer interrupts
+    #[cfg(feature = "watchdog")]
+    register(Stage::Cpus, "watchdog", |_| {
+        watchdog::stop();
+        Ok(())
+    });
+    #[cfg(all(feature = "smp", feature = "ipi"))]
+    register(Stage::Cpus, "park-cpus", park_cpus);
+    #[cfg(feature = "agent")]
+    {
+        kagent::register_command("poweroff", shell::poweroff);
+        kagent::register_command("halt", shell::halt);
+        kagent::register_command("reboot", shell::reboot);
+    }
+}
+
+#[allow(dead_code)] // Unused without subsystems to tear down
+fn register(stage: Stage, name: &'static str, hook: Hook) {
+    if let Err(err) = kpower::register_hook(stage, name, hook) {
+        warn!("shutdown: failed to register {}: {:?}", name, err);
+    }
+}
+
+/// Stops the other CPUs with their interrupts disabled, for good.
+#[cfg(all(feature = "smp", feature = "ipi"))]
+fn park_cpus(_request: &kpower::Request) -> kerrno::KResult {
+    use kerrno::{KError, ResultExt};
+
+    let this_cpu = khal::percpu::this_cpu_id();
+    for cpu in (0..kbuild_config::CPU_NUM).filter(|&cpu| cpu != this_cpu) {
+        let park = kipi::Callback::new(move || {
+            khal::hotplug::CPU_NOTIFIER.call(&khal::hotplug::CpuEvent::Offline(cpu));
+            loop {
+                khal::asm::stop_cpu();
+            }
+        });
+        kipi::run_on_cpu(cpu, park).or_kerr(KError::BadState)?;
+    }
+    Ok(())
+}
+
+/// Debug shell commands, run through the guest agent.
+#[cfg(feature = "agent")]
+mod shell {
+    use alloc::{format, string::String};
+    use core::time::Duration;
+
+    use kpower::{Action, Reason, Request};
+
+    /// Leaves the agent the time to answer before the shutdown starts.
+    const REPLY_DELAY: Duration = Duration::from_millis(100);
+
+    fn start(action: Action) -> Result<String, String> {
+        let request = Request {
+            action,
+            reason: Reason::Shell,
+        };
+        ktask::spawn(move || {
+            ktask::sleep(REPLY_DELAY);
+            kpower::shutdown(request)
+        });
+        Ok(format!("{:?}\n", action))
+    }
+
+    pub(super) fn poweroff(_args: &[&str]) -> Result<String, String> {
+        start(Action::PowerOff)
+    }
+
+    pub(super) fn halt(_args: &[&str]) -> Result<String, String> {
+        start(Action::Halt)
+    }
+
+    /// `reboot [cold|warm|bootloader]`, cold by default.
+    pub(super) fn reboot(args: &[&str]) -> Result<String, String> {
+        let mode = match args {
+            [] => kpower::RebootMode::Cold,
+            [mode] => kpower::parse_reboot_mode(mode)
+                .map_err(|_| format!("unknown reboot mode: {mode}"))?,
+            _ => return Err("usage: reboot [cold|warm|bootloader]".into()),
+        };
+        start(Action::Reboot(mode))
+    }
+}
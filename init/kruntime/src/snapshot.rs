@@ -0,0 +1,56 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Snapshot hooks of the runtime's subsystems, see [`ktask::snapshot`].
+use kpower::{POWER_NOTIFIER, PowerEvent};
+use ktask::snapshot::{self, Hooks};
+
+pub(crate) fn init() {
+    snapshot::register(Hooks {
+        name: "power",
+        quiesce: || {
+            POWER_NOTIFIER.call(&PowerEvent::Suspend);
+        },
+        resume: || {
+            POWER_NOTIFIER.call(&PowerEvent::Resume);
+        },
+    });
+    #[cfg(feature = "fs")]
+    snapshot::register(Hooks {
+        name: "fs",
+        quiesce: flush_filesystems,
+        // The disks come back with their backends on the host restarted
+        resume: kfs::reset_block_devices,
+    });
+    #[cfg(feature = "net")]
+    snapshot::register(Hooks {
+        name: "net",
+        quiesce: knet::poll_interfaces,
+        // Like the disks, the NICs are set up again. Interrupts raised while
+        // the VM was stopped may be lost, and after a migration the neighbors
+        // are to learn where the addresses went
+        resume: || {
+            knet::reset_interfaces();
+            knet::announce_interfaces();
+        },
+    });
+    #[cfg(feature = "agent")]
+    kagent::register_command("checkpoint", checkpoint);
+    snapshot::watch();
+}
+
+#[cfg(feature = "fs")]
+fn flush_filesystems() {
+    if let Err(err) = kfs::sync_filesystems() {
+        warn!("snapshot: failed to flush the filesystems: {:?}", err);
+    }
+}
+
+/// Takes a checkpoint for the host, answering with its marker line.
+#[cfg(feature = "agent")]
+fn checkpoint(_args: &[&str]) -> Result<alloc::string::String, alloc::string::String> {
+    use alloc::string::ToString;
+
+    Ok(snapshot::checkpoint().to_string())
+}
@@ -0,0 +1,35 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Panic and lockup reports to a collector on the host.
+use core::panic::PanicInfo;
+
+use backtrace::Backtrace;
+use knet::vsock::telemetry::{self, FrameKind};
+
+pub(crate) fn init() {
+    telemetry::start(telemetry::DEFAULT_PORT);
+}
+
+#[cfg_attr(not(target_os = "none"), allow(dead_code))]
+pub(crate) fn report_panic(info: &PanicInfo, backtrace: &Backtrace) {
+    telemetry::report(FrameKind::Panic, format_args!("{info}\n{backtrace}"));
+}
+
+#[cfg(feature = "watchdog")]
+pub(crate) fn report_lockup(lockup: &watchdog::Lockup) {
+    match *lockup {
+        watchdog::Lockup::Soft { cpu, stalled_ns } => telemetry::report(
+            FrameKind::SoftLockup,
+            format_args!(
+                "soft lockup on cpu {cpu}: stuck for {}ms",
+                stalled_ns / 1_000_000
+            ),
+        ),
+        watchdog::Lockup::Hard { cpu, task } => telemetry::report(
+            FrameKind::HardLockup,
+            format_args!("watchdog task {task} failed on cpu {cpu}"),
+        ),
+    };
+}
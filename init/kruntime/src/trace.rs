@@ -0,0 +1,23 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Scheduler tracing from boot, see [`ktrace`].
+
+/// Events kept per CPU; older ones are overwritten.
+const EVENTS_PER_CPU: usize = 4096;
+
+pub(crate) fn init() {
+    ktrace::start(EVENTS_PER_CPU);
+}
+
+/// Serves the trace buffer in the ftrace text format at `/trace`, to be
+/// opened in Perfetto or other ftrace viewers.
+#[cfg(feature = "httpd")]
+pub(crate) fn register_endpoint() {
+    use khttpd::http::Response;
+
+    khttpd::register_endpoint("/trace", |_request| {
+        Response::stream(200, "text/plain", |out| ktrace::export(out))
+    });
+}
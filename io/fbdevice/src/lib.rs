@@ -3,11 +3,17 @@
 // See LICENSES for license details.
 
 //! Framebuffer device initialization and access helpers.
+//!
+//! The primary framebuffer is blanked once idle for the [blank
+//! timeout](fb_set_blank_timeout), and unblanked on the next
+//! [activity](fb_activity), e.g. an input event.
 #![no_std]
 
 #[macro_use]
 extern crate log;
 
+use core::time::Duration;
+
 pub use kdriver::prelude::DisplayInfo;
 use kdriver::{DeviceContainer, prelude::*};
 use ksync::Mutex;
@@ -15,6 +21,9 @@ use lazyinit::LazyInit;
 
 static PRIMARY_FB: LazyInit<Mutex<DisplayDevice>> = LazyInit::new();
 
+static BLANK_TIMER: Mutex<BlankTimer> =
+    Mutex::new(BlankTimer::new(Some(BlankTimer::DEFAULT_TIMEOUT)));
+
 /// Initialize the framebuffer subsystem with available devices.
 pub fn fb_init(mut display_devs: DeviceContainer<DisplayDevice>) {
     info!("Initialize framebuffer subsystem...");
@@ -38,6 +47,67 @@ pub fn fb_info() -> DisplayInfo {
 }
 
 /// Flush the primary framebuffer to the display.
+///
+/// Nothing is shown while the display is blanked.
 pub fn fb_flush() -> bool {
     PRIMARY_FB.lock().flush().is_ok()
 }
+
+/// Blanks or unblanks the primary display at `now`, returning whether its
+/// driver could.
+pub fn fb_set_blanked(blanked: bool, now: Duration) -> bool {
+    if !fb_available() {
+        return false;
+    }
+    let mut timer = BLANK_TIMER.lock();
+    match PRIMARY_FB.lock().set_blanked(blanked) {
+        Ok(()) => {
+            timer.set_blanked(blanked, now);
+            true
+        }
+        Err(err) => {
+            debug!("failed to set the display blanked to {blanked}: {err:?}");
+            false
+        }
+    }
+}
+
+/// Returns whether the primary display is blanked.
+pub fn fb_blanked() -> bool {
+    BLANK_TIMER.lock().is_blanked()
+}
+
+/// Sets the inactivity after which the primary display is blanked, `None`
+/// to keep it on.
+pub fn fb_set_blank_timeout(timeout: Option<Duration>) {
+    BLANK_TIMER.lock().set_timeout(timeout);
+}
+
+/// Records user activity at `now`, unblanking the primary display.
+pub fn fb_activity(now: Duration) {
+    let mut timer = BLANK_TIMER.lock();
+    if timer.activity(now)
+        && fb_available()
+        && let Err(err) = PRIMARY_FB.lock().set_blanked(false)
+    {
+        warn!("failed to unblank the display: {err:?}");
+    }
+}
+
+/// Blanks the primary display if it was idle for the timeout at `now`.
+pub fn fb_check_idle(now: Duration) {
+    let mut timer = BLANK_TIMER.lock();
+    if !fb_available() || !timer.expired(now) {
+        return;
+    }
+    match PRIMARY_FB.lock().set_blanked(true) {
+        Ok(()) => info!("display blanked after inactivity"),
+        Err(err) => {
+            // Counts down again
+            timer.set_blanked(false, now);
+            if !matches!(err, DriverError::Unsupported) {
+                warn!("failed to blank the display: {err:?}");
+            }
+        }
+    }
+}
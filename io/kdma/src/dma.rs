@@ -2,6 +2,7 @@
 // Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
 // See LICENSES for license details.
 
+use alloc::collections::BTreeMap;
 use core::{alloc::Layout, ptr::NonNull};
 
 use alloc_engine::{AllocError, AllocResult, BaseAllocator, ByteAllocator};
@@ -25,15 +26,26 @@ pub(crate) static ALLOCATOR: SpinNoIrq<DmaAllocator> = SpinNoIrq::new(DmaAllocat
 
 pub(crate) struct DmaAllocator {
     alloc: DefaultByteAllocator,
+    /// Sizes of the page-granular allocations, by start address.
+    pages: BTreeMap<usize, usize>,
 }
 
 impl DmaAllocator {
     pub const fn new() -> Self {
         Self {
             alloc: DefaultByteAllocator::new(),
+            pages: BTreeMap::new(),
         }
     }
 
+    /// Returns the bus address of `cpu_addr` if `[cpu_addr, cpu_addr + len)`
+    /// lies within a page-granular allocation.
+    pub fn coherent_bus_addr(&self, cpu_addr: NonNull<u8>, len: usize) -> Option<DmaBusAddress> {
+        let start = cpu_addr.as_ptr() as usize;
+        let (&base, &size) = self.pages.range(..=start).next_back()?;
+        (start + len <= base + size).then(|| v2b(va!(start)))
+    }
+
     /// Allocate arbitrary number of bytes. Returns the left bound of the
     /// allocated region.
     ///
@@ -89,12 +101,19 @@ impl DmaAllocator {
             PAGE_SIZE_4K.max(layout.align()),
             UsageKind::Dma,
         )?;
+        self.map_coherent_pages(vaddr_raw, num_pages)
+    }
+
+    /// Makes the `num_pages` pages at `vaddr_raw` coherent, and tracks them
+    /// as a page-granular allocation.
+    fn map_coherent_pages(&mut self, vaddr_raw: usize, num_pages: usize) -> AllocResult<DMAInfo> {
         let vaddr = va!(vaddr_raw);
         let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::UNCACHED;
         #[cfg(feature = "sev")]
         // For SEV, DMA memory must be shared (not encrypted)
         let flags = flags | MappingFlags::SHARED;
         self.update_flags(vaddr, num_pages, flags)?;
+        self.pages.insert(vaddr_raw, num_pages * PAGE_SIZE_4K);
         Ok(DMAInfo {
             cpu_addr: unsafe { NonNull::new_unchecked(vaddr_raw as *mut u8) },
             bus_addr: v2b(vaddr),
@@ -120,6 +139,7 @@ impl DmaAllocator {
         if layout.size() >= PAGE_SIZE_4K {
             let num_pages = layout_pages(&layout);
             let virt_raw = dma.cpu_addr.as_ptr() as usize;
+            self.pages.remove(&virt_raw);
 
             let _ = self.update_flags(
                 va!(virt_raw),
@@ -127,13 +147,35 @@ impl DmaAllocator {
                 MappingFlags::READ | MappingFlags::WRITE,
             );
 
-            global_allocator().dealloc_dma_pages(virt_raw, num_pages, UsageKind::Dma);
+            if global_allocator().is_cma(virt_raw) {
+                global_allocator().dealloc_cma_pages(virt_raw, num_pages, UsageKind::Dma);
+            } else {
+                global_allocator().dealloc_dma_pages(virt_raw, num_pages, UsageKind::Dma);
+            }
         } else {
             self.alloc.deallocate(dma.cpu_addr, layout)
         }
     }
 }
 
+/// Allocates coherent pages from the CMA pool.
+///
+/// Moving the pages lent from the pool out of the way may take locks, so it
+/// is done without the lock of [`ALLOCATOR`].
+pub(crate) fn allocate_cma_memory(layout: Layout) -> AllocResult<DMAInfo> {
+    let num_pages = layout_pages(&layout);
+    let vaddr_raw = global_allocator().alloc_cma_pages(
+        num_pages,
+        PAGE_SIZE_4K.max(layout.align()),
+        UsageKind::Dma,
+    )?;
+    debug!("allocate {num_pages} DMA pages from the CMA pool @{vaddr_raw:#x}");
+    ALLOCATOR
+        .lock()
+        .map_coherent_pages(vaddr_raw, num_pages)
+        .inspect_err(|_| global_allocator().dealloc_cma_pages(vaddr_raw, num_pages, UsageKind::Dma))
+}
+
 fn v2b(addr: VirtAddr) -> DmaBusAddress {
     let paddr = v2p(addr);
     p2b(paddr)
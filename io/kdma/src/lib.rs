@@ -11,10 +11,10 @@ mod dma;
 
 use core::{alloc::Layout, ptr::NonNull};
 
-use alloc_engine::AllocResult;
+use alloc_engine::{AllocError, AllocResult};
 // Re-export the interface trait for implementors
 pub use dma::DmaPageTableIf;
-use memaddr::PhysAddr;
+use memaddr::{PAGE_SIZE_4K, PhysAddr};
 
 use self::dma::ALLOCATOR;
 
@@ -43,13 +43,23 @@ pub const fn p2b(paddr: PhysAddr) -> DmaBusAddress {
 /// memory, such as the starting address and size. If it's not possible to
 /// allocate memory meeting the criteria, returns [`None`].
 ///
+/// Allocations of whole pages the DMA memory cannot hold, such as
+/// framebuffers or device firmware, are taken from the CMA pool if there is
+/// one, which may move the pages it lent out of the way.
+///
 /// # Safety
 ///
 /// This function is unsafe because it directly interacts with the global
 /// allocator, which can potentially cause memory leaks or other issues if not
 /// used correctly.
 pub unsafe fn allocate_dma_memory(layout: Layout) -> AllocResult<DMAInfo> {
-    unsafe { ALLOCATOR.lock().allocate_dma_memory(layout) }
+    let result = unsafe { ALLOCATOR.lock().allocate_dma_memory(layout) };
+    match result {
+        Err(AllocError::NoMemory) if layout.size() >= PAGE_SIZE_4K => {
+            dma::allocate_cma_memory(layout)
+        }
+        result => result,
+    }
 }
 
 /// Frees coherent memory previously allocated.
@@ -69,6 +79,15 @@ pub unsafe fn deallocate_dma_memory(dma: DMAInfo, layout: Layout) {
     unsafe { ALLOCATOR.lock().deallocate_dma_memory(dma, layout) }
 }
 
+/// Returns the bus address of the memory at `cpu_addr` if all `len` bytes
+/// from it are within one coherent allocation of at least a page.
+///
+/// Such memory is already accessible to devices, so e.g. no bounce buffer is
+/// needed to hand it to one. Smaller allocations are not tracked.
+pub fn coherent_bus_addr(cpu_addr: NonNull<u8>, len: usize) -> Option<DmaBusAddress> {
+    ALLOCATOR.lock().coherent_bus_addr(cpu_addr, len)
+}
+
 /// A bus memory address.
 ///
 /// It's a wrapper type around an [`u64`].
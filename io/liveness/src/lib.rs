@@ -0,0 +1,66 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Guest liveness, published to the host so that it can restart hung
+//! guests.
+//!
+//! Two devices are used when the host provides them:
+//!
+//! - [`pvpanic`] raises an event on the host when the guest panics, for the
+//!   host to apply its crash policy at once;
+//! - the [liveness page](page) carries a heartbeat a kernel task bumps every
+//!   [`HEARTBEAT_INTERVAL`](page::HEARTBEAT_INTERVAL), along with the state
+//!   of the guest and the lockups the watchdog reported.
+//!
+//! With QEMU, pass `-device pvpanic-pci` and
+//! `-object memory-backend-file,id=live,share=on,mem-path=<file>,size=4K
+//! -device ivshmem-plain,memdev=live`.
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+pub mod page;
+pub mod pvpanic;
+
+pub use self::page::GuestState;
+
+/// Looks for the devices once the PCI bus is probed, and starts the
+/// heartbeat.
+pub fn init() {
+    let pvpanic = pvpanic::init();
+    let page = page::init();
+    if !pvpanic && !page {
+        info!("No pvpanic or ivshmem device, liveness not published");
+    }
+}
+
+/// Reports that the guest is up, once initialization is done.
+pub fn notify_running() {
+    page::set_state(GuestState::Running);
+}
+
+/// Reports a panic to the host.
+///
+/// Called from the panic handler, it only touches device memory.
+pub fn notify_panic() {
+    page::set_state(GuestState::Panicked);
+    pvpanic::notify(pvpanic::PANICKED);
+}
+
+/// Reports a deliberate shutdown, which the host should not take for a
+/// crash.
+pub fn notify_shutdown() {
+    page::set_state(GuestState::ShuttingDown);
+    pvpanic::notify(pvpanic::SHUTDOWN);
+}
+
+/// Counts a lockup detected by the watchdog.
+///
+/// The guest may recover from soft lockups; hard ones end in a panic,
+/// reported by [`notify_panic`].
+pub fn notify_lockup() {
+    page::count_lockup();
+}
@@ -0,0 +1,142 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! The liveness page, shared with the host through an `ivshmem-plain`
+//! device.
+//!
+//! The page starts the shared memory in BAR 2, with little-endian fields
+//! laid out as in [`LivenessPage`]. The host tells a hung guest, whose
+//! heartbeat stopped, from a slow one, whose heartbeat comes late: the
+//! lateness of the last beat is published with it.
+use alloc::string::ToString;
+use core::{
+    sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+const VENDOR_ID: u16 = 0x1af4;
+const DEVICE_ID: u16 = 0x1110;
+const SHMEM_BAR: u8 = 2;
+
+/// `"XKLIVE"` followed by two zero bytes, in little-endian.
+pub const MAGIC: u64 = u64::from_le_bytes(*b"XKLIVE\0\0");
+/// Version of the layout, bumped when it changes.
+pub const VERSION: u32 = 1;
+/// How often the heartbeat is published.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// State of the guest, as published in [`LivenessPage::state`].
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuestState {
+    Booting      = 0,
+    Running      = 1,
+    Panicked     = 2,
+    ShuttingDown = 3,
+}
+
+/// Layout of the page.
+///
+/// `magic` is written last when the page is set up, so the host reads the
+/// other fields only once it matches.
+#[repr(C)]
+pub struct LivenessPage {
+    pub magic: AtomicU64,
+    pub version: AtomicU32,
+    /// A [`GuestState`].
+    pub state: AtomicU32,
+    /// Expected time between two beats, in milliseconds.
+    pub interval_ms: AtomicU32,
+    pub cpus: AtomicU32,
+    /// Incremented on each beat by a kernel task, so the scheduler has to
+    /// make progress for it to move.
+    pub heartbeat: AtomicU64,
+    /// Monotonic time of the last beat.
+    pub uptime_ns: AtomicU64,
+    /// How much later than scheduled the last beat ran.
+    pub latency_ns: AtomicU64,
+    /// Lockups reported by the watchdog.
+    pub lockups: AtomicU64,
+}
+
+/// Virtual address of the page, 0 without a device.
+static PAGE: AtomicUsize = AtomicUsize::new(0);
+
+fn page() -> Option<&'static LivenessPage> {
+    let page = PAGE.load(Ordering::Acquire);
+    // Safety: set by `init` to shared memory large enough for the page
+    (page != 0).then(|| unsafe { &*(page as *const LivenessPage) })
+}
+
+/// Looks for the device and sets up the page, returning whether it was
+/// found.
+pub(crate) fn init() -> bool {
+    let Some((paddr, size)) = kdriver::pci_memory_bar(VENDOR_ID, DEVICE_ID, SHMEM_BAR) else {
+        return false;
+    };
+    if size < size_of::<LivenessPage>() {
+        warn!("ivshmem at {paddr:#x} too small for the liveness page: {size:#x} bytes");
+        return false;
+    }
+    let vaddr = khal::mem::p2v(paddr.into()).as_usize();
+    // Safety: BAR 2 of the device, mapped with the other MMIO ranges
+    let page = unsafe { &*(vaddr as *const LivenessPage) };
+    page.magic.store(0, Ordering::Relaxed);
+    page.version.store(VERSION, Ordering::Relaxed);
+    page.state
+        .store(GuestState::Booting as u32, Ordering::Relaxed);
+    page.interval_ms
+        .store(HEARTBEAT_INTERVAL.as_millis() as u32, Ordering::Relaxed);
+    page.cpus
+        .store(kbuild_config::CPU_NUM as u32, Ordering::Relaxed);
+    page.heartbeat.store(0, Ordering::Relaxed);
+    page.uptime_ns
+        .store(khal::time::monotonic_time_nanos(), Ordering::Relaxed);
+    page.latency_ns.store(0, Ordering::Relaxed);
+    page.lockups.store(0, Ordering::Relaxed);
+    page.magic.store(MAGIC, Ordering::Release);
+    PAGE.store(vaddr, Ordering::Release);
+    info!("liveness page at {paddr:#x}");
+
+    ktask::spawn_with_name(heartbeat_task, "liveness".to_string());
+    true
+}
+
+fn heartbeat_task() {
+    let Some(page) = page() else {
+        return;
+    };
+    // Timers run on wall time
+    let mut next = khal::time::wall_time() + HEARTBEAT_INTERVAL;
+    loop {
+        ktask::sleep_until(next);
+        let now = khal::time::wall_time();
+        page.uptime_ns
+            .store(khal::time::monotonic_time_nanos(), Ordering::Relaxed);
+        page.latency_ns.store(
+            now.saturating_sub(next).as_nanos() as u64,
+            Ordering::Relaxed,
+        );
+        page.heartbeat.fetch_add(1, Ordering::Release);
+        next += HEARTBEAT_INTERVAL;
+        // Beats missed while late are skipped rather than caught up
+        if next <= now {
+            next = now + HEARTBEAT_INTERVAL;
+        }
+    }
+}
+
+/// Publishes the state of the guest.
+pub fn set_state(state: GuestState) {
+    if let Some(page) = page() {
+        page.state.store(state as u32, Ordering::Release);
+    }
+}
+
+/// Counts a lockup reported by the watchdog.
+pub fn count_lockup() {
+    if let Some(page) = page() {
+        page.lockups.fetch_add(1, Ordering::Release);
+    }
+}
@@ -0,0 +1,55 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! The pvpanic device, through which the host learns of guest panics.
+//!
+//! `pvpanic-pci` has a one-byte register in BAR 0: reading it returns the
+//! events the host handles, writing an event raises it.
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+const VENDOR_ID: u16 = 0x1b36;
+const DEVICE_ID: u16 = 0x0011;
+
+/// The guest panicked.
+pub const PANICKED: u8 = 1 << 0;
+/// The guest panicked and a crash kernel takes over.
+pub const CRASH_LOADED: u8 = 1 << 1;
+/// The guest shuts down on purpose.
+pub const SHUTDOWN: u8 = 1 << 2;
+
+/// Virtual address of the register, 0 without a device.
+static REGISTER: AtomicUsize = AtomicUsize::new(0);
+static EVENTS: AtomicU8 = AtomicU8::new(0);
+
+/// Looks for the device, returning whether it was found.
+pub(crate) fn init() -> bool {
+    let Some((paddr, _)) = kdriver::pci_memory_bar(VENDOR_ID, DEVICE_ID, 0) else {
+        return false;
+    };
+    let register = khal::mem::p2v(paddr.into()).as_usize();
+    // Safety: BAR 0 of the device, mapped with the other MMIO ranges
+    let events = unsafe { (register as *const u8).read_volatile() };
+    info!("pvpanic at {paddr:#x}, host handles events {events:#x}");
+    EVENTS.store(events, Ordering::Relaxed);
+    REGISTER.store(register, Ordering::Release);
+    true
+}
+
+/// Returns the events the host handles, none without a device.
+pub fn supported_events() -> u8 {
+    EVENTS.load(Ordering::Relaxed)
+}
+
+/// Raises `event` on the host, returning whether it handles it.
+///
+/// Safe to call while panicking.
+pub fn notify(event: u8) -> bool {
+    let register = REGISTER.load(Ordering::Acquire);
+    if register == 0 || supported_events() & event == 0 {
+        return false;
+    }
+    // Safety: set by `init` to the register of the device
+    unsafe { (register as *mut u8).write_volatile(event) };
+    true
+}
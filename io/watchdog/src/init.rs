@@ -3,6 +3,8 @@
 // See LICENSES for license details.
 
 //! Watchdog initialization and NMI handler setup.
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use khal::{context::TrapFrame, percpu::this_cpu_id};
 use ktask::{KCpuMask, TaskInner};
 use log::debug;
@@ -13,6 +15,17 @@ use crate::rendezvous as rv;
 static mut TRAP_FRAMES: [Option<&TrapFrame>; platconfig::plat::CPU_NUM] =
     [None; platconfig::plat::CPU_NUM];
 
+/// Set once the detectors stopped, see [`stop`].
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// Stops lockup detection on all CPUs for good.
+///
+/// Called on shutdown before the other CPUs are parked, which would pass
+/// for hard lockups.
+pub fn stop() {
+    STOPPED.store(true, Ordering::Release);
+}
+
 /// Common watchdog initialization for both primary and secondary CPUs.
 ///
 /// It sets up:
@@ -33,6 +46,9 @@ fn init_common() {
 
     // Register NMI handler
     khal::nmi::register_nmi_handler(|| {
+        if STOPPED.load(Ordering::Acquire) {
+            return;
+        }
         // Every NMI checks whether watchdog tasks on THIS CPU are healthy.
         // If a failure is detected, THIS CPU becomes the cause CPU and
         // triggers a global rendezvous.
@@ -53,6 +69,11 @@ fn init_common() {
                 // Strong rendezvous: MUST wait until all CPUs are in NMI.
                 rv::wait_all_arrived_strong();
 
+                crate::lockup_detection::notify_lockup(&crate::Lockup::Hard {
+                    cpu: this_cpu,
+                    task: fail_name.unwrap_or("unknown"),
+                });
+
                 kplat::kprint_atomic!(
                     "[watchdog] failure detected on cpu {}, failed_task={:?}, arrived_mask={:#x}",
                     this_cpu,
@@ -93,10 +114,14 @@ fn init_common() {
 pub fn init_softlockup_detection() {
     // Timer callback used to detect soft lockup conditions.
     ktask::register_timer_callback(|_| {
+        if STOPPED.load(Ordering::Acquire) {
+            return;
+        }
         let now_ns = khal::time::monotonic_time_nanos();
         crate::timer_tick();
 
         if crate::check_softlockup(now_ns) {
+            crate::lockup_detection::report_softlockup(now_ns);
             if let Some(tf) = khal::context::active_exception_context() {
                 ktask::dump_cur_task_backtrace(this_cpu_id(), tf, false);
             }
@@ -9,9 +9,10 @@ pub mod lockup_detection;
 pub mod rendezvous;
 pub mod watchdog_task;
 pub use crate::{
-    init::{init_primary, init_secondary},
+    init::{init_primary, init_secondary, stop},
     lockup_detection::{
-        check_softlockup, register_hardlockup_detection_task, timer_tick, touch_softlockup,
+        Lockup, LockupHandler, check_softlockup, register_hardlockup_detection_task,
+        set_lockup_handler, timer_tick, touch_softlockup,
     },
     watchdog_task::register_watchdog_task,
 };
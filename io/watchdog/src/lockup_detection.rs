@@ -3,7 +3,9 @@
 // See LICENSES for license details.
 
 //! Soft/hard lockup detection state and helpers.
-use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use lazyinit::LazyInit;
 
 use crate::watchdog_task::WatchdogTask;
 
@@ -20,6 +22,8 @@ pub struct LockupDetection {
     /// Timestamp when watchdog thread last ran (nanoseconds).
     /// Updated by watchdog thread, checked by timer interrupt.
     soft_timestamp: AtomicU64,
+    /// Whether the current softlockup was already passed to the handler.
+    soft_reported: AtomicBool,
 
     // === Hardlockup Detection ===
     /// Timer interrupt counter (incremented in timer interrupt).
@@ -33,6 +37,7 @@ impl LockupDetection {
     pub const fn new() -> Self {
         Self {
             soft_timestamp: AtomicU64::new(0),
+            soft_reported: AtomicBool::new(false),
             hrtimer_interrupts: AtomicU32::new(0),
             hrtimer_interrupts_saved: AtomicU32::new(0),
         }
@@ -48,6 +53,7 @@ impl LockupDetection {
     #[inline]
     pub fn touch_softlockup(&self, timestamp_ns: u64) {
         self.soft_timestamp.store(timestamp_ns, Ordering::Release);
+        self.soft_reported.store(false, Ordering::Relaxed);
     }
 
     /// Get the soft timestamp.
@@ -70,6 +76,13 @@ impl LockupDetection {
         now_ns.saturating_sub(last) > threshold_ns
     }
 
+    /// Returns true only for the first call since the last touch, so a
+    /// softlockup is passed to the handler once.
+    #[inline]
+    pub fn first_softlockup_report(&self) -> bool {
+        !self.soft_reported.swap(true, Ordering::Relaxed)
+    }
+
     // =========================================================================
     // Hardlockup detection
     // =========================================================================
@@ -133,6 +146,47 @@ pub fn check_softlockup(now_ns: u64) -> bool {
     }
 }
 
+/// Report the softlockup of the current CPU to the lockup handler, once per
+/// softlockup.
+pub(crate) fn report_softlockup(now_ns: u64) {
+    let detection = unsafe { LOCKUP_DETECTION.current_ref_raw() };
+    if detection.first_softlockup_report() {
+        notify_lockup(&Lockup::Soft {
+            cpu: khal::percpu::this_cpu_id(),
+            stalled_ns: now_ns.saturating_sub(detection.soft_timestamp()),
+        });
+    }
+}
+
+/// A lockup detected by the watchdog.
+#[derive(Debug, Clone, Copy)]
+pub enum Lockup<'a> {
+    /// The watchdog thread of `cpu` has not run for `stalled_ns`.
+    Soft { cpu: usize, stalled_ns: u64 },
+    /// The watchdog task `task` failed on `cpu`; a global dump and a panic
+    /// follow.
+    Hard { cpu: usize, task: &'a str },
+}
+
+/// Handler of detected lockups, called from timer interrupt or NMI context.
+pub type LockupHandler = fn(&Lockup);
+
+static LOCKUP_HANDLER: LazyInit<LockupHandler> = LazyInit::new();
+
+/// Set the handler of detected lockups, e.g. to report them to a host.
+///
+/// Only one handler can be set.
+pub fn set_lockup_handler(handler: LockupHandler) {
+    LOCKUP_HANDLER.init_once(handler);
+}
+
+/// Pass a detected lockup to the handler, if any.
+pub(crate) fn notify_lockup(lockup: &Lockup) {
+    if let Some(handler) = LOCKUP_HANDLER.get() {
+        handler(lockup);
+    }
+}
+
 /// Register the hard lockup detection task on the current CPU.
 pub fn register_hardlockup_detection_task() {
     let task: &'static LockupDetection = unsafe { LOCKUP_DETECTION.current_ref_raw() };
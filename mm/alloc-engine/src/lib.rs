@@ -11,6 +11,9 @@
 //! - [`PageAllocator`]: Page-granularity memory allocator. (e.g.,
 //!   [`BitmapPageAllocator`])
 //! - [`IdAllocator`]: Used to allocate unique IDs.
+//!
+//! With the `object-cache` feature, [`ObjectCache`] serves objects of a single
+//! layout out of slabs.
 
 #![no_std]
 #![cfg_attr(feature = "allocator_api", feature(allocator_api))]
@@ -29,6 +32,11 @@ mod buddy;
 #[cfg(feature = "buddy")]
 pub use buddy::BuddyByteAllocator;
 
+#[cfg(feature = "object-cache")]
+mod object_cache;
+#[cfg(feature = "object-cache")]
+pub use object_cache::{CacheStats, ObjectCache, SlabSource};
+
 #[cfg(feature = "slab")]
 mod slab;
 #[cfg(feature = "slab")]
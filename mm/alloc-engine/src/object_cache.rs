@@ -0,0 +1,338 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Caches of fixed-size objects carved out of slabs.
+//!
+//! An [`ObjectCache`] serves objects of a single layout. It takes slabs,
+//! runs of contiguous pages, from a [`SlabSource`] and cuts them into
+//! objects, chained through a free link while unused. An optional
+//! constructor runs once per object when its slab is created, and objects
+//! must be given back in their constructed state, as with `kmem_cache`
+//! constructors in Linux: the free link is then kept past the object so
+//! that it does not clobber it.
+//!
+//! The slab descriptors are kept apart from the slabs, in the heap, so that
+//! page-sized objects waste no room.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use core::{alloc::Layout, ptr::NonNull};
+
+use super::{AllocResult, align_up};
+
+const PAGE_SIZE: usize = 0x1000;
+/// Objects a slab of small objects holds at least.
+const MIN_OBJECTS_PER_SLAB: usize = 8;
+/// Empty slabs kept when objects are freed, for the next allocations.
+const KEEP_EMPTY_SLABS: usize = 1;
+
+/// Where an [`ObjectCache`] gets its slabs from.
+pub trait SlabSource {
+    /// Allocates a page-aligned slab of `size` bytes, a multiple of the page
+    /// size.
+    fn alloc_slab(&mut self, size: usize) -> AllocResult<usize>;
+
+    /// Gives back a slab allocated by [`alloc_slab`](Self::alloc_slab).
+    fn dealloc_slab(&mut self, addr: usize, size: usize);
+}
+
+/// Statistics of an [`ObjectCache`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Size of the objects, as asked for.
+    pub object_size: usize,
+    /// Room an object takes in its slab.
+    pub stride: usize,
+    /// Size of a slab in bytes.
+    pub slab_size: usize,
+    /// Objects a slab holds.
+    pub objects_per_slab: usize,
+    /// Slabs allocated.
+    pub slabs: usize,
+    /// Slabs without objects in use, released by a shrink.
+    pub empty_slabs: usize,
+    /// Objects handed out and not given back yet.
+    pub active_objects: usize,
+    /// Objects the slabs hold, in use or not.
+    pub total_objects: usize,
+    /// Allocations made since the cache was created.
+    pub allocs: u64,
+    /// Frees made since the cache was created.
+    pub frees: u64,
+}
+
+struct Slab {
+    /// Address of the first free object, 0 if full.
+    free: usize,
+    in_use: usize,
+}
+
+/// A cache of objects of a single layout.
+pub struct ObjectCache {
+    layout: Layout,
+    stride: usize,
+    /// Offset of the free link in a free object.
+    link: usize,
+    slab_size: usize,
+    objects_per_slab: usize,
+    ctor: Option<fn(NonNull<u8>)>,
+    /// Descriptors of the slabs, by address.
+    slabs: BTreeMap<usize, Slab>,
+    /// Slabs with free objects, the lowest being used first.
+    available: BTreeSet<usize>,
+    empty_slabs: usize,
+    active: usize,
+    allocs: u64,
+    frees: u64,
+}
+
+impl ObjectCache {
+    /// Creates a cache of objects of `layout`, built by `ctor` if given.
+    ///
+    /// Panics if the alignment is over the page size.
+    pub const fn new(layout: Layout, ctor: Option<fn(NonNull<u8>)>) -> Self {
+        assert!(
+            layout.align() <= PAGE_SIZE,
+            "object alignment over the page size"
+        );
+        let word = Layout::new::<usize>();
+        let align = if layout.align() > word.align() {
+            layout.align()
+        } else {
+            word.align()
+        };
+        let (link, end) = match ctor {
+            Some(_) => {
+                let link = align_up(layout.size(), word.align());
+                (link, link + word.size())
+            }
+            None if layout.size() > word.size() => (0, layout.size()),
+            None => (0, word.size()),
+        };
+        let stride = align_up(end, align);
+        let slab_size = if stride >= PAGE_SIZE / MIN_OBJECTS_PER_SLAB {
+            let wanted = align_up(stride * MIN_OBJECTS_PER_SLAB, PAGE_SIZE);
+            let bound = align_up(stride, PAGE_SIZE) * 2;
+            if wanted < bound { wanted } else { bound }
+        } else {
+            PAGE_SIZE
+        };
+        Self {
+            layout,
+            stride,
+            link,
+            slab_size,
+            objects_per_slab: slab_size / stride,
+            ctor,
+            slabs: BTreeMap::new(),
+            available: BTreeSet::new(),
+            empty_slabs: 0,
+            active: 0,
+            allocs: 0,
+            frees: 0,
+        }
+    }
+
+    /// Returns the layout of the objects.
+    pub const fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Allocates an object, taking a new slab from `source` if needed.
+    pub fn allocate(&mut self, source: &mut impl SlabSource) -> AllocResult<NonNull<u8>> {
+        let addr = match self.available.first() {
+            Some(&addr) => addr,
+            None => self.grow(source)?,
+        };
+        let slab = self.slabs.get_mut(&addr).unwrap();
+        let object = slab.free;
+        // SAFETY: free objects hold the address of the next one at `link`.
+        slab.free = unsafe { ((object + self.link) as *const usize).read() };
+        if slab.in_use == 0 {
+            self.empty_slabs -= 1;
+        }
+        slab.in_use += 1;
+        if slab.free == 0 {
+            self.available.remove(&addr);
+        }
+        self.active += 1;
+        self.allocs += 1;
+        Ok(NonNull::new(object as *mut u8).unwrap())
+    }
+
+    /// Gives back an object, returning its slab to `source` if it is left
+    /// empty and enough empty slabs are kept already.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by this cache, and not be used any more.
+    /// With a constructor, the object must be in its constructed state.
+    pub unsafe fn deallocate(&mut self, ptr: NonNull<u8>, source: &mut impl SlabSource) {
+        let object = ptr.as_ptr() as usize;
+        let (&addr, slab) = self
+            .slabs
+            .range_mut(..=object)
+            .next_back()
+            .filter(|(addr, _)| object < *addr + self.slab_size)
+            .expect("object not from this cache");
+        // SAFETY: the object is free now, and has room for the link.
+        unsafe { ((object + self.link) as *mut usize).write(slab.free) };
+        slab.free = object;
+        slab.in_use -= 1;
+        self.available.insert(addr);
+        self.active -= 1;
+        self.frees += 1;
+        if slab.in_use == 0 {
+            if self.empty_slabs >= KEEP_EMPTY_SLABS {
+                self.release(addr, source);
+            } else {
+                self.empty_slabs += 1;
+            }
+        }
+    }
+
+    /// Gives the empty slabs back to `source`. Returns the number of bytes
+    /// released.
+    ///
+    /// Does not allocate, so that it may run when memory is short.
+    pub fn shrink(&mut self, source: &mut impl SlabSource) -> usize {
+        let slab_size = self.slab_size;
+        let mut released = 0;
+        self.slabs.retain(|&addr, slab| {
+            if slab.in_use != 0 {
+                return true;
+            }
+            source.dealloc_slab(addr, slab_size);
+            released += slab_size;
+            false
+        });
+        let slabs = &self.slabs;
+        self.available.retain(|addr| slabs.contains_key(addr));
+        self.empty_slabs = 0;
+        released
+    }
+
+    /// Returns the statistics of the cache.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            object_size: self.layout.size(),
+            stride: self.stride,
+            slab_size: self.slab_size,
+            objects_per_slab: self.objects_per_slab,
+            slabs: self.slabs.len(),
+            empty_slabs: self.empty_slabs,
+            active_objects: self.active,
+            total_objects: self.slabs.len() * self.objects_per_slab,
+            allocs: self.allocs,
+            frees: self.frees,
+        }
+    }
+
+    /// Adds a slab of free, constructed objects. Returns its address.
+    fn grow(&mut self, source: &mut impl SlabSource) -> AllocResult<usize> {
+        let addr = source.alloc_slab(self.slab_size)?;
+        let mut free = 0;
+        for index in (0..self.objects_per_slab).rev() {
+            let object = addr + index * self.stride;
+            if let Some(ctor) = self.ctor {
+                ctor(NonNull::new(object as *mut u8).unwrap());
+            }
+            // SAFETY: the object lies in the new slab.
+            unsafe { ((object + self.link) as *mut usize).write(free) };
+            free = object;
+        }
+        self.slabs.insert(addr, Slab { free, in_use: 0 });
+        self.available.insert(addr);
+        self.empty_slabs += 1;
+        Ok(addr)
+    }
+
+    fn release(&mut self, addr: usize, source: &mut impl SlabSource) {
+        self.slabs.remove(&addr);
+        self.available.remove(&addr);
+        source.dealloc_slab(addr, self.slab_size);
+    }
+}
+
+#[cfg(all(unittest, feature = "object-cache"))]
+#[allow(missing_docs)]
+pub mod tests_object_cache {
+    use alloc::{vec, vec::Vec};
+    use core::{alloc::Layout, ptr::NonNull};
+
+    use unittest::def_test;
+
+    use super::{ObjectCache, PAGE_SIZE, SlabSource};
+    use crate::AllocResult;
+
+    /// Hands out slabs from a vector, keeping track of those in use.
+    struct Pool {
+        memory: Vec<u8>,
+        next: usize,
+        live: usize,
+    }
+
+    impl Pool {
+        fn new(pages: usize) -> Self {
+            Self {
+                memory: vec![0; (pages + 1) * PAGE_SIZE],
+                next: 0,
+                live: 0,
+            }
+        }
+    }
+
+    impl SlabSource for Pool {
+        fn alloc_slab(&mut self, size: usize) -> AllocResult<usize> {
+            let base = (self.memory.as_ptr() as usize).next_multiple_of(PAGE_SIZE);
+            let addr = base + self.next;
+            self.next += size;
+            self.live += 1;
+            Ok(addr)
+        }
+
+        fn dealloc_slab(&mut self, _addr: usize, _size: usize) {
+            self.live -= 1;
+        }
+    }
+
+    #[def_test]
+    fn test_object_cache_reuse_and_shrink() {
+        let mut pool = Pool::new(8);
+        let mut cache = ObjectCache::new(Layout::new::<[u64; 4]>(), None);
+        let per_slab = cache.stats().objects_per_slab;
+        assert_eq!(per_slab, PAGE_SIZE / 32);
+
+        let objects = (0..per_slab + 1)
+            .map(|_| cache.allocate(&mut pool).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(cache.stats().slabs, 2);
+        assert_eq!(cache.stats().active_objects, per_slab + 1);
+
+        for object in objects {
+            unsafe { cache.deallocate(object, &mut pool) };
+        }
+        // One empty slab is kept for later
+        assert_eq!(pool.live, 1);
+        assert_eq!(cache.stats().empty_slabs, 1);
+        assert_eq!(cache.shrink(&mut pool), PAGE_SIZE);
+        assert_eq!(pool.live, 0);
+        assert_eq!(cache.stats().allocs, cache.stats().frees);
+    }
+
+    #[def_test]
+    fn test_object_cache_constructor_state_kept() {
+        fn ctor(ptr: NonNull<u8>) {
+            unsafe { ptr.cast::<u64>().write(0x5a5a) };
+        }
+        let mut pool = Pool::new(8);
+        let mut cache = ObjectCache::new(Layout::new::<u64>(), Some(ctor));
+        let object = cache.allocate(&mut pool).unwrap();
+        assert_eq!(unsafe { object.cast::<u64>().read() }, 0x5a5a);
+        unsafe { cache.deallocate(object, &mut pool) };
+        // The free link lives past the object
+        let again = cache.allocate(&mut pool).unwrap();
+        assert_eq!(again, object);
+        assert_eq!(unsafe { again.cast::<u64>().read() }, 0x5a5a);
+    }
+}
@@ -0,0 +1,260 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Named caches of kernel objects, in the manner of Linux `kmem_cache`s.
+//!
+//! A [`KmemCache`] is a static serving objects of one layout from slabs of
+//! pages accounted as [`UsageKind::Slab`]. Caches register themselves on
+//! their first allocation, so that [`for_each_cache`] lists them and
+//! [`shrink_caches`] reclaims their empty slabs, which the page allocator
+//! also does by itself before raising [`MEMORY_PRESSURE`].
+//!
+//! A cache implements [`Allocator`], to put e.g. an `Arc` in it with
+//! `Arc::new_in`. Layouts the objects do not fit fall back to the heap.
+//!
+//! [`MEMORY_PRESSURE`]: crate::MEMORY_PRESSURE
+
+use core::{
+    alloc::{AllocError, Allocator, Layout},
+    fmt,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+use alloc_engine::{AllocResult, CacheStats, ObjectCache, SlabSource};
+use kspin::SpinNoIrq;
+
+use crate::{PAGE_SIZE, UsageKind, global_allocator};
+
+/// Head of the list of the caches used so far.
+static CACHES: AtomicPtr<KmemCache> = AtomicPtr::new(ptr::null_mut());
+
+/// Slabs from the page allocator.
+struct SlabPages;
+
+impl SlabSource for SlabPages {
+    fn alloc_slab(&mut self, size: usize) -> AllocResult<usize> {
+        global_allocator().alloc_pages(size / PAGE_SIZE, PAGE_SIZE, UsageKind::Slab)
+    }
+
+    fn dealloc_slab(&mut self, addr: usize, size: usize) {
+        global_allocator().dealloc_pages(addr, size / PAGE_SIZE, UsageKind::Slab);
+    }
+}
+
+/// A named cache of objects of one layout.
+///
+/// ```ignore
+/// static TASK_CACHE: KmemCache = KmemCache::new("task", Layout::new::<Task>(), None);
+/// ```
+pub struct KmemCache {
+    name: &'static str,
+    layout: Layout,
+    shrinker: Option<fn() -> usize>,
+    inner: SpinNoIrq<ObjectCache>,
+    next: AtomicPtr<KmemCache>,
+    registered: AtomicBool,
+}
+
+impl KmemCache {
+    /// Creates a cache named `name` of objects of `layout`.
+    ///
+    /// `ctor` runs on each object when its slab is allocated, and objects
+    /// must be freed in the state it left them in.
+    ///
+    /// Panics if `layout` is aligned to more than a page.
+    pub const fn new(name: &'static str, layout: Layout, ctor: Option<fn(NonNull<u8>)>) -> Self {
+        Self {
+            name,
+            layout,
+            shrinker: None,
+            inner: SpinNoIrq::new(ObjectCache::new(layout, ctor)),
+            next: AtomicPtr::new(ptr::null_mut()),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    /// Sets a shrinker, called by [`shrink`](Self::shrink) before the empty
+    /// slabs are released, for the users of the cache to free the objects
+    /// they keep around without needing them. It returns the number of
+    /// objects freed.
+    ///
+    /// The page allocator running out does not call it, as the cache may be
+    /// locked then.
+    pub const fn with_shrinker(mut self, shrinker: fn() -> usize) -> Self {
+        self.shrinker = Some(shrinker);
+        self
+    }
+
+    /// Returns the name of the cache.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the layout of the objects.
+    pub const fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Allocates an object.
+    pub fn alloc(&'static self) -> AllocResult<NonNull<u8>> {
+        self.register();
+        self.inner.lock().allocate(&mut SlabPages)
+    }
+
+    /// Frees an object.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by [`alloc`](Self::alloc) on this
+    /// cache, and not be used any more.
+    pub unsafe fn dealloc(&self, ptr: NonNull<u8>) {
+        unsafe { self.inner.lock().deallocate(ptr, &mut SlabPages) }
+    }
+
+    /// Returns the statistics of the cache.
+    pub fn stats(&self) -> CacheStats {
+        self.inner.lock().stats()
+    }
+
+    /// Runs the shrinker, then gives the empty slabs back to the page
+    /// allocator. Returns the number of bytes released.
+    pub fn shrink(&self) -> usize {
+        if let Some(shrinker) = self.shrinker {
+            shrinker();
+        }
+        self.inner.lock().shrink(&mut SlabPages)
+    }
+
+    /// Gives the empty slabs back without calling the shrinker, unless the
+    /// cache is busy.
+    fn try_shrink(&self) -> usize {
+        self.inner
+            .try_lock()
+            .map_or(0, |mut inner| inner.shrink(&mut SlabPages))
+    }
+
+    /// Whether objects of `layout` come from the cache when allocated through
+    /// [`Allocator`].
+    fn fits(&self, layout: Layout) -> bool {
+        layout.size() <= self.layout.size() && layout.align() <= self.layout.align()
+    }
+
+    fn register(&'static self) {
+        if self.registered.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let this = self as *const _ as *mut KmemCache;
+        let mut head = CACHES.load(Ordering::Acquire);
+        loop {
+            self.next.store(head, Ordering::Relaxed);
+            match CACHES.compare_exchange_weak(head, this, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+impl fmt::Debug for KmemCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KmemCache")
+            .field("name", &self.name)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+unsafe impl Allocator for &'static KmemCache {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 || !self.fits(layout) {
+            return alloc::alloc::Global.allocate(layout);
+        }
+        let ptr = (*self).alloc().map_err(|_| AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() == 0 || !self.fits(layout) {
+            return unsafe { alloc::alloc::Global.deallocate(ptr, layout) };
+        }
+        unsafe { self.dealloc(ptr) }
+    }
+}
+
+/// Returns the layout of the allocation of an `Arc<T>`, to make a cache of
+/// them.
+pub const fn arc_layout<T>() -> Layout {
+    // Strong and weak counts, then the value
+    let Ok((layout, _)) = Layout::new::<[usize; 2]>().extend(Layout::new::<T>()) else {
+        panic!("Arc layout overflow");
+    };
+    layout.pad_to_align()
+}
+
+/// Calls `f` on each cache that has been used.
+pub fn for_each_cache(mut f: impl FnMut(&'static KmemCache)) {
+    let mut next = CACHES.load(Ordering::Acquire);
+    // SAFETY: the list only holds `'static` caches.
+    while let Some(cache) = unsafe { next.as_ref() } {
+        f(cache);
+        next = cache.next.load(Ordering::Acquire);
+    }
+}
+
+/// Shrinks every cache, see [`KmemCache::shrink`]. Returns the number of
+/// bytes released.
+pub fn shrink_caches() -> usize {
+    let mut released = 0;
+    for_each_cache(|cache| released += cache.shrink());
+    released
+}
+
+/// Gives back the empty slabs of the caches not in use, for the page
+/// allocator running out.
+pub(crate) fn reclaim() -> usize {
+    let mut released = 0;
+    for_each_cache(|cache| released += cache.try_shrink());
+    released
+}
+
+#[cfg(unittest)]
+#[allow(missing_docs)]
+pub mod tests_cache {
+    use alloc::{sync::Arc, vec::Vec};
+    use core::alloc::Layout;
+
+    use unittest::def_test;
+
+    use super::{KmemCache, arc_layout, for_each_cache};
+
+    static TEST_CACHE: KmemCache = KmemCache::new("test", arc_layout::<[u64; 5]>(), None);
+
+    #[def_test]
+    fn test_kmem_cache_arc() {
+        let values = (0..100u64)
+            .map(|i| Arc::new_in([i; 5], &TEST_CACHE))
+            .collect::<Vec<_>>();
+        assert_eq!(TEST_CACHE.stats().active_objects, 100);
+        assert!(values.iter().enumerate().all(|(i, v)| v[4] == i as u64));
+
+        let mut found = false;
+        for_each_cache(|cache| found |= cache.name() == "test");
+        assert!(found);
+
+        drop(values);
+        assert_eq!(TEST_CACHE.stats().active_objects, 0);
+        TEST_CACHE.shrink();
+        assert_eq!(TEST_CACHE.stats().slabs, 0);
+    }
+
+    #[def_test]
+    fn test_kmem_cache_fallback() {
+        // Too large for the cache, served by the heap
+        let big = Arc::new_in([0u64; 64], &TEST_CACHE);
+        assert_eq!(TEST_CACHE.stats().active_objects, 0);
+        drop(big);
+        assert!(TEST_CACHE.layout().size() >= Layout::new::<[u64; 7]>().size());
+    }
+}
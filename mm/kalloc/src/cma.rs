@@ -0,0 +1,341 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Contiguous memory allocator (CMA).
+//!
+//! A pool of memory is set aside at boot for large physically contiguous
+//! allocations, such as framebuffers or device firmware, which the page
+//! allocator may fail once fragmented. Until then, its pages are lent to the
+//! single page allocations the page allocator cannot satisfy, of the kinds a
+//! [`Migrator`] is registered for. A contiguous allocation has the lent
+//! pages it needs moved out of the pool first.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use kspin::SpinNoIrq;
+use strum::VariantArray;
+
+use crate::{PAGE_SIZE, UsageKind};
+
+/// A free page.
+const FREE: u8 = u8::MAX;
+/// A page of a contiguous allocation, or reserved for one.
+const TAKEN: u8 = u8::MAX - 1;
+/// Set on a lent page being moved out, along with its usage kind.
+const MIGRATING: u8 = 0x80;
+
+/// Moves the pages of one kind of movable allocations out of the CMA pool.
+pub trait Migrator: Send + Sync {
+    /// Moves the page at `from` to `to`, a page allocated for the same usage:
+    /// copies its content and has its owner use `to` instead, and free it
+    /// later in place of `from`. Returns `false` if the page cannot be moved
+    /// now.
+    ///
+    /// It runs in the context of the contiguous allocation, and must not
+    /// wait for locks the allocating task may hold, failing instead.
+    ///
+    /// # Safety
+    ///
+    /// `from` must be a page lent for the kind of the migrator, and `to` a
+    /// page allocated for it.
+    unsafe fn migrate(&self, from: usize, to: usize) -> bool;
+}
+
+static MIGRATORS: SpinNoIrq<Vec<(UsageKind, Arc<dyn Migrator>)>> = SpinNoIrq::new(Vec::new());
+
+/// Registers `migrator` for the pages allocated for `kind`, which can then
+/// be lent from the CMA pool.
+pub fn register_migrator(kind: UsageKind, migrator: Arc<dyn Migrator>) {
+    MIGRATORS.lock().push((kind, migrator));
+}
+
+pub(crate) fn migrator(kind: UsageKind) -> Option<Arc<dyn Migrator>> {
+    MIGRATORS
+        .lock()
+        .iter()
+        .find(|(it, _)| *it == kind)
+        .map(|(_, migrator)| migrator.clone())
+}
+
+/// Usage of the CMA pool, in pages.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CmaStats {
+    /// Pages of the pool.
+    pub total: usize,
+    /// Pages of contiguous allocations.
+    pub allocated: usize,
+    /// Pages lent to movable allocations.
+    pub lent: usize,
+}
+
+pub(crate) struct CmaPool {
+    /// Address of the first page handed out.
+    base: usize,
+    /// State of each page: [`FREE`], [`TAKEN`], or the kind it is lent for.
+    states: &'static mut [u8],
+    stats: CmaStats,
+    /// Where the search for a page to lend starts.
+    next: usize,
+}
+
+impl CmaPool {
+    /// Creates a pool of the pages in `[va, va + size)`, the first of which
+    /// hold the page states.
+    ///
+    /// # Safety
+    ///
+    /// The region must be mapped, and used by nothing else.
+    pub unsafe fn new(va: usize, size: usize) -> Option<Self> {
+        let count = size / PAGE_SIZE;
+        let meta_pages = count.div_ceil(PAGE_SIZE);
+        let pages = count.checked_sub(meta_pages).filter(|&n| n > 0)?;
+        let states = unsafe { core::slice::from_raw_parts_mut(va as *mut u8, pages) };
+        states.fill(FREE);
+        Some(Self {
+            base: va + meta_pages * PAGE_SIZE,
+            states,
+            stats: CmaStats {
+                total: pages,
+                ..Default::default()
+            },
+            next: 0,
+        })
+    }
+
+    pub fn stats(&self) -> CmaStats {
+        self.stats
+    }
+
+    /// Returns the index of the page at `va`, if in the pool.
+    pub fn index_of(&self, va: usize) -> Option<usize> {
+        let index = va.checked_sub(self.base)? / PAGE_SIZE;
+        (index < self.states.len()).then_some(index)
+    }
+
+    fn addr_of(&self, index: usize) -> usize {
+        self.base + index * PAGE_SIZE
+    }
+
+    /// Lends a free page for `kind`.
+    pub fn lend(&mut self, kind: UsageKind) -> Option<usize> {
+        let len = self.states.len();
+        let index = (self.next..len)
+            .chain(0..self.next)
+            .find(|&i| self.states[i] == FREE)?;
+        self.states[index] = kind as u8;
+        self.stats.lent += 1;
+        self.next = (index + 1) % len;
+        Some(self.addr_of(index))
+    }
+
+    /// Frees the `num_pages` pages at `va`, lent or allocated.
+    ///
+    /// A lent page being moved out stays reserved for the allocation moving
+    /// it.
+    pub fn free(&mut self, va: usize, num_pages: usize) {
+        let start = self
+            .index_of(va)
+            .expect("freeing pages out of the CMA pool");
+        for state in &mut self.states[start..start + num_pages] {
+            match *state {
+                FREE => panic!("freeing a free CMA page"),
+                TAKEN => {
+                    *state = FREE;
+                    self.stats.allocated -= 1;
+                }
+                kind if kind & MIGRATING != 0 => {
+                    *state = TAKEN;
+                    self.stats.lent -= 1;
+                    self.stats.allocated += 1;
+                }
+                _ => {
+                    *state = FREE;
+                    self.stats.lent -= 1;
+                }
+            }
+        }
+    }
+
+    /// Reserves the first run of `num_pages` pages aligned to `align_pow2`
+    /// from the page `from`, that no other allocation takes. The lent pages
+    /// of it are marked as being moved out.
+    fn reserve(&mut self, num_pages: usize, align_pow2: usize, from: usize) -> Option<usize> {
+        let mut start = from;
+        loop {
+            let misaligned = self.addr_of(start) % align_pow2;
+            if misaligned != 0 {
+                start += (align_pow2 - misaligned) / PAGE_SIZE;
+            }
+            let end = start.checked_add(num_pages)?;
+            if end > self.states.len() {
+                return None;
+            }
+            match (start..end).rev().find(|&i| {
+                let state = self.states[i];
+                state == TAKEN || (state != FREE && state & MIGRATING != 0)
+            }) {
+                Some(busy) => start = busy + 1,
+                None => break,
+            }
+        }
+        for state in &mut self.states[start..start + num_pages] {
+            *state = match *state {
+                FREE => {
+                    self.stats.allocated += 1;
+                    TAKEN
+                }
+                kind => kind | MIGRATING,
+            };
+        }
+        Some(start)
+    }
+
+    /// Gives up the run reserved at `start`, but for the lent pages not
+    /// moved out yet.
+    fn unreserve(&mut self, start: usize, num_pages: usize) {
+        for state in &mut self.states[start..start + num_pages] {
+            *state = match *state {
+                TAKEN => {
+                    self.stats.allocated -= 1;
+                    FREE
+                }
+                kind => kind & !MIGRATING,
+            };
+        }
+    }
+
+    /// Returns the kind of the lent page `index` being moved out, if it is
+    /// still lent.
+    fn migrating(&self, index: usize) -> Option<UsageKind> {
+        let state = self.states[index];
+        if state == TAKEN || state & MIGRATING == 0 {
+            return None;
+        }
+        UsageKind::VARIANTS
+            .get((state & !MIGRATING) as usize)
+            .copied()
+    }
+
+    /// Takes the lent page `index` back, once moved out.
+    fn migrated(&mut self, index: usize) {
+        self.states[index] = TAKEN;
+        self.stats.lent -= 1;
+        self.stats.allocated += 1;
+    }
+}
+
+/// Allocates `num_pages` contiguous pages aligned to `align_pow2` from the
+/// pool, moving the pages lent out of the way with `migrate`, called with
+/// the kind and address of each, and the lock of the pool released.
+pub(crate) fn alloc_contiguous(
+    pool: &SpinNoIrq<Option<CmaPool>>,
+    num_pages: usize,
+    align_pow2: usize,
+    mut migrate: impl FnMut(UsageKind, usize) -> bool,
+) -> Option<usize> {
+    let mut from = 0;
+    loop {
+        let start = pool.lock().as_mut()?.reserve(num_pages, align_pow2, from)?;
+        let mut failed = None;
+        for index in start..start + num_pages {
+            let (kind, va) = {
+                let pool = pool.lock();
+                let pool = pool.as_ref().unwrap();
+                match pool.migrating(index) {
+                    Some(kind) => (kind, pool.addr_of(index)),
+                    None => continue,
+                }
+            };
+            let moved = migrate(kind, va);
+            let mut pool = pool.lock();
+            let pool = pool.as_mut().unwrap();
+            if moved {
+                pool.migrated(index);
+            } else if pool.migrating(index).is_some() {
+                failed = Some(index);
+                break;
+            }
+            // Otherwise freed by its owner meanwhile.
+        }
+        let mut pool = pool.lock();
+        let pool = pool.as_mut().unwrap();
+        match failed {
+            None => return Some(pool.addr_of(start)),
+            Some(index) => {
+                debug!("CMA: failed to migrate page {:#x}", pool.addr_of(index));
+                pool.unreserve(start, num_pages);
+                from = index + 1;
+            }
+        }
+    }
+}
+
+#[cfg(unittest)]
+mod tests_cma {
+    use alloc::alloc::{alloc, dealloc};
+    use core::alloc::Layout;
+
+    use kspin::SpinNoIrq;
+    use unittest::def_test;
+
+    use super::{CmaPool, PAGE_SIZE, alloc_contiguous};
+    use crate::UsageKind;
+
+    const POOL_PAGES: usize = 17;
+
+    fn with_pool(f: impl FnOnce(&SpinNoIrq<Option<CmaPool>>)) {
+        let layout = Layout::from_size_align(POOL_PAGES * PAGE_SIZE, PAGE_SIZE).unwrap();
+        let va = unsafe { alloc(layout) } as usize;
+        let pool = SpinNoIrq::new(unsafe { CmaPool::new(va, POOL_PAGES * PAGE_SIZE) });
+        f(&pool);
+        unsafe { dealloc(va as *mut u8, layout) };
+    }
+
+    #[def_test]
+    fn test_cma_lend_and_allocate() {
+        with_pool(|pool| {
+            let lent = pool.lock().as_mut().unwrap().lend(UsageKind::PageCache);
+            let lent = lent.unwrap();
+            assert_eq!(pool.lock().as_ref().unwrap().stats().total, POOL_PAGES - 1);
+
+            // The lent page is moved out of the way
+            let mut moved = alloc::vec::Vec::new();
+            let addr = alloc_contiguous(pool, 4, PAGE_SIZE, |kind, va| {
+                moved.push((kind, va));
+                true
+            });
+            assert_eq!(addr, Some(lent));
+            assert_eq!(moved, [(UsageKind::PageCache, lent)]);
+            let stats = pool.lock().as_ref().unwrap().stats();
+            assert_eq!((stats.allocated, stats.lent), (4, 0));
+
+            pool.lock().as_mut().unwrap().free(lent, 4);
+            assert_eq!(pool.lock().as_ref().unwrap().stats().allocated, 0);
+        });
+    }
+
+    #[def_test]
+    fn test_cma_migration_failure() {
+        with_pool(|pool| {
+            let first = pool.lock().as_mut().unwrap().lend(UsageKind::PageCache);
+            let first = first.unwrap();
+
+            // The run past the page that cannot move is taken instead
+            let addr = alloc_contiguous(pool, 8, PAGE_SIZE, |_, va| va != first);
+            assert_eq!(addr, Some(first + PAGE_SIZE));
+            let stats = pool.lock().as_ref().unwrap().stats();
+            assert_eq!((stats.allocated, stats.lent), (8, 1));
+
+            // No room for another run
+            assert!(alloc_contiguous(pool, 8, PAGE_SIZE, |_, _| true).is_none());
+            assert_eq!(pool.lock().as_ref().unwrap().stats().allocated, 8);
+
+            let mut pool = pool.lock();
+            let pool = pool.as_mut().unwrap();
+            pool.free(first, 1);
+            pool.free(first + PAGE_SIZE, 8);
+            assert_eq!(pool.stats().lent + pool.stats().allocated, 0);
+        });
+    }
+}
@@ -0,0 +1,490 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Kernel address sanitizer: detection of out-of-bounds accesses and uses
+//! after free of the heap and the pages of the allocator.
+//!
+//! Each 8-byte granule of the memory given to the allocator has a shadow
+//! byte, kept at the end of its region: 0 if the granule is accessible, 1
+//! to 7 if only that many first bytes are, and a poison code otherwise.
+//! Heap objects are followed by a redzone, freed ones are poisoned and held
+//! in a quarantine for a while before being reused, and free pages are
+//! poisoned.
+//!
+//! Accesses are checked by the `__asan_*` hooks the compiler calls when
+//! building with `-Zsanitizer=kernel-address` in outline mode, as
+//! `make KASAN=y` does. [`kasan_check`] checks a range by hand, e.g. one
+//! written by a device or by assembly. Violations are logged with a
+//! backtrace, and execution goes on.
+
+use core::{
+    alloc::Layout,
+    cell::UnsafeCell,
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use kspin::{NoPreempt, SpinNoIrq};
+
+use crate::PAGE_SIZE;
+
+/// Bytes of memory a shadow byte stands for.
+const GRANULE: usize = 8;
+/// Regions with a shadow at most; later ones are not checked.
+const MAX_REGIONS: usize = 16;
+/// Freed objects held back at most.
+const QUARANTINE_LEN: usize = 1024;
+/// Bytes of freed objects held back at most.
+const QUARANTINE_BYTES: usize = 1024 * 1024;
+/// Violations logged at most, to keep a faulty loop from flooding the log.
+const MAX_REPORTS: usize = 32;
+
+/// Free page.
+pub(crate) const PAGE_FREE: u8 = 0xff;
+/// Heap memory not handed out yet, or reused after the quarantine.
+pub(crate) const HEAP_FREE: u8 = 0xfe;
+/// Past the end of a heap object.
+const HEAP_REDZONE: u8 = 0xfc;
+/// Freed heap object.
+const HEAP_FREED: u8 = 0xfb;
+
+#[derive(Clone, Copy)]
+struct Region {
+    start: usize,
+    end: usize,
+    shadow: usize,
+}
+
+/// The regions with a shadow, only ever appended to.
+struct Regions {
+    len: AtomicUsize,
+    slots: UnsafeCell<[Region; MAX_REGIONS]>,
+}
+
+// SAFETY: slots are written under `ADD_REGION` before `len` covers them.
+unsafe impl Sync for Regions {}
+
+static REGIONS: Regions = Regions {
+    len: AtomicUsize::new(0),
+    slots: UnsafeCell::new(
+        [Region {
+            start: 0,
+            end: 0,
+            shadow: 0,
+        }; MAX_REGIONS],
+    ),
+};
+static ADD_REGION: SpinNoIrq<()> = SpinNoIrq::new(());
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static REPORTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Nesting of [`Unchecked`] scopes on this CPU.
+#[percpu::def_percpu]
+static UNCHECKED: usize = 0;
+
+/// Turns the checks off on this CPU while alive, for the allocator to use
+/// the poisoned memory it manages.
+pub(crate) struct Unchecked(#[allow(dead_code)] NoPreempt);
+
+impl Unchecked {
+    pub(crate) fn new() -> Self {
+        let guard = NoPreempt::new();
+        // SAFETY: preemption is disabled.
+        unsafe { UNCHECKED.write_current_raw(UNCHECKED.read_current_raw() + 1) };
+        Self(guard)
+    }
+}
+
+impl Drop for Unchecked {
+    fn drop(&mut self) {
+        // SAFETY: preemption is still disabled.
+        unsafe { UNCHECKED.write_current_raw(UNCHECKED.read_current_raw() - 1) };
+    }
+}
+
+/// Freed objects waiting to be given back, oldest first.
+struct Quarantine {
+    /// Address, size and alignment of the objects, as allocated.
+    objects: [(usize, usize, usize); QUARANTINE_LEN],
+    head: usize,
+    len: usize,
+    bytes: usize,
+}
+
+impl Quarantine {
+    fn push(&mut self, addr: usize, layout: Layout) {
+        let tail = (self.head + self.len) % QUARANTINE_LEN;
+        self.objects[tail] = (addr, layout.size(), layout.align());
+        self.len += 1;
+        self.bytes += layout.size();
+    }
+
+    fn pop(&mut self) -> (NonNull<u8>, Layout) {
+        let (addr, size, align) = self.objects[self.head];
+        self.head = (self.head + 1) % QUARANTINE_LEN;
+        self.len -= 1;
+        self.bytes -= size;
+        // SAFETY: the layout was valid when pushed.
+        let layout = unsafe { Layout::from_size_align_unchecked(size, align) };
+        (NonNull::new(addr as *mut u8).unwrap(), layout)
+    }
+
+    /// Returns the object holding `addr`, if any.
+    fn find(&self, addr: usize) -> Option<(usize, usize)> {
+        (0..self.len)
+            .map(|i| self.objects[(self.head + i) % QUARANTINE_LEN])
+            .find(|&(start, size, _)| (start..start + size).contains(&addr))
+            .map(|(start, size, _)| (start, size))
+    }
+}
+
+static QUARANTINE: SpinNoIrq<Quarantine> = SpinNoIrq::new(Quarantine {
+    objects: [(0, 0, 0); QUARANTINE_LEN],
+    head: 0,
+    len: 0,
+    bytes: 0,
+});
+
+/// Takes the shadow of `[va, va + size)` from its end and fills it with
+/// `code`. Returns the size left to the allocator.
+///
+/// The checks start with the first region.
+pub(crate) fn add_region(va: usize, size: usize, code: u8) -> usize {
+    let shadow_size = size.div_ceil(GRANULE + 1).next_multiple_of(PAGE_SIZE);
+    let covered = size.saturating_sub(shadow_size) & !(PAGE_SIZE - 1);
+    let _lock = ADD_REGION.lock();
+    let len = REGIONS.len.load(Ordering::Relaxed);
+    if len == MAX_REGIONS || covered == 0 {
+        warn!("KASAN: region [{:#x}, {:#x}) not checked", va, va + size);
+        return size;
+    }
+    let region = Region {
+        start: va,
+        end: va + covered,
+        shadow: va + covered,
+    };
+    // SAFETY: the shadow is part of the region, which is ours, and the slot
+    // is not visible before `len` covers it.
+    unsafe {
+        core::ptr::write_bytes(region.shadow as *mut u8, code, covered / GRANULE);
+        (*REGIONS.slots.get())[len] = region;
+    }
+    REGIONS.len.store(len + 1, Ordering::Release);
+    ENABLED.store(true, Ordering::Release);
+    info!(
+        "KASAN: checking [{:#x}, {:#x}), shadow of {} KiB",
+        va,
+        va + covered,
+        shadow_size / 1024
+    );
+    covered
+}
+
+/// Returns the region holding `addr`.
+#[sanitize(address = "off")]
+#[inline]
+fn region_of(addr: usize) -> Option<Region> {
+    let len = REGIONS.len.load(Ordering::Acquire);
+    let slots = REGIONS.slots.get() as *const Region;
+    let mut i = 0;
+    while i < len {
+        // SAFETY: the first `len` slots are written.
+        let region = unsafe { *slots.add(i) };
+        if addr >= region.start && addr < region.end {
+            return Some(region);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Sets the shadow of `[addr, addr + size)`, granules the range begins in,
+/// to `code`.
+fn fill(addr: usize, size: usize, code: u8) {
+    let Some(region) = region_of(addr) else {
+        return;
+    };
+    let first = (addr - region.start) / GRANULE;
+    let end = (addr + size).min(region.end) - region.start;
+    let count = end.div_ceil(GRANULE).saturating_sub(first);
+    // SAFETY: the granules lie in the region, and so their shadow.
+    unsafe { core::ptr::write_bytes((region.shadow + first) as *mut u8, code, count) };
+}
+
+/// Poisons `[addr, addr + size)`, with `addr` aligned to a granule.
+pub(crate) fn poison(addr: usize, size: usize, code: u8) {
+    fill(addr, size, code);
+}
+
+/// Makes `[addr, addr + size)` accessible, with `addr` aligned to a granule.
+pub(crate) fn unpoison(addr: usize, size: usize) {
+    fill(addr, size, 0);
+    let partial = size % GRANULE;
+    if partial != 0 {
+        fill(addr + size - partial, 1, partial as u8);
+    }
+}
+
+/// Returns the layout to allocate for an object of `layout`, with room for
+/// its redzone.
+pub(crate) fn outer_layout(layout: Layout) -> Option<Layout> {
+    let size = layout.size().next_multiple_of(GRANULE);
+    let redzone = (size / 4).clamp(16, 4096).next_multiple_of(GRANULE);
+    Layout::from_size_align(size + redzone, layout.align().max(GRANULE)).ok()
+}
+
+/// Makes an object of `size` bytes at `ptr` accessible, and the rest of the
+/// `outer` bytes allocated for it its redzone.
+pub(crate) fn unpoison_object(ptr: NonNull<u8>, size: usize, outer: Layout) {
+    let addr = ptr.as_ptr() as usize;
+    let inner = size.next_multiple_of(GRANULE);
+    unpoison(addr, size);
+    poison(addr + inner, outer.size() - inner, HEAP_REDZONE);
+}
+
+/// Poisons a freed object of `outer` bytes and holds it back, giving the
+/// oldest ones to `free` once the quarantine is full.
+pub(crate) fn quarantine(
+    ptr: NonNull<u8>,
+    outer: Layout,
+    mut free: impl FnMut(NonNull<u8>, Layout),
+) {
+    poison(ptr.as_ptr() as usize, outer.size(), HEAP_FREED);
+    let mut quarantine = QUARANTINE.lock();
+    while quarantine.len > 0
+        && (quarantine.len == QUARANTINE_LEN || quarantine.bytes + outer.size() > QUARANTINE_BYTES)
+    {
+        let (ptr, layout) = quarantine.pop();
+        free(ptr, layout);
+    }
+    quarantine.push(ptr.as_ptr() as usize, outer);
+}
+
+/// Checks an access of `size` bytes at `addr`.
+#[sanitize(address = "off")]
+#[inline]
+fn check(addr: usize, size: usize, write: bool) {
+    if size == 0 || !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(region) = region_of(addr) else {
+        return;
+    };
+    let last = (addr + (size - 1)).min(region.end - 1);
+    let mut granule = addr & !(GRANULE - 1);
+    while granule <= last {
+        // SAFETY: the granule lies in the region, and so its shadow.
+        let value = unsafe { *((region.shadow + (granule - region.start) / GRANULE) as *const u8) };
+        if value != 0 {
+            let end = last.min(granule + GRANULE - 1) - granule;
+            if value as usize >= GRANULE || end >= value as usize {
+                let bad = if value as usize >= GRANULE {
+                    granule.max(addr)
+                } else {
+                    (granule + value as usize).max(addr)
+                };
+                report(addr, size, write, bad, value);
+                return;
+            }
+        }
+        granule += GRANULE;
+    }
+}
+
+/// Logs a bad access, unless the allocator made it.
+#[sanitize(address = "off")]
+#[cold]
+#[inline(never)]
+fn report(addr: usize, size: usize, write: bool, bad: usize, value: u8) {
+    // SAFETY: reading the counter of this CPU.
+    if unsafe { UNCHECKED.read_current_raw() } != 0 {
+        return;
+    }
+    let _unchecked = Unchecked::new();
+    if REPORTS.fetch_add(1, Ordering::Relaxed) >= MAX_REPORTS {
+        return;
+    }
+    let code = match value as usize {
+        // Past a partially accessible granule, into what follows
+        1..GRANULE => region_of(bad + GRANULE - 1)
+            .map(|region| {
+                let index = (bad + GRANULE - 1 - region.start) / GRANULE;
+                // SAFETY: the granule lies in the region.
+                unsafe { *((region.shadow + index) as *const u8) }
+            })
+            .unwrap_or(HEAP_REDZONE),
+        _ => value,
+    };
+    let kind = match code {
+        PAGE_FREE => "use-after-free on a page",
+        HEAP_FREED => "use-after-free",
+        HEAP_FREE => "wild access to free heap",
+        _ => "out-of-bounds",
+    };
+    error!("==================================================================");
+    error!("BUG: KASAN: {} at {:#x}", kind, bad);
+    error!(
+        "{} of size {} at {:#x}",
+        if write { "Write" } else { "Read" },
+        size,
+        addr
+    );
+    if let Some((start, object_size)) = QUARANTINE.try_lock().and_then(|q| q.find(bad)) {
+        error!(
+            "The address is {} bytes into a freed region of {} bytes at {:#x}",
+            bad - start,
+            object_size,
+            start
+        );
+    }
+    if let Some(region) = region_of(bad) {
+        let index = (bad - region.start) / GRANULE;
+        let first = index.saturating_sub(8);
+        let last = (index + 8).min((region.end - region.start) / GRANULE);
+        let mut line = alloc::string::String::new();
+        for i in first..last {
+            // SAFETY: the granules lie in the region.
+            let value = unsafe { *((region.shadow + i) as *const u8) };
+            let mark = if i == index { '[' } else { ' ' };
+            line += &alloc::format!("{mark}{value:02x}");
+        }
+        error!("Shadow bytes around {:#x}: {}", bad, line);
+    }
+    error!("{}", backtrace::Backtrace::capture());
+    error!("==================================================================");
+}
+
+/// Checks an access of `size` bytes at `addr` made without instrumentation,
+/// reporting it if bad.
+pub fn kasan_check(addr: usize, size: usize, write: bool) {
+    check(addr, size, write);
+}
+
+/// Returns the number of bad accesses found so far.
+pub fn kasan_reports() -> usize {
+    REPORTS.load(Ordering::Relaxed)
+}
+
+macro_rules! sized_hooks {
+    ($($size:literal => $load:ident, $load_noabort:ident, $store:ident, $store_noabort:ident;)*) => {$(
+        #[sanitize(address = "off")]
+        #[unsafe(no_mangle)]
+        extern "C" fn $load(addr: usize) {
+            check(addr, $size, false);
+        }
+
+        #[sanitize(address = "off")]
+        #[unsafe(no_mangle)]
+        extern "C" fn $load_noabort(addr: usize) {
+            check(addr, $size, false);
+        }
+
+        #[sanitize(address = "off")]
+        #[unsafe(no_mangle)]
+        extern "C" fn $store(addr: usize) {
+            check(addr, $size, true);
+        }
+
+        #[sanitize(address = "off")]
+        #[unsafe(no_mangle)]
+        extern "C" fn $store_noabort(addr: usize) {
+            check(addr, $size, true);
+        }
+    )*};
+}
+
+sized_hooks! {
+    1 => __asan_load1, __asan_load1_noabort, __asan_store1, __asan_store1_noabort;
+    2 => __asan_load2, __asan_load2_noabort, __asan_store2, __asan_store2_noabort;
+    4 => __asan_load4, __asan_load4_noabort, __asan_store4, __asan_store4_noabort;
+    8 => __asan_load8, __asan_load8_noabort, __asan_store8, __asan_store8_noabort;
+    16 => __asan_load16, __asan_load16_noabort, __asan_store16, __asan_store16_noabort;
+}
+
+#[sanitize(address = "off")]
+#[unsafe(no_mangle)]
+extern "C" fn __asan_loadN(addr: usize, size: usize) {
+    check(addr, size, false);
+}
+
+#[sanitize(address = "off")]
+#[unsafe(no_mangle)]
+extern "C" fn __asan_loadN_noabort(addr: usize, size: usize) {
+    check(addr, size, false);
+}
+
+#[sanitize(address = "off")]
+#[unsafe(no_mangle)]
+extern "C" fn __asan_storeN(addr: usize, size: usize) {
+    check(addr, size, true);
+}
+
+#[sanitize(address = "off")]
+#[unsafe(no_mangle)]
+extern "C" fn __asan_storeN_noabort(addr: usize, size: usize) {
+    check(addr, size, true);
+}
+
+#[sanitize(address = "off")]
+#[unsafe(no_mangle)]
+extern "C" fn __asan_handle_no_return() {}
+
+#[sanitize(address = "off")]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn __asan_memcpy(dst: *mut u8, src: *const u8, len: usize) -> *mut u8 {
+    check(src as usize, len, false);
+    check(dst as usize, len, true);
+    // SAFETY: the caller is `memcpy`'s.
+    unsafe { core::ptr::copy_nonoverlapping(src, dst, len) };
+    dst
+}
+
+#[sanitize(address = "off")]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn __asan_memmove(dst: *mut u8, src: *const u8, len: usize) -> *mut u8 {
+    check(src as usize, len, false);
+    check(dst as usize, len, true);
+    // SAFETY: the caller is `memmove`'s.
+    unsafe { core::ptr::copy(src, dst, len) };
+    dst
+}
+
+#[sanitize(address = "off")]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn __asan_memset(dst: *mut u8, value: i32, len: usize) -> *mut u8 {
+    check(dst as usize, len, true);
+    // SAFETY: the caller is `memset`'s.
+    unsafe { core::ptr::write_bytes(dst, value as u8, len) };
+    dst
+}
+
+#[cfg(unittest)]
+#[allow(missing_docs)]
+pub mod tests_kasan {
+    use core::alloc::Layout;
+
+    use unittest::def_test;
+
+    use super::{kasan_check, kasan_reports};
+    use crate::global_allocator;
+
+    #[def_test]
+    fn test_kasan_redzone_and_use_after_free() {
+        let layout = Layout::from_size_align(13, 1).unwrap();
+        let ptr = global_allocator().alloc(layout).unwrap();
+        let addr = ptr.as_ptr() as usize;
+        let reports = kasan_reports();
+        kasan_check(addr, 13, true);
+        assert_eq!(kasan_reports(), reports);
+
+        // One byte into the redzone
+        kasan_check(addr + 12, 2, false);
+        assert_eq!(kasan_reports(), reports + 1);
+
+        // Still in the quarantine
+        global_allocator().dealloc(ptr, layout);
+        kasan_check(addr, 1, false);
+        assert_eq!(kasan_reports(), reports + 2);
+    }
+}
@@ -4,6 +4,8 @@
 
 //! Kernel global allocator and page allocation helpers.
 #![no_std]
+#![feature(allocator_api)]
+#![cfg_attr(feature = "kasan", feature(sanitize))]
 
 #[macro_use]
 extern crate log;
@@ -19,16 +21,44 @@ use core::{
 };
 
 #[allow(unused_imports)]
-use alloc_engine::{AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator};
+use alloc_engine::{
+    AllocError, AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator,
+};
+use knotifier::{AtomicNotifierChain, NotifyResult};
 use kspin::SpinNoIrq;
 use strum::{IntoStaticStr, VariantArray};
 
 const PAGE_SIZE: usize = 0x1000;
 const MIN_HEAP_SIZE: usize = 0x8000; // 32 K
 
+mod cache;
+pub use alloc_engine::CacheStats;
+pub use cache::{KmemCache, arc_layout, for_each_cache, shrink_caches};
+
+mod cma;
+pub use cma::{CmaStats, Migrator, register_migrator};
+
 mod page;
 pub use page::GlobalPage;
 
+mod shrinker;
+pub use shrinker::{
+    LOW_MEMORY, ShrinkControl, Shrinker, ShrinkerId, clear_low_memory, is_low_memory,
+    low_watermark, register_shrinker, set_low_watermark, shrink_memory, shrinker_counts,
+    unregister_shrinker,
+};
+
+mod tags;
+pub use tags::{HeapTag, HeapTagGuard, TagUsage, heap_tag_usage, reset_heap_tag_peaks};
+
+#[cfg(feature = "kasan")]
+mod kasan;
+#[cfg(feature = "kasan")]
+pub use kasan::{kasan_check, kasan_reports};
+
+#[cfg(feature = "pcpu-cache")]
+mod pcpu_cache;
+
 #[cfg(feature = "tracking")]
 mod tracking;
 #[cfg(feature = "tracking")]
@@ -63,8 +93,30 @@ pub enum UsageKind {
     Dma,
     /// Memory used by [`GlobalPage`].
     Global,
+    /// Pages handed to the hypervisor through a memory balloon.
+    Balloon,
+    /// Slabs of the object caches, see [`KmemCache`].
+    Slab,
+}
+
+/// A page allocation failing, as raised on [`MEMORY_PRESSURE`].
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryPressure {
+    /// Number of pages asked for.
+    pub num_pages: usize,
+    /// What the pages were for.
+    pub kind: UsageKind,
 }
 
+/// Notified when the page allocator runs out, for subsystems to give pages
+/// back. The empty slabs of the object caches are given back first, then the
+/// [shrinkers](Shrinker) are asked for memory.
+///
+/// The callbacks run in the context of the allocation, and must not
+/// allocate pages themselves. It is retried once if one of them acted on
+/// the event.
+pub static MEMORY_PRESSURE: AtomicNotifierChain<MemoryPressure> = AtomicNotifierChain::new();
+
 /// Statistics of memory usage by category.
 #[derive(Clone, Copy)]
 pub struct Usages([usize; UsageKind::VARIANTS.len()]);
@@ -115,6 +167,7 @@ pub struct GlobalAllocator {
     #[cfg(not(feature = "level-1"))]
     palloc: SpinNoIrq<BitmapPageAllocator<PAGE_SIZE>>,
     dma_palloc: SpinNoIrq<BitmapPageAllocator<PAGE_SIZE>>,
+    cma: SpinNoIrq<Option<cma::CmaPool>>,
     usages: SpinNoIrq<Usages>,
 }
 
@@ -132,6 +185,7 @@ impl GlobalAllocator {
             #[cfg(not(feature = "level-1"))]
             palloc: SpinNoIrq::new(BitmapPageAllocator::new()),
             dma_palloc: SpinNoIrq::new(BitmapPageAllocator::new()),
+            cma: SpinNoIrq::new(None),
             usages: SpinNoIrq::new(Usages::new()),
         }
     }
@@ -158,6 +212,8 @@ impl GlobalAllocator {
         assert!(size > MIN_HEAP_SIZE);
         #[cfg(not(feature = "level-1"))]
         {
+            #[cfg(feature = "kasan")]
+            let size = kasan::add_region(va, size, kasan::PAGE_FREE);
             let heap_size = MIN_HEAP_SIZE;
             self.palloc.lock().init_region(va, size);
             let heap_addr = self
@@ -165,9 +221,16 @@ impl GlobalAllocator {
                 .unwrap();
 
             self.balloc.lock().init_region(heap_addr, heap_size);
+            #[cfg(feature = "kasan")]
+            kasan::poison(heap_addr, heap_size, kasan::HEAP_FREE);
         }
         #[cfg(feature = "level-1")]
         {
+            #[cfg(feature = "kasan")]
+            let (size, _unchecked) = (
+                kasan::add_region(va, size, kasan::HEAP_FREE),
+                kasan::Unchecked::new(),
+            );
             self.balloc.lock().init_region(va, size);
         }
     }
@@ -176,20 +239,65 @@ impl GlobalAllocator {
         self.dma_palloc.lock().init_region(va, size);
     }
 
+    /// Sets the given region aside as the CMA pool, see
+    /// [`alloc_cma_pages`](Self::alloc_cma_pages).
+    ///
+    /// The region must not be given to the allocator otherwise.
+    pub fn init_cma(&self, va: usize, size: usize) {
+        let pool = unsafe { cma::CmaPool::new(va, size) };
+        if pool.is_none() {
+            warn!("CMA pool of {size:#x} bytes is too small");
+        }
+        *self.cma.lock() = pool;
+    }
+
     /// Add the given region to the allocator.
     ///
     /// It will add the whole region to the byte allocator.
     pub fn add_memory(&self, va: usize, size: usize) -> AllocResult {
+        #[cfg(feature = "kasan")]
+        let (size, _unchecked) = (
+            kasan::add_region(va, size, kasan::HEAP_FREE),
+            kasan::Unchecked::new(),
+        );
         self.balloc.lock().add_region(va, size)
     }
 
     /// Allocate arbitrary number of bytes. Returns the left bound of the
     /// allocated region.
     ///
-    /// It firstly tries to allocate from the byte allocator. If there is no
-    /// memory, it asks the page allocator for more memory and adds it to the
-    /// byte allocator.
+    /// With the `pcpu-cache` feature, small allocations are served from the
+    /// cache of the current CPU first. Otherwise it tries to allocate from the
+    /// byte allocator. If there is no memory, it asks the page allocator for
+    /// more memory and adds it to the byte allocator.
+    ///
+    /// With the `kasan` feature, the block is followed by a redzone.
     pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        #[cfg(feature = "kasan")]
+        {
+            let outer = kasan::outer_layout(layout).ok_or(AllocError::InvalidInput)?;
+            let ptr = {
+                let _unchecked = kasan::Unchecked::new();
+                self.alloc_cached(outer)?
+            };
+            kasan::unpoison_object(ptr, layout.size(), outer);
+            Ok(ptr)
+        }
+        #[cfg(not(feature = "kasan"))]
+        self.alloc_cached(layout)
+    }
+
+    /// Allocates from the per-CPU caches, or the byte allocator.
+    fn alloc_cached(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        #[cfg(feature = "pcpu-cache")]
+        if let Some(class) = pcpu_cache::SizeClass::of(layout) {
+            return pcpu_cache::alloc(self, class);
+        }
+        self.alloc_bytes(layout)
+    }
+
+    /// Allocates from the byte allocator, bypassing the per-CPU caches.
+    fn alloc_bytes(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
         #[cfg(feature = "level-1")]
         {
             self.alloc_level1(layout)
@@ -213,7 +321,8 @@ impl GlobalAllocator {
     fn alloc_level2(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
         // simple two-level allocator: if no heap memory, allocate from the page allocator.
         let mut balloc = self.balloc.lock();
-        loop {
+        let mut reclaimed = false;
+        'retry: loop {
             if let Ok(ptr) = balloc.allocate(layout) {
                 self.usages.lock().alloc(UsageKind::RustHeap, layout.size());
                 return Ok(ptr);
@@ -236,7 +345,19 @@ impl GlobalAllocator {
                         Err(err) => {
                             req_size /= 2;
                             if req_size < min_size {
-                                return Err(err);
+                                if reclaimed {
+                                    return Err(err);
+                                }
+                                // The shrinkers free heap memory, which needs
+                                // the lock
+                                reclaimed = true;
+                                drop(balloc);
+                                let freed = shrinker::reclaim(min_size / PAGE_SIZE);
+                                balloc = self.balloc.lock();
+                                if freed == 0 {
+                                    return Err(err);
+                                }
+                                continue 'retry;
                             }
                             continue;
                         }
@@ -247,6 +368,8 @@ impl GlobalAllocator {
                         heap_addr + req_size
                     );
                     balloc.add_region(heap_addr, req_size)?;
+                    #[cfg(feature = "kasan")]
+                    kasan::poison(heap_addr, req_size, kasan::HEAP_FREE);
                     break;
                 }
             }
@@ -259,8 +382,27 @@ impl GlobalAllocator {
     /// the same as the one used in [`alloc`]. Otherwise, the behavior is
     /// undefined.
     ///
+    /// With the `kasan` feature, the region is poisoned and held in a
+    /// quarantine for a while before being given back.
+    ///
     /// [`alloc`]: GlobalAllocator::alloc
     pub fn dealloc(&self, ptr: NonNull<u8>, layout: Layout) {
+        #[cfg(feature = "kasan")]
+        {
+            let outer = kasan::outer_layout(layout).unwrap();
+            let _unchecked = kasan::Unchecked::new();
+            kasan::quarantine(ptr, outer, |ptr, layout| self.dealloc_cached(ptr, layout));
+        }
+        #[cfg(not(feature = "kasan"))]
+        self.dealloc_cached(ptr, layout)
+    }
+
+    /// Gives back to the per-CPU caches, or the byte allocator.
+    fn dealloc_cached(&self, ptr: NonNull<u8>, layout: Layout) {
+        #[cfg(feature = "pcpu-cache")]
+        if let Some(class) = pcpu_cache::SizeClass::of(layout) {
+            return pcpu_cache::dealloc(self, ptr, class);
+        }
         self.usages
             .lock()
             .dealloc(UsageKind::RustHeap, layout.size());
@@ -282,22 +424,69 @@ impl GlobalAllocator {
         #[cfg(feature = "level-1")]
         {
             // single-level allocator: allocate from the byte allocator.
+            #[cfg(feature = "kasan")]
+            let _unchecked = kasan::Unchecked::new();
             let mut balloc = self.balloc.lock();
             let layout = Layout::from_size_align(num_pages * PAGE_SIZE, align_pow2).unwrap();
             let ptr = balloc.allocate(layout)?;
             self.usages.lock().alloc(kind, num_pages * PAGE_SIZE);
+            #[cfg(feature = "kasan")]
+            kasan::unpoison(ptr.as_ptr() as usize, num_pages * PAGE_SIZE);
             Ok(ptr.as_ptr() as usize)
         }
         #[cfg(not(feature = "level-1"))]
         {
-            let addr = self.palloc.lock().allocate_pages(num_pages, align_pow2)?;
+            let result = self.palloc.lock().allocate_pages(num_pages, align_pow2);
+            let addr = match result {
+                Ok(addr) => addr,
+                // The heap grows with its lock held, which the callbacks may
+                // need to free memory
+                Err(err) if matches!(kind, UsageKind::RustHeap) => return Err(err),
+                Err(err) => {
+                    if let Some(addr) = self.lend_cma_page(num_pages, align_pow2, kind) {
+                        self.usages.lock().alloc(kind, PAGE_SIZE);
+                        return Ok(addr);
+                    }
+                    let retry = || self.palloc.lock().allocate_pages(num_pages, align_pow2);
+                    let freed = cache::reclaim() + shrinker::reclaim(num_pages);
+                    match (freed > 0).then(retry) {
+                        Some(Ok(addr)) => addr,
+                        _ => {
+                            let pressure = MemoryPressure { num_pages, kind };
+                            if MEMORY_PRESSURE.call(&pressure) == NotifyResult::Done {
+                                return Err(err);
+                            }
+                            retry()?
+                        }
+                    }
+                }
+            };
             if !matches!(kind, UsageKind::RustHeap) {
                 self.usages.lock().alloc(kind, num_pages * PAGE_SIZE);
+                shrinker::check_watermark(|| self.available_pages());
             }
+            #[cfg(feature = "kasan")]
+            kasan::unpoison(addr, num_pages * PAGE_SIZE);
             Ok(addr)
         }
     }
 
+    /// Allocates `num_pages` pages of `page_size` bytes, such as 2M or 1G
+    /// huge pages, each aligned to its size.
+    ///
+    /// `page_size` must be a power of 2 and a multiple of 4K.
+    pub fn alloc_sized_pages(
+        &self,
+        num_pages: usize,
+        page_size: usize,
+        kind: UsageKind,
+    ) -> AllocResult<usize> {
+        if !page_size.is_power_of_two() || page_size < PAGE_SIZE {
+            return Err(AllocError::InvalidInput);
+        }
+        self.alloc_pages(num_pages * (page_size / PAGE_SIZE), page_size, kind)
+    }
+
     /// Allocates contiguous DMA pages.
     pub fn alloc_dma_pages(
         &self,
@@ -315,6 +504,73 @@ impl GlobalAllocator {
         Ok(addr)
     }
 
+    /// Lends a page of the CMA pool for an allocation of `kind`, if it is a
+    /// single page and can be moved out.
+    #[cfg(not(feature = "level-1"))]
+    fn lend_cma_page(&self, num_pages: usize, align_pow2: usize, kind: UsageKind) -> Option<usize> {
+        if num_pages != 1 || align_pow2 > PAGE_SIZE || cma::migrator(kind).is_none() {
+            return None;
+        }
+        self.cma.lock().as_mut()?.lend(kind)
+    }
+
+    /// Allocates contiguous pages from the CMA pool, for large buffers such
+    /// as framebuffers or device firmware.
+    ///
+    /// The pages lent from the pool to movable allocations are moved out
+    /// first, which takes the [`Migrator`]s of their kinds. It fails if
+    /// there is no pool, or too many pages cannot be moved.
+    pub fn alloc_cma_pages(
+        &self,
+        num_pages: usize,
+        align_pow2: usize,
+        kind: UsageKind,
+    ) -> AllocResult<usize> {
+        if num_pages == 0 || !align_pow2.is_power_of_two() {
+            return Err(AllocError::InvalidInput);
+        }
+        let addr = cma::alloc_contiguous(&self.cma, num_pages, align_pow2, |lent, from| {
+            let Some(migrator) = cma::migrator(lent) else {
+                return false;
+            };
+            let Ok(to) = self.alloc_pages(1, PAGE_SIZE, lent) else {
+                return false;
+            };
+            if unsafe { migrator.migrate(from, to) } {
+                self.usages.lock().dealloc(lent, PAGE_SIZE);
+                true
+            } else {
+                self.dealloc_pages(to, 1, lent);
+                false
+            }
+        })
+        .ok_or(AllocError::NoMemory)?;
+        self.usages.lock().alloc(kind, num_pages * PAGE_SIZE);
+        Ok(addr)
+    }
+
+    /// Gives back pages allocated by [`alloc_cma_pages`].
+    ///
+    /// [`alloc_cma_pages`]: GlobalAllocator::alloc_cma_pages
+    pub fn dealloc_cma_pages(&self, va: usize, num_pages: usize, kind: UsageKind) {
+        self.usages.lock().dealloc(kind, num_pages * PAGE_SIZE);
+        self.cma.lock().as_mut().unwrap().free(va, num_pages);
+    }
+
+    /// Returns whether the page at `va` is part of the CMA pool, lent or
+    /// allocated.
+    pub fn is_cma(&self, va: usize) -> bool {
+        self.cma
+            .lock()
+            .as_ref()
+            .is_some_and(|pool| pool.index_of(va).is_some())
+    }
+
+    /// Returns the usage of the CMA pool, if there is one.
+    pub fn cma_stats(&self) -> Option<CmaStats> {
+        self.cma.lock().as_ref().map(|pool| pool.stats())
+    }
+
     /// Allocates contiguous pages starting from the given address.
     ///
     /// It allocates `num_pages` pages from the page allocator starting from the
@@ -343,6 +599,8 @@ impl GlobalAllocator {
             if kind != UsageKind::RustHeap {
                 self.usages.lock().alloc(kind, num_pages * PAGE_SIZE);
             }
+            #[cfg(feature = "kasan")]
+            kasan::unpoison(addr, num_pages * PAGE_SIZE);
             Ok(addr)
         }
     }
@@ -356,8 +614,19 @@ impl GlobalAllocator {
     /// [`alloc_pages`]: GlobalAllocator::alloc_pages
     pub fn dealloc_pages(&self, va: usize, num_pages: usize, kind: UsageKind) {
         self.usages.lock().dealloc(kind, num_pages * PAGE_SIZE);
+        if let Some(pool) = self.cma.lock().as_mut()
+            && pool.index_of(va).is_some()
+        {
+            // Lent from the CMA pool
+            pool.free(va, num_pages);
+            return;
+        }
+        #[cfg(feature = "kasan")]
+        kasan::poison(va, num_pages * PAGE_SIZE, kasan::PAGE_FREE);
         #[cfg(feature = "level-1")]
         {
+            #[cfg(feature = "kasan")]
+            let _unchecked = kasan::Unchecked::new();
             // single-level allocator: deallocate to the byte allocator.
             let mut balloc = self.balloc.lock();
             let layout = Layout::from_size_align(num_pages * PAGE_SIZE, PAGE_SIZE).unwrap();
@@ -368,13 +637,39 @@ impl GlobalAllocator {
         self.palloc.lock().deallocate_pages(va, num_pages);
     }
 
+    /// Gives back pages allocated by [`alloc_sized_pages`].
+    ///
+    /// [`alloc_sized_pages`]: GlobalAllocator::alloc_sized_pages
+    pub fn dealloc_sized_pages(
+        &self,
+        va: usize,
+        num_pages: usize,
+        page_size: usize,
+        kind: UsageKind,
+    ) {
+        self.dealloc_pages(va, num_pages * (page_size / PAGE_SIZE), kind)
+    }
+
     /// Gives back the allocated DMA pages starts from `va` to the DMA page allocator.
     pub fn dealloc_dma_pages(&self, va: usize, num_pages: usize, kind: UsageKind) {
         self.usages.lock().dealloc(kind, num_pages * PAGE_SIZE);
         self.dma_palloc.lock().deallocate_pages(va, num_pages);
     }
 
+    /// Gives the small blocks cached by the current CPU back to the byte
+    /// allocator. Does nothing without the `pcpu-cache` feature.
+    pub fn drain_cpu_cache(&self) {
+        #[cfg(feature = "pcpu-cache")]
+        {
+            #[cfg(feature = "kasan")]
+            let _unchecked = kasan::Unchecked::new();
+            pcpu_cache::drain(self);
+        }
+    }
+
     /// Returns the number of allocated bytes in the byte allocator.
+    ///
+    /// Blocks held in the per-CPU caches count as allocated.
     pub fn used_bytes(&self) -> usize {
         self.balloc.lock().used_bytes()
     }
@@ -410,17 +705,40 @@ impl GlobalAllocator {
     pub fn usages(&self) -> Usages {
         *self.usages.lock()
     }
+
+    /// Allocates heap memory for [`GlobalAlloc`], charging it to the current
+    /// [`HeapTag`].
+    fn alloc_heap(&self, layout: Layout) -> *mut u8 {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "heap-tags")] {
+                let ptr = tags::outer_layout(layout)
+                    .and_then(|outer| GlobalAllocator::alloc(self, outer).ok());
+                match ptr {
+                    Some(base) => unsafe { tags::tag_alloc(base, layout) },
+                    None => alloc::alloc::handle_alloc_error(layout),
+                }
+            } else {
+                match GlobalAllocator::alloc(self, layout) {
+                    Ok(ptr) => ptr.as_ptr(),
+                    Err(_) => alloc::alloc::handle_alloc_error(layout),
+                }
+            }
+        }
+    }
+
+    /// Gives back heap memory allocated by [`alloc_heap`].
+    ///
+    /// [`alloc_heap`]: GlobalAllocator::alloc_heap
+    fn dealloc_heap(&self, ptr: NonNull<u8>, layout: Layout) {
+        #[cfg(feature = "heap-tags")]
+        let (ptr, layout) = unsafe { tags::dealloc_tagged(ptr, layout) };
+        GlobalAllocator::dealloc(self, ptr, layout)
+    }
 }
 
 unsafe impl GlobalAlloc for GlobalAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let allocate_memory = || {
-            if let Ok(ptr) = GlobalAllocator::alloc(self, layout) {
-                ptr.as_ptr()
-            } else {
-                alloc::alloc::handle_alloc_error(layout)
-            }
-        };
+        let allocate_memory = || self.alloc_heap(layout);
 
         #[cfg(feature = "tracking")]
         {
@@ -436,9 +754,7 @@ unsafe impl GlobalAlloc for GlobalAllocator {
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         let ptr = NonNull::new(ptr).expect("dealloc null ptr");
 
-        let deallocate_memory = || {
-            GlobalAllocator::dealloc(self, ptr, layout);
-        };
+        let deallocate_memory = || self.dealloc_heap(ptr, layout);
 
         #[cfg(feature = "tracking")]
         {
@@ -540,6 +856,20 @@ pub fn global_init_dma_page_allocator(va: usize, size: usize) {
     GLOBAL_ALLOCATOR.init_dma_page_allocator(va, size);
 }
 
+/// Sets the given memory region aside as the CMA pool of the global
+/// allocator.
+///
+/// Users should ensure that the region is valid and not being used by
+/// others, including the global allocator itself.
+pub fn global_init_cma(va: usize, size: usize) {
+    debug!(
+        "initialize global CMA pool at: [{:#x}, {:#x})",
+        va,
+        va + size
+    );
+    GLOBAL_ALLOCATOR.init_cma(va, size);
+}
+
 #[cfg(unittest)]
 #[allow(missing_docs)]
 pub mod tests_kalloc {
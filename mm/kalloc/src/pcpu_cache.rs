@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Per-CPU caches of small heap blocks.
+//!
+//! Small allocations are rounded up to a size class and served from a
+//! magazine of free blocks of that class kept by each CPU, so most of them
+//! never take the lock of the byte allocator. An empty magazine is refilled,
+//! and a full one half flushed, with a single acquisition of the lock.
+//!
+//! The free blocks are chained through their first word. Blocks sitting in
+//! the caches count as used by the byte allocator and in the
+//! [`UsageKind::RustHeap`] usage.
+//!
+//! [`UsageKind::RustHeap`]: crate::UsageKind::RustHeap
+
+use core::{alloc::Layout, ptr::NonNull};
+
+use alloc_engine::{AllocResult, ByteAllocator};
+use kspin::NoPreemptIrqSave;
+
+use crate::{GlobalAllocator, UsageKind};
+
+/// Smallest size class, large enough to hold the link of a free block.
+const MIN_CLASS_SIZE: usize = 16;
+/// Number of size classes, the largest being 1 KiB.
+const NUM_CLASSES: usize = 7;
+/// Alignment of the blocks of every class.
+const CLASS_ALIGN: usize = MIN_CLASS_SIZE;
+/// Free blocks a magazine holds at most.
+const MAGAZINE_SIZE: usize = 32;
+/// Blocks moved between a magazine and the byte allocator at once.
+const BATCH: usize = MAGAZINE_SIZE / 2;
+
+/// A size class of small allocations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SizeClass(usize);
+
+impl SizeClass {
+    /// Returns the class serving `layout`, `None` for layouts too large or
+    /// too aligned to be cached.
+    pub(crate) fn of(layout: Layout) -> Option<Self> {
+        if layout.align() > CLASS_ALIGN {
+            return None;
+        }
+        let size = layout.size().max(MIN_CLASS_SIZE).next_power_of_two();
+        let index = (size / MIN_CLASS_SIZE).trailing_zeros() as usize;
+        (index < NUM_CLASSES).then_some(Self(index))
+    }
+
+    fn size(self) -> usize {
+        MIN_CLASS_SIZE << self.0
+    }
+
+    /// Layout of the blocks of the class in the byte allocator.
+    fn layout(self) -> Layout {
+        Layout::from_size_align(self.size(), CLASS_ALIGN).unwrap()
+    }
+}
+
+/// Free blocks of one size class.
+struct Magazine {
+    /// Address of the first free block, 0 if empty.
+    head: usize,
+    len: usize,
+}
+
+impl Magazine {
+    fn pop(&mut self) -> Option<NonNull<u8>> {
+        let block = NonNull::new(self.head as *mut usize)?;
+        // SAFETY: blocks in the magazine are free and at least a word long.
+        self.head = unsafe { block.read() };
+        self.len -= 1;
+        Some(block.cast())
+    }
+
+    fn push(&mut self, block: NonNull<u8>) {
+        // SAFETY: the block is free and at least a word long.
+        unsafe { block.cast::<usize>().write(self.head) };
+        self.head = block.as_ptr() as usize;
+        self.len += 1;
+    }
+}
+
+struct CpuCache {
+    magazines: [Magazine; NUM_CLASSES],
+}
+
+#[percpu::def_percpu]
+static CACHE: CpuCache = CpuCache {
+    magazines: [const { Magazine { head: 0, len: 0 } }; NUM_CLASSES],
+};
+
+/// Runs `f` on the magazine of `class` of this CPU.
+fn with_magazine<R>(class: SizeClass, f: impl FnOnce(&mut Magazine) -> R) -> R {
+    // Interrupt handlers allocate too
+    let _guard = NoPreemptIrqSave::new();
+    // SAFETY: neither preemption nor interrupts can reach the cache meanwhile.
+    let cache = unsafe { CACHE.current_ref_mut_raw() };
+    f(&mut cache.magazines[class.0])
+}
+
+/// Allocates a block of `class`.
+pub(crate) fn alloc(global: &GlobalAllocator, class: SizeClass) -> AllocResult<NonNull<u8>> {
+    with_magazine(class, |magazine| {
+        if let Some(block) = magazine.pop() {
+            return Ok(block);
+        }
+        let layout = class.layout();
+        let mut refilled = 0;
+        {
+            let mut balloc = global.balloc.lock();
+            while refilled < BATCH
+                && let Ok(block) = balloc.allocate(layout)
+            {
+                magazine.push(block);
+                refilled += 1;
+            }
+        }
+        if refilled == 0 {
+            // Let the byte allocator grow the heap
+            return global.alloc_bytes(layout);
+        }
+        global
+            .usages
+            .lock()
+            .alloc(UsageKind::RustHeap, refilled * class.size());
+        Ok(magazine.pop().unwrap())
+    })
+}
+
+/// Gives back a block of `class`.
+pub(crate) fn dealloc(global: &GlobalAllocator, block: NonNull<u8>, class: SizeClass) {
+    with_magazine(class, |magazine| {
+        magazine.push(block);
+        if magazine.len < MAGAZINE_SIZE {
+            return;
+        }
+        let layout = class.layout();
+        {
+            let mut balloc = global.balloc.lock();
+            for _ in 0..BATCH {
+                balloc.deallocate(magazine.pop().unwrap(), layout);
+            }
+        }
+        global
+            .usages
+            .lock()
+            .dealloc(UsageKind::RustHeap, BATCH * class.size());
+    })
+}
+
+/// Gives the blocks cached by this CPU back to the byte allocator.
+pub(crate) fn drain(global: &GlobalAllocator) {
+    let _guard = NoPreemptIrqSave::new();
+    // SAFETY: neither preemption nor interrupts can reach the cache meanwhile.
+    let cache = unsafe { CACHE.current_ref_mut_raw() };
+    let mut freed = 0;
+    let mut balloc = global.balloc.lock();
+    for (index, magazine) in cache.magazines.iter_mut().enumerate() {
+        let class = SizeClass(index);
+        while let Some(block) = magazine.pop() {
+            balloc.deallocate(block, class.layout());
+            freed += class.size();
+        }
+    }
+    drop(balloc);
+    global.usages.lock().dealloc(UsageKind::RustHeap, freed);
+}
+
+#[cfg(unittest)]
+mod tests_pcpu_cache {
+    use unittest::def_test;
+
+    use super::*;
+
+    #[def_test]
+    fn test_size_class_of() {
+        let class = |size, align| SizeClass::of(Layout::from_size_align(size, align).unwrap());
+        assert_eq!(class(1, 1), Some(SizeClass(0)));
+        assert_eq!(class(16, 8), Some(SizeClass(0)));
+        assert_eq!(class(17, 8), Some(SizeClass(1)));
+        assert_eq!(class(1024, 16), Some(SizeClass(6)));
+        assert_eq!(class(1025, 8), None);
+        assert_eq!(class(8, 32), None);
+        assert_eq!(SizeClass(6).size(), 1024);
+    }
+}
@@ -0,0 +1,218 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Shrinkers: callbacks of the subsystems caching memory they can give back.
+//!
+//! Caches such as the block buffers or the dentries register a [`Shrinker`]
+//! to be asked for memory back:
+//!
+//! - directly, when a page allocation fails, before giving up. The
+//!   shrinkers may not block then;
+//! - in the background, once the free pages drop below the [low
+//!   watermark](set_low_watermark). [`LOW_MEMORY`] is raised when they do,
+//!   for a reclaim task to call [`shrink_memory`] and
+//!   [`clear_low_memory`], and may block.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use knotifier::AtomicNotifierChain;
+use kspin::SpinNoIrq;
+
+use crate::PAGE_SIZE;
+
+/// What a [`Shrinker`] is asked to free.
+#[derive(Debug, Clone, Copy)]
+pub struct ShrinkControl {
+    /// Bytes wanted back, which the shrinker may fall short of or exceed.
+    pub bytes: usize,
+    /// Whether the shrinker may sleep, e.g. on a lock. It may not when
+    /// called from a failing allocation.
+    pub may_block: bool,
+}
+
+/// A cache giving memory back on request.
+pub trait Shrinker: Send + Sync {
+    /// Name of the cache, for logs.
+    fn name(&self) -> &'static str;
+
+    /// Returns about how many bytes [`scan`](Self::scan) could free.
+    fn count(&self) -> usize;
+
+    /// Frees memory, least useful first. Returns the number of bytes freed.
+    ///
+    /// Without [`may_block`](ShrinkControl::may_block), a shrinker whose
+    /// lock is taken frees nothing.
+    fn scan(&self, control: &ShrinkControl) -> usize;
+}
+
+/// A registered shrinker, to unregister it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShrinkerId(usize);
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+static SHRINKERS: SpinNoIrq<Vec<(usize, Arc<dyn Shrinker>)>> = SpinNoIrq::new(Vec::new());
+
+/// Free pages under which [`LOW_MEMORY`] is raised, 0 to never raise it.
+static LOW_WATERMARK: AtomicUsize = AtomicUsize::new(0);
+/// Set from raising [`LOW_MEMORY`] until [`clear_low_memory`].
+static LOW: AtomicBool = AtomicBool::new(false);
+
+/// Raised with the number of free pages when it drops below the [low
+/// watermark](set_low_watermark).
+///
+/// It is not raised again until [`clear_low_memory`]. The callbacks run in
+/// the context of the allocation: they should only wake a task up.
+pub static LOW_MEMORY: AtomicNotifierChain<usize> = AtomicNotifierChain::new();
+
+/// Registers `shrinker`.
+pub fn register_shrinker(shrinker: Arc<dyn Shrinker>) -> ShrinkerId {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    SHRINKERS.lock().push((id, shrinker));
+    ShrinkerId(id)
+}
+
+/// Unregisters a shrinker. Returns `false` if it was not registered.
+pub fn unregister_shrinker(id: ShrinkerId) -> bool {
+    let mut shrinkers = SHRINKERS.lock();
+    let len = shrinkers.len();
+    shrinkers.retain(|(it, _)| *it != id.0);
+    shrinkers.len() != len
+}
+
+/// Returns the bytes the shrinkers could free, by name.
+pub fn shrinker_counts() -> Vec<(&'static str, usize)> {
+    SHRINKERS
+        .lock()
+        .iter()
+        .map(|(_, shrinker)| (shrinker.name(), shrinker.count()))
+        .collect()
+}
+
+/// Asks the shrinkers for `bytes`, those able to free the most first, then
+/// gives the empty slabs of the object caches back. Returns the number of
+/// bytes freed.
+///
+/// The shrinkers may sleep, so it must be called from a task.
+pub fn shrink_memory(bytes: usize) -> usize {
+    let mut shrinkers = SHRINKERS
+        .lock()
+        .iter()
+        .map(|(_, shrinker)| (shrinker.count(), shrinker.clone()))
+        .collect::<Vec<_>>();
+    shrinkers.sort_unstable_by_key(|&(count, _)| core::cmp::Reverse(count));
+    let mut freed = 0;
+    for (_, shrinker) in shrinkers {
+        if freed >= bytes {
+            break;
+        }
+        let control = ShrinkControl {
+            bytes: bytes - freed,
+            may_block: true,
+        };
+        let bytes = shrinker.scan(&control);
+        debug!("shrinker {}: freed {} bytes", shrinker.name(), bytes);
+        freed += bytes;
+    }
+    freed + crate::shrink_caches()
+}
+
+/// Asks the shrinkers for `num_pages`, without blocking, for a failing page
+/// allocation. Returns the number of bytes freed.
+pub(crate) fn reclaim(num_pages: usize) -> usize {
+    // Allocating with the list locked, or from a shrinker
+    let Some(shrinkers) = SHRINKERS.try_lock() else {
+        return 0;
+    };
+    let bytes = num_pages * PAGE_SIZE;
+    let mut freed = 0;
+    for (_, shrinker) in shrinkers.iter() {
+        if freed >= bytes {
+            break;
+        }
+        let control = ShrinkControl {
+            bytes: bytes - freed,
+            may_block: false,
+        };
+        freed += shrinker.scan(&control);
+    }
+    freed
+}
+
+/// Sets the number of free pages under which [`LOW_MEMORY`] is raised, 0 to
+/// never raise it.
+pub fn set_low_watermark(pages: usize) {
+    LOW_WATERMARK.store(pages, Ordering::Relaxed);
+}
+
+/// Returns the number of free pages under which [`LOW_MEMORY`] is raised.
+pub fn low_watermark() -> usize {
+    LOW_WATERMARK.load(Ordering::Relaxed)
+}
+
+/// Returns `true` if [`LOW_MEMORY`] has been raised, and not cleared since.
+pub fn is_low_memory() -> bool {
+    LOW.load(Ordering::Acquire)
+}
+
+/// Lets [`LOW_MEMORY`] be raised again, once the shrinkers have been asked
+/// for memory.
+pub fn clear_low_memory() {
+    LOW.store(false, Ordering::Release);
+}
+
+/// Raises [`LOW_MEMORY`] if the free pages are below the low watermark.
+pub(crate) fn check_watermark(free_pages: impl FnOnce() -> usize) {
+    let watermark = low_watermark();
+    if watermark == 0 || is_low_memory() {
+        return;
+    }
+    let free_pages = free_pages();
+    if free_pages < watermark && !LOW.swap(true, Ordering::AcqRel) {
+        LOW_MEMORY.call(&free_pages);
+    }
+}
+
+#[cfg(unittest)]
+#[allow(missing_docs)]
+pub mod tests_shrinker {
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use unittest::def_test;
+
+    use super::*;
+
+    struct TestShrinker(AtomicUsize);
+
+    impl Shrinker for TestShrinker {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        fn count(&self) -> usize {
+            usize::MAX / 2
+        }
+
+        fn scan(&self, control: &ShrinkControl) -> usize {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            control.bytes
+        }
+    }
+
+    #[def_test]
+    fn test_shrinker_register_and_scan() {
+        let shrinker = Arc::new(TestShrinker(AtomicUsize::new(0)));
+        let id = register_shrinker(shrinker.clone());
+        assert!(shrinker_counts().iter().any(|&(name, _)| name == "test"));
+
+        // The shrinker able to free the most goes first, and frees it all
+        assert!(shrink_memory(PAGE_SIZE) >= PAGE_SIZE);
+        assert_eq!(shrinker.0.load(Ordering::Relaxed), 1);
+
+        assert!(unregister_shrinker(id));
+        assert!(!unregister_shrinker(id));
+        assert_eq!(Arc::strong_count(&shrinker), 1);
+    }
+}
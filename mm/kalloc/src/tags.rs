@@ -0,0 +1,260 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Heap usage attributed to subsystems.
+//!
+//! Code allocating on behalf of a subsystem opens a [`HeapTagGuard`] (or uses
+//! [`heap_tagged!`]), and every heap allocation made on that CPU until the
+//! guard drops is counted against its [`HeapTag`]. With the `heap-tags`
+//! feature each allocation carries its tag in a header in front of it, so it
+//! is released from the right counter wherever it is freed. Without the
+//! feature the guards compile to nothing.
+//!
+//! The current tag is per-CPU and guards disable preemption, so tagged scopes
+//! must not sleep. Interrupt handlers running inside a scope are counted
+//! against its tag.
+
+use strum::{IntoStaticStr, VariantArray};
+
+/// Subsystems heap allocations are attributed to.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, VariantArray, IntoStaticStr)]
+pub enum HeapTag {
+    /// Allocations made outside any tagged scope.
+    Other,
+    /// Kernel stacks of tasks.
+    TaskStack,
+    /// Block, inode and bitmap caches of file systems.
+    FsCache,
+    /// Socket and device buffers of the network stack.
+    NetBuf,
+}
+
+/// Current and peak heap usage of a [`HeapTag`], in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TagUsage {
+    /// Bytes currently allocated.
+    pub current: usize,
+    /// Highest value `current` reached since boot or the last
+    /// [`reset_heap_tag_peaks`].
+    pub peak: usize,
+}
+
+/// Attributes heap allocations on this CPU to a [`HeapTag`] until dropped.
+///
+/// Guards nest: dropping one restores the tag that was current before it.
+pub struct HeapTagGuard {
+    #[cfg(feature = "heap-tags")]
+    prev: HeapTag,
+    #[cfg(feature = "heap-tags")]
+    _preempt: kspin::NoPreempt,
+}
+
+impl HeapTagGuard {
+    /// Makes `tag` the current tag of this CPU.
+    #[cfg_attr(not(feature = "heap-tags"), allow(unused_variables))]
+    pub fn new(tag: HeapTag) -> Self {
+        #[cfg(feature = "heap-tags")]
+        {
+            let _preempt = kspin::NoPreempt::new();
+            let prev = imp::swap_current(tag);
+            Self { prev, _preempt }
+        }
+        #[cfg(not(feature = "heap-tags"))]
+        Self {}
+    }
+}
+
+impl Drop for HeapTagGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "heap-tags")]
+        imp::swap_current(self.prev);
+    }
+}
+
+/// Evaluates an expression with its heap allocations attributed to a tag.
+///
+/// ```ignore
+/// let buf = kalloc::heap_tagged!(NetBuf, vec![0u8; 4096]);
+/// ```
+#[macro_export]
+macro_rules! heap_tagged {
+    ($tag:ident, $expr:expr) => {{
+        let _guard = $crate::HeapTagGuard::new($crate::HeapTag::$tag);
+        $expr
+    }};
+}
+
+/// Returns the heap usage of `tag`, `None` without the `heap-tags` feature.
+pub fn heap_tag_usage(tag: HeapTag) -> Option<TagUsage> {
+    #[cfg(feature = "heap-tags")]
+    {
+        Some(imp::usage(tag))
+    }
+    #[cfg(not(feature = "heap-tags"))]
+    {
+        let _ = tag;
+        None
+    }
+}
+
+/// Restarts peak tracking of every tag from its current usage.
+pub fn reset_heap_tag_peaks() {
+    #[cfg(feature = "heap-tags")]
+    imp::reset_peaks();
+}
+
+#[cfg(feature = "heap-tags")]
+pub(crate) use imp::{dealloc_tagged, outer_layout, tag_alloc};
+
+#[cfg(feature = "heap-tags")]
+mod imp {
+    use core::{
+        alloc::Layout,
+        ptr::NonNull,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use strum::VariantArray;
+
+    use super::{HeapTag, TagUsage};
+
+    #[percpu::def_percpu]
+    static CURRENT_TAG: u8 = HeapTag::Other as u8;
+
+    struct Counter {
+        current: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    static COUNTERS: [Counter; HeapTag::VARIANTS.len()] = [const {
+        Counter {
+            current: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }; HeapTag::VARIANTS.len()];
+
+    pub(super) fn swap_current(tag: HeapTag) -> HeapTag {
+        let prev = CURRENT_TAG.read_current();
+        CURRENT_TAG.write_current(tag as u8);
+        HeapTag::VARIANTS[prev as usize]
+    }
+
+    pub(super) fn usage(tag: HeapTag) -> TagUsage {
+        let counter = &COUNTERS[tag as usize];
+        TagUsage {
+            current: counter.current.load(Ordering::Relaxed),
+            peak: counter.peak.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(super) fn reset_peaks() {
+        for counter in &COUNTERS {
+            let current = counter.current.load(Ordering::Relaxed);
+            counter.peak.store(current, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the layout of `layout` with room for the tag in front of it.
+    ///
+    /// The header takes a whole alignment unit so the user pointer keeps the
+    /// requested alignment.
+    pub(crate) fn outer_layout(layout: Layout) -> Option<Layout> {
+        let size = layout.size().checked_add(layout.align())?;
+        Layout::from_size_align(size, layout.align()).ok()
+    }
+
+    /// Writes the current tag into a fresh allocation from
+    /// [`outer_layout`] and returns the pointer handed to the user.
+    ///
+    /// # Safety
+    ///
+    /// `base` must be a live allocation of `outer_layout(layout)`.
+    pub(crate) unsafe fn tag_alloc(base: NonNull<u8>, layout: Layout) -> *mut u8 {
+        let tag = CURRENT_TAG.read_current();
+        let counter = &COUNTERS[tag as usize];
+        let current = counter.current.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        counter.peak.fetch_max(current, Ordering::Relaxed);
+        unsafe {
+            let ptr = base.as_ptr().add(layout.align());
+            ptr.sub(1).write(tag);
+            ptr
+        }
+    }
+
+    /// Releases a user pointer from its tag and returns the allocation and
+    /// layout to give back to the allocator.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from [`tag_alloc`] with the same `layout`.
+    pub(crate) unsafe fn dealloc_tagged(ptr: NonNull<u8>, layout: Layout) -> (NonNull<u8>, Layout) {
+        unsafe {
+            let tag = ptr.as_ptr().sub(1).read();
+            COUNTERS[tag as usize]
+                .current
+                .fetch_sub(layout.size(), Ordering::Relaxed);
+            let base = NonNull::new_unchecked(ptr.as_ptr().sub(layout.align()));
+            (base, outer_layout(layout).unwrap())
+        }
+    }
+}
+
+#[cfg(all(unittest, feature = "heap-tags"))]
+#[allow(missing_docs)]
+pub mod tests_tags {
+    use alloc::{boxed::Box, vec::Vec};
+
+    use unittest::def_test;
+
+    use super::{HeapTag, heap_tag_usage, reset_heap_tag_peaks};
+
+    #[def_test]
+    fn test_heap_tagged_usage() {
+        let before = heap_tag_usage(HeapTag::NetBuf).unwrap();
+        let buf: Vec<u8> = heap_tagged!(NetBuf, Vec::with_capacity(4096));
+        let during = heap_tag_usage(HeapTag::NetBuf).unwrap();
+        assert_eq!(during.current, before.current + 4096);
+        assert!(during.peak >= during.current);
+
+        // Freed outside the scope, still released from its tag
+        drop(buf);
+        let after = heap_tag_usage(HeapTag::NetBuf).unwrap();
+        assert_eq!(after.current, before.current);
+        assert!(after.peak >= before.current + 4096);
+
+        reset_heap_tag_peaks();
+        let reset = heap_tag_usage(HeapTag::NetBuf).unwrap();
+        assert_eq!(reset.peak, reset.current);
+    }
+
+    #[def_test]
+    fn test_heap_tag_nesting() {
+        let before = heap_tag_usage(HeapTag::FsCache).unwrap();
+        let (outer, inner) = heap_tagged!(FsCache, {
+            let inner = heap_tagged!(TaskStack, Box::new([0u64; 8]));
+            // Back to the outer tag
+            (Box::new([0u8; 100]), inner)
+        });
+        assert_eq!(
+            heap_tag_usage(HeapTag::FsCache).unwrap().current,
+            before.current + 100
+        );
+        drop((outer, inner));
+        assert_eq!(
+            heap_tag_usage(HeapTag::FsCache).unwrap().current,
+            before.current
+        );
+    }
+
+    #[def_test]
+    fn test_over_aligned_allocation() {
+        #[repr(align(64))]
+        struct Aligned([u8; 64]);
+
+        let boxed = heap_tagged!(FsCache, Box::new(Aligned([1; 64])));
+        assert_eq!(&*boxed as *const Aligned as usize % 64, 0);
+        assert!(boxed.0.iter().all(|&b| b == 1));
+    }
+}
@@ -8,8 +8,8 @@
 mod units;
 
 pub use self::units::{
-    AddrOps, AddrRange, DynPageIter, MemoryAddr, PageIter, PhysAddr, PhysAddrRange, VirtAddr,
-    VirtAddrRange,
+    AddrOps, AddrRange, AddrTranslate, DynPageIter, GuestPhysAddr, GuestPhysAddrRange, MemoryAddr,
+    OffsetTranslate, PageIter, PhysAddr, PhysAddrRange, VirtAddr, VirtAddrRange,
 };
 
 /// 4 KiB page size.
@@ -476,11 +476,15 @@ macro_rules! def_usize_addr_formatter {
 def_usize_addr! {
     pub type PhysAddr;
     pub type VirtAddr;
+    /// An address in the physical memory of a guest, as seen by it, which
+    /// the host translates, e.g. by a stage-2 page table.
+    pub type GuestPhysAddr;
 }
 
 def_usize_addr_formatter! {
     PhysAddr = "PA:{}";
     VirtAddr = "VA:{}";
+    GuestPhysAddr = "GPA:{}";
 }
 
 impl VirtAddr {
@@ -558,3 +562,10 @@ macro_rules! va {
         $crate::VirtAddr::from_usize($addr)
     };
 }
+
+#[macro_export]
+macro_rules! gpa {
+    ($addr:expr) => {
+        $crate::GuestPhysAddr::from_usize($addr)
+    };
+}
@@ -6,11 +6,13 @@
 mod addr;
 mod iter;
 mod range;
+mod translate;
 
 pub use self::{
-    addr::{AddrOps, MemoryAddr, PhysAddr, VirtAddr},
+    addr::{AddrOps, GuestPhysAddr, MemoryAddr, PhysAddr, VirtAddr},
     iter::{DynPageIter, PageIter},
-    range::{AddrRange, PhysAddrRange, VirtAddrRange},
+    range::{AddrRange, GuestPhysAddrRange, PhysAddrRange, VirtAddrRange},
+    translate::{AddrTranslate, OffsetTranslate},
 };
 
 #[cfg(unittest)]
@@ -18,7 +20,9 @@ pub use self::{
 pub mod tests_units {
     use unittest::def_test;
 
-    use super::{AddrRange, PhysAddr, VirtAddr};
+    use super::{
+        AddrRange, AddrTranslate, GuestPhysAddr, MemoryAddr, OffsetTranslate, PhysAddr, VirtAddr,
+    };
 
     #[def_test]
     fn test_virt_phys_addr_from_usize() {
@@ -40,4 +44,54 @@ pub mod tests_units {
         assert!(range.contains(VirtAddr::from(0x1000usize)));
         assert!(!range.contains(VirtAddr::from(0x2000usize)));
     }
+
+    #[def_test]
+    fn test_guest_phys_addr_ops() {
+        let gpa = GuestPhysAddr::from(0x8000_1234usize);
+        assert_eq!(gpa.align_down_4k(), GuestPhysAddr::from(0x8000_1000usize));
+        assert_eq!(gpa - GuestPhysAddr::from(0x8000_0000usize), 0x1234);
+        assert_eq!((gpa + 0x10).as_usize(), 0x8000_1244);
+    }
+
+    #[def_test]
+    fn test_offset_translate() {
+        let guest = AddrRange::from_start_size(GuestPhysAddr::from(0x8000_0000usize), 0x10_0000);
+        let stage2 = OffsetTranslate::new(guest, PhysAddr::from(0x4_0000_0000usize));
+        assert_eq!(
+            stage2.translate(GuestPhysAddr::from(0x8000_2345usize)),
+            Some(PhysAddr::from(0x4_0000_2345usize))
+        );
+        assert_eq!(
+            stage2.translate(GuestPhysAddr::from(0x8010_0000usize)),
+            None
+        );
+
+        let range = AddrRange::from_start_size(GuestPhysAddr::from(0x8000_1800usize), 0x2000);
+        let host = stage2.translate_range(range).unwrap();
+        assert_eq!(host.start, PhysAddr::from(0x4_0000_1800usize));
+        assert_eq!(host.size(), 0x2000);
+        let past_end = AddrRange::from_start_size(GuestPhysAddr::from(0x800f_f000usize), 0x2000);
+        assert!(stage2.translate_range(past_end).is_none());
+    }
+
+    /// Maps the guest pages in reverse order.
+    struct Reversed;
+
+    impl AddrTranslate<GuestPhysAddr, PhysAddr> for Reversed {
+        fn translate(&self, addr: GuestPhysAddr) -> Option<PhysAddr> {
+            let page = addr.as_usize() / 0x1000;
+            (page < 4).then(|| PhysAddr::from((3 - page) * 0x1000 + addr.as_usize() % 0x1000))
+        }
+    }
+
+    #[def_test]
+    fn test_translate_range_not_contiguous() {
+        let one_page = AddrRange::from_start_size(GuestPhysAddr::from(0x1010usize), 0xff0);
+        assert_eq!(
+            Reversed.translate_range(one_page).unwrap().start,
+            PhysAddr::from(0x2010usize)
+        );
+        let two_pages = AddrRange::from_start_size(GuestPhysAddr::from(0x1000usize), 0x1001);
+        assert!(Reversed.translate_range(two_pages).is_none());
+    }
 }
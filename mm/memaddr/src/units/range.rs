@@ -4,7 +4,7 @@
 
 use core::{fmt, ops::Range};
 
-use crate::{MemoryAddr, PhysAddr, VirtAddr};
+use crate::{GuestPhysAddr, MemoryAddr, PhysAddr, VirtAddr};
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct AddrRange<A: MemoryAddr> {
@@ -159,6 +159,7 @@ where
 
 pub type VirtAddrRange = AddrRange<VirtAddr>;
 pub type PhysAddrRange = AddrRange<PhysAddr>;
+pub type GuestPhysAddrRange = AddrRange<GuestPhysAddr>;
 
 #[macro_export]
 macro_rules! addr_range {
@@ -181,6 +182,13 @@ macro_rules! pa_range {
     };
 }
 
+#[macro_export]
+macro_rules! gpa_range {
+    ($range:expr) => {
+        $crate::GuestPhysAddrRange::try_from($range).expect("invalid address range in `gpa_range!`")
+    };
+}
+
 #[cfg(unittest)]
 #[allow(missing_docs)]
 pub mod tests_range {
@@ -0,0 +1,73 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+use core::marker::PhantomData;
+
+use crate::{AddrRange, MemoryAddr, PAGE_SIZE_4K};
+
+/// Translates the addresses of one address space into another, as a page
+/// table does, or a stage-2 page table from guest-physical to host-physical
+/// addresses.
+pub trait AddrTranslate<S: MemoryAddr, D: MemoryAddr> {
+    /// Returns the address `addr` is mapped to, if it is.
+    fn translate(&self, addr: S) -> Option<D>;
+
+    /// Returns the range `range` is mapped to, if it is mapped to a single
+    /// contiguous range.
+    ///
+    /// By default, every 4K page of the range is translated.
+    fn translate_range(&self, range: AddrRange<S>) -> Option<AddrRange<D>> {
+        let start = self.translate(range.start)?;
+        let mut addr = range.start.align_down_4k();
+        while addr < range.end {
+            if addr > range.start && self.translate(addr)? != start.add(addr.sub_addr(range.start))
+            {
+                return None;
+            }
+            addr = addr.checked_add(PAGE_SIZE_4K)?;
+        }
+        AddrRange::try_new(start, start.checked_add(range.size())?)
+    }
+}
+
+/// A translation by a constant offset, such as a linear mapping, or the
+/// guest-physical memory of a VM backed by contiguous host memory.
+pub struct OffsetTranslate<S: MemoryAddr, D> {
+    /// Range of the addresses translated.
+    range: AddrRange<S>,
+    /// Address `range.start` is mapped to.
+    base: D,
+    _marker: PhantomData<fn(S) -> D>,
+}
+
+impl<S: MemoryAddr, D: MemoryAddr> OffsetTranslate<S, D> {
+    /// Creates a translation of the addresses in `range`, the first of which
+    /// is mapped to `base`.
+    pub const fn new(range: AddrRange<S>, base: D) -> Self {
+        Self {
+            range,
+            base,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<S: MemoryAddr, D: MemoryAddr> AddrTranslate<S, D> for OffsetTranslate<S, D> {
+    fn translate(&self, addr: S) -> Option<D> {
+        if !self.range.contains(addr) {
+            return None;
+        }
+        self.base.checked_add(addr.sub_addr(self.range.start))
+    }
+
+    fn translate_range(&self, range: AddrRange<S>) -> Option<AddrRange<D>> {
+        if !self.range.contains_range(range) {
+            return None;
+        }
+        let start = self
+            .base
+            .checked_add(range.start.sub_addr(self.range.start))?;
+        AddrRange::try_new(start, start.checked_add(range.size())?)
+    }
+}
@@ -0,0 +1,213 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Address space identifiers.
+//!
+//! With an ASID (a PCID on x86), the TLB entries of a user address space are
+//! kept across context switches instead of being flushed. The ASIDs are only
+//! used with TLB shootdowns, as the CPUs that ran an address space keep its
+//! entries until they are flushed by ASID.
+//!
+//! ASID 0 is not allocated: the address spaces without one, and the kernel
+//! tasks, share it and have it flushed when switched to.
+use alloc_engine::{AllocError, AllocResult, BaseAllocator, IdAllocator};
+use kspin::SpinNoIrq;
+
+const MAX_ASIDS: usize = 1 << 16;
+const BITS: usize = u64::BITS as usize;
+
+/// A bitmap allocator of ASIDs, handing them out in turn so that an ASID
+/// freed is reused as late as possible.
+pub(crate) struct AsidAllocator {
+    bitmap: [u64; MAX_ASIDS / BITS],
+    start: usize,
+    end: usize,
+    used: usize,
+    /// Where the next search starts.
+    next: usize,
+}
+
+impl AsidAllocator {
+    pub const fn new() -> Self {
+        Self {
+            bitmap: [0; MAX_ASIDS / BITS],
+            start: 0,
+            end: 0,
+            used: 0,
+            next: 0,
+        }
+    }
+
+    fn get(&self, id: usize) -> bool {
+        self.bitmap[id / BITS] & (1 << (id % BITS)) != 0
+    }
+
+    fn set(&mut self, id: usize, allocated: bool) {
+        if allocated {
+            self.bitmap[id / BITS] |= 1 << (id % BITS);
+        } else {
+            self.bitmap[id / BITS] &= !(1 << (id % BITS));
+        }
+    }
+
+    fn is_free(&self, start: usize, count: usize) -> bool {
+        (start..start + count).all(|id| !self.get(id))
+    }
+}
+
+impl BaseAllocator for AsidAllocator {
+    fn init_region(&mut self, base: usize, size: usize) {
+        let end = (base + size).min(MAX_ASIDS);
+        self.bitmap.fill(0);
+        self.start = base.min(end);
+        self.end = end;
+        self.used = 0;
+        self.next = self.start;
+    }
+
+    fn add_region(&mut self, _base: usize, _size: usize) -> AllocResult {
+        Err(AllocError::NoMemory) // unsupported
+    }
+}
+
+impl IdAllocator for AsidAllocator {
+    fn allocate_ids(&mut self, count: usize, align_pow2: usize) -> AllocResult<usize> {
+        if count == 0 || !align_pow2.is_power_of_two() {
+            return Err(AllocError::InvalidInput);
+        }
+        if count > self.available() {
+            return Err(AllocError::NoMemory);
+        }
+        let first = self.start.next_multiple_of(align_pow2);
+        let last = self.end.checked_sub(count).ok_or(AllocError::NoMemory)?;
+        if first > last {
+            return Err(AllocError::NoMemory);
+        }
+        let from = self
+            .next
+            .clamp(first, last + 1)
+            .next_multiple_of(align_pow2);
+        let candidates = (from..=last)
+            .step_by(align_pow2)
+            .chain((first..from.min(last + 1)).step_by(align_pow2));
+        for id in candidates {
+            if self.is_free(id, count) {
+                for i in id..id + count {
+                    self.set(i, true);
+                }
+                self.used += count;
+                self.next = id + count;
+                return Ok(id);
+            }
+        }
+        Err(AllocError::NoMemory)
+    }
+
+    fn deallocate_ids(&mut self, start_id: usize, count: usize) {
+        for id in start_id..start_id + count {
+            assert!(self.get(id), "ASID {id} is not allocated");
+            self.set(id, false);
+        }
+        self.used -= count;
+    }
+
+    fn is_allocated(&self, id: usize) -> bool {
+        (self.start..self.end).contains(&id) && self.get(id)
+    }
+
+    fn reserve_id(&mut self, id: usize) -> AllocResult {
+        if !(self.start..self.end).contains(&id) {
+            return Err(AllocError::InvalidInput);
+        }
+        if self.get(id) {
+            return Err(AllocError::MemoryOverlap);
+        }
+        self.set(id, true);
+        self.used += 1;
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        self.end - self.start
+    }
+
+    fn used(&self) -> usize {
+        self.used
+    }
+
+    fn available(&self) -> usize {
+        self.size() - self.used
+    }
+}
+
+static ASIDS: SpinNoIrq<AsidAllocator> = SpinNoIrq::new(AsidAllocator::new());
+
+/// Enables the ASIDs on the primary CPU, and makes them available if the
+/// CPU has some.
+pub(crate) fn init() {
+    #[cfg(feature = "ipi")]
+    {
+        let max = khal::asm::enable_asid();
+        if max > 0 {
+            ASIDS.lock().init_region(1, max as usize);
+        }
+        info!("{max} ASIDs available");
+    }
+}
+
+/// Enables the ASIDs on a secondary CPU.
+pub(crate) fn init_secondary() {
+    #[cfg(feature = "ipi")]
+    khal::asm::enable_asid();
+}
+
+/// Allocates an ASID, or returns 0 if none is left.
+pub(crate) fn alloc() -> u16 {
+    ASIDS.lock().allocate_ids(1, 1).map_or(0, |asid| asid as u16)
+}
+
+/// Frees an ASID from [`alloc`], once no TLB holds entries tagged with it.
+pub(crate) fn dealloc(asid: u16) {
+    if asid != 0 {
+        ASIDS.lock().deallocate_ids(asid as usize, 1);
+    }
+}
+
+#[cfg(unittest)]
+mod tests_asid {
+    use alloc_engine::{BaseAllocator, IdAllocator};
+    use unittest::def_test;
+
+    use super::AsidAllocator;
+
+    #[def_test]
+    fn test_asid_allocator_round_robin() {
+        let mut asids = AsidAllocator::new();
+        asids.init_region(1, 3);
+        assert_eq!(asids.size(), 3);
+        assert_eq!(asids.allocate_ids(1, 1).unwrap(), 1);
+        assert_eq!(asids.allocate_ids(1, 1).unwrap(), 2);
+        asids.deallocate_ids(1, 1);
+        // The ASID freed last is reused last
+        assert_eq!(asids.allocate_ids(1, 1).unwrap(), 3);
+        assert_eq!(asids.allocate_ids(1, 1).unwrap(), 1);
+        assert!(asids.allocate_ids(1, 1).is_err());
+        assert_eq!(asids.available(), 0);
+        assert!(!asids.is_allocated(0));
+    }
+
+    #[def_test]
+    fn test_asid_allocator_reserve_and_align() {
+        let mut asids = AsidAllocator::new();
+        asids.init_region(1, 255);
+        asids.reserve_id(4).unwrap();
+        assert!(asids.reserve_id(4).is_err());
+        assert!(asids.reserve_id(0).is_err());
+        assert_eq!(asids.allocate_ids(2, 4).unwrap(), 8);
+        assert!(asids.is_allocated(9));
+        assert_eq!(asids.used(), 3);
+        asids.deallocate_ids(8, 2);
+        assert_eq!(asids.available(), 254);
+    }
+}
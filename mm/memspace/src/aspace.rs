@@ -25,6 +25,8 @@ pub struct AddrSpace {
     range: VirtAddrRange,
     areas: MemorySet<Backend>,
     pgtbl: PageTable,
+    size_limit: usize,
+    asid: u16,
 }
 
 impl AddrSpace {
@@ -58,6 +60,41 @@ impl AddrSpace {
         self.pgtbl.root_paddr()
     }
 
+    /// Returns the address space identifier the TLB entries of the page
+    /// table are tagged with, or 0 if it has none.
+    pub const fn asid(&self) -> u16 {
+        self.asid
+    }
+
+    /// Returns the total size of the mapped areas.
+    pub fn mapped_size(&self) -> usize {
+        self.areas.iter().map(|area| area.size()).sum()
+    }
+
+    /// Returns the size of the memory the areas have pages mapped for.
+    ///
+    /// Areas mapped without populating them only get pages on first access,
+    /// so it is usually well below [`mapped_size`](Self::mapped_size).
+    pub fn resident_size(&self) -> usize {
+        self.areas
+            .iter()
+            .map(|area| self.pgtbl.mapped_size(area.start(), area.size()))
+            .sum()
+    }
+
+    /// Returns the most [`map`](Self::map) may bring the mapped areas to,
+    /// `usize::MAX` unless limited.
+    pub const fn size_limit(&self) -> usize {
+        self.size_limit
+    }
+
+    /// Limits the total size of the mapped areas, as `RLIMIT_AS` does.
+    ///
+    /// Areas already mapped stay, even past the new limit.
+    pub fn set_size_limit(&mut self, limit: usize) {
+        self.size_limit = limit;
+    }
+
     /// Checks if the address space contains the given address range.
     pub fn contains_range(&self, start: VirtAddr, size: usize) -> bool {
         self.range.contains(start) && (self.range.end - start) >= size
@@ -69,6 +106,8 @@ impl AddrSpace {
             range: VirtAddrRange::from_start_size(base, size),
             areas: MemorySet::new(),
             pgtbl: PageTable::try_new().map_err(|_| KError::NoMemory)?,
+            size_limit: usize::MAX,
+            asid: crate::asid::alloc(),
         })
     }
 
@@ -124,6 +163,8 @@ impl AddrSpace {
     /// See [`Backend`] for more details about the mapping backends.
     ///
     /// The `flags` parameter indicates the mapping permissions and attributes.
+    /// Where the virtual and physical addresses are aligned alike, 2M and 1G
+    /// pages are used to spare TLB entries.
     ///
     /// Returns an error if the address range is out of the address space or not
     /// aligned.
@@ -147,6 +188,9 @@ impl AddrSpace {
     }
 
     /// Map a region using the provided backend and flags.
+    ///
+    /// Fails with [`KError::NoMemory`] if the mapped areas would grow past
+    /// the [size limit](Self::set_size_limit).
     pub fn map(
         &mut self,
         start: VirtAddr,
@@ -156,6 +200,9 @@ impl AddrSpace {
         backend: Backend,
     ) -> KResult {
         self.validate_region(start, size)?;
+        if self.mapped_size().saturating_add(size) > self.size_limit {
+            k_bail!(NoMemory, "address space limit exceeded");
+        }
 
         let area = MemoryArea::new(start, size, flags, backend);
         self.areas.map(area, &mut self.pgtbl, false)?;
@@ -196,6 +243,30 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Writes the modified pages in the specified range back to the files
+    /// mapped there, as `msync(2)` does.
+    ///
+    /// Returns [`KError::NoMemory`] if the range is not fully mapped.
+    pub fn sync(&mut self, mut start: VirtAddr, size: usize) -> KResult {
+        self.validate_region(start, size)?;
+        let end = start + size;
+
+        let mut modify = self.pgtbl.modify();
+        while let Some(area) = self.areas.find(start) {
+            let range = VirtAddrRange::new(start, area.end().min(end));
+            area.backend().sync(range, &mut modify)?;
+            start = area.end();
+            if start >= end {
+                break;
+            }
+        }
+
+        if start < end {
+            k_bail!(NoMemory);
+        }
+        Ok(())
+    }
+
     /// Removes mappings within the specified virtual address range.
     ///
     /// Returns an error if the address range is out of the address space or not
@@ -204,6 +275,7 @@ impl AddrSpace {
         self.validate_region(start, size)?;
 
         self.areas.unmap(start, size, &mut self.pgtbl)?;
+        self.flush_tlb(start, size);
         Ok(())
     }
 
@@ -274,13 +346,25 @@ impl AddrSpace {
 
         self.areas
             .protect(start, size, |_| Some(flags), &mut self.pgtbl)?;
+        self.flush_tlb(start, size);
 
         Ok(())
     }
 
+    /// Flushes the range from the TLB of all the CPUs running the address
+    /// space, after its mappings were removed or downgraded.
+    pub(crate) fn flush_tlb(&self, start: VirtAddr, size: usize) {
+        crate::tlb::flush_range(
+            self.pgtbl.root_paddr(),
+            self.asid,
+            VirtAddrRange::from_start_size(start, size),
+        );
+    }
+
     /// Removes all mappings in the address space.
     pub fn clear(&mut self) {
         self.areas.clear(&mut self.pgtbl).unwrap();
+        self.flush_tlb(self.base(), self.size());
     }
 
     /// Checks whether an access to the specified memory region is valid.
@@ -364,13 +448,24 @@ impl AddrSpace {
         false
     }
 
-    /// Attempts to clone the current address space into a new one.
+    /// Attempts to clone the current address space into a new one, as
+    /// `fork(2)` does.
     ///
     /// This method creates a new empty address space with the same base and
     /// size, then iterates over all memory areas in the original address
-    /// space to copy or share their mappings into the new one.
+    /// space to copy or share their mappings into the new one:
+    ///
+    /// - linear and shared mappings map the same memory;
+    /// - private mappings share their pages read-only in both address spaces,
+    ///   each copying a page on its first write to it;
+    /// - file and shared memory object mappings fault their pages in again.
+    ///
+    /// The kernel mappings are not copied, and the new page table is found
+    /// with [`page_table_root`](Self::page_table_root).
     pub fn try_clone(&mut self) -> KResult<Arc<Mutex<Self>>> {
-        let new_aspace = Arc::new(Mutex::new(Self::new_empty(self.base(), self.size())?));
+        let mut new_aspace = Self::new_empty(self.base(), self.size())?;
+        new_aspace.size_limit = self.size_limit;
+        let new_aspace = Arc::new(Mutex::new(new_aspace));
         let new_aspace_clone = new_aspace.clone();
 
         let mut guard = new_aspace.lock();
@@ -390,6 +485,9 @@ impl AddrSpace {
             aspace.areas.map(new_area, &mut aspace.pgtbl, false)?;
         }
         drop(guard);
+        // The private mappings were made read-only.
+        drop(self_modify);
+        self.flush_tlb(self.base(), self.size());
 
         Ok(new_aspace)
     }
@@ -416,5 +514,56 @@ impl fmt::Debug for AddrSpace {
 impl Drop for AddrSpace {
     fn drop(&mut self) {
         self.clear();
+        // No TLB holds entries of the ASID anymore.
+        crate::asid::dealloc(self.asid);
+    }
+}
+
+#[cfg(unittest)]
+mod tests_aspace {
+    use khal::paging::{MappingFlags, PageSize};
+    use memaddr::VirtAddr;
+    use unittest::def_test;
+
+    use super::AddrSpace;
+    use crate::backend::Backend;
+
+    #[def_test]
+    fn test_try_clone_copies_on_write() {
+        let base = VirtAddr::from_usize(0x1000_0000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+        let mut parent = AddrSpace::new_empty(base, 0x10_0000).unwrap();
+        parent
+            .map(
+                base,
+                0x2000,
+                flags,
+                true,
+                Backend::new_alloc(base, PageSize::Size4K),
+            )
+            .unwrap();
+        parent.write(base, b"parent").unwrap();
+
+        let child = parent.try_clone().unwrap();
+        let mut child = child.lock();
+        let (parent_pa, parent_flags, _) = parent.page_table().query(base).unwrap();
+        let (child_pa, child_flags, _) = child.page_table().query(base).unwrap();
+        assert_eq!(parent_pa, child_pa);
+        assert!(!parent_flags.contains(MappingFlags::WRITE));
+        assert!(!child_flags.contains(MappingFlags::WRITE));
+
+        // A write fault in the child gives it a copy of its own
+        child
+            .populate_area(base, 0x1000, MappingFlags::WRITE)
+            .unwrap();
+        let (child_pa, ..) = child.page_table().query(base).unwrap();
+        assert_ne!(child_pa, parent_pa);
+        child.write(base, b"child!").unwrap();
+
+        let mut buf = [0; 6];
+        parent.read(base, &mut buf).unwrap();
+        assert_eq!(&buf, b"parent");
+        child.read(base, &mut buf).unwrap();
+        assert_eq!(&buf, b"child!");
     }
 }
@@ -3,6 +3,11 @@
 // See LICENSES for license details.
 
 //! Copy-on-write mapping backend.
+//!
+//! Frames are shared read-only between the address spaces duplicated by
+//! [`AddrSpace::try_clone`], and copied on the first write. Reads of
+//! untouched anonymous pages map a single frame of zeros the same way, so
+//! that memory only read is never allocated.
 use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
 use core::slice;
 
@@ -21,9 +26,19 @@ use crate::{
     backend::{Backend, BackendOps, alloc_frame, dealloc_frame, pages_in},
 };
 
-struct FrameRefCnt(u8);
+struct FrameRefCnt(u32);
 
 impl FrameRefCnt {
+    /// Takes one more reference to the frame.
+    fn share(&mut self) -> KResult {
+        assert!(self.0 > 0, "referencing unreferenced frame");
+        self.0 = self.0.checked_add(1).ok_or_else(|| {
+            warn!("frame reference count overflow");
+            KError::BadAddress
+        })?;
+        Ok(())
+    }
+
     // This function may lock FRAME_TABLE again, so the caller should drop the lock first.
     fn drop_frame(&mut self, pa: PhysAddr, pgsize: PageSize) {
         assert!(self.0 > 0, "dropping unreferenced frame");
@@ -45,7 +60,7 @@ struct FrameTableRefCount {
 }
 
 impl FrameTableRefCount {
-    const INITIAL_CNT: u8 = 1;
+    const INITIAL_CNT: u32 = 1;
 
     const fn new() -> Self {
         Self {
@@ -74,6 +89,26 @@ impl FrameTableRefCount {
 
 static FRAME_TABLE: SpinNoIrq<FrameTableRefCount> = SpinNoIrq::new(FrameTableRefCount::new());
 
+/// The frame of zeros shared by the untouched anonymous pages read from.
+///
+/// It holds a reference of its own, so it is never freed.
+static ZERO_FRAME: SpinNoIrq<Option<PhysAddr>> = SpinNoIrq::new(None);
+
+fn zero_frame() -> KResult<PhysAddr> {
+    let mut zero = ZERO_FRAME.lock();
+    if let Some(frame) = *zero {
+        return Ok(frame);
+    }
+    let frame = alloc_frame(true, PageSize::Size4K)?;
+    FRAME_TABLE.lock().init_frame(frame);
+    *zero = Some(frame);
+    Ok(frame)
+}
+
+fn is_zero_frame(pa: PhysAddr) -> bool {
+    *ZERO_FRAME.lock() == Some(pa)
+}
+
 /// Copy-on-write mapping backend.
 ///
 /// This corresponds to the `MAP_PRIVATE` flag.
@@ -115,6 +150,23 @@ impl CowBackend {
         Ok(())
     }
 
+    /// Maps the frame of zeros read-only at `va`, for a read of an untouched
+    /// anonymous page.
+    fn map_zero_at(va: VirtAddr, flags: MappingFlags, pgtbl: &mut PageTableMut) -> KResult {
+        let frame = zero_frame()?;
+        let frame_ref = FRAME_TABLE
+            .lock()
+            .get_frame_ref(frame)
+            .ok_or(KError::BadAddress)?;
+        let mut frame_ref = frame_ref.lock();
+        frame_ref.share()?;
+        if let Err(err) = pgtbl.map(va, frame, PageSize::Size4K, flags - MappingFlags::WRITE) {
+            frame_ref.drop_frame(frame, PageSize::Size4K);
+            return Err(super::map_paging_err(err));
+        }
+        Ok(())
+    }
+
     fn dispatch_irq_cow_fault(
         &self,
         va: VirtAddr,
@@ -122,6 +174,7 @@ impl CowBackend {
         flags: MappingFlags,
         pgtble: &mut PageTableMut,
     ) -> KResult {
+        let zero = is_zero_frame(pa);
         let mut frame_table = FRAME_TABLE.lock();
         let frame = frame_table.get_frame_ref(pa).ok_or(KError::BadAddress)?;
         drop(frame_table);
@@ -135,13 +188,15 @@ impl CowBackend {
             }
             _ => {
                 // Multiple references, need to copy the frame.
-                let new_frame = self.alloc_new_frame(false)?;
-                unsafe {
-                    core::ptr::copy_nonoverlapping(
-                        p2v(pa).as_ptr(),
-                        p2v(new_frame).as_mut_ptr(),
-                        self.size as _,
-                    );
+                let new_frame = self.alloc_new_frame(zero)?;
+                if !zero {
+                    unsafe {
+                        core::ptr::copy_nonoverlapping(
+                            p2v(pa).as_ptr(),
+                            p2v(new_frame).as_mut_ptr(),
+                            self.size as _,
+                        );
+                    }
                 }
                 pgtble
                     .remap(va, new_frame, flags)
@@ -182,6 +237,38 @@ impl BackendOps for CowBackend {
         Ok(())
     }
 
+    fn protect(
+        &self,
+        range: VirtAddrRange,
+        new_flags: MappingFlags,
+        pgtbl: &mut PageTableMut,
+    ) -> KResult {
+        if !new_flags.contains(MappingFlags::WRITE) {
+            return pgtbl
+                .protect_region(range.start, range.size(), new_flags)
+                .map_err(super::map_paging_err);
+        }
+        // Shared frames stay read-only, to be copied on the first write.
+        for addr in pages_in(range, self.size)? {
+            let paddr = match pgtbl.query(addr) {
+                Ok((paddr, ..)) => paddr,
+                Err(PagingError::NotMapped) => continue,
+                Err(_) => return Err(KError::BadAddress),
+            };
+            let frame = FRAME_TABLE
+                .lock()
+                .get_frame_ref(paddr)
+                .ok_or(KError::BadAddress)?;
+            let flags = if frame.lock().0 > 1 {
+                new_flags - MappingFlags::WRITE
+            } else {
+                new_flags
+            };
+            pgtbl.protect(addr, flags).map_err(super::map_paging_err)?;
+        }
+        Ok(())
+    }
+
     fn populate(
         &self,
         range: VirtAddrRange,
@@ -203,9 +290,17 @@ impl BackendOps for CowBackend {
                         pages += 1;
                     }
                 }
-                // If the page is not mapped, try map it.
+                // If the page is not mapped, try map it. Anonymous pages only
+                // read share the frame of zeros until written.
                 Err(PagingError::NotMapped) => {
-                    self.alloc_new_at(addr, flags, pgtbl)?;
+                    if self.file.is_none()
+                        && self.size == PageSize::Size4K
+                        && !access_flags.contains(MappingFlags::WRITE)
+                    {
+                        Self::map_zero_at(addr, flags, pgtbl)?;
+                    } else {
+                        self.alloc_new_at(addr, flags, pgtbl)?;
+                    }
                     pages += 1;
                 }
                 Err(_) => return Err(KError::BadAddress),
@@ -237,13 +332,7 @@ impl BackendOps for CowBackend {
                         .lock()
                         .get_frame_ref(paddr)
                         .ok_or(KError::BadAddress)?;
-                    let mut frame = frame.lock();
-                    assert!(frame.0 > 0, "referencing unreferenced frame");
-                    frame.0 += 1;
-                    if frame.0 == u8::MAX {
-                        warn!("frame reference count overflow");
-                        return Err(KError::BadAddress);
-                    }
+                    frame.lock().share()?;
                     old_pgtbl
                         .protect(vaddr, cow_flags)
                         .map_err(super::map_paging_err)?;
@@ -276,6 +365,8 @@ impl Backend {
         })
     }
 
+    /// Creates a backend of anonymous memory, its pages being allocated on
+    /// first access unless the area is populated.
     pub fn new_alloc(start: VirtAddr, size: PageSize) -> Self {
         Self::Cow(CowBackend {
             start,
@@ -284,3 +375,30 @@ impl Backend {
         })
     }
 }
+
+#[cfg(unittest)]
+mod tests_cow {
+    use khal::mem::p2v;
+    use unittest::def_test;
+
+    use super::{FRAME_TABLE, FrameRefCnt, is_zero_frame, zero_frame};
+
+    #[def_test]
+    fn test_zero_frame_shared_and_kept() {
+        let frame = zero_frame().unwrap();
+        assert_eq!(zero_frame().unwrap(), frame);
+        assert!(is_zero_frame(frame));
+        let frame_ref = FRAME_TABLE.lock().get_frame_ref(frame).unwrap();
+        assert!(frame_ref.lock().0 >= 1);
+        let bytes = unsafe { core::slice::from_raw_parts(p2v(frame).as_ptr(), 0x1000) };
+        assert!(bytes.iter().all(|&b| b == 0));
+    }
+
+    #[def_test]
+    fn test_frame_ref_count_overflow() {
+        let mut frame_ref = FrameRefCnt(u32::MAX - 1);
+        assert!(frame_ref.share().is_ok());
+        assert!(frame_ref.share().is_err());
+        assert_eq!(frame_ref.0, u32::MAX);
+    }
+}
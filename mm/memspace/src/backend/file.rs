@@ -8,7 +8,10 @@ use alloc::{
     sync::{Arc, Weak},
     vec::Vec,
 };
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use kerrno::{KError, KResult};
 use kfs::{CachedFile, FileFlags};
@@ -76,9 +79,10 @@ impl FileBackendInner {
             return;
         }
 
-        let pt = aspace.page_table_mut();
-        match pt.modify().unmap(vaddr) {
-            Ok(_) | Err(PagingError::NotMapped) => {}
+        let unmapped = aspace.page_table_mut().modify().unmap(vaddr);
+        match unmapped {
+            Ok(_) => aspace.flush_tlb(vaddr, PageSize::Size4K as usize),
+            Err(PagingError::NotMapped) => {}
             Err(err) => {
                 warn!("Failed to unmap page {:?}: {:?}", vaddr, err);
             }
@@ -109,6 +113,12 @@ impl FileBackend {
     pub fn futex_dispatch_irq(&self) -> Weak<()> {
         Arc::downgrade(&self.0.futex_dispatch_irq)
     }
+
+    /// Returns the file pages backing `range`.
+    fn pages_of(&self, range: VirtAddrRange) -> Range<u32> {
+        let start = ((range.start - self.0.start) / PAGE_SIZE_4K) as u32 + self.0.offset_page;
+        start..start + (range.size() / PAGE_SIZE_4K) as u32
+    }
 }
 
 impl BackendOps for FileBackend {
@@ -121,6 +131,11 @@ impl BackendOps for FileBackend {
     }
 
     fn unmap(&self, range: VirtAddrRange, pt: &mut PageTableMut) -> KResult {
+        // The pages may outlive the mapping in the page cache, but what was
+        // written through it should reach the file now
+        if let Err(err) = self.0.cache.write_back_pages(self.pages_of(range)) {
+            warn!("Failed to write back {:?}: {:?}", range, err);
+        }
         for addr in pages_in(range, PageSize::Size4K)? {
             match pt.unmap(addr) {
                 Ok(_) | Err(PagingError::NotMapped) => {}
@@ -142,6 +157,27 @@ impl BackendOps for FileBackend {
         self.check_flags(new_flags)
     }
 
+    fn sync(&self, range: VirtAddrRange, pgtbl: &mut PageTableMut) -> KResult {
+        if self.0.cache.in_memory() {
+            return Ok(());
+        }
+        // Write-protect first, so that writes from now on fault and dirty the
+        // pages again
+        for addr in pages_in(range, PageSize::Size4K)? {
+            match pgtbl.query(addr) {
+                Ok((paddr, flags, _)) if flags.contains(MappingFlags::WRITE) => {
+                    pgtbl
+                        .remap(addr, paddr, flags - MappingFlags::WRITE)
+                        .map_err(map_paging_err)?;
+                }
+                Ok(_) | Err(PagingError::NotMapped) => {}
+                Err(_) => return Err(KError::BadAddress),
+            }
+        }
+        self.0.cache.write_back_pages(self.pages_of(range))?;
+        Ok(())
+    }
+
     fn populate(
         &self,
         range: VirtAddrRange,
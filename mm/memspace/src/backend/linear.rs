@@ -20,6 +20,9 @@ use crate::{
 /// The offset between the virtual address and the physical address is
 /// constant, which is specified by `pa_va_offset`. For example, the virtual
 /// address `vaddr` is mapped to the physical address `vaddr - pa_va_offset`.
+///
+/// Parts of the region aligned in both address spaces are mapped with 2M or 1G
+/// pages, which are split again when a part of them is unmapped or protected.
 #[derive(Clone)]
 pub struct LinearBackend {
     offset: isize,
@@ -40,7 +43,7 @@ impl BackendOps for LinearBackend {
         let pa_range = PhysAddrRange::from_start_size(self.pa(range.start), range.size());
         debug!("Linear::map: {range:?} -> {pa_range:?} {flags:?}");
         pgtbl
-            .map_region(range.start, |va| self.pa(va), range.size(), flags, false)
+            .map_region(range.start, |va| self.pa(va), range.size(), flags, true)
             .map_err(map_paging_err)?;
         Ok(())
     }
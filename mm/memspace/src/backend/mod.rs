@@ -13,15 +13,17 @@ use khal::{
     paging::{MappingFlags, PageSize, PageTable, PageTableMut, PagingError},
 };
 use ksync::Mutex;
-use memaddr::{DynPageIter, PAGE_SIZE_4K, PhysAddr, VirtAddr, VirtAddrRange};
+use memaddr::{DynPageIter, PhysAddr, VirtAddr, VirtAddrRange};
 use memset::MemorySetBackend;
 
 pub mod cow;
 pub mod file;
 pub mod linear;
 pub mod shared;
+pub mod shm;
 
 pub use shared::SharedPages;
+pub use shm::ShmObject;
 
 use crate::aspace::AddrSpace;
 
@@ -32,10 +34,9 @@ fn divide_page(size: usize, pgsize: PageSize) -> usize {
 
 fn alloc_frame(zeroed: bool, size: PageSize) -> KResult<PhysAddr> {
     let pgsize = size as usize;
-    let num_pages = pgsize / PAGE_SIZE_4K;
     let vaddr = VirtAddr::from(
         global_allocator()
-            .alloc_pages(num_pages, pgsize, UsageKind::VirtMem)
+            .alloc_sized_pages(1, pgsize, UsageKind::VirtMem)
             .map_err(|_| KError::NoMemory)?,
     );
     if zeroed {
@@ -48,9 +49,7 @@ fn alloc_frame(zeroed: bool, size: PageSize) -> KResult<PhysAddr> {
 
 fn dealloc_frame(frame: PhysAddr, align: PageSize) {
     let vaddr = p2v(frame);
-    let page_size: usize = align.into();
-    let num_pages = page_size / PAGE_SIZE_4K;
-    global_allocator().dealloc_pages(vaddr.as_usize(), num_pages, UsageKind::VirtMem);
+    global_allocator().dealloc_sized_pages(vaddr.as_usize(), 1, align.into(), UsageKind::VirtMem);
 }
 
 fn pages_in(range: VirtAddrRange, align: PageSize) -> KResult<DynPageIter<VirtAddr>> {
@@ -85,6 +84,24 @@ pub trait BackendOps {
         Ok(())
     }
 
+    /// Changes the flags of the pages mapped in a memory region.
+    fn protect(
+        &self,
+        range: VirtAddrRange,
+        new_flags: MappingFlags,
+        pgtbl: &mut PageTableMut,
+    ) -> KResult {
+        pgtbl
+            .protect_region(range.start, range.size(), new_flags)
+            .map_err(map_paging_err)
+    }
+
+    /// Writes the modified pages of a memory region back to where they come
+    /// from, for backends mapping more than anonymous memory.
+    fn sync(&self, _range: VirtAddrRange, _pgtbl: &mut PageTableMut) -> KResult {
+        Ok(())
+    }
+
     /// Populate a memory region and return how many pages now satisfy
     /// `access_flags`.
     ///
@@ -126,6 +143,7 @@ pub enum Backend {
     Linear(linear::LinearBackend),
     Cow(cow::CowBackend),
     Shared(shared::SharedBackend),
+    Shm(shm::ShmBackend),
     File(file::FileBackend),
 }
 
@@ -167,9 +185,12 @@ impl MemorySetBackend for Backend {
         new_flags: Self::Flags,
         pgtbl: &mut Self::PageTable,
     ) -> bool {
-        pgtbl
-            .modify()
-            .protect_region(start, size, new_flags)
-            .is_ok()
+        let range = VirtAddrRange::from_start_size(start, size);
+        if let Err(err) = BackendOps::protect(self, range, new_flags, &mut pgtbl.modify()) {
+            warn!("Failed to protect area: {:?}", err);
+            false
+        } else {
+            true
+        }
     }
 }
@@ -0,0 +1,314 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Shared memory objects and their mapping backend.
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use kerrno::{KError, KResult};
+use khal::{
+    mem::p2v,
+    paging::{MappingFlags, PageSize, PageTableMut, PagingError},
+};
+use kspin::SpinNoIrq;
+use ksync::Mutex;
+use memaddr::{PAGE_SIZE_4K, PhysAddr, VirtAddr, VirtAddrRange};
+
+use super::{alloc_frame, dealloc_frame};
+use crate::{
+    aspace::AddrSpace,
+    backend::{Backend, BackendOps, map_paging_err, pages_in},
+};
+
+/// A page of a [`ShmObject`], freed once neither the object nor a mapping
+/// holds it.
+struct ShmFrame(PhysAddr);
+
+impl Drop for ShmFrame {
+    fn drop(&mut self) {
+        dealloc_frame(self.0, PageSize::Size4K);
+    }
+}
+
+struct ShmPages {
+    size: usize,
+    frames: Vec<Option<Arc<ShmFrame>>>,
+}
+
+/// A shared memory object: memory that any number of address spaces may
+/// map, and which may grow or shrink, as POSIX shared memory objects and
+/// memfds do.
+///
+/// The pages are allocated, zeroed, on first access. A mapping holds the
+/// pages it maps, so that those cut off by shrinking the object stay valid
+/// until unmapped, though they are no longer part of it.
+pub struct ShmObject {
+    pages: Mutex<ShmPages>,
+    futex_key: Arc<()>,
+}
+
+impl ShmObject {
+    /// Creates an object of `size` bytes.
+    pub fn new(size: usize) -> Arc<Self> {
+        Arc::new(Self {
+            pages: Mutex::new(ShmPages {
+                size,
+                frames: (0..size.div_ceil(PAGE_SIZE_4K)).map(|_| None).collect(),
+            }),
+            futex_key: Arc::new(()),
+        })
+    }
+
+    /// Returns the size of the object in bytes.
+    pub fn size(&self) -> usize {
+        self.pages.lock().size
+    }
+
+    /// Returns the size of the pages allocated for the object.
+    pub fn resident_size(&self) -> usize {
+        let pages = self.pages.lock();
+        pages.frames.iter().flatten().count() * PAGE_SIZE_4K
+    }
+
+    /// Grows or shrinks the object to `size` bytes.
+    ///
+    /// The bytes cut off read as zeros if the object grows again.
+    pub fn resize(&self, size: usize) {
+        let mut pages = self.pages.lock();
+        if size < pages.size
+            && let Some(Some(frame)) = pages.frames.get(size / PAGE_SIZE_4K)
+        {
+            let tail = size % PAGE_SIZE_4K;
+            unsafe {
+                core::ptr::write_bytes(p2v(frame.0).as_mut_ptr().add(tail), 0, PAGE_SIZE_4K - tail)
+            };
+        }
+        pages.frames.resize(size.div_ceil(PAGE_SIZE_4K), None);
+        pages.size = size;
+    }
+
+    /// Reads from the object at `offset`. Returns the number of bytes read,
+    /// short past its end.
+    pub fn read_at(&self, buf: &mut [u8], offset: usize) -> usize {
+        let pages = self.pages.lock();
+        let len = buf.len().min(pages.size.saturating_sub(offset));
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done;
+            let in_page = pos % PAGE_SIZE_4K;
+            let count = (PAGE_SIZE_4K - in_page).min(len - done);
+            let dst = &mut buf[done..done + count];
+            match &pages.frames[pos / PAGE_SIZE_4K] {
+                Some(frame) => unsafe {
+                    core::ptr::copy_nonoverlapping(
+                        p2v(frame.0).as_ptr().add(in_page),
+                        dst.as_mut_ptr(),
+                        count,
+                    )
+                },
+                None => dst.fill(0),
+            }
+            done += count;
+        }
+        len
+    }
+
+    /// Writes to the object at `offset`, growing it if needed. Returns the
+    /// number of bytes written.
+    pub fn write_at(&self, buf: &[u8], offset: usize) -> KResult<usize> {
+        let end = offset.checked_add(buf.len()).ok_or(KError::InvalidInput)?;
+        if end > self.size() {
+            self.resize(end);
+        }
+        let mut pages = self.pages.lock();
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done;
+            let in_page = pos % PAGE_SIZE_4K;
+            let count = (PAGE_SIZE_4K - in_page).min(buf.len() - done);
+            let frame = Self::frame_locked(&mut pages, pos / PAGE_SIZE_4K)?;
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    buf[done..].as_ptr(),
+                    p2v(frame.0).as_mut_ptr().add(in_page),
+                    count,
+                )
+            };
+            done += count;
+        }
+        Ok(done)
+    }
+
+    /// Returns a weak handle identifying the object, for shared futexes.
+    pub fn futex_key(&self) -> Weak<()> {
+        Arc::downgrade(&self.futex_key)
+    }
+
+    /// Returns the page at `index`, allocating it if needed.
+    fn frame(&self, index: usize) -> KResult<Arc<ShmFrame>> {
+        Self::frame_locked(&mut self.pages.lock(), index)
+    }
+
+    fn frame_locked(pages: &mut ShmPages, index: usize) -> KResult<Arc<ShmFrame>> {
+        let slot = pages.frames.get_mut(index).ok_or(KError::BadAddress)?;
+        if let Some(frame) = slot {
+            return Ok(frame.clone());
+        }
+        let frame = Arc::new(ShmFrame(alloc_frame(true, PageSize::Size4K)?));
+        *slot = Some(frame.clone());
+        Ok(frame)
+    }
+}
+
+/// Mapping backend of a [`ShmObject`].
+///
+/// Pages are mapped on first access, writable if the area is, so that
+/// every mapping sees the writes of the others.
+#[derive(Clone)]
+pub struct ShmBackend {
+    start: VirtAddr,
+    /// Offset in the object mapped at `start`.
+    offset: usize,
+    object: Arc<ShmObject>,
+    /// The pages mapped, by address.
+    mapped: Arc<SpinNoIrq<BTreeMap<VirtAddr, Arc<ShmFrame>>>>,
+}
+
+impl ShmBackend {
+    /// Returns the object mapped.
+    pub fn object(&self) -> &Arc<ShmObject> {
+        &self.object
+    }
+
+    /// Returns the offset in the object mapped at `vaddr`.
+    pub fn offset_of(&self, vaddr: VirtAddr) -> usize {
+        self.offset + (vaddr - self.start)
+    }
+}
+
+impl BackendOps for ShmBackend {
+    fn page_size(&self) -> PageSize {
+        PageSize::Size4K
+    }
+
+    fn map(&self, range: VirtAddrRange, flags: MappingFlags, _pgtbl: &mut PageTableMut) -> KResult {
+        debug!("Shm::map: {range:?} {flags:?}");
+        Ok(())
+    }
+
+    fn unmap(&self, range: VirtAddrRange, pgtbl: &mut PageTableMut) -> KResult {
+        debug!("Shm::unmap: {range:?}");
+        let mut mapped = self.mapped.lock();
+        for addr in pages_in(range, PageSize::Size4K)? {
+            match pgtbl.unmap(addr) {
+                Ok(_) | Err(PagingError::NotMapped) => {}
+                Err(err) => return Err(map_paging_err(err)),
+            }
+            mapped.remove(&addr);
+        }
+        Ok(())
+    }
+
+    fn populate(
+        &self,
+        range: VirtAddrRange,
+        flags: MappingFlags,
+        access_flags: MappingFlags,
+        pgtbl: &mut PageTableMut,
+    ) -> KResult<(usize, Option<Box<dyn FnOnce(&mut AddrSpace)>>)> {
+        let mut pages = 0;
+        for addr in pages_in(range, PageSize::Size4K)? {
+            match pgtbl.query(addr) {
+                Ok((_, page_flags, _)) => {
+                    if page_flags.contains(access_flags) {
+                        pages += 1;
+                    }
+                }
+                // Past the end of the object, the access fails.
+                Err(PagingError::NotMapped) => {
+                    let frame = self.object.frame(self.offset_of(addr) / PAGE_SIZE_4K)?;
+                    pgtbl
+                        .map(addr, frame.0, PageSize::Size4K, flags)
+                        .map_err(map_paging_err)?;
+                    self.mapped.lock().insert(addr, frame);
+                    pages += 1;
+                }
+                Err(_) => return Err(KError::BadAddress),
+            }
+        }
+        Ok((pages, None))
+    }
+
+    fn clone_map(
+        &self,
+        _range: VirtAddrRange,
+        _flags: MappingFlags,
+        _old_pgtbl: &mut PageTableMut,
+        _new_pgtbl: &mut PageTableMut,
+        _new_aspace: &Arc<Mutex<AddrSpace>>,
+    ) -> KResult<Backend> {
+        // The new mapping faults the pages in from the object.
+        Ok(Backend::Shm(ShmBackend {
+            start: self.start,
+            offset: self.offset,
+            object: self.object.clone(),
+            mapped: Arc::new(SpinNoIrq::new(BTreeMap::new())),
+        }))
+    }
+}
+
+impl Backend {
+    /// Creates a backend mapping `object` from `offset`, a multiple of the
+    /// page size.
+    pub fn new_shm(start: VirtAddr, object: Arc<ShmObject>, offset: usize) -> Self {
+        Self::Shm(ShmBackend {
+            start,
+            offset,
+            object,
+            mapped: Arc::new(SpinNoIrq::new(BTreeMap::new())),
+        })
+    }
+}
+
+#[cfg(unittest)]
+mod tests_shm {
+    use unittest::def_test;
+
+    use super::ShmObject;
+
+    #[def_test]
+    fn test_shm_object_read_write_resize() {
+        let object = ShmObject::new(0x1800);
+        assert_eq!(object.resident_size(), 0);
+
+        let mut buf = [0xff; 4];
+        assert_eq!(object.read_at(&mut buf, 0x1000), 4);
+        assert_eq!(buf, [0; 4]);
+
+        // Across the page boundary
+        assert_eq!(object.write_at(b"shared", 0xffd).unwrap(), 6);
+        assert_eq!(object.resident_size(), 0x2000);
+        let mut buf = [0; 6];
+        assert_eq!(object.read_at(&mut buf, 0xffd), 6);
+        assert_eq!(&buf, b"shared");
+
+        // The tail cut off reads as zeros once grown again
+        object.resize(0xfff);
+        assert_eq!(object.resident_size(), 0x1000);
+        object.resize(0x1000);
+        let mut buf = [0xff; 2];
+        assert_eq!(object.read_at(&mut buf, 0xffe), 2);
+        assert_eq!(buf, [b'h', 0]);
+
+        // Writing past the end grows the object
+        assert_eq!(object.write_at(b"x", 0x2000).unwrap(), 1);
+        assert_eq!(object.size(), 0x2001);
+        let mut buf = [0; 4];
+        assert_eq!(object.read_at(&mut buf, 0x1ffe), 3);
+    }
+}
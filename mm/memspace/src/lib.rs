@@ -10,8 +10,10 @@ extern crate log;
 
 extern crate alloc;
 
+mod asid;
 mod aspace;
 pub mod backend;
+mod tlb;
 
 use kerrno::LinuxResult;
 use khal::{
@@ -109,6 +111,8 @@ pub fn init_memory_management() {
     unsafe { khal::asm::write_kernel_page_table(root) };
     // flush all TLB
     khal::asm::flush_tlb(None);
+    tlb::init();
+    asid::init();
 }
 
 /// Initializes kernel paging for secondary CPUs.
@@ -116,6 +120,7 @@ pub fn init_memory_management_secondary() {
     unsafe { khal::asm::write_kernel_page_table(kernel_page_table_root()) };
     // flush all TLB
     khal::asm::flush_tlb(None);
+    asid::init_secondary();
 }
 
 #[cfg(unittest)]
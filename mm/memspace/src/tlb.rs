@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! TLB shootdown.
+//!
+//! The page table cursors only flush the TLB of the CPU they run on. When
+//! mappings are removed or downgraded, the other CPUs running the same
+//! address space may still hold the old entries, so they are sent the range
+//! to flush through an IPI, and waited for.
+//!
+//! An address space with an ASID has its entries flushed by ASID on every
+//! CPU, as they outlive context switches. One without is told by its page
+//! table root, and the CPUs running another one have nothing to flush, as
+//! switching to it flushes the entries of ASID 0.
+use memaddr::{PhysAddr, VirtAddrRange};
+
+#[cfg(feature = "ipi")]
+mod shootdown {
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use kbuild_config::CPU_NUM;
+    use khal::hotplug::{CPU_NOTIFIER, CpuEvent};
+    use knotifier::NotifyResult;
+    use memaddr::{PAGE_SIZE_4K, PhysAddr, VirtAddrRange, va};
+
+    /// Above this many pages, the whole TLB is flushed instead.
+    const FLUSH_ALL_THRESHOLD: usize = 32;
+
+    /// The CPUs taking IPIs.
+    static ONLINE: [AtomicBool; CPU_NUM] = [const { AtomicBool::new(false) }; CPU_NUM];
+
+    pub fn init() {
+        CPU_NOTIFIER.register(0, |event| {
+            match *event {
+                CpuEvent::Online(cpu) => ONLINE[cpu].store(true, Ordering::Release),
+                CpuEvent::Offline(cpu) => ONLINE[cpu].store(false, Ordering::Release),
+            }
+            NotifyResult::Ok
+        });
+    }
+
+    fn current_root() -> PhysAddr {
+        #[cfg(target_arch = "aarch64")]
+        {
+            khal::asm::user_pt_root()
+        }
+        #[cfg(not(target_arch = "aarch64"))]
+        {
+            khal::asm::read_user_page_table()
+        }
+    }
+
+    /// Flushes `range` from the TLB of the current CPU.
+    fn flush_local(range: VirtAddrRange, asid: u16) {
+        let flush = |vaddr| match asid {
+            0 => khal::asm::flush_tlb(vaddr),
+            _ => khal::asm::flush_tlb_asid(vaddr, asid),
+        };
+        if range.size() / PAGE_SIZE_4K > FLUSH_ALL_THRESHOLD {
+            flush(None);
+        } else {
+            let mut vaddr = range.start;
+            while vaddr < range.end {
+                flush(Some(vaddr));
+                vaddr += PAGE_SIZE_4K;
+            }
+        }
+    }
+
+    pub fn flush_range(root: PhysAddr, asid: u16, range: VirtAddrRange) {
+        // Kernel mappings are shared by all the page tables.
+        let global = range.start >= va!(kbuild_config::KERNEL_ASPACE_BASE);
+        let pending = Arc::new(AtomicUsize::new(0));
+        for (cpu, online) in ONLINE.iter().enumerate() {
+            if !online.load(Ordering::Acquire) {
+                continue;
+            }
+            pending.fetch_add(1, Ordering::AcqRel);
+            let ack = pending.clone();
+            let sent = kipi::run_on_cpu(cpu, move || {
+                if asid != 0 || global || current_root() == root {
+                    flush_local(range, asid);
+                }
+                ack.fetch_sub(1, Ordering::Release);
+            });
+            if sent.is_err() {
+                pending.fetch_sub(1, Ordering::AcqRel);
+            }
+        }
+        while pending.load(Ordering::Acquire) != 0 {
+            // Another CPU may be waiting on us with the interrupts disabled
+            // as well.
+            if !khal::asm::is_enabled() {
+                kipi::ipi_handler();
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Starts tracking the CPUs to send shootdowns to.
+pub(crate) fn init() {
+    #[cfg(feature = "ipi")]
+    shootdown::init();
+}
+
+/// Flushes `range` of the address space with page table `root` and `asid`
+/// from the TLB of every CPU, once the page table no longer maps it as it
+/// did.
+///
+/// Returns when all the CPUs are done. Without IPIs, only the current CPU
+/// is flushed, by the page table cursor.
+#[cfg_attr(not(feature = "ipi"), allow(unused_variables))]
+pub(crate) fn flush_range(root: PhysAddr, asid: u16, range: VirtAddrRange) {
+    #[cfg(feature = "ipi")]
+    if !range.is_empty() {
+        shootdown::flush_range(root, asid, range);
+    }
+}
@@ -118,7 +118,8 @@ impl From<PagingFlags> for Arm64Attr {
             a |= Self::UXN | Self::PXN;
         }
         if f.contains(PagingFlags::USER) {
-            a |= Self::AP_EL0;
+            // Tagged with the ASID of the address space.
+            a |= Self::AP_EL0 | Self::NG;
         }
         let mem_attr = if f.contains(PagingFlags::DEVICE) {
             Arm64MemAttr::Device
@@ -11,7 +11,7 @@ use memaddr::{PhysAddr, VirtAddr};
 pub use x86_64::structures::paging::page_table::PageTableFlags as PTF;
 
 use crate::{
-    defs::{PageTableEntry, PagingFlags, PagingMetaData},
+    defs::{PageSize, PageTableEntry, PagingFlags, PagingMetaData},
     table64::{PageTable64, PageTableMut},
 };
 
@@ -193,6 +193,17 @@ impl PagingMetaData for X64PagingMetaData {
             x86_64::instructions::tlb::flush_all();
         }
     }
+
+    /// 1G pages need the `pdpe1gb` CPU feature.
+    fn max_page_size() -> PageSize {
+        // SAFETY: the extended leaves are there on every x86_64 CPU.
+        let ext = unsafe { core::arch::x86_64::__cpuid(0x8000_0001) };
+        if ext.edx & (1 << 26) != 0 {
+            PageSize::Size1G
+        } else {
+            PageSize::Size2M
+        }
+    }
 }
 
 pub type X64PageTable<H> = PageTable64<X64PagingMetaData, X64PageEntry, H>;
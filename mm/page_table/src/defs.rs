@@ -83,6 +83,11 @@ pub trait PagingMetaData: Sync + Send {
     }
 
     fn flush_tlb(vaddr: Option<Self::VirtAddr>);
+
+    /// Returns the largest page size the hardware can map.
+    fn max_page_size() -> PageSize {
+        PageSize::Size1G
+    }
 }
 
 /// Hooks for allocating and mapping page table frames.
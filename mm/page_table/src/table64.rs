@@ -65,6 +65,17 @@ impl<M: PagingMetaData, PTE: PageTableEntry, H: PagingHandler> PageTable64<M, PT
         Ok((entry.paddr().add(off), entry.flags(), size))
     }
 
+    /// Returns how much of the region `[start, start + size)` is mapped.
+    ///
+    /// Unlike [`query`](Self::query) on every page, the whole range of an
+    /// empty upper-level entry is skipped at once.
+    pub fn mapped_size(&self, start: M::VirtAddr, size: usize) -> usize {
+        let start: usize = start.into();
+        let end = start.saturating_add(size);
+        let shift = 12 + 9 * (M::LEVELS - 1);
+        self.mapped_size_in(self.root_paddr, shift, start, end)
+    }
+
     /// Create a mutable mapping view that tracks TLB flushes.
     pub fn modify(&mut self) -> PageTableMut<'_, M, PTE, H> {
         PageTableMut::new(self)
@@ -124,6 +135,29 @@ impl<M: PagingMetaData, PTE: PageTableEntry, H: PagingHandler> PageTable64<M, PT
         Ok((p1e, PageSize::Size4K))
     }
 
+    /// Returns how much of `[start, end)` the table at `table` maps, each of
+    /// its entries covering `1 << shift` bytes.
+    fn mapped_size_in(&self, table: PhysAddr, shift: usize, start: usize, end: usize) -> usize {
+        let entries = self.table_of(table);
+        let mut mapped = 0;
+        let mut vaddr = start;
+        while vaddr < end {
+            let next = ((vaddr >> shift) + 1)
+                .checked_shl(shift as u32)
+                .map_or(end, |next| next.min(end));
+            let entry = &entries[(vaddr >> shift) & (ENTRY_COUNT - 1)];
+            if shift == 12 || entry.is_huge() {
+                if entry.is_present() {
+                    mapped += next - vaddr;
+                }
+            } else if entry.paddr().as_usize() != 0 {
+                mapped += self.mapped_size_in(entry.paddr(), shift - 9, vaddr, next);
+            }
+            vaddr = next;
+        }
+        mapped
+    }
+
     fn dealloc_tree(&self, table_paddr: PhysAddr, level: usize) {
         if level < M::LEVELS - 1 {
             for entry in self.table_of(table_paddr) {
@@ -349,11 +383,13 @@ impl<'a, M: PagingMetaData, PTE: PageTableEntry, H: PagingHandler> PageTableMut<
         if !PageSize::Size4K.is_aligned(vaddr_val) || !PageSize::Size4K.is_aligned(rem_size) {
             return Err(PtError::NotAligned);
         }
+        let max_size = M::max_page_size();
         while rem_size > 0 {
             let v_addr = vaddr_val.into();
             let p_addr = phys_getter(v_addr);
             let p_size = if allow_huge {
-                if PageSize::Size1G.is_aligned(vaddr_val)
+                if max_size == PageSize::Size1G
+                    && PageSize::Size1G.is_aligned(vaddr_val)
                     && p_addr.is_aligned(PageSize::Size1G)
                     && rem_size >= PageSize::Size1G as usize
                 {
@@ -377,11 +413,13 @@ impl<'a, M: PagingMetaData, PTE: PageTableEntry, H: PagingHandler> PageTableMut<
         Ok(())
     }
 
+    /// Unmaps a region, splitting the huge pages it covers in part.
     pub fn unmap_region(&mut self, vaddr: M::VirtAddr, size: usize) -> PtResult {
         let mut vaddr_val: usize = vaddr.into();
         let mut rem_size = size;
         while rem_size > 0 {
             let v_addr = vaddr_val.into();
+            self.split_to_fit(v_addr, rem_size)?;
             let (_, _, p_size) = self.unmap(v_addr)?;
             vaddr_val += p_size as usize;
             rem_size -= p_size as usize;
@@ -389,6 +427,8 @@ impl<'a, M: PagingMetaData, PTE: PageTableEntry, H: PagingHandler> PageTableMut<
         Ok(())
     }
 
+    /// Changes the flags of a region, splitting the huge pages it covers in
+    /// part.
     pub fn protect_region(
         &mut self,
         vaddr: M::VirtAddr,
@@ -399,6 +439,7 @@ impl<'a, M: PagingMetaData, PTE: PageTableEntry, H: PagingHandler> PageTableMut<
         let mut rem_size = size;
         while rem_size > 0 {
             let v_addr = vaddr_val.into();
+            self.split_to_fit(v_addr, rem_size)?;
             let p_size = match self.protect(v_addr, flags) {
                 Ok(s) => s,
                 Err(PtError::NotMapped) => PageSize::Size4K,
@@ -410,6 +451,36 @@ impl<'a, M: PagingMetaData, PTE: PageTableEntry, H: PagingHandler> PageTableMut<
         Ok(())
     }
 
+    /// Splits the huge page mapping `vaddr` until the page there starts at
+    /// `vaddr` and spans at most `size` bytes.
+    fn split_to_fit(&mut self, vaddr: M::VirtAddr, size: usize) -> PtResult {
+        let vaddr_val: usize = vaddr.into();
+        loop {
+            let (entry, page_size) = match self.get_entry(vaddr) {
+                Ok((entry, page_size)) => (*entry, page_size),
+                Err(_) => return Ok(()),
+            };
+            if !page_size.is_huge()
+                || (page_size.is_aligned(vaddr_val) && size >= page_size as usize)
+            {
+                return Ok(());
+            }
+            let sub_size = match page_size {
+                PageSize::Size1G => PageSize::Size2M,
+                _ => PageSize::Size4K,
+            };
+            let table_paddr = PageTable64::<M, PTE, H>::alloc_table()?;
+            let flags = entry.flags();
+            for (i, sub) in self.table_of_mut(table_paddr).iter_mut().enumerate() {
+                let paddr = entry.paddr().add(i * sub_size as usize);
+                *sub = PageTableEntry::new_page(paddr, flags, sub_size.is_huge());
+            }
+            let (entry, _) = self.get_entry_mut(vaddr)?;
+            *entry = PageTableEntry::new_table(table_paddr);
+            self.flush(vaddr);
+        }
+    }
+
     #[cfg(feature = "copy-from")]
     pub fn copy_from(&mut self, other: &PageTable64<M, PTE, H>, start: M::VirtAddr, size: usize) {
         if size == 0 {
@@ -0,0 +1,135 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Request handling.
+use alloc::{format, string::String, vec, vec::Vec};
+
+use kerrno::{KError, KResult, LinuxError};
+use kfs::{File, OpenOptions};
+
+use crate::{
+    COMMANDS,
+    protocol::{MAX_CHUNK_LEN, Request},
+};
+
+/// What to do once the reply has been sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    None,
+    Shutdown,
+    Reboot,
+}
+
+/// The outcome of a request.
+pub struct Reply {
+    /// `0` or a Linux errno.
+    pub status: u32,
+    pub body: Vec<u8>,
+    pub action: Action,
+}
+
+impl Reply {
+    fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: 0,
+            body: body.into(),
+            action: Action::None,
+        }
+    }
+
+    fn error(err: KError, message: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: LinuxError::from(err).into_raw() as u32,
+            body: message.into(),
+            action: Action::None,
+        }
+    }
+
+    fn then(mut self, action: Action) -> Self {
+        self.action = action;
+        self
+    }
+}
+
+impl From<KResult<Vec<u8>>> for Reply {
+    fn from(result: KResult<Vec<u8>>) -> Self {
+        match result {
+            Ok(body) => Self::ok(body),
+            Err(err) => Self::error(err, format!("{err}")),
+        }
+    }
+}
+
+/// Handles `request`.
+pub fn handle(request: &Request) -> Reply {
+    match *request {
+        Request::Ping => Reply::ok(Vec::new()),
+        Request::Info => Reply::ok(info()),
+        Request::Metrics => {
+            let mut out = String::new();
+            kmetrics::export(&mut out);
+            Reply::ok(out)
+        }
+        Request::Read { path, offset, len } => read(path, offset, len).into(),
+        Request::Write {
+            path,
+            offset,
+            truncate,
+            data,
+        } => write(path, offset, truncate, data).into(),
+        Request::Exec { command } => exec(command),
+        Request::Shutdown => Reply::ok(Vec::new()).then(Action::Shutdown),
+        Request::Reboot => Reply::ok(Vec::new()).then(Action::Reboot),
+    }
+}
+
+fn info() -> String {
+    format!(
+        "version={}\nuptime_ms={}\ncpus={}\n",
+        env!("CARGO_PKG_VERSION"),
+        khal::time::monotonic_time().as_millis(),
+        kbuild_config::CPU_NUM,
+    )
+}
+
+fn read(path: &str, offset: u64, len: u32) -> KResult<Vec<u8>> {
+    let ctx = kfs::ROOT_FS_CONTEXT.get().ok_or(KError::NotFound)?;
+    let file = File::open(ctx, path)?;
+    let mut buf = vec![0; (len as usize).min(MAX_CHUNK_LEN)];
+    let count = file.read_at(&mut buf[..], offset)?;
+    buf.truncate(count);
+    Ok(buf)
+}
+
+fn write(path: &str, offset: u64, truncate: bool, data: &[u8]) -> KResult<Vec<u8>> {
+    let ctx = kfs::ROOT_FS_CONTEXT.get().ok_or(KError::NotFound)?;
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(truncate)
+        .open(ctx, path)?
+        .into_file()?;
+    let mut written = 0;
+    while written < data.len() {
+        match file.write_at(&data[written..], offset + written as u64)? {
+            0 => return Err(KError::WriteZero),
+            count => written += count,
+        }
+    }
+    Ok((written as u32).to_le_bytes().to_vec())
+}
+
+fn exec(command: &str) -> Reply {
+    let args: Vec<&str> = command.split_whitespace().collect();
+    let Some((&name, args)) = args.split_first() else {
+        return Reply::error(KError::InvalidInput, "empty command");
+    };
+    let Some(command) = COMMANDS.lock().get(name).copied() else {
+        return Reply::error(KError::NotFound, format!("unknown command: {name}"));
+    };
+    match command(args) {
+        Ok(output) => Reply::ok(output),
+        Err(message) => Reply::error(KError::Io, message),
+    }
+}
@@ -0,0 +1,44 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Guest agent for host orchestration over vsock.
+//!
+//! Like `qemu-guest-agent`, it lets the host manage a guest without a
+//! network: query the kernel, transfer files to and from the VFS, run
+//! commands and power the guest off or restart it. The host connects to
+//! [`DEFAULT_PORT`] and exchanges frames described in [`protocol`].
+//!
+//! There are no user-space programs to execute, so `exec` runs commands
+//! that subsystems add with [`register_command`].
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod handler;
+pub mod protocol;
+mod server;
+
+mod test_agent;
+
+use alloc::{collections::BTreeMap, string::String};
+
+use ksync::Mutex;
+pub use server::start;
+
+/// Port served when none is configured.
+pub const DEFAULT_PORT: u32 = 9701;
+
+/// Runs a command with its arguments, returning its output or a
+/// description of the failure.
+pub type Command = fn(&[&str]) -> Result<String, String>;
+
+static COMMANDS: Mutex<BTreeMap<&'static str, Command>> = Mutex::new(BTreeMap::new());
+
+/// Makes `command` available to `exec` as `name`, replacing the previous
+/// command.
+pub fn register_command(name: &'static str, command: Command) {
+    COMMANDS.lock().insert(name, command);
+}
@@ -0,0 +1,171 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Request decoding and response encoding.
+//!
+//! Every message is a frame: a little-endian `u32` length followed by that
+//! many bytes. A request starts with its operation code; a response starts
+//! with a little-endian `u32` status, `0` or a Linux errno, followed by the
+//! result or, on failure, a description of the error.
+use alloc::vec::Vec;
+
+use kerrno::{KError, KResult};
+
+/// Size of the length prefix of a frame.
+pub const FRAME_LEN_SIZE: usize = 4;
+/// Largest chunk of a file read or written by one request.
+pub const MAX_CHUNK_LEN: usize = 1024 * 1024;
+/// Largest frame accepted, enough for a full write chunk.
+pub const MAX_FRAME_LEN: usize = MAX_CHUNK_LEN + 4096;
+
+pub const OP_PING: u8 = 0;
+pub const OP_INFO: u8 = 1;
+pub const OP_METRICS: u8 = 2;
+pub const OP_READ: u8 = 3;
+pub const OP_WRITE: u8 = 4;
+pub const OP_EXEC: u8 = 5;
+pub const OP_SHUTDOWN: u8 = 6;
+pub const OP_REBOOT: u8 = 7;
+
+/// Write flag: truncate the file before writing.
+pub const WRITE_TRUNCATE: u8 = 1;
+
+/// A decoded request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Request<'a> {
+    /// Answered with an empty success.
+    Ping,
+    /// Kernel version, uptime and CPU count as `key=value` lines.
+    Info,
+    /// Metrics in the Prometheus text format.
+    Metrics,
+    /// Up to `len` bytes of `path` from `offset`: `offset: u64`, `len: u32`,
+    /// then the path.
+    Read {
+        path: &'a str,
+        offset: u64,
+        len: u32,
+    },
+    /// Writes `data` to `path` at `offset`, creating the file: `offset: u64`,
+    /// `flags: u8`, `path_len: u16`, the path, then the data. Answered with
+    /// the number of bytes written as a `u32`.
+    Write {
+        path: &'a str,
+        offset: u64,
+        truncate: bool,
+        data: &'a [u8],
+    },
+    /// Runs a registered command: its name and arguments separated by
+    /// whitespace.
+    Exec { command: &'a str },
+    /// Powers the guest off once answered.
+    Shutdown,
+    /// Restarts the guest once answered.
+    Reboot,
+}
+
+/// Reads the body of a frame.
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> KResult<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(KError::InvalidData);
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> KResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> KResult<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> KResult<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> KResult<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn str(&mut self, len: usize) -> KResult<&'a str> {
+        core::str::from_utf8(self.take(len)?).map_err(|_| KError::InvalidData)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        core::mem::take(&mut self.0)
+    }
+}
+
+impl<'a> Request<'a> {
+    /// Decodes the body of a request frame.
+    pub fn parse(body: &'a [u8]) -> KResult<Self> {
+        let mut cursor = Cursor(body);
+        let request = match cursor.u8()? {
+            OP_PING => Self::Ping,
+            OP_INFO => Self::Info,
+            OP_METRICS => Self::Metrics,
+            OP_READ => {
+                let offset = cursor.u64()?;
+                let len = cursor.u32()?;
+                let path = cursor.str(cursor.0.len())?;
+                Self::Read { path, offset, len }
+            }
+            OP_WRITE => {
+                let offset = cursor.u64()?;
+                let flags = cursor.u8()?;
+                let path_len = cursor.u16()? as usize;
+                let path = cursor.str(path_len)?;
+                let data = cursor.rest();
+                if data.len() > MAX_CHUNK_LEN {
+                    return Err(KError::InvalidInput);
+                }
+                Self::Write {
+                    path,
+                    offset,
+                    truncate: flags & WRITE_TRUNCATE != 0,
+                    data,
+                }
+            }
+            OP_EXEC => Self::Exec {
+                command: cursor.str(cursor.0.len())?,
+            },
+            OP_SHUTDOWN => Self::Shutdown,
+            OP_REBOOT => Self::Reboot,
+            _ => return Err(KError::Unsupported),
+        };
+        if !cursor.0.is_empty() {
+            return Err(KError::InvalidData);
+        }
+        Ok(request)
+    }
+}
+
+/// Returns the length of the first frame in `buf` with its prefix, or
+/// `None` if more data is needed.
+pub fn frame_len(buf: &[u8]) -> KResult<Option<usize>> {
+    let Some(prefix) = buf.get(..FRAME_LEN_SIZE) else {
+        return Ok(None);
+    };
+    let len = u32::from_le_bytes(prefix.try_into().unwrap()) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(KError::InvalidInput);
+    }
+    let total = FRAME_LEN_SIZE + len;
+    Ok((buf.len() >= total).then_some(total))
+}
+
+/// Encodes a response frame with `status` and `body`.
+pub fn encode_response(status: u32, body: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(FRAME_LEN_SIZE + 4 + body.len());
+    frame.extend_from_slice(&((4 + body.len()) as u32).to_le_bytes());
+    frame.extend_from_slice(&status.to_le_bytes());
+    frame.extend_from_slice(body);
+    frame
+}
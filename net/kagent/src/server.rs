@@ -0,0 +1,126 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Connection handling.
+//!
+//! A task accepts connections from the host and hands each to a task of its
+//! own, which answers requests in order until the host closes it.
+use alloc::{boxed::Box, string::ToString, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use kerrno::{KError, KResult};
+use knet::{
+    RecvOptions, SendOptions, Shutdown, Socket, SocketAddrEx, SocketOps,
+    vsock::{VsockAddr, VsockSocket, VsockStreamTransport},
+};
+
+use crate::{
+    handler::{Action, Reply, handle},
+    protocol::{FRAME_LEN_SIZE, Request, encode_response, frame_len},
+};
+
+/// Connections waiting to be accepted.
+const BACKLOG: usize = 4;
+/// Accepts connections from any CID.
+const VMADDR_CID_ANY: u64 = u32::MAX as u64;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Starts serving the host on vsock `port`.
+pub fn start(port: u32) -> KResult {
+    if RUNNING.swap(true, Ordering::AcqRel) {
+        return Err(KError::AlreadyExists);
+    }
+    let listener = Socket::Vsock(Box::new(VsockSocket::new(VsockStreamTransport::new())));
+    let addr = VsockAddr {
+        cid: VMADDR_CID_ANY,
+        port,
+    };
+    let listening = listener
+        .bind(SocketAddrEx::Vsock(addr))
+        .and_then(|_| listener.listen(BACKLOG));
+    if let Err(err) = listening {
+        RUNNING.store(false, Ordering::Release);
+        return Err(err);
+    }
+    info!("agent: listening on vsock port {}", port);
+    ktask::spawn_with_name(move || accept_loop(listener), "agent".to_string());
+    Ok(())
+}
+
+fn accept_loop(listener: Socket) {
+    loop {
+        let socket = match listener.accept() {
+            Ok(socket) => socket,
+            Err(err) => {
+                warn!("agent: accept failed: {:?}", err);
+                continue;
+            }
+        };
+        ktask::spawn_with_name(
+            move || {
+                if let Err(err) = serve(&socket) {
+                    debug!("agent: connection closed: {:?}", err);
+                }
+                let _ = socket.shutdown(Shutdown::Both);
+            },
+            "agent-conn".to_string(),
+        );
+    }
+}
+
+/// Receives more data into `buf`, failing with `ENOTCONN` at end of stream.
+fn fill(socket: &Socket, buf: &mut Vec<u8>) -> KResult {
+    let mut chunk = [0u8; 4096];
+    let len = socket.recv(&mut chunk[..], RecvOptions::default())?;
+    if len == 0 {
+        return Err(KError::NotConnected);
+    }
+    buf.extend_from_slice(&chunk[..len]);
+    Ok(())
+}
+
+fn send_all(socket: &Socket, mut data: &[u8]) -> KResult {
+    while !data.is_empty() {
+        let len = socket.send(data, SendOptions::default())?;
+        data = &data[len..];
+    }
+    Ok(())
+}
+
+fn serve(socket: &Socket) -> KResult {
+    let mut buf = Vec::new();
+    loop {
+        let len = loop {
+            if let Some(len) = frame_len(&buf)? {
+                break len;
+            }
+            match fill(socket, &mut buf) {
+                Ok(()) => {}
+                // The host went away between requests
+                Err(KError::NotConnected) if buf.is_empty() => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        };
+        let reply = match Request::parse(&buf[FRAME_LEN_SIZE..len]) {
+            Ok(request) => handle(&request),
+            Err(err) => Reply::from(Err(err)),
+        };
+        debug!("agent: request of {} bytes -> status {}", len, reply.status);
+        buf.drain(..len);
+        send_all(socket, &encode_response(reply.status, &reply.body))?;
+
+        match reply.action {
+            Action::None => {}
+            Action::Shutdown => {
+                info!("agent: shutdown requested by the host");
+                kpower::power_off(kpower::Reason::Host);
+            }
+            Action::Reboot => {
+                info!("agent: reboot requested by the host");
+                kpower::reboot(kpower::RebootMode::Cold, kpower::Reason::Host);
+            }
+        }
+    }
+}
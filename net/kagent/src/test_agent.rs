@@ -0,0 +1,114 @@
+//! Unit tests for the agent protocol and request handling.
+
+#![cfg(unittest)]
+
+use alloc::{string::String, vec, vec::Vec};
+
+use kerrno::{KError, LinuxError};
+use unittest::def_test;
+
+use crate::{
+    handler::{Action, handle},
+    protocol::*,
+    register_command,
+};
+
+fn frame(body: &[u8]) -> Vec<u8> {
+    let mut frame = (body.len() as u32).to_le_bytes().to_vec();
+    frame.extend_from_slice(body);
+    frame
+}
+
+#[def_test]
+fn test_parse_requests() {
+    assert_eq!(Request::parse(&[OP_PING]), Ok(Request::Ping));
+    assert_eq!(Request::parse(&[OP_REBOOT]), Ok(Request::Reboot));
+
+    let mut read = vec![OP_READ];
+    read.extend_from_slice(&16u64.to_le_bytes());
+    read.extend_from_slice(&4096u32.to_le_bytes());
+    read.extend_from_slice(b"/etc/hosts");
+    assert_eq!(
+        Request::parse(&read),
+        Ok(Request::Read {
+            path: "/etc/hosts",
+            offset: 16,
+            len: 4096,
+        })
+    );
+
+    let mut write = vec![OP_WRITE];
+    write.extend_from_slice(&0u64.to_le_bytes());
+    write.push(WRITE_TRUNCATE);
+    write.extend_from_slice(&4u16.to_le_bytes());
+    write.extend_from_slice(b"/tmpdata");
+    assert_eq!(
+        Request::parse(&write),
+        Ok(Request::Write {
+            path: "/tmp",
+            offset: 0,
+            truncate: true,
+            data: b"data",
+        })
+    );
+
+    assert_eq!(Request::parse(&[]), Err(KError::InvalidData));
+    assert_eq!(Request::parse(&[OP_PING, 0]), Err(KError::InvalidData));
+    assert_eq!(Request::parse(&[OP_READ, 1, 2]), Err(KError::InvalidData));
+    assert_eq!(Request::parse(&[0xff]), Err(KError::Unsupported));
+    assert_eq!(Request::parse(&[OP_EXEC, 0xc0]), Err(KError::InvalidData));
+}
+
+#[def_test]
+fn test_frames() {
+    let request = frame(&[OP_INFO]);
+    assert_eq!(frame_len(&request[..3]), Ok(None));
+    assert_eq!(frame_len(&request[..4]), Ok(None));
+    assert_eq!(frame_len(&request), Ok(Some(5)));
+    let too_long = ((MAX_FRAME_LEN + 1) as u32).to_le_bytes();
+    assert_eq!(frame_len(&too_long), Err(KError::InvalidInput));
+
+    assert_eq!(
+        encode_response(2, b"no"),
+        [6, 0, 0, 0, 2, 0, 0, 0, b'n', b'o']
+    );
+}
+
+fn echo(args: &[&str]) -> Result<String, String> {
+    match args {
+        [] => Err("nothing to echo".into()),
+        args => Ok(args.join(" ")),
+    }
+}
+
+#[def_test]
+fn test_exec() {
+    register_command("echo", echo);
+
+    let reply = handle(&Request::Exec {
+        command: "  echo hello   world ",
+    });
+    assert_eq!(
+        (reply.status, reply.body.as_slice()),
+        (0, &b"hello world"[..])
+    );
+
+    let reply = handle(&Request::Exec { command: "echo" });
+    assert_eq!(reply.status, LinuxError::EIO.into_raw() as u32);
+    assert_eq!(reply.body, b"nothing to echo");
+
+    let reply = handle(&Request::Exec { command: "missing" });
+    assert_eq!(reply.status, LinuxError::ENOENT.into_raw() as u32);
+    assert_eq!(
+        handle(&Request::Exec { command: " " }).status,
+        LinuxError::EINVAL.into_raw() as u32
+    );
+}
+
+#[def_test]
+fn test_power_actions() {
+    let reply = handle(&Request::Ping);
+    assert_eq!((reply.status, reply.action), (0, Action::None));
+    assert_eq!(handle(&Request::Shutdown).action, Action::Shutdown);
+    assert_eq!(handle(&Request::Reboot).action, Action::Reboot);
+}
@@ -0,0 +1,326 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! HTTP/1.1 message parsing and serialization.
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+use kerrno::{KError, KResult};
+use kio::Write;
+
+/// Largest request head accepted.
+pub const MAX_HEAD_LEN: usize = 8 * 1024;
+/// Largest request body accepted.
+pub const MAX_BODY_LEN: usize = 64 * 1024;
+/// Bytes buffered before a chunk is sent.
+const CHUNK_LEN: usize = 1024;
+
+/// Protocol version of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+/// A parsed request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// Path of the target, without the query.
+    pub path: String,
+    pub query: Option<String>,
+    pub version: Version,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Returns the value of the header `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns whether the client asked to keep the connection open, which
+    /// is the default from HTTP/1.1 on.
+    pub fn keep_alive(&self) -> bool {
+        let connection = self.header("Connection");
+        let has = |token: &str| {
+            connection.is_some_and(|it| {
+                it.split(',')
+                    .any(|it| it.trim().eq_ignore_ascii_case(token))
+            })
+        };
+        match self.version {
+            Version::Http10 => has("keep-alive"),
+            Version::Http11 => !has("close"),
+        }
+    }
+
+    /// Returns the length of the body announced in the head.
+    pub fn content_length(&self) -> KResult<usize> {
+        if self.header("Transfer-Encoding").is_some() {
+            // Chunked request bodies are not supported
+            return Err(KError::Unsupported);
+        }
+        match self.header("Content-Length") {
+            None => Ok(0),
+            Some(len) => len.trim().parse().map_err(|_| KError::InvalidData),
+        }
+    }
+}
+
+/// Parses the head of a request from the start of `buf`.
+///
+/// Returns `None` if the head is incomplete, otherwise the request, with an
+/// empty body, and the length of the head. Fails with `EINVAL` on malformed
+/// requests.
+pub fn parse_head(buf: &[u8]) -> KResult<Option<(Request, usize)>> {
+    let Some(end) = buf.windows(4).position(|it| it == b"\r\n\r\n") else {
+        return Ok(None);
+    };
+    let head = str::from_utf8(&buf[..end]).map_err(|_| KError::InvalidData)?;
+    let mut lines = head.split("\r\n");
+
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(target), Some(version), None) = (
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+    ) else {
+        return Err(KError::InvalidData);
+    };
+    if method.is_empty() || !method.bytes().all(|it| it.is_ascii_uppercase()) {
+        return Err(KError::InvalidData);
+    }
+    let version = match version {
+        "HTTP/1.0" => Version::Http10,
+        "HTTP/1.1" => Version::Http11,
+        _ => return Err(KError::InvalidData),
+    };
+    if !target.starts_with('/') {
+        return Err(KError::InvalidData);
+    }
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query.to_string())),
+        None => (target, None),
+    };
+
+    let mut headers = Vec::new();
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or(KError::InvalidData)?;
+        if name.is_empty() || name.contains(' ') {
+            return Err(KError::InvalidData);
+        }
+        headers.push((name.to_string(), value.trim().to_string()));
+    }
+
+    let request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query,
+        version,
+        headers,
+        body: Vec::new(),
+    };
+    Ok(Some((request, end + 4)))
+}
+
+/// Produces a streamed body into a [`ChunkWriter`].
+pub type BodyStream = Box<dyn FnOnce(&mut ChunkWriter<'_>) + Send>;
+
+/// Body of a response.
+pub enum Body {
+    Full(Vec<u8>),
+    /// Sent with chunked transfer coding, the length being unknown up
+    /// front.
+    Stream(BodyStream),
+}
+
+/// A response to send.
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Body,
+}
+
+impl Response {
+    /// Creates a response with a plain text body.
+    pub fn text(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            headers: Vec::from([(
+                "Content-Type".to_string(),
+                "text/plain; charset=utf-8".to_string(),
+            )]),
+            body: Body::Full(body.into().into_bytes()),
+        }
+    }
+
+    /// Creates a response whose body is written by `f` as it is sent.
+    pub fn stream(
+        status: u16,
+        content_type: &str,
+        f: impl FnOnce(&mut ChunkWriter<'_>) + Send + 'static,
+    ) -> Self {
+        Self {
+            status,
+            headers: Vec::from([("Content-Type".to_string(), content_type.to_string())]),
+            body: Body::Stream(Box::new(f)),
+        }
+    }
+
+    /// Creates an error response carrying the reason phrase of `status`.
+    pub fn error(status: u16) -> Self {
+        Self::text(status, format!("{} {}\n", status, reason(status)))
+    }
+
+    /// Adds a header.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Writes the response to `out`.
+    ///
+    /// Streamed bodies are chunked for HTTP/1.1 clients. HTTP/1.0 clients get
+    /// them as is, and the connection has to be closed to mark their end.
+    /// `HEAD` requests get the head only.
+    pub fn write_to(
+        self,
+        out: &mut dyn Write,
+        version: Version,
+        keep_alive: bool,
+        head_only: bool,
+    ) -> KResult {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        let chunked = version == Version::Http11;
+        match &self.body {
+            Body::Full(body) => head.push_str(&format!("Content-Length: {}\r\n", body.len())),
+            Body::Stream(_) if chunked => head.push_str("Transfer-Encoding: chunked\r\n"),
+            Body::Stream(_) => {}
+        }
+        let close = !keep_alive || (!chunked && matches!(self.body, Body::Stream(_)));
+        head.push_str(if close {
+            "Connection: close\r\n\r\n"
+        } else {
+            "Connection: keep-alive\r\n\r\n"
+        });
+        out.write_all(head.as_bytes())?;
+        if head_only {
+            return Ok(());
+        }
+
+        match self.body {
+            Body::Full(body) => out.write_all(&body),
+            Body::Stream(f) => {
+                let mut writer = ChunkWriter::new(out, chunked);
+                f(&mut writer);
+                writer.finish()
+            }
+        }
+    }
+}
+
+/// Returns the reason phrase of a status code.
+pub fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Content Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
+
+/// Buffers a streamed body and sends it in chunks.
+///
+/// Handlers write to it with [`write!`]. The first I/O error is kept and
+/// fails the remaining writes.
+pub struct ChunkWriter<'a> {
+    out: &'a mut dyn Write,
+    chunked: bool,
+    buf: Vec<u8>,
+    error: Option<KError>,
+}
+
+impl<'a> ChunkWriter<'a> {
+    /// Creates a writer to `out`, chunking the data if `chunked` is set.
+    pub fn new(out: &'a mut dyn Write, chunked: bool) -> Self {
+        Self {
+            out,
+            chunked,
+            buf: Vec::with_capacity(CHUNK_LEN),
+            error: None,
+        }
+    }
+
+    /// Appends data to the body.
+    pub fn write_bytes(&mut self, data: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK_LEN {
+            self.flush_chunk();
+        }
+    }
+
+    fn flush_chunk(&mut self) {
+        if self.buf.is_empty() || self.error.is_some() {
+            return;
+        }
+        let result = if self.chunked {
+            self.out
+                .write_all(format!("{:x}\r\n", self.buf.len()).as_bytes())
+                .and_then(|_| self.out.write_all(&self.buf))
+                .and_then(|_| self.out.write_all(b"\r\n"))
+        } else {
+            self.out.write_all(&self.buf)
+        };
+        self.buf.clear();
+        self.error = result.err();
+    }
+
+    /// Sends the buffered data and, if chunking, the last chunk.
+    pub fn finish(mut self) -> KResult {
+        self.flush_chunk();
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        if self.chunked {
+            self.out.write_all(b"0\r\n\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Write for ChunkWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        if self.error.is_some() {
+            return Err(fmt::Error);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! In-kernel HTTP/1.1 server for diagnostics endpoints.
+//!
+//! Meant for monitoring a fleet of instances, it serves:
+//!
+//! - `/metrics`: kernel metrics in the Prometheus text format, see
+//!   [`kmetrics`];
+//! - `/healthz`: `200 ok` while every registered health check passes, `503`
+//!   and the failures otherwise;
+//! - `/`: the list of endpoints.
+//!
+//! Subsystems add endpoints with [`register_endpoint`], health checks with
+//! [`register_health_check`] and metrics with [`kmetrics::register`]. Only
+//! `GET` and `HEAD` requests are served; connections are kept alive and
+//! streamed bodies are chunked.
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+pub mod http;
+mod metrics;
+mod server;
+
+mod test_http;
+
+use alloc::{format, string::String, vec::Vec};
+
+use kerrno::KResult;
+use ksync::Mutex;
+pub use server::{Handler, dispatch, endpoints, register_endpoint};
+
+use crate::http::{Request, Response};
+
+/// Port served when none is configured.
+pub const DEFAULT_PORT: u16 = 9100;
+
+/// Checks the health of a subsystem, describing the problem on failure.
+pub type HealthCheck = fn() -> Result<(), String>;
+
+static HEALTH_CHECKS: Mutex<Vec<(&'static str, HealthCheck)>> = Mutex::new(Vec::new());
+
+/// Adds a check to `/healthz`.
+pub fn register_health_check(name: &'static str, check: HealthCheck) {
+    HEALTH_CHECKS.lock().push((name, check));
+}
+
+fn healthz(_request: &Request) -> Response {
+    let checks = HEALTH_CHECKS.lock().clone();
+    let failures: Vec<_> = checks
+        .into_iter()
+        .filter_map(|(name, check)| check().err().map(|err| format!("{name}: {err}\n")))
+        .collect();
+    if failures.is_empty() {
+        Response::text(200, "ok\n")
+    } else {
+        Response::text(503, failures.concat())
+    }
+}
+
+fn metrics(_request: &Request) -> Response {
+    Response::stream(200, "text/plain; version=0.0.4", |out| {
+        kmetrics::export(out)
+    })
+}
+
+fn index(_request: &Request) -> Response {
+    let mut body = String::new();
+    for path in endpoints() {
+        body.push_str(&path);
+        body.push('\n');
+    }
+    Response::text(200, body)
+}
+
+/// Starts serving the diagnostics endpoints on `port`.
+pub fn start(port: u16) -> KResult {
+    register_endpoint("/", index);
+    register_endpoint("/healthz", healthz);
+    register_endpoint("/metrics", metrics);
+    metrics::register();
+    server::start(port)
+}
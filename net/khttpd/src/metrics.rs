@@ -0,0 +1,114 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Metrics of the server and of the mounted file systems.
+//!
+//! They are exported through [`kmetrics`] along with those of the other
+//! subsystems.
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use fs_ng_vfs::{MountStatsSnapshot, Mountpoint};
+use kmetrics::{Kind, MetricsWriter};
+
+/// Counters of the server itself.
+pub(crate) struct ServerStats {
+    pub requests: AtomicU64,
+    pub errors: AtomicU64,
+    pub connections: AtomicU64,
+    pub rejected: AtomicU64,
+}
+
+pub(crate) static SERVER_STATS: ServerStats = ServerStats {
+    requests: AtomicU64::new(0),
+    errors: AtomicU64::new(0),
+    connections: AtomicU64::new(0),
+    rejected: AtomicU64::new(0),
+};
+
+/// Adds the collectors of this crate to the export.
+pub(crate) fn register() {
+    kmetrics::register_collector(collect_server);
+    kmetrics::register_collector(collect_mounts);
+}
+
+fn collect_server(writer: &mut MetricsWriter) {
+    let stats = &SERVER_STATS;
+    writer.single(
+        "xkernel_http_requests_total",
+        Kind::Counter,
+        "Requests served by the diagnostics server.",
+        stats.requests.load(Ordering::Relaxed),
+    );
+    writer.single(
+        "xkernel_http_errors_total",
+        Kind::Counter,
+        "Requests answered with a 4xx or 5xx status.",
+        stats.errors.load(Ordering::Relaxed),
+    );
+    writer.single(
+        "xkernel_http_connections",
+        Kind::Gauge,
+        "Open connections to the diagnostics server.",
+        stats.connections.load(Ordering::Relaxed),
+    );
+    writer.single(
+        "xkernel_http_rejected_total",
+        Kind::Counter,
+        "Connections refused for lack of capacity.",
+        stats.rejected.load(Ordering::Relaxed),
+    );
+}
+
+/// Reads a counter from the stats of a mount.
+type MountCounter = fn(&MountStatsSnapshot) -> u64;
+
+fn collect_mounts(writer: &mut MetricsWriter) {
+    fn walk(mp: &Arc<Mountpoint>, out: &mut Vec<(String, MountStatsSnapshot)>) {
+        let path = mp
+            .root_location()
+            .absolute_path()
+            .map(|it| it.to_string())
+            .unwrap_or_default();
+        out.push((path, mp.stats().snapshot()));
+        for child in mp.child_mounts() {
+            walk(&child, out);
+        }
+    }
+
+    let Some(ctx) = kfs::ROOT_FS_CONTEXT.get() else {
+        return;
+    };
+    let mut mounts = Vec::new();
+    walk(ctx.root_dir().mountpoint(), &mut mounts);
+
+    let families: [(&str, &str, MountCounter); 9] = [
+        ("lookups", "Name lookups.", |it| it.lookups),
+        (
+            "dentry_hits",
+            "Lookups answered by the dentry cache.",
+            |it| it.dentry_hits,
+        ),
+        ("page_hits", "Page cache hits.", |it| it.page_hits),
+        ("page_misses", "Page cache misses.", |it| it.page_misses),
+        ("readahead_pages", "Pages read ahead.", |it| {
+            it.readahead_pages
+        }),
+        ("reads", "Read operations.", |it| it.reads),
+        ("read_bytes", "Bytes read.", |it| it.read_bytes),
+        ("writes", "Write operations.", |it| it.writes),
+        ("write_bytes", "Bytes written.", |it| it.write_bytes),
+    ];
+    for (suffix, help, get) in families {
+        let name = alloc::format!("xkernel_mount_{suffix}_total");
+        writer.family(&name, Kind::Counter, help);
+        for (path, stats) in &mounts {
+            writer.sample(&name, &[("mountpoint", path)], get(stats));
+        }
+    }
+}
@@ -0,0 +1,206 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Connection handling.
+//!
+//! A task accepts connections and hands each to a task of its own, which
+//! serves requests until the client closes the connection, asks to, or
+//! stays idle for [`IDLE_TIMEOUT`].
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use kerrno::{KError, KResult};
+use kio::Write;
+use knet::{
+    RecvOptions, SendOptions, Shutdown, Socket, SocketAddrEx, SocketOps,
+    options::{Configurable, SetSocketOption},
+    tcp::TcpSocket,
+};
+use ksync::Mutex;
+
+use crate::{
+    http::{MAX_BODY_LEN, MAX_HEAD_LEN, Request, Response, Version, parse_head},
+    metrics::SERVER_STATS,
+};
+
+/// Connections served at once; more are refused with status 503.
+const MAX_CONNECTIONS: u64 = 8;
+/// Requests served over a connection before it is closed.
+const MAX_REQUESTS: usize = 100;
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Handles requests to an endpoint.
+pub type Handler = Arc<dyn Fn(&Request) -> Response + Send + Sync>;
+
+static ENDPOINTS: Mutex<BTreeMap<String, Handler>> = Mutex::new(BTreeMap::new());
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Serves `path` with `handler`, replacing the previous handler.
+pub fn register_endpoint(
+    path: &str,
+    handler: impl Fn(&Request) -> Response + Send + Sync + 'static,
+) {
+    ENDPOINTS.lock().insert(path.to_string(), Arc::new(handler));
+}
+
+/// Returns the paths of all endpoints.
+pub fn endpoints() -> Vec<String> {
+    ENDPOINTS.lock().keys().cloned().collect()
+}
+
+/// Returns the response to `request`.
+pub fn dispatch(request: &Request) -> Response {
+    if request.method != "GET" && request.method != "HEAD" {
+        return Response::error(405).with_header("Allow", "GET, HEAD");
+    }
+    let handler = ENDPOINTS.lock().get(&request.path).cloned();
+    match handler {
+        Some(handler) => handler(request),
+        None => Response::error(404),
+    }
+}
+
+/// Starts serving on `port` of every address of the initial network stack.
+pub fn start(port: u16) -> KResult {
+    if RUNNING.swap(true, Ordering::AcqRel) {
+        return Err(KError::AlreadyExists);
+    }
+    let listener = TcpSocket::new_in(knet::netns::init_stack());
+    let addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port);
+    let listening = listener
+        .bind(SocketAddrEx::Ip(addr))
+        .and_then(|_| listener.listen(MAX_CONNECTIONS as usize));
+    if let Err(err) = listening {
+        RUNNING.store(false, Ordering::Release);
+        return Err(err);
+    }
+    info!("httpd: listening on {}", addr);
+    ktask::spawn_with_name(move || accept_loop(listener), "httpd".to_string());
+    Ok(())
+}
+
+fn accept_loop(listener: TcpSocket) {
+    loop {
+        let socket = match listener.accept() {
+            Ok(socket) => socket,
+            Err(err) => {
+                warn!("httpd: accept failed: {:?}", err);
+                continue;
+            }
+        };
+        let stats = &SERVER_STATS;
+        if stats.connections.fetch_add(1, Ordering::AcqRel) >= MAX_CONNECTIONS {
+            stats.connections.fetch_sub(1, Ordering::AcqRel);
+            stats.rejected.fetch_add(1, Ordering::Relaxed);
+            let mut out = SocketWriter(&socket);
+            let _ = Response::error(503).write_to(&mut out, Version::Http11, false, false);
+            let _ = socket.shutdown(Shutdown::Both);
+            continue;
+        }
+        ktask::spawn_with_name(
+            move || {
+                if let Err(err) = serve(&socket) {
+                    debug!("httpd: connection closed: {:?}", err);
+                }
+                let _ = socket.shutdown(Shutdown::Both);
+                SERVER_STATS.connections.fetch_sub(1, Ordering::AcqRel);
+            },
+            "httpd-conn".to_string(),
+        );
+    }
+}
+
+/// Sends through a socket.
+struct SocketWriter<'a>(&'a Socket);
+
+impl Write for SocketWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> KResult<usize> {
+        self.0.send(buf, SendOptions::default())
+    }
+
+    fn flush(&mut self) -> KResult {
+        Ok(())
+    }
+}
+
+/// Receives more data into `buf`, failing with `ENOTCONN` at end of stream.
+fn fill(socket: &Socket, buf: &mut Vec<u8>) -> KResult {
+    let mut chunk = [0u8; 1024];
+    let len = socket.recv(&mut chunk[..], RecvOptions::default())?;
+    if len == 0 {
+        return Err(KError::NotConnected);
+    }
+    buf.extend_from_slice(&chunk[..len]);
+    Ok(())
+}
+
+fn serve(socket: &Socket) -> KResult {
+    socket.set_option(SetSocketOption::ReceiveTimeout(&IDLE_TIMEOUT))?;
+    let mut out = SocketWriter(socket);
+    let mut buf = Vec::new();
+
+    for served in 1..=MAX_REQUESTS {
+        let (mut request, head_len) = loop {
+            match parse_head(&buf) {
+                Ok(Some(parsed)) => break parsed,
+                Ok(None) if buf.len() < MAX_HEAD_LEN => {}
+                Ok(None) => return reply_error(&mut out, 431),
+                Err(_) => return reply_error(&mut out, 400),
+            }
+            match fill(socket, &mut buf) {
+                Ok(()) => {}
+                // The client went away between requests
+                Err(KError::TimedOut | KError::NotConnected) if buf.is_empty() => return Ok(()),
+                Err(KError::TimedOut) => return reply_error(&mut out, 408),
+                Err(err) => return Err(err),
+            }
+        };
+        let body_len = match request.content_length() {
+            Ok(len) if len <= MAX_BODY_LEN => len,
+            Ok(_) => return reply_error(&mut out, 413),
+            Err(KError::Unsupported) => return reply_error(&mut out, 501),
+            Err(_) => return reply_error(&mut out, 400),
+        };
+        while buf.len() < head_len + body_len {
+            fill(socket, &mut buf)?;
+        }
+        request.body = buf[head_len..head_len + body_len].to_vec();
+        buf.drain(..head_len + body_len);
+
+        let keep_alive = request.keep_alive() && served < MAX_REQUESTS;
+        let response = dispatch(&request);
+        SERVER_STATS.requests.fetch_add(1, Ordering::Relaxed);
+        if response.status >= 400 {
+            SERVER_STATS.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        debug!(
+            "httpd: {} {} -> {}",
+            request.method, request.path, response.status
+        );
+        let streamed = matches!(response.body, crate::http::Body::Stream(_));
+        let head_only = request.method == "HEAD";
+        response.write_to(&mut out, request.version, keep_alive, head_only)?;
+        // HTTP/1.0 clients learn that a stream ended when the connection
+        // closes
+        if !keep_alive || (streamed && request.version == Version::Http10) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn reply_error(out: &mut SocketWriter, status: u16) -> KResult {
+    SERVER_STATS.requests.fetch_add(1, Ordering::Relaxed);
+    SERVER_STATS.errors.fetch_add(1, Ordering::Relaxed);
+    Response::error(status).write_to(out, Version::Http11, false, false)
+}
@@ -0,0 +1,102 @@
+//! Unit tests for request parsing, response encoding and metrics.
+
+#![cfg(unittest)]
+
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+
+use kerrno::KError;
+use kmetrics::{Kind, MetricsWriter};
+use unittest::def_test;
+
+use crate::http::{ChunkWriter, Response, Version, parse_head};
+
+fn response_bytes(response: Response, version: Version, keep_alive: bool) -> String {
+    let mut out = Vec::new();
+    response
+        .write_to(&mut out, version, keep_alive, false)
+        .unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[def_test]
+fn test_parse_head() {
+    let buf = b"GET /metrics?x=1 HTTP/1.1\r\nHost: a\r\nContent-Length: 3\r\n\r\nabc";
+    let (request, len) = parse_head(buf).unwrap().unwrap();
+    assert_eq!(len, buf.len() - 3);
+    assert_eq!(request.method, "GET");
+    assert_eq!(request.path, "/metrics");
+    assert_eq!(request.query.as_deref(), Some("x=1"));
+    assert_eq!(request.header("host"), Some("a"));
+    assert_eq!(request.content_length(), Ok(3));
+    assert!(request.keep_alive());
+
+    assert!(
+        parse_head(b"GET / HTTP/1.1\r\nHost: a\r\n")
+            .unwrap()
+            .is_none()
+    );
+    assert!(parse_head(b"GET / HTTP/2\r\n\r\n").is_err());
+    assert!(parse_head(b"GET foo HTTP/1.1\r\n\r\n").is_err());
+    assert!(parse_head(b"GET / HTTP/1.1\r\nbad header\r\n\r\n").is_err());
+
+    let (request, _) = parse_head(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n")
+        .unwrap()
+        .unwrap();
+    assert_eq!(request.content_length(), Err(KError::Unsupported));
+}
+
+#[def_test]
+fn test_keep_alive() {
+    let parse = |head: &[u8]| parse_head(head).unwrap().unwrap().0;
+    assert!(!parse(b"GET / HTTP/1.1\r\nConnection: Close\r\n\r\n").keep_alive());
+    assert!(!parse(b"GET / HTTP/1.0\r\n\r\n").keep_alive());
+    assert!(parse(b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n").keep_alive());
+}
+
+#[def_test]
+fn test_chunked_response() {
+    let stream = || {
+        Response::stream(200, "text/plain", |out| {
+            let _ = out.write_str("hello ");
+            let _ = out.write_str("world");
+        })
+    };
+    let text = response_bytes(stream(), Version::Http11, true);
+    assert!(text.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(text.contains("Transfer-Encoding: chunked\r\n"));
+    assert!(text.contains("Connection: keep-alive\r\n"));
+    assert!(text.ends_with("\r\n\r\nb\r\nhello world\r\n0\r\n\r\n"));
+
+    // HTTP/1.0 clients get the raw body and a closed connection
+    let text = response_bytes(stream(), Version::Http10, true);
+    assert!(!text.contains("Transfer-Encoding"));
+    assert!(text.ends_with("Connection: close\r\n\r\nhello world"));
+
+    let text = response_bytes(Response::error(404), Version::Http11, false);
+    assert!(text.contains("Content-Length: 14\r\n"));
+    assert!(text.ends_with("\r\n\r\n404 Not Found\n"));
+
+    // Large bodies are split
+    let mut out = Vec::new();
+    let mut writer = ChunkWriter::new(&mut out, true);
+    writer.write_bytes(&[b'x'; 1500]);
+    writer.finish().unwrap();
+    assert!(out.starts_with(b"5dc\r\n"));
+}
+
+#[def_test]
+fn test_metrics_format() {
+    let mut out = Vec::new();
+    let mut chunks = ChunkWriter::new(&mut out, false);
+    let mut writer = MetricsWriter::new(&mut chunks);
+    writer.single("up", Kind::Gauge, "Whether it is up.", 1);
+    writer.family("reads_total", Kind::Counter, "Reads.");
+    writer.sample("reads_total", &[("path", "/a\"b\\c\n"), ("dev", "x")], 7);
+    chunks.finish().unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "# HELP up Whether it is up.\n# TYPE up gauge\nup 1\n# HELP reads_total Reads.\n# TYPE \
+         reads_total counter\nreads_total{path=\"/a\\\"b\\\\c\\n\",dev=\"x\"} 7\n"
+    );
+}
@@ -0,0 +1,203 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Learning Ethernet bridges between NICs.
+//!
+//! A bridge created by [`NetStack::add_bridge`] enslaves NICs as ports with
+//! [`Bridge::add_port`] and forwards frames between them, like a Linux
+//! `brN` device. The switching is done by a driver-level
+//! [`Bridge`](kdriver::prelude::Bridge), which learned addresses age out of
+//! after [`AGEING_TIME`]; this module names its ports and makes it a device
+//! of the stack.
+//!
+//! The address of the bridge itself sits behind the local port, a virtual
+//! NIC of the bridge which is the device the stack routes through.
+//!
+//! Frames received by the enslaved NICs are forwarded when the stack polls
+//! the bridge device.
+//!
+//! [`NetStack::add_bridge`]: crate::netns::NetStack::add_bridge
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+use core::{task::Waker, time::Duration};
+
+use kdriver::prelude::{
+    Bridge as Switch, IoPolicy, MacAddress, NetCapabilities, NetDriverOps, VirtualNic,
+};
+use kerrno::{KError, KResult};
+use kpoll::PollSet;
+use ksync::spin::SpinNoIrq;
+use ktask::future::register_irq_waker;
+use smoltcp::{
+    storage::PacketBuffer,
+    time::Instant,
+    wire::{IpAddress, Ipv4Cidr},
+};
+
+use crate::device::{EthernetDevice, NetDevice};
+
+/// How long a learned address is kept without traffic from it.
+pub const AGEING_TIME: Duration = Duration::from_secs(300);
+
+struct BridgeInner {
+    switch: Switch,
+    /// The name of each port, the local port first.
+    ports: SpinNoIrq<Vec<(usize, String)>>,
+    /// Woken when a port receives a frame or changes link state.
+    wakers: Arc<PollSet>,
+}
+
+/// A learning Ethernet bridge.
+///
+/// Handles are cheap to clone; the bridge lives as long as its device.
+#[derive(Clone)]
+pub struct Bridge {
+    inner: Arc<BridgeInner>,
+}
+
+impl Bridge {
+    /// Creates a bridge named `name` addressed `mac`, returning it and its
+    /// device.
+    pub(crate) fn new(name: String, mac: MacAddress, ip: Ipv4Cidr) -> (Self, Box<dyn NetDevice>) {
+        let switch = Switch::with_ageing(AGEING_TIME, khal::time::monotonic_time);
+        let local = switch.add_port(mac);
+        let local_port = local.bridge_port().unwrap();
+        let bridge = Self {
+            inner: Arc::new(BridgeInner {
+                switch,
+                ports: SpinNoIrq::new(Vec::from([(local_port, name.clone())])),
+                wakers: Arc::new(PollSet::new()),
+            }),
+        };
+        let dev = BridgeDevice {
+            eth: EthernetDevice::new(name, local, Some(ip)),
+            bridge: bridge.inner.clone(),
+        };
+        (bridge, Box::new(dev))
+    }
+
+    /// Enslaves the NIC `dev` as the port `name`.
+    pub fn add_port(&self, name: String, mut dev: impl NetDriverOps + 'static) -> KResult {
+        let mut ports = self.inner.ports.lock();
+        if ports.iter().any(|(_, port)| *port == name) {
+            return Err(KError::AlreadyExists);
+        }
+        let wakers = self.inner.wakers.clone();
+        dev.set_rx_notify_hook(Box::new(move || {
+            wakers.wake();
+        }));
+        let wakers = self.inner.wakers.clone();
+        dev.set_link_change_hook(Box::new(move |_| {
+            wakers.wake();
+        }));
+        info!("bridge {}: added port {}", ports[0].1, name);
+        ports.push((self.inner.switch.add_nic(dev), name));
+        self.inner.wakers.wake();
+        Ok(())
+    }
+
+    /// Releases the port `name`, forgetting the addresses learned on it.
+    ///
+    /// The local port, named after the bridge, cannot be removed.
+    pub fn remove_port(&self, name: &str) -> KResult {
+        let mut ports = self.inner.ports.lock();
+        let index = ports
+            .iter()
+            .position(|(_, port)| port == name)
+            .ok_or(KError::NotFound)?;
+        if index == 0 {
+            return Err(KError::InvalidInput);
+        }
+        let (port, _) = ports.remove(index);
+        self.inner.switch.remove_nic(port);
+        info!("bridge {}: removed port {}", ports[0].1, name);
+        Ok(())
+    }
+
+    /// Returns the name of the bridge.
+    pub fn name(&self) -> String {
+        self.inner.ports.lock()[0].1.clone()
+    }
+
+    /// Returns the names of the ports, starting with the local port.
+    pub fn ports(&self) -> Vec<String> {
+        let ports = self.inner.ports.lock();
+        ports.iter().map(|(_, name)| name.clone()).collect()
+    }
+
+    /// Returns the port that `mac` was learned on.
+    pub fn lookup(&self, mac: MacAddress) -> Option<String> {
+        let port = self.inner.switch.lookup(mac)?;
+        let ports = self.inner.ports.lock();
+        let (_, name) = ports.iter().find(|(id, _)| *id == port)?;
+        Some(name.clone())
+    }
+}
+
+/// The device of a bridge, forwarding between the ports when polled.
+struct BridgeDevice {
+    eth: EthernetDevice<VirtualNic>,
+    bridge: Arc<BridgeInner>,
+}
+
+impl NetDevice for BridgeDevice {
+    fn name(&self) -> &str {
+        self.eth.name()
+    }
+
+    fn capabilities(&self) -> NetCapabilities {
+        NetCapabilities::empty()
+    }
+
+    fn link_up(&self) -> bool {
+        self.eth.link_up()
+    }
+
+    fn set_ipv4(&mut self, ip: Option<Ipv4Cidr>) {
+        self.eth.set_ipv4(ip);
+    }
+
+    fn announce(&mut self) {
+        self.eth.announce();
+    }
+
+    fn reset(&mut self) {
+        // Not locked while the switch is, as when adding ports
+        let ports = self.bridge.ports.lock().clone();
+        self.bridge.switch.for_each_nic(|port, dev| {
+            if let Some((_, name)) = ports.iter().find(|(id, _)| *id == port) {
+                crate::device::reset_nic(name, dev);
+            }
+        });
+    }
+
+    /// Sets the policy of the bridge's own port.
+    fn set_io_policy(&mut self, policy: IoPolicy) -> KResult {
+        self.eth.set_io_policy(policy)
+    }
+
+    fn poll_rx(&mut self, buffer: &mut PacketBuffer<()>, timestamp: Instant) -> bool {
+        self.bridge.switch.poll();
+        self.eth.poll_rx(buffer, timestamp)
+    }
+
+    fn send_ip_packet(
+        &mut self,
+        next_hop: IpAddress,
+        ip_packet: &[u8],
+        timestamp: Instant,
+    ) -> bool {
+        // Forwarded out of the local port right away
+        self.eth.send_ip_packet(next_hop, ip_packet, timestamp)
+    }
+
+    fn register_rx_waker(&self, waker: &Waker) {
+        self.eth.register_rx_waker(waker);
+        self.bridge.wakers.register(waker);
+        self.bridge.switch.for_each_nic(|_, dev| {
+            if let Some(irq) = dev.irq() {
+                register_irq_waker(irq, waker);
+            }
+        });
+    }
+}
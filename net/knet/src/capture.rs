@@ -0,0 +1,304 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Packet capture, in the spirit of `AF_PACKET`.
+//!
+//! A [`Capture`] receives a copy of every frame its devices send and
+//! receive, optionally narrowed down to one interface, one direction and
+//! the packets a classic BPF [`Filter`] accepts. Copies are queued in a ring
+//! of bounded size per consumer; packets arriving while it is full are
+//! dropped and counted rather than slowing down the stack.
+//!
+//! Ethernet devices are tapped at the link layer, loopback and veth devices
+//! carry bare IP packets. [`pcap_file_header`] and
+//! [`CapturedPacket::pcap_record`] turn captures into a pcap stream.
+pub mod filter;
+
+use alloc::{collections::VecDeque, string::String, sync::Arc, vec::Vec};
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    task::Context,
+};
+
+pub use filter::{Filter, Insn};
+use kerrno::{KError, KResult};
+use khal::time::wall_time_nanos;
+use kpoll::{IoEvents, PollSet, Pollable};
+use ksync::spin::SpinNoIrq;
+use ktask::future::{block_on, poll_io};
+
+use crate::netns::poll_interfaces;
+
+/// Largest number of bytes copied from a packet, unless told otherwise.
+pub const DEFAULT_SNAPLEN: u32 = 65535;
+/// Bytes of packet data queued per capture, unless told otherwise.
+pub const DEFAULT_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Which way a packet went through its device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+/// What a captured packet starts with.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkType {
+    /// An Ethernet header.
+    Ethernet = 1,
+    /// An IPv4 or IPv6 header.
+    RawIp = 101,
+}
+
+/// What to capture.
+#[derive(Debug, Clone)]
+pub struct CaptureConfig {
+    /// Only capture packets of this device.
+    pub interface: Option<String>,
+    /// Only capture packets going this way.
+    pub direction: Option<Direction>,
+    /// Only capture packets this filter accepts, truncated to its result.
+    pub filter: Option<Filter>,
+    /// Largest number of bytes copied from a packet.
+    pub snaplen: u32,
+    /// Bytes of packet data queued before packets are dropped.
+    pub buffer_size: usize,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            interface: None,
+            direction: None,
+            filter: None,
+            snaplen: DEFAULT_SNAPLEN,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+}
+
+/// A copy of a packet.
+#[derive(Debug, Clone)]
+pub struct CapturedPacket {
+    /// Wall time the packet went through its device.
+    pub timestamp_ns: u64,
+    pub interface: String,
+    pub direction: Direction,
+    pub link_type: LinkType,
+    /// Length of the packet before truncation.
+    pub orig_len: u32,
+    pub data: Vec<u8>,
+}
+
+impl CapturedPacket {
+    /// Encodes the packet as a pcap record.
+    pub fn pcap_record(&self) -> Vec<u8> {
+        let secs = self.timestamp_ns / 1_000_000_000;
+        let micros = self.timestamp_ns % 1_000_000_000 / 1_000;
+        let mut record = Vec::with_capacity(16 + self.data.len());
+        record.extend_from_slice(&(secs as u32).to_le_bytes());
+        record.extend_from_slice(&(micros as u32).to_le_bytes());
+        record.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        record.extend_from_slice(&self.orig_len.to_le_bytes());
+        record.extend_from_slice(&self.data);
+        record
+    }
+}
+
+/// Encodes the header of a pcap stream with microsecond timestamps.
+pub fn pcap_file_header(link_type: LinkType, snaplen: u32) -> [u8; 24] {
+    let mut header = [0; 24];
+    header[0..4].copy_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+    header[4..6].copy_from_slice(&2u16.to_le_bytes());
+    header[6..8].copy_from_slice(&4u16.to_le_bytes());
+    // Time zone offset and timestamp accuracy stay zero
+    header[16..20].copy_from_slice(&snaplen.to_le_bytes());
+    header[20..24].copy_from_slice(&(link_type as u32).to_le_bytes());
+    header
+}
+
+/// Counters of a capture, like `PACKET_STATISTICS`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CaptureStats {
+    /// Packets queued.
+    pub captured: u64,
+    /// Packets accepted but dropped because the ring was full.
+    pub dropped: u64,
+}
+
+struct Ring {
+    packets: VecDeque<CapturedPacket>,
+    /// Bytes of packet data in `packets`.
+    used: usize,
+}
+
+/// The kernel side of a capture.
+struct Tap {
+    interface: Option<String>,
+    direction: Option<Direction>,
+    filter: SpinNoIrq<Option<Filter>>,
+    snaplen: u32,
+    buffer_size: usize,
+    ring: SpinNoIrq<Ring>,
+    captured: AtomicU64,
+    dropped: AtomicU64,
+    wakers: PollSet,
+}
+
+impl Tap {
+    fn deliver(
+        &self,
+        interface: &str,
+        direction: Direction,
+        link_type: LinkType,
+        packet: &[u8],
+        timestamp_ns: u64,
+    ) {
+        if self
+            .interface
+            .as_deref()
+            .is_some_and(|name| name != interface)
+            || self.direction.is_some_and(|dir| dir != direction)
+        {
+            return;
+        }
+        let accepted = match &*self.filter.lock() {
+            Some(filter) => filter.run(packet),
+            None => u32::MAX,
+        };
+        let len = (accepted.min(self.snaplen) as usize).min(packet.len());
+        if len == 0 {
+            return;
+        }
+
+        let mut ring = self.ring.lock();
+        if ring.used + len > self.buffer_size {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        ring.used += len;
+        ring.packets.push_back(CapturedPacket {
+            timestamp_ns,
+            interface: interface.into(),
+            direction,
+            link_type,
+            orig_len: packet.len() as u32,
+            data: packet[..len].to_vec(),
+        });
+        drop(ring);
+        self.captured.fetch_add(1, Ordering::Relaxed);
+        self.wakers.wake();
+    }
+}
+
+static TAPS: SpinNoIrq<Vec<Arc<Tap>>> = SpinNoIrq::new(Vec::new());
+/// Length of [`TAPS`], so that devices skip it while nobody captures.
+static TAP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Hands a packet that went through device `interface` to the captures.
+pub(crate) fn tap(interface: &str, direction: Direction, link_type: LinkType, packet: &[u8]) {
+    if TAP_COUNT.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    let taps = TAPS.lock().clone();
+    let timestamp_ns = wall_time_nanos();
+    for tap in taps {
+        tap.deliver(interface, direction, link_type, packet, timestamp_ns);
+    }
+}
+
+/// A packet capture, removed when dropped.
+pub struct Capture {
+    tap: Arc<Tap>,
+    nonblocking: AtomicBool,
+}
+
+impl Capture {
+    /// Starts capturing packets as described by `config`.
+    pub fn open(config: CaptureConfig) -> KResult<Self> {
+        if config.snaplen == 0 || config.buffer_size == 0 {
+            return Err(KError::InvalidInput);
+        }
+        let tap = Arc::new(Tap {
+            interface: config.interface,
+            direction: config.direction,
+            filter: SpinNoIrq::new(config.filter),
+            snaplen: config.snaplen,
+            buffer_size: config.buffer_size,
+            ring: SpinNoIrq::new(Ring {
+                packets: VecDeque::new(),
+                used: 0,
+            }),
+            captured: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            wakers: PollSet::new(),
+        });
+        let mut taps = TAPS.lock();
+        taps.push(tap.clone());
+        TAP_COUNT.store(taps.len(), Ordering::Release);
+        Ok(Self {
+            tap,
+            nonblocking: AtomicBool::new(false),
+        })
+    }
+
+    /// Replaces the filter; packets already queued are kept.
+    pub fn set_filter(&self, filter: Option<Filter>) {
+        *self.tap.filter.lock() = filter;
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+    }
+
+    /// Takes the oldest queued packet, if any.
+    pub fn try_recv(&self) -> Option<CapturedPacket> {
+        let mut ring = self.tap.ring.lock();
+        let packet = ring.packets.pop_front()?;
+        ring.used -= packet.data.len();
+        Some(packet)
+    }
+
+    /// Takes the oldest queued packet, waiting for one unless nonblocking.
+    pub fn recv(&self) -> KResult<CapturedPacket> {
+        block_on(poll_io(
+            self,
+            IoEvents::IN,
+            self.nonblocking.load(Ordering::Acquire),
+            || self.try_recv().ok_or(KError::WouldBlock),
+        ))
+    }
+
+    pub fn stats(&self) -> CaptureStats {
+        CaptureStats {
+            captured: self.tap.captured.load(Ordering::Relaxed),
+            dropped: self.tap.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Pollable for Capture {
+    fn poll(&self) -> IoEvents {
+        // Packets only reach the taps while the stacks are polled
+        poll_interfaces();
+        let mut events = IoEvents::empty();
+        events.set(IoEvents::IN, !self.tap.ring.lock().packets.is_empty());
+        events
+    }
+
+    fn register(&self, context: &mut Context<'_>, events: IoEvents) {
+        if events.contains(IoEvents::IN) {
+            self.tap.wakers.register(context.waker());
+        }
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        let mut taps = TAPS.lock();
+        taps.retain(|tap| !Arc::ptr_eq(tap, &self.tap));
+        TAP_COUNT.store(taps.len(), Ordering::Release);
+    }
+}
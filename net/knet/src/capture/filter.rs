@@ -0,0 +1,274 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Classic BPF packet filters.
+//!
+//! Programs use the instruction set of `SO_ATTACH_FILTER`, so the output of
+//! `tcpdump -dd` can be loaded as is. A program returns how many bytes of
+//! the packet to keep, `0` dropping it.
+use alloc::vec::Vec;
+
+use kerrno::{KError, KResult};
+
+// Instruction classes
+pub const BPF_LD: u16 = 0x00;
+pub const BPF_LDX: u16 = 0x01;
+pub const BPF_ST: u16 = 0x02;
+pub const BPF_STX: u16 = 0x03;
+pub const BPF_ALU: u16 = 0x04;
+pub const BPF_JMP: u16 = 0x05;
+pub const BPF_RET: u16 = 0x06;
+pub const BPF_MISC: u16 = 0x07;
+
+// Load sizes
+pub const BPF_W: u16 = 0x00;
+pub const BPF_H: u16 = 0x08;
+pub const BPF_B: u16 = 0x10;
+
+// Load modes
+pub const BPF_IMM: u16 = 0x00;
+pub const BPF_ABS: u16 = 0x20;
+pub const BPF_IND: u16 = 0x40;
+pub const BPF_MEM: u16 = 0x60;
+pub const BPF_LEN: u16 = 0x80;
+pub const BPF_MSH: u16 = 0xa0;
+
+// ALU operations
+pub const BPF_ADD: u16 = 0x00;
+pub const BPF_SUB: u16 = 0x10;
+pub const BPF_MUL: u16 = 0x20;
+pub const BPF_DIV: u16 = 0x30;
+pub const BPF_OR: u16 = 0x40;
+pub const BPF_AND: u16 = 0x50;
+pub const BPF_LSH: u16 = 0x60;
+pub const BPF_RSH: u16 = 0x70;
+pub const BPF_NEG: u16 = 0x80;
+pub const BPF_MOD: u16 = 0x90;
+pub const BPF_XOR: u16 = 0xa0;
+
+// Jumps
+pub const BPF_JA: u16 = 0x00;
+pub const BPF_JEQ: u16 = 0x10;
+pub const BPF_JGT: u16 = 0x20;
+pub const BPF_JGE: u16 = 0x30;
+pub const BPF_JSET: u16 = 0x40;
+
+// Operand sources
+pub const BPF_K: u16 = 0x00;
+pub const BPF_X: u16 = 0x08;
+pub const BPF_A: u16 = 0x10;
+
+// Miscellaneous
+pub const BPF_TAX: u16 = 0x00;
+pub const BPF_TXA: u16 = 0x80;
+
+/// Words of scratch memory.
+const BPF_MEMWORDS: u32 = 16;
+/// Longest program accepted.
+pub const BPF_MAXINSNS: usize = 4096;
+
+/// An instruction, laid out like `struct sock_filter`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Insn {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+impl Insn {
+    /// An instruction that does not branch.
+    pub const fn stmt(code: u16, k: u32) -> Self {
+        Self {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    /// A conditional jump.
+    pub const fn jump(code: u16, k: u32, jt: u8, jf: u8) -> Self {
+        Self { code, jt, jf, k }
+    }
+}
+
+/// A validated filter program.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    insns: Vec<Insn>,
+}
+
+impl Filter {
+    /// Validates `insns` like `sk_chk_filter` does: every instruction must
+    /// be known, jumps must stay inside the program and go forward, scratch
+    /// memory must exist and the program must end with a return.
+    pub fn new(insns: Vec<Insn>) -> KResult<Self> {
+        if insns.is_empty() || insns.len() > BPF_MAXINSNS {
+            return Err(KError::InvalidInput);
+        }
+        for (pc, insn) in insns.iter().enumerate() {
+            let in_range = |offset: u32| pc + 1 + (offset as usize) < insns.len();
+            let valid = match insn.code & 0x07 {
+                BPF_LD => match insn.code & !0x07 {
+                    mode if mode == BPF_MEM | BPF_W => insn.k < BPF_MEMWORDS,
+                    mode if mode & 0x18 == 0x18 => false,
+                    mode => {
+                        matches!(mode & 0xe0, BPF_ABS | BPF_IND)
+                            || mode == BPF_IMM
+                            || mode == BPF_LEN
+                    }
+                },
+                BPF_LDX => match insn.code & !0x07 {
+                    mode if mode == BPF_MEM | BPF_W => insn.k < BPF_MEMWORDS,
+                    mode => mode == BPF_IMM || mode == BPF_LEN || mode == BPF_MSH | BPF_B,
+                },
+                BPF_ST | BPF_STX => insn.code & !0x07 == 0 && insn.k < BPF_MEMWORDS,
+                BPF_ALU => {
+                    let op = insn.code & 0xf0;
+                    let src = insn.code & BPF_X;
+                    insn.code & !0xf8 == BPF_ALU
+                        && op <= BPF_XOR
+                        && (op == BPF_NEG
+                            || (op != BPF_DIV && op != BPF_MOD)
+                            || src == BPF_X
+                            || insn.k != 0)
+                }
+                BPF_JMP => match insn.code & 0xf0 {
+                    BPF_JA => insn.code == BPF_JMP | BPF_JA && in_range(insn.k),
+                    BPF_JEQ | BPF_JGT | BPF_JGE | BPF_JSET => {
+                        insn.code & !0xf8 == BPF_JMP
+                            && in_range(insn.jt as u32)
+                            && in_range(insn.jf as u32)
+                    }
+                    _ => false,
+                },
+                BPF_RET => matches!(insn.code & !0x07, BPF_K | BPF_A),
+                _ => matches!(insn.code & !0x07, BPF_TAX | BPF_TXA),
+            };
+            if !valid {
+                return Err(KError::InvalidInput);
+            }
+        }
+        if insns.last().unwrap().code & 0x07 != BPF_RET {
+            return Err(KError::InvalidInput);
+        }
+        Ok(Self { insns })
+    }
+
+    /// A filter accepting every packet whole.
+    pub fn accept_all() -> Self {
+        Self {
+            insns: alloc::vec![Insn::stmt(BPF_RET | BPF_K, u32::MAX)],
+        }
+    }
+
+    pub fn insns(&self) -> &[Insn] {
+        &self.insns
+    }
+
+    /// Runs the program on `packet`, returning how many bytes to keep.
+    ///
+    /// Loads past the end of the packet and divisions by zero drop it.
+    pub fn run(&self, packet: &[u8]) -> u32 {
+        let load = |offset: u32, size: usize| -> Option<u32> {
+            let start = offset as usize;
+            let bytes = packet.get(start..start.checked_add(size)?)?;
+            Some(
+                bytes
+                    .iter()
+                    .fold(0, |value, &byte| value << 8 | byte as u32),
+            )
+        };
+        let (mut a, mut x) = (0u32, 0u32);
+        let mut mem = [0u32; BPF_MEMWORDS as usize];
+        let mut pc = 0;
+        loop {
+            let insn = self.insns[pc];
+            pc += 1;
+            let k = insn.k;
+            let size = match insn.code & 0x18 {
+                BPF_W => 4,
+                BPF_H => 2,
+                _ => 1,
+            };
+            match insn.code & 0x07 {
+                BPF_LD => {
+                    a = match insn.code & 0xe0 {
+                        BPF_IMM => k,
+                        BPF_ABS => match load(k, size) {
+                            Some(value) => value,
+                            None => return 0,
+                        },
+                        BPF_IND => match load(x.wrapping_add(k), size) {
+                            Some(value) => value,
+                            None => return 0,
+                        },
+                        BPF_MEM => mem[k as usize],
+                        _ => packet.len() as u32,
+                    }
+                }
+                BPF_LDX => {
+                    x = match insn.code & 0xe0 {
+                        BPF_IMM => k,
+                        BPF_MEM => mem[k as usize],
+                        BPF_LEN => packet.len() as u32,
+                        // IPv4 header length
+                        _ => match load(k, 1) {
+                            Some(value) => (value & 0x0f) << 2,
+                            None => return 0,
+                        },
+                    }
+                }
+                BPF_ST => mem[k as usize] = a,
+                BPF_STX => mem[k as usize] = x,
+                BPF_ALU => {
+                    let operand = if insn.code & BPF_X != 0 { x } else { k };
+                    a = match insn.code & 0xf0 {
+                        BPF_ADD => a.wrapping_add(operand),
+                        BPF_SUB => a.wrapping_sub(operand),
+                        BPF_MUL => a.wrapping_mul(operand),
+                        BPF_DIV => match a.checked_div(operand) {
+                            Some(value) => value,
+                            None => return 0,
+                        },
+                        BPF_MOD => match a.checked_rem(operand) {
+                            Some(value) => value,
+                            None => return 0,
+                        },
+                        BPF_OR => a | operand,
+                        BPF_AND => a & operand,
+                        BPF_LSH => a.checked_shl(operand).unwrap_or(0),
+                        BPF_RSH => a.checked_shr(operand).unwrap_or(0),
+                        BPF_NEG => a.wrapping_neg(),
+                        _ => a ^ operand,
+                    }
+                }
+                BPF_JMP => {
+                    let operand = if insn.code & BPF_X != 0 { x } else { k };
+                    let taken = match insn.code & 0xf0 {
+                        BPF_JA => {
+                            pc += k as usize;
+                            continue;
+                        }
+                        BPF_JEQ => a == operand,
+                        BPF_JGT => a > operand,
+                        BPF_JGE => a >= operand,
+                        _ => a & operand != 0,
+                    };
+                    pc += if taken { insn.jt } else { insn.jf } as usize;
+                }
+                BPF_RET => return if insn.code & BPF_A != 0 { a } else { k },
+                _ => {
+                    if insn.code & BPF_TXA != 0 {
+                        a = x;
+                    } else {
+                        x = a;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Software checksums for devices without checksum offload.
+//!
+//! smoltcp is told to neither compute nor verify checksums, since the router
+//! knows which device a packet goes through and only devices lacking
+//! [`NetCapabilities::TX_CSUM`] / [`NetCapabilities::RX_CSUM`] pay for it.
+//!
+//! [`NetCapabilities::TX_CSUM`]: kdriver::prelude::NetCapabilities::TX_CSUM
+//! [`NetCapabilities::RX_CSUM`]: kdriver::prelude::NetCapabilities::RX_CSUM
+use smoltcp::wire::{
+    Icmpv4Packet, Icmpv6Packet, IpAddress, IpProtocol, IpVersion, Ipv4Packet, Ipv6Address,
+    Ipv6Packet, TcpPacket, UdpPacket,
+};
+
+/// Transport header of a packet, located by [`transport`].
+pub(crate) struct Transport {
+    pub protocol: IpProtocol,
+    pub src: IpAddress,
+    pub dst: IpAddress,
+    /// Range of the transport segment within the IP packet.
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Skips IPv6 extension headers, returning the upper-layer protocol and its
+/// offset within `payload`.
+fn skip_ipv6_extensions(mut protocol: IpProtocol, payload: &[u8]) -> Option<(IpProtocol, usize)> {
+    let mut offset = 0;
+    loop {
+        match protocol {
+            IpProtocol::HopByHop | IpProtocol::Ipv6Route | IpProtocol::Ipv6Opts => {
+                let header = payload.get(offset..offset + 2)?;
+                protocol = IpProtocol::from(header[0]);
+                offset += (header[1] as usize + 1) * 8;
+            }
+            // Fragments are checksummed as a whole after reassembly
+            IpProtocol::Ipv6Frag => return None,
+            _ => return Some((protocol, offset)),
+        }
+    }
+}
+
+/// Locates the transport segment of an unfragmented IP packet.
+pub(crate) fn transport(packet: &[u8]) -> Option<Transport> {
+    match IpVersion::of_packet(packet).ok()? {
+        IpVersion::Ipv4 => {
+            let ip = Ipv4Packet::new_checked(packet).ok()?;
+            if ip.more_frags() || ip.frag_offset() != 0 {
+                return None;
+            }
+            Some(Transport {
+                protocol: ip.next_header(),
+                src: IpAddress::Ipv4(ip.src_addr()),
+                dst: IpAddress::Ipv4(ip.dst_addr()),
+                start: ip.header_len() as usize,
+                end: ip.total_len() as usize,
+            })
+        }
+        IpVersion::Ipv6 => {
+            let ip = Ipv6Packet::new_checked(packet).ok()?;
+            let (protocol, offset) = skip_ipv6_extensions(ip.next_header(), ip.payload())?;
+            let start = ip.header_len() + offset;
+            let end = ip.header_len() + ip.payload_len() as usize;
+            (start <= end).then(|| Transport {
+                protocol,
+                src: IpAddress::Ipv6(ip.src_addr()),
+                dst: IpAddress::Ipv6(ip.dst_addr()),
+                start,
+                end,
+            })
+        }
+    }
+}
+
+fn ipv6_addrs(t: &Transport) -> Option<(Ipv6Address, Ipv6Address)> {
+    match (t.src, t.dst) {
+        (IpAddress::Ipv6(src), IpAddress::Ipv6(dst)) => Some((src, dst)),
+        _ => None,
+    }
+}
+
+/// Fills in the IPv4 header and transport checksums of an outgoing packet.
+pub(crate) fn fill_checksums(packet: &mut [u8]) {
+    if IpVersion::of_packet(packet) == Ok(IpVersion::Ipv4)
+        && let Ok(mut ip) = Ipv4Packet::new_checked(&mut *packet)
+    {
+        ip.fill_checksum();
+    }
+
+    let Some(t) = transport(packet) else {
+        return;
+    };
+    let segment = &mut packet[t.start..t.end];
+    match t.protocol {
+        IpProtocol::Tcp => {
+            if let Ok(mut tcp) = TcpPacket::new_checked(segment) {
+                tcp.fill_checksum(&t.src, &t.dst);
+            }
+        }
+        IpProtocol::Udp => {
+            if let Ok(mut udp) = UdpPacket::new_checked(segment) {
+                udp.fill_checksum(&t.src, &t.dst);
+            }
+        }
+        IpProtocol::Icmp => {
+            if let Ok(mut icmp) = Icmpv4Packet::new_checked(segment) {
+                icmp.fill_checksum();
+            }
+        }
+        IpProtocol::Icmpv6 => {
+            if let (Some((src, dst)), Ok(mut icmp)) =
+                (ipv6_addrs(&t), Icmpv6Packet::new_checked(segment))
+            {
+                icmp.fill_checksum(&src, &dst);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Verifies the IPv4 header and transport checksums of an incoming packet.
+///
+/// Packets this function cannot parse are passed on for smoltcp to reject.
+pub(crate) fn verify_checksums(packet: &[u8]) -> bool {
+    if IpVersion::of_packet(packet) == Ok(IpVersion::Ipv4)
+        && let Ok(ip) = Ipv4Packet::new_checked(packet)
+        && !ip.verify_checksum()
+    {
+        return false;
+    }
+
+    let Some(t) = transport(packet) else {
+        return true;
+    };
+    let segment = &packet[t.start..t.end];
+    match t.protocol {
+        IpProtocol::Tcp => {
+            TcpPacket::new_checked(segment).map_or(true, |tcp| tcp.verify_checksum(&t.src, &t.dst))
+        }
+        IpProtocol::Udp => {
+            UdpPacket::new_checked(segment).map_or(true, |udp| udp.verify_checksum(&t.src, &t.dst))
+        }
+        IpProtocol::Icmp => {
+            Icmpv4Packet::new_checked(segment).map_or(true, |icmp| icmp.verify_checksum())
+        }
+        IpProtocol::Icmpv6 => match (ipv6_addrs(&t), Icmpv6Packet::new_checked(segment)) {
+            (Some((src, dst)), Ok(icmp)) => icmp.verify_checksum(&src, &dst),
+            _ => true,
+        },
+        _ => true,
+    }
+}
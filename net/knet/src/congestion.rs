@@ -0,0 +1,102 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! TCP congestion control selection.
+//!
+//! The algorithms themselves live in smoltcp; this module maps them to the
+//! names used by `TCP_CONGESTION` and keeps the system-wide default applied
+//! to newly created sockets.
+//!
+//! BBR is an experimental, simplified variant: smoltcp does not pace
+//! segments, so it sizes the congestion window from the estimated bottleneck
+//! bandwidth and minimum RTT instead. It is never the default.
+use core::{
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use kerrno::{KError, KResult};
+use smoltcp::socket::tcp as smol;
+
+/// A TCP congestion control algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum CongestionControl {
+    /// TCP Reno (RFC 5681).
+    Reno  = 0,
+    /// CUBIC (RFC 8312), better suited to high bandwidth-delay links.
+    #[default]
+    Cubic = 1,
+    /// BBR without pacing, driven by bandwidth and RTT estimates.
+    Bbr   = 2,
+}
+
+impl CongestionControl {
+    /// All available algorithms.
+    pub const ALL: [Self; 3] = [Self::Reno, Self::Cubic, Self::Bbr];
+
+    /// Returns the name used by `TCP_CONGESTION` and procfs.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Reno => "reno",
+            Self::Cubic => "cubic",
+            Self::Bbr => "bbr",
+        }
+    }
+
+    /// Looks up an algorithm by name.
+    ///
+    /// Like Linux, an unknown or unavailable algorithm yields `ENOENT`.
+    pub fn from_name(name: &str) -> KResult<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|it| it.name() == name)
+            .ok_or(KError::NotFound)
+    }
+
+    pub(crate) fn to_smol(self) -> smol::CongestionControl {
+        match self {
+            Self::Reno => smol::CongestionControl::Reno,
+            Self::Cubic => smol::CongestionControl::Cubic,
+            Self::Bbr => smol::CongestionControl::Bbr,
+        }
+    }
+
+    pub(crate) fn from_smol(cc: smol::CongestionControl) -> Self {
+        match cc {
+            smol::CongestionControl::Reno => Self::Reno,
+            smol::CongestionControl::Bbr => Self::Bbr,
+            // Sockets are never left without congestion control
+            smol::CongestionControl::Cubic | smol::CongestionControl::None => Self::Cubic,
+        }
+    }
+
+    const fn from_u8(val: u8) -> Self {
+        match val {
+            0 => Self::Reno,
+            2 => Self::Bbr,
+            _ => Self::Cubic,
+        }
+    }
+}
+
+impl fmt::Display for CongestionControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+static DEFAULT_CONGESTION_CONTROL: AtomicU8 = AtomicU8::new(CongestionControl::Cubic as u8);
+
+/// Returns the algorithm used by newly created TCP sockets.
+pub fn default_congestion_control() -> CongestionControl {
+    CongestionControl::from_u8(DEFAULT_CONGESTION_CONTROL.load(Ordering::Relaxed))
+}
+
+/// Sets the algorithm used by newly created TCP sockets.
+///
+/// Existing sockets keep their current algorithm.
+pub fn set_default_congestion_control(cc: CongestionControl) {
+    DEFAULT_CONGESTION_CONTROL.store(cc as u8, Ordering::Relaxed);
+}
@@ -15,6 +15,9 @@ macro_rules! env_or_default {
 pub const IP: &str = env_or_default!("K_IP");
 pub const GATEWAY: &str = env_or_default!("K_GW");
 pub const IP_PREFIX: u8 = 24;
+/// Comma-separated name servers, the gateway if empty.
+pub const DNS: &str = env_or_default!("K_DNS");
+pub const HOSTNAME: &str = "kylin-x";
 
 pub const STANDARD_MTU: usize = 1500;
 
@@ -23,6 +26,7 @@ pub const TCP_TX_BUF_LEN: usize = 64 * 1024;
 pub const UDP_RX_BUF_LEN: usize = 64 * 1024;
 pub const UDP_TX_BUF_LEN: usize = 64 * 1024;
 pub const LISTEN_QUEUE_SIZE: usize = 512;
+pub const SOMAXCONN: usize = 4096;
 
 pub const SOCKET_BUFFER_SIZE: usize = 64;
 pub const ETHERNET_MAX_PENDING_PACKETS: usize = 32;
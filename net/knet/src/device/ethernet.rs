@@ -3,50 +3,130 @@
 // See LICENSES for license details.
 
 //! Ethernet device adapter for the smoltcp stack.
-use alloc::{string::String, vec};
-use core::task::Waker;
+use alloc::{boxed::Box, string::String, sync::Arc, vec};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    task::Waker,
+};
 
 use hashbrown::HashMap;
 use kdriver::prelude::{
-    DriverError, DriverOps, NetBufHandle, NetDevice as DriverNetDevice, NetDriverOps,
+    DriverError, IoPolicy, NetBufHandle, NetCapabilities, NetDevice as DriverNetDevice,
+    NetDriverOps,
 };
+use kerrno::KResult;
+use kmetrics::Counter;
+use kpoll::PollSet;
 use ktask::future::register_irq_waker;
 use smoltcp::{
     storage::{PacketBuffer, PacketMetadata},
     time::{Duration, Instant},
     wire::{
         ArpOperation, ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol,
-        EthernetRepr, IpAddress, Ipv4Cidr,
+        EthernetRepr, IpAddress, Ipv4Address, Ipv4Cidr,
     },
 };
 
 use crate::{
+    capture::{self, Direction, LinkType},
     consts::{ETHERNET_MAX_PENDING_PACKETS, STANDARD_MTU},
-    device::NetDevice as NetDeviceOps,
+    device::{NETDEV_NOTIFIER, NetDevice as NetDeviceOps, NetdevEvent},
 };
 
 const EMPTY_MAC: EthernetAddress = EthernetAddress([0; 6]);
 
+static RX_FRAMES: Counter = Counter::new(
+    "xkernel_net_rx_frames_total",
+    "Frames received by Ethernet devices.",
+);
+static RX_BYTES: Counter = Counter::new(
+    "xkernel_net_rx_bytes_total",
+    "Bytes received by Ethernet devices.",
+);
+static TX_FRAMES: Counter = Counter::new(
+    "xkernel_net_tx_frames_total",
+    "Frames sent by Ethernet devices.",
+);
+static TX_BYTES: Counter = Counter::new(
+    "xkernel_net_tx_bytes_total",
+    "Bytes sent by Ethernet devices.",
+);
+
+/// Adds the Ethernet traffic counters to the metrics export.
+pub(crate) fn register_metrics() {
+    for counter in [&RX_FRAMES, &RX_BYTES, &TX_FRAMES, &TX_BYTES] {
+        kmetrics::register(counter);
+    }
+}
+
+/// Maps an IPv4 multicast group to its MAC address (RFC 1112).
+fn multicast_mac(group: Ipv4Address) -> EthernetAddress {
+    let [_, b1, b2, b3] = group.octets();
+    EthernetAddress([0x01, 0x00, 0x5e, b1 & 0x7f, b2, b3])
+}
+
 struct ArpNeighbor {
     hardware_address: EthernetAddress,
     expires_at: Instant,
 }
 
+/// Link state, updated by the driver's link change hook.
+///
+/// The wakers are also woken by drivers that notify of received packets
+/// without an interrupt.
+struct Link {
+    up: AtomicBool,
+    wakers: PollSet,
+}
+
+/// How the NIC fares at sending frames, judged by its [`IoPolicy`].
+struct Health {
+    policy: IoPolicy,
+    /// Whether the last frame failed to go out.
+    failing: bool,
+    /// When frames started failing to go out, as seen by the poller.
+    failing_since: Option<Instant>,
+    /// Timeouts since frames started failing to go out.
+    retries: u32,
+    /// Whether the NIC was taken offline, which reads as having no carrier.
+    offline: bool,
+}
+
+impl Health {
+    fn new(policy: IoPolicy) -> Self {
+        Self {
+            policy,
+            failing: false,
+            failing_since: None,
+            retries: 0,
+            offline: false,
+        }
+    }
+}
+
 /// Ethernet device backed by a driver-provided NIC.
-pub struct EthernetDevice {
-    #[allow(dead_code)]
+///
+/// A NIC failing to send for longer than the timeout of its [`IoPolicy`] is
+/// reset and, once out of retries, taken offline: the device then reports
+/// no carrier, and the router drops the packets routed to it, until it is
+/// [reset](NetDeviceOps::reset).
+pub struct EthernetDevice<D = DriverNetDevice> {
     name: String,
-    inner: DriverNetDevice,
+    inner: D,
     neighbors: HashMap<IpAddress, Option<ArpNeighbor>>,
-    ip: Ipv4Cidr,
+    ip: Option<Ipv4Cidr>,
+    link: Arc<Link>,
+    /// Link state as last acted upon.
+    carrier: bool,
+    health: Health,
 
     pending_tx: PacketBuffer<'static, IpAddress>,
 }
-impl EthernetDevice {
+impl<D: NetDriverOps> EthernetDevice<D> {
     const NEIGHBOR_TTL: Duration = Duration::from_secs(60);
 
-    /// Create a new Ethernet device wrapper.
-    pub fn new(name: String, inner: DriverNetDevice, ip: Ipv4Cidr) -> Self {
+    /// Create a new Ethernet device wrapper, addressed `ip` if known yet.
+    pub fn new(name: String, mut inner: D, ip: Option<Ipv4Cidr>) -> Self {
         let pending_tx = PacketBuffer::new(
             vec![PacketMetadata::EMPTY; ETHERNET_MAX_PENDING_PACKETS],
             vec![
@@ -55,22 +135,92 @@ impl EthernetDevice {
                     * ETHERNET_MAX_PENDING_PACKETS
             ],
         );
+        let carrier = inner.link_up();
+        let link = Arc::new(Link {
+            up: AtomicBool::new(carrier),
+            wakers: PollSet::new(),
+        });
+        let hook_link = link.clone();
+        inner.set_link_change_hook(Box::new(move |up| {
+            hook_link.up.store(up, Ordering::Release);
+            hook_link.wakers.wake();
+        }));
+        let rx_link = link.clone();
+        inner.set_rx_notify_hook(Box::new(move || {
+            rx_link.wakers.wake();
+        }));
         Self {
             name,
             inner,
             neighbors: HashMap::new(),
             ip,
+            link,
+            carrier,
+            health: Health::new(IoPolicy::default()),
             pending_tx,
         }
     }
 
+    /// Acts on link state changes reported since the last call.
+    fn update_carrier(&mut self) {
+        let up = self.link_up();
+        if up == self.carrier {
+            return;
+        }
+        self.carrier = up;
+        if up {
+            info!("{}: link up", self.name);
+        } else {
+            info!("{}: link down", self.name);
+            // Neighbors may be gone by the time the link comes back
+            self.neighbors.clear();
+            while self.pending_tx.dequeue().is_ok() {}
+        }
+        NETDEV_NOTIFIER.call(&NetdevEvent {
+            name: self.name.clone(),
+            up,
+        });
+    }
+
+    /// Acts on the NIC failing to send for longer than the policy allows,
+    /// resetting it or taking it offline.
+    fn update_health(&mut self, now: Instant) {
+        let health = &mut self.health;
+        if health.offline || !health.failing {
+            health.failing_since = None;
+            health.retries = 0;
+            return;
+        }
+        let since = *health.failing_since.get_or_insert(now);
+        let Some(timeout) = health.policy.timeout else {
+            return;
+        };
+        if now - since < Duration::from(timeout) {
+            return;
+        }
+        if health.retries < health.policy.retries {
+            health.retries += 1;
+            health.failing_since = Some(now);
+            warn!("{}: failing to send, retry {}", self.name, health.retries);
+            if health.policy.reset {
+                super::reset_nic(&self.name, &mut self.inner);
+            }
+        } else if health.policy.offline {
+            warn!("{}: failing to send, taken offline", self.name);
+            health.offline = true;
+            self.link.wakers.wake();
+        }
+    }
+
     #[inline]
     fn mac_addr(&self) -> EthernetAddress {
         EthernetAddress(self.inner.mac().0)
     }
 
     fn send_to<F>(
+        name: &str,
         inner: &mut dyn NetDriverOps,
+        health: &mut Health,
         dst: EthernetAddress,
         size: usize,
         f: F,
@@ -78,6 +228,7 @@ impl EthernetDevice {
     ) where
         F: FnOnce(&mut [u8]),
     {
+        health.failing = true;
         if let Err(err) = inner.recycle_tx() {
             warn!("recycle_tx failed: {:?}", err);
             return;
@@ -100,8 +251,15 @@ impl EthernetDevice {
         repr.emit(&mut frame);
         f(frame.payload_mut());
         trace!("SEND {} bytes: {:02X?}", tx_buf.len(), tx_buf.data());
-        if let Err(err) = inner.send(tx_buf) {
-            warn!("send failed: {:?}", err);
+        capture::tap(name, Direction::Outgoing, LinkType::Ethernet, tx_buf.data());
+        let len = tx_buf.len();
+        match inner.send(tx_buf) {
+            Ok(()) => {
+                health.failing = false;
+                TX_FRAMES.inc();
+                TX_BYTES.add(len as u64);
+            }
+            Err(err) => warn!("send failed: {:?}", err),
         }
     }
 
@@ -118,6 +276,7 @@ impl EthernetDevice {
         };
 
         if !repr.dst_addr.is_broadcast()
+            && !repr.dst_addr.is_multicast()
             && repr.dst_addr != EMPTY_MAC
             && repr.dst_addr != self.mac_addr()
         {
@@ -149,13 +308,16 @@ impl EthernetDevice {
         let arp_repr = ArpRepr::EthernetIpv4 {
             operation: ArpOperation::Request,
             source_hardware_addr: self.mac_addr(),
-            source_protocol_addr: self.ip.address(),
+            // Without an address yet, this is an ARP probe (RFC 5227)
+            source_protocol_addr: self.ip.map_or(Ipv4Address::UNSPECIFIED, |ip| ip.address()),
             target_hardware_addr: EthernetAddress::BROADCAST,
             target_protocol_addr: target_ipv4,
         };
 
         Self::send_to(
+            &self.name,
             &mut self.inner,
+            &mut self.health,
             EthernetAddress::BROADCAST,
             arp_repr.buffer_len(),
             |buf| arp_repr.emit(&mut ArpPacket::new_unchecked(buf)),
@@ -198,9 +360,9 @@ impl EthernetDevice {
             {
                 return;
             }
-            if self.ip.address() != target_protocol_addr {
+            let Some(ip) = self.ip.filter(|ip| ip.address() == target_protocol_addr) else {
                 return;
-            }
+            };
 
             debug!("ARP: {} -> {}", source_protocol_addr, source_hardware_addr);
             self.neighbors.insert(
@@ -215,13 +377,15 @@ impl EthernetDevice {
                 let response = ArpRepr::EthernetIpv4 {
                     operation: ArpOperation::Reply,
                     source_hardware_addr: self.mac_addr(),
-                    source_protocol_addr: self.ip.address(),
+                    source_protocol_addr: ip.address(),
                     target_hardware_addr: source_hardware_addr,
                     target_protocol_addr: source_protocol_addr,
                 };
 
                 Self::send_to(
+                    &self.name,
                     &mut self.inner,
+                    &mut self.health,
                     source_hardware_addr,
                     response.buffer_len(),
                     |buf| response.emit(&mut ArpPacket::new_unchecked(buf)),
@@ -248,7 +412,9 @@ impl EthernetDevice {
                     }
 
                     Self::send_to(
+                        &self.name,
                         &mut self.inner,
+                        &mut self.health,
                         neighbor.hardware_address,
                         buf.len(),
                         |b| b.copy_from_slice(buf),
@@ -261,23 +427,89 @@ impl EthernetDevice {
     }
 }
 
-impl NetDeviceOps for EthernetDevice {
+impl<D: NetDriverOps> NetDeviceOps for EthernetDevice<D> {
     fn name(&self) -> &str {
         &self.name
     }
 
+    fn capabilities(&self) -> NetCapabilities {
+        self.inner.capabilities()
+    }
+
+    fn link_up(&self) -> bool {
+        !self.health.offline && self.link.up.load(Ordering::Acquire)
+    }
+
+    fn set_ipv4(&mut self, ip: Option<Ipv4Cidr>) {
+        self.ip = ip;
+        // Packets waiting for a neighbor carry the old source address
+        self.neighbors.clear();
+        while self.pending_tx.dequeue().is_ok() {}
+    }
+
+    fn announce(&mut self) {
+        if let Some(ip) = self.ip
+            && self.link_up()
+        {
+            // A gratuitous ARP request, asking for our own address
+            let ip = IpAddress::Ipv4(ip.address());
+            self.send_arp_request(ip);
+            self.neighbors.remove(&ip);
+        }
+    }
+
+    /// Resets the NIC, which brings it back online.
+    fn reset(&mut self) {
+        if super::reset_nic(&self.name, &mut self.inner) && self.health.offline {
+            info!("{}: back online", self.name);
+            self.health = Health::new(self.health.policy);
+        }
+    }
+
+    fn set_io_policy(&mut self, policy: IoPolicy) -> KResult {
+        self.health.policy = policy;
+        Ok(())
+    }
+
     fn poll_rx(&mut self, buffer: &mut PacketBuffer<()>, timestamp: Instant) -> bool {
+        self.update_health(timestamp);
+        self.update_carrier();
+        if self.health.offline {
+            return false;
+        }
+        // Masked until the queue is drained, so that the frames arriving in
+        // between wake the poller only once
+        if self.inner.ack_interrupt() {
+            trace!("{}: rx interrupt", self.name);
+        }
+        self.inner.enable_rx_interrupt(false);
         loop {
             let rx_buf: NetBufHandle = match self.inner.recv() {
                 Ok(buf) => buf,
-                Err(err) => {
-                    if !matches!(err, DriverError::WouldBlock) {
-                        warn!("recv failed: {:?}", err);
+                Err(DriverError::WouldBlock) => {
+                    self.inner.enable_rx_interrupt(true);
+                    // A frame that arrived before unmasking raised nothing
+                    if self.inner.can_rx() {
+                        self.inner.enable_rx_interrupt(false);
+                        continue;
                     }
                     return false;
                 }
+                Err(err) => {
+                    warn!("recv failed: {:?}", err);
+                    self.inner.enable_rx_interrupt(true);
+                    return false;
+                }
             };
             trace!("RECV {} bytes: {:02X?}", rx_buf.len(), rx_buf.data());
+            RX_FRAMES.inc();
+            RX_BYTES.add(rx_buf.len() as u64);
+            capture::tap(
+                &self.name,
+                Direction::Incoming,
+                LinkType::Ethernet,
+                rx_buf.data(),
+            );
 
             let result = self.handle_rx_frame(rx_buf.data(), buffer, timestamp);
             self.inner.recycle_rx(rx_buf).unwrap();
@@ -293,9 +525,13 @@ impl NetDeviceOps for EthernetDevice {
         ip_packet: &[u8],
         timestamp: Instant,
     ) -> bool {
-        if next_hop.is_broadcast() || self.ip.broadcast().map(IpAddress::Ipv4) == Some(next_hop) {
+        if next_hop.is_broadcast()
+            || self.ip.and_then(|ip| ip.broadcast()).map(IpAddress::Ipv4) == Some(next_hop)
+        {
             Self::send_to(
+                &self.name,
                 &mut self.inner,
+                &mut self.health,
                 EthernetAddress::BROADCAST,
                 ip_packet.len(),
                 |buf| buf.copy_from_slice(ip_packet),
@@ -303,12 +539,28 @@ impl NetDeviceOps for EthernetDevice {
             );
             return false;
         }
+        if let IpAddress::Ipv4(group) = next_hop
+            && group.is_multicast()
+        {
+            Self::send_to(
+                &self.name,
+                &mut self.inner,
+                &mut self.health,
+                multicast_mac(group),
+                ip_packet.len(),
+                |buf| buf.copy_from_slice(ip_packet),
+                EthernetProtocol::Ipv4,
+            );
+            return false;
+        }
 
         let need_request = match self.neighbors.get(&next_hop) {
             Some(Some(neighbor)) => {
                 if neighbor.expires_at > timestamp {
                     Self::send_to(
+                        &self.name,
                         &mut self.inner,
+                        &mut self.health,
                         neighbor.hardware_address,
                         ip_packet.len(),
                         |buf| buf.copy_from_slice(ip_packet),
@@ -340,6 +592,7 @@ impl NetDeviceOps for EthernetDevice {
     }
 
     fn register_rx_waker(&self, waker: &Waker) {
+        self.link.wakers.register(waker);
         if let Some(irq) = self.inner.irq() {
             register_irq_waker(irq, waker);
         }
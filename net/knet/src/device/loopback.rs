@@ -6,6 +6,7 @@
 use alloc::vec;
 use core::task::Waker;
 
+use kdriver::prelude::NetCapabilities;
 use kpoll::PollSet;
 use smoltcp::{
     storage::{PacketBuffer, PacketMetadata},
@@ -14,6 +15,7 @@ use smoltcp::{
 };
 
 use crate::{
+    capture::{self, Direction, LinkType},
     consts::{SOCKET_BUFFER_SIZE, STANDARD_MTU},
     device::NetDevice,
 };
@@ -26,9 +28,12 @@ pub struct LoopbackDevice {
 impl LoopbackDevice {
     /// Create a new loopback device.
     pub fn new() -> Self {
-        let queue = PacketBuffer::new(
-            vec![PacketMetadata::EMPTY; SOCKET_BUFFER_SIZE],
-            vec![0u8; STANDARD_MTU * SOCKET_BUFFER_SIZE],
+        let queue = kalloc::heap_tagged!(
+            NetBuf,
+            PacketBuffer::new(
+                vec![PacketMetadata::EMPTY; SOCKET_BUFFER_SIZE],
+                vec![0u8; STANDARD_MTU * SOCKET_BUFFER_SIZE],
+            )
         );
         Self {
             queue,
@@ -42,8 +47,14 @@ impl NetDevice for LoopbackDevice {
         "lo"
     }
 
+    fn capabilities(&self) -> NetCapabilities {
+        // Packets never leave memory, so there is nothing to protect
+        NetCapabilities::RX_CSUM | NetCapabilities::TX_CSUM
+    }
+
     fn poll_rx(&mut self, buffer: &mut PacketBuffer<()>, _timestamp: Instant) -> bool {
         self.queue.dequeue().ok().is_some_and(|(_, rx_buf)| {
+            capture::tap("lo", Direction::Incoming, LinkType::RawIp, rx_buf);
             buffer
                 .enqueue(rx_buf.len(), ())
                 .unwrap()
@@ -61,6 +72,7 @@ impl NetDevice for LoopbackDevice {
         match self.queue.enqueue(ip_packet.len(), ()) {
             Ok(tx_buf) => {
                 tx_buf.copy_from_slice(ip_packet);
+                capture::tap("lo", Direction::Outgoing, LinkType::RawIp, ip_packet);
                 self.wakers.wake();
                 true
             }
@@ -3,24 +3,79 @@
 // See LICENSES for license details.
 
 //! Network device abstractions.
+use alloc::string::String;
 use core::task::Waker;
 
-use smoltcp::{storage::PacketBuffer, time::Instant, wire::IpAddress};
+use kdriver::prelude::{DriverError, DriverOps, IoPolicy, NetCapabilities};
+use kerrno::{KError, KResult};
+use knotifier::AtomicNotifierChain;
+use smoltcp::{
+    storage::PacketBuffer,
+    time::Instant,
+    wire::{IpAddress, Ipv4Cidr},
+};
 
 mod ethernet;
 mod loopback;
+mod veth;
 #[cfg(feature = "vsock")]
 mod vsock;
 
 pub use ethernet::*;
 pub use loopback::*;
+pub use veth::*;
 #[cfg(feature = "vsock")]
 pub use vsock::*;
 
+/// An interface gaining or losing its carrier.
+#[derive(Debug, Clone)]
+pub struct NetdevEvent {
+    /// Name of the interface.
+    pub name: String,
+    /// Whether the link is up now.
+    pub up: bool,
+}
+
+/// Notified of link changes, with the network stack of the interface locked.
+pub static NETDEV_NOTIFIER: AtomicNotifierChain<NetdevEvent> = AtomicNotifierChain::new();
+
 /// Trait implemented by network device backends.
 pub trait NetDevice: Send + Sync {
     fn name(&self) -> &str;
 
+    /// Hardware offloads of the device.
+    ///
+    /// The router computes and verifies checksums in software for devices
+    /// lacking checksum offload.
+    fn capabilities(&self) -> NetCapabilities {
+        NetCapabilities::empty()
+    }
+
+    /// Whether the device has carrier.
+    ///
+    /// The router drops packets routed to a device without carrier.
+    fn link_up(&self) -> bool {
+        true
+    }
+
+    /// Changes the address the device resolves neighbors for, `None` while
+    /// it has none.
+    fn set_ipv4(&mut self, _ip: Option<Ipv4Cidr>) {}
+
+    /// Announces the address of the device on its link, e.g. after the VM
+    /// was migrated.
+    fn announce(&mut self) {}
+
+    /// Resets the NICs behind the device, e.g. after their backends on the
+    /// host restarted. Frames in flight are lost.
+    fn reset(&mut self) {}
+
+    /// Sets what the device does about its NIC failing to send, see
+    /// [`NetStack::set_io_policy`](crate::netns::NetStack::set_io_policy).
+    fn set_io_policy(&mut self, _policy: IoPolicy) -> KResult {
+        Err(KError::OperationNotSupported)
+    }
+
     /// Polls the device and pushes received IP packets into `buffer`.
     fn poll_rx(&mut self, buffer: &mut PacketBuffer<()>, timestamp: Instant) -> bool;
     /// Sends an IP packet to the next hop.
@@ -34,3 +89,19 @@ pub trait NetDevice: Send + Sync {
     /// Register a waker for receive readiness.
     fn register_rx_waker(&self, waker: &Waker);
 }
+
+/// Resets the NIC `dev`, logging the outcome; NICs unable to reset are left
+/// alone.
+///
+/// Returns whether the NIC was reset.
+pub(crate) fn reset_nic(name: &str, dev: &mut (impl DriverOps + ?Sized)) -> bool {
+    match dev.reset() {
+        Ok(()) => {
+            info!("{}: reset", name);
+            return true;
+        }
+        Err(DriverError::Unsupported) => {}
+        Err(err) => warn!("{}: failed to reset: {:?}", name, err),
+    }
+    false
+}
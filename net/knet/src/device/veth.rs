@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Virtual point-to-point links between network stacks.
+use alloc::{boxed::Box, format, string::String, sync::Arc};
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Waker,
+};
+
+use kdriver::prelude::{MacAddress, NetCapabilities, NetDriverOps, VirtualNic};
+use kpoll::PollSet;
+use smoltcp::{storage::PacketBuffer, time::Instant, wire::IpAddress};
+
+use crate::{
+    capture::{self, Direction, LinkType},
+    device::NetDevice,
+};
+
+/// The address of both NICs of a pair, which carry no Ethernet header.
+const VETH_MAC: MacAddress = MacAddress([0x02, 0, 0, 0, 0, 0]);
+
+/// One end of a veth pair.
+///
+/// IP packets sent on one end are received on the other. The pair is a
+/// [`VirtualNic`] pair carrying no link-layer header, so each end is a
+/// point-to-point link.
+pub struct VethDevice {
+    name: String,
+    nic: VirtualNic,
+    /// Woken when a packet arrives or the peer goes away.
+    wakers: Arc<PollSet>,
+}
+
+impl VethDevice {
+    /// Creates a connected pair of devices.
+    pub fn new_pair() -> (Self, Self) {
+        static NEXT_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+        let (nic_a, nic_b) = VirtualNic::new_pair(VETH_MAC, VETH_MAC);
+        let index = NEXT_INDEX.fetch_add(2, Ordering::Relaxed);
+        let end = |mut nic: VirtualNic, side| {
+            let wakers = Arc::new(PollSet::new());
+            let rx_wakers = wakers.clone();
+            nic.set_rx_notify_hook(Box::new(move || {
+                rx_wakers.wake();
+            }));
+            // Let the peer notice the carrier loss
+            let link_wakers = wakers.clone();
+            nic.set_link_change_hook(Box::new(move |_| {
+                link_wakers.wake();
+            }));
+            Self {
+                name: format!("veth{}", index + side),
+                nic,
+                wakers,
+            }
+        };
+        (end(nic_a, 0), end(nic_b, 1))
+    }
+}
+
+impl NetDevice for VethDevice {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn capabilities(&self) -> NetCapabilities {
+        // Like loopback, packets never leave memory
+        NetCapabilities::RX_CSUM | NetCapabilities::TX_CSUM
+    }
+
+    fn link_up(&self) -> bool {
+        self.nic.link_up()
+    }
+
+    fn poll_rx(&mut self, buffer: &mut PacketBuffer<()>, _timestamp: Instant) -> bool {
+        let Ok(rx_buf) = self.nic.recv() else {
+            return false;
+        };
+        let packet = rx_buf.data();
+        capture::tap(&self.name, Direction::Incoming, LinkType::RawIp, packet);
+        buffer
+            .enqueue(packet.len(), ())
+            .unwrap()
+            .copy_from_slice(packet);
+        if let Err(err) = self.nic.recycle_rx(rx_buf) {
+            warn!("{}: recycle_rx failed: {:?}", self.name, err);
+        }
+        true
+    }
+
+    fn send_ip_packet(
+        &mut self,
+        next_hop: IpAddress,
+        ip_packet: &[u8],
+        _timestamp: Instant,
+    ) -> bool {
+        let result = self
+            .nic
+            .alloc_tx_buf(ip_packet.len())
+            .and_then(|mut tx_buf| {
+                tx_buf.data_mut().copy_from_slice(ip_packet);
+                self.nic.send(tx_buf)
+            });
+        if let Err(err) = result {
+            warn!(
+                "{}: send failed: {:?}, dropping packet to {}",
+                self.name, err, next_hop
+            );
+            return false;
+        }
+        capture::tap(&self.name, Direction::Outgoing, LinkType::RawIp, ip_packet);
+        // The peer stack is polled along with this one
+        true
+    }
+
+    fn register_rx_waker(&self, waker: &Waker) {
+        self.wakers.register(waker);
+    }
+}
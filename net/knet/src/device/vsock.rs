@@ -10,7 +10,7 @@ use core::{
 };
 
 use kdriver::prelude::*;
-use kerrno::{KError, KResult, k_bail};
+use kerrno::{KError, KResult, ResultExt, k_bail};
 use ksync::Mutex;
 use ktask::future::{block_on, interruptible};
 
@@ -21,6 +21,7 @@ static VSOCK_DEV: Mutex<Option<VsockDevice>> = Mutex::new(None);
 static VSOCK_EVENT_QUEUE: Mutex<VecDeque<VsockDriverEventType>> = Mutex::new(VecDeque::new());
 
 const VSOCK_RX_SCRATCH_SIZE: usize = 0x1000; // 4KiB scratch buffer for vsock receive
+const VSOCK_ATOMIC_SEND_ATTEMPTS: usize = 100_000; // polls for credit before giving up
 
 /// Registers a vsock device. Only one vsock device can be registered.
 pub fn register_vsock_dev(dev: VsockDevice) -> KResult {
@@ -170,37 +171,58 @@ fn handle_vsock_event(event: VsockDriverEventType, dev: &mut VsockDevice, buf: &
     match event {
         VsockDriverEventType::ConnectionRequest(conn_id) => {
             if let Err(e) = manager.on_connection_request(conn_id) {
-                info!("Connection request failed: {conn_id:?}, error={e:?}");
+                info!("Connection request refused: {conn_id:?}, error={e:?}");
+                // Reset the peer instead of leaving it waiting for a response
+                let _ = dev.abort(conn_id);
             }
         }
 
         VsockDriverEventType::Received(conn_id, len) => {
-            let free_space = if let Some(conn) = manager.get_connection(conn_id) {
-                conn.lock().rx_buffer_free()
-            } else {
+            let Some(conn) = manager.get_connection(conn_id) else {
                 info!("Received data for unknown connection: {conn_id:?}");
                 return;
             };
 
-            if free_space == 0 {
-                VSOCK_EVENT_QUEUE
-                    .lock()
-                    .push_back(VsockDriverEventType::Received(conn_id, len));
-                return;
+            // Drain the driver, keeping message boundaries for seqpacket
+            loop {
+                let free_space = conn.lock().rx_buffer_free();
+                if free_space == 0 {
+                    VSOCK_EVENT_QUEUE
+                        .lock()
+                        .push_back(VsockDriverEventType::Received(conn_id, len));
+                    return;
+                }
+
+                let max_read = core::cmp::min(free_space, buf.len());
+                match dev.recv_msg(conn_id, &mut buf[..max_read]) {
+                    Ok((0, false)) => break,
+                    Ok((read_len, eom)) => {
+                        if let Err(e) = manager.on_data_received(conn_id, &buf[..read_len], eom) {
+                            info!(
+                                "Failed to dispatch_irq received data: conn_id={conn_id:?}, \
+                                 error={e:?}",
+                            );
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        info!("Failed to receive vsock data: conn_id={conn_id:?}, error={e:?}",);
+                        break;
+                    }
+                }
             }
+        }
 
-            let max_read = core::cmp::min(free_space, buf.len());
-            match dev.recv(conn_id, &mut buf[..max_read]) {
-                Ok(read_len) => {
-                    if let Err(e) = manager.on_data_received(conn_id, &buf[..read_len]) {
-                        info!(
-                            "Failed to dispatch_irq received data: conn_id={conn_id:?}, \
-                             error={e:?}",
-                        );
+        VsockDriverEventType::DatagramReceived(conn_id, _len) => {
+            match dev.recv_dgram(conn_id, buf) {
+                Ok(len) => {
+                    let len = len.min(buf.len());
+                    if let Err(e) = manager.on_datagram_received(conn_id, &buf[..len]) {
+                        debug!("Dropped vsock datagram: conn_id={conn_id:?}, error={e:?}");
                     }
                 }
                 Err(e) => {
-                    info!("Failed to receive vsock data: conn_id={conn_id:?}, error={e:?}",);
+                    info!("Failed to receive vsock datagram: conn_id={conn_id:?}, error={e:?}");
                 }
             }
         }
@@ -217,43 +239,101 @@ fn handle_vsock_event(event: VsockDriverEventType, dev: &mut VsockDevice, buf: &
             }
         }
 
+        VsockDriverEventType::CreditUpdate(conn_id) => {
+            if let Err(e) = manager.on_credit_update(conn_id) {
+                debug!("Credit update for unknown connection: {conn_id:?}, error={e:?}");
+            }
+        }
+
         VsockDriverEventType::Unknown => warn!("Received unknown vsock event"),
     }
 }
 
-pub fn vsock_listen(addr: VsockAddr) -> KResult<()> {
+pub fn vsock_listen(addr: VsockAddr, ty: VsockSocketType) -> KResult<()> {
     let mut guard = VSOCK_DEV.lock();
     let dev = guard.as_mut().ok_or(KError::NotFound)?;
-    dev.listen(addr.port);
-    Ok(())
+    dev.listen(addr.port, ty).into_kresult()
 }
 
-fn map_dev_err(e: DriverError) -> KError {
-    match e {
-        DriverError::AlreadyExists => KError::AlreadyExists,
-        DriverError::WouldBlock => KError::WouldBlock,
-        DriverError::InvalidInput => KError::InvalidInput,
-        DriverError::Io => KError::Io,
-        _ => KError::BadState,
-    }
+pub fn vsock_unlisten(port: u32) -> KResult<()> {
+    let mut guard = VSOCK_DEV.lock();
+    let dev = guard.as_mut().ok_or(KError::NotFound)?;
+    dev.unlisten(port);
+    Ok(())
 }
 
-pub fn vsock_connect(conn_id: VsockConnId) -> KResult<()> {
+pub fn vsock_connect(conn_id: VsockConnId, ty: VsockSocketType) -> KResult<()> {
     let mut guard = VSOCK_DEV.lock();
     let dev = guard.as_mut().ok_or(KError::NotFound)?;
-    dev.connect(conn_id).map_err(map_dev_err)
+    dev.connect(conn_id, ty).into_kresult()
 }
 
 pub fn vsock_send(conn_id: VsockConnId, buf: &[u8]) -> KResult<usize> {
     let mut guard = VSOCK_DEV.lock();
     let dev = guard.as_mut().ok_or(KError::NotFound)?;
-    dev.send(conn_id, buf).map_err(map_dev_err)
+    dev.send(conn_id, buf).into_kresult()
+}
+
+/// Sends all of `buf` on `conn_id` without sleeping, waiting for peer
+/// credit for a bounded time.
+///
+/// For reports from panic, interrupt and NMI context: fails with
+/// [`KError::ResourceBusy`] instead of waiting for the device lock. Events
+/// seen while waiting are left for the poll task.
+pub fn vsock_send_atomic(conn_id: VsockConnId, buf: &[u8]) -> KResult<usize> {
+    let mut guard = VSOCK_DEV.try_lock().ok_or(KError::ResourceBusy)?;
+    let dev = guard.as_mut().ok_or(KError::NotFound)?;
+    let mut sent = 0;
+    let mut attempts = 0;
+    while sent < buf.len() {
+        match dev.send(conn_id, &buf[sent..]) {
+            Ok(len) => sent += len,
+            Err(DriverError::WouldBlock) if attempts < VSOCK_ATOMIC_SEND_ATTEMPTS => {
+                attempts += 1;
+                if let Ok(Some(event)) = dev.poll_event()
+                    && let Some(mut queue) = VSOCK_EVENT_QUEUE.try_lock()
+                {
+                    queue.push_back(event);
+                }
+                core::hint::spin_loop();
+            }
+            Err(e) if sent == 0 => return Err(e.into()),
+            Err(_) => break,
+        }
+    }
+    Ok(sent)
+}
+
+pub fn vsock_send_dgram(src_port: u32, peer_addr: VsockAddr, buf: &[u8]) -> KResult<usize> {
+    let mut guard = VSOCK_DEV.lock();
+    let dev = guard.as_mut().ok_or(KError::NotFound)?;
+    dev.send_dgram(src_port, peer_addr, buf).into_kresult()
+}
+
+pub fn vsock_supports(ty: VsockSocketType) -> bool {
+    VSOCK_DEV
+        .lock()
+        .as_ref()
+        .is_some_and(|dev| dev.supports(ty))
 }
 
 pub fn vsock_disconnect(conn_id: VsockConnId) -> KResult<()> {
     let mut guard = VSOCK_DEV.lock();
     let dev = guard.as_mut().ok_or(KError::NotFound)?;
-    dev.disconnect(conn_id).map_err(map_dev_err)
+    dev.disconnect(conn_id).into_kresult()
+}
+
+pub fn vsock_abort(conn_id: VsockConnId) -> KResult<()> {
+    let mut guard = VSOCK_DEV.lock();
+    let dev = guard.as_mut().ok_or(KError::NotFound)?;
+    dev.abort(conn_id).into_kresult()
+}
+
+/// Returns how many bytes the peer can currently accept on `conn_id`.
+pub fn vsock_send_credit(conn_id: VsockConnId) -> KResult<usize> {
+    let mut guard = VSOCK_DEV.lock();
+    let dev = guard.as_mut().ok_or(KError::NotFound)?;
+    dev.send_credit(conn_id).into_kresult()
 }
 
 pub fn vsock_guest_cid() -> KResult<u64> {
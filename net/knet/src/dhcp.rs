@@ -0,0 +1,647 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! DHCP client.
+//!
+//! Without a static address given at build time (`K_IP` left empty), `eth0`
+//! is configured by DHCP (RFC 2131): a task acquires a lease, applies its
+//! address, router and name servers to the initial stack and renews it
+//! before it runs out. A lease that expires or is refused leaves the
+//! interface without an address until a new one is acquired.
+//!
+//! Messages go through a raw socket, since they are exchanged before the
+//! interface has an address, and servers are asked to broadcast replies
+//! until then.
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use core::{
+    net::{Ipv4Addr, SocketAddr},
+    task::{Context, Waker},
+    time::Duration,
+};
+
+use kerrno::{KError, KResult};
+use khal::time::{monotonic_time, monotonic_time_nanos};
+use kpoll::{IoEvents, PollSet, Pollable};
+use ksync::Mutex;
+use ktask::future::{block_on, poll_io, sleep, timeout};
+use smoltcp::{
+    iface::SocketHandle,
+    phy::ChecksumCapabilities,
+    socket::raw,
+    wire::{
+        EthernetAddress, IpProtocol, IpVersion, Ipv4Cidr, Ipv4Packet, Ipv4Repr, UdpPacket, UdpRepr,
+    },
+};
+
+use crate::{
+    consts::{DNS, HOSTNAME, IP_PREFIX, STANDARD_MTU},
+    dns::{self, DNS_PORT},
+    mdns,
+    netns::{NetStack, init_stack},
+    poll_interfaces,
+};
+
+const SERVER_PORT: u16 = 67;
+const CLIENT_PORT: u16 = 68;
+
+const BOOTREQUEST: u8 = 1;
+const BOOTREPLY: u8 = 2;
+const HTYPE_ETHERNET: u8 = 1;
+/// Asks servers to broadcast their replies.
+const FLAG_BROADCAST: u16 = 0x8000;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+/// Length of a message up to its options.
+const HEADER_LEN: usize = 240;
+
+const OPT_PAD: u8 = 0;
+const OPT_SUBNET_MASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_DNS: u8 = 6;
+const OPT_HOSTNAME: u8 = 12;
+const OPT_REQUESTED_ADDR: u8 = 50;
+const OPT_LEASE_TIME: u8 = 51;
+const OPT_MESSAGE_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8 = 54;
+const OPT_PARAMETERS: u8 = 55;
+const OPT_RENEWAL_TIME: u8 = 58;
+const OPT_REBINDING_TIME: u8 = 59;
+const OPT_CLIENT_ID: u8 = 61;
+const OPT_END: u8 = 255;
+
+/// First retransmission timeout, doubled after each attempt.
+const MIN_RETRANSMIT: Duration = Duration::from_secs(4);
+const MAX_RETRANSMIT: Duration = Duration::from_secs(64);
+/// How long to wait for the acknowledgement of an offer.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// Longest sleep at a time, so that far deadlines do not overflow timers.
+const MAX_SLEEP: Duration = Duration::from_secs(3600);
+
+/// The kind of a DHCP message.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MessageType {
+    Discover = 1,
+    Offer = 2,
+    Request = 3,
+    Decline = 4,
+    Ack = 5,
+    Nak = 6,
+    Release = 7,
+}
+
+impl MessageType {
+    fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            1 => Self::Discover,
+            2 => Self::Offer,
+            3 => Self::Request,
+            4 => Self::Decline,
+            5 => Self::Ack,
+            6 => Self::Nak,
+            7 => Self::Release,
+            _ => return None,
+        })
+    }
+}
+
+/// A message from the client.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ClientMessage<'a> {
+    pub kind: MessageType,
+    pub xid: u32,
+    pub mac: EthernetAddress,
+    /// The leased address, while renewing or rebinding it.
+    pub ciaddr: Ipv4Addr,
+    /// The offered address, while requesting it.
+    pub requested: Option<Ipv4Addr>,
+    /// The server whose offer is requested.
+    pub server: Option<Ipv4Addr>,
+    pub hostname: &'a str,
+}
+
+impl ClientMessage<'_> {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![0; HEADER_LEN];
+        buf[0] = BOOTREQUEST;
+        buf[1] = HTYPE_ETHERNET;
+        buf[2] = self.mac.0.len() as u8;
+        buf[4..8].copy_from_slice(&self.xid.to_be_bytes());
+        if self.ciaddr.is_unspecified() {
+            // Unicast replies could not reach an unconfigured interface
+            buf[10..12].copy_from_slice(&FLAG_BROADCAST.to_be_bytes());
+        }
+        buf[12..16].copy_from_slice(&self.ciaddr.octets());
+        buf[28..34].copy_from_slice(&self.mac.0);
+        buf[236..240].copy_from_slice(&MAGIC_COOKIE);
+
+        let mut option = |code: u8, data: &[u8]| {
+            buf.push(code);
+            buf.push(data.len() as u8);
+            buf.extend_from_slice(data);
+        };
+        option(OPT_MESSAGE_TYPE, &[self.kind as u8]);
+        let mut client_id = vec![HTYPE_ETHERNET];
+        client_id.extend_from_slice(&self.mac.0);
+        option(OPT_CLIENT_ID, &client_id);
+        if let Some(addr) = self.requested {
+            option(OPT_REQUESTED_ADDR, &addr.octets());
+        }
+        if let Some(addr) = self.server {
+            option(OPT_SERVER_ID, &addr.octets());
+        }
+        if !self.hostname.is_empty() {
+            option(OPT_HOSTNAME, self.hostname.as_bytes());
+        }
+        option(
+            OPT_PARAMETERS,
+            &[
+                OPT_SUBNET_MASK,
+                OPT_ROUTER,
+                OPT_DNS,
+                OPT_LEASE_TIME,
+                OPT_RENEWAL_TIME,
+                OPT_REBINDING_TIME,
+            ],
+        );
+        buf.push(OPT_END);
+        buf
+    }
+}
+
+/// A message from a server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ServerMessage {
+    pub kind: MessageType,
+    /// The address offered or acknowledged.
+    pub yiaddr: Ipv4Addr,
+    pub server: Option<Ipv4Addr>,
+    pub subnet_mask: Option<Ipv4Addr>,
+    pub router: Option<Ipv4Addr>,
+    pub dns: Vec<Ipv4Addr>,
+    /// Times in seconds.
+    pub lease_time: Option<u32>,
+    pub renewal_time: Option<u32>,
+    pub rebinding_time: Option<u32>,
+}
+
+fn addr_of(data: &[u8]) -> Option<Ipv4Addr> {
+    Some(Ipv4Addr::from(<[u8; 4]>::try_from(data.get(..4)?).ok()?))
+}
+
+fn u32_of(data: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(data.try_into().ok()?))
+}
+
+impl ServerMessage {
+    /// Parses a reply to transaction `xid` of the client `mac`.
+    ///
+    /// Options overloaded into the `sname` and `file` fields are ignored.
+    pub fn parse(buf: &[u8], xid: u32, mac: EthernetAddress) -> Option<Self> {
+        if buf.len() < HEADER_LEN
+            || buf[0] != BOOTREPLY
+            || buf[4..8] != xid.to_be_bytes()
+            || buf[28..34] != mac.0
+            || buf[236..240] != MAGIC_COOKIE
+        {
+            return None;
+        }
+        let mut kind = None;
+        let mut message = Self {
+            kind: MessageType::Offer,
+            yiaddr: addr_of(&buf[16..20])?,
+            server: None,
+            subnet_mask: None,
+            router: None,
+            dns: Vec::new(),
+            lease_time: None,
+            renewal_time: None,
+            rebinding_time: None,
+        };
+
+        let mut options = &buf[HEADER_LEN..];
+        while let [code, rest @ ..] = options {
+            match *code {
+                OPT_PAD => {
+                    options = rest;
+                    continue;
+                }
+                OPT_END => break,
+                _ => {}
+            }
+            let (&len, rest) = rest.split_first()?;
+            let data = rest.get(..len as usize)?;
+            options = &rest[len as usize..];
+            match *code {
+                OPT_MESSAGE_TYPE => kind = data.first().copied().and_then(MessageType::from_u8),
+                OPT_SERVER_ID => message.server = addr_of(data),
+                OPT_SUBNET_MASK => message.subnet_mask = addr_of(data),
+                OPT_ROUTER => message.router = addr_of(data),
+                OPT_DNS => message.dns = data.chunks_exact(4).filter_map(addr_of).collect(),
+                OPT_LEASE_TIME => message.lease_time = u32_of(data),
+                OPT_RENEWAL_TIME => message.renewal_time = u32_of(data),
+                OPT_REBINDING_TIME => message.rebinding_time = u32_of(data),
+                _ => {}
+            }
+        }
+        message.kind = kind?;
+        Some(message)
+    }
+
+    /// The lease granted by this acknowledgement, received at `now` on the
+    /// monotonic clock.
+    ///
+    /// Renewal and rebinding default to half and seven eighths of the lease
+    /// time.
+    pub fn lease(&self, now: Duration) -> Option<Lease> {
+        let server = self.server?;
+        let lease_time = self.lease_time?;
+        if self.yiaddr.is_unspecified() || self.yiaddr.is_broadcast() {
+            return None;
+        }
+        let addr = self
+            .subnet_mask
+            .and_then(|mask| Ipv4Cidr::from_netmask(self.yiaddr, mask).ok())
+            .unwrap_or_else(|| Ipv4Cidr::new(self.yiaddr, IP_PREFIX));
+
+        let at = |secs: u32| {
+            if lease_time == u32::MAX {
+                Duration::MAX
+            } else {
+                now.saturating_add(Duration::from_secs(secs.into()))
+            }
+        };
+        let rebinding_time = self
+            .rebinding_time
+            .filter(|&time| time <= lease_time)
+            .unwrap_or((lease_time as u64 * 7 / 8) as u32);
+        let renewal_time = self
+            .renewal_time
+            .filter(|&time| time <= rebinding_time)
+            .unwrap_or(lease_time / 2)
+            .min(rebinding_time);
+        Some(Lease {
+            addr,
+            // A router off the subnet would be unreachable
+            router: self.router.filter(|router| addr.contains_addr(router)),
+            dns: self.dns.clone(),
+            server,
+            renew_at: at(renewal_time),
+            rebind_at: at(rebinding_time),
+            expires_at: at(lease_time),
+        })
+    }
+}
+
+/// An address leased from a DHCP server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    pub addr: Ipv4Cidr,
+    pub router: Option<Ipv4Addr>,
+    pub dns: Vec<Ipv4Addr>,
+    /// The server that granted the lease.
+    pub server: Ipv4Addr,
+    /// When to ask the server to extend the lease, on the monotonic clock.
+    pub renew_at: Duration,
+    /// When to ask any server to extend the lease.
+    pub rebind_at: Duration,
+    /// When the lease runs out, `Duration::MAX` if never.
+    pub expires_at: Duration,
+}
+
+static LEASE: Mutex<Option<Lease>> = Mutex::new(None);
+
+/// Returns the lease `eth0` is configured with, if it is configured by DHCP.
+pub fn lease() -> Option<Lease> {
+    LEASE.lock().clone()
+}
+
+/// Wraps a client message into UDP and IPv4 headers.
+fn udp_datagram(src: Ipv4Addr, dst: Ipv4Addr, payload: &[u8]) -> Vec<u8> {
+    let udp = UdpRepr {
+        src_port: CLIENT_PORT,
+        dst_port: SERVER_PORT,
+    };
+    let ip = Ipv4Repr {
+        src_addr: src,
+        dst_addr: dst,
+        next_header: IpProtocol::Udp,
+        payload_len: udp.header_len() + payload.len(),
+        hop_limit: 64,
+    };
+    let caps = ChecksumCapabilities::default();
+    let mut buf = vec![0; ip.buffer_len() + ip.payload_len];
+    let mut packet = Ipv4Packet::new_unchecked(&mut buf[..]);
+    ip.emit(&mut packet, &caps);
+    udp.emit(
+        &mut UdpPacket::new_unchecked(packet.payload_mut()),
+        &src.into(),
+        &dst.into(),
+        payload.len(),
+        |buf| buf.copy_from_slice(payload),
+        &caps,
+    );
+    buf
+}
+
+/// Returns the payload of a datagram from a server to a client.
+fn parse_datagram(packet: &[u8]) -> Option<&[u8]> {
+    let ip = Ipv4Packet::new_checked(packet).ok()?;
+    let udp = UdpPacket::new_checked(ip.payload()).ok()?;
+    (udp.src_port() == SERVER_PORT && udp.dst_port() == CLIENT_PORT).then(|| udp.payload())
+}
+
+/// A raw socket receiving every UDP datagram of a stack.
+struct RawSocket {
+    stack: Arc<NetStack>,
+    handle: SocketHandle,
+    wakers: Arc<PollSet>,
+}
+
+impl RawSocket {
+    fn new(stack: Arc<NetStack>) -> Self {
+        let buffer = || {
+            raw::PacketBuffer::new(
+                vec![raw::PacketMetadata::EMPTY; 16],
+                vec![0; 16 * STANDARD_MTU],
+            )
+        };
+        let socket = raw::Socket::new(
+            Some(IpVersion::Ipv4),
+            Some(IpProtocol::Udp),
+            buffer(),
+            buffer(),
+        );
+        let handle = stack.sockets.add(socket);
+        Self {
+            stack,
+            handle,
+            wakers: Arc::new(PollSet::new()),
+        }
+    }
+
+    fn send(&self, packet: &[u8]) {
+        let sent = self
+            .stack
+            .sockets
+            .with_socket_mut::<raw::Socket, _, _>(self.handle, |socket| socket.send_slice(packet));
+        if sent.is_err() {
+            debug!("DHCP: send buffer full");
+        }
+        poll_interfaces();
+    }
+
+    fn try_recv(&self) -> KResult<Vec<u8>> {
+        self.stack
+            .sockets
+            .with_socket_mut::<raw::Socket, _, _>(self.handle, |socket| {
+                socket.recv().map(<[u8]>::to_vec)
+            })
+            .map_err(|_| KError::WouldBlock)
+    }
+
+    /// Receives a datagram, waiting until `deadline` on the monotonic clock.
+    fn recv_until(&self, deadline: Duration) -> Option<Vec<u8>> {
+        let wait = deadline.checked_sub(monotonic_time())?;
+        block_on(timeout(
+            Some(wait),
+            poll_io(self, IoEvents::IN, false, || self.try_recv()),
+        ))
+        .ok()?
+        .ok()
+    }
+
+    /// Sends `message` to `dst` until `accept` takes a reply, backing off
+    /// exponentially, or until `deadline`.
+    fn exchange<T>(
+        &self,
+        message: &ClientMessage,
+        dst: Ipv4Addr,
+        deadline: Option<Duration>,
+        mut accept: impl FnMut(ServerMessage) -> Option<T>,
+    ) -> Option<T> {
+        let packet = udp_datagram(message.ciaddr, dst, &message.encode());
+        let mut retransmit = MIN_RETRANSMIT;
+        loop {
+            let now = monotonic_time();
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                return None;
+            }
+            self.send(&packet);
+            let until =
+                deadline.map_or(now + retransmit, |deadline| deadline.min(now + retransmit));
+            while let Some(datagram) = self.recv_until(until) {
+                let reply = parse_datagram(&datagram)
+                    .and_then(|payload| ServerMessage::parse(payload, message.xid, message.mac));
+                if let Some(value) = reply.and_then(&mut accept) {
+                    return Some(value);
+                }
+            }
+            retransmit = (retransmit * 2).min(MAX_RETRANSMIT);
+        }
+    }
+}
+
+impl Pollable for RawSocket {
+    fn poll(&self) -> IoEvents {
+        poll_interfaces();
+        let mut events = IoEvents::empty();
+        self.stack
+            .sockets
+            .with_socket::<raw::Socket, _, _>(self.handle, |socket| {
+                events.set(IoEvents::IN, socket.can_recv());
+            });
+        events
+    }
+
+    fn register(&self, context: &mut Context<'_>, events: IoEvents) {
+        if events.contains(IoEvents::IN) {
+            self.wakers.register(context.waker());
+            let waker = Waker::from(self.wakers.clone());
+            self.stack
+                .sockets
+                .with_socket_mut::<raw::Socket, _, _>(self.handle, |socket| {
+                    socket.register_recv_waker(&waker);
+                });
+        }
+    }
+}
+
+impl Drop for RawSocket {
+    fn drop(&mut self) {
+        self.stack.sockets.remove(self.handle);
+    }
+}
+
+fn sleep_until(deadline: Duration) {
+    loop {
+        let now = monotonic_time();
+        if now >= deadline {
+            break;
+        }
+        block_on(sleep((deadline - now).min(MAX_SLEEP)));
+    }
+}
+
+/// Configures the device `name` of the initial stack with `lease`.
+fn bind(name: &str, lease: &Lease) {
+    if let Err(err) = init_stack().set_ipv4_addr(name, Some(lease.addr), lease.router) {
+        warn!("DHCP: cannot configure {name}: {err:?}");
+        return;
+    }
+    info!(
+        "{name}: leased {} from {}, router {:?}, dns {:?}",
+        lease.addr, lease.server, lease.router, lease.dns
+    );
+    // Name servers given at build time take precedence
+    if matches!(DNS, "") && !lease.dns.is_empty() {
+        dns::set_nameservers(
+            lease
+                .dns
+                .iter()
+                .map(|&server| SocketAddr::new(server.into(), DNS_PORT))
+                .collect(),
+        );
+    }
+    *LEASE.lock() = Some(lease.clone());
+    mdns::start_responder();
+}
+
+fn unbind(name: &str) {
+    *LEASE.lock() = None;
+    if let Err(err) = init_stack().set_ipv4_addr(name, None, None) {
+        warn!("DHCP: cannot unconfigure {name}: {err:?}");
+    }
+}
+
+struct Client {
+    name: String,
+    mac: EthernetAddress,
+    socket: RawSocket,
+    xid: u32,
+}
+
+impl Client {
+    fn message(&self, kind: MessageType, ciaddr: Ipv4Addr) -> ClientMessage<'static> {
+        ClientMessage {
+            kind,
+            xid: self.xid,
+            mac: self.mac,
+            ciaddr,
+            requested: None,
+            server: None,
+            hostname: HOSTNAME,
+        }
+    }
+
+    /// Acquires a new lease, trying for as long as it takes.
+    fn acquire(&mut self) -> Lease {
+        loop {
+            self.xid = self.xid.wrapping_add(1);
+            let discover = self.message(MessageType::Discover, Ipv4Addr::UNSPECIFIED);
+            let Some(offer) = self
+                .socket
+                .exchange(&discover, Ipv4Addr::BROADCAST, None, |reply| {
+                    (reply.kind == MessageType::Offer && reply.server.is_some()).then_some(reply)
+                })
+            else {
+                continue;
+            };
+
+            let request = ClientMessage {
+                kind: MessageType::Request,
+                requested: Some(offer.yiaddr),
+                server: offer.server,
+                ..discover
+            };
+            let deadline = monotonic_time() + REQUEST_TIMEOUT;
+            let reply =
+                self.socket
+                    .exchange(&request, Ipv4Addr::BROADCAST, Some(deadline), |reply| {
+                        let answered = reply.server == offer.server
+                            && matches!(reply.kind, MessageType::Ack | MessageType::Nak);
+                        answered.then_some(reply)
+                    });
+            match reply {
+                Some(reply) if reply.kind == MessageType::Ack => {
+                    if let Some(lease) = reply.lease(monotonic_time()) {
+                        return lease;
+                    }
+                    warn!("DHCP: unusable acknowledgement {reply:?}");
+                }
+                Some(_) => warn!("DHCP: {} refused", offer.yiaddr),
+                None => debug!("DHCP: no acknowledgement for {}", offer.yiaddr),
+            }
+        }
+    }
+
+    /// Keeps `lease` bound, renewing it in time, until it is lost.
+    fn keep(&mut self, mut lease: Lease) {
+        loop {
+            sleep_until(lease.renew_at);
+            self.xid = self.xid.wrapping_add(1);
+            let request = self.message(MessageType::Request, lease.addr.address());
+            // Renewing with the server, then rebinding with any of them
+            let reply = [
+                (lease.server, lease.rebind_at),
+                (Ipv4Addr::BROADCAST, lease.expires_at),
+            ]
+            .into_iter()
+            .find_map(|(dst, deadline)| {
+                self.socket
+                    .exchange(&request, dst, Some(deadline), |reply| {
+                        matches!(reply.kind, MessageType::Ack | MessageType::Nak).then_some(reply)
+                    })
+            });
+
+            let renewed = match reply {
+                Some(reply) if reply.kind == MessageType::Ack => reply.lease(monotonic_time()),
+                Some(_) => {
+                    warn!("DHCP: lease of {} revoked", lease.addr);
+                    return;
+                }
+                None => {
+                    warn!("DHCP: lease of {} expired", lease.addr);
+                    return;
+                }
+            };
+            let Some(renewed) = renewed else {
+                warn!("DHCP: unusable renewal of {}", lease.addr);
+                return;
+            };
+            if (renewed.addr, renewed.router, &renewed.dns)
+                != (lease.addr, lease.router, &lease.dns)
+            {
+                bind(&self.name, &renewed);
+            } else {
+                debug!("DHCP: renewed lease of {}", lease.addr);
+                *LEASE.lock() = Some(renewed.clone());
+            }
+            lease = renewed;
+        }
+    }
+}
+
+/// Starts configuring the device `name` of the initial stack, whose
+/// hardware address is `mac`, by DHCP.
+pub(crate) fn start(name: String, mac: EthernetAddress) {
+    let task_name = alloc::format!("dhcp-{name}");
+    ktask::spawn_with_name(
+        move || {
+            let [_, _, m2, m3, m4, m5] = mac.0;
+            let mut client = Client {
+                name,
+                mac,
+                socket: RawSocket::new(init_stack()),
+                xid: u32::from_be_bytes([m2, m3, m4, m5]) ^ monotonic_time_nanos() as u32,
+            };
+            loop {
+                let lease = client.acquire();
+                bind(&client.name, &lease);
+                client.keep(lease);
+                unbind(&client.name);
+            }
+        },
+        task_name,
+    );
+}
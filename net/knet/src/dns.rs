@@ -0,0 +1,671 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! DNS client.
+//!
+//! [`dns_query`] resolves host names to addresses, over multicast DNS for
+//! names under `.local` (see [`mdns`](crate::mdns)) and over unicast DNS to
+//! the configured [`nameservers`] otherwise. Answers are cached for their
+//! TTL, capped at [`MAX_TTL`] seconds.
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::atomic::{AtomicU16, Ordering},
+    time::Duration,
+};
+
+use kalloc::{ShrinkControl, Shrinker};
+use kerrno::{KError, KResult, ResultExt};
+use khal::time::{monotonic_time, monotonic_time_nanos};
+use ksync::Mutex;
+
+use crate::{
+    RecvOptions, SendOptions, SocketAddrEx, SocketOps,
+    consts::{DNS, GATEWAY},
+    mdns,
+    options::{Configurable, SetSocketOption},
+    udp::UdpSocket,
+};
+
+pub(crate) const TYPE_A: u16 = 1;
+pub(crate) const TYPE_CNAME: u16 = 5;
+pub(crate) const TYPE_PTR: u16 = 12;
+pub(crate) const TYPE_TXT: u16 = 16;
+pub(crate) const TYPE_AAAA: u16 = 28;
+pub(crate) const TYPE_SRV: u16 = 33;
+pub(crate) const TYPE_ANY: u16 = 255;
+
+const CLASS_IN: u16 = 1;
+/// Top bit of the class: "unicast response" in mDNS questions, "cache
+/// flush" in mDNS records.
+const CLASS_TOP: u16 = 0x8000;
+
+pub(crate) const FLAG_RESPONSE: u16 = 0x8000;
+pub(crate) const FLAG_AUTHORITATIVE: u16 = 0x0400;
+const FLAG_RECURSION: u16 = 0x0100;
+const RCODE_NXDOMAIN: u16 = 3;
+
+/// Longest TTL honored by the cache, in seconds.
+pub const MAX_TTL: u32 = 3600;
+/// Host names cached at most.
+const CACHE_SIZE: usize = 128;
+/// Name servers are tried this many times each.
+const ATTEMPTS: usize = 2;
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+/// Largest message over UDP without EDNS.
+pub(crate) const MAX_MESSAGE_LEN: usize = 512;
+
+pub(crate) const DNS_PORT: u16 = 53;
+
+/// A question of a DNS message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Question {
+    pub name: String,
+    pub qtype: u16,
+    /// mDNS only: the querier prefers a unicast response.
+    pub unicast: bool,
+}
+
+/// The data of a resource record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Cname(String),
+    Ptr(String),
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+    Txt(Vec<String>),
+    Other(u16, Vec<u8>),
+}
+
+impl RData {
+    pub fn rtype(&self) -> u16 {
+        match self {
+            RData::A(_) => TYPE_A,
+            RData::Aaaa(_) => TYPE_AAAA,
+            RData::Cname(_) => TYPE_CNAME,
+            RData::Ptr(_) => TYPE_PTR,
+            RData::Srv { .. } => TYPE_SRV,
+            RData::Txt(_) => TYPE_TXT,
+            RData::Other(rtype, _) => *rtype,
+        }
+    }
+}
+
+/// A resource record of the `IN` class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Record {
+    pub name: String,
+    pub ttl: u32,
+    /// mDNS only: the record replaces cached ones of the same name and type.
+    pub flush: bool,
+    pub data: RData,
+}
+
+/// A DNS message (RFC 1035).
+///
+/// Records of the authority section are dropped when parsing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Message {
+    pub id: u16,
+    pub flags: u16,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+    pub additionals: Vec<Record>,
+}
+
+impl Message {
+    /// Creates a query for the records of type `qtype` of `name`.
+    pub fn query(id: u16, name: &str, qtype: u16) -> Self {
+        Self {
+            id,
+            questions: vec![Question {
+                name: name.to_string(),
+                qtype,
+                unicast: false,
+            }],
+            ..Default::default()
+        }
+    }
+
+    pub fn is_response(&self) -> bool {
+        self.flags & FLAG_RESPONSE != 0
+    }
+
+    pub fn rcode(&self) -> u16 {
+        self.flags & 0xf
+    }
+
+    /// Serializes the message, without name compression.
+    ///
+    /// Names must have been checked with [`check_name`].
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(MAX_MESSAGE_LEN);
+        for value in [
+            self.id,
+            self.flags,
+            self.questions.len() as u16,
+            self.answers.len() as u16,
+            0,
+            self.additionals.len() as u16,
+        ] {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        for question in &self.questions {
+            put_name(&mut out, &question.name);
+            out.extend_from_slice(&question.qtype.to_be_bytes());
+            let class = CLASS_IN | if question.unicast { CLASS_TOP } else { 0 };
+            out.extend_from_slice(&class.to_be_bytes());
+        }
+        for record in self.answers.iter().chain(&self.additionals) {
+            put_record(&mut out, record);
+        }
+        out
+    }
+
+    /// Parses a message, failing with `EINVAL` if it is malformed.
+    pub fn parse(buf: &[u8]) -> KResult<Self> {
+        let mut reader = Reader { buf, pos: 0 };
+        let id = reader.u16()?;
+        let flags = reader.u16()?;
+        let counts = [reader.u16()?, reader.u16()?, reader.u16()?, reader.u16()?];
+
+        let mut message = Self {
+            id,
+            flags,
+            ..Default::default()
+        };
+        for _ in 0..counts[0] {
+            let name = reader.name()?;
+            let qtype = reader.u16()?;
+            let class = reader.u16()?;
+            message.questions.push(Question {
+                name,
+                qtype,
+                unicast: class & CLASS_TOP != 0,
+            });
+        }
+        for _ in 0..counts[1] {
+            message.answers.extend(reader.record()?);
+        }
+        for _ in 0..counts[2] {
+            reader.record()?;
+        }
+        for _ in 0..counts[3] {
+            message.additionals.extend(reader.record()?);
+        }
+        Ok(message)
+    }
+
+    /// Returns the records of both the answer and the additional section.
+    pub fn records(&self) -> impl Iterator<Item = &Record> {
+        self.answers.iter().chain(&self.additionals)
+    }
+}
+
+/// Checks that `name` is a valid domain name: non-empty labels of at most 63
+/// bytes, at most 253 bytes in total.
+pub(crate) fn check_name(name: &str) -> KResult {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if name.is_empty()
+        || name.len() > 253
+        || name
+            .split('.')
+            .any(|label| label.is_empty() || label.len() > 63)
+    {
+        return Err(KError::InvalidInput);
+    }
+    Ok(())
+}
+
+fn put_name(out: &mut Vec<u8>, name: &str) {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    for label in name.split('.').filter(|it| !it.is_empty()) {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+}
+
+fn put_record(out: &mut Vec<u8>, record: &Record) {
+    put_name(out, &record.name);
+    out.extend_from_slice(&record.data.rtype().to_be_bytes());
+    let class = CLASS_IN | if record.flush { CLASS_TOP } else { 0 };
+    out.extend_from_slice(&class.to_be_bytes());
+    out.extend_from_slice(&record.ttl.to_be_bytes());
+
+    let len_at = out.len();
+    out.extend_from_slice(&[0, 0]);
+    match &record.data {
+        RData::A(addr) => out.extend_from_slice(&addr.octets()),
+        RData::Aaaa(addr) => out.extend_from_slice(&addr.octets()),
+        RData::Cname(name) | RData::Ptr(name) => put_name(out, name),
+        RData::Srv {
+            priority,
+            weight,
+            port,
+            target,
+        } => {
+            for value in [priority, weight, port] {
+                out.extend_from_slice(&value.to_be_bytes());
+            }
+            put_name(out, target);
+        }
+        RData::Txt(strings) => {
+            for string in strings {
+                let string = &string.as_bytes()[..string.len().min(255)];
+                out.push(string.len() as u8);
+                out.extend_from_slice(string);
+            }
+            // The data may not be empty
+            if strings.is_empty() {
+                out.push(0);
+            }
+        }
+        RData::Other(_, data) => out.extend_from_slice(data),
+    }
+    let len = (out.len() - len_at - 2) as u16;
+    out[len_at..len_at + 2].copy_from_slice(&len.to_be_bytes());
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> KResult<&'a [u8]> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or(KError::InvalidInput)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> KResult<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> KResult<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Reads a possibly compressed name.
+    fn name(&mut self) -> KResult<String> {
+        let mut name = String::new();
+        let mut pos = self.pos;
+        let mut jumped = false;
+        // Pointers must go backwards, which rules out loops
+        let mut limit = pos;
+        loop {
+            let len = *self.buf.get(pos).ok_or(KError::InvalidInput)? as usize;
+            match len {
+                0 => {
+                    pos += 1;
+                    break;
+                }
+                0xc0.. => {
+                    let low = *self.buf.get(pos + 1).ok_or(KError::InvalidInput)? as usize;
+                    let target = (len & 0x3f) << 8 | low;
+                    if target >= limit {
+                        return Err(KError::InvalidInput);
+                    }
+                    if !jumped {
+                        self.pos = pos + 2;
+                        jumped = true;
+                    }
+                    pos = target;
+                    limit = target;
+                }
+                0x40.. => return Err(KError::InvalidInput),
+                _ => {
+                    let label = self
+                        .buf
+                        .get(pos + 1..pos + 1 + len)
+                        .ok_or(KError::InvalidInput)?;
+                    if !name.is_empty() {
+                        name.push('.');
+                    }
+                    name.push_str(str::from_utf8(label).or_kerr(KError::InvalidInput)?);
+                    if name.len() > 255 {
+                        return Err(KError::InvalidInput);
+                    }
+                    pos += 1 + len;
+                }
+            }
+        }
+        if !jumped {
+            self.pos = pos;
+        }
+        Ok(name)
+    }
+
+    /// Reads a record, returning `None` for classes other than `IN`.
+    fn record(&mut self) -> KResult<Option<Record>> {
+        let name = self.name()?;
+        let rtype = self.u16()?;
+        let class = self.u16()?;
+        let ttl = self.u32()?;
+        let len = self.u16()? as usize;
+        let end = self.pos + len;
+        if end > self.buf.len() {
+            return Err(KError::InvalidInput);
+        }
+
+        let data = match rtype {
+            TYPE_A => RData::A(Ipv4Addr::from(
+                <[u8; 4]>::try_from(self.bytes(len)?).or_kerr(KError::InvalidInput)?,
+            )),
+            TYPE_AAAA => RData::Aaaa(Ipv6Addr::from(
+                <[u8; 16]>::try_from(self.bytes(len)?).or_kerr(KError::InvalidInput)?,
+            )),
+            TYPE_CNAME => RData::Cname(self.name()?),
+            TYPE_PTR => RData::Ptr(self.name()?),
+            TYPE_SRV => RData::Srv {
+                priority: self.u16()?,
+                weight: self.u16()?,
+                port: self.u16()?,
+                target: self.name()?,
+            },
+            TYPE_TXT => {
+                let mut strings = Vec::new();
+                let mut data = self.bytes(len)?;
+                while let Some((&len, rest)) = data.split_first() {
+                    let string = rest.get(..len as usize).ok_or(KError::InvalidInput)?;
+                    if !string.is_empty() {
+                        strings.push(String::from_utf8_lossy(string).into_owned());
+                    }
+                    data = &rest[len as usize..];
+                }
+                RData::Txt(strings)
+            }
+            _ => RData::Other(rtype, self.bytes(len)?.to_vec()),
+        };
+        if self.pos != end {
+            return Err(KError::InvalidInput);
+        }
+
+        Ok((class & !CLASS_TOP == CLASS_IN).then_some(Record {
+            name,
+            ttl,
+            flush: class & CLASS_TOP != 0,
+            data,
+        }))
+    }
+}
+
+/// Returns a transaction ID that is hard to guess off-path.
+pub(crate) fn next_id() -> u16 {
+    static NEXT: AtomicU16 = AtomicU16::new(0);
+    let nanos = monotonic_time_nanos();
+    NEXT.fetch_add(1, Ordering::Relaxed) ^ (nanos ^ nanos >> 16) as u16
+}
+
+/// Collects the addresses of `name` from the answers of `message`, following
+/// CNAMEs, along with the shortest TTL involved.
+pub(crate) fn addresses_of(message: &Message, name: &str) -> Option<(Vec<IpAddr>, u32)> {
+    let mut names = vec![name];
+    let mut ttl = u32::MAX;
+    // Bounded by the number of records, as each name is added once
+    let mut i = 0;
+    while i < names.len() {
+        let current = names[i];
+        for record in message.records() {
+            if let RData::Cname(target) = &record.data
+                && record.name.eq_ignore_ascii_case(current)
+                && !names.iter().any(|it| it.eq_ignore_ascii_case(target))
+            {
+                ttl = ttl.min(record.ttl);
+                names.push(target.as_str());
+            }
+        }
+        i += 1;
+    }
+
+    let mut addrs = Vec::new();
+    for record in message.records() {
+        if !names.iter().any(|it| it.eq_ignore_ascii_case(&record.name)) {
+            continue;
+        }
+        let addr = match record.data {
+            RData::A(addr) => IpAddr::V4(addr),
+            RData::Aaaa(addr) => IpAddr::V6(addr),
+            _ => continue,
+        };
+        if !addrs.contains(&addr) {
+            addrs.push(addr);
+            ttl = ttl.min(record.ttl);
+        }
+    }
+    (!addrs.is_empty()).then_some((addrs, ttl))
+}
+
+struct CacheEntry {
+    addrs: Vec<IpAddr>,
+    expires_at: Duration,
+}
+
+/// Addresses of host names, keyed by lowercase name.
+#[derive(Default)]
+pub(crate) struct Cache {
+    entries: BTreeMap<String, CacheEntry>,
+}
+
+impl Cache {
+    pub const fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, name: &str, now: Duration) -> Option<Vec<IpAddr>> {
+        self.entries
+            .get(&name.to_ascii_lowercase())
+            .filter(|entry| entry.expires_at > now)
+            .map(|entry| entry.addrs.clone())
+    }
+
+    /// Caches `addrs` for `ttl` seconds. When full, expired entries are
+    /// dropped first, then the one closest to expiring.
+    pub fn insert(&mut self, name: &str, addrs: Vec<IpAddr>, ttl: u32, now: Duration) {
+        if ttl == 0 {
+            return;
+        }
+        let name = name.to_ascii_lowercase();
+        if self.entries.len() >= CACHE_SIZE && !self.entries.contains_key(&name) {
+            self.entries.retain(|_, entry| entry.expires_at > now);
+            if self.entries.len() >= CACHE_SIZE
+                && let Some(oldest) = self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires_at)
+                    .map(|(name, _)| name.clone())
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+        let expires_at = now + Duration::from_secs(ttl.min(MAX_TTL) as u64);
+        self.entries.insert(name, CacheEntry { addrs, expires_at });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Returns about how many bytes the entries take.
+    pub fn bytes(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|(name, entry)| {
+                size_of::<(String, CacheEntry)>()
+                    + name.len()
+                    + entry.addrs.len() * size_of::<IpAddr>()
+            })
+            .sum()
+    }
+}
+
+static CACHE: Mutex<Cache> = Mutex::new(Cache::new());
+
+/// Flushes the cache when memory runs out.
+struct CacheShrinker;
+
+impl Shrinker for CacheShrinker {
+    fn name(&self) -> &'static str {
+        "dns"
+    }
+
+    fn count(&self) -> usize {
+        CACHE.try_lock().map_or(0, |cache| cache.bytes())
+    }
+
+    fn scan(&self, control: &ShrinkControl) -> usize {
+        let cache = if control.may_block {
+            Some(CACHE.lock())
+        } else {
+            CACHE.try_lock()
+        };
+        cache.map_or(0, |mut cache| {
+            let bytes = cache.bytes();
+            cache.clear();
+            bytes
+        })
+    }
+}
+
+/// Has the cache flushed when memory runs out.
+pub(crate) fn register_shrinker() {
+    kalloc::register_shrinker(Arc::new(CacheShrinker));
+}
+/// Name servers set with [`set_nameservers`], `None` for the defaults.
+static NAMESERVERS: Mutex<Option<Vec<SocketAddr>>> = Mutex::new(None);
+
+/// Returns the name servers used for unicast queries.
+///
+/// They default to the servers given by `K_DNS` at build time, or the
+/// gateway if there are none.
+pub fn nameservers() -> Vec<SocketAddr> {
+    if let Some(servers) = NAMESERVERS.lock().as_ref() {
+        return servers.clone();
+    }
+    let servers = [DNS, GATEWAY].into_iter().find(|it| !it.is_empty());
+    servers
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|it| it.trim().parse::<IpAddr>().ok())
+        .map(|ip| SocketAddr::new(ip, DNS_PORT))
+        .collect()
+}
+
+/// Replaces the name servers used for unicast queries and flushes the
+/// cache.
+pub fn set_nameservers(servers: Vec<SocketAddr>) {
+    *NAMESERVERS.lock() = Some(servers);
+    flush_cache();
+}
+
+/// Forgets all cached answers.
+pub fn flush_cache() {
+    CACHE.lock().clear();
+}
+
+/// Resolves `name` to its addresses.
+///
+/// IP address literals resolve to themselves and `localhost` to the
+/// loopback address. Fails with `ENOENT` if the name does not exist and
+/// `ETIMEDOUT` if no server answered.
+pub fn dns_query(name: &str) -> KResult<Vec<IpAddr>> {
+    if let Ok(addr) = name.parse::<IpAddr>() {
+        return Ok(vec![addr]);
+    }
+    check_name(name)?;
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if name.eq_ignore_ascii_case("localhost") {
+        return Ok(vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]);
+    }
+
+    if let Some(addrs) = CACHE.lock().get(name, monotonic_time()) {
+        return Ok(addrs);
+    }
+    let (addrs, ttl) = if mdns::is_local(name) {
+        mdns::query_host(name)?
+    } else {
+        query_unicast(name)?
+    };
+    debug!("DNS: {} -> {:?}, ttl {}", name, addrs, ttl);
+    CACHE
+        .lock()
+        .insert(name, addrs.clone(), ttl, monotonic_time());
+    Ok(addrs)
+}
+
+fn query_unicast(name: &str) -> KResult<(Vec<IpAddr>, u32)> {
+    let servers = nameservers();
+    if servers.is_empty() {
+        warn!("DNS: no name server configured");
+        return Err(KError::NotFound);
+    }
+    let socket = UdpSocket::new();
+    socket.set_option(SetSocketOption::ReceiveTimeout(&QUERY_TIMEOUT))?;
+
+    let mut buf = [0u8; MAX_MESSAGE_LEN];
+    for _ in 0..ATTEMPTS {
+        for server in &servers {
+            let mut query = Message::query(next_id(), name, TYPE_A);
+            query.flags = FLAG_RECURSION;
+            socket.send(
+                &query.encode()[..],
+                SendOptions {
+                    to: Some(SocketAddrEx::Ip(*server)),
+                    ..Default::default()
+                },
+            )?;
+
+            let reply = loop {
+                let mut from = SocketAddrEx::Ip(*server);
+                let options = RecvOptions {
+                    from: Some(&mut from),
+                    ..Default::default()
+                };
+                let len = match socket.recv(&mut buf[..], options) {
+                    Ok(len) => len,
+                    Err(KError::TimedOut) => break None,
+                    Err(err) => return Err(err),
+                };
+                // Ignore stray and spoofed replies
+                if from.into_ip().ok() != Some(*server) {
+                    continue;
+                }
+                match Message::parse(&buf[..len]) {
+                    Ok(reply) if reply.is_response() && reply.id == query.id => break Some(reply),
+                    _ => continue,
+                }
+            };
+            let Some(reply) = reply else {
+                debug!("DNS: no reply from {}", server);
+                continue;
+            };
+            match reply.rcode() {
+                0 => return addresses_of(&reply, name).ok_or(KError::NotFound),
+                RCODE_NXDOMAIN => return Err(KError::NotFound),
+                rcode => debug!("DNS: {} failed with rcode {}", server, rcode),
+            }
+        }
+    }
+    Err(KError::TimedOut)
+}
@@ -3,20 +3,18 @@
 // See LICENSES for license details.
 
 //! General socket options and polling helpers.
+use alloc::sync::Arc;
 use core::{
-    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
     task::Waker,
     time::Duration,
 };
 
 use kerrno::KResult;
-use kpoll::{IoEvents, Pollable};
+use kpoll::{IoEvents, PollSet, Pollable};
 use ktask::future::{block_on, poll_io, timeout};
 
-use crate::{
-    SERVICE,
-    options::{Configurable, GetSocketOption, SetSocketOption},
-};
+use crate::options::{Configurable, GetSocketOption, SetSocketOption};
 
 /// General options for all sockets.
 pub(crate) struct GeneralOptions {
@@ -28,7 +26,9 @@ pub(crate) struct GeneralOptions {
     send_timeout_nanos: AtomicU64,
     recv_timeout_nanos: AtomicU64,
 
-    device_mask: AtomicU32,
+    /// Tasks waiting for the socket, woken by smoltcp when its queues or its
+    /// state change.
+    wakers: Arc<PollSet>,
 }
 impl Default for GeneralOptions {
     fn default() -> Self {
@@ -45,7 +45,7 @@ impl GeneralOptions {
             send_timeout_nanos: AtomicU64::new(0),
             recv_timeout_nanos: AtomicU64::new(0),
 
-            device_mask: AtomicU32::new(0),
+            wakers: Arc::new(PollSet::new()),
         }
     }
 
@@ -71,19 +71,13 @@ impl GeneralOptions {
         (nanos > 0).then(|| Duration::from_nanos(nanos))
     }
 
-    /// Set the device mask used for receive waker registration.
-    pub fn set_device_mask(&self, mask: u32) {
-        self.device_mask.store(mask, Ordering::Release);
-    }
-
-    /// Return the device mask used for receive wakers.
-    pub fn device_mask(&self) -> u32 {
-        self.device_mask.load(Ordering::Acquire)
-    }
-
-    /// Register a waker for receive readiness.
-    pub fn register_rx_waker(&self, waker: &Waker) {
-        SERVICE.lock().register_rx_waker(self.device_mask(), waker);
+    /// Registers `waker` to be woken when the socket's waker is.
+    ///
+    /// Returns the socket's waker, to be handed to smoltcp with
+    /// `register_recv_waker` and `register_send_waker` after every wakeup.
+    pub fn register_waker(&self, waker: &Waker) -> Waker {
+        self.wakers.register(waker);
+        Waker::from(self.wakers.clone())
     }
 
     /// Poll for send readiness and run the provided operation.
@@ -11,6 +11,14 @@
 //! - [`TcpSocket`]: A TCP socket that provides POSIX-like APIs.
 //! - [`UdpSocket`]: A UDP socket that provides POSIX-like APIs.
 //! - [`dns_query`]: Function for DNS query.
+//! - [`dhcp`]: DHCP client configuring `eth0` without a static address.
+//! - [`capture`]: Packet capture with classic BPF filters.
+//! - [`mdns`]: Multicast DNS responder and service discovery.
+//! - [`congestion`]: TCP congestion control selection.
+//! - [`netns`]: Isolated network stack instances.
+//! - [`qdisc`]: Egress traffic shaping and prioritization.
+//! - [`stats`]: Protocol counters and open socket listing.
+//! - [`bridge`]: Learning Ethernet bridges between NICs.
 //!
 //! [smoltcp]: https://github.com/smoltcp-rs/smoltcp
 
@@ -22,15 +30,26 @@
 extern crate log;
 extern crate alloc;
 
+pub mod bridge;
+pub mod capture;
+mod checksum;
+pub mod congestion;
 mod consts;
 mod device;
+pub mod dhcp;
+pub mod dns;
 mod general;
 mod listen_table;
+pub mod mdns;
+pub mod netns;
 pub mod options;
+pub mod qdisc;
 mod router;
 mod service;
 mod socket;
 pub(crate) mod state;
+pub mod stats;
+mod syncookie;
 pub mod tcp;
 pub mod udp;
 pub mod unix;
@@ -38,90 +57,117 @@ pub mod unix;
 pub mod vsock;
 mod wrapper;
 
+mod test_bridge;
+mod test_capture;
+mod test_checksum;
+mod test_dhcp;
+mod test_dns;
+mod test_netns;
 mod test_options;
+mod test_qdisc;
 mod test_state;
+mod test_stats;
+mod test_syncookie;
+#[cfg(feature = "vsock")]
+mod test_vsock;
+mod test_wakeup;
 
-use alloc::{borrow::ToOwned, boxed::Box};
+use alloc::{borrow::ToOwned, boxed::Box, vec::Vec};
 
+pub use device::{NETDEV_NOTIFIER, NetdevEvent};
+pub use dns::dns_query;
 use kdriver::{DeviceContainer, prelude::*};
-use ksync::Mutex;
-use lazyinit::LazyInit;
-use smoltcp::wire::{EthernetAddress, Ipv4Address, Ipv4Cidr};
+pub use netns::{announce_interfaces, poll_interfaces, reset_interfaces, stop_interfaces};
+use smoltcp::wire::{EthernetAddress, IpCidr, Ipv4Address, Ipv4Cidr};
 pub use socket::*;
 
 use crate::{
     consts::{GATEWAY, IP, IP_PREFIX},
-    device::{EthernetDevice, LoopbackDevice},
-    listen_table::ListenTable,
-    router::{Router, Rule},
-    service::Service,
-    wrapper::SocketSetWrapper,
+    device::EthernetDevice,
+    netns::{NetStack, add_loopback, set_init_stack},
+    router::Rule,
 };
 
-static LISTEN_TABLE: LazyInit<ListenTable> = LazyInit::new();
-static SOCKET_SET: LazyInit<SocketSetWrapper> = LazyInit::new();
-
-static SERVICE: LazyInit<Mutex<Service>> = LazyInit::new();
-
 /// Initializes the network subsystem by NIC devices.
+///
+/// The first NIC becomes `eth0`, addressed `K_IP` with gateway `K_GW` if
+/// given at build time, else configured by [`dhcp`].
 pub fn init_network(mut net_devs: DeviceContainer<NetDevice>) {
     info!("Initialize network subsystem...");
+    device::register_metrics();
+    dns::register_shrinker();
+
+    let mut dhcp_mac = None;
+    let stack = NetStack::build(|router| {
+        let mut ip_addrs = Vec::from([add_loopback(router).into()]);
+
+        if let Some(dev) = net_devs.take_one() {
+            info!("  use NIC 0: {:?}", dev.name());
+
+            let eth0_address = EthernetAddress(dev.mac().0);
+            let eth0_link = (dev.link_up(), dev.speed());
+            let eth0_ip = match IP {
+                "" => None,
+                ip => Some(Ipv4Cidr::new(
+                    ip.parse().expect("Invalid IPv4 address"),
+                    IP_PREFIX,
+                )),
+            };
+
+            let eth0_dev = router.add_device(Box::new(EthernetDevice::new(
+                "eth0".to_owned(),
+                dev,
+                eth0_ip,
+            )));
+
+            if let Some(eth0_ip) = eth0_ip {
+                router.add_rule(Rule::new(
+                    eth0_ip.into(),
+                    None,
+                    eth0_dev,
+                    eth0_ip.address().into(),
+                ));
+                router.add_rule(Rule::new(
+                    Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0).into(),
+                    Some(GATEWAY.parse().expect("Invalid gateway address")),
+                    eth0_dev,
+                    eth0_ip.address().into(),
+                ));
+            } else {
+                dhcp_mac = Some(eth0_address);
+            }
+
+            info!("eth0:");
+            info!("  mac:  {}", eth0_address);
+            match eth0_ip {
+                Some(eth0_ip) => info!("  ip:   {}", eth0_ip),
+                None => info!("  ip:   dhcp"),
+            }
+            match eth0_link {
+                (true, Some(speed)) => info!("  link: up, {} Mbit/s", speed),
+                (true, None) => info!("  link: up"),
+                (false, _) => info!("  link: down"),
+            }
+
+            ip_addrs.extend(eth0_ip.map(IpCidr::Ipv4));
+        } else {
+            warn!("  No network device found!");
+        }
 
-    let mut router = Router::new();
-    let lo_dev = router.add_device(Box::new(LoopbackDevice::new()));
-
-    let lo_ip = Ipv4Cidr::new(Ipv4Address::new(127, 0, 0, 1), 8);
-    router.add_rule(Rule::new(
-        lo_ip.into(),
-        None,
-        lo_dev,
-        lo_ip.address().into(),
-    ));
-
-    let eth0_ip = if let Some(dev) = net_devs.take_one() {
-        info!("  use NIC 0: {:?}", dev.name());
-
-        let eth0_address = EthernetAddress(dev.mac().0);
-        let eth0_ip = Ipv4Cidr::new(IP.parse().expect("Invalid IPv4 address"), IP_PREFIX);
-
-        let eth0_dev = router.add_device(Box::new(EthernetDevice::new(
-            "eth0".to_owned(),
-            dev,
-            eth0_ip,
-        )));
-
-        router.add_rule(Rule::new(
-            Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0).into(),
-            Some(GATEWAY.parse().expect("Invalid gateway address")),
-            eth0_dev,
-            eth0_ip.address().into(),
-        ));
-
-        info!("eth0:");
-        info!("  mac:  {}", eth0_address);
-        info!("  ip:   {}", eth0_ip);
-
-        Some(eth0_ip)
-    } else {
-        warn!("  No network device found!");
-        None
-    };
-
-    for dev in &router.devices {
-        info!("Device: {}", dev.name());
-    }
-
-    let mut service = Service::new(router);
-    service.iface.update_ip_addrs(|ip_addrs| {
-        ip_addrs.push(lo_ip.into()).unwrap();
-        if let Some(eth0_ip) = eth0_ip {
-            ip_addrs.push(eth0_ip.into()).unwrap();
+        for dev in &router.devices {
+            info!("Device: {}", dev.name());
         }
+        ip_addrs
     });
-    SERVICE.init_once(Mutex::new(service));
-
-    SOCKET_SET.init_once(SocketSetWrapper::new());
-    LISTEN_TABLE.init_once(ListenTable::new());
+    let has_nic = !stack.service.lock().ipv4_addrs().is_empty();
+    set_init_stack(stack);
+    if has_nic {
+        mdns::start_responder();
+    }
+    // The responder starts once a lease is bound
+    if let Some(mac) = dhcp_mac {
+        dhcp::start("eth0".to_owned(), mac);
+    }
 }
 
 /// Init vsock subsystem by vsock devices.
@@ -138,7 +184,3 @@ pub fn init_vsock(mut vsock_devs: DeviceContainer<VsockDevice>) {
         warn!("  No vsock device found!");
     }
 }
-
-pub fn poll_interfaces() {
-    while SERVICE.lock().poll(&mut SOCKET_SET.inner.lock()) {}
-}
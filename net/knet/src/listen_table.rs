@@ -3,56 +3,148 @@
 // See LICENSES for license details.
 
 //! TCP listen table and backlog management.
-use alloc::{boxed::Box, collections::VecDeque, sync::Arc, vec};
-use core::ops::DerefMut;
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc, vec::Vec};
+use core::{ops::DerefMut, task::Waker, time::Duration};
 
 use kerrno::{KError, KResult};
+use khal::time::{monotonic_time, monotonic_time_nanos, wall_time_nanos};
 use ksync::Mutex;
 use smoltcp::{
     iface::{SocketHandle, SocketSet},
-    socket::tcp::{self, SocketBuffer, State},
+    socket::{
+        AnySocket,
+        tcp::{self, State},
+    },
     wire::{IpEndpoint, IpListenEndpoint},
 };
 
 use crate::{
-    SOCKET_SET,
-    consts::{LISTEN_QUEUE_SIZE, TCP_RX_BUF_LEN, TCP_TX_BUF_LEN},
+    congestion::CongestionControl,
+    consts::LISTEN_QUEUE_SIZE,
+    stats::{Protocol, SocketInfo, TcpState, listen_addr},
+    syncookie::{COOKIE_LIFETIME, CookieJar},
+    tcp::{max_syn_backlog, new_tcp_socket, syncookies_enabled},
+    wrapper::SocketSetWrapper,
 };
 
 const PORT_NUM: usize = 65536;
 
+/// How long a connection may stay half-open before it is dropped from the
+/// SYN backlog.
+const SYN_RECEIVED_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What to do with an incoming SYN.
+pub enum SynAction {
+    /// Let smoltcp handle it.
+    Pass,
+    /// Drop it silently so that the peer retries later.
+    Drop,
+    /// Answer it with the given SYN cookie.
+    Cookie(u32),
+}
+
+struct PendingConn {
+    dispatch_irq: SocketHandle,
+    since: Duration,
+}
+
 struct ListenTableEntry {
     listen_endpoint: IpListenEndpoint,
-    syn_queue: VecDeque<SocketHandle>,
+    /// Both half-open connections and those waiting to be accepted.
+    syn_queue: VecDeque<PendingConn>,
+    /// Maximum number of connections waiting to be accepted.
+    backlog: usize,
+    /// When a SYN cookie was last sent, if ever.
+    last_cookie: Option<Duration>,
+    /// Congestion control inherited by accepted connections.
+    congestion: CongestionControl,
+    /// SYNs dropped because the queues were full.
+    drops: u64,
+    /// Waker of the listening socket, woken when a connection changes state.
+    waker: Option<Waker>,
 }
 
 impl ListenTableEntry {
     /// Create a new listen table entry for the given endpoint.
-    pub fn new(listen_endpoint: IpListenEndpoint) -> Self {
+    pub fn new(
+        listen_endpoint: IpListenEndpoint,
+        backlog: usize,
+        congestion: CongestionControl,
+    ) -> Self {
         Self {
             listen_endpoint,
             syn_queue: VecDeque::with_capacity(LISTEN_QUEUE_SIZE),
+            backlog,
+            last_cookie: None,
+            congestion,
+            drops: 0,
+            waker: None,
         }
     }
-}
 
-impl Drop for ListenTableEntry {
-    fn drop(&mut self) {
-        for &dispatch_irq in &self.syn_queue {
-            SOCKET_SET.remove(dispatch_irq);
+    /// Drops half-open connections that have been waiting for too long.
+    fn expire(&mut self, now: Duration, sockets: &mut SocketSet<'_>) {
+        self.syn_queue.retain(|conn| {
+            let expired = now.saturating_sub(conn.since) > SYN_RECEIVED_TIMEOUT
+                && is_half_open(sockets.get::<tcp::Socket>(conn.dispatch_irq));
+            if expired {
+                debug!("TCP socket {}: SYN backlog timeout", conn.dispatch_irq);
+                sockets.remove(conn.dispatch_irq);
+            }
+            !expired
+        });
+    }
+
+    /// Returns the number of half-open connections and of connections
+    /// waiting to be accepted.
+    fn queue_lens(&self, sockets: &SocketSet<'_>) -> (usize, usize) {
+        let mut half_open = 0;
+        let mut established = 0;
+        for conn in &self.syn_queue {
+            let socket = sockets.get::<tcp::Socket>(conn.dispatch_irq);
+            if is_half_open(socket) {
+                half_open += 1;
+            } else if socket.state() != State::Closed {
+                established += 1;
+            }
+        }
+        (half_open, established)
+    }
+
+    /// Linux allows one connection beyond the backlog.
+    fn accept_queue_full(&self, sockets: &SocketSet<'_>) -> bool {
+        self.queue_lens(sockets).1 > self.backlog
+    }
+
+    fn add_listener(&mut self, port: u16, now: Duration, sockets: &mut SocketSet<'_>) -> bool {
+        let mut socket = new_tcp_socket(self.congestion);
+        if let Err(err) = socket.listen(IpListenEndpoint { addr: None, port }) {
+            warn!("Failed to listen on {}: {:?}", self.listen_endpoint, err);
+            return false;
         }
+        if let Some(waker) = &self.waker {
+            socket.register_recv_waker(waker);
+        }
+        let dispatch_irq = sockets.add(socket);
+        self.syn_queue.push_back(PendingConn {
+            dispatch_irq,
+            since: now,
+        });
+        true
     }
 }
 
 pub struct ListenTable {
     tcp: TcpListenTable,
+    cookies: CookieJar,
+    sockets: Arc<SocketSetWrapper<'static>>,
 }
 
 type TcpListenTable = Box<[Arc<Mutex<Option<Box<ListenTableEntry>>>>]>;
 
 impl ListenTable {
-    /// Create an empty listen table.
-    pub fn new() -> Self {
+    /// Create an empty listen table for the sockets in `sockets`.
+    pub fn new(sockets: Arc<SocketSetWrapper<'static>>) -> Self {
         let tcp = unsafe {
             let mut buf = Box::new_uninit_slice(PORT_NUM);
             for i in 0..PORT_NUM {
@@ -60,19 +152,51 @@ impl ListenTable {
             }
             buf.assume_init()
         };
-        Self { tcp }
+        // Seeded from boot timing; cookies only need to be unpredictable
+        // to remote hosts
+        let seed = monotonic_time_nanos() ^ wall_time_nanos().rotate_left(32);
+        let cookies = CookieJar::new(
+            seed,
+            seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ tcp.as_ptr() as u64,
+        );
+        Self {
+            tcp,
+            cookies,
+            sockets,
+        }
+    }
+
+    fn is_connected(&self, dispatch_irq: SocketHandle) -> bool {
+        self.sockets
+            .with_socket::<tcp::Socket, _, _>(dispatch_irq, |socket| !is_half_open(socket))
+    }
+
+    fn is_closed(&self, dispatch_irq: SocketHandle) -> bool {
+        self.sockets
+            .with_socket::<tcp::Socket, _, _>(dispatch_irq, |socket| {
+                matches!(socket.state(), State::Closed)
+            })
     }
 
     pub fn can_listen(&self, port: u16) -> bool {
         self.tcp[port as usize].lock().is_none()
     }
 
-    pub fn listen(&self, listen_endpoint: IpListenEndpoint) -> KResult {
+    pub fn listen(
+        &self,
+        listen_endpoint: IpListenEndpoint,
+        backlog: usize,
+        congestion: CongestionControl,
+    ) -> KResult {
         let port = listen_endpoint.port;
         assert_ne!(port, 0);
         let mut entry = self.tcp[port as usize].lock();
         if entry.is_none() {
-            *entry = Some(Box::new(ListenTableEntry::new(listen_endpoint)));
+            *entry = Some(Box::new(ListenTableEntry::new(
+                listen_endpoint,
+                backlog,
+                congestion,
+            )));
             Ok(())
         } else {
             warn!("socket already listening on port {port}");
@@ -80,9 +204,21 @@ impl ListenTable {
         }
     }
 
+    /// Changes the backlog of an existing listener, like a repeated
+    /// `listen()` does.
+    pub fn set_backlog(&self, port: u16, backlog: usize) {
+        if let Some(entry) = self.tcp[port as usize].lock().as_mut() {
+            entry.backlog = backlog;
+        }
+    }
+
     pub fn unlisten(&self, port: u16) {
         debug!("TCP socket unlisten on {}", port);
-        *self.tcp[port as usize].lock() = None;
+        if let Some(entry) = self.tcp[port as usize].lock().take() {
+            for conn in &entry.syn_queue {
+                self.sockets.remove(conn.dispatch_irq);
+            }
+        }
     }
 
     fn listen_entry(&self, port: u16) -> Arc<Mutex<Option<Box<ListenTableEntry>>>> {
@@ -94,13 +230,33 @@ impl ListenTable {
             Ok(entry
                 .syn_queue
                 .iter()
-                .any(|&dispatch_irq| is_connected(dispatch_irq)))
+                .any(|conn| self.is_connected(conn.dispatch_irq)))
         } else {
             warn!("accept before listen");
             Err(KError::InvalidInput)
         }
     }
 
+    /// Makes the half-open connections to `port` wake `waker` when they
+    /// change state, e.g. become ready to be accepted.
+    pub fn register_waker(&self, port: u16, waker: &Waker) {
+        let entry = self.listen_entry(port);
+        let mut entry = entry.lock();
+        let Some(entry) = entry.deref_mut() else {
+            return;
+        };
+        entry.waker = Some(waker.clone());
+        // smoltcp forgets a waker once woken
+        for conn in &entry.syn_queue {
+            self.sockets
+                .with_socket_mut::<tcp::Socket, _, _>(conn.dispatch_irq, |socket| {
+                    if is_half_open(socket) {
+                        socket.register_recv_waker(waker);
+                    }
+                });
+        }
+    }
+
     pub fn accept(&self, port: u16) -> KResult<SocketHandle> {
         let entry = self.listen_entry(port);
         let mut table = entry.lock();
@@ -109,11 +265,11 @@ impl ListenTable {
             return Err(KError::InvalidInput);
         };
 
-        let syn_queue: &mut VecDeque<SocketHandle> = &mut entry.syn_queue;
+        let syn_queue: &mut VecDeque<PendingConn> = &mut entry.syn_queue;
         let idx = syn_queue
             .iter()
             .enumerate()
-            .find_map(|(idx, &dispatch_irq)| is_connected(dispatch_irq).then_some(idx))
+            .find_map(|(idx, conn)| self.is_connected(conn.dispatch_irq).then_some(idx))
             .ok_or(KError::WouldBlock)?; // wait for connection
         if idx > 0 {
             warn!(
@@ -122,10 +278,10 @@ impl ListenTable {
                 syn_queue.len()
             );
         }
-        let dispatch_irq = syn_queue.swap_remove_front(idx).unwrap();
+        let dispatch_irq = syn_queue.swap_remove_front(idx).unwrap().dispatch_irq;
         // If the connection is reset, return ConnectionReset error
         // Otherwise, return the dispatch_irq and the address tuple
-        if is_closed(dispatch_irq) {
+        if self.is_closed(dispatch_irq) {
             warn!("accept failed: connection reset");
             Err(KError::ConnectionReset)
         } else {
@@ -133,49 +289,118 @@ impl ListenTable {
         }
     }
 
+    /// Describes the listeners, with the connections waiting to be accepted
+    /// as receive queue and the backlog as send queue.
+    pub fn listeners(&self, sockets: &SocketSet<'_>) -> Vec<SocketInfo> {
+        self.tcp
+            .iter()
+            .filter_map(|entry| {
+                let entry = entry.lock();
+                let entry = entry.as_ref()?;
+                Some(SocketInfo {
+                    protocol: Protocol::Tcp,
+                    local: listen_addr(entry.listen_endpoint),
+                    remote: None,
+                    state: Some(TcpState::Listen),
+                    rx_queue: entry.queue_lens(sockets).1,
+                    tx_queue: entry.backlog,
+                    retransmits: 0,
+                    drops: entry.drops,
+                })
+            })
+            .collect()
+    }
+
+    /// Handles a SYN from `src` to `dst` carrying the peer's initial
+    /// sequence number and MSS.
     pub fn incoming_tcp_packet(
         &self,
         src: IpEndpoint,
         dst: IpEndpoint,
+        peer_isn: u32,
+        mss: u16,
         sockets: &mut SocketSet<'_>,
-    ) {
-        if let Some(entry) = self.listen_entry(dst.port).lock().deref_mut() {
-            // TODO(mivik): accept address check
-            if entry.syn_queue.len() >= LISTEN_QUEUE_SIZE {
-                // SYN queue is full, drop the packet
-                warn!("SYN queue overflow!");
-                return;
-            }
+    ) -> SynAction {
+        let entry = self.listen_entry(dst.port);
+        let mut entry = entry.lock();
+        // Without a listener, smoltcp answers with a RST
+        let Some(entry) = entry.deref_mut() else {
+            return SynAction::Pass;
+        };
+        // TODO(mivik): accept address check
 
-            let mut socket = smoltcp::socket::tcp::Socket::new(
-                SocketBuffer::new(vec![0; TCP_RX_BUF_LEN]),
-                SocketBuffer::new(vec![0; TCP_TX_BUF_LEN]),
-            );
-            if let Err(err) = socket.listen(IpListenEndpoint {
-                addr: None,
-                port: dst.port,
-            }) {
-                warn!("Failed to listen on {}: {:?}", entry.listen_endpoint, err);
-                return;
+        let now = monotonic_time();
+        entry.expire(now, sockets);
+        let (half_open, established) = entry.queue_lens(sockets);
+        if established > entry.backlog {
+            debug!("accept queue of {} overflow", entry.listen_endpoint);
+            entry.drops += 1;
+            return SynAction::Drop;
+        }
+        if half_open >= max_syn_backlog() {
+            if syncookies_enabled() {
+                debug!(
+                    "SYN queue of {} overflow, sending cookie to {}",
+                    entry.listen_endpoint, src
+                );
+                entry.last_cookie = Some(now);
+                return SynAction::Cookie(self.cookies.generate(dst, src, peer_isn, mss, now));
             }
-            let dispatch_irq = sockets.add(socket);
+            warn!("SYN queue overflow!");
+            entry.drops += 1;
+            return SynAction::Drop;
+        }
+
+        if entry.add_listener(dst.port, now, sockets) {
             debug!(
                 "TCP socket {}: prepare for connection {} -> {}",
-                dispatch_irq, src, entry.listen_endpoint
+                entry.syn_queue.back().unwrap().dispatch_irq,
+                src,
+                entry.listen_endpoint
             );
-            entry.syn_queue.push_back(dispatch_irq);
         }
+        SynAction::Pass
     }
-}
 
-fn is_connected(dispatch_irq: SocketHandle) -> bool {
-    SOCKET_SET.with_socket::<tcp::Socket, _, _>(dispatch_irq, |socket| {
-        !matches!(socket.state(), State::Listen | State::SynReceived)
-    })
+    /// Handles an ACK from `src` to `dst` that may complete a handshake
+    /// answered with a SYN cookie.
+    ///
+    /// Returns the MSS encoded in the cookie if the connection was admitted,
+    /// in which case a listening socket is ready to take the replayed SYN.
+    pub fn incoming_cookie_ack(
+        &self,
+        src: IpEndpoint,
+        dst: IpEndpoint,
+        peer_isn: u32,
+        cookie: u32,
+        sockets: &mut SocketSet<'_>,
+    ) -> Option<u16> {
+        if !syncookies_enabled() {
+            return None;
+        }
+        let entry = self.listen_entry(dst.port);
+        let mut entry = entry.lock();
+        let entry = entry.deref_mut().as_mut()?;
+
+        // Only look for cookies while they are being handed out
+        let now = monotonic_time();
+        if now.saturating_sub(entry.last_cookie?) > COOKIE_LIFETIME {
+            return None;
+        }
+        let mss = self.cookies.check(dst, src, peer_isn, cookie, now)?;
+        // A valid-looking cookie may still belong to an existing connection
+        let exists = sockets.iter().any(|(_, socket)| {
+            tcp::Socket::downcast(socket).is_some_and(|socket| {
+                socket.local_endpoint() == Some(dst) && socket.remote_endpoint() == Some(src)
+            })
+        });
+        if exists || entry.accept_queue_full(sockets) {
+            return None;
+        }
+        entry.add_listener(dst.port, now, sockets).then_some(mss)
+    }
 }
 
-fn is_closed(dispatch_irq: SocketHandle) -> bool {
-    SOCKET_SET.with_socket::<tcp::Socket, _, _>(dispatch_irq, |socket| {
-        matches!(socket.state(), State::Closed)
-    })
+fn is_half_open(socket: &tcp::Socket) -> bool {
+    matches!(socket.state(), State::Listen | State::SynReceived)
 }
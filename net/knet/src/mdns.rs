@@ -0,0 +1,559 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Multicast DNS (RFC 6762) and DNS-based service discovery (RFC 6763).
+//!
+//! The responder runs on the initial stack once a NIC is up. It announces
+//! `<hostname>.local` and the services registered with [`register_service`],
+//! and answers queries for them.
+//!
+//! Lookups are one-shot queries sent from an ephemeral port, which
+//! responders answer by unicast, so they do not compete with the responder
+//! for port 5353. Responders that only answer by multicast are not heard.
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{
+    mem,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use kerrno::{KError, KResult};
+use khal::time::monotonic_time;
+use ksync::Mutex;
+
+use crate::{
+    RecvOptions, SendOptions, SocketAddrEx, SocketOps,
+    consts::{HOSTNAME, STANDARD_MTU},
+    dns::{
+        FLAG_AUTHORITATIVE, FLAG_RESPONSE, Message, RData, Record, TYPE_A, TYPE_ANY, TYPE_PTR,
+        addresses_of, check_name, next_id,
+    },
+    netns::{NetStack, init_stack, try_current_stack},
+    options::{Configurable, SetSocketOption},
+    udp::UdpSocket,
+};
+
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub const MDNS_PORT: u16 = 5353;
+
+/// TTL of records naming this host, in seconds.
+const HOST_TTL: u32 = 120;
+/// TTL of other records.
+const SERVICE_TTL: u32 = 4500;
+/// TTL cap for responses to one-shot queriers (RFC 6762 section 6.7).
+const LEGACY_TTL: u32 = 10;
+/// Unsolicited announcements sent at startup and after a change, one per
+/// second.
+const ANNOUNCEMENTS: usize = 2;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+/// How long one-shot queries collect responses.
+const QUERY_WINDOW: Duration = Duration::from_secs(1);
+
+/// Enumerates the service types on the link (RFC 6763 section 9).
+const SERVICES_META: &str = "_services._dns-sd._udp.local";
+
+/// A service announced by the responder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceInfo {
+    /// Instance name, e.g. `Kernel web console`.
+    pub instance: String,
+    /// Service type and protocol, e.g. `_http._tcp`.
+    pub service: String,
+    pub port: u16,
+    /// `key=value` attributes.
+    pub txt: Vec<String>,
+}
+
+impl ServiceInfo {
+    fn service_name(&self) -> String {
+        format!("{}.local", self.service)
+    }
+
+    fn instance_name(&self) -> String {
+        format!("{}.{}.local", self.instance, self.service)
+    }
+
+    fn check(&self) -> KResult {
+        let mut labels = self.service.split('.');
+        let valid_type = matches!(
+            (labels.next(), labels.next(), labels.next()),
+            (Some(name), Some("_tcp" | "_udp"), None) if name.len() > 1 && name.starts_with('_')
+        );
+        if !valid_type || self.instance.contains('.') {
+            return Err(KError::InvalidInput);
+        }
+        check_name(&self.instance_name())
+    }
+
+    /// Returns the PTR, SRV and TXT records of the service on `host`.
+    fn records(&self, host: &str, ttl: Option<u32>) -> [Record; 3] {
+        let instance_name = self.instance_name();
+        [
+            Record {
+                name: self.service_name(),
+                ttl: ttl.unwrap_or(SERVICE_TTL),
+                flush: false,
+                data: RData::Ptr(instance_name.clone()),
+            },
+            Record {
+                name: instance_name.clone(),
+                ttl: ttl.unwrap_or(HOST_TTL),
+                flush: true,
+                data: RData::Srv {
+                    priority: 0,
+                    weight: 0,
+                    port: self.port,
+                    target: host.to_string(),
+                },
+            },
+            Record {
+                name: instance_name,
+                ttl: ttl.unwrap_or(SERVICE_TTL),
+                flush: true,
+                data: RData::Txt(self.txt.clone()),
+            },
+        ]
+    }
+}
+
+/// A service instance found by [`browse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredService {
+    pub info: ServiceInfo,
+    /// Host name the service runs on.
+    pub host: String,
+    /// Addresses of the host, if the responder included them.
+    pub addrs: Vec<IpAddr>,
+}
+
+/// What the responder is authoritative for.
+pub(crate) struct Zone {
+    pub hostname: String,
+    pub addrs: Vec<Ipv4Addr>,
+    pub services: Vec<ServiceInfo>,
+}
+
+impl Zone {
+    fn of(stack: &NetStack) -> Self {
+        Self {
+            hostname: hostname(),
+            addrs: stack.service.lock().ipv4_addrs(),
+            services: SERVICES.lock().clone(),
+        }
+    }
+
+    fn host_name(&self) -> String {
+        format!("{}.local", self.hostname)
+    }
+
+    /// Returns all records of the zone.
+    pub fn records(&self) -> Vec<Record> {
+        let host = self.host_name();
+        let mut records: Vec<_> = self
+            .addrs
+            .iter()
+            .map(|addr| Record {
+                name: host.clone(),
+                ttl: HOST_TTL,
+                flush: true,
+                data: RData::A(*addr),
+            })
+            .collect();
+        for info in &self.services {
+            let meta = Record {
+                name: SERVICES_META.to_string(),
+                ttl: SERVICE_TTL,
+                flush: false,
+                data: RData::Ptr(info.service_name()),
+            };
+            if !records.contains(&meta) {
+                records.push(meta);
+            }
+            records.extend(info.records(&host, None));
+        }
+        records
+    }
+
+    /// Answers `query`, returning `None` if nothing in it concerns this
+    /// host.
+    pub fn answer(&self, query: &Message) -> Option<Message> {
+        let records = self.records();
+
+        let mut answers: Vec<Record> = Vec::new();
+        for question in &query.questions {
+            for record in named(&records, &question.name) {
+                if (question.qtype == TYPE_ANY || question.qtype == record.data.rtype())
+                    && !answers.contains(record)
+                {
+                    answers.push(record.clone());
+                }
+            }
+        }
+        // Known-answer suppression (RFC 6762 section 7.1)
+        answers.retain(|record| {
+            !query.answers.iter().any(|known| {
+                known.name.eq_ignore_ascii_case(&record.name)
+                    && known.data == record.data
+                    && known.ttl >= record.ttl / 2
+            })
+        });
+        if answers.is_empty() {
+            return None;
+        }
+
+        // Records the querier will ask for next (RFC 6763 section 12)
+        let mut additionals: Vec<Record> = Vec::new();
+        let mut targets: Vec<&str> = answers
+            .iter()
+            .filter(|record| !record.name.eq_ignore_ascii_case(SERVICES_META))
+            .filter_map(|record| match &record.data {
+                RData::Ptr(target) | RData::Srv { target, .. } => Some(target.as_str()),
+                _ => None,
+            })
+            .collect();
+        while let Some(target) = targets.pop() {
+            for record in named(&records, target) {
+                if answers.contains(record) || additionals.contains(record) {
+                    continue;
+                }
+                if let RData::Srv { target, .. } = &record.data {
+                    targets.push(target);
+                }
+                additionals.push(record.clone());
+            }
+        }
+
+        Some(Message {
+            id: 0,
+            flags: FLAG_RESPONSE | FLAG_AUTHORITATIVE,
+            questions: Vec::new(),
+            answers,
+            additionals,
+        })
+    }
+}
+
+/// Host name set with [`set_hostname`], empty for the default.
+static HOST: Mutex<String> = Mutex::new(String::new());
+static SERVICES: Mutex<Vec<ServiceInfo>> = Mutex::new(Vec::new());
+/// Records withdrawn since the responder last ran.
+static GOODBYES: Mutex<Vec<Record>> = Mutex::new(Vec::new());
+static ANNOUNCE: AtomicBool = AtomicBool::new(false);
+static RESPONDER: AtomicBool = AtomicBool::new(false);
+
+/// Returns the host name announced as `<hostname>.local`.
+pub fn hostname() -> String {
+    let host = HOST.lock();
+    if host.is_empty() {
+        HOSTNAME.to_string()
+    } else {
+        host.clone()
+    }
+}
+
+/// Changes the host name, which must be a single label.
+pub fn set_hostname(name: &str) -> KResult {
+    if name.contains('.') {
+        return Err(KError::InvalidInput);
+    }
+    check_name(name)?;
+    *HOST.lock() = name.to_string();
+    ANNOUNCE.store(true, Ordering::Release);
+    Ok(())
+}
+
+/// Announces a service, replacing the one with the same instance name and
+/// type.
+pub fn register_service(info: ServiceInfo) -> KResult {
+    info.check()?;
+    let mut services = SERVICES.lock();
+    services.retain(|it| it.instance != info.instance || it.service != info.service);
+    info!("mDNS: registered {}", info.instance_name());
+    services.push(info);
+    ANNOUNCE.store(true, Ordering::Release);
+    Ok(())
+}
+
+/// Withdraws a service registered with [`register_service`].
+pub fn unregister_service(instance: &str, service: &str) -> KResult {
+    let mut services = SERVICES.lock();
+    let index = services
+        .iter()
+        .position(|it| it.instance == instance && it.service == service)
+        .ok_or(KError::NotFound)?;
+    let info = services.remove(index);
+    let host = format!("{}.local", hostname());
+    GOODBYES.lock().extend(info.records(&host, Some(0)));
+    Ok(())
+}
+
+/// Returns the registered services.
+pub fn services() -> Vec<ServiceInfo> {
+    SERVICES.lock().clone()
+}
+
+/// Starts the responder on the initial stack, unless it is running.
+pub fn start_responder() {
+    if !RESPONDER.swap(true, Ordering::AcqRel) {
+        ktask::spawn_with_name(responder_task, "mdns".to_string());
+    }
+}
+
+fn responder_task() {
+    if let Err(err) = run_responder() {
+        warn!("mDNS responder stopped: {:?}", err);
+    }
+    RESPONDER.store(false, Ordering::Release);
+}
+
+fn group_addr() -> SocketAddrEx {
+    SocketAddrEx::Ip(SocketAddr::new(MDNS_GROUP.into(), MDNS_PORT))
+}
+
+fn send_to(socket: &UdpSocket, message: &Message, to: SocketAddrEx) {
+    let options = SendOptions {
+        to: Some(to),
+        ..Default::default()
+    };
+    if let Err(err) = socket.send(&message.encode()[..], options) {
+        debug!("mDNS: send failed: {:?}", err);
+    }
+}
+
+fn unsolicited(answers: Vec<Record>) -> Message {
+    Message {
+        flags: FLAG_RESPONSE | FLAG_AUTHORITATIVE,
+        answers,
+        ..Default::default()
+    }
+}
+
+fn run_responder() -> KResult {
+    let stack = init_stack();
+    stack.join_multicast_group(MDNS_GROUP.into())?;
+    let socket = UdpSocket::new_in(stack.clone());
+    socket.bind(SocketAddrEx::Ip(SocketAddr::new(
+        Ipv4Addr::UNSPECIFIED.into(),
+        MDNS_PORT,
+    )))?;
+    // Receivers drop packets that may have come from off-link
+    socket.set_option(SetSocketOption::Ttl(&255))?;
+    socket.set_option(SetSocketOption::ReceiveTimeout(&ANNOUNCE_INTERVAL))?;
+    info!("mDNS: responding as {}.local", hostname());
+
+    let mut announcements = ANNOUNCEMENTS;
+    let mut buf = vec![0u8; STANDARD_MTU];
+    loop {
+        let goodbyes = mem::take(&mut *GOODBYES.lock());
+        if !goodbyes.is_empty() {
+            send_to(&socket, &unsolicited(goodbyes), group_addr());
+        }
+        if ANNOUNCE.swap(false, Ordering::AcqRel) {
+            announcements = ANNOUNCEMENTS;
+        }
+        if announcements > 0 {
+            announcements -= 1;
+            let records = Zone::of(&stack).records();
+            if !records.is_empty() {
+                send_to(&socket, &unsolicited(records), group_addr());
+            }
+        }
+
+        let mut from = group_addr();
+        let options = RecvOptions {
+            from: Some(&mut from),
+            ..Default::default()
+        };
+        let len = match socket.recv(&mut buf[..], options) {
+            Ok(len) => len,
+            Err(KError::TimedOut) => continue,
+            Err(err) => return Err(err),
+        };
+        let Ok(query) = Message::parse(&buf[..len]) else {
+            continue;
+        };
+        if query.is_response() {
+            continue;
+        }
+        let Some(mut reply) = Zone::of(&stack).answer(&query) else {
+            continue;
+        };
+        let from = from.into_ip()?;
+        let to = if from.port() != MDNS_PORT {
+            // A one-shot querier expects a conventional DNS response
+            reply.id = query.id;
+            reply.questions = query.questions;
+            for record in reply.answers.iter_mut().chain(&mut reply.additionals) {
+                record.ttl = record.ttl.min(LEGACY_TTL);
+                record.flush = false;
+            }
+            SocketAddrEx::Ip(from)
+        } else if query.questions.iter().all(|it| it.unicast) {
+            SocketAddrEx::Ip(from)
+        } else {
+            group_addr()
+        };
+        send_to(&socket, &reply, to);
+    }
+}
+
+/// Returns the records of `name`, ignoring ASCII case.
+fn named<'a>(
+    records: impl IntoIterator<Item = &'a Record>,
+    name: &'a str,
+) -> impl Iterator<Item = &'a Record> {
+    records
+        .into_iter()
+        .filter(move |record| record.name.eq_ignore_ascii_case(name))
+}
+
+/// Strips `suffix` from `name`, ignoring ASCII case.
+fn strip_suffix<'a>(name: &'a str, suffix: &str) -> Option<&'a str> {
+    let split = name.len().checked_sub(suffix.len())?;
+    (name.is_char_boundary(split) && name[split..].eq_ignore_ascii_case(suffix))
+        .then(|| &name[..split])
+}
+
+/// Returns whether `name` is resolved over multicast DNS.
+pub(crate) fn is_local(name: &str) -> bool {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    strip_suffix(name, ".local").is_some_and(|it| !it.is_empty())
+}
+
+/// Sends a one-shot query for the `qtype` records of `name` and collects
+/// responses until `done` returns `true` or [`QUERY_WINDOW`] passes.
+fn one_shot(
+    name: &str,
+    qtype: u16,
+    mut done: impl FnMut(&Message) -> bool,
+) -> KResult<Vec<Message>> {
+    let socket = UdpSocket::new();
+    let query = Message::query(next_id(), name, qtype);
+    send_to(&socket, &query, group_addr());
+
+    let deadline = monotonic_time() + QUERY_WINDOW;
+    let mut replies = Vec::new();
+    let mut buf = vec![0u8; STANDARD_MTU];
+    while let Some(remaining) = deadline
+        .checked_sub(monotonic_time())
+        .filter(|it| !it.is_zero())
+    {
+        socket.set_option(SetSocketOption::ReceiveTimeout(&remaining))?;
+        let mut from = group_addr();
+        let options = RecvOptions {
+            from: Some(&mut from),
+            ..Default::default()
+        };
+        let len = match socket.recv(&mut buf[..], options) {
+            Ok(len) => len,
+            Err(KError::TimedOut) => break,
+            Err(err) => return Err(err),
+        };
+        match Message::parse(&buf[..len]) {
+            Ok(reply) if reply.is_response() && reply.id == query.id => {
+                let stop = done(&reply);
+                replies.push(reply);
+                if stop {
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(replies)
+}
+
+/// Resolves the `.local` host `name`.
+pub(crate) fn query_host(name: &str) -> KResult<(Vec<IpAddr>, u32)> {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if strip_suffix(name, ".local").is_some_and(|host| host.eq_ignore_ascii_case(&hostname()))
+        && let Some(stack) = try_current_stack()
+    {
+        let addrs = stack.service.lock().ipv4_addrs();
+        if !addrs.is_empty() {
+            return Ok((addrs.into_iter().map(IpAddr::V4).collect(), HOST_TTL));
+        }
+    }
+
+    let replies = one_shot(name, TYPE_A, |reply| addresses_of(reply, name).is_some())?;
+    replies
+        .iter()
+        .find_map(|reply| addresses_of(reply, name))
+        .ok_or(KError::NotFound)
+}
+
+/// Finds the instances of the service type `service`, e.g. `_http._tcp`, on
+/// the link.
+pub fn browse(service: &str) -> KResult<Vec<DiscoveredService>> {
+    let service_name = format!("{service}.local");
+    check_name(&service_name)?;
+    let replies = one_shot(&service_name, TYPE_PTR, |_| false)?;
+    Ok(collect_services(&replies, service))
+}
+
+/// Assembles the instances of `service` from the records of `replies`.
+pub(crate) fn collect_services(replies: &[Message], service: &str) -> Vec<DiscoveredService> {
+    let service_name = format!("{service}.local");
+    let records: Vec<&Record> = replies.iter().flat_map(Message::records).collect();
+    // Goodbyes withdraw records
+    let records: Vec<&Record> = records.into_iter().filter(|it| it.ttl > 0).collect();
+
+    let mut found: Vec<DiscoveredService> = Vec::new();
+    for record in named(records.iter().copied(), &service_name) {
+        let RData::Ptr(instance_name) = &record.data else {
+            continue;
+        };
+        let Some(instance) = strip_suffix(instance_name, &service_name)
+            .and_then(|it| it.strip_suffix('.'))
+            .filter(|it| !it.is_empty())
+        else {
+            continue;
+        };
+        if found.iter().any(|it| it.info.instance == instance) {
+            continue;
+        }
+        let Some((port, host)) =
+            named(records.iter().copied(), instance_name).find_map(|it| match &it.data {
+                RData::Srv { port, target, .. } => Some((*port, target.clone())),
+                _ => None,
+            })
+        else {
+            continue;
+        };
+        let txt = named(records.iter().copied(), instance_name)
+            .find_map(|it| match &it.data {
+                RData::Txt(txt) => Some(txt.clone()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let mut addrs = Vec::new();
+        for it in named(records.iter().copied(), &host) {
+            let addr = match it.data {
+                RData::A(addr) => IpAddr::V4(addr),
+                RData::Aaaa(addr) => IpAddr::V6(addr),
+                _ => continue,
+            };
+            if !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+        }
+        found.push(DiscoveredService {
+            info: ServiceInfo {
+                instance: instance.to_string(),
+                service: service.to_string(),
+                port,
+                txt,
+            },
+            host,
+            addrs,
+        });
+    }
+    found
+}
@@ -0,0 +1,363 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Network namespaces.
+//!
+//! Each [`NetStack`] has its own devices, routing table and sockets. The
+//! initial stack owns the NICs; other stacks start out with a loopback device
+//! only and are connected with [`NetStack::add_veth_pair`], attached to
+//! virtual NICs with [`NetStack::add_nic`], or bridged to them with
+//! [`NetStack::add_bridge`].
+//!
+//! A process uses the stack in its [`NET_NS`]. Children inherit it, so all
+//! processes of a process group share a stack unless one of them asks for a
+//! new namespace.
+//!
+//! All stacks are driven by a single `net-poll` task, woken by the receive
+//! interrupts of the devices and by the timers of the stacks. Sockets do not
+//! poll: a blocked task waits on its socket, woken by smoltcp once the
+//! socket's queue has data or room, or its state changes.
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    future::poll_fn,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Poll, Waker},
+};
+
+use kdriver::prelude::{IoPolicy, MacAddress, NetDriverOps};
+use kerrno::{KError, KResult};
+use kpoll::PollSet;
+use ksync::{Mutex, spin::SpinNoIrq};
+use ktask::future::block_on;
+use lazyinit::LazyInit;
+use smoltcp::wire::{IpAddress, IpCidr, Ipv4Address, Ipv4Cidr};
+
+use crate::{
+    bridge::Bridge,
+    device::{EthernetDevice, LoopbackDevice, NetDevice, VethDevice},
+    listen_table::ListenTable,
+    qdisc::QdiscConfig,
+    router::{Router, Rule},
+    service::Service,
+    stats::{ProtoStats, SocketInfo},
+    wrapper::SocketSetWrapper,
+};
+
+static INIT_STACK: LazyInit<Arc<NetStack>> = LazyInit::new();
+
+/// All stacks that are still alive, for [`poll_interfaces`].
+static STACKS: SpinNoIrq<Vec<Weak<NetStack>>> = SpinNoIrq::new(Vec::new());
+
+scope_local::scope_local! {
+    /// The network namespace of the current process, `None` for the initial
+    /// one.
+    pub static NET_NS: Option<Arc<NetStack>> = None;
+}
+
+/// An isolated network stack.
+pub struct NetStack {
+    id: usize,
+    pub(crate) service: Mutex<Service>,
+    pub(crate) sockets: Arc<SocketSetWrapper<'static>>,
+    pub(crate) listen_table: Arc<ListenTable>,
+    pub(crate) stats: Arc<ProtoStats>,
+}
+
+impl NetStack {
+    /// Creates a stack whose devices and routes are set up by `setup`, which
+    /// returns the addresses of the stack.
+    pub(crate) fn build(setup: impl FnOnce(&mut Router) -> Vec<IpCidr>) -> Arc<Self> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        let sockets = Arc::new(SocketSetWrapper::new());
+        let listen_table = Arc::new(ListenTable::new(sockets.clone()));
+        let stats = Arc::new(ProtoStats::default());
+        let mut router = Router::new(listen_table.clone(), stats.clone());
+        let ip_addrs = setup(&mut router);
+
+        let mut service = Service::new(router);
+        service.iface.update_ip_addrs(|addrs| {
+            for ip in ip_addrs {
+                addrs.push(ip).unwrap();
+            }
+        });
+
+        let stack = Arc::new(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            service: Mutex::new(service),
+            sockets,
+            listen_table,
+            stats,
+        });
+        let mut stacks = STACKS.lock();
+        stacks.retain(|it| it.strong_count() > 0);
+        stacks.push(Arc::downgrade(&stack));
+        drop(stacks);
+        start_poller();
+        stack
+    }
+
+    /// Creates a stack with only a loopback device.
+    pub fn new() -> Arc<Self> {
+        Self::build(|router| Vec::from([add_loopback(router).into()]))
+    }
+
+    /// Identifier of the stack, 0 for the initial one.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Connects this stack to `peer` with a pair of veth devices, addressed
+    /// `local` here and `remote` in `peer`.
+    ///
+    /// Returns the names of the local and the remote device.
+    pub fn add_veth_pair(
+        &self,
+        peer: &NetStack,
+        local: Ipv4Cidr,
+        remote: Ipv4Cidr,
+    ) -> KResult<(String, String)> {
+        if ptr::eq(self, peer) {
+            return Err(KError::InvalidInput);
+        }
+        let (local_dev, remote_dev) = VethDevice::new_pair();
+        let names = (local_dev.name().to_string(), remote_dev.name().to_string());
+        // If attaching the remote end fails, the local one is left without
+        // carrier
+        self.service.lock().add_device(Box::new(local_dev), local)?;
+        peer.service
+            .lock()
+            .add_device(Box::new(remote_dev), remote)?;
+        info!(
+            "netns {}: {} {} <-> netns {}: {} {}",
+            self.id, names.0, local, peer.id, names.1, remote
+        );
+        Ok(names)
+    }
+
+    /// Attaches the Ethernet NIC `dev` as `name`, addressed `ip`.
+    ///
+    /// Meant for virtual NICs, e.g. ports of a
+    /// [`Bridge`](kdriver::prelude::Bridge).
+    pub fn add_nic(&self, name: String, dev: impl NetDriverOps + 'static, ip: Ipv4Cidr) -> KResult {
+        let dev = EthernetDevice::new(name, dev, Some(ip));
+        self.service.lock().add_device(Box::new(dev), ip)?;
+        Ok(())
+    }
+
+    /// Creates the bridge `name` addressed `mac` and `ip`, without ports.
+    pub fn add_bridge(&self, name: String, mac: MacAddress, ip: Ipv4Cidr) -> KResult<Bridge> {
+        let (bridge, dev) = Bridge::new(name, mac, ip);
+        self.service.lock().add_device(dev, ip)?;
+        Ok(bridge)
+    }
+
+    /// Readdresses the device `name` to `ip`, or removes its address with
+    /// `None`, and routes everything off its subnet through `gateway`.
+    ///
+    /// The routes through the device are replaced, including those added
+    /// with [`add_route`](Self::add_route).
+    pub fn set_ipv4_addr(
+        &self,
+        name: &str,
+        ip: Option<Ipv4Cidr>,
+        gateway: Option<Ipv4Address>,
+    ) -> KResult {
+        self.service.lock().set_ipv4_addr(name, ip, gateway)
+    }
+
+    /// Routes `filter` through `via`, which must be reachable over one of the
+    /// devices of this stack.
+    pub fn add_route(&self, filter: IpCidr, via: IpAddress) -> KResult {
+        self.service.lock().add_route(filter, via)
+    }
+
+    /// Attaches a queuing discipline to the device `name`, or detaches it
+    /// with `None`.
+    pub fn set_qdisc(&self, name: &str, config: Option<QdiscConfig>) -> KResult {
+        self.service.lock().set_qdisc(name, config)
+    }
+
+    /// Sets what the device `name` does about its NIC failing to send for
+    /// longer than the timeout of `policy`: retry, reset it, or take it
+    /// offline.
+    ///
+    /// Only devices backed by a NIC take a policy.
+    pub fn set_io_policy(&self, name: &str, policy: IoPolicy) -> KResult {
+        self.service.lock().set_io_policy(name, policy)
+    }
+
+    /// Returns the name and queuing discipline of each device.
+    pub fn qdiscs(&self) -> Vec<(String, Option<QdiscConfig>)> {
+        self.service.lock().qdiscs()
+    }
+
+    /// Accepts packets sent to the multicast group `addr`.
+    pub fn join_multicast_group(&self, addr: IpAddress) -> KResult {
+        self.service.lock().join_multicast_group(addr)
+    }
+
+    /// Returns the protocol counters of this stack.
+    pub fn stats(&self) -> &ProtoStats {
+        &self.stats
+    }
+
+    /// Lists the TCP listeners and connections and the bound UDP sockets.
+    pub fn sockets(&self) -> Vec<SocketInfo> {
+        let service = self.service.lock();
+        let sockets = self.sockets.inner.lock();
+        let mut infos = self.listen_table.listeners(&sockets);
+        infos.extend(service.socket_infos(&sockets));
+        infos
+    }
+
+    /// Polls the devices and sockets of this stack once.
+    fn poll(&self) -> bool {
+        self.service.lock().poll(&mut self.sockets.inner.lock())
+    }
+
+    /// Registers `waker` for the next timeout of this stack and for the
+    /// frames received by its devices.
+    fn register_rx_waker(&self, waker: &Waker) {
+        self.service
+            .lock()
+            .register_rx_waker(waker, &self.sockets.inner.lock());
+    }
+}
+
+/// Adds a loopback device serving `127.0.0.0/8` and returns its address.
+pub(crate) fn add_loopback(router: &mut Router) -> Ipv4Cidr {
+    let lo_dev = router.add_device(Box::new(LoopbackDevice::new()));
+    let lo_ip = Ipv4Cidr::new(Ipv4Address::new(127, 0, 0, 1), 8);
+    router.add_rule(Rule::new(
+        lo_ip.into(),
+        None,
+        lo_dev,
+        lo_ip.address().into(),
+    ));
+    lo_ip
+}
+
+pub(crate) fn set_init_stack(stack: Arc<NetStack>) {
+    INIT_STACK.init_once(stack);
+}
+
+/// Returns the stack that owns the NICs.
+pub fn init_stack() -> Arc<NetStack> {
+    INIT_STACK.clone()
+}
+
+/// Returns the stack of the current process.
+pub fn current_stack() -> Arc<NetStack> {
+    NET_NS.clone().unwrap_or_else(init_stack)
+}
+
+/// Like [`current_stack`], but returns `None` before the network is
+/// initialized.
+pub fn try_current_stack() -> Option<Arc<NetStack>> {
+    NET_NS.clone().or_else(|| INIT_STACK.get().cloned())
+}
+
+fn alive_stacks() -> Vec<Arc<NetStack>> {
+    STACKS.lock().iter().filter_map(Weak::upgrade).collect()
+}
+
+/// Polls `stacks` until none of them makes progress, returning whether any
+/// did.
+///
+/// Stacks are polled together since a veth pair hands packets from one to
+/// another.
+fn poll_stacks(stacks: &[Arc<NetStack>]) -> bool {
+    if STOPPED.load(Ordering::Acquire) {
+        return false;
+    }
+    let mut polled = false;
+    while stacks
+        .iter()
+        .fold(false, |progress, stack| stack.poll() | progress)
+    {
+        polled = true;
+    }
+    polled
+}
+
+/// Set once the stacks stopped for a shutdown.
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// Woken when the timers of the stacks may have changed.
+static POLLER: PollSet = PollSet::new();
+
+/// Starts the `net-poll` task unless it runs already.
+fn start_poller() {
+    static STARTED: AtomicBool = AtomicBool::new(false);
+    if !STARTED.swap(true, Ordering::AcqRel) {
+        ktask::spawn_with_name(poller_task, "net-poll".to_string());
+    }
+}
+
+fn poller_task() {
+    block_on(poll_fn(|cx| {
+        // Registered before polling, so that no frame goes unnoticed
+        POLLER.register(cx.waker());
+        let stacks = alive_stacks();
+        for stack in &stacks {
+            stack.register_rx_waker(cx.waker());
+        }
+        // Progress may have set new timers, which are registered next round
+        if poll_stacks(&stacks) {
+            cx.waker().wake_by_ref();
+        }
+        Poll::<()>::Pending
+    }));
+}
+
+/// Polls all network stacks until none of them makes progress.
+///
+/// Needed only to hand out what was just queued on a socket right away;
+/// received frames are picked up by the `net-poll` task.
+pub fn poll_interfaces() {
+    if poll_stacks(&alive_stacks()) {
+        POLLER.wake();
+    }
+}
+
+/// Sends what is queued on all network stacks, and stops polling them.
+///
+/// Called on shutdown: the NICs are left alone from then on, and whatever
+/// the sockets queue afterwards is not sent.
+pub fn stop_interfaces() {
+    poll_interfaces();
+    STOPPED.store(true, Ordering::Release);
+}
+
+/// Announces the addresses of all network stacks on their links, and polls
+/// them to send the announcements.
+///
+/// Called after the VM was restored or migrated, for the neighbors to learn
+/// where the addresses are now.
+pub fn announce_interfaces() {
+    let stacks = alive_stacks();
+    for stack in &stacks {
+        stack.service.lock().announce();
+    }
+    poll_interfaces();
+}
+
+/// Resets the NICs of all network stacks, and polls them to hand the
+/// receive buffers to the new queues.
+///
+/// Called after the VM was restored, or when the backends on the host
+/// restarted.
+pub fn reset_interfaces() {
+    for stack in &alive_stacks() {
+        stack.service.lock().reset();
+    }
+    poll_interfaces();
+}
@@ -9,6 +9,8 @@ use core::time::Duration;
 use enum_dispatch::enum_dispatch;
 use kerrno::{KError, KResult, LinuxError};
 
+use crate::congestion::CongestionControl;
+
 macro_rules! define_options {
     ($($name:ident($value:ty),)*) => {
         /// Operation to get a socket option.
@@ -68,6 +70,7 @@ define_options! {
     NoDelay(bool),
     MaxSegment(usize),
     TcpInfo(()),
+    Congestion(CongestionControl),
 
     // ---- IP level options (IP_*) ----
     Ttl(u8),
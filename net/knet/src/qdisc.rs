@@ -0,0 +1,281 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Egress queuing disciplines.
+//!
+//! A device without a qdisc gets packets as soon as they are routed. With
+//! one, packets are sorted into [`BANDS`] priority bands, picked according
+//! to the [`Scheduling`] policy, and released no faster than a token bucket
+//! allows. Band 0 holds latency-sensitive traffic, see [`classify`].
+//!
+//! Qdiscs apply to devices of the IP router. Vsock traffic has its own
+//! device and never queues behind IP transfers.
+use alloc::{collections::VecDeque, vec::Vec};
+use core::fmt;
+
+use kerrno::{KError, KResult, ResultExt};
+use smoltcp::{
+    time::{Duration, Instant},
+    wire::{IpAddress, IpProtocol, IpVersion, Ipv4Packet, Ipv6Packet, TcpPacket},
+};
+
+use crate::{checksum::transport, consts::STANDARD_MTU};
+
+/// Number of priority bands.
+pub const BANDS: usize = 3;
+
+/// Packets queued per band before tail drop.
+const BAND_LIMIT: usize = 128;
+
+/// How the next band to send from is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheduling {
+    /// A single queue in arrival order.
+    Fifo,
+    /// Always the lowest non-empty band.
+    Strict,
+    /// Deficit round robin, band `i` getting a share of the bandwidth
+    /// proportional to the `i`th weight.
+    Weighted([u32; BANDS]),
+}
+
+/// Configuration of a queuing discipline.
+///
+/// It is written as `rate=<bytes/s> burst=<bytes> sched=<policy>` where the
+/// policy is one of `fifo`, `prio` and `wrr:<w0>,<w1>,<w2>`. Omitted keys
+/// keep their defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QdiscConfig {
+    /// Shaping rate in bytes per second, 0 for no shaping.
+    pub rate: u64,
+    /// Token bucket depth in bytes, i.e. the largest burst sent at once.
+    pub burst: u64,
+    /// Band selection policy.
+    pub sched: Scheduling,
+}
+
+impl Default for QdiscConfig {
+    fn default() -> Self {
+        Self {
+            rate: 0,
+            burst: 16 * STANDARD_MTU as u64,
+            sched: Scheduling::Strict,
+        }
+    }
+}
+
+impl QdiscConfig {
+    /// Parses a configuration, failing with `EINVAL` on malformed input.
+    pub fn parse(s: &str) -> KResult<Self> {
+        let mut config = Self::default();
+        for item in s.split_whitespace() {
+            let (key, value) = item.split_once('=').ok_or(KError::InvalidInput)?;
+            match key {
+                "rate" => config.rate = value.parse().or_kerr(KError::InvalidInput)?,
+                "burst" => config.burst = value.parse().or_kerr(KError::InvalidInput)?,
+                "sched" => config.sched = parse_sched(value)?,
+                _ => return Err(KError::InvalidInput),
+            }
+        }
+        if config.burst == 0 {
+            return Err(KError::InvalidInput);
+        }
+        Ok(config)
+    }
+}
+
+fn parse_sched(value: &str) -> KResult<Scheduling> {
+    match value {
+        "fifo" => return Ok(Scheduling::Fifo),
+        "prio" => return Ok(Scheduling::Strict),
+        _ => {}
+    }
+    let weights = value.strip_prefix("wrr:").ok_or(KError::InvalidInput)?;
+    let mut parsed = [0; BANDS];
+    let mut weights = weights.split(',');
+    for weight in &mut parsed {
+        *weight = weights
+            .next()
+            .and_then(|it| it.parse().ok())
+            .filter(|it| *it > 0)
+            .ok_or(KError::InvalidInput)?;
+    }
+    if weights.next().is_some() {
+        return Err(KError::InvalidInput);
+    }
+    Ok(Scheduling::Weighted(parsed))
+}
+
+impl fmt::Display for QdiscConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rate={} burst={} sched=", self.rate, self.burst)?;
+        match self.sched {
+            Scheduling::Fifo => f.write_str("fifo"),
+            Scheduling::Strict => f.write_str("prio"),
+            Scheduling::Weighted([w0, w1, w2]) => write!(f, "wrr:{w0},{w1},{w2}"),
+        }
+    }
+}
+
+/// Returns the band of an outgoing IP packet.
+///
+/// Like Linux's `pfifo_fast`, the DSCP/TOS field decides first: expedited
+/// forwarding, network control and `IPTOS_LOWDELAY` go to band 0, CS1 and
+/// `IPTOS_THROUGHPUT` to band 2. Otherwise ICMP and TCP segments without
+/// payload, which carry handshakes, ACKs and keepalives, go to band 0, and
+/// everything else to band 1.
+pub(crate) fn classify(packet: &[u8]) -> usize {
+    let tos = match IpVersion::of_packet(packet) {
+        Ok(IpVersion::Ipv4) => Ipv4Packet::new_checked(packet).map(|ip| ip.dscp() << 2 | ip.ecn()),
+        Ok(IpVersion::Ipv6) => Ipv6Packet::new_checked(packet).map(|ip| ip.traffic_class()),
+        Err(_) => return 1,
+    };
+    let Ok(tos) = tos else {
+        return 1;
+    };
+    match tos >> 2 {
+        // EF, CS6, CS7
+        46 | 48 | 56 => return 0,
+        // CS1
+        8 => return 2,
+        _ => {}
+    }
+    match tos & 0x1e {
+        0x10 => return 0,
+        0x08 => return 2,
+        _ => {}
+    }
+
+    let Some(t) = transport(packet) else {
+        return 1;
+    };
+    match t.protocol {
+        IpProtocol::Icmp | IpProtocol::Icmpv6 => 0,
+        IpProtocol::Tcp => match TcpPacket::new_checked(&packet[t.start..t.end]) {
+            Ok(tcp) if tcp.payload().is_empty() => 0,
+            _ => 1,
+        },
+        _ => 1,
+    }
+}
+
+/// An egress queuing discipline of a device.
+pub(crate) struct Qdisc {
+    config: QdiscConfig,
+    bands: [VecDeque<(IpAddress, Vec<u8>)>; BANDS],
+    /// Available bytes; may go negative after a packet larger than `burst`.
+    tokens: i64,
+    refilled_at: Option<Instant>,
+    /// Deficit round robin state.
+    deficits: [u64; BANDS],
+    cursor: usize,
+    visited: bool,
+    /// Packets dropped because their band was full.
+    pub drops: u64,
+}
+
+impl Qdisc {
+    pub fn new(config: QdiscConfig) -> Self {
+        Self {
+            config,
+            bands: Default::default(),
+            tokens: config.burst as i64,
+            refilled_at: None,
+            deficits: [0; BANDS],
+            cursor: 0,
+            visited: false,
+            drops: 0,
+        }
+    }
+
+    pub fn config(&self) -> QdiscConfig {
+        self.config
+    }
+
+    /// Queues a packet for `next_hop`, returning `false` if it was dropped.
+    pub fn enqueue(&mut self, next_hop: IpAddress, packet: &[u8]) -> bool {
+        let band = match self.config.sched {
+            Scheduling::Fifo => 0,
+            _ => classify(packet),
+        };
+        let queue = &mut self.bands[band];
+        if queue.len() >= BAND_LIMIT {
+            self.drops += 1;
+            return false;
+        }
+        queue.push_back((next_hop, packet.to_vec()));
+        true
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if self.config.rate == 0 {
+            return;
+        }
+        if let Some(last) = self.refilled_at
+            && now > last
+        {
+            let earned = self.config.rate as u128 * (now - last).total_micros() as u128 / 1_000_000;
+            let tokens = (self.tokens as i128 + earned as i128).min(self.config.burst as i128);
+            self.tokens = tokens as i64;
+        }
+        self.refilled_at = Some(now);
+    }
+
+    /// Returns the band whose head packet goes next.
+    fn select(&mut self) -> Option<usize> {
+        if self.bands.iter().all(VecDeque::is_empty) {
+            return None;
+        }
+        let Scheduling::Weighted(weights) = self.config.sched else {
+            return self.bands.iter().position(|band| !band.is_empty());
+        };
+        loop {
+            let band = self.cursor;
+            match self.bands[band].front() {
+                None => self.deficits[band] = 0,
+                Some((_, packet)) => {
+                    if !self.visited {
+                        self.deficits[band] += weights[band] as u64 * STANDARD_MTU as u64;
+                        self.visited = true;
+                    }
+                    if self.deficits[band] >= packet.len() as u64 {
+                        return Some(band);
+                    }
+                }
+            }
+            self.cursor = (band + 1) % BANDS;
+            self.visited = false;
+        }
+    }
+
+    /// Takes the next packet if the token bucket allows sending it.
+    pub fn dequeue(&mut self, now: Instant) -> Option<(IpAddress, Vec<u8>)> {
+        self.refill(now);
+        let band = self.select()?;
+        let len = self.bands[band].front()?.1.len() as u64;
+        if self.config.rate != 0 && self.tokens < len.min(self.config.burst) as i64 {
+            return None;
+        }
+        if self.config.rate != 0 {
+            self.tokens -= len as i64;
+        }
+        if matches!(self.config.sched, Scheduling::Weighted(_)) {
+            self.deficits[band] -= len;
+        }
+        self.bands[band].pop_front()
+    }
+
+    /// Returns when the next packet may be sent, if one is held back.
+    pub fn poll_at(&mut self, now: Instant) -> Option<Instant> {
+        self.refill(now);
+        let band = self.select()?;
+        let len = self.bands[band].front()?.1.len() as u64;
+        let missing = len.min(self.config.burst) as i64 - self.tokens;
+        if self.config.rate == 0 || missing <= 0 {
+            return Some(now);
+        }
+        let micros = (missing as u64 * 1_000_000).div_ceil(self.config.rate);
+        Some(now + Duration::from_micros(micros))
+    }
+}
@@ -3,20 +3,31 @@
 // See LICENSES for license details.
 
 //! Routing table and route selection.
-use alloc::{boxed::Box, vec, vec::Vec};
+use alloc::{borrow::Cow, boxed::Box, collections::vec_deque::VecDeque, sync::Arc, vec, vec::Vec};
+use core::cell::RefCell;
 
+use kalloc::{HeapTag, HeapTagGuard};
+use kdriver::prelude::NetCapabilities;
+use khal::time::monotonic_time;
 use smoltcp::{
     iface::SocketSet,
-    phy::{DeviceCapabilities, Medium},
+    phy::{ChecksumCapabilities, DeviceCapabilities, Medium},
     storage::PacketMetadata,
     time::Instant,
-    wire::{IpAddress, IpCidr, IpProtocol, IpVersion, Ipv4Packet, Ipv6Packet, TcpPacket},
+    wire::{
+        IpAddress, IpCidr, IpEndpoint, IpProtocol, IpVersion, Ipv4Packet, Ipv6Packet, TcpPacket,
+        TcpRepr,
+    },
 };
 
 use crate::{
-    LISTEN_TABLE,
+    checksum::{fill_checksums, transport, verify_checksums},
     consts::{SOCKET_BUFFER_SIZE, STANDARD_MTU},
     device::NetDevice,
+    listen_table::{ListenTable, SynAction},
+    qdisc::Qdisc,
+    stats::{IpStats, ProtoStats, TcpFlows},
+    syncookie::{SynProxy, Verdict},
 };
 
 #[derive(Debug)]
@@ -57,6 +68,13 @@ impl RouteTable {
         self.rules.insert(idx, rule);
     }
 
+    /// Removes the rules sending packets out of device `dev`.
+    pub fn remove_device(&mut self, dev: usize) -> Vec<Rule> {
+        let (removed, kept) = self.rules.drain(..).partition(|rule| rule.dev == dev);
+        self.rules = kept;
+        removed
+    }
+
     pub fn lookup(&self, dst: &IpAddress) -> Option<&Rule> {
         self.rules
             .iter()
@@ -66,12 +84,22 @@ impl RouteTable {
 
 pub struct Router {
     rx_buffer: PacketBuffer,
+    /// Whether each packet in `rx_buffer` still needs its checksums verified.
+    rx_unverified: VecDeque<bool>,
     tx_buffer: PacketBuffer,
+    syn_proxy: RefCell<SynProxy>,
+    listen_table: Arc<ListenTable>,
+    stats: Arc<ProtoStats>,
+    /// Outgoing TCP connections, to spot retransmissions.
+    pub(crate) tcp_flows: TcpFlows,
     pub(crate) devices: Vec<Box<dyn NetDevice>>,
+    /// Egress queuing discipline of each device, if any.
+    pub(crate) qdiscs: Vec<Option<Qdisc>>,
     pub(crate) table: RouteTable,
 }
 impl Router {
-    pub fn new() -> Self {
+    pub fn new(listen_table: Arc<ListenTable>, stats: Arc<ProtoStats>) -> Self {
+        let _tag = HeapTagGuard::new(HeapTag::NetBuf);
         let rx_buffer = PacketBuffer::new(
             vec![PacketMetadata::EMPTY; SOCKET_BUFFER_SIZE],
             vec![0u8; STANDARD_MTU * SOCKET_BUFFER_SIZE],
@@ -82,8 +110,14 @@ impl Router {
         );
         Self {
             rx_buffer,
+            rx_unverified: VecDeque::with_capacity(SOCKET_BUFFER_SIZE),
             tx_buffer,
+            syn_proxy: RefCell::default(),
+            listen_table,
+            stats,
+            tcp_flows: TcpFlows::default(),
             devices: Vec::new(),
+            qdiscs: Vec::new(),
             table: RouteTable::new(),
         }
     }
@@ -94,127 +128,279 @@ impl Router {
 
     pub fn add_device(&mut self, device: Box<dyn NetDevice>) -> usize {
         self.devices.push(device);
+        self.qdiscs.push(None);
         self.devices.len() - 1
     }
 
     pub fn poll(&mut self, timestamp: Instant) {
         for dev in &mut self.devices {
-            while !self.rx_buffer.is_full() && dev.poll_rx(&mut self.rx_buffer, timestamp) {}
+            let unverified = !dev.capabilities().contains(NetCapabilities::RX_CSUM);
+            while !self.rx_buffer.is_full() && dev.poll_rx(&mut self.rx_buffer, timestamp) {
+                self.rx_unverified.push_back(unverified);
+            }
         }
     }
 
     pub fn dispatch(&mut self, timestamp: Instant) -> bool {
         let mut poll_next = false;
+        let syn_proxy = self.syn_proxy.get_mut();
+        while let Some(mut packet) = syn_proxy.replies.pop_front() {
+            self.stats.outgoing(&packet);
+            poll_next |= route_ip_packet(
+                &mut self.devices,
+                &mut self.qdiscs,
+                &self.table,
+                &self.stats.ip,
+                &mut packet,
+                timestamp,
+            );
+        }
         while let Ok(((), ip_packet)) = self.tx_buffer.dequeue() {
-            match IpVersion::of_packet(ip_packet).expect("got invalid IP packet") {
-                IpVersion::Ipv4 => {
-                    let ip_packet = smoltcp::wire::Ipv4Packet::new_checked(ip_packet)
-                        .expect("got invalid IPv4 packet");
-                    let dst_addr = IpAddress::Ipv4(ip_packet.dst_addr());
-                    if ip_packet.dst_addr().is_broadcast() {
-                        let buf = ip_packet.into_inner();
-                        for dev in &mut self.devices {
-                            poll_next |= dev.send_ip_packet(dst_addr, buf, timestamp);
-                        }
-                    } else {
-                        let Some(rule) = self.table.lookup(&dst_addr) else {
-                            warn!("No route found for destination: {}", dst_addr);
-                            continue;
-                        };
-                        assert_eq!(rule.src, IpAddress::Ipv4(ip_packet.src_addr()));
-
-                        let next_hop = rule.via.unwrap_or(dst_addr);
-                        let dev = &mut self.devices[rule.dev];
-                        poll_next |=
-                            dev.send_ip_packet(next_hop, ip_packet.into_inner(), timestamp);
-                    }
-                }
-                IpVersion::Ipv6 => {
-                    let ip_packet = smoltcp::wire::Ipv6Packet::new_checked(ip_packet)
-                        .expect("got invalid IPv6 packet");
-                    let dst_addr = IpAddress::Ipv6(ip_packet.dst_addr());
-                    if ip_packet.dst_addr().is_multicast() {
-                        let buf = ip_packet.into_inner();
-                        for dev in &mut self.devices {
-                            poll_next |= dev.send_ip_packet(dst_addr, buf, timestamp);
-                        }
-                    } else {
-                        let Some(rule) = self.table.lookup(&dst_addr) else {
-                            warn!("No route found for destination: {}", dst_addr);
-                            continue;
-                        };
-                        assert_eq!(rule.src, IpAddress::Ipv6(ip_packet.src_addr()));
-
-                        let next_hop = rule.via.unwrap_or(dst_addr);
-                        let dev = &mut self.devices[rule.dev];
-                        poll_next |=
-                            dev.send_ip_packet(next_hop, ip_packet.into_inner(), timestamp);
-                    }
+            if syn_proxy.outgoing(ip_packet) {
+                self.stats.outgoing(ip_packet);
+                if self.tcp_flows.outgoing(ip_packet) {
+                    self.stats.tcp.retrans_segs.inc();
                 }
+                poll_next |= route_ip_packet(
+                    &mut self.devices,
+                    &mut self.qdiscs,
+                    &self.table,
+                    &self.stats.ip,
+                    ip_packet,
+                    timestamp,
+                );
+            }
+        }
+        // Packets held back by shapers whose tokens have refilled
+        for (dev, qdisc) in self.devices.iter_mut().zip(&mut self.qdiscs) {
+            if let Some(qdisc) = qdisc {
+                poll_next |= release(dev.as_mut(), qdisc, timestamp);
             }
         }
-        poll_next
+        poll_next || !syn_proxy.injected.is_empty()
+    }
+
+    /// Returns when a queuing discipline next has a packet to release.
+    pub fn poll_at(&mut self, timestamp: Instant) -> Option<Instant> {
+        self.qdiscs
+            .iter_mut()
+            .flatten()
+            .filter_map(|qdisc| qdisc.poll_at(timestamp))
+            .min()
     }
 }
 
-pub struct TxToken<'a>(&'a mut PacketBuffer);
+/// Sends the packets `qdisc` lets through to `dev`.
+fn release(dev: &mut dyn NetDevice, qdisc: &mut Qdisc, timestamp: Instant) -> bool {
+    let mut poll_next = false;
+    while let Some((next_hop, packet)) = qdisc.dequeue(timestamp) {
+        poll_next |= dev.send_ip_packet(next_hop, &packet, timestamp);
+    }
+    poll_next
+}
 
-impl smoltcp::phy::TxToken for TxToken<'_> {
-    fn consume<R, F>(self, len: usize, f: F) -> R
-    where
-        F: FnOnce(&mut [u8]) -> R,
-    {
-        f(self
-            .0
-            .enqueue(len, ())
-            .expect("This was checked before creating the TxToken"))
+/// Sends an IP packet to `dev`, through its queuing discipline if it has one.
+fn transmit(
+    dev: &mut dyn NetDevice,
+    qdisc: Option<&mut Qdisc>,
+    stats: &IpStats,
+    next_hop: IpAddress,
+    ip_packet: &[u8],
+    timestamp: Instant,
+) -> bool {
+    match qdisc {
+        Some(qdisc) => {
+            if !qdisc.enqueue(next_hop, ip_packet) {
+                debug!("{}: egress queue full, dropping packet", dev.name());
+                stats.out_discards.inc();
+            }
+            release(dev, qdisc, timestamp)
+        }
+        None => dev.send_ip_packet(next_hop, ip_packet, timestamp),
     }
 }
 
-fn snoop_tcp_packet(buf: &[u8], sockets: &mut SocketSet<'_>) {
-    let (protocol, src_addr, dst_addr, payload) = match IpVersion::of_packet(buf).unwrap() {
+fn route_ip_packet(
+    devices: &mut [Box<dyn NetDevice>],
+    qdiscs: &mut [Option<Qdisc>],
+    table: &RouteTable,
+    stats: &IpStats,
+    ip_packet: &mut [u8],
+    timestamp: Instant,
+) -> bool {
+    let (src_addr, dst_addr, is_broadcast) = match IpVersion::of_packet(ip_packet)
+        .expect("got invalid IP packet")
+    {
         IpVersion::Ipv4 => {
-            let ip_packet = Ipv4Packet::new_unchecked(buf);
+            let ip_packet = Ipv4Packet::new_checked(&*ip_packet).expect("got invalid IPv4 packet");
             (
-                ip_packet.next_header(),
                 IpAddress::Ipv4(ip_packet.src_addr()),
                 IpAddress::Ipv4(ip_packet.dst_addr()),
-                ip_packet.payload(),
+                // Link-local multicast such as mDNS goes out on every link
+                ip_packet.dst_addr().is_broadcast() || ip_packet.dst_addr().is_multicast(),
             )
         }
         IpVersion::Ipv6 => {
-            let ip_packet = Ipv6Packet::new_unchecked(buf);
+            let ip_packet = Ipv6Packet::new_checked(&*ip_packet).expect("got invalid IPv6 packet");
             (
-                ip_packet.next_header(),
                 IpAddress::Ipv6(ip_packet.src_addr()),
                 IpAddress::Ipv6(ip_packet.dst_addr()),
-                ip_packet.payload(),
+                ip_packet.dst_addr().is_multicast(),
             )
         }
     };
-    if protocol == IpProtocol::Tcp {
-        let tcp_packet = TcpPacket::new_unchecked(payload);
-        let src_addr = (src_addr, tcp_packet.src_port()).into();
-        let dst_addr = (dst_addr, tcp_packet.dst_port()).into();
-        let is_first = tcp_packet.syn() && !tcp_packet.ack();
-        if is_first {
-            LISTEN_TABLE.incoming_tcp_packet(src_addr, dst_addr, sockets);
+
+    let mut poll_next = false;
+    if is_broadcast {
+        // Filling is idempotent, so one pass serves every device
+        if devices
+            .iter()
+            .any(|dev| !dev.capabilities().contains(NetCapabilities::TX_CSUM))
+        {
+            fill_checksums(ip_packet);
+        }
+        for (dev, qdisc) in devices.iter_mut().zip(qdiscs) {
+            if dev.link_up() {
+                poll_next |= transmit(
+                    dev.as_mut(),
+                    qdisc.as_mut(),
+                    stats,
+                    dst_addr,
+                    ip_packet,
+                    timestamp,
+                );
+            }
+        }
+    } else {
+        let Some(rule) = table.lookup(&dst_addr) else {
+            warn!("No route found for destination: {}", dst_addr);
+            stats.out_no_routes.inc();
+            return false;
+        };
+        assert_eq!(rule.src, src_addr);
+
+        let next_hop = rule.via.unwrap_or(dst_addr);
+        let dev = &mut devices[rule.dev];
+        if !dev.link_up() {
+            debug!(
+                "{}: no carrier, dropping packet to {}",
+                dev.name(),
+                dst_addr
+            );
+            stats.out_discards.inc();
+            return false;
+        }
+        if !dev.capabilities().contains(NetCapabilities::TX_CSUM) {
+            fill_checksums(ip_packet);
+        }
+        let qdisc = qdiscs[rule.dev].as_mut();
+        poll_next |= transmit(dev.as_mut(), qdisc, stats, next_hop, ip_packet, timestamp);
+    }
+    poll_next
+}
+
+pub struct TxToken<'a>(&'a mut PacketBuffer);
+
+impl smoltcp::phy::TxToken for TxToken<'_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(self
+            .0
+            .enqueue(len, ())
+            .expect("This was checked before creating the TxToken"))
+    }
+}
+
+/// Looks at TCP packets before smoltcp does, to queue connection requests
+/// on their listener and to run SYN cookies.
+fn snoop_tcp_packet(
+    buf: &[u8],
+    listen_table: &ListenTable,
+    syn_proxy: &mut SynProxy,
+    stats: &ProtoStats,
+    sockets: &mut SocketSet<'_>,
+) -> Verdict {
+    match syn_proxy.incoming(buf) {
+        Verdict::Pass => {}
+        verdict => return verdict,
+    }
+    syn_proxy.sweep(sockets, monotonic_time());
+
+    let Some(t) = transport(buf).filter(|t| t.protocol == IpProtocol::Tcp) else {
+        return Verdict::Pass;
+    };
+    let Ok(tcp_packet) = TcpPacket::new_checked(&buf[t.start..t.end]) else {
+        return Verdict::Pass;
+    };
+    let src_addr = IpEndpoint::new(t.src, tcp_packet.src_port());
+    let dst_addr = IpEndpoint::new(t.dst, tcp_packet.dst_port());
+    let seq = tcp_packet.seq_number().0 as u32;
+
+    if tcp_packet.syn() && !tcp_packet.ack() {
+        // RFC 9293 default MSS for peers that do not announce one
+        let mss = TcpRepr::parse(
+            &tcp_packet,
+            &t.src,
+            &t.dst,
+            &ChecksumCapabilities::ignored(),
+        )
+        .ok()
+        .and_then(|repr| repr.max_seg_size)
+        .unwrap_or(536);
+        match listen_table.incoming_tcp_packet(src_addr, dst_addr, seq, mss, sockets) {
+            SynAction::Pass => Verdict::Pass,
+            SynAction::Drop => {
+                stats.tcp.listen_drops.inc();
+                Verdict::Drop
+            }
+            SynAction::Cookie(cookie) => {
+                syn_proxy.reply_cookie(dst_addr, src_addr, seq, cookie);
+                Verdict::Drop
+            }
         }
+    } else if tcp_packet.ack() && !tcp_packet.syn() && !tcp_packet.rst() {
+        let cookie = (tcp_packet.ack_number().0 as u32).wrapping_sub(1);
+        let peer_isn = seq.wrapping_sub(1);
+        listen_table
+            .incoming_cookie_ack(src_addr, dst_addr, peer_isn, cookie, sockets)
+            .and_then(|mss| syn_proxy.admit(dst_addr, src_addr, cookie, mss, buf))
+            .map_or(Verdict::Pass, Verdict::Replace)
+    } else {
+        Verdict::Pass
     }
 }
 
-pub struct RxToken<'a>(&'a [u8]);
+pub struct RxToken<'a> {
+    packet: Cow<'a, [u8]>,
+    listen_table: &'a ListenTable,
+    syn_proxy: &'a RefCell<SynProxy>,
+    stats: &'a ProtoStats,
+    verdict: RefCell<Verdict>,
+}
 
 impl<'a> smoltcp::phy::RxToken for RxToken<'a> {
     fn consume<R, F>(self, f: F) -> R
     where
         F: FnOnce(&[u8]) -> R,
     {
-        f(self.0)
+        match self.verdict.into_inner() {
+            Verdict::Pass => f(&self.packet),
+            Verdict::Replace(packet) => f(&packet),
+            // smoltcp ignores what it cannot parse
+            Verdict::Drop => f(&[]),
+        }
     }
 
     fn preprocess(&self, sockets: &mut SocketSet) {
-        snoop_tcp_packet(self.0, sockets);
+        *self.verdict.borrow_mut() = snoop_tcp_packet(
+            &self.packet,
+            self.listen_table,
+            &mut self.syn_proxy.borrow_mut(),
+            self.stats,
+            sockets,
+        );
     }
 }
 
@@ -223,14 +409,37 @@ impl smoltcp::phy::Device for Router {
     type TxToken<'a> = TxToken<'a>;
 
     fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
-        if self.rx_buffer.is_empty() || self.tx_buffer.is_full() {
-            None
-        } else {
-            Some((
-                RxToken(self.rx_buffer.dequeue().unwrap().1),
-                TxToken(&mut self.tx_buffer),
-            ))
+        if self.tx_buffer.is_full() {
+            return None;
         }
+        let packet = if let Some(packet) = self.syn_proxy.get_mut().injected.pop_front() {
+            Cow::Owned(packet)
+        } else {
+            while let Ok(((), ip_packet)) = self.rx_buffer.peek() {
+                let unverified = self.rx_unverified.front().copied().unwrap_or(true);
+                if !unverified || verify_checksums(ip_packet) {
+                    break;
+                }
+                debug!("Dropping received packet with bad checksum");
+                self.stats.bad_checksum(ip_packet);
+                let _ = self.rx_buffer.dequeue();
+                self.rx_unverified.pop_front();
+            }
+            self.rx_unverified.pop_front();
+            let packet = self.rx_buffer.dequeue().ok()?.1;
+            self.stats.incoming(packet);
+            Cow::Borrowed(packet)
+        };
+        Some((
+            RxToken {
+                packet,
+                listen_table: &self.listen_table,
+                syn_proxy: &self.syn_proxy,
+                stats: &self.stats,
+                verdict: RefCell::new(Verdict::Pass),
+            },
+            TxToken(&mut self.tx_buffer),
+        ))
     }
 
     fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
@@ -246,6 +455,8 @@ impl smoltcp::phy::Device for Router {
         caps.medium = Medium::Ip;
         caps.max_transmission_unit = STANDARD_MTU;
         caps.max_burst_size = Some(SOCKET_BUFFER_SIZE);
+        // Checksums are handled per device, see `crate::checksum`
+        caps.checksum = ChecksumCapabilities::ignored();
         caps
     }
 }
@@ -3,21 +3,28 @@
 // See LICENSES for license details.
 
 //! Network service wrapper around smoltcp interface.
-use alloc::boxed::Box;
+use alloc::{boxed::Box, string::String, vec::Vec};
 use core::{
     pin::Pin,
     task::{Context, Waker},
 };
 
+use kdriver::prelude::IoPolicy;
+use kerrno::{KError, KResult};
 use khal::time::{NANOS_PER_MICROS, TimeValue, wall_time_nanos};
 use ktask::future::sleep_until;
 use smoltcp::{
-    iface::{Interface, SocketSet},
+    iface::{Interface, MulticastError, SocketSet},
     time::Instant,
-    wire::{HardwareAddress, IpAddress, IpListenEndpoint},
+    wire::{HardwareAddress, IpAddress, IpCidr, Ipv4Address, Ipv4Cidr},
 };
 
-use crate::{SOCKET_SET, router::Router};
+use crate::{
+    device::NetDevice,
+    qdisc::{Qdisc, QdiscConfig},
+    router::{Router, Rule},
+    stats::{self, SocketInfo},
+};
 
 fn now() -> Instant {
     Instant::from_micros_const((wall_time_nanos() / NANOS_PER_MICROS) as i64)
@@ -40,34 +47,195 @@ impl Service {
         }
     }
 
+    /// Announces the addresses of the devices on their links.
+    pub fn announce(&mut self) {
+        for dev in &mut self.router.devices {
+            dev.announce();
+        }
+    }
+
+    /// Resets the NICs of the devices.
+    pub fn reset(&mut self) {
+        for dev in &mut self.router.devices {
+            dev.reset();
+        }
+    }
+
     pub fn poll(&mut self, sockets: &mut SocketSet) -> bool {
         let timestamp = now();
 
         self.router.poll(timestamp);
         self.iface.poll(timestamp, &mut self.router, sockets);
+        self.router.tcp_flows.prune(sockets);
         self.router.dispatch(timestamp)
     }
 
+    /// Returns the TCP connections and bound UDP sockets in `sockets`.
+    pub fn socket_infos(&self, sockets: &SocketSet) -> Vec<SocketInfo> {
+        stats::socket_infos(sockets, &self.router.tcp_flows)
+    }
+
     pub fn get_source_address(&self, dst_addr: &IpAddress) -> IpAddress {
-        let Some(rule) = self.router.table.lookup(dst_addr) else {
+        let mut rule = self.router.table.lookup(dst_addr);
+        if rule.is_none() && dst_addr.is_multicast() {
+            // Multicast goes out on every link anyway, see `route_ip_packet`
+            rule = self.router.table.lookup(&Ipv4Address::LOCALHOST.into());
+        }
+        let Some(rule) = rule else {
             panic!("no route to destination: {dst_addr}");
         };
         rule.src
     }
 
-    pub fn device_mask_for(&self, endpoint: &IpListenEndpoint) -> u32 {
-        match endpoint.addr {
-            Some(addr) => self
-                .router
-                .table
-                .lookup(&addr)
-                .map_or(0, |it| 1u32 << it.dev),
-            None => u32::MAX,
+    /// Attaches `device` with the address `ip`, routing its subnet through
+    /// it.
+    pub fn add_device(&mut self, device: Box<dyn NetDevice>, ip: Ipv4Cidr) -> KResult<usize> {
+        let mut pushed = Ok(());
+        self.iface.update_ip_addrs(|ip_addrs| {
+            pushed = ip_addrs.push(ip.into()).map_err(|_| KError::NoMemory);
+        });
+        pushed?;
+
+        let dev = self.router.add_device(device);
+        self.router
+            .add_rule(Rule::new(ip.into(), None, dev, ip.address().into()));
+        Ok(dev)
+    }
+
+    /// Readdresses the device `name` to `ip`, or leaves it without an
+    /// address with `None`, replacing the routes through it. Everything not
+    /// on its subnet is routed through `gateway`, if given.
+    pub fn set_ipv4_addr(
+        &mut self,
+        name: &str,
+        ip: Option<Ipv4Cidr>,
+        gateway: Option<Ipv4Address>,
+    ) -> KResult {
+        let dev = self
+            .router
+            .devices
+            .iter()
+            .position(|dev| dev.name() == name)
+            .ok_or(KError::NotFound)?;
+        if gateway.is_some_and(|gateway| !ip.is_some_and(|ip| ip.contains_addr(&gateway))) {
+            return Err(KError::InvalidInput);
         }
+
+        let old: Vec<_> = self
+            .router
+            .table
+            .remove_device(dev)
+            .into_iter()
+            .filter(|rule| rule.via.is_none())
+            .map(|rule| rule.src)
+            .collect();
+        let mut pushed = Ok(());
+        self.iface.update_ip_addrs(|ip_addrs| {
+            ip_addrs.retain(|cidr| !old.contains(&cidr.address()));
+            if let Some(ip) = ip {
+                pushed = ip_addrs.push(ip.into()).map_err(|_| KError::NoMemory);
+            }
+        });
+        let ip = match pushed {
+            Ok(()) => ip,
+            Err(err) => {
+                self.router.devices[dev].set_ipv4(None);
+                return Err(err);
+            }
+        };
+        self.router.devices[dev].set_ipv4(ip);
+
+        if let Some(ip) = ip {
+            let src = IpAddress::Ipv4(ip.address());
+            self.router.add_rule(Rule::new(ip.into(), None, dev, src));
+            if let Some(gateway) = gateway {
+                let default = Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0);
+                self.router
+                    .add_rule(Rule::new(default.into(), Some(gateway.into()), dev, src));
+            }
+        }
+        Ok(())
     }
 
-    pub fn register_rx_waker(&mut self, mask: u32, waker: &Waker) {
-        let next = self.iface.poll_at(now(), &SOCKET_SET.inner.lock());
+    /// Routes `filter` through the on-link neighbor `via`.
+    pub fn add_route(&mut self, filter: IpCidr, via: IpAddress) -> KResult {
+        let rule = self
+            .router
+            .table
+            .lookup(&via)
+            .filter(|rule| rule.via.is_none())
+            .ok_or(KError::InvalidInput)?;
+        let rule = Rule::new(filter, Some(via), rule.dev, rule.src);
+        self.router.add_rule(rule);
+        Ok(())
+    }
+
+    /// Accepts packets sent to the multicast group `addr`.
+    pub fn join_multicast_group(&mut self, addr: IpAddress) -> KResult {
+        self.iface
+            .join_multicast_group(addr)
+            .map_err(|err| match err {
+                MulticastError::GroupTableFull => KError::NoMemory,
+                _ => KError::InvalidInput,
+            })
+    }
+
+    /// Returns the IPv4 addresses of the devices other than loopback.
+    pub fn ipv4_addrs(&self) -> Vec<Ipv4Address> {
+        self.iface
+            .ip_addrs()
+            .iter()
+            .filter_map(|cidr| match cidr.address() {
+                IpAddress::Ipv4(addr) if !addr.is_loopback() => Some(addr),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Attaches a queuing discipline to the device `name`, or detaches it
+    /// with `None`, dropping the packets it holds.
+    pub fn set_qdisc(&mut self, name: &str, config: Option<QdiscConfig>) -> KResult {
+        let dev = self
+            .router
+            .devices
+            .iter()
+            .position(|dev| dev.name() == name)
+            .ok_or(KError::NotFound)?;
+        self.router.qdiscs[dev] = config.map(Qdisc::new);
+        Ok(())
+    }
+
+    /// Sets what the device `name` does about its NIC failing to send.
+    pub fn set_io_policy(&mut self, name: &str, policy: IoPolicy) -> KResult {
+        self.router
+            .devices
+            .iter_mut()
+            .find(|dev| dev.name() == name)
+            .ok_or(KError::NotFound)?
+            .set_io_policy(policy)
+    }
+
+    /// Returns the name and queuing discipline of each device.
+    pub fn qdiscs(&self) -> Vec<(String, Option<QdiscConfig>)> {
+        self.router
+            .devices
+            .iter()
+            .zip(&self.router.qdiscs)
+            .map(|(dev, qdisc)| (dev.name().into(), qdisc.as_ref().map(Qdisc::config)))
+            .collect()
+    }
+
+    /// Registers `waker` for the next timeout of the interface and for the
+    /// frames received by any device.
+    pub fn register_rx_waker(&mut self, waker: &Waker, sockets: &SocketSet) {
+        let timestamp = now();
+        let next = match (
+            self.iface.poll_at(timestamp, sockets),
+            self.router.poll_at(timestamp),
+        ) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
 
         if let Some(t) = next {
             let next = TimeValue::from_micros(t.total_micros() as _);
@@ -86,10 +254,8 @@ impl Service {
             }
         }
 
-        for (i, device) in self.router.devices.iter().enumerate() {
-            if mask & (1 << i) != 0 {
-                device.register_rx_waker(waker);
-            }
+        for device in &self.router.devices {
+            device.register_rx_waker(waker);
         }
     }
 }
@@ -144,8 +144,9 @@ pub trait SocketOps: Configurable {
     /// Connects the socket to a remote address.
     fn connect(&self, remote_addr: SocketAddrEx) -> KResult;
 
-    /// Starts listening on the bound address and port.
-    fn listen(&self) -> KResult {
+    /// Starts listening on the bound address and port, queueing up to
+    /// `backlog` connections for [`SocketOps::accept`].
+    fn listen(&self, _backlog: usize) -> KResult {
         Err(KError::OperationNotSupported)
     }
     /// Accepts a connection on a listening socket, returning a new socket.
@@ -176,8 +177,8 @@ impl<T: SocketOps + ?Sized> SocketOps for Box<T> {
         (**self).connect(remote_addr)
     }
 
-    fn listen(&self) -> KResult {
-        (**self).listen()
+    fn listen(&self, backlog: usize) -> KResult {
+        (**self).listen(backlog)
     }
 
     fn accept(&self) -> KResult<Socket> {
@@ -0,0 +1,376 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Protocol and socket statistics.
+//!
+//! Each [`NetStack`] counts the packets passing its router in [`ProtoStats`],
+//! after the MIB counters of `/proc/net/snmp`, and describes its open sockets
+//! with [`NetStack::sockets`].
+//!
+//! smoltcp does not report retransmissions, so the router tracks the highest
+//! sequence number sent on each connection and counts segments that do not
+//! go beyond it.
+//!
+//! [`NetStack`]: crate::netns::NetStack
+//! [`NetStack::sockets`]: crate::netns::NetStack::sockets
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
+use core::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use smoltcp::{
+    iface::SocketSet,
+    socket::{Socket, tcp},
+    wire::{IpEndpoint, IpListenEndpoint, IpProtocol, TcpPacket},
+};
+
+use crate::checksum::transport;
+
+/// A statistics counter.
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    /// Returns the current value.
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// IP counters.
+#[derive(Debug, Default)]
+pub struct IpStats {
+    /// Packets received from devices.
+    pub in_receives: Counter,
+    /// Received packets other than TCP and UDP dropped for a bad checksum.
+    pub in_hdr_errors: Counter,
+    /// Packets handed to the router for sending.
+    pub out_requests: Counter,
+    /// Packets dropped for lack of a route.
+    pub out_no_routes: Counter,
+    /// Packets dropped by a device without carrier or a full egress queue.
+    pub out_discards: Counter,
+}
+
+/// TCP counters.
+#[derive(Debug, Default)]
+pub struct TcpStats {
+    /// Connections started by `connect()`.
+    pub active_opens: Counter,
+    /// Connections returned by `accept()`.
+    pub passive_opens: Counter,
+    /// Connection attempts that failed.
+    pub attempt_fails: Counter,
+    /// Segments received.
+    pub in_segs: Counter,
+    /// Segments sent, including retransmissions.
+    pub out_segs: Counter,
+    /// Segments retransmitted.
+    pub retrans_segs: Counter,
+    /// Segments dropped for a bad checksum.
+    pub in_errs: Counter,
+    /// Segments sent with RST.
+    pub out_rsts: Counter,
+    /// SYNs dropped because a listener's queues overflowed.
+    pub listen_drops: Counter,
+}
+
+/// UDP counters.
+#[derive(Debug, Default)]
+pub struct UdpStats {
+    /// Datagrams received.
+    pub in_datagrams: Counter,
+    /// Datagrams answered with port unreachable.
+    pub no_ports: Counter,
+    /// Datagrams dropped for a bad checksum.
+    pub in_errors: Counter,
+    /// Datagrams sent.
+    pub out_datagrams: Counter,
+}
+
+/// Per-protocol counters of a network stack.
+#[derive(Debug, Default)]
+pub struct ProtoStats {
+    /// IP counters.
+    pub ip: IpStats,
+    /// TCP counters.
+    pub tcp: TcpStats,
+    /// UDP counters.
+    pub udp: UdpStats,
+}
+
+impl ProtoStats {
+    /// Counts a packet received from a device.
+    pub(crate) fn incoming(&self, packet: &[u8]) {
+        self.ip.in_receives.inc();
+        match transport(packet).map(|t| t.protocol) {
+            Some(IpProtocol::Tcp) => self.tcp.in_segs.inc(),
+            Some(IpProtocol::Udp) => self.udp.in_datagrams.inc(),
+            _ => {}
+        }
+    }
+
+    /// Counts a received packet dropped for a bad checksum.
+    pub(crate) fn bad_checksum(&self, packet: &[u8]) {
+        self.ip.in_receives.inc();
+        match transport(packet).map(|t| t.protocol) {
+            Some(IpProtocol::Tcp) => self.tcp.in_errs.inc(),
+            Some(IpProtocol::Udp) => self.udp.in_errors.inc(),
+            _ => self.ip.in_hdr_errors.inc(),
+        }
+    }
+
+    /// Counts a packet handed to the router for sending.
+    pub(crate) fn outgoing(&self, packet: &[u8]) {
+        self.ip.out_requests.inc();
+        let Some(t) = transport(packet) else {
+            return;
+        };
+        let segment = &packet[t.start..t.end];
+        match t.protocol {
+            IpProtocol::Tcp => {
+                self.tcp.out_segs.inc();
+                if TcpPacket::new_checked(segment).is_ok_and(|tcp| tcp.rst()) {
+                    self.tcp.out_rsts.inc();
+                }
+            }
+            IpProtocol::Udp => self.udp.out_datagrams.inc(),
+            // Destination unreachable, port unreachable
+            IpProtocol::Icmp if segment.starts_with(&[3, 3]) => self.udp.no_ports.inc(),
+            IpProtocol::Icmpv6 if segment.starts_with(&[1, 4]) => self.udp.no_ports.inc(),
+            _ => {}
+        }
+    }
+}
+
+struct Flow {
+    /// Sequence number following the last one sent.
+    snd_max: u32,
+    retransmits: u64,
+}
+
+impl Flow {
+    fn new(isn: u32) -> Self {
+        Self {
+            snd_max: isn,
+            retransmits: 0,
+        }
+    }
+}
+
+/// Outgoing TCP connections, by local and remote endpoint.
+#[derive(Default)]
+pub(crate) struct TcpFlows {
+    flows: BTreeMap<(IpEndpoint, IpEndpoint), Flow>,
+    /// Number of flows at which closed connections are next dropped.
+    prune_at: usize,
+}
+
+/// Flows tracked before closed connections are looked for.
+const MIN_PRUNE_AT: usize = 64;
+
+impl TcpFlows {
+    /// Tracks an outgoing IP packet, returning whether it carries a TCP
+    /// retransmission.
+    pub fn outgoing(&mut self, packet: &[u8]) -> bool {
+        let Some(t) = transport(packet).filter(|t| t.protocol == IpProtocol::Tcp) else {
+            return false;
+        };
+        let Ok(tcp) = TcpPacket::new_checked(&packet[t.start..t.end]) else {
+            return false;
+        };
+        if tcp.rst() {
+            return false;
+        }
+        let seq = tcp.seq_number().0 as u32;
+        let len = tcp.payload().len() as u32 + tcp.syn() as u32 + tcp.fin() as u32;
+        let key = (
+            IpEndpoint::new(t.src, tcp.src_port()),
+            IpEndpoint::new(t.dst, tcp.dst_port()),
+        );
+        self.track(key, seq, len, tcp.syn())
+    }
+
+    fn track(&mut self, key: (IpEndpoint, IpEndpoint), seq: u32, len: u32, syn: bool) -> bool {
+        // Pure ACKs do not occupy sequence space
+        if len == 0 {
+            return false;
+        }
+        let end = seq.wrapping_add(len);
+        let flow = self.flows.entry(key).or_insert(Flow::new(seq));
+        // A SYN with another initial sequence number reuses the endpoints for
+        // a new connection
+        if syn && flow.snd_max != end {
+            *flow = Flow::new(seq);
+        }
+        if (end.wrapping_sub(flow.snd_max) as i32) <= 0 {
+            flow.retransmits += 1;
+            true
+        } else {
+            flow.snd_max = end;
+            false
+        }
+    }
+
+    /// Returns the number of segments retransmitted from `local` to
+    /// `remote`.
+    pub fn retransmits(&self, local: IpEndpoint, remote: IpEndpoint) -> u64 {
+        self.flows
+            .get(&(local, remote))
+            .map_or(0, |flow| flow.retransmits)
+    }
+
+    /// Forgets connections that no longer have a socket, once enough flows
+    /// have piled up since the last time.
+    pub fn prune(&mut self, sockets: &SocketSet<'_>) {
+        if self.flows.len() < self.prune_at.max(MIN_PRUNE_AT) {
+            return;
+        }
+        let live: BTreeSet<_> = sockets
+            .iter()
+            .filter_map(|(_, socket)| match socket {
+                Socket::Tcp(socket) => Some((socket.local_endpoint()?, socket.remote_endpoint()?)),
+                _ => None,
+            })
+            .collect();
+        self.flows.retain(|key, _| live.contains(key));
+        self.prune_at = self.flows.len() * 2;
+    }
+}
+
+/// Transport protocol of a socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// TCP.
+    Tcp,
+    /// UDP.
+    Udp,
+}
+
+/// State of a TCP socket, numbered like the states of Linux.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpState {
+    /// Connection established.
+    Established = 1,
+    /// SYN sent, waiting for the peer's SYN.
+    SynSent     = 2,
+    /// SYN received, waiting for the ACK of ours.
+    SynRecv     = 3,
+    /// Closed by us, waiting for the ACK of our FIN.
+    FinWait1    = 4,
+    /// Closed by us, waiting for the peer's FIN.
+    FinWait2    = 5,
+    /// Closed by both, lingering for late segments.
+    TimeWait    = 6,
+    /// Closed.
+    Close       = 7,
+    /// Closed by the peer, waiting for us to close.
+    CloseWait   = 8,
+    /// Closed by both, waiting for the ACK of our FIN.
+    LastAck     = 9,
+    /// Waiting for connections.
+    Listen      = 10,
+    /// Closed by both at once.
+    Closing     = 11,
+}
+
+impl From<tcp::State> for TcpState {
+    fn from(state: tcp::State) -> Self {
+        match state {
+            tcp::State::Closed => Self::Close,
+            tcp::State::Listen => Self::Listen,
+            tcp::State::SynSent => Self::SynSent,
+            tcp::State::SynReceived => Self::SynRecv,
+            tcp::State::Established => Self::Established,
+            tcp::State::FinWait1 => Self::FinWait1,
+            tcp::State::FinWait2 => Self::FinWait2,
+            tcp::State::CloseWait => Self::CloseWait,
+            tcp::State::Closing => Self::Closing,
+            tcp::State::LastAck => Self::LastAck,
+            tcp::State::TimeWait => Self::TimeWait,
+        }
+    }
+}
+
+/// An open socket, as listed by [`NetStack::sockets`].
+///
+/// [`NetStack::sockets`]: crate::netns::NetStack::sockets
+#[derive(Debug, Clone)]
+pub struct SocketInfo {
+    /// Transport protocol.
+    pub protocol: Protocol,
+    /// Local address, unspecified if bound to all of them.
+    pub local: SocketAddr,
+    /// Remote address of a connection.
+    pub remote: Option<SocketAddr>,
+    /// Connection state of a TCP socket.
+    pub state: Option<TcpState>,
+    /// Bytes waiting to be read, or connections waiting to be accepted by a
+    /// listener.
+    pub rx_queue: usize,
+    /// Bytes waiting to be sent, or the backlog of a listener.
+    pub tx_queue: usize,
+    /// Segments retransmitted.
+    pub retransmits: u64,
+    /// Connection requests a listener dropped.
+    pub drops: u64,
+}
+
+/// Converts a bound endpoint, unspecified if bound to all addresses.
+pub(crate) fn listen_addr(endpoint: IpListenEndpoint) -> SocketAddr {
+    let ip = endpoint
+        .addr
+        .map_or_else(|| Ipv4Addr::UNSPECIFIED.into(), Into::into);
+    SocketAddr::new(ip, endpoint.port)
+}
+
+/// Describes the TCP connections and bound UDP sockets in `sockets`.
+///
+/// Sockets waiting for a SYN on behalf of a listener are left out, the
+/// listeners themselves are described by the listen table.
+pub(crate) fn socket_infos(sockets: &SocketSet<'_>, flows: &TcpFlows) -> Vec<SocketInfo> {
+    sockets
+        .iter()
+        .filter_map(|(_, socket)| match socket {
+            Socket::Tcp(socket) => {
+                let state = socket.state();
+                if matches!(state, tcp::State::Closed | tcp::State::Listen) {
+                    return None;
+                }
+                let local = socket.local_endpoint()?;
+                let remote = socket.remote_endpoint()?;
+                Some(SocketInfo {
+                    protocol: Protocol::Tcp,
+                    local: local.into(),
+                    remote: Some(remote.into()),
+                    state: Some(state.into()),
+                    rx_queue: socket.recv_queue(),
+                    tx_queue: socket.send_queue(),
+                    retransmits: flows.retransmits(local, remote),
+                    drops: 0,
+                })
+            }
+            Socket::Udp(socket) if socket.is_open() => Some(SocketInfo {
+                protocol: Protocol::Udp,
+                local: listen_addr(socket.endpoint()),
+                remote: None,
+                state: None,
+                rx_queue: socket.recv_queue(),
+                tx_queue: socket.send_queue(),
+                retransmits: 0,
+                drops: 0,
+            }),
+            _ => None,
+        })
+        .collect()
+}
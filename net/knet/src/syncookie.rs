@@ -0,0 +1,392 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! SYN cookies (RFC 4987, section 3.6).
+//!
+//! When the SYN backlog of a listener overflows, the connection request is
+//! encoded into the initial sequence number of a SYN-ACK instead of being
+//! queued, and recovered from the ACK that completes the handshake.
+//!
+//! smoltcp always picks its own initial sequence number, so a connection
+//! admitted by cookie is replayed to smoltcp as an ordinary handshake and the
+//! router shifts sequence numbers between the cookie and smoltcp's choice for
+//! the rest of the connection, much like netfilter's SYNPROXY.
+use alloc::{collections::VecDeque, vec, vec::Vec};
+#[allow(deprecated)]
+use core::hash::SipHasher;
+use core::{
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+
+use hashbrown::HashMap;
+use smoltcp::{
+    iface::SocketSet,
+    phy::ChecksumCapabilities,
+    socket::{AnySocket, tcp as smol},
+    wire::{
+        IpAddress, IpEndpoint, IpProtocol, IpRepr, TcpControl, TcpPacket, TcpRepr, TcpSeqNumber,
+    },
+};
+
+use crate::{
+    checksum::transport,
+    consts::{STANDARD_MTU, TCP_RX_BUF_LEN},
+};
+
+/// MSS values a cookie can encode, as in Linux's `msstab`.
+const MSS_TABLE: [u16; 4] = [536, 1300, 1440, 1460];
+/// Lifetime of one cookie counter value.
+const COOKIE_PERIOD_SECS: u64 = 64;
+/// Number of counter periods after which a cookie expires.
+const MAX_COOKIE_AGE: u32 = 2;
+/// How long after the last cookie was sent ACKs are checked for one.
+pub(crate) const COOKIE_LIFETIME: Duration =
+    Duration::from_secs(COOKIE_PERIOD_SECS * (MAX_COOKIE_AGE as u64 + 1));
+/// Interval between sweeps for proxied connections that have gone away.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+const TCP_OPT_SACK: u8 = 5;
+
+/// Generates and validates SYN cookies.
+pub(crate) struct CookieJar {
+    k0: u64,
+    k1: u64,
+}
+
+impl CookieJar {
+    pub fn new(k0: u64, k1: u64) -> Self {
+        Self { k0, k1 }
+    }
+
+    fn hash(&self, local: IpEndpoint, remote: IpEndpoint, count: u32) -> u32 {
+        // SipHash is keyed and fast enough for per-SYN use
+        #[allow(deprecated)]
+        let mut hasher = SipHasher::new_with_keys(self.k0, self.k1);
+        (local, remote, count).hash(&mut hasher);
+        hasher.finish() as u32
+    }
+
+    fn counter(now: Duration) -> u32 {
+        (now.as_secs() / COOKIE_PERIOD_SECS) as u32
+    }
+
+    /// Computes the cookie for a SYN from `remote` to `local`, encoding the
+    /// largest table MSS not above the peer's `mss`.
+    ///
+    /// The layout follows Linux: the top 8 bits carry the counter, the low 24
+    /// bits a keyed hash plus the MSS index, and both are offset by another
+    /// hash and the peer's initial sequence number.
+    pub fn generate(
+        &self,
+        local: IpEndpoint,
+        remote: IpEndpoint,
+        peer_isn: u32,
+        mss: u16,
+        now: Duration,
+    ) -> u32 {
+        let index = MSS_TABLE.iter().rposition(|&it| it <= mss).unwrap_or(0) as u32;
+        let count = Self::counter(now);
+        self.hash(local, remote, 0)
+            .wrapping_add(peer_isn)
+            .wrapping_add(count << 24)
+            .wrapping_add(self.hash(local, remote, count).wrapping_add(index) & 0xff_ffff)
+    }
+
+    /// Validates a cookie echoed by the handshake-completing ACK, returning
+    /// the encoded MSS.
+    pub fn check(
+        &self,
+        local: IpEndpoint,
+        remote: IpEndpoint,
+        peer_isn: u32,
+        cookie: u32,
+        now: Duration,
+    ) -> Option<u16> {
+        let value = cookie
+            .wrapping_sub(self.hash(local, remote, 0))
+            .wrapping_sub(peer_isn);
+        let now_count = Self::counter(now);
+        let age = now_count.wrapping_sub(value >> 24) & 0xff;
+        if age > MAX_COOKIE_AGE {
+            return None;
+        }
+        let count = now_count.wrapping_sub(age);
+        let index = value.wrapping_sub(self.hash(local, remote, count)) & 0xff_ffff;
+        MSS_TABLE.get(index as usize).copied()
+    }
+}
+
+/// A TCP segment located within an IP packet.
+struct Segment {
+    src: IpEndpoint,
+    dst: IpEndpoint,
+    start: usize,
+    end: usize,
+}
+
+fn tcp_segment(packet: &[u8]) -> Option<Segment> {
+    let t = transport(packet)?;
+    if t.protocol != IpProtocol::Tcp {
+        return None;
+    }
+    let tcp = TcpPacket::new_checked(packet.get(t.start..t.end)?).ok()?;
+    Some(Segment {
+        src: IpEndpoint::new(t.src, tcp.src_port()),
+        dst: IpEndpoint::new(t.dst, tcp.dst_port()),
+        start: t.start,
+        end: t.end,
+    })
+}
+
+/// Moves a segment from smoltcp's sequence space to the peer's view.
+pub(crate) fn shift_seq(segment: &mut [u8], delta: u32) {
+    let mut tcp = TcpPacket::new_unchecked(segment);
+    let seq = tcp.seq_number().0 as u32;
+    tcp.set_seq_number(TcpSeqNumber(seq.wrapping_add(delta) as i32));
+}
+
+/// Moves the acknowledgement and SACK blocks of a segment from the peer's
+/// view of our sequence space to smoltcp's.
+pub(crate) fn unshift_ack(segment: &mut [u8], delta: u32) {
+    let mut tcp = TcpPacket::new_unchecked(segment);
+    let ack = tcp.ack_number().0 as u32;
+    tcp.set_ack_number(TcpSeqNumber(ack.wrapping_sub(delta) as i32));
+
+    let options = tcp.options_mut();
+    let mut i = 0;
+    while i < options.len() {
+        match options[i] {
+            0 => break,
+            1 => i += 1,
+            kind => {
+                let Some(&len) = options.get(i + 1) else {
+                    break;
+                };
+                let len = len as usize;
+                if len < 2 || i + len > options.len() {
+                    break;
+                }
+                if kind == TCP_OPT_SACK {
+                    for edge in options[i + 2..i + len].chunks_exact_mut(4) {
+                        let value = u32::from_be_bytes(edge.try_into().unwrap());
+                        edge.copy_from_slice(&value.wrapping_sub(delta).to_be_bytes());
+                    }
+                }
+                i += len;
+            }
+        }
+    }
+}
+
+/// Builds an IP packet carrying a TCP segment without payload.
+///
+/// Checksums are left to the router, like for packets from smoltcp.
+fn build_tcp_packet(src: IpAddress, dst: IpAddress, repr: &TcpRepr) -> Vec<u8> {
+    let ip_repr = IpRepr::new(src, dst, IpProtocol::Tcp, repr.buffer_len(), 64);
+    let mut packet = vec![0; ip_repr.buffer_len()];
+    let caps = ChecksumCapabilities::ignored();
+    ip_repr.emit(&mut packet[..], &caps);
+    let header_len = ip_repr.header_len();
+    repr.emit(
+        &mut TcpPacket::new_unchecked(&mut packet[header_len..]),
+        &src,
+        &dst,
+        &caps,
+    );
+    packet
+}
+
+/// A connection admitted by cookie.
+struct ProxiedConn {
+    /// Our initial sequence number as seen by the peer.
+    cookie: u32,
+    /// Offset from smoltcp's sequence space to the peer's view, known once
+    /// smoltcp has answered the replayed SYN.
+    delta: Option<u32>,
+    /// The handshake-completing ACK, held back until `delta` is known.
+    deferred: Option<Vec<u8>>,
+}
+
+/// What to do with a packet entering smoltcp.
+pub(crate) enum Verdict {
+    /// Pass the packet unchanged.
+    Pass,
+    /// Pass a different packet instead.
+    Replace(Vec<u8>),
+    /// Drop the packet.
+    Drop,
+}
+
+/// Sequence number translation for connections admitted by cookie.
+#[derive(Default)]
+pub(crate) struct SynProxy {
+    /// Keyed by `(local, remote)`.
+    conns: HashMap<(IpEndpoint, IpEndpoint), ProxiedConn>,
+    /// Packets to hand to smoltcp before anything received from devices.
+    pub injected: VecDeque<Vec<u8>>,
+    /// Packets to transmit on behalf of smoltcp.
+    pub replies: VecDeque<Vec<u8>>,
+    last_sweep: Duration,
+}
+
+impl SynProxy {
+    /// Answers a SYN from `remote` with a cookie SYN-ACK.
+    ///
+    /// No options beyond MSS are offered, since the cookie cannot remember
+    /// the peer's.
+    pub fn reply_cookie(
+        &mut self,
+        local: IpEndpoint,
+        remote: IpEndpoint,
+        peer_isn: u32,
+        cookie: u32,
+    ) {
+        let ip_header_len = match local.addr {
+            IpAddress::Ipv4(_) => 20,
+            IpAddress::Ipv6(_) => 40,
+        };
+        let repr = TcpRepr {
+            src_port: local.port,
+            dst_port: remote.port,
+            control: TcpControl::Syn,
+            seq_number: TcpSeqNumber(cookie as i32),
+            ack_number: Some(TcpSeqNumber(peer_isn.wrapping_add(1) as i32)),
+            window_len: TCP_RX_BUF_LEN.min(u16::MAX as usize) as u16,
+            window_scale: None,
+            max_seg_size: Some((STANDARD_MTU - ip_header_len - 20) as u16),
+            sack_permitted: false,
+            sack_ranges: [None; 3],
+            timestamp: None,
+            payload: &[],
+        };
+        self.replies
+            .push_back(build_tcp_packet(local.addr, remote.addr, &repr));
+    }
+
+    /// Starts replaying the handshake of a connection whose cookie was
+    /// validated, returning the SYN to hand to smoltcp.
+    pub fn admit(
+        &mut self,
+        local: IpEndpoint,
+        remote: IpEndpoint,
+        cookie: u32,
+        mss: u16,
+        ack_packet: &[u8],
+    ) -> Option<Vec<u8>> {
+        let segment = tcp_segment(ack_packet)?;
+        let tcp = TcpPacket::new_unchecked(&ack_packet[segment.start..segment.end]);
+        let repr = TcpRepr {
+            src_port: remote.port,
+            dst_port: local.port,
+            control: TcpControl::Syn,
+            seq_number: tcp.seq_number() - 1,
+            ack_number: None,
+            window_len: tcp.window_len(),
+            window_scale: None,
+            max_seg_size: Some(mss),
+            sack_permitted: false,
+            sack_ranges: [None; 3],
+            timestamp: None,
+            payload: &[],
+        };
+        self.conns.insert(
+            (local, remote),
+            ProxiedConn {
+                cookie,
+                delta: None,
+                deferred: Some(ack_packet.to_vec()),
+            },
+        );
+        Some(build_tcp_packet(remote.addr, local.addr, &repr))
+    }
+
+    /// Translates a packet received for a proxied connection.
+    pub fn incoming(&mut self, packet: &[u8]) -> Verdict {
+        if self.conns.is_empty() {
+            return Verdict::Pass;
+        }
+        let Some(segment) = tcp_segment(packet) else {
+            return Verdict::Pass;
+        };
+        let key = (segment.dst, segment.src);
+        let Some(conn) = self.conns.get(&key) else {
+            return Verdict::Pass;
+        };
+        // The peer retransmits while smoltcp is still catching up
+        let Some(delta) = conn.delta else {
+            return Verdict::Drop;
+        };
+
+        let mut packet = packet.to_vec();
+        let tcp = &mut packet[segment.start..segment.end];
+        if TcpPacket::new_unchecked(&*tcp).ack() {
+            unshift_ack(tcp, delta);
+        }
+        if TcpPacket::new_unchecked(&*tcp).rst() {
+            self.conns.remove(&key);
+        }
+        Verdict::Replace(packet)
+    }
+
+    /// Translates a packet sent by smoltcp, returning whether to transmit it.
+    pub fn outgoing(&mut self, packet: &mut [u8]) -> bool {
+        if self.conns.is_empty() {
+            return true;
+        }
+        let Some(segment) = tcp_segment(packet) else {
+            return true;
+        };
+        let key = (segment.src, segment.dst);
+        let Some(conn) = self.conns.get_mut(&key) else {
+            return true;
+        };
+        let tcp = &mut packet[segment.start..segment.end];
+        let (syn, ack, rst, seq) = {
+            let tcp = TcpPacket::new_unchecked(&*tcp);
+            (tcp.syn(), tcp.ack(), tcp.rst(), tcp.seq_number().0 as u32)
+        };
+
+        match conn.delta {
+            Some(delta) => {
+                shift_seq(tcp, delta);
+                if rst {
+                    self.conns.remove(&key);
+                }
+                true
+            }
+            // smoltcp answering the replayed SYN, which the peer must not see
+            None if syn && ack => {
+                conn.delta = Some(conn.cookie.wrapping_sub(seq));
+                if let Some(deferred) = conn.deferred.take() {
+                    self.injected.push_back(deferred);
+                }
+                false
+            }
+            None => {
+                if rst {
+                    self.conns.remove(&key);
+                }
+                false
+            }
+        }
+    }
+
+    /// Forgets connections whose smoltcp socket is gone.
+    pub fn sweep(&mut self, sockets: &SocketSet<'_>, now: Duration) {
+        if self.conns.is_empty() || now.saturating_sub(self.last_sweep) < SWEEP_INTERVAL {
+            return;
+        }
+        self.last_sweep = now;
+        self.conns.retain(|&(local, remote), _| {
+            sockets.iter().any(|(_, socket)| {
+                smol::Socket::downcast(socket).is_some_and(|socket| {
+                    socket.state() != smol::State::Closed
+                        && socket.local_endpoint() == Some(local)
+                        && socket.remote_endpoint() == Some(remote)
+                })
+            })
+        });
+    }
+}
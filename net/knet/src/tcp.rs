@@ -3,10 +3,26 @@
 // See LICENSES for license details.
 
 //! TCP socket implementation.
+//!
+//! # Loss recovery
+//!
+//! Retransmission is left to the in-tree smoltcp fork, which knet only
+//! configures:
+//! - the timestamps option (RFC 7323) is offered when
+//!   [`timestamps_enabled`], with a 1ms TSval clock;
+//! - the RTO is computed as in RFC 6298 and doubled on each timeout. With
+//!   timestamps, every ACK of new data yields an RTT sample from its echo,
+//!   including ACKs of retransmitted segments;
+//! - SACK is negotiated. SACK blocks from the peer are kept in a scoreboard,
+//!   so that recovery only retransmits the holes;
+//! - lost segments are fast-retransmitted after three duplicate ACKs;
+//! - after a timeout, F-RTO (RFC 5682) tells from the next two ACKs whether
+//!   it was spurious, and if so undoes the RTO back-off and the congestion
+//!   window reduction.
 use alloc::{boxed::Box, sync::Arc, vec};
 use core::{
     net::{Ipv4Addr, SocketAddr},
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
     task::Context,
 };
 
@@ -21,26 +37,90 @@ use smoltcp::{
     wire::{IpEndpoint, IpListenEndpoint},
 };
 
-use super::{LISTEN_TABLE, SOCKET_SET};
 use crate::{
-    RecvFlags, RecvOptions, SERVICE, SendOptions, Shutdown, Socket, SocketAddrEx, SocketOps,
-    consts::{TCP_RX_BUF_LEN, TCP_TX_BUF_LEN},
+    RecvFlags, RecvOptions, SendOptions, Shutdown, Socket, SocketAddrEx, SocketOps,
+    congestion::{CongestionControl, default_congestion_control},
+    consts::{LISTEN_QUEUE_SIZE, SOMAXCONN, TCP_RX_BUF_LEN, TCP_TX_BUF_LEN},
     general::GeneralOptions,
+    listen_table::ListenTable,
+    netns::{NetStack, current_stack},
     options::{Configurable, GetSocketOption, SetSocketOption},
     poll_interfaces,
     state::*,
 };
 
-pub(crate) fn new_tcp_socket() -> smol::Socket<'static> {
-    smol::Socket::new(
-        smol::SocketBuffer::new(vec![0; TCP_RX_BUF_LEN]),
-        smol::SocketBuffer::new(vec![0; TCP_TX_BUF_LEN]),
-    )
+static TIMESTAMPS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Returns whether new connections offer the TCP timestamps option (RFC 7323).
+pub fn timestamps_enabled() -> bool {
+    TIMESTAMPS_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Enables or disables the TCP timestamps option for new connections.
+pub fn set_timestamps_enabled(enabled: bool) {
+    TIMESTAMPS_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+static SOMAXCONN_LIMIT: AtomicUsize = AtomicUsize::new(SOMAXCONN);
+static MAX_SYN_BACKLOG: AtomicUsize = AtomicUsize::new(LISTEN_QUEUE_SIZE);
+static SYNCOOKIES_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Returns the upper bound applied to the backlog passed to `listen()`.
+pub fn somaxconn() -> usize {
+    SOMAXCONN_LIMIT.load(Ordering::Relaxed)
+}
+
+/// Sets the upper bound applied to the backlog of future `listen()` calls.
+pub fn set_somaxconn(limit: usize) {
+    SOMAXCONN_LIMIT.store(limit, Ordering::Relaxed);
+}
+
+/// Returns the maximum number of half-open connections per listener.
+pub fn max_syn_backlog() -> usize {
+    MAX_SYN_BACKLOG.load(Ordering::Relaxed)
+}
+
+/// Sets the maximum number of half-open connections per listener.
+pub fn set_max_syn_backlog(limit: usize) {
+    MAX_SYN_BACKLOG.store(limit, Ordering::Relaxed);
+}
+
+/// Returns whether SYN cookies are sent once the SYN backlog overflows.
+pub fn syncookies_enabled() -> bool {
+    SYNCOOKIES_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Enables or disables SYN cookies.
+pub fn set_syncookies_enabled(enabled: bool) {
+    SYNCOOKIES_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// TSval clock with a 1ms tick, within the range RFC 7323 recommends. smoltcp
+/// relies on the tick to measure the RTT from echoed timestamps.
+fn tcp_tsval() -> u32 {
+    (khal::time::monotonic_time_nanos() / khal::time::NS_MS) as u32
+}
+
+pub(crate) fn new_tcp_socket(cc: CongestionControl) -> smol::Socket<'static> {
+    let mut socket = kalloc::heap_tagged!(
+        NetBuf,
+        smol::Socket::new(
+            smol::SocketBuffer::new(vec![0; TCP_RX_BUF_LEN]),
+            smol::SocketBuffer::new(vec![0; TCP_TX_BUF_LEN]),
+        )
+    );
+    socket.set_congestion_control(cc.to_smol());
+    // smoltcp drops the option again if the peer does not echo it
+    if timestamps_enabled() {
+        socket.set_tsval_generator(Some(tcp_tsval));
+    }
+    socket
 }
 
 /// A TCP socket that provides POSIX-like APIs.
 pub struct TcpSocket {
     state: StateLock,
+    stack: Arc<NetStack>,
     dispatch_irq: SocketHandle,
 
     general: GeneralOptions,
@@ -51,11 +131,20 @@ pub struct TcpSocket {
 unsafe impl Sync for TcpSocket {}
 
 impl TcpSocket {
-    /// Creates a new TCP socket.
+    /// Creates a new TCP socket in the network namespace of the current
+    /// process.
     pub fn new() -> Self {
+        Self::new_in(current_stack())
+    }
+
+    /// Creates a new TCP socket in `stack`.
+    pub fn new_in(stack: Arc<NetStack>) -> Self {
         Self {
             state: StateLock::new(State::Idle),
-            dispatch_irq: SOCKET_SET.add(new_tcp_socket()),
+            dispatch_irq: stack
+                .sockets
+                .add(new_tcp_socket(default_congestion_control())),
+            stack,
 
             general: GeneralOptions::new(),
             rx_closed: AtomicBool::new(false),
@@ -64,21 +153,16 @@ impl TcpSocket {
     }
 
     /// Creates a new TCP socket that is already connected.
-    fn new_connected(dispatch_irq: SocketHandle) -> Self {
-        let result = Self {
+    fn new_connected(stack: Arc<NetStack>, dispatch_irq: SocketHandle) -> Self {
+        Self {
             state: StateLock::new(State::Connected),
+            stack,
             dispatch_irq,
 
             general: GeneralOptions::new(),
             rx_closed: AtomicBool::new(false),
             poll_rx_closed: Arc::new(PollSet::new()),
-        };
-        result.with_smol_socket(|socket| {
-            result
-                .general
-                .set_device_mask(SERVICE.lock().device_mask_for(&socket.get_bound_endpoint()));
-        });
-        result
+        }
     }
 }
 
@@ -100,7 +184,9 @@ impl TcpSocket {
     }
 
     fn with_smol_socket<R>(&self, f: impl FnOnce(&mut smol::Socket) -> R) -> R {
-        SOCKET_SET.with_socket_mut::<smol::Socket, _, _>(self.dispatch_irq, f)
+        self.stack
+            .sockets
+            .with_socket_mut::<smol::Socket, _, _>(self.dispatch_irq, f)
     }
 
     fn bound_endpoint(&self) -> KResult<IpListenEndpoint> {
@@ -126,6 +212,7 @@ impl TcpSocket {
             }
             _ => {
                 self.state.set(State::Closed); // connection failed
+                self.stack.stats.tcp.attempt_fails.inc();
                 true
             }
         });
@@ -150,7 +237,8 @@ impl TcpSocket {
         let mut events = IoEvents::empty();
         events.set(
             IoEvents::IN,
-            LISTEN_TABLE
+            self.stack
+                .listen_table
                 .can_accept(self.bound_endpoint().unwrap().port)
                 .unwrap(),
         );
@@ -186,6 +274,11 @@ impl Configurable for TcpSocket {
             O::TcpInfo(_) => {
                 // TODO(mivik): implement TCP_INFO
             }
+            O::Congestion(cc) => {
+                **cc = self.with_smol_socket(|socket| {
+                    CongestionControl::from_smol(socket.congestion_control())
+                });
+            }
             _ => return Ok(false),
         }
         Ok(true)
@@ -209,6 +302,9 @@ impl Configurable for TcpSocket {
                     socket.set_keep_alive(keep_alive.then(|| Duration::from_secs(75)));
                 });
             }
+            O::Congestion(cc) => {
+                self.with_smol_socket(|socket| socket.set_congestion_control(cc.to_smol()));
+            }
             _ => return Ok(false),
         }
         Ok(true)
@@ -223,10 +319,12 @@ impl SocketOps for TcpSocket {
             .transit(State::Idle, || {
                 // TODO: check addr is available
                 if local_addr.port() == 0 {
-                    local_addr.set_port(get_ephemeral_port()?);
+                    local_addr.set_port(get_ephemeral_port(&self.stack.listen_table)?);
                 }
                 if !self.general.reuse_address() {
-                    SOCKET_SET.bind_check(local_addr.ip().into(), local_addr.port())?;
+                    self.stack
+                        .sockets
+                        .bind_check(local_addr.ip().into(), local_addr.port())?;
                 }
 
                 self.with_smol_socket(|socket| {
@@ -242,8 +340,6 @@ impl SocketOps for TcpSocket {
                         port: local_addr.port(),
                     };
                     socket.set_bound_endpoint(endpoint);
-                    self.general
-                        .set_device_mask(SERVICE.lock().device_mask_for(&endpoint));
                     Ok(())
                 })?;
                 debug!(
@@ -273,11 +369,15 @@ impl SocketOps for TcpSocket {
                 let mut bound_endpoint =
                     self.with_smol_socket(|socket| socket.get_bound_endpoint());
                 if bound_endpoint.addr.is_none() {
-                    bound_endpoint.addr =
-                        Some(SERVICE.lock().get_source_address(&remote_endpoint.addr));
+                    bound_endpoint.addr = Some(
+                        self.stack
+                            .service
+                            .lock()
+                            .get_source_address(&remote_endpoint.addr),
+                    );
                 }
                 if bound_endpoint.port == 0 {
-                    bound_endpoint.port = get_ephemeral_port()?;
+                    bound_endpoint.port = get_ephemeral_port(&self.stack.listen_table)?;
                 }
                 info!(
                     "TCP connection from {} to {}",
@@ -286,11 +386,9 @@ impl SocketOps for TcpSocket {
 
                 self.with_smol_socket(|socket| {
                     socket.set_bound_endpoint(bound_endpoint);
-                    self.general
-                        .set_device_mask(SERVICE.lock().device_mask_for(&bound_endpoint));
                     socket
                         .connect(
-                            crate::SERVICE.lock().iface.context(),
+                            self.stack.service.lock().iface.context(),
                             remote_endpoint,
                             bound_endpoint,
                         )
@@ -305,6 +403,7 @@ impl SocketOps for TcpSocket {
                     Ok(())
                 })
             })?;
+        self.stack.stats.tcp.active_opens.inc();
 
         // Hack: let the server listen
         ktask::yield_now();
@@ -323,14 +422,26 @@ impl SocketOps for TcpSocket {
         })
     }
 
-    fn listen(&self) -> KResult {
+    fn listen(&self, backlog: usize) -> KResult {
+        let backlog = backlog.min(somaxconn());
         if let Ok(guard) = self.state.lock(State::Idle) {
             guard.transit(State::Listening, || {
-                let bound_endpoint = self.with_smol_socket(|socket| socket.get_bound_endpoint());
-                LISTEN_TABLE.listen(bound_endpoint)?;
+                let (bound_endpoint, cc) = self.with_smol_socket(|socket| {
+                    (
+                        socket.get_bound_endpoint(),
+                        CongestionControl::from_smol(socket.congestion_control()),
+                    )
+                });
+                self.stack
+                    .listen_table
+                    .listen(bound_endpoint, backlog, cc)?;
                 debug!("listening on {}", bound_endpoint);
                 Ok(())
             })?;
+        } else if self.is_listening() {
+            self.stack
+                .listen_table
+                .set_backlog(self.bound_endpoint()?.port, backlog);
         } else {
             // ignore simultaneous `listen`s.
         }
@@ -345,15 +456,19 @@ impl SocketOps for TcpSocket {
         let bound_port = self.bound_endpoint()?.port;
         self.general.recv_poller(self, || {
             poll_interfaces();
-            LISTEN_TABLE.accept(bound_port).map(|dispatch_irq| {
-                let socket = TcpSocket::new_connected(dispatch_irq);
-                debug!(
-                    "accepted connection from {}, {}",
-                    dispatch_irq,
-                    socket.with_smol_socket(|socket| socket.remote_endpoint().unwrap())
-                );
-                Socket::Tcp(Box::new(socket))
-            })
+            self.stack
+                .listen_table
+                .accept(bound_port)
+                .map(|dispatch_irq| {
+                    self.stack.stats.tcp.passive_opens.inc();
+                    let socket = TcpSocket::new_connected(self.stack.clone(), dispatch_irq);
+                    debug!(
+                        "accepted connection from {}, {}",
+                        dispatch_irq,
+                        socket.with_smol_socket(|socket| socket.remote_endpoint().unwrap())
+                    );
+                    Socket::Tcp(Box::new(socket))
+                })
         })
     }
 
@@ -457,7 +572,9 @@ impl SocketOps for TcpSocket {
         // listener
         if let Ok(guard) = self.state.lock(State::Listening) {
             guard.transit(State::Closed, || {
-                LISTEN_TABLE.unlisten(self.bound_endpoint()?.port);
+                self.stack
+                    .listen_table
+                    .unlisten(self.bound_endpoint()?.port);
                 poll_interfaces();
                 Ok(())
             })?;
@@ -483,7 +600,19 @@ impl Pollable for TcpSocket {
 
     fn register(&self, context: &mut Context<'_>, events: IoEvents) {
         if events.intersects(IoEvents::IN | IoEvents::OUT | IoEvents::RDHUP) {
-            self.general.register_rx_waker(context.waker());
+            let waker = self.general.register_waker(context.waker());
+            if self.is_listening() {
+                if let Ok(endpoint) = self.bound_endpoint() {
+                    self.stack
+                        .listen_table
+                        .register_waker(endpoint.port, &waker);
+                }
+            } else {
+                self.with_smol_socket(|socket| {
+                    socket.register_recv_waker(&waker);
+                    socket.register_send_waker(&waker);
+                });
+            }
         }
         if events.contains(IoEvents::RDHUP) {
             self.poll_rx_closed.register(context.waker());
@@ -496,13 +625,13 @@ impl Drop for TcpSocket {
         if let Err(err) = self.shutdown(Shutdown::Both) {
             warn!("TCP socket {}: shutdown failed: {}", self.dispatch_irq, err);
         }
-        SOCKET_SET.remove(self.dispatch_irq);
+        self.stack.sockets.remove(self.dispatch_irq);
         // This is crucial for the close messages to be sent.
         poll_interfaces();
     }
 }
 
-fn get_ephemeral_port() -> KResult<u16> {
+fn get_ephemeral_port(listen_table: &ListenTable) -> KResult<u16> {
     const PORT_START: u16 = 0xc000;
     const PORT_END: u16 = 0xffff;
     static CURR: Mutex<u16> = Mutex::new(PORT_START);
@@ -517,7 +646,7 @@ fn get_ephemeral_port() -> KResult<u16> {
         } else {
             *curr += 1;
         }
-        if LISTEN_TABLE.can_listen(port) {
+        if listen_table.can_listen(port) {
             return Ok(port);
         }
         tries += 1;
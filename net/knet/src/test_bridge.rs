@@ -0,0 +1,136 @@
+//! Unit tests for learning Ethernet bridges.
+
+#![cfg(unittest)]
+
+use alloc::borrow::ToOwned;
+use core::net::{Ipv4Addr, SocketAddr};
+
+use kdriver::prelude::{MacAddress, VirtualNic};
+use kerrno::KError;
+use smoltcp::wire::{Ipv4Address, Ipv4Cidr};
+use unittest::def_test;
+
+use crate::{
+    RecvOptions, SendOptions, SocketAddrEx, SocketOps,
+    netns::NetStack,
+    options::{Configurable, SetSocketOption},
+    poll_interfaces,
+    udp::UdpSocket,
+};
+
+const B_MAC: MacAddress = MacAddress([0x02, 0, 0, 0, 0x21, 2]);
+const C_MAC: MacAddress = MacAddress([0x02, 0, 0, 0, 0x21, 3]);
+
+fn addr(ip: [u8; 4], port: u16) -> SocketAddrEx {
+    SocketAddrEx::Ip(SocketAddr::new(Ipv4Addr::from(ip).into(), port))
+}
+
+fn nonblocking(socket: UdpSocket) -> UdpSocket {
+    socket
+        .set_option(SetSocketOption::NonBlocking(&true))
+        .unwrap();
+    socket
+}
+
+/// Sends `payload` from `client` to `to` and waits for it on `server`.
+fn exchange(client: &UdpSocket, server: &UdpSocket, to: SocketAddrEx, payload: &[u8]) -> bool {
+    client
+        .send(
+            payload,
+            SendOptions {
+                to: Some(to),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    // Address resolution takes a few rounds
+    let mut buf = [0u8; 16];
+    (0..16).any(|_| {
+        poll_interfaces();
+        server
+            .recv(&mut buf[..], RecvOptions::default())
+            .is_ok_and(|len| &buf[..len] == payload)
+    })
+}
+
+#[def_test]
+fn test_bridge_forwarding() {
+    let a = NetStack::new();
+    let b = NetStack::new();
+    let c = NetStack::new();
+    let bridge = a
+        .add_bridge(
+            "br0".to_owned(),
+            MacAddress([0x02, 0, 0, 0, 0x21, 1]),
+            Ipv4Cidr::new(Ipv4Address::new(10, 210, 0, 1), 24),
+        )
+        .unwrap();
+    for (stack, mac, port, host) in [(&b, B_MAC, "veth1", 2), (&c, C_MAC, "veth2", 3)] {
+        let (nic, peer) = VirtualNic::new_pair(mac, MacAddress([0x02, 0, 0, 0, 0x22, host]));
+        stack
+            .add_nic(
+                "eth1".to_owned(),
+                nic,
+                Ipv4Cidr::new(Ipv4Address::new(10, 210, 0, host), 24),
+            )
+            .unwrap();
+        bridge.add_port(port.to_owned(), peer).unwrap();
+    }
+    assert_eq!(bridge.ports(), ["br0", "veth1", "veth2"]);
+
+    // Between two ports
+    let server = nonblocking(UdpSocket::new_in(c.clone()));
+    server.bind(addr([10, 210, 0, 3], 7200)).unwrap();
+    let client = nonblocking(UdpSocket::new_in(b.clone()));
+    client.bind(addr([10, 210, 0, 2], 7201)).unwrap();
+    assert!(exchange(
+        &client,
+        &server,
+        addr([10, 210, 0, 3], 7200),
+        b"bridged"
+    ));
+    assert_eq!(bridge.lookup(B_MAC).as_deref(), Some("veth1"));
+    assert_eq!(bridge.lookup(C_MAC).as_deref(), Some("veth2"));
+
+    // To the address of the bridge itself
+    let local = nonblocking(UdpSocket::new_in(a));
+    local.bind(addr([10, 210, 0, 1], 7202)).unwrap();
+    assert!(exchange(
+        &client,
+        &local,
+        addr([10, 210, 0, 1], 7202),
+        b"local"
+    ));
+
+    // Nothing crosses a released port
+    bridge.remove_port("veth2").unwrap();
+    assert_eq!(bridge.lookup(C_MAC), None);
+    assert!(!exchange(
+        &client,
+        &server,
+        addr([10, 210, 0, 3], 7200),
+        b"gone"
+    ));
+}
+
+#[def_test]
+fn test_bridge_ports() {
+    let stack = NetStack::new();
+    let bridge = stack
+        .add_bridge(
+            "br1".to_owned(),
+            MacAddress([0x02, 0, 0, 0, 0x23, 1]),
+            Ipv4Cidr::new(Ipv4Address::new(10, 211, 0, 1), 24),
+        )
+        .unwrap();
+    let (nic, _peer) = VirtualNic::new_pair(B_MAC, C_MAC);
+    bridge.add_port("veth1".to_owned(), nic).unwrap();
+    let (nic, _peer) = VirtualNic::new_pair(B_MAC, C_MAC);
+    assert_eq!(
+        bridge.add_port("veth1".to_owned(), nic),
+        Err(KError::AlreadyExists)
+    );
+    assert_eq!(bridge.remove_port("br1"), Err(KError::InvalidInput));
+    assert_eq!(bridge.remove_port("veth2"), Err(KError::NotFound));
+    assert_eq!(bridge.name(), "br1");
+}
@@ -0,0 +1,160 @@
+//! Unit tests for packet capture.
+
+#![cfg(unittest)]
+
+use alloc::{string::ToString, vec, vec::Vec};
+
+use kerrno::KError;
+use unittest::def_test;
+
+use crate::capture::{
+    Capture, CaptureConfig, CaptureStats, Direction, LinkType, filter::*, pcap_file_header, tap,
+};
+
+/// Builds an Ethernet frame carrying an IPv4 packet of `protocol`.
+fn ipv4_frame(protocol: u8, payload_len: usize) -> Vec<u8> {
+    let mut frame = vec![0u8; 14 + 20 + payload_len];
+    frame[12..14].copy_from_slice(&0x0800u16.to_be_bytes());
+    frame[14] = 0x45;
+    frame[14 + 9] = protocol;
+    frame
+}
+
+/// `tcpdump -dd ip and udp`
+fn udp_filter() -> Filter {
+    Filter::new(vec![
+        Insn::stmt(BPF_LD | BPF_H | BPF_ABS, 12),
+        Insn::jump(BPF_JMP | BPF_JEQ | BPF_K, 0x0800, 0, 3),
+        Insn::stmt(BPF_LD | BPF_B | BPF_ABS, 23),
+        Insn::jump(BPF_JMP | BPF_JEQ | BPF_K, 17, 0, 1),
+        Insn::stmt(BPF_RET | BPF_K, 65535),
+        Insn::stmt(BPF_RET | BPF_K, 0),
+    ])
+    .unwrap()
+}
+
+#[def_test]
+fn test_filter_matches_protocol() {
+    let filter = udp_filter();
+    assert_eq!(filter.run(&ipv4_frame(17, 8)), 65535);
+    assert_eq!(filter.run(&ipv4_frame(6, 20)), 0);
+    // Too short to hold the protocol field
+    assert_eq!(filter.run(&[0x08, 0x00]), 0);
+}
+
+#[def_test]
+fn test_filter_header_length_and_scratch() {
+    // Loads the IPv4 header length, keeps it in memory and returns it
+    let filter = Filter::new(vec![
+        Insn::stmt(BPF_LDX | BPF_B | BPF_MSH, 14),
+        Insn::stmt(BPF_STX, 3),
+        Insn::stmt(BPF_LD | BPF_MEM, 3),
+        Insn::stmt(BPF_RET | BPF_A, 0),
+    ])
+    .unwrap();
+    assert_eq!(filter.run(&ipv4_frame(17, 0)), 20);
+
+    // A zero divisor at run time drops the packet
+    let filter = Filter::new(vec![
+        Insn::stmt(BPF_LD | BPF_IMM, 100),
+        Insn::stmt(BPF_ALU | BPF_DIV | BPF_X, 0),
+        Insn::stmt(BPF_RET | BPF_A, 0),
+    ])
+    .unwrap();
+    assert_eq!(filter.run(&[]), 0);
+}
+
+#[def_test]
+fn test_filter_validation() {
+    let invalid = [
+        vec![],
+        // Does not end with a return
+        vec![Insn::stmt(BPF_LD | BPF_IMM, 1)],
+        // Jumps past the end
+        vec![
+            Insn::jump(BPF_JMP | BPF_JEQ | BPF_K, 0, 1, 0),
+            Insn::stmt(BPF_RET | BPF_K, 0),
+        ],
+        // Scratch memory out of range
+        vec![Insn::stmt(BPF_ST, 16), Insn::stmt(BPF_RET | BPF_K, 0)],
+        // Division by a zero constant
+        vec![
+            Insn::stmt(BPF_ALU | BPF_DIV | BPF_K, 0),
+            Insn::stmt(BPF_RET | BPF_K, 0),
+        ],
+        // Unknown opcode
+        vec![Insn::stmt(0xff, 0), Insn::stmt(BPF_RET | BPF_K, 0)],
+    ];
+    for insns in invalid {
+        assert_eq!(Filter::new(insns).unwrap_err(), KError::InvalidInput);
+    }
+    assert_eq!(Filter::accept_all().run(&[1, 2, 3]), u32::MAX);
+}
+
+#[def_test]
+fn test_capture_ring() {
+    let capture = Capture::open(CaptureConfig {
+        interface: Some("captest0".to_string()),
+        direction: Some(Direction::Incoming),
+        filter: Some(udp_filter()),
+        snaplen: 40,
+        buffer_size: 100,
+    })
+    .unwrap();
+    capture.set_nonblocking(true);
+
+    tap(
+        "captest0",
+        Direction::Incoming,
+        LinkType::Ethernet,
+        &ipv4_frame(17, 30),
+    );
+    // Filtered out by interface, direction and protocol
+    tap(
+        "captest1",
+        Direction::Incoming,
+        LinkType::Ethernet,
+        &ipv4_frame(17, 30),
+    );
+    tap(
+        "captest0",
+        Direction::Outgoing,
+        LinkType::Ethernet,
+        &ipv4_frame(17, 30),
+    );
+    tap(
+        "captest0",
+        Direction::Incoming,
+        LinkType::Ethernet,
+        &ipv4_frame(6, 30),
+    );
+
+    let packet = capture.recv().unwrap();
+    assert_eq!(packet.interface, "captest0");
+    assert_eq!(packet.orig_len, 64);
+    assert_eq!(packet.data.len(), 40);
+    assert_eq!(packet.pcap_record().len(), 16 + 40);
+    assert_eq!(capture.recv().unwrap_err(), KError::WouldBlock);
+
+    // Two truncated packets fit in the ring, the third is dropped
+    for _ in 0..3 {
+        tap(
+            "captest0",
+            Direction::Incoming,
+            LinkType::Ethernet,
+            &ipv4_frame(17, 30),
+        );
+    }
+    assert_eq!(
+        capture.stats(),
+        CaptureStats {
+            captured: 3,
+            dropped: 1,
+        }
+    );
+    assert!(capture.try_recv().is_some());
+    assert!(capture.try_recv().is_some());
+    assert!(capture.try_recv().is_none());
+
+    assert_eq!(pcap_file_header(LinkType::Ethernet, 40)[20], 1);
+}
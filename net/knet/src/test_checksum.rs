@@ -0,0 +1,51 @@
+//! Unit tests for software checksums.
+
+#![cfg(unittest)]
+
+use unittest::def_test;
+
+use crate::checksum::{fill_checksums, verify_checksums};
+
+/// An IPv4/UDP packet from 10.0.2.15:1234 to 10.0.2.2:53 with zeroed
+/// checksums and a 4-byte payload.
+fn udp_packet() -> [u8; 32] {
+    let mut packet = [0u8; 32];
+    packet[..20].copy_from_slice(&[
+        0x45, 0x00, 0x00, 0x20, // version/IHL, TOS, total length
+        0x00, 0x01, 0x00, 0x00, // identification, flags/fragment offset
+        0x40, 0x11, 0x00, 0x00, // TTL, protocol (UDP), header checksum
+        10, 0, 2, 15, // source
+        10, 0, 2, 2, // destination
+    ]);
+    packet[20..28].copy_from_slice(&[0x04, 0xd2, 0x00, 0x35, 0x00, 0x0c, 0x00, 0x00]);
+    packet[28..].copy_from_slice(b"ping");
+    packet
+}
+
+#[def_test]
+fn test_fill_then_verify() {
+    let mut packet = udp_packet();
+    fill_checksums(&mut packet);
+    assert_ne!(&packet[10..12], &[0, 0]);
+    assert_ne!(&packet[26..28], &[0, 0]);
+    assert!(verify_checksums(&packet));
+
+    // Filling again leaves the packet unchanged
+    let filled = packet;
+    fill_checksums(&mut packet);
+    assert_eq!(packet, filled);
+}
+
+#[def_test]
+fn test_verify_detects_corruption() {
+    let mut packet = udp_packet();
+    fill_checksums(&mut packet);
+
+    let mut bad_payload = packet;
+    bad_payload[31] ^= 0xff;
+    assert!(!verify_checksums(&bad_payload));
+
+    let mut bad_header = packet;
+    bad_header[8] -= 1;
+    assert!(!verify_checksums(&bad_header));
+}
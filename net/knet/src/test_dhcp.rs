@@ -0,0 +1,183 @@
+//! Unit tests for the DHCP client.
+
+#![cfg(unittest)]
+
+use alloc::{vec, vec::Vec};
+use core::{net::Ipv4Addr, time::Duration};
+
+use smoltcp::wire::{EthernetAddress, Ipv4Address, Ipv4Cidr};
+use unittest::def_test;
+
+use crate::{
+    dhcp::{ClientMessage, MessageType, ServerMessage},
+    netns::NetStack,
+};
+
+const MAC: EthernetAddress = EthernetAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+const XID: u32 = 0x1234_5678;
+
+/// Builds a server message of `kind` for [`MAC`] carrying `options`.
+fn reply(kind: MessageType, yiaddr: [u8; 4], options: &[(u8, &[u8])]) -> Vec<u8> {
+    let mut buf = vec![0u8; 240];
+    buf[0] = 2;
+    buf[1] = 1;
+    buf[2] = 6;
+    buf[4..8].copy_from_slice(&XID.to_be_bytes());
+    buf[16..20].copy_from_slice(&yiaddr);
+    buf[28..34].copy_from_slice(&MAC.0);
+    buf[236..240].copy_from_slice(&[99, 130, 83, 99]);
+    buf.extend_from_slice(&[53, 1, kind as u8]);
+    for (code, data) in options {
+        buf.push(*code);
+        buf.push(data.len() as u8);
+        buf.extend_from_slice(data);
+    }
+    buf.push(255);
+    buf
+}
+
+#[def_test]
+fn test_client_message() {
+    let message = ClientMessage {
+        kind: MessageType::Request,
+        xid: XID,
+        mac: MAC,
+        ciaddr: Ipv4Addr::UNSPECIFIED,
+        requested: Some(Ipv4Addr::new(10, 0, 2, 15)),
+        server: Some(Ipv4Addr::new(10, 0, 2, 2)),
+        hostname: "test",
+    };
+    let buf = message.encode();
+    assert_eq!(buf[0], 1);
+    assert_eq!(&buf[4..8], &XID.to_be_bytes());
+    // Broadcast replies are asked for without an address
+    assert_eq!(&buf[10..12], &[0x80, 0]);
+    assert_eq!(&buf[28..34], &MAC.0);
+    assert_eq!(&buf[240..243], &[53, 1, MessageType::Request as u8]);
+    assert!(buf.windows(6).any(|it| it == [50, 4, 10, 0, 2, 15]));
+    assert!(buf.windows(6).any(|it| it == [54, 4, 10, 0, 2, 2]));
+    assert_eq!(buf.last(), Some(&255));
+
+    let renew = ClientMessage {
+        ciaddr: Ipv4Addr::new(10, 0, 2, 15),
+        requested: None,
+        server: None,
+        ..message
+    };
+    let buf = renew.encode();
+    assert_eq!(&buf[10..12], &[0, 0]);
+    assert_eq!(&buf[12..16], &[10, 0, 2, 15]);
+    assert!(!buf[240..].windows(2).any(|it| it == [50, 4]));
+}
+
+#[def_test]
+fn test_server_message_lease() {
+    let buf = reply(
+        MessageType::Ack,
+        [10, 0, 2, 15],
+        &[
+            (0, &[]),
+            (1, &[255, 255, 255, 0]),
+            (3, &[10, 0, 2, 2]),
+            (6, &[10, 0, 2, 3, 8, 8, 8, 8]),
+            (51, &3600u32.to_be_bytes()),
+            (54, &[10, 0, 2, 2]),
+        ],
+    );
+    let message = ServerMessage::parse(&buf, XID, MAC).unwrap();
+    assert_eq!(message.kind, MessageType::Ack);
+    assert_eq!(message.dns.len(), 2);
+
+    let now = Duration::from_secs(100);
+    let lease = message.lease(now).unwrap();
+    assert_eq!(
+        lease.addr,
+        Ipv4Cidr::new(Ipv4Address::new(10, 0, 2, 15), 24)
+    );
+    assert_eq!(lease.router, Some(Ipv4Addr::new(10, 0, 2, 2)));
+    assert_eq!(lease.server, Ipv4Addr::new(10, 0, 2, 2));
+    assert_eq!(lease.renew_at, now + Duration::from_secs(1800));
+    assert_eq!(lease.rebind_at, now + Duration::from_secs(3150));
+    assert_eq!(lease.expires_at, now + Duration::from_secs(3600));
+
+    // A router off the subnet is dropped, an infinite lease never expires
+    let buf = reply(
+        MessageType::Ack,
+        [192, 168, 1, 20],
+        &[
+            (3, &[10, 0, 0, 1]),
+            (51, &u32::MAX.to_be_bytes()),
+            (54, &[192, 168, 1, 1]),
+        ],
+    );
+    let lease = ServerMessage::parse(&buf, XID, MAC)
+        .unwrap()
+        .lease(now)
+        .unwrap();
+    assert_eq!(lease.router, None);
+    assert_eq!(lease.expires_at, Duration::MAX);
+
+    // No lease without a lease time
+    let buf = reply(MessageType::Ack, [10, 0, 2, 15], &[(54, &[10, 0, 2, 2])]);
+    assert!(
+        ServerMessage::parse(&buf, XID, MAC)
+            .unwrap()
+            .lease(now)
+            .is_none()
+    );
+}
+
+#[def_test]
+fn test_server_message_rejected() {
+    let buf = reply(MessageType::Offer, [10, 0, 2, 15], &[(54, &[10, 0, 2, 2])]);
+    assert!(ServerMessage::parse(&buf, XID, MAC).is_some());
+    // Another transaction or client
+    assert!(ServerMessage::parse(&buf, XID + 1, MAC).is_none());
+    assert!(ServerMessage::parse(&buf, XID, EthernetAddress([2; 6])).is_none());
+    // Truncated option
+    let mut truncated = buf.clone();
+    truncated.truncate(truncated.len() - 3);
+    assert!(ServerMessage::parse(&truncated, XID, MAC).is_none());
+    // A request
+    let mut request = buf;
+    request[0] = 1;
+    assert!(ServerMessage::parse(&request, XID, MAC).is_none());
+}
+
+#[def_test]
+fn test_set_ipv4_addr() {
+    let a = NetStack::new();
+    let b = NetStack::new();
+    let (veth_a, _) = a
+        .add_veth_pair(
+            &b,
+            Ipv4Cidr::new(Ipv4Address::new(10, 202, 0, 1), 24),
+            Ipv4Cidr::new(Ipv4Address::new(10, 202, 0, 2), 24),
+        )
+        .unwrap();
+
+    let new = Ipv4Cidr::new(Ipv4Address::new(10, 203, 0, 1), 24);
+    a.set_ipv4_addr(&veth_a, Some(new), Some(Ipv4Address::new(10, 203, 0, 254)))
+        .unwrap();
+    assert_eq!(
+        a.service.lock().ipv4_addrs(),
+        [Ipv4Address::new(10, 203, 0, 1)]
+    );
+    // Everything goes through the gateway
+    assert_eq!(
+        a.service
+            .lock()
+            .get_source_address(&Ipv4Address::new(1, 1, 1, 1).into()),
+        Ipv4Address::new(10, 203, 0, 1).into()
+    );
+
+    a.set_ipv4_addr(&veth_a, None, None).unwrap();
+    assert!(a.service.lock().ipv4_addrs().is_empty());
+
+    // The gateway must be on the subnet
+    assert!(
+        a.set_ipv4_addr(&veth_a, Some(new), Some(Ipv4Address::new(10, 0, 0, 1)))
+            .is_err()
+    );
+    assert!(a.set_ipv4_addr("nope0", Some(new), None).is_err());
+}
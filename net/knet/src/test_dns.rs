@@ -0,0 +1,163 @@
+//! Unit tests for the DNS client and the mDNS responder.
+
+#![cfg(unittest)]
+
+use alloc::{string::ToString, vec, vec::Vec};
+use core::{
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+};
+
+use unittest::def_test;
+
+use crate::{
+    dns::{
+        Cache, FLAG_RESPONSE, Message, RData, Record, TYPE_A, TYPE_PTR, TYPE_SRV, addresses_of,
+        check_name,
+    },
+    mdns::{ServiceInfo, Zone, collect_services, is_local},
+};
+
+fn record(name: &str, ttl: u32, data: RData) -> Record {
+    Record {
+        name: name.to_string(),
+        ttl,
+        flush: false,
+        data,
+    }
+}
+
+fn zone() -> Zone {
+    Zone {
+        hostname: "kylin".to_string(),
+        addrs: vec![Ipv4Addr::new(10, 0, 2, 15)],
+        services: vec![ServiceInfo {
+            instance: "Console".to_string(),
+            service: "_http._tcp".to_string(),
+            port: 8080,
+            txt: vec!["path=/".to_string()],
+        }],
+    }
+}
+
+#[def_test]
+fn test_message_roundtrip() {
+    let mut message = Message::query(0x1234, "example.com", TYPE_A);
+    message.flags = FLAG_RESPONSE;
+    message.answers = vec![
+        record(
+            "example.com",
+            60,
+            RData::Cname("www.example.com".to_string()),
+        ),
+        record(
+            "www.example.com",
+            30,
+            RData::A(Ipv4Addr::new(93, 184, 216, 34)),
+        ),
+    ];
+    message.additionals = vec![record(
+        "_x._tcp.local",
+        10,
+        RData::Srv {
+            priority: 1,
+            weight: 2,
+            port: 3,
+            target: "host.local".to_string(),
+        },
+    )];
+    assert_eq!(Message::parse(&message.encode()).unwrap(), message);
+
+    assert!(check_name("a.b").is_ok());
+    assert!(check_name("a..b").is_err());
+    assert!(check_name(&"a".repeat(64)).is_err());
+    assert!(Message::parse(&[0; 5]).is_err());
+}
+
+#[def_test]
+fn test_compressed_names() {
+    let mut packet = vec![
+        0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0, // header
+        3, b'f', b'o', b'o', 3, b'c', b'o', b'm', 0, 0, 1, 0, 1, // question
+        0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 30, 0, 4, 1, 2, 3, 4, // answer
+    ];
+    let message = Message::parse(&packet).unwrap();
+    assert_eq!(message.questions[0].name, "foo.com");
+    assert_eq!(message.answers[0].name, "foo.com");
+    assert_eq!(
+        addresses_of(&message, "FOO.com"),
+        Some((vec![IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4))], 30))
+    );
+
+    // A pointer to itself must not loop
+    packet[25] = 0xc0;
+    packet[26] = 25;
+    assert!(Message::parse(&packet).is_err());
+}
+
+#[def_test]
+fn test_cache_expiry() {
+    let mut cache = Cache::new();
+    let addrs = vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))];
+    let now = Duration::from_secs(100);
+    cache.insert("Host.example", addrs.clone(), 5, now);
+    cache.insert("uncached.example", addrs.clone(), 0, now);
+
+    assert_eq!(cache.get("host.example", now), Some(addrs.clone()));
+    assert_eq!(cache.get("uncached.example", now), None);
+    assert_eq!(
+        cache.get("host.example", now + Duration::from_secs(5)),
+        None
+    );
+
+    // TTLs are capped
+    cache.insert("long.example", addrs, u32::MAX, now);
+    assert!(
+        cache
+            .get("long.example", now + Duration::from_secs(7200))
+            .is_none()
+    );
+}
+
+#[def_test]
+fn test_responder_answers() {
+    let zone = zone();
+    assert!(
+        zone.answer(&Message::query(0, "other.local", TYPE_A))
+            .is_none()
+    );
+
+    let reply = zone
+        .answer(&Message::query(0, "KYLIN.local", TYPE_A))
+        .unwrap();
+    assert_eq!(reply.answers.len(), 1);
+    assert_eq!(reply.answers[0].data, RData::A(Ipv4Addr::new(10, 0, 2, 15)));
+
+    // Browsing gets the SRV, TXT and A records along with the PTR
+    let reply = zone
+        .answer(&Message::query(0, "_http._tcp.local", TYPE_PTR))
+        .unwrap();
+    assert_eq!(reply.answers.len(), 1);
+    let types: Vec<_> = reply.additionals.iter().map(|it| it.data.rtype()).collect();
+    assert_eq!(types.len(), 3);
+    assert!(types.contains(&TYPE_SRV) && types.contains(&TYPE_A));
+
+    let found = collect_services(&[Message::parse(&reply.encode()).unwrap()], "_http._tcp");
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].info, zone.services[0]);
+    assert_eq!(found[0].host, "kylin.local");
+    assert_eq!(found[0].addrs, [IpAddr::V4(Ipv4Addr::new(10, 0, 2, 15))]);
+
+    // Known answers are not repeated
+    let mut query = Message::query(0, "kylin.local", TYPE_A);
+    query.answers = reply.additionals.clone();
+    assert!(zone.answer(&query).is_none());
+}
+
+#[def_test]
+fn test_local_names() {
+    assert!(is_local("printer.local"));
+    assert!(is_local("printer.LOCAL."));
+    assert!(!is_local(".local"));
+    assert!(!is_local("example.com"));
+}
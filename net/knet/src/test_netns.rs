@@ -0,0 +1,182 @@
+//! Unit tests for network namespaces.
+
+#![cfg(unittest)]
+
+use alloc::borrow::ToOwned;
+use core::net::{Ipv4Addr, SocketAddr};
+
+use kdriver::prelude::{Bridge, IoPolicy, MacAddress};
+use kerrno::KError;
+use smoltcp::wire::{Ipv4Address, Ipv4Cidr};
+use unittest::def_test;
+
+use crate::{
+    RecvOptions, SendOptions, SocketAddrEx, SocketOps,
+    netns::NetStack,
+    options::{Configurable, SetSocketOption},
+    poll_interfaces,
+    udp::UdpSocket,
+};
+
+fn addr(ip: [u8; 4], port: u16) -> SocketAddrEx {
+    SocketAddrEx::Ip(SocketAddr::new(Ipv4Addr::from(ip).into(), port))
+}
+
+fn nonblocking(socket: UdpSocket) -> UdpSocket {
+    socket
+        .set_option(SetSocketOption::NonBlocking(&true))
+        .unwrap();
+    socket
+}
+
+#[def_test]
+fn test_veth_pair_delivery() {
+    let a = NetStack::new();
+    let b = NetStack::new();
+    let (veth_a, veth_b) = a
+        .add_veth_pair(
+            &b,
+            Ipv4Cidr::new(Ipv4Address::new(10, 200, 0, 1), 24),
+            Ipv4Cidr::new(Ipv4Address::new(10, 200, 0, 2), 24),
+        )
+        .unwrap();
+    assert_ne!(veth_a, veth_b);
+
+    let server = nonblocking(UdpSocket::new_in(b.clone()));
+    server.bind(addr([10, 200, 0, 2], 7000)).unwrap();
+    let client = nonblocking(UdpSocket::new_in(a.clone()));
+    let sent = client
+        .send(
+            &b"hello"[..],
+            SendOptions {
+                to: Some(addr([10, 200, 0, 2], 7000)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(sent, 5);
+
+    let mut buf = [0u8; 16];
+    let mut from = addr([0, 0, 0, 0], 0);
+    let len = server
+        .recv(
+            &mut buf[..],
+            RecvOptions {
+                from: Some(&mut from),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(&buf[..len], b"hello");
+    assert_eq!(from.into_ip().unwrap().ip(), Ipv4Addr::new(10, 200, 0, 1));
+}
+
+#[def_test]
+fn test_stacks_are_isolated() {
+    let a = NetStack::new();
+    let b = NetStack::new();
+    assert_ne!(a.id(), b.id());
+
+    // Both stacks may bind the same address
+    let server_a = nonblocking(UdpSocket::new_in(a.clone()));
+    server_a.bind(addr([127, 0, 0, 1], 7001)).unwrap();
+    let server_b = nonblocking(UdpSocket::new_in(b.clone()));
+    server_b.bind(addr([127, 0, 0, 1], 7001)).unwrap();
+
+    let client = nonblocking(UdpSocket::new_in(a));
+    client
+        .send(
+            &b"ping"[..],
+            SendOptions {
+                to: Some(addr([127, 0, 0, 1], 7001)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    let mut buf = [0u8; 16];
+    let mut from = addr([0, 0, 0, 0], 0);
+    let recv = |socket: &UdpSocket, buf: &mut [u8], from: &mut SocketAddrEx| {
+        socket.recv(
+            buf,
+            RecvOptions {
+                from: Some(from),
+                ..Default::default()
+            },
+        )
+    };
+    assert_eq!(recv(&server_a, &mut buf, &mut from).unwrap(), 4);
+    assert!(recv(&server_b, &mut buf, &mut from).is_err());
+}
+
+#[def_test]
+fn test_bridged_stacks() {
+    let bridge = Bridge::new();
+    let a = NetStack::new();
+    let b = NetStack::new();
+    a.add_nic(
+        "eth1".to_owned(),
+        bridge.add_port(MacAddress([0x02, 0, 0, 0, 1, 1])),
+        Ipv4Cidr::new(Ipv4Address::new(10, 201, 0, 1), 24),
+    )
+    .unwrap();
+    b.add_nic(
+        "eth1".to_owned(),
+        bridge.add_port(MacAddress([0x02, 0, 0, 0, 1, 2])),
+        Ipv4Cidr::new(Ipv4Address::new(10, 201, 0, 2), 24),
+    )
+    .unwrap();
+
+    let server = nonblocking(UdpSocket::new_in(b));
+    server.bind(addr([10, 201, 0, 2], 7002)).unwrap();
+    let client = nonblocking(UdpSocket::new_in(a));
+    client
+        .send(
+            &b"bridged"[..],
+            SendOptions {
+                to: Some(addr([10, 201, 0, 2], 7002)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    // Address resolution takes a few rounds
+    let mut buf = [0u8; 16];
+    let mut from = addr([0, 0, 0, 0], 0);
+    let len = (0..16)
+        .find_map(|_| {
+            poll_interfaces();
+            let options = RecvOptions {
+                from: Some(&mut from),
+                ..Default::default()
+            };
+            server.recv(&mut buf[..], options).ok()
+        })
+        .expect("no frame forwarded by the bridge");
+    assert_eq!(&buf[..len], b"bridged");
+    assert_eq!(bridge.port_count(), 2);
+    assert_eq!(bridge.lookup(MacAddress([0x02, 0, 0, 0, 1, 1])), Some(0));
+}
+
+#[def_test]
+fn test_set_io_policy() {
+    let stack = NetStack::new();
+    let bridge = Bridge::new();
+    stack
+        .add_nic(
+            "eth1".to_owned(),
+            bridge.add_port(MacAddress([0x02, 0, 0, 0, 2, 1])),
+            Ipv4Cidr::new(Ipv4Address::new(10, 202, 0, 1), 24),
+        )
+        .unwrap();
+
+    assert!(stack.set_io_policy("eth1", IoPolicy::PATIENT).is_ok());
+    assert_eq!(
+        stack.set_io_policy("lo", IoPolicy::default()),
+        Err(KError::OperationNotSupported)
+    );
+    assert_eq!(
+        stack.set_io_policy("eth9", IoPolicy::default()),
+        Err(KError::NotFound)
+    );
+}
@@ -5,9 +5,14 @@
 extern crate alloc;
 use core::time::Duration;
 
+use kerrno::KError;
 use unittest::def_test;
 
-use crate::options::{GetSocketOption, SetSocketOption, UnixCredentials};
+use crate::{
+    congestion::{CongestionControl, default_congestion_control, set_default_congestion_control},
+    options::{GetSocketOption, SetSocketOption, UnixCredentials},
+    tcp::{new_tcp_socket, set_timestamps_enabled, timestamps_enabled},
+};
 
 #[def_test]
 fn test_unix_credentials_construction() {
@@ -188,3 +193,50 @@ fn test_ttl_boundary_values() {
         _ => panic!("Expected Ttl variant"),
     }
 }
+
+#[def_test]
+fn test_congestion_control_names() {
+    for cc in CongestionControl::ALL {
+        assert_eq!(CongestionControl::from_name(cc.name()).unwrap(), cc);
+        assert_eq!(CongestionControl::from_smol(cc.to_smol()), cc);
+    }
+    assert_eq!(
+        CongestionControl::from_name("cubic").unwrap(),
+        CongestionControl::Cubic
+    );
+    assert_eq!(
+        CongestionControl::from_name("bbr").unwrap(),
+        CongestionControl::Bbr
+    );
+    // Not provided by smoltcp
+    assert_eq!(CongestionControl::from_name("vegas"), Err(KError::NotFound));
+    assert!(CongestionControl::from_name("").is_err());
+
+    let cc = CongestionControl::Reno;
+    match SetSocketOption::Congestion(&cc) {
+        SetSocketOption::Congestion(val) => assert_eq!(*val, CongestionControl::Reno),
+        _ => panic!("Expected Congestion variant"),
+    }
+}
+
+#[def_test]
+fn test_default_congestion_control() {
+    let saved = default_congestion_control();
+    set_default_congestion_control(CongestionControl::Reno);
+    assert_eq!(default_congestion_control(), CongestionControl::Reno);
+    set_default_congestion_control(CongestionControl::Bbr);
+    assert_eq!(default_congestion_control(), CongestionControl::Bbr);
+    set_default_congestion_control(CongestionControl::Cubic);
+    assert_eq!(default_congestion_control(), CongestionControl::Cubic);
+    set_default_congestion_control(saved);
+}
+
+#[def_test]
+fn test_tcp_timestamps_toggle() {
+    let saved = timestamps_enabled();
+    set_timestamps_enabled(false);
+    assert!(!new_tcp_socket(CongestionControl::Reno).timestamp_enabled());
+    set_timestamps_enabled(true);
+    assert!(new_tcp_socket(CongestionControl::Reno).timestamp_enabled());
+    set_timestamps_enabled(saved);
+}
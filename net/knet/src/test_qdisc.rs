@@ -0,0 +1,152 @@
+//! Unit tests for queuing disciplines.
+
+#![cfg(unittest)]
+
+use alloc::{string::ToString, vec, vec::Vec};
+
+use smoltcp::{
+    time::Instant,
+    wire::{IpAddress, Ipv4Address},
+};
+use unittest::def_test;
+
+use crate::qdisc::{Qdisc, QdiscConfig, Scheduling, classify};
+
+const ICMP: u8 = 1;
+const TCP: u8 = 6;
+const UDP: u8 = 17;
+
+/// Builds an IPv4 packet carrying `payload`.
+fn ipv4(tos: u8, protocol: u8, payload: &[u8]) -> Vec<u8> {
+    let len = 20 + payload.len();
+    let mut packet = vec![0u8; len];
+    packet[0] = 0x45;
+    packet[1] = tos;
+    packet[2..4].copy_from_slice(&(len as u16).to_be_bytes());
+    packet[8] = 64;
+    packet[9] = protocol;
+    packet[12..16].copy_from_slice(&[10, 0, 0, 1]);
+    packet[16..20].copy_from_slice(&[10, 0, 0, 2]);
+    packet[20..].copy_from_slice(payload);
+    packet
+}
+
+/// Builds a TCP segment with `data_len` bytes of payload.
+fn tcp(data_len: usize) -> Vec<u8> {
+    let mut segment = vec![0u8; 20 + data_len];
+    segment[12] = 5 << 4;
+    segment[13] = 0x10;
+    segment
+}
+
+fn hop() -> IpAddress {
+    Ipv4Address::new(10, 0, 0, 2).into()
+}
+
+/// Returns the length of each packet released at `now`.
+fn drain(qdisc: &mut Qdisc, now: Instant) -> Vec<usize> {
+    let mut lens = Vec::new();
+    while let Some((_, packet)) = qdisc.dequeue(now) {
+        lens.push(packet.len());
+    }
+    lens
+}
+
+#[def_test]
+fn test_config_roundtrip() {
+    let config = QdiscConfig::parse("rate=125000 burst=3000 sched=wrr:4,2,1").unwrap();
+    assert_eq!(config.rate, 125000);
+    assert_eq!(config.burst, 3000);
+    assert_eq!(config.sched, Scheduling::Weighted([4, 2, 1]));
+    assert_eq!(QdiscConfig::parse(&config.to_string()).unwrap(), config);
+
+    assert_eq!(QdiscConfig::parse("").unwrap(), QdiscConfig::default());
+    assert!(QdiscConfig::parse("rate=fast").is_err());
+    assert!(QdiscConfig::parse("burst=0").is_err());
+    assert!(QdiscConfig::parse("sched=wrr:1,0,1").is_err());
+    assert!(QdiscConfig::parse("sched=wrr:1,1").is_err());
+    assert!(QdiscConfig::parse("color=red").is_err());
+}
+
+#[def_test]
+fn test_classify() {
+    // DSCP EF and IPTOS_LOWDELAY
+    assert_eq!(classify(&ipv4(46 << 2, UDP, &[0; 8])), 0);
+    assert_eq!(classify(&ipv4(0x10, UDP, &[0; 8])), 0);
+    // DSCP CS1 and IPTOS_THROUGHPUT
+    assert_eq!(classify(&ipv4(8 << 2, TCP, &tcp(0))), 2);
+    assert_eq!(classify(&ipv4(0x08, UDP, &[0; 8])), 2);
+
+    assert_eq!(classify(&ipv4(0, ICMP, &[8, 0, 0, 0, 0, 0, 0, 0])), 0);
+    // Pure ACKs are urgent, data is not
+    assert_eq!(classify(&ipv4(0, TCP, &tcp(0))), 0);
+    assert_eq!(classify(&ipv4(0, TCP, &tcp(100))), 1);
+    assert_eq!(classify(&ipv4(0, UDP, &[0; 8])), 1);
+}
+
+#[def_test]
+fn test_strict_priority_under_shaping() {
+    let mut qdisc = Qdisc::new(QdiscConfig {
+        rate: 1000,
+        burst: 1000,
+        sched: Scheduling::Strict,
+    });
+    let bulk = ipv4(0, TCP, &tcp(500));
+    let ack = ipv4(0, TCP, &tcp(0));
+    let t0 = Instant::from_millis(0);
+
+    assert!(qdisc.enqueue(hop(), &bulk));
+    assert!(qdisc.enqueue(hop(), &bulk));
+    assert!(qdisc.enqueue(hop(), &bulk));
+    // Only the burst goes out at once
+    assert_eq!(drain(&mut qdisc, t0), [bulk.len()]);
+
+    // A later ACK overtakes the queued bulk data
+    assert!(qdisc.enqueue(hop(), &ack));
+    assert_eq!(drain(&mut qdisc, t0), [ack.len()]);
+
+    // The rest waits for the bucket to refill
+    let next = qdisc.poll_at(t0).unwrap();
+    assert!(next > t0);
+    assert!(drain(&mut qdisc, Instant::from_millis(100)).is_empty());
+    assert_eq!(drain(&mut qdisc, next), [bulk.len()]);
+    assert_eq!(drain(&mut qdisc, Instant::from_millis(2000)), [bulk.len()]);
+    assert_eq!(qdisc.poll_at(Instant::from_millis(2000)), None);
+}
+
+#[def_test]
+fn test_weighted_shares() {
+    let mut qdisc = Qdisc::new(QdiscConfig {
+        rate: 0,
+        sched: Scheduling::Weighted([1, 2, 1]),
+        ..Default::default()
+    });
+    let urgent = ipv4(0x10, UDP, &[0; 1000]);
+    let normal = ipv4(0, UDP, &[0; 1000]);
+    for _ in 0..8 {
+        assert!(qdisc.enqueue(hop(), &urgent));
+        assert!(qdisc.enqueue(hop(), &normal));
+    }
+
+    // Band 1 gets twice the bytes of band 0 while both are backlogged
+    let order: Vec<_> = (0..6)
+        .map(|_| qdisc.dequeue(Instant::from_millis(0)).unwrap().1[1])
+        .collect();
+    assert_eq!(order.iter().filter(|tos| **tos == 0x10).count(), 2);
+    assert_eq!(order.iter().filter(|tos| **tos == 0).count(), 4);
+}
+
+#[def_test]
+fn test_tail_drop() {
+    let mut qdisc = Qdisc::new(QdiscConfig {
+        rate: 1,
+        burst: 1,
+        sched: Scheduling::Fifo,
+    });
+    let packet = ipv4(0, UDP, &[0; 8]);
+    let queued = (0..1000)
+        .take_while(|_| qdisc.enqueue(hop(), &packet))
+        .count();
+    assert!(queued > 0 && queued < 1000);
+    assert_eq!(qdisc.drops, 1);
+}
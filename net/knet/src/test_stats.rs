@@ -0,0 +1,145 @@
+//! Unit tests for protocol counters and socket listing.
+
+#![cfg(unittest)]
+
+use alloc::{vec, vec::Vec};
+use core::net::{Ipv4Addr, SocketAddr};
+
+use smoltcp::wire::{IpEndpoint, Ipv4Address, TcpPacket};
+use unittest::def_test;
+
+use crate::{
+    RecvOptions, SendOptions, SocketAddrEx, SocketOps,
+    netns::NetStack,
+    options::{Configurable, SetSocketOption},
+    stats::{ProtoStats, Protocol, TcpFlows},
+    udp::UdpSocket,
+};
+
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const ACK: u8 = 0x10;
+
+/// An IPv4/TCP packet from 10.0.2.15:40000 to 10.0.2.2:80 carrying `len`
+/// bytes of payload.
+fn tcp_packet(seq: u32, flags: u8, len: usize) -> Vec<u8> {
+    let total = 40 + len;
+    let mut packet = vec![0u8; total];
+    packet[..20].copy_from_slice(&[
+        0x45, 0x00, 0x00, 0x00, // version/IHL, TOS, total length
+        0x00, 0x01, 0x00, 0x00, // identification, flags/fragment offset
+        0x40, 0x06, 0x00, 0x00, // TTL, protocol (TCP), header checksum
+        10, 0, 2, 15, // source
+        10, 0, 2, 2, // destination
+    ]);
+    packet[2..4].copy_from_slice(&(total as u16).to_be_bytes());
+    let mut tcp = TcpPacket::new_unchecked(&mut packet[20..]);
+    tcp.set_src_port(40000);
+    tcp.set_dst_port(80);
+    tcp.set_seq_number(smoltcp::wire::TcpSeqNumber(seq as i32));
+    tcp.set_header_len(20);
+    packet[33] = flags;
+    packet
+}
+
+fn endpoints() -> (IpEndpoint, IpEndpoint) {
+    (
+        IpEndpoint::new(Ipv4Address::new(10, 0, 2, 15).into(), 40000),
+        IpEndpoint::new(Ipv4Address::new(10, 0, 2, 2).into(), 80),
+    )
+}
+
+#[def_test]
+fn test_retransmit_detection() {
+    let mut flows = TcpFlows::default();
+    let (local, remote) = endpoints();
+
+    assert!(!flows.outgoing(&tcp_packet(1000, SYN, 0)));
+    // A SYN retransmitted before the handshake completes
+    assert!(flows.outgoing(&tcp_packet(1000, SYN, 0)));
+    assert!(!flows.outgoing(&tcp_packet(1001, ACK, 100)));
+    // Pure ACKs and resets are never retransmissions
+    assert!(!flows.outgoing(&tcp_packet(1101, ACK, 0)));
+    assert!(!flows.outgoing(&tcp_packet(1001, RST, 0)));
+    // The same data again, then data partially sent before
+    assert!(flows.outgoing(&tcp_packet(1001, ACK, 100)));
+    assert!(!flows.outgoing(&tcp_packet(1051, ACK, 100)));
+    assert!(flows.outgoing(&tcp_packet(1101, ACK, 50)));
+    assert_eq!(flows.retransmits(local, remote), 3);
+    assert_eq!(flows.retransmits(remote, local), 0);
+
+    // A new connection on the same endpoints starts over
+    assert!(!flows.outgoing(&tcp_packet(5000, SYN, 0)));
+    assert_eq!(flows.retransmits(local, remote), 0);
+
+    // Sequence numbers wrap around
+    assert!(!flows.outgoing(&tcp_packet(u32::MAX - 10, SYN, 0)));
+    assert!(!flows.outgoing(&tcp_packet(u32::MAX - 9, ACK, 100)));
+    assert!(flows.outgoing(&tcp_packet(u32::MAX - 9, ACK, 20)));
+}
+
+#[def_test]
+fn test_outgoing_counters() {
+    let stats = ProtoStats::default();
+    stats.outgoing(&tcp_packet(1, SYN, 0));
+    stats.outgoing(&tcp_packet(2, RST, 0));
+    stats.bad_checksum(&tcp_packet(3, ACK, 10));
+    assert_eq!(stats.ip.out_requests.get(), 2);
+    assert_eq!(stats.tcp.out_segs.get(), 2);
+    assert_eq!(stats.tcp.out_rsts.get(), 1);
+    assert_eq!(stats.tcp.in_errs.get(), 1);
+    assert_eq!(stats.ip.in_receives.get(), 1);
+}
+
+#[def_test]
+fn test_udp_counters_and_sockets() {
+    let stack = NetStack::new();
+    let addr = |port| SocketAddrEx::Ip(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port));
+    let server = UdpSocket::new_in(stack.clone());
+    server
+        .set_option(SetSocketOption::NonBlocking(&true))
+        .unwrap();
+    server.bind(addr(7100)).unwrap();
+    let client = UdpSocket::new_in(stack.clone());
+    client.bind(addr(7101)).unwrap();
+
+    client
+        .send(
+            &b"ping"[..],
+            SendOptions {
+                to: Some(addr(7100)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let mut buf = [0u8; 8];
+    let len = server.recv(&mut buf[..], RecvOptions::default()).unwrap();
+    assert_eq!(&buf[..len], b"ping");
+
+    let stats = stack.stats();
+    assert_eq!(stats.udp.out_datagrams.get(), 1);
+    assert_eq!(stats.udp.in_datagrams.get(), 1);
+    assert!(stats.ip.in_receives.get() >= 1);
+
+    let mut ports: Vec<_> = stack
+        .sockets()
+        .iter()
+        .filter(|it| it.protocol == Protocol::Udp)
+        .map(|it| it.local.port())
+        .collect();
+    ports.sort();
+    assert_eq!(ports, [7100, 7101]);
+
+    // A datagram to a closed port is answered with port unreachable
+    client
+        .send(
+            &b"ping"[..],
+            SendOptions {
+                to: Some(addr(7102)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    crate::poll_interfaces();
+    assert_eq!(stats.udp.no_ports.get(), 1);
+}
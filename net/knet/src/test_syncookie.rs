@@ -0,0 +1,78 @@
+//! Unit tests for SYN cookies.
+
+#![cfg(unittest)]
+
+use core::time::Duration;
+
+use smoltcp::wire::{IpEndpoint, Ipv4Address, TcpPacket};
+use unittest::def_test;
+
+use crate::syncookie::{CookieJar, shift_seq, unshift_ack};
+
+fn endpoints() -> (IpEndpoint, IpEndpoint) {
+    (
+        IpEndpoint::new(Ipv4Address::new(10, 0, 2, 15).into(), 80),
+        IpEndpoint::new(Ipv4Address::new(10, 0, 2, 2).into(), 40000),
+    )
+}
+
+#[def_test]
+fn test_cookie_roundtrip() {
+    let jar = CookieJar::new(0x0123_4567_89ab_cdef, 0xfedc_ba98_7654_3210);
+    let (local, remote) = endpoints();
+    let now = Duration::from_secs(1000);
+
+    let cookie = jar.generate(local, remote, 0xdead_beef, 1460, now);
+    assert_eq!(
+        jar.check(local, remote, 0xdead_beef, cookie, now),
+        Some(1460)
+    );
+    // The MSS is rounded down to the table
+    let cookie = jar.generate(local, remote, 7, 1400, now);
+    assert_eq!(
+        jar.check(local, remote, 7, cookie, now + Duration::from_secs(64)),
+        Some(1300)
+    );
+}
+
+#[def_test]
+fn test_cookie_rejects_forgery() {
+    let jar = CookieJar::new(1, 2);
+    let (local, remote) = endpoints();
+    let now = Duration::from_secs(1000);
+    let cookie = jar.generate(local, remote, 42, 536, now);
+
+    assert_eq!(jar.check(local, remote, 43, cookie, now), None);
+    assert_eq!(jar.check(remote, local, 42, cookie, now), None);
+    assert_eq!(jar.check(local, remote, 42, cookie ^ 1, now), None);
+    assert_eq!(
+        jar.check(local, remote, 42, cookie, now + Duration::from_secs(64 * 4)),
+        None
+    );
+}
+
+#[def_test]
+fn test_sequence_translation() {
+    // 20-byte header followed by NOP, NOP, SACK with one block
+    let mut segment = [0u8; 32];
+    {
+        let mut tcp = TcpPacket::new_unchecked(&mut segment[..]);
+        tcp.set_header_len(32);
+    }
+    segment[4..8].copy_from_slice(&100u32.to_be_bytes());
+    segment[8..12].copy_from_slice(&1000u32.to_be_bytes());
+    segment[20..24].copy_from_slice(&[1, 1, 5, 10]);
+    segment[24..28].copy_from_slice(&1200u32.to_be_bytes());
+    segment[28..32].copy_from_slice(&1300u32.to_be_bytes());
+
+    shift_seq(&mut segment, 5);
+    unshift_ack(&mut segment, 900);
+    assert_eq!(&segment[4..8], &105u32.to_be_bytes());
+    assert_eq!(&segment[8..12], &100u32.to_be_bytes());
+    assert_eq!(&segment[24..28], &300u32.to_be_bytes());
+    assert_eq!(&segment[28..32], &400u32.to_be_bytes());
+
+    // Translation wraps around the sequence space
+    unshift_ack(&mut segment, 101);
+    assert_eq!(&segment[8..12], &u32::MAX.to_be_bytes());
+}
@@ -0,0 +1,78 @@
+//! Unit tests for vsock listen backlogs and telemetry frames.
+
+#![cfg(unittest)]
+
+use unittest::def_test;
+
+use crate::vsock::{
+    VsockAddr, VsockConnId,
+    connection_manager::AcceptQueue,
+    telemetry::{FRAME_HEADER_LEN, FrameBuf, FrameHeader, FrameKind, MAX_PAYLOAD_LEN},
+};
+
+fn conn_id(port: u32) -> VsockConnId {
+    VsockConnId {
+        peer_addr: VsockAddr { cid: 2, port },
+        local_port: 1024,
+    }
+}
+
+/// Returns how many connections fit into a queue created with `backlog`.
+fn capacity(backlog: usize) -> u32 {
+    let mut queue = AcceptQueue::new(backlog);
+    let mut count = 0;
+    while queue.push(conn_id(count)).is_ok() {
+        count += 1;
+    }
+    count
+}
+
+#[def_test]
+fn test_accept_queue_backlog() {
+    assert_eq!(capacity(3), 3);
+    // Clamped to at least one and at most the queue size
+    assert_eq!(capacity(0), 1);
+    assert_eq!(capacity(usize::MAX), 128);
+
+    let mut queue = AcceptQueue::new(2);
+    queue.push(conn_id(1)).unwrap();
+    queue.push(conn_id(2)).unwrap();
+    assert!(queue.push(conn_id(3)).is_err());
+    assert_eq!(queue.pop(), Some(conn_id(1)));
+    queue.push(conn_id(3)).unwrap();
+    assert_eq!(queue.pop(), Some(conn_id(2)));
+    assert_eq!(queue.pop(), Some(conn_id(3)));
+    assert!(queue.is_empty());
+}
+
+#[def_test]
+fn test_telemetry_frame() {
+    let mut frame = FrameBuf::new();
+    frame.build(
+        FrameKind::Panic,
+        3,
+        42,
+        format_args!("panicked at {}", "main.rs"),
+    );
+    let bytes = frame.as_bytes();
+    assert_eq!(&bytes[..4], b"XKTM");
+    assert_eq!(
+        FrameHeader::decode(bytes),
+        Some(FrameHeader {
+            kind: FrameKind::Panic,
+            cpu: 3,
+            len: 20,
+            timestamp_ns: 42,
+        })
+    );
+    assert_eq!(&bytes[FRAME_HEADER_LEN..], b"panicked at main.rs");
+    assert_eq!(FrameHeader::decode(&bytes[..FRAME_HEADER_LEN - 1]), None);
+    assert_eq!(FrameHeader::decode(&[0; FRAME_HEADER_LEN]), None);
+
+    // Long reports are cut at a character boundary
+    let text = "é".repeat(MAX_PAYLOAD_LEN);
+    frame.build(FrameKind::SoftLockup, 0, 0, format_args!("x{text}"));
+    let header = FrameHeader::decode(frame.as_bytes()).unwrap();
+    assert_eq!(header.len as usize, MAX_PAYLOAD_LEN - 1);
+    assert!(core::str::from_utf8(&frame.as_bytes()[FRAME_HEADER_LEN..]).is_ok());
+}
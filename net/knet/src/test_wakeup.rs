@@ -0,0 +1,78 @@
+//! Unit tests for waking blocked sockets.
+
+#![cfg(unittest)]
+
+use alloc::{sync::Arc, task::Wake};
+use core::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Waker},
+};
+
+use kpoll::{IoEvents, Pollable};
+use unittest::def_test;
+
+use crate::{
+    RecvOptions, SendOptions, SocketAddrEx, SocketOps,
+    netns::NetStack,
+    options::{Configurable, SetSocketOption},
+    poll_interfaces,
+    udp::UdpSocket,
+};
+
+#[derive(Default)]
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn addr(port: u16) -> SocketAddrEx {
+    SocketAddrEx::Ip(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port))
+}
+
+fn bound(stack: &Arc<NetStack>, port: u16) -> UdpSocket {
+    let socket = UdpSocket::new_in(stack.clone());
+    socket
+        .set_option(SetSocketOption::NonBlocking(&true))
+        .unwrap();
+    socket.bind(addr(port)).unwrap();
+    socket
+}
+
+/// Registers a waker for `socket` becoming readable, like a blocked task.
+fn wait(socket: &UdpSocket) -> Arc<CountingWaker> {
+    let counter = Arc::new(CountingWaker::default());
+    let waker = Waker::from(counter.clone());
+    socket.register(&mut Context::from_waker(&waker), IoEvents::IN);
+    counter
+}
+
+#[def_test]
+fn test_wake_only_receiving_socket() {
+    let stack = NetStack::new();
+    let receiver = bound(&stack, 7300);
+    let idle = bound(&stack, 7301);
+    let sender = bound(&stack, 7302);
+
+    let woken = wait(&receiver);
+    let not_woken = wait(&idle);
+    sender
+        .send(
+            &b"wake"[..],
+            SendOptions {
+                to: Some(addr(7300)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    poll_interfaces();
+    assert!(woken.0.load(Ordering::Relaxed) > 0);
+    assert_eq!(not_woken.0.load(Ordering::Relaxed), 0);
+
+    let mut buf = [0u8; 8];
+    let len = receiver.recv(&mut buf[..], RecvOptions::default()).unwrap();
+    assert_eq!(&buf[..len], b"wake");
+}
@@ -3,7 +3,7 @@
 // See LICENSES for license details.
 
 //! UDP socket implementation.
-use alloc::vec;
+use alloc::{sync::Arc, vec};
 use core::{
     net::{IpAddr, Ipv4Addr, SocketAddr},
     task::Context,
@@ -22,23 +22,28 @@ use smoltcp::{
 };
 
 use crate::{
-    RecvFlags, RecvOptions, SERVICE, SOCKET_SET, SendOptions, Shutdown, SocketAddrEx, SocketOps,
+    RecvFlags, RecvOptions, SendOptions, Shutdown, SocketAddrEx, SocketOps,
     consts::{UDP_RX_BUF_LEN, UDP_TX_BUF_LEN},
     general::GeneralOptions,
+    netns::{NetStack, current_stack},
     options::{Configurable, GetSocketOption, SetSocketOption},
     poll_interfaces,
 };
 
 pub(crate) fn new_udp_socket() -> smol::Socket<'static> {
     // TODO(mivik): buffer size
-    smol::Socket::new(
-        smol::PacketBuffer::new(vec![PacketMetadata::EMPTY; 256], vec![0; UDP_RX_BUF_LEN]),
-        smol::PacketBuffer::new(vec![PacketMetadata::EMPTY; 256], vec![0; UDP_TX_BUF_LEN]),
+    kalloc::heap_tagged!(
+        NetBuf,
+        smol::Socket::new(
+            smol::PacketBuffer::new(vec![PacketMetadata::EMPTY; 256], vec![0; UDP_RX_BUF_LEN]),
+            smol::PacketBuffer::new(vec![PacketMetadata::EMPTY; 256], vec![0; UDP_TX_BUF_LEN]),
+        )
     )
 }
 
 /// A UDP socket that provides POSIX-like APIs.
 pub struct UdpSocket {
+    stack: Arc<NetStack>,
     dispatch_irq: SocketHandle,
     local_addr: RwLock<Option<IpEndpoint>>,
     peer_addr: RwLock<Option<(IpEndpoint, IpAddress)>>,
@@ -47,13 +52,20 @@ pub struct UdpSocket {
 }
 
 impl UdpSocket {
-    /// Creates a new UDP socket.
+    /// Creates a new UDP socket in the network namespace of the current
+    /// process.
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
+        Self::new_in(current_stack())
+    }
+
+    /// Creates a new UDP socket in `stack`.
+    pub fn new_in(stack: Arc<NetStack>) -> Self {
         let socket = new_udp_socket();
-        let dispatch_irq = SOCKET_SET.add(socket);
+        let dispatch_irq = stack.sockets.add(socket);
 
         Self {
+            stack,
             dispatch_irq,
             local_addr: RwLock::new(None),
             peer_addr: RwLock::new(None),
@@ -63,7 +75,9 @@ impl UdpSocket {
     }
 
     fn with_smol_socket<R>(&self, f: impl FnOnce(&mut smol::Socket) -> R) -> R {
-        SOCKET_SET.with_socket_mut::<smol::Socket, _, _>(self.dispatch_irq, f)
+        self.stack
+            .sockets
+            .with_socket_mut::<smol::Socket, _, _>(self.dispatch_irq, f)
     }
 
     fn remote_endpoint(&self) -> KResult<(IpEndpoint, IpAddress)> {
@@ -135,7 +149,9 @@ impl SocketOps for UdpSocket {
 
         if !self.general.reuse_address() {
             // Check if the address is already in use
-            SOCKET_SET.bind_check(local_endpoint.addr, local_endpoint.port)?;
+            self.stack
+                .sockets
+                .bind_check(local_endpoint.addr, local_endpoint.port)?;
         }
 
         self.with_smol_socket(|socket| {
@@ -144,8 +160,6 @@ impl SocketOps for UdpSocket {
                 smol::BindError::Unaddressable => k_err_type!(ConnectionRefused, "unaddressable"),
             })
         })?;
-        self.general
-            .set_device_mask(SERVICE.lock().device_mask_for(&endpoint));
 
         *guard = Some(local_endpoint);
         info!("UDP socket {}: bound on {}", self.dispatch_irq, endpoint);
@@ -163,7 +177,11 @@ impl SocketOps for UdpSocket {
         }
 
         let remote_addr = IpEndpoint::from(remote_addr);
-        let src = SERVICE.lock().get_source_address(&remote_addr.addr);
+        let src = self
+            .stack
+            .service
+            .lock()
+            .get_source_address(&remote_addr.addr);
         *guard = Some((remote_addr, src));
         debug!(
             "UDP socket {}: connected to {}",
@@ -176,7 +194,7 @@ impl SocketOps for UdpSocket {
         let (remote_addr, source_addr) = match options.to {
             Some(addr) => {
                 let addr = IpEndpoint::from(addr.into_ip()?);
-                let src = SERVICE.lock().get_source_address(&addr.addr);
+                let src = self.stack.service.lock().get_source_address(&addr.addr);
                 (addr, src)
             }
             None => self.remote_endpoint()?,
@@ -334,7 +352,11 @@ impl Pollable for UdpSocket {
 
     fn register(&self, context: &mut Context<'_>, events: IoEvents) {
         if events.intersects(IoEvents::IN | IoEvents::OUT) {
-            self.general.register_rx_waker(context.waker());
+            let waker = self.general.register_waker(context.waker());
+            self.with_smol_socket(|socket| {
+                socket.register_recv_waker(&waker);
+                socket.register_send_waker(&waker);
+            });
         }
     }
 }
@@ -342,7 +364,7 @@ impl Pollable for UdpSocket {
 impl Drop for UdpSocket {
     fn drop(&mut self) {
         self.shutdown(Shutdown::Both).ok();
-        SOCKET_SET.remove(self.dispatch_irq);
+        self.stack.sockets.remove(self.dispatch_irq);
     }
 }
 
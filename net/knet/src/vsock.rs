@@ -3,21 +3,21 @@
 // See LICENSES for license details.
 
 //! Vsock socket support.
-// pub(crate) mod dgram; todo
-
 pub(crate) mod connection_manager;
+pub(crate) mod dgram;
 pub(crate) mod stream;
+pub mod telemetry;
 
 use alloc::boxed::Box;
 use core::task::Context;
 
 use enum_dispatch::enum_dispatch;
-pub use kdriver::prelude::{VsockAddr, VsockConnId};
+pub use kdriver::prelude::{VsockAddr, VsockConnId, VsockSocketType};
 use kerrno::{KError, KResult};
 use kio::{IoBuf, IoBufMut, Read, Write};
 use kpoll::{IoEvents, Pollable};
 
-pub use self::stream::VsockStreamTransport;
+pub use self::{dgram::VsockDgramTransport, stream::VsockStreamTransport};
 use crate::{
     RecvOptions, SendOptions, Shutdown, Socket, SocketAddrEx, SocketOps,
     options::{Configurable, GetSocketOption, SetSocketOption},
@@ -27,7 +27,7 @@ use crate::{
 #[enum_dispatch]
 pub trait VsockTransportOps: Configurable + Pollable + Send + Sync {
     fn bind(&self, local_addr: VsockAddr) -> KResult;
-    fn listen(&self) -> KResult;
+    fn listen(&self, backlog: usize) -> KResult;
     fn connect(&self, peer_addr: VsockAddr) -> KResult;
     fn accept(&self) -> KResult<(VsockTransport, VsockAddr)>;
     fn send(&self, src: impl Read + IoBuf, options: SendOptions) -> KResult<usize>;
@@ -40,26 +40,30 @@ pub trait VsockTransportOps: Configurable + Pollable + Send + Sync {
 #[enum_dispatch(Configurable, VsockTransportOps)]
 pub enum VsockTransport {
     Stream(VsockStreamTransport),
-    // Dgram(VsockDgramVsockTransport),
+    Dgram(VsockDgramTransport),
 }
 
 impl Pollable for VsockTransport {
     fn poll(&self) -> IoEvents {
         match self {
             VsockTransport::Stream(stream) => stream.poll(),
-            // VsockTransport::Dgram(dgram) => dgram.poll(),
+            VsockTransport::Dgram(dgram) => dgram.poll(),
         }
     }
 
     fn register(&self, context: &mut core::task::Context<'_>, events: IoEvents) {
         match self {
             VsockTransport::Stream(stream) => stream.register(context, events),
-            // VsockTransport::Dgram(dgram) => dgram.register(context, events),
+            VsockTransport::Dgram(dgram) => dgram.register(context, events),
         }
     }
 }
 
-/// A network socket using the vsock protocol.
+/// Returns whether the vsock device supports sockets of type `ty`.
+pub fn supports(ty: VsockSocketType) -> bool {
+    crate::device::vsock_supports(ty)
+}
+
 /// A network socket using the vsock protocol.
 pub struct VsockSocket {
     transport: VsockTransport,
@@ -95,8 +99,8 @@ impl SocketOps for VsockSocket {
         self.transport.connect(remote_addr)
     }
 
-    fn listen(&self) -> KResult {
-        self.transport.listen()
+    fn listen(&self, backlog: usize) -> KResult {
+        self.transport.listen(backlog)
     }
 
     fn accept(&self) -> KResult<Socket> {
@@ -3,7 +3,11 @@
 // See LICENSES for license details.
 
 //! Vsock connection manager.
-use alloc::{collections::BTreeMap, sync::Arc};
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
 
 use kerrno::{KError, KResult, k_bail};
 use kpoll::PollSet;
@@ -13,7 +17,8 @@ use ringbuf::{HeapCons, HeapProd, HeapRb, traits::*};
 use super::{VsockAddr, VsockConnId};
 
 pub const VSOCK_RX_BUFFER_SIZE: usize = 64 * 1024; // 64KB receive buffer
-const VSOCK_ACCEPT_QUEUE_SIZE: usize = 128; // accept queue size
+const VSOCK_ACCEPT_QUEUE_SIZE: usize = 128; // largest accept queue
+const VSOCK_DGRAM_QUEUE_SIZE: usize = 64; // datagrams held per socket
 
 /// connection states
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -34,11 +39,17 @@ pub struct Connection {
     /// recv buffer read from driver
     rx_producer: HeapProd<u8>,
     rx_consumer: HeapCons<u8>,
+    /// seqpacket: `rx_bytes` at the end of each message in the recv buffer
+    msg_ends: VecDeque<usize>,
 
     /// Waker lists
     rx_wakers: PollSet,
+    tx_wakers: PollSet,
     connect_wakers: PollSet,
 
+    /// a send ran out of peer credit
+    tx_blocked: bool,
+
     /// closed flags
     rx_closed: bool,
     tx_closed: bool,
@@ -59,8 +70,11 @@ impl Connection {
             peer_addr,
             rx_producer,
             rx_consumer,
+            msg_ends: VecDeque::new(),
             rx_wakers: PollSet::new(),
+            tx_wakers: PollSet::new(),
             connect_wakers: PollSet::new(),
+            tx_blocked: false,
             rx_closed: false,
             tx_closed: false,
             rx_bytes: 0,
@@ -85,6 +99,11 @@ impl Connection {
         self.rx_wakers.register(context.waker());
     }
 
+    /// Register a waker for peer credit
+    pub fn register_tx_poll(&mut self, context: &mut core::task::Context<'_>) {
+        self.tx_wakers.register(context.waker());
+    }
+
     /// Register a waker for connect Events
     pub fn register_connect_poll(&mut self, _context: &mut core::task::Context<'_>) {
         self.connect_wakers.register(_context.waker());
@@ -132,6 +151,26 @@ impl Connection {
         }
     }
 
+    /// Marks the end of a message, for seqpacket connections.
+    pub fn end_rx_message(&mut self) {
+        self.msg_ends.push_back(self.rx_bytes);
+    }
+
+    /// Get the length of the oldest complete message
+    #[inline]
+    pub fn rx_message_len(&self) -> Option<usize> {
+        let read = self.rx_bytes - self.rx_buffer_used();
+        self.msg_ends.front().map(|end| end - read)
+    }
+
+    /// Discard the oldest complete message
+    pub fn pop_rx_message(&mut self) {
+        if let Some(len) = self.rx_message_len() {
+            self.advance_rx_read(len);
+            self.msg_ends.pop_front();
+        }
+    }
+
     #[inline]
     pub fn rx_slices(&self) -> (&[u8], &[u8]) {
         self.rx_consumer.as_slices()
@@ -154,6 +193,17 @@ impl Connection {
         self.rx_wakers.wake();
     }
 
+    /// Whether sending waits for the peer to grant credit
+    #[inline]
+    pub fn tx_blocked(&self) -> bool {
+        self.tx_blocked
+    }
+
+    #[inline]
+    pub fn set_tx_blocked(&mut self, blocked: bool) {
+        self.tx_blocked = blocked;
+    }
+
     #[inline]
     pub fn wake_connect(&mut self) {
         self.connect_wakers.wake();
@@ -207,8 +257,11 @@ pub struct AcceptQueue {
 }
 
 impl AcceptQueue {
-    pub fn new() -> Self {
-        let rb = HeapRb::<VsockConnId>::new(VSOCK_ACCEPT_QUEUE_SIZE);
+    /// Creates a queue holding up to `backlog` connections, clamped like
+    /// `listen(2)` does.
+    pub fn new(backlog: usize) -> Self {
+        let capacity = backlog.clamp(1, VSOCK_ACCEPT_QUEUE_SIZE);
+        let rb = HeapRb::<VsockConnId>::new(capacity);
         let (producer, consumer) = rb.split();
         Self { producer, consumer }
     }
@@ -237,9 +290,9 @@ pub struct ListenQueue {
 }
 
 impl ListenQueue {
-    pub fn new(local_addr: VsockAddr) -> Self {
+    pub fn new(local_addr: VsockAddr, backlog: usize) -> Self {
         Self {
-            accept_queue: AcceptQueue::new(),
+            accept_queue: AcceptQueue::new(backlog),
             wakers: PollSet::new(),
             local_addr,
         }
@@ -254,10 +307,44 @@ impl ListenQueue {
     }
 }
 
+/// Datagrams received on a bound datagram port
+pub struct DgramQueue {
+    datagrams: VecDeque<(VsockAddr, Vec<u8>)>,
+    wakers: PollSet,
+    dropped: usize,
+}
+
+impl DgramQueue {
+    fn new() -> Self {
+        Self {
+            datagrams: VecDeque::new(),
+            wakers: PollSet::new(),
+            dropped: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.datagrams.is_empty()
+    }
+
+    pub fn front(&self) -> Option<&(VsockAddr, Vec<u8>)> {
+        self.datagrams.front()
+    }
+
+    pub fn pop(&mut self) -> Option<(VsockAddr, Vec<u8>)> {
+        self.datagrams.pop_front()
+    }
+
+    pub fn register_poll(&mut self, context: &mut core::task::Context<'_>) {
+        self.wakers.register(context.waker());
+    }
+}
+
 /// Global connection manager
 pub struct VsockConnectionManager {
     connections: BTreeMap<VsockConnId, Arc<Mutex<Connection>>>,
     listen_queues: BTreeMap<u32, Arc<Mutex<ListenQueue>>>,
+    dgram_queues: BTreeMap<u32, Arc<Mutex<DgramQueue>>>,
     next_ephemeral_port: u32,
 }
 
@@ -269,6 +356,7 @@ impl VsockConnectionManager {
         Self {
             connections: BTreeMap::new(),
             listen_queues: BTreeMap::new(),
+            dgram_queues: BTreeMap::new(),
             next_ephemeral_port: Self::EPHEMERAL_PORT_START,
         }
     }
@@ -289,8 +377,8 @@ impl VsockConnectionManager {
                 port + 1
             };
 
-            // check if port is in use by listen queue
-            if !self.listen_queues.contains_key(&port) {
+            // check if port is in use by listen queue or datagram socket
+            if !self.listen_queues.contains_key(&port) && !self.dgram_queues.contains_key(&port) {
                 // check if port is in use by existing connections
                 let port_in_use = self.connections.keys().any(|id| id.local_port == port);
                 if !port_in_use {
@@ -304,21 +392,36 @@ impl VsockConnectionManager {
         }
     }
 
-    /// create a listen queue
-    pub fn listen(&mut self, local_addr: VsockAddr) -> KResult<()> {
+    /// create a listen queue holding up to `backlog` pending connections
+    pub fn listen(&mut self, local_addr: VsockAddr, backlog: usize) -> KResult<()> {
         if self.listen_queues.contains_key(&local_addr.port) {
             k_bail!(AddrInUse, "port already in use");
         }
 
-        let queue = Arc::new(Mutex::new(ListenQueue::new(local_addr)));
+        let queue = Arc::new(Mutex::new(ListenQueue::new(local_addr, backlog)));
         self.listen_queues.insert(local_addr.port, queue);
         Ok(())
     }
 
-    /// stop listening
-    pub fn unlisten(&mut self, port: u32) {
-        self.listen_queues.remove(&port);
-        debug!("Vsock unlisten on port {}", port);
+    /// stop listening, returning the connections that were never accepted
+    ///
+    /// They are removed here and must be reset by the caller.
+    pub fn unlisten(&mut self, port: u32) -> Vec<VsockConnId> {
+        let mut pending = Vec::new();
+        if let Some(queue) = self.listen_queues.remove(&port) {
+            while let Some(conn_id) = queue.lock().accept_queue.pop() {
+                pending.push(conn_id);
+            }
+        }
+        for &conn_id in &pending {
+            self.remove_connection(conn_id);
+        }
+        debug!(
+            "Vsock unlisten on port {}, {} pending connections reset",
+            port,
+            pending.len()
+        );
+        pending
     }
 
     /// check if port accept
@@ -430,8 +533,56 @@ impl VsockConnectionManager {
         Ok(())
     }
 
+    /// bind a datagram port
+    pub fn bind_dgram(&mut self, port: u32) -> KResult<Arc<Mutex<DgramQueue>>> {
+        if self.dgram_queues.contains_key(&port) {
+            k_bail!(AddrInUse, "port already in use");
+        }
+        let queue = Arc::new(Mutex::new(DgramQueue::new()));
+        self.dgram_queues.insert(port, queue.clone());
+        crate::device::start_vsock_polling();
+        Ok(queue)
+    }
+
+    /// unbind a datagram port
+    pub fn unbind_dgram(&mut self, port: u32) {
+        if let Some(queue) = self.dgram_queues.remove(&port) {
+            crate::device::stop_vsock_polling();
+            debug!(
+                "Vsock datagram port {} closed, dropped {} datagrams",
+                port,
+                queue.lock().dropped
+            );
+        }
+    }
+
+    /// dispatch_irq datagram received (by driver event)
+    pub fn on_datagram_received(&mut self, conn_id: VsockConnId, data: &[u8]) -> KResult<()> {
+        let queue = self
+            .dgram_queues
+            .get(&conn_id.local_port)
+            .ok_or(KError::NotFound)?;
+        let mut queue = queue.lock();
+        if queue.datagrams.len() >= VSOCK_DGRAM_QUEUE_SIZE {
+            queue.dropped += 1;
+            k_bail!(ResourceBusy, "datagram queue full");
+        }
+        queue
+            .datagrams
+            .push_back((conn_id.peer_addr, data.to_vec()));
+        queue.wakers.wake();
+        Ok(())
+    }
+
     /// dispatch_irq data received (by driver event)
-    pub fn on_data_received(&mut self, conn_id: VsockConnId, data: &[u8]) -> KResult<()> {
+    ///
+    /// `eom` marks the end of a message on seqpacket connections.
+    pub fn on_data_received(
+        &mut self,
+        conn_id: VsockConnId,
+        data: &[u8],
+        eom: bool,
+    ) -> KResult<()> {
         let conn = self
             .connections
             .get(&conn_id)
@@ -440,7 +591,10 @@ impl VsockConnectionManager {
 
         let mut conn_guard = conn.lock();
         let written = conn_guard.push_rx_data(data);
-        if written > 0 {
+        if eom {
+            conn_guard.end_rx_message();
+        }
+        if written > 0 || eom {
             conn_guard.wake_rx();
         }
 
@@ -463,11 +617,21 @@ impl VsockConnectionManager {
             conn_guard.rx_closed = true;
             conn_guard.tx_closed = true;
             conn_guard.wake_rx();
+            conn_guard.tx_wakers.wake();
             trace!("Connection {:?} disconnected", conn_id);
         }
         Ok(())
     }
 
+    /// dispatch_irq credit granted by the peer (by driver event)
+    pub fn on_credit_update(&mut self, conn_id: VsockConnId) -> KResult<()> {
+        let conn = self.connections.get(&conn_id).ok_or(KError::NotFound)?;
+        let mut conn_guard = conn.lock();
+        conn_guard.tx_blocked = false;
+        conn_guard.tx_wakers.wake();
+        Ok(())
+    }
+
     /// dispatch_irq connected event (by driver event)
     pub fn on_connected(&mut self, conn_id: VsockConnId) -> KResult<()> {
         if let Some(conn) = self.connections.get(&conn_id) {
@@ -0,0 +1,183 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Vsock datagram socket implementation.
+use alloc::{sync::Arc, vec::Vec};
+use core::task::Context;
+
+use kerrno::{KError, KResult, k_bail};
+use kio::prelude::*;
+use kpoll::{IoEvents, Pollable};
+use ksync::Mutex;
+
+use super::connection_manager::*;
+use crate::{
+    RecvFlags, RecvOptions, SendOptions, Shutdown, SocketAddrEx,
+    general::GeneralOptions,
+    options::{Configurable, GetSocketOption, SetSocketOption},
+    vsock::{VsockAddr, VsockTransport, VsockTransportOps},
+};
+
+/// Connectionless vsock transport.
+///
+/// Only usable when the device offers datagrams.
+pub struct VsockDgramTransport {
+    local_addr: Mutex<Option<VsockAddr>>,
+    peer_addr: Mutex<Option<VsockAddr>>,
+    queue: Mutex<Option<Arc<Mutex<DgramQueue>>>>,
+    general: GeneralOptions,
+}
+
+impl VsockDgramTransport {
+    pub fn new() -> Self {
+        Self {
+            local_addr: Mutex::new(None),
+            peer_addr: Mutex::new(None),
+            queue: Mutex::new(None),
+            general: GeneralOptions::new(),
+        }
+    }
+
+    fn get_queue(&self) -> KResult<Arc<Mutex<DgramQueue>>> {
+        self.queue.lock().clone().ok_or(KError::NotConnected)
+    }
+
+    /// Binds to an ephemeral port unless already bound.
+    fn ensure_bound(&self) -> KResult<u32> {
+        if let Some(addr) = *self.local_addr.lock() {
+            return Ok(addr.port);
+        }
+        self.bind(VsockAddr {
+            cid: crate::device::vsock_guest_cid()?,
+            port: 0,
+        })?;
+        self.local_addr
+            .lock()
+            .map(|addr| addr.port)
+            .ok_or(KError::NotConnected)
+    }
+}
+
+impl Default for VsockDgramTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Configurable for VsockDgramTransport {
+    fn get_option_inner(&self, opt: &mut GetSocketOption) -> KResult<bool> {
+        self.general.get_option_inner(opt)
+    }
+
+    fn set_option_inner(&self, opt: SetSocketOption) -> KResult<bool> {
+        self.general.set_option_inner(opt)
+    }
+}
+
+impl VsockTransportOps for VsockDgramTransport {
+    fn bind(&self, mut local_addr: VsockAddr) -> KResult<()> {
+        let mut guard = self.local_addr.lock();
+        if guard.is_some() {
+            k_bail!(InvalidInput, "already bound");
+        }
+        let mut manager = VSOCK_CONN_MANAGER.lock();
+        if local_addr.port == 0 {
+            local_addr.port = manager.allocate_port()?;
+        }
+        let queue = manager.bind_dgram(local_addr.port)?;
+        *self.queue.lock() = Some(queue);
+        *guard = Some(local_addr);
+        trace!("Vsock datagram binding to {:?}", local_addr);
+        Ok(())
+    }
+
+    fn listen(&self, _backlog: usize) -> KResult<()> {
+        Err(KError::OperationNotSupported)
+    }
+
+    fn connect(&self, peer_addr: VsockAddr) -> KResult<()> {
+        self.ensure_bound()?;
+        *self.peer_addr.lock() = Some(peer_addr);
+        Ok(())
+    }
+
+    fn accept(&self) -> KResult<(VsockTransport, VsockAddr)> {
+        Err(KError::OperationNotSupported)
+    }
+
+    fn send(&self, mut src: impl Read + IoBuf, options: SendOptions) -> KResult<usize> {
+        let peer_addr = match options.to {
+            Some(addr) => addr.into_vsock()?,
+            None => self.peer_addr.lock().ok_or(KError::NotConnected)?,
+        };
+        let src_port = self.ensure_bound()?;
+
+        let mut datagram = Vec::new();
+        src.read_to_end(&mut datagram)?;
+        crate::device::vsock_send_dgram(src_port, peer_addr, &datagram)
+    }
+
+    fn recv(&self, mut dst: impl Write, mut options: RecvOptions) -> KResult<usize> {
+        let queue = self.get_queue()?;
+
+        self.general.recv_poller(self, || {
+            let mut queue = queue.lock();
+            let (peer_addr, data) = queue.front().ok_or(KError::WouldBlock)?;
+            let (peer_addr, len) = (*peer_addr, data.len());
+            let count = dst.write(data)?;
+            if count < len {
+                trace!("Vsock datagram truncated: {} -> {} bytes", len, count);
+            }
+            if let Some(from) = options.from.as_deref_mut() {
+                *from = SocketAddrEx::Vsock(peer_addr);
+            }
+            if !options.flags.contains(RecvFlags::PEEK) {
+                queue.pop();
+            }
+            Ok(if options.flags.contains(RecvFlags::TRUNCATE) {
+                len
+            } else {
+                count
+            })
+        })
+    }
+
+    fn shutdown(&self, _how: Shutdown) -> KResult<()> {
+        Ok(())
+    }
+
+    fn local_addr(&self) -> KResult<Option<VsockAddr>> {
+        Ok(*self.local_addr.lock())
+    }
+
+    fn peer_addr(&self) -> KResult<Option<VsockAddr>> {
+        Ok(*self.peer_addr.lock())
+    }
+}
+
+impl Pollable for VsockDgramTransport {
+    fn poll(&self) -> IoEvents {
+        let mut events = IoEvents::OUT;
+        if let Ok(queue) = self.get_queue() {
+            events.set(IoEvents::IN, !queue.lock().is_empty());
+        }
+        events
+    }
+
+    fn register(&self, context: &mut Context<'_>, events: IoEvents) {
+        if events.contains(IoEvents::IN)
+            && let Ok(queue) = self.get_queue()
+        {
+            queue.lock().register_poll(context);
+        }
+    }
+}
+
+impl Drop for VsockDgramTransport {
+    fn drop(&mut self) {
+        if let Some(addr) = *self.local_addr.lock() {
+            VSOCK_CONN_MANAGER.lock().unbind_dgram(addr.port);
+        }
+    }
+}
@@ -2,8 +2,8 @@
 // Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
 // See LICENSES for license details.
 
-//! Vsock stream socket implementation.
-use alloc::sync::Arc;
+//! Vsock stream and seqpacket socket implementation.
+use alloc::{sync::Arc, vec::Vec};
 use core::task::Context;
 
 use kerrno::{KError, KResult, k_bail, k_err_type};
@@ -17,10 +17,18 @@ use crate::{
     general::GeneralOptions,
     options::{Configurable, GetSocketOption, SetSocketOption},
     state::*,
-    vsock::{VsockAddr, VsockConnId, VsockTransport, VsockTransportOps},
+    vsock::{VsockAddr, VsockConnId, VsockSocketType, VsockTransport, VsockTransportOps},
 };
 
+/// Largest chunk a stream send hands to the driver at once.
+const VSOCK_TX_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Connection-oriented vsock transport.
+///
+/// Seqpacket sockets share it with streams, but send and receive whole
+/// messages.
 pub struct VsockStreamTransport {
+    ty: VsockSocketType,
     conn_id: Mutex<Option<VsockConnId>>,
     connection: Mutex<Option<Arc<Mutex<Connection>>>>,
     state: StateLock,
@@ -29,7 +37,16 @@ pub struct VsockStreamTransport {
 
 impl VsockStreamTransport {
     pub fn new() -> Self {
+        Self::with_type(VsockSocketType::Stream)
+    }
+
+    pub fn new_seqpacket() -> Self {
+        Self::with_type(VsockSocketType::SeqPacket)
+    }
+
+    fn with_type(ty: VsockSocketType) -> Self {
         Self {
+            ty,
             conn_id: Mutex::new(None),
             connection: Mutex::new(None),
             state: StateLock::new(State::Idle),
@@ -37,11 +54,60 @@ impl VsockStreamTransport {
         }
     }
 
+    /// Returns the id of the connection, once connecting or accepted.
+    pub(crate) fn conn_id(&self) -> Option<VsockConnId> {
+        *self.conn_id.lock()
+    }
+
     fn get_connection(&self) -> KResult<Arc<Mutex<Connection>>> {
         self.connection.lock().clone().ok_or(KError::NotConnected)
     }
 }
 
+impl VsockStreamTransport {
+    /// Marks the connection as waiting for `wanted` bytes of peer credit,
+    /// unless it arrived since the send would have blocked.
+    fn block_tx(conn: &Mutex<Connection>, conn_id: VsockConnId, wanted: usize) -> KResult<()> {
+        conn.lock().set_tx_blocked(true);
+        if crate::device::vsock_send_credit(conn_id)? >= wanted {
+            conn.lock().set_tx_blocked(false);
+        }
+        Ok(())
+    }
+
+    /// Receives the oldest complete message, discarding what does not fit.
+    fn recv_message(
+        conn: &mut Connection,
+        dst: &mut impl Write,
+        options: &RecvOptions,
+    ) -> KResult<usize> {
+        let Some(len) = conn.rx_message_len() else {
+            if conn.rx_closed() {
+                return Ok(0);
+            }
+            return Err(KError::WouldBlock);
+        };
+
+        let (left, right) = conn.rx_slices();
+        let first = left.len().min(len);
+        let mut count = dst.write(&left[..first])?;
+        if count == first && first < len {
+            count += dst.write(&right[..len - first])?;
+        }
+        if count < len {
+            trace!("Vsock message truncated: {} -> {} bytes", len, count);
+        }
+        if !options.flags.contains(RecvFlags::PEEK) {
+            conn.pop_rx_message();
+        }
+        Ok(if options.flags.contains(RecvFlags::TRUNCATE) {
+            len
+        } else {
+            count
+        })
+    }
+}
+
 impl Default for VsockStreamTransport {
     fn default() -> Self {
         Self::new()
@@ -80,7 +146,7 @@ impl VsockTransportOps for VsockStreamTransport {
         Ok(())
     }
 
-    fn listen(&self) -> KResult<()> {
+    fn listen(&self, backlog: usize) -> KResult<()> {
         let guard = self
             .state
             .lock(State::Idle)
@@ -91,8 +157,8 @@ impl VsockTransportOps for VsockStreamTransport {
             let local_addr = conn.lock().local_addr();
 
             // register in the global listen table
-            VSOCK_CONN_MANAGER.lock().listen(local_addr)?;
-            crate::device::vsock_listen(local_addr)?;
+            VSOCK_CONN_MANAGER.lock().listen(local_addr, backlog)?;
+            crate::device::vsock_listen(local_addr, self.ty)?;
             // set state
             conn.lock().set_state(ConnectionState::Listening);
             trace!("Vsock listening on {:?}", local_addr);
@@ -121,6 +187,7 @@ impl VsockTransportOps for VsockStreamTransport {
 
             // create new VsockStreamTransport
             let new_transport = VsockStreamTransport {
+                ty: self.ty,
                 conn_id: Mutex::new(Some(conn_id)),
                 connection: Mutex::new(Some(conn)),
                 state: StateLock::new(State::Connected),
@@ -185,7 +252,7 @@ impl VsockTransportOps for VsockStreamTransport {
             drop(manager);
 
             // driver connect
-            crate::device::vsock_connect(conn_id)?;
+            crate::device::vsock_connect(conn_id, self.ty)?;
             debug!("Vsock connecting from {} to {:?}", local_port, peer_addr);
             Ok(())
         })?;
@@ -217,12 +284,44 @@ impl VsockTransportOps for VsockStreamTransport {
         let conn_id = self.conn_id.lock().ok_or(KError::NotConnected)?;
         drop(conn_guard);
 
-        // now virtio-driver only support non-blocking send
-        let result = src.write_to(&mut kio::write_fn(|buf| {
-            crate::device::vsock_send(conn_id, buf)
-        }));
-        conn.lock().add_tx_bytes(result.unwrap_or(0));
-        result
+        if self.ty == VsockSocketType::SeqPacket {
+            // The driver sends a message whole or not at all
+            let mut message = Vec::new();
+            src.read_to_end(&mut message)?;
+            return self.general.send_poller(self, || {
+                let result = crate::device::vsock_send(conn_id, &message);
+                if matches!(result, Err(KError::WouldBlock)) {
+                    Self::block_tx(&conn, conn_id, message.len().max(1))?;
+                }
+                conn.lock().add_tx_bytes(result.unwrap_or(0));
+                result
+            });
+        }
+
+        // Only take from `src` what the peer has room for, so nothing read
+        // is lost when the driver would block
+        self.general.send_poller(self, || {
+            let mut count = 0;
+            let mut chunk = Vec::new();
+            while src.remaining() > 0 {
+                let credit = crate::device::vsock_send_credit(conn_id)?;
+                if credit == 0 {
+                    break;
+                }
+                chunk.resize(src.remaining().min(credit).min(VSOCK_TX_CHUNK_SIZE), 0);
+                let read = src.read(&mut chunk)?;
+                if read == 0 {
+                    break;
+                }
+                count += crate::device::vsock_send(conn_id, &chunk[..read])?;
+            }
+            if count == 0 && src.remaining() > 0 {
+                Self::block_tx(&conn, conn_id, 1)?;
+                return Err(KError::WouldBlock);
+            }
+            conn.lock().add_tx_bytes(count);
+            Ok(count)
+        })
     }
 
     fn recv(&self, mut dst: impl Write, options: RecvOptions) -> KResult<usize> {
@@ -243,6 +342,10 @@ impl VsockTransportOps for VsockStreamTransport {
                 return Err(KError::NotConnected);
             }
 
+            if self.ty == VsockSocketType::SeqPacket {
+                return Self::recv_message(&mut conn_guard, &mut dst, &options);
+            }
+
             if conn_guard.rx_buffer_used() == 0 {
                 return Err(KError::WouldBlock);
             }
@@ -287,7 +390,12 @@ impl VsockTransportOps for VsockStreamTransport {
             if conn.state() == ConnectionState::Connected {
                 crate::device::vsock_disconnect(conn_id)?;
             } else if conn.state() == ConnectionState::Listening {
-                VSOCK_CONN_MANAGER.lock().unlisten(conn_id.local_port);
+                // Reset the peers that were never accepted
+                let pending = VSOCK_CONN_MANAGER.lock().unlisten(conn_id.local_port);
+                for pending_id in pending {
+                    let _ = crate::device::vsock_abort(pending_id);
+                }
+                crate::device::vsock_unlisten(conn_id.local_port)?;
             }
         }
         conn.set_state(ConnectionState::Closed);
@@ -330,7 +438,7 @@ impl Pollable for VsockStreamTransport {
             }
             ConnectionState::Connected | ConnectionState::Closed => {
                 events.set(IoEvents::IN, conn.rx_buffer_used() > 0 || conn.rx_closed());
-                events.set(IoEvents::OUT, !conn.tx_closed());
+                events.set(IoEvents::OUT, !conn.tx_closed() && !conn.tx_blocked());
             }
             ConnectionState::Connecting => {
                 // if connected, set OUT
@@ -356,9 +464,7 @@ impl Pollable for VsockStreamTransport {
                         conn.register_rx_poll(context);
                     }
                     if events.contains(IoEvents::OUT) {
-                        warn!(
-                            "VsockStreamTransport: OUT event on connected socket is not supported"
-                        );
+                        conn.register_tx_poll(context);
                     }
                 }
                 ConnectionState::Connecting => {
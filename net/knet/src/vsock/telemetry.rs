@@ -0,0 +1,223 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Failure telemetry for a collector on the host.
+//!
+//! A guest connects to [`HOST_CID`] on a dedicated port at boot and sends
+//! one frame per report over the stream: a [`FrameHeader`] followed by a
+//! UTF-8 text payload. Reports are sent without sleeping, so they can be
+//! made from panic, interrupt and NMI context; a report is dropped when the
+//! device or the frame buffer is busy rather than risk a deadlock.
+use alloc::string::ToString;
+use core::fmt::{self, Write};
+
+use ksync::Mutex;
+use lazyinit::LazyInit;
+
+use super::{VsockAddr, VsockConnId, VsockStreamTransport, VsockTransportOps};
+
+/// CID of the host.
+pub const HOST_CID: u64 = 2;
+
+/// Port of the collector, unless told otherwise.
+pub const DEFAULT_PORT: u32 = 9700;
+
+/// Magic number at the start of every frame, "XKTM" in little endian.
+pub const FRAME_MAGIC: u32 = u32::from_le_bytes(*b"XKTM");
+
+/// Version of the frame format.
+pub const FRAME_VERSION: u8 = 1;
+
+/// Size of an encoded [`FrameHeader`].
+pub const FRAME_HEADER_LEN: usize = 24;
+
+/// Largest payload of a frame; longer reports are truncated.
+pub const MAX_PAYLOAD_LEN: usize = 8192 - FRAME_HEADER_LEN;
+
+/// What a frame reports.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    /// First frame after connecting, naming the kernel.
+    Hello = 0,
+    /// A kernel panic with its backtrace.
+    Panic = 1,
+    /// A CPU did not schedule for too long.
+    SoftLockup = 2,
+    /// A watchdog check failed; the global dump and a panic follow.
+    HardLockup = 3,
+}
+
+impl FrameKind {
+    fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => Self::Hello,
+            1 => Self::Panic,
+            2 => Self::SoftLockup,
+            3 => Self::HardLockup,
+            _ => return None,
+        })
+    }
+}
+
+/// Header of a frame, encoded in little endian:
+///
+/// | offset | size | field          |
+/// |--------|------|----------------|
+/// | 0      | 4    | magic          |
+/// | 4      | 1    | version        |
+/// | 5      | 1    | kind           |
+/// | 6      | 2    | reserved, 0    |
+/// | 8      | 4    | cpu            |
+/// | 12     | 4    | payload length |
+/// | 16     | 8    | timestamp (ns) |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub kind: FrameKind,
+    /// CPU that made the report.
+    pub cpu: u32,
+    /// Length of the payload that follows.
+    pub len: u32,
+    /// Monotonic time of the report.
+    pub timestamp_ns: u64,
+}
+
+impl FrameHeader {
+    pub fn encode(&self, buf: &mut [u8; FRAME_HEADER_LEN]) {
+        buf[0..4].copy_from_slice(&FRAME_MAGIC.to_le_bytes());
+        buf[4] = FRAME_VERSION;
+        buf[5] = self.kind as u8;
+        buf[6..8].fill(0);
+        buf[8..12].copy_from_slice(&self.cpu.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.len.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.timestamp_ns.to_le_bytes());
+    }
+
+    /// Decodes a header, or returns `None` if `buf` does not start with one.
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let buf = buf.get(..FRAME_HEADER_LEN)?;
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap());
+        if u32_at(0) != FRAME_MAGIC || buf[4] != FRAME_VERSION {
+            return None;
+        }
+        Some(Self {
+            kind: FrameKind::from_u8(buf[5])?,
+            cpu: u32_at(8),
+            len: u32_at(12),
+            timestamp_ns: u64::from_le_bytes(buf[16..24].try_into().unwrap()),
+        })
+    }
+}
+
+/// A frame being built; text past [`MAX_PAYLOAD_LEN`] is dropped.
+pub struct FrameBuf {
+    buf: [u8; FRAME_HEADER_LEN + MAX_PAYLOAD_LEN],
+    len: usize,
+}
+
+impl FrameBuf {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; FRAME_HEADER_LEN + MAX_PAYLOAD_LEN],
+            len: FRAME_HEADER_LEN,
+        }
+    }
+
+    /// Starts a new frame of `kind` with `payload` as its text.
+    pub fn build(&mut self, kind: FrameKind, cpu: u32, timestamp_ns: u64, payload: fmt::Arguments) {
+        self.len = FRAME_HEADER_LEN;
+        let _ = self.write_fmt(payload);
+        let header = FrameHeader {
+            kind,
+            cpu,
+            len: (self.len - FRAME_HEADER_LEN) as u32,
+            timestamp_ns,
+        };
+        header.encode((&mut self.buf[..FRAME_HEADER_LEN]).try_into().unwrap());
+    }
+
+    /// Returns the encoded frame.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Default for FrameBuf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for FrameBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut count = s.len().min(self.buf.len() - self.len);
+        while !s.is_char_boundary(count) {
+            count -= 1;
+        }
+        self.buf[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+/// The connection to the collector, kept open until shutdown.
+static CONNECTION: LazyInit<(VsockStreamTransport, VsockConnId)> = LazyInit::new();
+static FRAME: Mutex<FrameBuf> = Mutex::new(FrameBuf::new());
+
+/// Connects to the collector on `port` of the host in the background.
+///
+/// Nothing is reported if the host does not listen on `port`.
+pub fn start(port: u32) {
+    ktask::spawn_with_name(
+        move || {
+            let transport = VsockStreamTransport::new();
+            let addr = VsockAddr {
+                cid: HOST_CID,
+                port,
+            };
+            if let Err(e) = transport.connect(addr) {
+                info!("No vsock telemetry collector on port {port}: {e:?}");
+                return;
+            }
+            let Some(conn_id) = transport.conn_id() else {
+                return;
+            };
+            CONNECTION.init_once((transport, conn_id));
+            info!("Vsock telemetry connected to {addr:?}");
+            report(
+                FrameKind::Hello,
+                format_args!("x-kernel {}", env!("CARGO_PKG_VERSION")),
+            );
+        },
+        "vsock-telemetry".to_string(),
+    );
+}
+
+/// Returns whether reports reach a collector.
+pub fn is_connected() -> bool {
+    CONNECTION.is_inited()
+}
+
+/// Sends a report of `kind` to the collector, if connected.
+///
+/// Returns whether the report was sent in full.
+pub fn report(kind: FrameKind, payload: fmt::Arguments) -> bool {
+    let Some((_, conn_id)) = CONNECTION.get() else {
+        return false;
+    };
+    // A report from a nested panic, or from an interrupt while the frame is
+    // being sent, is dropped
+    let Some(mut frame) = FRAME.try_lock() else {
+        return false;
+    };
+    frame.build(
+        kind,
+        khal::percpu::this_cpu_id() as u32,
+        khal::time::monotonic_time_nanos(),
+        payload,
+    );
+    let frame = frame.as_bytes();
+    crate::device::vsock_send_atomic(*conn_id, frame).is_ok_and(|sent| sent == frame.len())
+}
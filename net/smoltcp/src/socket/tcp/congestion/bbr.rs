@@ -0,0 +1,325 @@
+use crate::{
+    socket::tcp::RttEstimator,
+    time::{Duration, Instant},
+};
+
+use super::Controller;
+
+// Constants for the BBR congestion control algorithm.
+// See draft-cardwell-iccrg-bbr-congestion-control. Gains are in thousandths.
+const STARTUP_GAIN: u64 = 2885; // 2 / ln(2)
+const PROBE_BW_CWND_GAIN: u64 = 2000;
+const PROBE_BW_GAINS: [u64; 8] = [1250, 750, 1000, 1000, 1000, 1000, 1000, 1000];
+const FULL_BW_GROWTH: u64 = 1250;
+const FULL_BW_ROUNDS: u8 = 3;
+const BW_FILTER_ROUNDS: usize = 10;
+const MIN_RTT_EXPIRY: Duration = Duration::from_secs(10);
+const PROBE_RTT_DURATION: Duration = Duration::from_millis(200);
+const MIN_CWND_SEGMENTS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Mode {
+    Startup,
+    Drain,
+    ProbeBw,
+    ProbeRtt,
+}
+
+/// A simplified BBR ("BBR-lite").
+///
+/// The bottleneck bandwidth is the windowed maximum of the bytes acknowledged per
+/// round, where a round lasts one minimum RTT, and the minimum RTT is taken from
+/// the smoothed RTT. smoltcp has no pacer, so the congestion window is the only
+/// brake and the pacing gain cycle of ProbeBW is applied to it instead.
+/// Application-limited rounds are not detected; the max filter ages them out.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Bbr {
+    cwnd: usize, // Congestion window
+    mss: usize,
+    rwnd: usize, // Remote window
+    mode: Mode,
+    /// Delivery rate of the last rounds, in bytes per second.
+    bw_samples: [u64; BW_FILTER_ROUNDS],
+    round: usize,
+    round_start: Option<Instant>,
+    round_delivered: usize,
+    /// Minimum RTT in milliseconds, and when it was last lowered or refreshed.
+    min_rtt: Option<u32>,
+    min_rtt_stamp: Instant,
+    filled_pipe: bool,
+    full_bw: u64,
+    full_bw_rounds: u8,
+    cycle_index: usize,
+    probe_rtt_done: Instant,
+}
+
+impl Bbr {
+    pub fn new() -> Bbr {
+        Bbr {
+            cwnd: 1024 * 2,
+            mss: 536,
+            rwnd: 64 * 1024,
+            mode: Mode::Startup,
+            bw_samples: [0; BW_FILTER_ROUNDS],
+            round: 0,
+            round_start: None,
+            round_delivered: 0,
+            min_rtt: None,
+            min_rtt_stamp: Instant::from_millis(0),
+            filled_pipe: false,
+            full_bw: 0,
+            full_bw_rounds: 0,
+            cycle_index: 0,
+            probe_rtt_done: Instant::from_millis(0),
+        }
+    }
+
+    fn min_cwnd(&self) -> usize {
+        self.mss * MIN_CWND_SEGMENTS
+    }
+
+    fn btl_bw(&self) -> u64 {
+        self.bw_samples.iter().copied().max().unwrap_or(0)
+    }
+
+    /// Returns the window the model asks for, or `None` before the first estimate.
+    fn target_cwnd(&self) -> Option<usize> {
+        let min_rtt = self.min_rtt?;
+        let bw = self.btl_bw();
+        if bw == 0 {
+            return None;
+        }
+        let bdp = bw * min_rtt as u64 / 1000;
+        let target = match self.mode {
+            Mode::Startup => bdp * STARTUP_GAIN / 1000,
+            Mode::Drain => bdp,
+            Mode::ProbeBw | Mode::ProbeRtt => {
+                bdp * PROBE_BW_CWND_GAIN / 1000 * PROBE_BW_GAINS[self.cycle_index] / 1000
+            }
+        };
+        Some(usize::try_from(target).unwrap_or(usize::MAX))
+    }
+
+    fn update_min_rtt(&mut self, now: Instant, rtt: &RttEstimator) {
+        if !rtt.have_measurement {
+            return;
+        }
+        let srtt = rtt.srtt.max(1);
+
+        match self.min_rtt {
+            Some(min_rtt) if srtt >= min_rtt => {
+                if self.mode != Mode::ProbeRtt && now >= self.min_rtt_stamp + MIN_RTT_EXPIRY {
+                    // Drain the queue so the next estimate sees the path empty.
+                    self.mode = Mode::ProbeRtt;
+                    self.probe_rtt_done = now + PROBE_RTT_DURATION;
+                }
+            }
+            _ => {
+                self.min_rtt = Some(srtt);
+                self.min_rtt_stamp = now;
+            }
+        }
+
+        if self.mode == Mode::ProbeRtt && now >= self.probe_rtt_done {
+            self.min_rtt = Some(srtt);
+            self.min_rtt_stamp = now;
+            self.mode = if self.filled_pipe {
+                self.cycle_index = 0;
+                Mode::ProbeBw
+            } else {
+                Mode::Startup
+            };
+        }
+    }
+
+    fn on_round_end(&mut self, bw: u64) {
+        self.bw_samples[self.round % BW_FILTER_ROUNDS] = bw;
+        self.round = self.round.wrapping_add(1);
+
+        match self.mode {
+            Mode::Startup => {
+                let btl_bw = self.btl_bw();
+                if btl_bw >= self.full_bw * FULL_BW_GROWTH / 1000 {
+                    self.full_bw = btl_bw;
+                    self.full_bw_rounds = 0;
+                } else {
+                    self.full_bw_rounds += 1;
+                    if self.full_bw_rounds >= FULL_BW_ROUNDS {
+                        self.filled_pipe = true;
+                        self.mode = Mode::Drain;
+                    }
+                }
+            }
+            Mode::Drain => {
+                self.cycle_index = 0;
+                self.mode = Mode::ProbeBw;
+            }
+            Mode::ProbeBw => {
+                self.cycle_index = (self.cycle_index + 1) % PROBE_BW_GAINS.len();
+            }
+            Mode::ProbeRtt => {}
+        }
+    }
+
+    fn update_cwnd(&mut self, len: usize) {
+        let cwnd = match (self.mode, self.target_cwnd()) {
+            // The window is pinned to its floor while probing RTT.
+            (Mode::ProbeRtt, _) => self.cwnd,
+            (Mode::Startup, Some(target)) if self.cwnd >= target => self.cwnd,
+            (Mode::Startup, _) | (_, None) => self.cwnd.saturating_add(len),
+            (_, Some(target)) => self.cwnd.saturating_add(len).min(target),
+        };
+
+        self.cwnd = cwnd.min(self.rwnd).max(self.min_cwnd());
+    }
+}
+
+impl Controller for Bbr {
+    fn window(&self) -> usize {
+        match self.mode {
+            Mode::ProbeRtt => self.cwnd.min(self.min_cwnd()),
+            _ => self.cwnd,
+        }
+    }
+
+    fn set_remote_window(&mut self, remote_window: usize) {
+        if self.rwnd < remote_window {
+            self.rwnd = remote_window;
+        }
+    }
+
+    fn on_ack(&mut self, now: Instant, len: usize, rtt: &RttEstimator) {
+        self.update_min_rtt(now, rtt);
+        self.round_delivered = self.round_delivered.saturating_add(len);
+
+        if let Some(min_rtt) = self.min_rtt {
+            let round_start = *self.round_start.get_or_insert(now);
+            let elapsed = (now - round_start).total_millis();
+            if elapsed > 0 && elapsed >= min_rtt as u64 {
+                self.on_round_end(self.round_delivered as u64 * 1000 / elapsed);
+                self.round_start = Some(now);
+                self.round_delivered = 0;
+            }
+        }
+
+        self.update_cwnd(len);
+    }
+
+    fn on_retransmit(&mut self, _now: Instant) {
+        // The model is kept; the window grows back towards it as ACKs arrive.
+        self.cwnd = self.min_cwnd();
+    }
+
+    fn set_mss(&mut self, mss: usize) {
+        self.mss = mss;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::time::Instant;
+
+    use super::*;
+
+    // A 1 MB/s link with a 100 ms RTT, i.e. a 100 kB BDP.
+    const LINK_BDP: usize = 100_000;
+
+    fn rtt_100ms() -> RttEstimator {
+        let mut rtt = RttEstimator::default();
+        rtt.sample(100);
+        rtt
+    }
+
+    fn run_rounds(bbr: &mut Bbr, rtt: &RttEstimator, now: &mut Instant, rounds: usize) {
+        for _ in 0..rounds {
+            let delivered = bbr.window().min(LINK_BDP);
+            for _ in 0..10 {
+                *now += Duration::from_millis(10);
+                bbr.on_ack(*now, delivered / 10, rtt);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bbr_startup() {
+        let rtt = rtt_100ms();
+        let mut now = Instant::from_millis(0);
+
+        let mut bbr = Bbr::new();
+        bbr.set_mss(1480);
+        bbr.set_remote_window(64 * 1024 * 1024);
+
+        run_rounds(&mut bbr, &rtt, &mut now, 5);
+        assert_eq!(bbr.mode, Mode::Startup);
+        assert!(bbr.window() > 2 * 1024 * 16);
+
+        run_rounds(&mut bbr, &rtt, &mut now, 20);
+        println!("BBR: {bbr:?}");
+        assert!(bbr.filled_pipe);
+        assert_eq!(bbr.mode, Mode::ProbeBw);
+        assert!(bbr.btl_bw() >= 1_000_000 && bbr.btl_bw() <= 1_100_000);
+        assert!(bbr.window() >= LINK_BDP && bbr.window() <= LINK_BDP * 3);
+    }
+
+    #[test]
+    fn test_bbr_probe_rtt() {
+        let rtt = rtt_100ms();
+        let mut now = Instant::from_millis(0);
+
+        let mut bbr = Bbr::new();
+        bbr.set_mss(1480);
+        bbr.set_remote_window(64 * 1024 * 1024);
+
+        run_rounds(&mut bbr, &rtt, &mut now, 20);
+        let cwnd = bbr.window();
+
+        now += MIN_RTT_EXPIRY;
+        bbr.on_ack(now, 1480, &rtt);
+        assert_eq!(bbr.mode, Mode::ProbeRtt);
+        assert_eq!(bbr.window(), bbr.min_cwnd());
+
+        now += PROBE_RTT_DURATION;
+        bbr.on_ack(now, 1480, &rtt);
+        assert_eq!(bbr.mode, Mode::ProbeBw);
+        assert!(bbr.window() >= cwnd.min(LINK_BDP));
+    }
+
+    #[test]
+    fn test_bbr_retransmit() {
+        let rtt = rtt_100ms();
+        let mut now = Instant::from_millis(0);
+
+        let mut bbr = Bbr::new();
+        bbr.set_mss(1480);
+        bbr.set_remote_window(64 * 1024 * 1024);
+
+        run_rounds(&mut bbr, &rtt, &mut now, 20);
+        let btl_bw = bbr.btl_bw();
+
+        bbr.on_retransmit(now);
+        assert_eq!(bbr.window(), bbr.min_cwnd());
+        assert_eq!(bbr.btl_bw(), btl_bw);
+
+        // The window regrows per ACK without waiting for a new model.
+        bbr.on_ack(now, 1480, &rtt);
+        assert_eq!(bbr.window(), bbr.min_cwnd() + 1480);
+    }
+
+    #[test]
+    fn bbr_min_cwnd() {
+        let remote_window = 64 * 1024;
+        let now = Instant::from_millis(0);
+
+        let mut bbr = Bbr::new();
+        bbr.set_remote_window(remote_window);
+
+        for _ in 0..100 {
+            bbr.on_retransmit(now);
+            bbr.on_ack(now, 0, &RttEstimator::default());
+            assert!(bbr.window() >= bbr.min_cwnd());
+            assert!(bbr.window() <= remote_window);
+        }
+    }
+}
@@ -18,7 +18,9 @@ static FDT_MEM_BASE: AtomicUsize = AtomicUsize::new(0);
 static FDT_MEM: Once<[MemRange; 2]> = Once::new();
 static DICE_MEM_BASE: AtomicUsize = AtomicUsize::new(0);
 static DICE_MEM_SIZE: AtomicUsize = AtomicUsize::new(0);
-/// Capture FDT/DICE memory ranges before the allocator is initialized.
+static CMA_MEM_SIZE: AtomicUsize = AtomicUsize::new(crate::config::plat::CMA_MEM_SIZE);
+/// Capture FDT/DICE memory ranges and the CMA pool size before the allocator
+/// is initialized.
 pub(crate) fn early_init(fdt_paddr: usize) {
     FDT_MEM_BASE.store(fdt_paddr, Ordering::SeqCst);
     let fdt = unsafe { LinuxFdt::from_ptr(fdt_paddr as *const u8).expect("Failed to parse FDT") };
@@ -30,6 +32,13 @@ pub(crate) fn early_init(fdt_paddr: usize) {
             break;
         }
     });
+    // A reusable DMA pool is one the kernel may use until it is needed.
+    if let Some(cma) = fdt.linux_reserved_memory().and_then(|rsvd| {
+        rsvd.dynamic_nodes()
+            .find(|node| node.reusable() && node.shared_dma_pool())
+    }) {
+        CMA_MEM_SIZE.store(cma.size(), Ordering::SeqCst);
+    }
 }
 /// Platform-specific memory description for the kernel.
 struct HwMemoryImpl;
@@ -69,6 +78,10 @@ impl HwMemory for HwMemoryImpl {
         )]
     }
 
+    fn cma_size() -> usize {
+        CMA_MEM_SIZE.load(Ordering::Relaxed)
+    }
+
     fn p2v(paddr: PhysAddr) -> VirtAddr {
         va!(paddr.as_usize() + PHYS_VIRT_OFFSET)
     }
@@ -3,7 +3,7 @@
 // See LICENSES for license details.
 
 //! Power and SMP boot controls for the platform.
-use kplat::sys::SysCtrl;
+use kplat::sys::{RebootMode, SysCtrl};
 struct PowerImpl;
 #[impl_dev_interface]
 impl SysCtrl for PowerImpl {
@@ -19,4 +19,9 @@ impl SysCtrl for PowerImpl {
     fn shutdown() -> ! {
         aarch64_peripherals::psci::shutdown()
     }
+
+    /// Request a system reset through PSCI.
+    fn reboot(mode: RebootMode) -> ! {
+        aarch64_peripherals::psci::reboot(mode == RebootMode::Warm)
+    }
 }
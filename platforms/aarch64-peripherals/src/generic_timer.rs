@@ -78,6 +78,10 @@ macro_rules! time_if_impl {
                 $crate::pl031::offset_ns()
             }
 
+            fn rtc_ns() -> Option<u64> {
+                $crate::pl031::rtc_ns()
+            }
+
             fn freq() -> u64 {
                 $crate::generic_timer::freq()
             }
@@ -3,11 +3,15 @@
 // See LICENSES for license details.
 
 //! PL031 RTC helper for epoch offset calculation.
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 use arm_pl031::Rtc;
 use kplat::memory::VirtAddr;
 
 use crate::generic_timer::{now_ticks, t2ns};
 static mut RTC_EPOCHOFFSET_NANOS: u64 = 0;
+/// Base address of the RTC, 0 until [`early_init`] found one.
+static RTC_BASE: AtomicUsize = AtomicUsize::new(0);
 /// Return the cached epoch offset in nanoseconds.
 #[inline]
 pub fn offset_ns() -> u64 {
@@ -18,9 +22,19 @@ pub fn early_init(rtc_base: VirtAddr) {
     if rtc_base.as_usize() == 0 {
         return;
     }
-    let rtc = unsafe { Rtc::new(rtc_base.as_mut_ptr() as _) };
-    let epoch_time_nanos = rtc.get_unix_timestamp() as u64 * 1_000_000_000;
-    unsafe {
-        RTC_EPOCHOFFSET_NANOS = epoch_time_nanos - t2ns(now_ticks());
+    RTC_BASE.store(rtc_base.as_usize(), Ordering::Release);
+    if let Some(epoch_time_nanos) = rtc_ns() {
+        unsafe {
+            RTC_EPOCHOFFSET_NANOS = epoch_time_nanos - t2ns(now_ticks());
+        }
+    }
+}
+/// Read the RTC in nanoseconds since the epoch, if [`early_init`] found one.
+pub fn rtc_ns() -> Option<u64> {
+    let rtc_base = RTC_BASE.load(Ordering::Acquire);
+    if rtc_base == 0 {
+        return None;
     }
+    let rtc = unsafe { Rtc::new(rtc_base as _) };
+    Some(rtc.get_unix_timestamp() as u64 * 1_000_000_000)
 }
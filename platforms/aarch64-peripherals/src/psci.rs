@@ -13,6 +13,8 @@ const PSCI_0_2_FN_CPU_ON: u32 = PSCI_0_2_FN_BASE + 3;
 const PSCI_0_2_FN_MIGRATE: u32 = PSCI_0_2_FN_BASE + 5;
 const PSCI_0_2_FN_SYSTEM_OFF: u32 = PSCI_0_2_FN_BASE + 8;
 const PSCI_0_2_FN_SYSTEM_RESET: u32 = PSCI_0_2_FN_BASE + 9;
+const PSCI_1_1_FN_SYSTEM_RESET2: u32 = PSCI_0_2_FN_BASE + 0x12;
+const PSCI_1_1_SYSTEM_WARM_RESET: usize = 0;
 const PSCI_0_2_FN64_CPU_SUSPEND: u32 = PSCI_0_2_FN_BASE + PSCI_0_2_64BIT + 1;
 const PSCI_0_2_FN64_CPU_ON: u32 = PSCI_0_2_FN_BASE + PSCI_0_2_64BIT + 3;
 const PSCI_0_2_FN64_MIGRATE: u32 = PSCI_0_2_FN_BASE + PSCI_0_2_64BIT + 5;
@@ -20,15 +22,15 @@ static PSCI_METHOD_HVC: AtomicBool = AtomicBool::new(false);
 #[derive(PartialEq, Debug)]
 #[repr(i32)]
 enum PsciError {
-    NotSupported    = -1,
-    InvalidParams   = -2,
-    Denied          = -3,
-    AlreadyOn       = -4,
-    OnPending       = -5,
+    NotSupported = -1,
+    InvalidParams = -2,
+    Denied = -3,
+    AlreadyOn = -4,
+    OnPending = -5,
     InternalFailure = -6,
-    NotPresent      = -7,
-    Disabled        = -8,
-    InvalidAddress  = -9,
+    NotPresent = -7,
+    Disabled = -8,
+    InvalidAddress = -9,
 }
 impl From<i32> for PsciError {
     fn from(code: i32) -> PsciError {
@@ -102,6 +104,19 @@ pub fn shutdown() -> ! {
         kcpu::instrs::stop_cpu();
     }
 }
+/// Reset the system via PSCI, warm if `warm` and the firmware has
+/// `SYSTEM_RESET2`.
+pub fn reboot(warm: bool) -> ! {
+    info!("Rebooting...");
+    if warm && let Err(e) = psci_call(PSCI_1_1_FN_SYSTEM_RESET2, PSCI_1_1_SYSTEM_WARM_RESET, 0, 0) {
+        debug!("warm reset failed ({e:?}), resetting cold");
+    }
+    psci_call(PSCI_0_2_FN_SYSTEM_RESET, 0, 0, 0).ok();
+    warn!("It should reboot!");
+    loop {
+        kcpu::instrs::stop_cpu();
+    }
+}
 /// Power on a target CPU with the given entry point and argument.
 pub fn cpu_on(target_cpu: usize, entry_point: usize, arg: usize) {
     info!("Starting CPU {target_cpu:x} ON ...");
@@ -10,6 +10,7 @@
 /// Platform-level configuration constants
 pub mod plat {
     pub const BOOT_STACK_SIZE: usize = 0x40000;
+    pub const CMA_MEM_SIZE: usize = 0x80_0000;
     pub const DMA_MEM_BASE: usize = 0x4000_0000;
     pub const DMA_MEM_SIZE: usize = 0x200_000;
     pub const KERNEL_ASPACE_BASE: usize = kbuild_config::KERNEL_ASPACE_BASE as _;
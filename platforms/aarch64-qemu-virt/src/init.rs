@@ -41,3 +41,15 @@ impl BootHandler for BootHandlerImpl {
         aarch64_peripherals::generic_timer::enable_local(TIMER_IRQ);
     }
 }
+
+struct BoardInfoImpl;
+#[impl_dev_interface]
+impl kplat::board::BoardInfo for BoardInfoImpl {
+    fn uart_paddr() -> Option<usize> {
+        Some(UART_PADDR)
+    }
+
+    fn intc_paddr() -> Option<usize> {
+        Some(GICD_PADDR)
+    }
+}
@@ -4,7 +4,7 @@
 
 //! Power control implementation for aarch64-qemu-virt.
 
-use kplat::sys::SysCtrl;
+use kplat::sys::{RebootMode, SysCtrl};
 struct PowerImpl;
 #[impl_dev_interface]
 impl SysCtrl for PowerImpl {
@@ -18,4 +18,8 @@ impl SysCtrl for PowerImpl {
     fn shutdown() -> ! {
         aarch64_peripherals::psci::shutdown()
     }
+
+    fn reboot(mode: RebootMode) -> ! {
+        aarch64_peripherals::psci::reboot(mode == RebootMode::Warm)
+    }
 }
@@ -39,3 +39,15 @@ impl BootHandler for BootHandlerImpl {
         }
     }
 }
+
+struct BoardInfoImpl;
+#[impl_dev_interface]
+impl kplat::board::BoardInfo for BoardInfoImpl {
+    fn uart_paddr() -> Option<usize> {
+        Some(UART_PADDR)
+    }
+
+    fn intc_paddr() -> Option<usize> {
+        Some(GICD_PADDR)
+    }
+}
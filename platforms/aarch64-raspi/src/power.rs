@@ -3,7 +3,7 @@
 // See LICENSES for license details.
 
 //! Raspberry Pi system control implementation.
-use kplat::sys::SysCtrl;
+use kplat::sys::{RebootMode, SysCtrl};
 struct PowerImpl;
 #[impl_dev_interface]
 impl SysCtrl for PowerImpl {
@@ -17,4 +17,11 @@ impl SysCtrl for PowerImpl {
             kcpu::instrs::stop_cpu();
         }
     }
+
+    fn reboot(_mode: RebootMode) -> ! {
+        log::warn!("Reboot is not supported, halting...");
+        loop {
+            kcpu::instrs::stop_cpu();
+        }
+    }
 }
@@ -0,0 +1,20 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Board description a platform package was configured for.
+//!
+//! Generic code checks it against what the firmware reports at boot, to
+//! catch kernels built for the wrong variant of a board.
+
+use kplat_macros::device_interface;
+
+#[device_interface]
+pub trait BoardInfo {
+    /// Returns the physical address of the console UART, if it is memory
+    /// mapped.
+    fn uart_paddr() -> Option<usize>;
+    /// Returns the physical address of the interrupt controller, e.g. the
+    /// GIC distributor or the PLIC, if it is memory mapped.
+    fn intc_paddr() -> Option<usize>;
+}
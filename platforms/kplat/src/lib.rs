@@ -9,6 +9,7 @@
 
 extern crate kplat_macros;
 
+pub mod board;
 pub mod boot;
 pub mod cpu;
 pub mod interrupts;
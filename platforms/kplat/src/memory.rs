@@ -142,6 +142,8 @@ pub trait HwMemory {
     fn mmio_regions() -> &'static [MemRange];
     /// Returns DMA-capable ranges provided by the platform.
     fn dma_regions() -> &'static [MemRange];
+    /// Returns the size of the CMA pool to carve out of free RAM, 0 for none.
+    fn cma_size() -> usize;
     /// Converts a physical address to virtual.
     fn p2v(pa: PhysAddr) -> VirtAddr;
     /// Converts a virtual address to physical.
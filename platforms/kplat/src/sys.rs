@@ -6,6 +6,20 @@
 
 use kplat_macros::device_interface;
 
+/// How the system restarts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RebootMode {
+    /// Resets the whole machine, as if it was powered off and on.
+    Cold,
+    /// Resets the CPUs only, keeping the contents of memory where the
+    /// platform can.
+    Warm,
+    /// Stops in the bootloader or the firmware instead of booting again.
+    ///
+    /// Platforms without a way to tell the firmware restart cold.
+    Bootloader,
+}
+
 #[device_interface]
 pub trait SysCtrl {
     #[cfg(feature = "smp")]
@@ -14,4 +28,8 @@ pub trait SysCtrl {
 
     /// Shuts down the system.
     fn shutdown() -> !;
+
+    /// Restarts the system in `mode`, or the closest mode the platform
+    /// supports.
+    fn reboot(mode: RebootMode) -> !;
 }
@@ -4,7 +4,10 @@
 
 //! Platform timer interface and helpers.
 
-use core::time::Duration;
+use core::{
+    sync::atomic::{AtomicI64, Ordering},
+    time::Duration,
+};
 
 use kplat_macros::device_interface;
 
@@ -34,6 +37,9 @@ pub trait GlobalTimer {
     fn ns2t(ns: u64) -> u64;
     /// Returns wall clock offset in nanoseconds.
     fn offset_ns() -> u64;
+    /// Reads the real-time clock, in nanoseconds since the epoch, or `None`
+    /// without one.
+    fn rtc_ns() -> Option<u64>;
 
     /// Returns the timer interrupt ID.
     fn interrupt_id() -> usize;
@@ -42,6 +48,9 @@ pub trait GlobalTimer {
     fn arm_timer(deadline: u64);
 }
 
+/// Steps applied to the wall clock since boot, in nanoseconds.
+static WALL_STEP_NS: AtomicI64 = AtomicI64::new(0);
+
 /// Returns the current monotonic time in nanoseconds.
 pub fn now_ns() -> u64 {
     t2ns(now_ticks())
@@ -54,7 +63,24 @@ pub fn now() -> ClockTime {
 
 /// Returns the wall-clock time in nanoseconds.
 pub fn wall_ns() -> u64 {
-    now_ns() + offset_ns()
+    now_ns() + wall_offset_ns()
+}
+
+/// Returns the offset of the wall clock from the monotonic clock, including
+/// the steps made by [`step_wall`].
+pub fn wall_offset_ns() -> u64 {
+    offset_ns().wrapping_add_signed(wall_step_ns())
+}
+
+/// Returns the sum of the steps made by [`step_wall`].
+pub fn wall_step_ns() -> i64 {
+    WALL_STEP_NS.load(Ordering::Acquire)
+}
+
+/// Moves the wall clock by `delta_ns`, e.g. to the RTC after the VM was
+/// paused. The monotonic clock is unaffected.
+pub fn step_wall(delta_ns: i64) {
+    WALL_STEP_NS.fetch_add(delta_ns, Ordering::AcqRel);
 }
 
 /// Returns the wall-clock time as `ClockTime`.
@@ -28,3 +28,15 @@ impl BootHandler for BootHandlerImpl {
         crate::time::init_percpu();
     }
 }
+
+struct BoardInfoImpl;
+#[impl_dev_interface]
+impl kplat::board::BoardInfo for BoardInfoImpl {
+    fn uart_paddr() -> Option<usize> {
+        Some(crate::config::devices::UART_PADDR)
+    }
+
+    fn intc_paddr() -> Option<usize> {
+        Some(crate::config::devices::PCH_PIC_PADDR)
+    }
+}
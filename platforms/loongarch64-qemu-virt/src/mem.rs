@@ -43,6 +43,10 @@ impl HwMemory for HwMemoryImpl {
         &[]
     }
 
+    fn cma_size() -> usize {
+        crate::config::plat::CMA_MEM_SIZE
+    }
+
     fn p2v(paddr: PhysAddr) -> VirtAddr {
         va!(paddr.as_usize() + PHYS_VIRT_OFFSET)
     }
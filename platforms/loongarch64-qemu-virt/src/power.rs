@@ -4,7 +4,7 @@
 
 use kplat::{
     mem::{p2v, pa},
-    power::SysCtrl,
+    power::{RebootMode, SysCtrl},
 };
 
 use crate::config::devices::GED_PADDR;
@@ -26,4 +26,17 @@ impl SysCtrl for PowerImpl {
             kcpu::instrs::stop_cpu();
         }
     }
+
+    fn reboot(_mode: RebootMode) -> ! {
+        // The reset register of the GED follows the sleep control and
+        // status registers; there is one kind of reset only
+        let reset_addr: *mut u8 = p2v(pa!(GED_PADDR + 2)).as_mut_ptr();
+        info!("Rebooting...");
+        unsafe { reset_addr.write_volatile(0x42) };
+        kcpu::instrs::stop_cpu();
+        warn!("It should reboot!");
+        loop {
+            kcpu::instrs::stop_cpu();
+        }
+    }
 }
@@ -15,22 +15,35 @@ pub(super) fn init_percpu() {
     kplat::interrupts::enable(crate::config::devices::TIMER_IRQ, true);
 }
 #[cfg(feature = "rtc")]
+const LS7A_RTC_VADDR: kplat::memory::PhysAddr =
+    kplat::memory::pa!(crate::config::devices::RTC_PADDR);
+#[cfg(feature = "rtc")]
 fn init_rtc() {
-    use chrono::{TimeZone, Timelike, Utc};
-    use kplat::memory::{PhysAddr, p2v, pa};
-    const SYS_TOY_READ0: usize = 0x2C;
-    const SYS_TOY_READ1: usize = 0x30;
+    use kplat::memory::p2v;
     const SYS_RTCCTRL: usize = 0x40;
     const TOY_ENABLE: u32 = 1 << 11;
     const OSC_ENABLE: u32 = 1 << 8;
-    const LS7A_RTC_VADDR: PhysAddr = pa!(crate::config::devices::RTC_PADDR);
     let rtc_base_ptr = p2v(LS7A_RTC_VADDR).as_mut_ptr();
-    fn extract_bits(value: u32, range: core::ops::Range<u32>) -> u32 {
-        (value >> range.start) & ((1 << (range.end - range.start)) - 1)
-    }
     unsafe {
         (rtc_base_ptr.add(SYS_RTCCTRL) as *mut u32).write_volatile(TOY_ENABLE | OSC_ENABLE);
     }
+    if let Some(epoch_time_nanos) = read_rtc() {
+        unsafe {
+            RTC_EPOCHOFFSET_NANOS =
+                epoch_time_nanos - GlobalTimerImpl::t2ns(GlobalTimerImpl::now_ticks());
+        }
+    }
+}
+#[cfg(feature = "rtc")]
+fn read_rtc() -> Option<u64> {
+    use chrono::{TimeZone, Timelike, Utc};
+    use kplat::memory::p2v;
+    const SYS_TOY_READ0: usize = 0x2C;
+    const SYS_TOY_READ1: usize = 0x30;
+    let rtc_base_ptr = p2v(LS7A_RTC_VADDR).as_mut_ptr();
+    fn extract_bits(value: u32, range: core::ops::Range<u32>) -> u32 {
+        (value >> range.start) & ((1 << (range.end - range.start)) - 1)
+    }
     let toy_high = unsafe { (rtc_base_ptr.add(SYS_TOY_READ1) as *const u32).read_volatile() };
     let toy_low = unsafe { (rtc_base_ptr.add(SYS_TOY_READ0) as *const u32).read_volatile() };
     let date_time = Utc
@@ -45,12 +58,7 @@ fn init_rtc() {
         .unwrap()
         .with_nanosecond(extract_bits(toy_low, 0..4) * kplat::timer::NANOS_PER_MILLIS as u32)
         .unwrap();
-    if let Some(epoch_time_nanos) = date_time.timestamp_nanos_opt() {
-        unsafe {
-            RTC_EPOCHOFFSET_NANOS =
-                epoch_time_nanos as u64 - GlobalTimerImpl::t2ns(GlobalTimerImpl::now_ticks());
-        }
-    }
+    date_time.timestamp_nanos_opt().map(|nanos| nanos as u64)
 }
 pub(super) fn early_init() {
     NANOS_PER_TICK
@@ -69,6 +77,13 @@ impl GlobalTimer for GlobalTimerImpl {
         unsafe { RTC_EPOCHOFFSET_NANOS }
     }
 
+    fn rtc_ns() -> Option<u64> {
+        #[cfg(feature = "rtc")]
+        return read_rtc();
+        #[cfg(not(feature = "rtc"))]
+        None
+    }
+
     fn t2ns(ticks: u64) -> u64 {
         ticks * *NANOS_PER_TICK
     }
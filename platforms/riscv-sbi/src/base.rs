@@ -0,0 +1,150 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Base extension: SBI version, implementation and extension probing.
+use core::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{SbiResult, ecall};
+
+const EID_BASE: u32 = 0x10;
+const FID_GET_SPEC_VERSION: u32 = 0;
+const FID_GET_IMPL_ID: u32 = 1;
+const FID_GET_IMPL_VERSION: u32 = 2;
+const FID_PROBE_EXTENSION: u32 = 3;
+
+/// An SBI extension the kernel uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Extension {
+    Timer,
+    Ipi,
+    Rfence,
+    Hsm,
+    Srst,
+    Pmu,
+    Dbcn,
+}
+
+impl Extension {
+    const ALL: [Self; 7] = [
+        Self::Timer,
+        Self::Ipi,
+        Self::Rfence,
+        Self::Hsm,
+        Self::Srst,
+        Self::Pmu,
+        Self::Dbcn,
+    ];
+
+    /// Extension ID of the extension.
+    pub const fn eid(self) -> u32 {
+        match self {
+            Self::Timer => 0x5449_4d45,
+            Self::Ipi => 0x0073_5049,
+            Self::Rfence => 0x5246_4e43,
+            Self::Hsm => 0x0048_534d,
+            Self::Srst => 0x5352_5354,
+            Self::Pmu => 0x0050_4d55,
+            Self::Dbcn => 0x4442_434e,
+        }
+    }
+
+    const fn bit(self) -> u32 {
+        1 << self as u8
+    }
+}
+
+/// Set once the extensions have been probed.
+const PROBED: u32 = 1 << 31;
+
+/// Extensions the firmware has, one bit per [`Extension`].
+static EXTENSIONS: AtomicU32 = AtomicU32::new(0);
+
+/// Version of the SBI specification the firmware implements, as
+/// `(major, minor)`. Fails on legacy (v0.1) firmware.
+pub fn spec_version() -> SbiResult<(usize, usize)> {
+    let version = ecall(EID_BASE, FID_GET_SPEC_VERSION, [0; 6])?;
+    Ok(((version >> 24) & 0x7f, version & 0xff_ffff))
+}
+
+/// Implementation ID of the firmware, e.g. 1 for OpenSBI.
+pub fn impl_id() -> SbiResult<usize> {
+    ecall(EID_BASE, FID_GET_IMPL_ID, [0; 6])
+}
+
+/// Version of the firmware, in an implementation-specific encoding.
+pub fn impl_version() -> SbiResult<usize> {
+    ecall(EID_BASE, FID_GET_IMPL_VERSION, [0; 6])
+}
+
+/// Asks the firmware whether it implements extension `eid`.
+pub fn probe_extension(eid: u32) -> bool {
+    ecall(EID_BASE, FID_PROBE_EXTENSION, [eid as usize, 0, 0, 0, 0, 0]).is_ok_and(|it| it != 0)
+}
+
+fn impl_name(id: usize) -> &'static str {
+    match id {
+        0 => "BBL",
+        1 => "OpenSBI",
+        2 => "Xvisor",
+        3 => "KVM",
+        4 => "RustSBI",
+        5 => "Diosix",
+        6 => "Coffer",
+        7 => "Xen",
+        8 => "PolarFire HSS",
+        9 => "coreboot",
+        10 => "oreboot",
+        11 => "bhyve",
+        _ => "unknown",
+    }
+}
+
+/// Lists the extensions in a set of [`EXTENSIONS`] bits.
+struct Found(u32);
+
+impl fmt::Debug for Found {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(Extension::ALL.iter().filter(|it| self.0 & it.bit() != 0))
+            .finish()
+    }
+}
+
+/// Probes the extensions of the firmware, and logs what it offers.
+///
+/// Called by [`has`] when needed, but platforms may call it early to have
+/// the log.
+pub fn init() {
+    let mut found = PROBED;
+    match spec_version() {
+        Ok((major, minor)) => {
+            for ext in Extension::ALL {
+                if probe_extension(ext.eid()) {
+                    found |= ext.bit();
+                }
+            }
+            let id = impl_id().unwrap_or(usize::MAX);
+            info!(
+                "SBI v{major}.{minor}, {} {:#x}, extensions: {:?}",
+                impl_name(id),
+                impl_version().unwrap_or(0),
+                Found(found)
+            );
+        }
+        Err(_) => info!("SBI v0.1, legacy calls only"),
+    }
+    EXTENSIONS.store(found, Ordering::Release);
+}
+
+/// Whether the firmware implements `ext`.
+pub fn has(ext: Extension) -> bool {
+    if EXTENSIONS.load(Ordering::Acquire) & PROBED == 0 {
+        init();
+    }
+    EXTENSIONS.load(Ordering::Acquire) & ext.bit() != 0
+}
@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Debug Console extension: the console of the firmware, for early output
+//! and boards without a UART driver.
+use crate::{Extension, SbiError, SbiResult, ecall, has, legacy_ecall};
+
+const FID_CONSOLE_WRITE: u32 = 0;
+const FID_CONSOLE_READ: u32 = 1;
+const FID_CONSOLE_WRITE_BYTE: u32 = 2;
+const LEGACY_CONSOLE_PUTCHAR: u32 = 0x01;
+
+/// Writes `byte` to the console, blocking until done.
+pub fn console_write_byte(byte: u8) -> SbiResult {
+    if has(Extension::Dbcn) {
+        let args = [byte as usize, 0, 0, 0, 0, 0];
+        return ecall(Extension::Dbcn.eid(), FID_CONSOLE_WRITE_BYTE, args).map(drop);
+    }
+    match legacy_ecall(LEGACY_CONSOLE_PUTCHAR, byte as usize, 0) {
+        0 => Ok(()),
+        _ => Err(SbiError::Failed),
+    }
+}
+
+/// Writes `bytes` to the console a byte at a time, translating `\n` to
+/// `\r\n`.
+pub fn console_write_str(bytes: &[u8]) -> SbiResult {
+    for &byte in bytes {
+        if byte == b'\n' {
+            console_write_byte(b'\r')?;
+        }
+        console_write_byte(byte)?;
+    }
+    Ok(())
+}
+
+/// Writes up to `len` bytes at physical address `paddr` to the console,
+/// without blocking. Returns how many were written.
+pub fn console_write(paddr: usize, len: usize) -> SbiResult<usize> {
+    if !has(Extension::Dbcn) {
+        return Err(SbiError::NotSupported);
+    }
+    ecall(
+        Extension::Dbcn.eid(),
+        FID_CONSOLE_WRITE,
+        [len, paddr, 0, 0, 0, 0],
+    )
+}
+
+/// Reads up to `len` bytes from the console to physical address `paddr`,
+/// without blocking. Returns how many were read.
+pub fn console_read(paddr: usize, len: usize) -> SbiResult<usize> {
+    if !has(Extension::Dbcn) {
+        return Err(SbiError::NotSupported);
+    }
+    ecall(
+        Extension::Dbcn.eid(),
+        FID_CONSOLE_READ,
+        [len, paddr, 0, 0, 0, 0],
+    )
+}
@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Hart State Management extension: starting, stopping and suspending harts.
+//!
+//! Legacy firmware starts every hart at boot and has no equivalent, so all
+//! calls fail with [`SbiError::NotSupported`] without the extension.
+use crate::{Extension, SbiError, SbiResult, ecall, has};
+
+const FID_HART_START: u32 = 0;
+const FID_HART_STOP: u32 = 1;
+const FID_HART_GET_STATUS: u32 = 2;
+const FID_HART_SUSPEND: u32 = 3;
+
+/// Default retentive suspend: the hart resumes after the `hart_suspend`
+/// call with its state kept, like `wfi`.
+pub const SUSPEND_RETENTIVE: u32 = 0;
+/// Default non-retentive suspend: the hart resumes at the resume address,
+/// like after [`hart_start`].
+pub const SUSPEND_NON_RETENTIVE: u32 = 0x8000_0000;
+
+/// State of a hart as seen by the firmware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HartState {
+    Started,
+    Stopped,
+    StartPending,
+    StopPending,
+    Suspended,
+    SuspendPending,
+    ResumePending,
+}
+
+fn hsm_call(fid: u32, args: [usize; 3]) -> SbiResult<usize> {
+    if !has(Extension::Hsm) {
+        return Err(SbiError::NotSupported);
+    }
+    ecall(
+        Extension::Hsm.eid(),
+        fid,
+        [args[0], args[1], args[2], 0, 0, 0],
+    )
+}
+
+/// Starts `hartid` in supervisor mode at physical address `start_addr`,
+/// with the hart ID in `a0`, `opaque` in `a1` and paging disabled.
+pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> SbiResult {
+    hsm_call(FID_HART_START, [hartid, start_addr, opaque]).map(drop)
+}
+
+/// Stops the calling hart, handing it back to the firmware. Only returns on
+/// failure.
+pub fn hart_stop() -> SbiError {
+    match hsm_call(FID_HART_STOP, [0; 3]) {
+        Ok(_) => SbiError::Failed,
+        Err(err) => err,
+    }
+}
+
+/// Returns the state of `hartid`.
+pub fn hart_status(hartid: usize) -> SbiResult<HartState> {
+    Ok(match hsm_call(FID_HART_GET_STATUS, [hartid, 0, 0])? {
+        0 => HartState::Started,
+        1 => HartState::Stopped,
+        2 => HartState::StartPending,
+        3 => HartState::StopPending,
+        4 => HartState::Suspended,
+        5 => HartState::SuspendPending,
+        6 => HartState::ResumePending,
+        _ => return Err(SbiError::Failed),
+    })
+}
+
+/// Suspends the calling hart in `suspend_type`, e.g. [`SUSPEND_RETENTIVE`].
+///
+/// A non-retentive suspend resumes at physical address `resume_addr` as
+/// [`hart_start`] would, with `opaque` in `a1`; the call only returns on
+/// failure then.
+pub fn hart_suspend(suspend_type: u32, resume_addr: usize, opaque: usize) -> SbiResult {
+    hsm_call(
+        FID_HART_SUSPEND,
+        [suspend_type as usize, resume_addr, opaque],
+    )
+    .map(drop)
+}
@@ -0,0 +1,31 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! IPI extension: supervisor software interrupts to other harts.
+use crate::{Extension, SbiError, SbiResult, ecall, has, legacy_ecall};
+
+const FID_SEND_IPI: u32 = 0;
+const LEGACY_SEND_IPI: u32 = 0x04;
+
+/// Sends a software interrupt to the harts in `hart_mask`, bit `i` standing
+/// for hart `hart_mask_base + i`.
+pub fn send_ipi(hart_mask: usize, hart_mask_base: usize) -> SbiResult {
+    if has(Extension::Ipi) {
+        let args = [hart_mask, hart_mask_base, 0, 0, 0, 0];
+        return ecall(Extension::Ipi.eid(), FID_SEND_IPI, args).map(drop);
+    }
+    // The legacy call takes a mask of the harts from 0, by address
+    let mask = u32::try_from(hart_mask_base)
+        .ok()
+        .and_then(|base| {
+            hart_mask
+                .checked_shl(base)
+                .filter(|it| it >> base == hart_mask)
+        })
+        .ok_or(SbiError::InvalidParam)?;
+    match legacy_ecall(LEGACY_SEND_IPI, &mask as *const usize as usize, 0) {
+        0 => Ok(()),
+        _ => Err(SbiError::Failed),
+    }
+}
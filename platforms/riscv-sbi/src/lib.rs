@@ -0,0 +1,118 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Client of the RISC-V Supervisor Binary Interface (SBI).
+//!
+//! Every SBI call of the kernel goes through this crate, with typed
+//! wrappers per extension:
+//!
+//! - [`base`]: the SBI version and the [probing](base::has) of extensions;
+//! - [`timer`] and [`ipi`]: the supervisor timer and inter-processor
+//!   interrupts;
+//! - [`hsm`]: starting, stopping and suspending harts;
+//! - [`srst`]: shutting down and rebooting the system;
+//! - [`pmu`]: the performance monitoring counters;
+//! - [`dbcn`]: the firmware debug console.
+//!
+//! The extensions are probed once, on the first call needing one. Where the
+//! legacy SBI v0.1 has an equivalent, it is used on firmware lacking the
+//! extension; otherwise the wrappers fail with [`SbiError::NotSupported`].
+#![cfg(target_arch = "riscv64")]
+#![no_std]
+#[macro_use]
+extern crate log;
+
+pub mod base;
+pub mod dbcn;
+pub mod hsm;
+pub mod ipi;
+pub mod pmu;
+pub mod srst;
+pub mod timer;
+
+pub use base::{Extension, has};
+
+/// Errors of SBI calls, from the standard error codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbiError {
+    Failed,
+    NotSupported,
+    InvalidParam,
+    Denied,
+    InvalidAddress,
+    AlreadyAvailable,
+    AlreadyStarted,
+    AlreadyStopped,
+    NoSharedMemory,
+    InvalidState,
+    BadRange,
+    Timeout,
+    Io,
+}
+
+impl SbiError {
+    fn from_code(code: isize) -> Self {
+        use SbiError::*;
+        match code {
+            -2 => NotSupported,
+            -3 => InvalidParam,
+            -4 => Denied,
+            -5 => InvalidAddress,
+            -6 => AlreadyAvailable,
+            -7 => AlreadyStarted,
+            -8 => AlreadyStopped,
+            -9 => NoSharedMemory,
+            -10 => InvalidState,
+            -11 => BadRange,
+            -12 => Timeout,
+            -13 => Io,
+            // SBI_ERR_FAILED, and codes of later specifications
+            _ => Failed,
+        }
+    }
+}
+
+/// Result of an SBI call.
+pub type SbiResult<T = ()> = Result<T, SbiError>;
+
+/// Calls function `fid` of extension `eid`, unused arguments being 0.
+#[inline]
+pub(crate) fn ecall(eid: u32, fid: u32, args: [usize; 6]) -> SbiResult<usize> {
+    let (error, value): (isize, usize);
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") args[0] => error,
+            inlateout("a1") args[1] => value,
+            in("a2") args[2],
+            in("a3") args[3],
+            in("a4") args[4],
+            in("a5") args[5],
+            in("a6") fid,
+            in("a7") eid,
+            options(nostack),
+        )
+    }
+    if error == 0 {
+        Ok(value)
+    } else {
+        Err(SbiError::from_code(error))
+    }
+}
+
+/// Calls legacy (v0.1) extension `eid`, which returns a single value.
+#[inline]
+pub(crate) fn legacy_ecall(eid: u32, arg0: usize, arg1: usize) -> isize {
+    let ret;
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") arg0 => ret,
+            in("a1") arg1,
+            in("a7") eid,
+            options(nostack),
+        )
+    }
+    ret
+}
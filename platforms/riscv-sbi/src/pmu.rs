@@ -0,0 +1,151 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Performance Monitoring Unit extension.
+//!
+//! The firmware maps events to counters: [`counter_config_matching`] picks
+//! a counter able to count an event among a set, then the counter is read
+//! directly through its CSR, or with [`counter_fw_read`] for firmware
+//! counters.
+use crate::{Extension, SbiError, SbiResult, ecall, has};
+
+const FID_NUM_COUNTERS: u32 = 0;
+const FID_COUNTER_GET_INFO: u32 = 1;
+const FID_COUNTER_CONFIG_MATCHING: u32 = 2;
+const FID_COUNTER_START: u32 = 3;
+const FID_COUNTER_STOP: u32 = 4;
+const FID_COUNTER_FW_READ: u32 = 5;
+
+/// Hardware general events, as event indices of type 0.
+pub mod event {
+    pub const CPU_CYCLES: usize = 1;
+    pub const INSTRUCTIONS: usize = 2;
+    pub const CACHE_REFERENCES: usize = 3;
+    pub const CACHE_MISSES: usize = 4;
+    pub const BRANCH_INSTRUCTIONS: usize = 5;
+    pub const BRANCH_MISSES: usize = 6;
+    pub const BUS_CYCLES: usize = 7;
+    pub const STALLED_CYCLES_FRONTEND: usize = 8;
+    pub const STALLED_CYCLES_BACKEND: usize = 9;
+    pub const REF_CPU_CYCLES: usize = 10;
+}
+
+/// [`counter_config_matching`]: use the first counter of the set, without
+/// matching.
+pub const CFG_FLAG_SKIP_MATCH: usize = 1 << 0;
+/// [`counter_config_matching`]: reset the counter to 0.
+pub const CFG_FLAG_CLEAR_VALUE: usize = 1 << 1;
+/// [`counter_config_matching`]: start the counter right away.
+pub const CFG_FLAG_AUTO_START: usize = 1 << 2;
+/// [`counter_config_matching`]: do not count in VU-mode.
+pub const CFG_FLAG_SET_VUINH: usize = 1 << 3;
+/// [`counter_config_matching`]: do not count in VS-mode.
+pub const CFG_FLAG_SET_VSINH: usize = 1 << 4;
+/// [`counter_config_matching`]: do not count in U-mode.
+pub const CFG_FLAG_SET_UINH: usize = 1 << 5;
+/// [`counter_config_matching`]: do not count in S-mode.
+pub const CFG_FLAG_SET_SINH: usize = 1 << 6;
+/// [`counter_config_matching`]: do not count in M-mode.
+pub const CFG_FLAG_SET_MINH: usize = 1 << 7;
+/// [`counter_start`]: set the counters to the initial value given.
+pub const START_FLAG_SET_INIT_VALUE: usize = 1 << 0;
+/// [`counter_stop`]: release the counters from their events.
+pub const STOP_FLAG_RESET: usize = 1 << 0;
+
+/// What a counter is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CounterInfo {
+    /// A hardware counter, read through CSR `csr`, `width` bits wide.
+    Hardware { csr: u16, width: u8 },
+    /// A counter kept by the firmware, read with [`counter_fw_read`].
+    Firmware,
+}
+
+fn pmu_call(fid: u32, args: [usize; 6]) -> SbiResult<usize> {
+    if !has(Extension::Pmu) {
+        return Err(SbiError::NotSupported);
+    }
+    ecall(Extension::Pmu.eid(), fid, args)
+}
+
+/// Returns the number of counters, hardware and firmware.
+pub fn num_counters() -> SbiResult<usize> {
+    pmu_call(FID_NUM_COUNTERS, [0; 6])
+}
+
+/// Describes counter `counter_idx`.
+pub fn counter_info(counter_idx: usize) -> SbiResult<CounterInfo> {
+    let info = pmu_call(FID_COUNTER_GET_INFO, [counter_idx, 0, 0, 0, 0, 0])?;
+    Ok(if info >> (usize::BITS - 1) != 0 {
+        CounterInfo::Firmware
+    } else {
+        CounterInfo::Hardware {
+            csr: (info & 0xfff) as u16,
+            width: ((info >> 12) & 0x3f) as u8 + 1,
+        }
+    })
+}
+
+/// Configures a counter among those of `counter_idx_mask` (bit `i` standing
+/// for counter `counter_idx_base + i`) to count `event_idx`, and returns its
+/// index.
+pub fn counter_config_matching(
+    counter_idx_base: usize,
+    counter_idx_mask: usize,
+    config_flags: usize,
+    event_idx: usize,
+    event_data: u64,
+) -> SbiResult<usize> {
+    pmu_call(
+        FID_COUNTER_CONFIG_MATCHING,
+        [
+            counter_idx_base,
+            counter_idx_mask,
+            config_flags,
+            event_idx,
+            event_data as usize,
+            0,
+        ],
+    )
+}
+
+/// Starts the counters of the set, from `initial_value` with
+/// [`START_FLAG_SET_INIT_VALUE`].
+pub fn counter_start(
+    counter_idx_base: usize,
+    counter_idx_mask: usize,
+    start_flags: usize,
+    initial_value: u64,
+) -> SbiResult {
+    pmu_call(
+        FID_COUNTER_START,
+        [
+            counter_idx_base,
+            counter_idx_mask,
+            start_flags,
+            initial_value as usize,
+            0,
+            0,
+        ],
+    )
+    .map(drop)
+}
+
+/// Stops the counters of the set.
+pub fn counter_stop(
+    counter_idx_base: usize,
+    counter_idx_mask: usize,
+    stop_flags: usize,
+) -> SbiResult {
+    pmu_call(
+        FID_COUNTER_STOP,
+        [counter_idx_base, counter_idx_mask, stop_flags, 0, 0, 0],
+    )
+    .map(drop)
+}
+
+/// Reads firmware counter `counter_idx`.
+pub fn counter_fw_read(counter_idx: usize) -> SbiResult<u64> {
+    pmu_call(FID_COUNTER_FW_READ, [counter_idx, 0, 0, 0, 0, 0]).map(|it| it as u64)
+}
@@ -0,0 +1,44 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! System Reset extension: shutting down and rebooting.
+use crate::{Extension, SbiError, ecall, has, legacy_ecall};
+
+const FID_SYSTEM_RESET: u32 = 0;
+const LEGACY_SHUTDOWN: u32 = 0x08;
+
+/// What a system reset does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ResetType {
+    Shutdown   = 0,
+    ColdReboot = 1,
+    WarmReboot = 2,
+}
+
+/// Why the system is reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ResetReason {
+    NoReason      = 0,
+    SystemFailure = 1,
+}
+
+/// Resets the system. Only returns on failure.
+///
+/// Legacy firmware can only shut down.
+pub fn system_reset(reset_type: ResetType, reason: ResetReason) -> SbiError {
+    if has(Extension::Srst) {
+        let args = [reset_type as usize, reason as usize, 0, 0, 0, 0];
+        return match ecall(Extension::Srst.eid(), FID_SYSTEM_RESET, args) {
+            Ok(_) => SbiError::Failed,
+            Err(err) => err,
+        };
+    }
+    if reset_type == ResetType::Shutdown {
+        legacy_ecall(LEGACY_SHUTDOWN, 0, 0);
+        return SbiError::Failed;
+    }
+    SbiError::NotSupported
+}
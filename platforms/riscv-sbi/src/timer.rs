@@ -0,0 +1,22 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Timer extension.
+use crate::{Extension, ecall, has, legacy_ecall};
+
+const FID_SET_TIMER: u32 = 0;
+const LEGACY_SET_TIMER: u32 = 0x00;
+
+/// Arms the supervisor timer of the calling hart for when `time` reaches
+/// `stime_value`, and clears the pending timer interrupt.
+pub fn set_timer(stime_value: u64) {
+    if has(Extension::Timer) {
+        let args = [stime_value as usize, 0, 0, 0, 0, 0];
+        if let Err(err) = ecall(Extension::Timer.eid(), FID_SET_TIMER, args) {
+            warn!("set_timer failed: {err:?}");
+        }
+    } else {
+        legacy_ecall(LEGACY_SET_TIMER, stime_value as usize, 0);
+    }
+}
@@ -9,6 +9,7 @@ impl BootHandler for BootHandlerImpl {
     fn early_init(_cpu_id: usize, _mbi: usize) {
         kcpu::boot::init_trap();
         crate::console::early_init();
+        riscv_sbi::base::init();
         crate::time::early_init();
     }
 
@@ -28,3 +29,15 @@ impl BootHandler for BootHandlerImpl {
         crate::time::init_percpu();
     }
 }
+
+struct BoardInfoImpl;
+#[impl_dev_interface]
+impl kplat::board::BoardInfo for BoardInfoImpl {
+    fn uart_paddr() -> Option<usize> {
+        Some(crate::config::devices::UART_PADDR)
+    }
+
+    fn intc_paddr() -> Option<usize> {
+        Some(crate::config::devices::PLIC_PADDR)
+    }
+}
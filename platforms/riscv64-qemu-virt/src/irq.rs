@@ -15,7 +15,7 @@ use kplat::{
 use kspin::SpinNoIrq;
 use riscv::register::sie;
 use riscv_plic::Plic;
-use sbi_rt::HartMask;
+use riscv_sbi::ipi::send_ipi;
 
 use crate::config::{devices::PLIC_PADDR, plat::PHYS_VIRT_OFFSET};
 pub(super) const INTC_IRQ_BASE: usize = 1 << (usize::BITS - 1);
@@ -184,13 +184,13 @@ impl IntrManager for IntrManagerImpl {
     fn notify_cpu(_interrupt_id: usize, target: TargetCpu) {
         match target {
             TargetCpu::Self_ => {
-                let res = sbi_rt::send_ipi(HartMask::from_mask_base(1 << this_cpu_id(), 0));
+                let res = send_ipi(1 << this_cpu_id(), 0);
                 if res.is_err() {
                     warn!("notify_cpu failed: {res:?}");
                 }
             }
             TargetCpu::Specific(cpu_id) => {
-                let res = sbi_rt::send_ipi(HartMask::from_mask_base(1 << cpu_id, 0));
+                let res = send_ipi(1 << cpu_id, 0);
                 if res.is_err() {
                     warn!("notify_cpu failed: {res:?}");
                 }
@@ -201,7 +201,7 @@ impl IntrManager for IntrManagerImpl {
             } => {
                 for i in 0..cpu_num {
                     if i != cpu_id {
-                        let res = sbi_rt::send_ipi(HartMask::from_mask_base(1 << i, 0));
+                        let res = send_ipi(1 << i, 0);
                         if res.is_err() {
                             warn!("notify_cpu_all_others failed: {res:?}");
                         }
@@ -2,25 +2,37 @@
 // Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
 // See LICENSES for license details.
 
-use kplat::sys::SysCtrl;
+use kplat::sys::{RebootMode, SysCtrl};
+use riscv_sbi::srst::{ResetReason, ResetType, system_reset};
 struct PowerImpl;
 #[impl_dev_interface]
 impl SysCtrl for PowerImpl {
     #[cfg(feature = "smp")]
     fn boot_ap(cpu_id: usize, stack_top_paddr: usize) {
         use kplat::memory::{v2p, va};
-        if sbi_rt::probe_extension(sbi_rt::Hsm).is_unavailable() {
-            warn!("HSM SBI extension is not supported for current SEE.");
-            return;
-        }
         let entry = v2p(va!(crate::boot::_start_secondary as *const () as usize));
-        sbi_rt::hart_start(cpu_id, entry.as_usize(), stack_top_paddr);
+        if let Err(e) = riscv_sbi::hsm::hart_start(cpu_id, entry.as_usize(), stack_top_paddr) {
+            warn!("failed to start hart {cpu_id}: {e:?}");
+        }
     }
 
     fn shutdown() -> ! {
         info!("Shutting down...");
-        sbi_rt::system_reset(sbi_rt::Shutdown, sbi_rt::NoReason);
-        warn!("It should shutdown!");
+        let e = system_reset(ResetType::Shutdown, ResetReason::NoReason);
+        warn!("It should shutdown! ({e:?})");
+        loop {
+            kcpu::instrs::stop_cpu();
+        }
+    }
+
+    fn reboot(mode: RebootMode) -> ! {
+        info!("Rebooting...");
+        if mode == RebootMode::Warm {
+            let e = system_reset(ResetType::WarmReboot, ResetReason::NoReason);
+            debug!("warm reset failed ({e:?}), resetting cold");
+        }
+        let e = system_reset(ResetType::ColdReboot, ResetReason::NoReason);
+        warn!("It should reboot! ({e:?})");
         loop {
             kcpu::instrs::stop_cpu();
         }
@@ -7,13 +7,7 @@ use riscv::register::time;
 const NANOS_PER_TICK: u64 = NS_SEC / crate::config::devices::TIMER_FREQUENCY as u64;
 static mut RTC_EPOCHOFFSET_NANOS: u64 = 0;
 pub(super) fn early_init() {
-    #[cfg(feature = "rtc")]
-    use crate::config::{devices::RTC_PADDR, plat::PHYS_VIRT_OFFSET};
-    #[cfg(feature = "rtc")]
-    if RTC_PADDR != 0 {
-        use riscv_goldfish::Rtc;
-        let epoch_time_nanos =
-            Rtc::new(RTC_PADDR + PHYS_VIRT_OFFSET).get_unix_timestamp() * 1_000_000_000;
+    if let Some(epoch_time_nanos) = GlobalTimerImpl::rtc_ns() {
         unsafe {
             RTC_EPOCHOFFSET_NANOS =
                 epoch_time_nanos - GlobalTimerImpl::t2ns(GlobalTimerImpl::now_ticks());
@@ -21,7 +15,7 @@ pub(super) fn early_init() {
     }
 }
 pub(super) fn init_percpu() {
-    sbi_rt::set_timer(0);
+    riscv_sbi::timer::set_timer(0);
 }
 struct GlobalTimerImpl;
 #[impl_dev_interface]
@@ -42,6 +36,20 @@ impl GlobalTimer for GlobalTimerImpl {
         unsafe { RTC_EPOCHOFFSET_NANOS }
     }
 
+    fn rtc_ns() -> Option<u64> {
+        #[cfg(feature = "rtc")]
+        {
+            use crate::config::{devices::RTC_PADDR, plat::PHYS_VIRT_OFFSET};
+            if RTC_PADDR != 0 {
+                use riscv_goldfish::Rtc;
+                return Some(
+                    Rtc::new(RTC_PADDR + PHYS_VIRT_OFFSET).get_unix_timestamp() * 1_000_000_000,
+                );
+            }
+        }
+        None
+    }
+
     fn freq() -> u64 {
         crate::config::devices::TIMER_FREQUENCY as u64
     }
@@ -51,6 +59,6 @@ impl GlobalTimer for GlobalTimerImpl {
     }
 
     fn arm_timer(deadline_ns: u64) {
-        sbi_rt::set_timer(Self::ns2t(deadline_ns));
+        riscv_sbi::timer::set_timer(Self::ns2t(deadline_ns));
     }
 }
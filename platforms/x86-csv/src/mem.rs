@@ -69,6 +69,10 @@ impl HwMemory for HwMemoryImpl {
         )]
     }
 
+    fn cma_size() -> usize {
+        crate::config::plat::CMA_MEM_SIZE
+    }
+
     /// Returns all device memory (MMIO) ranges on the platform.
     fn mmio_regions() -> &'static [MemRange] {
         &MMIO_RANGES
@@ -2,7 +2,7 @@
 // Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
 // See LICENSES for license details.
 
-use kplat::sys::SysCtrl;
+use kplat::sys::{RebootMode, SysCtrl};
 use x86_64::instructions::port::PortWriteOnly;
 struct PowerImpl;
 #[impl_dev_interface]
@@ -29,4 +29,20 @@ impl SysCtrl for PowerImpl {
             kcpu::instrs::stop_cpu();
         }
     }
+
+    fn reboot(mode: RebootMode) -> ! {
+        info!("Rebooting...");
+        if mode != RebootMode::Warm {
+            // Full reset through the reset control register of the chipset,
+            // cycling the power
+            unsafe { PortWriteOnly::new(0xcf9).write(0x0eu8) };
+        }
+        // Pulse the reset line through the keyboard controller
+        unsafe { PortWriteOnly::new(0x64).write(0xfeu8) };
+        kcpu::instrs::stop_cpu();
+        warn!("It should reboot!");
+        loop {
+            kcpu::instrs::stop_cpu();
+        }
+    }
 }
@@ -67,6 +67,13 @@ impl GlobalTimer for GlobalTimerImpl {
         unsafe { RTC_EPOCHOFFSET_NANOS }
     }
 
+    fn rtc_ns() -> Option<u64> {
+        #[cfg(feature = "rtc")]
+        return Some(x86_rtc::Rtc::new().get_unix_timestamp() * 1_000_000_000);
+        #[cfg(not(feature = "rtc"))]
+        None
+    }
+
     fn arm_timer(deadline_ns: u64) {
         let lapic = super::apic::local_apic();
         let now_ns = Self::t2ns(Self::now_ticks());
@@ -31,3 +31,15 @@ impl BootHandler for BootHandlerImpl {
         crate::time::init_secondary();
     }
 }
+
+struct BoardInfoImpl;
+#[impl_dev_interface]
+impl kplat::board::BoardInfo for BoardInfoImpl {
+    fn uart_paddr() -> Option<usize> {
+        None
+    }
+
+    fn intc_paddr() -> Option<usize> {
+        None
+    }
+}
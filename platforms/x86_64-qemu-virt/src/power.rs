@@ -4,7 +4,7 @@
 
 //! Power control implementation for x86_64-qemu-virt.
 
-use kplat::sys::SysCtrl;
+use kplat::sys::{RebootMode, SysCtrl};
 use x86_64::instructions::port::PortWriteOnly;
 struct PowerImpl;
 #[impl_dev_interface]
@@ -31,4 +31,20 @@ impl SysCtrl for PowerImpl {
             kcpu::instrs::stop_cpu();
         }
     }
+
+    fn reboot(mode: RebootMode) -> ! {
+        info!("Rebooting...");
+        if mode != RebootMode::Warm {
+            // Full reset through the reset control register of the chipset,
+            // cycling the power
+            unsafe { PortWriteOnly::new(0xcf9).write(0x0eu8) };
+        }
+        // Pulse the reset line through the keyboard controller
+        unsafe { PortWriteOnly::new(0x64).write(0xfeu8) };
+        kcpu::instrs::stop_cpu();
+        warn!("It should reboot!");
+        loop {
+            kcpu::instrs::stop_cpu();
+        }
+    }
 }
@@ -72,6 +72,13 @@ impl GlobalTimer for GlobalTimerImpl {
         unsafe { RTC_EPOCHOFFSET_NANOS }
     }
 
+    fn rtc_ns() -> Option<u64> {
+        #[cfg(feature = "rtc")]
+        return Some(x86_rtc::Rtc::new().get_unix_timestamp() * 1_000_000_000);
+        #[cfg(not(feature = "rtc"))]
+        None
+    }
+
     fn freq() -> u64 {
         crate::config::devices::TIMER_FREQUENCY as u64
     }
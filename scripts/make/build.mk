@@ -27,6 +27,14 @@ else ifneq ($(filter $(or $(MAKECMDGOALS), $(.DEFAULT_GOAL)), all build run just
   ifeq ($(DWARF), y)
     RUSTFLAGS += -C force-frame-pointers -C debuginfo=2 -C strip=none
   endif
+  ifeq ($(KASAN), y)
+    # Outline checks calling the hooks of kalloc, the stack and globals left
+    # out. The prebuilt core and alloc are not instrumented.
+    RUSTFLAGS += -Zsanitizer=kernel-address -Cunsafe-allow-abi-mismatch=sanitizer \
+      -Cllvm-args=-asan-instrumentation-with-call-threshold=0 \
+      -Cllvm-args=-asan-stack=0 -Cllvm-args=-asan-globals=0 \
+      -Cllvm-args=-asan-kernel-mem-intrinsic-prefix=1
+  endif
   $(if $(V), $(info RUSTFLAGS: "$(RUSTFLAGS)"))
   export RUSTFLAGS
   ifeq ($(LTO), y)
@@ -17,6 +17,10 @@ ifeq ($(DWARF),y)
   kfeat += dwarf
 endif
 
+ifeq ($(KASAN),y)
+  kfeat += kasan
+endif
+
 APP_FEATURES += $(subst -,_,$(PLAT))
 
 KFEAT := $(strip $(addprefix $(kfeat_prefix),$(kfeat)))
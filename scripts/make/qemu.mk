@@ -19,9 +19,9 @@ NET_DEV ?= user
 VFIO_PCI ?=
 VHOST ?= n
 
-# Network options
-IP ?= 10.0.2.15
-GW ?= 10.0.2.2
+# Network options, configured by DHCP if empty
+IP ?=
+GW ?=
 
 QEMU := qemu-system-$(ARCH)
 
@@ -34,7 +34,11 @@
 //! # Feature Flags
 //!
 //! - `smp`: Enable for multi-core systems (adds atomic lock state)
+//! - `queued`: Use queued (MCS) locks on multi-core systems, fair and
+//!   scalable under contention on many CPUs (implies `smp`)
 //! - `preempt`: Enable preemption control (requires implementing [`KernelGuardIf`])
+//! - `lockdep`: Validate the order locks are taken in (requires implementing
+//!   [`lockdep::LockdepIf`])
 //!
 //! # Usage Patterns
 //!
@@ -97,6 +101,10 @@ extern crate alloc;
 
 mod guard;
 mod lock;
+#[cfg(feature = "lockdep")]
+pub mod lockdep;
+#[cfg(feature = "smp")]
+mod raw;
 mod tests;
 
 pub use guard::{BaseGuard, IrqSave, KernelGuardIf, NoOp, NoPreempt, NoPreemptIrqSave};
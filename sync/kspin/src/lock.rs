@@ -7,8 +7,8 @@
 //! This module provides a generic spinlock that can be configured
 //! with different guard types to control preemption and interrupts.
 
-#[cfg(feature = "smp")]
-use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "lockdep")]
+use core::panic::Location;
 use core::{
     cell::UnsafeCell,
     fmt,
@@ -17,6 +17,10 @@ use core::{
 };
 
 use crate::guard::BaseGuard;
+#[cfg(feature = "lockdep")]
+use crate::lockdep::{self, ClassKey, LockMode};
+#[cfg(feature = "smp")]
+use crate::raw::RawLock;
 
 /// A spinlock with configurable guard behavior.
 ///
@@ -31,6 +35,12 @@ use crate::guard::BaseGuard;
 /// Without the `smp` feature, the lock state is optimized away since
 /// no actual atomic synchronization is needed.
 ///
+/// # Queued locking
+///
+/// With the `queued` feature, the waiters line up and take the lock in
+/// order, each spinning on its own cache line, instead of all spinning on
+/// the lock; this scales better on machines with many CPUs.
+///
 /// # Examples
 ///
 /// ```rust,ignore
@@ -46,7 +56,10 @@ use crate::guard::BaseGuard;
 pub struct SpinLock<G: BaseGuard, T: ?Sized> {
     _phantom: PhantomData<G>,
     #[cfg(feature = "smp")]
-    lock: AtomicBool,
+    lock: RawLock,
+    /// Where the lock was created, its lockdep class.
+    #[cfg(feature = "lockdep")]
+    class: &'static Location<'static>,
     data: UnsafeCell<T>,
 }
 
@@ -59,7 +72,9 @@ pub struct SpinLockGuard<'a, G: BaseGuard, T: ?Sized + 'a> {
     guard_state: G::State,
     data: *mut T,
     #[cfg(feature = "smp")]
-    lock: &'a AtomicBool,
+    lock: &'a RawLock,
+    #[cfg(feature = "lockdep")]
+    lock_addr: usize,
 }
 
 // Same unsafe impls as `std::sync::Mutex`
@@ -69,12 +84,15 @@ unsafe impl<G: BaseGuard, T: ?Sized + Send> Send for SpinLock<G, T> {}
 impl<G: BaseGuard, T> SpinLock<G, T> {
     /// Create a new spinlock.
     #[inline(always)]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub const fn new(data: T) -> Self {
         Self {
             _phantom: PhantomData,
             data: UnsafeCell::new(data),
             #[cfg(feature = "smp")]
-            lock: AtomicBool::new(false),
+            lock: RawLock::new(),
+            #[cfg(feature = "lockdep")]
+            class: Location::caller(),
         }
     }
 
@@ -92,23 +110,20 @@ impl<G: BaseGuard, T: ?Sized> SpinLock<G, T> {
     ///
     /// May panic or deadlock if called while already holding the lock.
     #[inline(always)]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn lock(&self) -> SpinLockGuard<'_, G, T> {
         let guard_state = G::acquire();
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(
+            self.addr(),
+            ClassKey::Site(self.class),
+            false,
+            LockMode::Exclusive,
+            Location::caller(),
+        );
 
         #[cfg(feature = "smp")]
-        {
-            // Try to acquire using weak CAS in a loop
-            while self
-                .lock
-                .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
-                .is_err()
-            {
-                // Spin until lock appears available
-                while self.is_locked() {
-                    core::hint::spin_loop();
-                }
-            }
-        }
+        self.lock.lock();
 
         SpinLockGuard {
             _phantom: &PhantomData,
@@ -116,9 +131,16 @@ impl<G: BaseGuard, T: ?Sized> SpinLock<G, T> {
             data: unsafe { &mut *self.data.get() },
             #[cfg(feature = "smp")]
             lock: &self.lock,
+            #[cfg(feature = "lockdep")]
+            lock_addr: self.addr(),
         }
     }
 
+    #[cfg(feature = "lockdep")]
+    fn addr(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+
     /// Check if lock is currently held (heuristic only).
     ///
     /// # Warning
@@ -129,7 +151,7 @@ impl<G: BaseGuard, T: ?Sized> SpinLock<G, T> {
     pub fn is_locked(&self) -> bool {
         #[cfg(feature = "smp")]
         {
-            self.lock.load(Ordering::Relaxed)
+            self.lock.is_locked()
         }
         #[cfg(not(feature = "smp"))]
         {
@@ -141,25 +163,33 @@ impl<G: BaseGuard, T: ?Sized> SpinLock<G, T> {
     ///
     /// Returns `Some(guard)` if successful, `None` if already locked.
     #[inline(always)]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     pub fn try_lock(&self) -> Option<SpinLockGuard<'_, G, T>> {
         let guard_state = G::acquire();
 
         #[cfg(feature = "smp")]
-        let is_unlocked = self
-            .lock
-            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
-            .is_ok();
+        let is_unlocked = self.lock.try_lock();
 
         #[cfg(not(feature = "smp"))]
         let is_unlocked = true;
 
         if is_unlocked {
+            #[cfg(feature = "lockdep")]
+            lockdep::acquire(
+                self.addr(),
+                ClassKey::Site(self.class),
+                false,
+                LockMode::Try,
+                Location::caller(),
+            );
             Some(SpinLockGuard {
                 _phantom: &PhantomData,
                 guard_state,
                 data: unsafe { &mut *self.data.get() },
                 #[cfg(feature = "smp")]
                 lock: &self.lock,
+                #[cfg(feature = "lockdep")]
+                lock_addr: self.addr(),
             })
         } else {
             G::release(guard_state);
@@ -175,8 +205,10 @@ impl<G: BaseGuard, T: ?Sized> SpinLock<G, T> {
     /// Violating this may cause data races.
     #[inline(always)]
     pub unsafe fn force_unlock(&self) {
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.addr(), false);
         #[cfg(feature = "smp")]
-        self.lock.store(false, Ordering::Release);
+        self.lock.unlock();
     }
 
     /// Get mutable reference (zero-cost).
@@ -191,6 +223,7 @@ impl<G: BaseGuard, T: ?Sized> SpinLock<G, T> {
 
 impl<G: BaseGuard, T: Default> Default for SpinLock<G, T> {
     #[inline(always)]
+    #[cfg_attr(feature = "lockdep", track_caller)]
     fn default() -> Self {
         Self::new(Default::default())
     }
@@ -233,8 +266,10 @@ impl<G: BaseGuard, T: ?Sized + fmt::Debug> fmt::Debug for SpinLockGuard<'_, G, T
 impl<G: BaseGuard, T: ?Sized> Drop for SpinLockGuard<'_, G, T> {
     #[inline(always)]
     fn drop(&mut self) {
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.lock_addr, false);
         #[cfg(feature = "smp")]
-        self.lock.store(false, Ordering::Release);
+        self.lock.unlock();
 
         G::release(self.guard_state);
     }
@@ -0,0 +1,550 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Lock dependency validator (lockdep).
+//!
+//! Each lock belongs to a class: the spinlocks created at the same place in
+//! the code share a class, and the sleeping locks of `ksync` are a class
+//! each. The validator records the order the classes are taken in, as
+//! dependencies from the locks held to the lock taken, and reports:
+//!
+//! - a lock taken again by its holder,
+//! - a lock taken while holding a lock that was taken after it before,
+//!   which may deadlock two tasks taking them in those orders,
+//! - a sleeping lock taken in IRQ context, or while holding a spinlock.
+//!
+//! The spinlocks held are tracked per CPU and the sleeping locks per task,
+//! in the [`HeldLocks`] the kernel provides through [`LockdepIf`]. The first
+//! violation is reported through [`LockdepIf::report`], then the validator
+//! turns itself off, as it does once it runs out of room for the classes.
+
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    mem::MaybeUninit,
+    panic::Location,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Lock classes the validator tracks at most.
+const MAX_CLASSES: usize = 512;
+/// Locks a CPU or a task can hold at once.
+const MAX_HELD: usize = 32;
+
+const WORDS: usize = MAX_CLASSES / 64;
+
+/// Key of a free class slot.
+const FREE: usize = 0;
+/// Key of a forgotten class slot, skipped by the lookups.
+const FORGOTTEN: usize = 1;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Hooks the kernel provides to the validator.
+#[crate_interface::def_interface]
+pub trait LockdepIf {
+    /// Returns the spinlocks held by the current CPU, or with `sleeping`, the
+    /// sleeping locks held by the current task.
+    ///
+    /// Returns null while there are none to track, e.g. before the tasks are
+    /// set up.
+    fn held_locks(sleeping: bool) -> *const HeldLocks;
+
+    /// Returns whether the current CPU is handling an interrupt.
+    fn in_irq() -> bool;
+
+    /// Reports a violation, with the validator turned off already.
+    fn report(report: &Report);
+}
+
+/// The key of a lock class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassKey {
+    /// The locks created at this place in the code.
+    Site(&'static Location<'static>),
+    /// The lock at this address.
+    Addr(usize),
+}
+
+impl ClassKey {
+    fn raw(self) -> usize {
+        match self {
+            Self::Site(site) => site as *const Location<'static> as usize,
+            Self::Addr(addr) => addr,
+        }
+    }
+}
+
+impl fmt::Display for ClassKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Site(site) => write!(f, "lock created at {site}"),
+            Self::Addr(addr) => write!(f, "lock at {addr:#x}"),
+        }
+    }
+}
+
+/// How a lock is taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Taken exclusively, waiting for it.
+    Exclusive,
+    /// Taken shared with other readers, waiting for it.
+    Shared,
+    /// Taken without waiting, which cannot deadlock.
+    Try,
+}
+
+/// A lock held.
+#[derive(Debug, Clone, Copy)]
+pub struct HeldLock {
+    /// Address of the lock.
+    pub addr: usize,
+    /// Class of the lock.
+    pub class: ClassKey,
+    /// Whether it is a sleeping lock.
+    pub sleeping: bool,
+    /// How it was taken.
+    pub mode: LockMode,
+    /// Where it was taken.
+    pub site: &'static Location<'static>,
+    index: u16,
+}
+
+impl fmt::Display for HeldLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.sleeping { "sleeping" } else { "spin" };
+        write!(f, "{} ({kind}), taken at {}", self.class, self.site)
+    }
+}
+
+/// The locks held by a CPU or a task, in the order they were taken.
+pub struct HeldLocks {
+    locks: UnsafeCell<[MaybeUninit<HeldLock>; MAX_HELD]>,
+    depth: UnsafeCell<usize>,
+}
+
+// Only accessed by the CPU or the task holding the locks, with IRQs disabled.
+unsafe impl Sync for HeldLocks {}
+
+impl HeldLocks {
+    /// Creates an empty set of locks held.
+    pub const fn new() -> Self {
+        Self {
+            locks: UnsafeCell::new([const { MaybeUninit::uninit() }; MAX_HELD]),
+            depth: UnsafeCell::new(0),
+        }
+    }
+
+    /// Returns the locks held.
+    ///
+    /// # Safety
+    ///
+    /// Must be called by the CPU or the task holding them, with IRQs
+    /// disabled.
+    pub unsafe fn as_slice(&self) -> &[HeldLock] {
+        unsafe {
+            let locks = &*self.locks.get();
+            core::slice::from_raw_parts(locks.as_ptr().cast(), *self.depth.get())
+        }
+    }
+
+    unsafe fn push(&self, lock: HeldLock) {
+        unsafe {
+            let depth = &mut *self.depth.get();
+            // Not tracked once full: its release is then ignored.
+            if *depth < MAX_HELD {
+                (*self.locks.get())[*depth].write(lock);
+                *depth += 1;
+            }
+        }
+    }
+
+    unsafe fn remove(&self, addr: usize) {
+        unsafe {
+            let depth = &mut *self.depth.get();
+            let locks = &mut *self.locks.get();
+            let held = core::slice::from_raw_parts(locks.as_ptr().cast::<HeldLock>(), *depth);
+            // Not found if taken before the locks were tracked.
+            if let Some(i) = held.iter().rposition(|lock| lock.addr == addr) {
+                locks.copy_within(i + 1..*depth, i);
+                *depth -= 1;
+            }
+        }
+    }
+}
+
+impl Default for HeldLocks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A violation of the locking rules.
+#[derive(Debug, Clone, Copy)]
+pub enum Violation {
+    /// The lock is held already.
+    Recursive,
+    /// The lock was taken after the lock held before.
+    Inversion {
+        /// The lock held.
+        held: HeldLock,
+    },
+    /// A sleeping lock is taken in IRQ context.
+    SleepInIrq,
+    /// A sleeping lock is taken while holding a spinlock.
+    SleepUnderSpin {
+        /// The spinlock held.
+        held: HeldLock,
+    },
+}
+
+/// A violation of the locking rules, as the validator reports it.
+pub struct Report<'a> {
+    /// The violation.
+    pub violation: Violation,
+    /// The lock being taken.
+    pub lock: HeldLock,
+    /// The spinlocks held by the current CPU.
+    pub held_spin: &'a [HeldLock],
+    /// The sleeping locks held by the current task.
+    pub held_sleeping: &'a [HeldLock],
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lock = &self.lock;
+        match &self.violation {
+            Violation::Recursive => {
+                writeln!(f, "possible recursive locking: taking {lock}, held already")?
+            }
+            Violation::Inversion { held } => writeln!(
+                f,
+                "possible circular locking dependency: taking {lock} while holding {held}, taken \
+                 after it before"
+            )?,
+            Violation::SleepInIrq => writeln!(f, "sleeping lock taken in IRQ context: {lock}")?,
+            Violation::SleepUnderSpin { held } => {
+                writeln!(f, "sleeping lock {lock} taken while holding {held}")?
+            }
+        }
+        write!(f, "locks held:")?;
+        for held in self.held_spin.iter().chain(self.held_sleeping) {
+            write!(f, "\n  {held}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The lock classes, and the dependencies between them.
+struct Graph {
+    keys: [usize; MAX_CLASSES],
+    /// `deps[a]` has bit `b` set if class `b` was taken while holding `a`.
+    deps: [[u64; WORDS]; MAX_CLASSES],
+    visited: [u64; WORDS],
+    stack: [u16; MAX_CLASSES],
+}
+
+fn test_bit(set: &[u64; WORDS], bit: u16) -> bool {
+    set[bit as usize / 64] & (1 << (bit % 64)) != 0
+}
+
+fn set_bit(set: &mut [u64; WORDS], bit: u16) {
+    set[bit as usize / 64] |= 1 << (bit % 64);
+}
+
+impl Graph {
+    const fn new() -> Self {
+        Self {
+            keys: [FREE; MAX_CLASSES],
+            deps: [[0; WORDS]; MAX_CLASSES],
+            visited: [0; WORDS],
+            stack: [0; MAX_CLASSES],
+        }
+    }
+
+    fn slot(key: usize) -> usize {
+        (key / 8) % MAX_CLASSES
+    }
+
+    fn find(&self, key: usize) -> Option<u16> {
+        let start = Self::slot(key);
+        for i in (start..MAX_CLASSES).chain(0..start) {
+            match self.keys[i] {
+                FREE => return None,
+                k if k == key => return Some(i as u16),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Returns the index of the class `key`, registering it if new, or
+    /// `None` if there is no room left.
+    fn class(&mut self, key: usize) -> Option<u16> {
+        if let Some(index) = self.find(key) {
+            return Some(index);
+        }
+        let start = Self::slot(key);
+        let index = (start..MAX_CLASSES)
+            .chain(0..start)
+            .find(|&i| matches!(self.keys[i], FREE | FORGOTTEN))?;
+        self.keys[index] = key;
+        Some(index as u16)
+    }
+
+    /// Removes the class `key` and its dependencies.
+    fn forget(&mut self, key: usize) {
+        let Some(index) = self.find(key) else {
+            return;
+        };
+        self.keys[index as usize] = FORGOTTEN;
+        self.deps[index as usize] = [0; WORDS];
+        for deps in &mut self.deps {
+            deps[index as usize / 64] &= !(1 << (index % 64));
+        }
+    }
+
+    fn depends(&self, from: u16, to: u16) -> bool {
+        test_bit(&self.deps[from as usize], to)
+    }
+
+    fn add_dep(&mut self, from: u16, to: u16) {
+        set_bit(&mut self.deps[from as usize], to);
+    }
+
+    /// Returns whether `to` was taken after `from`, directly or not.
+    fn reaches(&mut self, from: u16, to: u16) -> bool {
+        self.visited = [0; WORDS];
+        set_bit(&mut self.visited, from);
+        self.stack[0] = from;
+        let mut top = 1;
+        while top > 0 {
+            top -= 1;
+            let class = self.stack[top] as usize;
+            for word in 0..WORDS {
+                let mut next = self.deps[class][word] & !self.visited[word];
+                while next != 0 {
+                    let bit = (word * 64) as u16 + next.trailing_zeros() as u16;
+                    next &= next - 1;
+                    if bit == to {
+                        return true;
+                    }
+                    set_bit(&mut self.visited, bit);
+                    self.stack[top] = bit;
+                    top += 1;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// The graph, behind a lock of its own, which is not tracked.
+struct GraphLock {
+    locked: AtomicBool,
+    graph: UnsafeCell<Graph>,
+}
+
+unsafe impl Sync for GraphLock {}
+
+impl GraphLock {
+    /// Runs `f` on the graph. Called with IRQs disabled.
+    fn with<R>(&self, f: impl FnOnce(&mut Graph) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        let res = f(unsafe { &mut *self.graph.get() });
+        self.locked.store(false, Ordering::Release);
+        res
+    }
+}
+
+static GRAPH: GraphLock = GraphLock {
+    locked: AtomicBool::new(false),
+    graph: UnsafeCell::new(Graph::new()),
+};
+
+/// Returns whether the validator is on.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn held_locks<'a>(sleeping: bool) -> Option<&'a HeldLocks> {
+    let held = crate_interface::call_interface!(LockdepIf::held_locks(sleeping));
+    unsafe { held.as_ref() }
+}
+
+/// Checks that taking `lock` follows the locking rules, recording the
+/// dependencies from the locks held if it does.
+fn check(graph: &mut Graph, lock: &HeldLock, held: &[HeldLock]) -> Option<Violation> {
+    for prev in held {
+        if prev.addr == lock.addr
+            && lock.mode != LockMode::Try
+            && !(prev.mode == LockMode::Shared && lock.mode == LockMode::Shared)
+        {
+            return Some(Violation::Recursive);
+        }
+    }
+    if lock.mode == LockMode::Try {
+        return None;
+    }
+    for prev in held {
+        if prev.index == lock.index || graph.depends(prev.index, lock.index) {
+            continue;
+        }
+        if graph.reaches(lock.index, prev.index) {
+            return Some(Violation::Inversion { held: *prev });
+        }
+        graph.add_dep(prev.index, lock.index);
+    }
+    None
+}
+
+/// Records that the lock at `addr`, of the class `class`, is being taken at
+/// `site`, reporting it if this breaks the locking rules.
+///
+/// Called before waiting for the lock, or once taken with [`LockMode::Try`].
+pub fn acquire(
+    addr: usize,
+    class: ClassKey,
+    sleeping: bool,
+    mode: LockMode,
+    site: &'static Location<'static>,
+) {
+    if !is_enabled() {
+        return;
+    }
+    let _irq = crate::IrqSave::new();
+    let Some(target) = held_locks(sleeping) else {
+        return;
+    };
+    let spin = held_locks(false);
+    let task = held_locks(true);
+    // Safety: held by the current CPU and task, with IRQs disabled.
+    let held_spin = spin.map_or(&[][..], |held| unsafe { held.as_slice() });
+    let held_sleeping = task.map_or(&[][..], |held| unsafe { held.as_slice() });
+
+    let Some(index) = GRAPH.with(|graph| graph.class(class.raw())) else {
+        ENABLED.store(false, Ordering::Relaxed);
+        return;
+    };
+    let lock = HeldLock {
+        addr,
+        class,
+        sleeping,
+        mode,
+        site,
+        index,
+    };
+
+    let mut violation = None;
+    if sleeping {
+        if crate_interface::call_interface!(LockdepIf::in_irq) {
+            violation = Some(Violation::SleepInIrq);
+        } else if let Some(held) = held_spin.last() {
+            violation = Some(Violation::SleepUnderSpin { held: *held });
+        }
+    }
+    let violation = violation.or_else(|| {
+        GRAPH.with(|graph| {
+            check(graph, &lock, held_spin).or_else(|| check(graph, &lock, held_sleeping))
+        })
+    });
+
+    match violation {
+        // Reported once only.
+        Some(violation) if ENABLED.swap(false, Ordering::Relaxed) => {
+            let report = Report {
+                violation,
+                lock,
+                held_spin,
+                held_sleeping,
+            };
+            crate_interface::call_interface!(LockdepIf::report(&report));
+        }
+        Some(_) => {}
+        None => unsafe { target.push(lock) },
+    }
+}
+
+/// Records that the lock at `addr` is released.
+pub fn release(addr: usize, sleeping: bool) {
+    if !is_enabled() {
+        return;
+    }
+    let _irq = crate::IrqSave::new();
+    if let Some(held) = held_locks(sleeping) {
+        unsafe { held.remove(addr) };
+    }
+}
+
+/// Forgets the class `class`, of a lock being freed, so that a lock reusing
+/// its address does not inherit its dependencies.
+pub fn forget(class: ClassKey) {
+    if !is_enabled() {
+        return;
+    }
+    let _irq = crate::IrqSave::new();
+    GRAPH.with(|graph| graph.forget(class.raw()));
+}
+
+#[cfg(unittest)]
+#[allow(missing_docs)]
+pub mod tests_lockdep {
+    use alloc::{
+        alloc::{alloc_zeroed, handle_alloc_error},
+        boxed::Box,
+    };
+    use core::alloc::Layout;
+
+    use unittest::{assert, assert_eq, def_test};
+
+    use super::{Graph, MAX_CLASSES};
+
+    fn new_graph() -> Box<Graph> {
+        // Too large for the stack; all zeroes is an empty graph.
+        let layout = Layout::new::<Graph>();
+        let graph = unsafe { alloc_zeroed(layout) } as *mut Graph;
+        if graph.is_null() {
+            handle_alloc_error(layout);
+        }
+        unsafe { Box::from_raw(graph) }
+    }
+
+    #[def_test]
+    fn test_lockdep_graph_cycle() {
+        let mut graph = new_graph();
+        let a = graph.class(0x1000).unwrap();
+        let b = graph.class(0x2000).unwrap();
+        let c = graph.class(0x3000).unwrap();
+        assert_eq!(graph.class(0x2000), Some(b));
+
+        graph.add_dep(a, b);
+        graph.add_dep(b, c);
+        // `c` is taken after `a`, so taking `a` while holding `c` inverts
+        // the order.
+        assert!(graph.reaches(a, c));
+        assert!(!graph.reaches(c, a));
+
+        graph.forget(0x2000);
+        assert!(!graph.reaches(a, c));
+        assert_eq!(graph.find(0x3000), Some(c));
+    }
+
+    #[def_test]
+    fn test_lockdep_graph_full() {
+        let mut graph = new_graph();
+        for i in 0..MAX_CLASSES {
+            assert!(graph.class(0x1000 + i * 8).is_some());
+        }
+        assert!(graph.class(0x10).is_none());
+        graph.forget(0x1000);
+        assert!(graph.class(0x10).is_some());
+    }
+}
@@ -0,0 +1,223 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Lock states of the spinlocks on multi-core systems.
+//!
+//! The default [`TasLock`] spins on a single test-and-set flag, which all
+//! the waiting CPUs bounce between their caches. The [`QueuedLock`] of the
+//! `queued` feature has the waiters line up in a queue, each spinning on its
+//! own node, and takes the lock in the order they came.
+
+use core::{
+    hint::spin_loop,
+    sync::atomic::{AtomicBool, Ordering},
+};
+#[cfg(feature = "queued")]
+use core::{ptr, sync::atomic::AtomicPtr};
+
+/// The lock state of the spinlocks.
+#[cfg(not(feature = "queued"))]
+pub(crate) type RawLock = TasLock;
+/// The lock state of the spinlocks.
+#[cfg(feature = "queued")]
+pub(crate) type RawLock = QueuedLock;
+
+/// A test-and-set lock.
+#[cfg(not(feature = "queued"))]
+pub(crate) struct TasLock(AtomicBool);
+
+#[cfg(not(feature = "queued"))]
+impl TasLock {
+    pub(crate) const fn new() -> Self {
+        Self(AtomicBool::new(false))
+    }
+
+    #[inline(always)]
+    pub(crate) fn lock(&self) {
+        // Try to acquire using weak CAS in a loop
+        while self
+            .0
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // Spin until lock appears available
+            while self.is_locked() {
+                spin_loop();
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn try_lock(&self) -> bool {
+        self.0
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    #[inline(always)]
+    pub(crate) fn unlock(&self) {
+        self.0.store(false, Ordering::Release);
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_locked(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A waiter of a [`QueuedLock`], on the stack of the CPU waiting.
+#[cfg(feature = "queued")]
+struct Node {
+    next: AtomicPtr<Node>,
+    /// Cleared once the waiter is at the head of the queue.
+    waiting: AtomicBool,
+}
+
+/// A queued (MCS) lock.
+///
+/// Only the waiter at the head of the queue spins on the lock itself; the
+/// others spin on their own node until the one before them reaches the
+/// lock. A node is only needed while waiting, so it lives on the stack of
+/// [`QueuedLock::lock`], and the lock is released without the queue.
+#[cfg(feature = "queued")]
+pub(crate) struct QueuedLock {
+    locked: AtomicBool,
+    /// Last waiter of the queue, null if there is none.
+    tail: AtomicPtr<Node>,
+}
+
+#[cfg(feature = "queued")]
+impl QueuedLock {
+    pub(crate) const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            tail: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn lock(&self) {
+        if !self.try_lock() {
+            self.lock_slow();
+        }
+    }
+
+    #[cold]
+    fn lock_slow(&self) {
+        let node = Node {
+            next: AtomicPtr::new(ptr::null_mut()),
+            waiting: AtomicBool::new(true),
+        };
+        let node_ptr = &node as *const Node as *mut Node;
+
+        let prev = self.tail.swap(node_ptr, Ordering::AcqRel);
+        if !prev.is_null() {
+            // Safety: `prev` waits in `lock_slow` until it passes the head
+            // of the queue on to this node, linked here.
+            unsafe { (*prev).next.store(node_ptr, Ordering::Release) };
+            while node.waiting.load(Ordering::Acquire) {
+                spin_loop();
+            }
+        }
+
+        // At the head of the queue: only the lock holder is ahead.
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.locked.load(Ordering::Relaxed) {
+                spin_loop();
+            }
+        }
+
+        // Leaves the queue, passing its head on to the next waiter.
+        if self
+            .tail
+            .compare_exchange(
+                node_ptr,
+                ptr::null_mut(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            // A waiter came after this node, and is linking itself to it.
+            let next = loop {
+                let next = node.next.load(Ordering::Acquire);
+                if !next.is_null() {
+                    break next;
+                }
+                spin_loop();
+            };
+            // Safety: `next` waits in `lock_slow` until this store.
+            unsafe { (*next).waiting.store(false, Ordering::Release) };
+        }
+    }
+
+    /// Takes the lock if it is free and nobody waits for it, so that the
+    /// waiters are not overtaken.
+    #[inline(always)]
+    pub(crate) fn try_lock(&self) -> bool {
+        self.tail.load(Ordering::Relaxed).is_null()
+            && self
+                .locked
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+    }
+
+    #[inline(always)]
+    pub(crate) fn unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::UnsafeCell, sync::Arc, thread};
+
+    use super::RawLock;
+
+    /// A counter only touched with `lock` held.
+    struct Counter {
+        lock: RawLock,
+        count: UnsafeCell<usize>,
+    }
+
+    unsafe impl Sync for Counter {}
+
+    #[test]
+    fn raw_lock_contended() {
+        const THREADS: usize = 8;
+        const ROUNDS: usize = 10_000;
+
+        let counter = Arc::new(Counter {
+            lock: RawLock::new(),
+            count: UnsafeCell::new(0),
+        });
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let counter = counter.clone();
+                thread::spawn(move || {
+                    for _ in 0..ROUNDS {
+                        counter.lock.lock();
+                        // Safety: the lock is held.
+                        unsafe { *counter.count.get() += 1 };
+                        counter.lock.unlock();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(!counter.lock.is_locked());
+        assert_eq!(unsafe { *counter.count.get() }, THREADS * ROUNDS);
+    }
+}
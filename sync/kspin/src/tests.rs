@@ -155,3 +155,17 @@ fn debug_output() {
     let debug_str = format!("{:?}", lock);
     assert!(debug_str.contains("42") || debug_str.contains("SpinLock"));
 }
+
+#[def_test]
+#[cfg(feature = "smp")]
+fn raw_lock_works() {
+    let lock = crate::raw::RawLock::new();
+    assert!(lock.try_lock());
+    assert!(lock.is_locked());
+    assert!(!lock.try_lock());
+    lock.unlock();
+    lock.lock();
+    assert!(lock.is_locked());
+    lock.unlock();
+    assert!(!lock.is_locked());
+}
@@ -10,6 +10,10 @@ use core::fmt;
 pub use linux_sysno::Errno as LinuxError;
 use strum::EnumCount;
 
+mod result;
+
+pub use self::result::{ResultExt, retry_would_block};
+
 /// The error kind type used by x-kernel.
 ///
 /// Similar to [`std::io::ErrorKind`].
@@ -0,0 +1,167 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Combinators translating subsystem results into [`KResult`].
+
+use core::fmt::Display;
+
+use crate::{KError, KResult};
+
+/// Extension methods on [`Result`] for turning its error into a [`KError`].
+///
+/// The subsystem error types (`DriverError`, `AllocError`, `MemorySetError`,
+/// [`KErrorKind`](crate::KErrorKind), [`LinuxError`](crate::LinuxError), ...)
+/// implement `Into<KError>`, so their results convert without a hand-written
+/// mapping at every call site.
+///
+/// # Examples
+///
+/// ```
+/// # use kerrno::{KError, KErrorKind, ResultExt};
+/// let res: Result<(), KErrorKind> = Err(KErrorKind::NoMemory);
+/// assert_eq!(res.context("allocating the ring"), Err(KError::NoMemory));
+///
+/// let res: Result<u32, core::num::ParseIntError> = "x".parse();
+/// assert_eq!(res.or_kerr(KError::InvalidInput), Err(KError::InvalidInput));
+/// ```
+pub trait ResultExt<T, E> {
+    /// Converts the error into a [`KError`].
+    fn into_kresult(self) -> KResult<T>
+    where
+        E: Into<KError>;
+
+    /// Converts the error into a [`KError`] and logs it with `context`.
+    fn context<C: Display>(self, context: C) -> KResult<T>
+    where
+        E: Into<KError>;
+
+    /// Like [`context`](Self::context), building the context only on error.
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> KResult<T>
+    where
+        E: Into<KError>;
+
+    /// Replaces the error, whatever it is, with `err`.
+    fn or_kerr(self, err: KError) -> KResult<T>;
+}
+
+impl<T, E> ResultExt<T, E> for Result<T, E> {
+    #[inline]
+    fn into_kresult(self) -> KResult<T>
+    where
+        E: Into<KError>,
+    {
+        self.map_err(Into::into)
+    }
+
+    fn context<C: Display>(self, context: C) -> KResult<T>
+    where
+        E: Into<KError>,
+    {
+        self.with_context(|| context)
+    }
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> KResult<T>
+    where
+        E: Into<KError>,
+    {
+        self.map_err(|err| {
+            let err = err.into();
+            log::warn!("[{:?}] {}", err, f());
+            err
+        })
+    }
+
+    #[inline]
+    fn or_kerr(self, err: KError) -> KResult<T> {
+        self.map_err(|_| err)
+    }
+}
+
+/// Calls `op` until it stops failing with [`KError::WouldBlock`], at most
+/// `attempts` times (at least once), spinning in between.
+///
+/// For callers that cannot sleep, waiting for a resource another CPU or the
+/// device frees up shortly. The last error is returned once out of attempts.
+///
+/// # Examples
+///
+/// ```
+/// # use kerrno::{KError, KErrorKind, retry_would_block};
+/// let mut busy = 2;
+/// let res = retry_would_block(5, || {
+///     if busy > 0 {
+///         busy -= 1;
+///         return Err(KErrorKind::WouldBlock);
+///     }
+///     Ok(42)
+/// });
+/// assert_eq!(res, Ok(42));
+/// assert_eq!(
+///     retry_would_block(3, || Err::<(), _>(KErrorKind::WouldBlock)),
+///     Err(KError::WouldBlock)
+/// );
+/// ```
+pub fn retry_would_block<T, E: Into<KError>>(
+    attempts: usize,
+    mut op: impl FnMut() -> Result<T, E>,
+) -> KResult<T> {
+    let mut tried = 1;
+    loop {
+        match op().into_kresult() {
+            Err(err) if tried < attempts && err.canonicalize() == KError::WouldBlock => {
+                tried += 1;
+                core::hint::spin_loop();
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{KErrorKind, LinuxError};
+
+    #[test]
+    fn test_conversions() {
+        let res: Result<(), _> = Err(KErrorKind::NotFound);
+        assert_eq!(res.into_kresult(), Err(KError::NotFound));
+        let res: Result<(), _> = Err(LinuxError::EAGAIN);
+        assert_eq!(
+            res.with_context(|| "polling").map_err(KError::canonicalize),
+            Err(KError::WouldBlock)
+        );
+        assert_eq!(Ok::<_, ()>(1).or_kerr(KError::Io), Ok(1));
+    }
+
+    #[test]
+    fn test_retry_would_block() {
+        let mut calls = 0;
+        let res = retry_would_block(3, || {
+            calls += 1;
+            Err::<(), _>(LinuxError::EAGAIN)
+        });
+        assert_eq!(res, Err(KError::from(LinuxError::EAGAIN)));
+        assert_eq!(calls, 3);
+
+        // Other errors are not retried.
+        let mut calls = 0;
+        let res = retry_would_block(3, || {
+            calls += 1;
+            Err::<(), _>(KErrorKind::Io)
+        });
+        assert_eq!(res, Err(KError::Io));
+        assert_eq!(calls, 1);
+
+        let mut calls = 0;
+        assert_eq!(
+            retry_would_block(0, || {
+                calls += 1;
+                Ok::<_, KErrorKind>(())
+            }),
+            Ok(())
+        );
+        assert_eq!(calls, 1);
+    }
+}
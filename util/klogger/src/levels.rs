@@ -0,0 +1,242 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Log levels by target, adjustable at runtime.
+//!
+//! Levels are given by directives in the syntax of `RUST_LOG`, separated by
+//! commas or whitespace:
+//!
+//! - `level` sets the default level;
+//! - `pattern=level` sets the level of the targets, i.e. module paths, that
+//!   `pattern` matches, where `*` stands for any characters. A pattern
+//!   without `*` matches the target and its submodules;
+//! - `pattern=` drops the directive of `pattern`.
+//!
+//! The longest matching pattern decides, e.g. with `kdriver::*=debug` and
+//! `kdriver::virtio::*=trace`, `kdriver::virtio::blk` logs at trace level.
+//!
+//! The table lives in fixed storage, as the levels are set before the heap
+//! is up. Levels above those let through by the `log-level-*` features of
+//! `log` still have no effect.
+use core::{
+    fmt::{self, Write},
+    str::FromStr,
+};
+
+use kspin::SpinNoIrq;
+use log::LevelFilter;
+
+/// Directives kept at most.
+pub const MAX_DIRECTIVES: usize = 32;
+/// Bytes of a pattern at most.
+pub const MAX_PATTERN_LEN: usize = 64;
+
+/// Error setting log levels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelError {
+    InvalidLevel,
+    PatternTooLong,
+    TooManyDirectives,
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::InvalidLevel => "invalid log level",
+            Self::PatternTooLong => "target pattern too long",
+            Self::TooManyDirectives => "too many log level directives",
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Directive {
+    pattern: [u8; MAX_PATTERN_LEN],
+    len: usize,
+    level: LevelFilter,
+}
+
+impl Directive {
+    const EMPTY: Self = Self {
+        pattern: [0; MAX_PATTERN_LEN],
+        len: 0,
+        level: LevelFilter::Off,
+    };
+
+    fn pattern(&self) -> &str {
+        // Safety: copied from a `str` in `Table::set`
+        unsafe { core::str::from_utf8_unchecked(&self.pattern[..self.len]) }
+    }
+}
+
+struct Table {
+    default: LevelFilter,
+    directives: [Directive; MAX_DIRECTIVES],
+    len: usize,
+}
+
+impl Table {
+    fn directives(&self) -> &[Directive] {
+        &self.directives[..self.len]
+    }
+
+    fn set(&mut self, pattern: &str, level: Option<LevelFilter>) -> Result<(), LevelError> {
+        let index = self
+            .directives()
+            .iter()
+            .position(|d| d.pattern() == pattern);
+        match (index, level) {
+            (Some(index), Some(level)) => self.directives[index].level = level,
+            (Some(index), None) => {
+                self.directives.copy_within(index + 1..self.len, index);
+                self.len -= 1;
+            }
+            (None, Some(level)) => {
+                if pattern.len() > MAX_PATTERN_LEN {
+                    return Err(LevelError::PatternTooLong);
+                }
+                if self.len == MAX_DIRECTIVES {
+                    return Err(LevelError::TooManyDirectives);
+                }
+                let directive = &mut self.directives[self.len];
+                directive.pattern[..pattern.len()].copy_from_slice(pattern.as_bytes());
+                directive.len = pattern.len();
+                directive.level = level;
+                self.len += 1;
+            }
+            (None, None) => {}
+        }
+        Ok(())
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.directives()
+            .iter()
+            .filter(|d| matches(d.pattern(), target))
+            .max_by_key(|d| d.len)
+            .map_or(self.default, |d| d.level)
+    }
+
+    /// Lets through the records of the most verbose level in use.
+    fn update_max_level(&self) {
+        let max = self
+            .directives()
+            .iter()
+            .map(|d| d.level)
+            .fold(self.default, Ord::max);
+        log::set_max_level(max);
+    }
+}
+
+static TABLE: SpinNoIrq<Table> = SpinNoIrq::new(Table {
+    default: LevelFilter::Warn,
+    directives: [Directive::EMPTY; MAX_DIRECTIVES],
+    len: 0,
+});
+
+/// Returns whether `pattern` matches `target`, see the [module docs](self).
+pub(crate) fn matches(pattern: &str, target: &str) -> bool {
+    if !pattern.contains('*') {
+        return target
+            .strip_prefix(pattern)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"));
+    }
+    let (pattern, target) = (pattern.as_bytes(), target.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was in the pattern and how far it got in the target
+    let mut star = None;
+    while t < target.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == target[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Parses a directive into its pattern, `None` for the default level, and
+/// its level, `None` to drop the directive.
+pub(crate) fn parse_directive(
+    directive: &str,
+) -> Result<(Option<&str>, Option<LevelFilter>), LevelError> {
+    let parse = |level: &str| LevelFilter::from_str(level).map_err(|_| LevelError::InvalidLevel);
+    match directive.split_once('=') {
+        None => Ok((None, Some(parse(directive)?))),
+        Some((pattern, "")) => Ok((Some(pattern), None)),
+        Some((pattern, level)) => Ok((Some(pattern), Some(parse(level)?))),
+    }
+}
+
+/// Returns the level records of `target` are logged at.
+pub fn level_for(target: &str) -> LevelFilter {
+    TABLE.lock().level_for(target)
+}
+
+/// Applies the directives in `spec` in order, stopping at the first invalid
+/// one.
+pub fn apply_directives(spec: &str) -> Result<(), LevelError> {
+    let mut table = TABLE.lock();
+    let result = spec
+        .split([',', ' ', '\t', '\n'])
+        .filter(|directive| !directive.is_empty())
+        .try_for_each(|directive| match parse_directive(directive)? {
+            (None, level) => {
+                table.default = level.unwrap_or(LevelFilter::Off);
+                Ok(())
+            }
+            (Some(pattern), level) => table.set(pattern, level),
+        });
+    table.update_max_level();
+    result
+}
+
+/// Replaces the levels with those of `spec`.
+///
+/// Without a default level in `spec`, logging is off but for the targets
+/// given.
+pub fn reset_directives(spec: &str) -> Result<(), LevelError> {
+    {
+        let mut table = TABLE.lock();
+        table.default = LevelFilter::Off;
+        table.len = 0;
+        table.update_max_level();
+    }
+    apply_directives(spec)
+}
+
+fn level_name(level: LevelFilter) -> &'static str {
+    match level {
+        LevelFilter::Off => "off",
+        LevelFilter::Error => "error",
+        LevelFilter::Warn => "warn",
+        LevelFilter::Info => "info",
+        LevelFilter::Debug => "debug",
+        LevelFilter::Trace => "trace",
+    }
+}
+
+/// Writes the default level and the directives, one per line, in the syntax
+/// [`apply_directives`] takes.
+pub fn write_directives(out: &mut dyn Write) -> fmt::Result {
+    let table = TABLE.lock();
+    writeln!(out, "{}", level_name(table.default))?;
+    for directive in table.directives() {
+        writeln!(
+            out,
+            "{}={}",
+            directive.pattern(),
+            level_name(directive.level)
+        )?;
+    }
+    Ok(())
+}
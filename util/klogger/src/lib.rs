@@ -3,14 +3,18 @@
 // See LICENSES for license details.
 
 //! Kernel logging utilities and macros.
+//!
+//! Records are filtered by target, with levels adjustable at runtime, see
+//! [`levels`].
 #![cfg_attr(not(feature = "std"), no_std)]
 
 extern crate log;
 
-use core::{
-    fmt::{self, Write},
-    str::FromStr,
-};
+pub mod levels;
+
+mod test_levels;
+
+use core::fmt::{self, Write};
 
 #[cfg(not(feature = "std"))]
 use crate_interface::call_interface;
@@ -84,8 +88,8 @@ impl Write for KernelLogger {
 
 impl Log for KernelLogger {
     #[inline]
-    fn enabled(&self, _metadata: &Metadata) -> bool {
-        true
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= levels::level_for(metadata.target())
     }
 
     fn log(&self, record: &Record) {
@@ -171,9 +175,12 @@ pub fn init_klogger() {
     log::set_max_level(LevelFilter::Warn);
 }
 
-pub fn set_log_level(level: &str) {
-    let lf = LevelFilter::from_str(level)
-        .ok()
-        .unwrap_or(LevelFilter::Off);
-    log::set_max_level(lf);
+/// Sets the log levels from `spec`, e.g. `warn,kdriver::virtio::*=debug`,
+/// see [`levels`].
+///
+/// Logging is off if `spec` gives no valid default level.
+pub fn set_log_level(spec: &str) {
+    if levels::reset_directives(spec).is_err() {
+        let _ = levels::apply_directives("off");
+    }
 }
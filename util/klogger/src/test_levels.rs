@@ -0,0 +1,44 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Unit tests for target patterns and level directives.
+
+#![cfg(unittest)]
+
+use log::LevelFilter;
+use unittest::def_test;
+
+use crate::levels::{LevelError, matches, parse_directive};
+
+#[def_test]
+fn test_plain_pattern_matches_submodules() {
+    assert!(matches("kdriver", "kdriver"));
+    assert!(matches("kdriver", "kdriver::virtio"));
+    assert!(!matches("kdriver", "kdriver_base"));
+    assert!(!matches("kdriver::virtio", "kdriver"));
+}
+
+#[def_test]
+fn test_wildcard_pattern() {
+    assert!(matches("kdriver::*", "kdriver::virtio::blk"));
+    assert!(!matches("kdriver::*", "kdriver"));
+    assert!(matches("*::net", "kdriver::net"));
+    assert!(!matches("*::net", "kdriver::net::ixgbe"));
+    assert!(matches("k*::*blk", "kdriver::virtio::blk"));
+    assert!(matches("*", "anything"));
+}
+
+#[def_test]
+fn test_parse_directive() {
+    assert_eq!(
+        parse_directive("debug"),
+        Ok((None, Some(LevelFilter::Debug)))
+    );
+    assert_eq!(
+        parse_directive("knet::*=TRACE"),
+        Ok((Some("knet::*"), Some(LevelFilter::Trace)))
+    );
+    assert_eq!(parse_directive("knet::*="), Ok((Some("knet::*"), None)));
+    assert_eq!(parse_directive("knet=loud"), Err(LevelError::InvalidLevel));
+}
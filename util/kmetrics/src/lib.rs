@@ -0,0 +1,79 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Kernel metrics registry.
+//!
+//! Subsystems define [`Counter`]s, [`Gauge`]s and [`Histogram`]s as statics,
+//! record into them on their hot paths and [`register`] them once at init.
+//! Values that already live elsewhere, such as allocator usage, are exported
+//! by a [`Collector`] instead.
+//!
+//! Everything registered is written in the Prometheus text format by
+//! [`export`], which backs the `/metrics` endpoint of the diagnostics
+//! server, or printed to the console by [`dump`].
+#![no_std]
+
+extern crate alloc;
+
+mod metric;
+mod writer;
+
+mod test_metrics;
+
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+use kspin::SpinNoIrq;
+
+pub use self::{
+    metric::{Counter, Gauge, Histogram, Metric},
+    writer::{Kind, MetricsWriter},
+};
+
+/// Writes metrics that are not kept in a [`Metric`].
+pub type Collector = fn(&mut MetricsWriter);
+
+#[derive(Clone, Copy)]
+enum Entry {
+    Metric(&'static dyn Metric),
+    Collector(Collector),
+}
+
+static REGISTRY: SpinNoIrq<Vec<Entry>> = SpinNoIrq::new(Vec::new());
+
+/// Adds a metric to the export.
+pub fn register(metric: &'static dyn Metric) {
+    REGISTRY.lock().push(Entry::Metric(metric));
+}
+
+/// Adds a collector to the export.
+pub fn register_collector(collector: Collector) {
+    REGISTRY.lock().push(Entry::Collector(collector));
+}
+
+/// Writes all registered metrics to `out`.
+pub fn export(out: &mut dyn Write) {
+    let mut writer = MetricsWriter::new(out);
+    // Collectors may register others, so do not hold the lock
+    let entries = REGISTRY.lock().clone();
+    for entry in entries {
+        match entry {
+            Entry::Metric(metric) => metric.write(&mut writer),
+            Entry::Collector(collector) => collector(&mut writer),
+        }
+    }
+}
+
+/// Prints all registered metrics to the console.
+pub fn dump() {
+    struct Console;
+
+    impl Write for Console {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            klogger::print_fmt(format_args!("{s}"))
+        }
+    }
+
+    export(&mut Console);
+}
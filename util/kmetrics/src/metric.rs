@@ -0,0 +1,206 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Counters, gauges and histograms.
+//!
+//! Counters and histograms are recorded into a shard per CPU, so hot paths
+//! never share a cache line; shards are summed on export. Gauges hold a
+//! single value since they are set rather than accumulated.
+use alloc::format;
+use core::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use kbuild_config::CPU_NUM;
+
+use crate::writer::{Kind, MetricsWriter};
+
+/// A metric that can be exported.
+pub trait Metric: Sync {
+    /// Writes the family of the metric.
+    fn write(&self, writer: &mut MetricsWriter);
+}
+
+/// Returns the shard of the current CPU.
+#[inline]
+fn this_shard() -> usize {
+    kplat::cpu::id() % CPU_NUM
+}
+
+/// A per-CPU value on its own cache line.
+#[repr(align(64))]
+struct Shard(AtomicU64);
+
+impl Shard {
+    const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+}
+
+/// A monotonically increasing count.
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    shards: [Shard; CPU_NUM],
+}
+
+impl Counter {
+    pub const fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            shards: [const { Shard::new() }; CPU_NUM],
+        }
+    }
+
+    /// Adds one.
+    #[inline]
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Adds `n`.
+    #[inline]
+    pub fn add(&self, n: u64) {
+        self.shards[this_shard()].0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Returns the total over all CPUs.
+    pub fn get(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|shard| shard.0.load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+impl Metric for Counter {
+    fn write(&self, writer: &mut MetricsWriter) {
+        writer.single(self.name, Kind::Counter, self.help, self.get());
+    }
+}
+
+/// A value that goes up and down.
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicI64,
+}
+
+impl Gauge {
+    pub const fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            value: AtomicI64::new(0),
+        }
+    }
+
+    pub fn set(&self, value: i64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    pub fn add(&self, n: i64) {
+        self.value.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn sub(&self, n: i64) {
+        self.value.fetch_sub(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+impl Metric for Gauge {
+    fn write(&self, writer: &mut MetricsWriter) {
+        writer.single(self.name, Kind::Gauge, self.help, self.get());
+    }
+}
+
+/// Observations of one CPU.
+#[repr(align(64))]
+struct HistogramShard<const N: usize> {
+    /// Observations per bucket, not cumulative.
+    buckets: [AtomicU64; N],
+    count: AtomicU64,
+    sum: AtomicU64,
+}
+
+impl<const N: usize> HistogramShard<N> {
+    const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; N],
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+        }
+    }
+}
+
+/// A distribution of values over `N` buckets.
+///
+/// `bounds` are the inclusive upper bounds of the buckets, in increasing
+/// order; values above the last one are only reflected in the count and sum.
+pub struct Histogram<const N: usize> {
+    name: &'static str,
+    help: &'static str,
+    bounds: [u64; N],
+    shards: [HistogramShard<N>; CPU_NUM],
+}
+
+impl<const N: usize> Histogram<N> {
+    pub const fn new(name: &'static str, help: &'static str, bounds: [u64; N]) -> Self {
+        const { assert!(N > 0, "a histogram needs buckets") };
+        Self {
+            name,
+            help,
+            bounds,
+            shards: [const { HistogramShard::new() }; CPU_NUM],
+        }
+    }
+
+    /// Records `value`.
+    #[inline]
+    pub fn observe(&self, value: u64) {
+        let shard = &self.shards[this_shard()];
+        if let Some(bucket) = self.bounds.iter().position(|&bound| value <= bound) {
+            shard.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        shard.count.fetch_add(1, Ordering::Relaxed);
+        shard.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Returns the cumulative count of each bucket, the total count and the
+    /// sum of all values.
+    pub fn snapshot(&self) -> ([u64; N], u64, u64) {
+        let mut buckets = [0; N];
+        let (mut count, mut sum) = (0, 0);
+        for shard in &self.shards {
+            for (total, bucket) in buckets.iter_mut().zip(&shard.buckets) {
+                *total += bucket.load(Ordering::Relaxed);
+            }
+            count += shard.count.load(Ordering::Relaxed);
+            sum += shard.sum.load(Ordering::Relaxed);
+        }
+        for i in 1..N {
+            buckets[i] += buckets[i - 1];
+        }
+        (buckets, count, sum)
+    }
+}
+
+impl<const N: usize> Metric for Histogram<N> {
+    fn write(&self, writer: &mut MetricsWriter) {
+        let (buckets, count, sum) = self.snapshot();
+        // Buckets are recorded before the count, which may lag behind
+        let count = count.max(buckets[N - 1]);
+        writer.family(self.name, Kind::Histogram, self.help);
+        let bucket_name = format!("{}_bucket", self.name);
+        for (bound, total) in self.bounds.iter().zip(buckets) {
+            writer.sample(&bucket_name, &[("le", &format!("{bound}"))], total);
+        }
+        writer.sample(&bucket_name, &[("le", "+Inf")], count);
+        writer.sample(&format!("{}_sum", self.name), &[], sum);
+        writer.sample(&format!("{}_count", self.name), &[], count);
+    }
+}
@@ -0,0 +1,59 @@
+//! Unit tests for metric recording and export.
+
+#![cfg(unittest)]
+
+use alloc::string::String;
+
+use unittest::def_test;
+
+use crate::{Counter, Gauge, Histogram, Kind, Metric, MetricsWriter};
+
+fn exported(metric: &dyn Metric) -> String {
+    let mut out = String::new();
+    metric.write(&mut MetricsWriter::new(&mut out));
+    out
+}
+
+#[def_test]
+fn test_counter_and_gauge() {
+    static COUNTER: Counter = Counter::new("test_events_total", "Events.");
+    COUNTER.inc();
+    COUNTER.add(4);
+    assert_eq!(COUNTER.get(), 5);
+    assert_eq!(
+        exported(&COUNTER),
+        "# HELP test_events_total Events.\n# TYPE test_events_total counter\ntest_events_total \
+         5\n"
+    );
+
+    let gauge = Gauge::new("test_level", "Level.");
+    gauge.set(3);
+    gauge.sub(5);
+    gauge.add(1);
+    assert_eq!(gauge.get(), -1);
+}
+
+#[def_test]
+fn test_histogram() {
+    let histogram = Histogram::new("test_latency_us", "Latency.", [10, 100]);
+    for value in [1, 10, 11, 500] {
+        histogram.observe(value);
+    }
+    assert_eq!(histogram.snapshot(), ([2, 3], 4, 522));
+    assert_eq!(
+        exported(&histogram),
+        "# HELP test_latency_us Latency.\n# TYPE test_latency_us \
+         histogram\ntest_latency_us_bucket{le=\"10\"} 2\ntest_latency_us_bucket{le=\"100\"} \
+         3\ntest_latency_us_bucket{le=\"+Inf\"} 4\ntest_latency_us_sum 522\ntest_latency_us_count \
+         4\n"
+    );
+}
+
+#[def_test]
+fn test_label_escaping() {
+    let mut out = String::new();
+    let mut writer = MetricsWriter::new(&mut out);
+    writer.family("reads_total", Kind::Counter, "Reads.");
+    writer.sample("reads_total", &[("path", "/a\"b\\c\n"), ("dev", "x")], 7);
+    assert!(out.ends_with("reads_total{path=\"/a\\\"b\\\\c\\n\",dev=\"x\"} 7\n"));
+}
@@ -0,0 +1,74 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Prometheus text exposition format.
+use core::fmt::{self, Display, Write};
+
+/// Kind of a metric family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+/// Writes metric families to any text sink.
+pub struct MetricsWriter<'a> {
+    out: &'a mut dyn Write,
+}
+
+impl<'a> MetricsWriter<'a> {
+    pub fn new(out: &'a mut dyn Write) -> Self {
+        Self { out }
+    }
+
+    /// Starts the family `name`, whose samples follow.
+    pub fn family(&mut self, name: &str, kind: Kind, help: &str) {
+        let kind = match kind {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+            Kind::Histogram => "histogram",
+        };
+        let _ = write!(self.out, "# HELP {name} {help}\n# TYPE {name} {kind}\n");
+    }
+
+    /// Writes a sample of the current family.
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        let _ = self.out.write_str(name);
+        if !labels.is_empty() {
+            let _ = self.out.write_char('{');
+            for (i, (key, value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    let _ = self.out.write_char(',');
+                }
+                let _ = write!(self.out, "{key}=\"{}\"", Escaped(value));
+            }
+            let _ = self.out.write_char('}');
+        }
+        let _ = writeln!(self.out, " {value}");
+    }
+
+    /// Writes a family with a single unlabeled sample.
+    pub fn single(&mut self, name: &str, kind: Kind, help: &str, value: impl Display) {
+        self.family(name, kind, help);
+        self.sample(name, &[], value);
+    }
+}
+
+/// Escapes a label value.
+struct Escaped<'a>(&'a str);
+
+impl Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '\\' => f.write_str("\\\\")?,
+                '"' => f.write_str("\\\"")?,
+                '\n' => f.write_str("\\n")?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
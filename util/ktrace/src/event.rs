@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Traced events and their ftrace formats.
+use core::fmt::{self, Display};
+
+/// A task name, truncated like Linux's `comm` to 15 bytes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Comm {
+    bytes: [u8; Self::LEN],
+    len: u8,
+}
+
+impl Comm {
+    const LEN: usize = 15;
+
+    pub fn new(name: &str) -> Self {
+        let mut len = name.len().min(Self::LEN);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut bytes = [0; Self::LEN];
+        bytes[..len].copy_from_slice(&name.as_bytes()[..len]);
+        Self {
+            bytes,
+            len: len as u8,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Safety: cut at a char boundary of a `str` in `new`
+        unsafe { core::str::from_utf8_unchecked(&self.bytes[..self.len as usize]) }
+    }
+}
+
+impl Display for Comm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl fmt::Debug for Comm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// A task as it appears in events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskInfo {
+    pub pid: u64,
+    /// Priority on Linux's scale, 120 being that of nice 0.
+    pub prio: i32,
+    pub comm: Comm,
+}
+
+/// State of a task switched out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrevState {
+    /// Preempted or yielding, still runnable.
+    Running,
+    /// Blocked.
+    Sleeping,
+    /// Exited.
+    Dead,
+}
+
+impl PrevState {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Running => "R",
+            Self::Sleeping => "S",
+            Self::Dead => "X",
+        }
+    }
+}
+
+/// A traced event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A CPU switches from `prev` to `next`.
+    SchedSwitch {
+        prev: TaskInfo,
+        prev_state: PrevState,
+        next: TaskInfo,
+    },
+    /// `task` becomes runnable on `target_cpu`.
+    SchedWakeup { task: TaskInfo, target_cpu: usize },
+    /// `task` moves to another CPU's run queue.
+    SchedMigrate {
+        task: TaskInfo,
+        orig_cpu: usize,
+        dest_cpu: usize,
+    },
+}
+
+impl Event {
+    /// Returns the name of the ftrace event.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SchedSwitch { .. } => "sched_switch",
+            Self::SchedWakeup { .. } => "sched_wakeup",
+            Self::SchedMigrate { .. } => "sched_migrate_task",
+        }
+    }
+}
+
+/// Writes the fields as in ftrace's `trace` file.
+impl Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SchedSwitch {
+                prev,
+                prev_state,
+                next,
+            } => write!(
+                f,
+                "prev_comm={} prev_pid={} prev_prio={} prev_state={} ==> next_comm={} next_pid={} \
+                 next_prio={}",
+                prev.comm,
+                prev.pid,
+                prev.prio,
+                prev_state.as_str(),
+                next.comm,
+                next.pid,
+                next.prio
+            ),
+            Self::SchedWakeup { task, target_cpu } => write!(
+                f,
+                "comm={} pid={} prio={} target_cpu={:03}",
+                task.comm, task.pid, task.prio, target_cpu
+            ),
+            Self::SchedMigrate {
+                task,
+                orig_cpu,
+                dest_cpu,
+            } => write!(
+                f,
+                "comm={} pid={} prio={} orig_cpu={} dest_cpu={}",
+                task.comm, task.pid, task.prio, orig_cpu, dest_cpu
+            ),
+        }
+    }
+}
@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Kernel event trace buffer.
+//!
+//! Subsystems [`record`] [`Event`]s on their hot paths while tracing is
+//! [`start`]ed, checking [`is_enabled`] first so that building an event costs
+//! nothing otherwise. Each CPU keeps its latest events in a ring of its own:
+//! recording never contends across CPUs, and once a ring is full the oldest
+//! events are overwritten, like a flight recorder.
+//!
+//! [`export`] merges the rings by time into the text format of ftrace's
+//! `trace` file, which Perfetto, `trace-cmd` and other ftrace tools import.
+#![no_std]
+
+extern crate alloc;
+
+mod event;
+mod writer;
+
+mod test_trace;
+
+use alloc::vec::Vec;
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use kbuild_config::CPU_NUM;
+use kspin::SpinNoIrq;
+
+pub use self::{
+    event::{Comm, Event, PrevState, TaskInfo},
+    writer::write_trace,
+};
+
+/// An event as recorded.
+#[derive(Debug, Clone)]
+pub struct Record {
+    /// Monotonic time of the event, in nanoseconds.
+    pub timestamp_ns: u64,
+    pub cpu: usize,
+    /// The task running when the event was recorded.
+    pub task: TaskInfo,
+    pub event: Event,
+}
+
+/// The events of one CPU, oldest first once `head` is taken into account.
+struct Ring {
+    records: Vec<Record>,
+    capacity: usize,
+    /// Where the next record goes once the ring is full.
+    head: usize,
+    /// Events recorded, including overwritten ones.
+    written: u64,
+}
+
+impl Ring {
+    const fn new() -> Self {
+        Self {
+            records: Vec::new(),
+            capacity: 0,
+            head: 0,
+            written: 0,
+        }
+    }
+
+    fn push(&mut self, record: Record) {
+        if self.records.len() < self.capacity {
+            self.records.push(record);
+        } else if self.capacity > 0 {
+            self.records[self.head] = record;
+            self.head = (self.head + 1) % self.capacity;
+        }
+        self.written += 1;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Record> {
+        let (newer, older) = self.records.split_at(self.head);
+        older.iter().chain(newer)
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RINGS: [SpinNoIrq<Ring>; CPU_NUM] = [const { SpinNoIrq::new(Ring::new()) }; CPU_NUM];
+
+/// Returns whether events are being recorded.
+#[inline]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Starts recording, keeping up to `capacity` events per CPU.
+///
+/// Events recorded before are discarded.
+pub fn start(capacity: usize) {
+    ENABLED.store(false, Ordering::Relaxed);
+    for ring in &RINGS {
+        let mut ring = ring.lock();
+        *ring = Ring::new();
+        ring.records.reserve_exact(capacity);
+        ring.capacity = capacity;
+    }
+    ENABLED.store(true, Ordering::Release);
+}
+
+/// Stops recording, keeping the events for [`export`].
+pub fn stop() {
+    ENABLED.store(false, Ordering::Release);
+}
+
+/// Records `event`, which happens while `task` runs on this CPU.
+pub fn record(task: TaskInfo, event: Event) {
+    if !is_enabled() {
+        return;
+    }
+    let cpu = kplat::cpu::id() % CPU_NUM;
+    let timestamp_ns = kplat::timer::now_ns();
+    RINGS[cpu].lock().push(Record {
+        timestamp_ns,
+        cpu,
+        task,
+        event,
+    });
+}
+
+/// Returns the events kept, ordered by time, and how many were recorded in
+/// total.
+pub fn snapshot() -> (Vec<Record>, u64) {
+    let mut records = Vec::new();
+    let mut written = 0;
+    for ring in &RINGS {
+        let ring = ring.lock();
+        records.extend(ring.iter().cloned());
+        written += ring.written;
+    }
+    records.sort_by_key(|record| record.timestamp_ns);
+    (records, written)
+}
+
+/// Writes the events kept in the ftrace text format to `out`.
+pub fn export(out: &mut dyn Write) {
+    let (records, written) = snapshot();
+    write_trace(out, &records, written);
+}
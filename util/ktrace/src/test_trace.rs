@@ -0,0 +1,102 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Unit tests for event recording and export.
+
+#![cfg(unittest)]
+
+use alloc::{string::String, vec::Vec};
+
+use unittest::def_test;
+
+use crate::{Comm, Event, PrevState, Record, Ring, TaskInfo, write_trace};
+
+fn task(pid: u64, name: &str) -> TaskInfo {
+    TaskInfo {
+        pid,
+        prio: 120,
+        comm: Comm::new(name),
+    }
+}
+
+fn wakeup(timestamp_ns: u64) -> Record {
+    Record {
+        timestamp_ns,
+        cpu: 0,
+        task: task(1, "init"),
+        event: Event::SchedWakeup {
+            task: task(2, "worker"),
+            target_cpu: 0,
+        },
+    }
+}
+
+#[def_test]
+fn test_comm_truncated() {
+    assert_eq!(Comm::new("short").as_str(), "short");
+    assert_eq!(
+        Comm::new("a-very-long-task-name").as_str(),
+        "a-very-long-tas"
+    );
+    // Not cut inside a character
+    assert_eq!(Comm::new("aaaaaaaaaaaaaa\u{e9}").as_str(), "aaaaaaaaaaaaaa");
+}
+
+#[def_test]
+fn test_ring_overwrites_oldest() {
+    let mut ring = Ring::new();
+    ring.capacity = 2;
+    for ts in 1..=3 {
+        ring.push(wakeup(ts));
+    }
+    let kept: Vec<_> = ring.iter().map(|r| r.timestamp_ns).collect();
+    assert_eq!(kept, [2, 3]);
+    assert_eq!(ring.written, 3);
+}
+
+#[def_test]
+fn test_ftrace_format() {
+    let records = [
+        Record {
+            timestamp_ns: 1_500_000_000,
+            cpu: 1,
+            task: task(3, "sh"),
+            event: Event::SchedSwitch {
+                prev: task(3, "sh"),
+                prev_state: PrevState::Sleeping,
+                next: TaskInfo {
+                    pid: 0,
+                    prio: 120,
+                    comm: Comm::new("swapper/1"),
+                },
+            },
+        },
+        Record {
+            timestamp_ns: 2_000_001_000,
+            cpu: 0,
+            task: task(1, "init"),
+            event: Event::SchedMigrate {
+                task: task(3, "sh"),
+                orig_cpu: 1,
+                dest_cpu: 0,
+            },
+        },
+    ];
+    let mut out = String::new();
+    write_trace(&mut out, &records, 5);
+
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines[0], "# tracer: nop");
+    assert!(lines[2].starts_with("# entries-in-buffer/entries-written: 2/5"));
+    assert_eq!(
+        lines[6],
+        "              sh-3       [001] d..2     1.500000: sched_switch: prev_comm=sh prev_pid=3 \
+         prev_prio=120 prev_state=S ==> next_comm=swapper/1 next_pid=0 next_prio=120"
+    );
+    assert_eq!(
+        lines[7],
+        "            init-1       [000] d..2     2.000001: sched_migrate_task: comm=sh pid=3 \
+         prio=120 orig_cpu=1 dest_cpu=0"
+    );
+}
@@ -0,0 +1,39 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! ftrace `trace` file text format.
+use core::fmt::Write;
+
+use kbuild_config::CPU_NUM;
+
+use crate::Record;
+
+/// Writes `records`, ordered by time, out of `written` recorded.
+///
+/// Every event is marked as happening with interrupts off at preemption
+/// depth 2, as the scheduler records them.
+pub fn write_trace(out: &mut dyn Write, records: &[Record], written: u64) {
+    let _ = write!(
+        out,
+        "# tracer: nop\n#\n# entries-in-buffer/entries-written: {}/{}   #P:{}\n#\n#           \
+         TASK-PID     CPU#  ||||   TIMESTAMP  FUNCTION\n#              | |         |   ||||      \
+         |         |\n",
+        records.len(),
+        written,
+        CPU_NUM
+    );
+    for record in records {
+        let _ = writeln!(
+            out,
+            "{:>16}-{:<7} [{:03}] d..2 {:5}.{:06}: {}: {}",
+            record.task.comm,
+            record.task.pid,
+            record.cpu,
+            record.timestamp_ns / 1_000_000_000,
+            record.timestamp_ns % 1_000_000_000 / 1_000,
+            record.event.name(),
+            record.event
+        );
+    }
+}
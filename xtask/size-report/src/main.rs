@@ -0,0 +1,392 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 KylinSoft Co., Ltd. <https://www.kylinos.cn/>
+// See LICENSES for license details.
+
+//! Reports what each subsystem or crate of a kernel image costs in flash and
+//! RAM.
+//!
+//! The sized symbols listed by `nm` are attributed to the crate their
+//! demangled path starts with, and workspace crates to the top-level
+//! directory they live in (`drivers`, `net`, `fs`, ...). Code, read-only data
+//! and initialized data take flash; initialized data and zeroed data take
+//! RAM. Bytes not covered by any symbol, such as padding, are not counted.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Component, Path},
+    process::{self, Command},
+};
+
+use clap::{Parser, ValueEnum};
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Path to the kernel ELF
+    elf: String,
+
+    /// Root of the workspace the image was built from
+    #[arg(long, default_value = ".")]
+    workspace: String,
+
+    /// The `nm` to list symbols with
+    #[arg(long, default_value = "rust-nm")]
+    nm: String,
+
+    /// What to group symbols by
+    #[arg(long, value_enum, default_value_t = GroupBy::Subsystem)]
+    by: GroupBy,
+
+    /// Flash budget in bytes, with an optional `K` or `M` suffix
+    #[arg(long, value_parser = parse_size)]
+    flash_budget: Option<u64>,
+
+    /// RAM budget in bytes, with an optional `K` or `M` suffix
+    #[arg(long, value_parser = parse_size)]
+    ram_budget: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum GroupBy {
+    Subsystem,
+    Crate,
+}
+
+/// Bytes taken by each kind of section.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Sizes {
+    text: u64,
+    rodata: u64,
+    data: u64,
+    bss: u64,
+}
+
+impl Sizes {
+    fn flash(&self) -> u64 {
+        self.text + self.rodata + self.data
+    }
+
+    fn ram(&self) -> u64 {
+        self.data + self.bss
+    }
+
+    fn add(&mut self, other: &Sizes) {
+        self.text += other.text;
+        self.rodata += other.rodata;
+        self.data += other.data;
+        self.bss += other.bss;
+    }
+}
+
+/// Section kinds by `nm` symbol type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Text,
+    Rodata,
+    Data,
+    Bss,
+}
+
+/// Parses a line of `nm --print-size`: `address size type name`.
+///
+/// Returns `None` for symbols without a size or not in an allocated section.
+fn parse_nm_line(line: &str) -> Option<(u64, Kind, &str)> {
+    let mut fields = line.splitn(4, ' ');
+    let _address = fields.next()?;
+    let size = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let kind = match fields.next()? {
+        "T" | "t" | "W" | "w" => Kind::Text,
+        "R" | "r" => Kind::Rodata,
+        "D" | "d" | "G" | "g" | "V" | "v" => Kind::Data,
+        "B" | "b" | "S" | "s" => Kind::Bss,
+        _ => return None,
+    };
+    Some((size, kind, fields.next()?))
+}
+
+/// Returns the crate a demangled Rust symbol belongs to.
+///
+/// Trait implementations like `<ksync::Mutex<T> as core::ops::Drop>::drop`
+/// belong to the crate of the implementing type, or to the crate of the trait
+/// when implemented for a primitive type.
+fn symbol_crate(name: &str) -> Option<&str> {
+    fn path_crate(path: &str) -> Option<&str> {
+        let path = path.trim_start_matches(['<', '&', '*', '[', '(']);
+        let path = ["mut ", "const ", "dyn "]
+            .iter()
+            .fold(path, |path, prefix| path.trim_start_matches(prefix));
+        let (krate, _) = path.split_once("::")?;
+        let is_ident =
+            !krate.is_empty() && krate.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        is_ident.then_some(krate)
+    }
+
+    if !name.starts_with('<') {
+        return path_crate(name);
+    }
+    path_crate(name).or_else(|| {
+        let (_, tr) = name.split_once(" as ")?;
+        path_crate(tr)
+    })
+}
+
+/// Maps the crates of the workspace to the top-level directory they live in.
+fn workspace_subsystems(root: &Path) -> Result<HashMap<String, String>, String> {
+    let manifest_path = root.join("Cargo.toml");
+    let manifest: toml::Table = fs::read_to_string(&manifest_path)
+        .map_err(|e| format!("failed to read {}: {e}", manifest_path.display()))?
+        .parse()
+        .map_err(|e| format!("failed to parse {}: {e}", manifest_path.display()))?;
+    let workspace = manifest.get("workspace").ok_or("not a workspace")?;
+
+    let members = workspace
+        .get("members")
+        .and_then(|members| members.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|member| member.as_str());
+    let dependencies = workspace
+        .get("dependencies")
+        .and_then(|deps| deps.as_table())
+        .into_iter()
+        .flatten()
+        .filter_map(|(_, dep)| dep.get("path")?.as_str());
+
+    let mut subsystems = HashMap::new();
+    for path in members.chain(dependencies) {
+        let Some(Component::Normal(subsystem)) = Path::new(path).components().next() else {
+            continue;
+        };
+        let Ok(content) = fs::read_to_string(root.join(path).join("Cargo.toml")) else {
+            continue;
+        };
+        let Ok(package) = content.parse::<toml::Table>() else {
+            continue;
+        };
+        if let Some(name) = package
+            .get("package")
+            .and_then(|package| package.get("name"))
+            .and_then(|name| name.as_str())
+        {
+            subsystems.insert(
+                name.replace('-', "_"),
+                subsystem.to_string_lossy().into_owned(),
+            );
+        }
+    }
+    Ok(subsystems)
+}
+
+/// Returns the group a symbol is accounted to.
+fn group_of(name: &str, by: GroupBy, subsystems: &HashMap<String, String>) -> String {
+    let Some(krate) = symbol_crate(name) else {
+        return "(other)".into();
+    };
+    match by {
+        GroupBy::Crate => krate.into(),
+        GroupBy::Subsystem => match subsystems.get(krate) {
+            Some(subsystem) => subsystem.clone(),
+            None if ["core", "alloc", "std", "compiler_builtins"].contains(&krate) => {
+                "(rust)".into()
+            }
+            None => "(external)".into(),
+        },
+    }
+}
+
+/// Sums the sizes of the symbols listed by `nm` per group.
+fn account(
+    nm_output: &str,
+    by: GroupBy,
+    subsystems: &HashMap<String, String>,
+) -> HashMap<String, Sizes> {
+    let mut groups: HashMap<String, Sizes> = HashMap::new();
+    for (size, kind, name) in nm_output.lines().filter_map(parse_nm_line) {
+        let sizes = groups.entry(group_of(name, by, subsystems)).or_default();
+        match kind {
+            Kind::Text => sizes.text += size,
+            Kind::Rodata => sizes.rodata += size,
+            Kind::Data => sizes.data += size,
+            Kind::Bss => sizes.bss += size,
+        }
+    }
+    groups
+}
+
+fn parse_size(s: &str) -> Result<u64, String> {
+    let (digits, unit) = match s.strip_suffix(['K', 'k']) {
+        Some(digits) => (digits, 1 << 10),
+        None => match s.strip_suffix(['M', 'm']) {
+            Some(digits) => (digits, 1 << 20),
+            None => (s, 1),
+        },
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    };
+    value
+        .map(|value| value * unit)
+        .map_err(|e| format!("invalid size `{s}`: {e}"))
+}
+
+/// Prints the budget line of `what`; returns whether it is exceeded.
+fn print_budget(what: &str, used: u64, budget: Option<u64>) -> bool {
+    let Some(budget) = budget else {
+        return false;
+    };
+    let percent = used as f64 * 100.0 / budget.max(1) as f64;
+    let over = used > budget;
+    println!(
+        "{what:<5} {used:>10} / {budget:>10} bytes ({percent:5.1}%){}",
+        if over { "  OVER BUDGET" } else { "" }
+    );
+    over
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let subsystems = workspace_subsystems(Path::new(&args.workspace)).unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        process::exit(1);
+    });
+    let output = Command::new(&args.nm)
+        .args(["--print-size", "--demangle", "--no-sort"])
+        .arg(&args.elf)
+        .output()
+        .unwrap_or_else(|e| {
+            eprintln!("error: failed to run {}: {e}", args.nm);
+            process::exit(1);
+        });
+    if !output.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+        process::exit(1);
+    }
+
+    let groups = account(
+        &String::from_utf8_lossy(&output.stdout),
+        args.by,
+        &subsystems,
+    );
+    let mut rows: Vec<_> = groups.into_iter().collect();
+    rows.sort_by(|(a, a_sizes), (b, b_sizes)| {
+        (b_sizes.flash() + b_sizes.ram())
+            .cmp(&(a_sizes.flash() + a_sizes.ram()))
+            .then_with(|| a.cmp(b))
+    });
+
+    let header = match args.by {
+        GroupBy::Subsystem => "subsystem",
+        GroupBy::Crate => "crate",
+    };
+    let width = rows
+        .iter()
+        .map(|(name, _)| name.len())
+        .chain([header.len(), "total".len()])
+        .max()
+        .unwrap_or_default();
+    let print_row = |name: &str, sizes: &Sizes| {
+        println!(
+            "{name:<width$} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            sizes.text,
+            sizes.rodata,
+            sizes.data,
+            sizes.bss,
+            sizes.flash(),
+            sizes.ram()
+        );
+    };
+    println!(
+        "{header:<width$} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "text", "rodata", "data", "bss", "flash", "ram"
+    );
+    let mut total = Sizes::default();
+    for (name, sizes) in &rows {
+        print_row(name, sizes);
+        total.add(sizes);
+    }
+    print_row("total", &total);
+
+    let flash_over = print_budget("flash", total.flash(), args.flash_budget);
+    let ram_over = print_budget("ram", total.ram(), args.ram_budget);
+    if flash_over || ram_over {
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nm_line() {
+        assert_eq!(
+            parse_nm_line("ffff000040080000 0000000000000010 T _start"),
+            Some((0x10, Kind::Text, "_start"))
+        );
+        assert_eq!(
+            parse_nm_line("ffff000040200000 0000000000000100 b ksync::spin::LOCK"),
+            Some((0x100, Kind::Bss, "ksync::spin::LOCK"))
+        );
+        // Undefined and absolute symbols take no space in the image
+        assert_eq!(parse_nm_line("                 U memcpy"), None);
+        assert_eq!(
+            parse_nm_line("0000000000001000 0000000000000008 A _skernel"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_symbol_crate() {
+        assert_eq!(symbol_crate("knet::tcp::TcpSocket::send"), Some("knet"));
+        assert_eq!(
+            symbol_crate("<ksync::mutex::Mutex<T> as core::ops::drop::Drop>::drop"),
+            Some("ksync")
+        );
+        assert_eq!(
+            symbol_crate("<&mut kio::Buf as core::fmt::Debug>::fmt"),
+            Some("kio")
+        );
+        assert_eq!(
+            symbol_crate("<u64 as core::fmt::Display>::fmt"),
+            Some("core")
+        );
+        assert_eq!(symbol_crate("memcpy"), None);
+    }
+
+    #[test]
+    fn test_account() {
+        let subsystems = HashMap::from([
+            ("knet".to_string(), "net".to_string()),
+            ("kdriver".to_string(), "drivers".to_string()),
+        ]);
+        let nm = "\
+0 10 T knet::poll
+0 20 r knet::CONSTS
+0 08 D kdriver::DEVICES
+0 40 B kdriver::BUFFER
+0 04 T core::panicking::panic
+0 02 T memset
+";
+        let groups = account(nm, GroupBy::Subsystem, &subsystems);
+        assert_eq!(groups["net"].flash(), 0x30);
+        assert_eq!(groups["drivers"].flash(), 0x08);
+        assert_eq!(groups["drivers"].ram(), 0x48);
+        assert_eq!(groups["(rust)"].text, 4);
+        assert_eq!(groups["(other)"].text, 2);
+
+        let groups = account(nm, GroupBy::Crate, &subsystems);
+        assert_eq!(groups["kdriver"].bss, 0x40);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("0x1000"), Ok(4096));
+        assert_eq!(parse_size("512K"), Ok(512 << 10));
+        assert_eq!(parse_size("2M"), Ok(2 << 20));
+        assert!(parse_size("lots").is_err());
+    }
+}